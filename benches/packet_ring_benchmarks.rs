@@ -0,0 +1,81 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use kaipo_watcher::collectors::packet_ring::{FrameMeta, PacketRing};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A minimum-size frame and a full-size one, which the ring cuts to its slot size
+const FRAME_SIZES: [usize; 2] = [64, 1514];
+
+/// Frames moved per iteration
+const BATCH: u64 = 1_000;
+
+/// Benchmark moving frames through the capture ring
+fn benchmark_push_and_pop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("packet_ring");
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements(BATCH));
+
+    for size in FRAME_SIZES {
+        let frame = vec![0x45u8; size];
+        let ring = PacketRing::new(10_000);
+        group.bench_function(format!("push_pop_{size}b"), |b| {
+            b.iter(|| {
+                for n in 0..BATCH {
+                    let meta = FrameMeta { wire_len: size as u32, interface: 0, timestamp_ns: n as i64, process: None, filtered: false };
+                    ring.try_push(black_box(&frame), meta);
+                }
+                while let Some(len) = ring.pop_with(|frame| frame.data.len()) {
+                    black_box(len);
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+/// Benchmark draining the ring while capture threads fill it, as with several interfaces
+fn benchmark_concurrent_producers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("packet_ring_concurrent");
+    group.measurement_time(Duration::from_secs(10));
+    group.throughput(Throughput::Elements(BATCH));
+
+    let ring = Arc::new(PacketRing::new(10_000));
+    let running = Arc::new(AtomicBool::new(true));
+    let producers: Vec<_> = (0..2u16)
+        .map(|interface| {
+            let ring = Arc::clone(&ring);
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || {
+                let frame = [0x45u8; 128];
+                let meta = FrameMeta { wire_len: 128, interface, timestamp_ns: 0, process: None, filtered: false };
+                while running.load(Ordering::Relaxed) {
+                    if !ring.try_push(&frame, meta) {
+                        std::hint::spin_loop();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    group.bench_function("two_producers_one_consumer", |b| {
+        b.iter(|| {
+            let mut received = 0;
+            while received < BATCH {
+                if ring.pop_with(|frame| black_box(frame.meta.interface)).is_some() {
+                    received += 1;
+                }
+            }
+        });
+    });
+
+    running.store(false, Ordering::Relaxed);
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_push_and_pop, benchmark_concurrent_producers);
+criterion_main!(benches);
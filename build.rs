@@ -0,0 +1,31 @@
+// Delay-loads Npcap's Packet.dll on Windows, which libpnet links against, so kw starts
+// without Npcap installed and can count traffic from kernel network events instead.
+// The DLL is only loaded the first time a capture function is called.
+// With the `remote` feature, also generates the agent/hub gRPC code from proto/remote.proto
+// with the vendored protoc, so building needs no protobuf toolchain
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let windows_msvc = std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows")
+        && std::env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc");
+    if windows_msvc && std::env::var_os("CARGO_FEATURE_CAPTURE").is_some() {
+        println!("cargo:rustc-link-arg=/DELAYLOAD:Packet.dll");
+        println!("cargo:rustc-link-lib=delayimp");
+    }
+
+    #[cfg(feature = "remote")]
+    compile_remote_proto();
+}
+
+#[cfg(feature = "remote")]
+fn compile_remote_proto() {
+    println!("cargo:rerun-if-changed=proto/remote.proto");
+
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this host");
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc);
+    tonic_build::configure()
+        .compile_protos_with_config(config, &["proto/remote.proto"], &["proto"])
+        .expect("Failed to compile proto/remote.proto");
+}
@@ -0,0 +1,317 @@
+// Bandwidth anomaly detection: per-interface baselines for each hour of the day
+// Each (interface, hour) keeps an exponentially weighted mean and variance of the
+// stored throughput samples; readings far from their hour's baseline are flagged
+
+use crate::config::AnomalyConfig;
+use crate::storage::packet_storage::BandwidthSample;
+use chrono::{DateTime, Duration, Local, Timelike};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Anomalies of one interface closer together than this form one episode
+const EPISODE_GAP_MINUTES: i64 = 5;
+
+/// Direction of an unusual reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnomalyKind {
+    /// Far more traffic than usual for the hour
+    Spike,
+    /// Far less traffic than usual for the hour
+    Drop,
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnomalyKind::Spike => write!(f, "spike"),
+            AnomalyKind::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+/// A throughput reading outside its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub interface: String,
+    pub timestamp: DateTime<Local>,
+    /// Observed throughput (download + upload)
+    pub bytes_per_sec: f64,
+    /// Baseline mean for the interface at this hour of the day
+    pub expected_bytes_per_sec: f64,
+    /// Distance from the baseline in standard deviations
+    pub deviations: f64,
+}
+
+impl Anomaly {
+    pub fn kind(&self) -> AnomalyKind {
+        if self.bytes_per_sec >= self.expected_bytes_per_sec {
+            AnomalyKind::Spike
+        } else {
+            AnomalyKind::Drop
+        }
+    }
+
+    /// Observed throughput relative to the baseline, e.g. "4.2× usual" or "10% of usual"
+    pub fn describe(&self) -> String {
+        let ratio = self.bytes_per_sec / self.expected_bytes_per_sec.max(1.0);
+        match self.kind() {
+            AnomalyKind::Spike => format!("{ratio:.1}× usual"),
+            AnomalyKind::Drop => format!("{:.0}% of usual", ratio * 100.0),
+        }
+    }
+}
+
+/// Consecutive anomalies of one interface in the same direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnomalyEpisode {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    /// Samples flagged during the episode
+    pub samples: usize,
+    /// The reading furthest from its baseline
+    pub peak: Anomaly,
+}
+
+/// Exponentially weighted mean and variance of one interface at one hour of the day
+#[derive(Debug, Clone, Copy, Default)]
+struct Baseline {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl Baseline {
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+            self.variance = 0.0;
+        } else {
+            let diff = value - self.mean;
+            let increment = alpha * diff;
+            self.mean += increment;
+            self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
+}
+
+/// Per-interface hourly throughput baselines
+///
+/// A reading is anomalous when it is at least `sensitivity` standard deviations and
+/// `min_change_bytes_per_sec` away from the mean of its interface and hour of the day,
+/// and that hour has seen `min_samples` samples.
+#[derive(Debug, Clone, Default)]
+pub struct BaselineModel {
+    config: AnomalyConfig,
+    baselines: HashMap<(String, u32), Baseline>,
+}
+
+impl BaselineModel {
+    pub fn new(config: AnomalyConfig) -> Self {
+        Self {
+            config,
+            baselines: HashMap::new(),
+        }
+    }
+
+    /// Baselines learned from `samples`, oldest first
+    pub fn learned_from(config: AnomalyConfig, samples: &[BandwidthSample]) -> Self {
+        let mut model = Self::new(config);
+        for sample in samples {
+            model.learn(&sample.interface_name, sample.timestamp, sample.total_bps());
+        }
+        model
+    }
+
+    /// Whether any interface has a baseline to judge against
+    pub fn is_empty(&self) -> bool {
+        self.baselines.values().all(|b| b.samples < self.config.min_samples)
+    }
+
+    /// Folds a reading into the baseline of its interface and hour
+    /// Once a baseline is established, outliers only count up to the flagging threshold,
+    /// so a long spike does not quickly become its own baseline
+    pub fn learn(&mut self, interface: &str, at: DateTime<Local>, bytes_per_sec: f64) {
+        let alpha = self.config.smoothing.clamp(0.001, 1.0);
+        let baseline = self.baselines.entry((interface.to_string(), at.hour())).or_default();
+        let value = if baseline.samples >= self.config.min_samples.max(1) {
+            let limit = (self.config.sensitivity * baseline.variance.sqrt()).max(self.config.min_change_bytes_per_sec);
+            bytes_per_sec.clamp(baseline.mean - limit, baseline.mean + limit)
+        } else {
+            bytes_per_sec
+        };
+        baseline.update(value, alpha);
+    }
+
+    /// Judges a reading against the baseline without learning from it
+    pub fn check(&self, interface: &str, at: DateTime<Local>, bytes_per_sec: f64) -> Option<Anomaly> {
+        let baseline = self.baselines.get(&(interface.to_string(), at.hour()))?;
+        if baseline.samples < self.config.min_samples.max(1) {
+            return None;
+        }
+
+        let change = bytes_per_sec - baseline.mean;
+        if change.abs() < self.config.min_change_bytes_per_sec {
+            return None;
+        }
+        // A perfectly steady baseline would turn any change into infinite deviations
+        let deviations = change.abs() / baseline.variance.sqrt().max(1.0);
+        if deviations < self.config.sensitivity {
+            return None;
+        }
+
+        Some(Anomaly {
+            interface: interface.to_string(),
+            timestamp: at,
+            bytes_per_sec,
+            expected_bytes_per_sec: baseline.mean,
+            deviations,
+        })
+    }
+
+    /// Judges a reading, then learns from it
+    pub fn observe(&mut self, interface: &str, at: DateTime<Local>, bytes_per_sec: f64) -> Option<Anomaly> {
+        let anomaly = self.check(interface, at, bytes_per_sec);
+        self.learn(interface, at, bytes_per_sec);
+        anomaly
+    }
+}
+
+/// Anomalies among `samples` from `since` on, each judged against the baselines
+/// learned from everything before it; `samples` must be oldest first
+pub fn find_anomalies(config: AnomalyConfig, samples: &[BandwidthSample], since: DateTime<Local>) -> Vec<Anomaly> {
+    let mut model = BaselineModel::new(config);
+    samples
+        .iter()
+        .filter_map(|sample| {
+            let anomaly = model.observe(&sample.interface_name, sample.timestamp, sample.total_bps());
+            anomaly.filter(|a| a.timestamp >= since)
+        })
+        .collect()
+}
+
+/// Groups anomalies into episodes per interface and direction, in order of start
+pub fn episodes(anomalies: &[Anomaly]) -> Vec<AnomalyEpisode> {
+    let gap = Duration::minutes(EPISODE_GAP_MINUTES);
+    let mut open: HashMap<(String, AnomalyKind), AnomalyEpisode> = HashMap::new();
+    let mut closed = Vec::new();
+
+    for anomaly in anomalies {
+        let key = (anomaly.interface.clone(), anomaly.kind());
+        match open.get_mut(&key) {
+            Some(episode) if anomaly.timestamp - episode.end <= gap => {
+                episode.end = anomaly.timestamp;
+                episode.samples += 1;
+                if anomaly.deviations > episode.peak.deviations {
+                    episode.peak = anomaly.clone();
+                }
+            }
+            _ => {
+                let episode = AnomalyEpisode {
+                    start: anomaly.timestamp,
+                    end: anomaly.timestamp,
+                    samples: 1,
+                    peak: anomaly.clone(),
+                };
+                closed.extend(open.insert(key, episode));
+            }
+        }
+    }
+
+    closed.extend(open.into_values());
+    closed.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.peak.interface.cmp(&b.peak.interface)));
+    closed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config() -> AnomalyConfig {
+        AnomalyConfig {
+            sensitivity: 3.0,
+            smoothing: 0.1,
+            min_samples: 10,
+            history_days: 14,
+            min_change_bytes_per_sec: 1_000.0,
+        }
+    }
+
+    fn sample(day: u32, hour: u32, minute: u32, bytes_per_sec: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp: Local.with_ymd_and_hms(2024, 3, day, hour, minute, 0).unwrap(),
+            interface_name: "eth0".to_string(),
+            duration_secs: 60.0,
+            download_bps: bytes_per_sec,
+            upload_bps: 0.0,
+        }
+    }
+
+    /// A week of quiet nights (2:00) and busy evenings (20:00) with some jitter
+    fn history() -> Vec<BandwidthSample> {
+        let mut samples = Vec::new();
+        for day in 1..=7 {
+            for minute in 0..30 {
+                let jitter = (minute % 5) as f64 * 200.0;
+                samples.push(sample(day, 2, minute, 2_000.0 + jitter));
+                samples.push(sample(day, 20, minute, 500_000.0 + jitter * 10.0));
+            }
+        }
+        samples.sort_by_key(|s| s.timestamp);
+        samples
+    }
+
+    #[test]
+    fn test_baselines_are_per_hour() {
+        let model = BaselineModel::learned_from(config(), &history());
+        let night = Local.with_ymd_and_hms(2024, 3, 8, 2, 15, 0).unwrap();
+        let evening = Local.with_ymd_and_hms(2024, 3, 8, 20, 15, 0).unwrap();
+
+        // Evening traffic at night is a spike; the same rate in the evening is normal
+        let spike = model.check("eth0", night, 500_000.0).unwrap();
+        assert_eq!(spike.kind(), AnomalyKind::Spike);
+        assert!(model.check("eth0", evening, 500_000.0).is_none());
+
+        // A silent evening is a drop
+        assert_eq!(model.check("eth0", evening, 0.0).unwrap().kind(), AnomalyKind::Drop);
+
+        // Hours and interfaces without enough history are not judged
+        let morning = Local.with_ymd_and_hms(2024, 3, 8, 9, 0, 0).unwrap();
+        assert!(model.check("eth0", morning, 10_000_000.0).is_none());
+        assert!(model.check("wlan0", night, 10_000_000.0).is_none());
+    }
+
+    #[test]
+    fn test_small_changes_are_ignored() {
+        let model = BaselineModel::learned_from(config(), &history());
+        let night = Local.with_ymd_and_hms(2024, 3, 8, 2, 15, 0).unwrap();
+        // Many deviations above a steady baseline, but below the minimum change
+        assert!(model.check("eth0", night, 2_900.0).is_none());
+
+        let strict = BaselineModel::learned_from(
+            AnomalyConfig { sensitivity: 10_000.0, ..config() },
+            &history(),
+        );
+        assert!(strict.check("eth0", night, 500_000.0).is_none());
+    }
+
+    #[test]
+    fn test_find_anomalies_groups_episodes() {
+        let mut samples = history();
+        for minute in 0..3 {
+            samples.push(sample(8, 2, minute, 800_000.0));
+        }
+        samples.push(sample(8, 20, 0, 0.0));
+        let since = Local.with_ymd_and_hms(2024, 3, 8, 0, 0, 0).unwrap();
+
+        let anomalies = find_anomalies(config(), &samples, since);
+        assert_eq!(anomalies.len(), 4);
+
+        let episodes = episodes(&anomalies);
+        assert_eq!(episodes.len(), 2);
+        assert_eq!(episodes[0].samples, 3);
+        assert_eq!(episodes[0].peak.kind(), AnomalyKind::Spike);
+        assert_eq!(episodes[1].peak.kind(), AnomalyKind::Drop);
+    }
+}
@@ -0,0 +1,341 @@
+// Classification confidence: how sure the protocol analyzer is of each flow's protocol
+// Also counts, per interface and minute, the traffic no protocol was identified for, and
+// flags minutes where that unknown share rises well above its moving average
+
+use crate::analyzers::protocol_analyzer::SecurityFlag;
+use crate::models::{NetworkPacket, TransportProtocol};
+use chrono::{DateTime, Duration, Local, Timelike};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Minutes of traffic an interface needs before its unknown share is judged
+const BASELINE_MINUTES: u64 = 10;
+
+/// Minutes carrying less traffic than this are neither judged nor learned from
+const MIN_MINUTE_BYTES: u64 = 100_000;
+
+/// Weight of each minute in the moving average of the unknown share
+const SMOOTHING: f64 = 0.1;
+
+/// Finished minutes held between `take_samples` calls; older ones are dropped beyond this
+const MAX_BUFFERED_SAMPLES: usize = 10_000;
+
+/// How much the protocol identification of a flow can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ClassificationConfidence {
+    /// No protocol identified
+    #[default]
+    Unknown,
+    /// Well-known port, but the transport does not match the protocol
+    Low,
+    /// Well-known port between two privileged ports, or matched by the fallback port table
+    Medium,
+    /// Well-known port and transport, talking to an ephemeral client port
+    High,
+}
+
+impl ClassificationConfidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClassificationConfidence::Unknown => "unknown",
+            ClassificationConfidence::Low => "low",
+            ClassificationConfidence::Medium => "medium",
+            ClassificationConfidence::High => "high",
+        }
+    }
+}
+
+impl fmt::Display for ClassificationConfidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Classified and unclassified traffic of one interface over one minute (or, merged, longer)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassificationSample {
+    /// Start of the minute
+    pub timestamp: DateTime<Local>,
+    pub interface: String,
+    pub packets: u64,
+    pub bytes: u64,
+    /// TCP and UDP traffic no protocol was identified for
+    pub unknown_packets: u64,
+    pub unknown_bytes: u64,
+    /// Traffic identified with low confidence
+    pub low_confidence_bytes: u64,
+}
+
+impl ClassificationSample {
+    fn new(interface: &str, timestamp: DateTime<Local>) -> Self {
+        Self {
+            timestamp,
+            interface: interface.to_string(),
+            packets: 0,
+            bytes: 0,
+            unknown_packets: 0,
+            unknown_bytes: 0,
+            low_confidence_bytes: 0,
+        }
+    }
+
+    /// Fraction of bytes no protocol was identified for
+    pub fn unknown_share(&self) -> f64 {
+        if self.bytes == 0 {
+            0.0
+        } else {
+            self.unknown_bytes as f64 / self.bytes as f64
+        }
+    }
+
+    /// Fraction of bytes identified with low confidence
+    pub fn low_confidence_share(&self) -> f64 {
+        if self.bytes == 0 {
+            0.0
+        } else {
+            self.low_confidence_bytes as f64 / self.bytes as f64
+        }
+    }
+
+    /// Adds the counters of `other`, keeping this sample's time and interface
+    pub fn merge(&mut self, other: &ClassificationSample) {
+        self.packets += other.packets;
+        self.bytes += other.bytes;
+        self.unknown_packets += other.unknown_packets;
+        self.unknown_bytes += other.unknown_bytes;
+        self.low_confidence_bytes += other.low_confidence_bytes;
+    }
+}
+
+/// Moving average of an interface's unknown share
+#[derive(Debug, Clone, Copy, Default)]
+struct ShareBaseline {
+    mean: f64,
+    minutes: u64,
+}
+
+/// Per-interface minutes of classified and unclassified traffic
+///
+/// A minute is flagged when its unknown share is at least `surge_percent` percentage
+/// points above the interface's moving average.
+#[derive(Debug)]
+pub struct ClassificationTracker {
+    surge_percent: f64,
+    open: HashMap<String, ClassificationSample>,
+    baselines: HashMap<String, ShareBaseline>,
+    finished: Vec<ClassificationSample>,
+}
+
+impl Default for ClassificationTracker {
+    fn default() -> Self {
+        Self::new(20.0)
+    }
+}
+
+impl ClassificationTracker {
+    pub fn new(surge_percent: f64) -> Self {
+        Self {
+            surge_percent,
+            open: HashMap::new(),
+            baselines: HashMap::new(),
+            finished: Vec::new(),
+        }
+    }
+
+    /// Counts the packet in its interface's current minute; returns a flag when this
+    /// packet starts a new minute and the one it closes had an unusual unknown share
+    pub fn observe(&mut self, packet: &NetworkPacket, confidence: ClassificationConfidence) -> Option<SecurityFlag> {
+        let minute = start_of_minute(packet.timestamp);
+        let flag = match self.open.get(&packet.interface) {
+            Some(open) if minute > open.timestamp => {
+                let closed = self.open.remove(&packet.interface).expect("open minute exists");
+                self.close(closed)
+            }
+            _ => None,
+        };
+
+        let sample = self
+            .open
+            .entry(packet.interface.clone())
+            .or_insert_with(|| ClassificationSample::new(&packet.interface, minute));
+        sample.packets += 1;
+        sample.bytes += packet.size_bytes;
+        // ICMP and other transports carry no application protocol to identify
+        let classifiable = matches!(packet.transport_protocol, TransportProtocol::Tcp | TransportProtocol::Udp);
+        match confidence {
+            ClassificationConfidence::Unknown if classifiable => {
+                sample.unknown_packets += 1;
+                sample.unknown_bytes += packet.size_bytes;
+            }
+            ClassificationConfidence::Low => sample.low_confidence_bytes += packet.size_bytes,
+            _ => {}
+        }
+        flag
+    }
+
+    /// Minutes finished since the last call
+    pub fn take_samples(&mut self) -> Vec<ClassificationSample> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Every minute not yet handed out, the current ones included; used at end of capture
+    pub fn drain(&mut self) -> Vec<ClassificationSample> {
+        let open: Vec<_> = self.open.drain().map(|(_, sample)| sample).collect();
+        for sample in open {
+            self.close(sample);
+        }
+        self.take_samples()
+    }
+
+    /// Judges a finished minute against the interface's usual share, then learns from it
+    fn close(&mut self, sample: ClassificationSample) -> Option<SecurityFlag> {
+        let mut flag = None;
+        if sample.bytes >= MIN_MINUTE_BYTES {
+            let share = sample.unknown_share() * 100.0;
+            let baseline = self.baselines.entry(sample.interface.clone()).or_default();
+            if baseline.minutes >= BASELINE_MINUTES && share - baseline.mean >= self.surge_percent {
+                flag = Some(SecurityFlag::UnknownTrafficSurge(share, baseline.mean));
+            }
+
+            // A long surge only counts up to the flagging threshold, so it does not
+            // quickly become the new normal
+            let value = if baseline.minutes >= BASELINE_MINUTES {
+                share.min(baseline.mean + self.surge_percent)
+            } else {
+                share
+            };
+            baseline.mean = if baseline.minutes == 0 {
+                value
+            } else {
+                baseline.mean + SMOOTHING * (value - baseline.mean)
+            };
+            baseline.minutes += 1;
+        }
+
+        if self.finished.len() >= MAX_BUFFERED_SAMPLES {
+            self.finished.remove(0);
+        }
+        self.finished.push(sample);
+        flag
+    }
+}
+
+/// All samples added up, None when there are none
+pub fn total(samples: &[ClassificationSample]) -> Option<ClassificationSample> {
+    let (first, rest) = samples.split_first()?;
+    let mut total = first.clone();
+    for sample in rest {
+        total.merge(sample);
+    }
+    Some(total)
+}
+
+/// Merges samples into one per hour across interfaces, oldest first
+pub fn hourly(samples: &[ClassificationSample]) -> Vec<ClassificationSample> {
+    let mut hours: Vec<ClassificationSample> = Vec::new();
+    let mut sorted: Vec<&ClassificationSample> = samples.iter().collect();
+    sorted.sort_by_key(|sample| sample.timestamp);
+
+    for sample in sorted {
+        let hour = start_of_minute(sample.timestamp) - Duration::minutes(sample.timestamp.minute() as i64);
+        match hours.last_mut() {
+            Some(last) if last.timestamp == hour => last.merge(sample),
+            _ => {
+                let mut merged = ClassificationSample::new(&sample.interface, hour);
+                merged.merge(sample);
+                hours.push(merged);
+            }
+        }
+    }
+    hours
+}
+
+fn start_of_minute(at: DateTime<Local>) -> DateTime<Local> {
+    at - Duration::seconds(at.second() as i64) - Duration::nanoseconds(at.nanosecond() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol};
+    use chrono::TimeZone;
+
+    fn packet(minute: u32, size_bytes: u64) -> NetworkPacket {
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            size_bytes,
+            PacketProtocol::IPv4,
+            PacketDirection::Inbound,
+        );
+        packet.timestamp = Local.with_ymd_and_hms(2024, 3, 1, 10, minute, 30).unwrap();
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet
+    }
+
+    /// One minute of traffic with `unknown_percent` of its bytes unclassified
+    fn minute(tracker: &mut ClassificationTracker, minute: u32, unknown_percent: u64) -> Vec<SecurityFlag> {
+        (0..100)
+            .filter_map(|i| {
+                let confidence = if i < unknown_percent {
+                    ClassificationConfidence::Unknown
+                } else {
+                    ClassificationConfidence::High
+                };
+                tracker.observe(&packet(minute, 1_500), confidence)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_minutes_count_unknown_traffic() {
+        let mut tracker = ClassificationTracker::new(20.0);
+        minute(&mut tracker, 0, 10);
+        let mut icmp = packet(0, 1_000);
+        icmp.transport_protocol = TransportProtocol::Icmp;
+        tracker.observe(&icmp, ClassificationConfidence::Unknown);
+        tracker.observe(&packet(0, 500), ClassificationConfidence::Low);
+        assert!(tracker.take_samples().is_empty());
+
+        let samples = tracker.drain();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].packets, 102);
+        assert_eq!(samples[0].unknown_packets, 10);
+        assert_eq!(samples[0].unknown_bytes, 15_000);
+        assert_eq!(samples[0].low_confidence_bytes, 500);
+        assert_eq!(samples[0].timestamp, Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_surge_flagged_after_baseline() {
+        let mut tracker = ClassificationTracker::new(20.0);
+        // A surge before the baseline is established is not judged
+        let early: usize = (0..12).map(|m| minute(&mut tracker, m, if m == 3 { 90 } else { 5 }).len()).sum();
+        assert_eq!(early, 0);
+
+        // Closing minute 12 happens on the first packet of minute 13
+        assert!(minute(&mut tracker, 12, 60).is_empty());
+        let flags = minute(&mut tracker, 13, 5);
+        assert_eq!(flags.len(), 1);
+        let SecurityFlag::UnknownTrafficSurge(share, usual) = flags[0] else {
+            panic!("unexpected flag {:?}", flags[0]);
+        };
+        assert_eq!(share, 60.0);
+        assert!(usual < 20.0);
+
+        // Small rises stay quiet
+        assert!(minute(&mut tracker, 14, 15).is_empty());
+        assert!(minute(&mut tracker, 15, 5).is_empty());
+        assert_eq!(tracker.take_samples().len(), 15);
+    }
+
+    #[test]
+    fn test_hourly_merges_minutes() {
+        let mut tracker = ClassificationTracker::new(20.0);
+        minute(&mut tracker, 0, 10);
+        minute(&mut tracker, 59, 30);
+        let hours = hourly(&tracker.drain());
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].bytes, 300_000);
+        assert!((hours[0].unknown_share() - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,460 @@
+// Congestion detection: round-trip times that rise with throughput, the signature of
+// bufferbloat, where a full queue in the modem or router delays every packet behind it.
+// Each ping target's latency is held against its idle baseline, the round-trip time while
+// the link is quiet; minutes under load with latency well above it count as congested.
+// Stored minutes are judged by `analyze`, the live readings of `kw live` by `CongestionMonitor`
+
+use crate::collectors::ping_collector::{PingSample, PingStatus};
+use crate::config::CongestionConfig;
+use crate::storage::packet_storage::BandwidthSample;
+use chrono::{DateTime, Duration, Local};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Congested minutes of one target closer together than this form one episode
+const EPISODE_GAP_MINUTES: i64 = 5;
+
+/// Idle minutes a target needs for their median to be its baseline; with fewer, the
+/// lowest tenth of all its latencies stands in
+const MIN_IDLE_MINUTES: usize = 5;
+
+/// Minutes with both readings needed before a correlation is given
+const MIN_CORRELATED_MINUTES: usize = 10;
+
+/// Weight of each idle reading when the live baseline rises; it falls to lower readings at once
+const LIVE_BASELINE_SMOOTHING: f64 = 0.05;
+
+/// Latency added under load, graded the way common bufferbloat tests do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BufferbloatGrade {
+    /// Under 5 ms
+    A,
+    /// Under 30 ms
+    B,
+    /// Under 60 ms: calls and games start to suffer
+    C,
+    /// Under 200 ms
+    D,
+    F,
+}
+
+impl BufferbloatGrade {
+    pub fn from_added_latency(added_ms: f64) -> Self {
+        match added_ms {
+            ms if ms < 5.0 => Self::A,
+            ms if ms < 30.0 => Self::B,
+            ms if ms < 60.0 => Self::C,
+            ms if ms < 200.0 => Self::D,
+            _ => Self::F,
+        }
+    }
+}
+
+impl fmt::Display for BufferbloatGrade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A minute under load with one target's latency well above its idle baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct CongestedMinute {
+    pub target: String,
+    /// Start of the minute
+    pub timestamp: DateTime<Local>,
+    /// Throughput of the busiest interface
+    pub bytes_per_sec: f64,
+    pub latency_ms: f64,
+    pub baseline_ms: f64,
+}
+
+impl CongestedMinute {
+    pub fn added_latency_ms(&self) -> f64 {
+        self.latency_ms - self.baseline_ms
+    }
+}
+
+/// Consecutive congested minutes of one target
+#[derive(Debug, Clone, PartialEq)]
+pub struct CongestionEpisode {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub minutes: usize,
+    /// The minute with the most added latency
+    pub peak: CongestedMinute,
+}
+
+/// How one target's latency followed the load over a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetCongestion {
+    pub target: String,
+    /// Minutes with both a latency and a throughput reading
+    pub minutes: usize,
+    /// Typical latency while the link was idle
+    pub idle_latency_ms: Option<f64>,
+    /// Median latency of the minutes under load
+    pub loaded_latency_ms: Option<f64>,
+    /// Pearson correlation of throughput and latency; near 1 when latency follows the load
+    pub correlation: Option<f64>,
+    /// Congested minutes, oldest first
+    pub congested: Vec<CongestedMinute>,
+}
+
+impl TargetCongestion {
+    /// Latency the load added, from idle to the median under load
+    pub fn added_latency_ms(&self) -> Option<f64> {
+        Some((self.loaded_latency_ms? - self.idle_latency_ms?).max(0.0))
+    }
+
+    pub fn grade(&self) -> Option<BufferbloatGrade> {
+        self.added_latency_ms().map(BufferbloatGrade::from_added_latency)
+    }
+}
+
+/// One target's latency in a minute, with the load of that minute
+#[derive(Debug, Clone, Copy)]
+struct Reading {
+    timestamp: DateTime<Local>,
+    bytes_per_sec: f64,
+    latency_ms: f64,
+}
+
+/// Which throughputs count as load and which as idle, relative to the busiest seen
+#[derive(Debug, Clone, Copy)]
+struct LoadLevels {
+    load_bytes_per_sec: f64,
+    idle_bytes_per_sec: f64,
+}
+
+impl LoadLevels {
+    fn new(config: &CongestionConfig, peak_bytes_per_sec: f64) -> Self {
+        Self {
+            load_bytes_per_sec: (peak_bytes_per_sec * config.load_percent / 100.0).max(config.min_load_bytes_per_sec),
+            idle_bytes_per_sec: (peak_bytes_per_sec * config.idle_percent / 100.0).max(config.min_load_bytes_per_sec),
+        }
+    }
+
+    fn is_loaded(&self, bytes_per_sec: f64) -> bool {
+        bytes_per_sec >= self.load_bytes_per_sec
+    }
+
+    fn is_idle(&self, bytes_per_sec: f64) -> bool {
+        bytes_per_sec < self.idle_bytes_per_sec.min(self.load_bytes_per_sec)
+    }
+}
+
+/// Throughput of the busiest non-loopback interface per minute, keyed by minutes since
+/// the epoch; the busiest rather than the sum, so tunnels and bridges are not counted twice
+pub fn minute_load(samples: &[BandwidthSample]) -> BTreeMap<i64, f64> {
+    let mut load = BTreeMap::new();
+    for sample in samples.iter().filter(|s| !s.is_loopback()) {
+        // Samples are stamped at the end of their interval; the middle places them in their minute
+        let middle = sample.timestamp - Duration::milliseconds((sample.duration_secs * 500.0) as i64);
+        let busiest = load.entry(minute(middle)).or_insert(0.0_f64);
+        *busiest = busiest.max(sample.total_bps());
+    }
+    load
+}
+
+/// Each ping target's latency against the load in the same minutes, in order of first appearance
+pub fn analyze(config: &CongestionConfig, bandwidth: &[BandwidthSample], pings: &[PingSample]) -> Vec<TargetCongestion> {
+    let load = minute_load(bandwidth);
+    let peak = load.values().copied().fold(0.0, f64::max);
+    let levels = LoadLevels::new(config, peak);
+
+    let mut targets: Vec<(String, Vec<Reading>)> = Vec::new();
+    for ping in pings {
+        let (Some(latency_ms), Some(&bytes_per_sec)) = (ping.latency_ms, load.get(&minute(ping.timestamp))) else {
+            continue;
+        };
+        let reading = Reading { timestamp: ping.timestamp, bytes_per_sec, latency_ms };
+        match targets.iter_mut().find(|(target, _)| *target == ping.target) {
+            Some((_, readings)) => readings.push(reading),
+            None => targets.push((ping.target.clone(), vec![reading])),
+        }
+    }
+
+    targets
+        .into_iter()
+        .map(|(target, readings)| {
+            let latencies = |keep: &dyn Fn(f64) -> bool| -> Vec<f64> {
+                readings.iter().filter(|r| keep(r.bytes_per_sec)).map(|r| r.latency_ms).collect()
+            };
+            let idle = latencies(&|bps| levels.is_idle(bps));
+            let idle_latency_ms = if idle.len() >= MIN_IDLE_MINUTES {
+                percentile(idle, 0.5)
+            } else {
+                percentile(latencies(&|_| true), 0.1)
+            };
+            let loaded_latency_ms = percentile(latencies(&|bps| levels.is_loaded(bps)), 0.5);
+            let congested = match idle_latency_ms {
+                Some(baseline_ms) => readings
+                    .iter()
+                    .filter(|r| {
+                        levels.is_loaded(r.bytes_per_sec) && r.latency_ms - baseline_ms >= config.latency_increase_ms
+                    })
+                    .map(|r| CongestedMinute {
+                        target: target.clone(),
+                        timestamp: r.timestamp,
+                        bytes_per_sec: r.bytes_per_sec,
+                        latency_ms: r.latency_ms,
+                        baseline_ms,
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            TargetCongestion {
+                minutes: readings.len(),
+                idle_latency_ms,
+                loaded_latency_ms,
+                correlation: correlation(&readings.iter().map(|r| (r.bytes_per_sec, r.latency_ms)).collect::<Vec<_>>()),
+                congested,
+                target,
+            }
+        })
+        .collect()
+}
+
+/// Groups congested minutes into episodes per target, in order of start
+pub fn episodes(congested: &[CongestedMinute]) -> Vec<CongestionEpisode> {
+    let gap = Duration::minutes(EPISODE_GAP_MINUTES);
+    let mut open: HashMap<String, CongestionEpisode> = HashMap::new();
+    let mut closed = Vec::new();
+
+    for minute in congested {
+        match open.get_mut(&minute.target) {
+            Some(episode) if minute.timestamp - episode.end <= gap => {
+                episode.end = minute.timestamp;
+                episode.minutes += 1;
+                if minute.added_latency_ms() > episode.peak.added_latency_ms() {
+                    episode.peak = minute.clone();
+                }
+            }
+            _ => {
+                let episode = CongestionEpisode {
+                    start: minute.timestamp,
+                    end: minute.timestamp,
+                    minutes: 1,
+                    peak: minute.clone(),
+                };
+                closed.extend(open.insert(minute.target.clone(), episode));
+            }
+        }
+    }
+
+    closed.extend(open.into_values());
+    closed.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.peak.target.cmp(&b.peak.target)));
+    closed
+}
+
+/// A target whose latency is well above its idle baseline while the link is under load
+#[derive(Debug, Clone, PartialEq)]
+pub struct Congestion {
+    pub target: String,
+    pub latency_ms: f64,
+    pub baseline_ms: f64,
+}
+
+impl Congestion {
+    pub fn added_latency_ms(&self) -> f64 {
+        self.latency_ms - self.baseline_ms
+    }
+}
+
+/// Judges live readings: learns each target's idle latency while the link is quiet and
+/// flags the targets whose latency rises well above it under load
+#[derive(Debug, Clone)]
+pub struct CongestionMonitor {
+    config: CongestionConfig,
+    /// Busiest throughput seen so far
+    peak_bytes_per_sec: f64,
+    baselines: HashMap<String, f64>,
+}
+
+impl CongestionMonitor {
+    pub fn new(config: CongestionConfig) -> Self {
+        Self {
+            config,
+            peak_bytes_per_sec: 0.0,
+            baselines: HashMap::new(),
+        }
+    }
+
+    /// Targets congested at `bytes_per_sec`, the busiest interface's throughput, given their
+    /// current latency; none until a target's idle latency has been seen
+    pub fn observe(&mut self, bytes_per_sec: f64, status: &[PingStatus]) -> Vec<Congestion> {
+        self.peak_bytes_per_sec = self.peak_bytes_per_sec.max(bytes_per_sec);
+        let levels = LoadLevels::new(&self.config, self.peak_bytes_per_sec);
+        let mut congested = Vec::new();
+
+        for target in status {
+            let Some(latency_ms) = target.latency_ms.filter(|_| target.probes > 0) else {
+                continue;
+            };
+            if levels.is_idle(bytes_per_sec) {
+                let baseline = self.baselines.entry(target.target.clone()).or_insert(latency_ms);
+                *baseline = if latency_ms < *baseline {
+                    latency_ms
+                } else {
+                    *baseline + LIVE_BASELINE_SMOOTHING * (latency_ms - *baseline)
+                };
+            } else if let Some(&baseline_ms) = self.baselines.get(&target.target)
+                && levels.is_loaded(bytes_per_sec)
+                && latency_ms - baseline_ms >= self.config.latency_increase_ms
+            {
+                congested.push(Congestion {
+                    target: target.target.clone(),
+                    latency_ms,
+                    baseline_ms,
+                });
+            }
+        }
+        congested
+    }
+}
+
+fn minute(timestamp: DateTime<Local>) -> i64 {
+    timestamp.timestamp().div_euclid(60)
+}
+
+/// The value `fraction` of the way through the sorted `values`
+fn percentile(mut values: Vec<f64>, fraction: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let index = ((values.len() - 1) as f64 * fraction).round() as usize;
+    Some(values[index])
+}
+
+/// Pearson correlation of the pairs; none with too few or without variation
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < MIN_CORRELATED_MINUTES {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    // Rounding leaves a trace of variance in constant series
+    let varies = |variance: f64| variance > 1e-9 * n;
+    (varies(variance_x) && varies(variance_y)).then(|| covariance / (variance_x * variance_y).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 20, 0, 0).unwrap() + Duration::minutes(minute.into())
+    }
+
+    fn bandwidth(minute: u32, interface: &str, bytes_per_sec: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp: at(minute + 1),
+            interface_name: interface.to_string(),
+            duration_secs: 60.0,
+            download_bps: bytes_per_sec,
+            upload_bps: 0.0,
+        }
+    }
+
+    fn ping(minute: u32, target: &str, latency_ms: f64) -> PingSample {
+        PingSample {
+            timestamp: at(minute),
+            target: target.to_string(),
+            address: "1.1.1.1".to_string(),
+            sent: 30,
+            received: 30,
+            latency_ms: Some(latency_ms),
+            jitter_ms: Some(1.0),
+        }
+    }
+
+    /// Ten quiet minutes at 12 ms, then ten minutes of a 10 MB/s download; the external
+    /// target's latency climbs with it, the gateway's does not
+    fn evening() -> (Vec<BandwidthSample>, Vec<PingSample>) {
+        let mut samples = Vec::new();
+        let mut pings = Vec::new();
+        for minute in 0..20 {
+            let busy = minute >= 10;
+            samples.push(bandwidth(minute, "eth0", if busy { 10_000_000.0 } else { 20_000.0 }));
+            samples.push(bandwidth(minute, "lo", 50_000_000.0));
+            pings.push(ping(minute, "1.1.1.1", if busy { 90.0 + minute as f64 } else { 12.0 }));
+            pings.push(ping(minute, "gateway", 1.0));
+        }
+        (samples, pings)
+    }
+
+    #[test]
+    fn test_latency_rising_with_load_is_congestion() {
+        let (samples, pings) = evening();
+        let targets = analyze(&CongestionConfig::default(), &samples, &pings);
+        assert_eq!(targets.len(), 2);
+
+        let external = &targets[0];
+        assert_eq!(external.target, "1.1.1.1");
+        assert_eq!(external.minutes, 20);
+        assert_eq!(external.idle_latency_ms, Some(12.0));
+        assert_eq!(external.congested.len(), 10);
+        assert_eq!(external.grade(), Some(BufferbloatGrade::D));
+        assert!(external.correlation.unwrap() > 0.9);
+
+        let gateway = &targets[1];
+        assert!(gateway.congested.is_empty());
+        assert_eq!(gateway.grade(), Some(BufferbloatGrade::A));
+        assert_eq!(gateway.correlation, None, "no variation in latency");
+
+        let episodes = episodes(&external.congested);
+        assert_eq!(episodes.len(), 1);
+        assert_eq!((episodes[0].start, episodes[0].end, episodes[0].minutes), (at(10), at(19), 10));
+        assert_eq!(episodes[0].peak.latency_ms, 109.0);
+    }
+
+    #[test]
+    fn test_quiet_links_are_never_congested() {
+        let (_, pings) = evening();
+        let quiet: Vec<BandwidthSample> = (0..20).map(|minute| bandwidth(minute, "eth0", 50_000.0)).collect();
+        let targets = analyze(&CongestionConfig::default(), &quiet, &pings);
+        assert!(targets.iter().all(|t| t.congested.is_empty() && t.loaded_latency_ms.is_none()));
+        assert!(analyze(&CongestionConfig::default(), &[], &pings).is_empty());
+    }
+
+    #[test]
+    fn test_monitor_learns_idle_latency_first() {
+        let status = |latency_ms| {
+            vec![PingStatus {
+                target: "1.1.1.1".to_string(),
+                address: "1.1.1.1".to_string(),
+                probes: 30,
+                latency_ms: Some(latency_ms),
+                jitter_ms: None,
+                loss_percent: 0.0,
+            }]
+        };
+        let mut monitor = CongestionMonitor::new(CongestionConfig::default());
+        // Under load before any idle reading: nothing to compare with
+        assert!(monitor.observe(10_000_000.0, &status(100.0)).is_empty());
+        assert!(monitor.observe(10_000.0, &status(15.0)).is_empty());
+        assert!(monitor.observe(10_000.0, &status(12.0)).is_empty());
+
+        let congested = monitor.observe(9_000_000.0, &status(80.0));
+        assert_eq!(congested.len(), 1);
+        assert_eq!(congested[0].added_latency_ms(), 68.0);
+        assert!(monitor.observe(9_000_000.0, &status(30.0)).is_empty());
+    }
+
+    #[test]
+    fn test_grades() {
+        assert_eq!(BufferbloatGrade::from_added_latency(0.0), BufferbloatGrade::A);
+        assert_eq!(BufferbloatGrade::from_added_latency(45.0), BufferbloatGrade::C);
+        assert_eq!(BufferbloatGrade::from_added_latency(250.0), BufferbloatGrade::F);
+        assert_eq!(percentile(vec![3.0, 1.0, 2.0], 0.5), Some(2.0));
+    }
+}
@@ -0,0 +1,565 @@
+// Device discovery: friendly names and device types of LAN hosts from the mDNS and
+// SSDP announcements they multicast anyway, so nothing is sent to find them
+// A printer announcing "_ipp._tcp" or a TV advertising a UPnP MediaRenderer tells
+// us what it is; the name comes from its service instance or host name
+
+use crate::models::NetworkPacket;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Multicast DNS (Bonjour, Avahi)
+pub const MDNS_PORT: u16 = 5353;
+/// Simple Service Discovery Protocol (UPnP)
+pub const SSDP_PORT: u16 = 1900;
+
+/// Longest name kept from an announcement
+const MAX_NAME_LEN: usize = 64;
+/// DNS records read from one mDNS message; announcements carry a few dozen at most
+const MAX_RECORDS: usize = 64;
+
+const DNS_TYPE_A: u16 = 1;
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_TYPE_AAAA: u16 = 28;
+const DNS_TYPE_SRV: u16 = 33;
+
+/// What kind of device a host appears to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeviceType {
+    Printer,
+    Tv,
+    Phone,
+    Speaker,
+    Computer,
+    Router,
+    Unknown,
+}
+
+impl DeviceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceType::Printer => "printer",
+            DeviceType::Tv => "tv",
+            DeviceType::Phone => "phone",
+            DeviceType::Speaker => "speaker",
+            DeviceType::Computer => "computer",
+            DeviceType::Router => "router",
+            DeviceType::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "printer" => DeviceType::Printer,
+            "tv" => DeviceType::Tv,
+            "phone" => DeviceType::Phone,
+            "speaker" => DeviceType::Speaker,
+            "computer" => DeviceType::Computer,
+            "router" => DeviceType::Router,
+            _ => DeviceType::Unknown,
+        }
+    }
+
+    /// How telling the type is; a TV also announces itself as an AirPlay speaker,
+    /// so the more specific type wins when a host advertises several
+    fn rank(&self) -> u8 {
+        match self {
+            DeviceType::Printer | DeviceType::Tv | DeviceType::Phone => 3,
+            DeviceType::Speaker | DeviceType::Router => 2,
+            DeviceType::Computer => 1,
+            DeviceType::Unknown => 0,
+        }
+    }
+
+    /// The type suggested by a DNS-SD service such as `_ipp._tcp`
+    fn from_mdns_service(service: &str) -> Self {
+        let service = service.to_ascii_lowercase();
+        let name = service.split('.').next().unwrap_or("");
+        match name {
+            "_ipp" | "_ipps" | "_printer" | "_pdl-datastream" | "_scanner" | "_uscan" => DeviceType::Printer,
+            "_googlecast" | "_airplay" | "_androidtvremote2" | "_roku-rcp" | "_amzn-wplay" => DeviceType::Tv,
+            "_apple-mobdev2" | "_apple-pairable" => DeviceType::Phone,
+            "_raop" | "_spotify-connect" | "_sonos" => DeviceType::Speaker,
+            "_smb" | "_afpovertcp" | "_ssh" | "_sftp-ssh" | "_rfb" | "_workstation" | "_adisk" => DeviceType::Computer,
+            _ => DeviceType::Unknown,
+        }
+    }
+
+    /// The type suggested by an Apple `model=` TXT value, e.g. `iPhone14,2` or `MacBookPro18,1`
+    fn from_model(model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        if model.starts_with("iphone") || model.starts_with("ipad") || model.starts_with("ipod") {
+            DeviceType::Phone
+        } else if model.starts_with("appletv") {
+            DeviceType::Tv
+        } else if model.starts_with("audioaccessory") || model.starts_with("homepod") {
+            DeviceType::Speaker
+        } else if model.starts_with("mac") || model.starts_with("imac") {
+            DeviceType::Computer
+        } else {
+            DeviceType::Unknown
+        }
+    }
+
+    /// The type suggested by a UPnP device or service URN in an SSDP `NT` or `ST` header
+    fn from_upnp_urn(urn: &str) -> Self {
+        let urn = urn.to_ascii_lowercase();
+        if urn.contains(":printer:") || urn.contains(":printbasic:") {
+            DeviceType::Printer
+        } else if urn.contains(":mediarenderer:") || urn.contains("dial-multiscreen-org") || urn.contains(":tvdevice:") {
+            DeviceType::Tv
+        } else if urn.contains(":zoneplayer:") {
+            DeviceType::Speaker
+        } else if urn.contains(":internetgatewaydevice:") || urn.contains(":wanipconnection:") {
+            DeviceType::Router
+        } else {
+            DeviceType::Unknown
+        }
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Protocol a device announced itself over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiscoverySource {
+    Mdns,
+    Ssdp,
+}
+
+impl DiscoverySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscoverySource::Mdns => "mDNS",
+            DiscoverySource::Ssdp => "SSDP",
+        }
+    }
+
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "SSDP" => DiscoverySource::Ssdp,
+            _ => DiscoverySource::Mdns,
+        }
+    }
+}
+
+/// What one mDNS or SSDP message says about the host that sent it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceAnnouncement {
+    pub name: Option<String>,
+    pub device_type: DeviceType,
+    pub source: DiscoverySource,
+}
+
+/// Reads a device announcement from a UDP payload on the mDNS or SSDP port
+pub fn parse_announcement(source_port: u16, dest_port: u16, payload: &[u8]) -> Option<DeviceAnnouncement> {
+    if source_port == MDNS_PORT || dest_port == MDNS_PORT {
+        parse_mdns(payload)
+    } else if source_port == SSDP_PORT || dest_port == SSDP_PORT {
+        parse_ssdp(payload)
+    } else {
+        None
+    }
+}
+
+/// Name and type from an mDNS response
+///
+/// The friendly name is a Chromecast's `fn=` TXT value, else the service instance name
+/// ("Office Printer" of `Office Printer._ipp._tcp.local`), else the host name of an
+/// address record. Queries describe the asker's interests, not the asker, and are ignored.
+pub fn parse_mdns(payload: &[u8]) -> Option<DeviceAnnouncement> {
+    let header = payload.get(..12)?;
+    let is_response = header[2] & 0x80 != 0;
+    if !is_response {
+        return None;
+    }
+    let count = |offset: usize| u16::from_be_bytes([header[offset], header[offset + 1]]) as usize;
+    let questions = count(4);
+    let records = (count(6) + count(8) + count(10)).min(MAX_RECORDS);
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (_, next) = read_dns_name(payload, offset)?;
+        offset = next + 4;
+    }
+
+    let mut friendly_name = None;
+    let mut instance_name = None;
+    let mut host_name = None;
+    let mut device_type = DeviceType::Unknown;
+    let mut model_type = DeviceType::Unknown;
+    for _ in 0..records {
+        let (owner, next) = read_dns_name(payload, offset)?;
+        let fixed = payload.get(next..next + 10)?;
+        let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let data_len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let data_start = next + 10;
+        let data = payload.get(data_start..data_start + data_len)?;
+        offset = data_start + data_len;
+
+        match record_type {
+            DNS_TYPE_PTR => {
+                let Some((target, _)) = read_dns_name(payload, data_start) else {
+                    continue;
+                };
+                let service_type = DeviceType::from_mdns_service(&owner);
+                if service_type.rank() > device_type.rank() {
+                    device_type = service_type;
+                }
+                // Instances are "<name>.<service>.<proto>.local"; the service enumeration
+                // record points at service types, which start with an underscore
+                if let Some(instance) = target.split('.').next().filter(|label| !label.starts_with('_')) {
+                    instance_name.get_or_insert_with(|| instance.to_string());
+                }
+            }
+            DNS_TYPE_SRV => {
+                let service_type = DeviceType::from_mdns_service(owner.split_once('.').map_or("", |(_, rest)| rest));
+                if service_type.rank() > device_type.rank() {
+                    device_type = service_type;
+                }
+                if let Some(instance) = owner.split('.').next().filter(|label| !label.starts_with('_')) {
+                    instance_name.get_or_insert_with(|| instance.to_string());
+                }
+            }
+            DNS_TYPE_TXT => {
+                for (key, value) in txt_entries(data) {
+                    match key.to_ascii_lowercase().as_str() {
+                        "fn" if !value.is_empty() => friendly_name = Some(value),
+                        "model" | "md" => {
+                            let hinted = DeviceType::from_model(&value);
+                            if hinted != DeviceType::Unknown {
+                                model_type = hinted;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            DNS_TYPE_A | DNS_TYPE_AAAA => {
+                if let Some(host) = owner.strip_suffix(".local").filter(|host| !host.is_empty()) {
+                    host_name.get_or_insert_with(|| host.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The hardware model is more telling than the services a device happens to offer
+    if model_type != DeviceType::Unknown {
+        device_type = model_type;
+    }
+    let name = friendly_name.or(instance_name).or(host_name).map(clean_name);
+    if name.is_none() && device_type == DeviceType::Unknown {
+        return None;
+    }
+    Some(DeviceAnnouncement { name, device_type, source: DiscoverySource::Mdns })
+}
+
+/// Name and type from an SSDP `NOTIFY` or search response
+///
+/// SSDP carries no friendly name (that is in the description XML, which would mean
+/// contacting the device), so the product named in the `SERVER` header stands in for it.
+/// Searches (`M-SEARCH`) come from control points looking for others and are ignored.
+pub fn parse_ssdp(payload: &[u8]) -> Option<DeviceAnnouncement> {
+    let text = std::str::from_utf8(payload).ok()?;
+    let mut lines = text.lines();
+    let start = lines.next()?;
+    if !start.starts_with("NOTIFY ") && !start.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+
+    let mut device_type = DeviceType::Unknown;
+    let mut name = None;
+    for line in lines {
+        let Some((header, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match header.trim().to_ascii_uppercase().as_str() {
+            "NT" | "ST" => {
+                let hinted = DeviceType::from_upnp_urn(value);
+                if hinted.rank() > device_type.rank() {
+                    device_type = hinted;
+                }
+            }
+            "SERVER" => name = server_product(value),
+            _ => {}
+        }
+    }
+
+    if name.is_none() && device_type == DeviceType::Unknown {
+        return None;
+    }
+    Some(DeviceAnnouncement { name, device_type, source: DiscoverySource::Ssdp })
+}
+
+/// The product of a `SERVER: <os>/<version> UPnP/<version> <product>/<version>` header
+fn server_product(server: &str) -> Option<String> {
+    let mut tokens = server.split_whitespace();
+    tokens.find(|token| token.to_ascii_lowercase().starts_with("upnp/"))?;
+    let product: Vec<&str> = tokens.map(|token| token.split('/').next().unwrap_or(token)).collect();
+    let product = product.join(" ");
+    (!product.is_empty()).then(|| clean_name(product))
+}
+
+/// `key=value` strings of a TXT record
+fn txt_entries(data: &[u8]) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some(&len) = data.get(offset) {
+        let Some(entry) = data.get(offset + 1..offset + 1 + len as usize) else {
+            break;
+        };
+        if let Some((key, value)) = String::from_utf8_lossy(entry).split_once('=') {
+            entries.push((key.to_string(), value.to_string()));
+        }
+        offset += 1 + len as usize;
+    }
+    entries
+}
+
+/// A dotted DNS name starting at `offset`, following compression pointers, and the offset
+/// just past it in the message
+fn read_dns_name(message: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+    // Pointers may only point backwards, but a malformed message could loop anyway
+    for _ in 0..32 {
+        let len = *message.get(position)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(position + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            let pointer = (len & 0x3f) << 8 | *message.get(position + 1)? as usize;
+            end.get_or_insert(position + 2);
+            position = pointer;
+            continue;
+        }
+        let label = message.get(position + 1..position + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        position += 1 + len;
+    }
+    None
+}
+
+/// Printable, length-limited form of an announced name
+fn clean_name(name: String) -> String {
+    let cleaned: String = name.chars().filter(|c| !c.is_control()).take(MAX_NAME_LEN).collect();
+    cleaned.trim().to_string()
+}
+
+/// A LAN host identified from its announcements
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveredDevice {
+    pub address: IpAddr,
+    /// Interface the announcement was captured on
+    pub interface: String,
+    pub name: Option<String>,
+    pub device_type: DeviceType,
+    pub source: DiscoverySource,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+}
+
+impl DiscoveredDevice {
+    /// "Office Printer, printer", shown next to the device's address
+    pub fn label(&self) -> String {
+        match (&self.name, self.device_type) {
+            (Some(name), DeviceType::Unknown) => name.clone(),
+            (Some(name), device_type) => format!("{name}, {device_type}"),
+            (None, device_type) => device_type.to_string(),
+        }
+    }
+}
+
+/// Devices discovered so far, by address
+#[derive(Debug, Default)]
+pub struct DeviceDirectory {
+    devices: HashMap<IpAddr, DiscoveredDevice>,
+    /// Addresses whose name or type changed since the last `take_updates`
+    updated: Vec<IpAddr>,
+}
+
+impl DeviceDirectory {
+    /// Records the packet's announcement, if it carries one
+    /// Returns the device when the announcement told us something new about it
+    pub fn observe(&mut self, packet: &NetworkPacket) -> Option<&DiscoveredDevice> {
+        let announcement = packet.announcement.as_ref()?;
+        let address = packet.source_addr?;
+
+        let mut changed = false;
+        let device = self.devices.entry(address).or_insert_with(|| {
+            changed = true;
+            DiscoveredDevice {
+                address,
+                interface: packet.interface.clone(),
+                name: None,
+                device_type: DeviceType::Unknown,
+                source: announcement.source,
+                first_seen: packet.timestamp,
+                last_seen: packet.timestamp,
+            }
+        });
+        device.last_seen = packet.timestamp;
+        // mDNS names are chosen by the owner; SSDP only gives a product name
+        let better_name = match (&device.name, &announcement.name) {
+            (None, Some(_)) => true,
+            (Some(_), Some(_)) => announcement.source == DiscoverySource::Mdns && device.source == DiscoverySource::Ssdp,
+            _ => false,
+        };
+        if better_name {
+            device.name = announcement.name.clone();
+            device.source = announcement.source;
+            changed = true;
+        }
+        if announcement.device_type.rank() > device.device_type.rank() {
+            device.device_type = announcement.device_type;
+            changed = true;
+        }
+
+        if !changed {
+            return None;
+        }
+        if !self.updated.contains(&address) {
+            self.updated.push(address);
+        }
+        self.devices.get(&address)
+    }
+
+    /// Every device, by address
+    pub fn devices(&self) -> Vec<&DiscoveredDevice> {
+        let mut devices: Vec<_> = self.devices.values().collect();
+        devices.sort_by_key(|device| device.address);
+        devices
+    }
+
+    /// Devices found or renamed since the last call, for storage
+    pub fn take_updates(&mut self) -> Vec<DiscoveredDevice> {
+        std::mem::take(&mut self.updated)
+            .into_iter()
+            .filter_map(|address| self.devices.get(&address).cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol};
+
+    fn dns_name(name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for label in name.split('.') {
+            bytes.push(label.len() as u8);
+            bytes.extend_from_slice(label.as_bytes());
+        }
+        bytes.push(0);
+        bytes
+    }
+
+    fn record(owner: &str, record_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = dns_name(owner);
+        bytes.extend_from_slice(&record_type.to_be_bytes());
+        bytes.extend_from_slice(&[0x80, 0x01, 0, 0, 0x11, 0x94]);
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn mdns_response(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut message = vec![0, 0, 0x84, 0, 0, 0];
+        message.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        for record in records {
+            message.extend_from_slice(record);
+        }
+        message
+    }
+
+    #[test]
+    fn test_mdns_printer() {
+        let message = mdns_response(&[
+            record("_ipp._tcp.local", DNS_TYPE_PTR, &dns_name("Office Printer._ipp._tcp.local")),
+            record("brother-hl.local", DNS_TYPE_A, &[192, 168, 1, 40]),
+        ]);
+        let announcement = parse_mdns(&message).unwrap();
+        assert_eq!(announcement.name.as_deref(), Some("Office Printer"));
+        assert_eq!(announcement.device_type, DeviceType::Printer);
+    }
+
+    #[test]
+    fn test_mdns_model_and_friendly_name() {
+        let mut txt = vec![14];
+        txt.extend_from_slice(b"fn=Living Room");
+        let message = mdns_response(&[
+            record("_googlecast._tcp.local", DNS_TYPE_PTR, &dns_name("Chromecast-abc._googlecast._tcp.local")),
+            record("Chromecast-abc._googlecast._tcp.local", DNS_TYPE_TXT, &txt),
+        ]);
+        let announcement = parse_mdns(&message).unwrap();
+        assert_eq!(announcement.name.as_deref(), Some("Living Room"));
+        assert_eq!(announcement.device_type, DeviceType::Tv);
+
+        let mut txt = vec![15];
+        txt.extend_from_slice(b"model=iPhone14,2");
+        let message = mdns_response(&[
+            record("_companion-link._tcp.local", DNS_TYPE_PTR, &dns_name("Sam's iPhone._companion-link._tcp.local")),
+            record("Sam's iPhone._device-info._tcp.local", DNS_TYPE_TXT, &txt),
+        ]);
+        let announcement = parse_mdns(&message).unwrap();
+        assert_eq!(announcement.name.as_deref(), Some("Sam's iPhone"));
+        assert_eq!(announcement.device_type, DeviceType::Phone);
+    }
+
+    #[test]
+    fn test_mdns_queries_ignored() {
+        let mut query = mdns_response(&[]);
+        query[2] = 0;
+        assert!(parse_mdns(&query).is_none());
+        assert!(parse_mdns(&[0x84; 5]).is_none());
+    }
+
+    #[test]
+    fn test_ssdp_notify() {
+        let notify = b"NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+            NT: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\
+            SERVER: Linux/4.9 UPnP/1.0 Roku/9.4\r\n\r\n";
+        let announcement = parse_ssdp(notify).unwrap();
+        assert_eq!(announcement.name.as_deref(), Some("Roku"));
+        assert_eq!(announcement.device_type, DeviceType::Tv);
+        assert_eq!(announcement.source, DiscoverySource::Ssdp);
+
+        assert!(parse_ssdp(b"M-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_directory_merges_announcements() {
+        let mut directory = DeviceDirectory::default();
+        let mut packet = NetworkPacket::new("eth0".to_string(), 300, PacketProtocol::IPv4, PacketDirection::Inbound);
+        packet.source_addr = Some("192.168.1.50".parse().unwrap());
+        packet.announcement = Some(DeviceAnnouncement {
+            name: Some("Samsung".to_string()),
+            device_type: DeviceType::Unknown,
+            source: DiscoverySource::Ssdp,
+        });
+        assert!(directory.observe(&packet).is_some());
+        assert!(directory.observe(&packet).is_none());
+
+        packet.announcement = Some(DeviceAnnouncement {
+            name: Some("Bedroom TV".to_string()),
+            device_type: DeviceType::Tv,
+            source: DiscoverySource::Mdns,
+        });
+        let device = directory.observe(&packet).unwrap();
+        assert_eq!(device.label(), "Bedroom TV, tv");
+        assert_eq!(directory.take_updates().len(), 1);
+        assert!(directory.take_updates().is_empty());
+    }
+}
@@ -0,0 +1,353 @@
+// Duplicate packet detection: the same IP packet seen more than once on an interface
+// Copies arrive within milliseconds when frames circle between switches, when a router sends
+// packets back the way they came, or when a mirror port duplicates traffic. Counted per
+// interface and minute, with the TTL of each copy compared to the first so routing loops,
+// which lower it, stand apart from switching loops, which do not
+
+use crate::models::NetworkPacket;
+use chrono::{DateTime, Duration, Local, Timelike};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+
+/// A packet seen again within this long of its first copy counts as a duplicate; shorter than
+/// any TCP retransmission timeout, so resent segments are not mistaken for copies
+const DUPLICATE_WINDOW_MS: i64 = 100;
+
+/// Packets remembered per interface; the oldest are forgotten early beyond this
+const MAX_RECENT_PACKETS: usize = 100_000;
+
+/// Bytes of the IP payload hashed into a fingerprint: transport header and some data
+const FINGERPRINT_BYTES: usize = 128;
+
+/// Finished minutes held between `take_samples` calls; older ones are dropped beyond this
+const MAX_BUFFERED_SAMPLES: usize = 10_000;
+
+/// Copies of one packet with its TTL unchanged that point to a switching loop
+const LOOP_COPIES: u32 = 4;
+
+/// TTL lost between the first and a later copy that points to a routing loop; a drop of one
+/// is a packet routed back out of the interface it came in on
+const LOOP_TTL_DROP: u8 = 2;
+
+/// Share of duplicated packets worth reporting, and the packets a minute needs to judge it
+const DUPLICATE_PERCENT: f64 = 1.0;
+const MIN_MINUTE_PACKETS: u64 = 100;
+
+/// Hash identifying an IP packet across copies (FNV-1a over the addresses, the IPv4
+/// identification, the payload length and the start of the payload)
+pub fn packet_fingerprint(source: IpAddr, dest: IpAddr, identification: u16, payload: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for address in [source, dest] {
+        match address {
+            IpAddr::V4(address) => feed(&address.octets()),
+            IpAddr::V6(address) => feed(&address.octets()),
+        }
+    }
+    feed(&identification.to_be_bytes());
+    feed(&(payload.len() as u32).to_be_bytes());
+    feed(&payload[..payload.len().min(FINGERPRINT_BYTES)]);
+    hash
+}
+
+/// Duplicate counts of one interface over one minute (or, merged, longer)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateSample {
+    /// Start of the minute
+    pub timestamp: DateTime<Local>,
+    pub interface: String,
+    /// IP packets seen, copies included
+    pub packets: u64,
+    /// Copies beyond the first of each packet
+    pub duplicates: u64,
+    /// Copies whose TTL differed from the first copy's
+    pub ttl_changed: u64,
+    /// Most TTL one packet lost between its first and a later copy
+    pub max_ttl_drop: u8,
+    /// Most times one packet was seen, 0 when none was seen twice
+    pub max_copies: u32,
+    /// Addresses of the packet seen most often
+    pub source: Option<String>,
+    pub destination: Option<String>,
+}
+
+impl DuplicateSample {
+    fn new(interface: &str, timestamp: DateTime<Local>) -> Self {
+        Self {
+            timestamp,
+            interface: interface.to_string(),
+            packets: 0,
+            duplicates: 0,
+            ttl_changed: 0,
+            max_ttl_drop: 0,
+            max_copies: 0,
+            source: None,
+            destination: None,
+        }
+    }
+
+    /// Percent of packets that were copies
+    pub fn duplicate_percent(&self) -> f64 {
+        if self.packets == 0 {
+            0.0
+        } else {
+            self.duplicates as f64 / self.packets as f64 * 100.0
+        }
+    }
+
+    /// Adds the counters of `other`, keeping this sample's time and interface
+    pub fn merge(&mut self, other: &DuplicateSample) {
+        self.packets += other.packets;
+        self.duplicates += other.duplicates;
+        self.ttl_changed += other.ttl_changed;
+        self.max_ttl_drop = self.max_ttl_drop.max(other.max_ttl_drop);
+        if other.max_copies > self.max_copies {
+            self.max_copies = other.max_copies;
+            self.source = other.source.clone();
+            self.destination = other.destination.clone();
+        }
+    }
+
+    /// What the counts point to, the most serious cause first
+    pub fn finding(&self) -> Option<DuplicateFinding> {
+        if self.max_ttl_drop >= LOOP_TTL_DROP {
+            Some(DuplicateFinding::RoutingLoop(self.max_ttl_drop))
+        } else if self.max_copies >= LOOP_COPIES {
+            Some(DuplicateFinding::SwitchingLoop(self.max_copies))
+        } else if self.packets >= MIN_MINUTE_PACKETS && self.duplicate_percent() >= DUPLICATE_PERCENT {
+            Some(DuplicateFinding::Duplicates(self.duplicate_percent()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Likely cause of duplicated packets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicateFinding {
+    /// Copies came back with their TTL lowered by this much: routers forwarding in a circle
+    RoutingLoop(u8),
+    /// One packet was seen this many times with its TTL unchanged: frames circling between switches
+    SwitchingLoop(u32),
+    /// This percent of packets arrived twice: a mirror port, a bridged capture or a faulty link
+    Duplicates(f64),
+}
+
+impl fmt::Display for DuplicateFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DuplicateFinding::RoutingLoop(drop) => {
+                write!(f, "possible routing loop, copies came back with their TTL {drop} lower")
+            }
+            DuplicateFinding::SwitchingLoop(copies) => {
+                write!(f, "possible switching loop, one packet seen {copies} times with its TTL unchanged")
+            }
+            DuplicateFinding::Duplicates(percent) => {
+                write!(f, "{percent:.1}% of packets seen twice, check for mirrored ports or bridged captures")
+            }
+        }
+    }
+}
+
+/// First copy of a recently seen packet
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    at: DateTime<Local>,
+    ttl: u8,
+    copies: u32,
+}
+
+/// Recently seen packets and the current minute of one interface
+#[derive(Debug, Default)]
+struct InterfaceState {
+    recent: HashMap<u64, Seen>,
+    /// Fingerprints in the order they were first seen, for forgetting them
+    order: VecDeque<(DateTime<Local>, u64)>,
+    open: Option<DuplicateSample>,
+}
+
+impl InterfaceState {
+    /// Forgets packets first seen longer than the duplicate window before `now`
+    fn expire(&mut self, now: DateTime<Local>) {
+        let window = Duration::milliseconds(DUPLICATE_WINDOW_MS);
+        while let Some(&(at, fingerprint)) = self.order.front() {
+            if now - at <= window && self.order.len() < MAX_RECENT_PACKETS {
+                break;
+            }
+            self.order.pop_front();
+            if self.recent.get(&fingerprint).is_some_and(|seen| seen.at == at) {
+                self.recent.remove(&fingerprint);
+            }
+        }
+    }
+}
+
+/// Per-interface minutes of duplicated packets
+#[derive(Debug, Default)]
+pub struct DuplicateTracker {
+    interfaces: HashMap<String, InterfaceState>,
+    finished: Vec<DuplicateSample>,
+}
+
+impl DuplicateTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the packet in its interface's current minute, as a copy when the same packet
+    /// was seen there within the duplicate window
+    pub fn observe(&mut self, packet: &NetworkPacket) {
+        let Some(ip) = packet.ip else {
+            return;
+        };
+        if !self.interfaces.contains_key(&packet.interface) {
+            self.interfaces.insert(packet.interface.clone(), InterfaceState::default());
+        }
+        let state = self.interfaces.get_mut(&packet.interface).expect("interface state exists");
+
+        let minute = start_of_minute(packet.timestamp);
+        if state.open.as_ref().is_some_and(|open| minute > open.timestamp)
+            && let Some(closed) = state.open.take()
+        {
+            push_finished(&mut self.finished, closed);
+        }
+        state.expire(packet.timestamp);
+        let sample = state.open.get_or_insert_with(|| DuplicateSample::new(&packet.interface, minute));
+        sample.packets += 1;
+        match state.recent.get_mut(&ip.fingerprint) {
+            Some(seen) => {
+                seen.copies += 1;
+                sample.duplicates += 1;
+                if ip.ttl != seen.ttl {
+                    sample.ttl_changed += 1;
+                    sample.max_ttl_drop = sample.max_ttl_drop.max(seen.ttl.saturating_sub(ip.ttl));
+                }
+                if seen.copies > sample.max_copies {
+                    sample.max_copies = seen.copies;
+                    sample.source = packet.source_addr.map(|address| address.to_string());
+                    sample.destination = packet.dest_addr.map(|address| address.to_string());
+                }
+            }
+            None => {
+                state.recent.insert(ip.fingerprint, Seen { at: packet.timestamp, ttl: ip.ttl, copies: 1 });
+                state.order.push_back((packet.timestamp, ip.fingerprint));
+            }
+        }
+    }
+
+    /// Minutes finished since the last call
+    pub fn take_samples(&mut self) -> Vec<DuplicateSample> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Every minute not yet handed out, the current ones included; used at end of capture
+    pub fn drain(&mut self) -> Vec<DuplicateSample> {
+        for state in self.interfaces.values_mut() {
+            if let Some(open) = state.open.take() {
+                push_finished(&mut self.finished, open);
+            }
+        }
+        self.take_samples()
+    }
+}
+
+fn push_finished(finished: &mut Vec<DuplicateSample>, sample: DuplicateSample) {
+    if finished.len() >= MAX_BUFFERED_SAMPLES {
+        finished.remove(0);
+    }
+    finished.push(sample);
+}
+
+/// Samples added up per interface, busiest first
+pub fn per_interface(samples: &[DuplicateSample]) -> Vec<DuplicateSample> {
+    let mut totals: Vec<DuplicateSample> = Vec::new();
+    for sample in samples {
+        match totals.iter_mut().find(|total| total.interface == sample.interface) {
+            Some(total) => total.merge(sample),
+            None => totals.push(sample.clone()),
+        }
+    }
+    totals.sort_by(|a, b| b.packets.cmp(&a.packets).then_with(|| a.interface.cmp(&b.interface)));
+    totals
+}
+
+fn start_of_minute(at: DateTime<Local>) -> DateTime<Local> {
+    at - Duration::seconds(at.second() as i64) - Duration::nanoseconds(at.nanosecond() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{IpHeader, PacketDirection, PacketProtocol};
+    use chrono::TimeZone;
+    use std::net::Ipv4Addr;
+
+    fn packet(id: u16, ttl: u8, at_ms: i64) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 100, PacketProtocol::IPv4, PacketDirection::Inbound);
+        let source = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+        let dest = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 255));
+        packet.timestamp = Local.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap() + Duration::milliseconds(at_ms);
+        packet.source_addr = Some(source);
+        packet.dest_addr = Some(dest);
+        packet.ip = Some(IpHeader { ttl, fingerprint: packet_fingerprint(source, dest, id, b"payload"), fragment: None });
+        packet
+    }
+
+    #[test]
+    fn test_copies_within_window_count_as_duplicates() {
+        let mut tracker = DuplicateTracker::new();
+        for i in 0..200 {
+            tracker.observe(&packet(i, 64, i64::from(i) * 10));
+            match i {
+                // Two copies of packet 5 and one of packet 6 shortly after the first
+                6 => {
+                    tracker.observe(&packet(5, 64, 55));
+                    tracker.observe(&packet(5, 64, 60));
+                    tracker.observe(&packet(6, 64, 65));
+                }
+                // The same packet again, but well after the window
+                90 => tracker.observe(&packet(7, 64, 900)),
+                _ => {}
+            }
+        }
+
+        let samples = tracker.drain();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].packets, 204);
+        assert_eq!(samples[0].duplicates, 3);
+        assert_eq!(samples[0].max_copies, 3);
+        assert_eq!(samples[0].source.as_deref(), Some("10.0.0.5"));
+        assert_eq!(samples[0].finding(), Some(DuplicateFinding::Duplicates(3.0 / 204.0 * 100.0)));
+        assert!(tracker.drain().is_empty());
+    }
+
+    #[test]
+    fn test_loops_told_apart_by_ttl() {
+        let mut tracker = DuplicateTracker::new();
+        // Circling between switches: same TTL every time
+        for copy in 0..6 {
+            tracker.observe(&packet(1, 64, copy));
+        }
+        let switching = tracker.drain();
+        assert_eq!(switching[0].finding(), Some(DuplicateFinding::SwitchingLoop(6)));
+
+        // Circling between routers: one less each hop
+        for hop in 0..3 {
+            tracker.observe(&packet(2, 64 - hop as u8, 60_000 + hop));
+        }
+        let routing = tracker.drain();
+        assert_eq!(routing[0].ttl_changed, 2);
+        assert_eq!(routing[0].finding(), Some(DuplicateFinding::RoutingLoop(2)));
+
+        let totals = per_interface(&[switching[0].clone(), routing[0].clone()]);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].duplicates, 7);
+        assert_eq!(totals[0].max_copies, 6);
+    }
+}
@@ -0,0 +1,116 @@
+// Payload entropy: Shannon entropy of packet payloads, sampled per flow
+// Compressed or encrypted data sits close to 8 bits/byte, plain text and most
+// application protocols well below, so high entropy on a port that should not
+// carry encrypted traffic hints at a tunnel or exfiltration
+
+/// Payloads shorter than this are skipped; a few bytes say nothing about the data
+pub const MIN_PAYLOAD_BYTES: usize = 32;
+/// Only the start of each payload is scored, bounding the per-packet cost
+pub const MAX_SAMPLE_BYTES: usize = 512;
+/// Payload-bearing packets scored per flow before sampling stops
+pub const SAMPLES_PER_FLOW: u32 = 16;
+/// Samples needed before a flow's entropy is trusted
+pub const MIN_FLOW_SAMPLES: u32 = 4;
+/// Mean entropy (bits/byte) above which a flow counts as high entropy
+pub const HIGH_ENTROPY_THRESHOLD: f64 = 7.2;
+
+/// Shannon entropy of `bytes` in bits per byte (0.0 to 8.0)
+pub fn shannon_entropy(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u32; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Entropy of a transport payload, `None` when it is too short to score
+pub fn sample_payload_entropy(payload: &[u8]) -> Option<f64> {
+    if payload.len() < MIN_PAYLOAD_BYTES {
+        return None;
+    }
+    Some(shannon_entropy(&payload[..payload.len().min(MAX_SAMPLE_BYTES)]))
+}
+
+/// Running mean of the sampled payload entropy of one flow
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlowEntropy {
+    pub samples: u32,
+    pub mean: f64,
+}
+
+impl FlowEntropy {
+    /// Adds a sample; returns false once the flow has been sampled enough
+    pub fn add_sample(&mut self, entropy: f64) -> bool {
+        if self.samples >= SAMPLES_PER_FLOW {
+            return false;
+        }
+        self.samples += 1;
+        self.mean += (entropy - self.mean) / self.samples as f64;
+        true
+    }
+
+    /// Mean entropy once enough samples have been seen
+    pub fn value(&self) -> Option<f64> {
+        (self.samples >= MIN_FLOW_SAMPLES).then_some(self.mean)
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.value().is_some_and(|e| e >= HIGH_ENTROPY_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entropy_bounds() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[b'a'; 100]), 0.0);
+
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert!((shannon_entropy(&all_bytes) - 8.0).abs() < 1e-9);
+
+        let text = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n";
+        let text_entropy = shannon_entropy(text);
+        assert!(text_entropy > 3.0 && text_entropy < HIGH_ENTROPY_THRESHOLD);
+    }
+
+    #[test]
+    fn test_short_payloads_are_not_sampled() {
+        assert_eq!(sample_payload_entropy(&[0u8; MIN_PAYLOAD_BYTES - 1]), None);
+        assert_eq!(sample_payload_entropy(&[0u8; MIN_PAYLOAD_BYTES]), Some(0.0));
+    }
+
+    #[test]
+    fn test_flow_entropy_needs_samples_and_stops() {
+        let mut flow = FlowEntropy::default();
+        for _ in 0..MIN_FLOW_SAMPLES - 1 {
+            assert!(flow.add_sample(7.9));
+        }
+        assert_eq!(flow.value(), None);
+        assert!(!flow.is_high());
+
+        flow.add_sample(7.9);
+        assert!(flow.is_high());
+
+        for _ in MIN_FLOW_SAMPLES..SAMPLES_PER_FLOW {
+            flow.add_sample(1.0);
+        }
+        assert!(!flow.add_sample(1.0));
+        assert_eq!(flow.samples, SAMPLES_PER_FLOW);
+    }
+}
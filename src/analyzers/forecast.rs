@@ -0,0 +1,304 @@
+// Usage forecast: where a billing period's data usage is heading, from stored history
+// Daily totals are fitted with a linear trend plus, given two weeks of history, a
+// day-of-week offset (weekends often differ); the remaining days of the period are
+// projected from the fit, with bounds widening with the scatter of the history around it
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone};
+use std::collections::BTreeMap;
+
+use crate::storage::packet_storage::BandwidthSample;
+
+/// Days of history needed before a forecast is made
+pub const MIN_HISTORY_DAYS: usize = 7;
+
+/// Days of history needed before day-of-week differences are modelled
+const SEASONAL_HISTORY_DAYS: usize = 14;
+
+/// Alternating line and weekday-offset fits; the joint fit settles within a few
+const BACKFIT_ROUNDS: usize = 10;
+
+/// Standard normal quantile of the two-sided 95% bounds
+const Z_95: f64 = 1.96;
+
+/// Bytes moved per local calendar day, from the samples `counted` accepts
+/// Days without samples are absent rather than zero: a gap in monitoring is not a quiet day
+pub fn daily_usage(samples: &[BandwidthSample], counted: impl Fn(&BandwidthSample) -> bool) -> Vec<(NaiveDate, f64)> {
+    let mut days: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for sample in samples.iter().filter(|sample| counted(sample)) {
+        *days.entry(sample.timestamp.date_naive()).or_default() += sample.total_bps() * sample.duration_secs;
+    }
+    days.into_iter().collect()
+}
+
+/// Start of the billing period containing `now`, which begins on `reset_day` of each month
+/// (clamped to the month's last day)
+pub fn period_start(now: DateTime<Local>, reset_day: u32) -> DateTime<Local> {
+    let this_month = reset_date(now.year(), now.month(), reset_day);
+    let start = if now.date_naive() >= this_month {
+        this_month
+    } else if now.month() == 1 {
+        reset_date(now.year() - 1, 12, reset_day)
+    } else {
+        reset_date(now.year(), now.month() - 1, reset_day)
+    };
+    local_midnight(start)
+}
+
+/// End of the billing period starting at `start`: the next reset
+pub fn period_end(start: DateTime<Local>, reset_day: u32) -> DateTime<Local> {
+    let (year, month) = if start.month() == 12 { (start.year() + 1, 1) } else { (start.year(), start.month() + 1) };
+    local_midnight(reset_date(year, month, reset_day))
+}
+
+fn reset_date(year: i32, month: u32, reset_day: u32) -> NaiveDate {
+    let day = reset_day.clamp(1, 31);
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .expect("every month has a first day")
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Local> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight))
+}
+
+/// Projected usage at the end of a billing period
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageForecast {
+    pub period_start: DateTime<Local>,
+    pub period_end: DateTime<Local>,
+    /// Bytes recorded in the period so far
+    pub used_bytes: f64,
+    /// Expected bytes at the end of the period
+    pub projected_bytes: f64,
+    /// 95% bounds of the projection; the lower never falls below what is already used
+    pub low_bytes: f64,
+    pub high_bytes: f64,
+    /// Change in daily usage per day of history
+    pub trend_bytes_per_day: f64,
+    /// Days of history the fit used
+    pub history_days: usize,
+    /// Whether day-of-week differences were modelled
+    pub seasonal: bool,
+    /// Expected cumulative usage at the end of each remaining day, for finding when a quota runs out
+    projected_days: Vec<(NaiveDate, f64)>,
+}
+
+impl UsageForecast {
+    /// The first day expected to end with more than `limit_bytes` used, if any in the period
+    pub fn limit_reached_on(&self, limit_bytes: f64) -> Option<NaiveDate> {
+        if self.used_bytes > limit_bytes {
+            return self.projected_days.first().map(|(day, _)| *day);
+        }
+        self.projected_days.iter().find(|(_, used)| *used > limit_bytes).map(|(day, _)| *day)
+    }
+}
+
+/// Forecast of the period from `start` to `end`, as of `now`, from daily totals
+/// `history` may reach back before the period; only days before today are fitted, as
+/// today is still in progress. `None` with less than `MIN_HISTORY_DAYS` complete days.
+pub fn forecast(
+    history: &[(NaiveDate, f64)],
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Option<UsageForecast> {
+    let today = now.date_naive();
+    let complete: Vec<(NaiveDate, f64)> = history.iter().copied().filter(|(day, _)| *day < today).collect();
+    if complete.len() < MIN_HISTORY_DAYS {
+        return None;
+    }
+    let model = DailyModel::fit(&complete);
+
+    let used_bytes: f64 = history
+        .iter()
+        .filter(|(day, _)| *day >= start.date_naive() && *day < end.date_naive())
+        .map(|(_, bytes)| bytes)
+        .sum();
+
+    // What is left of today counts in proportion to the hours remaining
+    let elapsed_today = (now - local_midnight(today)).num_seconds() as f64 / 86_400.0;
+    let mut projected_days = Vec::new();
+    let mut cumulative = used_bytes;
+    let mut remaining_days = 0.0;
+    let mut day = today;
+    while day < end.date_naive() {
+        let weight = if day == today { (1.0 - elapsed_today).clamp(0.0, 1.0) } else { 1.0 };
+        cumulative += model.predict(day) * weight;
+        remaining_days += weight;
+        projected_days.push((day, cumulative));
+        day += Duration::days(1);
+    }
+
+    // Independent daily errors add up in variance; the trend's own uncertainty is left out,
+    // which matters little over the few weeks of one period
+    let margin = Z_95 * model.residual_sd * remaining_days.sqrt();
+    Some(UsageForecast {
+        period_start: start,
+        period_end: end,
+        used_bytes,
+        projected_bytes: cumulative,
+        low_bytes: (cumulative - margin).max(used_bytes),
+        high_bytes: cumulative + margin,
+        trend_bytes_per_day: model.slope,
+        history_days: complete.len(),
+        seasonal: model.weekday_offsets.is_some(),
+        projected_days,
+    })
+}
+
+/// Least-squares line through daily totals with optional day-of-week offsets
+struct DailyModel {
+    origin: NaiveDate,
+    intercept: f64,
+    slope: f64,
+    /// Mean residual per weekday (Monday first) after the line is removed
+    weekday_offsets: Option<[f64; 7]>,
+    /// Standard deviation of what the model leaves unexplained
+    residual_sd: f64,
+}
+
+impl DailyModel {
+    fn fit(days: &[(NaiveDate, f64)]) -> Self {
+        let mut model = Self { origin: days[0].0, intercept: 0.0, slope: 0.0, weekday_offsets: None, residual_sd: 0.0 };
+        model.fit_line(days);
+        if days.len() >= SEASONAL_HISTORY_DAYS {
+            // The line and the offsets are fitted in turn, so an uneven number of weekend days
+            // in the history is not mistaken for a trend
+            for _ in 0..BACKFIT_ROUNDS {
+                model.fit_offsets(days);
+                model.fit_line(days);
+            }
+            model.fit_offsets(days);
+        }
+
+        // Two parameters for the line and six more for the weekday offsets
+        let parameters = if model.weekday_offsets.is_some() { 8.0 } else { 2.0 };
+        let squared: f64 = days.iter().map(|(day, bytes)| (bytes - model.predict_unclamped(*day)).powi(2)).sum();
+        model.residual_sd = (squared / (days.len() as f64 - parameters).max(1.0)).sqrt();
+        model
+    }
+
+    /// Least-squares line through the totals less their weekday offsets
+    fn fit_line(&mut self, days: &[(NaiveDate, f64)]) {
+        let points: Vec<(f64, f64)> = days
+            .iter()
+            .map(|(day, bytes)| ((*day - self.origin).num_days() as f64, bytes - self.offset(*day)))
+            .collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        self.slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+        self.intercept = mean_y - self.slope * mean_x;
+    }
+
+    /// Mean distance of each weekday's totals from the line
+    fn fit_offsets(&mut self, days: &[(NaiveDate, f64)]) {
+        let mut sums = [0.0; 7];
+        let mut counts = [0u32; 7];
+        for (day, bytes) in days {
+            let weekday = day.weekday().num_days_from_monday() as usize;
+            sums[weekday] += bytes - self.line(*day);
+            counts[weekday] += 1;
+        }
+        let mut offsets = [0.0; 7];
+        for (offset, (sum, count)) in offsets.iter_mut().zip(sums.iter().zip(counts)) {
+            if count > 0 {
+                *offset = sum / count as f64;
+            }
+        }
+        self.weekday_offsets = Some(offsets);
+    }
+
+    fn line(&self, day: NaiveDate) -> f64 {
+        self.intercept + self.slope * (day - self.origin).num_days() as f64
+    }
+
+    fn offset(&self, day: NaiveDate) -> f64 {
+        self.weekday_offsets
+            .map_or(0.0, |offsets| offsets[day.weekday().num_days_from_monday() as usize])
+    }
+
+    fn predict_unclamped(&self, day: NaiveDate) -> f64 {
+        self.line(day) + self.offset(day)
+    }
+
+    /// Expected bytes on `day`; a falling trend never projects negative usage
+    fn predict(&self, day: NaiveDate) -> f64 {
+        self.predict_unclamped(day).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: f64 = 1e9;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    fn history(from: NaiveDate, days: i64, bytes: impl Fn(NaiveDate) -> f64) -> Vec<(NaiveDate, f64)> {
+        (0..days).map(|i| from + Duration::days(i)).map(|day| (day, bytes(day))).collect()
+    }
+
+    #[test]
+    fn test_billing_period_bounds() {
+        assert_eq!(period_start(at(2024, 3, 20, 12), 1), at(2024, 3, 1, 0));
+        assert_eq!(period_start(at(2024, 3, 10, 12), 15), at(2024, 2, 15, 0));
+        assert_eq!(period_start(at(2024, 1, 10, 12), 15), at(2023, 12, 15, 0));
+        // A reset on the 31st falls on the last day of shorter months
+        assert_eq!(period_start(at(2024, 3, 1, 12), 31), at(2024, 2, 29, 0));
+        assert_eq!(period_end(at(2024, 12, 1, 0), 1), at(2025, 1, 1, 0));
+    }
+
+    #[test]
+    fn test_steady_usage_projects_the_daily_rate() {
+        let start = at(2024, 3, 1, 0);
+        let end = period_end(start, 1);
+        let now = at(2024, 3, 16, 0);
+        let history = history(NaiveDate::from_ymd_opt(2024, 2, 15).unwrap(), 30, |_| 2.0 * GB);
+
+        let forecast = forecast(&history, start, end, now).unwrap();
+        assert!((forecast.used_bytes - 30.0 * GB).abs() < 1.0);
+        assert!((forecast.projected_bytes - 62.0 * GB).abs() < 1e3);
+        assert!(forecast.high_bytes - forecast.low_bytes < 1e3);
+        assert!(forecast.seasonal);
+        assert_eq!(forecast.limit_reached_on(50.0 * GB), NaiveDate::from_ymd_opt(2024, 3, 26));
+        assert_eq!(forecast.limit_reached_on(100.0 * GB), None);
+    }
+
+    #[test]
+    fn test_weekends_and_trend_are_modelled() {
+        let start = at(2024, 3, 1, 0);
+        let end = period_end(start, 1);
+        let now = at(2024, 3, 16, 0);
+        let weekend_heavy = |day: NaiveDate| if day.weekday().num_days_from_monday() >= 5 { 5.0 * GB } else { 1.0 * GB };
+        let history = history(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(), 44, weekend_heavy);
+
+        let forecast = forecast(&history, start, end, now).unwrap();
+        // March 16 - 31 has 6 weekend days and 10 weekdays
+        let expected = forecast.used_bytes + 6.0 * 5.0 * GB + 10.0 * GB;
+        assert!((forecast.projected_bytes - expected).abs() < 0.5 * GB, "{forecast:?}");
+        assert!(forecast.low_bytes <= forecast.projected_bytes && forecast.projected_bytes <= forecast.high_bytes);
+
+        let rising = history.iter().enumerate().map(|(i, (day, _))| (*day, i as f64 * 0.1 * GB)).collect::<Vec<_>>();
+        let forecast = super::forecast(&rising, start, end, now).unwrap();
+        assert!((forecast.trend_bytes_per_day - 0.1 * GB).abs() < 1e3);
+    }
+
+    #[test]
+    fn test_too_little_history() {
+        let now = at(2024, 3, 16, 0);
+        let history = history(NaiveDate::from_ymd_opt(2024, 3, 10).unwrap(), 7, |_| GB);
+        // Today is not a complete day, leaving six
+        assert!(forecast(&history, at(2024, 3, 1, 0), at(2024, 4, 1, 0), now).is_none());
+    }
+}
@@ -0,0 +1,289 @@
+// MTU and fragmentation: packets split or refused on the way for their size
+// Counts, per interface, remote host and minute, the IP fragments exchanged with the host
+// and the ICMP "fragmentation needed" and ICMPv6 "packet too big" messages about packets
+// sent to it, and suggests an MTU that gets through the path unsplit
+
+use crate::models::{NetworkPacket, PacketDirection, PacketTooBig};
+use chrono::{DateTime, Duration, Local, Timelike};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// ICMP destination unreachable, and its "fragmentation needed and DF set" code
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMP_FRAGMENTATION_NEEDED: u8 = 4;
+/// ICMPv6 packet too big
+const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+
+/// "Packet too big" messages about one host that show path MTU discovery is not lowering
+/// the packets sent to it, usually because the messages are filtered before the sender
+pub const PMTUD_STUCK_REFUSALS: u64 = 10;
+
+/// Remote hosts tracked per minute; fragments to further hosts go uncounted until it ends
+const MAX_OPEN_REMOTES: usize = 10_000;
+
+/// Finished minutes held between `take_samples` calls; older ones are dropped beyond this
+const MAX_BUFFERED_SAMPLES: usize = 10_000;
+
+/// IP and TCP header bytes an MTU leaves less room for in a TCP segment
+const IPV4_TCP_HEADERS: u32 = 40;
+const IPV6_TCP_HEADERS: u32 = 60;
+
+/// The report in an ICMP (`v6` false) or ICMPv6 message, when it says a packet was too big
+pub fn parse_packet_too_big(icmp: &[u8], v6: bool) -> Option<PacketTooBig> {
+    // The message quotes the start of the dropped packet after its 8-byte header
+    let quoted = icmp.get(8..)?;
+    if v6 {
+        if *icmp.first()? != ICMPV6_PACKET_TOO_BIG {
+            return None;
+        }
+        let destination: [u8; 16] = quoted.get(24..40)?.try_into().ok()?;
+        let mtu = u32::from_be_bytes(icmp.get(4..8)?.try_into().ok()?);
+        Some(PacketTooBig { destination: IpAddr::V6(Ipv6Addr::from(destination)), mtu: Some(mtu).filter(|mtu| *mtu > 0) })
+    } else {
+        if icmp.get(..2)? != [ICMP_DEST_UNREACHABLE, ICMP_FRAGMENTATION_NEEDED] {
+            return None;
+        }
+        let destination: [u8; 4] = quoted.get(16..20)?.try_into().ok()?;
+        // Routers from before path MTU discovery leave the next-hop MTU at zero
+        let mtu = u16::from_be_bytes(icmp.get(6..8)?.try_into().ok()?);
+        Some(PacketTooBig {
+            destination: IpAddr::V4(Ipv4Addr::from(destination)),
+            mtu: Some(u32::from(mtu)).filter(|mtu| *mtu > 0),
+        })
+    }
+}
+
+/// Fragmentation and size refusals for one remote host on one interface over one minute
+/// (or, merged, longer)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtuSample {
+    /// Start of the minute
+    pub timestamp: DateTime<Local>,
+    pub interface: String,
+    pub remote: String,
+    /// IP fragments to or from the host
+    pub fragments: u64,
+    /// Packets split into fragments, counted by their first fragment
+    pub fragmented_packets: u64,
+    /// Largest fragment followed by more, as an IP packet: the MTU the packets were split for
+    pub largest_fragment: u32,
+    /// Messages saying a packet to the host was too big for a link on the way
+    pub too_big: u64,
+    /// Smallest MTU those messages reported
+    pub reported_mtu: Option<u32>,
+}
+
+impl MtuSample {
+    fn new(interface: &str, remote: IpAddr, timestamp: DateTime<Local>) -> Self {
+        Self {
+            timestamp,
+            interface: interface.to_string(),
+            remote: remote.to_string(),
+            fragments: 0,
+            fragmented_packets: 0,
+            largest_fragment: 0,
+            too_big: 0,
+            reported_mtu: None,
+        }
+    }
+
+    /// Adds the counters of `other`, keeping this sample's time, interface and host
+    pub fn merge(&mut self, other: &MtuSample) {
+        self.fragments += other.fragments;
+        self.fragmented_packets += other.fragmented_packets;
+        self.largest_fragment = self.largest_fragment.max(other.largest_fragment);
+        self.too_big += other.too_big;
+        self.reported_mtu = match (self.reported_mtu, other.reported_mtu) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// MTU packets to the host get through at unsplit: the smallest one a router reported,
+    /// or the size packets were fragmented to; None when neither is known
+    pub fn suggested_mtu(&self) -> Option<u32> {
+        let fragmented = Some(self.largest_fragment).filter(|size| *size > 0);
+        match (self.reported_mtu, fragmented) {
+            (Some(reported), Some(fragmented)) => Some(reported.min(fragmented)),
+            (reported, fragmented) => reported.or(fragmented),
+        }
+    }
+
+    /// TCP maximum segment size matching `mtu` on the path to the host
+    pub fn mss_for(&self, mtu: u32) -> u32 {
+        let headers = if self.remote.contains(':') { IPV6_TCP_HEADERS } else { IPV4_TCP_HEADERS };
+        mtu.saturating_sub(headers)
+    }
+}
+
+/// Per-interface and per-host minutes of fragments and size refusals
+#[derive(Debug, Default)]
+pub struct FragmentationTracker {
+    /// Minute the open samples are for
+    minute: Option<DateTime<Local>>,
+    open: HashMap<(String, IpAddr), MtuSample>,
+    finished: Vec<MtuSample>,
+}
+
+impl FragmentationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the packet when it is a fragment or a size refusal; any packet past the open
+    /// minute closes it, so quiet hosts' counts are not held back
+    pub fn observe(&mut self, packet: &NetworkPacket) {
+        if self.minute.is_some_and(|open| packet.timestamp >= open + Duration::minutes(1)) {
+            self.close_minute();
+            self.minute = None;
+        }
+        let fragment = packet.ip.and_then(|ip| ip.fragment);
+        if fragment.is_none() && packet.too_big.is_none() {
+            return;
+        }
+        let minute = start_of_minute(packet.timestamp);
+        self.minute = Some(minute);
+
+        if let Some(report) = packet.too_big
+            && let Some(sample) = self.sample(&packet.interface, report.destination, minute)
+        {
+            sample.too_big += 1;
+            if let Some(mtu) = report.mtu {
+                sample.reported_mtu = Some(sample.reported_mtu.map_or(mtu, |reported| reported.min(mtu)));
+            }
+        }
+
+        let remote = match packet.direction {
+            PacketDirection::Inbound => packet.source_addr,
+            _ => packet.dest_addr,
+        };
+        if let (Some(fragment), Some(remote)) = (fragment, remote)
+            && let Some(sample) = self.sample(&packet.interface, remote, minute)
+        {
+            sample.fragments += 1;
+            if fragment.offset == 0 {
+                sample.fragmented_packets += 1;
+            }
+            if fragment.more {
+                sample.largest_fragment = sample.largest_fragment.max(u32::from(fragment.length));
+            }
+        }
+    }
+
+    /// Minutes finished since the last call
+    pub fn take_samples(&mut self) -> Vec<MtuSample> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Every minute not yet handed out, the current one included; used at end of capture
+    pub fn drain(&mut self) -> Vec<MtuSample> {
+        self.close_minute();
+        self.minute = None;
+        self.take_samples()
+    }
+
+    fn sample(&mut self, interface: &str, remote: IpAddr, minute: DateTime<Local>) -> Option<&mut MtuSample> {
+        let key = (interface.to_string(), remote);
+        if !self.open.contains_key(&key) && self.open.len() >= MAX_OPEN_REMOTES {
+            return None;
+        }
+        Some(self.open.entry(key).or_insert_with(|| MtuSample::new(interface, remote, minute)))
+    }
+
+    fn close_minute(&mut self) {
+        for (_, sample) in self.open.drain() {
+            if self.finished.len() >= MAX_BUFFERED_SAMPLES {
+                self.finished.remove(0);
+            }
+            self.finished.push(sample);
+        }
+    }
+}
+
+/// Samples added up per remote host across interfaces, the most refused and fragmented first
+pub fn per_remote(samples: &[MtuSample]) -> Vec<MtuSample> {
+    let mut totals: Vec<MtuSample> = Vec::new();
+    for sample in samples {
+        match totals.iter_mut().find(|total| total.remote == sample.remote) {
+            Some(total) => total.merge(sample),
+            None => totals.push(sample.clone()),
+        }
+    }
+    totals.sort_by(|a, b| {
+        (b.too_big + b.fragmented_packets)
+            .cmp(&(a.too_big + a.fragmented_packets))
+            .then_with(|| a.remote.cmp(&b.remote))
+    });
+    totals
+}
+
+fn start_of_minute(at: DateTime<Local>) -> DateTime<Local> {
+    at - Duration::seconds(at.second() as i64) - Duration::nanoseconds(at.nanosecond() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{IpFragment, IpHeader, PacketProtocol};
+
+    fn fragment(offset: u16, more: bool, length: u16) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 1_514, PacketProtocol::IPv4, PacketDirection::Outbound);
+        packet.source_addr = Some("10.0.0.5".parse().unwrap());
+        packet.dest_addr = Some("203.0.113.9".parse().unwrap());
+        packet.ip = Some(IpHeader { ttl: 64, fingerprint: 0, fragment: Some(IpFragment { offset, more, length }) });
+        packet
+    }
+
+    #[test]
+    fn test_packet_too_big_messages_parsed() {
+        // ICMP fragmentation needed, next-hop MTU 1400, quoting a packet to 203.0.113.9
+        let mut icmp = vec![3, 4, 0, 0, 0, 0, 0x05, 0x78];
+        let mut quoted = vec![0x45, 0, 0x05, 0xdc, 0, 0, 0x40, 0, 64, 6, 0, 0, 10, 0, 0, 5, 203, 0, 113, 9];
+        icmp.append(&mut quoted);
+        let report = parse_packet_too_big(&icmp, false).unwrap();
+        assert_eq!(report.destination, "203.0.113.9".parse::<IpAddr>().unwrap());
+        assert_eq!(report.mtu, Some(1_400));
+
+        // Other unreachable codes are not about size
+        icmp[1] = 3;
+        assert_eq!(parse_packet_too_big(&icmp, false), None);
+
+        let mut icmpv6 = vec![2, 0, 0, 0, 0, 0, 0x05, 0x00];
+        icmpv6.extend_from_slice(&[0x60, 0, 0, 0, 0, 0, 17, 64]);
+        icmpv6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        icmpv6.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        let report = parse_packet_too_big(&icmpv6, true).unwrap();
+        assert_eq!(report.destination, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(report.mtu, Some(1_280));
+        assert_eq!(parse_packet_too_big(&icmpv6[..20], true), None);
+    }
+
+    #[test]
+    fn test_fragments_and_refusals_suggest_an_mtu() {
+        let mut tracker = FragmentationTracker::new();
+        for _ in 0..3 {
+            tracker.observe(&fragment(0, true, 1_420));
+            tracker.observe(&fragment(1_400, false, 300));
+        }
+        let mut refusal = NetworkPacket::new("eth0".to_string(), 70, PacketProtocol::IPv4, PacketDirection::Inbound);
+        refusal.source_addr = Some("192.0.2.1".parse().unwrap());
+        refusal.too_big = Some(PacketTooBig { destination: "203.0.113.9".parse().unwrap(), mtu: Some(1_400) });
+        tracker.observe(&refusal);
+        // Whole packets are not counted
+        tracker.observe(&NetworkPacket::new("eth0".to_string(), 1_514, PacketProtocol::IPv4, PacketDirection::Outbound));
+
+        let samples = tracker.drain();
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample.remote, "203.0.113.9");
+        assert_eq!((sample.fragments, sample.fragmented_packets, sample.too_big), (6, 3, 1));
+        assert_eq!(sample.largest_fragment, 1_420);
+        assert_eq!(sample.suggested_mtu(), Some(1_400));
+        assert_eq!(sample.mss_for(1_400), 1_360);
+        assert!(tracker.drain().is_empty());
+
+        let totals = per_remote(&[sample.clone(), sample.clone()]);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].fragments, 12);
+    }
+}
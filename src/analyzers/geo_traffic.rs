@@ -0,0 +1,164 @@
+// Traffic by destination country and autonomous system: the stored traffic with each public
+// address is placed with GeoIP lookups and summed per country and per ASN, so egress to
+// places the network has no business with stands out. Countries outside the configured
+// expected ones are marked, with the addresses that carried most of their traffic
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::config::GeoIpConfig;
+use crate::enrichment::GeoInfo;
+use crate::storage::packet_storage::RemoteTraffic;
+
+/// Busiest addresses kept for each country and autonomous system
+const TOP_ADDRESSES: usize = 3;
+
+/// Traffic with the addresses of one country or autonomous system
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoTotal {
+    /// ISO country code or "AS13335"; `None` for addresses the database does not cover
+    pub key: Option<String>,
+    /// Country name or AS organization
+    pub name: Option<String>,
+    pub addresses: usize,
+    pub flows: u64,
+    pub bytes: u64,
+    /// Busiest addresses with their bytes, at most `TOP_ADDRESSES`
+    pub top_addresses: Vec<(IpAddr, u64)>,
+    /// Whether the country is outside `[geoip] expected_countries`
+    pub unexpected: bool,
+}
+
+impl GeoTotal {
+    fn new(key: Option<String>, name: Option<String>) -> Self {
+        Self { key, name, addresses: 0, flows: 0, bytes: 0, top_addresses: Vec::new(), unexpected: false }
+    }
+
+    fn add(&mut self, remote: &RemoteTraffic) {
+        self.addresses += 1;
+        self.flows += remote.flows;
+        self.bytes += remote.bytes;
+        // Remotes arrive busiest first, so the first few are the top addresses
+        if self.top_addresses.len() < TOP_ADDRESSES {
+            self.top_addresses.push((remote.address, remote.bytes));
+        }
+    }
+
+    /// Key and name for display, e.g. "NZ New Zealand" or "AS13335 Cloudflare"
+    pub fn label(&self) -> String {
+        match (&self.key, &self.name) {
+            (Some(key), Some(name)) => format!("{key} {name}"),
+            (Some(key), None) => key.clone(),
+            (None, _) => "Unknown".to_string(),
+        }
+    }
+}
+
+/// Public traffic of a period by country and by autonomous system, busiest first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoTraffic {
+    pub countries: Vec<GeoTotal>,
+    pub asns: Vec<GeoTotal>,
+    /// Bytes exchanged with all public addresses
+    pub total_bytes: u64,
+}
+
+impl GeoTraffic {
+    /// Countries outside the expected ones, busiest first
+    pub fn unexpected_countries(&self) -> impl Iterator<Item = &GeoTotal> {
+        self.countries.iter().filter(|c| c.unexpected)
+    }
+}
+
+/// Sums `remotes`, busiest first as storage returns them, per country and ASN found by `lookup`
+pub fn aggregate(remotes: &[RemoteTraffic], lookup: impl Fn(IpAddr) -> GeoInfo, config: &GeoIpConfig) -> GeoTraffic {
+    let mut countries: HashMap<Option<String>, GeoTotal> = HashMap::new();
+    let mut asns: HashMap<Option<u32>, GeoTotal> = HashMap::new();
+    let mut total_bytes = 0;
+    for remote in remotes {
+        let info = lookup(remote.address);
+        total_bytes += remote.bytes;
+        countries
+            .entry(info.country.clone())
+            .or_insert_with(|| {
+                let mut total = GeoTotal::new(info.country.clone(), info.country_name.clone());
+                total.unexpected = info.country.as_deref().is_some_and(|c| config.is_unexpected(c));
+                total
+            })
+            .add(remote);
+        asns.entry(info.asn)
+            .or_insert_with(|| GeoTotal::new(info.asn.map(|asn| format!("AS{asn}")), info.as_organization.clone()))
+            .add(remote);
+    }
+
+    let sorted = |totals: Vec<GeoTotal>| {
+        let mut totals = totals;
+        totals.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+        totals
+    };
+    GeoTraffic {
+        countries: sorted(countries.into_values().collect()),
+        asns: sorted(asns.into_values().collect()),
+        total_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(address: &str, bytes: u64) -> RemoteTraffic {
+        RemoteTraffic { address: address.parse().unwrap(), flows: 2, packets: 10, bytes }
+    }
+
+    fn lookup(ip: IpAddr) -> GeoInfo {
+        let (country, name, asn, organization) = match ip.to_string().as_str() {
+            "1.1.1.1" | "1.0.0.1" => ("AU", "Australia", 13335, "Cloudflare"),
+            "8.8.8.8" => ("US", "United States", 15169, "Google"),
+            "203.0.113.9" => ("KP", "North Korea", 131279, "Star Joint Venture"),
+            _ => return GeoInfo::default(),
+        };
+        GeoInfo {
+            country: Some(country.to_string()),
+            country_name: Some(name.to_string()),
+            asn: Some(asn),
+            as_organization: Some(organization.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_by_country_and_asn() {
+        let remotes = [
+            remote("1.1.1.1", 5000),
+            remote("8.8.8.8", 3000),
+            remote("1.0.0.1", 2500),
+            remote("203.0.113.9", 700),
+            remote("198.51.100.1", 100),
+        ];
+        let config = GeoIpConfig { expected_countries: vec!["au".to_string(), "US".to_string()], ..GeoIpConfig::default() };
+        let traffic = aggregate(&remotes, lookup, &config);
+
+        assert_eq!(traffic.total_bytes, 11300);
+        let countries: Vec<_> = traffic.countries.iter().map(|c| (c.label(), c.addresses, c.bytes, c.unexpected)).collect();
+        assert_eq!(
+            countries,
+            vec![
+                ("AU Australia".to_string(), 2, 7500, false),
+                ("US United States".to_string(), 1, 3000, false),
+                ("KP North Korea".to_string(), 1, 700, true),
+                ("Unknown".to_string(), 1, 100, false),
+            ]
+        );
+        assert_eq!(traffic.countries[0].flows, 4);
+        assert_eq!(traffic.countries[0].top_addresses[1], ("1.0.0.1".parse().unwrap(), 2500));
+        assert_eq!(traffic.asns[0].label(), "AS13335 Cloudflare");
+        let unexpected: Vec<_> = traffic.unexpected_countries().map(|c| c.label()).collect();
+        assert_eq!(unexpected, ["KP North Korea"]);
+    }
+
+    #[test]
+    fn test_nothing_unexpected_without_expected_countries() {
+        let traffic = aggregate(&[remote("203.0.113.9", 700)], lookup, &GeoIpConfig::default());
+        assert_eq!(traffic.unexpected_countries().count(), 0);
+    }
+}
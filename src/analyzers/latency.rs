@@ -0,0 +1,391 @@
+// LatencyTracker: Passive round-trip time estimation for TCP traffic
+// Times each handshake step (SYN -> SYN-ACK, SYN-ACK -> ACK) and matches TCP
+// timestamp options (TSval echoed back as TSecr) on established connections
+
+use crate::analyzers::tcp_state::DUPLICATE_WINDOW_MICROS;
+use crate::models::{NetworkPacket, PacketDirection, TcpSegment};
+use chrono::{DateTime, Duration, Local};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+
+/// Unanswered SYNs and timestamps older than this are forgotten
+pub const PENDING_TIMEOUT_SECS: i64 = 10;
+
+/// Distinct TSvals remembered per direction of a connection
+const MAX_PENDING_TIMESTAMPS: usize = 16;
+
+/// Samples held between `take_samples` calls; newer ones are dropped beyond this
+const MAX_BUFFERED_SAMPLES: usize = 100_000;
+
+/// How a round-trip time was measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RttSource {
+    /// Between two steps of the TCP handshake
+    Handshake,
+    /// From a TSval to the first segment echoing it
+    Timestamp,
+}
+
+impl RttSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RttSource::Handshake => "handshake",
+            RttSource::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// One round-trip time measurement towards `host`
+#[derive(Debug, Clone)]
+pub struct LatencySample {
+    pub timestamp: DateTime<Local>,
+    pub interface: String,
+    /// The side that answered, i.e. the host the round trip went to
+    pub host: IpAddr,
+    pub rtt_ms: f64,
+    pub source: RttSource,
+}
+
+/// Round-trip time percentiles for one destination
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyStats {
+    pub host: String,
+    pub samples: usize,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    /// Percentiles over `rtts_ms`, None when there are no samples
+    pub fn from_samples(host: impl Into<String>, rtts_ms: &[f64]) -> Option<Self> {
+        let mut sorted: Vec<f64> = rtts_ms.iter().copied().filter(|rtt| rtt.is_finite()).collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        Some(Self {
+            host: host.into(),
+            samples: sorted.len(),
+            min_ms: sorted[0],
+            p50_ms: percentile(&sorted, 50.0),
+            p90_ms: percentile(&sorted, 90.0),
+            p99_ms: percentile(&sorted, 99.0),
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+
+    /// Groups (host, rtt_ms) pairs into per-host statistics, most sampled host first
+    pub fn per_host(samples: impl IntoIterator<Item = (String, f64)>) -> Vec<Self> {
+        let mut by_host: HashMap<String, Vec<f64>> = HashMap::new();
+        for (host, rtt) in samples {
+            by_host.entry(host).or_default().push(rtt);
+        }
+        let mut stats: Vec<Self> = by_host
+            .into_iter()
+            .filter_map(|(host, rtts)| Self::from_samples(host, &rtts))
+            .collect();
+        stats.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.host.cmp(&b.host)));
+        stats
+    }
+}
+
+/// Nearest-rank percentile of an ascending, non-empty slice
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Handshake step waiting for its answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandshakeStep {
+    Syn,
+    SynAck,
+}
+
+/// What one direction of a connection is waiting to hear back about
+#[derive(Debug, Default)]
+struct PendingFlow {
+    handshake: Option<(HandshakeStep, DateTime<Local>)>,
+    /// The SYN was resent, so an answer cannot be matched to one attempt (Karn's rule)
+    ambiguous: bool,
+    /// (TSval, first time it was sent), oldest first
+    timestamps: VecDeque<(u32, DateTime<Local>)>,
+    last_seen: Option<DateTime<Local>>,
+}
+
+/// Derives round-trip times from TCP packets without sending any probes
+///
+/// A request sent from A to B and answered by B is a round trip to B, as seen
+/// from the capture point. Answers sent by this machine are skipped: they only
+/// time how fast the local stack responds.
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    /// Keyed by (sender, receiver) of the packets awaiting an answer
+    pending: HashMap<(SocketAddr, SocketAddr), PendingFlow>,
+    max_flows: usize,
+    samples: Vec<LatencySample>,
+    last_expiry: Option<DateTime<Local>>,
+}
+
+impl LatencyTracker {
+    pub fn new(max_flows: usize) -> Self {
+        Self {
+            max_flows,
+            ..Self::default()
+        }
+    }
+
+    /// Records a TCP packet and returns the round-trip time it completed, if any
+    pub fn observe(&mut self, packet: &NetworkPacket) -> Option<&LatencySample> {
+        let segment = packet.tcp?;
+        let source = SocketAddr::new(packet.source_addr?, packet.source_port?);
+        let dest = SocketAddr::new(packet.dest_addr?, packet.dest_port?);
+        self.expire(packet.timestamp);
+
+        let measured = self.match_answer(packet, &segment, source, dest);
+        self.remember_request(packet, &segment, source, dest);
+
+        let rtt = measured.filter(|(rtt, _)| *rtt < Duration::seconds(PENDING_TIMEOUT_SECS))?;
+        if packet.direction == PacketDirection::Outbound || self.samples.len() >= MAX_BUFFERED_SAMPLES {
+            return None;
+        }
+        self.samples.push(LatencySample {
+            timestamp: packet.timestamp,
+            interface: packet.interface.clone(),
+            host: source.ip(),
+            rtt_ms: rtt.0.num_microseconds().unwrap_or(0) as f64 / 1000.0,
+            source: rtt.1,
+        });
+        self.samples.last()
+    }
+
+    /// Measurements taken since the last call
+    pub fn take_samples(&mut self) -> Vec<LatencySample> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Matches `segment` against what the opposite direction is waiting for
+    fn match_answer(
+        &mut self,
+        packet: &NetworkPacket,
+        segment: &TcpSegment,
+        source: SocketAddr,
+        dest: SocketAddr,
+    ) -> Option<(Duration, RttSource)> {
+        let waiting = self.pending.get_mut(&(dest, source))?;
+        let syn = segment.has(TcpSegment::SYN);
+        let ack = segment.has(TcpSegment::ACK);
+
+        let mut measured = None;
+        if let Some((step, sent)) = waiting.handshake {
+            let answers = match step {
+                HandshakeStep::Syn => syn && ack,
+                HandshakeStep::SynAck => ack && !syn,
+            };
+            if answers {
+                waiting.handshake = None;
+                if !std::mem::take(&mut waiting.ambiguous) {
+                    measured = Some((packet.timestamp - sent, RttSource::Handshake));
+                }
+            }
+        }
+
+        // Echoes during the handshake repeat what the handshake already measured
+        if let Some((_, tsecr)) = segment.timestamps
+            && tsecr != 0
+            && let Some(index) = waiting.timestamps.iter().position(|(tsval, _)| *tsval == tsecr)
+        {
+            let (_, sent) = waiting.timestamps[index];
+            waiting.timestamps.drain(..=index);
+            if measured.is_none() && !syn {
+                measured = Some((packet.timestamp - sent, RttSource::Timestamp));
+            }
+        }
+        measured
+    }
+
+    /// Notes the SYN, SYN-ACK or TSval in `segment` so its answer can be timed
+    fn remember_request(&mut self, packet: &NetworkPacket, segment: &TcpSegment, source: SocketAddr, dest: SocketAddr) {
+        let step = match (segment.has(TcpSegment::SYN), segment.has(TcpSegment::ACK)) {
+            (true, false) => Some(HandshakeStep::Syn),
+            (true, true) => Some(HandshakeStep::SynAck),
+            _ => None,
+        };
+        if step.is_none() && segment.timestamps.is_none() {
+            return;
+        }
+        if !self.pending.contains_key(&(source, dest)) && self.pending.len() >= self.max_flows {
+            return;
+        }
+
+        let flow = self.pending.entry((source, dest)).or_default();
+        flow.last_seen = Some(packet.timestamp);
+        if let Some(step) = step {
+            match flow.handshake {
+                // The same packet captured twice is not a resend
+                Some((previous, sent))
+                    if previous == step
+                        && (packet.timestamp - sent).num_microseconds().is_some_and(|us| us < DUPLICATE_WINDOW_MICROS) => {}
+                Some((previous, _)) if previous == step => flow.ambiguous = true,
+                _ => {
+                    flow.handshake = Some((step, packet.timestamp));
+                    flow.ambiguous = false;
+                }
+            }
+        }
+        if let Some((tsval, _)) = segment.timestamps
+            && flow.timestamps.back().is_none_or(|(last, _)| *last != tsval)
+        {
+            if flow.timestamps.len() == MAX_PENDING_TIMESTAMPS {
+                flow.timestamps.pop_front();
+            }
+            flow.timestamps.push_back((tsval, packet.timestamp));
+        }
+    }
+
+    /// Drops requests that went unanswered for `PENDING_TIMEOUT_SECS`
+    fn expire(&mut self, now: DateTime<Local>) {
+        let timeout = Duration::seconds(PENDING_TIMEOUT_SECS);
+        if self.last_expiry.is_some_and(|last| now - last < timeout) {
+            return;
+        }
+        self.last_expiry = Some(now);
+        let cutoff = now - timeout;
+        self.pending.retain(|_, flow| {
+            if flow.handshake.is_some_and(|(_, sent)| sent < cutoff) {
+                flow.handshake = None;
+                flow.ambiguous = false;
+            }
+            while flow.timestamps.front().is_some_and(|(_, sent)| *sent < cutoff) {
+                flow.timestamps.pop_front();
+            }
+            flow.last_seen.is_some_and(|seen| seen >= cutoff)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketProtocol, TransportProtocol};
+
+    const SYN: u8 = TcpSegment::SYN;
+    const ACK: u8 = TcpSegment::ACK;
+
+    fn client() -> SocketAddr {
+        "192.168.1.10:50000".parse().unwrap()
+    }
+
+    fn server() -> SocketAddr {
+        "93.184.216.34:443".parse().unwrap()
+    }
+
+    fn segment(
+        from: SocketAddr,
+        to: SocketAddr,
+        flags: u8,
+        timestamps: Option<(u32, u32)>,
+        at: DateTime<Local>,
+        direction: PacketDirection,
+    ) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 60, PacketProtocol::IPv4, direction);
+        packet.timestamp = at;
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_addr = Some(from.ip());
+        packet.dest_addr = Some(to.ip());
+        packet.source_port = Some(from.port());
+        packet.dest_port = Some(to.port());
+        packet.tcp = Some(TcpSegment { flags, sequence: 1, acknowledgement: 0, window: 0, payload_len: 0, timestamps });
+        packet
+    }
+
+    #[test]
+    fn test_handshake_rtt_to_server() {
+        let mut tracker = LatencyTracker::new(100);
+        let start = Local::now();
+        let (c, s) = (client(), server());
+        use PacketDirection::{Inbound, Outbound};
+
+        assert!(tracker.observe(&segment(c, s, SYN, Some((100, 0)), start, Outbound)).is_none());
+        let sample = tracker
+            .observe(&segment(s, c, SYN | ACK, Some((9000, 100)), start + Duration::milliseconds(42), Inbound))
+            .cloned()
+            .unwrap();
+        assert_eq!(sample.host, s.ip());
+        assert_eq!(sample.source, RttSource::Handshake);
+        assert!((sample.rtt_ms - 42.0).abs() < 0.01);
+
+        // Our own final ACK only times the local stack
+        let own_ack = segment(c, s, ACK, Some((101, 9000)), start + Duration::milliseconds(43), Outbound);
+        assert!(tracker.observe(&own_ack).is_none());
+        assert_eq!(tracker.take_samples().len(), 1);
+    }
+
+    #[test]
+    fn test_timestamp_echo_counts_first_answer_only() {
+        let mut tracker = LatencyTracker::new(100);
+        let start = Local::now();
+        let (c, s) = (client(), server());
+        use PacketDirection::{Inbound, Outbound};
+
+        tracker.observe(&segment(c, s, ACK, Some((500, 7)), start, Outbound));
+        // A later segment with the same TSval does not restart the clock
+        tracker.observe(&segment(c, s, ACK, Some((500, 7)), start + Duration::milliseconds(5), Outbound));
+        let echo = segment(s, c, ACK, Some((8, 500)), start + Duration::milliseconds(30), Inbound);
+        let sample = tracker.observe(&echo).cloned().unwrap();
+        assert_eq!(sample.source, RttSource::Timestamp);
+        assert!((sample.rtt_ms - 30.0).abs() < 0.01);
+
+        let again = segment(s, c, ACK, Some((8, 500)), start + Duration::milliseconds(31), Inbound);
+        assert!(tracker.observe(&again).is_none());
+    }
+
+    #[test]
+    fn test_resent_syn_is_not_timed() {
+        let mut tracker = LatencyTracker::new(100);
+        let start = Local::now();
+        let (c, s) = (client(), server());
+        use PacketDirection::{Inbound, Outbound};
+
+        tracker.observe(&segment(c, s, SYN, None, start, Outbound));
+        tracker.observe(&segment(c, s, SYN, None, start + Duration::seconds(1), Outbound));
+        let syn_ack = segment(s, c, SYN | ACK, None, start + Duration::milliseconds(1020), Inbound);
+        assert!(tracker.observe(&syn_ack).is_none());
+    }
+
+    #[test]
+    fn test_unanswered_requests_expire() {
+        let mut tracker = LatencyTracker::new(100);
+        let start = Local::now();
+        let (c, s) = (client(), server());
+        use PacketDirection::{Inbound, Outbound};
+
+        tracker.observe(&segment(c, s, ACK, Some((500, 7)), start, Outbound));
+        let late = start + Duration::seconds(PENDING_TIMEOUT_SECS + 1);
+        assert!(tracker.observe(&segment(s, c, ACK, Some((8, 500)), late, Inbound)).is_none());
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let rtts: Vec<f64> = (1..=100).map(f64::from).collect();
+        let stats = LatencyStats::from_samples("10.0.0.1", &rtts).unwrap();
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.min_ms, 1.0);
+        assert_eq!(stats.p50_ms, 50.0);
+        assert_eq!(stats.p90_ms, 90.0);
+        assert_eq!(stats.p99_ms, 99.0);
+        assert_eq!(stats.max_ms, 100.0);
+        assert!(LatencyStats::from_samples("10.0.0.1", &[]).is_none());
+
+        let hosts = LatencyStats::per_host(vec![
+            ("a".to_string(), 1.0),
+            ("b".to_string(), 2.0),
+            ("b".to_string(), 4.0),
+        ]);
+        assert_eq!(hosts[0].host, "b");
+        assert_eq!(hosts[0].samples, 2);
+    }
+}
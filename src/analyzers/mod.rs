@@ -1,6 +1,27 @@
+pub mod anomaly;
+pub mod classification;
+pub mod congestion;
+pub mod discovery;
+pub mod duplicates;
+pub mod entropy;
+pub mod forecast;
+pub mod fragmentation;
+pub mod geo_traffic;
+pub mod latency;
 pub mod protocol_analyzer;
+pub mod shaping;
+pub mod tcp_state;
+pub mod threat_detector;
+pub mod throttling;
+pub mod usage_diff;
 
 pub use protocol_analyzer::{
-    AnalysisResult, FlowDirection, ProtocolAnalyzer,
+    AnalysisResult, ProtocolAnalyzer,
     SecurityFlag, TrafficType,
-};
\ No newline at end of file
+};
+pub use classification::{ClassificationConfidence, ClassificationSample};
+pub use duplicates::DuplicateSample;
+pub use fragmentation::MtuSample;
+pub use latency::{LatencySample, LatencyStats};
+pub use throttling::{ThrottleConfidence, ThrottledFlow};
+pub use tcp_state::{LossStats, TcpSession, TcpSummary};
\ No newline at end of file
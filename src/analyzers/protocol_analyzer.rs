@@ -2,6 +2,18 @@
 // Performs deep packet inspection to identify application protocols and security patterns
 // Maintains connection state and generates security alerts
 
+use crate::analyzers::classification::ClassificationTracker;
+use crate::analyzers::discovery::{DeviceDirectory, DiscoveredDevice};
+use crate::analyzers::duplicates::{DuplicateSample, DuplicateTracker};
+use crate::analyzers::fragmentation::{FragmentationTracker, MtuSample};
+use crate::analyzers::throttling::{ThrottleDetector, ThrottledFlow};
+use crate::analyzers::{ClassificationConfidence, ClassificationSample};
+use crate::analyzers::entropy::{FlowEntropy, MIN_FLOW_SAMPLES};
+use crate::analyzers::latency::{LatencySample, LatencyTracker};
+use crate::analyzers::tcp_state::{LossStats, TcpSession, TcpStateTracker, TcpSummary};
+use crate::analyzers::threat_detector::ThreatDetector;
+use crate::collectors::socket_stats::SocketStats;
+use crate::config::SecurityConfig;
 use crate::models::{
     common_application_protocols, ApplicationProtocol, NetworkPacket, TransportProtocol,
 };
@@ -9,6 +21,10 @@ use anyhow::Result;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv6Addr};
 
+/// Clients connect from ports at or above this; a well-known port talking to one is a
+/// confident protocol match
+const FIRST_EPHEMERAL_PORT: u16 = 1024;
+
 /// Advanced protocol analyzer for network traffic inspection
 /// 
 /// Performs deep packet inspection to identify application-layer protocols,
@@ -17,21 +33,31 @@ use std::net::{IpAddr, Ipv6Addr};
 /// 
 /// # Features
 /// 
-/// - Application protocol identification (HTTP, HTTPS, DNS, etc.)
+/// - Application protocol identification (HTTP, HTTPS, DNS, etc.) with a confidence level
+/// - Per-minute share of unclassified traffic, flagged when it grows abnormally
 /// - Connection state tracking with automatic cleanup
+/// - TCP handshake/teardown tracking with retransmission counts
 /// - Security pattern detection (suspicious ports, unencrypted sensitive data)
+/// - Port scan, SYN flood and ICMP sweep detection over a sliding window
 /// - Traffic classification (Web, Email, P2P, etc.)
+/// - LAN device names and types from mDNS and SSDP announcements
 /// - Geolocation analysis (planned)
 /// 
 /// # Example
 /// 
 /// ```rust
+/// use kaipo_watcher::{NetworkPacket, PacketDirection, PacketProtocol, ProtocolAnalyzer};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let packet = NetworkPacket::new("eth0".to_string(), 1500, PacketProtocol::IPv4, PacketDirection::Inbound);
 /// let mut analyzer = ProtocolAnalyzer::new();
 /// let analysis = analyzer.analyze_packet(&packet)?;
 /// 
 /// if let Some(protocol) = analysis.application_protocol {
 ///     println!("Detected {protocol} traffic");
 /// }
+/// # Ok(())
+/// # }
 /// ```
 pub struct ProtocolAnalyzer {
     /// Database of well-known protocols mapped by port number
@@ -40,6 +66,21 @@ pub struct ProtocolAnalyzer {
     protocol_stats: ProtocolStats,
     /// Active connection tracking for state analysis
     connection_tracker: ConnectionTracker,
+    /// TCP connection lifecycles (handshake, teardown, retransmissions and loss)
+    tcp_tracker: TcpStateTracker,
+    /// Passive round-trip time measurements
+    latency_tracker: LatencyTracker,
+    /// Port scan, SYN flood and ICMP sweep heuristics
+    threat_detector: ThreatDetector,
+    /// Classified and unclassified traffic per interface and minute
+    classification_tracker: ClassificationTracker,
+    /// LAN devices that announced themselves over mDNS or SSDP
+    device_directory: DeviceDirectory,
+    /// Packets seen more than once per interface and minute, for loop diagnostics
+    duplicate_tracker: DuplicateTracker,
+    /// IP fragments and "packet too big" messages per remote host and minute
+    fragmentation_tracker: FragmentationTracker,
+    throttle_detector: ThrottleDetector,
 }
 
 /// Statistical counters for protocol analysis
@@ -74,6 +115,10 @@ pub struct ConnectionInfo {
     pub first_seen: chrono::DateTime<chrono::Local>,
     pub last_seen: chrono::DateTime<chrono::Local>,
     pub application_protocol: Option<String>,
+    /// How sure the analyzer is of `application_protocol`
+    pub classification: ClassificationConfidence,
+    /// Sampled payload entropy of the flow
+    pub payload_entropy: FlowEntropy,
 }
 
 #[derive(Debug, Default)]
@@ -90,41 +135,55 @@ impl ConnectionTracker {
         }
     }
 
-    pub fn track_connection(&mut self, packet: &NetworkPacket) {
-        if let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) {
-            let connection_key = format!(
-                "{}:{}-{}:{}",
-                src,
-                packet.source_port.unwrap_or(0),
-                dst,
-                packet.dest_port.unwrap_or(0)
-            );
-
-            if let Some(connection) = self.connections.get_mut(&connection_key) {
+    /// Records the packet against its connection and returns the updated connection
+    /// A new connection is labelled with the protocol identified from its first packet
+    pub fn track_connection(
+        &mut self,
+        packet: &NetworkPacket,
+        application_protocol: Option<&str>,
+        classification: ClassificationConfidence,
+    ) -> Option<&ConnectionInfo> {
+        let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) else {
+            return None;
+        };
+        let connection_key = format!(
+            "{}:{}-{}:{}",
+            src,
+            packet.source_port.unwrap_or(0),
+            dst,
+            packet.dest_port.unwrap_or(0)
+        );
+
+        if !self.connections.contains_key(&connection_key) && self.connections.len() >= self.max_connections {
+            self.cleanup_old_connections();
+        }
+
+        let connection = self.connections
+            .entry(connection_key)
+            .and_modify(|connection| {
                 connection.packets += 1;
                 connection.bytes += packet.size_bytes;
                 connection.last_seen = packet.timestamp;
-            } else {
-                if self.connections.len() >= self.max_connections {
-                    self.cleanup_old_connections();
-                }
-
-                let connection = ConnectionInfo {
-                    source: src,
-                    destination: dst,
-                    source_port: packet.source_port,
-                    dest_port: packet.dest_port,
-                    protocol: packet.transport_protocol,
-                    packets: 1,
-                    bytes: packet.size_bytes,
-                    first_seen: packet.timestamp,
-                    last_seen: packet.timestamp,
-                    application_protocol: None,
-                };
-
-                self.connections.insert(connection_key, connection);
-            }
+            })
+            .or_insert_with(|| ConnectionInfo {
+                source: src,
+                destination: dst,
+                source_port: packet.source_port,
+                dest_port: packet.dest_port,
+                protocol: packet.transport_protocol,
+                packets: 1,
+                bytes: packet.size_bytes,
+                first_seen: packet.timestamp,
+                last_seen: packet.timestamp,
+                application_protocol: application_protocol.map(str::to_string),
+                classification,
+                payload_entropy: FlowEntropy::default(),
+            });
+
+        if let Some(entropy) = packet.payload_entropy {
+            connection.payload_entropy.add_sample(entropy);
         }
+        Some(connection)
     }
 
     #[allow(dead_code)]
@@ -157,21 +216,70 @@ impl ProtocolAnalyzer {
             known_protocols: common_application_protocols(),
             protocol_stats: ProtocolStats::default(),
             connection_tracker: ConnectionTracker::new(10000),
+            tcp_tracker: TcpStateTracker::new(10000),
+            latency_tracker: LatencyTracker::new(10000),
+            threat_detector: ThreatDetector::new(10000),
+            classification_tracker: ClassificationTracker::default(),
+            device_directory: DeviceDirectory::default(),
+            duplicate_tracker: DuplicateTracker::new(),
+            fragmentation_tracker: FragmentationTracker::new(),
+            throttle_detector: ThrottleDetector::new(),
+        }
+    }
+
+    /// Analyzer whose connection tracker keeps at most `max_connections` entries
+    pub fn with_connection_limit(max_connections: usize) -> Self {
+        Self {
+            connection_tracker: ConnectionTracker::new(max_connections),
+            tcp_tracker: TcpStateTracker::new(max_connections),
+            latency_tracker: LatencyTracker::new(max_connections),
+            threat_detector: ThreatDetector::new(max_connections),
+            ..Self::new()
         }
     }
 
+    /// Uses the detection thresholds from the `[security]` configuration section
+    pub fn with_security_config(mut self, config: SecurityConfig) -> Self {
+        self.classification_tracker = ClassificationTracker::new(config.unknown_traffic_percent);
+        self.threat_detector = std::mem::take(&mut self.threat_detector).with_config(config);
+        self
+    }
+
     pub fn analyze_packet(&mut self, packet: &NetworkPacket) -> Result<AnalysisResult> {
-        self.connection_tracker.track_connection(packet);
-        
+        let (application_protocol, classification_confidence) = self.identify_application_protocol(packet);
+        let flow_entropy = self.connection_tracker
+            .track_connection(packet, application_protocol.as_deref(), classification_confidence)
+            .map(|connection| connection.payload_entropy);
+        self.tcp_tracker.observe(packet);
+        self.latency_tracker.observe(packet);
+        self.device_directory.observe(packet);
+        self.duplicate_tracker.observe(packet);
+        self.fragmentation_tracker.observe(packet);
+        self.throttle_detector.observe(packet);
+
         let mut result = AnalysisResult {
-            application_protocol: self.identify_application_protocol(packet),
+            application_protocol,
+            classification_confidence,
             is_encrypted: self.is_encrypted_traffic(packet),
             traffic_type: self.classify_traffic_type(packet),
             security_flags: self.check_security_flags(packet),
+            payload_entropy: flow_entropy.and_then(|flow| flow.value()),
             flow_direction: FlowDirection::Local, // Will be updated below
             geolocation: None, // Will be updated below
         };
 
+        // Flag a flow once, on the sample that makes its entropy trustworthy
+        if let Some(flow) = flow_entropy
+            && !result.is_encrypted
+            && packet.payload_entropy.is_some()
+            && flow.samples == MIN_FLOW_SAMPLES
+            && flow.is_high()
+        {
+            result.security_flags.push(SecurityFlag::HighEntropyPayload(flow.mean));
+        }
+        result.security_flags.extend(self.threat_detector.observe(packet));
+        result.security_flags.extend(self.classification_tracker.observe(packet, classification_confidence));
+
         self.update_stats(packet, &result);
 
         result.flow_direction = self.determine_flow_direction(packet);
@@ -195,17 +303,119 @@ impl ProtocolAnalyzer {
         self.connection_tracker.get_connection_count()
     }
 
-    fn identify_application_protocol(&self, packet: &NetworkPacket) -> Option<String> {
-        if let Some(port) = packet.dest_port.or(packet.source_port) {
-            if let Some(protocol) = self.known_protocols.get(&port) {
-                return Some(protocol.name.clone());
-            }
+    pub fn tcp_summary(&self) -> TcpSummary {
+        self.tcp_tracker.summary()
+    }
+
+    /// Busiest TCP connections with their lifecycle state
+    pub fn top_tcp_sessions(&self, limit: usize) -> Vec<&TcpSession> {
+        self.tcp_tracker.top_sessions(limit)
+    }
+
+    /// Retransmission, duplicate ACK and out-of-order counts per capture interface
+    pub fn tcp_loss_by_interface(&self) -> Vec<(String, LossStats)> {
+        self.tcp_tracker.loss_by_interface()
+    }
+
+    /// TCP connections that closed or were evicted since the last call
+    pub fn take_finished_tcp_sessions(&mut self) -> Vec<TcpSession> {
+        self.tcp_tracker.take_finished()
+    }
+
+    /// Every TCP connection not yet handed out, open ones included; used at end of capture
+    pub fn drain_tcp_sessions(&mut self) -> Vec<TcpSession> {
+        self.tcp_tracker.drain()
+    }
+
+    /// Attaches the kernel's statistics of the host's own sockets to the TCP connections
+    /// they belong to; returns how many connections were matched
+    pub fn attach_socket_stats(&mut self, sockets: &[SocketStats]) -> usize {
+        self.tcp_tracker.attach_socket_stats(sockets)
+    }
+
+    /// Round-trip times measured since the last call
+    pub fn take_latency_samples(&mut self) -> Vec<LatencySample> {
+        self.latency_tracker.take_samples()
+    }
+
+    /// Minutes of classified and unclassified traffic finished since the last call
+    pub fn take_classification_samples(&mut self) -> Vec<ClassificationSample> {
+        self.classification_tracker.take_samples()
+    }
+
+    /// Devices discovered so far, by address
+    pub fn discovered_devices(&self) -> Vec<&DiscoveredDevice> {
+        self.device_directory.devices()
+    }
+
+    /// Devices found or renamed since the last call
+    pub fn take_discovered_devices(&mut self) -> Vec<DiscoveredDevice> {
+        self.device_directory.take_updates()
+    }
+
+    /// Every minute of classification counts not yet handed out; used at end of capture
+    pub fn drain_classification_samples(&mut self) -> Vec<ClassificationSample> {
+        self.classification_tracker.drain()
+    }
+
+    /// Finished minutes of duplicate packet counts since the last call
+    pub fn take_duplicate_samples(&mut self) -> Vec<DuplicateSample> {
+        self.duplicate_tracker.take_samples()
+    }
+
+    /// Every minute of duplicate packet counts not yet handed out; used at end of capture
+    pub fn drain_duplicate_samples(&mut self) -> Vec<DuplicateSample> {
+        self.duplicate_tracker.drain()
+    }
+
+    /// Finished minutes of fragmentation counts since the last call
+    pub fn take_mtu_samples(&mut self) -> Vec<MtuSample> {
+        self.fragmentation_tracker.take_samples()
+    }
+
+    /// Every minute of fragmentation counts not yet handed out; used at end of capture
+    pub fn drain_mtu_samples(&mut self) -> Vec<MtuSample> {
+        self.fragmentation_tracker.drain()
+    }
+
+    /// Connections flagged as held to a steady rate since the last call
+    pub fn take_throttled_flows(&mut self) -> Vec<ThrottledFlow> {
+        self.throttle_detector.take_flagged()
+    }
+
+    /// Judges every connection still followed and returns those flagged; used at end of capture
+    pub fn drain_throttled_flows(&mut self) -> Vec<ThrottledFlow> {
+        self.throttle_detector.drain()
+    }
+
+    /// The application protocol of the packet and how sure the identification is
+    ///
+    /// A well-known port on either side identifies the protocol; it is trusted most when
+    /// the transport matches and the other side uses an ephemeral port, as clients do.
+    fn identify_application_protocol(&self, packet: &NetworkPacket) -> (Option<String>, ClassificationConfidence) {
+        let ports = [(packet.dest_port, packet.source_port), (packet.source_port, packet.dest_port)];
+        for (port, other_port) in ports {
+            let Some(protocol) = port.and_then(|port| self.known_protocols.get(&port)) else {
+                continue;
+            };
+            let confidence = if protocol.transport != packet.transport_protocol {
+                ClassificationConfidence::Low
+            } else if other_port.is_some_and(|port| port >= FIRST_EPHEMERAL_PORT) {
+                ClassificationConfidence::High
+            } else {
+                ClassificationConfidence::Medium
+            };
+            return (Some(protocol.name.clone()), confidence);
         }
 
-        match packet.transport_protocol {
+        let fallback = match packet.transport_protocol {
             TransportProtocol::Tcp => self.analyze_tcp_payload(packet),
             TransportProtocol::Udp => self.analyze_udp_payload(packet),
             _ => None,
+        };
+        match fallback {
+            Some(protocol) => (Some(protocol), ClassificationConfidence::Medium),
+            None => (None, ClassificationConfidence::Unknown),
         }
     }
 
@@ -429,14 +639,33 @@ impl ProtocolAnalyzer {
 #[derive(Debug, Clone)]
 pub struct AnalysisResult {
     pub application_protocol: Option<String>,
+    /// How sure the analyzer is of `application_protocol`
+    pub classification_confidence: ClassificationConfidence,
     pub is_encrypted: bool,
     #[allow(dead_code)]
     pub traffic_type: TrafficType,
     pub security_flags: Vec<SecurityFlag>,
+    /// Mean sampled payload entropy (bits/byte) of the packet's flow, once enough samples exist
+    pub payload_entropy: Option<f64>,
     pub flow_direction: FlowDirection,
     pub geolocation: Option<GeolocationInfo>,
 }
 
+impl Default for AnalysisResult {
+    fn default() -> Self {
+        Self {
+            application_protocol: None,
+            classification_confidence: ClassificationConfidence::Unknown,
+            is_encrypted: false,
+            traffic_type: TrafficType::Other,
+            security_flags: Vec::new(),
+            payload_entropy: None,
+            flow_direction: FlowDirection::Local,
+            geolocation: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TrafficType {
     Web,
@@ -460,6 +689,35 @@ pub enum SecurityFlag {
     UnknownProtocol,
     #[allow(dead_code)]
     LargePayload,
+    /// Flow payload looks encrypted or compressed (mean bits/byte) on a port not known for encryption
+    HighEntropyPayload(f64),
+    /// One source probed this many ports of one host within the detection window
+    PortScan(usize),
+    /// One host received connection attempts from this many distinct sources within the window
+    SynFlood(usize),
+    /// One source pinged this many hosts within the detection window
+    IcmpSweep(usize),
+    /// Percent of an interface's bytes in the last minute no protocol was identified for,
+    /// and its usual percentage
+    UnknownTrafficSurge(f64, f64),
+}
+
+impl SecurityFlag {
+    /// Stable name used as the stored event type
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            SecurityFlag::SuspiciousPort => "SuspiciousPort",
+            SecurityFlag::UnencryptedSensitive => "UnencryptedSensitive",
+            SecurityFlag::HighFrequency => "HighFrequency",
+            SecurityFlag::UnknownProtocol => "UnknownProtocol",
+            SecurityFlag::LargePayload => "LargePayload",
+            SecurityFlag::HighEntropyPayload(_) => "HighEntropyPayload",
+            SecurityFlag::PortScan(_) => "PortScan",
+            SecurityFlag::SynFlood(_) => "SynFlood",
+            SecurityFlag::IcmpSweep(_) => "IcmpSweep",
+            SecurityFlag::UnknownTrafficSurge(..) => "UnknownTrafficSurge",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -570,4 +828,78 @@ mod tests {
         assert_eq!(connections[0].packets, 2);
         assert_eq!(connections[0].bytes, 3000);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_classification_confidence() {
+        let mut analyzer = ProtocolAnalyzer::new();
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            1500,
+            PacketProtocol::IPv4,
+            PacketDirection::Inbound,
+        );
+        packet.transport_protocol = TransportProtocol::Tcp;
+
+        // A reply from a web server to a client port
+        packet.source_port = Some(443);
+        packet.dest_port = Some(51000);
+        let result = analyzer.analyze_packet(&packet).unwrap();
+        assert_eq!(result.application_protocol, Some("HTTPS".to_string()));
+        assert_eq!(result.classification_confidence, ClassificationConfidence::High);
+
+        // Two privileged ports
+        packet.dest_port = Some(25);
+        let result = analyzer.analyze_packet(&packet).unwrap();
+        assert_eq!(result.classification_confidence, ClassificationConfidence::Medium);
+
+        // DNS port over TCP is not what the port table expects
+        packet.source_port = Some(53000);
+        packet.dest_port = Some(53);
+        let result = analyzer.analyze_packet(&packet).unwrap();
+        assert_eq!(result.classification_confidence, ClassificationConfidence::Low);
+
+        packet.dest_port = Some(40000);
+        let result = analyzer.analyze_packet(&packet).unwrap();
+        assert_eq!(result.application_protocol, None);
+        assert_eq!(result.classification_confidence, ClassificationConfidence::Unknown);
+    }
+
+    fn high_entropy_packet(dest_port: u16) -> NetworkPacket {
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            1400,
+            PacketProtocol::IPv4,
+            PacketDirection::Outbound,
+        );
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5)));
+        packet.source_port = Some(50000);
+        packet.dest_port = Some(dest_port);
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.payload_entropy = Some(7.9);
+        packet
+    }
+
+    fn high_entropy_flags(analyzer: &mut ProtocolAnalyzer, packet: &NetworkPacket) -> usize {
+        (0..10)
+            .map(|_| analyzer.analyze_packet(packet).unwrap())
+            .filter(|r| r.security_flags.iter().any(|f| matches!(f, SecurityFlag::HighEntropyPayload(_))))
+            .count()
+    }
+
+    #[test]
+    fn test_high_entropy_flow_flagged_once() {
+        let mut analyzer = ProtocolAnalyzer::new();
+        let packet = high_entropy_packet(9000);
+
+        assert_eq!(high_entropy_flags(&mut analyzer, &packet), 1);
+        let result = analyzer.analyze_packet(&packet).unwrap();
+        assert!(result.payload_entropy.is_some_and(|e| e > 7.8));
+    }
+
+    #[test]
+    fn test_high_entropy_expected_on_tls_ports() {
+        let mut analyzer = ProtocolAnalyzer::new();
+        assert_eq!(high_entropy_flags(&mut analyzer, &high_entropy_packet(443)), 0);
+    }
+}
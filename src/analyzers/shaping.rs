@@ -0,0 +1,552 @@
+// Bandwidth shaping recommendations: per-category QoS classes from stored usage
+// Connections are sorted into categories by their service port, each category's share
+// and busiest hour set its guaranteed rate under a shaper just below the line rate, and
+// the plan is written out as tc, nftables or OpenWrt SQM configuration
+
+use crate::storage::packet_storage::ServiceUsage;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+/// Share of the line rate the shaper allows, so queues build here rather than in the modem
+pub const SHAPED_FRACTION: f64 = 0.9;
+
+/// Most of the shaped rate bulk transfers may take while other traffic waits
+const BULK_CEIL_FRACTION: f64 = 0.6;
+
+/// Inclusive range of service ports, low to high
+pub type PortRange = (u16, u16);
+
+/// Traffic classes recommendations are made for, highest priority first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ShapingCategory {
+    /// DNS, NTP, ICMP, SSH, remote desktops, calls and games: small and latency sensitive
+    Interactive,
+    /// HTTP, HTTPS and QUIC
+    Web,
+    /// RTSP and RTMP
+    Streaming,
+    Email,
+    /// FTP, rsync and BitTorrent
+    Bulk,
+    /// Everything else, and the default class
+    Other,
+}
+
+impl ShapingCategory {
+    pub const ALL: [ShapingCategory; 6] = [
+        ShapingCategory::Interactive,
+        ShapingCategory::Web,
+        ShapingCategory::Streaming,
+        ShapingCategory::Email,
+        ShapingCategory::Bulk,
+        ShapingCategory::Other,
+    ];
+
+    /// Category of a connection from its transport protocol and service (lower) port
+    pub fn classify(protocol: &str, service_port: Option<u16>) -> Self {
+        if matches!(protocol, "Icmp" | "ICMPv6") {
+            return ShapingCategory::Interactive;
+        }
+        let Some(port) = service_port else {
+            return ShapingCategory::Other;
+        };
+        let udp = protocol == "Udp";
+        Self::ALL
+            .into_iter()
+            .find(|category| {
+                let (tcp_ports, udp_ports) = category.ports();
+                let ranges = if udp { udp_ports } else { tcp_ports };
+                (protocol == "Tcp" || udp) && ranges.iter().any(|(low, high)| (*low..=*high).contains(&port))
+            })
+            .unwrap_or(ShapingCategory::Other)
+    }
+
+    /// Inclusive (TCP, UDP) service port ranges of the category
+    pub fn ports(&self) -> (&'static [PortRange], &'static [PortRange]) {
+        match self {
+            ShapingCategory::Interactive => (
+                &[(22, 22), (23, 23), (53, 53), (3389, 3389), (5900, 5900)],
+                &[(53, 53), (123, 123), (3478, 3481), (5060, 5061), (8801, 8810), (19302, 19309)],
+            ),
+            ShapingCategory::Web => (&[(80, 80), (443, 443), (8080, 8080), (8443, 8443)], &[(443, 443)]),
+            ShapingCategory::Streaming => (&[(554, 554), (1935, 1935)], &[]),
+            ShapingCategory::Email => (&[(25, 25), (110, 110), (143, 143), (465, 465), (587, 587), (993, 993), (995, 995)], &[]),
+            ShapingCategory::Bulk => (&[(20, 21), (873, 873), (990, 990), (6881, 6889)], &[(6881, 6889)]),
+            ShapingCategory::Other => (&[], &[]),
+        }
+    }
+
+    /// HTB priority, lower is served first
+    pub fn priority(&self) -> u8 {
+        match self {
+            ShapingCategory::Interactive => 1,
+            ShapingCategory::Web => 2,
+            ShapingCategory::Streaming => 3,
+            ShapingCategory::Email | ShapingCategory::Other => 4,
+            ShapingCategory::Bulk => 5,
+        }
+    }
+
+    /// Minor number of the category's HTB class, e.g. 10 for 1:10
+    pub fn class_minor(&self) -> u16 {
+        match self {
+            ShapingCategory::Interactive => 10,
+            ShapingCategory::Web => 20,
+            ShapingCategory::Streaming => 30,
+            ShapingCategory::Email => 40,
+            ShapingCategory::Other => 50,
+            ShapingCategory::Bulk => 60,
+        }
+    }
+
+    /// DSCP class marked for cake's diffserv4 tins
+    pub fn dscp(&self) -> &'static str {
+        match self {
+            ShapingCategory::Interactive => "ef",
+            ShapingCategory::Streaming => "af41",
+            ShapingCategory::Bulk => "cs1",
+            ShapingCategory::Web | ShapingCategory::Email | ShapingCategory::Other => "cs0",
+        }
+    }
+
+    /// Least share of the shaped rate the category is guaranteed
+    fn floor_share(&self) -> f64 {
+        match self {
+            ShapingCategory::Interactive => 0.1,
+            _ => 0.05,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShapingCategory::Interactive => "interactive",
+            ShapingCategory::Web => "web",
+            ShapingCategory::Streaming => "streaming",
+            ShapingCategory::Email => "email",
+            ShapingCategory::Bulk => "bulk",
+            ShapingCategory::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ShapingCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Traffic of one category over the analyzed period
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryUsage {
+    pub category: ShapingCategory,
+    pub bytes: u64,
+    /// Start of the hour with the most traffic
+    pub peak_hour: Option<DateTime<Local>>,
+    /// Average rate during that hour
+    pub peak_bytes_per_sec: f64,
+}
+
+/// Sums hourly service usage into categories, busiest category first
+pub fn category_usage(rows: &[ServiceUsage]) -> Vec<CategoryUsage> {
+    let mut hourly: HashMap<(ShapingCategory, DateTime<Local>), u64> = HashMap::new();
+    for row in rows {
+        let category = ShapingCategory::classify(&row.protocol, row.service_port);
+        *hourly.entry((category, row.hour)).or_default() += row.bytes;
+    }
+
+    let mut usage: HashMap<ShapingCategory, CategoryUsage> = HashMap::new();
+    for ((category, hour), bytes) in hourly {
+        let entry = usage.entry(category).or_insert(CategoryUsage {
+            category,
+            bytes: 0,
+            peak_hour: None,
+            peak_bytes_per_sec: 0.0,
+        });
+        entry.bytes += bytes;
+        let rate = bytes as f64 / 3600.0;
+        if rate > entry.peak_bytes_per_sec {
+            entry.peak_bytes_per_sec = rate;
+            entry.peak_hour = Some(hour);
+        }
+    }
+
+    let mut usage: Vec<_> = usage.into_values().collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.category.cmp(&b.category)));
+    usage
+}
+
+/// Line rate the plan is shaped below, in Mbit/s
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineRate {
+    pub download_mbps: f64,
+    pub upload_mbps: f64,
+    /// Where the rate came from, e.g. "speed test 2026-10-14 09:00"
+    pub source: String,
+}
+
+/// Guaranteed rate and ceiling of one direction, in kbit/s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassRate {
+    pub rate_kbit: u64,
+    pub ceil_kbit: u64,
+}
+
+/// Recommended HTB class of a category
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapingClass {
+    pub category: ShapingCategory,
+    /// Share of the shaped rate guaranteed to the category
+    pub share: f64,
+    pub download: ClassRate,
+    pub upload: ClassRate,
+}
+
+/// Shaper settings and per-category classes recommended from observed usage
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapingPlan {
+    pub line: LineRate,
+    pub shaped_download_kbit: u64,
+    pub shaped_upload_kbit: u64,
+    pub usage: Vec<CategoryUsage>,
+    pub classes: Vec<ShapingClass>,
+}
+
+impl ShapingPlan {
+    /// Guarantees each category its share of the traffic or of the shaped rate at its
+    /// busiest hour, whichever is larger and never less than its floor, lets every class
+    /// borrow up to the shaped rate and holds bulk transfers back from the top of it
+    pub fn recommend(usage: Vec<CategoryUsage>, line: LineRate) -> Self {
+        let shaped_download_kbit = (line.download_mbps * 1000.0 * SHAPED_FRACTION) as u64;
+        let shaped_upload_kbit = (line.upload_mbps * 1000.0 * SHAPED_FRACTION) as u64;
+        let total_bytes: u64 = usage.iter().map(|u| u.bytes).sum();
+        // Connections are not split by direction, so peaks are weighed against the larger one
+        let shaped_bytes_per_sec = shaped_download_kbit.max(shaped_upload_kbit).max(1) as f64 * 1000.0 / 8.0;
+
+        let weights: Vec<(ShapingCategory, f64)> = ShapingCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let (observed, peak) = usage
+                    .iter()
+                    .find(|u| u.category == category)
+                    .map_or((0.0, 0.0), |u| {
+                        (u.bytes as f64 / total_bytes.max(1) as f64, u.peak_bytes_per_sec / shaped_bytes_per_sec)
+                    });
+                (category, observed.max(peak).max(category.floor_share()))
+            })
+            .collect();
+        let weight_sum: f64 = weights.iter().map(|(_, weight)| weight).sum();
+
+        let class_rate = |shaped_kbit: u64, share: f64, category: ShapingCategory| {
+            let ceil_kbit = if category == ShapingCategory::Bulk {
+                (shaped_kbit as f64 * BULK_CEIL_FRACTION) as u64
+            } else {
+                shaped_kbit
+            };
+            let rate_kbit = ((shaped_kbit as f64 * share) as u64).clamp(1, ceil_kbit.max(1));
+            ClassRate { rate_kbit, ceil_kbit: ceil_kbit.max(rate_kbit) }
+        };
+        let classes = weights
+            .into_iter()
+            .map(|(category, weight)| {
+                let share = weight / weight_sum;
+                ShapingClass {
+                    category,
+                    share,
+                    download: class_rate(shaped_download_kbit, share, category),
+                    upload: class_rate(shaped_upload_kbit, share, category),
+                }
+            })
+            .collect();
+
+        Self {
+            line,
+            shaped_download_kbit,
+            shaped_upload_kbit,
+            usage,
+            classes,
+        }
+    }
+
+    /// Shell script building HTB trees with fq_codel leaves: upload on `interface`
+    /// itself, download on an IFB device its ingress traffic is redirected to
+    pub fn to_tc(&self, interface: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "#!/bin/sh");
+        self.write_header(&mut out, interface);
+        let _ = writeln!(out, "set -e");
+        let _ = writeln!(out, "DEV={interface}");
+        let _ = writeln!(out, "IFB=ifb0");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "tc qdisc del dev $DEV root 2>/dev/null || true");
+        let _ = writeln!(out, "tc qdisc del dev $DEV ingress 2>/dev/null || true");
+        let _ = writeln!(out, "tc qdisc del dev $IFB root 2>/dev/null || true");
+        let _ = writeln!(out, "ip link add $IFB type ifb 2>/dev/null || true");
+        let _ = writeln!(out, "ip link set $IFB up");
+        let _ = writeln!(out, "tc qdisc add dev $DEV handle ffff: ingress");
+        let _ = writeln!(out, "tc filter add dev $DEV parent ffff: protocol all matchall action mirred egress redirect dev $IFB");
+
+        for (device, upload) in [("$DEV", true), ("$IFB", false)] {
+            let _ = writeln!(out);
+            if upload {
+                let _ = writeln!(out, "# Upload: packets to each category's service ports");
+            } else {
+                let _ = writeln!(out, "# Download: packets from each category's service ports");
+            }
+            self.write_htb_tree(&mut out, device, upload);
+            for class in &self.classes {
+                for filter in flower_filters(class.category, upload) {
+                    let _ = writeln!(
+                        out,
+                        "tc filter add dev {device} parent 1: {filter} classid 1:{}",
+                        class.category.class_minor()
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    /// nftables rules steering outgoing packets into the HTB classes with `meta priority`,
+    /// followed by the tc commands creating those classes on `interface`
+    pub fn to_nftables(&self, interface: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "#!/usr/sbin/nft -f");
+        self.write_header(&mut out, interface);
+        let _ = writeln!(out, "# Shapes upload only: incoming packets are queued before nftables sees them,");
+        let _ = writeln!(out, "# so use the tc or sqm export to shape download as well");
+        let _ = writeln!(out, "#");
+        let _ = writeln!(out, "# Create the classes first:");
+        let mut tree = String::new();
+        self.write_htb_tree(&mut tree, interface, true);
+        for line in tree.lines() {
+            let _ = writeln!(out, "#   {line}");
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "table inet kaipo_shaping");
+        let _ = writeln!(out, "delete table inet kaipo_shaping");
+        let _ = writeln!(out, "table inet kaipo_shaping {{");
+        let _ = writeln!(out, "    chain postrouting {{");
+        let _ = writeln!(out, "        type filter hook postrouting priority mangle; policy accept;");
+        for class in &self.classes {
+            for rule in nft_matches(class.category) {
+                let _ = writeln!(
+                    out,
+                    "        oifname \"{interface}\" {rule} meta priority set 1:{}",
+                    class.category.class_minor()
+                );
+            }
+        }
+        let _ = writeln!(out, "    }}");
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    /// OpenWrt SQM queue using cake's diffserv4 tins, plus an fw4 include marking DSCP
+    /// so each category lands in the matching tin
+    pub fn to_sqm(&self, interface: &str) -> String {
+        let mut out = String::new();
+        self.write_header(&mut out, interface);
+        let _ = writeln!(out, "# cake shares bandwidth between its tins by priority rather than by fixed");
+        let _ = writeln!(out, "# per-category rates; use the tc export for those.");
+        let _ = writeln!(out);
+        let _ = writeln!(out, "# /etc/config/sqm");
+        let _ = writeln!(out, "config queue 'kaipo'");
+        for (option, value) in [
+            ("enabled", "1".to_string()),
+            ("interface", interface.to_string()),
+            ("download", self.shaped_download_kbit.to_string()),
+            ("upload", self.shaped_upload_kbit.to_string()),
+            ("qdisc", "cake".to_string()),
+            ("script", "layer_cake.qos".to_string()),
+            ("linklayer", "none".to_string()),
+            ("qdisc_advanced", "1".to_string()),
+            ("squash_dscp", "0".to_string()),
+            ("squash_ingress", "0".to_string()),
+            ("ingress_ecn", "ECN".to_string()),
+            ("egress_ecn", "NOECN".to_string()),
+            ("qdisc_really_really_advanced", "1".to_string()),
+            ("iqdisc_opts", "diffserv4".to_string()),
+            ("eqdisc_opts", "diffserv4".to_string()),
+        ] {
+            let _ = writeln!(out, "\toption {option} '{value}'");
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, "# /etc/nftables.d/30-kaipo-dscp.nft (marks packets leaving through {interface};");
+        let _ = writeln!(out, "# download keeps the marks the sender set)");
+        let _ = writeln!(out, "chain kaipo_dscp {{");
+        let _ = writeln!(out, "    type filter hook postrouting priority mangle; policy accept;");
+        for category in ShapingCategory::ALL.into_iter().filter(|c| c.dscp() != "cs0") {
+            for rule in nft_matches(category) {
+                let _ = writeln!(
+                    out,
+                    "    oifname \"{interface}\" {rule} ip dscp set {dscp}",
+                    dscp = category.dscp()
+                );
+                let _ = writeln!(
+                    out,
+                    "    oifname \"{interface}\" {rule} ip6 dscp set {dscp}",
+                    dscp = category.dscp()
+                );
+            }
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    fn write_header(&self, out: &mut String, interface: &str) {
+        let _ = writeln!(out, "# Bandwidth shaping for {interface}, recommended by kaipo-watcher");
+        let _ = writeln!(
+            out,
+            "# Line rate {:.1} down / {:.1} up Mbit/s ({}), shaped to {:.0}%",
+            self.line.download_mbps,
+            self.line.upload_mbps,
+            self.line.source,
+            SHAPED_FRACTION * 100.0
+        );
+    }
+
+    fn write_htb_tree(&self, out: &mut String, device: &str, upload: bool) {
+        let shaped = if upload { self.shaped_upload_kbit } else { self.shaped_download_kbit };
+        let _ = writeln!(
+            out,
+            "tc qdisc replace dev {device} root handle 1: htb default {}",
+            ShapingCategory::Other.class_minor()
+        );
+        let _ = writeln!(out, "tc class add dev {device} parent 1: classid 1:1 htb rate {shaped}kbit ceil {shaped}kbit");
+        for class in &self.classes {
+            let rate = if upload { class.upload } else { class.download };
+            let minor = class.category.class_minor();
+            let _ = writeln!(
+                out,
+                "tc class add dev {device} parent 1:1 classid 1:{minor} htb rate {}kbit ceil {}kbit prio {}  # {}",
+                rate.rate_kbit,
+                rate.ceil_kbit,
+                class.category.priority(),
+                class.category
+            );
+            let _ = writeln!(out, "tc qdisc add dev {device} parent 1:{minor} fq_codel");
+        }
+    }
+}
+
+/// `tc filter` flower matches for a category, on the service port as destination (upload) or source
+fn flower_filters(category: ShapingCategory, upload: bool) -> Vec<String> {
+    let port_key = if upload { "dst_port" } else { "src_port" };
+    let (tcp_ports, udp_ports) = category.ports();
+    let mut filters = Vec::new();
+    for (family, icmp) in [("ip", "icmp"), ("ipv6", "icmpv6")] {
+        if category == ShapingCategory::Interactive {
+            filters.push(format!("protocol {family} prio {} flower ip_proto {icmp}", category.priority()));
+        }
+        for (transport, ranges) in [("tcp", tcp_ports), ("udp", udp_ports)] {
+            for range in ranges {
+                filters.push(format!(
+                    "protocol {family} prio {} flower ip_proto {transport} {port_key} {}",
+                    category.priority(),
+                    port_range(*range)
+                ));
+            }
+        }
+    }
+    filters
+}
+
+/// nftables matches for a category's outgoing packets, grouped per transport
+fn nft_matches(category: ShapingCategory) -> Vec<String> {
+    let (tcp_ports, udp_ports) = category.ports();
+    let mut matches = Vec::new();
+    if category == ShapingCategory::Interactive {
+        matches.push("meta l4proto { icmp, ipv6-icmp }".to_string());
+    }
+    for (transport, ranges) in [("tcp", tcp_ports), ("udp", udp_ports)] {
+        if !ranges.is_empty() {
+            let ports: Vec<_> = ranges.iter().map(|range| port_range(*range)).collect();
+            matches.push(format!("{transport} dport {{ {} }}", ports.join(", ")));
+        }
+    }
+    matches
+}
+
+/// `22` or `6881-6889`, as both tc flower and nftables write port ranges
+fn port_range((low, high): PortRange) -> String {
+    if low == high { low.to_string() } else { format!("{low}-{high}") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn line() -> LineRate {
+        LineRate { download_mbps: 100.0, upload_mbps: 20.0, source: "speed test".to_string() }
+    }
+
+    #[test]
+    fn test_categories_from_service_ports() {
+        assert_eq!(ShapingCategory::classify("Udp", Some(53)), ShapingCategory::Interactive);
+        assert_eq!(ShapingCategory::classify("ICMPv6", None), ShapingCategory::Interactive);
+        assert_eq!(ShapingCategory::classify("Udp", Some(443)), ShapingCategory::Web);
+        assert_eq!(ShapingCategory::classify("Tcp", Some(6885)), ShapingCategory::Bulk);
+        assert_eq!(ShapingCategory::classify("Tcp", Some(123)), ShapingCategory::Other);
+        assert_eq!(ShapingCategory::classify("Other(47)", Some(22)), ShapingCategory::Other);
+    }
+
+    #[test]
+    fn test_usage_summed_per_category_with_peak_hour() {
+        let hour = Local.with_ymd_and_hms(2026, 10, 14, 20, 0, 0).unwrap();
+        let row = |hour: DateTime<Local>, protocol: &str, port: u16, bytes: u64| ServiceUsage {
+            hour,
+            protocol: protocol.to_string(),
+            service_port: Some(port),
+            bytes,
+        };
+        let usage = category_usage(&[
+            row(hour, "Tcp", 443, 3_600_000),
+            row(hour, "Udp", 443, 3_600_000),
+            row(hour + Duration::hours(1), "Tcp", 80, 360_000),
+            row(hour, "Udp", 53, 36_000),
+        ]);
+
+        assert_eq!(usage[0].category, ShapingCategory::Web);
+        assert_eq!(usage[0].bytes, 7_560_000);
+        assert_eq!(usage[0].peak_hour, Some(hour));
+        assert_eq!(usage[0].peak_bytes_per_sec, 2000.0);
+        assert_eq!(usage[1].category, ShapingCategory::Interactive);
+    }
+
+    #[test]
+    fn test_plan_guarantees_fit_under_the_shaper() {
+        let usage = vec![
+            CategoryUsage { category: ShapingCategory::Bulk, bytes: 900, peak_hour: None, peak_bytes_per_sec: 0.0 },
+            CategoryUsage { category: ShapingCategory::Web, bytes: 100, peak_hour: None, peak_bytes_per_sec: 0.0 },
+        ];
+        let plan = ShapingPlan::recommend(usage, line());
+
+        assert_eq!((plan.shaped_download_kbit, plan.shaped_upload_kbit), (90_000, 18_000));
+        let guaranteed: u64 = plan.classes.iter().map(|c| c.download.rate_kbit).sum();
+        assert!(guaranteed <= plan.shaped_download_kbit);
+        let class = |category| plan.classes.iter().find(|c| c.category == category).unwrap();
+        // Interactive keeps its floor even though nothing was seen, bulk is capped below the shaper
+        assert!(class(ShapingCategory::Interactive).share >= 0.05);
+        assert_eq!(class(ShapingCategory::Bulk).download.ceil_kbit, 54_000);
+        assert_eq!(class(ShapingCategory::Web).download.ceil_kbit, 90_000);
+    }
+
+    #[test]
+    fn test_exports_reference_the_same_classes() {
+        let plan = ShapingPlan::recommend(Vec::new(), line());
+
+        let tc = plan.to_tc("eth0");
+        assert!(tc.contains("tc class add dev $DEV parent 1: classid 1:1 htb rate 18000kbit ceil 18000kbit"));
+        assert!(tc.contains("tc filter add dev $DEV parent 1: protocol ip prio 1 flower ip_proto udp dst_port 3478-3481 classid 1:10"));
+        assert!(tc.contains("tc filter add dev $IFB parent 1: protocol ipv6 prio 2 flower ip_proto tcp src_port 443 classid 1:20"));
+
+        let nft = plan.to_nftables("eth0");
+        assert!(nft.contains("oifname \"eth0\" udp dport { 6881-6889 } meta priority set 1:60"));
+
+        let sqm = plan.to_sqm("wan");
+        assert!(sqm.contains("\toption download '90000'"));
+        assert!(sqm.contains("oifname \"wan\" tcp dport { 20-21, 873, 990, 6881-6889 } ip dscp set cs1"));
+    }
+}
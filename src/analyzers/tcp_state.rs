@@ -0,0 +1,710 @@
+// TcpStateTracker: Follows TCP connections through handshake, data transfer and teardown
+// Times each connection, counts retransmitted segments, duplicate ACKs and out-of-order
+// segments, and spots half-open connections whose handshake never completed
+
+use crate::collectors::socket_stats::{SocketStats, TcpInfo};
+use crate::models::{NetworkPacket, TcpSegment};
+use chrono::{DateTime, Duration, Local};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+
+/// How long a handshake may take before the connection counts as half-open
+pub const HANDSHAKE_TIMEOUT_SECS: i64 = 3;
+
+/// Connections idle this long are dropped when the tracker is full
+const IDLE_TIMEOUT_MINUTES: i64 = 5;
+
+/// An identical segment within this window is the same packet captured twice
+/// (loopback and `any` captures see both the outgoing and incoming copy)
+pub(crate) const DUPLICATE_WINDOW_MICROS: i64 = 1000;
+
+/// Identical segments with the same TCP timestamp were sent within one timestamp tick, so
+/// they are copies even when a busy capture loop saw them further apart; a resend waits at
+/// least the minimum retransmission timeout and carries a new TSval
+const DUPLICATE_TIMESTAMPED_WINDOW_MICROS: i64 = 200_000;
+
+/// Recent segments per direction compared against for duplicate captures; the two copies
+/// of a loopback packet can have other segments of the same direction between them
+const RECENT_SEGMENTS: usize = 8;
+
+/// A segment filling a sequence gap this soon after the gap appeared was reordered on
+/// the way; later than this it is the resend of a segment lost before the capture point
+const REORDER_WINDOW_MICROS: i64 = 3000;
+
+/// Lifecycle state of a TCP connection as seen on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TcpState {
+    /// Client sent SYN, no answer yet
+    SynSent,
+    /// Server answered with SYN-ACK, final ACK outstanding
+    SynReceived,
+    /// Handshake completed, or the connection was already open when capture started
+    Established,
+    /// One side sent FIN
+    Closing,
+    /// Both sides sent FIN
+    Closed,
+    /// Aborted with RST
+    Reset,
+}
+
+impl TcpState {
+    pub fn is_handshaking(&self) -> bool {
+        matches!(self, TcpState::SynSent | TcpState::SynReceived)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, TcpState::Closed | TcpState::Reset)
+    }
+}
+
+impl fmt::Display for TcpState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TcpState::SynSent => "SYN-SENT",
+            TcpState::SynReceived => "SYN-RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::Closing => "CLOSING",
+            TcpState::Closed => "CLOSED",
+            TcpState::Reset => "RESET",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Segment counts behind the loss estimate of a connection or interface
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LossStats {
+    /// Segments occupying sequence space (data, SYN or FIN)
+    pub segments: u64,
+    /// Segments that resent sequence space already seen in the same direction
+    pub retransmissions: u64,
+    /// Pure ACKs repeating the previous acknowledgement while data was outstanding
+    pub duplicate_acks: u64,
+    /// Segments that arrived after later data in the same direction
+    pub out_of_order: u64,
+}
+
+impl LossStats {
+    /// Share of segments that had to be resent, as an estimate of packet loss
+    pub fn loss_rate(&self) -> f64 {
+        if self.segments == 0 {
+            0.0
+        } else {
+            self.retransmissions as f64 / self.segments as f64
+        }
+    }
+
+    pub fn add(&mut self, other: &LossStats) {
+        self.segments += other.segments;
+        self.retransmissions += other.retransmissions;
+        self.duplicate_acks += other.duplicate_acks;
+        self.out_of_order += other.out_of_order;
+    }
+}
+
+/// Sequence and acknowledgement tracking for one direction of a connection
+#[derive(Debug, Clone, Default)]
+struct DirectionState {
+    /// End of the highest sequence space seen
+    seq_end: Option<u32>,
+    /// Latest gap skipped by a segment that arrived ahead of it: (start, end, seen at)
+    hole: Option<(u32, u32, DateTime<Local>)>,
+    /// Latest segments, to recognise duplicate captures
+    recent: VecDeque<(TcpSegment, DateTime<Local>)>,
+    /// Acknowledgement number and window of the previous ACK
+    last_ack: Option<(u32, u16)>,
+    fin: bool,
+}
+
+impl DirectionState {
+    /// Classifies a segment by its sequence space: new data, out of order or resent
+    fn classify(&mut self, segment: &TcpSegment, at: DateTime<Local>) -> LossStats {
+        let len = segment.sequence_len();
+        if len == 0 {
+            return LossStats::default();
+        }
+        let mut outcome = LossStats { segments: 1, ..LossStats::default() };
+        let (start, end) = (segment.sequence, segment.sequence.wrapping_add(len));
+        let Some(highest) = self.seq_end else {
+            self.seq_end = Some(end);
+            return outcome;
+        };
+
+        if seq_after(end, highest) {
+            if seq_after(start, highest) {
+                self.hole = Some((highest, start, at));
+            }
+            self.seq_end = Some(end);
+        } else if let Some((hole_start, hole_end, opened)) = self.hole
+            && !seq_after(hole_start, start)
+            && !seq_after(end, hole_end)
+        {
+            // Fills (part of) the gap: shrink it from the front, forget it once filled
+            self.hole = match (start == hole_start, end == hole_end) {
+                (true, true) => None,
+                (true, false) => Some((end, hole_end, opened)),
+                _ => self.hole,
+            };
+            let reordered = (at - opened).num_microseconds().is_some_and(|us| us < REORDER_WINDOW_MICROS);
+            if reordered {
+                outcome.out_of_order = 1;
+            } else {
+                outcome.retransmissions = 1;
+            }
+        } else {
+            outcome.retransmissions = 1;
+        }
+        outcome
+    }
+
+    /// Remembers the acknowledgement of an ACK; true when it repeats the previous one
+    /// without carrying data while the peer still has unacknowledged data in flight
+    fn acknowledge(&mut self, segment: &TcpSegment, peer_seq_end: Option<u32>) -> bool {
+        if !segment.has(TcpSegment::ACK) {
+            return false;
+        }
+        let ack = (segment.acknowledgement, segment.window);
+        let pure = segment.payload_len == 0
+            && !segment.has(TcpSegment::SYN | TcpSegment::FIN | TcpSegment::RST);
+        let outstanding = peer_seq_end.is_some_and(|end| seq_after(end, segment.acknowledgement));
+        let duplicate = pure && outstanding && self.last_ack == Some(ack);
+        self.last_ack = Some(ack);
+        duplicate
+    }
+}
+
+/// Serial number comparison, so wrap-around at 2^32 is not mistaken for going backwards
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// One TCP connection, oriented from the side that opened it
+#[derive(Debug, Clone)]
+pub struct TcpSession {
+    pub interface: String,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub state: TcpState,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    /// When the final handshake ACK was seen (None for connections picked up mid-stream)
+    pub established_at: Option<DateTime<Local>>,
+    pub closed_at: Option<DateTime<Local>>,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Retransmissions, duplicate ACKs and out-of-order segments in both directions
+    pub loss: LossStats,
+    /// The kernel's statistics of the host's socket for this connection, as last read
+    pub kernel: Option<TcpInfo>,
+    client_side: DirectionState,
+    server_side: DirectionState,
+}
+
+impl TcpSession {
+    fn open(packet: &NetworkPacket, source: SocketAddr, dest: SocketAddr, segment: &TcpSegment) -> Self {
+        let syn = segment.has(TcpSegment::SYN);
+        let ack = segment.has(TcpSegment::ACK);
+        // A SYN-ACK as first packet means the client's SYN was missed
+        let (client, server, state) = match (syn, ack) {
+            (true, false) => (source, dest, TcpState::SynSent),
+            (true, true) => (dest, source, TcpState::SynReceived),
+            _ => (source, dest, TcpState::Established),
+        };
+
+        Self {
+            interface: packet.interface.clone(),
+            client,
+            server,
+            state,
+            first_seen: packet.timestamp,
+            last_seen: packet.timestamp,
+            established_at: None,
+            closed_at: None,
+            packets: 0,
+            bytes: 0,
+            loss: LossStats::default(),
+            kernel: None,
+            client_side: DirectionState::default(),
+            server_side: DirectionState::default(),
+        }
+    }
+
+    /// Time from first packet to close, or to the latest packet while still open
+    pub fn duration(&self) -> Duration {
+        self.closed_at.unwrap_or(self.last_seen) - self.first_seen
+    }
+
+    /// Time from SYN to the final handshake ACK
+    pub fn handshake_time(&self) -> Option<Duration> {
+        self.established_at.map(|established| established - self.first_seen)
+    }
+
+    /// Handshake that has been waiting longer than `HANDSHAKE_TIMEOUT_SECS` at `now`
+    pub fn is_half_open(&self, now: DateTime<Local>) -> bool {
+        self.state.is_handshaking() && now - self.first_seen >= Duration::seconds(HANDSHAKE_TIMEOUT_SECS)
+    }
+
+    /// Applies one segment; returns what it added to the loss counters
+    fn update(&mut self, packet: &NetworkPacket, segment: &TcpSegment, from_client: bool) -> LossStats {
+        self.packets += 1;
+        self.bytes += packet.size_bytes;
+        self.last_seen = packet.timestamp;
+
+        let (side, peer) = if from_client {
+            (&mut self.client_side, &self.server_side)
+        } else {
+            (&mut self.server_side, &self.client_side)
+        };
+        let window = match segment.timestamps {
+            Some(_) => DUPLICATE_TIMESTAMPED_WINDOW_MICROS,
+            None => DUPLICATE_WINDOW_MICROS,
+        };
+        let duplicate = side.recent.iter().any(|(previous, at)| {
+            previous == segment && (packet.timestamp - *at).num_microseconds().is_some_and(|us| us < window)
+        });
+        if side.recent.len() == RECENT_SEGMENTS {
+            side.recent.pop_front();
+        }
+        side.recent.push_back((*segment, packet.timestamp));
+        let mut outcome = LossStats::default();
+        if !duplicate {
+            outcome = side.classify(segment, packet.timestamp);
+            outcome.duplicate_acks = u64::from(side.acknowledge(segment, peer.seq_end));
+            self.loss.add(&outcome);
+        }
+
+        if self.state.is_finished() {
+            return outcome;
+        }
+
+        let syn = segment.has(TcpSegment::SYN);
+        if segment.has(TcpSegment::RST) {
+            self.state = TcpState::Reset;
+            self.closed_at = Some(packet.timestamp);
+        } else if syn && !from_client && self.state == TcpState::SynSent {
+            self.state = TcpState::SynReceived;
+        } else if !syn && from_client && segment.has(TcpSegment::ACK) && self.state.is_handshaking() {
+            self.state = TcpState::Established;
+            self.established_at = Some(packet.timestamp);
+        }
+
+        if segment.has(TcpSegment::FIN) && self.state != TcpState::Reset {
+            if from_client {
+                self.client_side.fin = true;
+            } else {
+                self.server_side.fin = true;
+            }
+            self.state = if self.client_side.fin && self.server_side.fin { TcpState::Closed } else { TcpState::Closing };
+            if self.state == TcpState::Closed {
+                self.closed_at = Some(packet.timestamp);
+            }
+        }
+        outcome
+    }
+}
+
+/// Connection counts across everything the tracker has seen
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TcpSummary {
+    /// Handshakes seen through to the final ACK
+    pub handshakes: u64,
+    /// Connections currently established or closing
+    pub open: usize,
+    /// Handshakes still waiting past the timeout
+    pub half_open: usize,
+    /// Connections closed with FIN from both sides
+    pub closed: u64,
+    /// Connections aborted with RST
+    pub reset: u64,
+    pub loss: LossStats,
+}
+
+/// Follows TCP connections by their address pairs
+#[derive(Debug, Default)]
+pub struct TcpStateTracker {
+    /// Keyed by (client, server)
+    sessions: HashMap<(SocketAddr, SocketAddr), TcpSession>,
+    max_sessions: usize,
+    /// Closed or evicted sessions not yet handed out by `take_finished`
+    finished: Vec<TcpSession>,
+    /// Timestamp of the newest packet, the tracker's notion of "now"
+    latest: Option<DateTime<Local>>,
+    handshakes: u64,
+    closed: u64,
+    reset: u64,
+    loss: LossStats,
+    /// Loss counters per capture interface, finished connections included
+    interface_loss: HashMap<String, LossStats>,
+}
+
+impl TcpStateTracker {
+    pub fn new(max_sessions: usize) -> Self {
+        Self {
+            max_sessions,
+            ..Self::default()
+        }
+    }
+
+    /// Records a TCP packet and returns the connection it belongs to
+    pub fn observe(&mut self, packet: &NetworkPacket) -> Option<&TcpSession> {
+        let segment = packet.tcp?;
+        let source = SocketAddr::new(packet.source_addr?, packet.source_port?);
+        let dest = SocketAddr::new(packet.dest_addr?, packet.dest_port?);
+        self.latest = Some(self.latest.map_or(packet.timestamp, |latest| latest.max(packet.timestamp)));
+
+        let key = if self.sessions.contains_key(&(dest, source)) { (dest, source) } else { (source, dest) };
+
+        // A fresh SYN on a finished connection means the port pair is being reused
+        let reopened = segment.has(TcpSegment::SYN)
+            && !segment.has(TcpSegment::ACK)
+            && self.sessions.get(&key).is_some_and(|s| s.state.is_finished());
+        if reopened {
+            self.sessions.remove(&key);
+        }
+
+        let key = if self.sessions.contains_key(&key) {
+            key
+        } else {
+            if self.sessions.len() >= self.max_sessions {
+                self.evict_idle();
+            }
+            let session = TcpSession::open(packet, source, dest, &segment);
+            let key = (session.client, session.server);
+            self.sessions.insert(key, session);
+            key
+        };
+
+        let session = self.sessions.get_mut(&key)?;
+        let from_client = session.client == source;
+        let was_handshaking = session.state.is_handshaking();
+        let was_finished = session.state.is_finished();
+        let outcome = session.update(packet, &segment, from_client);
+        if outcome != LossStats::default() {
+            self.loss.add(&outcome);
+            self.interface_loss.entry(session.interface.clone()).or_default().add(&outcome);
+        }
+
+        if was_handshaking && session.state == TcpState::Established {
+            self.handshakes += 1;
+        }
+        if !was_finished && session.state.is_finished() {
+            match session.state {
+                TcpState::Reset => self.reset += 1,
+                _ => self.closed += 1,
+            }
+            self.finished.push(session.clone());
+        }
+        Some(session)
+    }
+
+    /// Tracked connections, busiest first
+    pub fn top_sessions(&self, limit: usize) -> Vec<&TcpSession> {
+        let mut sessions: Vec<&TcpSession> = self.sessions.values().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.bytes));
+        sessions.truncate(limit);
+        sessions
+    }
+
+    /// The tracked connection between two endpoints, in either direction
+    pub fn session(&self, a: SocketAddr, b: SocketAddr) -> Option<&TcpSession> {
+        self.sessions.get(&(a, b)).or_else(|| self.sessions.get(&(b, a)))
+    }
+
+    /// Attaches the kernel's statistics of the host's own sockets to the open connections
+    /// they belong to; returns how many connections were matched
+    pub fn attach_socket_stats(&mut self, sockets: &[SocketStats]) -> usize {
+        let mut matched = 0;
+        for socket in sockets {
+            let key = match self.sessions.contains_key(&(socket.local, socket.remote)) {
+                true => (socket.local, socket.remote),
+                false => (socket.remote, socket.local),
+            };
+            if let Some(session) = self.sessions.get_mut(&key).filter(|session| !session.state.is_finished()) {
+                session.kernel = Some(socket.info.clone());
+                matched += 1;
+            }
+        }
+        matched
+    }
+
+    /// Loss counters per interface, sorted by interface name
+    pub fn loss_by_interface(&self) -> Vec<(String, LossStats)> {
+        let mut interfaces: Vec<_> = self.interface_loss.iter().map(|(name, loss)| (name.clone(), *loss)).collect();
+        interfaces.sort_by(|a, b| a.0.cmp(&b.0));
+        interfaces
+    }
+
+    pub fn summary(&self) -> TcpSummary {
+        let now = self.latest.unwrap_or_else(Local::now);
+        TcpSummary {
+            handshakes: self.handshakes,
+            open: self
+                .sessions
+                .values()
+                .filter(|s| matches!(s.state, TcpState::Established | TcpState::Closing))
+                .count(),
+            half_open: self.sessions.values().filter(|s| s.is_half_open(now)).count(),
+            closed: self.closed,
+            reset: self.reset,
+            loss: self.loss,
+        }
+    }
+
+    /// Connections that closed or were evicted since the last call
+    pub fn take_finished(&mut self) -> Vec<TcpSession> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Finished connections plus every connection still open, emptying the tracker
+    pub fn drain(&mut self) -> Vec<TcpSession> {
+        let mut sessions = self.take_finished();
+        sessions.extend(
+            self.sessions
+                .drain()
+                .map(|(_, session)| session)
+                .filter(|session| !session.state.is_finished()),
+        );
+        sessions
+    }
+
+    /// Forgets finished connections and those idle for a while; the latter are still
+    /// handed out through `take_finished` so their last state is not lost
+    fn evict_idle(&mut self) {
+        let cutoff = self.latest.unwrap_or_else(Local::now) - Duration::minutes(IDLE_TIMEOUT_MINUTES);
+        let finished = &mut self.finished;
+        self.sessions.retain(|_, session| {
+            if session.state.is_finished() {
+                return false;
+            }
+            if session.last_seen < cutoff {
+                finished.push(session.clone());
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol, TransportProtocol};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const CLIENT: (Ipv4Addr, u16) = (Ipv4Addr::new(192, 168, 1, 10), 50000);
+    const SERVER: (Ipv4Addr, u16) = (Ipv4Addr::new(203, 0, 113, 5), 443);
+
+    fn segment(from_client: bool, flags: u8, sequence: u32, payload_len: u32, at_ms: i64) -> NetworkPacket {
+        let (src, dst) = if from_client { (CLIENT, SERVER) } else { (SERVER, CLIENT) };
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            60 + payload_len as u64,
+            PacketProtocol::IPv4,
+            PacketDirection::Outbound,
+        );
+        packet.timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap().with_timezone(&Local)
+            + Duration::milliseconds(at_ms);
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_addr = Some(IpAddr::V4(src.0));
+        packet.source_port = Some(src.1);
+        packet.dest_addr = Some(IpAddr::V4(dst.0));
+        packet.dest_port = Some(dst.1);
+        packet.tcp = Some(TcpSegment { flags, sequence, acknowledgement: 0, window: 0, payload_len, timestamps: None });
+        packet
+    }
+
+    const SYN: u8 = TcpSegment::SYN;
+    const ACK: u8 = TcpSegment::ACK;
+    const FIN_ACK: u8 = TcpSegment::FIN | TcpSegment::ACK;
+
+    #[test]
+    fn test_handshake_transfer_and_teardown() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        tracker.observe(&segment(false, SYN | ACK, 5000, 0, 20));
+        tracker.observe(&segment(true, ACK, 1001, 0, 40));
+        tracker.observe(&segment(true, ACK, 1001, 500, 50));
+        tracker.observe(&segment(false, ACK, 5001, 1400, 80));
+        tracker.observe(&segment(true, FIN_ACK, 1501, 0, 1000));
+        let session = tracker.observe(&segment(false, FIN_ACK, 6401, 0, 1010)).unwrap();
+
+        assert_eq!(session.state, TcpState::Closed);
+        assert_eq!(session.client.port(), CLIENT.1);
+        assert_eq!(session.handshake_time(), Some(Duration::milliseconds(40)));
+        assert_eq!(session.duration(), Duration::milliseconds(1010));
+        assert_eq!(session.loss.retransmissions, 0);
+
+        let summary = tracker.summary();
+        assert_eq!((summary.handshakes, summary.closed, summary.open), (1, 1, 0));
+        assert_eq!(tracker.take_finished().len(), 1);
+    }
+
+    #[test]
+    fn test_retransmissions_counted_per_direction() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        tracker.observe(&segment(true, SYN, 1000, 0, 1000));
+        tracker.observe(&segment(false, SYN | ACK, 5000, 0, 1020));
+        tracker.observe(&segment(true, ACK, 1001, 0, 1040));
+        tracker.observe(&segment(true, ACK, 1001, 500, 1050));
+        tracker.observe(&segment(true, ACK, 1001, 500, 1300));
+        // Each direction has its own sequence space
+        tracker.observe(&segment(false, ACK, 5001, 500, 1310));
+
+        let summary = tracker.summary();
+        assert_eq!(summary.loss.retransmissions, 2);
+        assert_eq!(summary.open, 1);
+    }
+
+    #[test]
+    fn test_duplicate_capture_is_not_retransmission() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        tracker.observe(&segment(false, SYN | ACK, 5000, 0, 1));
+        tracker.observe(&segment(false, SYN | ACK, 5000, 0, 1));
+        tracker.observe(&segment(true, ACK, 1001, 0, 2));
+        tracker.observe(&segment(true, ACK, 1001, 0, 2));
+
+        let summary = tracker.summary();
+        assert_eq!((summary.loss.retransmissions, summary.handshakes), (0, 1));
+    }
+
+    #[test]
+    fn test_timestamped_copy_seen_late_is_not_retransmission() {
+        let mut tracker = TcpStateTracker::new(100);
+        let mut syn = segment(true, SYN, 1000, 0, 0);
+        if let Some(ref mut tcp) = syn.tcp {
+            tcp.timestamps = Some((77, 0));
+        }
+        tracker.observe(&syn);
+        // A busy capture loop stamped the second copy 50ms later
+        syn.timestamp += Duration::milliseconds(50);
+        tracker.observe(&syn);
+        assert_eq!(tracker.summary().loss.retransmissions, 0);
+
+        // The real resend after the retransmission timeout carries a new TSval
+        syn.timestamp += Duration::seconds(1);
+        if let Some(ref mut tcp) = syn.tcp {
+            tcp.timestamps = Some((1077, 0));
+        }
+        tracker.observe(&syn);
+        assert_eq!(tracker.summary().loss.retransmissions, 1);
+    }
+
+    #[test]
+    fn test_half_open_and_reset() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        assert_eq!(tracker.summary().half_open, 0);
+
+        // Another connection's traffic moves the clock past the handshake timeout
+        let mut later = segment(true, ACK, 1, 10, 4000);
+        later.source_port = Some(50001);
+        tracker.observe(&later);
+        assert_eq!(tracker.summary().half_open, 1);
+
+        let session = tracker.observe(&segment(false, TcpSegment::RST | ACK, 0, 0, 4100)).unwrap();
+        assert_eq!(session.state, TcpState::Reset);
+        assert_eq!(tracker.summary().reset, 1);
+        assert_eq!(tracker.summary().half_open, 0);
+    }
+
+    #[test]
+    fn test_sequence_wrap_is_not_retransmission() {
+        let mut side = DirectionState::default();
+        let at = Local::now();
+        let data = |sequence| TcpSegment {
+            flags: ACK,
+            sequence,
+            acknowledgement: 0,
+            window: 0,
+            payload_len: 100,
+            timestamps: None,
+        };
+        assert_eq!(side.classify(&data(u32::MAX - 99), at).retransmissions, 0);
+        assert_eq!(side.classify(&data(0), at).retransmissions, 0);
+        assert_eq!(side.classify(&data(0), at).retransmissions, 1);
+    }
+
+    fn ack(from_client: bool, acknowledgement: u32, at_ms: i64) -> NetworkPacket {
+        let mut packet = segment(from_client, ACK, 1, 0, at_ms);
+        if let Some(ref mut tcp) = packet.tcp {
+            tcp.acknowledgement = acknowledgement;
+            tcp.window = 512;
+        }
+        packet
+    }
+
+    #[test]
+    fn test_duplicate_acks_and_loss_rate() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(false, ACK, 5000, 500, 0));
+        tracker.observe(&segment(false, ACK, 5500, 500, 10));
+        tracker.observe(&segment(false, ACK, 6000, 500, 20));
+        tracker.observe(&ack(true, 5500, 30));
+        // Receiver keeps asking for 5500 while later data arrives
+        tracker.observe(&ack(true, 5500, 40));
+        tracker.observe(&ack(true, 5500, 50));
+        tracker.observe(&segment(false, ACK, 5500, 500, 60));
+        // Everything acknowledged: a repeat is no longer a duplicate ACK
+        tracker.observe(&ack(true, 6500, 70));
+        tracker.observe(&ack(true, 6500, 80));
+
+        let loss = tracker.summary().loss;
+        assert_eq!((loss.segments, loss.retransmissions, loss.duplicate_acks), (4, 1, 2));
+        assert_eq!(loss.loss_rate(), 0.25);
+        assert_eq!(tracker.loss_by_interface(), vec![("eth0".to_string(), loss)]);
+    }
+
+    #[test]
+    fn test_reordered_segment_is_not_retransmission() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, ACK, 1000, 100, 0));
+        // 1100..1200 is overtaken by the next segment and arrives just after it
+        tracker.observe(&segment(true, ACK, 1200, 100, 10));
+        tracker.observe(&segment(true, ACK, 1100, 100, 11));
+        // A second gap filled well after it appeared was resent by the sender
+        tracker.observe(&segment(true, ACK, 1400, 100, 20));
+        tracker.observe(&segment(true, ACK, 1300, 100, 300));
+
+        let client = SocketAddr::new(IpAddr::V4(CLIENT.0), CLIENT.1);
+        let server = SocketAddr::new(IpAddr::V4(SERVER.0), SERVER.1);
+        let loss = tracker.session(server, client).unwrap().loss;
+        assert_eq!((loss.out_of_order, loss.retransmissions), (1, 1));
+    }
+
+    #[test]
+    fn test_kernel_socket_stats_follow_the_connection() {
+        let mut tracker = TcpStateTracker::new(100);
+        tracker.observe(&segment(true, SYN, 1000, 0, 0));
+        tracker.observe(&segment(false, SYN | ACK, 5000, 0, 20));
+        tracker.observe(&segment(true, ACK, 1001, 0, 40));
+
+        let client = SocketAddr::new(IpAddr::V4(CLIENT.0), CLIENT.1);
+        let server = SocketAddr::new(IpAddr::V4(SERVER.0), SERVER.1);
+        let info = TcpInfo {
+            congestion_window: 10,
+            rtt_us: 20_000,
+            rtt_var_us: 1_000,
+            retransmits: 2,
+            lost: 0,
+            congestion_control: Some("cubic".to_string()),
+        };
+        // The host is the server: its socket's local end is the connection's server side
+        let sockets = [
+            SocketStats { local: server, remote: client, info: info.clone() },
+            SocketStats { local: server, remote: "198.51.100.1:22".parse().unwrap(), info: info.clone() },
+        ];
+        assert_eq!(tracker.attach_socket_stats(&sockets), 1);
+
+        // The statistics stay with the connection once it closes
+        tracker.observe(&segment(true, FIN_ACK, 1001, 0, 1000));
+        tracker.observe(&segment(false, FIN_ACK, 5001, 0, 1010));
+        let finished = tracker.take_finished();
+        assert_eq!(finished[0].kernel.as_ref(), Some(&info));
+        assert_eq!(tracker.attach_socket_stats(&sockets), 0);
+    }
+}
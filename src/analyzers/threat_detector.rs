@@ -0,0 +1,281 @@
+// ThreatDetector: Stateful heuristics for port scans, SYN floods and ICMP sweeps
+// Counts distinct ports, sources or hosts per key over a sliding window and raises one
+// flag when a threshold is crossed, then stays quiet for that key for a full window
+
+use crate::analyzers::protocol_analyzer::SecurityFlag;
+use crate::config::SecurityConfig;
+use crate::models::{NetworkPacket, TcpSegment, TransportProtocol};
+use chrono::{DateTime, Duration, Local};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+
+/// Stored event types raised by the detector
+pub const DETECTION_EVENT_TYPES: [&str; 3] = ["PortScan", "SynFlood", "IcmpSweep"];
+
+/// UDP datagrams sent from ports below this are taken as service replies, not probes
+const FIRST_UNPRIVILEGED_PORT: u16 = 1024;
+
+/// ICMP echo request type
+const ICMP_ECHO_REQUEST: u8 = 8;
+/// ICMPv6 echo request type
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+
+/// Distinct items seen for one key within the window
+#[derive(Debug)]
+struct WindowedSet<T> {
+    /// Item -> when it was last seen
+    items: HashMap<T, DateTime<Local>>,
+    last_seen: DateTime<Local>,
+    /// No further flag for this key before this time
+    quiet_until: Option<DateTime<Local>>,
+}
+
+/// Per-key sets of distinct items, bounded in both keys and items per key
+#[derive(Debug)]
+struct WindowedSets<K, T> {
+    sets: HashMap<K, WindowedSet<T>>,
+    max_keys: usize,
+}
+
+impl<K: Eq + Hash, T: Eq + Hash> WindowedSets<K, T> {
+    fn new(max_keys: usize) -> Self {
+        Self { sets: HashMap::new(), max_keys }
+    }
+
+    /// Records `item` under `key`; returns the number of distinct items when this
+    /// pushes the key to `threshold` within `window`
+    fn record(&mut self, key: K, item: T, at: DateTime<Local>, window: Duration, threshold: usize) -> Option<usize> {
+        if !self.sets.contains_key(&key) && self.sets.len() >= self.max_keys {
+            self.sets.retain(|_, set| {
+                at - set.last_seen < window || set.quiet_until.is_some_and(|until| until > at)
+            });
+            // Still full of active keys: new keys are not tracked until some go quiet
+            if self.sets.len() >= self.max_keys {
+                return None;
+            }
+        }
+
+        let set = self.sets.entry(key).or_insert_with(|| WindowedSet {
+            items: HashMap::new(),
+            last_seen: at,
+            quiet_until: None,
+        });
+        set.last_seen = at;
+        if set.quiet_until.is_some_and(|until| at < until) {
+            return None;
+        }
+        set.items.insert(item, at);
+
+        // Old items are only dropped once they could make a difference
+        if set.items.len() >= threshold {
+            set.items.retain(|_, seen| at - *seen < window);
+        }
+        if set.items.len() < threshold {
+            return None;
+        }
+        let count = set.items.len();
+        set.items.clear();
+        set.quiet_until = Some(at + window);
+        Some(count)
+    }
+}
+
+/// Detects port scans, SYN floods and ICMP sweeps from the packet stream
+///
+/// - Port scan: one source sends TCP probes without ACK (SYN, FIN, NULL or Xmas), or UDP
+///   datagrams from an unprivileged port, to many ports of one host. Replies are left out
+///   so a busy server answering a client's ephemeral ports does not look like a scan
+/// - SYN flood: one host receives connection attempts from many distinct source
+///   addresses and ports; resent SYNs count once
+/// - ICMP sweep: one source sends echo requests to many hosts
+#[derive(Debug)]
+pub struct ThreatDetector {
+    config: SecurityConfig,
+    port_probes: WindowedSets<(IpAddr, IpAddr), u16>,
+    syn_sources: WindowedSets<IpAddr, (IpAddr, u16)>,
+    ping_targets: WindowedSets<IpAddr, IpAddr>,
+}
+
+impl Default for ThreatDetector {
+    fn default() -> Self {
+        Self::new(10000)
+    }
+}
+
+impl ThreatDetector {
+    /// Detector tracking at most `max_tracked` sources or targets per heuristic
+    pub fn new(max_tracked: usize) -> Self {
+        Self {
+            config: SecurityConfig::default(),
+            port_probes: WindowedSets::new(max_tracked),
+            syn_sources: WindowedSets::new(max_tracked),
+            ping_targets: WindowedSets::new(max_tracked),
+        }
+    }
+
+    /// Uses the thresholds from the `[security]` configuration section
+    pub fn with_config(mut self, config: SecurityConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Feeds one packet; returns the flags for any threshold it crossed
+    pub fn observe(&mut self, packet: &NetworkPacket) -> Vec<SecurityFlag> {
+        let (Some(source), Some(destination)) = (packet.source_addr, packet.dest_addr) else {
+            return Vec::new();
+        };
+        let window = Duration::seconds(self.config.window_secs.max(1) as i64);
+        let at = packet.timestamp;
+        let mut flags = Vec::new();
+
+        let probe = match packet.transport_protocol {
+            TransportProtocol::Tcp => packet.tcp.filter(|segment| !segment.has(TcpSegment::ACK | TcpSegment::RST)),
+            _ => None,
+        };
+        let probes_port = match packet.transport_protocol {
+            TransportProtocol::Tcp => probe.is_some(),
+            // Services answer from their well-known port
+            TransportProtocol::Udp => packet.source_port.is_some_and(|port| port >= FIRST_UNPRIVILEGED_PORT),
+            _ => false,
+        };
+        if let (true, Some(port)) = (probes_port, packet.dest_port)
+            && let Some(ports) =
+                self.port_probes
+                    .record((source, destination), port, at, window, self.config.port_scan_ports)
+        {
+            flags.push(SecurityFlag::PortScan(ports));
+        }
+
+        if let (Some(segment), Some(port)) = (probe, packet.source_port)
+            && segment.has(TcpSegment::SYN)
+            && let Some(sources) =
+                self.syn_sources
+                    .record(destination, (source, port), at, window, self.config.syn_flood_syns)
+        {
+            flags.push(SecurityFlag::SynFlood(sources));
+        }
+
+        let echo_request = match packet.transport_protocol {
+            TransportProtocol::Icmp => packet.icmp_type == Some(ICMP_ECHO_REQUEST),
+            TransportProtocol::ICMPv6 => packet.icmp_type == Some(ICMPV6_ECHO_REQUEST),
+            _ => false,
+        };
+        if echo_request
+            && let Some(hosts) = self.ping_targets.record(source, destination, at, window, self.config.icmp_sweep_hosts)
+        {
+            flags.push(SecurityFlag::IcmpSweep(hosts));
+        }
+
+        flags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol};
+    use std::net::Ipv4Addr;
+
+    fn packet(transport: TransportProtocol, src: (u8, u16), dst: (u8, u16), at_ms: i64) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 60, PacketProtocol::IPv4, PacketDirection::Inbound);
+        packet.timestamp = DateTime::from_timestamp_millis(1_700_000_000_000 + at_ms).unwrap().into();
+        packet.transport_protocol = transport;
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, src.0)));
+        packet.source_port = Some(src.1);
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, dst.0)));
+        packet.dest_port = Some(dst.1);
+        packet
+    }
+
+    fn tcp(flags: u8, src: (u8, u16), dst: (u8, u16), at_ms: i64) -> NetworkPacket {
+        let mut packet = packet(TransportProtocol::Tcp, src, dst, at_ms);
+        packet.tcp = Some(TcpSegment { flags, sequence: 1, acknowledgement: 0, window: 0, payload_len: 0, timestamps: None });
+        packet
+    }
+
+    fn detector() -> ThreatDetector {
+        ThreatDetector::new(100).with_config(SecurityConfig {
+            window_secs: 10,
+            port_scan_ports: 5,
+            syn_flood_syns: 5,
+            icmp_sweep_hosts: 3,
+            ..SecurityConfig::default()
+        })
+    }
+
+    fn count(flags: &[Vec<SecurityFlag>], event_type: &str) -> usize {
+        flags.iter().flatten().filter(|f| f.event_type() == event_type).count()
+    }
+
+    #[test]
+    fn test_port_scan_flagged_once_per_window() {
+        let mut detector = detector();
+        let flags: Vec<_> = (0..20)
+            .map(|i| detector.observe(&tcp(TcpSegment::SYN, (5, 40000), (1, 20 + i), i as i64 * 10)))
+            .collect();
+        assert_eq!(count(&flags, "PortScan"), 1);
+        assert!(matches!(flags[4][..], [SecurityFlag::PortScan(5)]));
+
+        // After the quiet window the scan is reported again
+        let later = detector.observe(&tcp(TcpSegment::FIN, (5, 40000), (1, 999), 11_000));
+        assert!(later.is_empty());
+        let flags: Vec<_> = (0..5)
+            .map(|i| detector.observe(&tcp(TcpSegment::FIN, (5, 40000), (1, 1000 + i), 11_100 + i as i64)))
+            .collect();
+        assert_eq!(count(&flags, "PortScan"), 1);
+    }
+
+    #[test]
+    fn test_replies_and_slow_probes_are_not_scans() {
+        let mut detector = detector();
+        // A server answering many connections of one client targets its ephemeral ports
+        let replies: Vec<_> = (0..20)
+            .map(|i| detector.observe(&tcp(TcpSegment::SYN | TcpSegment::ACK, (1, 443), (5, 50000 + i), 0)))
+            .collect();
+        assert_eq!(count(&replies, "PortScan"), 0);
+        let dns: Vec<_> = (0..20)
+            .map(|i| detector.observe(&packet(TransportProtocol::Udp, (2, 53), (5, 50000 + i), 0)))
+            .collect();
+        assert_eq!(count(&dns, "PortScan"), 0);
+
+        // Probes spread wider than the window never add up
+        let slow: Vec<_> = (0..10)
+            .map(|i| detector.observe(&tcp(TcpSegment::SYN, (6, 40000), (1, 20 + i), i as i64 * 5_000)))
+            .collect();
+        assert_eq!(count(&slow, "PortScan"), 0);
+    }
+
+    #[test]
+    fn test_syn_flood_counts_distinct_sources() {
+        let mut detector = detector();
+        // The same SYN resent is one attempt
+        let resent: Vec<_> = (0..10)
+            .map(|i| detector.observe(&tcp(TcpSegment::SYN, (5, 40000), (1, 80), i * 100)))
+            .collect();
+        assert_eq!(count(&resent, "SynFlood"), 0);
+
+        let flood: Vec<_> = (0..10u8)
+            .map(|i| detector.observe(&tcp(TcpSegment::SYN, (100 + i, 40000), (1, 80), 1000)))
+            .collect();
+        assert_eq!(count(&flood, "SynFlood"), 1);
+    }
+
+    #[test]
+    fn test_icmp_sweep() {
+        let mut detector = detector();
+        let mut ping = |dst: u8, icmp_type: u8| {
+            let mut packet = packet(TransportProtocol::Icmp, (5, 0), (dst, 0), 0);
+            packet.source_port = None;
+            packet.dest_port = None;
+            packet.icmp_type = Some(icmp_type);
+            detector.observe(&packet)
+        };
+        // Echo replies are not part of a sweep
+        let replies: Vec<_> = (10..20).map(|dst| ping(dst, 0)).collect();
+        assert_eq!(count(&replies, "IcmpSweep"), 0);
+
+        let requests: Vec<_> = (10..20).map(|dst| ping(dst, ICMP_ECHO_REQUEST)).collect();
+        assert_eq!(count(&requests, "IcmpSweep"), 1);
+    }
+}
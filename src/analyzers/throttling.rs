@@ -0,0 +1,557 @@
+// Throttling detection: connections held to a suspiciously steady rate
+// Counts each TCP and UDP flow's bytes per second in both directions. A transfer whose busy
+// seconds stay within a few percent of one rate for long enough looks capped by a shaper
+// (an ISP throttling a service, or a server's own rate limit) rather than limited by the
+// path, which makes TCP's rate wander. Rates on a round figure, and several flows with one
+// host held to the same rate, make the cap more likely; filling the whole link makes it less
+
+use crate::models::{NetworkPacket, PacketDirection, TransportProtocol};
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Seconds at the start of a flow left out while TCP ramps up
+const SLOW_START_SECONDS: u32 = 3;
+
+/// Steady seconds a flow needs before its rate is judged
+const MIN_STEADY_SECONDS: u32 = 20;
+
+/// Bytes per second below which steady flows are calls, streams and keepalives sent at
+/// their own constant rate
+const MIN_RATE: f64 = 50_000.0;
+
+/// Seconds without a packet after which a flow is judged and forgotten
+const IDLE_SECONDS: i64 = 15;
+
+/// Seconds a long flow is judged over at a time
+const WINDOW_SECONDS: u32 = 300;
+
+/// Packet-time seconds between looks for idle flows
+const SWEEP_SECONDS: i64 = 5;
+
+/// Flows followed at once; further flows go unjudged until one ends
+const MAX_FLOWS: usize = 10_000;
+
+/// Flagged flows held between `take_flagged` calls; older ones are dropped beyond this
+const MAX_BUFFERED_FLOWS: usize = 1_000;
+
+/// Largest coefficient of variation of the per-second rate still called steady, per level
+const HIGH_VARIATION: f64 = 0.05;
+const MEDIUM_VARIATION: f64 = 0.08;
+const LOW_VARIATION: f64 = 0.10;
+
+/// Steady seconds the medium and high levels need
+const MEDIUM_SECONDS: u32 = 30;
+const HIGH_SECONDS: u32 = 60;
+
+/// How close to a whole number of kbit/s or Mbit/s (1 to 10 of the rate's decade) a rate
+/// has to be to count as round
+const ROUND_RATE_TOLERANCE: f64 = 0.05;
+
+/// How close the rates of two flows with one host have to be to count as the same cap
+const SAME_CAP_TOLERANCE: f64 = 0.05;
+
+/// Share of the interface's traffic above which a flow had the link to itself, so its
+/// steady rate may just be the line's
+pub const FILLED_LINK_SHARE: f64 = 0.9;
+
+/// How likely a steady flow is to be capped on purpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ThrottleConfidence {
+    /// Steady for at least 20 seconds within 10%
+    Low,
+    /// Steady for at least 30 seconds within 5%, or within 8% at a round rate
+    Medium,
+    /// Steady for at least a minute within 5% at a round rate
+    High,
+}
+
+impl ThrottleConfidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThrottleConfidence::Low => "low",
+            ThrottleConfidence::Medium => "medium",
+            ThrottleConfidence::High => "high",
+        }
+    }
+
+    /// Level stored as `as_str`; unknown text reads as low
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "high" => ThrottleConfidence::High,
+            "medium" => ThrottleConfidence::Medium,
+            _ => ThrottleConfidence::Low,
+        }
+    }
+
+    fn raised(self) -> Self {
+        match self {
+            ThrottleConfidence::Low => ThrottleConfidence::Medium,
+            _ => ThrottleConfidence::High,
+        }
+    }
+
+    fn lowered(self) -> Self {
+        match self {
+            ThrottleConfidence::High => ThrottleConfidence::Medium,
+            _ => ThrottleConfidence::Low,
+        }
+    }
+}
+
+impl fmt::Display for ThrottleConfidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A connection whose transfer held to one rate for a stretch of time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottledFlow {
+    /// First and last steady second judged
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub interface: String,
+    /// "Tcp" or "Udp"
+    pub transport: String,
+    /// Endpoint sending the steady direction, and the one receiving it
+    pub sender: String,
+    pub receiver: String,
+    /// Whether the steady direction came into this system
+    pub inbound: bool,
+    /// Mean of the steady seconds, in bytes per second
+    pub rate: f64,
+    /// Standard deviation of the steady seconds over their mean
+    pub variation: f64,
+    pub seconds: u32,
+    /// The flow's share of all traffic on the interface over the same time
+    pub link_share: f64,
+    pub confidence: ThrottleConfidence,
+}
+
+impl ThrottledFlow {
+    /// The endpoint at the far end: the sender of a download, the receiver of an upload
+    pub fn remote(&self) -> &str {
+        if self.inbound { &self.sender } else { &self.receiver }
+    }
+
+    /// Address of the far endpoint, without its port
+    pub fn remote_host(&self) -> String {
+        let remote = self.remote();
+        remote
+            .parse::<SocketAddr>()
+            .map(|socket| socket.ip().to_string())
+            .unwrap_or_else(|_| remote.to_string())
+    }
+
+    /// Adds a later stretch of the same connection, weighting the rate by steady seconds
+    pub fn merge(&mut self, other: &ThrottledFlow) {
+        let seconds = self.seconds + other.seconds;
+        if seconds > 0 {
+            let weight = |flow: &ThrottledFlow, value: f64| value * flow.seconds as f64 / seconds as f64;
+            self.rate = weight(self, self.rate) + weight(other, other.rate);
+            self.link_share = weight(self, self.link_share) + weight(other, other.link_share);
+        }
+        self.variation = self.variation.max(other.variation);
+        self.start = self.start.min(other.start);
+        self.end = self.end.max(other.end);
+        self.seconds = seconds;
+        self.confidence = self.confidence.max(other.confidence);
+    }
+
+    fn same_connection(&self, other: &ThrottledFlow) -> bool {
+        self.transport == other.transport && self.sender == other.sender && self.receiver == other.receiver
+    }
+}
+
+/// Whether a rate in bytes per second is close to a whole number of its decade in bits
+/// per second, as shapers are configured (2 Mbit/s, 500 kbit/s)
+pub fn is_round_rate(bytes_per_sec: f64) -> bool {
+    let bits = bytes_per_sec * 8.0;
+    if bits < 1.0 {
+        return false;
+    }
+    let decade = 10f64.powf(bits.log10().floor());
+    let nearest = (bits / decade).round().max(1.0) * decade;
+    (bits - nearest).abs() <= nearest * ROUND_RATE_TOLERANCE
+}
+
+/// Mean and spread of per-second byte counts, updated one second at a time (Welford)
+#[derive(Debug, Clone, Copy, Default)]
+struct SteadyRate {
+    seconds: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl SteadyRate {
+    fn push(&mut self, bytes: u64) {
+        self.seconds += 1;
+        let delta = bytes as f64 - self.mean;
+        self.mean += delta / self.seconds as f64;
+        self.m2 += delta * (bytes as f64 - self.mean);
+    }
+
+    fn variation(&self) -> f64 {
+        if self.seconds < 2 || self.mean <= 0.0 {
+            return f64::INFINITY;
+        }
+        (self.m2 / (self.seconds - 1) as f64).sqrt() / self.mean
+    }
+}
+
+/// One direction of a flow: a second is only counted when the seconds either side of it
+/// were busy too, so the partial seconds where a transfer starts, pauses or ends are left out
+#[derive(Debug, Clone, Copy, Default)]
+struct DirectionState {
+    before: u64,
+    candidate: u64,
+    inbound: bool,
+    rate: SteadyRate,
+}
+
+impl DirectionState {
+    fn finish_second(&mut self, bytes: u64, counted: bool) {
+        if counted && self.before > 0 && self.candidate > 0 && bytes > 0 {
+            self.rate.push(self.candidate);
+        }
+        self.before = self.candidate;
+        self.candidate = bytes;
+    }
+}
+
+#[derive(Debug)]
+struct FlowState {
+    interface: String,
+    /// Unix second being counted, and its bytes per direction
+    second: i64,
+    open: [u64; 2],
+    /// Finished seconds since the flow appeared, and since the window started
+    elapsed: u32,
+    window_seconds: u32,
+    window_start: DateTime<Local>,
+    last_seen: DateTime<Local>,
+    /// Bytes of the flow and of its interface since the window started
+    window_bytes: u64,
+    interface_bytes_at_start: u64,
+    directions: [DirectionState; 2],
+}
+
+impl FlowState {
+    fn new(packet: &NetworkPacket, interface_bytes: u64) -> Self {
+        Self {
+            interface: packet.interface.clone(),
+            second: packet.timestamp.timestamp(),
+            open: [0; 2],
+            elapsed: 0,
+            window_seconds: 0,
+            window_start: packet.timestamp,
+            last_seen: packet.timestamp,
+            window_bytes: 0,
+            // The flow's first packet is counted in both
+            interface_bytes_at_start: interface_bytes.saturating_sub(packet.size_bytes),
+            directions: [DirectionState::default(); 2],
+        }
+    }
+
+    /// Closes the counted second and the idle ones up to `second`
+    fn advance(&mut self, second: i64) {
+        let idle = (second - self.second - 1).clamp(0, 2) as u32;
+        for bytes in std::iter::once(self.open).chain(std::iter::repeat_n([0; 2], idle as usize)) {
+            let counted = self.elapsed >= SLOW_START_SECONDS;
+            for (direction, bytes) in self.directions.iter_mut().zip(bytes) {
+                direction.finish_second(bytes, counted);
+            }
+            self.elapsed += 1;
+            self.window_seconds += 1;
+        }
+        // Longer pauses still count towards the window's length
+        let skipped = (second - self.second - 1 - idle as i64).max(0) as u32;
+        self.window_seconds += skipped;
+        self.second = second;
+        self.open = [0; 2];
+    }
+
+    /// The flagged stretch since the window started, if its busier direction held steady
+    fn judge(&self, key: &FlowKey, interface_bytes: u64) -> Option<ThrottledFlow> {
+        let (index, direction) = self
+            .directions
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| {
+                (a.rate.mean * a.rate.seconds as f64).total_cmp(&(b.rate.mean * b.rate.seconds as f64))
+            })?;
+        let rate = direction.rate;
+        if rate.seconds < MIN_STEADY_SECONDS || rate.mean < MIN_RATE {
+            return None;
+        }
+        let variation = rate.variation();
+        let round = is_round_rate(rate.mean);
+        let mut confidence = if variation <= HIGH_VARIATION && rate.seconds >= HIGH_SECONDS && round {
+            ThrottleConfidence::High
+        } else if rate.seconds >= MEDIUM_SECONDS
+            && (variation <= HIGH_VARIATION || (variation <= MEDIUM_VARIATION && round))
+        {
+            ThrottleConfidence::Medium
+        } else if variation <= LOW_VARIATION {
+            ThrottleConfidence::Low
+        } else {
+            return None;
+        };
+
+        let interface_bytes = interface_bytes.saturating_sub(self.interface_bytes_at_start);
+        let link_share = if interface_bytes > 0 { self.window_bytes as f64 / interface_bytes as f64 } else { 0.0 };
+        if link_share >= FILLED_LINK_SHARE {
+            confidence = confidence.lowered();
+        }
+
+        let (sender, receiver) = if index == 0 { (key.1, key.2) } else { (key.2, key.1) };
+        Some(ThrottledFlow {
+            start: self.window_start,
+            end: self.last_seen,
+            interface: self.interface.clone(),
+            transport: format!("{:?}", key.0),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            inbound: direction.inbound,
+            rate: rate.mean,
+            variation,
+            seconds: rate.seconds,
+            link_share,
+            confidence,
+        })
+    }
+
+    fn start_window(&mut self, at: DateTime<Local>, interface_bytes: u64) {
+        self.window_start = at;
+        self.window_seconds = 0;
+        self.window_bytes = 0;
+        self.interface_bytes_at_start = interface_bytes;
+        for direction in &mut self.directions {
+            direction.rate = SteadyRate::default();
+        }
+    }
+}
+
+/// Transport and the flow's two endpoints, lower one first
+type FlowKey = (TransportProtocol, SocketAddr, SocketAddr);
+
+/// Per-second rates of the TCP and UDP flows seen, judged when a flow goes idle, every
+/// five minutes of a long one, and at the end of capture
+#[derive(Debug, Default)]
+pub struct ThrottleDetector {
+    flows: HashMap<FlowKey, FlowState>,
+    /// Bytes seen per interface, for each flow's share of it
+    interface_bytes: HashMap<String, u64>,
+    last_sweep: i64,
+    flagged: Vec<ThrottledFlow>,
+}
+
+impl ThrottleDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, packet: &NetworkPacket) {
+        let second = packet.timestamp.timestamp();
+        if second - self.last_sweep >= SWEEP_SECONDS {
+            self.sweep(second);
+        }
+        let interface_bytes = {
+            let total = self.interface_bytes.entry(packet.interface.clone()).or_insert(0);
+            *total += packet.size_bytes;
+            *total
+        };
+
+        if !matches!(packet.transport_protocol, TransportProtocol::Tcp | TransportProtocol::Udp) {
+            return;
+        }
+        let (Some(source_addr), Some(dest_addr), Some(source_port), Some(dest_port)) =
+            (packet.source_addr, packet.dest_addr, packet.source_port, packet.dest_port)
+        else {
+            return;
+        };
+        let source = SocketAddr::new(source_addr, source_port);
+        let dest = SocketAddr::new(dest_addr, dest_port);
+        let (key, index) = if source <= dest {
+            ((packet.transport_protocol, source, dest), 0)
+        } else {
+            ((packet.transport_protocol, dest, source), 1)
+        };
+
+        if !self.flows.contains_key(&key) && self.flows.len() >= MAX_FLOWS {
+            return;
+        }
+        let flow = self.flows.entry(key).or_insert_with(|| FlowState::new(packet, interface_bytes));
+        if second > flow.second {
+            flow.advance(second);
+        }
+        flow.open[index] += packet.size_bytes;
+        flow.window_bytes += packet.size_bytes;
+        flow.directions[index].inbound = packet.direction == PacketDirection::Inbound;
+        flow.last_seen = flow.last_seen.max(packet.timestamp);
+
+        if flow.window_seconds >= WINDOW_SECONDS {
+            let judged = flow.judge(&key, interface_bytes);
+            flow.start_window(packet.timestamp, interface_bytes);
+            self.push(judged);
+        }
+    }
+
+    /// Flows flagged since the last call
+    pub fn take_flagged(&mut self) -> Vec<ThrottledFlow> {
+        std::mem::take(&mut self.flagged)
+    }
+
+    /// Judges every flow still followed as it stands; used at end of capture
+    pub fn drain(&mut self) -> Vec<ThrottledFlow> {
+        for (key, flow) in std::mem::take(&mut self.flows) {
+            let interface_bytes = self.interface_bytes.get(&flow.interface).copied().unwrap_or(0);
+            let judged = flow.judge(&key, interface_bytes);
+            self.push(judged);
+        }
+        self.take_flagged()
+    }
+
+    /// Judges and forgets the flows idle for longer than `IDLE_SECONDS` before `now`
+    fn sweep(&mut self, now: i64) {
+        self.last_sweep = now;
+        let idle: Vec<FlowKey> = self
+            .flows
+            .iter()
+            .filter(|(_, flow)| now - flow.last_seen.timestamp() > IDLE_SECONDS)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in idle {
+            if let Some(mut flow) = self.flows.remove(&key) {
+                // The second counted last was busy up to the pause
+                flow.advance(flow.second + 1);
+                let interface_bytes = self.interface_bytes.get(&flow.interface).copied().unwrap_or(0);
+                let judged = flow.judge(&key, interface_bytes);
+                self.push(judged);
+            }
+        }
+    }
+
+    fn push(&mut self, flow: Option<ThrottledFlow>) {
+        if let Some(flow) = flow {
+            if self.flagged.len() >= MAX_BUFFERED_FLOWS {
+                self.flagged.remove(0);
+            }
+            self.flagged.push(flow);
+        }
+    }
+}
+
+/// Stretches of the same connection combined, then each flow raised a level when another
+/// connection with the same remote host held to the same rate; the likeliest caps first
+pub fn per_connection(flows: &[ThrottledFlow]) -> Vec<ThrottledFlow> {
+    let mut merged: Vec<ThrottledFlow> = Vec::new();
+    for flow in flows {
+        match merged.iter_mut().find(|other| other.same_connection(flow)) {
+            Some(other) => other.merge(flow),
+            None => merged.push(flow.clone()),
+        }
+    }
+
+    let corroborated: Vec<bool> = merged
+        .iter()
+        .enumerate()
+        .map(|(index, flow)| {
+            merged.iter().enumerate().any(|(other_index, other)| {
+                other_index != index
+                    && other.remote_host() == flow.remote_host()
+                    && (other.rate - flow.rate).abs() <= flow.rate.max(other.rate) * SAME_CAP_TOLERANCE
+            })
+        })
+        .collect();
+    for (flow, corroborated) in merged.iter_mut().zip(corroborated) {
+        if corroborated {
+            flow.confidence = flow.confidence.raised();
+        }
+    }
+
+    merged.sort_by(|a, b| b.confidence.cmp(&a.confidence).then_with(|| b.rate.total_cmp(&a.rate)));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PacketProtocol;
+    use chrono::{Duration, Timelike};
+
+    /// One second of a download from `server`, `bytes` in 1,000-byte packets
+    fn download(detector: &mut ThrottleDetector, start: DateTime<Local>, second: i64, server: &str, bytes: u64) {
+        for packet in 0..bytes / 1_000 {
+            let mut data = NetworkPacket::new("eth0".to_string(), 1_000, PacketProtocol::IPv4, PacketDirection::Inbound);
+            data.timestamp = start + Duration::seconds(second) + Duration::milliseconds(packet as i64);
+            data.transport_protocol = TransportProtocol::Tcp;
+            data.source_addr = Some(server.parse().unwrap());
+            data.source_port = Some(443);
+            data.dest_addr = Some("10.0.0.5".parse().unwrap());
+            data.dest_port = Some(50_000);
+            detector.observe(&data);
+        }
+    }
+
+    #[test]
+    fn test_steady_flows_flagged_by_confidence() {
+        let start = Local::now().with_nanosecond(0).unwrap();
+        let mut detector = ThrottleDetector::new();
+        // 2 Mbit/s held to within a percent for 90 seconds, with other traffic alongside
+        for second in 0..90 {
+            download(&mut detector, start, second, "203.0.113.9", if second % 2 == 0 { 249_000 } else { 251_000 });
+            download(&mut detector, start, second, "198.51.100.7", 200_000);
+        }
+        // Wandering between 1 and 3 Mbit/s is TCP finding the path's rate
+        for second in 0..90 {
+            download(&mut detector, start, second, "192.0.2.44", 125_000 + (second as u64 % 5) * 62_000);
+        }
+
+        let flagged = per_connection(&detector.drain());
+        let capped = flagged.iter().find(|flow| flow.sender == "203.0.113.9:443").unwrap();
+        assert_eq!(capped.receiver, "10.0.0.5:50000");
+        assert!(capped.inbound);
+        assert!((capped.rate - 250_000.0).abs() < 1_000.0);
+        assert!(capped.variation < 0.01);
+        assert!(capped.seconds >= HIGH_SECONDS);
+        assert_eq!(capped.confidence, ThrottleConfidence::High);
+        // Steady too, but not on a round rate
+        let steady = flagged.iter().find(|flow| flow.sender == "198.51.100.7:443").unwrap();
+        assert_eq!(steady.confidence, ThrottleConfidence::Medium);
+        assert!(flagged.iter().all(|flow| flow.sender != "192.0.2.44:443"));
+        assert!(detector.drain().is_empty());
+    }
+
+    #[test]
+    fn test_confidence_adjusted_for_link_and_shared_caps() {
+        let start = Local::now().with_nanosecond(0).unwrap();
+        let mut detector = ThrottleDetector::new();
+        // Alone on the link at a steady but unround rate: it may be the line's speed
+        for second in 0..40 {
+            download(&mut detector, start, second, "203.0.113.9", 170_000);
+        }
+        // Going idle has the flow judged without waiting for the end of capture
+        download(&mut detector, start, 60, "198.51.100.7", 1_000);
+        let flagged = detector.take_flagged();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].link_share > FILLED_LINK_SHARE);
+        assert_eq!(flagged[0].confidence, ThrottleConfidence::Low);
+
+        // Two connections with one host at the same rate point at a cap on that host
+        let mut other = flagged[0].clone();
+        other.sender = "203.0.113.9:8443".to_string();
+        let mut unrelated = flagged[0].clone();
+        unrelated.sender = "192.0.2.1:443".to_string();
+        unrelated.rate *= 2.0;
+        let flows = per_connection(&[flagged[0].clone(), other, unrelated]);
+        assert_eq!(flows.len(), 3);
+        assert_eq!(flows.iter().filter(|flow| flow.confidence == ThrottleConfidence::Medium).count(), 2);
+
+        assert!(is_round_rate(125_000.0));
+        assert!(is_round_rate(62_900.0));
+        assert!(!is_round_rate(170_000.0));
+    }
+}
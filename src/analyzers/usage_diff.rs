@@ -0,0 +1,219 @@
+// Usage snapshots and what changed between two of them
+// A snapshot is the usage of one period - totals, top talkers, protocol shares and the
+// local devices seen - kept as JSON so periodic reviews can compare it with a later one
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use crate::models::packet::is_private;
+use crate::models::Redactor;
+use crate::storage::packet_storage::UsageSummary;
+
+/// Protocol share changes smaller than this, in percentage points, are not reported
+pub const MIN_SHARE_SHIFT: f64 = 1.0;
+
+/// Traffic of one host or protocol in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub name: String,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl UsageEntry {
+    fn from_tuples(entries: &[(String, u64, u64)]) -> Vec<Self> {
+        entries
+            .iter()
+            .map(|(name, packets, bytes)| Self { name: name.clone(), packets: *packets, bytes: *bytes })
+            .collect()
+    }
+}
+
+/// Usage of one period, as written by `kw export summary`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSnapshot {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub connections: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Application protocols, busiest first
+    pub protocols: Vec<UsageEntry>,
+    /// Busiest hosts at either end of a connection, busiest first
+    pub top_talkers: Vec<UsageEntry>,
+    /// Private, link-local and loopback addresses that had connections, sorted
+    pub devices: Vec<String>,
+}
+
+impl UsageSnapshot {
+    /// Snapshot of a period from its usage summary and every address seen in it
+    pub fn new(start: DateTime<Local>, end: DateTime<Local>, usage: &UsageSummary, hosts_seen: &[String]) -> Self {
+        let devices: BTreeSet<String> = hosts_seen
+            .iter()
+            .filter(|host| host.parse::<IpAddr>().is_ok_and(is_private))
+            .cloned()
+            .collect();
+        Self {
+            start,
+            end,
+            connections: usage.connections,
+            packets: usage.packets,
+            bytes: usage.bytes,
+            protocols: UsageEntry::from_tuples(&usage.applications),
+            top_talkers: UsageEntry::from_tuples(&usage.hosts),
+            devices: devices.into_iter().collect(),
+        }
+    }
+
+    /// The snapshot with its addresses as the privacy mode shows them
+    pub fn redacted(mut self, redactor: &Redactor) -> Self {
+        if !redactor.is_active() {
+            return self;
+        }
+        for talker in &mut self.top_talkers {
+            talker.name = redactor.target(&talker.name).into_owned();
+        }
+        let devices: BTreeSet<String> =
+            self.devices.iter().map(|device| redactor.target(device).into_owned()).collect();
+        self.devices = devices.into_iter().collect();
+        self
+    }
+
+    /// Percentage of the snapshot's bytes carried by `protocol`
+    fn protocol_share(&self, protocol: &str) -> f64 {
+        let bytes = self.protocols.iter().find(|p| p.name == protocol).map_or(0, |p| p.bytes);
+        bytes as f64 / self.bytes.max(1) as f64 * 100.0
+    }
+}
+
+/// Change in one protocol's share of the traffic, in percent
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareShift {
+    pub protocol: String,
+    pub old_share: f64,
+    pub new_share: f64,
+}
+
+impl ShareShift {
+    /// Percentage points gained (positive) or lost
+    pub fn change(&self) -> f64 {
+        self.new_share - self.old_share
+    }
+}
+
+/// What changed from an older snapshot to a newer one
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageDiff {
+    /// Hosts in the newer top talkers that were not in the older ones, busiest first
+    pub new_talkers: Vec<UsageEntry>,
+    /// Hosts that were top talkers before and no longer are
+    pub former_talkers: Vec<UsageEntry>,
+    /// Protocols whose share moved by at least `MIN_SHARE_SHIFT` points, largest move first
+    pub share_shifts: Vec<ShareShift>,
+    pub new_devices: Vec<String>,
+    /// Devices seen before that had no connections in the newer period
+    pub missing_devices: Vec<String>,
+}
+
+impl UsageDiff {
+    pub fn between(old: &UsageSnapshot, new: &UsageSnapshot) -> Self {
+        let not_in = |entries: &[UsageEntry], others: &[UsageEntry]| -> Vec<UsageEntry> {
+            entries
+                .iter()
+                .filter(|entry| !others.iter().any(|other| other.name == entry.name))
+                .cloned()
+                .collect()
+        };
+
+        let protocols: BTreeSet<&str> =
+            old.protocols.iter().chain(&new.protocols).map(|p| p.name.as_str()).collect();
+        let mut share_shifts: Vec<ShareShift> = protocols
+            .into_iter()
+            .map(|protocol| ShareShift {
+                protocol: protocol.to_string(),
+                old_share: old.protocol_share(protocol),
+                new_share: new.protocol_share(protocol),
+            })
+            .filter(|shift| shift.change().abs() >= MIN_SHARE_SHIFT)
+            .collect();
+        share_shifts.sort_by(|a, b| b.change().abs().total_cmp(&a.change().abs()));
+
+        let devices_not_in = |devices: &[String], others: &[String]| -> Vec<String> {
+            devices.iter().filter(|device| !others.contains(device)).cloned().collect()
+        };
+
+        Self {
+            new_talkers: not_in(&new.top_talkers, &old.top_talkers),
+            former_talkers: not_in(&old.top_talkers, &new.top_talkers),
+            share_shifts,
+            new_devices: devices_not_in(&new.devices, &old.devices),
+            missing_devices: devices_not_in(&old.devices, &new.devices),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new_talkers.is_empty()
+            && self.former_talkers.is_empty()
+            && self.share_shifts.is_empty()
+            && self.new_devices.is_empty()
+            && self.missing_devices.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PrivacyMode;
+
+    fn snapshot(protocols: &[(&str, u64)], talkers: &[&str], hosts_seen: &[&str]) -> UsageSnapshot {
+        let usage = UsageSummary {
+            connections: 10,
+            packets: 100,
+            bytes: protocols.iter().map(|(_, bytes)| bytes).sum(),
+            applications: protocols.iter().map(|(name, bytes)| (name.to_string(), 1, *bytes)).collect(),
+            hosts: talkers.iter().map(|host| (host.to_string(), 1, 1000)).collect(),
+        };
+        let hosts_seen: Vec<String> = hosts_seen.iter().map(|host| host.to_string()).collect();
+        let now = Local::now();
+        UsageSnapshot::new(now, now, &usage, &hosts_seen)
+    }
+
+    #[test]
+    fn test_snapshot_keeps_only_local_devices() {
+        let snapshot = snapshot(&[], &[], &["192.168.1.20", "8.8.8.8", "fe80::1", "10.0.0.5", "not-an-ip"]);
+        assert_eq!(snapshot.devices, vec!["10.0.0.5", "192.168.1.20", "fe80::1"]);
+
+        let redacted = snapshot.redacted(&Redactor::new(PrivacyMode::Truncate, None));
+        assert_eq!(redacted.devices, vec!["10.0.x.x", "192.168.x.x", "fe80:0:…"]);
+    }
+
+    #[test]
+    fn test_diff_reports_talkers_shares_and_devices() {
+        let old = snapshot(&[("HTTPS", 900), ("DNS", 100)], &["10.0.0.5", "1.1.1.1"], &["10.0.0.5", "10.0.0.9"]);
+        let new = snapshot(
+            &[("HTTPS", 600), ("SSH", 395), ("DNS", 5)],
+            &["10.0.0.5", "203.0.113.7"],
+            &["10.0.0.5", "10.0.0.77"],
+        );
+
+        let diff = UsageDiff::between(&old, &new);
+        assert_eq!(diff.new_talkers.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["203.0.113.7"]);
+        assert_eq!(diff.former_talkers.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["1.1.1.1"]);
+        let shifts: Vec<(&str, i64)> =
+            diff.share_shifts.iter().map(|s| (s.protocol.as_str(), s.change().round() as i64)).collect();
+        assert_eq!(shifts, [("SSH", 40), ("HTTPS", -30), ("DNS", -10)]);
+        assert_eq!(diff.new_devices, ["10.0.0.77"]);
+        assert_eq!(diff.missing_devices, ["10.0.0.9"]);
+
+        assert!(UsageDiff::between(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_round_trips_through_json() {
+        let snapshot = snapshot(&[("HTTPS", 900)], &["10.0.0.5"], &["10.0.0.5"]);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<UsageSnapshot>(&json).unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,421 @@
+// `kw api`: read-only JSON endpoints over the packet database, so other tools can query
+// what kaipo-watcher recorded without opening SQLite themselves. The list endpoints take
+// `from` and `to` (RFC 3339, the last 24 hours by default), `interface`, `limit` and
+// `offset`, and answer with one page of rows, how to fetch the next and the window they
+// cover. `/api/v1/schema` describes every response as JSON Schema
+
+use anyhow::{Context, Result};
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Duration, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::storage::packet_storage::{BandwidthSample, Page, ProtocolTotal};
+use crate::storage::PacketStorage;
+
+/// Rows per page when the request gives no `limit`
+pub const DEFAULT_LIMIT: u64 = 100;
+
+/// Most rows one page returns, whatever the request asks for
+pub const MAX_LIMIT: u64 = 1000;
+
+/// Period covered when the request gives no `from`
+const DEFAULT_WINDOW_HOURS: i64 = 24;
+
+/// Serves the API on one address
+pub struct ApiServer {
+    listener: TcpListener,
+    storage: Arc<PacketStorage>,
+}
+
+impl ApiServer {
+    pub async fn bind(address: SocketAddr, storage: Arc<PacketStorage>) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .await
+            .with_context(|| format!("Failed to listen on {address}"))?;
+        Ok(Self { listener, storage })
+    }
+
+    /// The bound address, with the port chosen when binding to port 0
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Answers requests until `shutdown` completes
+    pub async fn run<F: Future<Output = ()> + Send + 'static>(self, shutdown: F) -> Result<()> {
+        info!("API listening on {}", self.local_addr()?);
+        axum::serve(self.listener, router(self.storage))
+            .with_graceful_shutdown(shutdown)
+            .await
+            .context("The API server failed")
+    }
+}
+
+fn router(storage: Arc<PacketStorage>) -> Router {
+    Router::new()
+        .route("/api/v1", get(index))
+        .route("/api/v1/bandwidth", get(bandwidth))
+        .route("/api/v1/protocols", get(protocols))
+        .route("/api/v1/schema", get(schema))
+        .fallback(|| async { ApiError::new(StatusCode::NOT_FOUND, "No such endpoint; see /api/v1") })
+        .with_state(storage)
+}
+
+/// Parameters of the list endpoints
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListQuery {
+    from: Option<String>,
+    to: Option<String>,
+    interface: Option<String>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+impl ListQuery {
+    /// The period asked for, ending now and starting a day earlier unless given
+    fn window(&self) -> Result<Window, ApiError> {
+        let to = self.to.as_deref().map(|to| parse_time("to", to)).transpose()?.unwrap_or_else(Local::now);
+        let from = match self.from.as_deref() {
+            Some(from) => parse_time("from", from)?,
+            None => to - Duration::hours(DEFAULT_WINDOW_HOURS),
+        };
+        if from > to {
+            return Err(ApiError::bad_request("`from` must not be later than `to`"));
+        }
+        Ok(Window { from, to })
+    }
+
+    fn limit(&self) -> Result<u64, ApiError> {
+        match self.limit.unwrap_or(DEFAULT_LIMIT) {
+            0 => Err(ApiError::bad_request("`limit` must be at least 1")),
+            limit => Ok(limit.min(MAX_LIMIT)),
+        }
+    }
+
+    fn interface(&self) -> Option<String> {
+        self.interface.clone().filter(|interface| !interface.is_empty())
+    }
+}
+
+fn parse_time(name: &str, value: &str) -> Result<DateTime<Local>, ApiError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Local))
+        .map_err(|_| ApiError::bad_request(format!("`{name}` must be an RFC 3339 time, e.g. 2024-05-01T12:00:00Z")))
+}
+
+#[derive(Debug, Serialize)]
+struct Window {
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+}
+
+#[derive(Debug, Serialize)]
+struct Pagination {
+    offset: u64,
+    limit: u64,
+    total: u64,
+    /// Offset of the next page; none on the last
+    next_offset: Option<u64>,
+}
+
+/// Body of every list endpoint
+#[derive(Debug, Serialize)]
+struct Listing<T> {
+    data: Vec<T>,
+    pagination: Pagination,
+    window: Window,
+}
+
+impl<T> Listing<T> {
+    fn new<R>(page: Page<R>, offset: u64, limit: u64, window: Window) -> Self
+    where
+        T: From<R>,
+    {
+        let end = offset + page.items.len() as u64;
+        Self {
+            data: page.items.into_iter().map(T::from).collect(),
+            pagination: Pagination {
+                offset,
+                limit,
+                total: page.total,
+                next_offset: (end < page.total).then_some(end),
+            },
+            window,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BandwidthRow {
+    timestamp: DateTime<Local>,
+    interface: String,
+    duration_secs: f64,
+    download_bps: f64,
+    upload_bps: f64,
+}
+
+impl From<BandwidthSample> for BandwidthRow {
+    fn from(sample: BandwidthSample) -> Self {
+        Self {
+            timestamp: sample.timestamp,
+            interface: sample.interface_name,
+            duration_secs: sample.duration_secs,
+            download_bps: sample.download_bps,
+            upload_bps: sample.upload_bps,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ProtocolRow {
+    protocol: String,
+    packets: u64,
+    bytes: u64,
+    encrypted: bool,
+}
+
+impl From<ProtocolTotal> for ProtocolRow {
+    fn from(total: ProtocolTotal) -> Self {
+        Self {
+            protocol: total.protocol,
+            packets: total.packets,
+            bytes: total.bytes,
+            encrypted: total.encrypted,
+        }
+    }
+}
+
+/// An error answered as `{"error": "..."}`
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        warn!("API query failed: {e:#}");
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to read the packet database")
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+async fn index() -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "endpoints": {
+            "/api/v1/bandwidth": "Stored speed samples, oldest first",
+            "/api/v1/protocols": "Captured packets and bytes per protocol, busiest first",
+            "/api/v1/schema": "JSON Schema of every response",
+        },
+        "parameters": {
+            "from": "RFC 3339 start of the window; 24 hours before `to` by default",
+            "to": "RFC 3339 end of the window; now by default",
+            "interface": "Only this interface",
+            "limit": format!("Rows per page, {DEFAULT_LIMIT} by default and at most {MAX_LIMIT}"),
+            "offset": "Rows to skip; the previous page's `pagination.next_offset`",
+        },
+    }))
+}
+
+async fn bandwidth(
+    State(storage): State<Arc<PacketStorage>>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<Json<Listing<BandwidthRow>>, ApiError> {
+    let Query(query) = query?;
+    let (window, limit, offset) = (query.window()?, query.limit()?, query.offset.unwrap_or(0));
+    let (from, to, interface) = (window.from, window.to, query.interface());
+    let page = tokio::task::spawn_blocking(move || {
+        storage.get_bandwidth_page(from, to, interface.as_deref(), offset, limit)
+    })
+    .await
+    .context("The bandwidth query panicked")??;
+    Ok(Json(Listing::new(page, offset, limit, window)))
+}
+
+async fn protocols(
+    State(storage): State<Arc<PacketStorage>>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<Json<Listing<ProtocolRow>>, ApiError> {
+    let Query(query) = query?;
+    let (window, limit, offset) = (query.window()?, query.limit()?, query.offset.unwrap_or(0));
+    let (from, to, interface) = (window.from, window.to, query.interface());
+    let page = tokio::task::spawn_blocking(move || {
+        storage.get_protocol_page(from, to, interface.as_deref(), offset, limit)
+    })
+    .await
+    .context("The protocol query panicked")??;
+    Ok(Json(Listing::new(page, offset, limit, window)))
+}
+
+async fn schema() -> Json<Value> {
+    Json(json!({
+        "bandwidth": listing_schema("Bandwidth samples", json!({
+            "type": "object",
+            "required": ["timestamp", "interface", "duration_secs", "download_bps", "upload_bps"],
+            "properties": {
+                "timestamp": { "type": "string", "format": "date-time", "description": "End of the sampled interval" },
+                "interface": { "type": "string" },
+                "duration_secs": { "type": "number", "description": "Length of the sampled interval" },
+                "download_bps": { "type": "number", "description": "Average bytes per second received" },
+                "upload_bps": { "type": "number", "description": "Average bytes per second sent" },
+            },
+        })),
+        "protocols": listing_schema("Protocol totals", json!({
+            "type": "object",
+            "required": ["protocol", "packets", "bytes", "encrypted"],
+            "properties": {
+                "protocol": { "type": "string" },
+                "packets": { "type": "integer", "minimum": 0 },
+                "bytes": { "type": "integer", "minimum": 0 },
+                "encrypted": { "type": "boolean", "description": "Whether any of its traffic was encrypted" },
+            },
+        })),
+        "error": {
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Error",
+            "type": "object",
+            "required": ["error"],
+            "properties": { "error": { "type": "string" } },
+        },
+    }))
+}
+
+/// Schema of a list endpoint's body with rows described by `row`
+fn listing_schema(title: &str, row: Value) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "object",
+        "required": ["data", "pagination", "window"],
+        "properties": {
+            "data": { "type": "array", "items": row },
+            "pagination": {
+                "type": "object",
+                "required": ["offset", "limit", "total", "next_offset"],
+                "properties": {
+                    "offset": { "type": "integer", "minimum": 0 },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_LIMIT },
+                    "total": { "type": "integer", "minimum": 0, "description": "Rows in the window across all pages" },
+                    "next_offset": { "type": ["integer", "null"], "description": "Offset of the next page; null on the last" },
+                },
+            },
+            "window": {
+                "type": "object",
+                "required": ["from", "to"],
+                "properties": {
+                    "from": { "type": "string", "format": "date-time" },
+                    "to": { "type": "string", "format": "date-time" },
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    async fn get(address: SocketAddr, path: &str) -> (u16, Value) {
+        let response = reqwest::get(format!("http://{address}{path}")).await.unwrap();
+        let status = response.status().as_u16();
+        (status, serde_json::from_str(&response.text().await.unwrap()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_pages() {
+        let temp_dir = tempdir().unwrap();
+        let storage = Arc::new(PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap());
+        let now = Local::now();
+        let samples: Vec<BandwidthSample> = (0..3)
+            .map(|minutes_ago| BandwidthSample {
+                timestamp: now - Duration::minutes(minutes_ago),
+                interface_name: "eth0".to_string(),
+                duration_secs: 60.0,
+                download_bps: 1000.0,
+                upload_bps: 10.0,
+            })
+            .collect();
+        storage.store_bandwidth_samples(&samples).unwrap();
+
+        let server = ApiServer::bind("127.0.0.1:0".parse().unwrap(), storage).await.unwrap();
+        let address = server.local_addr().unwrap();
+        let (_stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(server.run(async { let _ = stopped.await; }));
+
+        let (status, body) = get(address, "/api/v1/bandwidth?interface=eth0&limit=2").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        assert_eq!(body["data"][0]["interface"], "eth0");
+        assert_eq!(body["pagination"]["total"], 3);
+        assert_eq!(body["pagination"]["next_offset"], 2);
+
+        let (_, body) = get(address, "/api/v1/bandwidth?limit=2&offset=2").await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert!(body["pagination"]["next_offset"].is_null());
+
+        let (_, body) = get(address, "/api/v1/bandwidth?interface=wlan0").await;
+        assert_eq!(body["pagination"]["total"], 0);
+
+        let (_, body) = get(address, "/api/v1/protocols").await;
+        assert_eq!(body["data"], json!([]));
+
+        for bad in ["/api/v1/bandwidth?from=yesterday", "/api/v1/bandwidth?limit=0", "/api/v1/bandwidth?form=x"] {
+            let (status, body) = get(address, bad).await;
+            assert_eq!(status, 400, "{bad}");
+            assert!(body["error"].is_string());
+        }
+        assert_eq!(get(address, "/api/v2").await.0, 404);
+    }
+
+    #[test]
+    fn test_window_and_limit() {
+        let query = ListQuery {
+            from: Some("2024-05-01T00:00:00Z".to_string()),
+            to: Some("2024-05-02T00:00:00+00:00".to_string()),
+            limit: Some(5000),
+            ..Default::default()
+        };
+        let window = query.window().unwrap();
+        assert_eq!(window.to - window.from, Duration::days(1));
+        assert_eq!(query.limit().unwrap(), MAX_LIMIT);
+
+        let backwards = ListQuery { from: query.to.clone(), to: query.from.clone(), ..Default::default() };
+        assert!(backwards.window().is_err());
+        let window = ListQuery::default().window().unwrap();
+        assert_eq!(window.to - window.from, Duration::hours(DEFAULT_WINDOW_HOURS));
+    }
+}
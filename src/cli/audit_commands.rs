@@ -0,0 +1,90 @@
+// CLI Audit Commands: Lists and verifies the hash-chained audit log
+// Security events, alerts, configuration changes and tag edits are appended by the
+// storage layer as they happen; this only reads them back and recomputes the chain
+
+use anyhow::{bail, Result};
+use std::sync::Arc;
+
+use crate::cli::commands::AuditAction;
+use crate::storage::audit::{self, GENESIS_HASH};
+use crate::storage::PacketStorage;
+
+/// Categories accepted by `kw audit list --category`
+const CATEGORIES: [&str; 4] = [audit::SECURITY_EVENT, audit::ALERT, audit::CONFIG, audit::ADMIN];
+
+/// Command handler for `kw audit`
+pub struct AuditCommandHandler {
+    storage: Arc<PacketStorage>,
+}
+
+impl AuditCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn handle_audit_command(&self, action: AuditAction) -> Result<()> {
+        match action {
+            AuditAction::Verify { contains } => self.verify(contains.as_deref()),
+            AuditAction::List { limit, category } => self.list(limit, category),
+        }
+    }
+
+    fn verify(&self, contains: Option<&str>) -> Result<()> {
+        let verification = self.storage.verify_audit_log()?;
+        if let Some((id, reason)) = verification.broken {
+            println!("❌ Audit log chain broken at entry {id}: {reason}");
+            println!("   Entries before it verified: {}", verification.entries - 1);
+            bail!("Audit log verification failed");
+        }
+
+        match &verification.head {
+            Some(head) => {
+                println!("🔏 Audit log intact: {} entries verified", verification.entries);
+                println!("   Head hash: {head}");
+            }
+            None => println!("🔏 Audit log is empty (chain starts from {GENESIS_HASH})"),
+        }
+
+        // The chain alone cannot show entries cut off its end; an earlier head can
+        match contains {
+            Some(hash) => match self.storage.find_audit_hash(hash)? {
+                Some(id) => println!("   Earlier head {hash} found at entry {id}"),
+                None => bail!("Earlier head {hash} is not in the audit log; entries may have been removed"),
+            },
+            None if verification.head.is_some() => {
+                println!("   Note the head hash elsewhere and pass it to --contains on a later verify")
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn list(&self, limit: usize, category: Option<String>) -> Result<()> {
+        if let Some(category) = &category
+            && !CATEGORIES.contains(&category.as_str())
+        {
+            bail!("Unknown audit category '{category}'; expected one of: {}", CATEGORIES.join(", "));
+        }
+
+        let entries = self.storage.get_audit_entries(category.as_deref(), limit)?;
+        if entries.is_empty() {
+            println!("No audit log entries recorded yet");
+            return Ok(());
+        }
+
+        println!("🔏 Audit Log (newest first):");
+        println!("   {:>6} {:<19} {:<14} {:<18} {:<12} Detail", "Entry", "Time", "Category", "Action", "Hash");
+        for entry in &entries {
+            println!(
+                "   {:>6} {:<19} {:<14} {:<18} {:<12} {}",
+                entry.id,
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                entry.category,
+                entry.action,
+                &entry.hash[..entry.hash.len().min(12)],
+                entry.detail
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+// CLI Capture Stats Commands: Performance of the current or last capture session
+// Reads the capture samples `kw service run` and `kw packets` record, so the load a
+// setup handled, and how close it came to losing packets, can be checked afterwards
+
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::cli::report_commands::format_duration;
+use crate::collectors::bandwidth_collector::format_speed;
+use crate::storage::packet_storage::{CaptureSample, CaptureSessionSummary};
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw capture-stats`
+pub struct CaptureStatsCommandHandler {
+    storage: Arc<PacketStorage>,
+}
+
+impl CaptureStatsCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage }
+    }
+
+    /// Shows the most recent session with its last `limit` samples, or with `sessions`
+    /// a summary line for each of that many recent sessions
+    pub async fn handle_capture_stats_command(&self, sessions: Option<usize>, limit: usize) -> Result<()> {
+        let summaries = self.storage.get_capture_sessions(sessions.unwrap_or(1).max(1))?;
+        let Some(last) = summaries.first() else {
+            println!("No capture statistics recorded yet");
+            println!("They are recorded while `kw service run` or `kw packets` is capturing");
+            return Ok(());
+        };
+
+        if sessions.is_some() {
+            print_sessions(&summaries);
+            return Ok(());
+        }
+
+        print_session(last);
+        let samples = self.storage.get_capture_samples(last)?;
+        println!();
+        println!("📈 Over time (last {} of {} samples):", limit.min(samples.len()), samples.len());
+        println!(
+            "   {:<8} {:>9} {:>9} {:>12} {:>8} {:>8} {:>7} {:>8}",
+            "Time", "Pkts/s", "Peak", "Throughput", "Q drops", "K drops", "Queue", "Backlog"
+        );
+        for sample in &samples[samples.len().saturating_sub(limit)..] {
+            print_sample(sample);
+        }
+        Ok(())
+    }
+}
+
+fn print_session(session: &CaptureSessionSummary) {
+    println!("📡 Capture Statistics - {}", session.interface_name);
+    println!(
+        "   Session:        started {}, last sample {}",
+        session.session_start.format("%Y-%m-%d %H:%M:%S"),
+        session.last_sample.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("   Sampled:        {} in {} samples", format_duration(session.duration_secs), session.samples);
+    println!(
+        "   Packets/s:      {:.0} avg, {:.0} peak",
+        session.packets_per_second(),
+        session.peak_packets_per_second
+    );
+    println!(
+        "   Throughput:     {} avg, {} peak",
+        format_speed(session.bytes_per_second()),
+        format_speed(session.peak_bytes_per_second)
+    );
+    println!(
+        "   Queue drops:    {} of {} packets ({:.2}%)",
+        session.queue_drops,
+        session.packets,
+        session.queue_drop_rate() * 100.0
+    );
+    println!("   Kernel drops:   {}", describe_kernel_drops(session.kernel_drops));
+    println!(
+        "   Analyzer queue: peak {} of {} packets ({:.0}%)",
+        session.peak_queue_depth,
+        session.queue_capacity,
+        session.peak_queue_utilisation() * 100.0
+    );
+    println!("   Storage queue:  peak {} records", session.peak_storage_backlog);
+    println!("   Assessment:     {}", assess_headroom(session));
+}
+
+fn print_sessions(sessions: &[CaptureSessionSummary]) {
+    println!("📡 Capture Sessions (newest first):");
+    println!(
+        "   {:<19} {:<10} {:>8} {:>9} {:>9} {:>12} {:>9} {:>9} {:>6}",
+        "Started", "Interface", "Length", "Pkts/s", "Peak", "Throughput", "Q drops", "K drops", "Queue"
+    );
+    for session in sessions {
+        println!(
+            "   {:<19} {:<10} {:>8} {:>9.0} {:>9.0} {:>12} {:>9} {:>9} {:>5.0}%",
+            session.session_start.format("%Y-%m-%d %H:%M:%S"),
+            session.interface_name,
+            format_duration(session.duration_secs),
+            session.packets_per_second(),
+            session.peak_packets_per_second,
+            format_speed(session.bytes_per_second()),
+            session.queue_drops,
+            describe_kernel_drops(session.kernel_drops),
+            session.peak_queue_utilisation() * 100.0
+        );
+    }
+}
+
+fn print_sample(sample: &CaptureSample) {
+    println!(
+        "   {:<8} {:>9.0} {:>9.0} {:>12} {:>8} {:>8} {:>6.0}% {:>8}",
+        sample.timestamp.format("%H:%M:%S"),
+        sample.packets as f64 / sample.duration_secs.max(1.0),
+        sample.peak_packets_per_second,
+        format_speed(sample.bytes as f64 / sample.duration_secs.max(1.0)),
+        sample.queue_drops,
+        describe_kernel_drops(sample.kernel_drops),
+        sample.peak_queue_utilisation() * 100.0,
+        sample.peak_storage_backlog
+    );
+}
+
+fn describe_kernel_drops(drops: Option<u64>) -> String {
+    drops.map(|d| d.to_string()).unwrap_or_else(|| "n/a".to_string())
+}
+
+/// One-line verdict on how much more load the capture setup can take
+fn assess_headroom(session: &CaptureSessionSummary) -> &'static str {
+    if session.queue_drops > 0 {
+        "Overloaded - the analyzer fell behind and packets were dropped; raise the queue size or lower the load"
+    } else if session.kernel_drops.unwrap_or(0) > 0 {
+        "Kernel drops - the interface or driver lost packets before capture"
+    } else if session.peak_queue_utilisation() > 0.5 {
+        "Near capacity - the analyzer queue was more than half full at its peak"
+    } else {
+        "Headroom available - no drops and the analyzer kept up"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn session(queue_drops: u64, kernel_drops: Option<u64>, peak_queue_depth: u64) -> CaptureSessionSummary {
+        CaptureSessionSummary {
+            session_start: Local::now(),
+            interface_name: "eth0".to_string(),
+            last_sample: Local::now(),
+            samples: 10,
+            duration_secs: 600.0,
+            packets: 60_000,
+            bytes: 6_000_000,
+            peak_packets_per_second: 500.0,
+            peak_bytes_per_second: 50_000.0,
+            queue_drops,
+            kernel_drops,
+            queue_capacity: 1000,
+            peak_queue_depth,
+            peak_storage_backlog: 10,
+        }
+    }
+
+    #[test]
+    fn test_headroom_assessment() {
+        assert!(assess_headroom(&session(0, Some(0), 100)).starts_with("Headroom"));
+        assert!(assess_headroom(&session(0, None, 100)).starts_with("Headroom"));
+        assert!(assess_headroom(&session(0, Some(0), 800)).starts_with("Near capacity"));
+        assert!(assess_headroom(&session(0, Some(3), 100)).starts_with("Kernel drops"));
+        assert!(assess_headroom(&session(5, Some(3), 1000)).starts_with("Overloaded"));
+    }
+}
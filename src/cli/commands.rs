@@ -1,3 +1,5 @@
+#[cfg(feature = "graphs")]
+use clap::Args;
 use clap::{Parser, Subcommand};
 
 /// Main CLI structure for the kaipo-watcher application
@@ -12,6 +14,28 @@ cross-platform support, and intelligent interface filtering.")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Redact IP addresses and host names in output, overriding `[privacy] mode`
+    #[arg(
+        long,
+        global = true,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "hash",
+        value_parser = ["hash", "truncate", "off"],
+        help = "Redact IP addresses and host names in output, exports and graphs: --privacy hashes them, --privacy=truncate keeps their network and domain"
+    )]
+    pub privacy: Option<String>,
+
+    /// Keep data in a profile of its own instead of the default one
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Use the named profile's database and state instead of the default profile's, e.g. --profile work"
+    )]
+    pub profile: Option<String>,
 }
 
 /// Available CLI commands for the kaipo-watcher application
@@ -20,22 +44,38 @@ pub struct Cli {
 pub enum Commands {
     /// Real-time monitoring with terminal dashboard and sparkline graphs
     /// Displays live bandwidth stats with historical trend visualization
+    #[cfg(feature = "dashboard")]
     #[command(about = "Monitor network in real-time with interactive dashboard")]
     #[command(long_about = "Launches an interactive terminal dashboard with real-time bandwidth monitoring, \
 sparkline graphs showing historical trends, and comprehensive interface statistics. \
-Press 'q' or ESC to exit the dashboard.\n\n\
+Press 'q' or ESC to exit the dashboard. With --packets and no --interface, a terminal session first \
+lists the interfaces by type and relevance and asks which one to capture on (Enter for all).\n\n\
 Examples:\n  \
 kw live                               # Monitor all relevant interfaces\n  \
 kw live --interface en0               # Monitor specific interface\n  \
+kw live --interface \"en*\" -I wg0      # Every en* interface plus wg0\n  \
 kw live --important-only              # Clean view without virtual interfaces\n  \
-kw live --interval 2                  # Update every 2 seconds")]
+kw live --interval 2                  # Update every 2 seconds\n  \
+kw live --aggregate                   # Add a total of all listed interfaces\n  \
+kw live --layout grid                 # A speed panel with a sparkline per interface\n  \
+sudo kw live --packets                # Include scrollable connection table\n  \
+kw live --history-minutes 15          # Keep 15 minutes of history for the chart view\n  \
+kw live --snapshot-dir ~/kw-snapshots # Where the x key saves snapshots (? lists all keys)\n  \
+kw live --record outage.kw            # Record the session for kw replay\n  \
+kw live --packets --privileged-helper # Only the capture helper runs as root")]
     Live {
-        /// Filter to monitor only a specific network interface
-        #[arg(short = 'I', long, help = "Monitor specific network interface")]
-        interface: Option<String>,
+        /// Filter to monitor only some network interfaces: names or globs, repeatable
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Monitor specific network interfaces: names or globs such as \"en*\"; repeat or comma-separate for several"
+        )]
+        interface: Vec<String>,
 
-        /// Include detailed packet-level information (future feature)
-        #[arg(short, long, help = "Include packet-level details")]
+        /// Capture packets to populate the connection table (Tab to switch views)
+        /// Requires the same elevated privileges as the packets command
+        #[arg(short, long, help = "Capture packets and show active connections (press Tab in the dashboard)")]
         packets: bool,
 
         /// How often to update the display (in seconds)
@@ -47,6 +87,23 @@ kw live --interval 2                  # Update every 2 seconds")]
         )]
         interval: u64,
 
+        /// Keep the update interval even while collecting is slow or kw is busy
+        #[arg(
+            long,
+            help = "Keep --interval fixed instead of backing off while collection is slow or CPU use is high"
+        )]
+        fixed_interval: bool,
+
+        /// Smooth the speeds shown with a moving average over this many seconds
+        /// Overrides `[smoothing] window_secs`; 0 shows every reading as it is
+        #[arg(
+            long,
+            value_name = "SECS",
+            value_parser = parse_smoothing_secs,
+            help = "Smooth displayed speeds over this many seconds, weighting readings by confidence (0 = raw, at most 3600)"
+        )]
+        smooth: Option<f64>,
+
         /// Show only important interfaces (physical ethernet, wifi, VPN)
         /// Excludes virtual, container, and system interfaces for cleaner dashboard
         #[arg(
@@ -62,6 +119,175 @@ kw live --interval 2                  # Update every 2 seconds")]
             help = "Show all interfaces including virtual, container, and system interfaces"
         )]
         show_all: bool,
+
+        /// Add a synthetic "total" interface summing the listed ones, with its own history series
+        #[arg(
+            long,
+            help = "Add a \"total\" row and history series summing the listed interfaces (bonds counted once)"
+        )]
+        aggregate: bool,
+
+        /// Minutes of per-interface bandwidth history kept for the history chart
+        #[arg(
+            long,
+            default_value = "5",
+            help = "Minutes of bandwidth history shown in the history chart view"
+        )]
+        history_minutes: u64,
+
+        /// Run packet capture in a separate privileged helper process (Unix only)
+        /// The dashboard itself then runs without elevated privileges
+        #[arg(
+            long,
+            requires = "packets",
+            help = "Capture packets in a privileged helper process so the dashboard can run unprivileged"
+        )]
+        privileged_helper: bool,
+
+        /// Suggest renice/trickle commands for the heaviest processes while a link is saturated
+        #[arg(
+            long,
+            requires = "packets",
+            help = "Show renice/trickle suggestions in the contention view when a link is saturated"
+        )]
+        suggest_throttle: bool,
+
+        /// Draw the dashboard (tui), or write one JSON object per update interval to stdout (ndjson)
+        #[arg(
+            long,
+            default_value = "tui",
+            value_parser = ["tui", "ndjson"],
+            conflicts_with = "suggest_throttle",
+            help = "Output: tui for the dashboard, ndjson for one JSON object per interval on stdout"
+        )]
+        output: String,
+
+        /// Lay out the interfaces view as one list (single) or a speed panel per interface (grid)
+        #[arg(
+            long,
+            default_value = "single",
+            value_parser = ["single", "grid"],
+            help = "Interfaces view: single for the combined speeds over a list, grid for a panel per interface"
+        )]
+        layout: String,
+
+        /// Where the `x` key writes snapshots of the dashboard (JSON plus a PNG of recent speeds)
+        #[arg(
+            long,
+            default_value = ".",
+            value_name = "DIR",
+            help = "Directory the x key writes dashboard snapshots to (JSON and PNG)"
+        )]
+        snapshot_dir: std::path::PathBuf,
+
+        /// Write every update's readings to a session file that `kw replay` plays back
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Record the readings of every update to FILE, to play back later with kw replay"
+        )]
+        record: Option<std::path::PathBuf>,
+    },
+
+    /// Plays back a session recorded with `kw live --record` in the live dashboard
+    #[cfg(feature = "dashboard")]
+    #[command(about = "Replay a dashboard session recorded with kw live --record")]
+    #[command(long_about = "Plays a session file written by kw live --record back through the live dashboard, \
+at the pace it was recorded or faster, so an intermittent problem can be looked at again or by someone else. \
+The interface list, trends, grid and history chart, and the interface changes and alerts shown in the status \
+bar, are as recorded; connections are not recorded, and bonds and tunnels are not grouped. The dashboard \
+keys work as in kw live; the header shows when the sample on screen was taken.\n\n\
+Examples:\n  \
+kw replay outage.kw                   # Play back at the recorded pace\n  \
+kw replay outage.kw --speed 10        # Ten times faster\n  \
+kw replay outage.kw -I eth0 --layout grid")]
+    Replay {
+        /// Session file written by `kw live --record`
+        #[arg(help = "Session file written by kw live --record")]
+        file: std::path::PathBuf,
+
+        /// How many times faster than recorded the samples are played
+        #[arg(short, long, default_value = "1", help = "Playback speed: 1 for the recorded pace, 10 for ten times faster")]
+        speed: f64,
+
+        /// Show only some of the recorded interfaces: names or globs, repeatable
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Show only some recorded interfaces: names or globs, repeat or comma-separate for several"
+        )]
+        interface: Vec<String>,
+
+        /// Add a synthetic "total" interface summing the listed ones
+        #[arg(long, help = "Add a \"total\" row and history series summing the listed interfaces")]
+        aggregate: bool,
+
+        /// Minutes of per-interface bandwidth history kept for the history chart
+        #[arg(long, default_value = "5", help = "Minutes of bandwidth history shown in the history chart view")]
+        history_minutes: u64,
+
+        /// Lay out the interfaces view as one list (single) or a speed panel per interface (grid)
+        #[arg(
+            long,
+            default_value = "single",
+            value_parser = ["single", "grid"],
+            help = "Interfaces view: single for the combined speeds over a list, grid for a panel per interface"
+        )]
+        layout: String,
+    },
+
+    /// Continuously refreshing ranking of remote hosts by bandwidth, like iftop
+    /// Needs the same capture privileges as the packets command
+    #[cfg(all(feature = "dashboard", feature = "capture"))]
+    #[command(about = "Rank remote hosts by bandwidth in a live view")]
+    #[command(long_about = "Captures packets and shows the remote hosts exchanging the most traffic with this \
+machine over the last few seconds, re-ranked every refresh interval. Host names are looked up through the \
+[dns] backend as hosts appear. Keys: s cycles the sort order (total, received, sent), r turns host names on \
+or off, q or ESC exits. Without --interface a terminal session is asked which interface to capture on.\n\n\
+Examples:\n  \
+sudo kw top                           # Rank hosts over the last 10 seconds\n  \
+sudo kw top --interface en0 -w 30     # One interface, 30-second window\n  \
+sudo kw top --filter \"tcp port 443\"   # Only HTTPS traffic\n  \
+sudo kw top --no-resolve              # Show addresses only, no DNS lookups\n  \
+kw top --privileged-helper            # Only the capture helper runs as root")]
+    Top {
+        /// Network interfaces to capture on, names or globs
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Capture on specific network interfaces: names or globs, repeatable (default: ask in a terminal, else all)"
+        )]
+        interface: Vec<String>,
+
+        /// Seconds of traffic each host's rates are averaged over
+        #[arg(short, long, default_value = "10", help = "Rank hosts by their traffic over the last N seconds")]
+        window: u64,
+
+        /// How often the ranking is redrawn
+        #[arg(short = 'i', long, default_value = "1", help = "Refresh interval in seconds")]
+        interval: u64,
+
+        /// tcpdump-style capture filter applied before packets are counted
+        #[arg(
+            short,
+            long,
+            value_name = "EXPR",
+            help = "Capture filter expression, e.g. \"tcp port 443 and host 10.0.0.5\""
+        )]
+        filter: Option<String>,
+
+        /// Show addresses only; host names can still be turned on with `r`
+        #[arg(long, help = "Start without host name lookups (press r to turn them on)")]
+        no_resolve: bool,
+
+        /// Run packet capture in a separate privileged helper process (Unix only)
+        #[arg(
+            long,
+            help = "Capture packets in a privileged helper process so the view can run unprivileged"
+        )]
+        privileged_helper: bool,
     },
 
     /// One-time snapshot of current network status with accurate speed measurements
@@ -74,7 +300,11 @@ Examples:\n  \
 kw status --measurement-duration 5    # 5-second measurement for accuracy\n  \
 kw status --active-only               # Show only interfaces with traffic\n  \
 kw status --important-only            # Show only physical interfaces\n  \
-kw status --interface en0             # Monitor specific interface")]
+kw status --interface en0             # Monitor specific interface\n  \
+kw status --interface \"en*\" -I wg0    # Ethernet/Wi-Fi ports and the WireGuard tunnel\n  \
+kw status --aggregate                 # Add a total across all listed interfaces\n  \
+kw status --container web             # A Docker/Podman container, through its veth (Linux)\n  \
+kw status --namespace blue            # A network namespace from `ip netns add` (Linux)")]
     Status {
         /// Include additional details like total bytes and packet counts
         #[arg(short, long, help = "Show detailed information")]
@@ -90,6 +320,13 @@ kw status --interface en0             # Monitor specific interface")]
         )]
         measurement_duration: u64,
 
+        /// Read the counters every 100 ms during the measurement to catch micro-bursts
+        #[arg(
+            long,
+            help = "Sample every 100 ms during the measurement and report peak 100 ms throughput and micro-bursts"
+        )]
+        hires: bool,
+
         /// Filter to show only interfaces with active traffic during measurement
         #[arg(
             short = 'a',
@@ -98,9 +335,30 @@ kw status --interface en0             # Monitor specific interface")]
         )]
         active_only: bool,
 
-        /// Filter to monitor only a specific network interface
-        #[arg(short = 'I', long, help = "Monitor specific network interface")]
-        interface: Option<String>,
+        /// Filter to monitor only some network interfaces: names or globs, repeatable
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Monitor specific network interfaces: names or globs such as \"en*\"; repeat or comma-separate for several"
+        )]
+        interface: Vec<String>,
+
+        /// Network namespaces to monitor through the host ends of their veths (Linux)
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Monitor network namespaces through their veths: ip netns names, globs, inodes or pids (Linux)"
+        )]
+        namespace: Vec<String>,
+
+        /// Docker or Podman containers to monitor through the host ends of their veths (Linux)
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Monitor Docker or Podman containers through their veths: names, globs or id prefixes (Linux)"
+        )]
+        container: Vec<String>,
 
         /// Show only important interfaces (physical ethernet, wifi, VPN)
         /// Excludes virtual, container, and system interfaces for cleaner output
@@ -118,29 +376,255 @@ kw status --interface en0             # Monitor specific interface")]
         )]
         show_all: bool,
 
+        /// Add a synthetic "total" interface summing the listed ones
+        #[arg(
+            long,
+            help = "Add a \"total\" row summing the speeds and counters of the listed interfaces (bonds counted once)"
+        )]
+        aggregate: bool,
+
         /// Export interface analysis report with detailed platform-specific information
         #[arg(
             long,
             help = "Export detailed interface analysis report with platform-specific information"
         )]
         interface_analysis: bool,
+
+        /// Print vnStat's summary of stored usage instead of measuring speeds
+        #[arg(
+            long,
+            default_value = "default",
+            value_parser = ["default", "vnstat"],
+            help = "Output style: default, or vnstat for vnStat's rx/tx/total summary of the stored usage per month and day"
+        )]
+        style: String,
     },
 
-    /// Generate usage reports for specified time periods (future feature)
-    #[command(about = "Generate usage report")]
+    /// Generate usage reports for specified time periods
+    /// Ends with a data-quality section rating how trustworthy the period's numbers are
+    #[command(about = "Generate usage report with a data-quality summary")]
     Report {
         /// Time period for the report
         #[arg(
             short,
             long,
             default_value = "month",
-            help = "Report period: day, week, month"
+            help = "Report period: a span ending now (15m, 2h, 3d, day, week, month), today, yesterday, a day or month (2024-01-15, 2024-01), or a range (2024-01-01..2024-01-31)"
         )]
         period: String,
 
         /// Include per-application network usage breakdown
         #[arg(short, long, help = "Include per-application breakdown")]
         app_breakdown: bool,
+
+        /// Include traffic by destination country and autonomous system
+        #[arg(long, help = "Include traffic by country and ASN, using the [geoip] databases")]
+        geo: bool,
+
+        /// Contrast the period with the one of the same length before it
+        #[arg(
+            long,
+            value_name = "WHAT",
+            help = "Compare with another period: previous (e.g. this week vs last week)"
+        )]
+        compare: Option<String>,
+
+        /// Print vnStat's daily and monthly tables instead of the report sections
+        #[arg(
+            long,
+            default_value = "default",
+            value_parser = ["default", "vnstat"],
+            help = "Output style: default, or vnstat for vnStat's daily and monthly rx/tx/total tables"
+        )]
+        style: String,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Usage in the current billing period against the [quota] allowance, with a forecast
+    #[command(about = "Show this billing period's usage against the quota, with an end-of-period forecast")]
+    #[command(long_about = "Shows the traffic of the current billing period, which starts on [quota] reset_day, \
+against the [quota] monthly_gb allowance, and projects where it will end from the stored bandwidth history: \
+a linear trend over the daily totals, with weekday differences once two weeks are recorded, and 95% bounds. \
+Needs a week of samples from `kw service run`.")]
+    Quota {
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Capture throughput, drops and queue depths of the current or last capture session
+    #[command(about = "Show packet capture performance statistics")]
+    #[command(long_about = "Shows the packets and bytes per second, analyzer queue drops, kernel drops and \
+queue depths of the current or last capture session, as recorded by `kw service run` (every minute) \
+and `kw packets` (every 10 seconds). Kernel drops are only available on Linux.")]
+    CaptureStats {
+        /// List this many recent sessions instead of detailing the last one
+        #[arg(short, long, value_name = "N", help = "List the N most recent capture sessions")]
+        sessions: Option<usize>,
+
+        /// Samples shown in the over-time table
+        #[arg(short, long, default_value = "20", help = "Samples shown for the last session")]
+        limit: usize,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// kw's own health over time
+    #[command(about = "Show statistics about kw itself")]
+    Stats {
+        #[command(subcommand)]
+        action: StatsAction,
+    },
+
+    /// Active download, upload and latency test against HTTP endpoints
+    #[command(about = "Measure download, upload and latency against a speed test server")]
+    #[command(long_about = "Downloads from and uploads to the HTTP endpoints in the [speedtest] configuration \
+section (Cloudflare's speed test by default) for up to `duration_secs` each, after timing a series of small \
+requests for latency and jitter. Unlike the passive bandwidth readings this loads the connection, so it shows \
+what the line can reach. Results are stored; `kw graph speedtest` plots them over time.\n\n\
+Examples:\n  \
+kw speedtest                             # Run a test and store the result\n  \
+kw speedtest --no-upload                 # Latency and download only\n  \
+kw speedtest --history 10                # List the last 10 results")]
+    Speedtest {
+        /// Download endpoint for this run instead of the configured one
+        #[arg(long, value_name = "URL", help = "Download endpoint to test against")]
+        download_url: Option<String>,
+
+        /// Upload endpoint for this run instead of the configured one
+        #[arg(long, value_name = "URL", help = "Upload endpoint (HTTP POST) to test against")]
+        upload_url: Option<String>,
+
+        /// Skip the upload test
+        #[arg(long, help = "Measure latency and download only")]
+        no_upload: bool,
+
+        /// List stored results instead of running a test
+        #[arg(long, value_name = "N", conflicts_with_all = ["download_url", "upload_url", "no_upload"], help = "List the N most recent results")]
+        history: Option<usize>,
+    },
+
+    /// Name devices, interfaces and remote hosts so output is readable at a glance
+    #[command(about = "Tag a host, device or interface with a friendly name and note")]
+    #[command(long_about = "Attaches a friendly name (and optionally a note) to an IP address, a MAC address \
+or an interface name. Tags are kept in the packet database and shown next to the address or \
+interface wherever it appears: analyze, packets, report, status, the live dashboard, graphs and the web UI.\n\n\
+Examples:\n  \
+kw tag 192.168.1.34 \"kid's tablet\"      # Name a device by its IP address\n  \
+kw tag wlan0 \"home wifi\" --note \"5 GHz\" # Name an interface, with a note\n  \
+kw tag 192.168.1.34                     # Show one tag\n  \
+kw tag                                  # List all tags\n  \
+kw tag 192.168.1.34 --remove            # Delete a tag")]
+    Tag {
+        /// IP address, MAC address or interface name
+        #[arg(help = "IP address, MAC address or interface name; omit to list all tags")]
+        target: Option<String>,
+
+        /// Friendly name to show next to the target
+        #[arg(help = "Friendly name, e.g. \"kid's tablet\"")]
+        label: Option<String>,
+
+        /// Free-form note kept with the tag
+        #[arg(short, long, help = "Note to store with the tag")]
+        note: Option<String>,
+
+        /// Delete the tag instead of setting it
+        #[arg(short, long, conflicts_with_all = ["label", "note"], help = "Remove the tag for the target")]
+        remove: bool,
+    },
+
+    /// Read usage history recorded by vnStat or another tool into the bandwidth history
+    #[command(about = "Import usage history from vnStat or a CSV file into the bandwidth history")]
+    #[command(long_about = "Reads per-interface traffic totals recorded by another tool and stores them as \
+bandwidth samples, so reports, anomaly baselines and forecasts cover the time before kaipo-watcher was \
+installed. vnStat exports (`vnstat --json`, 1.x or 2.x) are taken at the finest resolution that covers each \
+day in full: hours, else days, else months. CSV files need a header with timestamp, rx_bytes and tx_bytes \
+columns, plus interface unless --interface names it, and optionally duration_secs. Entries overlapping \
+samples already stored for the interface are skipped, so importing twice counts nothing again, and so are \
+entries for the current, unfinished hour, day or month.\n\n\
+Examples:\n  \
+vnstat --json > vnstat.json && kw import vnstat.json --format vnstat\n  \
+kw import vnstat.json --format vnstat --interface eth0   # Only eth0\n  \
+kw import usage.csv --format csv --interface wan         # Rows without an interface column")]
+    Import {
+        /// File to read
+        #[arg(help = "File to import, e.g. the output of vnstat --json")]
+        file: std::path::PathBuf,
+
+        /// Format of the file
+        #[arg(long, value_parser = ["vnstat", "csv"], help = "File format: vnstat (vnstat --json) or csv")]
+        format: String,
+
+        /// Interfaces to import, or the interface of CSV rows without one
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Import only these interfaces; names the interface when a CSV file has no interface column"
+        )]
+        interface: Vec<String>,
+    },
+
+    /// Recommend QoS classes and rate limits from stored usage
+    #[command(about = "Recommend bandwidth shaping from stored usage, exportable as tc, nftables or OpenWrt SQM")]
+    #[command(long_about = "Sorts the traffic recorded in the period into categories by service port \
+(interactive, web, streaming, email, bulk, other), then recommends a shaper at 90% of the line rate with \
+one class per category: a guaranteed rate from the category's share of the traffic or its busiest hour, \
+and a limit. The line rate is the latest speed test that measured both directions, else the interface's \
+link speed, unless given with --download-mbit/--upload-mbit.\n\n\
+Examples:\n  \
+kw shaping                            # Recommendations from the last week\n  \
+kw shaping --period month --interface eth0 --format tc --output shaping.sh\n  \
+kw shaping --interface wan --format sqm --download-mbit 250 --upload-mbit 25")]
+    Shaping {
+        /// Usage period the recommendations are based on
+        #[arg(short, long, default_value = "week", help = "Usage period: a span ending now (day, week, month, 3d), a day or month, or a range such as 2024-01-01..2024-01-31")]
+        period: String,
+
+        /// Interface the shaper is set up on
+        #[arg(short = 'I', long, help = "Interface to shape (WAN or uplink); required for exports")]
+        interface: Option<String>,
+
+        /// Report or configuration format
+        #[arg(short, long, default_value = "text", help = "Output: text, tc (shell script), nftables or sqm (OpenWrt)")]
+        format: String,
+
+        /// Download line rate instead of the measured one
+        #[arg(long, value_name = "MBIT", help = "Download line rate in Mbit/s (default: latest speed test, then link speed)")]
+        download_mbit: Option<f64>,
+
+        /// Upload line rate instead of the measured one
+        #[arg(long, value_name = "MBIT", help = "Upload line rate in Mbit/s (default: latest speed test, then link speed)")]
+        upload_mbit: Option<f64>,
+
+        /// File to write the export to
+        #[arg(short, long, help = "Write the configuration to a file instead of printing it")]
+        output: Option<std::path::PathBuf>,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Inspect and verify the tamper-evident audit log
+    #[command(about = "List or verify the hash-chained audit log")]
+    #[command(long_about = "The audit log is an append-only record of stored security events, alerts shown \
+in the live dashboard, configuration changes picked up by `kw service run` and tag changes. Each entry's \
+hash covers the hash of the entry before it, so editing, removing or reordering an entry breaks the chain \
+from that point on. The database is opened read-only.\n\n\
+Examples:\n  \
+kw audit verify                       # Recompute the chain; exits with an error if it is broken\n  \
+kw audit verify --contains <hash>     # Also check a head hash noted earlier is still in the chain\n  \
+kw audit list                         # Show the 20 most recent entries\n  \
+kw audit list --category admin        # Only tag and other admin changes")]
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
     },
 
     /// Show historical network usage data (future feature)
@@ -151,34 +635,56 @@ kw status --interface en0             # Monitor specific interface")]
         days: Option<u32>,
     },
 
-    /// Export network data to various formats (future feature)
-    #[command(about = "Export data")]
+    /// Usage summaries for periodic reviews, and what changed between two of them
+    #[command(about = "Export usage summaries and compare them")]
     Export {
-        /// Output format for the exported data
-        #[arg(
-            short,
-            long,
-            default_value = "json",
-            help = "Export format: json, csv, html"
-        )]
-        format: String,
-
-        /// File path for the exported data
-        #[arg(short, long, help = "Output file path")]
-        output: Option<String>,
+        #[command(subcommand)]
+        action: ExportAction,
     },
 
     /// Real-time packet monitoring and analysis
+    #[cfg(feature = "capture")]
     #[command(about = "Monitor network packets")]
     Packets {
-        /// Network interface to monitor
-        #[arg(short = 'I', long, help = "Monitor specific network interface")]
-        interface: Option<String>,
+        /// Network interfaces to monitor, names or globs; without them a terminal session is asked
+        /// to pick one, otherwise the busiest interface is used
+        #[arg(
+            short = 'I',
+            long,
+            value_delimiter = ',',
+            help = "Monitor specific network interfaces: names or globs, repeatable (default: ask in a terminal, else the busiest one; \"any\" for all)"
+        )]
+        interface: Vec<String>,
+
+        /// Network namespaces to capture in, through the host ends of their veths (Linux)
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Capture a network namespace's traffic on its veths: ip netns names, globs, inodes or pids (Linux)"
+        )]
+        namespace: Vec<String>,
+
+        /// Docker or Podman containers to capture, through the host ends of their veths (Linux)
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Capture a Docker or Podman container's traffic on its veths: names, globs or id prefixes (Linux)"
+        )]
+        container: Vec<String>,
 
         /// Filter by protocol (tcp, udp, icmp, http, https)
         #[arg(short, long, help = "Filter by protocol")]
         protocol: Option<String>,
 
+        /// tcpdump-style capture filter applied before packets are processed
+        #[arg(
+            short,
+            long,
+            value_name = "EXPR",
+            help = "Capture filter expression, e.g. \"tcp port 443 and host 10.0.0.5\""
+        )]
+        filter: Option<String>,
+
         /// Capture duration in seconds
         #[arg(short, long, help = "Capture duration (e.g., 60s, 5m)")]
         capture: Option<String>,
@@ -190,9 +696,52 @@ kw status --interface en0             # Monitor specific interface")]
         /// Maximum number of connections to display
         #[arg(long, default_value = "10", help = "Maximum connections to show")]
         max_connections: usize,
+
+        /// Show connection addresses without looking up their host names
+        #[arg(long, help = "Do not look up host names for the listed connections")]
+        no_resolve: bool,
+
+        /// Group the protocol distribution by service name ("https", "wireguard") from the port
+        #[arg(long, help = "Group the protocol distribution by service name")]
+        by_service: bool,
+
+        /// Run packet capture in a separate privileged helper process (Unix only)
+        /// Only the helper is started with sudo or file capabilities
+        #[arg(
+            long,
+            help = "Capture packets in a privileged helper process so analysis runs unprivileged"
+        )]
+        privileged_helper: bool,
+
+        /// Kernel interface to capture through on Linux, overriding `[capture] backend`
+        #[arg(
+            long,
+            value_parser = ["af-packet", "af-xdp"],
+            help = "Capture through af-packet or af-xdp (Linux; af-xdp only on interfaces without addresses)"
+        )]
+        capture_backend: Option<String>,
+
+        /// Fraction of packets analyzed and stored under load, overriding `[capture] sample`
+        #[arg(long, value_name = "1/N", help = "Analyze and store one packet in N while traffic is over the sample threshold")]
+        sample: Option<String>,
+
+        /// Rate above which `--sample` applies, overriding `[capture] sample_threshold_pps`
+        #[arg(long, value_name = "PPS", help = "Packets per second above which packets are sampled (default 50000)")]
+        sample_threshold: Option<u64>,
+
+        /// Keep the last N packets' leading bytes and show them as hex dumps when capture stops
+        #[arg(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "200",
+            help = "Inspect the last N packets (default 200) as hex and ASCII dumps when capture stops; shows packet contents"
+        )]
+        inspect: Option<usize>,
     },
 
     /// Analyze captured traffic patterns
+    #[cfg(feature = "capture")]
     #[command(about = "Analyze network traffic patterns")]
     Analyze {
         /// Time period to analyze
@@ -200,7 +749,7 @@ kw status --interface en0             # Monitor specific interface")]
             short,
             long,
             default_value = "1h",
-            help = "Analysis period (e.g., 30m, 1h, 24h)"
+            help = "Analysis period: a span ending now (30m, 1h, 3d), today, yesterday, a day or month (2024-01-15, 2024-01), or a range (2024-01-01..2024-01-31)"
         )]
         period: String,
 
@@ -215,18 +764,286 @@ kw status --interface en0             # Monitor specific interface")]
         /// Show protocol distribution
         #[arg(long, help = "Show protocol distribution")]
         protocols: bool,
+
+        /// Include LAN diagnostics
+        #[arg(
+            long,
+            help = "Include LAN diagnostics: duplicate packets, and switching or routing loops told apart by TTL changes"
+        )]
+        diagnostics: bool,
+
+        /// Application protocols listed, busiest first
+        #[arg(short, long, default_value = "10", value_name = "N", help = "Show the N busiest application protocols by bytes")]
+        limit: usize,
+
+        /// Print the analysis as text or JSON
+        #[arg(
+            short,
+            long,
+            default_value = "text",
+            value_parser = ["text", "json"],
+            conflicts_with = "from_pcap",
+            help = "Output: text, or json for the traffic totals and top application protocols as one JSON document"
+        )]
+        format: String,
+
+        /// File the period's security events are exported to
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "security",
+            conflicts_with = "from_pcap",
+            help = "With --security, write the security events and their evidence to a JSON or SARIF report for SIEM tools"
+        )]
+        export: Option<std::path::PathBuf>,
+
+        /// Layout of the `--export` report
+        #[arg(
+            long,
+            value_parser = ["json", "sarif"],
+            requires = "export",
+            help = "Report format: json or sarif (default: sarif for .sarif files, else json)"
+        )]
+        export_format: Option<String>,
+
+        /// Resolve connection addresses to host names
+        #[arg(long, help = "Resolve connection addresses to host names using the [dns] backend")]
+        resolve: bool,
+
+        /// Import packets from a capture file instead of reading stored history
+        #[arg(long, value_name = "FILE", help = "Analyze and store packets from a pcap capture file")]
+        from_pcap: Option<std::path::PathBuf>,
+
+        /// Never write to the database
+        #[arg(long, conflicts_with = "from_pcap", help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+
+        /// Fraction of an imported capture's packets analyzed and stored where it is busy
+        #[arg(long, value_name = "1/N", requires = "from_pcap", help = "With --from-pcap, analyze and store one packet in N where the capture is over the sample threshold")]
+        sample: Option<String>,
+
+        /// Rate above which `--sample` applies to an imported capture
+        #[arg(long, value_name = "PPS", requires = "from_pcap", help = "Packets per second above which imported packets are sampled (default 50000)")]
+        sample_threshold: Option<u64>,
     },
 
     /// Generate network monitoring graphs
+    #[cfg(feature = "graphs")]
     #[command(about = "Generate network monitoring graphs")]
     Graph {
         /// Type of graph to generate
         #[command(subcommand)]
         graph_type: GraphType,
+
+        /// Never write to the database; accepted before or after the graph type
+        #[arg(long, global = true, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Apply the retention policy to the packet database now
+    #[command(about = "Roll up and delete old data as the [retention] policy says, then compact the database")]
+    #[command(long_about = "Applies the [retention] policy: bandwidth and protocol samples older than raw_days \
+are folded into hourly rollups, hourly rollups older than hourly_days into daily ones, daily rollups older \
+than daily_days are deleted, and raw packet records older than raw_days are deleted. The database file is \
+then rebuilt without its free space. `kw service run` applies the same policy every interval_hours.\n\n\
+Examples:\n  \
+kw maintain                           # Apply the policy and compact\n  \
+kw maintain --no-vacuum               # Apply the policy only")]
+    Maintain {
+        /// Skip rebuilding the database file
+        #[arg(long, help = "Do not compact the database file afterwards")]
+        no_vacuum: bool,
+    },
+
+    /// Remove all data, reports and services created by kaipo-watcher
+    #[command(about = "Remove databases, state files, generated reports and installed services")]
+    #[command(long_about = "Removes everything kaipo-watcher has written to disk: the packet database \
+(including WAL/SHM/journal files) and its key in the OS keychain, the counter state file in the data directory of the profile \
+in use, or of every profile with --all-profiles, graph/export files generated with default \
+names in the current directory, and any installed systemd/launchd service definitions or Windows service and event log registrations, along with the service's data directory. \
+Other files in a data directory are listed as left alone rather than removed. \
+A list of items is shown and confirmation is required before anything is deleted.\n\n\
+Examples:\n  \
+kw purge --dry-run                    # List what would be removed\n  \
+kw purge                              # Remove after confirmation\n  \
+kw purge --yes                        # Remove without prompting\n  \
+kw purge --all-profiles               # Also remove every other profile's data")]
+    Purge {
+        /// List what would be removed without deleting anything
+        #[arg(long, help = "Show what would be removed without deleting anything")]
+        dry_run: bool,
+
+        /// Skip the interactive confirmation prompt
+        #[arg(short, long, help = "Do not ask for confirmation")]
+        yes: bool,
+
+        /// Purge every profile rather than the one in use
+        #[arg(long, help = "Remove the data of every profile, not only the one in use")]
+        all_profiles: bool,
+    },
+
+    /// Encrypt the packet database at rest, or turn that off again
+    #[command(about = "Show, enable or disable encryption of the packet database")]
+    #[command(long_about = "With a key in [encryption] key, KW_ENCRYPTION__KEY or the OS keychain \
+([encryption] keychain = true), every database kw opens is encrypted with SQLCipher, and an encrypted \
+database cannot be read without the key. `enable` encrypts an existing plaintext database with the \
+configured key, and `disable` turns it back into plaintext; new databases are created encrypted while a key \
+is configured. Stop `kw service run` first: the database is rewritten. The plaintext the database held \
+before may remain readable on disk and in backups. Needs a kw built with the `encryption` feature.\n\n\
+Examples:\n  \
+kw encryption status                  # Whether the database is encrypted and where its key comes from\n  \
+KW_ENCRYPTION__KEY=... kw encryption enable  # Encrypt with a key from the environment\n  \
+kw encryption enable --generate-key   # Encrypt with a new key kept in the OS keychain\n  \
+kw encryption disable                 # Decrypt with the configured key")]
+    Encryption {
+        #[command(subcommand)]
+        action: EncryptionAction,
+    },
+
+    /// Check that packet capture can work here, and fix what kw can
+    #[command(about = "Check capture privileges and prerequisites, optionally fixing them")]
+    #[command(long_about = "Checks what packet capture needs on this platform: CAP_NET_RAW and CAP_NET_ADMIN \
+on Linux, root or BPF device access on macOS, Administrator rights and the Npcap driver on Windows, plus the \
+ping program the connectivity monitor uses. Exits with an error while something required is missing. \
+With --fix, offers the fixes kw can apply - granting the capabilities to the kw binary with setcap, or \
+giving your user access to the BPF devices - and runs each after confirmation, through sudo when needed.\n\n\
+Examples:\n  \
+kw doctor                             # Report what is missing\n  \
+kw doctor --fix                       # Offer to fix it\n  \
+kw doctor --fix --yes                 # Fix without prompting")]
+    Doctor {
+        /// Offer to apply the available fixes
+        #[arg(long, help = "Apply available fixes after confirmation")]
+        fix: bool,
+
+        /// Skip the interactive confirmation prompt
+        #[arg(short, long, requires = "fix", help = "Do not ask for confirmation")]
+        yes: bool,
+    },
+
+    /// Browser dashboard without the background service
+    #[cfg(feature = "api")]
+    #[command(about = "Serve the web UI: live speeds, history, usage and alerts in a browser")]
+    #[command(long_about = "Serves the web UI `kw service run` offers with [web] listen, on its own and without \
+packet capture: live speeds pushed over a WebSocket as they are read, the last hour of speeds, longer \
+bandwidth history and usage totals from the packet database, and recent alerts. History, usage and alerts \
+come from what `kw service run` has recorded in ./data/packets.db. There is no authentication, so keep it \
+on localhost or a trusted LAN address.\n\n\
+Examples:\n  \
+kw serve                              # http://127.0.0.1:8080, or [web] listen\n  \
+kw serve --web 0.0.0.0:8080           # Reachable from the LAN\n  \
+kw serve --read-only                  # Browse a copied database")]
+    Serve {
+        /// Address to serve on instead of [web] listen
+        #[arg(long, value_name = "ADDRESS", help = "Address to serve the web UI on (ip:port) [default: [web] listen or 127.0.0.1:8080]")]
+        web: Option<std::net::SocketAddr>,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Stream this machine's bandwidth to a hub
+    #[cfg(feature = "remote")]
+    #[command(about = "Report this machine's bandwidth and protocols to a kw hub over gRPC")]
+    #[command(long_about = "Reads every interface's speed each interval and streams it to a `kw hub`, \
+together with the protocols `kw service run` captured here in the same period when its packet database \
+is present. The token in [remote] token is sent with every stream; give the hub a certificate and use an \
+https address (or set [remote] ca_cert) to encrypt the connection. Unreachable hubs are retried with a \
+growing delay.\n\n\
+Examples:\n  \
+kw agent --hub hub.lan                # Report to hub.lan:50051\n  \
+kw agent --hub https://hub.lan:50051 --name nas\n  \
+KW_REMOTE__TOKEN=secret kw agent      # Hub and token from the environment and configuration")]
+    Agent {
+        /// Hub address instead of [remote] hub
+        #[arg(long, value_name = "ADDRESS", help = "Hub to report to: host, host:port or an http(s) URL")]
+        hub: Option<String>,
+
+        /// Name shown by the hub instead of [remote] name or the host name
+        #[arg(long, help = "Name of this machine at the hub")]
+        name: Option<String>,
+
+        /// Seconds between reports instead of [remote] interval_secs
+        #[arg(short, long, value_name = "SECONDS", help = "Seconds between reports")]
+        interval: Option<u64>,
+    },
+
+    /// Collect the reports of agents on other machines
+    #[cfg(feature = "remote")]
+    #[command(about = "Receive bandwidth reports from kw agents and list the fleet")]
+    #[command(long_about = "Accepts `kw agent` streams over gRPC, stores every agent's interface speeds \
+in the packet database as <agent>/<interface> - so reports, graphs and the web UI on this machine cover \
+them all - and lists each machine's latest speeds and busiest protocols every [remote] interval_secs. \
+Agents must present [remote] token when one is set; with tls_cert and tls_key the hub serves TLS. \
+Without them it only listens on a loopback address, unless --insecure allows the token and reports \
+to cross the network in the clear.\n\n\
+Examples:\n  \
+kw hub                                # Listen on [remote] listen (0.0.0.0:50051) with its TLS certificate\n  \
+kw hub --listen 127.0.0.1:6000\n  \
+kw hub --insecure                     # Plain gRPC on a trusted network\n  \
+kw graph bandwidth --interface nas/eth0   # Graph one agent's interface afterwards")]
+    Hub {
+        /// Address instead of [remote] listen
+        #[arg(long, value_name = "ADDRESS", help = "Address to accept agents on")]
+        listen: Option<String>,
+
+        /// Plain gRPC beyond loopback
+        #[arg(long, help = "Accept agents without TLS on a non-loopback address")]
+        insecure: bool,
+    },
+
+    /// Query the packet database over HTTP
+    #[cfg(feature = "api")]
+    #[command(about = "Serve a read-only JSON API over the packet database")]
+    #[command(long_about = "Serves what `kw service run` recorded in ./data/packets.db as JSON, so other \
+tools can query it without reading SQLite: /api/v1/bandwidth for stored speed samples and \
+/api/v1/protocols for packets and bytes per protocol. Both take from and to (RFC 3339, the last 24 hours \
+by default), interface, limit (at most 1000) and offset, and return a page of rows with pagination.next_offset \
+leading to the next. /api/v1/schema describes the responses as JSON Schema. The database is opened read-only \
+and there is no authentication, so keep it on localhost or a trusted LAN address.\n\n\
+Examples:\n  \
+kw api                                # http://127.0.0.1:8081, or [api] listen\n  \
+kw api --listen 0.0.0.0:8081\n  \
+curl 'http://127.0.0.1:8081/api/v1/bandwidth?interface=eth0&from=2024-05-01T00:00:00Z'")]
+    Api {
+        /// Address instead of [api] listen
+        #[arg(long, value_name = "ADDRESS", help = "Address to serve the API on (ip:port) [default: [api] listen or 127.0.0.1:8081]")]
+        listen: Option<std::net::SocketAddr>,
+    },
+
+    /// Run kaipo-watcher as a background service that starts at boot
+    #[cfg(feature = "capture")]
+    #[command(about = "Install, remove or run the background monitoring service")]
+    #[command(long_about = "Manages the kaipo-watcher background service, which captures packets on one \
+interface and records them in the packet database without a terminal attached. `install` registers it to \
+start at boot: as a systemd unit on Linux (logs in the journal, capture limited to CAP_NET_RAW and \
+CAP_NET_ADMIN), a LaunchDaemon on macOS (logs in /Library/Logs/kaipo-watcher.log) or with the Service \
+Control Manager on Windows (logs in the Application event log).\n\n\
+Examples:\n  \
+sudo kw service install               # Install and start the service (root or Administrator)\n  \
+kw service install --interface Ethernet\n  \
+kw service status                     # Show whether the service is running\n  \
+kw service uninstall                  # Stop and remove the service\n  \
+kw service run                        # Run the daemon in the foreground")]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+
+    /// Privileged packet capture helper (started internally by --privileged-helper)
+    /// Captures packets and streams them to the unprivileged UI over a Unix socket
+    #[cfg(feature = "capture")]
+    #[command(hide = true)]
+    CaptureHelper {
+        /// Unix socket to listen on for the UI process, in a directory private to the invoking user
+        #[arg(long, help = "Unix socket path to create, in a directory only the invoking user can enter")]
+        socket: std::path::PathBuf,
     },
 }
 
 /// Types of graphs that can be generated
+#[cfg(feature = "graphs")]
 #[derive(Subcommand)]
 pub enum GraphType {
     /// Generate bandwidth usage graphs
@@ -236,13 +1053,17 @@ pub enum GraphType {
         #[arg(
             short,
             long,
+            visible_alias = "last",
             default_value = "1h",
-            help = "Time period (e.g., 30m, 1h, 24h)"
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
         )]
         period: String,
 
-        /// Network interface to graph
-        #[arg(short = 'I', long, help = "Graph specific network interface")]
+        #[command(flatten)]
+        range: GraphRange,
+
+        /// Network interface(s) to graph
+        #[arg(short = 'I', long, help = "Graph specific network interface(s), comma-separated (e.g., eth0,wlan0)")]
         interface: Option<String>,
 
         /// Output file path
@@ -254,7 +1075,7 @@ pub enum GraphType {
             short,
             long,
             default_value = "png",
-            help = "Output format: png, svg, json, csv"
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
         )]
         format: String,
 
@@ -266,20 +1087,36 @@ pub enum GraphType {
             help = "Graph type: speed, total, both"
         )]
         graph_type: String,
+
+        /// How the speed chart splits traffic into lines
+        #[arg(
+            long,
+            default_value = "direction",
+            help = "Speed chart lines: direction (download vs upload) or interface (one line per interface)"
+        )]
+        series: String,
+
+        /// Leave out the event markers
+        #[arg(long, help = "Don't mark interface outages, alerts and security events on the speed chart")]
+        no_events: bool,
     },
 
     /// Generate protocol distribution graphs
-    #[command(about = "Generate protocol distribution graphs")]
+    #[command(about = "Generate protocol distribution graphs", visible_alias = "protocol")]
     Protocols {
         /// Time period for the graph
         #[arg(
             short,
             long,
+            visible_alias = "last",
             default_value = "1h",
-            help = "Time period (e.g., 30m, 1h, 24h)"
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
         )]
         period: String,
 
+        #[command(flatten)]
+        range: GraphRange,
+
         /// Network interface to graph
         #[arg(short = 'I', long, help = "Graph specific network interface")]
         interface: Option<String>,
@@ -293,7 +1130,7 @@ pub enum GraphType {
             short,
             long,
             default_value = "png",
-            help = "Output format: png, svg, json, csv"
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
         )]
         format: String,
 
@@ -302,9 +1139,13 @@ pub enum GraphType {
             short,
             long,
             default_value = "bar",
-            help = "Chart type: bar, pie, timeline"
+            help = "Chart type: bar, pie, timeline, stacked"
         )]
         chart_type: String,
+
+        /// Stacked area chart of protocol composition over time
+        #[arg(long, help = "Draw protocol composition over time as a stacked area chart (overrides --chart-type)")]
+        stacked: bool,
     },
 
     /// Generate connection pattern graphs
@@ -314,11 +1155,15 @@ pub enum GraphType {
         #[arg(
             short,
             long,
+            visible_alias = "last",
             default_value = "1h",
-            help = "Time period (e.g., 30m, 1h, 24h)"
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
         )]
         period: String,
 
+        #[command(flatten)]
+        range: GraphRange,
+
         /// Network interface to graph
         #[arg(short = 'I', long, help = "Graph specific network interface")]
         interface: Option<String>,
@@ -332,7 +1177,7 @@ pub enum GraphType {
             short,
             long,
             default_value = "png",
-            help = "Output format: png, svg, json, csv"
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
         )]
         format: String,
 
@@ -341,8 +1186,369 @@ pub enum GraphType {
             short,
             long,
             default_value = "timeline",
-            help = "Chart type: timeline, ports, traffic"
+            help = "Chart type: timeline, ports, traffic, flows (local ports to remote hosts, sized by traffic)"
+        )]
+        chart_type: String,
+
+        /// Leave connection addresses as they are in JSON and terminal output
+        #[arg(long, help = "Do not look up host names for the busiest connections' addresses")]
+        no_resolve: bool,
+    },
+
+    /// Generate round-trip time graphs from passive TCP measurements
+    #[command(about = "Generate round-trip time (latency) graphs per destination")]
+    Latency {
+        /// Time period for the graph
+        #[arg(
+            short,
+            long,
+            visible_alias = "last",
+            default_value = "1h",
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
+        )]
+        period: String,
+
+        #[command(flatten)]
+        range: GraphRange,
+
+        /// Network interface to graph
+        #[arg(short = 'I', long, help = "Graph specific network interface")]
+        interface: Option<String>,
+
+        /// Output file path
+        #[arg(short, long, help = "Output file path")]
+        output: Option<String>,
+
+        /// Graph format
+        #[arg(
+            short,
+            long,
+            default_value = "png",
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
+        )]
+        format: String,
+
+        /// Chart type
+        #[arg(
+            short,
+            long,
+            default_value = "timeline",
+            help = "Chart type: timeline (median RTT over time), percentiles (p50/p90/p99 per host)"
+        )]
+        chart_type: String,
+    },
+
+    /// Generate graphs of captured packet sizes
+    #[command(about = "Generate packet size distribution graphs", visible_alias = "sizes")]
+    PacketSizes {
+        /// Time period for the graph
+        #[arg(
+            short,
+            long,
+            visible_alias = "last",
+            default_value = "1h",
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
+        )]
+        period: String,
+
+        #[command(flatten)]
+        range: GraphRange,
+
+        /// Network interface to graph
+        #[arg(short = 'I', long, help = "Graph specific network interface")]
+        interface: Option<String>,
+
+        /// Output file path
+        #[arg(short, long, help = "Output file path")]
+        output: Option<String>,
+
+        /// Graph format
+        #[arg(
+            short,
+            long,
+            default_value = "png",
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
+        )]
+        format: String,
+
+        /// Chart type
+        #[arg(
+            short,
+            long,
+            default_value = "histogram",
+            help = "Chart type: histogram (share of each size per protocol), timeline (packets per size over time)"
+        )]
+        chart_type: String,
+    },
+
+    /// Generate graphs of stored speed test results
+    #[command(about = "Generate speed test history graphs")]
+    Speedtest {
+        /// Time period for the graph
+        #[arg(
+            short,
+            long,
+            visible_alias = "last",
+            default_value = "30d",
+            help = "Time period ending now, or at --to (e.g., 24h, 7d, 30d)"
+        )]
+        period: String,
+
+        #[command(flatten)]
+        range: GraphRange,
+
+        /// Output file path
+        #[arg(short, long, help = "Output file path")]
+        output: Option<String>,
+
+        /// Graph format
+        #[arg(
+            short,
+            long,
+            default_value = "png",
+            help = "Output format: png, svg, json, csv, or terminal/ascii/ansi to print the graph in the terminal"
+        )]
+        format: String,
+
+        /// Chart type
+        #[arg(
+            short,
+            long,
+            default_value = "speed",
+            help = "Chart type: speed (download and upload over time), latency (latency and jitter over time)"
         )]
         chart_type: String,
     },
+
+    /// Generate a map of which hosts talk to which remote services
+    #[command(about = "Generate a service dependency map from observed flows", visible_alias = "deps")]
+    Dependencies {
+        /// Time period to build the map from
+        #[arg(
+            short,
+            long,
+            visible_alias = "last",
+            default_value = "1h",
+            help = "Time period ending now, or a span ending at --to (e.g., 30m, 24h, 2w, 2024-05)"
+        )]
+        period: String,
+
+        #[command(flatten)]
+        range: GraphRange,
+
+        /// Output file path
+        #[arg(short, long, help = "Output file path")]
+        output: Option<String>,
+
+        /// Map format
+        #[arg(
+            short,
+            long,
+            default_value = "dot",
+            help = "Output format: dot (Graphviz), html (interactive force graph), json, or terminal/ascii/ansi to print the map"
+        )]
+        format: String,
+
+        /// Maximum number of host -> service links to include
+        #[arg(short, long, default_value = "50", help = "Keep only the N busiest host -> service links")]
+        limit: usize,
+    },
+}
+
+/// Time range and bucket size shared by every graph type
+#[cfg(feature = "graphs")]
+#[derive(Args, Debug, Clone, Default)]
+pub struct GraphRange {
+    /// Start of the graph instead of --period before the end
+    #[arg(
+        long,
+        conflicts_with = "period",
+        value_name = "TIME",
+        help = "Start time: 2024-05-01, \"2024-05-01 14:30\", RFC 3339, or a span ago such as 2d (ends at --to or now)"
+    )]
+    pub from: Option<String>,
+
+    /// End of the graph instead of now
+    #[arg(long, value_name = "TIME", help = "End time, in the same forms as --from (default: now)")]
+    pub to: Option<String>,
+
+    /// Bucket size time series are aggregated into
+    #[arg(
+        long,
+        value_name = "SPAN",
+        help = "Bucket size for time series (e.g., 1m, 1h, 1d); default: auto, about 300 points"
+    )]
+    pub resolution: Option<String>,
+}
+
+/// Usage summary export actions
+#[derive(Subcommand)]
+pub enum ExportAction {
+    /// Write the usage of a period as JSON
+    #[command(about = "Export a usage summary: totals, top talkers, protocol shares and local devices")]
+    Summary {
+        /// Period to summarize
+        #[arg(short, long, default_value = "week", help = "Period to summarize: a span ending now (day, week, month, 3d), a day or month, or a range such as 2024-01-01..2024-01-31")]
+        period: String,
+
+        /// File to write the summary to
+        #[arg(short, long, help = "Write the JSON summary to this file instead of stdout")]
+        output: Option<std::path::PathBuf>,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+
+    /// Compare two exported summaries, or the last period with the one before it
+    #[command(about = "Show what changed between two usage summaries")]
+    Diff {
+        /// Older summary
+        #[arg(value_name = "OLD", requires = "new", help = "Older summary written by `kw export summary`")]
+        old: Option<std::path::PathBuf>,
+
+        /// Newer summary
+        #[arg(value_name = "NEW", help = "Newer summary written by `kw export summary`")]
+        new: Option<std::path::PathBuf>,
+
+        /// Period compared with the one before it when no files are given
+        #[arg(short, long, default_value = "week", help = "Without files, compare this period (e.g. week, 3d or 2024-02) with the one of the same length before it")]
+        period: String,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+}
+
+/// Statistics about kw itself
+#[derive(Subcommand)]
+pub enum StatsAction {
+    /// How the bandwidth collector of `kw service run` has been doing
+    #[command(name = "self", about = "Show the bandwidth collector's health over time")]
+    #[command(long_about = "Shows how long the bandwidth collections of `kw service run` took, how often \
+refreshing the interface counters had to be retried, which interfaces failed validation, and how many \
+collections ran in degraded mode (some interfaces lost) or failed outright, per hour or, over more than \
+three days, per day. Recorded every minute by the daemon.\n\n\
+Examples:\n  \
+kw stats self                 # The last 24 hours\n  \
+kw stats self --hours 168     # The last week, per day")]
+    Collector {
+        /// Hours to look back
+        #[arg(long, default_value = "24", help = "Hours of collector health to show")]
+        hours: u32,
+
+        /// Never write to the database
+        #[arg(long, help = "Open the database without write access or schema changes, e.g. to inspect a copy")]
+        read_only: bool,
+    },
+}
+
+/// Audit log actions
+#[derive(Subcommand)]
+pub enum AuditAction {
+    /// Recompute every hash and report the first entry that does not fit
+    #[command(about = "Verify the audit log's hash chain")]
+    Verify {
+        /// Head hash printed by an earlier verify
+        #[arg(long, value_name = "HASH", help = "Also check that a head hash noted earlier is still part of the chain")]
+        contains: Option<String>,
+    },
+
+    /// Show recent entries
+    #[command(about = "List recent audit log entries")]
+    List {
+        /// Number of entries to show
+        #[arg(short, long, default_value = "20", help = "Number of entries to show, newest first")]
+        limit: usize,
+
+        /// Only entries of one category
+        #[arg(short, long, help = "Only show one category: security_event, alert, config or admin")]
+        category: Option<String>,
+    },
+}
+
+/// Database encryption actions
+#[derive(Subcommand)]
+pub enum EncryptionAction {
+    /// Whether the database is encrypted and where its key comes from
+    #[command(about = "Show whether the packet database is encrypted")]
+    Status,
+
+    /// Encrypt an existing plaintext database
+    #[command(about = "Encrypt the packet database with the configured key")]
+    Enable {
+        /// Make up a key and keep it in the OS keychain
+        #[arg(long, help = "Generate a random key and store it in the OS keychain under the profile's name")]
+        generate_key: bool,
+    },
+
+    /// Turn an encrypted database back into plaintext
+    #[command(about = "Decrypt the packet database with the configured key")]
+    Disable,
+}
+
+/// Service management actions
+#[cfg(feature = "capture")]
+#[derive(Subcommand)]
+pub enum ServiceAction {
+    /// Register and start the service so it runs at boot
+    #[command(about = "Install the service and start it")]
+    Install {
+        /// Network interface to capture on
+        #[arg(short, long, default_value = "any", help = "Network interface to capture on")]
+        interface: String,
+
+        /// Directory for the packet database
+        #[arg(long, help = "Directory for the packet database (default: platform data directory)")]
+        data_dir: Option<std::path::PathBuf>,
+    },
+
+    /// Stop and remove the installed service
+    #[command(about = "Stop and remove the service")]
+    Uninstall,
+
+    /// Show whether the service is installed and running
+    #[command(about = "Show the service state")]
+    Status,
+
+    /// Run the monitoring daemon (invoked by the service manager)
+    #[command(about = "Run the monitoring daemon in the foreground or under the service manager")]
+    Run {
+        /// Network interface to capture on
+        #[arg(short, long, default_value = "any", help = "Network interface to capture on")]
+        interface: String,
+
+        /// Directory for the packet database
+        #[arg(long, help = "Directory for the packet database (default: platform data directory)")]
+        data_dir: Option<std::path::PathBuf>,
+    },
+}
+
+/// Longest `--smooth` window accepted, an hour
+#[cfg(feature = "dashboard")]
+const MAX_SMOOTHING_SECS: f64 = 3600.0;
+
+/// `--smooth`: a number of seconds from 0 to `MAX_SMOOTHING_SECS`
+#[cfg(feature = "dashboard")]
+fn parse_smoothing_secs(value: &str) -> Result<f64, String> {
+    let secs: f64 = value.parse().map_err(|_| format!("{value:?} is not a number of seconds"))?;
+    if !(0.0..=MAX_SMOOTHING_SECS).contains(&secs) {
+        return Err(format!("must be between 0 and {MAX_SMOOTHING_SECS} seconds"));
+    }
+    Ok(secs)
+}
+
+#[cfg(all(test, feature = "dashboard"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoothing_window_is_bounded() {
+        assert_eq!(parse_smoothing_secs("0"), Ok(0.0));
+        assert_eq!(parse_smoothing_secs("2.5"), Ok(2.5));
+        for value in ["inf", "NaN", "-1", "1e300", "soon"] {
+            assert!(parse_smoothing_secs(value).is_err(), "{value}");
+        }
+    }
 }
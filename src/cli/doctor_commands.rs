@@ -0,0 +1,122 @@
+// CLI Doctor Commands: Checks what packet capture needs on this platform
+// Reports capture privileges, BPF devices or the Npcap driver and the ping program, and
+// with --fix runs the fixes the platform offers (setcap, BPF group access) after asking
+
+use anyhow::{bail, Context, Result};
+use std::process::Command;
+
+use crate::cli::purge_commands::confirm;
+use crate::collectors::platform::prerequisites::{self, CheckStatus, Fix, Prerequisite};
+
+/// Command handler for `kw doctor`
+pub struct DoctorCommandHandler;
+
+impl DoctorCommandHandler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Lists the checks; with `fix`, offers each available fix for a failed one
+    /// Fails when something capture needs is still missing, so scripts can test for it
+    pub async fn handle_doctor_command(&self, fix: bool, assume_yes: bool) -> Result<()> {
+        println!("🩺 Capture prerequisites ({})", std::env::consts::OS);
+        let checks = prerequisites::check_prerequisites();
+        print_checks(&checks);
+
+        let fixable: Vec<&Prerequisite> =
+            checks.iter().filter(|check| check.status != CheckStatus::Ok && check.fix.is_some()).collect();
+        if !fix {
+            if !fixable.is_empty() {
+                println!("\nRun `kw doctor --fix` to apply {} available fix(es)", fixable.len());
+            }
+            return verdict(&checks);
+        }
+        if fixable.is_empty() {
+            println!("\nNothing kw can fix by itself");
+            return verdict(&checks);
+        }
+
+        let mut applied = 0;
+        for check in fixable {
+            let Some(fix) = &check.fix else {
+                continue;
+            };
+            println!("\n🔧 {}: {}", check.name, fix.description);
+            let sudo = needs_sudo(fix);
+            for line in fix.command_lines() {
+                println!("   $ {}{line}", if sudo { "sudo " } else { "" });
+            }
+            if !assume_yes && !confirm("   Run these commands? [y/N]: ")? {
+                println!("   Skipped");
+                continue;
+            }
+            apply(fix, sudo)?;
+            applied += 1;
+            println!("   ✅ Done");
+        }
+        if applied > 0 {
+            // Capabilities and group membership only apply to processes started afterwards
+            println!("\nApplied {applied} fix(es); run `kw doctor` again to confirm");
+            return Ok(());
+        }
+        verdict(&checks)
+    }
+}
+
+impl Default for DoctorCommandHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn print_checks(checks: &[Prerequisite]) {
+    for check in checks {
+        let icon = match check.status {
+            CheckStatus::Ok => "✅",
+            CheckStatus::Warning => "⚠️ ",
+            CheckStatus::Missing => "❌",
+        };
+        println!("   {icon} {:<26} {}", check.name, check.detail);
+    }
+}
+
+/// Error when any check is still missing
+fn verdict(checks: &[Prerequisite]) -> Result<()> {
+    let missing = checks.iter().filter(|check| check.status == CheckStatus::Missing).count();
+    if missing > 0 {
+        bail!("{missing} capture prerequisite(s) missing");
+    }
+    println!("\nReady to capture");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn needs_sudo(fix: &Fix) -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    fix.privileged && unsafe { libc::geteuid() } != 0
+}
+
+#[cfg(not(unix))]
+fn needs_sudo(_fix: &Fix) -> bool {
+    false
+}
+
+/// Runs the fix's commands in order, with the terminal attached so sudo can ask for a password
+fn apply(fix: &Fix, sudo: bool) -> Result<()> {
+    for command in &fix.commands {
+        let mut process = if sudo {
+            let mut process = Command::new("sudo");
+            process.args(command);
+            process
+        } else {
+            let mut process = Command::new(&command[0]);
+            process.args(&command[1..]);
+            process
+        };
+        let status = process.status().with_context(|| format!("Failed to run {}", command[0]))?;
+        if !status.success() {
+            bail!("`{}` failed ({status})", command.join(" "));
+        }
+    }
+    Ok(())
+}
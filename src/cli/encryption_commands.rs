@@ -0,0 +1,115 @@
+// CLI Encryption Commands: SQLCipher encryption of the packet database at rest
+// Shows whether the database is encrypted and where its key comes from, and converts an
+// existing database between plaintext and encrypted with the configured key
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+
+use crate::cli::commands::EncryptionAction;
+use crate::config::EncryptionConfig;
+use crate::storage::encryption::{self, DatabaseKey, KeySource};
+
+/// Command handler for `kw encryption`
+pub struct EncryptionCommandHandler {
+    database: PathBuf,
+    /// Profile the keychain entry is named after
+    profile: String,
+    config: EncryptionConfig,
+}
+
+impl EncryptionCommandHandler {
+    pub fn new<P: AsRef<Path>>(database: P, profile: &str) -> Self {
+        Self {
+            database: database.as_ref().to_path_buf(),
+            profile: profile.to_string(),
+            config: EncryptionConfig::default(),
+        }
+    }
+
+    /// Where the key comes from
+    pub fn with_config(mut self, config: EncryptionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub async fn handle_encryption_command(&self, action: EncryptionAction) -> Result<()> {
+        match action {
+            EncryptionAction::Status => self.status(),
+            EncryptionAction::Enable { generate_key } => self.enable(generate_key),
+            EncryptionAction::Disable => self.disable(),
+        }
+    }
+
+    fn status(&self) -> Result<()> {
+        println!("🔐 Database Encryption");
+        println!("   Database: {}", self.database.display());
+        let state = if encryption::is_encrypted(&self.database) {
+            "encrypted"
+        } else if encryption::is_plaintext(&self.database) {
+            "not encrypted"
+        } else {
+            "not created yet"
+        };
+        println!("   State:    {state}");
+        match encryption::configured_key(&self.config, &self.profile) {
+            Ok(Some((_, source))) => println!("   Key:      from {source}"),
+            Ok(None) => println!("   Key:      none configured"),
+            Err(e) => println!("   Key:      unavailable ({e:#})"),
+        }
+        if !cfg!(feature = "encryption") {
+            println!("   This kw was built without the `encryption` feature");
+        }
+        Ok(())
+    }
+
+    fn enable(&self, generate_key: bool) -> Result<()> {
+        let configured = encryption::configured_key(&self.config, &self.profile)?;
+        let key = match (configured, generate_key) {
+            (Some((_, source)), true) => bail!("A key is already configured in {source}; leave out --generate-key to use it"),
+            (Some((key, _)), false) => key,
+            (None, true) => {
+                let key = DatabaseKey::generate()?;
+                encryption::store_keychain_key(&self.profile, &key)?;
+                println!("🔑 Stored a new key in the OS keychain for the {} profile", self.profile);
+                if !self.config.keychain {
+                    println!("   Set [encryption] keychain = true so that kw reads it from there");
+                }
+                key
+            }
+            (None, false) => bail!(
+                "No database key configured: set [encryption] key or KW_ENCRYPTION__KEY, or use --generate-key to keep a new one in the OS keychain"
+            ),
+        };
+
+        if encryption::is_encrypted(&self.database) {
+            println!("{} is already encrypted", self.database.display());
+            return Ok(());
+        }
+        if !encryption::is_plaintext(&self.database) {
+            println!("No database at {} yet; it will be created encrypted", self.database.display());
+            return Ok(());
+        }
+        encryption::encrypt_database(&self.database, &key)?;
+        println!("🔒 Encrypted {}", self.database.display());
+        println!("   Earlier plaintext copies, such as backups or blocks the file system has not reused, are not erased");
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<()> {
+        if !encryption::is_encrypted(&self.database) {
+            println!("{} is not encrypted", self.database.display());
+            return Ok(());
+        }
+        let Some((key, source)) = encryption::configured_key(&self.config, &self.profile)? else {
+            bail!("{} is encrypted, but no key is configured to decrypt it", self.database.display());
+        };
+        encryption::decrypt_database(&self.database, &key)?;
+        println!("🔓 Decrypted {}", self.database.display());
+        let hint = match source {
+            KeySource::Config => "remove [encryption] key or KW_ENCRYPTION__KEY",
+            KeySource::Keychain => "set [encryption] keychain = false",
+        };
+        println!("   kw will refuse the plaintext database while a key is configured: {hint}");
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+// CLI Export Commands: Usage summaries for periodic reviews
+// A summary is written as JSON so it can be kept alongside review notes; two summaries,
+// or the last period and the one before it, are compared for new top talkers, protocol
+// share shifts and devices that appeared or went quiet
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::analyzers::usage_diff::{UsageDiff, UsageSnapshot};
+use crate::cli::commands::ExportAction;
+use crate::cli::period::Period;
+use crate::display::Units;
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw export`
+pub struct ExportCommandHandler {
+    storage: Arc<PacketStorage>,
+    units: Units,
+}
+
+impl ExportCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self {
+            storage,
+            units: Units::default(),
+        }
+    }
+
+    /// Rate unit and number separators of the diff
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub async fn handle_export_command(&self, action: ExportAction) -> Result<()> {
+        match action {
+            ExportAction::Summary { period, output, .. } => self.summary(&period, output),
+            ExportAction::Diff { old, new, period, .. } => match (old, new) {
+                (Some(old), Some(new)) => self.diff(&read_snapshot(&old)?, &read_snapshot(&new)?),
+                _ => {
+                    let period = Period::parse(&period, Local::now())?;
+                    let previous = period.previous();
+                    let old = self.snapshot(previous.start, previous.end)?;
+                    let new = self.snapshot(period.start, period.end)?;
+                    self.diff(&old, &new)
+                }
+            },
+        }
+    }
+
+    fn summary(&self, period: &str, output: Option<PathBuf>) -> Result<()> {
+        let period = Period::parse(period, Local::now())?;
+        let snapshot = self.snapshot(period.start, period.end)?;
+        let json = serde_json::to_string_pretty(&snapshot)?;
+        match output {
+            Some(path) => {
+                std::fs::write(&path, json + "\n").with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("📤 Usage summary for {} written to {}", period.label, path.display());
+            }
+            None => println!("{json}"),
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<UsageSnapshot> {
+        let usage = self.storage.get_usage_summary(start, end)?;
+        let hosts = self.storage.get_hosts_seen(start, end)?;
+        Ok(UsageSnapshot::new(start, end, &usage, &hosts).redacted(self.storage.redactor()))
+    }
+
+    fn diff(&self, old: &UsageSnapshot, new: &UsageSnapshot) -> Result<()> {
+        if old.start > new.start {
+            bail!("The first summary is the newer one; give the older summary first");
+        }
+        let diff = UsageDiff::between(old, new);
+        let tags = self.storage.tag_book()?;
+        let units = &self.units;
+        let period = |snapshot: &UsageSnapshot| {
+            format!("{} → {}", snapshot.start.format("%Y-%m-%d %H:%M"), snapshot.end.format("%Y-%m-%d %H:%M"))
+        };
+
+        println!("🔀 Usage Changes");
+        println!("   Before: {}", period(old));
+        println!("   After:  {}", period(new));
+        println!();
+        println!(
+            "📦 Traffic:     {} → {}",
+            units.bytes(old.bytes as f64),
+            units.bytes(new.bytes as f64)
+        );
+        println!(
+            "   Connections: {} → {}",
+            units.number(old.connections as f64, 0),
+            units.number(new.connections as f64, 0)
+        );
+
+        if diff.is_empty() {
+            println!();
+            println!("✅ No new top talkers, protocol shifts or devices");
+            return Ok(());
+        }
+
+        if !diff.new_talkers.is_empty() || !diff.former_talkers.is_empty() {
+            println!();
+            println!("🖥️  Top talkers:");
+            for talker in &diff.new_talkers {
+                println!("   + {:<40} {:>10}", tags.describe(&talker.name), units.bytes(talker.bytes as f64));
+            }
+            for talker in &diff.former_talkers {
+                println!("   - {:<40} {:>10} before", tags.describe(&talker.name), units.bytes(talker.bytes as f64));
+            }
+        }
+
+        if !diff.share_shifts.is_empty() {
+            println!();
+            println!("🧩 Protocol share:");
+            for shift in &diff.share_shifts {
+                println!(
+                    "   {:<12} {:>5}% → {:>5}%  ({}{} points)",
+                    shift.protocol,
+                    units.number(shift.old_share, 1),
+                    units.number(shift.new_share, 1),
+                    if shift.change() > 0.0 { "+" } else { "" },
+                    units.number(shift.change(), 1)
+                );
+            }
+        }
+
+        if !diff.new_devices.is_empty() || !diff.missing_devices.is_empty() {
+            println!();
+            println!("📱 Local devices:");
+            for device in &diff.new_devices {
+                println!("   + {} (new)", tags.describe(device));
+            }
+            for device in &diff.missing_devices {
+                println!("   - {} (not seen)", tags.describe(device));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_snapshot(path: &Path) -> Result<UsageSnapshot> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("{} is not a summary written by `kw export summary`", path.display()))
+}
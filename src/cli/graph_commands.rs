@@ -1,64 +1,112 @@
-use crate::cli::commands::GraphType;
-use crate::graphs::bandwidth_graphs::BandwidthGraph;
+use crate::cli::commands::{GraphRange, GraphType};
+use crate::display::Units;
+use crate::enrichment::{resolve_host_names, CachingResolver};
+use crate::graphs::bandwidth_graphs::{BandwidthGraph, SeriesSplit};
 use crate::graphs::protocol_graphs::ProtocolGraph;
-use crate::graphs::connection_graphs::ConnectionGraph;
+use crate::graphs::connection_graphs::{ConnectionGraph, FLOW_LINKS};
+use crate::graphs::dependency_graphs::{DependencyFormat, DependencyGraph};
+use crate::graphs::latency_graphs::LatencyGraph;
+use crate::graphs::packet_size_graphs::PacketSizeGraph;
+use crate::graphs::speedtest_graphs::SpeedTestGraph;
 use crate::graphs::export::{ExportConfig, ExportFormat, ExportManager};
-use crate::graphs::GraphConfig;
+use crate::graphs::terminal::{TerminalCanvas, TerminalStyle};
+use crate::graphs::time_range::GraphWindow;
+use crate::graphs::{DatabaseManager, GraphConfig, ImageFormat};
+use crate::models::ServiceMap;
 use anyhow::Result;
-use chrono::{DateTime, Duration, Utc};
+use chrono::Utc;
+use log::warn;
 use std::sync::Arc;
 
-/// Simple database wrapper for graph operations
-pub struct DatabaseManager {
-    pub connection: std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>,
-}
-
-impl DatabaseManager {
-    pub async fn new(path: &str) -> Result<Self> {
-        let conn = rusqlite::Connection::open(path)?;
-        Ok(Self {
-            connection: Arc::new(std::sync::Mutex::new(conn)),
-        })
-    }
-}
+/// Busiest connections listed in connection graph exports and terminal output
+const TOP_CONNECTIONS: usize = 20;
 
 pub struct GraphCommandHandler {
     db: Arc<DatabaseManager>,
+    units: Units,
+    /// Reverse DNS for the addresses of the busiest connections
+    resolver: Option<Arc<CachingResolver>>,
+    /// Service names for ports in connection and dependency graphs
+    services: ServiceMap,
 }
 
 impl GraphCommandHandler {
     pub fn new(db: Arc<DatabaseManager>) -> Self {
-        Self { db }
+        Self { db, units: Units::default(), resolver: None, services: ServiceMap::new() }
+    }
+
+    /// Looks up host names for connection graphs, unless `--no-resolve` is given
+    pub fn with_resolver(mut self, resolver: CachingResolver) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Port names from the built-in table plus the `[services]` overrides
+    pub fn with_services(mut self, services: ServiceMap) -> Self {
+        self.services = services;
+        self
+    }
+
+    /// Rate unit and number separators used in axis labels and legends
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
     }
 
     pub async fn handle_graph_command(&self, graph_type: GraphType) -> Result<()> {
         match graph_type {
-            GraphType::Bandwidth { period, interface, output, format, graph_type } => {
-                self.handle_bandwidth_graph(period, interface, output, format, graph_type).await
+            GraphType::Bandwidth { period, range, interface, output, format, graph_type, series, no_events } => {
+                let window = self.resolve_window(&period, &range)?;
+                self.handle_bandwidth_graph(window, interface, output, format, graph_type, series, !no_events).await
+            }
+            GraphType::Protocols { period, range, interface, output, format, chart_type, stacked } => {
+                let window = self.resolve_window(&period, &range)?;
+                let chart_type = if stacked { "stacked".to_string() } else { chart_type };
+                self.handle_protocol_graph(window, interface, output, format, chart_type).await
+            }
+            GraphType::Connections { period, range, interface, output, format, chart_type, no_resolve } => {
+                let window = self.resolve_window(&period, &range)?;
+                self.handle_connection_graph(window, interface, output, format, chart_type, no_resolve).await
             }
-            GraphType::Protocols { period, interface, output, format, chart_type } => {
-                self.handle_protocol_graph(period, interface, output, format, chart_type).await
+            GraphType::Latency { period, range, interface, output, format, chart_type } => {
+                let window = self.resolve_window(&period, &range)?;
+                self.handle_latency_graph(window, interface, output, format, chart_type).await
             }
-            GraphType::Connections { period, interface, output, format, chart_type } => {
-                self.handle_connection_graph(period, interface, output, format, chart_type).await
+            GraphType::PacketSizes { period, range, interface, output, format, chart_type } => {
+                let window = self.resolve_window(&period, &range)?;
+                self.handle_packet_size_graph(window, interface, output, format, chart_type).await
+            }
+            GraphType::Speedtest { period, range, output, format, chart_type } => {
+                let window = self.resolve_window(&period, &range)?;
+                self.handle_speedtest_graph(window, output, format, chart_type).await
+            }
+            GraphType::Dependencies { period, range, output, format, limit } => {
+                let window = self.resolve_window(&period, &range)?;
+                if window.explicit_resolution {
+                    warn!("Dependency maps have no time axis; --resolution is ignored");
+                }
+                self.handle_dependency_graph(window, output, format, limit).await
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_bandwidth_graph(
         &self,
-        period: String,
+        window: GraphWindow,
         interface: Option<String>,
         output: Option<String>,
         format: String,
         graph_type: String,
+        series: String,
+        event_markers: bool,
     ) -> Result<()> {
-        let (start_time, end_time) = self.parse_period(&period)?;
+        let split = SeriesSplit::parse(&series)?;
         
         let output_path = output.unwrap_or_else(|| {
             let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
             match interface.as_ref() {
-                Some(iface) => format!("bandwidth_{iface}_{timestamp}.{format}"),
+                Some(iface) => format!("bandwidth_{}_{timestamp}.{format}", iface.replace(',', "-")),
                 None => format!("bandwidth_all_{timestamp}.{format}"),
             }
         });
@@ -72,16 +120,34 @@ impl GraphCommandHandler {
             },
             x_label: "Time".to_string(),
             y_label: "Speed (bytes/s)".to_string(),
+            units: self.units,
         };
 
-        let mut graph = BandwidthGraph::new(config);
-        graph.load_data(&self.db, start_time, end_time, interface.clone()).await?;
+        let mut graph = BandwidthGraph::new(config).with_series_split(split).with_event_markers(event_markers);
+        graph.load_data(&self.db, &window, interface.clone()).await?;
 
         if graph.data.is_empty() {
             println!("No bandwidth data found for the specified period.");
             return Ok(());
         }
 
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            match graph_type.as_str() {
+                "speed" => print!("{}", graph.render_speed_terminal(&canvas)),
+                "total" => print!("{}", graph.render_total_usage_terminal(&canvas)),
+                "both" => {
+                    print!("{}", graph.render_speed_terminal(&canvas));
+                    println!();
+                    print!("{}", graph.render_total_usage_terminal(&canvas));
+                }
+                _ => return Err(anyhow::anyhow!("Invalid graph type: {}", graph_type)),
+            }
+            return Ok(());
+        }
+
+        let image_format = ImageFormat::parse(&format);
         let export_format = self.parse_export_format(&format)?;
         let export_config = ExportConfig {
             format: export_format,
@@ -94,25 +160,26 @@ impl GraphCommandHandler {
 
         match graph_type.as_str() {
             "speed" => {
-                if format == "png" {
-                    graph.render_speed_chart(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_speed_chart(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_bandwidth_data(&graph)?;
                 }
             }
             "total" => {
-                if format == "png" {
-                    graph.render_total_usage_chart(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_total_usage_chart(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_bandwidth_data(&graph)?;
                 }
             }
             "both" => {
-                if format == "png" {
-                    let speed_path = output_path.replace(".png", "_speed.png");
-                    let total_path = output_path.replace(".png", "_total.png");
-                    graph.render_speed_chart(std::path::Path::new(&speed_path))?;
-                    graph.render_total_usage_chart(std::path::Path::new(&total_path))?;
+                if let Some(image_format) = image_format {
+                    let extension = format!(".{}", format.to_lowercase());
+                    let speed_path = output_path.replace(&extension, &format!("_speed{extension}"));
+                    let total_path = output_path.replace(&extension, &format!("_total{extension}"));
+                    graph.render_speed_chart(std::path::Path::new(&speed_path), image_format)?;
+                    graph.render_total_usage_chart(std::path::Path::new(&total_path), image_format)?;
                     println!("Generated speed chart: {speed_path}");
                     println!("Generated total usage chart: {total_path}");
                 } else {
@@ -124,9 +191,9 @@ impl GraphCommandHandler {
             }
         }
 
-        if format == "png" && graph_type != "both" {
+        if image_format.is_some() && graph_type != "both" {
             println!("Bandwidth graph saved to: {output_path}");
-        } else if format != "png" {
+        } else if image_format.is_none() {
             println!("Bandwidth data exported to: {output_path}");
         }
 
@@ -135,13 +202,12 @@ impl GraphCommandHandler {
 
     async fn handle_protocol_graph(
         &self,
-        period: String,
+        window: GraphWindow,
         interface: Option<String>,
         output: Option<String>,
         format: String,
         chart_type: String,
     ) -> Result<()> {
-        let (start_time, end_time) = self.parse_period(&period)?;
         
         let output_path = output.unwrap_or_else(|| {
             let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
@@ -160,16 +226,32 @@ impl GraphCommandHandler {
             },
             x_label: "Protocol".to_string(),
             y_label: "Packet Count".to_string(),
+            units: self.units,
         };
 
         let mut graph = ProtocolGraph::new(config);
-        graph.load_data(&self.db, start_time, end_time, interface.clone()).await?;
+        graph.load_data(&self.db, &window, interface.clone()).await?;
 
         if graph.data.is_empty() {
             println!("No protocol data found for the specified period.");
             return Ok(());
         }
 
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            let rendered = match chart_type.as_str() {
+                "bar" => graph.render_bar_terminal(&canvas),
+                "pie" => graph.render_pie_terminal(&canvas),
+                "timeline" => graph.render_timeline_terminal(&canvas),
+                "stacked" => graph.render_stacked_terminal(&canvas),
+                _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+            };
+            print!("{rendered}");
+            return Ok(());
+        }
+
+        let image_format = ImageFormat::parse(&format);
         let export_format = self.parse_export_format(&format)?;
         let export_config = ExportConfig {
             format: export_format,
@@ -182,22 +264,29 @@ impl GraphCommandHandler {
 
         match chart_type.as_str() {
             "bar" => {
-                if format == "png" {
-                    graph.render_bar_chart(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_bar_chart(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_protocol_data(&graph)?;
                 }
             }
             "pie" => {
-                if format == "png" {
-                    graph.render_pie_chart(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_pie_chart(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_protocol_data(&graph)?;
                 }
             }
             "timeline" => {
-                if format == "png" {
-                    graph.render_timeline_chart(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_timeline_chart(std::path::Path::new(&output_path), image_format)?;
+                } else {
+                    export_manager.export_protocol_data(&graph)?;
+                }
+            }
+            "stacked" => {
+                if let Some(image_format) = image_format {
+                    graph.render_stacked_chart(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_protocol_data(&graph)?;
                 }
@@ -207,7 +296,7 @@ impl GraphCommandHandler {
             }
         }
 
-        if format == "png" {
+        if image_format.is_some() {
             println!("Protocol {chart_type} chart saved to: {output_path}");
         } else {
             println!("Protocol data exported to: {output_path}");
@@ -218,13 +307,13 @@ impl GraphCommandHandler {
 
     async fn handle_connection_graph(
         &self,
-        period: String,
+        window: GraphWindow,
         interface: Option<String>,
         output: Option<String>,
         format: String,
         chart_type: String,
+        no_resolve: bool,
     ) -> Result<()> {
-        let (start_time, end_time) = self.parse_period(&period)?;
         
         let output_path = output.unwrap_or_else(|| {
             let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
@@ -243,16 +332,48 @@ impl GraphCommandHandler {
             },
             x_label: "Time".to_string(),
             y_label: "Connections".to_string(),
+            units: self.units,
         };
 
-        let mut graph = ConnectionGraph::new(config);
-        graph.load_data(&self.db, start_time, end_time, interface.clone()).await?;
+        let mut graph = ConnectionGraph::new(config).with_tags(self.db.tag_book());
+        graph.services = self.services.clone();
+        graph.load_data(&self.db, &window, interface.clone()).await?;
 
         if graph.data.is_empty() {
             println!("No connection data found for the specified period.");
             return Ok(());
         }
 
+        // Of the images only the flow diagram shows addresses, so the others skip the lookups
+        let image_format = ImageFormat::parse(&format);
+        if let Some(resolver) = &self.resolver
+            && !no_resolve
+            && (image_format.is_none() || chart_type == "flows")
+        {
+            let top_connections = graph.get_top_connections(TOP_CONNECTIONS);
+            let flows = graph.get_traffic_flows(FLOW_LINKS);
+            let addresses = top_connections
+                .iter()
+                .flat_map(|c| [c.source_ip.as_str(), c.dest_ip.as_str()])
+                .chain(flows.iter().map(|f| f.remote_ip.as_str()));
+            graph.host_names = resolve_host_names(resolver, addresses).await;
+        }
+
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            let rendered = match chart_type.as_str() {
+                "timeline" => graph.render_connection_timeline_terminal(&canvas),
+                "ports" => graph.render_port_distribution_terminal(&canvas),
+                "traffic" => graph.render_traffic_flow_terminal(&canvas),
+                "flows" => graph.render_traffic_flows_terminal(&canvas),
+                _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+            };
+            print!("{rendered}");
+            print!("{}", graph.render_top_connections_terminal(TOP_CONNECTIONS));
+            return Ok(());
+        }
+
         let export_format = self.parse_export_format(&format)?;
         let export_config = ExportConfig {
             format: export_format,
@@ -265,22 +386,29 @@ impl GraphCommandHandler {
 
         match chart_type.as_str() {
             "timeline" => {
-                if format == "png" {
-                    graph.render_connection_timeline(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_connection_timeline(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_connection_data(&graph)?;
                 }
             }
             "ports" => {
-                if format == "png" {
-                    graph.render_port_distribution(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_port_distribution(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_connection_data(&graph)?;
                 }
             }
             "traffic" => {
-                if format == "png" {
-                    graph.render_traffic_flow(std::path::Path::new(&output_path))?;
+                if let Some(image_format) = image_format {
+                    graph.render_traffic_flow(std::path::Path::new(&output_path), image_format)?;
+                } else {
+                    export_manager.export_connection_data(&graph)?;
+                }
+            }
+            "flows" => {
+                if let Some(image_format) = image_format {
+                    graph.render_traffic_flows(std::path::Path::new(&output_path), image_format)?;
                 } else {
                     export_manager.export_connection_data(&graph)?;
                 }
@@ -290,7 +418,7 @@ impl GraphCommandHandler {
             }
         }
 
-        if format == "png" {
+        if image_format.is_some() {
             println!("Connection {chart_type} chart saved to: {output_path}");
         } else {
             println!("Connection data exported to: {output_path}");
@@ -299,33 +427,287 @@ impl GraphCommandHandler {
         Ok(())
     }
 
-    fn parse_period(&self, period: &str) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
-        let now = Utc::now();
-        let duration = self.parse_duration(period)?;
-        let start_time = now - duration;
-        Ok((start_time, now))
+    async fn handle_latency_graph(
+        &self,
+        window: GraphWindow,
+        interface: Option<String>,
+        output: Option<String>,
+        format: String,
+        chart_type: String,
+    ) -> Result<()> {
+
+        let output_path = output.unwrap_or_else(|| {
+            let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+            match interface.as_ref() {
+                Some(iface) => format!("latency_{iface}_{timestamp}.{format}"),
+                None => format!("latency_all_{timestamp}.{format}"),
+            }
+        });
+
+        let config = GraphConfig {
+            width: 1200,
+            height: 800,
+            title: match interface.as_ref() {
+                Some(iface) => format!("Round-Trip Time - {iface}"),
+                None => "Round-Trip Time".to_string(),
+            },
+            x_label: "Time".to_string(),
+            y_label: "RTT (ms)".to_string(),
+            units: self.units,
+        };
+
+        let mut graph = LatencyGraph::new(config).with_tags(self.db.tag_book());
+        graph.load_data(&self.db, &window, interface.clone()).await?;
+
+        if graph.data.is_empty() {
+            println!("No latency data found for the specified period.");
+            return Ok(());
+        }
+
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            let rendered = match chart_type.as_str() {
+                "timeline" => graph.render_timeline_terminal(&canvas),
+                "percentiles" => graph.render_percentile_terminal(&canvas),
+                _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+            };
+            print!("{rendered}");
+            return Ok(());
+        }
+
+        let image_format = ImageFormat::parse(&format);
+        let export_manager = ExportManager::new(ExportConfig {
+            format: self.parse_export_format(&format)?,
+            output_path: output_path.clone(),
+            include_raw_data: true,
+            compress: false,
+        });
+
+        match (chart_type.as_str(), image_format) {
+            ("timeline", Some(image_format)) => {
+                graph.render_timeline_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("percentiles", Some(image_format)) => {
+                graph.render_percentile_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("timeline" | "percentiles", None) => export_manager.export_latency_data(&graph)?,
+            _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+        }
+
+        if image_format.is_some() {
+            println!("Latency {chart_type} chart saved to: {output_path}");
+        } else {
+            println!("Latency data exported to: {output_path}");
+        }
+
+        Ok(())
     }
 
-    fn parse_duration(&self, period: &str) -> Result<Duration> {
-        let period = period.to_lowercase();
-        
-        if period.ends_with('s') {
-            let seconds: i64 = period[..period.len()-1].parse()?;
-            Ok(Duration::seconds(seconds))
-        } else if period.ends_with('m') {
-            let minutes: i64 = period[..period.len()-1].parse()?;
-            Ok(Duration::minutes(minutes))
-        } else if period.ends_with('h') {
-            let hours: i64 = period[..period.len()-1].parse()?;
-            Ok(Duration::hours(hours))
-        } else if period.ends_with('d') {
-            let days: i64 = period[..period.len()-1].parse()?;
-            Ok(Duration::days(days))
+    async fn handle_packet_size_graph(
+        &self,
+        window: GraphWindow,
+        interface: Option<String>,
+        output: Option<String>,
+        format: String,
+        chart_type: String,
+    ) -> Result<()> {
+
+        let output_path = output.unwrap_or_else(|| {
+            let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+            match interface.as_ref() {
+                Some(iface) => format!("packet_sizes_{iface}_{timestamp}.{format}"),
+                None => format!("packet_sizes_all_{timestamp}.{format}"),
+            }
+        });
+
+        let config = GraphConfig {
+            width: 1200,
+            height: 800,
+            title: match interface.as_ref() {
+                Some(iface) => format!("Packet Sizes - {iface}"),
+                None => "Packet Sizes".to_string(),
+            },
+            x_label: "Time".to_string(),
+            y_label: "Packets".to_string(),
+            units: self.units,
+        };
+
+        let mut graph = PacketSizeGraph::new(config).with_tags(self.db.tag_book());
+        graph.load_data(&self.db, &window, interface.clone()).await?;
+
+        if graph.data.is_empty() {
+            println!("No packet size data found for the specified period.");
+            return Ok(());
+        }
+
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            let rendered = match chart_type.as_str() {
+                "histogram" => graph.render_histogram_terminal(&canvas),
+                "timeline" => graph.render_timeline_terminal(&canvas),
+                _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+            };
+            print!("{rendered}");
+            return Ok(());
+        }
+
+        let image_format = ImageFormat::parse(&format);
+        let export_manager = ExportManager::new(ExportConfig {
+            format: self.parse_export_format(&format)?,
+            output_path: output_path.clone(),
+            include_raw_data: true,
+            compress: false,
+        });
+
+        match (chart_type.as_str(), image_format) {
+            ("histogram", Some(image_format)) => {
+                graph.render_histogram_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("timeline", Some(image_format)) => {
+                graph.render_timeline_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("histogram" | "timeline", None) => export_manager.export_packet_size_data(&graph)?,
+            _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+        }
+
+        if image_format.is_some() {
+            println!("Packet size {chart_type} chart saved to: {output_path}");
         } else {
-            // Default to hours if no unit specified
-            let hours: i64 = period.parse()?;
-            Ok(Duration::hours(hours))
+            println!("Packet size data exported to: {output_path}");
         }
+
+        Ok(())
+    }
+
+    async fn handle_speedtest_graph(
+        &self,
+        window: GraphWindow,
+        output: Option<String>,
+        format: String,
+        chart_type: String,
+    ) -> Result<()> {
+
+        let output_path = output.unwrap_or_else(|| {
+            let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+            format!("speedtest_{timestamp}.{format}")
+        });
+
+        let config = GraphConfig {
+            width: 1200,
+            height: 800,
+            title: "Speed Test Results".to_string(),
+            x_label: "Time".to_string(),
+            y_label: "Speed".to_string(),
+            units: self.units,
+        };
+
+        let mut graph = SpeedTestGraph::new(config);
+        graph.load_data(&self.db, &window).await?;
+
+        if graph.data.is_empty() {
+            println!("No speed test results found for the specified period; run `kw speedtest` first.");
+            return Ok(());
+        }
+
+        // Terminal formats print straight to stdout instead of writing a file
+        if let Some(style) = TerminalStyle::parse(&format) {
+            let canvas = TerminalCanvas::for_terminal(style);
+            let rendered = match chart_type.as_str() {
+                "speed" => graph.render_speed_terminal(&canvas),
+                "latency" => graph.render_latency_terminal(&canvas),
+                _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+            };
+            print!("{rendered}");
+            return Ok(());
+        }
+
+        let image_format = ImageFormat::parse(&format);
+        let export_manager = ExportManager::new(ExportConfig {
+            format: self.parse_export_format(&format)?,
+            output_path: output_path.clone(),
+            include_raw_data: true,
+            compress: false,
+        });
+
+        match (chart_type.as_str(), image_format) {
+            ("speed", Some(image_format)) => {
+                graph.render_speed_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("latency", Some(image_format)) => {
+                graph.render_latency_chart(std::path::Path::new(&output_path), image_format)?;
+            }
+            ("speed" | "latency", None) => export_manager.export_speed_test_data(&graph)?,
+            _ => return Err(anyhow::anyhow!("Invalid chart type: {}", chart_type)),
+        }
+
+        if image_format.is_some() {
+            println!("Speed test {chart_type} chart saved to: {output_path}");
+        } else {
+            println!("Speed test data exported to: {output_path}");
+        }
+
+        Ok(())
+    }
+
+    async fn handle_dependency_graph(
+        &self,
+        window: GraphWindow,
+        output: Option<String>,
+        format: String,
+        limit: usize,
+    ) -> Result<()> {
+
+        let config = GraphConfig {
+            width: 1200,
+            height: 800,
+            title: format!("Service Dependencies - {}", window.label),
+            x_label: String::new(),
+            y_label: String::new(),
+            units: self.units,
+        };
+
+        let mut graph = DependencyGraph::new(config)
+            .with_tags(self.db.tag_book())
+            .with_services(self.services.clone());
+        graph.load_data(&self.db, &window, limit).await?;
+
+        if graph.edges.is_empty() {
+            println!("No connection data found for the specified period.");
+            return Ok(());
+        }
+
+        if TerminalStyle::parse(&format).is_some() {
+            print!("{}", graph.render_terminal());
+            return Ok(());
+        }
+
+        let dependency_format = DependencyFormat::parse(&format)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported dependency map format: {}", format))?;
+        let output_path = output.unwrap_or_else(|| {
+            let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+            format!("dependencies_{timestamp}.{}", dependency_format.extension())
+        });
+
+        graph.render(std::path::Path::new(&output_path), dependency_format)?;
+        println!(
+            "Dependency map with {} hosts/services and {} links saved to: {output_path}",
+            graph.nodes.len(),
+            graph.edges.len()
+        );
+
+        Ok(())
+    }
+
+    fn resolve_window(&self, period: &str, range: &GraphRange) -> Result<GraphWindow> {
+        GraphWindow::resolve(
+            period,
+            range.from.as_deref(),
+            range.to.as_deref(),
+            range.resolution.as_deref(),
+            Utc::now(),
+        )
     }
 
     fn parse_export_format(&self, format: &str) -> Result<ExportFormat> {
@@ -338,4 +720,4 @@ impl GraphCommandHandler {
             _ => Err(anyhow::anyhow!("Unsupported export format: {}", format)),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,84 @@
+// CLI Import Commands: usage history recorded by vnStat or another tool
+// Imported totals are stored as bandwidth samples, so reports and forecasts cover the time
+// before kaipo-watcher was installed; entries overlapping stored samples are skipped
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::display::Units;
+use crate::importers::{ImportFormat, UsageRecord};
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw import`
+pub struct ImportCommandHandler {
+    storage: Arc<PacketStorage>,
+    units: Units,
+}
+
+impl ImportCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage, units: Units::default() }
+    }
+
+    /// Number separators of the summary
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub async fn handle_import_command(&self, file: &Path, format: ImportFormat, interfaces: &[String]) -> Result<()> {
+        let content = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let default_interface = match interfaces {
+            [interface] => Some(interface.as_str()),
+            _ => None,
+        };
+        let records = format.read(&content, default_interface).with_context(|| format!("Failed to import {}", file.display()))?;
+
+        let now = Local::now();
+        let (finished, unfinished): (Vec<UsageRecord>, Vec<UsageRecord>) = records
+            .into_iter()
+            .filter(|record| interfaces.is_empty() || interfaces.contains(&record.interface))
+            .partition(|record| record.end <= now);
+        if finished.is_empty() {
+            println!("Nothing to import from {}", file.display());
+            if !unfinished.is_empty() {
+                println!("   {} entries are for the current hour, day or month, which is still growing", unfinished.len());
+            }
+            return Ok(());
+        }
+
+        let samples: Vec<_> = finished.iter().map(UsageRecord::to_sample).collect();
+        let stored = self.storage.import_bandwidth_samples(&samples)?;
+
+        println!("📥 Imported {stored} of {} entries from {}", finished.len(), file.display());
+        // (first start, last end, entries, bytes) per interface
+        let mut summary: BTreeMap<&str, (_, _, usize, f64)> = BTreeMap::new();
+        for record in &finished {
+            let entry = summary.entry(&record.interface).or_insert((record.start, record.end, 0, 0.0));
+            entry.0 = entry.0.min(record.start);
+            entry.1 = entry.1.max(record.end);
+            entry.2 += 1;
+            entry.3 += record.rx_bytes + record.tx_bytes;
+        }
+        for (interface, (start, end, entries, bytes)) in summary {
+            println!(
+                "   {:<16} {} → {}  {:>10}  ({} entries)",
+                interface,
+                start.format("%Y-%m-%d"),
+                end.format("%Y-%m-%d"),
+                self.units.bytes(bytes),
+                entries
+            );
+        }
+        if stored < finished.len() {
+            println!("   Skipped {} entries overlapping samples already stored for their interface", finished.len() - stored);
+        }
+        if !unfinished.is_empty() {
+            println!("   Skipped {} entries for the current, unfinished hour, day or month", unfinished.len());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+// CLI Interface Picker: Lets the user choose the capture interface when none is given
+// Samples interface traffic briefly, lists the interfaces most relevant first with their type,
+// InterfaceRelevance score and rate, and reads the choice from the terminal
+
+use anyhow::Result;
+use log::warn;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::time::Duration;
+
+use crate::collectors::bandwidth_collector::BandwidthStats;
+use crate::collectors::platform::interface_manager::InterfaceManager;
+use crate::collectors::BandwidthCollector;
+
+/// How long interface counters are sampled before listing or picking an interface
+const INTERFACE_SAMPLE_TIME: Duration = Duration::from_secs(1);
+
+/// Interface name that captures on every interface
+pub const ALL_INTERFACES: &str = "any";
+
+/// One row of the picker
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceChoice {
+    pub name: String,
+    pub kind: &'static str,
+    pub score: u8,
+    pub reason: String,
+    /// Download plus upload during the sample, bytes per second
+    pub rate_bps: f64,
+}
+
+/// Reads the default interfaces twice, `INTERFACE_SAMPLE_TIME` apart, so the second reading has rates
+pub async fn sample_interfaces() -> Result<Vec<BandwidthStats>> {
+    let mut collector = BandwidthCollector::new();
+    collector.collect_default()?;
+    tokio::time::sleep(INTERFACE_SAMPLE_TIME).await;
+    collector.collect_default()
+}
+
+/// Whether someone can answer a prompt: stdin and stdout are both terminals
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// The sampled interfaces with their type and relevance, most relevant first and busier first on a tie
+pub fn choices(stats: &[BandwidthStats]) -> Vec<InterfaceChoice> {
+    let mut manager = InterfaceManager::new();
+    let mut choices: Vec<InterfaceChoice> = stats
+        .iter()
+        .map(|stats| {
+            let info = manager.analyze_interface(&stats.interface_name);
+            InterfaceChoice {
+                name: stats.interface_name.clone(),
+                kind: info.interface_type.label(),
+                score: info.relevance.score,
+                reason: info.relevance.reason,
+                rate_bps: stats.total_bandwidth_bps(),
+            }
+        })
+        .collect();
+    choices.sort_by(|a, b| b.score.cmp(&a.score).then(b.rate_bps.total_cmp(&a.rate_bps)));
+    choices
+}
+
+/// Lists `choices` and asks for one; Enter (or the end of input) picks `default`,
+/// an interface name or `ALL_INTERFACES`
+pub fn prompt(choices: &[InterfaceChoice], default: &str) -> Result<String> {
+    let marker = |name: &str| if name == default { "*" } else { " " };
+    println!("🔌 Pick the interface to capture on:");
+    println!("     {:>2}  {:<16} {:<13} {:>5}  {:>11}  Why", "#", "Interface", "Type", "Score", "Traffic");
+    for (index, choice) in choices.iter().enumerate() {
+        println!(
+            "   {} {:>2}  {:<16} {:<13} {:>5}  {:>11}  {}",
+            marker(&choice.name),
+            index + 1,
+            choice.name,
+            choice.kind,
+            choice.score,
+            BandwidthStats::format_speed(choice.rate_bps),
+            choice.reason
+        );
+    }
+    println!("   {}  a  all interfaces ({ALL_INTERFACES})", marker(ALL_INTERFACES));
+
+    loop {
+        print!("Interface [1-{}, a for all, Enter for {default}]: ", choices.len());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            println!();
+            return Ok(default.to_string());
+        }
+        match parse_selection(&answer, choices, default) {
+            Some(name) => return Ok(name),
+            None => println!("   Enter a number from 1 to {}, an interface name or a", choices.len()),
+        }
+    }
+}
+
+/// For `kw live --packets` and `kw top` without `--interface`: asks which interface to capture on when there is
+/// more than one, `None` meaning all of them as before
+pub async fn pick_live_interface() -> Option<String> {
+    println!("🔎 No --interface given, sampling traffic on the interfaces...");
+    let stats = match sample_interfaces().await {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("Could not sample interface traffic, capturing on all interfaces: {e}");
+            return None;
+        }
+    };
+    if stats.len() < 2 {
+        return None;
+    }
+    match prompt(&choices(&stats), ALL_INTERFACES) {
+        Ok(choice) if choice != ALL_INTERFACES => Some(choice),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Interface prompt failed, capturing on all interfaces: {e}");
+            None
+        }
+    }
+}
+
+/// Resolves an answer to an interface name: a list number, a listed name, or "a"/"all"/"any";
+/// an empty answer is `default`, anything else `None`
+fn parse_selection(answer: &str, choices: &[InterfaceChoice], default: &str) -> Option<String> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Some(default.to_string());
+    }
+    if matches!(answer.to_lowercase().as_str(), "a" | "all" | ALL_INTERFACES) {
+        return Some(ALL_INTERFACES.to_string());
+    }
+    if let Ok(number) = answer.parse::<usize>() {
+        return number.checked_sub(1).and_then(|index| choices.get(index)).map(|choice| choice.name.clone());
+    }
+    choices.iter().find(|choice| choice.name == answer).map(|choice| choice.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choice(name: &str) -> InterfaceChoice {
+        InterfaceChoice {
+            name: name.to_string(),
+            kind: "Ethernet",
+            score: 90,
+            reason: "Physical ethernet connection".to_string(),
+            rate_bps: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let choices = [choice("enp3s0f0"), choice("wlp2s0")];
+        assert_eq!(parse_selection("2\n", &choices, "enp3s0f0").as_deref(), Some("wlp2s0"));
+        assert_eq!(parse_selection("\n", &choices, "enp3s0f0").as_deref(), Some("enp3s0f0"));
+        assert_eq!(parse_selection("wlp2s0", &choices, "any").as_deref(), Some("wlp2s0"));
+        assert_eq!(parse_selection("A", &choices, "enp3s0f0").as_deref(), Some(ALL_INTERFACES));
+        assert_eq!(parse_selection("0", &choices, "any"), None);
+        assert_eq!(parse_selection("3", &choices, "any"), None);
+        assert_eq!(parse_selection("eth9", &choices, "any"), None);
+    }
+}
@@ -0,0 +1,77 @@
+// CLI Maintain Commands: Applies the retention policy on demand
+// Old samples are rolled up and old records deleted as `[retention]` says, the same
+// policy the daemon applies periodically, then the database file is compacted
+
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::config::RetentionConfig;
+use crate::display::Units;
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw maintain`
+pub struct MaintainCommandHandler {
+    storage: Arc<PacketStorage>,
+    retention: RetentionConfig,
+    units: Units,
+}
+
+impl MaintainCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self {
+            storage,
+            retention: RetentionConfig::default(),
+            units: Units::default(),
+        }
+    }
+
+    /// Ages the policy applies
+    pub fn with_retention_config(mut self, config: RetentionConfig) -> Self {
+        self.retention = config;
+        self
+    }
+
+    /// Number separators of the summary
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub async fn handle_maintain_command(&self, no_vacuum: bool) -> Result<()> {
+        let retention = &self.retention;
+        let units = &self.units;
+        let days = |days: u32| if days == 0 { "kept".to_string() } else { format!("{days} days") };
+
+        println!("🧹 Database Maintenance");
+        println!(
+            "   Policy: raw {} | hourly rollups {} | daily rollups {}",
+            days(retention.raw_days),
+            days(retention.hourly_days),
+            days(retention.daily_days)
+        );
+        println!();
+
+        self.storage.flush_all()?;
+        let report = self.storage.apply_retention(retention)?;
+        println!("   Samples rolled into hours: {}", units.number(report.rolled_into_hours as f64, 0));
+        println!("   Hours rolled into days:    {}", units.number(report.rolled_into_days as f64, 0));
+        if report.deleted.is_empty() {
+            println!("   Deleted:                   nothing");
+        } else {
+            println!("   Deleted:");
+            for (table, rows) in &report.deleted {
+                println!("     {:<22} {:>10} rows", table, units.number(*rows as f64, 0));
+            }
+        }
+
+        if !no_vacuum {
+            let (before, after) = self.storage.compact()?;
+            println!(
+                "   Compacted:                 {} → {}",
+                units.bytes(before as f64),
+                units.bytes(after as f64)
+            );
+        }
+        Ok(())
+    }
+}
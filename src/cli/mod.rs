@@ -1,7 +1,79 @@
+// Built without the `cli` feature too, for the period parser the web UI and graphs share;
+// everything else needs clap and is left out of the library then
+
+#[cfg(feature = "cli")]
+pub mod audit_commands;
+#[cfg(feature = "cli")]
+pub mod capture_stats_commands;
+#[cfg(feature = "cli")]
 pub mod commands;
+#[cfg(feature = "cli")]
+pub mod doctor_commands;
+#[cfg(feature = "cli")]
+pub mod encryption_commands;
+#[cfg(feature = "cli")]
+pub mod export_commands;
+#[cfg(feature = "cli")]
+pub mod import_commands;
+#[cfg(feature = "cli")]
+pub mod interface_picker;
+#[cfg(all(feature = "cli", feature = "capture"))]
 pub mod packet_commands;
+#[cfg(all(feature = "cli", feature = "graphs"))]
 pub mod graph_commands;
+#[cfg(feature = "cli")]
+pub mod maintain_commands;
+pub mod period;
+#[cfg(feature = "cli")]
+pub mod purge_commands;
+#[cfg(feature = "cli")]
+pub mod report_commands;
+#[cfg(all(feature = "cli", feature = "capture"))]
+pub mod service_commands;
+#[cfg(feature = "cli")]
+pub mod shaping_commands;
+#[cfg(feature = "cli")]
+pub mod speedtest_commands;
+#[cfg(feature = "cli")]
+pub mod stats_commands;
+#[cfg(feature = "cli")]
+pub mod tag_commands;
+#[cfg(feature = "cli")]
+pub mod vnstat_style;
 
+#[cfg(feature = "cli")]
+pub use audit_commands::AuditCommandHandler;
+#[cfg(feature = "cli")]
+pub use capture_stats_commands::CaptureStatsCommandHandler;
+#[cfg(feature = "cli")]
 pub use commands::Cli;
+#[cfg(feature = "cli")]
+pub use doctor_commands::DoctorCommandHandler;
+#[cfg(feature = "cli")]
+pub use encryption_commands::EncryptionCommandHandler;
+#[cfg(feature = "cli")]
+pub use export_commands::ExportCommandHandler;
+#[cfg(feature = "cli")]
+pub use import_commands::ImportCommandHandler;
+#[cfg(all(feature = "cli", feature = "capture"))]
 pub use packet_commands::PacketCommandHandler;
-pub use graph_commands::GraphCommandHandler;
\ No newline at end of file
+#[cfg(all(feature = "cli", feature = "graphs"))]
+pub use graph_commands::GraphCommandHandler;
+#[cfg(feature = "cli")]
+pub use maintain_commands::MaintainCommandHandler;
+#[cfg(feature = "cli")]
+pub use purge_commands::PurgeCommandHandler;
+#[cfg(feature = "cli")]
+pub use report_commands::{ReportCommandHandler, ReportComparison};
+#[cfg(all(feature = "cli", feature = "capture"))]
+pub use service_commands::ServiceCommandHandler;
+#[cfg(feature = "cli")]
+pub use shaping_commands::ShapingCommandHandler;
+#[cfg(feature = "cli")]
+pub use speedtest_commands::SpeedTestCommandHandler;
+#[cfg(feature = "cli")]
+pub use stats_commands::StatsCommandHandler;
+#[cfg(feature = "cli")]
+pub use tag_commands::TagCommandHandler;
+#[cfg(feature = "cli")]
+pub use vnstat_style::OutputStyle;
\ No newline at end of file
@@ -2,17 +2,74 @@
 // Provides command-line handlers for real-time packet capture and traffic analysis
 // Implements interactive displays with live statistics and filtering options
 
-use crate::analyzers::{AnalysisResult, ProtocolAnalyzer, TrafficType};
+use crate::analyzers::classification::{self, ClassificationSample};
+use crate::analyzers::discovery::DiscoveredDevice;
+use crate::analyzers::duplicates::{self, DuplicateFinding, DuplicateSample};
+use crate::analyzers::fragmentation::{self, MtuSample, PMTUD_STUCK_REFUSALS};
+use crate::analyzers::entropy::HIGH_ENTROPY_THRESHOLD;
+use crate::analyzers::threat_detector::DETECTION_EVENT_TYPES;
+use crate::analyzers::throttling::{self, ThrottledFlow, FILLED_LINK_SHARE};
+use crate::analyzers::{
+    AnalysisResult, LatencyStats, LossStats, ProtocolAnalyzer, TcpSummary,
+};
+use crate::cli::interface_picker::{self, ALL_INTERFACES};
+use crate::cli::period::{self, Period};
+use crate::cli::speedtest_commands::format_mbit;
+use crate::collectors::capture_filter::CaptureFilter;
+use crate::collectors::capture_stats::CaptureMonitor;
+use crate::collectors::interface_selection::InterfaceSelection;
+use crate::collectors::bandwidth_collector::BandwidthStats;
+use crate::collectors::platform::CaptureBackend;
+use crate::collectors::packet_sampling::PacketSampler;
 use crate::collectors::PacketCollector;
+use crate::collectors::pcap_file::PcapFileReader;
+use crate::collectors::ping_collector::{self, PingSample};
+use crate::collectors::socket_stats::{self, SocketStatsCollector};
+use crate::config::{ResourceProfile, SecurityConfig};
+use crate::exporters::security_report::{SecurityReport, SecurityReportFormat};
+use crate::enrichment::{resolve_host_names, BackgroundLookups, CachingResolver, Resolver};
+use crate::models::packet_dump::{hex_dump, InspectBuffer, InspectedPacket, INSPECT_BYTES, PRIVACY_WARNING};
+use crate::models::{
+    NetworkPacket, PacketDirection, PacketProtocol, PacketSizeDistribution, PacketSizeHistogram, ServiceMap, TagBook,
+    TransportProtocol, PACKET_SIZE_BUCKETS, SMALL_PACKET_OVERLOAD_SHARE,
+};
+use crate::storage::packet_storage::{ApplicationUsage, TrafficSummary};
+use crate::shutdown::{self, SessionSummary};
 use crate::storage::PacketStorage;
 use anyhow::{Context, Result};
 use chrono::Local;
-use log::error;
+use serde_json::json;
+use log::{error, info, warn};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration as StdDuration;
+use std::time::{Duration as StdDuration, Instant};
 use tokio::sync::Mutex;
-use tokio::time::{interval, timeout};
+use tokio::time::interval;
+
+/// Connections listed as evidence for each exported security event
+const EVIDENCE_CONNECTIONS: usize = 5;
+
+/// How far from an exported event's time its evidence connections may have been active
+const EVIDENCE_SLACK_SECS: i64 = 60;
+
+/// How often `kw packets` records a capture performance sample
+const CAPTURE_STATS_INTERVAL: StdDuration = StdDuration::from_secs(10);
+
+/// Transport, source address and port, destination address and port of a connection in `kw packets`
+type ConnectionKey = (TransportProtocol, IpAddr, Option<u16>, IpAddr, Option<u16>);
+
+/// What a `kw packets` capture saw before it stopped
+struct CaptureTotals {
+    /// Stopped by Ctrl+C or SIGTERM rather than at the end of `--capture`
+    interrupted: bool,
+    packets: u64,
+    bytes: u64,
+    connections: usize,
+    protocols: usize,
+}
 
 /// Command handler for packet monitoring and analysis operations
 /// 
@@ -28,120 +85,364 @@ use tokio::time::{interval, timeout};
 /// 
 /// # Example
 /// 
-/// ```rust
+/// ```no_run
+/// use kaipo_watcher::cli::PacketCommandHandler;
+/// use kaipo_watcher::{InterfaceSelection, PacketStorage};
+/// use std::sync::Arc;
+///
+/// # async fn run() -> anyhow::Result<()> {
 /// let storage = Arc::new(PacketStorage::new("./data/packets.db", 100)?);
 /// let handler = PacketCommandHandler::new(storage);
 /// 
 /// // Start real-time monitoring
 /// handler.handle_packets_command(
-///     Some("eth0".to_string()),
+///     InterfaceSelection::parse("eth0"),
 ///     Some("tcp".to_string()),
 ///     Some("60s".to_string()),
 ///     true,
-///     10
+///     10,
+///     false,
 /// ).await?;
+/// # Ok(())
+/// # }
 /// ```
 pub struct PacketCommandHandler {
     storage: Arc<PacketStorage>,
     analyzer: Arc<Mutex<ProtocolAnalyzer>>,
+    resources: ResourceProfile,
+    /// Port scan, SYN flood and ICMP sweep thresholds
+    security: SecurityConfig,
+    /// Reverse DNS used to show host names next to connection addresses
+    resolver: Option<Arc<CachingResolver>>,
+    /// User tags shown next to addresses and interface names
+    tags: TagBook,
+    /// Service names shown after ports, e.g. "443/https"
+    services: ServiceMap,
+    /// tcpdump-style filter applied at capture time
+    capture_filter: Option<CaptureFilter>,
+    /// Kernel interface packets are captured through
+    capture_backend: CaptureBackend,
+    /// Analyzes one packet in N, standing for N, while traffic is over a threshold
+    sampler: Option<PacketSampler>,
+    /// File `kw analyze --security` exports the period's security events to
+    security_export: Option<(PathBuf, SecurityReportFormat)>,
+    /// Socket of a privileged capture helper, when capture is delegated to one
+    #[cfg(unix)]
+    capture_helper: Option<PathBuf>,
+    /// Latest packets kept with their leading bytes for `--inspect`
+    inspector: Option<std::sync::Mutex<InspectBuffer>>,
 }
 
 impl PacketCommandHandler {
     pub fn new(storage: Arc<PacketStorage>) -> Self {
+        let tags = storage.tag_book().unwrap_or_else(|e| {
+            warn!("Failed to load tags: {e}");
+            TagBook::default()
+        });
         Self {
             storage,
             analyzer: Arc::new(Mutex::new(ProtocolAnalyzer::new())),
+            resources: ResourceProfile::standard(),
+            security: SecurityConfig::default(),
+            resolver: None,
+            tags,
+            services: ServiceMap::new(),
+            capture_filter: None,
+            capture_backend: CaptureBackend::default(),
+            sampler: None,
+            security_export: None,
+            #[cfg(unix)]
+            capture_helper: None,
+            inspector: None,
         }
     }
 
+    /// Applies buffer sizes and the analyzer switch from the resource profile
+    pub fn with_resource_profile(mut self, resources: ResourceProfile) -> Self {
+        self.resources = resources;
+        self.rebuild_analyzer();
+        self
+    }
+
+    /// Detection thresholds for port scans, SYN floods and ICMP sweeps
+    pub fn with_security_config(mut self, config: SecurityConfig) -> Self {
+        self.security = config;
+        self.rebuild_analyzer();
+        self
+    }
+
+    fn rebuild_analyzer(&mut self) {
+        self.analyzer = Arc::new(Mutex::new(
+            ProtocolAnalyzer::with_connection_limit(self.resources.tracked_connections)
+                .with_security_config(self.security.clone()),
+        ));
+    }
+
+    /// Resolves connection addresses to host names in `kw packets` and `kw analyze`
+    pub fn with_resolver(mut self, resolver: CachingResolver) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Port names from the built-in table plus the `[services]` overrides
+    pub fn with_services(mut self, services: ServiceMap) -> Self {
+        self.services = services;
+        self
+    }
+
+    /// Captures only packets matching `filter`
+    pub fn with_capture_filter(mut self, filter: CaptureFilter) -> Self {
+        self.capture_filter = Some(filter);
+        self
+    }
+
+    /// Captures through `backend` (AF_PACKET or AF_XDP) on Linux
+    pub fn with_capture_backend(mut self, backend: CaptureBackend) -> Self {
+        self.capture_backend = backend;
+        self
+    }
+
+    /// Samples packets under load in `kw packets` and `kw analyze --from-pcap`
+    pub fn with_sampler(mut self, sampler: PacketSampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Writes the security events of `kw analyze --security` to `path` as a report
+    pub fn with_security_export(mut self, path: PathBuf, format: SecurityReportFormat) -> Self {
+        self.security_export = Some((path, format));
+        self
+    }
+
+    /// Receives packets from the capture helper at `socket_path` instead of capturing directly
+    #[cfg(unix)]
+    pub fn with_capture_helper(mut self, socket_path: PathBuf) -> Self {
+        self.capture_helper = Some(socket_path);
+        self
+    }
+
+    /// Keeps the last `count` packets captured, with up to `INSPECT_BYTES` of each, for a hex dump
+    pub fn with_inspector(mut self, count: usize) -> Self {
+        self.inspector = Some(std::sync::Mutex::new(InspectBuffer::new(count)));
+        self
+    }
+
+    /// Packets kept for `--inspect`, oldest first
+    pub fn take_inspected(&self) -> Vec<InspectedPacket> {
+        self.inspector
+            .as_ref()
+            .map(|buffer| buffer.lock().unwrap_or_else(|e| e.into_inner()).take())
+            .unwrap_or_default()
+    }
+
     pub async fn handle_packets_command(
         &self,
-        interface: Option<String>,
+        interface: Option<InterfaceSelection>,
         protocol_filter: Option<String>,
         capture_duration: Option<String>,
         detailed: bool,
         max_connections: usize,
+        by_service: bool,
     ) -> Result<()> {
         // Note about privileges
-        println!("⚠️  Note: Packet capture requires elevated privileges (sudo/administrator)");
+        #[cfg(unix)]
+        let uses_helper = self.capture_helper.is_some();
+        #[cfg(not(unix))]
+        let uses_helper = false;
+        if uses_helper {
+            println!("🔐 Packet capture runs in a separate privileged helper process");
+        } else {
+            println!("⚠️  Note: Packet capture requires elevated privileges (sudo/administrator)");
+        }
         println!();
 
-        let interface_name = interface.unwrap_or_else(|| "any".to_string());
-        let duration = parse_duration(&capture_duration.unwrap_or_else(|| "60s".to_string()))?;
+        let interface_name = match interface {
+            Some(selection) => selection.to_string(),
+            None => select_interface().await,
+        };
+        let duration = period::parse_span(capture_duration.as_deref().unwrap_or("60s"))
+            .context("Invalid --capture")?
+            .to_std()?;
 
         println!("🔍 Starting packet capture on interface: {interface_name}");
         println!("📊 Capture duration: {duration:?}");
         if let Some(protocol) = &protocol_filter {
             println!("🔧 Protocol filter: {protocol}");
         }
+        if let Some(filter) = &self.capture_filter {
+            println!("🔧 Capture filter: {filter}");
+        }
+        if let Some(sampler) = &self.sampler {
+            println!("📉 Sampling: {} above {} packets/s", sampler.rate(), sampler.threshold_pps());
+        }
+        if let Some(resolver) = &self.resolver {
+            println!("🔎 Resolving host names via {} (--no-resolve to turn off)", resolver.describe());
+        }
+        if self.inspector.is_some() {
+            println!("🔬 Keeping the first {INSPECT_BYTES} bytes of recent packets to inspect when capture stops");
+            println!("⚠️  {PRIVACY_WARNING}");
+            if uses_helper {
+                println!("   The capture helper sends packets already decoded, so their contents cannot be shown");
+            }
+        }
         println!();
 
         // Create packet collector
         let collector = PacketCollector::new(interface_name.clone())
-            .context("Failed to create packet collector")?;
-
-        // Start capture with timeout
-        let capture_result = timeout(duration, self.run_packet_capture(
-            collector,
-            protocol_filter,
-            detailed,
-            max_connections,
-        )).await;
-
-        match capture_result {
-            Ok(Ok(())) => {
-                println!("✅ Packet capture completed successfully");
-            }
-            Ok(Err(e)) => {
-                error!("Packet capture failed: {e}");
-                return Err(e);
-            }
-            Err(_) => {
-                println!("⏰ Capture duration completed");
-            }
+            .context("Failed to create packet collector")?
+            .with_channel_capacity(self.resources.packet_channel_capacity)
+            .with_capture_backend(self.capture_backend)
+            .with_raw_bytes(if self.inspector.is_some() { INSPECT_BYTES } else { 0 });
+        let collector = match &self.capture_filter {
+            Some(filter) => collector.with_filter(filter.clone()),
+            None => collector,
+        };
+        #[cfg(unix)]
+        let collector = match &self.capture_helper {
+            Some(socket_path) => collector.with_capture_helper(socket_path.clone()),
+            None => collector,
+        };
+
+        // Capture until the duration is up or a shutdown is requested; either way what is
+        // buffered is written below
+        let started = Instant::now();
+        let totals = self
+            .run_packet_capture(collector, interface_name.clone(), protocol_filter, detailed, max_connections, by_service, duration)
+            .await
+            .inspect_err(|e| error!("Packet capture failed: {e}"))?;
+        if totals.interrupted {
+            println!("\n🛑 Capture stopped");
+        } else {
+            println!("⏰ Capture duration completed");
         }
 
+        // Connections still open when capture stopped are stored with their last state
+        self.store_tcp_sessions(true).await;
+        self.store_latency_samples().await;
+        self.store_classification_samples(true).await;
+        self.store_duplicate_samples(true).await;
+        self.store_mtu_samples(true).await;
+        self.store_throttled_flows(true).await;
+        self.store_discovered_devices().await;
+        let pending = self.storage.pending_records();
+        self.storage.flush_all().context("Failed to write buffered packet records")?;
+
+        let mut summary = SessionSummary::new(format!("Capture summary for {interface_name}"), started);
+        summary.add("Packets", totals.packets);
+        summary.add("Data", format_bytes(totals.bytes));
+        summary.add("Connections", totals.connections);
+        summary.add("Protocols", totals.protocols);
+        summary.add("Written on exit", format!("{pending} buffered records"));
+        println!("\n{summary}");
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run_packet_capture(
         &self,
         collector: PacketCollector,
+        interface_name: String,
         protocol_filter: Option<String>,
         detailed: bool,
         max_connections: usize,
-    ) -> Result<()> {
+        by_service: bool,
+        duration: StdDuration,
+    ) -> Result<CaptureTotals> {
         // Start the collector
         collector.start().await.context("Failed to start packet collector")?;
+        if collector.uses_kernel_events() {
+            println!("🪟 Npcap is not installed: counting traffic per connection and process from kernel network events");
+            println!("   Packet contents are not available, so protocol detection relies on ports alone");
+        }
 
         // Statistics tracking
         let mut packet_count = 0u64;
         let mut byte_count = 0u64;
         let mut protocol_stats: HashMap<String, u64> = HashMap::new();
-        let mut connection_tracker: HashMap<String, (u64, u64)> = HashMap::new();
+        // (packets, bytes, sampled payload entropy) per connection
+        let mut connection_tracker: HashMap<ConnectionKey, (u64, u64, Option<f64>)> = HashMap::new();
+        // (name, bytes sent, bytes received) by process id, for capture sources that tag packets with one
+        let mut process_tracker: HashMap<u32, (String, u64, u64)> = HashMap::new();
+        // Host names arrive over the following refreshes while the capture carries on
+        let mut host_names = self.resolver.clone().map(BackgroundLookups::new);
+        let mut sampler = self.sampler.clone();
 
         // Display update interval
         let mut display_interval = interval(StdDuration::from_secs(1));
 
+        // Capture performance for `kw capture-stats`
+        let mut capture_stats = CaptureMonitor::new(interface_name);
+        let mut capture_stats_interval = interval(CAPTURE_STATS_INTERVAL);
+        capture_stats_interval.tick().await;
+
+        // Kernel statistics of the host's own sockets, attached to the TCP connections they carry
+        let mut sockets = SocketStatsCollector::new();
+        let mut socket_stats_interval = interval(socket_stats::POLL_INTERVAL);
+
         println!("📡 Capturing packets... (Press Ctrl+C to stop)\n");
+        let deadline = tokio::time::sleep(duration);
+        tokio::pin!(deadline);
+
+        // Decoded into again for every packet, so the capture path allocates nothing per packet
+        let mut packet = NetworkPacket::new(String::new(), 0, PacketProtocol::Ethernet, PacketDirection::Local);
 
-        loop {
+        let interrupted = loop {
             tokio::select! {
+                _ = &mut deadline => break false,
+                _ = shutdown::requested() => break true,
+
                 // Handle display updates
                 _ = display_interval.tick() => {
+                    capture_stats.poll(&collector.get_stats().await, collector.queue_depth(), self.storage.pending_records());
+                    self.store_tcp_sessions(false).await;
+                    self.store_latency_samples().await;
+                    self.store_classification_samples(false).await;
+                    self.store_duplicate_samples(false).await;
+                    self.store_mtu_samples(false).await;
+                    self.store_throttled_flows(false).await;
+                    self.store_discovered_devices().await;
+                    if let Some(host_names) = &mut host_names {
+                        host_names.collect();
+                    }
                     self.display_stats(
                         packet_count,
                         byte_count,
                         &protocol_stats,
                         &connection_tracker,
+                        host_names.as_mut(),
+                        sampler.as_ref(),
                         max_connections,
                         detailed,
                     ).await;
+                    print_process_traffic(&process_tracker, max_connections);
+                }
+
+                _ = capture_stats_interval.tick() => {
+                    let sample = capture_stats.sample(
+                        &collector.get_stats().await,
+                        collector.queue_depth(),
+                        self.storage.pending_records(),
+                    );
+                    if let Err(e) = self.storage.store_capture_sample(&sample) {
+                        warn!("Failed to store capture statistics: {e}");
+                    }
+                }
+
+                _ = socket_stats_interval.tick(), if self.resources.analyzer_enabled => {
+                    let sockets = sockets.collect();
+                    self.analyzer.lock().await.attach_socket_stats(&sockets);
                 }
 
                 // Handle packet reception
-                packet_opt = collector.receive_packet() => {
-                    if let Some(packet) = packet_opt {
+                received = collector.receive_packet_into(&mut packet) => {
+                    if received {
+                        // Over the sample threshold only every Nth packet goes on, standing for N
+                        let weight = match sampler.as_mut().map(|sampler| sampler.weigh(packet.timestamp)) {
+                            Some(None) => continue,
+                            Some(Some(weight)) => weight,
+                            None => 1,
+                        };
+
                         // Apply protocol filter
                         if let Some(ref filter) = protocol_filter {
                             let packet_protocol = format!("{:?}", packet.transport_protocol).to_lowercase();
@@ -150,55 +451,223 @@ impl PacketCommandHandler {
                             }
                         }
 
-                        // Analyze packet
-                        let mut analyzer = self.analyzer.lock().await;
-                        let analysis = if let Ok(analysis) = analyzer.analyze_packet(&packet) {
-                            self.process_packet_analysis(&packet, &analysis)?;
-                            analysis
+                        if let Some(inspector) = &self.inspector {
+                            let summary = self.inspect_summary(&packet);
+                            inspector.lock().unwrap_or_else(|e| e.into_inner()).push(InspectedPacket::new(&packet, summary));
+                        }
+
+                        // Analyze packet (skipped in low-memory mode when the analyzer is disabled,
+                        // in which case only the connection itself is recorded)
+                        let analysis = if self.resources.analyzer_enabled {
+                            let mut analyzer = self.analyzer.lock().await;
+                            if let Ok(analysis) = analyzer.analyze_packet(&packet) {
+                                self.process_packet_analysis(&packet, &analysis, weight)?;
+                                analysis
+                            } else {
+                                AnalysisResult::default()
+                            }
                         } else {
-                            AnalysisResult::default()
+                            let analysis = AnalysisResult::default();
+                            self.process_packet_analysis(&packet, &analysis, weight)?;
+                            analysis
                         };
 
                         // Update statistics
-                        packet_count += 1;
-                        byte_count += packet.size_bytes;
+                        packet_count += weight;
+                        byte_count += packet.size_bytes * weight;
 
                         // Update protocol stats
-                        let protocol_name = analysis_to_protocol_name(&packet, &analysis);
-                        *protocol_stats.entry(protocol_name).or_insert(0) += 1;
+                        let protocol_name = if by_service {
+                            self.service_group(&packet)
+                        } else {
+                            analysis_to_protocol_name(&packet, &analysis)
+                        };
+                        *protocol_stats.entry(protocol_name).or_insert(0) += weight;
 
                         // Update connection tracking
                         if let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) {
-                            let connection_key = format!("{}:{} -> {}:{}",
-                                src, packet.source_port.unwrap_or(0),
-                                dst, packet.dest_port.unwrap_or(0)
-                            );
-                            let entry = connection_tracker.entry(connection_key).or_insert((0, 0));
-                            entry.0 += 1; // packet count
-                            entry.1 += packet.size_bytes; // byte count
+                            let connection_key = (packet.transport_protocol, src, packet.source_port, dst, packet.dest_port);
+                            let entry = connection_tracker.entry(connection_key).or_insert((0, 0, None));
+                            entry.0 += weight; // packet count
+                            entry.1 += packet.size_bytes * weight; // byte count
+                            if analysis.payload_entropy.is_some() {
+                                entry.2 = analysis.payload_entropy;
+                            }
+                        }
+
+                        if let Some(process) = &packet.process {
+                            let (_, sent, received) = process_tracker
+                                .entry(process.pid)
+                                .or_insert_with(|| (process.name.clone(), 0, 0));
+                            match packet.direction {
+                                PacketDirection::Inbound => *received += packet.size_bytes * weight,
+                                _ => *sent += packet.size_bytes * weight,
+                            }
                         }
                     }
                 }
             }
+        };
+
+        if let Err(e) = collector.stop().await {
+            warn!("Failed to stop packet capture cleanly: {e}");
         }
+        Ok(CaptureTotals {
+            interrupted,
+            packets: packet_count,
+            bytes: byte_count,
+            connections: connection_tracker.len(),
+            protocols: protocol_stats.len(),
+        })
     }
 
+    /// Stores a packet and its analysis, counted `weight` times when it stands for sampled-out ones
     fn process_packet_analysis(
         &self,
         packet: &crate::models::NetworkPacket,
         analysis: &AnalysisResult,
+        weight: u64,
     ) -> Result<()> {
         // Store analysis results
-        self.storage.analyze_packet_for_storage(packet, analysis)?;
+        self.storage.analyze_sampled_packet_for_storage(packet, analysis, weight)?;
         Ok(())
     }
 
+    /// Writes finished TCP connections to storage; `all` includes those still open
+    async fn store_tcp_sessions(&self, all: bool) {
+        let sessions = {
+            let mut analyzer = self.analyzer.lock().await;
+            if all { analyzer.drain_tcp_sessions() } else { analyzer.take_finished_tcp_sessions() }
+        };
+        if let Err(e) = self.storage.store_tcp_sessions(&sessions) {
+            warn!("Failed to store TCP sessions: {e}");
+        }
+    }
+
+    /// `ip:port/service`, followed by the label `tags` give the address and its host name when
+    /// it has them
+    fn describe_endpoint(
+        &self,
+        tags: &TagBook,
+        address: IpAddr,
+        port: Option<u16>,
+        transport: TransportProtocol,
+        host_name: Option<&str>,
+    ) -> String {
+        let endpoint = format!("{}:{}", tags.address(address), self.services.label(port.unwrap_or(0), transport));
+        let host_name = host_name.map(|host| tags.redactor().host_name(host));
+        match (tags.label(&address.to_string()), host_name) {
+            (Some(label), Some(host)) => format!("{endpoint} ({label}, {host})"),
+            (Some(label), None) => format!("{endpoint} ({label})"),
+            (None, Some(host)) => format!("{endpoint} ({host})"),
+            (None, None) => endpoint,
+        }
+    }
+
+    /// Transport and endpoints of a packet kept for `--inspect`, or its link-layer protocol
+    /// when it carries no IP
+    fn inspect_summary(&self, packet: &NetworkPacket) -> String {
+        match (packet.source_addr, packet.dest_addr) {
+            (Some(src), Some(dst)) => format!(
+                "{:?} {} → {}",
+                packet.transport_protocol,
+                self.describe_endpoint(&self.tags, src, packet.source_port, packet.transport_protocol, None),
+                self.describe_endpoint(&self.tags, dst, packet.dest_port, packet.transport_protocol, None)
+            ),
+            _ => format!("{:?}", packet.protocol),
+        }
+    }
+
+    /// Protocol distribution key for `--by-service`: the connection's service name, or
+    /// e.g. "tcp/other" when neither port has one
+    fn service_group(&self, packet: &crate::models::NetworkPacket) -> String {
+        match self.services.connection_service(packet.source_port, packet.dest_port, packet.transport_protocol) {
+            Some(service) => service.to_string(),
+            None if packet.source_port.is_some() || packet.dest_port.is_some() => {
+                format!("{:?}/other", packet.transport_protocol).to_lowercase()
+            }
+            None => format!("{:?}", packet.transport_protocol).to_lowercase(),
+        }
+    }
+
+    /// Writes round-trip times measured since the last call to storage
+    async fn store_latency_samples(&self) {
+        let samples = self.analyzer.lock().await.take_latency_samples();
+        if let Err(e) = self.storage.store_latency_samples(&samples) {
+            warn!("Failed to store latency samples: {e}");
+        }
+    }
+
+    /// Writes devices found or renamed by their mDNS and SSDP announcements to storage
+    async fn store_discovered_devices(&self) {
+        let devices = self.analyzer.lock().await.take_discovered_devices();
+        if let Err(e) = self.storage.store_discovered_devices(&devices) {
+            warn!("Failed to store discovered devices: {e}");
+        }
+    }
+
+    /// Tags, with the labels of the devices the analyzer has discovered so far
+    async fn tags_with_devices(&self) -> TagBook {
+        let mut tags = self.tags.clone();
+        for device in self.analyzer.lock().await.discovered_devices() {
+            tags.add_discovered(device.address, device.label());
+        }
+        tags
+    }
+
+    /// Writes finished minutes of classification counts to storage; `all` includes the current ones
+    async fn store_classification_samples(&self, all: bool) {
+        let samples = {
+            let mut analyzer = self.analyzer.lock().await;
+            if all { analyzer.drain_classification_samples() } else { analyzer.take_classification_samples() }
+        };
+        if let Err(e) = self.storage.store_classification_samples(&samples) {
+            warn!("Failed to store classification counts: {e}");
+        }
+    }
+
+    /// Writes finished minutes of duplicate packet counts to storage; `all` includes the current ones
+    async fn store_duplicate_samples(&self, all: bool) {
+        let samples = {
+            let mut analyzer = self.analyzer.lock().await;
+            if all { analyzer.drain_duplicate_samples() } else { analyzer.take_duplicate_samples() }
+        };
+        if let Err(e) = self.storage.store_duplicate_samples(&samples) {
+            warn!("Failed to store duplicate packet counts: {e}");
+        }
+    }
+
+    /// Writes finished minutes of fragmentation counts to storage; `all` includes the current one
+    async fn store_mtu_samples(&self, all: bool) {
+        let samples = {
+            let mut analyzer = self.analyzer.lock().await;
+            if all { analyzer.drain_mtu_samples() } else { analyzer.take_mtu_samples() }
+        };
+        if let Err(e) = self.storage.store_mtu_samples(&samples) {
+            warn!("Failed to store fragmentation counts: {e}");
+        }
+    }
+
+    /// Writes connections flagged as held to a steady rate to storage; `all` judges the open ones too
+    async fn store_throttled_flows(&self, all: bool) {
+        let flows = {
+            let mut analyzer = self.analyzer.lock().await;
+            if all { analyzer.drain_throttled_flows() } else { analyzer.take_throttled_flows() }
+        };
+        if let Err(e) = self.storage.store_throttled_flows(&flows) {
+            warn!("Failed to store throttled connections: {e}");
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn display_stats(
         &self,
         packet_count: u64,
         byte_count: u64,
         protocol_stats: &HashMap<String, u64>,
-        connection_tracker: &HashMap<String, (u64, u64)>,
+        connection_tracker: &HashMap<ConnectionKey, (u64, u64, Option<f64>)>,
+        mut host_names: Option<&mut BackgroundLookups>,
+        sampler: Option<&PacketSampler>,
         max_connections: usize,
         detailed: bool,
     ) {
@@ -209,6 +678,9 @@ impl PacketCommandHandler {
         println!("{}", "═".repeat(50));
         println!("Total Packets: {packet_count}");
         println!("Total Bytes:   {}", format_bytes(byte_count));
+        if let Some(sampling) = sampler.and_then(describe_sampling) {
+            println!("Sampled:       {sampling}");
+        }
         println!();
 
         // Protocol distribution
@@ -224,16 +696,39 @@ impl PacketCommandHandler {
             println!();
         }
 
+        // Devices announced during the capture are named in the connections below
+        let tags = self.tags_with_devices().await;
+
         // Top connections
         if !connection_tracker.is_empty() {
             println!("🌐 Top Connections (by bytes):");
             let mut sorted_connections: Vec<_> = connection_tracker.iter().collect();
             sorted_connections.sort_by(|a, b| b.1.1.cmp(&a.1.1)); // Sort by bytes
+            sorted_connections.truncate(max_connections);
 
-            for (connection, (packets, bytes)) in sorted_connections.iter().take(max_connections) {
+            // Only the listed connections are looked up; their names show from a later refresh on
+            if let Some(host_names) = host_names.as_deref_mut() {
+                for ((_, src, _, dst, _), _) in &sorted_connections {
+                    host_names.request(*src);
+                    host_names.request(*dst);
+                }
+            }
+            let host_names = host_names.as_deref();
+            let name = |address: &IpAddr| host_names.and_then(|names| names.name(address));
+
+            for ((transport, src, src_port, dst, dst_port), (packets, bytes, entropy)) in sorted_connections {
+                let connection = format!(
+                    "{} -> {}",
+                    self.describe_endpoint(&tags, *src, *src_port, *transport, name(src)),
+                    self.describe_endpoint(&tags, *dst, *dst_port, *transport, name(dst))
+                );
                 if detailed {
                     println!("  {connection}");
                     println!("    Packets: {}, Bytes: {}", packets, format_bytes(*bytes));
+                    if let Some(entropy) = entropy {
+                        let marker = if *entropy >= HIGH_ENTROPY_THRESHOLD { " (high)" } else { "" };
+                        println!("    Payload entropy: {entropy:.2} bits/byte{marker}");
+                    }
                 } else {
                     println!("  {} - {}", connection, format_bytes(*bytes));
                 }
@@ -250,37 +745,93 @@ impl PacketCommandHandler {
             );
             println!("  Unique connections: {}", connection_tracker.len());
             println!();
+
+            let analyzer = self.analyzer.lock().await;
+            let devices = analyzer.discovered_devices();
+            if !devices.is_empty() {
+                print_discovered_devices(&devices, &self.tags);
+                println!();
+            }
+            drop(analyzer);
+
+            if self.resources.analyzer_enabled {
+                let analyzer = self.analyzer.lock().await;
+                let summary = analyzer.tcp_summary();
+                if summary != TcpSummary::default() {
+                    print_tcp_summary(&summary);
+                    print_interface_loss(&analyzer.tcp_loss_by_interface(), &self.tags);
+                    for session in analyzer.top_tcp_sessions(max_connections) {
+                        let handshake = session
+                            .handshake_time()
+                            .map(|t| format!(", handshake {:.2}ms", t.num_microseconds().unwrap_or(0) as f64 / 1000.0))
+                            .unwrap_or_default();
+                        println!(
+                            "  {} -> {}",
+                            self.tags.describe_endpoint(&session.client.to_string()),
+                            self.tags.describe_endpoint(&session.server.to_string())
+                        );
+                        println!(
+                            "    {} for {:.1}s{}, {}",
+                            session.state,
+                            session.duration().num_milliseconds() as f64 / 1000.0,
+                            handshake,
+                            describe_loss(&session.loss)
+                        );
+                        if let Some(kernel) = &session.kernel {
+                            println!("    kernel: {}", kernel.describe());
+                        }
+                    }
+                    println!();
+                }
+            }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle_analyze_command(
         &self,
         period: String,
         interface: Option<String>,
         security: bool,
         protocols: bool,
+        diagnostics: bool,
+        limit: usize,
+        format: &str,
     ) -> Result<()> {
-        let duration = parse_duration(&period)
-            .context("Failed to parse analysis period")?;
-        
-        let since = Local::now() - chrono::Duration::from_std(duration)
-            .map_err(|_| anyhow::anyhow!("Invalid duration for analysis"))?;
+        let analyzed = Period::parse(&period, Local::now()).context("Failed to parse analysis period")?;
+        let (since, until) = (analyzed.start, analyzed.end);
 
         let interface_name = interface.unwrap_or_else(|| "all".to_string());
 
-        println!("📈 Analyzing traffic patterns");
-        println!("Interface: {interface_name}");
-        println!("Period: {} (since {})", period, since.format("%Y-%m-%d %H:%M:%S"));
-        println!();
-
         // Get traffic summary from storage
-        let summary = self.storage.get_traffic_summary(&interface_name, since)
+        let summary = self.storage.get_traffic_summary(&interface_name, since, until)
             .context("Failed to retrieve traffic summary")?;
+        // Connections are not recorded per interface, so application protocols cover all of them
+        let applications = self.storage
+            .get_application_usage(since, until)
+            .context("Failed to retrieve application protocol statistics")?;
+
+        if format == "json" {
+            let document = analysis_json(&period, &interface_name, &analyzed, &summary, &applications, limit);
+            println!("{}", serde_json::to_string_pretty(&document)?);
+            if security && let Some((path, report_format)) = &self.security_export {
+                self.export_security_report(path, *report_format, &interface_name, &analyzed)?;
+            }
+            return Ok(());
+        }
+
+        println!("📈 Analyzing traffic patterns");
+        println!("Interface: {}", self.tags.describe(&interface_name));
+        println!("Period: {} ({} → {})", analyzed.label, since.format("%Y-%m-%d %H:%M:%S"), until.format("%Y-%m-%d %H:%M:%S"));
+        println!();
 
         // Display basic statistics
         println!("📊 Traffic Summary:");
         println!("  Total Packets: {}", summary.total_packets);
         println!("  Total Bytes:   {}", format_bytes(summary.total_bytes));
+        if summary.sample_rate > 1 {
+            println!("  Sampled:       up to 1 packet in {} was analyzed; counts are estimates", summary.sample_rate);
+        }
         println!();
 
         // Protocol distribution
@@ -301,25 +852,431 @@ impl PacketCommandHandler {
             println!();
         }
 
+        if !applications.is_empty() {
+            print_applications(&applications, limit);
+            println!();
+        }
+
         // Top connections
         if !summary.top_connections.is_empty() {
+            let connections: Vec<_> = summary.top_connections.iter().take(10).collect();
+            let host_names = match self.resolver {
+                Some(ref resolver) => {
+                    println!("🔎 Resolving host names via {}", resolver.describe());
+                    let addresses = connections.iter().flat_map(|c| [c.source.as_str(), c.destination.as_str()]);
+                    resolve_host_names(resolver, addresses).await
+                }
+                None => HashMap::new(),
+            };
+            let with_host = |address: &str| self.tags.describe_with(address, host_names.get(address).map(String::as_str));
+
             println!("🌐 Top Connections:");
-            for (i, connection) in summary.top_connections.iter().take(10).enumerate() {
+            for (i, connection) in connections.iter().enumerate() {
                 println!("  {}. {} -> {} ({})",
                     i + 1,
-                    connection.source,
-                    connection.destination,
+                    with_host(&connection.source),
+                    with_host(&connection.destination),
                     format_bytes(connection.bytes)
                 );
             }
             println!();
         }
 
+        let devices: Vec<_> = self.storage
+            .get_discovered_devices()
+            .context("Failed to retrieve discovered devices")?
+            .into_iter()
+            .filter(|device| device.last_seen >= since && device.first_seen <= until && (interface_name == "all" || device.interface == interface_name))
+            .collect();
+        if !devices.is_empty() {
+            print_discovered_devices(&devices.iter().collect::<Vec<_>>(), &self.tags);
+            println!();
+        }
+
+        let loss = self.storage
+            .get_tcp_loss(&interface_name, since, until, 10)
+            .context("Failed to retrieve TCP loss counters")?;
+        if !loss.interfaces.is_empty() {
+            println!("📉 TCP Loss by Interface:");
+            for (interface, stats) in &loss.interfaces {
+                println!("  {:<24} {} segments, {}", self.tags.describe(interface), stats.segments, describe_loss(stats));
+            }
+            if !loss.connections.is_empty() {
+                println!("  Lossiest connections:");
+                for (client, server, stats) in &loss.connections {
+                    println!(
+                        "    {} -> {}: {}",
+                        self.tags.describe_endpoint(client),
+                        self.tags.describe_endpoint(server),
+                        describe_loss(stats)
+                    );
+                }
+            }
+            println!();
+        }
+
+        let kernel = self.storage
+            .get_kernel_tcp_stats(&interface_name, since, until, 10)
+            .context("Failed to retrieve kernel socket statistics")?;
+        if !kernel.is_empty() {
+            println!("🧮 Kernel TCP Statistics (this host's connections):");
+            for (client, server, info) in &kernel {
+                println!(
+                    "  {} -> {}: {}",
+                    self.tags.describe_endpoint(client),
+                    self.tags.describe_endpoint(server),
+                    info.describe()
+                );
+            }
+            println!();
+        }
+
+        let latency = LatencyStats::per_host(
+            self.storage
+                .get_latency_samples(&interface_name, since, until)
+                .context("Failed to retrieve latency samples")?,
+        );
+        if !latency.is_empty() {
+            print_latency(&latency, &self.tags);
+            println!();
+        }
+
+        let classification = self.storage
+            .get_classification_samples(&interface_name, since, until)
+            .context("Failed to retrieve classification counts")?;
+        if !classification.is_empty() {
+            print_classification(&classification);
+            let surges = self.storage
+                .get_security_events(&interface_name, since, until, &["UnknownTrafficSurge"], 5)
+                .context("Failed to retrieve unknown traffic surges")?;
+            if !surges.is_empty() {
+                println!("  Recent surges:");
+                for event in &surges {
+                    println!(
+                        "    {} {}: {}",
+                        event.timestamp.format("%m-%d %H:%M:%S"),
+                        self.tags.describe(&event.interface_name),
+                        self.tags.redactor().text(&event.description)
+                    );
+                }
+            }
+            println!();
+        }
+
+        let mtu = self.storage
+            .get_mtu_samples(&interface_name, since, until)
+            .context("Failed to retrieve fragmentation counts")?;
+        if !mtu.is_empty() {
+            print_mtu(&mtu, &self.tags, limit);
+            println!();
+        }
+
+        let throttled = self.storage
+            .get_throttled_flows(&interface_name, since, until)
+            .context("Failed to retrieve throttled connections")?;
+        if !throttled.is_empty() {
+            print_throttling(&throttled, &self.tags, limit);
+            println!();
+        }
+
+        let packet_sizes = self.storage
+            .get_packet_sizes(&interface_name, since, until)
+            .context("Failed to retrieve packet sizes")?;
+        if !packet_sizes.is_empty() {
+            print_packet_sizes(&packet_sizes, &self.tags);
+            println!();
+        }
+
+        // Ping targets are not tied to an interface, so they are shown for any
+        let pings = self.storage
+            .get_ping_samples(since, until)
+            .context("Failed to retrieve ping statistics")?;
+        if !pings.is_empty() {
+            print_connectivity(&pings, &self.tags);
+            println!();
+        }
+
+        if diagnostics {
+            let duplicates = self.storage
+                .get_duplicate_samples(&interface_name, since, until)
+                .context("Failed to retrieve duplicate packet counts")?;
+            print_diagnostics(&duplicates, &self.tags, summary.sample_rate > 1);
+            println!();
+        }
+
         // Security analysis
         if security {
             println!("🔒 Security Analysis:");
-            // This would query security events from storage
-            println!("  No security issues detected in the analyzed period.");
+            let counts = self.storage
+                .get_security_event_counts(&interface_name, since, until)
+                .context("Failed to retrieve security events")?;
+            if counts.is_empty() {
+                println!("  No security issues detected in the analyzed period.");
+            } else {
+                for (event_type, count) in &counts {
+                    println!("  {event_type:<24} {count:>6}");
+                }
+            }
+            let detections = self.storage
+                .get_security_events(&interface_name, since, until, &DETECTION_EVENT_TYPES, 10)
+                .context("Failed to retrieve detected attacks")?;
+            if !detections.is_empty() {
+                println!("  Recent scans and floods:");
+                for event in &detections {
+                    let host = |ip: &Option<String>| {
+                        ip.as_deref().map(|ip| self.tags.describe(ip)).unwrap_or_else(|| "?".to_string())
+                    };
+                    println!(
+                        "    {} {} -> {}: {}",
+                        event.timestamp.format("%m-%d %H:%M:%S"),
+                        host(&event.source_ip),
+                        host(&event.dest_ip),
+                        self.tags.redactor().text(&event.description)
+                    );
+                }
+            }
+            if let Some((path, report_format)) = &self.security_export {
+                let events = self.export_security_report(path, *report_format, &interface_name, &analyzed)?;
+                println!("  📤 {events} events exported to {}", path.display());
+            }
+            println!();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the security events of `period`, with the connections behind each, to `path`;
+    /// returns the number of events
+    fn export_security_report(
+        &self,
+        path: &Path,
+        format: SecurityReportFormat,
+        interface: &str,
+        period: &Period,
+    ) -> Result<usize> {
+        let events = self.storage
+            .get_security_event_log(interface, period.start, period.end)
+            .context("Failed to retrieve security events")?;
+        let slack = chrono::Duration::seconds(EVIDENCE_SLACK_SECS);
+        let report = SecurityReport::new(period.start, period.end, interface.to_string(), events, |event| {
+            match (&event.source_ip, &event.dest_ip) {
+                (Some(source), Some(dest)) => self.storage.get_connections_between(
+                    source,
+                    dest,
+                    event.timestamp,
+                    slack,
+                    EVIDENCE_CONNECTIONS,
+                ),
+                _ => Ok(Vec::new()),
+            }
+        })?
+        .redacted(self.tags.redactor());
+        std::fs::write(path, report.render(format)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(report.events.len())
+    }
+
+    /// Feeds every packet of a pcap capture through the analyzer and into storage
+    pub async fn handle_pcap_import(
+        &self,
+        path: &Path,
+        interface: Option<String>,
+        security: bool,
+        protocols: bool,
+        diagnostics: bool,
+    ) -> Result<()> {
+        // Imported traffic is kept apart from live interfaces unless a name is given
+        let interface_name = interface.unwrap_or_else(|| {
+            let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+            format!("pcap:{stem}")
+        });
+        let reader = PcapFileReader::open(path, interface_name.clone())?;
+
+        println!("📂 Importing {}", path.display());
+        println!("Interface: {}", self.tags.describe(&interface_name));
+        println!("Link type: {:?}", reader.link_type());
+        println!();
+
+        let mut packet_count = 0u64;
+        let mut byte_count = 0u64;
+        let mut protocol_stats: HashMap<String, u64> = HashMap::new();
+        let mut security_stats: HashMap<&'static str, u64> = HashMap::new();
+        let mut first_seen = None;
+        let mut last_seen = None;
+        let mut packet_sizes = PacketSizeDistribution::default();
+        let mut sampler = self.sampler.clone();
+
+        for packet in reader {
+            let packet = packet?;
+            first_seen.get_or_insert(packet.timestamp);
+            last_seen = Some(packet.timestamp);
+            let weight = match sampler.as_mut().map(|sampler| sampler.weigh(packet.timestamp)) {
+                Some(None) => continue,
+                Some(Some(weight)) => weight,
+                None => 1,
+            };
+            let analysis = if self.resources.analyzer_enabled {
+                let mut analyzer = self.analyzer.lock().await;
+                analyzer.analyze_packet(&packet).unwrap_or_default()
+            } else {
+                AnalysisResult::default()
+            };
+            self.process_packet_analysis(&packet, &analysis, weight)?;
+            packet_sizes.add_packets(&packet, weight);
+
+            packet_count += weight;
+            byte_count += packet.size_bytes * weight;
+            *protocol_stats.entry(analysis_to_protocol_name(&packet, &analysis)).or_insert(0) += weight;
+            for flag in &analysis.security_flags {
+                *security_stats.entry(flag.event_type()).or_insert(0) += 1;
+            }
+        }
+        self.storage.flush_all().context("Failed to store imported packets")?;
+        let (tcp_summary, tcp_sessions, latency_samples, classification, duplicates, mtu, throttled, devices) = {
+            let mut analyzer = self.analyzer.lock().await;
+            (
+                analyzer.tcp_summary(),
+                analyzer.drain_tcp_sessions(),
+                analyzer.take_latency_samples(),
+                analyzer.drain_classification_samples(),
+                analyzer.drain_duplicate_samples(),
+                analyzer.drain_mtu_samples(),
+                analyzer.drain_throttled_flows(),
+                analyzer.take_discovered_devices(),
+            )
+        };
+        self.storage
+            .store_tcp_sessions(&tcp_sessions)
+            .context("Failed to store imported TCP sessions")?;
+        self.storage
+            .store_latency_samples(&latency_samples)
+            .context("Failed to store imported latency samples")?;
+        self.storage
+            .store_classification_samples(&classification)
+            .context("Failed to store imported classification counts")?;
+        self.storage
+            .store_duplicate_samples(&duplicates)
+            .context("Failed to store imported duplicate packet counts")?;
+        self.storage
+            .store_mtu_samples(&mtu)
+            .context("Failed to store imported fragmentation counts")?;
+        self.storage
+            .store_throttled_flows(&throttled)
+            .context("Failed to store imported throttled connections")?;
+        self.storage
+            .store_discovered_devices(&devices)
+            .context("Failed to store discovered devices")?;
+        if let Some(last) = last_seen {
+            self.storage
+                .store_packet_sizes(last, &packet_sizes)
+                .context("Failed to store imported packet sizes")?;
+        }
+
+        println!("📊 Import Summary:");
+        println!("  Total Packets: {packet_count}");
+        println!("  Total Bytes:   {}", format_bytes(byte_count));
+        if let (Some(first), Some(last)) = (first_seen, last_seen) {
+            println!("  Time Range:    {} - {}", first.format("%Y-%m-%d %H:%M:%S"), last.format("%Y-%m-%d %H:%M:%S"));
+        }
+        if let Some(sampling) = sampler.as_ref().and_then(describe_sampling) {
+            println!("  Sampled:       {sampling}");
+        }
+        println!();
+
+        if tcp_summary != TcpSummary::default() {
+            print_tcp_summary(&tcp_summary);
+            println!();
+        }
+
+        let latency = LatencyStats::per_host(
+            latency_samples.iter().map(|sample| (sample.host.to_string(), sample.rtt_ms)),
+        );
+        if !latency.is_empty() {
+            print_latency(&latency, &self.tags);
+            println!();
+        }
+
+        if !classification.is_empty() {
+            print_classification(&classification);
+            println!();
+        }
+
+        if !mtu.is_empty() {
+            print_mtu(&mtu, &self.tags, 10);
+            println!();
+        }
+
+        if !throttled.is_empty() {
+            print_throttling(&throttled, &self.tags, 10);
+            println!();
+        }
+
+        if !packet_sizes.is_empty() {
+            print_packet_sizes(&packet_sizes, &self.tags);
+            println!();
+        }
+
+        if !devices.is_empty() {
+            print_discovered_devices(&devices.iter().collect::<Vec<_>>(), &self.tags);
+            println!();
+        }
+
+        if protocols && !protocol_stats.is_empty() {
+            println!("🔧 Protocol Distribution:");
+            let mut sorted_protocols: Vec<_> = protocol_stats.iter().collect();
+            sorted_protocols.sort_by(|a, b| b.1.cmp(a.1));
+
+            for (protocol, count) in sorted_protocols {
+                let percentage = (*count as f64 / packet_count as f64) * 100.0;
+                println!("  {protocol:<12} {count:>10} packets ({percentage:>5.1}%)");
+            }
+            println!();
+        }
+
+        // Top connections, as seen by the analyzer over the whole file
+        let connections: Vec<_> = {
+            let analyzer = self.analyzer.lock().await;
+            analyzer
+                .get_top_connections(10)
+                .into_iter()
+                .map(|c| (c.source.to_string(), c.destination.to_string(), c.bytes))
+                .collect()
+        };
+        if !connections.is_empty() {
+            let host_names = match self.resolver {
+                Some(ref resolver) => {
+                    println!("🔎 Resolving host names via {}", resolver.describe());
+                    let addresses = connections.iter().flat_map(|(src, dst, _)| [src.as_str(), dst.as_str()]);
+                    resolve_host_names(resolver, addresses).await
+                }
+                None => HashMap::new(),
+            };
+            let tags = self.tags_with_devices().await;
+            let with_host = |address: &str| tags.describe_with(address, host_names.get(address).map(String::as_str));
+
+            println!("🌐 Top Connections:");
+            for (i, (source, destination, bytes)) in connections.iter().enumerate() {
+                println!("  {}. {} -> {} ({})", i + 1, with_host(source), with_host(destination), format_bytes(*bytes));
+            }
+            println!();
+        }
+
+        if diagnostics {
+            print_diagnostics(&duplicates, &self.tags, sampler.as_ref().and_then(describe_sampling).is_some());
+            println!();
+        }
+
+        if security {
+            println!("🔒 Security Analysis:");
+            if security_stats.is_empty() {
+                println!("  No security issues detected in the capture.");
+            } else {
+                let mut sorted_flags: Vec<_> = security_stats.iter().collect();
+                sorted_flags.sort_by(|a, b| b.1.cmp(a.1));
+                for (event_type, count) in sorted_flags {
+                    println!("  {event_type:<24} {count:>6}");
+                }
+            }
             println!();
         }
 
@@ -327,26 +1284,398 @@ impl PacketCommandHandler {
     }
 }
 
-fn parse_duration(duration_str: &str) -> Result<StdDuration> {
-    let duration_str = duration_str.trim();
-    
-    if duration_str.ends_with('s') {
-        let seconds: u64 = duration_str.trim_end_matches('s').parse()
-            .context("Invalid seconds format")?;
-        Ok(StdDuration::from_secs(seconds))
-    } else if duration_str.ends_with('m') {
-        let minutes: u64 = duration_str.trim_end_matches('m').parse()
-            .context("Invalid minutes format")?;
-        Ok(StdDuration::from_secs(minutes * 60))
-    } else if duration_str.ends_with('h') {
-        let hours: u64 = duration_str.trim_end_matches('h').parse()
-            .context("Invalid hours format")?;
-        Ok(StdDuration::from_secs(hours * 3600))
-    } else {
-        // Assume seconds if no unit
-        let seconds: u64 = duration_str.parse()
-            .context("Invalid duration format")?;
-        Ok(StdDuration::from_secs(seconds))
+/// How much of the traffic the sampler let through; `None` until it has skipped a packet
+fn describe_sampling(sampler: &PacketSampler) -> Option<String> {
+    let (seen, kept) = sampler.counts();
+    (kept < seen).then(|| {
+        format!(
+            "{kept} of {seen} packets analyzed ({} above {} packets/s); counts are estimates",
+            sampler.rate(),
+            sampler.threshold_pps()
+        )
+    })
+}
+
+fn print_tcp_summary(summary: &TcpSummary) {
+    println!("🤝 TCP Connections:");
+    println!("  Handshakes completed: {}", summary.handshakes);
+    println!("  Open:                 {}", summary.open);
+    println!("  Half-open:            {}", summary.half_open);
+    println!("  Closed (FIN):         {}", summary.closed);
+    println!("  Reset (RST):          {}", summary.reset);
+    println!("  Retransmissions:      {}", summary.loss.retransmissions);
+    println!("  Duplicate ACKs:       {}", summary.loss.duplicate_acks);
+    println!("  Out-of-order:         {}", summary.loss.out_of_order);
+    println!("  Estimated loss:       {:.2}%", summary.loss.loss_rate() * 100.0);
+}
+
+/// Loss estimate per capture interface, skipped when only one interface has TCP traffic
+fn print_interface_loss(interfaces: &[(String, LossStats)], tags: &TagBook) {
+    if interfaces.len() < 2 {
+        return;
+    }
+    println!("📉 Loss by Interface:");
+    for (interface, loss) in interfaces {
+        println!("  {:<24} {}", tags.describe(interface), describe_loss(loss));
+    }
+}
+
+/// Busiest processes by bytes sent and received, when the capture source tags packets with one
+/// Prints the packets kept for `--inspect` as hex and ASCII dumps, oldest first
+pub fn print_inspected(packets: &[InspectedPacket]) {
+    println!("\n🔬 Last {} packets captured", packets.len());
+    println!("⚠️  {PRIVACY_WARNING}");
+    for (index, packet) in packets.iter().enumerate() {
+        println!("\n#{} {} {} ({})", index + 1, packet.timestamp.format("%H:%M:%S%.6f"), packet.summary, packet.coverage());
+        for line in hex_dump(&packet.bytes) {
+            println!("  {line}");
+        }
+    }
+}
+
+fn print_process_traffic(traffic: &HashMap<u32, (String, u64, u64)>, max_processes: usize) {
+    if traffic.is_empty() {
+        return;
+    }
+    let mut sorted: Vec<_> = traffic.iter().collect();
+    sorted.sort_by_key(|(_, (_, sent, received))| std::cmp::Reverse(sent + received));
+
+    println!("🧵 Top Processes (by bytes):");
+    for (pid, (name, sent, received)) in sorted.into_iter().take(max_processes) {
+        println!("  {name:<24} {pid:>7}  ↑ {:>10}  ↓ {:>10}", format_bytes(*sent), format_bytes(*received));
+    }
+    println!();
+}
+
+/// `3 retransmissions (0.41% loss), 5 dup ACKs, 1 out-of-order`
+/// Lists the `limit` busiest application protocols with their share of the connection bytes
+fn print_applications(applications: &[ApplicationUsage], limit: usize) {
+    let total_bytes: u64 = applications.iter().map(|a| a.bytes).sum();
+    let share = |bytes: u64| if total_bytes == 0 { 0.0 } else { bytes as f64 / total_bytes as f64 * 100.0 };
+
+    println!("🧩 Top Application Protocols:");
+    for application in applications.iter().take(limit) {
+        println!("  {:<12} {:>7} flows, {:>10} packets, {:>10} ({:>5.1}%)",
+            application.protocol,
+            application.flows,
+            application.packets,
+            format_bytes(application.bytes),
+            share(application.bytes)
+        );
+    }
+    let rest = applications.get(limit..).unwrap_or_default();
+    if !rest.is_empty() {
+        let bytes = rest.iter().map(|a| a.bytes).sum();
+        let flows: u64 = rest.iter().map(|a| a.flows).sum();
+        println!("  … {} more protocols: {} flows, {} ({:.1}%)", rest.len(), flows, format_bytes(bytes), share(bytes));
+    }
+}
+
+/// The traffic totals and `limit` busiest application protocols of `kw analyze --format json`
+fn analysis_json(
+    period: &str,
+    interface: &str,
+    analyzed: &Period,
+    summary: &TrafficSummary,
+    applications: &[ApplicationUsage],
+    limit: usize,
+) -> serde_json::Value {
+    let total_bytes: u64 = applications.iter().map(|a| a.bytes).sum();
+    let share = |bytes: u64| if total_bytes == 0 { 0.0 } else { bytes as f64 / total_bytes as f64 * 100.0 };
+    let rest = applications.get(limit..).unwrap_or_default();
+    let mut protocols: Vec<_> = summary.protocols.iter().collect();
+    protocols.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+
+    json!({
+        "period": period,
+        "interface": interface,
+        "since": analyzed.start.to_rfc3339(),
+        "until": analyzed.end.to_rfc3339(),
+        "total_packets": summary.total_packets,
+        "total_bytes": summary.total_bytes,
+        "sample_rate": summary.sample_rate,
+        "protocols": protocols.iter().map(|(protocol, stats)| json!({
+            "protocol": protocol,
+            "packets": stats.packets,
+            "bytes": stats.bytes,
+        })).collect::<Vec<_>>(),
+        "applications": applications.iter().take(limit).map(|a| json!({
+            "protocol": a.protocol,
+            "flows": a.flows,
+            "packets": a.packets,
+            "bytes": a.bytes,
+            "share_percent": share(a.bytes),
+        })).collect::<Vec<_>>(),
+        "other_applications": {
+            "protocols": rest.len(),
+            "flows": rest.iter().map(|a| a.flows).sum::<u64>(),
+            "packets": rest.iter().map(|a| a.packets).sum::<u64>(),
+            "bytes": rest.iter().map(|a| a.bytes).sum::<u64>(),
+        },
+    })
+}
+
+fn describe_loss(loss: &LossStats) -> String {
+    format!(
+        "{} retransmissions ({:.2}% loss), {} dup ACKs, {} out-of-order",
+        loss.retransmissions,
+        loss.loss_rate() * 100.0,
+        loss.duplicate_acks,
+        loss.out_of_order
+    )
+}
+
+/// Round-trip time percentiles for the most sampled destinations
+fn print_latency(stats: &[LatencyStats], tags: &TagBook) {
+    println!("⏱️  Latency by Destination (RTT, ms):");
+    println!("  {:<40} {:>7} {:>8} {:>8} {:>8} {:>8}", "Host", "Samples", "p50", "p90", "p99", "Max");
+    for host in stats.iter().take(10) {
+        println!(
+            "  {:<40} {:>7} {:>8.2} {:>8.2} {:>8.2} {:>8.2}",
+            tags.describe(&host.host), host.samples, host.p50_ms, host.p90_ms, host.p99_ms, host.max_ms
+        );
+    }
+}
+
+/// LAN devices identified from their mDNS and SSDP announcements; a user tag is shown
+/// alongside the announced name
+fn print_discovered_devices(devices: &[&DiscoveredDevice], tags: &TagBook) {
+    println!("📟 Discovered Devices:");
+    println!("  {:<40} {:<10} {:<28} {:<6} Last seen", "Address", "Type", "Name", "Via");
+    for device in devices {
+        println!(
+            "  {:<40} {:<10} {:<28} {:<6} {}",
+            tags.describe(&device.address.to_string()),
+            device.device_type,
+            device.name.as_deref().unwrap_or("-"),
+            device.source.as_str(),
+            device.last_seen.format("%m-%d %H:%M")
+        );
+    }
+}
+
+/// Share of traffic no protocol was identified for, overall and for the last day of hours
+fn print_classification(samples: &[ClassificationSample]) {
+    let Some(total) = classification::total(samples) else {
+        return;
+    };
+    println!("❓ Unclassified Traffic:");
+    println!(
+        "  Unknown:        {:.1}% of bytes ({} of {}), {:.1}% of packets",
+        total.unknown_share() * 100.0,
+        format_bytes(total.unknown_bytes),
+        format_bytes(total.bytes),
+        total.unknown_packets as f64 / total.packets.max(1) as f64 * 100.0
+    );
+    println!("  Low confidence: {:.1}% of bytes", total.low_confidence_share() * 100.0);
+
+    let hours = classification::hourly(samples);
+    if hours.len() > 1 {
+        println!("  Unknown share by hour:");
+        for hour in &hours[hours.len().saturating_sub(24)..] {
+            let share = hour.unknown_share();
+            println!(
+                "    {}  {:>5.1}%  {}",
+                hour.timestamp.format("%m-%d %H:00"),
+                share * 100.0,
+                "█".repeat((share * 20.0).round() as usize)
+            );
+        }
+    }
+}
+
+/// Fragments and "packet too big" messages per remote host, with an MTU that gets through
+fn print_mtu(samples: &[MtuSample], tags: &TagBook, limit: usize) {
+    let hosts = fragmentation::per_remote(samples);
+    println!("📏 MTU and Fragmentation:");
+    println!("  {:<32} {:>10} {:>10} {:>9} {:>8}  Suggestion", "Host", "Fragmented", "Fragments", "Too big", "MTU");
+    for host in hosts.iter().take(limit) {
+        let reported = host.reported_mtu.map_or_else(|| "-".to_string(), |mtu| mtu.to_string());
+        let suggestion = match host.suggested_mtu() {
+            Some(mtu) => format!("MTU {mtu} or TCP MSS {} towards this host", host.mss_for(mtu)),
+            None => "no MTU reported; try 1280 and raise it until packets stop being refused".to_string(),
+        };
+        println!(
+            "  {:<32} {:>10} {:>10} {:>9} {:>8}  {}",
+            tags.describe(&host.remote),
+            host.fragmented_packets,
+            host.fragments,
+            host.too_big,
+            reported,
+            suggestion
+        );
+    }
+    if hosts.len() > limit {
+        println!("  ... and {} more hosts", hosts.len() - limit);
+    }
+    // Refusals that keep coming mean path MTU discovery is not taking effect
+    if hosts.iter().any(|host| host.too_big >= PMTUD_STUCK_REFUSALS) {
+        println!("  Packets kept being refused for their size: path MTU discovery is not lowering them,");
+        println!("  often because a firewall drops the ICMP messages on the way back. Lower the MTU or clamp the MSS");
+    }
+}
+
+/// Packet counts by size per interface and transport protocol, with the share of each bucket
+fn print_packet_sizes(sizes: &PacketSizeDistribution, tags: &TagBook) {
+    let row = |name: String, histogram: &PacketSizeHistogram| {
+        let total = histogram.total().max(1) as f64;
+        let buckets: Vec<String> = histogram
+            .counts()
+            .iter()
+            .map(|count| format!("{:>9} {:>5.1}%", count, *count as f64 / total * 100.0))
+            .collect();
+        println!("  {:<20} {:>10}  {}", name, histogram.total(), buckets.join("  "));
+    };
+    println!("📦 Packet Sizes:");
+    let header: Vec<String> = PACKET_SIZE_BUCKETS.iter().map(|bucket| format!("{bucket:>16}")).collect();
+    println!("  {:<20} {:>10}  {}", "Interface", "Packets", header.join("  "));
+    for (interface, histogram) in sizes.by_interface() {
+        row(tags.describe(&interface), &histogram);
+    }
+    println!("  {:<20}", "Protocol");
+    for (protocol, histogram) in sizes.by_protocol() {
+        row(protocol, &histogram);
+    }
+
+    // A link full of minimum-size packets runs out of packets per second long before bandwidth
+    let overloaded: Vec<String> = sizes
+        .by_interface()
+        .into_iter()
+        .filter(|(_, histogram)| histogram.small_packet_overload())
+        .map(|(interface, _)| tags.describe(&interface))
+        .collect();
+    if !overloaded.is_empty() {
+        println!(
+            "  ⚠️  {} carried {:.0}% or more packets of 64 bytes or less: look for ACK storms, floods or",
+            overloaded.join(", "),
+            SMALL_PACKET_OVERLOAD_SHARE * 100.0
+        );
+        println!("     chatty protocols, as per-packet costs rather than bandwidth will limit throughput");
+    }
+}
+
+/// Connections held to a suspiciously steady rate, the likeliest caps first
+fn print_throttling(flows: &[ThrottledFlow], tags: &TagBook, limit: usize) {
+    let flows = throttling::per_connection(flows);
+    println!("🐢 Possible Throttling:");
+    println!("  {:<60} {:>14} {:>8} {:>9}  Confidence", "Connection", "Rate", "Steady", "Variation");
+    for flow in flows.iter().take(limit) {
+        let filled = if flow.link_share >= FILLED_LINK_SHARE { " (filled the link)" } else { "" };
+        println!(
+            "  {:<60} {:>14} {:>7}s {:>8.1}%  {}{}",
+            format!(
+                "{} -> {} ({})",
+                tags.describe_endpoint(&flow.sender),
+                tags.describe_endpoint(&flow.receiver),
+                flow.transport.to_uppercase()
+            ),
+            format_mbit(flow.rate),
+            flow.seconds,
+            flow.variation * 100.0,
+            flow.confidence,
+            filled
+        );
+    }
+    if flows.len() > limit {
+        println!("  ... and {} more connections", flows.len() - limit);
+    }
+    println!("  A rate this steady usually comes from a shaper on the way or a limit at the server.");
+    if flows.iter().any(|flow| flow.link_share >= FILLED_LINK_SHARE) {
+        println!("  Connections that filled the link may just be running at the line's speed; compare with kw speedtest");
+    }
+}
+
+/// Duplicate packets per interface, and the minutes whose copies point to a loop or a
+/// duplicating link, the most serious first
+fn print_diagnostics(samples: &[DuplicateSample], tags: &TagBook, sampled: bool) {
+    println!("🩺 Diagnostics:");
+    let totals = duplicates::per_interface(samples);
+    if totals.is_empty() {
+        println!("  No duplicate packet counts recorded in the analyzed period.");
+        return;
+    }
+    println!("  Duplicate packets by interface:");
+    for total in &totals {
+        println!(
+            "    {:<24} {:>10} packets, {:>8} duplicates ({:.2}%), {} with a changed TTL",
+            tags.describe(&total.interface),
+            total.packets,
+            total.duplicates,
+            total.duplicate_percent(),
+            total.ttl_changed
+        );
+    }
+    if sampled {
+        println!("  Sampling skipped packets, so copies of them went unseen and counts are low");
+    }
+
+    // Routing loops first, then switching loops, then plain duplicates; newest first within each
+    let rank = |finding: &DuplicateFinding| match finding {
+        DuplicateFinding::RoutingLoop(_) => 0,
+        DuplicateFinding::SwitchingLoop(_) => 1,
+        DuplicateFinding::Duplicates(_) => 2,
+    };
+    let mut findings: Vec<_> = samples
+        .iter()
+        .filter_map(|sample| sample.finding().map(|finding| (sample, finding)))
+        .collect();
+    findings.sort_by(|a, b| rank(&a.1).cmp(&rank(&b.1)).then(b.0.timestamp.cmp(&a.0.timestamp)));
+    if findings.is_empty() {
+        println!("  No loops or duplicating links found.");
+        return;
+    }
+    println!("  Findings:");
+    for (sample, finding) in findings.iter().take(10) {
+        let packet = match (&sample.source, &sample.destination) {
+            (Some(source), Some(destination)) => format!(" ({} -> {})", tags.describe(source), tags.describe(destination)),
+            _ => String::new(),
+        };
+        println!(
+            "    {} {}: {finding}{packet}",
+            sample.timestamp.format("%m-%d %H:%M"),
+            tags.describe(&sample.interface)
+        );
+    }
+    if findings.len() > 10 {
+        println!("    ... and {} more minutes", findings.len() - 10);
+    }
+}
+
+/// Latency, jitter and loss per ping target over the period, and the minutes with the most loss
+fn print_connectivity(samples: &[PingSample], tags: &TagBook) {
+    let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{v:.1}"));
+    println!("📶 Connectivity (ping):");
+    println!("  {:<32} {:>8} {:>8} {:>12} {:>11}", "Target", "Sent", "Loss", "Latency ms", "Jitter ms");
+    for target in ping_collector::per_target(samples) {
+        let name = if target.target == target.address {
+            tags.describe(&target.target)
+        } else {
+            format!("{} ({})", target.target, tags.describe(&target.address))
+        };
+        println!(
+            "  {:<32} {:>8} {:>7.1}% {:>12} {:>11}",
+            name,
+            target.sent,
+            target.loss_percent(),
+            ms(target.latency_ms),
+            ms(target.jitter_ms)
+        );
+    }
+
+    let mut lossy: Vec<&PingSample> = samples.iter().filter(|s| s.received < s.sent).collect();
+    lossy.sort_by(|a, b| b.loss_percent().total_cmp(&a.loss_percent()).then(b.timestamp.cmp(&a.timestamp)));
+    if !lossy.is_empty() {
+        println!("  Worst minutes:");
+        for sample in lossy.iter().take(5) {
+            println!(
+                "    {} {}: {:.0}% loss ({} of {} probes answered)",
+                sample.timestamp.format("%m-%d %H:%M"),
+                sample.target,
+                sample.loss_percent(),
+                sample.received,
+                sample.sent
+            );
+        }
     }
 }
 
@@ -378,29 +1707,86 @@ fn analysis_to_protocol_name(
     }
 }
 
-impl Default for AnalysisResult {
-    fn default() -> Self {
-        Self {
-            application_protocol: None,
-            is_encrypted: false,
-            traffic_type: TrafficType::Other,
-            security_flags: Vec::new(),
-            flow_direction: crate::analyzers::FlowDirection::Local,
-            geolocation: None,
+/// Samples interface counters briefly, then asks which interface to capture on when run in a
+/// terminal with more than one to choose from; otherwise returns the interface carrying the most
+/// traffic, or "any" when none carried traffic during the sample
+async fn select_interface() -> String {
+    println!("🔎 No --interface given, sampling traffic on the interfaces...");
+    let stats = match interface_picker::sample_interfaces().await {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("Could not sample interface traffic, capturing on all interfaces: {e}");
+            println!("⚠️  Could not sample interface traffic; capturing on all interfaces");
+            return ALL_INTERFACES.to_string();
+        }
+    };
+
+    let busiest = busiest_interface(&stats);
+    if stats.len() > 1 && interface_picker::is_interactive() {
+        // The busiest interface stays the answer for a plain Enter
+        let default = busiest.map_or(ALL_INTERFACES, |busiest| busiest.interface_name.as_str());
+        match interface_picker::prompt(&interface_picker::choices(&stats), default) {
+            Ok(choice) => return choice,
+            Err(e) => warn!("Interface prompt failed, picking the busiest interface: {e}"),
+        }
+    }
+
+    match busiest {
+        Some(busiest) => {
+            info!(
+                "Auto-selected interface {} ({:.0} B/s during sample)",
+                busiest.interface_name,
+                busiest.total_bandwidth_bps()
+            );
+            println!(
+                "🔍 Auto-selected {} ({} during the sample); use --interface <name> to pick another, or --interface any for all",
+                busiest.interface_name,
+                BandwidthStats::format_speed(busiest.total_bandwidth_bps())
+            );
+            busiest.interface_name.clone()
+        }
+        None => {
+            info!("No interface carried traffic during the sample, capturing on all interfaces");
+            println!("🔍 No interface carried traffic during the sample; capturing on all interfaces");
+            ALL_INTERFACES.to_string()
         }
     }
 }
 
+/// Interface with the highest combined download and upload rate, if any had traffic
+fn busiest_interface(stats: &[BandwidthStats]) -> Option<&BandwidthStats> {
+    stats
+        .iter()
+        .filter(|stats| stats.has_activity())
+        .max_by(|a, b| a.total_bandwidth_bps().total_cmp(&b.total_bandwidth_bps()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collectors::bandwidth_collector::{CalculationConfidence, InterfaceState, InterfaceType};
 
     #[test]
-    fn test_parse_duration() {
-        assert_eq!(parse_duration("60s").unwrap(), StdDuration::from_secs(60));
-        assert_eq!(parse_duration("5m").unwrap(), StdDuration::from_secs(300));
-        assert_eq!(parse_duration("1h").unwrap(), StdDuration::from_secs(3600));
-        assert_eq!(parse_duration("30").unwrap(), StdDuration::from_secs(30));
+    fn test_busiest_interface() {
+        let stats = |name: &str, download: f64, upload: f64| BandwidthStats {
+            timestamp: chrono::Utc::now(),
+            interface_name: name.to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            packets_sent: 0,
+            download_speed_bps: download,
+            upload_speed_bps: upload,
+            calculation_confidence: CalculationConfidence::High,
+            time_since_last_update: 1.0,
+        };
+
+        let sample = [stats("eth0", 1000.0, 200.0), stats("wlan0", 100.0, 5000.0), stats("eth1", 0.0, 0.0)];
+        assert_eq!(busiest_interface(&sample).unwrap().interface_name, "wlan0");
+        assert!(busiest_interface(&[stats("eth0", 0.0, 0.0)]).is_none());
+        assert!(busiest_interface(&[]).is_none());
     }
 
     #[test]
@@ -410,4 +1796,39 @@ mod tests {
         assert_eq!(format_bytes(1536), "1.5 KB");
         assert_eq!(format_bytes(1048576), "1.0 MB");
     }
+
+    #[test]
+    fn test_analysis_json_limits_applications() {
+        let usage = |protocol: &str, flows, bytes| ApplicationUsage {
+            protocol: protocol.to_string(),
+            flows,
+            packets: flows * 10,
+            bytes,
+        };
+        let applications = [usage("TLS", 4, 600), usage("QUIC", 2, 300), usage("DNS", 10, 80), usage("SSH", 1, 20)];
+        let summary = TrafficSummary {
+            timestamp: Local::now(),
+            interface_name: "all".to_string(),
+            total_packets: 170,
+            total_bytes: 1000,
+            protocols: HashMap::new(),
+            sample_rate: 1,
+            traffic_types: HashMap::new(),
+            top_connections: Vec::new(),
+        };
+
+        let analyzed = Period::parse("1h", Local::now()).unwrap();
+        let document = analysis_json("1h", "all", &analyzed, &summary, &applications, 2);
+        let listed: Vec<_> = document["applications"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| (a["protocol"].as_str().unwrap(), a["share_percent"].as_f64().unwrap()))
+            .collect();
+        assert_eq!(listed, vec![("TLS", 60.0), ("QUIC", 30.0)]);
+        assert_eq!(document["other_applications"]["protocols"], 2);
+        assert_eq!(document["other_applications"]["flows"], 11);
+        assert_eq!(document["other_applications"]["bytes"], 100);
+        assert_eq!(document["total_bytes"], 1000);
+    }
 }
\ No newline at end of file
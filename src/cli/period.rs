@@ -0,0 +1,274 @@
+// Time periods for `--period`
+// `kw analyze`, `kw report`, `kw shaping`, `kw export`, `kw graph` and the web UI's
+// `?period=` read their period with one parser, and spans such as `kw packets --capture`
+// and `kw graph --resolution` with `parse_span`:
+//
+// - a span ending now: "90s", "15m", "2h", "3d" or "2w", combined as in "1h30m"; "day",
+//   "week" and "month" (30 days) are spans too
+// - "today" or "yesterday", from local midnight
+// - a calendar day or month: "2024-01-15", "2024-01"
+// - a range "START..END": each end a day, a month, a local time such as "2024-01-01 14:00",
+//   a time with an offset such as "2024-01-01T14:00:00+02:00" or "2024-01-01T12:00Z", or a
+//   span meaning that long ago. A day or month as the end includes all of it, and an empty
+//   end means now, so "2024-01-01..2024-01-31" is the whole of January
+//
+// Times without an offset are local, like the timestamps in the database; times with one are
+// converted to local time. A local time skipped by a daylight saving change is moved past
+// the gap, and one that occurs twice is taken the first time. Periods never reach past now
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, FixedOffset, Local, LocalResult, Months, NaiveDate, NaiveDateTime, TimeZone};
+
+/// Local times read from `--period` ends, with and without seconds
+const LOCAL_TIME_FORMATS: [&str; 4] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"];
+
+/// Times with an offset; a trailing `Z` is read as +00:00
+const OFFSET_TIME_FORMATS: [&str; 4] =
+    ["%Y-%m-%d %H:%M:%S%:z", "%Y-%m-%d %H:%M%:z", "%Y-%m-%dT%H:%M:%S%:z", "%Y-%m-%dT%H:%M%:z"];
+
+/// A stretch of time a command reports on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Period {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    /// How the period was asked for, e.g. "last 2h" or "2024-01-01 00:00 → 2024-02-01 00:00"
+    pub label: String,
+}
+
+impl Period {
+    /// Reads a `--period` value at `now`
+    pub fn parse(value: &str, now: DateTime<Local>) -> Result<Self> {
+        let value = value.trim();
+        let lower = value.to_lowercase();
+
+        if let Some((start, end)) = value.split_once("..") {
+            let start = start.trim();
+            if start.is_empty() {
+                bail!("The range '{value}' has no start (expected e.g. 2024-01-01..2024-01-31)");
+            }
+            let start = parse_point(start, now, false).context("Invalid start of the range")?;
+            let end = match end.trim() {
+                "" => now,
+                end => parse_point(end, now, true).context("Invalid end of the range")?,
+            };
+            return Self::between(start, end, now, None);
+        }
+
+        match lower.as_str() {
+            "today" => return Self::between(local_midnight(now.date_naive())?, now, now, Some("today".to_string())),
+            "yesterday" => {
+                let today = now.date_naive();
+                let start = local_midnight(today.pred_opt().context("Date out of range")?)?;
+                return Self::between(start, local_midnight(today)?, now, Some("yesterday".to_string()));
+            }
+            _ => {}
+        }
+        if let Some((start, end)) = calendar_unit(value)? {
+            return Self::between(start, end, now, Some(value.to_string()));
+        }
+        match parse_span(&lower) {
+            Ok(span) => Self::between(now - span, now, now, Some(format!("last {value}"))),
+            Err(e) => Err(e).with_context(|| {
+                format!("Unrecognised period '{value}' (expected e.g. 15m, 2h, 3d, week, 2024-01 or 2024-01-01..2024-01-31)")
+            }),
+        }
+    }
+
+    /// A period from `start` to `end`, cut off at `now`
+    fn between(start: DateTime<Local>, end: DateTime<Local>, now: DateTime<Local>, label: Option<String>) -> Result<Self> {
+        let end = end.min(now);
+        if start >= end {
+            bail!("The period would start at {} but end at {}", format_time(start), format_time(end));
+        }
+        let label = label.unwrap_or_else(|| format!("{} → {}", format_time(start), format_time(end)));
+        Ok(Self { start, end, label })
+    }
+
+    pub fn length(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// The period of the same length just before this one
+    pub fn previous(&self) -> Self {
+        let start = self.start - self.length();
+        Self { start, end: self.start, label: format!("{} → {}", format_time(start), format_time(self.start)) }
+    }
+}
+
+/// Reads a span such as "90s", "15m", "2h", "3d", "2w" or "1h30m"; "day", "week" and
+/// "month" (30 days) are spans too
+pub fn parse_span(value: &str) -> Result<Duration> {
+    let value = value.trim().to_lowercase();
+    match value.as_str() {
+        "day" => return Ok(Duration::days(1)),
+        "week" => return Ok(Duration::weeks(1)),
+        "month" => return Ok(Duration::days(30)),
+        "" => bail!("Empty span"),
+        _ => {}
+    }
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if number.is_empty() {
+            bail!("Expected a number before '{c}' in '{value}'");
+        }
+        let count: i64 = number.parse().with_context(|| format!("Span '{value}' is too long"))?;
+        let part = match c {
+            's' => Duration::try_seconds(count),
+            'm' => Duration::try_minutes(count),
+            'h' => Duration::try_hours(count),
+            'd' => Duration::try_days(count),
+            'w' => Duration::try_weeks(count),
+            _ => bail!("Unknown unit '{c}' in '{value}' (expected s, m, h, d or w)"),
+        };
+        total = part.and_then(|part| total.checked_add(&part)).with_context(|| format!("Span '{value}' is too long"))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        bail!("'{value}' needs a unit after {number} (s, m, h, d or w)");
+    }
+    if total <= Duration::zero() {
+        bail!("Span '{value}' must be longer than zero");
+    }
+    Ok(total)
+}
+
+/// A point in time as the start of a range reads it, e.g. for `kw graph --from`: a day or
+/// month stands for its start, and a span for that long before `now`
+pub fn parse_time(value: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    parse_point(value.trim(), now, false)
+}
+
+/// One end of a range; a day or month as the end stands for its end
+fn parse_point(value: &str, now: DateTime<Local>, end: bool) -> Result<DateTime<Local>> {
+    if value.eq_ignore_ascii_case("now") {
+        return Ok(now);
+    }
+    if let Some((start, unit_end)) = calendar_unit(value)? {
+        return Ok(if end { unit_end } else { start });
+    }
+
+    let with_offset = match value.strip_suffix(['Z', 'z']) {
+        Some(utc) => format!("{utc}+00:00"),
+        None => value.to_string(),
+    };
+    if let Some(time) = OFFSET_TIME_FORMATS
+        .iter()
+        .find_map(|format| DateTime::<FixedOffset>::parse_from_str(&with_offset, format).ok())
+    {
+        return Ok(time.with_timezone(&Local));
+    }
+    if let Some(naive) = LOCAL_TIME_FORMATS.iter().find_map(|format| NaiveDateTime::parse_from_str(value, format).ok()) {
+        return local(naive);
+    }
+    match parse_span(value) {
+        Ok(ago) => Ok(now - ago),
+        Err(_) => bail!("Unrecognised time '{value}' (expected e.g. 2024-01-31, 2024-01, \"2024-01-31 14:30\" or 3d)"),
+    }
+}
+
+/// Start and end of the day "YYYY-MM-DD" or the month "YYYY-MM"; `None` for anything else
+fn calendar_unit(value: &str) -> Result<Option<(DateTime<Local>, DateTime<Local>)>> {
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let next = day.succ_opt().context("Date out of range")?;
+        return Ok(Some((local_midnight(day)?, local_midnight(next)?)));
+    }
+    if let Ok(first) = NaiveDate::parse_from_str(&format!("{value}-01"), "%Y-%m-%d") {
+        let next = first.checked_add_months(Months::new(1)).context("Date out of range")?;
+        return Ok(Some((local_midnight(first)?, local_midnight(next)?)));
+    }
+    Ok(None)
+}
+
+fn local_midnight(day: NaiveDate) -> Result<DateTime<Local>> {
+    local(day.and_hms_opt(0, 0, 0).context("Invalid date")?)
+}
+
+/// `naive` on the local clock: the earlier of two, or moved past a daylight saving gap
+fn local(naive: NaiveDateTime) -> Result<DateTime<Local>> {
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Ok(time),
+        LocalResult::None => Local
+            .from_local_datetime(&(naive + Duration::hours(1)))
+            .earliest()
+            .with_context(|| format!("{naive} does not exist in the local time zone")),
+    }
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Local> {
+        local(NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_spans_end_now() {
+        let now = at("2024-03-10 12:00");
+        assert_eq!(parse_span("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_span("1H30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_span("2w").unwrap(), Duration::days(14));
+        assert_eq!(parse_span("month").unwrap(), Duration::days(30));
+        for invalid in ["30", "3y", "h", "0m", ""] {
+            assert!(parse_span(invalid).is_err(), "{invalid}");
+        }
+
+        let period = Period::parse("3d", now).unwrap();
+        assert_eq!((period.start, period.end), (now - Duration::days(3), now));
+        assert_eq!(period.label, "last 3d");
+        assert_eq!(Period::parse("week", now).unwrap().length(), Duration::weeks(1));
+        let previous = period.previous();
+        assert_eq!((previous.start, previous.end), (now - Duration::days(6), now - Duration::days(3)));
+        assert!(Period::parse("soon", now).is_err());
+    }
+
+    #[test]
+    fn test_calendar_periods_and_ranges() {
+        let now = at("2024-03-10 12:00");
+        let january = Period::parse("2024-01-01..2024-01-31", now).unwrap();
+        assert_eq!((january.start, january.end), (at("2024-01-01 00:00"), at("2024-02-01 00:00")));
+        assert_eq!(Period::parse("2024-01", now).unwrap(), Period { label: "2024-01".to_string(), ..january });
+
+        let day = Period::parse("2024-02-29", now).unwrap();
+        assert_eq!((day.start, day.end), (at("2024-02-29 00:00"), at("2024-03-01 00:00")));
+        let yesterday = Period::parse("yesterday", now).unwrap();
+        assert_eq!((yesterday.start, yesterday.end), (at("2024-03-09 00:00"), at("2024-03-10 00:00")));
+        assert_eq!(Period::parse("today", now).unwrap().start, at("2024-03-10 00:00"));
+
+        // Open and relative ends, times of day, and ends past now cut off at now
+        let open = Period::parse("2024-03-01 08:30..", now).unwrap();
+        assert_eq!((open.start, open.end), (at("2024-03-01 08:30"), now));
+        let relative = Period::parse("3d..1d", now).unwrap();
+        assert_eq!((relative.start, relative.end), (now - Duration::days(3), now - Duration::days(1)));
+        assert_eq!(Period::parse("2024-03..2024-12", now).unwrap().end, now);
+
+        // Offsets are converted to local time
+        let utc = Period::parse("2024-01-01T00:00Z..2024-01-02T00:00:00+02:00", now).unwrap();
+        assert_eq!(utc.start, DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap());
+        assert_eq!(utc.end, DateTime::parse_from_rfc3339("2024-01-01T22:00:00Z").unwrap());
+
+        assert!(Period::parse("2024-02-01..2024-01-01", now).is_err());
+        assert!(Period::parse("..2024-01-01", now).is_err());
+        assert!(Period::parse("2025-01", now).is_err());
+    }
+
+    #[test]
+    fn test_points_in_time() {
+        let now = at("2024-03-10 12:00");
+        assert_eq!(parse_time("2024-03-01", now).unwrap(), at("2024-03-01 00:00"));
+        assert_eq!(parse_time(" 2024-03-01 14:30 ", now).unwrap(), at("2024-03-01 14:30"));
+        assert_eq!(parse_time("2w", now).unwrap(), now - Duration::weeks(2));
+        assert_eq!(parse_time("now", now).unwrap(), now);
+        assert!(parse_time("2", now).is_err());
+    }
+}
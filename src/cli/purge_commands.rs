@@ -0,0 +1,636 @@
+// CLI Purge Commands: Clean removal of everything kaipo-watcher leaves on disk
+// Locates databases, state files, generated reports and installed services
+// and removes them after explicit confirmation (or lists them in dry-run mode)
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::data_dir::{COUNTER_STATE_FILE, DATABASE_FILE, PROFILES_DIR};
+use crate::service::{LAUNCHD_LABEL, SERVICE_NAME};
+use crate::storage::encryption;
+
+/// File name prefixes used by the graph command for auto-named output files
+const REPORT_PREFIXES: &[&str] = &["bandwidth_", "protocols_", "connections_"];
+
+/// File extensions produced by the graph and export commands
+const REPORT_EXTENSIONS: &[&str] = &["png", "svg", "json", "csv", "html"];
+
+/// Registry key of the Windows service registration, removed with `sc.exe delete`
+const WINDOWS_SERVICE_KEY: &str = r"HKLM\SYSTEM\CurrentControlSet\Services\kaipo-watcher";
+
+/// Registry key of the service's Application event log source
+#[cfg(windows)]
+const WINDOWS_EVENT_SOURCE_KEY: &str = r"HKLM\SYSTEM\CurrentControlSet\Services\EventLog\Application\kaipo-watcher";
+
+/// Category of an item scheduled for removal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PurgeTargetKind {
+    /// SQLite database and its WAL/SHM companions
+    Database,
+    /// Any other file kept in the data directory
+    StateFile,
+    /// Graph or export file generated with a default name
+    Report,
+    /// Installed systemd unit or launchd plist
+    Service,
+    /// Windows service registration or event log source, a registry key rather than a file
+    Registration,
+    /// Database key kept in the OS keychain for a profile, named by the profile
+    Keychain,
+}
+
+impl PurgeTargetKind {
+    fn label(&self) -> &'static str {
+        match self {
+            PurgeTargetKind::Database => "database",
+            PurgeTargetKind::StateFile => "state",
+            PurgeTargetKind::Report => "report",
+            PurgeTargetKind::Service => "service",
+            PurgeTargetKind::Registration => "registry",
+            PurgeTargetKind::Keychain => "keychain",
+        }
+    }
+}
+
+/// A single file scheduled for removal
+#[derive(Debug, Clone)]
+pub struct PurgeTarget {
+    pub path: PathBuf,
+    pub kind: PurgeTargetKind,
+    pub size_bytes: u64,
+}
+
+/// Everything `kw purge` found: what it removes, and the other files in the data directories,
+/// which kw did not write and leaves alone
+#[derive(Debug, Default)]
+pub struct PurgePlan {
+    pub targets: Vec<PurgeTarget>,
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Command handler for `kw purge`
+///
+/// Collects every artifact the tool may have created and removes them after
+/// the user confirms. Nothing outside the known locations is touched: reports
+/// are only matched when they follow the graph command's default naming scheme
+/// (`<kind>_<interface>_<YYYYmmdd_HHMMSS>.<ext>`).
+pub struct PurgeCommandHandler {
+    /// Directories holding packet databases and state files, one per profile purged
+    data_dirs: Vec<PathBuf>,
+    /// Directory scanned for auto-named graph/export output
+    reports_dir: PathBuf,
+    /// Candidate service definition files (only existing ones are purged)
+    service_paths: Vec<PathBuf>,
+    /// Data directories the installed service records into (only existing ones are purged)
+    service_data_dirs: Vec<PathBuf>,
+    /// Profiles whose database key is removed from the keychain, if one is kept there
+    keychain_profiles: Vec<String>,
+}
+
+impl PurgeCommandHandler {
+    pub fn new<P: AsRef<Path>>(data_dir: P) -> Self {
+        Self {
+            data_dirs: vec![data_dir.as_ref().to_path_buf()],
+            reports_dir: PathBuf::from("."),
+            service_paths: default_service_paths(),
+            service_data_dirs: default_service_data_dirs(),
+            keychain_profiles: Vec::new(),
+        }
+    }
+
+    /// Purges each of `data_dirs`, e.g. those of every profile, instead of the one given to `new`
+    pub fn with_data_dirs(mut self, data_dirs: Vec<PathBuf>) -> Self {
+        self.data_dirs = data_dirs;
+        self
+    }
+
+    /// Overrides the directory scanned for generated reports
+    pub fn with_reports_dir<P: AsRef<Path>>(mut self, reports_dir: P) -> Self {
+        self.reports_dir = reports_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Overrides the service definition locations (used by tests)
+    pub fn with_service_paths(mut self, service_paths: Vec<PathBuf>) -> Self {
+        self.service_paths = service_paths;
+        self
+    }
+
+    /// Removes the database keys `[encryption] keychain` stored for `profiles`
+    pub fn with_keychain_profiles(mut self, profiles: Vec<String>) -> Self {
+        self.keychain_profiles = profiles;
+        self
+    }
+
+    /// Overrides the service data directories (used by tests)
+    pub fn with_service_data_dirs(mut self, service_data_dirs: Vec<PathBuf>) -> Self {
+        self.service_data_dirs = service_data_dirs;
+        self
+    }
+
+    pub async fn handle_purge_command(&self, dry_run: bool, assume_yes: bool) -> Result<()> {
+        let PurgePlan { targets, skipped } = self.collect_targets()?;
+
+        if !skipped.is_empty() {
+            println!("Left alone, as kaipo-watcher did not write them:");
+            for path in &skipped {
+                println!("  {}", path.display());
+            }
+            println!();
+        }
+
+        if targets.is_empty() {
+            println!("Nothing to purge - no kaipo-watcher data found.");
+            return Ok(());
+        }
+
+        let total_bytes: u64 = targets.iter().map(|t| t.size_bytes).sum();
+
+        println!("🧹 The following items will be removed:");
+        for target in &targets {
+            println!(
+                "  [{:<8}] {} ({})",
+                target.kind.label(),
+                target.path.display(),
+                crate::collectors::bandwidth_collector::format_bytes(target.size_bytes as f64)
+            );
+        }
+        println!(
+            "\nTotal: {} items, {}",
+            targets.len(),
+            crate::collectors::bandwidth_collector::format_bytes(total_bytes as f64)
+        );
+
+        if dry_run {
+            println!("\nDry run - nothing was removed.");
+            return Ok(());
+        }
+
+        if !assume_yes && !confirm("\nRemove these items? [y/N]: ")? {
+            println!("Purge cancelled.");
+            return Ok(());
+        }
+
+        let removed = self.remove_targets(&targets);
+        println!("\n✅ Removed {}/{} items", removed, targets.len());
+
+        if removed < targets.len() {
+            println!("Some items could not be removed. Run with RUST_LOG=warn for details.");
+        }
+
+        Ok(())
+    }
+
+    /// Gathers every purgeable item that currently exists on disk
+    pub fn collect_targets(&self) -> Result<PurgePlan> {
+        let mut plan = PurgePlan::default();
+
+        for data_dir in self.data_dirs.iter().filter(|dir| dir.is_dir()) {
+            collect_data_files(data_dir, &mut plan)?;
+        }
+
+        // The service's data is root's, so without root it is reported rather than purged
+        for data_dir in self.service_data_dirs.iter().filter(|dir| dir.is_dir()) {
+            if let Err(e) = collect_data_files(data_dir, &mut plan) {
+                warn!("{e:#}");
+                eprintln!("⚠️  Skipping {}: {e:#}; run with sudo to remove the service's data", data_dir.display());
+            }
+        }
+
+        if self.reports_dir.is_dir() {
+            for entry in fs::read_dir(&self.reports_dir)
+                .with_context(|| format!("Failed to read reports directory {}", self.reports_dir.display()))?
+            {
+                let path = entry?.path();
+                if path.is_file() && is_generated_report(&path) {
+                    plan.targets.push(make_target(path, PurgeTargetKind::Report));
+                }
+            }
+        }
+
+        for path in &self.service_paths {
+            if path.is_file() {
+                plan.targets.push(make_target(path.clone(), PurgeTargetKind::Service));
+            }
+        }
+
+        #[cfg(windows)]
+        for key in [WINDOWS_SERVICE_KEY, WINDOWS_EVENT_SOURCE_KEY] {
+            if registry_key_exists(key) {
+                plan.targets.push(PurgeTarget { path: PathBuf::from(key), kind: PurgeTargetKind::Registration, size_bytes: 0 });
+            }
+        }
+
+        for profile in &self.keychain_profiles {
+            // A keychain that cannot be read, e.g. without a Secret Service, holds no key of ours
+            match encryption::keychain_key(profile) {
+                Ok(Some(_)) => {
+                    plan.targets.push(PurgeTarget { path: PathBuf::from(profile), kind: PurgeTargetKind::Keychain, size_bytes: 0 })
+                }
+                Ok(None) => {}
+                Err(e) => debug!("No keychain key looked up for {profile}: {e:#}"),
+            }
+        }
+
+        plan.targets.sort_by(|a, b| a.path.cmp(&b.path));
+        plan.skipped.sort();
+        Ok(plan)
+    }
+
+    /// Removes the given targets, returning how many were actually deleted
+    fn remove_targets(&self, targets: &[PurgeTarget]) -> usize {
+        let mut removed = 0;
+
+        // Services go first, so a running one no longer holds its database open
+        let (registrations, files): (Vec<&PurgeTarget>, Vec<&PurgeTarget>) =
+            targets.iter().partition(|target| target.kind == PurgeTargetKind::Registration);
+        let (keys, files): (Vec<&PurgeTarget>, Vec<&PurgeTarget>) =
+            files.into_iter().partition(|target| target.kind == PurgeTargetKind::Keychain);
+        for target in registrations {
+            match remove_registration(&target.path) {
+                Ok(()) => {
+                    debug!("Removed {}", target.path.display());
+                    removed += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to remove {}: {e:#}", target.path.display());
+                    eprintln!("  ✗ {}: {e:#}", target.path.display());
+                }
+            }
+        }
+
+        let mut databases_left = false;
+        for target in files {
+            if target.kind == PurgeTargetKind::Service {
+                unload_service(&target.path);
+            }
+
+            match fs::remove_file(&target.path) {
+                Ok(()) => {
+                    debug!("Removed {}", target.path.display());
+                    removed += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to remove {}: {}", target.path.display(), e);
+                    eprintln!("  ✗ {}: {}", target.path.display(), e);
+                    databases_left |= target.kind == PurgeTargetKind::Database;
+                }
+            }
+        }
+
+        // Keys go last, and not while a database they may unlock is still there
+        for target in keys {
+            let profile = target.path.to_string_lossy();
+            if databases_left {
+                eprintln!("  ✗ keychain key of {profile}: kept, as a database could not be removed");
+                continue;
+            }
+            match encryption::remove_keychain_key(&profile) {
+                Ok(_) => {
+                    debug!("Removed the keychain key of {profile}");
+                    removed += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to remove the keychain key of {profile}: {e:#}");
+                    eprintln!("  ✗ keychain key of {profile}: {e:#}");
+                }
+            }
+        }
+
+        // Drop the data directories themselves once they are empty, named profiles before the
+        // default one they live in, and the directory of profiles once the last one is gone
+        let mut data_dirs: Vec<&PathBuf> = self.data_dirs.iter().collect();
+        data_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for data_dir in data_dirs {
+            remove_empty_dir(data_dir);
+            if let Some(profiles) = data_dir.parent().filter(|parent| parent.ends_with(PROFILES_DIR)) {
+                remove_empty_dir(profiles);
+            }
+        }
+        // The service keeps its data in a `data` directory of its own, e.g. /var/lib/kaipo-watcher/data
+        for data_dir in &self.service_data_dirs {
+            remove_empty_dir(data_dir);
+            if let Some(service_dir) = data_dir.parent().filter(|parent| parent.ends_with(SERVICE_NAME)) {
+                remove_empty_dir(service_dir);
+            }
+        }
+
+        removed
+    }
+}
+
+/// Adds the files kw keeps in a data directory; any others are only noted as skipped, as
+/// `[data] dir` may point at a directory shared with other programs
+fn collect_data_files(data_dir: &Path, plan: &mut PurgePlan) -> Result<()> {
+    for entry in fs::read_dir(data_dir).with_context(|| format!("Failed to read data directory {}", data_dir.display()))? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        match data_file_kind(&path) {
+            Some(kind) => plan.targets.push(make_target(path, kind)),
+            None => plan.skipped.push(path),
+        }
+    }
+    Ok(())
+}
+
+fn remove_empty_dir(dir: &Path) {
+    if dir.is_dir() && fs::remove_dir(dir).is_ok() {
+        info!("Removed data directory {}", dir.display());
+    }
+}
+
+fn make_target(path: PathBuf, kind: PurgeTargetKind) -> PurgeTarget {
+    let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    PurgeTarget { path, kind, size_bytes }
+}
+
+/// What a file in a data directory is to kw: the packet database with the journals SQLite
+/// keeps beside it and the copy `kw encryption` converts into, or the daemon's counter state
+fn data_file_kind(path: &Path) -> Option<PurgeTargetKind> {
+    let name = path.file_name()?.to_str()?;
+    if let Some(rest) = name.strip_prefix(DATABASE_FILE) {
+        let rest = rest.strip_prefix(".converting").unwrap_or(rest);
+        return matches!(rest, "" | "-wal" | "-shm" | "-journal").then_some(PurgeTargetKind::Database);
+    }
+    // The state file is written to a .tmp sibling and renamed over, so a crash can leave one
+    let temporary = Path::new(COUNTER_STATE_FILE).with_extension("tmp");
+    (name == COUNTER_STATE_FILE || Path::new(name) == temporary).then_some(PurgeTargetKind::StateFile)
+}
+
+/// Matches files named by the graph command, e.g. `bandwidth_all_20250128_143000.png`
+/// or `bandwidth_eth0_20250128_143000_speed.png`
+fn is_generated_report(path: &Path) -> bool {
+    let (Some(stem), Some(ext)) = (
+        path.file_stem().and_then(|s| s.to_str()),
+        path.extension().and_then(|e| e.to_str()),
+    ) else {
+        return false;
+    };
+
+    if !REPORT_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+        return false;
+    }
+    if !REPORT_PREFIXES.iter().any(|prefix| stem.starts_with(prefix)) {
+        return false;
+    }
+
+    let stem = stem
+        .strip_suffix("_speed")
+        .or_else(|| stem.strip_suffix("_total"))
+        .unwrap_or(stem);
+    let parts: Vec<&str> = stem.rsplitn(3, '_').collect();
+
+    parts.len() == 3
+        && parts[0].len() == 6
+        && parts[1].len() == 8
+        && parts[0].chars().all(|c| c.is_ascii_digit())
+        && parts[1].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Locations where `kw` service definitions may be installed on this platform
+pub fn default_service_paths() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut paths = Vec::new();
+
+    if cfg!(target_os = "linux") {
+        paths.push(PathBuf::from(format!("/etc/systemd/system/{SERVICE_NAME}.service")));
+        if let Some(home) = &home {
+            paths.push(home.join(format!(".config/systemd/user/{SERVICE_NAME}.service")));
+        }
+    } else if cfg!(target_os = "macos") {
+        paths.push(PathBuf::from(format!("/Library/LaunchDaemons/{LAUNCHD_LABEL}.plist")));
+        if let Some(home) = &home {
+            paths.push(home.join(format!("Library/LaunchAgents/{LAUNCHD_LABEL}.plist")));
+        }
+    }
+
+    paths
+}
+
+/// Data directories `kw service install` points the service at by default on this platform,
+/// matching `default_data_dir` of the systemd and launchd modules
+pub fn default_service_data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if cfg!(target_os = "linux") {
+        dirs.push(PathBuf::from("/var/lib").join(SERVICE_NAME).join("data"));
+    } else if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/Library/Application Support").join(SERVICE_NAME).join("data"));
+    } else if cfg!(windows) {
+        let program_data = std::env::var_os("ProgramData").map_or_else(|| PathBuf::from(r"C:\ProgramData"), PathBuf::from);
+        dirs.push(program_data.join(SERVICE_NAME).join("data"));
+    }
+
+    dirs
+}
+
+/// Whether `reg.exe` finds `key`
+#[cfg(windows)]
+fn registry_key_exists(key: &str) -> bool {
+    Command::new("reg")
+        .args(["query", key])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Deletes the Windows service, stopping it first, or the event log source
+fn remove_registration(key: &Path) -> Result<()> {
+    let output = if key == Path::new(WINDOWS_SERVICE_KEY) {
+        // Fails when the service is already stopped, which is fine here
+        if let Err(e) = Command::new("sc.exe").args(["stop", SERVICE_NAME]).output() {
+            debug!("Failed to stop {SERVICE_NAME}: {e}");
+        }
+        // sc.exe reports failures on stdout
+        Command::new("sc.exe").args(["delete", SERVICE_NAME]).output().context("Failed to run sc.exe")?
+    } else {
+        Command::new("reg")
+            .arg("delete")
+            .arg(key)
+            .arg("/f")
+            .output()
+            .context("Failed to run reg.exe")?
+    };
+    if !output.status.success() {
+        let message = [output.stdout, output.stderr].concat();
+        bail!("{}", String::from_utf8_lossy(&message).trim());
+    }
+    Ok(())
+}
+
+/// Stops and disables a service before its definition file is deleted
+/// Failures are logged but never abort the purge
+fn unload_service(path: &Path) {
+    let result = if cfg!(target_os = "linux") {
+        let mut cmd = Command::new("systemctl");
+        if path.starts_with("/etc") {
+            cmd.args(["disable", "--now", &format!("{SERVICE_NAME}.service")]);
+        } else {
+            cmd.args(["--user", "disable", "--now", &format!("{SERVICE_NAME}.service")]);
+        }
+        cmd.output()
+    } else if cfg!(target_os = "macos") {
+        Command::new("launchctl").arg("unload").arg(path).output()
+    } else {
+        return;
+    };
+
+    match result {
+        Ok(output) if output.status.success() => info!("Stopped service defined in {}", path.display()),
+        Ok(output) => warn!(
+            "Service stop for {} exited with {}: {}",
+            path.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => warn!("Failed to stop service defined in {}: {}", path.display(), e),
+    }
+}
+
+pub fn confirm(prompt: &str) -> Result<bool> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generated_report_matching() {
+        assert!(is_generated_report(Path::new("bandwidth_all_20250128_143000.png")));
+        assert!(is_generated_report(Path::new("protocols_eth0_20250128_143000.csv")));
+        assert!(is_generated_report(Path::new("bandwidth_en0_20250128_143000_speed.png")));
+        assert!(!is_generated_report(Path::new("bandwidth_notes.txt")));
+        assert!(!is_generated_report(Path::new("bandwidth_report.png")));
+        assert!(!is_generated_report(Path::new("holiday_20250128_143000.png")));
+    }
+
+    #[test]
+    fn test_collect_targets() {
+        let data_dir = tempdir().unwrap();
+        let reports_dir = tempdir().unwrap();
+        let service_dir = tempdir().unwrap();
+
+        fs::write(data_dir.path().join("packets.db"), b"db").unwrap();
+        fs::write(data_dir.path().join("packets.db-wal"), b"wal").unwrap();
+        fs::write(data_dir.path().join("packets.db-journal"), b"journal").unwrap();
+        fs::write(data_dir.path().join("counter-state.tmp"), b"{}").unwrap();
+        fs::write(data_dir.path().join("state.json"), b"{}").unwrap();
+        fs::write(data_dir.path().join("other.db"), b"db").unwrap();
+        fs::write(reports_dir.path().join("connections_all_20250128_143000.json"), b"[]").unwrap();
+        fs::write(reports_dir.path().join("unrelated.png"), b"png").unwrap();
+        let unit = service_dir.path().join("kaipo-watcher.service");
+        fs::write(&unit, b"[Unit]").unwrap();
+
+        let service_data = service_dir.path().join(SERVICE_NAME).join("data");
+        fs::create_dir_all(&service_data).unwrap();
+        fs::write(service_data.join("packets.db"), b"db").unwrap();
+        fs::write(service_data.join("counter-state.json"), b"{}").unwrap();
+
+        let handler = PurgeCommandHandler::new(data_dir.path())
+            .with_reports_dir(reports_dir.path())
+            .with_service_paths(vec![unit, service_dir.path().join("missing.plist")])
+            .with_service_data_dirs(vec![service_data, service_dir.path().join("missing")]);
+
+        let PurgePlan { targets, mut skipped } = handler.collect_targets().unwrap();
+        let count = |kind| targets.iter().filter(|t| t.kind == kind).count();
+
+        assert_eq!(targets.len(), 8);
+        assert_eq!(count(PurgeTargetKind::Database), 4);
+        assert_eq!(count(PurgeTargetKind::StateFile), 2);
+        assert_eq!(count(PurgeTargetKind::Report), 1);
+        assert_eq!(count(PurgeTargetKind::Service), 1);
+
+        skipped.sort();
+        assert_eq!(skipped, vec![data_dir.path().join("other.db"), data_dir.path().join("state.json")]);
+    }
+
+    #[tokio::test]
+    async fn test_purge_leaves_unknown_files() {
+        let data_dir = tempdir().unwrap();
+        let db = data_dir.path().join("packets.db");
+        let notes = data_dir.path().join("notes.txt");
+        fs::write(&db, b"db").unwrap();
+        fs::write(&notes, b"mine").unwrap();
+
+        let handler = PurgeCommandHandler::new(data_dir.path())
+            .with_reports_dir(data_dir.path().join("reports"))
+            .with_service_paths(Vec::new())
+            .with_service_data_dirs(Vec::new());
+
+        handler.handle_purge_command(false, true).await.unwrap();
+        assert!(!db.exists());
+        assert!(notes.exists());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_keeps_files() {
+        let data_dir = tempdir().unwrap();
+        let reports_dir = tempdir().unwrap();
+        let db = data_dir.path().join("packets.db");
+        fs::write(&db, b"db").unwrap();
+
+        let handler = PurgeCommandHandler::new(data_dir.path())
+            .with_reports_dir(reports_dir.path())
+            .with_service_paths(Vec::new())
+            .with_service_data_dirs(Vec::new());
+
+        handler.handle_purge_command(true, true).await.unwrap();
+        assert!(db.exists());
+    }
+
+    #[tokio::test]
+    async fn test_purge_removes_files() {
+        let root = tempdir().unwrap();
+        let data_dir = root.path().join("data");
+        fs::create_dir(&data_dir).unwrap();
+        fs::write(data_dir.join("packets.db"), b"db").unwrap();
+        let report = root.path().join("bandwidth_all_20250128_143000.csv");
+        fs::write(&report, b"csv").unwrap();
+        let service_dir = root.path().join("lib").join(SERVICE_NAME);
+        fs::create_dir_all(service_dir.join("data")).unwrap();
+        fs::write(service_dir.join("data").join("packets.db"), b"db").unwrap();
+
+        let handler = PurgeCommandHandler::new(&data_dir)
+            .with_reports_dir(root.path())
+            .with_service_paths(Vec::new())
+            .with_service_data_dirs(vec![service_dir.join("data")]);
+
+        handler.handle_purge_command(false, true).await.unwrap();
+        assert!(!data_dir.exists());
+        assert!(!report.exists());
+        assert!(!service_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_purge_all_profiles() {
+        let root = tempdir().unwrap();
+        let work = root.path().join(PROFILES_DIR).join("work");
+        fs::create_dir_all(&work).unwrap();
+        fs::write(root.path().join("packets.db"), b"db").unwrap();
+        fs::write(work.join("packets.db"), b"db").unwrap();
+        fs::write(work.join("counter-state.json"), b"{}").unwrap();
+
+        let handler = PurgeCommandHandler::new(root.path())
+            .with_data_dirs(vec![root.path().to_path_buf(), work.clone()])
+            .with_reports_dir(root.path().join("reports"))
+            .with_service_paths(Vec::new())
+            .with_service_data_dirs(Vec::new());
+        assert_eq!(handler.collect_targets().unwrap().targets.len(), 3);
+
+        handler.handle_purge_command(false, true).await.unwrap();
+        assert!(!root.path().join(PROFILES_DIR).exists());
+        assert!(!root.path().exists());
+    }
+}
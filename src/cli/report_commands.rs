@@ -0,0 +1,779 @@
+// CLI Report Commands: Usage reports for a day, week, month or any other period
+// Summarizes captured traffic from the packet database, lists unusual bandwidth against
+// the hourly baselines, and qualifies it with a data-quality section, so readers can
+// tell how far the numbers can be trusted. With `--compare previous` it also contrasts
+// the period with the one before it, per interface and protocol. Where ping statistics
+// were recorded, a congestion section shows how far latency rose under load. With `--geo`
+// it sums the traffic with public addresses by country and autonomous system. A forecast
+// section projects the billing period's usage from the stored history; `kw quota` shows
+// the same forecast against the `[quota]` allowance. With `--style vnstat` the report is
+// vnStat's daily and monthly tables instead, as is `kw status --style vnstat` its summary
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Local};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::analyzers::anomaly::{episodes, find_anomalies};
+use crate::analyzers::congestion::{self, BufferbloatGrade};
+use crate::analyzers::forecast::{self, UsageForecast};
+use crate::analyzers::geo_traffic::{self, GeoTotal};
+use crate::cli::period::Period;
+use crate::cli::vnstat_style::{self, OutputStyle};
+use crate::collectors::bandwidth_collector::CalculationConfidence;
+use crate::collectors::interface_selection::InterfaceSelection;
+use crate::collectors::tunnels::detect_tunnels;
+use crate::config::{AnomalyConfig, CongestionConfig, GeoIpConfig, QuotaConfig};
+use crate::display::Units;
+use crate::enrichment::GeoIpDatabases;
+use crate::storage::packet_storage::{BandwidthSample, DataQualitySummary, UsageSummary};
+use crate::storage::PacketStorage;
+
+/// Busiest hosts listed in a report
+const REPORT_HOSTS: usize = 5;
+
+/// Unusual bandwidth episodes listed in a report
+const REPORT_ANOMALIES: usize = 10;
+
+/// Congestion episodes listed in a report
+const REPORT_CONGESTION: usize = 10;
+
+/// Countries and autonomous systems listed with `--geo`
+const REPORT_GEO: usize = 10;
+
+/// Interfaces and protocols listed in a comparison with the previous period
+const REPORT_CHANGES: usize = 10;
+
+/// Days of stored history before the billing period the forecast also learns from
+const FORECAST_HISTORY_DAYS: i64 = 28;
+
+/// What a report's period can be compared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportComparison {
+    /// The period of the same length just before, e.g. last week for this week
+    Previous,
+}
+
+impl ReportComparison {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "previous" | "prev" => Ok(Self::Previous),
+            other => bail!("Unsupported comparison '{other}' (expected previous)"),
+        }
+    }
+}
+
+/// One interface's or protocol's traffic in the report period and the period before
+#[derive(Debug, Clone, PartialEq)]
+struct Change {
+    name: String,
+    previous: u64,
+    current: u64,
+}
+
+impl Change {
+    fn delta(&self) -> i64 {
+        self.current as i64 - self.previous as i64
+    }
+
+    /// Change relative to the previous period, in percent; `None` when there was no traffic before
+    fn percent(&self) -> Option<f64> {
+        (self.previous > 0).then(|| self.delta() as f64 / self.previous as f64 * 100.0)
+    }
+}
+
+/// Command handler for `kw report`
+pub struct ReportCommandHandler {
+    storage: Arc<PacketStorage>,
+    anomaly: AnomalyConfig,
+    congestion: CongestionConfig,
+    geoip: GeoIpConfig,
+    quota: QuotaConfig,
+    units: Units,
+}
+
+impl ReportCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self {
+            storage,
+            anomaly: AnomalyConfig::default(),
+            congestion: CongestionConfig::default(),
+            geoip: GeoIpConfig::default(),
+            quota: QuotaConfig::default(),
+            units: Units::default(),
+        }
+    }
+
+    /// Sensitivity and history of the unusual bandwidth section
+    pub fn with_anomaly_config(mut self, config: AnomalyConfig) -> Self {
+        self.anomaly = config;
+        self
+    }
+
+    /// When rising latency under load counts as congestion
+    pub fn with_congestion_config(mut self, config: CongestionConfig) -> Self {
+        self.congestion = config;
+        self
+    }
+
+    /// GeoIP databases and expected countries of the `--geo` section
+    pub fn with_geoip_config(mut self, config: GeoIpConfig) -> Self {
+        self.geoip = config;
+        self
+    }
+
+    /// Allowance and billing period the forecast is made for
+    pub fn with_quota_config(mut self, config: QuotaConfig) -> Self {
+        self.quota = config;
+        self
+    }
+
+    /// Rate unit and number separators of the report
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub async fn handle_report_command(
+        &self,
+        period: &str,
+        app_breakdown: bool,
+        geo: bool,
+        compare: Option<ReportComparison>,
+        style: OutputStyle,
+    ) -> Result<()> {
+        let period = Period::parse(period, Local::now())?;
+        let (start, end) = (period.start, period.end);
+
+        if style == OutputStyle::Vnstat {
+            let usage = vnstat_style::usage_by_day(&self.storage.get_bandwidth_samples(start, end)?);
+            print!("{}", vnstat_style::daily_and_monthly(&usage, start, end, end));
+            if usage.is_empty() {
+                println!("No bandwidth samples recorded; they are recorded by `kw service run`");
+            }
+            return Ok(());
+        }
+
+        println!("📊 Usage Report - {}", period.label);
+        println!("   {} → {}", start.format("%Y-%m-%d %H:%M"), end.format("%Y-%m-%d %H:%M"));
+        println!();
+
+        let usage = self.storage.get_usage_summary(start, end)?;
+        println!("📦 Usage:");
+        let units = &self.units;
+        println!("   Connections: {}", units.number(usage.connections as f64, 0));
+        println!("   Packets:     {}", units.number(usage.packets as f64, 0));
+        println!("   Traffic:     {}", units.bytes(usage.bytes as f64));
+
+        if !usage.hosts.is_empty() {
+            let tags = self.storage.tag_book()?;
+            println!();
+            println!("🖥️  By host:");
+            for (address, packets, bytes) in usage.hosts.iter().take(REPORT_HOSTS) {
+                println!(
+                    "   {:<40} {:>10}  ({} packets)",
+                    tags.describe(address),
+                    units.bytes(*bytes as f64),
+                    units.number(*packets as f64, 0)
+                );
+            }
+        }
+
+        if app_breakdown {
+            println!();
+            println!("🧩 By application:");
+            if usage.applications.is_empty() {
+                println!("   No traffic recorded");
+            }
+            for (application, packets, bytes) in &usage.applications {
+                let share = if usage.bytes == 0 { 0.0 } else { *bytes as f64 / usage.bytes as f64 * 100.0 };
+                println!(
+                    "   {:<12} {:>10} {:>5}%  ({} packets)",
+                    application,
+                    units.bytes(*bytes as f64),
+                    units.number(share, 1),
+                    units.number(*packets as f64, 0)
+                );
+            }
+        }
+
+        if geo {
+            println!();
+            self.print_geo(start, end)?;
+        }
+
+        if let Some(ReportComparison::Previous) = compare {
+            println!();
+            self.print_comparison(&period, &usage)?;
+        }
+
+        println!();
+        self.print_anomalies(start, end)?;
+        println!();
+        self.print_congestion(start, end)?;
+        println!();
+        self.print_forecast(Local::now())?;
+        println!();
+        self.print_data_quality(start, end)?;
+        Ok(())
+    }
+
+    /// Usage of the current billing period against the allowance, and where it is heading
+    pub async fn handle_quota_command(&self) -> Result<()> {
+        let now = Local::now();
+        let start = forecast::period_start(now, self.quota.reset_day);
+        let end = forecast::period_end(start, self.quota.reset_day);
+        let used = self.period_usage(start, now)?;
+        let units = &self.units;
+
+        println!("📅 Quota - billing period {} → {}", start.format("%Y-%m-%d"), end.format("%Y-%m-%d"));
+        println!();
+        match self.limit_bytes() {
+            Some(limit) => {
+                let days_left = (end - now).num_seconds() as f64 / 86_400.0;
+                println!(
+                    "   Used:      {} of {} ({}%)",
+                    units.bytes(used),
+                    units.bytes(limit),
+                    units.number(used / limit * 100.0, 1)
+                );
+                println!("   Left:      {} over {}", units.bytes((limit - used).max(0.0)), format_duration(days_left * 86_400.0));
+                if used < limit && days_left > 0.0 {
+                    println!("   Per day:   {} keeps within the quota", units.bytes((limit - used) / days_left.max(1.0)));
+                }
+            }
+            None => {
+                println!("   Used:      {}", units.bytes(used));
+                println!("   No allowance set - add [quota] monthly_gb to the configuration");
+            }
+        }
+        println!();
+        self.print_forecast(now)
+    }
+
+    /// vnStat's summary of last and this month, yesterday and today, for `kw status --style vnstat`
+    pub async fn handle_vnstat_status(&self, interfaces: Option<&InterfaceSelection>) -> Result<()> {
+        let now = Local::now();
+        let since = forecast::period_start(now, 1) - Duration::days(31);
+        let mut samples = self.storage.get_bandwidth_samples(since, now)?;
+        if let Some(interfaces) = interfaces {
+            samples.retain(|sample| interfaces.matches(&sample.interface_name));
+        }
+        let usage = vnstat_style::usage_by_day(&samples);
+        if usage.is_empty() {
+            println!("No bandwidth samples recorded; they are recorded by `kw service run`");
+            return Ok(());
+        }
+        print!("{}", vnstat_style::summary(&usage, now));
+        Ok(())
+    }
+
+    /// Projected usage at the end of the billing period containing `now`, with its bounds
+    /// and, given an allowance, the day it is expected to run out
+    fn print_forecast(&self, now: DateTime<Local>) -> Result<()> {
+        let start = forecast::period_start(now, self.quota.reset_day);
+        let end = forecast::period_end(start, self.quota.reset_day);
+        let samples = self.storage.get_bandwidth_samples(start - Duration::days(FORECAST_HISTORY_DAYS), now)?;
+
+        println!("🔮 Forecast - billing period ending {}:", end.format("%Y-%m-%d"));
+        let Some(forecast) = forecast::forecast(&daily_usage(&samples), start, end, now) else {
+            println!("   Needs {} complete days of bandwidth samples", forecast::MIN_HISTORY_DAYS);
+            println!("   Samples are recorded by the background service (`kw service run`)");
+            return Ok(());
+        };
+        self.describe_forecast(&forecast);
+        Ok(())
+    }
+
+    fn describe_forecast(&self, forecast: &UsageForecast) {
+        let units = &self.units;
+        println!("   So far:    {}", units.bytes(forecast.used_bytes));
+        println!(
+            "   Projected: {} (95%: {} - {})",
+            units.bytes(forecast.projected_bytes),
+            units.bytes(forecast.low_bytes),
+            units.bytes(forecast.high_bytes)
+        );
+        println!(
+            "   Trend:     {}{} per day over {} days{}",
+            if forecast.trend_bytes_per_day < 0.0 { "-" } else { "+" },
+            units.bytes(forecast.trend_bytes_per_day.abs()),
+            forecast.history_days,
+            if forecast.seasonal { ", with weekday differences" } else { "" }
+        );
+
+        let Some(limit) = self.limit_bytes() else {
+            return;
+        };
+        println!(
+            "   Quota:     {}% of {} projected",
+            units.number(forecast.projected_bytes / limit * 100.0, 0),
+            units.bytes(limit)
+        );
+        match forecast.limit_reached_on(limit) {
+            Some(day) => println!("   ⚠️  Expected to run out on {}", day.format("%Y-%m-%d")),
+            None if forecast.high_bytes > limit => {
+                println!("   Expected to stay within the quota, though the upper bound exceeds it")
+            }
+            None => println!("   Expected to stay within the quota"),
+        }
+    }
+
+    /// Bytes moved on the counted interfaces from `start` to `end`
+    fn period_usage(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<f64> {
+        let samples = self.storage.get_bandwidth_samples(start, end)?;
+        Ok(daily_usage(&samples).iter().map(|(_, bytes)| bytes).sum())
+    }
+
+    fn limit_bytes(&self) -> Option<f64> {
+        self.quota.monthly_gb.filter(|gb| *gb > 0.0).map(|gb| gb * 1e9)
+    }
+
+    /// Totals, interfaces and protocols of the period against the one of the same length
+    /// before it, with the change in bytes and percent
+    fn print_comparison(&self, period: &Period, usage: &UsageSummary) -> Result<()> {
+        let before = period.previous();
+        let (start, end) = (period.start, period.end);
+        let previous = self.storage.get_usage_summary(before.start, before.end)?;
+        let units = &self.units;
+        println!("🔀 Compared with the period before ({}):", before.label);
+        // (name, previous, current, whether the values are byte counts)
+        let totals = [
+            ("Traffic", previous.bytes, usage.bytes, true),
+            ("Connections", previous.connections, usage.connections, false),
+            ("Packets", previous.packets, usage.packets, false),
+        ];
+        for (name, previous, current, is_bytes) in totals {
+            let change = Change { name: name.to_string(), previous, current };
+            let format = |value: u64| {
+                if is_bytes { units.bytes(value as f64) } else { units.number(value as f64, 0) }
+            };
+            let delta = format(change.delta().unsigned_abs());
+            println!(
+                "   {:<12} {} → {}  ({}{delta}, {})",
+                format!("{name}:"),
+                format(previous),
+                format(current),
+                if change.delta() < 0 { "-" } else { "+" },
+                self.format_percent(&change)
+            );
+        }
+
+        let samples = self.storage.get_bandwidth_samples(before.start, end)?;
+        let (previous_samples, current_samples): (Vec<_>, Vec<_>) =
+            samples.into_iter().partition(|sample| sample.timestamp <= start);
+        println!();
+        println!("   By interface:");
+        if previous_samples.is_empty() && current_samples.is_empty() {
+            println!("     No bandwidth samples recorded");
+            println!("     Samples are recorded by the background service (`kw service run`)");
+        } else {
+            let tags = self.storage.tag_book()?;
+            let changes = compare_usage(&interface_bytes(&previous_samples), &interface_bytes(&current_samples));
+            let tunnels = detect_tunnels(changes.iter().map(|change| change.name.as_str()));
+            self.print_changes(&changes, |name| match tunnels.iter().find(|tunnel| tunnel.name == name) {
+                Some(tunnel) => format!("{} ({tunnel})", tags.describe(name)),
+                None => tags.describe(name),
+            });
+            // The transport's bytes include the tunnel's, encrypted
+            if tunnels.iter().any(|tunnel| {
+                tunnel.transport.as_ref().is_some_and(|transport| changes.iter().any(|change| &change.name == transport))
+            }) {
+                println!("     Tunnel traffic is counted again, encrypted, in the interface it goes over");
+            }
+        }
+
+        let protocol_bytes = |usage: &UsageSummary| -> Vec<(String, u64)> {
+            usage.applications.iter().map(|(name, _, bytes)| (name.clone(), *bytes)).collect()
+        };
+        println!();
+        println!("   By protocol:");
+        if previous.applications.is_empty() && usage.applications.is_empty() {
+            println!("     No traffic recorded");
+        } else {
+            self.print_changes(
+                &compare_usage(&protocol_bytes(&previous), &protocol_bytes(usage)),
+                str::to_string,
+            );
+        }
+        Ok(())
+    }
+
+    fn print_changes(&self, changes: &[Change], describe: impl Fn(&str) -> String) {
+        let units = &self.units;
+        for change in changes.iter().take(REPORT_CHANGES) {
+            println!(
+                "     {:<26} {:>10} → {:>10}  {}{:>10}  {:>7}",
+                describe(&change.name),
+                units.bytes(change.previous as f64),
+                units.bytes(change.current as f64),
+                if change.delta() < 0 { "-" } else { "+" },
+                units.bytes(change.delta().unsigned_abs() as f64),
+                self.format_percent(change)
+            );
+        }
+        if changes.len() > REPORT_CHANGES {
+            println!("     ... and {} more", changes.len() - REPORT_CHANGES);
+        }
+    }
+
+    /// "+12.5%", or "new" for traffic that had none in the previous period
+    fn format_percent(&self, change: &Change) -> String {
+        match change.percent() {
+            Some(percent) => format!(
+                "{}{}%",
+                if percent > 0.0 { "+" } else { "" },
+                self.units.number(percent, 1)
+            ),
+            None if change.current > 0 => "new".to_string(),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Episodes of unusual bandwidth in the period, each reading judged against the
+    /// baselines learned from the history before it
+    fn print_anomalies(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+        let history_start = start - Duration::days(self.anomaly.history_days.into());
+        let samples = self.storage.get_bandwidth_samples(history_start, end)?;
+
+        println!("📈 Unusual Bandwidth:");
+        if samples.is_empty() {
+            println!("   No bandwidth samples recorded");
+            println!("   Samples are recorded by the background service (`kw service run`)");
+            return Ok(());
+        }
+
+        let episodes = episodes(&find_anomalies(self.anomaly.clone(), &samples, start));
+        if episodes.is_empty() {
+            println!("   None - traffic stayed within the usual range for each hour");
+            return Ok(());
+        }
+        let tags = self.storage.tag_book()?;
+        for episode in episodes.iter().take(REPORT_ANOMALIES) {
+            let peak = &episode.peak;
+            println!(
+                "   {} - {}  {:<15} {:<5} {} ({}, usually {})",
+                episode.start.format("%m-%d %H:%M"),
+                episode.end.format("%H:%M"),
+                tags.describe(&peak.interface),
+                peak.kind(),
+                self.units.rate(peak.bytes_per_sec),
+                peak.describe(),
+                self.units.rate(peak.expected_bytes_per_sec)
+            );
+        }
+        if episodes.len() > REPORT_ANOMALIES {
+            println!("   ... and {} more", episodes.len() - REPORT_ANOMALIES);
+        }
+        Ok(())
+    }
+
+    /// Latency under load against idle per ping target, and the periods it rose furthest
+    fn print_congestion(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+        let pings = self.storage.get_ping_samples(start, end)?;
+
+        println!("🚦 Congestion:");
+        if pings.is_empty() {
+            println!("   No ping statistics recorded");
+            println!("   They are recorded by `kw service run` and `kw live` while [ping] targets are set");
+            return Ok(());
+        }
+        let targets = congestion::analyze(&self.congestion, &self.storage.get_bandwidth_samples(start, end)?, &pings);
+        if targets.is_empty() {
+            println!("   No bandwidth samples recorded alongside the ping statistics");
+            return Ok(());
+        }
+
+        let tags = self.storage.tag_book()?;
+        let ms = |value: Option<f64>| value.map_or("-".to_string(), |ms| format!("{ms:.1} ms"));
+        for target in &targets {
+            let mut line = format!(
+                "   {:<20} idle {:>9}  under load {:>9}",
+                tags.describe(&target.target),
+                ms(target.idle_latency_ms),
+                ms(target.loaded_latency_ms)
+            );
+            match (target.added_latency_ms(), target.grade()) {
+                (Some(added), Some(grade)) => line.push_str(&format!(" (+{added:.1} ms, grade {grade})")),
+                _ => line.push_str(" (no minutes under load)"),
+            }
+            if let Some(correlation) = target.correlation {
+                line.push_str(&format!("  correlation {correlation:.2}"));
+            }
+            println!("{line}");
+        }
+
+        let congested: Vec<_> = targets.iter().flat_map(|t| t.congested.iter().cloned()).collect();
+        let episodes = congestion::episodes(&congested);
+        for episode in episodes.iter().take(REPORT_CONGESTION) {
+            let peak = &episode.peak;
+            println!(
+                "   {} - {}  {:<15} {:.1} ms (+{:.1} ms) at {}",
+                episode.start.format("%m-%d %H:%M"),
+                episode.end.format("%H:%M"),
+                tags.describe(&peak.target),
+                peak.latency_ms,
+                peak.added_latency_ms(),
+                self.units.rate(peak.bytes_per_sec)
+            );
+        }
+        if episodes.len() > REPORT_CONGESTION {
+            println!("   ... and {} more", episodes.len() - REPORT_CONGESTION);
+        }
+
+        if targets.iter().filter_map(|t| t.grade()).any(|grade| grade > BufferbloatGrade::B) {
+            println!("   💡 Latency rises under load: queues are filling in the modem or router (bufferbloat).");
+            println!("      Shape just below the line rate with smart queueing, e.g. `kw shaping --interface <wan> --format sqm`");
+        } else if episodes.is_empty() {
+            println!("   None - latency stayed within {:.0} ms of idle under load", self.congestion.latency_increase_ms);
+        }
+        Ok(())
+    }
+
+    /// Traffic with public addresses by country and autonomous system, flagging countries
+    /// outside `[geoip] expected_countries` with their busiest addresses
+    fn print_geo(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+        println!("🌍 By country:");
+        let Some(databases) = GeoIpDatabases::open(&self.geoip)? else {
+            println!("   No GeoIP databases configured");
+            println!("   Set [geoip] country_database and asn_database to MaxMind DB files, e.g. GeoLite2-Country.mmdb");
+            return Ok(());
+        };
+        let remotes = self.storage.get_remote_traffic(start, end)?;
+        if remotes.is_empty() {
+            println!("   No traffic with public addresses recorded");
+            return Ok(());
+        }
+        let traffic = geo_traffic::aggregate(&remotes, |ip| databases.lookup(ip), &self.geoip);
+
+        let units = &self.units;
+        let print_totals = |totals: &[GeoTotal]| {
+            for total in totals.iter().take(REPORT_GEO) {
+                let share = total.bytes as f64 / traffic.total_bytes.max(1) as f64 * 100.0;
+                println!(
+                    "   {:<32} {:>10} {:>5}%  ({} addresses, {} flows){}",
+                    total.label(),
+                    units.bytes(total.bytes as f64),
+                    units.number(share, 1),
+                    units.number(total.addresses as f64, 0),
+                    units.number(total.flows as f64, 0),
+                    if total.unexpected { "  ⚠️ unexpected" } else { "" }
+                );
+            }
+            if totals.len() > REPORT_GEO {
+                println!("   ... and {} more", totals.len() - REPORT_GEO);
+            }
+        };
+
+        if databases.has_countries() {
+            print_totals(&traffic.countries);
+        } else {
+            println!("   No [geoip] country_database configured");
+        }
+
+        println!();
+        println!("🏢 By network (ASN):");
+        if databases.has_asns() {
+            print_totals(&traffic.asns);
+        } else {
+            println!("   No [geoip] asn_database configured");
+        }
+
+        let unexpected: Vec<_> = traffic.unexpected_countries().collect();
+        if !unexpected.is_empty() {
+            let tags = self.storage.tag_book()?;
+            println!();
+            println!("⚠️  Traffic outside the expected countries ({}):", self.geoip.expected_countries.join(", "));
+            for country in unexpected {
+                let addresses: Vec<_> = country
+                    .top_addresses
+                    .iter()
+                    .map(|(ip, bytes)| format!("{} ({})", tags.describe(&ip.to_string()), units.bytes(*bytes as f64)))
+                    .collect();
+                println!("   {:<32} {}", country.label(), addresses.join(", "));
+            }
+        }
+        Ok(())
+    }
+
+    fn print_data_quality(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<()> {
+        let quality = self.storage.get_data_quality(start, end)?;
+        let period_secs = (end - start).num_seconds() as f64;
+
+        println!("🔎 Data Quality:");
+        if quality.sampled_secs <= 0.0 {
+            println!("   No quality samples recorded in this period");
+            println!("   Samples are recorded by the background service (`kw service run`)");
+            return Ok(());
+        }
+
+        let percent = |share: f64, decimals: usize| format!("{}%", self.units.number(share * 100.0, decimals));
+        println!(
+            "   Monitored:           {} of {} ({})",
+            format_duration(quality.sampled_secs),
+            format_duration(period_secs),
+            percent(coverage(&quality, period_secs), 1)
+        );
+        println!(
+            "   Confidence:          High {} | Medium {} | Low {} | None {}",
+            percent(quality.share(&CalculationConfidence::High), 1),
+            percent(quality.share(&CalculationConfidence::Medium), 1),
+            percent(quality.share(&CalculationConfidence::Low), 1),
+            percent(quality.share(&CalculationConfidence::None), 1)
+        );
+        println!("   Counter resets:      {}", quality.counter_resets);
+        println!("   Collection failures: {}", quality.collection_failures);
+        println!(
+            "   Capture drops:       {} of {} packets ({})",
+            self.units.number(quality.packets_dropped as f64, 0),
+            self.units.number(quality.packets_captured as f64, 0),
+            percent(quality.drop_rate(), 2)
+        );
+        println!("   Assessment:          {}", assess(&quality, period_secs));
+        Ok(())
+    }
+}
+
+/// Bytes moved per interface, from the average rates of its samples over their durations
+fn interface_bytes(samples: &[BandwidthSample]) -> Vec<(String, u64)> {
+    let mut bytes: HashMap<&str, f64> = HashMap::new();
+    for sample in samples {
+        *bytes.entry(&sample.interface_name).or_default() += sample.total_bps() * sample.duration_secs;
+    }
+    bytes.into_iter().map(|(name, bytes)| (name.to_string(), bytes.round() as u64)).collect()
+}
+
+/// Daily totals that count towards a quota: loopback stays on the machine, and tunnel
+/// traffic is already counted, encrypted, on the interface it goes over
+fn daily_usage(samples: &[BandwidthSample]) -> Vec<(chrono::NaiveDate, f64)> {
+    let names: BTreeSet<&str> = samples.iter().map(|sample| sample.interface_name.as_str()).collect();
+    let tunnels: Vec<String> = detect_tunnels(names).into_iter().map(|tunnel| tunnel.name).collect();
+    forecast::daily_usage(samples, |sample| !sample.is_loopback() && !tunnels.contains(&sample.interface_name))
+}
+
+/// Pairs up the traffic of each name in both periods, busiest in either period first
+fn compare_usage(previous: &[(String, u64)], current: &[(String, u64)]) -> Vec<Change> {
+    let mut changes: BTreeMap<&str, Change> = BTreeMap::new();
+    for (name, bytes) in previous {
+        changes
+            .entry(name)
+            .or_insert_with(|| Change { name: name.clone(), previous: 0, current: 0 })
+            .previous += bytes;
+    }
+    for (name, bytes) in current {
+        changes
+            .entry(name)
+            .or_insert_with(|| Change { name: name.clone(), previous: 0, current: 0 })
+            .current += bytes;
+    }
+    let mut changes: Vec<Change> = changes.into_values().collect();
+    changes.sort_by_key(|change| std::cmp::Reverse(change.current.max(change.previous)));
+    changes
+}
+
+/// Fraction of the period covered by quality samples
+fn coverage(quality: &DataQualitySummary, period_secs: f64) -> f64 {
+    (quality.sampled_secs / period_secs.max(1.0)).min(1.0)
+}
+
+/// One-line verdict on how trustworthy the period's usage numbers are
+fn assess(quality: &DataQualitySummary, period_secs: f64) -> &'static str {
+    let coverage = coverage(quality, period_secs);
+    let reliable = quality.share(&CalculationConfidence::High) + quality.share(&CalculationConfidence::Medium);
+    let drop_rate = quality.drop_rate();
+
+    if coverage < 0.5 || reliable < 0.5 || drop_rate > 0.05 {
+        "Unreliable - large gaps, low-confidence readings or heavy capture loss"
+    } else if coverage < 0.9
+        || reliable < 0.9
+        || drop_rate > 0.01
+        || quality.counter_resets > 0
+        || quality.collection_failures > 0
+    {
+        "Usable with caution - some readings are missing or estimated"
+    } else {
+        "Reliable"
+    }
+}
+
+/// Compact duration such as `6d 4h` or `23m`
+pub(crate) fn format_duration(secs: f64) -> String {
+    let total_minutes = (secs / 60.0).round() as u64;
+    let (days, hours, minutes) = (total_minutes / 1440, total_minutes / 60 % 24, total_minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quality(high_secs: f64, none_secs: f64, dropped: u64) -> DataQualitySummary {
+        DataQualitySummary {
+            sampled_secs: high_secs + none_secs,
+            high_secs,
+            none_secs,
+            packets_captured: 10_000,
+            packets_dropped: dropped,
+            ..DataQualitySummary::default()
+        }
+    }
+
+    #[test]
+    fn test_assessment_levels() {
+        let day = 86_400.0;
+        assert_eq!(assess(&quality(day, 0.0, 0), day), "Reliable");
+        assert!(assess(&quality(day * 0.8, day * 0.2, 0), day).starts_with("Usable"));
+        assert!(assess(&quality(day, 0.0, 200), day).starts_with("Usable"));
+        assert!(assess(&quality(day * 0.3, 0.0, 0), day).starts_with("Unreliable"));
+        assert!(assess(&quality(day, 0.0, 1_000), day).starts_with("Unreliable"));
+    }
+
+    fn sample(interface: &str, download_bps: f64, duration_secs: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp: Local::now(),
+            interface_name: interface.to_string(),
+            duration_secs,
+            download_bps,
+            upload_bps: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_interface_bytes_sum_rates_over_durations() {
+        let mut bytes = interface_bytes(&[sample("eth0", 1000.0, 60.0), sample("eth0", 500.0, 3600.0), sample("wg0", 10.0, 60.0)]);
+        bytes.sort();
+        assert_eq!(bytes, [("eth0".to_string(), 1_860_000), ("wg0".to_string(), 600)]);
+    }
+
+    #[test]
+    fn test_compare_usage_pairs_periods() {
+        let previous = [("HTTPS".to_string(), 800), ("DNS".to_string(), 100), ("SSH".to_string(), 50)];
+        let current = [("HTTPS".to_string(), 1000), ("QUIC".to_string(), 900), ("DNS".to_string(), 25)];
+        let changes = compare_usage(&previous, &current);
+
+        let names: Vec<&str> = changes.iter().map(|change| change.name.as_str()).collect();
+        assert_eq!(names, ["HTTPS", "QUIC", "DNS", "SSH"]);
+        assert_eq!(changes[0].delta(), 200);
+        assert_eq!(changes[0].percent(), Some(25.0));
+        assert_eq!(changes[1].percent(), None);
+        assert_eq!(changes[2].percent(), Some(-75.0));
+        assert_eq!(changes[3].percent(), Some(-100.0));
+    }
+
+    #[test]
+    fn test_comparison_parsing() {
+        assert_eq!(ReportComparison::parse("Previous").unwrap(), ReportComparison::Previous);
+        assert!(ReportComparison::parse("last-year").is_err());
+    }
+
+    #[test]
+    fn test_duration_formatting() {
+        assert_eq!(format_duration(1380.0), "23m");
+        assert_eq!(format_duration(5400.0), "1h 30m");
+        assert_eq!(format_duration(7.0 * 86_400.0), "7d 0h");
+    }
+}
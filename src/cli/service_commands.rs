@@ -0,0 +1,271 @@
+// CLI Service Commands: installs kw as a boot-time service and runs its daemon
+// Registration is platform specific (systemd, launchd or the Windows SCM) and lives in
+// the service module; the daemon itself is shared so `kw service run` behaves the same
+// under any service manager
+
+use crate::cli::commands::ServiceAction;
+use crate::service::SERVICE_NAME;
+use crate::collectors::bandwidth::SamplingPlan;
+use crate::config::{
+    CaptureConfig, ConfigFingerprint, ContentionConfig, HooksConfig, MqttConfig, PingConfig, ResourceProfile,
+    RetentionConfig, SecurityConfig, StorageConfig, WebhookConfig,
+};
+use crate::service::MonitorDaemon;
+#[cfg(target_os = "macos")]
+use crate::service::launchd as service_manager;
+#[cfg(target_os = "linux")]
+use crate::service::systemd as service_manager;
+#[cfg(windows)]
+use crate::service::windows as service_manager;
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Command handler for `kw service`
+pub struct ServiceCommandHandler {
+    resources: ResourceProfile,
+    capture: CaptureConfig,
+    sampling: SamplingPlan,
+    web_address: Option<SocketAddr>,
+    security: SecurityConfig,
+    ping: PingConfig,
+    retention: RetentionConfig,
+    hooks: HooksConfig,
+    contention: ContentionConfig,
+    webhook: WebhookConfig,
+    mqtt: MqttConfig,
+    storage: StorageConfig,
+    config_fingerprint: Option<ConfigFingerprint>,
+    /// Where `kw service run` keeps its data without `--data-dir`
+    data_dir: PathBuf,
+}
+
+impl ServiceCommandHandler {
+    pub fn new() -> Self {
+        Self {
+            resources: ResourceProfile::standard(),
+            capture: CaptureConfig::default(),
+            sampling: SamplingPlan::default(),
+            web_address: None,
+            security: SecurityConfig::default(),
+            ping: PingConfig::default(),
+            retention: RetentionConfig::default(),
+            hooks: HooksConfig::default(),
+            contention: ContentionConfig::default(),
+            webhook: WebhookConfig::default(),
+            mqtt: MqttConfig::default(),
+            storage: StorageConfig::default(),
+            config_fingerprint: None,
+            data_dir: PathBuf::from("./data"),
+        }
+    }
+
+    /// Resource profile handed to the daemon started by `kw service run`
+    pub fn with_resource_profile(mut self, resources: ResourceProfile) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// How the daemon captures packets
+    pub fn with_capture(mut self, config: CaptureConfig) -> Self {
+        self.capture = config;
+        self
+    }
+
+    /// Per-interface sampling intervals handed to the daemon
+    pub fn with_sampling(mut self, plan: SamplingPlan) -> Self {
+        self.sampling = plan;
+        self
+    }
+
+    /// Address the daemon serves its web UI on, if any
+    pub fn with_web_ui(mut self, address: Option<SocketAddr>) -> Self {
+        self.web_address = address;
+        self
+    }
+
+    /// Port scan, SYN flood and ICMP sweep thresholds handed to the daemon
+    pub fn with_security_config(mut self, config: SecurityConfig) -> Self {
+        self.security = config;
+        self
+    }
+
+    /// Connectivity targets the daemon pings
+    pub fn with_ping(mut self, config: PingConfig) -> Self {
+        self.ping = config;
+        self
+    }
+
+    /// Retention policy the daemon applies periodically
+    pub fn with_retention(mut self, config: RetentionConfig) -> Self {
+        self.retention = config;
+        self
+    }
+
+    /// Commands the daemon runs on alerts, interface changes, the monthly cap and saturated links
+    pub fn with_hooks(mut self, config: HooksConfig) -> Self {
+        self.hooks = config;
+        self
+    }
+
+    /// Saturation threshold and link speed override the daemon's `on_link_saturated` hook uses
+    pub fn with_contention(mut self, config: ContentionConfig) -> Self {
+        self.contention = config;
+        self
+    }
+
+    /// Endpoint the daemon posts periodic summaries to
+    pub fn with_webhook(mut self, config: WebhookConfig) -> Self {
+        self.webhook = config;
+        self
+    }
+
+    /// Broker the daemon publishes interface speeds and usage to
+    pub fn with_mqtt(mut self, config: MqttConfig) -> Self {
+        self.mqtt = config;
+        self
+    }
+
+    /// Database the daemon writes its records to
+    pub fn with_storage(mut self, config: StorageConfig) -> Self {
+        self.storage = config;
+        self
+    }
+
+    /// Configuration the daemon records in the audit log when it changed
+    pub fn with_config_fingerprint(mut self, fingerprint: ConfigFingerprint) -> Self {
+        self.config_fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// Data directory of the profile in use, which `kw service run` records to without `--data-dir`
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+
+    pub async fn handle_service_command(&self, action: ServiceAction) -> Result<()> {
+        match action {
+            ServiceAction::Install { interface, data_dir } => self.install(&interface, data_dir),
+            ServiceAction::Uninstall => self.uninstall(),
+            ServiceAction::Status => self.status(),
+            ServiceAction::Run { interface, data_dir } => {
+                let daemon = MonitorDaemon::new(interface, data_dir.unwrap_or_else(|| self.data_dir.clone()))
+                    .with_resource_profile(self.resources)
+                    .with_capture(self.capture.clone())
+                    .with_sampling(self.sampling.clone())
+                    .with_web_ui(self.web_address)
+                    .with_security_config(self.security.clone())
+                    .with_ping(self.ping.clone())
+                    .with_retention(self.retention.clone())
+                    .with_hooks(self.hooks.clone())
+                    .with_contention(self.contention.clone())
+                    .with_webhook(self.webhook.clone())
+                    .with_mqtt(self.mqtt.clone())
+                    .with_storage(self.storage.clone())
+                    .with_config_fingerprint(self.config_fingerprint.clone());
+                self.run(daemon).await
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    fn install(&self, interface: &str, data_dir: Option<PathBuf>) -> Result<()> {
+        let data_dir = data_dir.unwrap_or_else(service_manager::default_data_dir);
+        crate::service::windows::install(interface, &data_dir)?;
+
+        println!("✅ Installed and started the {} service", crate::service::windows::SERVICE_DISPLAY_NAME);
+        println!("   Interface: {interface}");
+        println!("   Data:      {}", data_dir.display());
+        println!("   Logs:      Event Viewer > Windows Logs > Application (source: kaipo-watcher)");
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn install(&self, interface: &str, data_dir: Option<PathBuf>) -> Result<()> {
+        let data_dir = data_dir.unwrap_or_else(service_manager::default_data_dir);
+        service_manager::install(interface, &data_dir)?;
+
+        println!("✅ Installed and started the systemd unit {}", service_manager::unit_path().display());
+        println!("   Interface: {interface}");
+        println!("   Data:      {}", data_dir.display());
+        println!("   Logs:      journalctl -u {SERVICE_NAME}");
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn install(&self, interface: &str, data_dir: Option<PathBuf>) -> Result<()> {
+        let data_dir = data_dir.unwrap_or_else(service_manager::default_data_dir);
+        service_manager::install(interface, &data_dir)?;
+
+        println!("✅ Installed and started the LaunchDaemon {}", service_manager::plist_path().display());
+        println!("   Interface: {interface}");
+        println!("   Data:      {}", data_dir.display());
+        println!("   Logs:      /Library/Logs/kaipo-watcher.log");
+        Ok(())
+    }
+
+    #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+    fn install(&self, _interface: &str, _data_dir: Option<PathBuf>) -> Result<()> {
+        anyhow::bail!(
+            "`kw service install` is only supported on Linux (systemd), macOS and Windows; \
+run `kw service run` from your service manager instead"
+        )
+    }
+
+    #[cfg(windows)]
+    fn uninstall(&self) -> Result<()> {
+        crate::service::windows::uninstall()?;
+        println!("✅ Removed the {} service", crate::service::windows::SERVICE_DISPLAY_NAME);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn uninstall(&self) -> Result<()> {
+        service_manager::uninstall()?;
+        println!("✅ Removed the {SERVICE_NAME} service");
+        Ok(())
+    }
+
+    #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+    fn uninstall(&self) -> Result<()> {
+        anyhow::bail!("`kw service uninstall` is only supported on Linux (systemd), macOS and Windows")
+    }
+
+    #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
+    fn status(&self) -> Result<()> {
+        match service_manager::status()? {
+            Some(state) => println!("{SERVICE_NAME}: {state}"),
+            None => println!("{SERVICE_NAME}: not installed"),
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+    fn status(&self) -> Result<()> {
+        anyhow::bail!("`kw service status` is only supported on Linux (systemd), macOS and Windows")
+    }
+
+    #[cfg(windows)]
+    async fn run(&self, daemon: MonitorDaemon) -> Result<()> {
+        crate::service::windows::run(daemon).await
+    }
+
+    #[cfg(not(windows))]
+    async fn run(&self, daemon: MonitorDaemon) -> Result<()> {
+        println!(
+            "📡 Monitoring {} in the foreground, recording to {} (Ctrl+C to stop)",
+            daemon.interface(),
+            daemon.data_dir().display()
+        );
+        let summary = daemon.run(crate::shutdown::requested()).await?;
+        println!("\n{summary}");
+        Ok(())
+    }
+}
+
+impl Default for ServiceCommandHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
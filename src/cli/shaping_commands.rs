@@ -0,0 +1,172 @@
+// CLI Shaping Commands: QoS recommendations from stored usage
+// Usage per category comes from the connections table and the line rate from the
+// latest speed test or the interface's link speed; the plan is printed as a report or
+// exported as tc, nftables or OpenWrt SQM configuration
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::analyzers::shaping::{category_usage, LineRate, ShapingPlan, SHAPED_FRACTION};
+use crate::cli::period::Period;
+use crate::cli::speedtest_commands::format_mbit;
+use crate::collectors::bandwidth_collector::format_bytes;
+use crate::collectors::link_speed::detect_link_speed_mbps;
+use crate::storage::PacketStorage;
+
+/// Stored speed tests searched for one that measured both directions
+const RECENT_SPEED_TESTS: usize = 10;
+
+/// Command handler for `kw shaping`
+pub struct ShapingCommandHandler {
+    storage: Arc<PacketStorage>,
+}
+
+impl ShapingCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage }
+    }
+
+    /// Builds a plan from the period's usage and prints it, or writes it in an export format
+    /// The rates override the measured line rate per direction
+    pub async fn handle_shaping_command(
+        &self,
+        period: &str,
+        interface: Option<String>,
+        format: &str,
+        download_mbit: Option<f64>,
+        upload_mbit: Option<f64>,
+        output: Option<PathBuf>,
+    ) -> Result<()> {
+        let format = format.to_lowercase();
+        if !matches!(format.as_str(), "text" | "tc" | "nftables" | "sqm") {
+            bail!("Unsupported shaping format '{format}' (expected text, tc, nftables or sqm)");
+        }
+        let period = Period::parse(period, Local::now())?;
+        let (start, end) = (period.start, period.end);
+
+        let line = self.line_rate(interface.as_deref(), download_mbit, upload_mbit)?;
+        let usage = category_usage(&self.storage.get_service_usage(start, end)?);
+        let plan = ShapingPlan::recommend(usage, line);
+
+        let export = match format.as_str() {
+            "text" => {
+                print_plan(&plan, &period.label, interface.as_deref());
+                return Ok(());
+            }
+            export_format => {
+                let Some(interface) = interface else {
+                    bail!("Give the interface to shape with --interface, e.g. the WAN or uplink interface");
+                };
+                match export_format {
+                    "tc" => plan.to_tc(&interface),
+                    "nftables" => plan.to_nftables(&interface),
+                    _ => plan.to_sqm(&interface),
+                }
+            }
+        };
+
+        match output {
+            Some(path) => {
+                std::fs::write(&path, export)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("🎛️  Shaping configuration written to {}", path.display());
+            }
+            None => print!("{export}"),
+        }
+        Ok(())
+    }
+
+    /// Rates given on the command line, else the latest complete speed test, else the link speed
+    fn line_rate(&self, interface: Option<&str>, download_mbit: Option<f64>, upload_mbit: Option<f64>) -> Result<LineRate> {
+        if let (Some(download_mbps), Some(upload_mbps)) = (download_mbit, upload_mbit) {
+            return Ok(LineRate { download_mbps, upload_mbps, source: "given".to_string() });
+        }
+
+        let measured = self
+            .storage
+            .get_speed_tests(RECENT_SPEED_TESTS)?
+            .into_iter()
+            .find_map(|test| {
+                let to_mbit = |bytes_per_sec: f64| bytes_per_sec * 8.0 / 1_000_000.0;
+                Some(LineRate {
+                    download_mbps: to_mbit(test.download_bps?),
+                    upload_mbps: to_mbit(test.upload_bps?),
+                    source: format!("speed test {}", test.timestamp.format("%Y-%m-%d %H:%M")),
+                })
+            })
+            .or_else(|| {
+                let interface = interface?;
+                let link_mbps = detect_link_speed_mbps(interface)? as f64;
+                Some(LineRate {
+                    download_mbps: link_mbps,
+                    upload_mbps: link_mbps,
+                    source: format!("link speed of {interface}"),
+                })
+            });
+        let Some(mut line) = measured else {
+            bail!("No line rate known; run `kw speedtest` first or pass --download-mbit and --upload-mbit");
+        };
+
+        if download_mbit.is_some() || upload_mbit.is_some() {
+            line.download_mbps = download_mbit.unwrap_or(line.download_mbps);
+            line.upload_mbps = upload_mbit.unwrap_or(line.upload_mbps);
+            line.source = format!("{}, partly given", line.source);
+        }
+        Ok(line)
+    }
+}
+
+fn print_plan(plan: &ShapingPlan, period: &str, interface: Option<&str>) {
+    let mbit = |kbit: u64| format!("{:.1}", kbit as f64 / 1000.0);
+
+    println!("🎛️  Shaping Recommendations - {period}");
+    println!(
+        "   Line rate: {:.1} down / {:.1} up Mbit/s ({})",
+        plan.line.download_mbps, plan.line.upload_mbps, plan.line.source
+    );
+    println!(
+        "   Shaper:    {} down / {} up Mbit/s ({:.0}% of the line rate, so queues build here rather than in the modem)",
+        mbit(plan.shaped_download_kbit),
+        mbit(plan.shaped_upload_kbit),
+        SHAPED_FRACTION * 100.0
+    );
+    println!();
+
+    println!("📦 Usage by category:");
+    let total_bytes: u64 = plan.usage.iter().map(|u| u.bytes).sum();
+    if plan.usage.is_empty() {
+        println!("   No connections recorded in the period; classes get their minimum shares");
+    } else {
+        println!("   {:<12} {:>10} {:>6}  {:<16} {:>14}", "Category", "Traffic", "Share", "Busiest hour", "Rate then");
+    }
+    for usage in &plan.usage {
+        println!(
+            "   {:<12} {:>10} {:>5.1}%  {:<16} {:>14}",
+            usage.category.as_str(),
+            format_bytes(usage.bytes as f64),
+            usage.bytes as f64 / total_bytes.max(1) as f64 * 100.0,
+            usage.peak_hour.map(|h| h.format("%Y-%m-%d %H:00").to_string()).unwrap_or_default(),
+            format_mbit(usage.peak_bytes_per_sec)
+        );
+    }
+    println!();
+
+    println!("🎯 Recommended classes (guaranteed / limit, Mbit/s):");
+    println!("   {:<4} {:<12} {:>6} {:>16} {:>16}", "Prio", "Category", "Share", "Download", "Upload");
+    for class in &plan.classes {
+        println!(
+            "   {:<4} {:<12} {:>5.1}% {:>16} {:>16}",
+            class.category.priority(),
+            class.category.as_str(),
+            class.share * 100.0,
+            format!("{} / {}", mbit(class.download.rate_kbit), mbit(class.download.ceil_kbit)),
+            format!("{} / {}", mbit(class.upload.rate_kbit), mbit(class.upload.ceil_kbit))
+        );
+    }
+    println!();
+
+    let interface = interface.unwrap_or("<wan>");
+    println!("💡 Export with: kw shaping --interface {interface} --format tc --output shaping.sh (or nftables, sqm)");
+}
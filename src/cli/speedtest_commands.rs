@@ -0,0 +1,170 @@
+// CLI Speed Test Commands: Active download, upload and latency measurement
+// Complements the passive bandwidth collector by measuring what the connection can reach;
+// results are stored so `kw graph speedtest` can show how the line holds up over time
+
+use anyhow::{bail, Result};
+use chrono::Local;
+use std::sync::Arc;
+
+use crate::collectors::bandwidth_collector::format_speed;
+use crate::collectors::speed_test::SpeedTester;
+use crate::config::SpeedTestConfig;
+use crate::storage::packet_storage::SpeedTestResult;
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw speedtest`
+pub struct SpeedTestCommandHandler {
+    storage: Arc<PacketStorage>,
+    config: SpeedTestConfig,
+}
+
+impl SpeedTestCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self {
+            storage,
+            config: SpeedTestConfig::default(),
+        }
+    }
+
+    /// Uses the endpoints and limits from the `[speedtest]` configuration section
+    pub fn with_config(mut self, config: SpeedTestConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Runs a test and stores its result, or with `history` lists that many stored results
+    /// The URLs override the configured endpoints for this run
+    pub async fn handle_speedtest_command(
+        &self,
+        download_url: Option<String>,
+        upload_url: Option<String>,
+        no_upload: bool,
+        history: Option<usize>,
+    ) -> Result<()> {
+        if let Some(limit) = history {
+            return self.list_results(limit);
+        }
+
+        let mut config = self.config.clone();
+        if let Some(url) = download_url {
+            config.download_url = url;
+        }
+        if let Some(url) = upload_url {
+            config.upload_url = url;
+        }
+        if no_upload {
+            config.upload_url.clear();
+        }
+        let tester = SpeedTester::new(config)?;
+
+        println!("🚀 Speed Test - {}", tester.server());
+        let mut result = SpeedTestResult {
+            timestamp: Local::now(),
+            server: tester.server(),
+            latency_ms: None,
+            jitter_ms: None,
+            download_bps: None,
+            upload_bps: None,
+            download_bytes: 0,
+            upload_bytes: 0,
+        };
+
+        match tester.measure_latency().await {
+            Ok(latency) => {
+                println!("   Latency:   {:.1} ms (jitter {:.1} ms)", latency.median_ms, latency.jitter_ms);
+                result.latency_ms = Some(latency.median_ms);
+                result.jitter_ms = Some(latency.jitter_ms);
+            }
+            Err(e) => println!("   Latency:   failed - {e:#}"),
+        }
+
+        match tester.measure_download().await {
+            Ok(transfer) => {
+                println!(
+                    "   Download:  {} - {:.1} MB in {:.1}s",
+                    describe_speed(transfer.bytes_per_second()),
+                    transfer.bytes as f64 / 1_000_000.0,
+                    transfer.duration.as_secs_f64()
+                );
+                result.download_bps = Some(transfer.bytes_per_second());
+                result.download_bytes = transfer.bytes;
+            }
+            Err(e) => println!("   Download:  failed - {e:#}"),
+        }
+
+        if tester.has_upload() {
+            match tester.measure_upload().await {
+                Ok(transfer) => {
+                    println!(
+                        "   Upload:    {} - {:.1} MB in {:.1}s",
+                        describe_speed(transfer.bytes_per_second()),
+                        transfer.bytes as f64 / 1_000_000.0,
+                        transfer.duration.as_secs_f64()
+                    );
+                    result.upload_bps = Some(transfer.bytes_per_second());
+                    result.upload_bytes = transfer.bytes;
+                }
+                Err(e) => println!("   Upload:    failed - {e:#}"),
+            }
+        } else {
+            println!("   Upload:    skipped");
+        }
+
+        if result.latency_ms.is_none() && result.download_bps.is_none() && result.upload_bps.is_none() {
+            bail!("Speed test against {} failed; nothing was recorded", result.server);
+        }
+        self.storage.store_speed_test(&result)?;
+        println!();
+        println!("💾 Result saved; see `kw speedtest --history 10` or `kw graph speedtest`");
+        Ok(())
+    }
+
+    fn list_results(&self, limit: usize) -> Result<()> {
+        let results = self.storage.get_speed_tests(limit)?;
+        if results.is_empty() {
+            println!("No speed tests recorded yet; run `kw speedtest` to measure the connection");
+            return Ok(());
+        }
+
+        println!("🚀 Speed Tests (newest first):");
+        println!(
+            "   {:<19} {:<24} {:>9} {:>8} {:>14} {:>14}",
+            "Time", "Server", "Latency", "Jitter", "Download", "Upload"
+        );
+        for result in &results {
+            let ms = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{v:.1} ms"));
+            let mbit = |value: Option<f64>| value.map_or_else(|| "-".to_string(), format_mbit);
+            println!(
+                "   {:<19} {:<24} {:>9} {:>8} {:>14} {:>14}",
+                result.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                result.server,
+                ms(result.latency_ms),
+                ms(result.jitter_ms),
+                mbit(result.download_bps),
+                mbit(result.upload_bps)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Bytes per second as line speed, e.g. "94.2 Mbit/s"
+pub fn format_mbit(bytes_per_sec: f64) -> String {
+    format!("{:.1} Mbit/s", bytes_per_sec * 8.0 / 1_000_000.0)
+}
+
+/// Line speed followed by the byte rate, e.g. "94.2 Mbit/s (11.23 MB/s)"
+fn describe_speed(bytes_per_sec: f64) -> String {
+    format!("{} ({})", format_mbit(bytes_per_sec), format_speed(bytes_per_sec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speeds_shown_as_line_rate() {
+        assert_eq!(format_mbit(12_500_000.0), "100.0 Mbit/s");
+        assert!(describe_speed(12_500_000.0).starts_with("100.0 Mbit/s ("));
+    }
+}
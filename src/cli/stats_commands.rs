@@ -0,0 +1,164 @@
+// CLI Stats Commands: Statistics about kw itself
+// `kw stats self` reads the collector health samples `kw service run` records every minute,
+// so a collector that has been slow, retrying or losing interfaces shows up over time
+// rather than only in the logs of the moment
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Timelike};
+use std::sync::Arc;
+
+use crate::cli::commands::StatsAction;
+use crate::cli::report_commands::format_duration;
+use crate::collectors::bandwidth::CollectorTelemetry;
+use crate::storage::packet_storage::CollectorHealthSample;
+use crate::storage::PacketStorage;
+
+/// Periods longer than this are shown per day rather than per hour
+const HOURLY_LIMIT_HOURS: u32 = 72;
+
+/// Command handler for `kw stats`
+pub struct StatsCommandHandler {
+    storage: Arc<PacketStorage>,
+}
+
+impl StatsCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn handle_stats_command(&self, action: StatsAction) -> Result<()> {
+        match action {
+            StatsAction::Collector { hours, .. } => self.collector_health(hours),
+        }
+    }
+
+    /// Totals of the last `hours`, then one line per hour or day
+    fn collector_health(&self, hours: u32) -> Result<()> {
+        let since = Local::now() - Duration::hours(i64::from(hours.max(1)));
+        let samples = self.storage.get_collector_health(since)?;
+        if samples.is_empty() {
+            println!("No collector health recorded in the last {hours} hours");
+            println!("It is recorded every minute while `kw service run` is running");
+            return Ok(());
+        }
+
+        let mut total = CollectorTelemetry::default();
+        for sample in &samples {
+            total.merge(&sample.telemetry);
+        }
+        let covered: f64 = samples.iter().map(|sample| sample.duration_secs).sum();
+
+        println!("🩺 Collector Health - last {hours} hours");
+        println!("   Sampled:        {} in {} samples", format_duration(covered), samples.len());
+        print_totals(&total);
+        println!("   Assessment:     {}", assess_health(&total));
+
+        let daily = hours > HOURLY_LIMIT_HOURS;
+        println!();
+        println!("📈 Per {}:", if daily { "day" } else { "hour" });
+        println!(
+            "   {:<16} {:>11} {:>7} {:>9} {:>8} {:>9} {:>9}",
+            "Period", "Collections", "Failed", "Degraded", "Retries", "Avg", "Slowest"
+        );
+        for (start, telemetry) in buckets(&samples, daily) {
+            println!(
+                "   {:<16} {:>11} {:>7} {:>9} {:>8} {:>9} {:>9}",
+                start.format(if daily { "%Y-%m-%d" } else { "%Y-%m-%d %H:00" }),
+                telemetry.collections,
+                telemetry.failed_collections,
+                telemetry.degraded_collections,
+                telemetry.retries,
+                format_millis(telemetry.average_duration()),
+                format_millis(telemetry.max_duration)
+            );
+        }
+        Ok(())
+    }
+}
+
+fn print_totals(total: &CollectorTelemetry) {
+    let share = |count: u64| count as f64 / total.collections.max(1) as f64 * 100.0;
+    println!("   Collections:    {}", total.collections);
+    println!("   Failed:         {} ({:.2}%)", total.failed_collections, share(total.failed_collections));
+    println!("   Degraded:       {} ({:.2}%)", total.degraded_collections, share(total.degraded_collections));
+    println!("   Retries:        {} refreshes of the interface counters repeated", total.retries);
+    println!(
+        "   Duration:       {} avg, {} slowest",
+        format_millis(total.average_duration()),
+        format_millis(total.max_duration)
+    );
+    if !total.failed_interfaces.is_empty() {
+        let mut failed: Vec<(&String, &u64)> = total.failed_interfaces.iter().collect();
+        failed.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let listed: Vec<String> = failed.iter().map(|(name, count)| format!("{name} failed {count}x")).collect();
+        println!("   Interfaces:     {}", listed.join(", "));
+    }
+}
+
+/// Samples merged per hour or per day, oldest first
+fn buckets(samples: &[CollectorHealthSample], daily: bool) -> Vec<(DateTime<Local>, CollectorTelemetry)> {
+    let mut buckets: Vec<(DateTime<Local>, CollectorTelemetry)> = Vec::new();
+    for sample in samples {
+        let mut start = sample.timestamp.with_minute(0).and_then(|t| t.with_second(0)).unwrap_or(sample.timestamp);
+        if daily {
+            start = start.with_hour(0).unwrap_or(start);
+        }
+        match buckets.last_mut() {
+            Some((last, telemetry)) if *last == start => telemetry.merge(&sample.telemetry),
+            _ => buckets.push((start, sample.telemetry.clone())),
+        }
+    }
+    buckets
+}
+
+fn format_millis(duration: std::time::Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// One-line verdict on the collector's health
+fn assess_health(total: &CollectorTelemetry) -> &'static str {
+    if total.failed_collections > 0 {
+        "Failing - some collections returned no readings; check `kw doctor` and the daemon's log"
+    } else if total.unhealthy_share() > 0.01 {
+        "Degraded - interfaces regularly fail validation; see the failed interfaces above"
+    } else if total.retries > 0 || total.max_duration > std::time::Duration::from_millis(500) {
+        "Mostly healthy - occasional retries or slow collections"
+    } else {
+        "Healthy - every collection succeeded on the first attempt"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(hour: u32, minute: u32, failed: &[String]) -> CollectorHealthSample {
+        let mut telemetry = CollectorTelemetry::default();
+        telemetry.record(std::time::Duration::from_millis(20), 0, failed, true);
+        CollectorHealthSample {
+            timestamp: Local.with_ymd_and_hms(2026, 3, 1, hour, minute, 0).unwrap(),
+            duration_secs: 60.0,
+            telemetry,
+        }
+    }
+
+    #[test]
+    fn test_samples_are_merged_per_hour_and_day() {
+        let eth1 = vec!["eth1".to_string()];
+        let samples = [sample(9, 0, &[]), sample(9, 59, &eth1), sample(10, 1, &[])];
+
+        let hourly = buckets(&samples, false);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].0.hour(), 9);
+        assert_eq!(hourly[0].1.collections, 2);
+        assert_eq!(hourly[0].1.degraded_collections, 1);
+        assert_eq!(hourly[1].1.collections, 1);
+
+        let daily = buckets(&samples, true);
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].1.collections, 3);
+        assert!(assess_health(&daily[0].1).starts_with("Degraded"));
+        assert!(assess_health(&hourly[1].1).starts_with("Healthy"));
+    }
+}
@@ -0,0 +1,97 @@
+// CLI Tag Commands: Friendly names and notes for hosts, devices and interfaces
+// Tags live in the packet database so every command and the daemon can show them
+
+use anyhow::{bail, Result};
+use std::sync::Arc;
+
+use crate::models::tag::normalize_target;
+use crate::models::Tag;
+use crate::storage::PacketStorage;
+
+/// Command handler for `kw tag`
+pub struct TagCommandHandler {
+    storage: Arc<PacketStorage>,
+}
+
+impl TagCommandHandler {
+    pub fn new(storage: Arc<PacketStorage>) -> Self {
+        Self { storage }
+    }
+
+    pub async fn handle_tag_command(
+        &self,
+        target: Option<String>,
+        label: Option<String>,
+        note: Option<String>,
+        remove: bool,
+    ) -> Result<()> {
+        let Some(target) = target else {
+            return self.list_tags();
+        };
+        let (target, kind) = normalize_target(&target);
+
+        if remove {
+            if self.storage.remove_tag(&target)? {
+                println!("🏷️  Removed tag for {target}");
+            } else {
+                println!("No tag found for {target}");
+            }
+            return Ok(());
+        }
+
+        let existing = self.storage.tag_book()?.get(&target).cloned();
+        let label = match (label, &existing) {
+            (Some(label), _) => label,
+            // A note on its own updates an existing tag
+            (None, Some(tag)) if note.is_some() => tag.label.clone(),
+            (None, Some(tag)) => {
+                print_tag(tag);
+                return Ok(());
+            }
+            (None, None) if note.is_some() => bail!("{target} has no tag yet; give a name to tag it with"),
+            (None, None) => {
+                println!("No tag found for {target}");
+                return Ok(());
+            }
+        };
+        if label.trim().is_empty() {
+            bail!("Tag name cannot be empty");
+        }
+
+        let note = note.or_else(|| existing.and_then(|tag| tag.note));
+        let tag = Tag::new(&target, label.trim(), note.filter(|n| !n.trim().is_empty()));
+        self.storage.set_tag(&tag)?;
+        println!("🏷️  Tagged {kind} {} as \"{}\"", tag.target, tag.label);
+        Ok(())
+    }
+
+    fn list_tags(&self) -> Result<()> {
+        let tags = self.storage.get_tags()?;
+        if tags.is_empty() {
+            println!("No tags yet. Add one with: kw tag <address|interface> \"name\"");
+            return Ok(());
+        }
+
+        println!("🏷️  Tags:");
+        println!("  {:<10} {:<40} {:<24} Note", "Kind", "Target", "Name");
+        for tag in &tags {
+            println!(
+                "  {:<10} {:<40} {:<24} {}",
+                tag.kind,
+                tag.target,
+                tag.label,
+                tag.note.as_deref().unwrap_or("")
+            );
+        }
+        Ok(())
+    }
+}
+
+fn print_tag(tag: &Tag) {
+    println!("🏷️  {} ({})", tag.target, tag.kind);
+    println!("  Name:    {}", tag.label);
+    if let Some(note) = &tag.note {
+        println!("  Note:    {note}");
+    }
+    println!("  Updated: {}", tag.updated_at.format("%Y-%m-%d %H:%M"));
+}
@@ -0,0 +1,296 @@
+// vnStat-style output: the summary and daily/monthly tables of vnStat's own commands,
+// rebuilt from the stored bandwidth samples for `kw status --style vnstat` and
+// `kw report --style vnstat`, so scripts written against vnStat keep working.
+// Volumes are in IEC units with two decimals and rates in bits, whatever the [display]
+// settings, and numbers carry no thousands separators, as vnStat prints them
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::storage::packet_storage::BandwidthSample;
+
+/// Layout of `kw status` and `kw report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputStyle {
+    #[default]
+    Default,
+    /// vnStat's table layout
+    Vnstat,
+}
+
+impl OutputStyle {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "default" | "kw" => Ok(Self::Default),
+            "vnstat" => Ok(Self::Vnstat),
+            other => bail!("Unsupported output style '{other}' (expected default or vnstat)"),
+        }
+    }
+}
+
+/// Bytes received and sent
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Traffic {
+    pub rx: f64,
+    pub tx: f64,
+}
+
+impl Traffic {
+    fn total(&self) -> f64 {
+        self.rx + self.tx
+    }
+
+    fn add(&mut self, other: Traffic) {
+        self.rx += other.rx;
+        self.tx += other.tx;
+    }
+}
+
+/// Traffic per interface and local day; loopback is left out, as vnStat does not monitor it
+pub fn usage_by_day(samples: &[BandwidthSample]) -> BTreeMap<String, BTreeMap<NaiveDate, Traffic>> {
+    let mut usage: BTreeMap<String, BTreeMap<NaiveDate, Traffic>> = BTreeMap::new();
+    for sample in samples.iter().filter(|sample| !sample.is_loopback()) {
+        usage
+            .entry(sample.interface_name.clone())
+            .or_default()
+            .entry(sample.timestamp.date_naive())
+            .or_default()
+            .add(Traffic {
+                rx: sample.download_bps * sample.duration_secs,
+                tx: sample.upload_bps * sample.duration_secs,
+            });
+    }
+    usage
+}
+
+/// vnStat's default summary: last and this month, yesterday and today per interface, with
+/// estimates for the periods still running
+pub fn summary(usage: &BTreeMap<String, BTreeMap<NaiveDate, Traffic>>, now: DateTime<Local>) -> String {
+    let today = now.date_naive();
+    let month_start = today.with_day(1).unwrap_or(today);
+    let last_month_start = month_start.pred_opt().and_then(|day| day.with_day(1)).unwrap_or(month_start);
+    let day_fraction = seconds_since_midnight(now) / 86_400.0;
+    let month_days = days_in_month(today) as f64;
+    let month_fraction = ((today.day() - 1) as f64 + day_fraction) / month_days;
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{:>27}      /      tx      /     total    /   estimated", "rx");
+    for (interface, days) in usage {
+        let sum = |from: NaiveDate, to: NaiveDate| {
+            let mut traffic = Traffic::default();
+            days.range(from..to).for_each(|(_, day)| traffic.add(*day));
+            traffic
+        };
+        let this_month = sum(month_start, today.succ_opt().unwrap_or(today));
+        let last_month = sum(last_month_start, month_start);
+        let yesterday = today.pred_opt().and_then(|day| days.get(&day)).copied().unwrap_or_default();
+        let today_traffic = days.get(&today).copied().unwrap_or_default();
+
+        let _ = writeln!(out, " {interface}:");
+        if last_month.total() > 0.0 {
+            let _ = writeln!(out, "{}", summary_row(&last_month_start.format("%Y-%m").to_string(), last_month, None));
+        }
+        let _ = writeln!(
+            out,
+            "{}",
+            summary_row(&month_start.format("%Y-%m").to_string(), this_month, estimate(this_month, month_fraction))
+        );
+        let _ = writeln!(out, "{}", summary_row("yesterday", yesterday, None));
+        let _ = writeln!(out, "{}", summary_row("today", today_traffic, estimate(today_traffic, day_fraction)));
+        let _ = writeln!(out);
+    }
+    out
+}
+
+fn summary_row(label: &str, traffic: Traffic, estimated: Option<f64>) -> String {
+    let mut row = format!(
+        "{label:>14} {:>13}  / {:>11}  / {:>11}",
+        format_bytes(traffic.rx),
+        format_bytes(traffic.tx),
+        format_bytes(traffic.total())
+    );
+    if let Some(estimated) = estimated {
+        let _ = write!(row, "  / {:>11}", format_bytes(estimated));
+    }
+    row
+}
+
+/// Daily table (`vnstat -d`) for the days from `start` to `end`, then the monthly table
+/// (`vnstat -m`) of the months they fall in, per interface
+pub fn daily_and_monthly(
+    usage: &BTreeMap<String, BTreeMap<NaiveDate, Traffic>>,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    now: DateTime<Local>,
+) -> String {
+    let today = now.date_naive();
+    let (first_day, last_day) = (start.date_naive(), end.date_naive());
+    let mut out = String::new();
+    for (interface, days) in usage {
+        let days_in_period: Vec<(NaiveDate, Traffic)> =
+            days.range(first_day..=last_day).map(|(day, traffic)| (*day, *traffic)).collect();
+        if days_in_period.is_empty() {
+            continue;
+        }
+
+        // Seconds each day has run, for its average rate
+        let elapsed = |day: NaiveDate| if day == today { seconds_since_midnight(now).max(1.0) } else { 86_400.0 };
+        let _ = writeln!(out);
+        let _ = writeln!(out, " {interface}  /  daily");
+        let _ = writeln!(out);
+        table_header(&mut out, "day");
+        for (day, traffic) in &days_in_period {
+            let _ = writeln!(out, "{}", table_row(&day.format("%Y-%m-%d").to_string(), *traffic, Some(rate(*traffic, elapsed(*day)))));
+        }
+        table_rule(&mut out);
+        if let Some((_, traffic)) = days_in_period.iter().find(|(day, _)| *day == today) {
+            let fraction = seconds_since_midnight(now) / 86_400.0;
+            let _ = writeln!(out, "{}", estimate_row(*traffic, fraction));
+        }
+
+        let mut months: BTreeMap<NaiveDate, Traffic> = BTreeMap::new();
+        for (day, traffic) in &days_in_period {
+            months.entry(day.with_day(1).unwrap_or(*day)).or_default().add(*traffic);
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(out, " {interface}  /  monthly");
+        let _ = writeln!(out);
+        table_header(&mut out, "month");
+        for (month, traffic) in &months {
+            // The month's seconds within the period, for its average rate
+            let seconds: f64 = month
+                .iter_days()
+                .take_while(|day| day.month() == month.month() && *day <= last_day)
+                .filter(|day| *day >= first_day)
+                .map(elapsed)
+                .sum();
+            let _ = writeln!(out, "{}", table_row(&month.format("%Y-%m").to_string(), *traffic, Some(rate(*traffic, seconds.max(1.0)))));
+        }
+        table_rule(&mut out);
+        // Only a month the period covers from its first day can be projected
+        let this_month = today.with_day(1).unwrap_or(today);
+        if let Some(traffic) = months.get(&this_month).filter(|_| first_day <= this_month) {
+            let fraction = ((today.day() - 1) as f64 * 86_400.0 + seconds_since_midnight(now)) / (days_in_month(today) as f64 * 86_400.0);
+            let _ = writeln!(out, "{}", estimate_row(*traffic, fraction));
+        }
+    }
+    out
+}
+
+fn table_header(out: &mut String, label: &str) {
+    let _ = writeln!(out, "{label:>15}        rx      |     tx      |    total    |   avg. rate");
+    table_rule(out);
+}
+
+fn table_rule(out: &mut String) {
+    let _ = writeln!(out, "     ------------------------+-------------+-------------+---------------");
+}
+
+/// A row of the table; estimates have no rate column
+fn table_row(label: &str, traffic: Traffic, rate: Option<String>) -> String {
+    let row = format!(
+        "     {label:<10} {:>13} | {:>11} | {:>11} |",
+        format_bytes(traffic.rx),
+        format_bytes(traffic.tx),
+        format_bytes(traffic.total())
+    );
+    match rate {
+        Some(rate) => format!("{row} {rate:>13}"),
+        None => row,
+    }
+}
+
+/// The running period's traffic scaled up to the whole of it
+fn estimate_row(traffic: Traffic, fraction: f64) -> String {
+    let scale = 1.0 / fraction.max(f64::EPSILON);
+    let estimated = Traffic { rx: traffic.rx * scale, tx: traffic.tx * scale };
+    table_row("estimated", estimated, None)
+}
+
+fn estimate(traffic: Traffic, fraction: f64) -> Option<f64> {
+    (fraction > 0.0).then(|| traffic.total() / fraction)
+}
+
+fn seconds_since_midnight(now: DateTime<Local>) -> f64 {
+    now.time().num_seconds_from_midnight() as f64
+}
+
+fn days_in_month(day: NaiveDate) -> u32 {
+    let first = day.with_day(1).unwrap_or(day);
+    first.checked_add_months(chrono::Months::new(1)).map_or(30, |next| (next - first).num_days() as u32)
+}
+
+/// A volume as vnStat prints it: "523 B", "1.23 GiB"
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes.max(0.0);
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{value:.0} B") } else { format!("{value:.2} {}", UNITS[unit]) }
+}
+
+/// Average rate of `traffic` over `seconds`, in bits as vnStat shows it
+fn rate(traffic: Traffic, seconds: f64) -> String {
+    const UNITS: [&str; 5] = ["bit/s", "kbit/s", "Mbit/s", "Gbit/s", "Tbit/s"];
+    let mut value = traffic.total() * 8.0 / seconds;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{value:.2} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(at: DateTime<Local>, interface: &str, rx: f64, tx: f64) -> BandwidthSample {
+        BandwidthSample { timestamp: at, interface_name: interface.to_string(), duration_secs: 1.0, download_bps: rx, upload_bps: tx }
+    }
+
+    #[test]
+    fn test_volumes_and_rates_as_vnstat_prints_them() {
+        assert_eq!(format_bytes(523.0), "523 B");
+        assert_eq!(format_bytes(1.5 * 1024.0 * 1024.0 * 1024.0), "1.50 GiB");
+        assert_eq!(rate(Traffic { rx: 125_000.0, tx: 0.0 }, 1.0), "1.00 Mbit/s");
+        assert!(OutputStyle::parse("vnStat").is_ok_and(|style| style == OutputStyle::Vnstat));
+        assert!(OutputStyle::parse("ifstat").is_err());
+    }
+
+    #[test]
+    fn test_summary_and_tables() {
+        let now = Local.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap();
+        let samples = [
+            sample(Local.with_ymd_and_hms(2024, 2, 20, 10, 0, 0).unwrap(), "eth0", 4096.0, 1024.0),
+            sample(Local.with_ymd_and_hms(2024, 3, 14, 10, 0, 0).unwrap(), "eth0", 2048.0, 0.0),
+            sample(Local.with_ymd_and_hms(2024, 3, 15, 10, 0, 0).unwrap(), "eth0", 1024.0, 1024.0),
+            sample(Local.with_ymd_and_hms(2024, 3, 15, 10, 0, 0).unwrap(), "lo", 1024.0, 1024.0),
+        ];
+        let usage = usage_by_day(&samples);
+        assert!(!usage.contains_key("lo"));
+
+        let summary = summary(&usage, now);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[1], " eth0:");
+        assert!(lines[2].trim_start().starts_with("2024-02") && lines[2].ends_with("5.00 KiB"), "{summary}");
+        assert!(lines[3].contains("4.00 KiB") && lines[3].matches('/').count() == 3, "{summary}");
+        assert!(lines[4].trim_start().starts_with("yesterday") && lines[4].contains("2.00 KiB"));
+        // Half of today gone: twice today's traffic is expected
+        assert!(lines[5].trim_start().starts_with("today") && lines[5].ends_with("4.00 KiB"), "{summary}");
+
+        let tables = daily_and_monthly(&usage, Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap(), now, now);
+        assert!(tables.contains(" eth0  /  daily"));
+        let row = tables.lines().find(|line| line.trim_start().starts_with("2024-03-14")).unwrap();
+        let columns: Vec<&str> = row.split('|').map(str::trim).collect();
+        assert_eq!(columns, ["2024-03-14      2.00 KiB", "0 B", "2.00 KiB", "0.19 bit/s"]);
+        assert!(tables.contains(" eth0  /  monthly"));
+        assert!(!tables.contains("2024-02"));
+    }
+}
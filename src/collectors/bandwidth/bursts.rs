@@ -0,0 +1,220 @@
+//! Sub-second sampling for micro-bursts
+//!
+//! Per-second speeds average away bursts that fill a link for a few hundred milliseconds,
+//! long enough to overflow switch and modem buffers. `kw status --hires` reads the interface
+//! counters every 100 ms over the measurement window and reports each interface's busiest
+//! interval and how often its traffic burst well above the window's average.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use sysinfo::Networks;
+use tokio::time::MissedTickBehavior;
+
+/// Interval between counter readings in high-resolution mode
+pub const HIRES_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An interval is part of a burst when its rate is this many times the window's average
+pub const BURST_FACTOR: f64 = 3.0;
+
+/// ...and at least this many bytes per second (1 Mbit/s), so a link that is all but idle
+/// does not count single packets as bursts
+pub const MIN_BURST_BYTES_PER_SEC: f64 = 125_000.0;
+
+/// Bursts of one interface over a high-resolution window
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstStats {
+    pub interface_name: String,
+    /// Counter readings compared, one less than were taken
+    pub intervals: usize,
+    pub average_download_bps: f64,
+    pub average_upload_bps: f64,
+    /// Fastest single interval in each direction
+    pub peak_download_bps: f64,
+    pub peak_upload_bps: f64,
+    /// Runs of consecutive intervals above the burst threshold
+    pub bursts: usize,
+    pub longest_burst: Duration,
+    /// Time spent bursting, all bursts together
+    pub burst_time: Duration,
+}
+
+impl BurstStats {
+    /// Share of the window spent bursting, 0.0 - 1.0
+    pub fn burst_share(&self, window: Duration) -> f64 {
+        if window.is_zero() {
+            return 0.0;
+        }
+        (self.burst_time.as_secs_f64() / window.as_secs_f64()).min(1.0)
+    }
+}
+
+/// Counter deltas of one interval: (duration, bytes received, bytes sent)
+type Interval = (Duration, u64, u64);
+
+/// Turns successive counter readings into per-interval rates and bursts
+#[derive(Debug, Default)]
+pub struct BurstSampler {
+    /// Last reading of each interface: (bytes received, bytes sent)
+    last: HashMap<String, (u64, u64)>,
+    last_reading: Option<Instant>,
+    intervals: HashMap<String, Vec<Interval>>,
+}
+
+impl BurstSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reading of cumulative (interface, bytes received, bytes sent) counters taken at `at`
+    /// Interfaces whose counters went backwards are restarted from the new reading
+    pub fn record<'a>(&mut self, at: Instant, counters: impl IntoIterator<Item = (&'a str, u64, u64)>) {
+        let elapsed = self.last_reading.map(|last| at.saturating_duration_since(last));
+        self.last_reading = Some(at);
+        for (name, received, sent) in counters {
+            let previous = self.last.insert(name.to_string(), (received, sent));
+            if let (Some(elapsed), Some((last_received, last_sent))) = (elapsed, previous)
+                && !elapsed.is_zero()
+                && received >= last_received
+                && sent >= last_sent
+            {
+                self.intervals
+                    .entry(name.to_string())
+                    .or_default()
+                    .push((elapsed, received - last_received, sent - last_sent));
+            }
+        }
+    }
+
+    /// Bursts of every interface read at least twice, sorted by interface name
+    pub fn finish(&self) -> Vec<BurstStats> {
+        let mut stats: Vec<BurstStats> = self
+            .intervals
+            .iter()
+            .map(|(name, intervals)| burst_stats(name, intervals))
+            .collect();
+        stats.sort_by(|a, b| a.interface_name.cmp(&b.interface_name));
+        stats
+    }
+}
+
+fn burst_stats(name: &str, intervals: &[Interval]) -> BurstStats {
+    let rate = |bytes: u64, duration: Duration| bytes as f64 / duration.as_secs_f64();
+    let total_time: f64 = intervals.iter().map(|(duration, _, _)| duration.as_secs_f64()).sum();
+    let average_download_bps = intervals.iter().map(|(_, received, _)| *received as f64).sum::<f64>() / total_time;
+    let average_upload_bps = intervals.iter().map(|(_, _, sent)| *sent as f64).sum::<f64>() / total_time;
+
+    // Bursts are judged on the busier direction, as link utilisation is
+    let threshold = (average_download_bps.max(average_upload_bps) * BURST_FACTOR).max(MIN_BURST_BYTES_PER_SEC);
+    let mut stats = BurstStats {
+        interface_name: name.to_string(),
+        intervals: intervals.len(),
+        average_download_bps,
+        average_upload_bps,
+        peak_download_bps: 0.0,
+        peak_upload_bps: 0.0,
+        bursts: 0,
+        longest_burst: Duration::ZERO,
+        burst_time: Duration::ZERO,
+    };
+    let mut current_burst: Option<Duration> = None;
+    for &(duration, received, sent) in intervals {
+        let download = rate(received, duration);
+        let upload = rate(sent, duration);
+        stats.peak_download_bps = stats.peak_download_bps.max(download);
+        stats.peak_upload_bps = stats.peak_upload_bps.max(upload);
+
+        if download.max(upload) >= threshold {
+            if current_burst.is_none() {
+                stats.bursts += 1;
+            }
+            let length = current_burst.unwrap_or_default() + duration;
+            stats.longest_burst = stats.longest_burst.max(length);
+            stats.burst_time += duration;
+            current_burst = Some(length);
+        } else {
+            current_burst = None;
+        }
+    }
+    stats
+}
+
+/// Reads every interface's counters each `interval` for `window` and returns their bursts
+/// Uses its own view of the interfaces, so a collector's readings around it are unaffected
+pub async fn sample_bursts(window: Duration, interval: Duration) -> Vec<BurstStats> {
+    let mut networks = Networks::new_with_refreshed_list();
+    let mut sampler = BurstSampler::new();
+    let mut ticker = tokio::time::interval(interval);
+    // A late reading is timed as it was taken; catching up would only add short intervals
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let deadline = Instant::now() + window;
+    loop {
+        ticker.tick().await;
+        networks.refresh(true);
+        let at = Instant::now();
+        sampler.record(
+            at,
+            networks
+                .iter()
+                .map(|(name, network)| (name.as_str(), network.total_received(), network.total_transmitted())),
+        );
+        if at >= deadline {
+            break;
+        }
+    }
+    sampler.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steady_traffic_does_not_burst() {
+        let start = Instant::now();
+        let mut sampler = BurstSampler::new();
+        // 100 KB per 100 ms (1 MB/s) on eth0, nothing on lo
+        for step in 0..=10u32 {
+            sampler.record(start + HIRES_INTERVAL * step, [("eth0", u64::from(step) * 100_000, 0), ("lo", 0, 0)]);
+        }
+
+        let stats = sampler.finish();
+        assert_eq!(stats.len(), 2);
+        let eth0 = &stats[0];
+        assert_eq!(eth0.interface_name, "eth0");
+        assert_eq!(eth0.intervals, 10);
+        assert!((eth0.average_download_bps - 1_000_000.0).abs() < 1.0);
+        assert!((eth0.peak_download_bps - 1_000_000.0).abs() < 1.0);
+        assert_eq!(eth0.bursts, 0);
+
+        // An idle link never bursts
+        assert_eq!(stats[1].bursts, 0);
+        assert_eq!(stats[1].peak_download_bps, 0.0);
+    }
+
+    #[test]
+    fn test_burst_runs_are_counted_once() {
+        let start = Instant::now();
+        let mut sampler = BurstSampler::new();
+        let mut sent = 0u64;
+        // Mostly 10 KB per 100 ms, with 1 MB bursts of 200 ms and 100 ms
+        for step in 0..=20u32 {
+            sampler.record(start + HIRES_INTERVAL * step, [("eth0", 0, sent)]);
+            sent += match step {
+                4 | 5 | 12 => 1_000_000,
+                _ => 10_000,
+            };
+        }
+
+        let eth0 = &sampler.finish()[0];
+        assert_eq!(eth0.bursts, 2);
+        assert_eq!(eth0.longest_burst, Duration::from_millis(200));
+        assert_eq!(eth0.burst_time, Duration::from_millis(300));
+        assert!((eth0.burst_share(Duration::from_secs(2)) - 0.15).abs() < 1e-9);
+        assert!((eth0.peak_upload_bps - 10_000_000.0).abs() < 1.0);
+
+        // A counter reset restarts the interface instead of producing a huge interval
+        sampler.record(start + HIRES_INTERVAL * 21, [("eth0", 0, 0)]);
+        assert_eq!(sampler.finish()[0].intervals, 20);
+    }
+}
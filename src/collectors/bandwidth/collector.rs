@@ -7,9 +7,10 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use log::{debug, error, info, trace, warn};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::thread;
-use std::time::Duration;
-use sysinfo::Networks;
+use std::time::{Duration, Instant};
+use sysinfo::{Networks, System};
 
 use crate::collectors::bandwidth::errors::{
     BandwidthError, log_error_event_anyhow, log_success_event,
@@ -17,11 +18,30 @@ use crate::collectors::bandwidth::errors::{
 use crate::collectors::bandwidth::reporting::{
     BandwidthReporter, ErrorContextReport, InterfaceSummaryReport, TroubleshootingReport,
 };
+use crate::collectors::bandwidth::sampling::SamplingPlan;
+use crate::collectors::bandwidth::state::{self, CounterState};
 use crate::collectors::bandwidth::stats::{BandwidthStats, InterfaceState, InterfaceType};
+use crate::collectors::bandwidth::telemetry::CollectorTelemetry;
 use crate::collectors::bandwidth::validation::{
     calculate_speeds_with_validation, validate_interface_data,
 };
+use crate::collectors::platform::interface_events::{InterfaceEvent, InterfaceWatcher};
 use crate::collectors::platform::interface_manager::{InterfaceManager, PlatformInterfaceInfo};
+use crate::collectors::platform::power_events::{PowerEvent, PowerWatcher};
+
+/// Slack when deciding whether an interface is due, so timer jitter does not skip a sample
+const SAMPLING_JITTER: Duration = Duration::from_millis(100);
+
+/// Timing of one `collect` call, for callers that adapt how often they collect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollectionMetrics {
+    /// Number of the collection since the collector was created
+    pub collection: u64,
+    /// Wall-clock time the collection took, refresh of the interface counters included
+    pub duration: Duration,
+    /// Interfaces read or repeated from the sampling cache
+    pub interfaces: usize,
+}
 
 /// Collects bandwidth statistics from network interfaces
 /// Maintains previous readings to calculate speed deltas with robust error handling
@@ -42,6 +62,35 @@ pub struct BandwidthCollector {
     min_time_threshold: f64,
     /// Counter for total collections performed
     collection_count: u64,
+    /// Number of interface counter resets or wraparounds seen so far
+    counter_resets: u64,
+    /// Per-interface sampling intervals; empty samples every interface on every collection
+    sampling: SamplingPlan,
+    /// When each interface was last sampled under the sampling plan
+    last_sampled: HashMap<String, Instant>,
+    /// Last reading of each interface, reported again while it is not due
+    cached_stats: HashMap<String, BandwidthStats>,
+    /// Interface hotplug notifications; without it interface changes are noticed on the next refresh
+    interface_watcher: Option<InterfaceWatcher>,
+    /// Suspend and resume notifications; without them a sleep is only suspected from a long interval
+    power_watcher: Option<PowerWatcher>,
+    /// Timing of the last successful collection
+    last_metrics: Option<CollectionMetrics>,
+    /// Refresh attempts repeated during the current collection
+    refresh_retries: u32,
+    /// How collections went since `take_telemetry` was last called
+    telemetry: CollectorTelemetry,
+    /// Where the counter baselines are kept across restarts, if anywhere
+    state_file: Option<PathBuf>,
+    /// When the state file was last written
+    last_state_save: Option<Instant>,
+}
+
+impl Drop for BandwidthCollector {
+    /// Saves the latest counters, so a collector started next on this boot continues from them
+    fn drop(&mut self) {
+        self.save_state();
+    }
 }
 
 impl Default for BandwidthCollector {
@@ -61,6 +110,17 @@ impl BandwidthCollector {
             retry_delay_ms: 100,
             min_time_threshold: 0.1, // 100ms minimum interval
             collection_count: 0,
+            counter_resets: 0,
+            sampling: SamplingPlan::default(),
+            last_sampled: HashMap::new(),
+            cached_stats: HashMap::new(),
+            interface_watcher: None,
+            power_watcher: None,
+            last_metrics: None,
+            refresh_retries: 0,
+            telemetry: CollectorTelemetry::default(),
+            state_file: None,
+            last_state_save: None,
         }
     }
 
@@ -74,6 +134,123 @@ impl BandwidthCollector {
             retry_delay_ms,
             min_time_threshold: 0.1,
             collection_count: 0,
+            counter_resets: 0,
+            sampling: SamplingPlan::default(),
+            last_sampled: HashMap::new(),
+            cached_stats: HashMap::new(),
+            interface_watcher: None,
+            power_watcher: None,
+            last_metrics: None,
+            refresh_retries: 0,
+            telemetry: CollectorTelemetry::default(),
+            state_file: None,
+            last_state_save: None,
+        }
+    }
+
+    /// Samples interfaces at their own intervals instead of on every collection
+    pub fn with_sampling_plan(mut self, plan: SamplingPlan) -> Self {
+        self.sampling = plan;
+        self
+    }
+
+    /// Keeps the last counters of each interface in `path`, and starts from the ones saved
+    /// there by a collector that ran shortly before on the same boot
+    pub fn with_state_file(mut self, path: PathBuf) -> Self {
+        match CounterState::load(&path) {
+            Ok(Some(saved)) => {
+                let baselines = saved.baselines(Utc::now(), System::boot_time());
+                if baselines.is_empty() {
+                    debug!("Saved counters in {} are from an earlier boot or too old", path.display());
+                } else {
+                    info!("Restored counter baselines of {} interfaces from {}", baselines.len(), path.display());
+                }
+                self.previous_stats.extend(baselines);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Starting without saved counters: {e:#}"),
+        }
+        self.state_file = Some(path);
+        self
+    }
+
+    /// Reacts to interfaces appearing, disappearing or changing as the operating system reports them
+    /// Falls back to noticing changes on the next refresh when notifications are unavailable
+    pub fn with_interface_watcher(mut self) -> Self {
+        match InterfaceWatcher::start() {
+            Ok(watcher) => self.interface_watcher = Some(watcher),
+            Err(e) => warn!("Interface change notifications unavailable, relying on polling: {}", e),
+        }
+        self
+    }
+
+    /// Starts every interface from a fresh baseline after the system sleeps, as it reports it
+    /// Falls back to the interval heuristics when notifications are unavailable
+    pub fn with_power_watcher(mut self) -> Self {
+        match PowerWatcher::start() {
+            Ok(watcher) => self.power_watcher = Some(watcher),
+            Err(e) => warn!("Suspend and resume notifications unavailable, relying on interval checks: {}", e),
+        }
+        self
+    }
+
+    /// Applies suspend and resume events reported since the last call and returns them
+    /// The baselines of every interface are dropped, so the first reading after the wake-up
+    /// starts afresh instead of averaging the traffic before the sleep over the time asleep
+    pub fn poll_power_events(&mut self) -> Vec<PowerEvent> {
+        let Some(watcher) = &self.power_watcher else {
+            return Vec::new();
+        };
+        let events = watcher.try_events();
+        if events.is_empty() {
+            return events;
+        }
+
+        info!(
+            "Power state changed ({}), restarting counter baselines of {} interfaces",
+            events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", "),
+            self.previous_stats.len()
+        );
+        self.interface_manager.clear_cache();
+        self.previous_stats.clear();
+        self.last_sampled.clear();
+        self.cached_stats.clear();
+        events
+    }
+
+    /// Applies interface changes reported since the last call and returns them
+    /// The interface cache is cleared, and removed interfaces are forgotten so that one
+    /// plugged in again starts from a fresh baseline instead of a bogus counter delta
+    pub fn poll_interface_events(&mut self) -> Vec<InterfaceEvent> {
+        let Some(watcher) = &self.interface_watcher else {
+            return Vec::new();
+        };
+        let events = watcher.try_events();
+        if events.is_empty() {
+            return events;
+        }
+
+        info!("Interfaces changed: {}", events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", "));
+        self.interface_manager.clear_cache();
+        for event in &events {
+            if let InterfaceEvent::Removed(name) = event {
+                self.previous_stats.remove(name);
+                self.last_sampled.remove(name);
+                self.cached_stats.remove(name);
+            }
+        }
+        events
+    }
+
+    /// Whether `interface_name` is due for a new reading under the sampling plan
+    fn is_due(&mut self, interface_name: &str) -> bool {
+        let Some(last) = self.last_sampled.get(interface_name).copied() else {
+            return true;
+        };
+        let interface_type = self.interface_manager.analyze_interface(interface_name).interface_type;
+        match self.sampling.interval_for(interface_name, &interface_type) {
+            Some(interval) => last.elapsed() + SAMPLING_JITTER >= interval,
+            None => true,
         }
     }
 
@@ -81,6 +258,36 @@ impl BandwidthCollector {
     /// Returns only relevant interfaces based on platform-specific filtering and prioritization
     pub fn collect_filtered(&mut self) -> Result<Vec<BandwidthStats>> {
         let all_stats = self.collect()?;
+        Ok(self.keep_relevant(all_stats))
+    }
+
+    /// Collects bandwidth statistics for default interfaces only
+    /// Returns interfaces that should be shown by default (excludes virtual/irrelevant interfaces)
+    pub fn collect_default(&mut self) -> Result<Vec<BandwidthStats>> {
+        let all_stats = self.collect()?;
+        Ok(self.keep_default(all_stats))
+    }
+
+    /// `collect_default` without blocking the async runtime while refreshes are retried
+    pub async fn collect_default_async(&mut self) -> Result<Vec<BandwidthStats>> {
+        let all_stats = self.collect_async().await?;
+        Ok(self.keep_default(all_stats))
+    }
+
+    /// Collects bandwidth statistics for important interfaces only
+    /// Returns only high-priority interfaces (physical ethernet, wifi, VPN)
+    pub fn collect_important(&mut self) -> Result<Vec<BandwidthStats>> {
+        let all_stats = self.collect()?;
+        Ok(self.keep_important(all_stats))
+    }
+
+    /// `collect_important` without blocking the async runtime while refreshes are retried
+    pub async fn collect_important_async(&mut self) -> Result<Vec<BandwidthStats>> {
+        let all_stats = self.collect_async().await?;
+        Ok(self.keep_important(all_stats))
+    }
+
+    fn keep_relevant(&mut self, all_stats: Vec<BandwidthStats>) -> Vec<BandwidthStats> {
         let interface_names: Vec<String> =
             all_stats.iter().map(|s| s.interface_name.clone()).collect();
         let relevant_interfaces = self
@@ -103,13 +310,10 @@ impl BandwidthCollector {
             interface_names.len()
         );
 
-        Ok(filtered_stats)
+        filtered_stats
     }
 
-    /// Collects bandwidth statistics for default interfaces only
-    /// Returns interfaces that should be shown by default (excludes virtual/irrelevant interfaces)
-    pub fn collect_default(&mut self) -> Result<Vec<BandwidthStats>> {
-        let all_stats = self.collect()?;
+    fn keep_default(&mut self, all_stats: Vec<BandwidthStats>) -> Vec<BandwidthStats> {
         let interface_names: Vec<String> =
             all_stats.iter().map(|s| s.interface_name.clone()).collect();
         let default_interfaces = self
@@ -132,13 +336,10 @@ impl BandwidthCollector {
             interface_names.len()
         );
 
-        Ok(default_stats)
+        default_stats
     }
 
-    /// Collects bandwidth statistics for important interfaces only
-    /// Returns only high-priority interfaces (physical ethernet, wifi, VPN)
-    pub fn collect_important(&mut self) -> Result<Vec<BandwidthStats>> {
-        let all_stats = self.collect()?;
+    fn keep_important(&mut self, all_stats: Vec<BandwidthStats>) -> Vec<BandwidthStats> {
         let interface_names: Vec<String> =
             all_stats.iter().map(|s| s.interface_name.clone()).collect();
         let important_interfaces = self
@@ -161,7 +362,7 @@ impl BandwidthCollector {
             interface_names.len()
         );
 
-        Ok(important_stats)
+        important_stats
     }
 
     /// Gets detailed interface information for a specific interface
@@ -202,22 +403,44 @@ impl BandwidthCollector {
     /// Returns a vector of BandwidthStats, one per active interface
     /// Implements robust error handling and comprehensive logging
     pub fn collect(&mut self) -> Result<Vec<BandwidthStats>> {
-        let collection_start = std::time::Instant::now();
-        let now = Utc::now();
+        let collection_start = self.begin_collection();
+        // Refresh network statistics with retry logic and detailed error logging
+        let refreshed = self.refresh_network_data_with_retry();
+        self.finish_collection(collection_start, refreshed)
+    }
+
+    /// `collect` for async callers: waits between refresh retries with `tokio::time::sleep`
+    /// rather than blocking the runtime's thread
+    pub async fn collect_async(&mut self) -> Result<Vec<BandwidthStats>> {
+        let collection_start = self.begin_collection();
+        let refreshed = self.refresh_network_data_with_retry_async().await;
+        self.finish_collection(collection_start, refreshed)
+    }
+
+    /// Counts and logs a new collection; returns when it started
+    fn begin_collection(&mut self) -> Instant {
+        let collection_start = Instant::now();
         self.collection_count += 1;
+        self.refresh_retries = 0;
+        self.poll_interface_events();
+        self.poll_power_events();
 
         // Enhanced logging for collection events
         info!(
             "Starting bandwidth collection #{} at {} (cached_interfaces={}, retry_config=max_retries={}, delay={}ms)",
             self.collection_count,
-            now.format("%H:%M:%S%.3f"),
+            Utc::now().format("%H:%M:%S%.3f"),
             self.previous_stats.len(),
             self.max_retries,
             self.retry_delay_ms
         );
+        collection_start
+    }
 
-        // Refresh network statistics with retry logic and detailed error logging
-        match self.refresh_network_data_with_retry() {
+    /// Turns the refreshed interface counters into statistics
+    fn finish_collection(&mut self, collection_start: Instant, refreshed: Result<()>) -> Result<Vec<BandwidthStats>> {
+        let now = Utc::now();
+        match refreshed {
             Ok(()) => {
                 debug!(
                     "Network data refresh successful for collection #{} (interfaces_found={})",
@@ -232,6 +455,7 @@ impl BandwidthCollector {
                 );
                 // Log error event for monitoring
                 log_error_event_anyhow(&e, "network_refresh_failure", self.collection_count);
+                self.telemetry.record(collection_start.elapsed(), self.refresh_retries, &[], false);
                 return Err(e.context("Critical failure: Unable to refresh network data"));
             }
         }
@@ -240,18 +464,21 @@ impl BandwidthCollector {
         let mut successful_interfaces = 0;
         let mut failed_interfaces = 0;
         let mut interface_errors = Vec::new();
+        let mut failed_names = Vec::new();
 
         // Collect interface data first to avoid borrowing issues
+        // The totals since the interface came up, not the bytes since the last refresh, so
+        // readings compare with those of earlier refreshes and of a collector before a restart
         let interface_data: Vec<(String, u64, u64, u64, u64)> = self
             .networks
             .iter()
             .map(|(name, network)| {
                 (
                     name.to_string(),
-                    network.received(),
-                    network.transmitted(),
-                    network.packets_received(),
-                    network.packets_transmitted(),
+                    network.total_received(),
+                    network.total_transmitted(),
+                    network.total_packets_received(),
+                    network.total_packets_transmitted(),
                 )
             })
             .collect();
@@ -276,6 +503,15 @@ impl BandwidthCollector {
                 packets_sent
             );
 
+            // Interfaces with a longer sampling interval repeat their last reading until due
+            if !self.sampling.is_empty() && !self.is_due(&interface_name) {
+                if let Some(cached) = self.cached_stats.get(&interface_name) {
+                    stats.push(cached.clone());
+                    successful_interfaces += 1;
+                }
+                continue;
+            }
+
             // Validate interface data before processing
             match validate_interface_data(
                 &interface_name,
@@ -299,6 +535,7 @@ impl BandwidthCollector {
 
                     let error_msg = format!("Interface '{}': {}", interface_name, validation_error);
                     interface_errors.push(error_msg);
+                    failed_names.push(interface_name.clone());
                     failed_interfaces += 1;
 
                     // Mark interface as having consecutive failures
@@ -315,6 +552,12 @@ impl BandwidthCollector {
                 }
             }
 
+            if let Some((prev_rx, prev_tx, _, _)) = self.previous_stats.get(&interface_name)
+                && (bytes_received < *prev_rx || bytes_sent < *prev_tx)
+            {
+                self.counter_resets += 1;
+            }
+
             // Calculate speeds with enhanced error handling
             let (download_speed_bps, upload_speed_bps, calculation_confidence) =
                 calculate_speeds_with_validation(
@@ -372,6 +615,11 @@ impl BandwidthCollector {
                 time_since_last_update,
             });
 
+            if !self.sampling.is_empty() {
+                self.last_sampled.insert(interface_name.clone(), Instant::now());
+                self.cached_stats.insert(interface_name.clone(), stats[stats.len() - 1].clone());
+            }
+
             successful_interfaces += 1;
         }
 
@@ -430,10 +678,10 @@ impl BandwidthCollector {
                 "No specific interface errors recorded - possible system-level issue".to_string()
             };
 
-            return Err(anyhow::anyhow!(
-                "No network interfaces could be processed successfully. {}",
-                error_context
-            ));
+            let error = anyhow::anyhow!("No network interfaces could be processed successfully. {}", error_context);
+            log_error_event_anyhow(&error, "no_interfaces_processed", self.collection_count);
+            self.telemetry.record(collection_duration, self.refresh_retries, &failed_names, false);
+            return Err(error);
         } else if failed_interfaces > 0 {
             warn!(
                 "Collection #{} operating in degraded mode: {}/{} interfaces failed (degradation_level: {})",
@@ -458,10 +706,45 @@ impl BandwidthCollector {
             total_upload_bps,
             self.collection_count,
         );
+        self.telemetry.record(collection_duration, self.refresh_retries, &failed_names, true);
+        if self.state_file.is_some() && self.last_state_save.is_none_or(|at| at.elapsed() >= state::SAVE_INTERVAL) {
+            self.save_state();
+        }
+        self.last_metrics = Some(CollectionMetrics {
+            collection: self.collection_count,
+            duration: collection_duration,
+            interfaces: stats.len(),
+        });
 
         Ok(stats)
     }
 
+    /// How long the last successful collection took; `None` before the first
+    pub fn last_collection_metrics(&self) -> Option<CollectionMetrics> {
+        self.last_metrics
+    }
+
+    /// Writes the counter baselines to the state file, if one is set
+    pub fn save_state(&mut self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        match CounterState::new(&self.previous_stats, System::boot_time()).save(path) {
+            Ok(()) => self.last_state_save = Some(Instant::now()),
+            Err(e) => warn!("Failed to save counter baselines: {e:#}"),
+        }
+    }
+
+    /// How collections went since the last call, for storing as the collector's own health
+    pub fn take_telemetry(&mut self) -> CollectorTelemetry {
+        std::mem::take(&mut self.telemetry)
+    }
+
+    /// Number of interface counter resets or wraparounds detected since creation
+    pub fn counter_resets(&self) -> u64 {
+        self.counter_resets
+    }
+
     /// Returns total bandwidth usage across all interfaces
     /// Returns tuple of (total_download_bytes, total_upload_bytes)
     pub fn get_total_bandwidth(&self) -> (f64, f64) {
@@ -478,70 +761,108 @@ impl BandwidthCollector {
     }
     /// Refreshes network data with retry logic and comprehensive error logging
     fn refresh_network_data_with_retry(&mut self) -> Result<()> {
+        let retry_start = self.begin_refresh();
         let mut last_error = None;
-        let retry_start = std::time::Instant::now();
+        for attempt in 0..=self.max_retries {
+            match self.refresh_attempt(attempt, retry_start) {
+                Ok(()) => return Ok(()),
+                Err((e, delay)) => {
+                    last_error = Some(e);
+                    if let Some(delay) = delay {
+                        thread::sleep(delay);
+                    }
+                }
+            }
+        }
+        Err(self.refresh_failed(last_error, retry_start))
+    }
+
+    /// `refresh_network_data_with_retry` sleeping between attempts on the tokio timer
+    async fn refresh_network_data_with_retry_async(&mut self) -> Result<()> {
+        let retry_start = self.begin_refresh();
+        let mut last_error = None;
+        for attempt in 0..=self.max_retries {
+            match self.refresh_attempt(attempt, retry_start) {
+                Ok(()) => return Ok(()),
+                Err((e, delay)) => {
+                    last_error = Some(e);
+                    if let Some(delay) = delay {
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+            }
+        }
+        Err(self.refresh_failed(last_error, retry_start))
+    }
 
+    fn begin_refresh(&self) -> Instant {
         debug!(
             "Starting network data refresh with retry logic for collection #{} (max_retries={}, retry_delay={}ms)",
             self.collection_count, self.max_retries, self.retry_delay_ms
         );
+        Instant::now()
+    }
 
-        for attempt in 0..=self.max_retries {
-            let attempt_start = std::time::Instant::now();
+    /// One refresh of the retry loop; on failure, the error and how long to wait before the
+    /// next attempt, `None` after the last one
+    fn refresh_attempt(&mut self, attempt: u32, retry_start: Instant) -> Result<(), (String, Option<Duration>)> {
+        let attempt_start = Instant::now();
 
-            match self.refresh_network_data() {
-                Ok(()) => {
-                    let total_duration = retry_start.elapsed();
-                    let total_duration_ms = total_duration.as_secs_f64() * 1000.0;
-
-                    if attempt > 0 {
-                        info!(
-                            "Network data refresh succeeded after retries for collection #{}: attempt {}/{} (total_duration={:.3}ms, recovery_successful=true)",
-                            self.collection_count,
-                            attempt + 1,
-                            self.max_retries + 1,
-                            total_duration_ms
-                        );
-                    } else {
-                        trace!(
-                            "Network data refresh succeeded on first attempt for collection #{} (duration={:.3}ms, optimal_performance=true)",
-                            self.collection_count,
-                            attempt_start.elapsed().as_secs_f64() * 1000.0
-                        );
-                    }
-                    return Ok(());
+        match self.refresh_network_data() {
+            Ok(()) => {
+                let total_duration = retry_start.elapsed();
+                let total_duration_ms = total_duration.as_secs_f64() * 1000.0;
+
+                if attempt > 0 {
+                    info!(
+                        "Network data refresh succeeded after retries for collection #{}: attempt {}/{} (total_duration={:.3}ms, recovery_successful=true)",
+                        self.collection_count,
+                        attempt + 1,
+                        self.max_retries + 1,
+                        total_duration_ms
+                    );
+                } else {
+                    trace!(
+                        "Network data refresh succeeded on first attempt for collection #{} (duration={:.3}ms, optimal_performance=true)",
+                        self.collection_count,
+                        attempt_start.elapsed().as_secs_f64() * 1000.0
+                    );
                 }
-                Err(e) => {
-                    let attempt_duration = attempt_start.elapsed();
-                    let attempt_duration_ms = attempt_duration.as_secs_f64() * 1000.0;
-                    last_error = Some(e.clone());
-
-                    if attempt < self.max_retries {
-                        let delay = Duration::from_millis(self.retry_delay_ms * (1 << attempt));
-                        warn!(
-                            "Network refresh attempt {}/{} failed for collection #{} (duration={:.3}ms) - retrying with exponential backoff in {}ms: {}",
-                            attempt + 1,
-                            self.max_retries + 1,
-                            self.collection_count,
-                            attempt_duration_ms,
-                            delay.as_millis(),
-                            e
-                        );
-                        thread::sleep(delay);
-                    } else {
-                        error!(
-                            "Network refresh final attempt {}/{} failed for collection #{} (duration={:.3}ms) - no more retries: {}",
-                            attempt + 1,
-                            self.max_retries + 1,
-                            self.collection_count,
-                            attempt_duration_ms,
-                            e
-                        );
-                    }
+                Ok(())
+            }
+            Err(e) => {
+                let attempt_duration = attempt_start.elapsed();
+                let attempt_duration_ms = attempt_duration.as_secs_f64() * 1000.0;
+
+                if attempt < self.max_retries {
+                    self.refresh_retries += 1;
+                    let delay = Duration::from_millis(self.retry_delay_ms * (1 << attempt));
+                    warn!(
+                        "Network refresh attempt {}/{} failed for collection #{} (duration={:.3}ms) - retrying with exponential backoff in {}ms: {}",
+                        attempt + 1,
+                        self.max_retries + 1,
+                        self.collection_count,
+                        attempt_duration_ms,
+                        delay.as_millis(),
+                        e
+                    );
+                    Err((e, Some(delay)))
+                } else {
+                    error!(
+                        "Network refresh final attempt {}/{} failed for collection #{} (duration={:.3}ms) - no more retries: {}",
+                        attempt + 1,
+                        self.max_retries + 1,
+                        self.collection_count,
+                        attempt_duration_ms,
+                        e
+                    );
+                    Err((e, None))
                 }
             }
         }
+    }
 
+    fn refresh_failed(&self, last_error: Option<String>, retry_start: Instant) -> anyhow::Error {
         let total_duration = retry_start.elapsed();
         let total_duration_ms = total_duration.as_secs_f64() * 1000.0;
         let final_error =
@@ -554,11 +875,11 @@ impl BandwidthCollector {
             total_duration_ms
         );
 
-        Err(BandwidthError::RefreshFailed {
+        BandwidthError::RefreshFailed {
             message: final_error,
             retry_attempts: self.max_retries,
         }
-        .into())
+        .into()
     }
 
     /// Performs the actual network data refresh using proper sysinfo API calls
@@ -880,4 +1201,62 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_collect_async_shares_state_with_collect() {
+        let mut collector = BandwidthCollector::new();
+        let Ok(first) = collector.collect() else {
+            // No interfaces to read in this environment
+            return;
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let second = collector.collect_async().await.unwrap();
+        assert_eq!(collector.last_collection_metrics().unwrap().collection, 2);
+        assert_eq!(first.len(), second.len());
+        // The second reading measures speed against the first
+        assert!(second.iter().all(|stat| stat.time_since_last_update > 0.0));
+
+        let default = collector.collect_default_async().await.unwrap();
+        assert!(default.len() <= second.len());
+    }
+
+    #[test]
+    fn test_resume_restarts_every_baseline() {
+        let plan = SamplingPlan::from_intervals(&HashMap::from([("*".to_string(), 1)])).unwrap();
+        let mut collector = BandwidthCollector::new().with_sampling_plan(plan);
+        let (power, watcher) = PowerWatcher::manual();
+        collector.power_watcher = Some(watcher);
+
+        let Ok(first) = collector.collect() else {
+            // No interfaces to read in this environment
+            return;
+        };
+        if first.is_empty() {
+            return;
+        }
+        assert!(collector.poll_power_events().is_empty());
+        assert!(!collector.previous_stats.is_empty());
+        assert!(!collector.last_sampled.is_empty());
+        assert!(!collector.cached_stats.is_empty());
+        assert!(collector.get_interface_manager_stats().0 > 0);
+
+        power.send(PowerEvent::Suspending).unwrap();
+        power.send(PowerEvent::Resumed).unwrap();
+        assert_eq!(collector.poll_power_events(), [PowerEvent::Suspending, PowerEvent::Resumed]);
+        assert!(collector.previous_stats.is_empty());
+        assert!(collector.last_sampled.is_empty());
+        assert!(collector.cached_stats.is_empty());
+        assert_eq!(collector.get_interface_manager_stats().0, 0);
+
+        // The reading after the wake-up is a new baseline rather than a rate across the sleep
+        std::thread::sleep(Duration::from_millis(20));
+        let after = collector.collect().unwrap();
+        assert_eq!(after.len(), first.len());
+        for stat in &after {
+            assert_eq!(stat.time_since_last_update, 0.0, "{}", stat.interface_name);
+            assert_eq!(stat.download_speed_bps, 0.0);
+            assert_eq!(stat.upload_speed_bps, 0.0);
+        }
+    }
 }
@@ -12,17 +12,24 @@
 //! - `stats`: BandwidthStats and related data structures
 //! - `validation`: Data validation logic and speed calculation validation
 //! - `reporting`: Troubleshooting reports and diagnostic information
+//! - `sampling`: Per-interface sampling intervals
+//! - `bursts`: Sub-second sampling for micro-bursts (`kw status --hires`)
+//! - `telemetry`: The collector's own health, stored by the daemon (`kw stats self`)
+//! - `state`: Counter baselines kept across restarts
 //! - `formatting`: Utility functions for formatting bandwidth data
 //!
 //! ## Usage
 //!
 //! This module maintains the exact same public API as the original bandwidth_collector.rs:
 //!
-//! ```rust
-//! use crate::collectors::bandwidth::BandwidthCollector;
+//! ```no_run
+//! use kaipo_watcher::collectors::bandwidth::BandwidthCollector;
 //!
+//! # fn main() -> anyhow::Result<()> {
 //! let mut collector = BandwidthCollector::new();
 //! let stats = collector.collect()?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ## Refactoring Benefits
@@ -34,15 +41,21 @@
 //! - **Backward Compatibility**: All existing code continues to work unchanged
 
 // Module declarations
+pub mod bursts;
 pub mod collector;
 pub mod errors;
 pub mod formatting;
 pub mod reporting;
+pub mod sampling;
+pub mod state;
 pub mod stats;
+pub mod telemetry;
 pub mod validation;
 
 // Re-export the main collector - primary public interface
 pub use collector::BandwidthCollector;
+pub use sampling::SamplingPlan;
+pub use telemetry::CollectorTelemetry;
 
 // Re-export core data structures and types
 pub use stats::{BandwidthStats, CalculationConfidence, InterfaceState, InterfaceType};
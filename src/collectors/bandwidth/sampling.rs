@@ -0,0 +1,163 @@
+//! Per-interface sampling intervals
+//!
+//! Lets busy hosts read their primary link every second while dozens of container
+//! bridges are only re-sampled every 30 seconds. Interfaces that are not due keep
+//! their previous reading, and their next speed is averaged over the longer gap.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::collectors::interface_selection::glob_match;
+use crate::collectors::platform::interface_manager::{EnhancedInterfaceType, VirtualInterfaceType};
+
+/// Which interfaces a sampling rule applies to
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    /// Exact interface name, e.g. `eth0`
+    Name(String),
+    /// Glob with `*` and `?`, e.g. `docker*`
+    Pattern(String),
+    /// Interface type group, e.g. `@container`
+    Group(String),
+}
+
+/// Interface type groups usable as `@group` selectors
+pub const GROUPS: &[&str] = &["ethernet", "wifi", "loopback", "virtual", "vpn", "container", "vm", "bridge", "tunnel"];
+
+/// Sampling intervals per interface, name or group
+///
+/// Precedence: exact name, then the most specific glob (most literal characters), then group.
+/// Interfaces matching no rule use the default interval, or every collection without one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SamplingPlan {
+    rules: Vec<(Selector, Duration)>,
+    default_interval: Option<Duration>,
+}
+
+impl SamplingPlan {
+    /// Builds a plan from `selector = seconds` pairs
+    pub fn from_intervals(intervals: &HashMap<String, u64>) -> Result<Self> {
+        let mut rules = Vec::with_capacity(intervals.len());
+        for (key, secs) in intervals {
+            if *secs == 0 {
+                bail!("Sampling interval for '{key}' must be at least 1 second");
+            }
+            let selector = if let Some(group) = key.strip_prefix('@') {
+                let group = group.to_lowercase();
+                if !GROUPS.contains(&group.as_str()) {
+                    bail!("Unknown interface group '@{group}' (expected one of: {})", GROUPS.join(", "));
+                }
+                Selector::Group(group)
+            } else if key.contains(['*', '?']) {
+                Selector::Pattern(key.clone())
+            } else {
+                Selector::Name(key.clone())
+            };
+            rules.push((selector, Duration::from_secs(*secs)));
+        }
+
+        Ok(Self {
+            rules,
+            default_interval: None,
+        })
+    }
+
+    /// Interval for interfaces no rule matches
+    pub fn with_default(mut self, interval: Duration) -> Self {
+        self.default_interval = Some(interval);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Shortest interval in the plan: how often collection has to run to honour every rule
+    pub fn min_interval(&self) -> Option<Duration> {
+        self.rules.iter().map(|(_, d)| *d).chain(self.default_interval).min()
+    }
+
+    /// Interval that applies to `name`, if any
+    pub fn interval_for(&self, name: &str, interface_type: &EnhancedInterfaceType) -> Option<Duration> {
+        let exact = self.rules.iter().find_map(|(selector, interval)| match selector {
+            Selector::Name(n) if n == name => Some(*interval),
+            _ => None,
+        });
+
+        let pattern = || {
+            self.rules
+                .iter()
+                .filter_map(|(selector, interval)| match selector {
+                    Selector::Pattern(p) if glob_match(p, name) => Some((literal_len(p), *interval)),
+                    _ => None,
+                })
+                .max_by_key(|(specificity, _)| *specificity)
+                .map(|(_, interval)| interval)
+        };
+
+        let group = || {
+            self.rules.iter().find_map(|(selector, interval)| match selector {
+                Selector::Group(g) if in_group(g, interface_type) => Some(*interval),
+                _ => None,
+            })
+        };
+
+        exact.or_else(pattern).or_else(group).or(self.default_interval)
+    }
+}
+
+fn in_group(group: &str, interface_type: &EnhancedInterfaceType) -> bool {
+    match (group, interface_type) {
+        ("ethernet", EnhancedInterfaceType::Ethernet { .. }) => true,
+        ("wifi", EnhancedInterfaceType::WiFi { .. }) => true,
+        ("loopback", EnhancedInterfaceType::Loopback) => true,
+        ("virtual", EnhancedInterfaceType::Virtual { .. }) => true,
+        (_, EnhancedInterfaceType::Virtual { virtual_type }) => matches!(
+            (group, virtual_type),
+            ("vpn", VirtualInterfaceType::VPN)
+                | ("container", VirtualInterfaceType::Container)
+                | ("vm", VirtualInterfaceType::VM)
+                | ("bridge", VirtualInterfaceType::Bridge)
+                | ("tunnel", VirtualInterfaceType::Tunnel)
+        ),
+        _ => false,
+    }
+}
+
+fn literal_len(pattern: &str) -> usize {
+    pattern.chars().filter(|c| !matches!(c, '*' | '?')).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(pairs: &[(&str, u64)]) -> SamplingPlan {
+        let intervals = pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        SamplingPlan::from_intervals(&intervals).unwrap()
+    }
+
+    #[test]
+    fn test_rule_precedence() {
+        let container = EnhancedInterfaceType::Virtual { virtual_type: VirtualInterfaceType::Container };
+        let plan = plan(&[("docker0", 5), ("docker*", 30), ("d*", 60), ("@container", 20)])
+            .with_default(Duration::from_secs(1));
+
+        assert_eq!(plan.interval_for("docker0", &container), Some(Duration::from_secs(5)));
+        assert_eq!(plan.interval_for("docker1", &container), Some(Duration::from_secs(30)));
+        assert_eq!(plan.interval_for("dummy0", &EnhancedInterfaceType::Unknown), Some(Duration::from_secs(60)));
+        assert_eq!(plan.interval_for("veth12ab", &container), Some(Duration::from_secs(20)));
+        assert_eq!(plan.interval_for("eth0", &EnhancedInterfaceType::Unknown), Some(Duration::from_secs(1)));
+        assert_eq!(plan.min_interval(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let intervals = HashMap::from([("@printers".to_string(), 30)]);
+        assert!(SamplingPlan::from_intervals(&intervals).is_err());
+
+        let intervals = HashMap::from([("eth0".to_string(), 0)]);
+        assert!(SamplingPlan::from_intervals(&intervals).is_err());
+    }
+}
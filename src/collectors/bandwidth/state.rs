@@ -0,0 +1,133 @@
+//! Counter baselines kept across restarts
+//!
+//! Speeds are worked out from the difference between two readings, so a freshly started
+//! collector has nothing to compare its first reading with and reports it with no
+//! confidence. A collector given a state file writes the last counters of each interface
+//! to it every `SAVE_INTERVAL` and when it is dropped, and starts from them after a
+//! restart, so the first reading already has a speed. Baselines from before a reboot or
+//! older than `MAX_AGE` are not used: the counters have started over, or the first speed
+//! would average a long gap in monitoring.
+
+use crate::config::data_dir;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// How often a collector with a state file writes it
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Oldest saved baseline a restarted collector starts from
+pub const MAX_AGE: Duration = Duration::from_secs(300);
+
+/// Boot times read a second or two apart on some systems, from uptime and the clock
+const BOOT_TIME_TOLERANCE_SECS: u64 = 5;
+
+/// Last counters of one interface
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SavedCounters {
+    bytes_received: u64,
+    bytes_sent: u64,
+    timestamp: DateTime<Utc>,
+}
+
+/// The collector's counter baselines as written to the state file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CounterState {
+    saved_at: DateTime<Utc>,
+    /// Boot time of the system, in seconds since the epoch, when the counters were read
+    boot_time: u64,
+    interfaces: BTreeMap<String, SavedCounters>,
+}
+
+impl CounterState {
+    /// State of `previous` readings: (bytes received, bytes sent, time, consecutive failures)
+    pub fn new(previous: &HashMap<String, (u64, u64, DateTime<Utc>, u32)>, boot_time: u64) -> Self {
+        let interfaces = previous
+            .iter()
+            .map(|(name, &(bytes_received, bytes_sent, timestamp, _))| {
+                (name.clone(), SavedCounters { bytes_received, bytes_sent, timestamp })
+            })
+            .collect();
+        Self { saved_at: Utc::now(), boot_time, interfaces }
+    }
+
+    /// Reads the state file; `None` when there is none yet
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let state = serde_json::from_str(&content).with_context(|| format!("Invalid counter state in {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Writes the state next to `path` and renames it into place, so a crash while writing
+    /// leaves the previous state rather than half a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            data_dir::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let temporary = path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary).with_context(|| format!("Failed to create {}", temporary.display()))?;
+        file.write_all(serde_json::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+        data_dir::give_to_sudo_user(path);
+        Ok(())
+    }
+
+    /// Baselines for a collector starting at `now` on a system booted at `boot_time`
+    /// Empty when the state is from an earlier boot or older than `MAX_AGE`
+    pub fn baselines(&self, now: DateTime<Utc>, boot_time: u64) -> HashMap<String, (u64, u64, DateTime<Utc>, u32)> {
+        let same_boot = self.boot_time.abs_diff(boot_time) <= BOOT_TIME_TOLERANCE_SECS;
+        let age = (now - self.saved_at).to_std().unwrap_or(Duration::MAX);
+        if !same_boot || age > MAX_AGE {
+            return HashMap::new();
+        }
+        self.interfaces
+            .iter()
+            .map(|(name, saved)| (name.clone(), (saved.bytes_received, saved.bytes_sent, saved.timestamp, 0)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_state_survives_a_restart_of_the_same_boot_only() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state").join("counters.json");
+        assert_eq!(CounterState::load(&path).unwrap(), None);
+
+        let read_at = Utc::now();
+        let previous = HashMap::from([("eth0".to_string(), (1_000, 2_000, read_at, 3))]);
+        CounterState::new(&previous, 1_700_000_000).save(&path).unwrap();
+        let state = CounterState::load(&path).unwrap().unwrap();
+
+        // Consecutive failures start over
+        let baselines = state.baselines(Utc::now(), 1_700_000_001);
+        assert_eq!(baselines["eth0"], (1_000, 2_000, read_at, 0));
+
+        // Counters of an earlier boot, or from too long ago, are no baseline
+        assert!(state.baselines(Utc::now(), 1_700_086_400).is_empty());
+        let later = Utc::now() + chrono::Duration::from_std(MAX_AGE).unwrap() + chrono::Duration::seconds(1);
+        assert!(state.baselines(later, 1_700_000_000).is_empty());
+    }
+
+    #[test]
+    fn test_a_damaged_state_file_is_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("counters.json");
+        fs::write(&path, "{\"saved_at\":").unwrap();
+        assert!(CounterState::load(&path).is_err());
+    }
+}
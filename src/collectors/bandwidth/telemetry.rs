@@ -0,0 +1,112 @@
+//! The bandwidth collector's own health
+//!
+//! Alongside the structured log events each collection emits, the collector counts how its
+//! collections went: how long they took, how often refreshing the interface counters had to
+//! be retried, which interfaces failed validation and how often it carried on in degraded
+//! mode or failed outright. `kw service run` stores these counts every minute, and
+//! `kw stats self` shows them over time.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// How the collections since the counts were last taken went
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectorTelemetry {
+    /// Collections attempted, failed ones included
+    pub collections: u64,
+    /// Collections that returned no readings at all
+    pub failed_collections: u64,
+    /// Collections that returned readings but lost some interfaces on the way
+    pub degraded_collections: u64,
+    /// Refreshes of the interface counters repeated after a failed attempt
+    pub retries: u64,
+    /// Time spent collecting, all collections together
+    pub total_duration: Duration,
+    /// Longest single collection
+    pub max_duration: Duration,
+    /// Failed readings per interface
+    pub failed_interfaces: BTreeMap<String, u64>,
+}
+
+impl CollectorTelemetry {
+    /// Counts one collection; `succeeded` is false when it returned an error
+    pub fn record(&mut self, duration: Duration, retries: u32, failed_interfaces: &[String], succeeded: bool) {
+        self.collections += 1;
+        self.retries += u64::from(retries);
+        self.total_duration += duration;
+        self.max_duration = self.max_duration.max(duration);
+        if !succeeded {
+            self.failed_collections += 1;
+        } else if !failed_interfaces.is_empty() {
+            self.degraded_collections += 1;
+        }
+        for name in failed_interfaces {
+            *self.failed_interfaces.entry(name.clone()).or_default() += 1;
+        }
+    }
+
+    /// Adds the counts of `other`, taken later
+    pub fn merge(&mut self, other: &CollectorTelemetry) {
+        self.collections += other.collections;
+        self.failed_collections += other.failed_collections;
+        self.degraded_collections += other.degraded_collections;
+        self.retries += other.retries;
+        self.total_duration += other.total_duration;
+        self.max_duration = self.max_duration.max(other.max_duration);
+        for (name, failures) in &other.failed_interfaces {
+            *self.failed_interfaces.entry(name.clone()).or_default() += failures;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collections == 0
+    }
+
+    /// Mean time one collection took; zero before the first
+    pub fn average_duration(&self) -> Duration {
+        match u32::try_from(self.collections) {
+            Ok(0) => Duration::ZERO,
+            Ok(collections) => self.total_duration / collections,
+            Err(_) => Duration::from_secs_f64(self.total_duration.as_secs_f64() / self.collections as f64),
+        }
+    }
+
+    /// Share of collections that failed or ran degraded, 0.0 - 1.0
+    pub fn unhealthy_share(&self) -> f64 {
+        if self.collections == 0 {
+            return 0.0;
+        }
+        (self.failed_collections + self.degraded_collections) as f64 / self.collections as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collections_are_counted_by_outcome() {
+        let mut telemetry = CollectorTelemetry::default();
+        assert_eq!(telemetry.average_duration(), Duration::ZERO);
+
+        telemetry.record(Duration::from_millis(10), 0, &[], true);
+        telemetry.record(Duration::from_millis(30), 2, &["eth1".to_string()], true);
+        telemetry.record(Duration::from_millis(500), 3, &[], false);
+        assert_eq!(telemetry.collections, 3);
+        assert_eq!(telemetry.degraded_collections, 1);
+        assert_eq!(telemetry.failed_collections, 1);
+        assert_eq!(telemetry.retries, 5);
+        assert_eq!(telemetry.max_duration, Duration::from_millis(500));
+        assert_eq!(telemetry.average_duration(), Duration::from_millis(180));
+        assert!((telemetry.unhealthy_share() - 2.0 / 3.0).abs() < 1e-9);
+
+        let mut later = CollectorTelemetry::default();
+        later.record(Duration::from_millis(20), 0, &["eth1".to_string(), "wlan0".to_string()], true);
+        telemetry.merge(&later);
+        assert_eq!(telemetry.collections, 4);
+        assert_eq!(telemetry.degraded_collections, 2);
+        assert_eq!(telemetry.failed_interfaces["eth1"], 2);
+        assert_eq!(telemetry.failed_interfaces["wlan0"], 1);
+        assert_eq!(telemetry.max_duration, Duration::from_millis(500));
+    }
+}
@@ -129,4 +129,19 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_collection_metrics_follow_each_collection() {
+        let mut collector = BandwidthCollector::new();
+        assert!(collector.last_collection_metrics().is_none());
+
+        if let Ok(stats) = collector.collect() {
+            let metrics = collector.last_collection_metrics().unwrap();
+            assert_eq!(metrics.collection, 1);
+            assert_eq!(metrics.interfaces, stats.len());
+        }
+        if collector.collect().is_ok() {
+            assert_eq!(collector.last_collection_metrics().unwrap().collection, 2);
+        }
+    }
 }
@@ -9,11 +9,14 @@
 //!
 //! This module maintains complete backward compatibility:
 //!
-//! ```rust
-//! use crate::collectors::bandwidth_collector::{BandwidthCollector, BandwidthStats};
+//! ```no_run
+//! use kaipo_watcher::collectors::bandwidth_collector::{BandwidthCollector, BandwidthStats};
 //!
+//! # fn main() -> anyhow::Result<()> {
 //! let mut collector = BandwidthCollector::new();
-//! let stats = collector.collect()?;
+//! let stats: Vec<BandwidthStats> = collector.collect()?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ## Migration
@@ -22,7 +25,7 @@
 //! New code can optionally use the new modular structure:
 //!
 //! ```rust
-//! use crate::collectors::bandwidth::{BandwidthCollector, BandwidthStats};
+//! use kaipo_watcher::collectors::bandwidth::{BandwidthCollector, BandwidthStats};
 //! ```
 
 // Re-export all public types from the new bandwidth module
@@ -0,0 +1,1086 @@
+// Capture filter expressions in the style of tcpdump / BPF
+// ("tcp port 443 and host 10.0.0.5", "udp dst port 53 or icmp", "not net 10.0.0.0/8")
+// The expression is compiled once into a predicate tree, and from that into a classic BPF
+// program that the capture socket runs in the kernel, so rejected packets are never copied
+// to kw. The tree itself is only evaluated for packets no kernel program has seen: capture
+// paths that cannot take one, and programs the kernel refuses
+
+use anyhow::{anyhow, bail, Result};
+use pnet::ipnetwork::IpNetwork;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::models::{NetworkPacket, PacketProtocol, TransportProtocol};
+
+/// A compiled capture filter
+#[derive(Debug, Clone)]
+pub struct CaptureFilter {
+    expression: String,
+    root: Node,
+}
+
+impl CaptureFilter {
+    /// Compiles a tcpdump-style expression
+    ///
+    /// Supported primitives, each optionally prefixed with `src`/`dst` and a protocol:
+    /// `host ADDR`, `net CIDR` (or `net ADDR mask MASK`), `port N`, `portrange N-M`,
+    /// `tcp`, `udp`, `icmp`, `icmp6`, `ip`, `ip6`, `arp`, `less N`, `greater N`,
+    /// combined with `and`/`&&`, `or`/`||`, `not`/`!` and parentheses.
+    /// As in tcpdump, a bare value after `and`/`or` reuses the previous qualifiers
+    /// (`port 80 or 443`).
+    pub fn parse(expression: &str) -> Result<Self> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            bail!("Empty capture filter");
+        }
+
+        let mut parser = Parser { tokens, pos: 0, last: None };
+        let root = parser.parse_expr()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected '{token}' in capture filter");
+        }
+
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn matches(&self, packet: &NetworkPacket) -> bool {
+        self.root.matches(packet)
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Compiles the filter to a classic BPF program over frames captured as `link`, accepting
+    /// exactly the packets `matches` accepts once decoded; none when a jump would be too long
+    /// for BPF's 8-bit offsets, which only very long expressions reach
+    ///
+    /// As with tcpdump's programs, a frame too short for a field the program reads is rejected.
+    pub fn to_bpf(&self, link: BpfLink) -> Option<Vec<BpfInstruction>> {
+        let mut compiler = Compiler { link, code: Vec::new(), labels: vec![None, None] };
+        compiler.compile(&self.root.to_test())
+    }
+}
+
+impl fmt::Display for CaptureFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    Src,
+    Dst,
+    Either,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Proto {
+    Ip,
+    Ip6,
+    Arp,
+    Tcp,
+    Udp,
+    Icmp,
+    Icmp6,
+}
+
+impl Proto {
+    fn from_keyword(word: &str) -> Option<Self> {
+        match word {
+            "ip" => Some(Self::Ip),
+            "ip6" => Some(Self::Ip6),
+            "arp" => Some(Self::Arp),
+            "tcp" => Some(Self::Tcp),
+            "udp" => Some(Self::Udp),
+            "icmp" => Some(Self::Icmp),
+            "icmp6" => Some(Self::Icmp6),
+            _ => None,
+        }
+    }
+
+    fn matches(self, packet: &NetworkPacket) -> bool {
+        match self {
+            Self::Ip => packet.protocol == PacketProtocol::IPv4,
+            Self::Ip6 => packet.protocol == PacketProtocol::IPv6,
+            Self::Arp => packet.protocol == PacketProtocol::Arp,
+            Self::Tcp => packet.transport_protocol == TransportProtocol::Tcp,
+            Self::Udp => packet.transport_protocol == TransportProtocol::Udp,
+            Self::Icmp => packet.transport_protocol == TransportProtocol::Icmp,
+            Self::Icmp6 => packet.transport_protocol == TransportProtocol::ICMPv6,
+        }
+    }
+}
+
+/// What a primitive compares against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Host,
+    Net,
+    Port,
+    PortRange,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Proto(Proto),
+    Host(Dir, IpAddr),
+    Net(Dir, IpNetwork),
+    Ports(Dir, u16, u16),
+    Less(u64),
+    Greater(u64),
+}
+
+impl Node {
+    fn matches(&self, packet: &NetworkPacket) -> bool {
+        match self {
+            Node::And(a, b) => a.matches(packet) && b.matches(packet),
+            Node::Or(a, b) => a.matches(packet) || b.matches(packet),
+            Node::Not(a) => !a.matches(packet),
+            Node::Proto(proto) => proto.matches(packet),
+            Node::Host(dir, addr) => either(*dir, packet.source_addr, packet.dest_addr, |a| a == *addr),
+            Node::Net(dir, net) => either(*dir, packet.source_addr, packet.dest_addr, |a| net.contains(a)),
+            Node::Ports(dir, low, high) => {
+                either(*dir, packet.source_port, packet.dest_port, |p| (*low..=*high).contains(&p))
+            }
+            Node::Less(len) => packet.size_bytes <= *len,
+            Node::Greater(len) => packet.size_bytes >= *len,
+        }
+    }
+}
+
+fn either<T: Copy>(dir: Dir, src: Option<T>, dst: Option<T>, test: impl Fn(T) -> bool) -> bool {
+    let check = |value: Option<T>| value.is_some_and(&test);
+    match dir {
+        Dir::Src => check(src),
+        Dir::Dst => check(dst),
+        Dir::Either => check(src) || check(dst),
+    }
+}
+
+/// A classic BPF instruction, laid out as Linux's `struct sock_filter` and the BSDs' `struct bpf_insn`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BpfInstruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// What comes before the Ethernet header in the frames a BPF program runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpfLink {
+    /// Nothing: AF_PACKET sockets and BPF devices on Ethernet interfaces
+    Ethernet,
+    /// A macOS pktap header, whose length is its first field, little-endian
+    Pktap,
+}
+
+/// Classic BPF opcodes, from <linux/bpf_common.h> and <net/bpf.h>
+mod op {
+    pub const LD: u16 = 0x00;
+    pub const LDX: u16 = 0x01;
+    pub const ST: u16 = 0x02;
+    pub const ALU: u16 = 0x04;
+    pub const JMP: u16 = 0x05;
+    pub const RET: u16 = 0x06;
+    pub const MISC: u16 = 0x07;
+
+    pub const W: u16 = 0x00;
+    pub const H: u16 = 0x08;
+    pub const B: u16 = 0x10;
+
+    pub const ABS: u16 = 0x20;
+    pub const IND: u16 = 0x40;
+    pub const MEM: u16 = 0x60;
+    pub const LEN: u16 = 0x80;
+    pub const MSH: u16 = 0xa0;
+
+    pub const ADD: u16 = 0x00;
+    pub const SUB: u16 = 0x10;
+    pub const OR: u16 = 0x40;
+    pub const AND: u16 = 0x50;
+    pub const LSH: u16 = 0x60;
+
+    pub const JEQ: u16 = 0x10;
+    pub const JGT: u16 = 0x20;
+    pub const JGE: u16 = 0x30;
+
+    pub const K: u16 = 0x00;
+    pub const X: u16 = 0x08;
+
+    pub const TAX: u16 = 0x00;
+}
+
+/// Return value keeping the whole frame
+const ACCEPT_FRAME: u32 = u32::MAX;
+
+/// Ethernet header length, and the offsets into it and the IP headers the tests read
+const ETHERNET_LEN: u32 = 14;
+const ETHERTYPE: u32 = 12;
+const IPV4_FRAGMENT: u32 = ETHERNET_LEN + 6;
+const IPV4_PROTOCOL: u32 = ETHERNET_LEN + 9;
+const IPV4_SOURCE: u32 = ETHERNET_LEN + 12;
+const IPV4_DESTINATION: u32 = ETHERNET_LEN + 16;
+const IPV6_NEXT_HEADER: u32 = ETHERNET_LEN + 6;
+const IPV6_SOURCE: u32 = ETHERNET_LEN + 8;
+const IPV6_DESTINATION: u32 = ETHERNET_LEN + 24;
+/// The IPv6 payload, where a fragment header sits when there is one
+const IPV6_PAYLOAD: u32 = ETHERNET_LEN + 40;
+const IPV6_FRAGMENT_LEN: u32 = 8;
+
+/// IP protocol numbers the tests compare against
+const TCP: u32 = 6;
+const UDP: u32 = 17;
+const ICMP: u32 = 1;
+const ICMPV6: u32 = 58;
+const IPV6_FRAGMENT_HEADER: u32 = 44;
+
+/// Where a load's offset counts from
+#[derive(Debug, Clone, Copy)]
+enum Base {
+    Ethernet,
+    /// The end of the IPv4 header, whose length the frame gives
+    Ipv4Payload,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Load {
+    Byte(Base, u32),
+    Half(Base, u32),
+    Word(Base, u32),
+    /// Length of the frame from its Ethernet header on
+    Length,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Jump {
+    Eq,
+    Gt,
+    Ge,
+}
+
+/// A predicate over raw frames, the form `Node` takes before code generation
+#[derive(Debug, Clone)]
+enum Test {
+    And(Box<Test>, Box<Test>),
+    Or(Box<Test>, Box<Test>),
+    Not(Box<Test>),
+    Compare { load: Load, mask: Option<u32>, jump: Jump, value: u32 },
+    /// The loaded value equals one of `values`
+    OneOf { load: Load, values: Vec<u32> },
+}
+
+impl Test {
+    fn and(self, other: Test) -> Test {
+        Test::And(Box::new(self), Box::new(other))
+    }
+
+    fn or(self, other: Test) -> Test {
+        Test::Or(Box::new(self), Box::new(other))
+    }
+
+    fn not(self) -> Test {
+        Test::Not(Box::new(self))
+    }
+}
+
+fn compare(load: Load, jump: Jump, value: u32) -> Test {
+    Test::Compare { load, mask: None, jump, value }
+}
+
+fn masked(load: Load, mask: u32, value: u32) -> Test {
+    Test::Compare { load, mask: Some(mask), jump: Jump::Eq, value: value & mask }
+}
+
+fn byte_is(offset: u32, value: u32) -> Test {
+    compare(Load::Byte(Base::Ethernet, offset), Jump::Eq, value)
+}
+
+/// The decoder's `PacketProtocol::IPv4`: the ethertype, and a whole header behind it
+fn ipv4() -> Test {
+    compare(Load::Half(Base::Ethernet, ETHERTYPE), Jump::Eq, 0x0800).and(compare(Load::Length, Jump::Ge, ETHERNET_LEN + 20))
+}
+
+fn ipv6() -> Test {
+    compare(Load::Half(Base::Ethernet, ETHERTYPE), Jump::Eq, 0x86dd).and(compare(Load::Length, Jump::Ge, IPV6_PAYLOAD))
+}
+
+fn ipv4_protocol(protocol: u32) -> Test {
+    ipv4().and(byte_is(IPV4_PROTOCOL, protocol))
+}
+
+/// The decoder looks past a fragment header for the transport protocol, but no further
+fn ipv6_fragment() -> Test {
+    byte_is(IPV6_NEXT_HEADER, IPV6_FRAGMENT_HEADER).and(compare(Load::Length, Jump::Ge, IPV6_PAYLOAD + IPV6_FRAGMENT_LEN))
+}
+
+fn ipv6_next_header(protocol: u32) -> Test {
+    ipv6().and(byte_is(IPV6_NEXT_HEADER, protocol).or(ipv6_fragment().and(byte_is(IPV6_PAYLOAD, protocol))))
+}
+
+fn directed(dir: Dir, source: u32, destination: u32, test: impl Fn(u32) -> Test) -> Test {
+    match dir {
+        Dir::Src => test(source),
+        Dir::Dst => test(destination),
+        Dir::Either => test(source).or(test(destination)),
+    }
+}
+
+/// The first `prefix` bits of the address at `offset` equal those of `address`
+fn address_prefix(offset: u32, address: IpAddr, prefix: u8) -> Test {
+    let words: Vec<u32> = match address {
+        IpAddr::V4(address) => vec![address.into()],
+        IpAddr::V6(address) => address.octets().chunks(4).map(|word| u32::from_be_bytes(word.try_into().unwrap())).collect(),
+    };
+    // At least one word, so a zero prefix still compares something (with an empty mask)
+    let count = u32::from(prefix).div_ceil(32).max(1);
+    (0..count)
+        .map(|i| {
+            let bits = u32::from(prefix).saturating_sub(32 * i).min(32);
+            let mask = if bits == 0 { 0 } else { u32::MAX << (32 - bits) };
+            masked(Load::Word(Base::Ethernet, offset + 4 * i), mask, words[i as usize])
+        })
+        .reduce(Test::and)
+        .unwrap()
+}
+
+fn port_range(load: Load, low: u16, high: u16) -> Test {
+    if low == high {
+        compare(load, Jump::Eq, low.into())
+    } else {
+        compare(load, Jump::Ge, low.into()).and(compare(load, Jump::Gt, high.into()).not())
+    }
+}
+
+fn tcp_or_udp(offset: u32) -> Test {
+    Test::OneOf { load: Load::Byte(Base::Ethernet, offset), values: vec![TCP, UDP] }
+}
+
+/// Ports exist only for TCP and UDP, and in the first fragment
+fn ports(dir: Dir, low: u16, high: u16) -> Test {
+    let in_ipv4 = ipv4()
+        .and(tcp_or_udp(IPV4_PROTOCOL))
+        .and(masked(Load::Half(Base::Ethernet, IPV4_FRAGMENT), 0x1fff, 0))
+        .and(directed(dir, 0, 2, |at| port_range(Load::Half(Base::Ipv4Payload, at), low, high)));
+    let ipv6_ports = |transport: u32| directed(dir, transport, transport + 2, |at| port_range(Load::Half(Base::Ethernet, at), low, high));
+    let unfragmented = tcp_or_udp(IPV6_NEXT_HEADER).and(ipv6_ports(IPV6_PAYLOAD));
+    let first_fragment = ipv6_fragment()
+        .and(tcp_or_udp(IPV6_PAYLOAD))
+        .and(masked(Load::Half(Base::Ethernet, IPV6_PAYLOAD + 2), 0xfff8, 0))
+        .and(ipv6_ports(IPV6_PAYLOAD + IPV6_FRAGMENT_LEN));
+    in_ipv4.or(ipv6().and(unfragmented.or(first_fragment)))
+}
+
+impl Proto {
+    fn to_test(self) -> Test {
+        match self {
+            Self::Ip => ipv4(),
+            Self::Ip6 => ipv6(),
+            Self::Arp => compare(Load::Half(Base::Ethernet, ETHERTYPE), Jump::Eq, 0x0806),
+            Self::Tcp => ipv4_protocol(TCP).or(ipv6_next_header(TCP)),
+            Self::Udp => ipv4_protocol(UDP).or(ipv6_next_header(UDP)),
+            Self::Icmp => ipv4_protocol(ICMP),
+            Self::Icmp6 => ipv6_next_header(ICMPV6),
+        }
+    }
+}
+
+impl Node {
+    fn to_test(&self) -> Test {
+        let clamp = |len: u64| u32::try_from(len).unwrap_or(u32::MAX);
+        match self {
+            Node::And(a, b) => a.to_test().and(b.to_test()),
+            Node::Or(a, b) => a.to_test().or(b.to_test()),
+            Node::Not(a) => a.to_test().not(),
+            Node::Proto(proto) => proto.to_test(),
+            Node::Host(dir, addr) => Node::Net(*dir, IpNetwork::from(*addr)).to_test(),
+            Node::Net(dir, IpNetwork::V4(net)) => ipv4().and(directed(*dir, IPV4_SOURCE, IPV4_DESTINATION, |at| {
+                address_prefix(at, IpAddr::V4(net.network()), net.prefix())
+            })),
+            Node::Net(dir, IpNetwork::V6(net)) => ipv6().and(directed(*dir, IPV6_SOURCE, IPV6_DESTINATION, |at| {
+                address_prefix(at, IpAddr::V6(net.network()), net.prefix())
+            })),
+            Node::Ports(dir, low, high) => ports(*dir, *low, *high),
+            Node::Less(len) => compare(Load::Length, Jump::Gt, clamp(*len)).not(),
+            Node::Greater(len) => compare(Load::Length, Jump::Ge, clamp(*len)),
+        }
+    }
+}
+
+/// Instruction with its jumps still naming labels
+struct Pending {
+    code: u16,
+    jt: usize,
+    jf: usize,
+    k: u32,
+}
+
+/// Turns a `Test` into straight-line code whose jumps all go forward, to labels placed as it goes
+struct Compiler {
+    link: BpfLink,
+    code: Vec<Pending>,
+    /// Instruction each label stands for, once placed
+    labels: Vec<Option<usize>>,
+}
+
+const ACCEPT: usize = 0;
+const REJECT: usize = 1;
+
+/// Scratch memory slot holding the pktap header's length
+const PKTAP_HEADER_LEN: u32 = 0;
+
+impl Compiler {
+    fn compile(&mut self, test: &Test) -> Option<Vec<BpfInstruction>> {
+        if self.link == BpfLink::Pktap {
+            self.pktap_header_len();
+        }
+        self.test(test, ACCEPT, REJECT);
+        self.place(ACCEPT);
+        self.emit(op::RET | op::K, ACCEPT_FRAME);
+        self.place(REJECT);
+        self.emit(op::RET | op::K, 0);
+
+        let offset = |from: usize, label: usize| -> Option<u8> {
+            let target = self.labels[label]?;
+            u8::try_from(target.checked_sub(from + 1)?).ok()
+        };
+        self.code
+            .iter()
+            .enumerate()
+            .map(|(at, pending)| {
+                let is_jump = pending.code & 0x07 == op::JMP;
+                Some(BpfInstruction {
+                    code: pending.code,
+                    jt: if is_jump { offset(at, pending.jt)? } else { 0 },
+                    jf: if is_jump { offset(at, pending.jf)? } else { 0 },
+                    k: pending.k,
+                })
+            })
+            .collect()
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn emit(&mut self, code: u16, k: u32) {
+        self.code.push(Pending { code, jt: 0, jf: 0, k });
+    }
+
+    /// Jumps to `yes` when `test` holds and to `no` otherwise
+    fn test(&mut self, test: &Test, yes: usize, no: usize) {
+        match test {
+            Test::And(a, b) => {
+                let next = self.label();
+                self.test(a, next, no);
+                self.place(next);
+                self.test(b, yes, no);
+            }
+            Test::Or(a, b) => {
+                let next = self.label();
+                self.test(a, yes, next);
+                self.place(next);
+                self.test(b, yes, no);
+            }
+            Test::Not(a) => self.test(a, no, yes),
+            Test::Compare { load, mask, jump, value } => {
+                self.load(*load);
+                if let Some(mask) = mask {
+                    self.emit(op::ALU | op::AND | op::K, *mask);
+                }
+                let jump = match jump {
+                    Jump::Eq => op::JEQ,
+                    Jump::Gt => op::JGT,
+                    Jump::Ge => op::JGE,
+                };
+                self.code.push(Pending { code: op::JMP | jump | op::K, jt: yes, jf: no, k: *value });
+            }
+            Test::OneOf { load, values } => {
+                self.load(*load);
+                for (i, value) in values.iter().enumerate() {
+                    let next = if i + 1 == values.len() { no } else { self.label() };
+                    self.code.push(Pending { code: op::JMP | op::JEQ | op::K, jt: yes, jf: next, k: *value });
+                    if next != no {
+                        self.place(next);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Loads into the accumulator, using the index register for offsets the frame decides
+    fn load(&mut self, load: Load) {
+        let (size, base, offset) = match load {
+            Load::Byte(base, offset) => (op::B, base, offset),
+            Load::Half(base, offset) => (op::H, base, offset),
+            Load::Word(base, offset) => (op::W, base, offset),
+            Load::Length => {
+                // BPF's length counts the pktap header, which `NetworkPacket::size_bytes` does not
+                if self.link == BpfLink::Pktap {
+                    self.emit(op::LDX | op::W | op::MEM, PKTAP_HEADER_LEN);
+                    self.emit(op::LD | op::W | op::LEN, 0);
+                    self.emit(op::ALU | op::SUB | op::X, 0);
+                } else {
+                    self.emit(op::LD | op::W | op::LEN, 0);
+                }
+                return;
+            }
+        };
+        match (self.link, base) {
+            (BpfLink::Ethernet, Base::Ethernet) => self.emit(op::LD | size | op::ABS, offset),
+            (BpfLink::Ethernet, Base::Ipv4Payload) => {
+                self.emit(op::LDX | op::B | op::MSH, ETHERNET_LEN);
+                self.emit(op::LD | size | op::IND, ETHERNET_LEN + offset);
+            }
+            (BpfLink::Pktap, Base::Ethernet) => {
+                self.emit(op::LDX | op::W | op::MEM, PKTAP_HEADER_LEN);
+                self.emit(op::LD | size | op::IND, offset);
+            }
+            (BpfLink::Pktap, Base::Ipv4Payload) => {
+                self.emit(op::LDX | op::W | op::MEM, PKTAP_HEADER_LEN);
+                self.emit(op::LD | op::B | op::IND, ETHERNET_LEN);
+                self.emit(op::ALU | op::AND | op::K, 0x0f);
+                self.emit(op::ALU | op::LSH | op::K, 2);
+                self.emit(op::ALU | op::ADD | op::X, 0);
+                self.emit(op::MISC | op::TAX, 0);
+                self.emit(op::LD | size | op::IND, ETHERNET_LEN + offset);
+            }
+        }
+    }
+
+    /// Keeps the pktap header's length in scratch memory for the loads to index from;
+    /// headers are far shorter than 64 KiB, so its two low bytes are enough
+    fn pktap_header_len(&mut self) {
+        self.emit(op::LD | op::B | op::ABS, 1);
+        self.emit(op::ALU | op::LSH | op::K, 8);
+        self.emit(op::MISC | op::TAX, 0);
+        self.emit(op::LD | op::B | op::ABS, 0);
+        self.emit(op::ALU | op::OR | op::X, 0);
+        self.emit(op::ST, PKTAP_HEADER_LEN);
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                tokens.push(c.to_string());
+                chars.next();
+            }
+            '!' => {
+                tokens.push("not".to_string());
+                chars.next();
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    bail!("Use '{c}{c}' (or '{}') in capture filters", if c == '&' { "and" } else { "or" });
+                }
+                tokens.push(if c == '&' { "and" } else { "or" }.to_string());
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '!' | '&' | '|') {
+                        break;
+                    }
+                    word.push(c.to_ascii_lowercase());
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Qualifiers of the last primitive, for tcpdump's `port 80 or 443` shorthand
+#[derive(Debug, Clone, Copy)]
+struct Qualifiers {
+    dir: Dir,
+    proto: Option<Proto>,
+    kind: Kind,
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+    last: Option<Qualifiers>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect_value(&mut self, after: &str) -> Result<String> {
+        match self.next() {
+            Some(token) if !is_keyword(&token) => Ok(token),
+            Some(token) => bail!("Expected a value after '{after}', found '{token}'"),
+            None => bail!("Expected a value after '{after}'"),
+        }
+    }
+
+    /// `and` and `or` share one precedence level and associate left to right, as in tcpdump
+    fn parse_expr(&mut self) -> Result<Node> {
+        let mut node = self.parse_not()?;
+        loop {
+            match self.peek() {
+                Some("and") => {
+                    self.pos += 1;
+                    node = Node::And(Box::new(node), Box::new(self.parse_not()?));
+                }
+                Some("or") => {
+                    self.pos += 1;
+                    node = Node::Or(Box::new(node), Box::new(self.parse_not()?));
+                }
+                _ => return Ok(node),
+            }
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Node> {
+        if self.peek() == Some("not") {
+            self.pos += 1;
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node> {
+        match self.peek() {
+            Some("(") => {
+                self.pos += 1;
+                let node = self.parse_expr()?;
+                if self.next().as_deref() != Some(")") {
+                    bail!("Missing ')' in capture filter");
+                }
+                Ok(node)
+            }
+            Some(_) => self.parse_primitive(),
+            None => bail!("Capture filter ends unexpectedly"),
+        }
+    }
+
+    fn parse_primitive(&mut self) -> Result<Node> {
+        let first = self.peek().unwrap_or_default().to_string();
+
+        // Bare value: repeat the previous primitive's qualifiers
+        if !is_keyword(&first) {
+            let last = self
+                .last
+                .ok_or_else(|| anyhow!("Unknown capture filter keyword '{first}'"))?;
+            self.pos += 1;
+            return self.build(last, &first);
+        }
+
+        match first.as_str() {
+            "less" | "greater" => {
+                self.pos += 1;
+                let value = self.expect_value(&first)?;
+                let len = value.parse().map_err(|_| anyhow!("Invalid length '{value}'"))?;
+                return Ok(if first == "less" { Node::Less(len) } else { Node::Greater(len) });
+            }
+            _ => {}
+        }
+
+        let mut proto = None;
+        if let Some(p) = Proto::from_keyword(&first) {
+            proto = Some(p);
+            self.pos += 1;
+        }
+
+        let dir = match self.peek() {
+            Some("src") => Dir::Src,
+            Some("dst") => Dir::Dst,
+            _ => Dir::Either,
+        };
+        if dir != Dir::Either {
+            self.pos += 1;
+        }
+
+        let kind = match self.peek() {
+            Some("host") => Some(Kind::Host),
+            Some("net") => Some(Kind::Net),
+            Some("port") => Some(Kind::Port),
+            Some("portrange") => Some(Kind::PortRange),
+            _ => None,
+        };
+
+        let kind = match kind {
+            Some(kind) => {
+                self.pos += 1;
+                kind
+            }
+            // `src 10.0.0.1` means `src host 10.0.0.1`
+            None if dir != Dir::Either => Kind::Host,
+            // Lone protocol, e.g. `tcp`
+            None => {
+                return proto
+                    .map(Node::Proto)
+                    .ok_or_else(|| anyhow!("Unknown capture filter keyword '{first}'"));
+            }
+        };
+
+        let qualifiers = Qualifiers { dir, proto, kind };
+        let label = self.tokens[self.pos.saturating_sub(1)].clone();
+        let value = self.expect_value(&label)?;
+        self.build(qualifiers, &value)
+    }
+
+    fn build(&mut self, qualifiers: Qualifiers, value: &str) -> Result<Node> {
+        let Qualifiers { dir, proto, kind } = qualifiers;
+        let node = match kind {
+            Kind::Host => Node::Host(dir, value.parse().map_err(|_| anyhow!("Invalid host address '{value}'"))?),
+            Kind::Net => Node::Net(dir, self.parse_net(value)?),
+            Kind::Port => {
+                let port = parse_port(value)?;
+                Node::Ports(dir, port, port)
+            }
+            Kind::PortRange => {
+                let (low, high) = value
+                    .split_once('-')
+                    .ok_or_else(|| anyhow!("Port range must look like 'low-high', got '{value}'"))?;
+                let (low, high) = (parse_port(low)?, parse_port(high)?);
+                if low > high {
+                    bail!("Invalid port range '{value}'");
+                }
+                Node::Ports(dir, low, high)
+            }
+        };
+
+        self.last = Some(qualifiers);
+        Ok(match proto {
+            Some(proto) => Node::And(Box::new(Node::Proto(proto)), Box::new(node)),
+            None => node,
+        })
+    }
+
+    fn parse_net(&mut self, value: &str) -> Result<IpNetwork> {
+        if self.peek() == Some("mask") {
+            self.pos += 1;
+            let mask = self.expect_value("mask")?;
+            let addr: IpAddr = value.parse().map_err(|_| anyhow!("Invalid network '{value}'"))?;
+            let mask: IpAddr = mask.parse().map_err(|_| anyhow!("Invalid netmask '{mask}'"))?;
+            return IpNetwork::with_netmask(addr, mask).map_err(|e| anyhow!("Invalid network '{value}': {e}"));
+        }
+        if value.contains('/') {
+            return value.parse().map_err(|e| anyhow!("Invalid network '{value}': {e}"));
+        }
+        let addr: IpAddr = value.parse().map_err(|_| anyhow!("Invalid network '{value}'"))?;
+        IpNetwork::new(addr, if addr.is_ipv4() { 32 } else { 128 }).map_err(|e| anyhow!("{e}"))
+    }
+}
+
+fn is_keyword(token: &str) -> bool {
+    matches!(
+        token,
+        "and" | "or" | "not" | "(" | ")" | "src" | "dst" | "host" | "net" | "mask" | "port" | "portrange"
+            | "less" | "greater"
+    ) || Proto::from_keyword(token).is_some()
+}
+
+fn parse_port(value: &str) -> Result<u16> {
+    if let Ok(port) = value.parse() {
+        return Ok(port);
+    }
+    // Service names, as in /etc/services
+    let port = match value {
+        "ftp-data" => 20,
+        "ftp" => 21,
+        "ssh" => 22,
+        "telnet" => 23,
+        "smtp" => 25,
+        "domain" | "dns" => 53,
+        "http" | "www" => 80,
+        "pop3" => 110,
+        "ntp" => 123,
+        "imap" => 143,
+        "snmp" => 161,
+        "ldap" => 389,
+        "https" => 443,
+        "imaps" => 993,
+        "pop3s" => 995,
+        "mysql" => 3306,
+        "rdp" => 3389,
+        "postgresql" => 5432,
+        _ => bail!("Invalid port '{value}'"),
+    };
+    Ok(port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PacketDirection;
+
+    fn packet(transport: TransportProtocol, src: &str, sport: u16, dst: &str, dport: u16) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 100, PacketProtocol::IPv4, PacketDirection::Outbound);
+        packet.transport_protocol = transport;
+        packet.source_addr = Some(src.parse().unwrap());
+        packet.dest_addr = Some(dst.parse().unwrap());
+        packet.source_port = Some(sport);
+        packet.dest_port = Some(dport);
+        packet
+    }
+
+    #[test]
+    fn test_protocol_port_and_host() {
+        let filter = CaptureFilter::parse("tcp port 443 and host 10.0.0.5").unwrap();
+        assert!(filter.matches(&packet(TransportProtocol::Tcp, "10.0.0.5", 51000, "1.1.1.1", 443)));
+        assert!(filter.matches(&packet(TransportProtocol::Tcp, "1.1.1.1", 443, "10.0.0.5", 51000)));
+        assert!(!filter.matches(&packet(TransportProtocol::Udp, "10.0.0.5", 51000, "1.1.1.1", 443)));
+        assert!(!filter.matches(&packet(TransportProtocol::Tcp, "10.0.0.6", 51000, "1.1.1.1", 443)));
+    }
+
+    #[test]
+    fn test_direction_and_precedence() {
+        // Left to right: (udp dst port 53 or icmp) and not src net 10.0.0.0/8
+        let filter = CaptureFilter::parse("udp dst port domain or icmp && !src net 10.0.0.0/8").unwrap();
+        assert!(filter.matches(&packet(TransportProtocol::Udp, "192.168.1.1", 5000, "8.8.8.8", 53)));
+        assert!(!filter.matches(&packet(TransportProtocol::Udp, "10.1.1.1", 5000, "8.8.8.8", 53)));
+        assert!(!filter.matches(&packet(TransportProtocol::Udp, "8.8.8.8", 53, "192.168.1.1", 5000)));
+        assert!(filter.matches(&packet(TransportProtocol::Icmp, "192.168.1.1", 0, "8.8.8.8", 0)));
+        assert!(!filter.matches(&packet(TransportProtocol::Icmp, "10.1.1.1", 0, "8.8.8.8", 0)));
+
+        let grouped = CaptureFilter::parse("not (tcp or udp)").unwrap();
+        assert!(!grouped.matches(&packet(TransportProtocol::Tcp, "10.0.0.1", 1, "10.0.0.2", 2)));
+        assert!(grouped.matches(&packet(TransportProtocol::Icmp, "10.0.0.1", 0, "10.0.0.2", 0)));
+    }
+
+    #[test]
+    fn test_repeated_qualifiers_ranges_and_masks() {
+        let filter = CaptureFilter::parse("tcp dst port 80 or 8080").unwrap();
+        assert!(filter.matches(&packet(TransportProtocol::Tcp, "10.0.0.1", 40000, "10.0.0.2", 8080)));
+        assert!(!filter.matches(&packet(TransportProtocol::Udp, "10.0.0.1", 40000, "10.0.0.2", 8080)));
+
+        let range = CaptureFilter::parse("portrange 6000-6010").unwrap();
+        assert!(range.matches(&packet(TransportProtocol::Tcp, "10.0.0.1", 6005, "10.0.0.2", 80)));
+        assert!(!range.matches(&packet(TransportProtocol::Tcp, "10.0.0.1", 6011, "10.0.0.2", 80)));
+
+        let masked = CaptureFilter::parse("src net 192.168.0.0 mask 255.255.0.0").unwrap();
+        assert!(masked.matches(&packet(TransportProtocol::Tcp, "192.168.4.4", 1, "10.0.0.2", 2)));
+
+        let v6 = CaptureFilter::parse("host fe80::1 and greater 64").unwrap();
+        assert!(v6.matches(&packet(TransportProtocol::Tcp, "fe80::1", 1, "fe80::2", 2)));
+    }
+
+    /// Runs a classic BPF program the way the kernel does; out-of-bounds loads reject
+    fn run(program: &[BpfInstruction], frame: &[u8]) -> bool {
+        let load = |at: u32, size: usize| {
+            let bytes = frame.get(at as usize..at as usize + size)?;
+            Some(bytes.iter().fold(0u32, |value, byte| value << 8 | u32::from(*byte)))
+        };
+        let (mut a, mut x, mut pc, mut memory) = (0u32, 0u32, 0usize, [0u32; 16]);
+        loop {
+            let instruction = program[pc];
+            pc += 1;
+            let size = match instruction.code & 0x18 {
+                op::W => 4,
+                op::H => 2,
+                _ => 1,
+            };
+            let operand = if instruction.code & op::X != 0 { x } else { instruction.k };
+            match instruction.code & 0x07 {
+                op::LD => {
+                    let value = match instruction.code & 0xe0 {
+                        op::ABS => load(instruction.k, size),
+                        op::IND => load(x.wrapping_add(instruction.k), size),
+                        op::LEN => Some(frame.len() as u32),
+                        code => panic!("unexpected load mode {code:#x}"),
+                    };
+                    let Some(value) = value else { return false };
+                    a = value;
+                }
+                op::LDX if instruction.code & 0xe0 == op::MEM => x = memory[instruction.k as usize],
+                op::LDX => {
+                    let Some(value) = load(instruction.k, 1) else { return false };
+                    x = 4 * (value & 0x0f);
+                }
+                op::ST => memory[instruction.k as usize] = a,
+                op::ALU => {
+                    a = match instruction.code & 0xf0 {
+                        op::ADD => a.wrapping_add(operand),
+                        op::SUB => a.wrapping_sub(operand),
+                        op::OR => a | operand,
+                        op::AND => a & operand,
+                        op::LSH => a << operand,
+                        code => panic!("unexpected ALU operation {code:#x}"),
+                    };
+                }
+                op::JMP => {
+                    let taken = match instruction.code & 0xf0 {
+                        op::JEQ => a == operand,
+                        op::JGT => a > operand,
+                        op::JGE => a >= operand,
+                        code => panic!("unexpected jump {code:#x}"),
+                    };
+                    pc += usize::from(if taken { instruction.jt } else { instruction.jf });
+                }
+                op::RET => return instruction.k != 0,
+                _ => x = a,
+            }
+        }
+    }
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x02; 12];
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn transport(protocol: u8, source: u16, destination: u16) -> Vec<u8> {
+        let mut header = vec![0; if protocol == 6 { 20 } else { 8 }];
+        header[..2].copy_from_slice(&source.to_be_bytes());
+        header[2..4].copy_from_slice(&destination.to_be_bytes());
+        if protocol == 6 {
+            header[12] = 5 << 4;
+        }
+        header
+    }
+
+    /// An IPv4 packet with `options` words of options and the given fragment offset, in 8-byte units
+    fn ipv4(protocol: u8, source: &str, destination: &str, options: usize, fragment: u16, payload: &[u8]) -> Vec<u8> {
+        let header_len = 20 + 4 * options;
+        let mut packet = vec![0; header_len];
+        packet[0] = 0x40 | (header_len / 4) as u8;
+        packet[2..4].copy_from_slice(&((header_len + payload.len()) as u16).to_be_bytes());
+        packet[6..8].copy_from_slice(&fragment.to_be_bytes());
+        packet[8] = 64;
+        packet[9] = protocol;
+        packet[12..16].copy_from_slice(&source.parse::<std::net::Ipv4Addr>().unwrap().octets());
+        packet[16..20].copy_from_slice(&destination.parse::<std::net::Ipv4Addr>().unwrap().octets());
+        packet.extend_from_slice(payload);
+        ethernet(0x0800, &packet)
+    }
+
+    fn ipv6(next_header: u8, source: &str, destination: &str, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 40];
+        packet[0] = 0x60;
+        packet[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet[6] = next_header;
+        packet[7] = 64;
+        packet[8..24].copy_from_slice(&source.parse::<std::net::Ipv6Addr>().unwrap().octets());
+        packet[24..40].copy_from_slice(&destination.parse::<std::net::Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(payload);
+        ethernet(0x86dd, &packet)
+    }
+
+    fn ipv6_fragment(next_header: u8, offset: u16, payload: &[u8]) -> Vec<u8> {
+        let mut header = vec![next_header, 0, 0, 0, 0, 0, 0, 1];
+        header[2..4].copy_from_slice(&(offset | 1).to_be_bytes());
+        header.extend_from_slice(payload);
+        header
+    }
+
+    /// The frame as the capture path decodes it
+    fn decode(frame: &[u8]) -> NetworkPacket {
+        use crate::collectors::PacketCollector;
+        use pnet::packet::ethernet::EtherType;
+
+        let mut packet = NetworkPacket::new("eth0".to_string(), frame.len() as u64, PacketProtocol::Ethernet, PacketDirection::Local);
+        let ethertype = EtherType(u16::from_be_bytes([frame[12], frame[13]]));
+        PacketCollector::process_network_layer(&mut packet, ethertype, &frame[14..], &[]);
+        packet
+    }
+
+    #[test]
+    fn test_bpf_programs_agree_with_the_matcher() {
+        let frames = [
+            ipv4(6, "10.0.0.5", "1.1.1.1", 0, 0, &transport(6, 51000, 443)),
+            ipv4(6, "192.168.4.4", "10.0.0.2", 1, 0, &transport(6, 6005, 80)),
+            ipv4(17, "192.168.1.1", "8.8.8.8", 0, 0, &transport(17, 5000, 53)),
+            ipv4(17, "10.1.1.1", "8.8.8.8", 0, 0x2000, &transport(17, 5000, 53)),
+            // A later fragment, whose payload would read as port 443
+            ipv4(6, "10.0.0.5", "1.1.1.1", 0, 185, &transport(6, 443, 443)),
+            ipv4(1, "192.168.1.1", "8.8.8.8", 0, 0, &[8, 0, 0, 0, 0, 0, 0, 0]),
+            ipv6(6, "fe80::1", "fe80::2", &transport(6, 6005, 80)),
+            ipv6(17, "2001:db8:8000::1", "2001:db8::2", &transport(17, 53, 40000)),
+            ipv6(58, "fe80::1", "ff02::1", &[135, 0, 0, 0, 0, 0, 0, 0]),
+            ipv6(44, "fe80::1", "fe80::2", &ipv6_fragment(17, 0, &transport(17, 5000, 53))),
+            ipv6(44, "fe80::1", "fe80::2", &ipv6_fragment(17, 1480, &transport(17, 53, 53))),
+            ethernet(0x0806, &[0; 28]),
+            ethernet(0x0800, &[0x45; 10]),
+            ethernet(0x88cc, &[0; 46]),
+        ];
+        let expressions = [
+            "tcp",
+            "udp",
+            "icmp",
+            "icmp6",
+            "ip",
+            "ip6",
+            "arp",
+            "tcp port 443 and host 10.0.0.5",
+            "udp dst port domain or icmp && !src net 10.0.0.0/8",
+            "not (tcp or udp)",
+            "not port 80",
+            "portrange 6000-6010",
+            "tcp src port 6005 or 53",
+            "src net 192.168.0.0 mask 255.255.0.0",
+            "net 0.0.0.0/0",
+            "net 2001:db8::/33",
+            "dst fe80::2",
+            "host fe80::1 and greater 64",
+            "less 60",
+        ];
+        for expression in expressions {
+            let filter = CaptureFilter::parse(expression).unwrap();
+            let ethernet = filter.to_bpf(BpfLink::Ethernet).unwrap();
+            let pktap = filter.to_bpf(BpfLink::Pktap).unwrap();
+            for (i, frame) in frames.iter().enumerate() {
+                let expected = filter.matches(&decode(frame));
+                assert_eq!(run(&ethernet, frame), expected, "{expression:?} on frame {i}");
+
+                // pktap headers vary in length; this one is 156 bytes, like macOS's
+                let mut record = vec![0; 156];
+                record[..4].copy_from_slice(&156u32.to_le_bytes());
+                record.extend_from_slice(frame);
+                assert_eq!(run(&pktap, &record), expected, "{expression:?} on frame {i} behind pktap");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bpf_jumps_too_long_for_a_program() {
+        let hosts: Vec<String> = (1..=20).map(|i| format!("host 2001:db8::{i}")).collect();
+        let filter = CaptureFilter::parse(&hosts.join(" or ")).unwrap();
+        assert!(filter.to_bpf(BpfLink::Ethernet).is_none());
+        assert!(CaptureFilter::parse("tcp port 443").unwrap().to_bpf(BpfLink::Ethernet).is_some());
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for expression in ["", "tcp port", "host 999.1.1.1", "port 70000", "(tcp", "tcp )", "foo", "tcp & udp", "portrange 9-1"] {
+            assert!(CaptureFilter::parse(expression).is_err(), "{expression:?} should not parse");
+        }
+    }
+}
@@ -0,0 +1,441 @@
+// Privileged capture helper: runs packet capture in a small separate process
+// The helper is the only part that needs root/CAP_NET_RAW; the UI and analysis
+// code connect to it over a Unix domain socket and run unprivileged. The socket lives
+// in a directory only the invoking user can enter, and each end checks the other's
+// uid before trusting the connection
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::ffi::{CString, OsString};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::{Child, Command};
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+use crate::collectors::capture_filter::CaptureFilter;
+use crate::collectors::platform::CaptureBackend;
+use crate::collectors::platform;
+use crate::collectors::PacketCollector;
+use crate::models::NetworkPacket;
+
+/// Environment variable pointing at a dedicated helper binary (e.g. a copy of `kw`
+/// with `cap_net_raw,cap_net_admin=eip` set). When present it is launched directly
+/// instead of through `sudo`.
+pub const HELPER_BINARY_ENV: &str = "KW_CAPTURE_HELPER";
+
+/// How long the UI waits for the helper socket to appear (covers the sudo prompt)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// First line sent by the UI process after connecting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelperRequest {
+    /// Interfaces to capture on, e.g. "eth0" or "en*,wg0" ("any" selects the first active interface)
+    pub interface: String,
+    /// Capture filter expression, applied in the helper before packets are sent
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// Kernel interface to capture through, from `[capture] backend` or `--capture-backend`
+    #[serde(default)]
+    pub backend: CaptureBackend,
+}
+
+/// Messages streamed from the helper to the UI process, one JSON document per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HelperMessage {
+    /// A parsed packet from the capture (boxed, as packets are far larger than errors)
+    Packet(Box<NetworkPacket>),
+    /// Capture could not be started or failed; the helper exits afterwards
+    Error(String),
+}
+
+/// Name of the socket inside its private directory
+const SOCKET_NAME: &str = "helper.sock";
+
+/// Creates a directory only the current user can enter, to hold the helper socket
+/// It goes under `$XDG_RUNTIME_DIR` when set, which is private to the user already, else
+/// under the temporary directory, with a name nobody can claim beforehand
+pub fn create_socket_dir() -> Result<PathBuf> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute() && dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir);
+    let template = CString::new(base.join("kaipo-watcher-capture-XXXXXX").into_os_string().into_vec())
+        .context("Invalid directory for the helper socket")?;
+    let mut template = template.into_bytes_with_nul();
+    // SAFETY: a writable, NUL-terminated path ending in XXXXXX, as mkdtemp requires
+    let created = unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) };
+    if created.is_null() {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to create a directory for the helper socket in {}", base.display()));
+    }
+    template.pop();
+    // mkdtemp creates it with mode 0700
+    Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+/// The user the helper works for: the one who ran sudo when started through it, else
+/// the user running it
+fn invoking_uid() -> u32 {
+    // SAFETY: neither call can fail or touches memory
+    let (uid, euid) = unsafe { (libc::getuid(), libc::geteuid()) };
+    let sudo_uid = std::env::var("SUDO_UID").ok().and_then(|v| v.parse::<u32>().ok());
+    match sudo_uid {
+        Some(sudo_uid) if euid == 0 => sudo_uid,
+        _ => uid,
+    }
+}
+
+/// Fails unless the process at the other end of `stream` runs as one of `allowed`
+fn check_peer(stream: &UnixStream, allowed: &[u32]) -> Result<()> {
+    let uid = stream.peer_cred().context("Failed to read the peer of the helper socket")?.uid();
+    if !allowed.contains(&uid) {
+        bail!("Refusing a helper socket connection from uid {uid}");
+    }
+    Ok(())
+}
+
+/// Fails unless `dir` is a real directory owned by `owner` that nobody else can enter,
+/// so no other user can reach or replace a socket inside it
+fn check_socket_dir(dir: &Path, owner: u32) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect helper socket directory {}", dir.display()))?;
+    if !metadata.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    if metadata.uid() != owner || metadata.mode() & 0o077 != 0 {
+        bail!(
+            "Helper socket directory {} must belong to uid {owner} and be closed to everyone else (mode 0700)",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Runs the privileged side: accepts a single client, captures on the requested
+/// interface and forwards packets until the client disconnects
+///
+/// The socket must go in a directory private to the invoking user, and is never put
+/// in place of anything already at `socket_path`
+pub async fn run_helper(socket_path: &Path) -> Result<()> {
+    let owner = invoking_uid();
+    let dir = match socket_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    check_socket_dir(dir, owner)?;
+    if std::fs::symlink_metadata(socket_path).is_ok() {
+        bail!("{} already exists; the helper only creates a new socket", socket_path.display());
+    }
+
+    let listener = UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind helper socket {}", socket_path.display()))?;
+    let result = async {
+        restrict_socket(socket_path)?;
+        info!("Capture helper listening on {}", socket_path.display());
+        let (stream, _) = listener.accept().await.context("Failed to accept UI connection")?;
+        check_peer(&stream, &[owner])?;
+        serve_client(stream).await
+    }
+    .await;
+
+    // Only the socket this helper bound is removed
+    let _ = std::fs::remove_file(socket_path);
+    result
+}
+
+/// Limits the socket to its owner. When started through sudo, ownership is handed
+/// back to the invoking user so the unprivileged UI can connect.
+fn restrict_socket(socket_path: &Path) -> Result<()> {
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+        .context("Failed to set helper socket permissions")?;
+
+    let sudo_uid = std::env::var("SUDO_UID").ok().and_then(|v| v.parse::<u32>().ok());
+    let sudo_gid = std::env::var("SUDO_GID").ok().and_then(|v| v.parse::<u32>().ok());
+    if sudo_uid.is_some() {
+        std::os::unix::fs::chown(socket_path, sudo_uid, sudo_gid)
+            .context("Failed to hand helper socket to invoking user")?;
+    }
+
+    Ok(())
+}
+
+async fn serve_client(stream: UnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let request_line = lines
+        .next_line()
+        .await?
+        .context("UI disconnected before sending a capture request")?;
+    let request: HelperRequest =
+        serde_json::from_str(&request_line).context("Invalid capture request")?;
+    info!("Capture helper starting capture on interface: {}", request.interface);
+
+    let mut collector = PacketCollector::new(request.interface)?.with_capture_backend(request.backend);
+    if let Some(expression) = request.filter {
+        match CaptureFilter::parse(&expression) {
+            Ok(filter) => collector = collector.with_filter(filter),
+            Err(e) => {
+                send_message(&mut writer, &HelperMessage::Error(format!("{e:#}"))).await?;
+                return Err(e);
+            }
+        }
+    }
+    if let Err(e) = collector.start().await {
+        send_message(&mut writer, &HelperMessage::Error(e.to_string())).await?;
+        return Err(e);
+    }
+
+    loop {
+        tokio::select! {
+            packet = collector.receive_packet() => {
+                let Some(packet) = packet else { break };
+                if send_message(&mut writer, &HelperMessage::Packet(Box::new(packet))).await.is_err() {
+                    debug!("UI process disconnected");
+                    break;
+                }
+            }
+            // The UI never sends anything after the request, so EOF means it went away
+            line = lines.next_line() => {
+                if !matches!(line, Ok(Some(_))) {
+                    debug!("UI process closed the connection");
+                    break;
+                }
+            }
+        }
+    }
+
+    collector.stop().await?;
+    info!("Capture helper finished");
+    Ok(())
+}
+
+async fn send_message<W: AsyncWriteExt + Unpin>(writer: &mut W, message: &HelperMessage) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// Connects to a running helper and asks it to capture on `interface`, optionally filtered
+/// Retries until the socket appears so a helper that is still starting is tolerated; the
+/// helper must run as root or as this user
+pub async fn connect(
+    socket_path: &Path,
+    interface: &str,
+    filter: Option<String>,
+    backend: CaptureBackend,
+) -> Result<HelperConnection> {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    let mut stream = loop {
+        match UnixStream::connect(socket_path).await {
+            Ok(stream) => break stream,
+            Err(e) if Instant::now() < deadline => {
+                debug!("Waiting for capture helper at {}: {}", socket_path.display(), e);
+                sleep(Duration::from_millis(200)).await;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Capture helper did not come up at {}", socket_path.display())
+                });
+            }
+        }
+    };
+    // SAFETY: getuid cannot fail or touch memory
+    check_peer(&stream, &[0, unsafe { libc::getuid() }])?;
+
+    let mut request = serde_json::to_vec(&HelperRequest {
+        interface: interface.to_string(),
+        filter,
+        backend,
+    })?;
+    request.push(b'\n');
+    stream.write_all(&request).await?;
+
+    Ok(HelperConnection { lines: BufReader::new(stream).lines() })
+}
+
+/// UI side of a helper connection; dropping it tells the helper to stop
+pub struct HelperConnection {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl HelperConnection {
+    /// Reads the next message, `None` once the helper is gone
+    /// Cancel safe, so it can be raced against a timeout
+    pub async fn next_message(&mut self) -> Result<Option<HelperMessage>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(
+                serde_json::from_str(&line).context("Invalid message from capture helper")?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Handle to a helper process launched by the UI; dropping it removes the socket directory
+pub struct CaptureHelperProcess {
+    socket_dir: PathBuf,
+    socket_path: PathBuf,
+    child: Child,
+}
+
+impl CaptureHelperProcess {
+    /// Launches the helper with elevated rights
+    ///
+    /// Order of preference:
+    /// 1. The binary named by `KW_CAPTURE_HELPER` (expected to carry file capabilities)
+    /// 2. This executable directly, if the current process already has capture privileges
+    /// 3. This executable through `sudo`
+    pub fn spawn() -> Result<Self> {
+        let socket_dir = create_socket_dir()?;
+        let socket_path = socket_dir.join(SOCKET_NAME);
+        let current_exe = std::env::current_exe().context("Failed to locate kw executable")?;
+
+        let mut command = if let Some(helper) = std::env::var_os(HELPER_BINARY_ENV) {
+            Command::new(helper)
+        } else if platform::has_capture_privileges() {
+            Command::new(&current_exe)
+        } else {
+            let mut sudo = Command::new("sudo");
+            sudo.arg(&current_exe);
+            sudo
+        };
+
+        command
+            .arg("capture-helper")
+            .arg("--socket")
+            .arg(&socket_path)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit());
+
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&socket_dir);
+                return Err(e).context("Failed to launch capture helper");
+            }
+        };
+        info!("Launched capture helper, socket: {}", socket_path.display());
+
+        Ok(Self { socket_dir, socket_path, child })
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Waits briefly for the helper to exit after the UI dropped its connection
+    pub async fn shutdown(mut self) {
+        match timeout(Duration::from_secs(3), self.child.wait()).await {
+            Ok(Ok(status)) => debug!("Capture helper exited with {}", status),
+            Ok(Err(e)) => warn!("Failed to wait for capture helper: {}", e),
+            Err(_) => warn!("Capture helper did not exit in time"),
+        }
+    }
+}
+
+impl Drop for CaptureHelperProcess {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.socket_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol};
+
+    #[test]
+    fn test_helper_message_roundtrip() {
+        let packet = NetworkPacket::new(
+            "eth0".to_string(),
+            1500,
+            PacketProtocol::IPv4,
+            PacketDirection::Inbound,
+        );
+        let encoded = serde_json::to_string(&HelperMessage::Packet(Box::new(packet))).unwrap();
+        assert!(!encoded.contains('\n'));
+
+        match serde_json::from_str::<HelperMessage>(&encoded).unwrap() {
+            HelperMessage::Packet(decoded) => {
+                assert_eq!(decoded.size_bytes, 1500);
+                assert_eq!(decoded.interface, "eth0");
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_socket_dir_is_private() {
+        let dir = create_socket_dir().unwrap();
+        let metadata = std::fs::symlink_metadata(&dir).unwrap();
+        assert!(metadata.is_dir());
+        assert_eq!(metadata.mode() & 0o777, 0o700);
+        assert_eq!(metadata.uid(), unsafe { libc::getuid() });
+        assert!(check_socket_dir(&dir, metadata.uid()).is_ok());
+        assert!(check_socket_dir(&dir, metadata.uid() + 1).is_err());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_helper_leaves_foreign_paths_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let uid = unsafe { libc::getuid() };
+
+        // A directory others can enter is refused
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_socket_dir(dir.path(), uid).is_err());
+        assert!(run_helper(&dir.path().join(SOCKET_NAME)).await.is_err());
+
+        // Whatever is already at the path stays there
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o700)).unwrap();
+        let socket_path = dir.path().join(SOCKET_NAME);
+        std::fs::write(&socket_path, "not a socket").unwrap();
+        assert!(run_helper(&socket_path).await.is_err());
+        assert_eq!(std::fs::read_to_string(&socket_path).unwrap(), "not a socket");
+    }
+
+    #[tokio::test]
+    async fn test_peers_are_checked_by_uid() {
+        let (stream, _other) = UnixStream::pair().unwrap();
+        let uid = unsafe { libc::getuid() };
+        assert!(check_peer(&stream, &[uid]).is_ok());
+        assert!(check_peer(&stream, &[uid + 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_sends_request_and_reads_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("helper.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let request = BufReader::new(reader).lines().next_line().await.unwrap().unwrap();
+            let request: HelperRequest = serde_json::from_str(&request).unwrap();
+            assert_eq!(request.filter.as_deref(), Some("tcp port 443"));
+            assert_eq!(request.backend, CaptureBackend::AfXdp);
+            send_message(&mut writer, &HelperMessage::Error(format!("no {}", request.interface)))
+                .await
+                .unwrap();
+        });
+
+        let mut connection = connect(&socket_path, "eth7", Some("tcp port 443".to_string()), CaptureBackend::AfXdp)
+            .await
+            .unwrap();
+        match connection.next_message().await.unwrap() {
+            Some(HelperMessage::Error(message)) => assert_eq!(message, "no eth7"),
+            other => panic!("unexpected message: {other:?}"),
+        }
+        server.await.unwrap();
+        assert!(connection.next_message().await.unwrap().is_none());
+    }
+}
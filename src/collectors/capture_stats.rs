@@ -0,0 +1,187 @@
+// Capture performance statistics: throughput, drops and queue depths of a capture session
+// Sampled by `kw service run` and `kw packets` and stored for `kw capture-stats`, so
+// the headroom of a capture setup can be judged from real numbers. Each sample also carries
+// the packet size histograms of its interval, for `kw analyze` and `kw graph packet-sizes`
+//
+// Kernel drops come from the interface counters (Linux: /sys/class/net/<if>/statistics/rx_dropped);
+// other platforms report none
+
+use chrono::{DateTime, Local};
+use std::time::Instant;
+
+use crate::models::{PacketSizeDistribution, PacketStatistics};
+use crate::storage::packet_storage::CaptureSample;
+
+/// Collects capture performance between samples of one session
+///
+/// `poll` should run about once a second: it records queue depths and the capture
+/// rate, which the collector updates every second. `sample` closes the interval.
+#[derive(Debug)]
+pub struct CaptureMonitor {
+    interface: String,
+    session_start: DateTime<Local>,
+    last_sample: Instant,
+    last_packets: u64,
+    last_bytes: u64,
+    last_queue_drops: u64,
+    last_kernel_drops: Option<u64>,
+    last_packet_sizes: PacketSizeDistribution,
+    peak_packets_per_second: f64,
+    peak_bytes_per_second: f64,
+    queue_capacity: usize,
+    queue_depth_sum: u64,
+    peak_queue_depth: usize,
+    peak_storage_backlog: usize,
+    polls: u64,
+}
+
+impl CaptureMonitor {
+    /// Starts a session on `interface` ("any" for all interfaces)
+    pub fn new(interface: String) -> Self {
+        let last_kernel_drops = kernel_drops(&interface);
+        Self {
+            interface,
+            session_start: Local::now(),
+            last_sample: Instant::now(),
+            last_packets: 0,
+            last_bytes: 0,
+            last_queue_drops: 0,
+            last_kernel_drops,
+            last_packet_sizes: PacketSizeDistribution::default(),
+            peak_packets_per_second: 0.0,
+            peak_bytes_per_second: 0.0,
+            queue_capacity: 0,
+            queue_depth_sum: 0,
+            peak_queue_depth: 0,
+            peak_storage_backlog: 0,
+            polls: 0,
+        }
+    }
+
+    /// Records the current rate, the packets waiting for analysis out of `queue_capacity`,
+    /// and the records waiting to be written to the database
+    pub fn poll(&mut self, stats: &PacketStatistics, queue: (usize, usize), storage_backlog: usize) {
+        let (queued, queue_capacity) = queue;
+        self.peak_packets_per_second = self.peak_packets_per_second.max(stats.packets_per_second);
+        self.peak_bytes_per_second = self.peak_bytes_per_second.max(stats.bytes_per_second);
+        self.queue_capacity = queue_capacity;
+        self.queue_depth_sum += queued as u64;
+        self.peak_queue_depth = self.peak_queue_depth.max(queued);
+        self.peak_storage_backlog = self.peak_storage_backlog.max(storage_backlog);
+        self.polls += 1;
+    }
+
+    /// Closes the current interval; `stats` holds the collector's cumulative statistics
+    pub fn sample(&mut self, stats: &PacketStatistics, queue: (usize, usize), storage_backlog: usize) -> CaptureSample {
+        self.poll(stats, queue, storage_backlog);
+
+        let kernel_drops = kernel_drops(&self.interface);
+        let sample = CaptureSample {
+            timestamp: Local::now(),
+            session_start: self.session_start,
+            interface_name: self.interface.clone(),
+            duration_secs: self.last_sample.elapsed().as_secs_f64(),
+            packets: stats.total_packets.saturating_sub(self.last_packets),
+            bytes: stats.total_bytes.saturating_sub(self.last_bytes),
+            peak_packets_per_second: self.peak_packets_per_second,
+            peak_bytes_per_second: self.peak_bytes_per_second,
+            queue_drops: stats.dropped_packets.saturating_sub(self.last_queue_drops),
+            kernel_drops: kernel_drops
+                .zip(self.last_kernel_drops)
+                .map(|(now, before)| now.saturating_sub(before)),
+            queue_capacity: self.queue_capacity as u64,
+            avg_queue_depth: self.queue_depth_sum as f64 / self.polls as f64,
+            peak_queue_depth: self.peak_queue_depth as u64,
+            peak_storage_backlog: self.peak_storage_backlog as u64,
+            packet_sizes: stats.packet_sizes.since(&self.last_packet_sizes),
+        };
+
+        self.last_sample = Instant::now();
+        self.last_packets = stats.total_packets;
+        self.last_bytes = stats.total_bytes;
+        self.last_queue_drops = stats.dropped_packets;
+        self.last_kernel_drops = kernel_drops;
+        self.last_packet_sizes = stats.packet_sizes.clone();
+        self.peak_packets_per_second = 0.0;
+        self.peak_bytes_per_second = 0.0;
+        self.queue_depth_sum = 0;
+        self.peak_queue_depth = 0;
+        self.peak_storage_backlog = 0;
+        self.polls = 0;
+        sample
+    }
+}
+
+/// Packets the kernel or driver dropped on `interface` since boot; "any" sums all interfaces
+#[cfg(target_os = "linux")]
+pub fn kernel_drops(interface: &str) -> Option<u64> {
+    let read = |name: &str| -> Option<u64> {
+        std::fs::read_to_string(format!("/sys/class/net/{name}/statistics/rx_dropped"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    if interface != "any" {
+        return read(interface);
+    }
+    let entries = std::fs::read_dir("/sys/class/net").ok()?;
+    Some(
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| read(&entry.file_name().to_string_lossy()))
+            .sum(),
+    )
+}
+
+/// Packets the kernel or driver dropped on `interface` since boot; "any" sums all interfaces
+#[cfg(not(target_os = "linux"))]
+pub fn kernel_drops(_interface: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProtocolDistribution;
+
+    fn stats(packets: u64, bytes: u64, dropped: u64, packets_per_second: f64) -> PacketStatistics {
+        PacketStatistics {
+            total_packets: packets,
+            total_bytes: bytes,
+            dropped_packets: dropped,
+            packets_per_second,
+            bytes_per_second: packets_per_second * 100.0,
+            protocol_distribution: ProtocolDistribution::default(),
+            top_connections: Vec::new(),
+            packet_sizes: PacketSizeDistribution::default(),
+            start_time: Local::now(),
+            end_time: Local::now(),
+        }
+    }
+
+    #[test]
+    fn test_samples_cover_their_interval() {
+        let mut monitor = CaptureMonitor::new("kw-test0".to_string());
+        monitor.poll(&stats(100, 10_000, 0, 100.0), (10, 1000), 5);
+        monitor.poll(&stats(400, 40_000, 2, 300.0), (50, 1000), 20);
+        let first = monitor.sample(&stats(500, 50_000, 3, 100.0), (0, 1000), 0);
+
+        assert_eq!((first.packets, first.bytes, first.queue_drops), (500, 50_000, 3));
+        assert_eq!(first.peak_packets_per_second, 300.0);
+        assert_eq!(first.peak_bytes_per_second, 30_000.0);
+        assert_eq!(first.queue_capacity, 1000);
+        assert_eq!(first.avg_queue_depth, 20.0);
+        assert_eq!(first.peak_queue_depth, 50);
+        assert_eq!(first.peak_storage_backlog, 20);
+        // The test interface does not exist, so the kernel has nothing to report
+        assert_eq!(first.kernel_drops, None);
+
+        // The next interval starts from the counters and peaks of this one
+        let second = monitor.sample(&stats(600, 60_000, 3, 50.0), (1, 1000), 0);
+        assert_eq!((second.packets, second.bytes, second.queue_drops), (100, 10_000, 0));
+        assert_eq!(second.peak_packets_per_second, 50.0);
+        assert_eq!(second.peak_queue_depth, 1);
+        assert_eq!(second.session_start, first.session_start);
+    }
+}
@@ -0,0 +1,154 @@
+// One bandwidth collector shared by everything in a process that reads interface speeds
+// A background task collects at a fixed interval and publishes each reading on a watch
+// channel, so every subscriber sees the same numbers and interfaces are polled once
+// rather than once per reader
+
+use log::warn;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::collectors::bandwidth::CollectorTelemetry;
+use crate::collectors::bandwidth_collector::BandwidthStats;
+use crate::collectors::BandwidthCollector;
+
+/// One collection as published to subscribers
+#[derive(Debug, Clone)]
+pub struct BandwidthSnapshot {
+    /// Number of the collection since the service started
+    pub collection: u64,
+    /// When the collection finished
+    pub taken_at: Instant,
+    /// Reading of every interface; those of the last successful collection if this one failed
+    pub stats: Arc<Vec<BandwidthStats>>,
+    /// Why this collection failed
+    pub error: Option<String>,
+    /// Collections that have failed since the service started
+    pub failures: u64,
+    /// Interface counter resets or wraparounds seen since the service started
+    pub counter_resets: u64,
+}
+
+/// Owns a `BandwidthCollector` and broadcasts its readings
+///
+/// Subscribers get a `watch::Receiver`, so a slow one only ever skips to the newest
+/// reading and never holds the collector up. The collector task stops when the
+/// service is dropped.
+pub struct CollectorService {
+    snapshots: watch::Receiver<BandwidthSnapshot>,
+    /// The collector's own health since `take_telemetry` was last called
+    telemetry: Arc<Mutex<CollectorTelemetry>>,
+    task: JoinHandle<()>,
+}
+
+impl CollectorService {
+    /// Takes a first reading, which subscribers start from, then one every `period`
+    pub async fn start(mut collector: BandwidthCollector, period: Duration) -> Self {
+        let mut failures = 0;
+        let first = collect(&mut collector, 1, None, &mut failures).await;
+        let (sender, snapshots) = watch::channel(first);
+        let telemetry = Arc::new(Mutex::new(collector.take_telemetry()));
+        let shared_telemetry = Arc::clone(&telemetry);
+
+        let task = tokio::spawn(async move {
+            let mut ticker = interval(period);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes at once and the first reading is already taken
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let previous = sender.borrow().clone();
+                let snapshot = collect(&mut collector, previous.collection + 1, Some(previous), &mut failures).await;
+                shared_telemetry.lock().unwrap().merge(&collector.take_telemetry());
+                if sender.send(snapshot).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { snapshots, telemetry, task }
+    }
+
+    /// A receiver marked as having seen the current reading
+    pub fn subscribe(&self) -> watch::Receiver<BandwidthSnapshot> {
+        self.snapshots.clone()
+    }
+
+    /// How the collections since the last call went, starting from the service's first
+    pub fn take_telemetry(&self) -> CollectorTelemetry {
+        std::mem::take(&mut *self.telemetry.lock().unwrap())
+    }
+}
+
+impl Drop for CollectorService {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn collect(
+    collector: &mut BandwidthCollector,
+    collection: u64,
+    previous: Option<BandwidthSnapshot>,
+    failures: &mut u64,
+) -> BandwidthSnapshot {
+    let result = collector.collect_async().await;
+    let (stats, error) = match result {
+        Ok(stats) => (Arc::new(stats), None),
+        Err(e) => {
+            warn!("Bandwidth collection #{collection} failed: {e}");
+            *failures += 1;
+            let stats = previous.map(|snapshot| snapshot.stats).unwrap_or_default();
+            (stats, Some(e.to_string()))
+        }
+    };
+    BandwidthSnapshot {
+        collection,
+        taken_at: Instant::now(),
+        stats,
+        error,
+        failures: *failures,
+        counter_resets: collector.counter_resets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_share_each_reading() {
+        let service = CollectorService::start(BandwidthCollector::new(), Duration::from_millis(200)).await;
+        let mut first = service.subscribe();
+        let mut second = service.subscribe();
+        assert_eq!(first.borrow().collection, 1);
+        assert!(!first.has_changed().unwrap());
+
+        first.changed().await.unwrap();
+        second.changed().await.unwrap();
+        let (a, b) = (first.borrow_and_update().clone(), second.borrow_and_update().clone());
+        assert!(a.collection >= 2);
+        assert_eq!(a.taken_at, b.taken_at);
+        assert!(Arc::ptr_eq(&a.stats, &b.stats));
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_covers_every_collection() {
+        let service = CollectorService::start(BandwidthCollector::new(), Duration::from_millis(50)).await;
+        let mut receiver = service.subscribe();
+        receiver.changed().await.unwrap();
+        // Counted before the reading is published, the first collection included
+        assert!(service.take_telemetry().collections >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_the_service_stops_collection() {
+        let service = CollectorService::start(BandwidthCollector::new(), Duration::from_millis(10)).await;
+        let mut receiver = service.subscribe();
+        drop(service);
+        // Drains a reading that may have landed before the task was aborted, then ends
+        while receiver.changed().await.is_ok() {}
+    }
+}
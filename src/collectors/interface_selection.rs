@@ -0,0 +1,136 @@
+//! Interface selection shared by `status`, `live` and `packets`
+//!
+//! `--interface` may be given several times and takes exact names and globs with `*`
+//! and `?`, e.g. `--interface "en*" --interface wg0`; an interface is selected when any
+//! pattern matches it. Comma-separated lists work too, which is also how a selection is
+//! handed to the capture helper as one string.
+
+use std::fmt;
+
+/// Captures on every interface rather than a selection
+pub const ANY_INTERFACE: &str = "any";
+
+/// Interface names and globs, any of which selects an interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceSelection {
+    patterns: Vec<String>,
+}
+
+impl InterfaceSelection {
+    /// Selection from `--interface` values, each possibly a comma-separated list;
+    /// `None` when no pattern is left, meaning no filtering
+    pub fn new<S: AsRef<str>>(values: &[S]) -> Option<Self> {
+        let patterns: Vec<String> = values
+            .iter()
+            .flat_map(|value| value.as_ref().split(','))
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_string)
+            .collect();
+        (!patterns.is_empty()).then_some(Self { patterns })
+    }
+
+    /// Selection from one comma-separated string, as printed by `Display`
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::new(&[value])
+    }
+
+    /// Whether `name` is selected: equal to a pattern, or matching one as a glob
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern == name || glob_match(pattern, name))
+    }
+
+    /// The single interface selected without any glob, e.g. for `--interface eth0`
+    pub fn single_name(&self) -> Option<&str> {
+        match self.patterns.as_slice() {
+            [name] if !is_glob(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether the selection is the `any` pseudo-interface rather than a list of interfaces
+    pub fn is_any(&self) -> bool {
+        self.single_name() == Some(ANY_INTERFACE)
+    }
+}
+
+impl fmt::Display for InterfaceSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.patterns.join(","))
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Matches `name` against a glob with `*` (any run) and `?` (any one character)
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and retry
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("br-*", "br-3f2a9c"));
+        assert!(glob_match("veth*", "veth"));
+        assert!(glob_match("en?", "en0"));
+        assert!(glob_match("*tun*", "utun3"));
+        assert!(!glob_match("en?", "en10"));
+        assert!(!glob_match("docker*", "eth0"));
+    }
+
+    #[test]
+    fn test_selection_from_flags_and_lists() {
+        let selection = InterfaceSelection::new(&["en*", "wg0,tun?"]).unwrap();
+        assert!(selection.matches("en0"));
+        assert!(selection.matches("enp3s0f0"));
+        assert!(selection.matches("wg0"));
+        assert!(selection.matches("tun1"));
+        assert!(!selection.matches("wg01"));
+        assert!(!selection.matches("eth0"));
+        assert_eq!(selection.to_string(), "en*,wg0,tun?");
+        assert_eq!(InterfaceSelection::parse(&selection.to_string()), Some(selection));
+
+        assert_eq!(InterfaceSelection::new::<&str>(&[]), None);
+        assert_eq!(InterfaceSelection::new(&[" , "]), None);
+    }
+
+    #[test]
+    fn test_single_name_and_any() {
+        assert_eq!(InterfaceSelection::parse("eth0").unwrap().single_name(), Some("eth0"));
+        assert_eq!(InterfaceSelection::parse("eth*").unwrap().single_name(), None);
+        assert_eq!(InterfaceSelection::parse("eth0,wg0").unwrap().single_name(), None);
+        assert!(InterfaceSelection::parse("any").unwrap().is_any());
+        assert!(!InterfaceSelection::parse("any,eth0").unwrap().is_any());
+    }
+}
@@ -0,0 +1,542 @@
+// Bond, bridge and team interfaces and the member links behind them
+//
+// - Linux: members point at their aggregate through /sys/class/net/<member>/master; the kind
+//   comes from the aggregate's uevent DEVTYPE and a bond's active link from bonding/active_slave
+// - macOS: the `bond interfaces:` and `member:` lines of `ifconfig -a`
+// - Elsewhere (including Windows NIC teaming): no aggregates are detected
+//
+// An aggregate's counters already include its members' traffic, so lists show the aggregate
+// once with its members beneath it instead of as separate, seemingly duplicate interfaces.
+// Likewise a VPN tunnel's traffic is in its transport's counters too, encrypted, so totals
+// leave out tunnels whose transport is listed
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::collectors::bandwidth_collector::{BandwidthStats, CalculationConfidence, InterfaceState, InterfaceType};
+use crate::collectors::tunnels::Tunnel;
+
+/// Name of the synthetic interface `--aggregate` adds for the sum of the listed ones
+pub const TOTAL_INTERFACE: &str = "total";
+
+/// How an aggregate combines its member links
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+    /// Bonded links (link aggregation, active-backup and the other bonding modes)
+    Bond,
+    /// Bridged links switched as one segment
+    Bridge,
+    /// Links combined by the team driver
+    Team,
+}
+
+impl fmt::Display for AggregateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateKind::Bond => write!(f, "bond"),
+            AggregateKind::Bridge => write!(f, "bridge"),
+            AggregateKind::Team => write!(f, "team"),
+        }
+    }
+}
+
+/// A link belonging to an aggregate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateMember {
+    pub name: String,
+    /// Whether the link is up and has a carrier
+    pub up: bool,
+}
+
+/// A bond, bridge or team interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkAggregate {
+    pub name: String,
+    pub kind: AggregateKind,
+    /// Member links, sorted by name
+    pub members: Vec<AggregateMember>,
+    /// Link carrying the traffic of an active-backup bond
+    pub active_member: Option<String>,
+}
+
+impl LinkAggregate {
+    fn member(&self, name: &str) -> Option<&AggregateMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+}
+
+/// Aggregates on this system, sorted by name
+#[cfg(target_os = "linux")]
+pub fn detect_link_aggregates() -> Vec<LinkAggregate> {
+    use std::path::Path;
+
+    let net = Path::new("/sys/class/net");
+    let Ok(entries) = std::fs::read_dir(net) else {
+        return Vec::new();
+    };
+
+    let mut members: HashMap<String, Vec<AggregateMember>> = HashMap::new();
+    for entry in entries.flatten() {
+        let Ok(master) = std::fs::read_link(entry.path().join("master")) else {
+            continue;
+        };
+        let Some(master) = master.file_name() else {
+            continue;
+        };
+        let operstate = std::fs::read_to_string(entry.path().join("operstate")).unwrap_or_default();
+        members.entry(master.to_string_lossy().into_owned()).or_default().push(AggregateMember {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            up: operstate.trim() == "up",
+        });
+    }
+
+    let mut aggregates: Vec<LinkAggregate> = members
+        .into_iter()
+        .filter_map(|(name, mut members)| {
+            let dir = net.join(&name);
+            let uevent = std::fs::read_to_string(dir.join("uevent")).unwrap_or_default();
+            let kind = match parse_uevent_devtype(&uevent) {
+                Some("bond") => AggregateKind::Bond,
+                Some("bridge") => AggregateKind::Bridge,
+                Some("team") => AggregateKind::Team,
+                _ if dir.join("bonding").is_dir() => AggregateKind::Bond,
+                _ if dir.join("bridge").is_dir() => AggregateKind::Bridge,
+                // VRFs, Open vSwitch datapaths and the like
+                _ => return None,
+            };
+            let active_member = std::fs::read_to_string(dir.join("bonding/active_slave"))
+                .ok()
+                .map(|active| active.trim().to_string())
+                .filter(|active| !active.is_empty());
+            members.sort_by(|a, b| a.name.cmp(&b.name));
+            Some(LinkAggregate { name, kind, members, active_member })
+        })
+        .collect();
+    aggregates.sort_by(|a, b| a.name.cmp(&b.name));
+    aggregates
+}
+
+/// Aggregates on this system, sorted by name
+#[cfg(target_os = "macos")]
+pub fn detect_link_aggregates() -> Vec<LinkAggregate> {
+    match std::process::Command::new("ifconfig").arg("-a").output() {
+        Ok(output) => parse_ifconfig_aggregates(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Aggregates on this system, sorted by name
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn detect_link_aggregates() -> Vec<LinkAggregate> {
+    Vec::new()
+}
+
+/// DEVTYPE of a sysfs uevent file, e.g. `bond` for `DEVTYPE=bond`
+#[cfg(any(target_os = "linux", test))]
+fn parse_uevent_devtype(uevent: &str) -> Option<&str> {
+    uevent.lines().find_map(|line| line.trim().strip_prefix("DEVTYPE="))
+}
+
+/// Bonds and bridges described by `ifconfig -a`
+/// Members are up when their own block reports `status: active`, or is UP without a status line
+#[cfg(any(target_os = "macos", test))]
+fn parse_ifconfig_aggregates(output: &str) -> Vec<LinkAggregate> {
+    // (name, flags line, body lines) of each interface block
+    let mut blocks: Vec<(&str, &str, Vec<&str>)> = Vec::new();
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            if let Some((name, flags)) = line.split_once(": ") {
+                blocks.push((name, flags, Vec::new()));
+            }
+        } else if let Some((_, _, body)) = blocks.last_mut() {
+            body.push(line.trim());
+        }
+    }
+
+    let is_up = |name: &str| {
+        blocks.iter().find(|(block, _, _)| *block == name).is_some_and(|(_, flags, body)| {
+            match body.iter().find_map(|line| line.strip_prefix("status:")) {
+                Some(status) => status.trim() == "active",
+                None => flags.contains("<UP") || flags.contains(",UP"),
+            }
+        })
+    };
+
+    let mut aggregates: Vec<LinkAggregate> = blocks
+        .iter()
+        .filter_map(|(name, _, body)| {
+            let (kind, mut members): (AggregateKind, Vec<&str>) =
+                match body.iter().find_map(|line| line.strip_prefix("bond interfaces:")) {
+                    Some(list) => (AggregateKind::Bond, list.split_whitespace().collect()),
+                    None => (
+                        AggregateKind::Bridge,
+                        body.iter()
+                            .filter_map(|line| line.strip_prefix("member:"))
+                            .filter_map(|member| member.split_whitespace().next())
+                            .collect(),
+                    ),
+                };
+            if members.is_empty() {
+                return None;
+            }
+            members.sort_unstable();
+            Some(LinkAggregate {
+                name: name.to_string(),
+                kind,
+                members: members
+                    .into_iter()
+                    .map(|member| AggregateMember { name: member.to_string(), up: is_up(member) })
+                    .collect(),
+                active_member: None,
+            })
+        })
+        .collect();
+    aggregates.sort_by(|a, b| a.name.cmp(&b.name));
+    aggregates
+}
+
+/// An interface as lists show it: on its own, or an aggregate with its member links beneath
+#[derive(Debug)]
+pub struct InterfaceGroup<'a> {
+    pub stats: &'a BandwidthStats,
+    pub aggregate: Option<&'a LinkAggregate>,
+    /// Member links in the aggregate's order, with their counters when they were collected
+    pub members: Vec<(&'a AggregateMember, Option<&'a BandwidthStats>)>,
+    /// The VPN tunnel this interface is
+    pub tunnel: Option<&'a Tunnel>,
+}
+
+impl InterfaceGroup<'_> {
+    /// Whether the interface is listed in `groups`, on its own or as an aggregate's member
+    fn listed_in(name: &str, groups: &[InterfaceGroup]) -> bool {
+        groups.iter().any(|group| {
+            group.stats.interface_name == name || group.members.iter().any(|(member, _)| member.name == name)
+        })
+    }
+
+    /// Tunnel whose traffic `groups` already count through its listed transport
+    pub fn carried_by_listed(&self, groups: &[InterfaceGroup]) -> Option<&Tunnel> {
+        self.tunnel.filter(|tunnel| {
+            tunnel.transport.as_deref().is_some_and(|transport| Self::listed_in(transport, groups))
+        })
+    }
+}
+
+/// The groups whose traffic a total adds up: all of them but tunnels whose transport is listed
+pub fn counted_groups<'g, 'a>(groups: &'g [InterfaceGroup<'a>]) -> impl Iterator<Item = &'g InterfaceGroup<'a>> {
+    groups.iter().filter(|group| group.carried_by_listed(groups).is_none())
+}
+
+/// Groups collected interfaces under the aggregates they belong to, noting which are tunnels
+///
+/// Members of an aggregate that was collected are only listed beneath it; members of one
+/// that was filtered out stay on their own. Groups keep the order of `stats`.
+pub fn group_interfaces<'a>(
+    stats: &'a [BandwidthStats],
+    aggregates: &'a [LinkAggregate],
+    tunnels: &'a [Tunnel],
+) -> Vec<InterfaceGroup<'a>> {
+    let by_name: HashMap<&str, &BandwidthStats> =
+        stats.iter().map(|stat| (stat.interface_name.as_str(), stat)).collect();
+    let grouped: Vec<&LinkAggregate> =
+        aggregates.iter().filter(|aggregate| by_name.contains_key(aggregate.name.as_str())).collect();
+
+    stats
+        .iter()
+        .filter(|stat| {
+            !grouped.iter().any(|aggregate| aggregate.member(&stat.interface_name).is_some())
+        })
+        .map(|stat| {
+            let aggregate = grouped.iter().find(|aggregate| aggregate.name == stat.interface_name).copied();
+            let members = aggregate
+                .map(|aggregate| {
+                    aggregate
+                        .members
+                        .iter()
+                        .map(|member| (member, by_name.get(member.name.as_str()).copied()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let tunnel = tunnels.iter().find(|tunnel| tunnel.name == stat.interface_name);
+            InterfaceGroup { stats: stat, aggregate, members, tunnel }
+        })
+        .collect()
+}
+
+/// Sums the speeds and counters of `groups` into one synthetic "total" interface
+///
+/// Each group counts once, so members of a listed aggregate are not added again, and
+/// neither are tunnels whose transport is listed. The total is as confident as its least
+/// confident interface and up when any of them is; `None` when there is nothing to sum.
+pub fn total_stats(groups: &[InterfaceGroup]) -> Option<BandwidthStats> {
+    let first = counted_groups(groups).next()?.stats;
+    let mut total = BandwidthStats {
+        timestamp: first.timestamp,
+        interface_name: TOTAL_INTERFACE.to_string(),
+        interface_type: InterfaceType::Unknown,
+        interface_state: InterfaceState::Down,
+        bytes_received: 0,
+        bytes_sent: 0,
+        packets_received: 0,
+        packets_sent: 0,
+        download_speed_bps: 0.0,
+        upload_speed_bps: 0.0,
+        calculation_confidence: CalculationConfidence::High,
+        time_since_last_update: 0.0,
+    };
+    for stat in counted_groups(groups).map(|group| group.stats) {
+        total.timestamp = total.timestamp.max(stat.timestamp);
+        if stat.interface_state == InterfaceState::Up {
+            total.interface_state = InterfaceState::Up;
+        }
+        total.bytes_received += stat.bytes_received;
+        total.bytes_sent += stat.bytes_sent;
+        total.packets_received += stat.packets_received;
+        total.packets_sent += stat.packets_sent;
+        total.download_speed_bps += stat.download_speed_bps;
+        total.upload_speed_bps += stat.upload_speed_bps;
+        if confidence_rank(&stat.calculation_confidence) > confidence_rank(&total.calculation_confidence) {
+            total.calculation_confidence = stat.calculation_confidence.clone();
+        }
+        total.time_since_last_update = total.time_since_last_update.max(stat.time_since_last_update);
+    }
+    Some(total)
+}
+
+/// Higher is less confident
+fn confidence_rank(confidence: &CalculationConfidence) -> u8 {
+    match confidence {
+        CalculationConfidence::High => 0,
+        CalculationConfidence::Medium => 1,
+        CalculationConfidence::Low => 2,
+        CalculationConfidence::None => 3,
+    }
+}
+
+/// A change to an aggregate's member links
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailoverEvent {
+    /// An active-backup bond moved its traffic to another link
+    ActiveChanged { aggregate: String, from: Option<String>, to: Option<String> },
+    /// A member link lost its carrier or went down
+    MemberDown { aggregate: String, member: String },
+    /// A member link came back
+    MemberUp { aggregate: String, member: String },
+}
+
+impl fmt::Display for FailoverEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |link: &Option<String>| link.clone().unwrap_or_else(|| "none".to_string());
+        match self {
+            FailoverEvent::ActiveChanged { aggregate, from, to } => {
+                write!(f, "{aggregate} failed over {} → {}", name(from), name(to))
+            }
+            FailoverEvent::MemberDown { aggregate, member } => write!(f, "{aggregate} member {member} down"),
+            FailoverEvent::MemberUp { aggregate, member } => write!(f, "{aggregate} member {member} up"),
+        }
+    }
+}
+
+/// Names failovers and member links going down or up between successive detections
+#[derive(Debug, Default)]
+pub struct FailoverTracker {
+    previous: HashMap<String, LinkAggregate>,
+}
+
+impl FailoverTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events since the last call; the first call only records the current state
+    pub fn observe(&mut self, aggregates: &[LinkAggregate]) -> Vec<FailoverEvent> {
+        let mut events = Vec::new();
+        for aggregate in aggregates {
+            let Some(previous) = self.previous.get(&aggregate.name) else {
+                continue;
+            };
+            for member in &aggregate.members {
+                match previous.member(&member.name) {
+                    Some(before) if before.up && !member.up => events.push(FailoverEvent::MemberDown {
+                        aggregate: aggregate.name.clone(),
+                        member: member.name.clone(),
+                    }),
+                    Some(before) if !before.up && member.up => events.push(FailoverEvent::MemberUp {
+                        aggregate: aggregate.name.clone(),
+                        member: member.name.clone(),
+                    }),
+                    _ => {}
+                }
+            }
+            if previous.active_member != aggregate.active_member {
+                events.push(FailoverEvent::ActiveChanged {
+                    aggregate: aggregate.name.clone(),
+                    from: previous.active_member.clone(),
+                    to: aggregate.active_member.clone(),
+                });
+            }
+        }
+        self.previous = aggregates.iter().map(|aggregate| (aggregate.name.clone(), aggregate.clone())).collect();
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::tunnels::TunnelKind;
+
+    fn bond(members: &[(&str, bool)], active: Option<&str>) -> LinkAggregate {
+        LinkAggregate {
+            name: "bond0".to_string(),
+            kind: AggregateKind::Bond,
+            members: members
+                .iter()
+                .map(|(name, up)| AggregateMember { name: name.to_string(), up: *up })
+                .collect(),
+            active_member: active.map(str::to_string),
+        }
+    }
+
+    fn stats(name: &str) -> BandwidthStats {
+        BandwidthStats {
+            timestamp: chrono::Utc::now(),
+            interface_name: name.to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            packets_sent: 0,
+            download_speed_bps: 0.0,
+            upload_speed_bps: 0.0,
+            calculation_confidence: CalculationConfidence::High,
+            time_since_last_update: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_uevent_devtype() {
+        assert_eq!(parse_uevent_devtype("DEVTYPE=bond\nINTERFACE=bond0\nIFINDEX=3\n"), Some("bond"));
+        assert_eq!(parse_uevent_devtype("INTERFACE=eth0\nIFINDEX=2\n"), None);
+    }
+
+    #[test]
+    fn test_parse_ifconfig_aggregates() {
+        let output = "\
+en0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+\tether 3c:22:fb:00:00:01
+\tstatus: active
+en1: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+\tstatus: inactive
+bond0: flags=8843<UP,BROADCAST,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+\tbond interfaces: en1 en0
+\tstatus: active
+bridge0: flags=8863<UP,BROADCAST,SMART,RUNNING,SIMPLEX,MULTICAST> mtu 1500
+\tmember: en2 flags=3<LEARNING,DISCOVER>
+\t        ifmaxaddr 0 port 5 priority 0 path cost 0
+lo0: flags=8049<UP,LOOPBACK,RUNNING,MULTICAST> mtu 16384
+";
+        let aggregates = parse_ifconfig_aggregates(output);
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates[0], bond(&[("en0", true), ("en1", false)], None));
+        assert_eq!(aggregates[1].name, "bridge0");
+        assert_eq!(aggregates[1].kind, AggregateKind::Bridge);
+        // en2 has no block of its own
+        assert_eq!(aggregates[1].members, vec![AggregateMember { name: "en2".to_string(), up: false }]);
+    }
+
+    #[test]
+    fn test_group_interfaces() {
+        let collected = vec![stats("eth0"), stats("bond0"), stats("eth1"), stats("wlan0"), stats("eth2")];
+        let mut bridge = bond(&[("eth2", true)], None);
+        bridge.name = "br0".to_string();
+        let aggregates = vec![bond(&[("eth0", true), ("eth1", true), ("eth3", false)], Some("eth0")), bridge];
+
+        let groups = group_interfaces(&collected, &aggregates, &[]);
+        let names: Vec<&str> = groups.iter().map(|group| group.stats.interface_name.as_str()).collect();
+        // br0 was not collected, so its member is listed on its own
+        assert_eq!(names, ["bond0", "wlan0", "eth2"]);
+        assert_eq!(groups[0].aggregate.map(|a| a.name.as_str()), Some("bond0"));
+        let members: Vec<(&str, bool)> =
+            groups[0].members.iter().map(|(member, stats)| (member.name.as_str(), stats.is_some())).collect();
+        assert_eq!(members, [("eth0", true), ("eth1", true), ("eth3", false)]);
+        assert!(groups[1].aggregate.is_none() && groups[1].members.is_empty());
+    }
+
+    #[test]
+    fn test_total_stats_counts_aggregates_once() {
+        let traffic = |name: &str, download: f64, confidence: CalculationConfidence| BandwidthStats {
+            download_speed_bps: download,
+            upload_speed_bps: download / 10.0,
+            bytes_received: download as u64,
+            packets_received: 10,
+            calculation_confidence: confidence,
+            ..stats(name)
+        };
+        let collected = [
+            traffic("bond0", 3000.0, CalculationConfidence::High),
+            traffic("eth0", 2000.0, CalculationConfidence::High),
+            traffic("eth1", 1000.0, CalculationConfidence::High),
+            traffic("wlan0", 500.0, CalculationConfidence::Medium),
+        ];
+        let aggregates = [bond(&[("eth0", true), ("eth1", true)], Some("eth0"))];
+
+        let total = total_stats(&group_interfaces(&collected, &aggregates, &[])).unwrap();
+        assert_eq!(total.interface_name, TOTAL_INTERFACE);
+        assert_eq!(total.download_speed_bps, 3500.0);
+        assert_eq!(total.upload_speed_bps, 350.0);
+        assert_eq!(total.bytes_received, 3500);
+        assert_eq!(total.packets_received, 20);
+        assert_eq!(total.calculation_confidence, CalculationConfidence::Medium);
+        assert_eq!(total.interface_state, InterfaceState::Up);
+
+        // Without grouping every interface counts
+        let total = total_stats(&group_interfaces(&collected, &[], &[])).unwrap();
+        assert_eq!(total.download_speed_bps, 6500.0);
+        assert!(total_stats(&[]).is_none());
+    }
+
+    #[test]
+    fn test_total_stats_counts_tunnels_through_their_transport() {
+        let traffic = |name: &str, download: f64| BandwidthStats { download_speed_bps: download, ..stats(name) };
+        let collected = [traffic("bond0", 3000.0), traffic("eth0", 3000.0), traffic("wg0", 1000.0), traffic("tun0", 200.0)];
+        let aggregates = [bond(&[("eth0", true)], Some("eth0"))];
+        let tunnel = |name: &str, transport: &str| Tunnel {
+            name: name.to_string(),
+            kind: TunnelKind::WireGuard,
+            transport: Some(transport.to_string()),
+        };
+        // wg0 goes out through a bond member, tun0 through an interface that is not listed
+        let tunnels = [tunnel("wg0", "eth0"), tunnel("tun0", "wlan0")];
+
+        let groups = group_interfaces(&collected, &aggregates, &tunnels);
+        assert_eq!(groups[1].tunnel.map(|tunnel| tunnel.name.as_str()), Some("wg0"));
+        assert!(groups[1].carried_by_listed(&groups).is_some());
+        assert!(groups[2].carried_by_listed(&groups).is_none());
+        assert_eq!(total_stats(&groups).unwrap().download_speed_bps, 3200.0);
+
+        // With the transport filtered out, the tunnel is all there is of that traffic
+        let listed: Vec<InterfaceGroup> = groups.into_iter().filter(|group| group.stats.interface_name != "bond0").collect();
+        assert_eq!(total_stats(&listed).unwrap().download_speed_bps, 1200.0);
+    }
+
+    #[test]
+    fn test_failover_tracker() {
+        let mut tracker = FailoverTracker::new();
+        assert!(tracker.observe(&[bond(&[("eth0", true), ("eth1", true)], Some("eth0"))]).is_empty());
+        assert!(tracker.observe(&[bond(&[("eth0", true), ("eth1", true)], Some("eth0"))]).is_empty());
+
+        let events = tracker.observe(&[bond(&[("eth0", false), ("eth1", true)], Some("eth1"))]);
+        assert_eq!(
+            events.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["bond0 member eth0 down", "bond0 failed over eth0 → eth1"]
+        );
+
+        let events = tracker.observe(&[bond(&[("eth0", true), ("eth1", true)], Some("eth1"))]);
+        assert_eq!(
+            events,
+            [FailoverEvent::MemberUp { aggregate: "bond0".to_string(), member: "eth0".to_string() }]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Negotiated link speed and duplex of network interfaces, used to judge when a link is saturated
+//
+// - Linux: the ethtool ioctl (ETHTOOL_GSET), falling back to /sys/class/net/<interface>/speed
+//   and duplex
+// - macOS: the media line of `ifconfig <interface>`, as IOKit reports it to the driver
+// - Windows: `Get-NetAdapter` via PowerShell
+//
+// Wi-Fi, loopback and most virtual interfaces report no fixed speed and yield `None`
+
+use std::fmt;
+
+/// Whether a link carries both directions at full speed at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Full,
+    /// Both directions share the link speed, as on hubs and some mismatched links
+    Half,
+}
+
+impl fmt::Display for Duplex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Duplex::Full => write!(f, "full duplex"),
+            Duplex::Half => write!(f, "half duplex"),
+        }
+    }
+}
+
+/// Negotiated speed and duplex of a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkInfo {
+    pub speed_mbps: u64,
+    /// None when the operating system does not say
+    pub duplex: Option<Duplex>,
+}
+
+impl LinkInfo {
+    /// Throughput as a fraction of the link's capacity: the busier direction on a full (or
+    /// unknown) duplex link, both directions together on a half duplex one
+    pub fn utilisation(&self, download_bps: f64, upload_bps: f64) -> f64 {
+        let bytes_per_sec = match self.duplex {
+            Some(Duplex::Half) => download_bps + upload_bps,
+            _ => download_bps.max(upload_bps),
+        };
+        bytes_per_sec * 8.0 / (self.speed_mbps.max(1) as f64 * 1_000_000.0)
+    }
+
+    /// Speed and duplex, e.g. "1 Gbit/s full duplex"
+    pub fn describe(&self) -> String {
+        let speed = if self.speed_mbps >= 1000 && self.speed_mbps.is_multiple_of(1000) {
+            format!("{} Gbit/s", self.speed_mbps / 1000)
+        } else {
+            format!("{} Mbit/s", self.speed_mbps)
+        };
+        match self.duplex {
+            Some(duplex) => format!("{speed} {duplex}"),
+            None => speed,
+        }
+    }
+}
+
+/// Link speed of `interface` in Mbit/s, if the operating system reports one
+pub fn detect_link_speed_mbps(interface: &str) -> Option<u64> {
+    detect_link(interface).map(|link| link.speed_mbps)
+}
+
+/// Speed and duplex of `interface`, if the operating system reports a speed
+#[cfg(target_os = "linux")]
+pub fn detect_link(interface: &str) -> Option<LinkInfo> {
+    ethtool::query(interface).or_else(|| {
+        let read = |file: &str| std::fs::read_to_string(format!("/sys/class/net/{interface}/{file}")).ok();
+        // Interfaces without a carrier report -1 (or fail to read)
+        let speed = read("speed")?.trim().parse::<i64>().ok().filter(|s| *s > 0)? as u64;
+        let duplex = read("duplex").and_then(|duplex| match duplex.trim() {
+            "full" => Some(Duplex::Full),
+            "half" => Some(Duplex::Half),
+            _ => None,
+        });
+        Some(LinkInfo { speed_mbps: speed, duplex })
+    })
+}
+
+/// Speed and duplex of `interface`, if the operating system reports a speed
+#[cfg(target_os = "macos")]
+pub fn detect_link(interface: &str) -> Option<LinkInfo> {
+    let output = std::process::Command::new("ifconfig").arg(interface).output().ok()?;
+    parse_ifconfig_media(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Speed and duplex of `interface`, if the operating system reports a speed
+#[cfg(target_os = "windows")]
+pub fn detect_link(interface: &str) -> Option<LinkInfo> {
+    let script = format!(
+        "$adapter = Get-NetAdapter -Name '{}'; \"$($adapter.Speed) $($adapter.FullDuplex)\"",
+        interface.replace('\'', "''")
+    );
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .output()
+        .ok()?;
+    parse_net_adapter(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Speed and duplex of `interface`, if the operating system reports a speed
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn detect_link(_interface: &str) -> Option<LinkInfo> {
+    None
+}
+
+/// The ETHTOOL_GSET ioctl, answered by the driver without needing privileges
+#[cfg(target_os = "linux")]
+mod ethtool {
+    use super::{Duplex, LinkInfo};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const ETHTOOL_GSET: u32 = 0x0000_0001;
+    const DUPLEX_HALF: u8 = 0x00;
+    const DUPLEX_FULL: u8 = 0x01;
+    /// Speed reported while the link has no carrier
+    const SPEED_UNKNOWN: u32 = 0xffff_ffff;
+
+    /// struct ethtool_cmd from linux/ethtool.h
+    #[repr(C)]
+    #[derive(Default)]
+    struct EthtoolCmd {
+        cmd: u32,
+        supported: u32,
+        advertising: u32,
+        speed: u16,
+        duplex: u8,
+        port: u8,
+        phy_address: u8,
+        transceiver: u8,
+        autoneg: u8,
+        mdio_support: u8,
+        maxtxpkt: u32,
+        maxrxpkt: u32,
+        speed_hi: u16,
+        eth_tp_mdix: u8,
+        eth_tp_mdix_ctrl: u8,
+        lp_advertising: u32,
+        reserved: [u32; 2],
+    }
+
+    pub fn query(interface: &str) -> Option<LinkInfo> {
+        let name = interface.as_bytes();
+        if name.is_empty() || name.len() >= libc::IFNAMSIZ {
+            return None;
+        }
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return None;
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut command = EthtoolCmd { cmd: ETHTOOL_GSET, ..Default::default() };
+        let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+        for (slot, byte) in request.ifr_name.iter_mut().zip(name) {
+            *slot = *byte as libc::c_char;
+        }
+        request.ifr_ifru.ifru_data = (&mut command as *mut EthtoolCmd).cast();
+        // The request number's type differs between libc implementations
+        let result = unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCETHTOOL as _, &mut request) };
+        if result < 0 {
+            return None;
+        }
+
+        let speed = u32::from(command.speed) | (u32::from(command.speed_hi) << 16);
+        if speed == 0 || speed == SPEED_UNKNOWN || speed == u32::from(u16::MAX) {
+            return None;
+        }
+        let duplex = match command.duplex {
+            DUPLEX_FULL => Some(Duplex::Full),
+            DUPLEX_HALF => Some(Duplex::Half),
+            _ => None,
+        };
+        Some(LinkInfo { speed_mbps: u64::from(speed), duplex })
+    }
+}
+
+/// Speed and duplex from an ifconfig media line such as
+/// `media: autoselect (1000baseT <full-duplex>)`
+#[cfg(any(target_os = "macos", test))]
+fn parse_ifconfig_media(output: &str) -> Option<LinkInfo> {
+    let media = output.lines().find_map(|line| line.trim().strip_prefix("media:"))?;
+    let active = media.rsplit_once('(').map(|(_, s)| s).unwrap_or(media);
+    let (speed, options) = active.trim().split_once("base")?;
+    let speed_mbps = match speed.strip_suffix('G') {
+        Some(gigabits) => gigabits.parse::<u64>().ok().map(|g| g * 1000),
+        None => speed.parse::<u64>().ok(),
+    }?;
+    let duplex = if options.contains("full-duplex") {
+        Some(Duplex::Full)
+    } else if options.contains("half-duplex") {
+        Some(Duplex::Half)
+    } else {
+        None
+    };
+    Some(LinkInfo { speed_mbps, duplex })
+}
+
+/// Speed and duplex from `Get-NetAdapter`'s Speed (bits per second) and FullDuplex,
+/// e.g. "1000000000 True"
+#[cfg(any(target_os = "windows", test))]
+fn parse_net_adapter(output: &str) -> Option<LinkInfo> {
+    let mut fields = output.split_whitespace();
+    let bits = fields.next()?.parse::<u64>().ok()?;
+    let duplex = match fields.next() {
+        Some("True") => Some(Duplex::Full),
+        Some("False") => Some(Duplex::Half),
+        _ => None,
+    };
+    (bits >= 1_000_000).then_some(LinkInfo { speed_mbps: bits / 1_000_000, duplex })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ifconfig_media() {
+        let ethernet = "en0: flags=8863<UP,BROADCAST,RUNNING> mtu 1500\n\tether 3c:22:fb:00:00:01\n\tmedia: autoselect (1000baseT <full-duplex>)\n\tstatus: active\n";
+        assert_eq!(parse_ifconfig_media(ethernet), Some(LinkInfo { speed_mbps: 1000, duplex: Some(Duplex::Full) }));
+
+        let ten_gig = "\tmedia: autoselect (10GbaseT <full-duplex>)\n";
+        assert_eq!(parse_ifconfig_media(ten_gig).map(|link| link.speed_mbps), Some(10_000));
+
+        let hub = "\tmedia: 10baseT/UTP <half-duplex>\n";
+        assert_eq!(parse_ifconfig_media(hub), Some(LinkInfo { speed_mbps: 10, duplex: Some(Duplex::Half) }));
+
+        // Wi-Fi does not report a negotiated speed
+        let wifi = "en1: flags=8863<UP>\n\tmedia: autoselect\n\tstatus: active\n";
+        assert_eq!(parse_ifconfig_media(wifi), None);
+
+        assert_eq!(parse_net_adapter("2500000000 True\r\n"), Some(LinkInfo { speed_mbps: 2500, duplex: Some(Duplex::Full) }));
+        assert_eq!(parse_net_adapter("0 False"), None);
+    }
+
+    #[test]
+    fn test_utilisation_depends_on_duplex() {
+        let full = LinkInfo { speed_mbps: 100, duplex: Some(Duplex::Full) };
+        // 6 MB/s down and 4 MB/s up on 100 Mbit/s (12.5 MB/s)
+        assert!((full.utilisation(6_000_000.0, 4_000_000.0) - 0.48).abs() < 1e-9);
+        let half = LinkInfo { duplex: Some(Duplex::Half), ..full };
+        assert!((half.utilisation(6_000_000.0, 4_000_000.0) - 0.8).abs() < 1e-9);
+
+        assert_eq!(full.describe(), "100 Mbit/s full duplex");
+        assert_eq!(LinkInfo { speed_mbps: 10_000, duplex: None }.describe(), "10 Gbit/s");
+    }
+}
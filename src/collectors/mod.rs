@@ -1,12 +1,33 @@
 pub mod bandwidth;
 pub mod bandwidth_collector;
+pub mod capture_stats;
+#[cfg(feature = "capture")]
+pub mod capture_filter;
+pub mod collector_service;
+#[cfg(all(unix, feature = "capture"))]
+pub mod capture_helper;
+pub mod interface_selection;
+pub mod link_aggregation;
+pub mod link_speed;
+#[cfg(feature = "capture")]
 pub mod packet_collector;
+#[cfg(feature = "capture")]
+pub mod packet_ring;
+pub mod packet_sampling;
+#[cfg(feature = "capture")]
+pub mod pcap_file;
+pub mod ping_collector;
 pub mod platform;
+pub mod process_collector;
+pub mod socket_stats;
+pub mod speed_test;
+pub mod tunnels;
 
 // The new bandwidth module structure is ready to be used
 // For now, continue using the original bandwidth_collector to maintain compatibility
 // This will be switched in task 10 when the original file is replaced
 pub use bandwidth_collector::BandwidthCollector;
+#[cfg(feature = "capture")]
 pub use packet_collector::PacketCollector;
 
 // The new bandwidth module can be used like this:
@@ -1,63 +1,142 @@
-use anyhow::{Context, Result};
-use chrono::Local;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
 use log::{error, info, warn};
 use pnet::datalink::{self, Channel::Ethernet, NetworkInterface};
-use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
 use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::Ipv4Packet;
-use pnet::packet::ipv6::Ipv6Packet;
-use pnet::packet::tcp::TcpPacket;
+use pnet::packet::ipv4::{Ipv4Flags, Ipv4Packet};
+use pnet::packet::ipv6::{FragmentPacket, Ipv6Packet};
+use pnet::packet::tcp::{TcpOptionNumbers, TcpPacket};
 use pnet::packet::udp::UdpPacket;
 use pnet::packet::Packet;
+#[cfg(windows)]
+use std::collections::HashMap;
 use std::net::IpAddr;
-use std::sync::Arc;
-use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::sync::Mutex;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(unix)]
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
 use tokio::time::{interval, Duration};
 
+use crate::analyzers::entropy::sample_payload_entropy;
+use crate::analyzers::discovery::parse_announcement;
+use crate::analyzers::duplicates::packet_fingerprint;
+use crate::analyzers::fragmentation::parse_packet_too_big;
+use crate::collectors::capture_filter::CaptureFilter;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::collectors::capture_filter::{BpfInstruction, BpfLink};
+use crate::collectors::interface_selection::{InterfaceSelection, ANY_INTERFACE};
+use crate::collectors::packet_ring::{Frame, FrameMeta, PacketRing};
+use crate::collectors::platform::CaptureBackend;
 use crate::models::{
-    NetworkPacket, PacketDirection, PacketProtocol, PacketStatistics, ProtocolDistribution,
-    TransportProtocol,
+    IpFragment, IpHeader, NetworkPacket, PacketDirection, PacketProtocol, PacketSizeDistribution, PacketStatistics,
+    ProcessInfo, ProtocolDistribution, TcpSegment, TransportProtocol,
 };
 
+/// Frames the ring holds unless `with_channel_capacity` says otherwise
+const DEFAULT_RING_CAPACITY: usize = 10_000;
+
+/// How long a capture thread waits for traffic before checking whether to stop
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// High-performance packet collector for network monitoring
 /// 
-/// Uses libpnet for raw packet capture with platform-specific optimizations.
-/// Implements producer-consumer pattern with bounded channels for memory safety.
+/// One capture thread per interface copies frames into a preallocated lock-free ring;
+/// consumers decode them from the ring in place. On Linux the threads read a TPACKET_V3
+/// memory-mapped AF_PACKET ring, elsewhere (or when that is unavailable) libpnet.
 /// 
 /// # Architecture
 /// 
 /// ```text
-/// Raw Packets -> pnet capture -> Channel -> PacketCollector -> NetworkPacket
+/// Raw Frames -> capture thread -> PacketRing -> receive_packet_into -> NetworkPacket
 /// ```
 /// 
 /// # Platform Requirements
 /// 
 /// - Linux: Requires CAP_NET_RAW capability or root privileges
 /// - macOS: Requires root privileges for BPF access
-/// - Windows: Requires Administrator privileges and Npcap
+/// - Windows: Requires Administrator privileges; without Npcap, traffic is counted from
+///   kernel network events (ETW), which carry no packet contents
 /// 
 /// # Example
 /// 
-/// ```rust
-/// let mut collector = PacketCollector::new("eth0".to_string())?;
+/// ```no_run
+/// use kaipo_watcher::PacketCollector;
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let collector = PacketCollector::new("eth0".to_string())?;
 /// collector.start().await?;
 /// 
 /// while let Some(packet) = collector.receive_packet().await {
 ///     println!("Captured {} bytes", packet.size_bytes);
 /// }
+/// # Ok(())
+/// # }
 /// ```
 pub struct PacketCollector {
     /// Network interface to monitor (e.g., "eth0", "wlan0")
     interface_name: String,
-    /// Channel sender for captured packets (producer side)
-    packet_sender: Sender<NetworkPacket>,
-    /// Channel receiver for captured packets (consumer side)
-    packet_receiver: Arc<Mutex<Receiver<NetworkPacket>>>,
+    /// Frames captured but not yet received; capture threads push, consumers decode
+    ring: Arc<PacketRing>,
+    /// Name and addresses of each captured interface, by the index frames carry
+    interfaces: OnceLock<Vec<CaptureInterface>>,
     /// Shared statistics for monitoring capture performance
     stats: Arc<Mutex<PacketStatistics>>,
     /// Atomic flag to control capture loop execution
-    running: Arc<Mutex<bool>>,
+    running: Arc<AtomicBool>,
+    /// Capture filter; packets it rejects are dropped before stats and consumers, by the
+    /// kernel where the capture socket takes a BPF program
+    filter: Option<Arc<CaptureFilter>>,
+    /// Kernel interface capture threads read from
+    backend: CaptureBackend,
+    /// Leading bytes of each frame copied into `NetworkPacket::raw`; 0 copies none
+    raw_bytes: usize,
+    /// Privileged capture helper; when set, packets are read from the helper
+    /// instead of opening a raw capture channel in this process
+    #[cfg(unix)]
+    helper: Option<HelperLink>,
+    /// Packets made from kernel network events, set when capture starts without a capture driver
+    #[cfg(windows)]
+    kernel_events: OnceLock<DecodedQueue>,
+}
+
+/// An interface a capture thread reads, as the consumer needs it for decoding
+#[derive(Debug, Clone)]
+struct CaptureInterface {
+    name: String,
+    /// Non-loopback addresses, for telling inbound from outbound traffic
+    local_ips: Vec<IpAddr>,
+}
+
+/// Packets that arrive already decoded, bypassing the ring
+#[cfg(any(unix, windows))]
+struct DecodedQueue {
+    sender: Sender<NetworkPacket>,
+    receiver: tokio::sync::Mutex<Receiver<NetworkPacket>>,
+}
+
+#[cfg(any(unix, windows))]
+impl DecodedQueue {
+    fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self { sender, receiver: tokio::sync::Mutex::new(receiver) }
+    }
+}
+
+/// Connection to a capture helper, whose packets arrive already decoded
+#[cfg(unix)]
+struct HelperLink {
+    socket_path: PathBuf,
+    queue: DecodedQueue,
+}
+
+#[cfg(unix)]
+impl HelperLink {
+    fn new(socket_path: PathBuf, capacity: usize) -> Self {
+        Self { socket_path, queue: DecodedQueue::new(capacity) }
+    }
 }
 
 impl PacketCollector {
@@ -69,47 +148,96 @@ impl PacketCollector {
     /// 
     /// # Returns
     /// 
-    /// A new PacketCollector instance with bounded channel (10,000 packets)
+    /// A new PacketCollector instance with a ring of 10,000 frames
     /// and initialized statistics. The collector is created in stopped state.
     /// 
     /// # Example
     /// 
-    /// ```rust
+    /// ```no_run
+    /// # use kaipo_watcher::PacketCollector;
+    /// # fn main() -> anyhow::Result<()> {
     /// let collector = PacketCollector::new("eth0".to_string())?;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn new(interface_name: String) -> Result<Self> {
-        // Create bounded channel to prevent memory exhaustion under high traffic
-        // Buffer size of 10,000 packets provides good balance between
-        // responsiveness and memory usage
-        let (sender, receiver) = mpsc::channel(10000);
-        
         // Initialize packet statistics with zero values
         // These will be updated as packets are captured and processed
         let stats = PacketStatistics {
             total_packets: 0,
             total_bytes: 0,
+            dropped_packets: 0,
             packets_per_second: 0.0,
             bytes_per_second: 0.0,
             protocol_distribution: ProtocolDistribution::default(),
             top_connections: Vec::new(),
+            packet_sizes: PacketSizeDistribution::default(),
             start_time: Local::now(),
             end_time: Local::now(),
         };
 
         Ok(Self {
             interface_name,
-            packet_sender: sender,
-            packet_receiver: Arc::new(Mutex::new(receiver)),
+            ring: Arc::new(PacketRing::new(DEFAULT_RING_CAPACITY)),
+            interfaces: OnceLock::new(),
             stats: Arc::new(Mutex::new(stats)),
-            running: Arc::new(Mutex::new(false)),
+            running: Arc::new(AtomicBool::new(false)),
+            filter: None,
+            backend: CaptureBackend::default(),
+            raw_bytes: 0,
+            #[cfg(unix)]
+            helper: None,
+            #[cfg(windows)]
+            kernel_events: OnceLock::new(),
         })
     }
 
+    /// Replaces the packet ring with one holding at most `capacity` frames
+    /// Smaller rings drop packets sooner under load but bound memory use
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.ring = Arc::new(PacketRing::new(capacity));
+        #[cfg(unix)]
+        if let Some(helper) = self.helper.take() {
+            self.helper = Some(HelperLink::new(helper.socket_path, capacity));
+        }
+        self
+    }
+
+    /// Keeps the first `bytes` of every frame in `NetworkPacket::raw`, for `kw packets --inspect`
+    /// Packets from a capture helper or kernel network events arrive decoded and keep none
+    pub fn with_raw_bytes(mut self, bytes: usize) -> Self {
+        self.raw_bytes = bytes;
+        self
+    }
+
+    /// Only keeps packets matching `filter`
+    /// The TPACKET_V3 and pktap capture paths hand it to the kernel as a BPF program, so
+    /// rejected packets are never copied out; other paths check each decoded packet. With a
+    /// capture helper the filter is sent to the helper, so rejected packets never cross the socket
+    pub fn with_filter(mut self, filter: CaptureFilter) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Captures through `backend` where the platform and interface allow it
+    /// With a capture helper the choice is passed on to the helper
+    pub fn with_capture_backend(mut self, backend: CaptureBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Reads packets from a privileged capture helper listening on `socket_path`
+    /// instead of capturing in this process, so the caller can run unprivileged
+    #[cfg(unix)]
+    pub fn with_capture_helper(mut self, socket_path: PathBuf) -> Self {
+        self.helper = Some(HelperLink::new(socket_path, self.ring.capacity()));
+        self
+    }
+
     /// Starts packet capture on the configured interface
     /// 
-    /// This method spawns a background task that performs the actual packet capture
-    /// using libpnet. The captured packets are parsed and sent through the internal
-    /// channel for consumption by `receive_packet()`.
+    /// This method starts a capture thread per selected interface, which copies frames
+    /// into the collector's ring for `receive_packet()` to decode.
     /// 
     /// # Errors
     /// 
@@ -121,35 +249,171 @@ impl PacketCollector {
     /// 
     /// - Linux: Requires CAP_NET_RAW or root privileges
     /// - macOS: Requires root privileges for BPF device access
-    /// - Windows: Requires Administrator privileges and Npcap driver
+    /// - Windows: Requires Administrator privileges; falls back to kernel network
+    ///   events when neither Npcap nor WinPcap is installed
     pub async fn start(&self) -> Result<()> {
-        // Check if capture is already running to prevent duplicate tasks
-        let mut running = self.running.lock().await;
-        if *running {
+        // Check if capture is already running to prevent duplicate threads
+        if self.running.swap(true, Ordering::SeqCst) {
             return Ok(());
         }
-        *running = true;
-        drop(running);
 
-        // Locate the specified network interface
-        // This validates that the interface exists and is available for capture
-        let interface = self
-            .find_interface(&self.interface_name)
-            .context(format!("Failed to find network interface: {}", self.interface_name))?;
+        #[cfg(unix)]
+        if let Some(helper) = &self.helper {
+            return self.start_from_helper(helper).await;
+        }
+
+        #[cfg(windows)]
+        if !crate::collectors::platform::windows::WindowsPacketCapture::has_capture_driver() {
+            return self.start_from_kernel_events();
+        }
+
+        // Locate the selected network interfaces
+        // This validates that they exist and are available for capture
+        let interfaces = self.find_interfaces(&self.interface_name);
+        if interfaces.is_empty() {
+            bail!("Failed to find network interface: {}", self.interface_name);
+        }
+        let _ = self.interfaces.set(interfaces.iter().map(CaptureInterface::from).collect());
+
+        // One capture thread per interface, all feeding the same ring
+        for (index, interface) in interfaces.into_iter().enumerate() {
+            info!("Starting packet capture on interface: {}", interface.name);
+
+            let ring = Arc::clone(&self.ring);
+            let running = Arc::clone(&self.running);
+            let backend = self.backend;
+            let filter = self.filter.clone();
+            std::thread::Builder::new()
+                .name(format!("capture-{}", interface.name))
+                .spawn(move || Self::capture_thread(interface, index as u16, backend, filter, ring, running))?;
+        }
+
+        self.spawn_rate_updater();
+
+        Ok(())
+    }
 
-        info!("Starting packet capture on interface: {}", interface.name);
+    /// Connects to the capture helper and forwards its packets into the local channel
+    #[cfg(unix)]
+    async fn start_from_helper(&self, helper: &HelperLink) -> Result<()> {
+        use crate::collectors::capture_helper::{self, HelperMessage};
+
+        let filter_expression = self.filter.as_ref().map(|f| f.expression().to_string());
+        let mut connection = match capture_helper::connect(&helper.socket_path, &self.interface_name, filter_expression, self.backend).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                self.running.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        info!("Receiving packets from capture helper at {}", helper.socket_path.display());
 
-        // Clone shared references for use in the capture task
         let stats_clone = Arc::clone(&self.stats);
         let running_clone = Arc::clone(&self.running);
-        let sender = self.packet_sender.clone();
+        let sender = helper.queue.sender.clone();
+        let filter = self.filter.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = Self::capture_loop(interface, sender, stats_clone, running_clone).await {
-                error!("Packet capture error: {e}");
+            // Also stop once the collector (and its receiver) has been dropped
+            while running_clone.load(Ordering::Relaxed) && !sender.is_closed() {
+                // Wake up periodically so stop() is honoured even when no traffic arrives
+                let Ok(message) = tokio::time::timeout(Duration::from_millis(500), connection.next_message()).await else {
+                    continue;
+                };
+                match message {
+                    Ok(Some(HelperMessage::Packet(packet))) => {
+                        // The helper filters too; this only matters for helpers predating filters
+                        if filter.as_ref().is_some_and(|f| !f.matches(&packet)) {
+                            continue;
+                        }
+                        let mut stats_guard = stats_clone.lock().unwrap();
+                        stats_guard.total_packets += 1;
+                        stats_guard.total_bytes += packet.size_bytes;
+                        stats_guard.protocol_distribution.add_packet(&packet);
+                        stats_guard.packet_sizes.add_packet(&packet);
+                        Self::enqueue(&sender, *packet, &mut stats_guard);
+                    }
+                    Ok(Some(HelperMessage::Error(message))) => {
+                        error!("Capture helper error: {message}");
+                        break;
+                    }
+                    Ok(None) => {
+                        info!("Capture helper closed the connection");
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Failed to read from capture helper: {e}");
+                        break;
+                    }
+                }
             }
+            // Dropping the connection closes the socket, which tells the helper to exit
         });
 
+        self.spawn_rate_updater();
+
+        Ok(())
+    }
+
+    /// Turns kernel network events into packets, for Windows without a capture driver
+    /// Events cover every interface and carry only the process, size and endpoints of each
+    /// send and receive, so packets have no payload or TCP header and sizes exclude headers
+    #[cfg(windows)]
+    fn start_from_kernel_events(&self) -> Result<()> {
+        use crate::collectors::platform::etw::KernelNetworkSession;
+
+        warn!("No capture driver installed; counting traffic from kernel network events instead of packets");
+        let queue = self.kernel_events.get_or_init(|| DecodedQueue::new(self.ring.capacity()));
+
+        let stats_clone = Arc::clone(&self.stats);
+        let sender = queue.sender.clone();
+        let filter = self.filter.clone();
+        let interface_name = self.interface_name.clone();
+        // Looked up once per process id; a reused id keeps the name it was first seen with
+        let mut process_names: HashMap<u32, String> = HashMap::new();
+        let session = KernelNetworkSession::start(move |event| {
+            let mut packet = event.to_packet(&interface_name);
+            if filter.as_ref().is_some_and(|f| !f.matches(&packet)) {
+                return;
+            }
+            let name = process_names.entry(event.pid).or_insert_with(|| process_name(event.pid));
+            packet.process = Some(ProcessInfo { pid: event.pid, name: name.clone() });
+
+            let mut stats_guard = stats_clone.lock().unwrap();
+            stats_guard.total_packets += 1;
+            stats_guard.total_bytes += packet.size_bytes;
+            stats_guard.protocol_distribution.add_packet(&packet);
+            stats_guard.packet_sizes.add_packet(&packet);
+            Self::enqueue(&sender, packet, &mut stats_guard);
+        });
+        let session = match session {
+            Ok(session) => session,
+            Err(e) => {
+                self.running.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        info!("Receiving traffic from kernel network events");
+
+        // The session stops when dropped, once capture stops or the collector is gone
+        let running = Arc::clone(&self.running);
+        let sender = queue.sender.clone();
+        std::thread::Builder::new()
+            .name("etw-session".to_string())
+            .spawn(move || {
+                while running.load(Ordering::Relaxed) && !sender.is_closed() {
+                    std::thread::sleep(CAPTURE_POLL_INTERVAL);
+                }
+                drop(session);
+            })?;
+
+        self.spawn_rate_updater();
+
+        Ok(())
+    }
+
+    /// Recomputes per-second rates once a second
+    fn spawn_rate_updater(&self) {
         let stats_clone = Arc::clone(&self.stats);
         tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(1));
@@ -158,7 +422,7 @@ impl PacketCollector {
 
             loop {
                 interval.tick().await;
-                let mut stats = stats_clone.lock().await;
+                let mut stats = stats_clone.lock().unwrap();
                 let current_packets = stats.total_packets;
                 let current_bytes = stats.total_bytes;
                 
@@ -170,28 +434,122 @@ impl PacketCollector {
                 last_bytes = current_bytes;
             }
         });
-
-        Ok(())
     }
 
     #[allow(dead_code)]
     pub async fn stop(&self) -> Result<()> {
-        let mut running = self.running.lock().await;
-        *running = false;
+        self.running.store(false, Ordering::SeqCst);
         info!("Stopping packet capture");
         Ok(())
     }
 
     #[allow(dead_code)]
     pub async fn get_stats(&self) -> PacketStatistics {
-        self.stats.lock().await.clone()
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.dropped_packets += self.ring.dropped();
+        stats
+    }
+
+    /// (packets waiting to be received, ring capacity)
+    pub fn queue_depth(&self) -> (usize, usize) {
+        #[cfg(any(unix, windows))]
+        if let Some(queue) = self.decoded_queue() {
+            let capacity = queue.sender.max_capacity();
+            return (capacity - queue.sender.capacity(), capacity);
+        }
+        (self.ring.len(), self.ring.capacity())
+    }
+
+    /// Whether packets come from kernel network events rather than a capture driver
+    pub fn uses_kernel_events(&self) -> bool {
+        #[cfg(windows)]
+        {
+            self.kernel_events.get().is_some()
+        }
+        #[cfg(not(windows))]
+        {
+            false
+        }
+    }
+
+    /// Where packets arrive already decoded, from a capture helper or kernel network events
+    #[cfg(any(unix, windows))]
+    fn decoded_queue(&self) -> Option<&DecodedQueue> {
+        #[cfg(unix)]
+        {
+            self.helper.as_ref().map(|helper| &helper.queue)
+        }
+        #[cfg(windows)]
+        {
+            self.kernel_events.get()
+        }
     }
 
     pub async fn receive_packet(&self) -> Option<NetworkPacket> {
-        self.packet_receiver.lock().await.recv().await
+        let mut packet = Self::empty_packet();
+        self.receive_packet_into(&mut packet).await.then_some(packet)
+    }
+
+    /// Waits for the next captured packet and decodes it into `packet`, reusing its buffers
+    /// Consumers that handle one packet at a time avoid an allocation per packet this way
+    pub async fn receive_packet_into(&self, packet: &mut NetworkPacket) -> bool {
+        #[cfg(any(unix, windows))]
+        if let Some(queue) = self.decoded_queue() {
+            let Some(received) = queue.receiver.lock().await.recv().await else {
+                return false;
+            };
+            *packet = received;
+            return true;
+        }
+
+        loop {
+            if self.next_from_ring(packet) {
+                return true;
+            }
+            self.ring.readable().await;
+        }
+    }
+
+    /// Returns the next captured packet if one is already queued, without waiting
+    /// Used by polling consumers such as the live dashboard render loop
+    pub async fn try_receive_packet(&self) -> Option<NetworkPacket> {
+        #[cfg(any(unix, windows))]
+        if let Some(queue) = self.decoded_queue() {
+            return queue.receiver.lock().await.try_recv().ok();
+        }
+
+        let mut packet = Self::empty_packet();
+        self.next_from_ring(&mut packet).then_some(packet)
+    }
+
+    fn empty_packet() -> NetworkPacket {
+        NetworkPacket::new(String::new(), 0, PacketProtocol::Ethernet, PacketDirection::Local)
+    }
+
+    /// Decodes the oldest queued frame the filter accepts into `packet` and counts it;
+    /// false once the ring is empty
+    fn next_from_ring(&self, packet: &mut NetworkPacket) -> bool {
+        let interfaces = self.interfaces.get().map(Vec::as_slice).unwrap_or_default();
+        while let Some(filtered) = self.ring.pop_with(|frame| {
+            Self::decode_frame(frame, interfaces, self.raw_bytes, packet);
+            frame.meta.filtered
+        }) {
+            // Frames the kernel filtered need no second look
+            if !filtered && self.filter.as_ref().is_some_and(|f| !f.matches(packet)) {
+                continue;
+            }
+            let mut stats = self.stats.lock().unwrap();
+            stats.total_packets += 1;
+            stats.total_bytes += packet.size_bytes;
+            stats.protocol_distribution.add_packet(packet);
+            stats.packet_sizes.add_packet(packet);
+            return true;
+        }
+        false
     }
 
-    fn find_interface(&self, name: &str) -> Option<NetworkInterface> {
+    /// Interfaces matching `name`, an `InterfaceSelection` such as "eth0" or "en*,wg0"
+    fn find_interfaces(&self, name: &str) -> Vec<NetworkInterface> {
         let interfaces = datalink::interfaces();
         
         // Debug: log all available interfaces
@@ -201,13 +559,15 @@ impl PacketCollector {
                 iface.name, iface.is_up(), iface.is_loopback(), iface.ips.len());
         }
         
-        // If specific interface requested, find it
-        if name != "any" {
-            let found = interfaces.into_iter().find(|iface| iface.name == name);
-            if found.is_some() {
-                info!("Found requested interface: {}", name);
-            } else {
+        // If specific interfaces were requested, find them
+        if let Some(selection) = InterfaceSelection::parse(name).filter(|selection| !selection.is_any()) {
+            let found: Vec<NetworkInterface> =
+                interfaces.into_iter().filter(|iface| selection.matches(&iface.name)).collect();
+            if found.is_empty() {
                 error!("Interface {} not found", name);
+            } else {
+                let names: Vec<&str> = found.iter().map(|iface| iface.name.as_str()).collect();
+                info!("Found requested interface(s) for {}: {}", name, names.join(", "));
             }
             return found;
         }
@@ -218,22 +578,218 @@ impl PacketCollector {
         });
         
         if let Some(ref iface) = found {
-            info!("Selected interface for 'any': {}", iface.name);
+            info!("Selected interface for '{ANY_INTERFACE}': {}", iface.name);
         } else {
-            error!("No suitable interface found for 'any'");
+            error!("No suitable interface found for '{ANY_INTERFACE}'");
         }
         
-        found
+        found.into_iter().collect()
     }
 
-    async fn capture_loop(
+    /// Body of a capture thread: AF_XDP when asked for and possible, then the TPACKET_V3 ring
+    /// where the kernel offers one, libpnet otherwise
+    fn capture_thread(
         interface: NetworkInterface,
-        sender: Sender<NetworkPacket>,
-        stats: Arc<Mutex<PacketStatistics>>,
-        running: Arc<Mutex<bool>>,
-    ) -> Result<()> {
+        index: u16,
+        backend: CaptureBackend,
+        filter: Option<Arc<CaptureFilter>>,
+        ring: Arc<PacketRing>,
+        running: Arc<AtomicBool>,
+    ) {
+        #[cfg(target_os = "linux")]
+        {
+            use crate::collectors::platform::af_packet::{self, AfPacketRing};
+
+            if backend == CaptureBackend::AfXdp && Self::af_xdp_capture(&interface, index, &ring, &running) {
+                return;
+            }
+
+            let program = Self::bpf_program(filter.as_deref(), BpfLink::Ethernet);
+            match AfPacketRing::open(interface.index, af_packet::blocks_for(ring.capacity()), program.as_deref()) {
+                Ok(socket) => {
+                    info!("Capturing on {} through a TPACKET_V3 ring", interface.name);
+                    if let Err(e) = Self::af_packet_loop(socket, index, &ring, &running) {
+                        error!("Packet capture error: {e}");
+                    }
+                    return;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    error!(
+                        "Packet capture error: Permission denied. Packet capture requires elevated privileges (sudo/administrator)"
+                    );
+                    return;
+                }
+                Err(e) => warn!("TPACKET_V3 ring unavailable on {} ({e}); reading packets one at a time", interface.name),
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            use crate::collectors::platform::pktap::PktapCapture;
+
+            // pktap only mirrors Ethernet-type interfaces, which have a hardware address
+            if !interface.is_loopback() && interface.mac.is_some_and(|mac| !mac.is_zero()) {
+                let program = Self::bpf_program(filter.as_deref(), BpfLink::Pktap);
+                match PktapCapture::open(&interface.name, program.as_deref()) {
+                    Ok(capture) => {
+                        info!("Capturing on {} through pktap, with each packet's process", interface.name);
+                        if let Err(e) = Self::pktap_loop(capture, index, &ring, &running) {
+                            error!("Packet capture error: {e}");
+                        }
+                        return;
+                    }
+                    Err(e) => warn!("pktap unavailable on {} ({e}); capturing without process information", interface.name),
+                }
+            }
+        }
+
+        if let Err(e) = Self::capture_loop(interface, index, &ring, &running) {
+            error!("Packet capture error: {e}");
+        }
+    }
+
+    /// `filter` as a BPF program for the capture socket; none without a filter, or when the
+    /// program would be too long and packets are checked after decoding instead
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn bpf_program(filter: Option<&CaptureFilter>, link: BpfLink) -> Option<Vec<BpfInstruction>> {
+        let filter = filter?;
+        let program = filter.to_bpf(link);
+        if program.is_none() {
+            warn!("Capture filter '{filter}' is too long for a kernel program; filtering in kw instead");
+        }
+        program
+    }
+
+    /// Captures on every receive queue of `interface` through AF_XDP, one thread per queue;
+    /// false when AF_XDP cannot be used there and the caller should fall back
+    #[cfg(target_os = "linux")]
+    fn af_xdp_capture(interface: &NetworkInterface, index: u16, ring: &Arc<PacketRing>, running: &Arc<AtomicBool>) -> bool {
+        use crate::collectors::platform::af_xdp;
+
+        // Frames taken by AF_XDP skip the host's stack, which would cut off an interface in use;
+        // IPv6 link-local addresses come with every link and say nothing about that
+        let in_use = interface.ips.iter().any(|network| match network.ip() {
+            IpAddr::V6(address) => !address.is_unicast_link_local(),
+            IpAddr::V4(_) => true,
+        });
+        if in_use {
+            warn!(
+                "Not using AF_XDP on {}: it has addresses and its traffic would no longer reach this host; using AF_PACKET",
+                interface.name
+            );
+            return false;
+        }
+        let mut sockets = match af_xdp::open_interface(&interface.name, interface.index) {
+            Ok(sockets) => sockets,
+            Err(e) => {
+                warn!("AF_XDP unavailable on {} ({e}); using AF_PACKET", interface.name);
+                return false;
+            }
+        };
+
+        // This thread takes the first queue, and each further queue gets a thread of its own
+        let first = sockets.remove(0);
+        for socket in sockets {
+            let ring = Arc::clone(ring);
+            let running = Arc::clone(running);
+            let spawned = std::thread::Builder::new()
+                .name(format!("capture-{}-q{}", interface.name, socket.queue))
+                .spawn(move || {
+                    if let Err(e) = Self::af_xdp_loop(socket, index, &ring, &running) {
+                        error!("Packet capture error: {e}");
+                    }
+                });
+            if let Err(e) = spawned {
+                error!("Failed to start capture thread for {}: {e}", interface.name);
+            }
+        }
+        if let Err(e) = Self::af_xdp_loop(first, index, ring, running) {
+            error!("Packet capture error: {e}");
+        }
+        true
+    }
+
+    /// Moves frames from one AF_XDP socket into the ring
+    #[cfg(target_os = "linux")]
+    fn af_xdp_loop(
+        mut socket: crate::collectors::platform::af_xdp::XdpSocket,
+        index: u16,
+        ring: &PacketRing,
+        running: &AtomicBool,
+    ) -> std::io::Result<()> {
+        while running.load(Ordering::Relaxed) {
+            let frames = socket.receive(CAPTURE_POLL_INTERVAL, |frame| {
+                let meta = FrameMeta { wire_len: frame.data.len() as u32, interface: index, timestamp_ns: frame.timestamp_ns, process: None, filtered: false };
+                ring.try_push(frame.data, meta);
+            })?;
+            if frames > 0 {
+                ring.wake();
+            }
+        }
+        // The socket's counters run from when it opened, so they are read once at the end
+        ring.record_drops(socket.drops()?);
+        Ok(())
+    }
+
+    /// Moves frames from the kernel's ring into ours a block at a time
+    #[cfg(target_os = "linux")]
+    fn af_packet_loop(
+        mut socket: crate::collectors::platform::af_packet::AfPacketRing,
+        index: u16,
+        ring: &PacketRing,
+        running: &AtomicBool,
+    ) -> std::io::Result<()> {
+        let filtered = socket.filtered();
+        while running.load(Ordering::Relaxed) {
+            let frames = socket.next_block(CAPTURE_POLL_INTERVAL, |frame| {
+                let meta = FrameMeta { wire_len: frame.wire_len, interface: index, timestamp_ns: frame.timestamp_ns, process: None, filtered };
+                ring.try_push(frame.data, meta);
+            })?;
+            if frames > 0 {
+                ring.wake();
+            } else {
+                // Quiet moment: collect what the kernel dropped while busy
+                ring.record_drops(socket.take_drops()?);
+            }
+        }
+        ring.record_drops(socket.take_drops()?);
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn pktap_loop(
+        mut capture: crate::collectors::platform::pktap::PktapCapture,
+        index: u16,
+        ring: &PacketRing,
+        running: &AtomicBool,
+    ) -> std::io::Result<()> {
+        let filtered = capture.filtered();
+        while running.load(Ordering::Relaxed) {
+            let frames = capture.next_batch(CAPTURE_POLL_INTERVAL, |record| {
+                let meta = FrameMeta {
+                    wire_len: record.wire_len,
+                    interface: index,
+                    timestamp_ns: record.timestamp_ns,
+                    process: record.frame.process,
+                    filtered,
+                };
+                ring.try_push(record.frame.data, meta);
+            })?;
+            if frames > 0 {
+                ring.wake();
+            }
+        }
+        Ok(())
+    }
+
+    fn capture_loop(interface: NetworkInterface, index: u16, ring: &PacketRing, running: &AtomicBool) -> Result<()> {
         info!("Creating datalink channel for interface: {}", interface.name);
-        let (_, mut rx) = match datalink::channel(&interface, Default::default()) {
+        // A read timeout keeps the thread from blocking forever on quiet links, so stop() takes effect
+        let config = datalink::Config {
+            read_timeout: Some(CAPTURE_POLL_INTERVAL),
+            ..Default::default()
+        };
+        let (_, mut rx) = match datalink::channel(&interface, config) {
             Ok(Ethernet(tx, rx)) => {
                 info!("Successfully created Ethernet channel");
                 (tx, rx)
@@ -258,60 +814,25 @@ impl PacketCollector {
             }
         };
 
-        let interface_name = interface.name.clone();
-        let local_ips: Vec<IpAddr> = interface
-            .ips
-            .iter()
-            .filter_map(|ip| match ip.ip() {
-                IpAddr::V4(addr) if !addr.is_loopback() => Some(IpAddr::V4(addr)),
-                IpAddr::V6(addr) if !addr.is_loopback() => Some(IpAddr::V6(addr)),
-                _ => None,
-            })
-            .collect();
-
-        info!("Capturing packets on {interface_name} with IPs: {local_ips:?}");
-
-        let mut packet_count = 0;
-        while *running.lock().await {
+        info!("Capturing packets on {}", interface.name);
+
+        while running.load(Ordering::Relaxed) {
             match rx.next() {
-                Ok(packet) => {
-                    packet_count += 1;
-                    if packet_count % 100 == 0 {
-                        info!("Processed {} raw packets", packet_count);
-                    }
-                    
-                    if let Some(ethernet) = EthernetPacket::new(packet) {
-                        if let Some(network_packet) = Self::process_ethernet_packet(
-                            &ethernet,
-                            &interface_name,
-                            &local_ips,
-                        ) {
-                            info!("Captured packet: {} bytes, protocol: {:?}", 
-                                network_packet.size_bytes, network_packet.transport_protocol);
-                            
-                            let mut stats_guard = stats.lock().await;
-                            stats_guard.total_packets += 1;
-                            stats_guard.total_bytes += network_packet.size_bytes;
-                            stats_guard.protocol_distribution.add_packet(&network_packet);
-                            drop(stats_guard);
-
-                            if let Err(e) = sender.send(network_packet).await {
-                                warn!("Failed to send packet to receiver: {e}");
-                            }
-                        } else {
-                            if packet_count <= 10 {
-                                info!("Skipped packet (no network layer or filtered)");
-                            }
-                        }
-                    } else {
-                        if packet_count <= 10 {
-                            info!("Failed to parse Ethernet packet");
-                        }
-                    }
+                Ok(frame) => {
+                    let meta = FrameMeta {
+                        wire_len: frame.len() as u32,
+                        interface: index,
+                        timestamp_ns: Local::now().timestamp_nanos_opt().unwrap_or_default(),
+                        process: None,
+                        filtered: false,
+                    };
+                    ring.try_push(frame, meta);
+                    ring.wake();
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
                 Err(e) => {
                     error!("Error receiving packet: {e}");
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    std::thread::sleep(CAPTURE_POLL_INTERVAL);
                 }
             }
         }
@@ -319,21 +840,56 @@ impl PacketCollector {
         Ok(())
     }
 
-    fn process_ethernet_packet(
-        ethernet: &EthernetPacket,
-        interface_name: &str,
-        local_ips: &[IpAddr],
-    ) -> Option<NetworkPacket> {
-        let mut packet = NetworkPacket::new(
-            interface_name.to_string(),
-            ethernet.packet().len() as u64,
+    /// Queues a decoded packet for the consumer without blocking the helper connection or event thread
+    /// A full queue drops the packet and counts it, rather than stalling until the source drops silently
+    #[cfg(any(unix, windows))]
+    fn enqueue(sender: &Sender<NetworkPacket>, packet: NetworkPacket, stats: &mut PacketStatistics) {
+        match sender.try_send(packet) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => stats.dropped_packets += 1,
+            Err(TrySendError::Closed(_)) => warn!("Failed to send packet to receiver: channel closed"),
+        }
+    }
+
+    /// Fills `packet` from a frame in the ring, which starts at the Ethernet header, keeping its
+    /// first `raw_bytes` bytes
+    fn decode_frame(frame: Frame<'_>, interfaces: &[CaptureInterface], raw_bytes: usize, packet: &mut NetworkPacket) {
+        let (name, local_ips) = interfaces
+            .get(frame.meta.interface as usize)
+            .map_or(("", &[][..]), |interface| (interface.name.as_str(), interface.local_ips.as_slice()));
+        // Reused for the frame's process, so tagged frames do not allocate a name each
+        let process = packet.process.take();
+        packet.reset(
+            DateTime::from_timestamp_nanos(frame.meta.timestamp_ns).with_timezone(&Local),
+            name,
+            frame.meta.wire_len.into(),
             PacketProtocol::Ethernet,
             PacketDirection::Local,
         );
+        packet.raw.extend_from_slice(&frame.data[..frame.data.len().min(raw_bytes)]);
+        if let Some(frame_process) = frame.meta.process {
+            let mut process = process.unwrap_or(ProcessInfo { pid: 0, name: String::new() });
+            process.pid = frame_process.pid;
+            process.name.clear();
+            process.name.push_str(frame_process.name());
+            packet.process = Some(process);
+        }
+        if let Some(ethernet) = EthernetPacket::new(frame.data) {
+            Self::process_network_layer(packet, ethernet.get_ethertype(), ethernet.payload(), local_ips);
+        }
+    }
 
-        match ethernet.get_ethertype() {
+    /// Fills in addresses, ports and direction from a network-layer payload
+    /// Shared with capture sources that are not Ethernet framed, such as pcap files
+    pub(crate) fn process_network_layer(
+        packet: &mut NetworkPacket,
+        ethertype: EtherType,
+        payload: &[u8],
+        local_ips: &[IpAddr],
+    ) {
+        match ethertype {
             EtherTypes::Ipv4 => {
-                if let Some(ipv4) = Ipv4Packet::new(ethernet.payload()) {
+                if let Some(ipv4) = Ipv4Packet::new(payload) {
                     packet.protocol = PacketProtocol::IPv4;
                     packet.source_addr = Some(IpAddr::V4(ipv4.get_source()));
                     packet.dest_addr = Some(IpAddr::V4(ipv4.get_destination()));
@@ -343,24 +899,50 @@ impl PacketCollector {
                         packet.dest_addr.unwrap(),
                         local_ips,
                     );
+                    let fragment_offset = ipv4.get_fragment_offset() * 8;
+                    let more_fragments = ipv4.get_flags() & Ipv4Flags::MoreFragments != 0;
+                    packet.ip = Some(IpHeader {
+                        ttl: ipv4.get_ttl(),
+                        fingerprint: packet_fingerprint(
+                            packet.source_addr.unwrap(),
+                            packet.dest_addr.unwrap(),
+                            ipv4.get_identification(),
+                            ipv4.payload(),
+                        ),
+                        fragment: (fragment_offset > 0 || more_fragments).then_some(IpFragment {
+                            offset: fragment_offset,
+                            more: more_fragments,
+                            length: ipv4.get_total_length(),
+                        }),
+                    });
+                    // Only the first fragment starts with the transport header
+                    let transport = if fragment_offset == 0 { ipv4.payload() } else { &[] };
 
                     match ipv4.get_next_level_protocol() {
                         IpNextHeaderProtocols::Tcp => {
                             packet.transport_protocol = TransportProtocol::Tcp;
-                            if let Some(tcp) = TcpPacket::new(ipv4.payload()) {
+                            if let Some(tcp) = TcpPacket::new(transport) {
                                 packet.source_port = Some(tcp.get_source());
                                 packet.dest_port = Some(tcp.get_destination());
+                                packet.payload_entropy = sample_payload_entropy(tcp.payload());
+                                let declared_len = (ipv4.get_total_length() as usize)
+                                    .saturating_sub(ipv4.get_header_length() as usize * 4);
+                                packet.tcp = Some(Self::tcp_segment(&tcp, declared_len));
                             }
                         }
                         IpNextHeaderProtocols::Udp => {
                             packet.transport_protocol = TransportProtocol::Udp;
-                            if let Some(udp) = UdpPacket::new(ipv4.payload()) {
+                            if let Some(udp) = UdpPacket::new(transport) {
                                 packet.source_port = Some(udp.get_source());
                                 packet.dest_port = Some(udp.get_destination());
+                                packet.payload_entropy = sample_payload_entropy(udp.payload());
+                                packet.announcement = parse_announcement(udp.get_source(), udp.get_destination(), udp.payload());
                             }
                         }
                         IpNextHeaderProtocols::Icmp => {
                             packet.transport_protocol = TransportProtocol::Icmp;
+                            packet.icmp_type = transport.first().copied();
+                            packet.too_big = parse_packet_too_big(transport, false);
                         }
                         _ => {
                             packet.transport_protocol = TransportProtocol::Other(ipv4.get_next_level_protocol().0);
@@ -369,7 +951,7 @@ impl PacketCollector {
                 }
             }
             EtherTypes::Ipv6 => {
-                if let Some(ipv6) = Ipv6Packet::new(ethernet.payload()) {
+                if let Some(ipv6) = Ipv6Packet::new(payload) {
                     packet.protocol = PacketProtocol::IPv6;
                     packet.source_addr = Some(IpAddr::V6(ipv6.get_source()));
                     packet.dest_addr = Some(IpAddr::V6(ipv6.get_destination()));
@@ -379,27 +961,58 @@ impl PacketCollector {
                         packet.dest_addr.unwrap(),
                         local_ips,
                     );
+                    // A fragment header, when present, sits between the IPv6 header and the transport
+                    let fragment = FragmentPacket::new(ipv6.payload())
+                        .filter(|_| ipv6.get_next_header() == IpNextHeaderProtocols::Ipv6Frag);
+                    let (next_header, transport, declared_len) = match &fragment {
+                        Some(fragment) => (
+                            fragment.get_next_header(),
+                            if fragment.get_fragment_offset_with_flags() & 0xfff8 == 0 { &ipv6.payload()[8..] } else { &[][..] },
+                            (ipv6.get_payload_length() as usize).saturating_sub(8),
+                        ),
+                        None => (ipv6.get_next_header(), ipv6.payload(), ipv6.get_payload_length() as usize),
+                    };
+                    packet.ip = Some(IpHeader {
+                        ttl: ipv6.get_hop_limit(),
+                        fingerprint: packet_fingerprint(
+                            packet.source_addr.unwrap(),
+                            packet.dest_addr.unwrap(),
+                            0,
+                            ipv6.payload(),
+                        ),
+                        fragment: fragment.map(|fragment| IpFragment {
+                            offset: fragment.get_fragment_offset_with_flags() & 0xfff8,
+                            more: !fragment.is_last_fragment(),
+                            length: ipv6.get_payload_length().saturating_add(40),
+                        }),
+                    });
 
-                    match ipv6.get_next_header() {
+                    match next_header {
                         IpNextHeaderProtocols::Tcp => {
                             packet.transport_protocol = TransportProtocol::Tcp;
-                            if let Some(tcp) = TcpPacket::new(ipv6.payload()) {
+                            if let Some(tcp) = TcpPacket::new(transport) {
                                 packet.source_port = Some(tcp.get_source());
                                 packet.dest_port = Some(tcp.get_destination());
+                                packet.payload_entropy = sample_payload_entropy(tcp.payload());
+                                packet.tcp = Some(Self::tcp_segment(&tcp, declared_len));
                             }
                         }
                         IpNextHeaderProtocols::Udp => {
                             packet.transport_protocol = TransportProtocol::Udp;
-                            if let Some(udp) = UdpPacket::new(ipv6.payload()) {
+                            if let Some(udp) = UdpPacket::new(transport) {
                                 packet.source_port = Some(udp.get_source());
                                 packet.dest_port = Some(udp.get_destination());
+                                packet.payload_entropy = sample_payload_entropy(udp.payload());
+                                packet.announcement = parse_announcement(udp.get_source(), udp.get_destination(), udp.payload());
                             }
                         }
                         IpNextHeaderProtocols::Icmpv6 => {
                             packet.transport_protocol = TransportProtocol::ICMPv6;
+                            packet.icmp_type = transport.first().copied();
+                            packet.too_big = parse_packet_too_big(transport, true);
                         }
                         _ => {
-                            packet.transport_protocol = TransportProtocol::Other(ipv6.get_next_header().0);
+                            packet.transport_protocol = TransportProtocol::Other(next_header.0);
                         }
                     }
                 }
@@ -408,11 +1021,31 @@ impl PacketCollector {
                 packet.protocol = PacketProtocol::Arp;
             }
             _ => {
-                packet.protocol = PacketProtocol::Other(ethernet.get_ethertype().0);
+                packet.protocol = PacketProtocol::Other(ethertype.0);
             }
         }
+    }
 
-        Some(packet)
+    /// TCP fields of a segment; `declared_len` is the TCP length given by the IP header, which
+    /// stays right when the capture buffer cut the packet short (large loopback or offloaded segments)
+    fn tcp_segment(tcp: &TcpPacket, declared_len: usize) -> TcpSegment {
+        let header_len = tcp.get_data_offset() as usize * 4;
+        TcpSegment {
+            flags: tcp.get_flags(),
+            sequence: tcp.get_sequence(),
+            acknowledgement: tcp.get_acknowledgement(),
+            window: tcp.get_window(),
+            payload_len: declared_len.saturating_sub(header_len).max(tcp.payload().len()) as u32,
+            timestamps: tcp
+                .get_options_iter()
+                .find(|option| option.get_number() == TcpOptionNumbers::TIMESTAMPS)
+                .and_then(|option| {
+                    let data = option.payload();
+                    let tsval = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?);
+                    let tsecr = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?);
+                    Some((tsval, tsecr))
+                }),
+        }
     }
 
     fn determine_direction(
@@ -432,6 +1065,40 @@ impl PacketCollector {
     }
 }
 
+/// Name of process `pid` from sysinfo, or the id itself once the process is gone
+#[cfg(windows)]
+fn process_name(pid: u32) -> String {
+    use sysinfo::{Pid, ProcessesToUpdate, System};
+
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[Pid::from_u32(pid)]), true);
+    system
+        .process(Pid::from_u32(pid))
+        .map(|p| p.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| pid.to_string())
+}
+
+impl From<&NetworkInterface> for CaptureInterface {
+    fn from(interface: &NetworkInterface) -> Self {
+        Self {
+            name: interface.name.clone(),
+            local_ips: interface
+                .ips
+                .iter()
+                .map(|ip| ip.ip())
+                .filter(|ip| !ip.is_loopback())
+                .collect(),
+        }
+    }
+}
+
+impl Drop for PacketCollector {
+    /// Capture threads stop with the collector, which is the only one reading their frames
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
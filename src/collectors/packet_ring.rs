@@ -0,0 +1,331 @@
+// Lock-free ring of captured frames between the capture threads and the packet consumer
+// Capture threads copy each frame's headers and the start of its payload into a preallocated
+// slot and the consumer decodes straight from the slot, so the hot path neither allocates nor locks
+//
+// Each slot carries a sequence number saying whose turn it is (Vyukov's bounded queue): a
+// producer may fill slot `pos % capacity` once its sequence equals `pos`, the consumer may read
+// it once the sequence is `pos + 1`, and reading hands it back with `pos + capacity`
+
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+use crate::analyzers::entropy::MAX_SAMPLE_BYTES;
+
+/// Bytes kept of each frame: room for Ethernet, VLAN, IPv6 and TCP headers with options, plus
+/// the payload sample scored for entropy; sizes beyond this come from the wire length
+pub const FRAME_BYTES: usize = 128 + MAX_SAMPLE_BYTES;
+
+/// Where and when a frame was captured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameMeta {
+    /// Length of the frame on the wire, which may exceed the bytes kept
+    pub wire_len: u32,
+    /// Index of the capturing interface in the collector's interface table
+    pub interface: u16,
+    /// Capture time in nanoseconds since the Unix epoch
+    pub timestamp_ns: i64,
+    /// Process that sent or received the frame, where the capture path reports it
+    pub process: Option<FrameProcess>,
+    /// Whether the kernel already ran the capture filter on the frame
+    pub filtered: bool,
+}
+
+/// Owning process of a frame as the kernel reports it, kept inline so pushing allocates nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameProcess {
+    pub pid: u32,
+    /// Command name, NUL-padded to the kernel's MAXCOMLEN + 1
+    pub name: [u8; 17],
+}
+
+impl FrameProcess {
+    /// The command name up to its first NUL
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|b| *b == 0).unwrap_or(self.name.len());
+        std::str::from_utf8(&self.name[..len]).unwrap_or_default()
+    }
+}
+
+/// A queued frame, borrowed from its slot while the consumer reads it
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    pub meta: FrameMeta,
+    /// The first `FRAME_BYTES` of the frame at most
+    pub data: &'a [u8],
+}
+
+struct FrameBuf {
+    meta: FrameMeta,
+    len: u16,
+    data: [u8; FRAME_BYTES],
+}
+
+/// One frame's worth of storage; cache-line aligned so neighbouring slots filled by
+/// different threads do not share a line
+#[repr(align(64))]
+struct Slot {
+    sequence: AtomicUsize,
+    frame: UnsafeCell<FrameBuf>,
+}
+
+/// Keeps a hot counter on its own cache line
+#[repr(align(64))]
+struct CacheAligned<T>(T);
+
+/// Bounded multi-producer ring of captured frames
+///
+/// Any number of capture threads push with `try_push`; a full ring drops the frame and counts
+/// it rather than blocking capture. Consumers pop with `pop_with` and wait with `readable`.
+pub struct PacketRing {
+    slots: Box<[Slot]>,
+    /// Position the next frame is written to
+    tail: CacheAligned<AtomicUsize>,
+    /// Position the next frame is read from
+    head: CacheAligned<AtomicUsize>,
+    dropped: AtomicU64,
+    /// Set by a consumer about to sleep; the next `wake` notifies it
+    waiting: AtomicBool,
+    notify: Notify,
+}
+
+// Slots are only touched by the thread whose turn the sequence number says it is
+unsafe impl Sync for PacketRing {}
+unsafe impl Send for PacketRing {}
+
+impl PacketRing {
+    /// A ring holding up to `capacity` frames, all allocated up front
+    pub fn new(capacity: usize) -> Self {
+        let slots = (0..capacity.max(1))
+            .map(|position| Slot {
+                sequence: AtomicUsize::new(position),
+                frame: UnsafeCell::new(FrameBuf {
+                    meta: FrameMeta::default(),
+                    len: 0,
+                    data: [0; FRAME_BYTES],
+                }),
+            })
+            .collect();
+        Self {
+            slots,
+            tail: CacheAligned(AtomicUsize::new(0)),
+            head: CacheAligned(AtomicUsize::new(0)),
+            dropped: AtomicU64::new(0),
+            waiting: AtomicBool::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Frames queued, including any a producer is still copying in
+    pub fn len(&self) -> usize {
+        let head = self.head.0.load(Ordering::SeqCst);
+        self.tail.0.load(Ordering::SeqCst).saturating_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Frames dropped because the ring was full, plus any reported with `record_drops`
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Counts frames lost before reaching the ring, such as those the kernel dropped
+    pub fn record_drops(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Copies the first `FRAME_BYTES` of `data` into the next free slot; false, counting a
+    /// drop, when the ring is full. Call `wake` after a batch so a waiting consumer sees it
+    pub fn try_push(&self, data: &[u8], meta: FrameMeta) -> bool {
+        let mut position = self.tail.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % self.slots.len()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position) as isize {
+                0 => match self.tail.0.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let kept = data.len().min(FRAME_BYTES);
+                        // Winning the exchange gives this producer the slot until it publishes
+                        let frame = unsafe { &mut *slot.frame.get() };
+                        frame.meta = meta;
+                        frame.len = kept as u16;
+                        frame.data[..kept].copy_from_slice(&data[..kept]);
+                        slot.sequence.store(position + 1, Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => position = current,
+                },
+                // The slot still holds a frame from one lap ago
+                lag if lag < 0 => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                // Another producer took this position; catch up
+                _ => position = self.tail.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Hands the oldest frame to `read` without copying it out; `None` when the ring is empty
+    pub fn pop_with<R>(&self, read: impl FnOnce(Frame<'_>) -> R) -> Option<R> {
+        let mut position = self.head.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % self.slots.len()];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position + 1) as isize {
+                0 => match self.head.0.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Returns the slot to producers even if `read` panics
+                        let _release = Release { slot, sequence: position + self.slots.len() };
+                        let frame = unsafe { &*slot.frame.get() };
+                        return Some(read(Frame {
+                            meta: frame.meta,
+                            data: &frame.data[..frame.len as usize],
+                        }));
+                    }
+                    Err(current) => position = current,
+                },
+                // Nothing published at this position yet
+                lag if lag < 0 => return None,
+                _ => position = self.head.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Wakes a consumer sleeping in `readable`; producers call it once per batch pushed
+    pub fn wake(&self) {
+        if self.waiting.swap(false, Ordering::SeqCst) {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Waits until a frame is queued
+    pub async fn readable(&self) {
+        while self.is_empty() {
+            let notified = self.notify.notified();
+            self.waiting.store(true, Ordering::SeqCst);
+            // A push between the first check and raising the flag would not wake us
+            if !self.is_empty() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Gives a slot back to producers once its frame has been read
+struct Release<'a> {
+    slot: &'a Slot,
+    sequence: usize,
+}
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        self.slot.sequence.store(self.sequence, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn meta(n: u32) -> FrameMeta {
+        FrameMeta { wire_len: n, interface: 0, timestamp_ns: n as i64, process: None, filtered: false }
+    }
+
+    #[test]
+    fn test_frames_come_out_in_order_and_wrap_around() {
+        let ring = PacketRing::new(3);
+        for lap in 0..4u32 {
+            for n in 0..3 {
+                assert!(ring.try_push(&[(lap * 3 + n) as u8; 10], meta(lap * 3 + n)));
+            }
+            assert!(!ring.try_push(&[0; 10], meta(99)), "full ring drops");
+            assert_eq!(ring.len(), 3);
+            for n in 0..3 {
+                let (wire_len, first) = ring.pop_with(|frame| (frame.meta.wire_len, frame.data[0])).unwrap();
+                assert_eq!(wire_len, lap * 3 + n);
+                assert_eq!(first, (lap * 3 + n) as u8);
+            }
+            assert!(ring.pop_with(|_| ()).is_none());
+        }
+        assert_eq!(ring.dropped(), 4);
+    }
+
+    #[test]
+    fn test_long_frames_are_cut_to_the_slot_size() {
+        let ring = PacketRing::new(1);
+        assert!(ring.try_push(&[7; 9000], meta(9000)));
+        let (wire_len, kept) = ring.pop_with(|frame| (frame.meta.wire_len, frame.data.len())).unwrap();
+        assert_eq!((wire_len, kept), (9000, FRAME_BYTES));
+    }
+
+    #[test]
+    fn test_concurrent_producers_lose_nothing_that_fits() {
+        const PRODUCERS: u32 = 4;
+        const FRAMES: u32 = 20_000;
+        let ring = Arc::new(PacketRing::new(256));
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let ring = Arc::clone(&ring);
+                std::thread::spawn(move || {
+                    for n in 0..FRAMES {
+                        let meta = FrameMeta { wire_len: n, interface: producer as u16, timestamp_ns: 0, process: None, filtered: false };
+                        while !ring.try_push(&n.to_be_bytes(), meta) {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Each producer's frames arrive in the order it pushed them
+        let mut next = [0u32; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * FRAMES {
+            let popped = ring.pop_with(|frame| {
+                let producer = frame.meta.interface as usize;
+                assert_eq!(frame.data, next[producer].to_be_bytes());
+                next[producer] += 1;
+            });
+            if popped.is_some() {
+                received += 1;
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_readable_wakes_on_push() {
+        let ring = Arc::new(PacketRing::new(8));
+        let producer = Arc::clone(&ring);
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            producer.try_push(&[1, 2, 3], meta(3));
+            producer.wake();
+        });
+        tokio::time::timeout(std::time::Duration::from_secs(5), ring.readable()).await.unwrap();
+        assert_eq!(ring.pop_with(|frame| frame.data.to_vec()), Some(vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,214 @@
+// Packet sampling for capture under load
+// Above a packets-per-second threshold only one packet in N is analyzed and stored, and each
+// of those stands for N; below it every packet is. Rates are measured on packet timestamps,
+// so a capture file is sampled the same way as the live traffic it was taken from
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Packets per second above which sampling starts, unless configured otherwise
+pub const DEFAULT_SAMPLE_THRESHOLD_PPS: u64 = 50_000;
+
+/// Keep one packet in `N`, written `1/N`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SampleRate(u32);
+
+impl SampleRate {
+    /// One packet kept in `one_in`; at least 1, which keeps them all
+    pub fn one_in(one_in: u32) -> Self {
+        Self(one_in.max(1))
+    }
+
+    /// How many packets each kept packet stands for
+    pub fn weight(self) -> u64 {
+        self.0.into()
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = anyhow::Error;
+
+    /// Accepts `1/N` or a bare `N`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let denominator = match s.split_once('/') {
+            Some((numerator, denominator)) => {
+                if numerator.trim() != "1" {
+                    bail!("Sample rate '{s}' must be written 1/N");
+                }
+                denominator
+            }
+            None => s,
+        };
+        let one_in: u32 = denominator
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid sample rate '{s}', expected 1/N such as 1/100"))?;
+        if one_in == 0 {
+            bail!("Sample rate '{s}' keeps no packets");
+        }
+        Ok(Self(one_in))
+    }
+}
+
+impl TryFrom<String> for SampleRate {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<SampleRate> for String {
+    fn from(rate: SampleRate) -> Self {
+        rate.to_string()
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1/{}", self.0)
+    }
+}
+
+/// Decides packet by packet whether to analyze it, by the rate of the last second
+#[derive(Debug, Clone)]
+pub struct PacketSampler {
+    rate: SampleRate,
+    threshold_pps: u64,
+    /// Start of the current one-second window
+    window_start: Option<DateTime<Local>>,
+    window_packets: u64,
+    previous_window_packets: u64,
+    /// Packets seen while sampling, to pick every Nth
+    sampling_count: u64,
+    kept: u64,
+    seen: u64,
+}
+
+impl PacketSampler {
+    pub fn new(rate: SampleRate, threshold_pps: u64) -> Self {
+        Self {
+            rate,
+            threshold_pps,
+            window_start: None,
+            window_packets: 0,
+            previous_window_packets: 0,
+            sampling_count: 0,
+            kept: 0,
+            seen: 0,
+        }
+    }
+
+    pub fn rate(&self) -> SampleRate {
+        self.rate
+    }
+
+    pub fn threshold_pps(&self) -> u64 {
+        self.threshold_pps
+    }
+
+    /// Whether the packets of the last second went over the threshold
+    pub fn is_sampling(&self) -> bool {
+        self.window_packets > self.threshold_pps || self.previous_window_packets > self.threshold_pps
+    }
+
+    /// Packets passed to `weigh` and how many of them were kept
+    pub fn counts(&self) -> (u64, u64) {
+        (self.seen, self.kept)
+    }
+
+    /// How many packets the packet captured at `timestamp` stands for, or `None` when it
+    /// is skipped; always 1 while the rate is under the threshold
+    pub fn weigh(&mut self, timestamp: DateTime<Local>) -> Option<u64> {
+        self.seen += 1;
+        match self.window_start {
+            Some(start) if (timestamp - start).num_milliseconds() < 1000 => {}
+            Some(start) if (timestamp - start).num_milliseconds() < 2000 => {
+                self.previous_window_packets = self.window_packets;
+                self.window_packets = 0;
+                self.window_start = Some(start + chrono::Duration::seconds(1));
+            }
+            // A quiet gap longer than a second, or the first packet
+            _ => {
+                self.previous_window_packets = 0;
+                self.window_packets = 0;
+                self.window_start = Some(timestamp);
+            }
+        }
+        self.window_packets += 1;
+
+        if !self.is_sampling() {
+            self.sampling_count = 0;
+            self.kept += 1;
+            return Some(1);
+        }
+        self.sampling_count += 1;
+        if (self.sampling_count - 1).is_multiple_of(self.rate.weight()) {
+            self.kept += 1;
+            Some(self.rate.weight())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_sample_rate_parsing() {
+        assert_eq!("1/100".parse::<SampleRate>().unwrap(), SampleRate::one_in(100));
+        assert_eq!(" 1 / 10 ".parse::<SampleRate>().unwrap(), SampleRate::one_in(10));
+        assert_eq!("50".parse::<SampleRate>().unwrap(), SampleRate::one_in(50));
+        assert_eq!(SampleRate::one_in(100).to_string(), "1/100");
+        assert!("2/100".parse::<SampleRate>().is_err());
+        assert!("1/0".parse::<SampleRate>().is_err());
+        assert!("1/x".parse::<SampleRate>().is_err());
+    }
+
+    #[test]
+    fn test_every_packet_is_kept_under_the_threshold() {
+        let mut sampler = PacketSampler::new(SampleRate::one_in(10), 100);
+        let start = Local::now();
+        for n in 0..100 {
+            assert_eq!(sampler.weigh(start + Duration::milliseconds(n * 10)), Some(1));
+        }
+        assert!(!sampler.is_sampling());
+        assert_eq!(sampler.counts(), (100, 100));
+    }
+
+    #[test]
+    fn test_one_in_n_is_kept_over_the_threshold_and_weighted() {
+        let mut sampler = PacketSampler::new(SampleRate::one_in(10), 100);
+        let start = Local::now();
+        // 1,000 packets in one second: the first 100 are kept whole, the rest sampled
+        let weights: Vec<_> = (0..1000).map(|n| sampler.weigh(start + Duration::microseconds(n * 900))).collect();
+        assert!(sampler.is_sampling());
+        assert!(weights[..100].iter().all(|w| *w == Some(1)));
+        let estimated: u64 = weights.iter().flatten().sum();
+        assert_eq!(estimated, 1000);
+        assert_eq!(sampler.counts(), (1000, 190));
+    }
+
+    #[test]
+    fn test_sampling_stops_once_traffic_calms_down() {
+        let mut sampler = PacketSampler::new(SampleRate::one_in(10), 100);
+        let start = Local::now();
+        for n in 0..500 {
+            sampler.weigh(start + Duration::milliseconds(n));
+        }
+        assert!(sampler.is_sampling());
+        // Still sampling through the next second, as the last one was busy
+        sampler.weigh(start + Duration::milliseconds(1500));
+        assert!(sampler.is_sampling());
+        assert_eq!(sampler.weigh(start + Duration::milliseconds(2500)), Some(1));
+        assert!(!sampler.is_sampling());
+    }
+}
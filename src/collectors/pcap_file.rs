@@ -0,0 +1,269 @@
+// Offline packet source: reads classic libpcap capture files (tcpdump, Wireshark "pcap")
+// Packets are decoded with the same network-layer parser as live capture, so an
+// imported capture flows through the analyzer and storage exactly like live traffic
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use pnet::packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+use crate::collectors::PacketCollector;
+use crate::models::packet::is_private;
+use crate::models::{NetworkPacket, PacketDirection, PacketProtocol};
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const MAGIC_PCAPNG: u32 = 0x0a0d_0d0a;
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+/// Largest record accepted; anything bigger means a corrupt file
+const MAX_RECORD_LEN: usize = 256 * 1024;
+
+/// Link-layer framing of the packets in a capture file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    /// LINKTYPE_ETHERNET
+    Ethernet,
+    /// LINKTYPE_LINUX_SLL, produced by `tcpdump -i any`
+    LinuxSll,
+    /// LINKTYPE_RAW and the BSD DLT_RAW value: bare IPv4/IPv6 packets
+    RawIp,
+}
+
+impl LinkType {
+    fn from_header(value: u32) -> Result<Self> {
+        match value {
+            1 => Ok(Self::Ethernet),
+            113 => Ok(Self::LinuxSll),
+            12 | 101 => Ok(Self::RawIp),
+            other => bail!("Unsupported pcap link type {other} (supported: Ethernet, Linux cooked, raw IP)"),
+        }
+    }
+}
+
+/// Reads packets from a classic libpcap capture file
+pub struct PcapFileReader<R: Read> {
+    reader: R,
+    swapped: bool,
+    nanos: bool,
+    link_type: LinkType,
+    interface: String,
+}
+
+impl PcapFileReader<BufReader<File>> {
+    /// Opens `path`; packets are attributed to `interface`
+    pub fn open<P: AsRef<Path>>(path: P, interface: String) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::new(BufReader::new(file), interface)
+            .with_context(|| format!("Failed to read pcap file {}", path.display()))
+    }
+}
+
+impl<R: Read> PcapFileReader<R> {
+    pub fn new(mut reader: R, interface: String) -> Result<Self> {
+        let mut header = [0u8; GLOBAL_HEADER_LEN];
+        reader.read_exact(&mut header).context("File is too short to be a pcap capture")?;
+
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let (swapped, nanos) = match magic {
+            MAGIC_MICROS => (false, false),
+            MAGIC_NANOS => (false, true),
+            m if m.swap_bytes() == MAGIC_MICROS => (true, false),
+            m if m.swap_bytes() == MAGIC_NANOS => (true, true),
+            MAGIC_PCAPNG => bail!("pcapng files are not supported; convert with `editcap -F pcap in.pcapng out.pcap`"),
+            _ => bail!("Not a pcap file (magic number {magic:#010x})"),
+        };
+
+        let mut reader = Self {
+            reader,
+            swapped,
+            nanos,
+            link_type: LinkType::Ethernet,
+            interface,
+        };
+        reader.link_type = LinkType::from_header(reader.u32_at(&header, 20))?;
+        Ok(reader)
+    }
+
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    /// Next decoded packet, `None` at end of file
+    pub fn next_packet(&mut self) -> Result<Option<NetworkPacket>> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read pcap record"),
+        }
+
+        let seconds = self.u32_at(&header, 0);
+        let fraction = self.u32_at(&header, 4);
+        let captured_len = self.u32_at(&header, 8) as usize;
+        let original_len = self.u32_at(&header, 12);
+        if captured_len > MAX_RECORD_LEN {
+            bail!("Corrupt pcap record ({captured_len} bytes)");
+        }
+
+        let mut data = vec![0u8; captured_len];
+        self.reader.read_exact(&mut data).context("Truncated pcap record")?;
+
+        let nanos = if self.nanos { fraction } else { fraction.saturating_mul(1000) };
+        let mut packet = self.decode(&data);
+        // Snapped captures only keep the start of each packet; count the real size
+        packet.size_bytes = u64::from(original_len).max(captured_len as u64);
+        if let Some(timestamp) = Local.timestamp_opt(i64::from(seconds), nanos).single() {
+            packet.timestamp = timestamp;
+        }
+        infer_direction(&mut packet);
+        Ok(Some(packet))
+    }
+
+    fn decode(&self, data: &[u8]) -> NetworkPacket {
+        let mut packet = NetworkPacket::new(
+            self.interface.clone(),
+            data.len() as u64,
+            PacketProtocol::Ethernet,
+            PacketDirection::Local,
+        );
+
+        match self.link_type {
+            LinkType::Ethernet => {
+                if let Some(ethernet) = EthernetPacket::new(data) {
+                    use pnet::packet::Packet;
+                    PacketCollector::process_network_layer(&mut packet, ethernet.get_ethertype(), ethernet.payload(), &[]);
+                }
+            }
+            LinkType::LinuxSll => {
+                // 16-byte cooked header; the protocol field holds the EtherType
+                if data.len() >= 16 {
+                    let ethertype = EtherType(u16::from_be_bytes([data[14], data[15]]));
+                    PacketCollector::process_network_layer(&mut packet, ethertype, &data[16..], &[]);
+                }
+            }
+            LinkType::RawIp => {
+                let ethertype = match data.first().map(|b| b >> 4) {
+                    Some(4) => Some(EtherTypes::Ipv4),
+                    Some(6) => Some(EtherTypes::Ipv6),
+                    _ => None,
+                };
+                if let Some(ethertype) = ethertype {
+                    PacketCollector::process_network_layer(&mut packet, ethertype, data, &[]);
+                }
+            }
+        }
+
+        packet
+    }
+
+    fn u32_at(&self, bytes: &[u8], offset: usize) -> u32 {
+        let value = u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        if self.swapped { value.swap_bytes() } else { value }
+    }
+}
+
+impl<R: Read> Iterator for PcapFileReader<R> {
+    type Item = Result<NetworkPacket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+/// Direction for packets captured on another machine, whose addresses are unknown here:
+/// traffic between a private and a public address is treated as leaving/entering the private side
+fn infer_direction(packet: &mut NetworkPacket) {
+    let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) else {
+        return;
+    };
+    packet.direction = match (is_private(src), is_private(dst)) {
+        (true, false) => PacketDirection::Outbound,
+        (false, true) => PacketDirection::Inbound,
+        _ => PacketDirection::Local,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TransportProtocol;
+    use std::io::Cursor;
+
+    /// Ethernet + IPv4 + UDP frame from 192.168.1.10:5000 to 8.8.8.8:53
+    fn udp_frame() -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]); // EtherType IPv4
+        let mut ip = vec![0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 192, 168, 1, 10, 8, 8, 8, 8];
+        ip.extend_from_slice(&[0x13, 0x88, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00]);
+        frame.extend_from_slice(&ip);
+        frame
+    }
+
+    fn capture(swapped: bool, link_type: u32, records: &[(u32, &[u8])]) -> Vec<u8> {
+        let word = |v: u32| if swapped { v.to_be_bytes() } else { v.to_le_bytes() };
+        let half = |v: u16| if swapped { v.to_be_bytes() } else { v.to_le_bytes() };
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&word(MAGIC_MICROS));
+        file.extend_from_slice(&half(2));
+        file.extend_from_slice(&half(4));
+        file.extend_from_slice(&word(0));
+        file.extend_from_slice(&word(0));
+        file.extend_from_slice(&word(65535));
+        file.extend_from_slice(&word(link_type));
+        for (seconds, data) in records {
+            file.extend_from_slice(&word(*seconds));
+            file.extend_from_slice(&word(250_000));
+            file.extend_from_slice(&word(data.len() as u32));
+            file.extend_from_slice(&word(data.len() as u32));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    #[test]
+    fn test_reads_ethernet_records() {
+        let frame = udp_frame();
+        let bytes = capture(false, 1, &[(1_700_000_000, &frame), (1_700_000_001, &frame)]);
+        let packets: Vec<_> = PcapFileReader::new(Cursor::new(bytes), "pcap:test".to_string())
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(packets.len(), 2);
+        let packet = &packets[0];
+        assert_eq!(packet.interface, "pcap:test");
+        assert_eq!(packet.transport_protocol, TransportProtocol::Udp);
+        assert_eq!(packet.dest_port, Some(53));
+        assert_eq!(packet.direction, PacketDirection::Outbound);
+        assert_eq!(packet.timestamp.timestamp(), 1_700_000_000);
+        assert_eq!(packet.timestamp.timestamp_subsec_micros(), 250_000);
+    }
+
+    #[test]
+    fn test_reads_big_endian_raw_ip() {
+        let frame = udp_frame();
+        let bytes = capture(true, 101, &[(1_700_000_000, &frame[14..])]);
+        let mut reader = PcapFileReader::new(Cursor::new(bytes), "pcap".to_string()).unwrap();
+
+        assert_eq!(reader.link_type(), LinkType::RawIp);
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.source_addr, Some("192.168.1.10".parse().unwrap()));
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_other_formats() {
+        let mut pcapng = vec![0x0a, 0x0d, 0x0d, 0x0a];
+        pcapng.resize(GLOBAL_HEADER_LEN, 0);
+        let error = PcapFileReader::new(Cursor::new(pcapng), "pcap".to_string()).err().unwrap();
+        assert!(error.to_string().contains("pcapng"));
+
+        assert!(PcapFileReader::new(Cursor::new(vec![0u8; GLOBAL_HEADER_LEN]), "pcap".to_string()).is_err());
+        assert!(PcapFileReader::new(Cursor::new(capture(false, 105, &[])), "pcap".to_string()).is_err());
+    }
+}
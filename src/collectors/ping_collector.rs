@@ -0,0 +1,514 @@
+// PingCollector: Continuous ICMP echo probes of a few targets
+// Runs the system `ping` program, which may send ICMP without root on every platform,
+// and keeps the recent round-trip times, jitter and loss of each target together with
+// per-minute summaries for storage
+//
+// The target "gateway" stands for the default gateway, found once when probing starts:
+// - Linux: /proc/net/route
+// - macOS: `route -n get default`
+// - Windows: `Get-NetRoute` via PowerShell
+
+use chrono::{DateTime, Duration as ChronoDuration, Local, Timelike};
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::config::PingConfig;
+
+/// Target name replaced by the default gateway's address
+pub const GATEWAY_TARGET: &str = "gateway";
+
+/// Probes per target the current connectivity status is judged over
+const RECENT_PROBES: usize = 30;
+
+/// Finished minutes held between `take_samples` calls; older ones are dropped beyond this
+const MAX_BUFFERED_SAMPLES: usize = 10_000;
+
+/// Probe results of one target over one minute (or, merged, longer)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PingSample {
+    /// Start of the minute
+    pub timestamp: DateTime<Local>,
+    /// Target as configured, e.g. "gateway" or "1.1.1.1"
+    pub target: String,
+    /// Address the probes were sent to
+    pub address: String,
+    pub sent: u64,
+    pub received: u64,
+    /// Mean round-trip time of the answered probes
+    pub latency_ms: Option<f64>,
+    /// Mean difference between consecutive round-trip times
+    pub jitter_ms: Option<f64>,
+}
+
+impl PingSample {
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            (self.sent - self.received) as f64 / self.sent as f64 * 100.0
+        }
+    }
+
+    /// Adds the probes of `other`, keeping this sample's time and target
+    /// Latency is weighted by answered probes, jitter by the probes it was measured over
+    pub fn merge(&mut self, other: &PingSample) {
+        let weighted = |a: Option<f64>, wa: u64, b: Option<f64>, wb: u64| match (a, b) {
+            (Some(a), Some(b)) if wa + wb > 0 => Some((a * wa as f64 + b * wb as f64) / (wa + wb) as f64),
+            (a, b) => a.or(b),
+        };
+        self.latency_ms = weighted(self.latency_ms, self.received, other.latency_ms, other.received);
+        self.jitter_ms = weighted(
+            self.jitter_ms,
+            self.received.saturating_sub(1),
+            other.jitter_ms,
+            other.received.saturating_sub(1),
+        );
+        self.sent += other.sent;
+        self.received += other.received;
+    }
+}
+
+/// Current state of one target over its most recent probes
+#[derive(Debug, Clone, PartialEq)]
+pub struct PingStatus {
+    pub target: String,
+    pub address: String,
+    pub probes: usize,
+    pub latency_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub loss_percent: f64,
+}
+
+impl PingStatus {
+    pub fn quality(&self) -> ConnectivityQuality {
+        let latency = self.latency_ms.unwrap_or(0.0);
+        let jitter = self.jitter_ms.unwrap_or(0.0);
+        if self.loss_percent >= 100.0 {
+            ConnectivityQuality::Down
+        } else if self.loss_percent >= 5.0 || latency >= 150.0 || jitter >= 30.0 {
+            ConnectivityQuality::Poor
+        } else if self.loss_percent > 0.0 || latency >= 60.0 || jitter >= 10.0 {
+            ConnectivityQuality::Fair
+        } else {
+            ConnectivityQuality::Good
+        }
+    }
+}
+
+/// Overall judgement of the probed targets, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConnectivityQuality {
+    Good,
+    /// Some loss, or latency or jitter noticeable in calls and games
+    Fair,
+    /// Loss of 5% or more, or latency or jitter that disrupts interactive use
+    Poor,
+    /// No answers at all
+    Down,
+}
+
+impl ConnectivityQuality {
+    /// The worst quality among the targets, None before any probe
+    pub fn overall(status: &[PingStatus]) -> Option<Self> {
+        status.iter().filter(|s| s.probes > 0).map(PingStatus::quality).max()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectivityQuality::Good => "Good",
+            ConnectivityQuality::Fair => "Fair",
+            ConnectivityQuality::Poor => "Poor",
+            ConnectivityQuality::Down => "Down",
+        }
+    }
+}
+
+/// The minute being filled for one target
+#[derive(Debug)]
+struct OpenMinute {
+    sample: PingSample,
+    rtt_sum: f64,
+    jitter_sum: f64,
+    last_rtt: Option<f64>,
+}
+
+impl OpenMinute {
+    fn finish(mut self) -> PingSample {
+        let received = self.sample.received;
+        self.sample.latency_ms = (received > 0).then(|| self.rtt_sum / received as f64);
+        self.sample.jitter_ms = (received > 1).then(|| self.jitter_sum / (received - 1) as f64);
+        self.sample
+    }
+}
+
+/// Recent probes and the open minute of one target
+#[derive(Debug)]
+struct TargetProbes {
+    target: String,
+    address: String,
+    /// Round-trip times of the most recent probes, None for lost ones
+    recent: VecDeque<Option<f64>>,
+    minute: Option<OpenMinute>,
+}
+
+impl TargetProbes {
+    fn new(target: &str, address: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            address: address.to_string(),
+            recent: VecDeque::with_capacity(RECENT_PROBES),
+            minute: None,
+        }
+    }
+
+    /// Counts a probe sent at `at`; returns the previous minute once a new one starts
+    fn record(&mut self, at: DateTime<Local>, rtt_ms: Option<f64>) -> Option<PingSample> {
+        if self.recent.len() >= RECENT_PROBES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(rtt_ms);
+
+        let start = start_of_minute(at);
+        let finished = match self.minute.take() {
+            Some(open) if start > open.sample.timestamp => Some(open.finish()),
+            open => {
+                self.minute = open;
+                None
+            }
+        };
+
+        let minute = self.minute.get_or_insert_with(|| OpenMinute {
+            sample: PingSample {
+                timestamp: start,
+                target: self.target.clone(),
+                address: self.address.clone(),
+                sent: 0,
+                received: 0,
+                latency_ms: None,
+                jitter_ms: None,
+            },
+            rtt_sum: 0.0,
+            jitter_sum: 0.0,
+            last_rtt: None,
+        });
+        minute.sample.sent += 1;
+        if let Some(rtt) = rtt_ms {
+            minute.sample.received += 1;
+            minute.rtt_sum += rtt;
+            if let Some(last) = minute.last_rtt {
+                minute.jitter_sum += (rtt - last).abs();
+            }
+            minute.last_rtt = Some(rtt);
+        }
+        finished
+    }
+
+    fn status(&self) -> PingStatus {
+        let answered: Vec<f64> = self.recent.iter().flatten().copied().collect();
+        let latency_ms = (!answered.is_empty()).then(|| answered.iter().sum::<f64>() / answered.len() as f64);
+        let jitter_ms = (answered.len() > 1).then(|| {
+            answered.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f64>() / (answered.len() - 1) as f64
+        });
+        let lost = self.recent.len() - answered.len();
+        PingStatus {
+            target: self.target.clone(),
+            address: self.address.clone(),
+            probes: self.recent.len(),
+            latency_ms,
+            jitter_ms,
+            loss_percent: if self.recent.is_empty() { 0.0 } else { lost as f64 / self.recent.len() as f64 * 100.0 },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PingState {
+    targets: Vec<TargetProbes>,
+    finished: Vec<PingSample>,
+}
+
+impl PingState {
+    fn record(&mut self, index: usize, at: DateTime<Local>, rtt_ms: Option<f64>) {
+        if let Some(sample) = self.targets[index].record(at, rtt_ms) {
+            if self.finished.len() >= MAX_BUFFERED_SAMPLES {
+                self.finished.remove(0);
+            }
+            self.finished.push(sample);
+        }
+    }
+}
+
+/// Pings the configured targets in the background
+///
+/// Every `interval_secs` each target gets one echo request; a probe not answered within
+/// `timeout_ms` counts as lost.
+pub struct PingCollector {
+    config: PingConfig,
+    state: Arc<Mutex<PingState>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl PingCollector {
+    pub fn new(config: PingConfig) -> Self {
+        Self {
+            config,
+            state: Arc::new(Mutex::new(PingState::default())),
+            task: None,
+        }
+    }
+
+    /// Resolves the targets and starts probing; targets that cannot be resolved are skipped
+    pub fn start(&mut self) {
+        if self.task.is_some() {
+            return;
+        }
+        let mut targets = Vec::new();
+        for target in &self.config.targets {
+            match resolve_target(target) {
+                Some(address) => targets.push(TargetProbes::new(target, &address)),
+                None => warn!("Ping target {target} could not be resolved and is skipped"),
+            }
+        }
+        if targets.is_empty() {
+            return;
+        }
+        let addresses: Vec<String> = targets.iter().map(|t| t.address.clone()).collect();
+        self.state.lock().unwrap().targets = targets;
+
+        let state = Arc::clone(&self.state);
+        let interval = Duration::from_secs(self.config.interval_secs.max(1));
+        let timeout = Duration::from_millis(self.config.timeout_ms.max(1));
+        debug!("Pinging {} every {}s", addresses.join(", "), interval.as_secs());
+        self.task = Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut warned = false;
+            loop {
+                ticker.tick().await;
+                let sent_at = Local::now();
+                let mut probes = tokio::task::JoinSet::new();
+                for (index, address) in addresses.iter().enumerate() {
+                    let address = address.clone();
+                    probes.spawn(async move { (index, ping_once(&address, timeout).await) });
+                }
+                while let Some(Ok((index, result))) = probes.join_next().await {
+                    match result {
+                        Ok(rtt_ms) => state.lock().unwrap().record(index, sent_at, rtt_ms),
+                        // Not a lost probe: nothing was sent
+                        Err(e) if !warned => {
+                            warn!("Failed to run ping: {e}");
+                            warned = true;
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+        }));
+    }
+
+    /// Stops probing; buffered minutes stay available to `drain`
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+
+    /// Recent latency, jitter and loss per target
+    pub fn status(&self) -> Vec<PingStatus> {
+        self.state.lock().unwrap().targets.iter().map(TargetProbes::status).collect()
+    }
+
+    /// Minutes finished since the last call
+    pub fn take_samples(&self) -> Vec<PingSample> {
+        std::mem::take(&mut self.state.lock().unwrap().finished)
+    }
+
+    /// Every minute not yet handed out, the current ones included; used when monitoring ends
+    pub fn drain(&self) -> Vec<PingSample> {
+        let mut state = self.state.lock().unwrap();
+        let open: Vec<PingSample> = state
+            .targets
+            .iter_mut()
+            .filter_map(|target| target.minute.take().map(OpenMinute::finish))
+            .collect();
+        state.finished.extend(open);
+        std::mem::take(&mut state.finished)
+    }
+}
+
+impl Drop for PingCollector {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Samples merged into one per target, in the order targets first appear
+pub fn per_target(samples: &[PingSample]) -> Vec<PingSample> {
+    let mut targets: Vec<PingSample> = Vec::new();
+    for sample in samples {
+        match targets.iter_mut().find(|t| t.target == sample.target) {
+            Some(total) => total.merge(sample),
+            None => targets.push(sample.clone()),
+        }
+    }
+    targets
+}
+
+/// Address to probe for a configured target
+fn resolve_target(target: &str) -> Option<String> {
+    if target.eq_ignore_ascii_case(GATEWAY_TARGET) {
+        return default_gateway().map(|ip| ip.to_string());
+    }
+    // Host names are left to `ping` to resolve
+    Some(target.to_string())
+}
+
+/// Sends one echo request with the system `ping`; the round-trip time in ms, None when lost
+async fn ping_once(address: &str, timeout: Duration) -> std::io::Result<Option<f64>> {
+    let is_ipv6 = address.parse::<IpAddr>().is_ok_and(|ip| ip.is_ipv6());
+    let mut command;
+    if cfg!(target_os = "windows") {
+        command = tokio::process::Command::new("ping");
+        command.args(["-n", "1", "-w", &timeout.as_millis().to_string()]);
+    } else if cfg!(target_os = "macos") {
+        // macOS has a separate ping6 whose timeout option differs; waiting is bounded below
+        command = tokio::process::Command::new(if is_ipv6 { "ping6" } else { "ping" });
+        command.args(["-c", "1"]);
+        if !is_ipv6 {
+            command.args(["-W", &timeout.as_millis().to_string()]);
+        }
+    } else {
+        command = tokio::process::Command::new("ping");
+        command.args(["-c", "1", "-W", &timeout.as_secs().max(1).to_string()]);
+    }
+    command.arg(address).kill_on_drop(true);
+
+    let Ok(output) = tokio::time::timeout(timeout + Duration::from_secs(1), command.output()).await else {
+        return Ok(None);
+    };
+    let rtt_ms = parse_ping_time(&String::from_utf8_lossy(&output?.stdout));
+    Ok(rtt_ms.filter(|rtt| *rtt <= timeout.as_secs_f64() * 1000.0))
+}
+
+/// Round-trip time from ping output, e.g. `time=12.3 ms` (Unix) or `time=12ms` / `time<1ms` (Windows)
+/// Windows prints a localised word for "time" outside English, which is not understood
+fn parse_ping_time(output: &str) -> Option<f64> {
+    output.lines().find_map(|line| {
+        let (_, rest) = line.split_once("time=").or_else(|| line.split_once("time<"))?;
+        let number: String = rest.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+        number.parse().ok()
+    })
+}
+
+/// The default IPv4 gateway, if the operating system reports one
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Option<IpAddr> {
+    parse_proc_net_route(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+/// The default IPv4 gateway, if the operating system reports one
+#[cfg(target_os = "macos")]
+pub fn default_gateway() -> Option<IpAddr> {
+    let output = std::process::Command::new("route").args(["-n", "get", "default"]).output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gateway:"))
+        .and_then(|gateway| gateway.trim().parse().ok())
+}
+
+/// The default IPv4 gateway, if the operating system reports one
+#[cfg(target_os = "windows")]
+pub fn default_gateway() -> Option<IpAddr> {
+    let script = "(Get-NetRoute -DestinationPrefix '0.0.0.0/0' | Sort-Object RouteMetric | Select-Object -First 1).NextHop";
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", script])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// The default IPv4 gateway, if the operating system reports one
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+pub fn default_gateway() -> Option<IpAddr> {
+    None
+}
+
+/// Gateway of the default route in /proc/net/route, stored as little-endian hex
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_net_route(content: &str) -> Option<IpAddr> {
+    content.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        (gateway != 0).then(|| IpAddr::from(gateway.to_le_bytes()))
+    })
+}
+
+fn start_of_minute(at: DateTime<Local>) -> DateTime<Local> {
+    at - ChronoDuration::seconds(at.second() as i64) - ChronoDuration::nanoseconds(at.nanosecond() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minute: u32, second: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 1, 10, minute, second).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ping_output() {
+        let linux = "PING 1.1.1.1 (1.1.1.1) 56(84) bytes of data.\n64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=12.3 ms\n";
+        assert_eq!(parse_ping_time(linux), Some(12.3));
+        let windows = "Reply from 192.168.1.1: bytes=32 time<1ms TTL=64\n";
+        assert_eq!(parse_ping_time(windows), Some(1.0));
+        assert_eq!(parse_ping_time("Request timed out.\n"), None);
+
+        let route = "Iface\tDestination\tGateway\tFlags\nwlan0\t0001A8C0\t00000000\t0001\nwlan0\t00000000\t0101A8C0\t0003\n";
+        assert_eq!(parse_proc_net_route(route), Some("192.168.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_minutes_and_status() {
+        let mut probes = TargetProbes::new("gateway", "192.168.1.1");
+        assert!(probes.record(at(0, 0), Some(10.0)).is_none());
+        assert!(probes.record(at(0, 20), Some(14.0)).is_none());
+        assert!(probes.record(at(0, 40), None).is_none());
+
+        let minute = probes.record(at(1, 0), Some(12.0)).unwrap();
+        assert_eq!((minute.sent, minute.received), (3, 2));
+        assert_eq!(minute.latency_ms, Some(12.0));
+        assert_eq!(minute.jitter_ms, Some(4.0));
+        assert!((minute.loss_percent() - 100.0 / 3.0).abs() < 1e-9);
+
+        let status = probes.status();
+        assert_eq!(status.probes, 4);
+        assert_eq!(status.loss_percent, 25.0);
+        assert_eq!(status.quality(), ConnectivityQuality::Poor);
+    }
+
+    #[test]
+    fn test_per_target_merge() {
+        let sample = |minute: u32, target: &str, received: u64, latency: f64| PingSample {
+            timestamp: at(minute, 0),
+            target: target.to_string(),
+            address: target.to_string(),
+            sent: 30,
+            received,
+            latency_ms: Some(latency),
+            jitter_ms: None,
+        };
+        let merged = per_target(&[sample(0, "1.1.1.1", 30, 10.0), sample(0, "gateway", 30, 1.0), sample(1, "1.1.1.1", 10, 30.0)]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].sent, merged[0].received), (60, 40));
+        assert_eq!(merged[0].latency_ms, Some(15.0));
+        assert_eq!(ConnectivityQuality::overall(&[]), None);
+    }
+}
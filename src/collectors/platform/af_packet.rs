@@ -0,0 +1,264 @@
+// AF_PACKET capture through a TPACKET_V3 memory-mapped ring
+// The kernel fills blocks of frames in memory shared with kw and hands over a whole block at a
+// time, so a busy link costs one poll per block instead of a system call and a copy per packet
+
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::collectors::capture_filter::BpfInstruction;
+
+/// Size of one ring block; frames never span blocks, so this also bounds the largest frame
+const BLOCK_SIZE: usize = 1 << 20;
+
+/// Frame size the ring request is expressed in; V3 packs frames tightly regardless
+const FRAME_SIZE: usize = 2048;
+
+/// How long the kernel keeps a partly filled block before handing it over, in milliseconds
+const BLOCK_TIMEOUT_MS: u32 = 50;
+
+/// Bounds on the ring's block count, 2 to 64 MiB
+const MIN_BLOCKS: usize = 2;
+const MAX_BLOCKS: usize = 64;
+
+/// Kernel ring blocks with room for about `frames` full-size frames
+pub fn blocks_for(frames: usize) -> usize {
+    (frames * FRAME_SIZE).div_ceil(BLOCK_SIZE).clamp(MIN_BLOCKS, MAX_BLOCKS)
+}
+
+/// A frame as the kernel left it in the ring
+#[derive(Debug, Clone, Copy)]
+pub struct RingFrame<'a> {
+    /// Captured bytes, starting at the link-layer header
+    pub data: &'a [u8],
+    /// Length on the wire, which may exceed `data`
+    pub wire_len: u32,
+    /// Capture time in nanoseconds since the Unix epoch
+    pub timestamp_ns: i64,
+}
+
+/// A packet socket bound to one interface, reading through a TPACKET_V3 ring
+pub struct AfPacketRing {
+    socket: OwnedFd,
+    map: NonNull<u8>,
+    block_count: usize,
+    /// Block the kernel fills next
+    next_block: usize,
+    /// Whether the kernel runs the capture filter on this socket
+    filtered: bool,
+}
+
+// The mapping belongs to this value alone; it is only read through `&mut self`
+unsafe impl Send for AfPacketRing {}
+
+impl AfPacketRing {
+    /// Opens a packet socket on the interface with index `ifindex` and maps a ring of
+    /// `block_count` blocks; fails with PermissionDenied without CAP_NET_RAW
+    ///
+    /// `filter` is attached before the ring exists, so no frame reaches it unfiltered; when
+    /// the kernel refuses the program the socket captures everything and `filtered` says so.
+    pub fn open(ifindex: u32, block_count: usize, filter: Option<&[BpfInstruction]>) -> io::Result<Self> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol.into()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let filtered = match filter.map(|program| attach_filter(&socket, program)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                log::warn!("The kernel refused the capture filter ({e}); filtering in kw instead");
+                false
+            }
+            None => false,
+        };
+
+        let version = libc::tpacket_versions::TPACKET_V3 as libc::c_int;
+        set_option(&socket, libc::PACKET_VERSION, &version)?;
+
+        let block_count = block_count.max(1);
+        let mut request: libc::tpacket_req3 = unsafe { mem::zeroed() };
+        request.tp_block_size = BLOCK_SIZE as u32;
+        request.tp_block_nr = block_count as u32;
+        request.tp_frame_size = FRAME_SIZE as u32;
+        request.tp_frame_nr = (BLOCK_SIZE / FRAME_SIZE * block_count) as u32;
+        request.tp_retire_blk_tov = BLOCK_TIMEOUT_MS;
+        set_option(&socket, libc::PACKET_RX_RING, &request)?;
+
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                BLOCK_SIZE * block_count,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                socket.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // From here on dropping the ring unmaps it
+        let ring = Self {
+            socket,
+            map: NonNull::new(map.cast()).ok_or_else(io::Error::last_os_error)?,
+            block_count,
+            next_block: 0,
+            filtered,
+        };
+
+        let mut address: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = protocol;
+        address.sll_ifindex = ifindex as libc::c_int;
+        let bound = unsafe {
+            libc::bind(
+                ring.socket.as_raw_fd(),
+                ptr::from_ref(&address).cast(),
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ring)
+    }
+
+    /// Hands each frame of the next filled block to `each`, waiting up to `timeout` for one;
+    /// returns the number of frames, 0 when nothing arrived in time
+    pub fn next_block(&mut self, timeout: Duration, mut each: impl FnMut(RingFrame<'_>)) -> io::Result<usize> {
+        let block = unsafe { self.map.as_ptr().add(self.next_block * BLOCK_SIZE) };
+        let descriptor = block.cast::<libc::tpacket_block_desc>();
+        // The kernel flips the status word to hand the block over and back
+        let status = unsafe { AtomicU32::from_ptr(&raw mut (*descriptor).hdr.bh1.block_status) };
+
+        if status.load(Ordering::Acquire) & libc::TP_STATUS_USER == 0 {
+            let mut poll = libc::pollfd {
+                fd: self.socket.as_raw_fd(),
+                events: libc::POLLIN | libc::POLLERR,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) } < 0 {
+                let error = io::Error::last_os_error();
+                return if error.kind() == io::ErrorKind::Interrupted { Ok(0) } else { Err(error) };
+            }
+            if status.load(Ordering::Acquire) & libc::TP_STATUS_USER == 0 {
+                return Ok(0);
+            }
+        }
+
+        let (frames, first) = unsafe {
+            let header = &(*descriptor).hdr.bh1;
+            (header.num_pkts as usize, header.offset_to_first_pkt as usize)
+        };
+        let mut offset = first;
+        for _ in 0..frames {
+            let header = unsafe { &*block.add(offset).cast::<libc::tpacket3_hdr>() };
+            let data = unsafe {
+                slice::from_raw_parts(block.add(offset + header.tp_mac as usize), header.tp_snaplen as usize)
+            };
+            each(RingFrame {
+                data,
+                wire_len: header.tp_len,
+                timestamp_ns: i64::from(header.tp_sec) * 1_000_000_000 + i64::from(header.tp_nsec),
+            });
+            offset += header.tp_next_offset as usize;
+        }
+
+        status.store(libc::TP_STATUS_KERNEL, Ordering::Release);
+        self.next_block = (self.next_block + 1) % self.block_count;
+        Ok(frames)
+    }
+
+    /// Whether every frame of the ring has passed the capture filter
+    pub fn filtered(&self) -> bool {
+        self.filtered
+    }
+
+    /// Frames the kernel dropped because the ring was full since the last call
+    pub fn take_drops(&self) -> io::Result<u64> {
+        let mut stats: libc::tpacket_stats_v3 = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::tpacket_stats_v3>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                self.socket.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                ptr::from_mut(&mut stats).cast(),
+                &mut len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(stats.tp_drops.into())
+    }
+}
+
+impl Drop for AfPacketRing {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map.as_ptr().cast(), BLOCK_SIZE * self.block_count);
+        }
+    }
+}
+
+/// Has the kernel run `program` on every packet the socket receives (SO_ATTACH_FILTER)
+fn attach_filter(socket: &OwnedFd, program: &[BpfInstruction]) -> io::Result<()> {
+    let program = libc::sock_fprog {
+        len: u16::try_from(program.len()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
+        filter: program.as_ptr().cast_mut().cast(),
+    };
+    set_option_at(socket, libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &program)
+}
+
+fn set_option<T>(socket: &OwnedFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    set_option_at(socket, libc::SOL_PACKET, option, value)
+}
+
+fn set_option_at<T>(socket: &OwnedFd, level: libc::c_int, option: libc::c_int, value: &T) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            ptr::from_ref(value).cast(),
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_accepts_compiled_filters() {
+        use crate::collectors::capture_filter::{BpfLink, CaptureFilter};
+
+        // The kernel checks a program when it is attached, to any socket
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        assert!(fd >= 0);
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+        for expression in ["tcp port 443 and host 10.0.0.5", "not (udp portrange 6000-6010 or net 2001:db8::/32)", "arp or less 64"] {
+            let program = CaptureFilter::parse(expression).unwrap().to_bpf(BpfLink::Ethernet).unwrap();
+            attach_filter(&socket, &program).unwrap_or_else(|e| panic!("{expression:?}: {e}"));
+        }
+    }
+
+    #[test]
+    fn test_ring_size_follows_the_frame_budget() {
+        assert_eq!(blocks_for(1_000), MIN_BLOCKS);
+        assert_eq!(blocks_for(10_000), 20);
+        assert_eq!(blocks_for(1_000_000), MAX_BLOCKS);
+    }
+}
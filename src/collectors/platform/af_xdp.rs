@@ -0,0 +1,524 @@
+// AF_XDP capture: an XDP program hands frames to per-queue AF_XDP sockets straight from the driver
+// Frames land in memory shared with kw (zero-copy where the driver supports it) and skip the
+// kernel's network stack entirely, which is what makes it fast and also why it is opt-in: traffic
+// taken this way never reaches the host, so it only suits mirror ports and taps
+//
+// The program is a handful of hand-assembled instructions loaded through bpf(2), so no BPF
+// toolchain or libbpf is needed; it is attached through a BPF link and detaches when kw exits
+
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Size of one UMEM chunk, the most a frame can take
+const FRAME_SIZE: usize = 2048;
+
+/// UMEM chunks per socket; also the size of its fill and RX rings (a power of two)
+const FRAMES_PER_QUEUE: u32 = 2048;
+
+// bpf(2) commands, object types and flags used here (linux/bpf.h)
+const BPF_MAP_CREATE: libc::c_long = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_long = 2;
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_LINK_CREATE: libc::c_long = 28;
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+
+/// The program's license; redirecting to an XSKMAP needs a GPL-compatible one
+const PROGRAM_LICENSE: &CStr = c"Dual MIT/GPL";
+
+/// One eBPF instruction (struct bpf_insn)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    code: u8,
+    /// Destination register in the low nibble, source in the high one
+    registers: u8,
+    offset: i16,
+    immediate: i32,
+}
+
+impl Instruction {
+    const fn new(code: u8, destination: u8, source: u8, offset: i16, immediate: i32) -> Self {
+        Self { code, registers: destination | (source << 4), offset, immediate }
+    }
+}
+
+/// `return bpf_redirect_map(&xsks, ctx->rx_queue_index, XDP_PASS);`
+/// Frames on queues without a socket in the map carry on into the network stack
+fn redirect_program(map_fd: i32) -> [Instruction; 6] {
+    const BPF_PSEUDO_MAP_FD: u8 = 1;
+    const XDP_PASS: i32 = 2;
+    const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+    [
+        // r2 = ((struct xdp_md *)r1)->rx_queue_index
+        Instruction::new(0x61, 2, 1, 16, 0),
+        // r1 = map (a 64-bit immediate, so two instructions)
+        Instruction::new(0x18, 1, BPF_PSEUDO_MAP_FD, 0, map_fd),
+        Instruction::new(0, 0, 0, 0, 0),
+        // r3 = XDP_PASS, the action when the map has no socket for the queue
+        Instruction::new(0xb7, 3, 0, 0, XDP_PASS),
+        Instruction::new(0x85, 0, 0, 0, BPF_FUNC_REDIRECT_MAP),
+        Instruction::new(0x95, 0, 0, 0, 0),
+    ]
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapUpdateAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+/// Runs a bpf(2) command; commands creating an object return its descriptor
+fn bpf<T>(command: libc::c_long, attr: &mut T) -> io::Result<libc::c_long> {
+    let result = unsafe {
+        libc::syscall(libc::SYS_bpf, command, ptr::from_mut(attr), mem::size_of::<T>() as libc::c_uint)
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+fn bpf_object<T>(command: libc::c_long, attr: &mut T) -> io::Result<OwnedFd> {
+    let fd = bpf(command, attr)?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+/// Receive queues of `interface`, from the `rx-N` entries in sysfs
+pub fn rx_queue_count(interface: &str) -> u32 {
+    let queues = Path::new("/sys/class/net").join(interface).join("queues");
+    let count = std::fs::read_dir(queues)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("rx-"))
+                .count() as u32
+        })
+        .unwrap_or(0);
+    count.max(1)
+}
+
+/// The XDP program on an interface and the map routing its queues to sockets
+/// Dropping the last reference detaches the program
+pub struct XdpAttachment {
+    map: OwnedFd,
+    _program: OwnedFd,
+    _link: OwnedFd,
+    /// Whether the driver runs the program itself rather than the generic fallback
+    pub native: bool,
+}
+
+impl XdpAttachment {
+    /// Loads the redirect program and attaches it to the interface with index `ifindex`,
+    /// in driver mode where supported and generic mode otherwise
+    pub fn attach(ifindex: u32, queues: u32) -> io::Result<Self> {
+        let mut map_attr = MapCreateAttr {
+            map_type: BPF_MAP_TYPE_XSKMAP,
+            key_size: 4,
+            value_size: 4,
+            max_entries: queues.max(1),
+        };
+        let map = bpf_object(BPF_MAP_CREATE, &mut map_attr)?;
+
+        let program = redirect_program(map.as_raw_fd());
+        let mut name = [0u8; 16];
+        name[..6].copy_from_slice(b"kw_xsk");
+        let mut load_attr = ProgLoadAttr {
+            prog_type: BPF_PROG_TYPE_XDP,
+            insn_cnt: program.len() as u32,
+            insns: program.as_ptr() as u64,
+            license: PROGRAM_LICENSE.as_ptr() as u64,
+            prog_name: name,
+            expected_attach_type: BPF_XDP,
+            ..ProgLoadAttr::default()
+        };
+        let program = bpf_object(BPF_PROG_LOAD, &mut load_attr)?;
+
+        let mut link_attr = LinkCreateAttr {
+            prog_fd: program.as_raw_fd() as u32,
+            target_ifindex: ifindex,
+            attach_type: BPF_XDP,
+            flags: XDP_FLAGS_DRV_MODE,
+        };
+        let (link, native) = match bpf_object(BPF_LINK_CREATE, &mut link_attr) {
+            Ok(link) => (link, true),
+            Err(_) => {
+                link_attr.flags = XDP_FLAGS_SKB_MODE;
+                (bpf_object(BPF_LINK_CREATE, &mut link_attr)?, false)
+            }
+        };
+        Ok(Self { map, _program: program, _link: link, native })
+    }
+
+    /// Sends frames arriving on `queue` to `socket`
+    fn route(&self, queue: u32, socket: &OwnedFd) -> io::Result<()> {
+        let value = socket.as_raw_fd() as u32;
+        let mut attr = MapUpdateAttr {
+            map_fd: self.map.as_raw_fd() as u32,
+            key: ptr::from_ref(&queue) as u64,
+            value: ptr::from_ref(&value) as u64,
+            ..MapUpdateAttr::default()
+        };
+        bpf(BPF_MAP_UPDATE_ELEM, &mut attr).map(drop)
+    }
+}
+
+/// A frame as the driver left it in the UMEM
+#[derive(Debug, Clone, Copy)]
+pub struct XdpFrame<'a> {
+    pub data: &'a [u8],
+    /// Receive time in nanoseconds since the Unix epoch; AF_XDP carries no timestamps, so
+    /// this is when the batch was taken
+    pub timestamp_ns: i64,
+}
+
+/// Producer and consumer indexes and descriptors of one ring shared with the kernel
+struct Ring {
+    producer: NonNull<AtomicU32>,
+    consumer: NonNull<AtomicU32>,
+    flags: NonNull<AtomicU32>,
+    descriptors: NonNull<u8>,
+    mask: u32,
+    map: NonNull<libc::c_void>,
+    map_len: usize,
+}
+
+impl Ring {
+    /// Maps the ring at `page_offset` whose layout `offsets` describes
+    fn map<T>(socket: &OwnedFd, offsets: &libc::xdp_ring_offset, size: u32, page_offset: u64) -> io::Result<Self> {
+        let map_len = offsets.desc as usize + size as usize * mem::size_of::<T>();
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                socket.as_raw_fd(),
+                page_offset as libc::off_t,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let base = map.cast::<u8>();
+        unsafe {
+            Ok(Self {
+                producer: NonNull::new_unchecked(base.add(offsets.producer as usize).cast()),
+                consumer: NonNull::new_unchecked(base.add(offsets.consumer as usize).cast()),
+                flags: NonNull::new_unchecked(base.add(offsets.flags as usize).cast()),
+                descriptors: NonNull::new_unchecked(base.add(offsets.desc as usize)),
+                mask: size - 1,
+                map: NonNull::new_unchecked(map),
+                map_len,
+            })
+        }
+    }
+
+    // The indexes live in the mapping, which stays until the ring is dropped
+    fn producer(&self) -> &AtomicU32 {
+        unsafe { self.producer.as_ref() }
+    }
+
+    fn consumer(&self) -> &AtomicU32 {
+        unsafe { self.consumer.as_ref() }
+    }
+
+    fn flags(&self) -> &AtomicU32 {
+        unsafe { self.flags.as_ref() }
+    }
+
+    fn slot<T>(&self, index: u32) -> *mut T {
+        unsafe { self.descriptors.as_ptr().cast::<T>().add((index & self.mask) as usize) }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map.as_ptr(), self.map_len);
+        }
+    }
+}
+
+/// An AF_XDP socket receiving one queue of an interface into its own UMEM
+pub struct XdpSocket {
+    rx: Ring,
+    fill: Ring,
+    umem: NonNull<u8>,
+    socket: OwnedFd,
+    /// Keeps the program attached while the socket is in use
+    _attachment: Arc<XdpAttachment>,
+    pub queue: u32,
+}
+
+// The rings and UMEM belong to this socket alone and are only touched through `&mut self`
+unsafe impl Send for XdpSocket {}
+
+impl XdpSocket {
+    /// Binds a socket to `queue` of the interface with index `ifindex` and routes the queue to it
+    pub fn open(attachment: Arc<XdpAttachment>, ifindex: u32, queue: u32) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let umem_len = FRAME_SIZE * FRAMES_PER_QUEUE as usize;
+        let umem = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                umem_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if umem == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let umem = NonNull::new(umem.cast::<u8>()).ok_or_else(io::Error::last_os_error)?;
+        // Unmaps the UMEM again if setting up the rest fails
+        let guard = UmemGuard(umem);
+
+        // The v1 layout, without flags, is accepted by every kernel with AF_XDP
+        let registration = libc::xdp_umem_reg_v1 {
+            addr: umem.as_ptr() as u64,
+            len: umem_len as u64,
+            chunk_size: FRAME_SIZE as u32,
+            headroom: 0,
+        };
+        set_option(&socket, libc::XDP_UMEM_REG, &registration)?;
+        set_option(&socket, libc::XDP_UMEM_FILL_RING, &FRAMES_PER_QUEUE)?;
+        set_option(&socket, libc::XDP_UMEM_COMPLETION_RING, &FRAMES_PER_QUEUE)?;
+        set_option(&socket, libc::XDP_RX_RING, &FRAMES_PER_QUEUE)?;
+
+        let mut offsets: libc::xdp_mmap_offsets = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::xdp_mmap_offsets>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_XDP,
+                libc::XDP_MMAP_OFFSETS,
+                ptr::from_mut(&mut offsets).cast(),
+                &mut len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let rx = Ring::map::<libc::xdp_desc>(&socket, &offsets.rx, FRAMES_PER_QUEUE, libc::XDP_PGOFF_RX_RING as u64)?;
+        let fill = Ring::map::<u64>(&socket, &offsets.fr, FRAMES_PER_QUEUE, libc::XDP_UMEM_PGOFF_FILL_RING)?;
+
+        // Every chunk starts out in the fill ring, for the driver to receive into
+        for chunk in 0..FRAMES_PER_QUEUE {
+            unsafe { fill.slot::<u64>(chunk).write((chunk as usize * FRAME_SIZE) as u64) };
+        }
+        fill.producer().store(FRAMES_PER_QUEUE, Ordering::Release);
+
+        let mut address: libc::sockaddr_xdp = unsafe { mem::zeroed() };
+        address.sxdp_family = libc::AF_XDP as u16;
+        address.sxdp_flags = libc::XDP_USE_NEED_WAKEUP;
+        address.sxdp_ifindex = ifindex;
+        address.sxdp_queue_id = queue;
+        let bound = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                ptr::from_ref(&address).cast(),
+                mem::size_of::<libc::sockaddr_xdp>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        attachment.route(queue, &socket)?;
+
+        mem::forget(guard);
+        Ok(Self { rx, fill, umem, socket, _attachment: attachment, queue })
+    }
+
+    /// Hands each received frame to `each`, waiting up to `timeout` when none are ready;
+    /// returns the number of frames
+    pub fn receive(&mut self, timeout: Duration, mut each: impl FnMut(XdpFrame<'_>)) -> io::Result<usize> {
+        let consumer = self.rx.consumer().load(Ordering::Relaxed);
+        let mut ready = self.rx.producer().load(Ordering::Acquire).wrapping_sub(consumer);
+        if ready == 0 {
+            self.wait(timeout)?;
+            ready = self.rx.producer().load(Ordering::Acquire).wrapping_sub(consumer);
+            if ready == 0 {
+                return Ok(0);
+            }
+        }
+
+        let timestamp_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as i64);
+        let fill_at = self.fill.producer().load(Ordering::Relaxed);
+        for index in 0..ready {
+            let descriptor = unsafe { self.rx.slot::<libc::xdp_desc>(consumer.wrapping_add(index)).read() };
+            let data = unsafe {
+                slice::from_raw_parts(self.umem.as_ptr().add(descriptor.addr as usize), descriptor.len as usize)
+            };
+            each(XdpFrame { data, timestamp_ns });
+            // Hand the chunk straight back for the driver to fill again
+            let chunk = descriptor.addr & !(FRAME_SIZE as u64 - 1);
+            unsafe { self.fill.slot::<u64>(fill_at.wrapping_add(index)).write(chunk) };
+        }
+        self.rx.consumer().store(consumer.wrapping_add(ready), Ordering::Release);
+        self.fill.producer().store(fill_at.wrapping_add(ready), Ordering::Release);
+
+        if self.fill.flags().load(Ordering::Relaxed) & libc::XDP_RING_NEED_WAKEUP != 0 {
+            unsafe {
+                libc::recvfrom(self.socket.as_raw_fd(), ptr::null_mut(), 0, libc::MSG_DONTWAIT, ptr::null_mut(), ptr::null_mut());
+            }
+        }
+        Ok(ready as usize)
+    }
+
+    fn wait(&self, timeout: Duration) -> io::Result<()> {
+        let mut poll = libc::pollfd { fd: self.socket.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) } < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Frames dropped since the socket opened because the RX ring was full or no chunk was free
+    pub fn drops(&self) -> io::Result<u64> {
+        let mut stats: libc::xdp_statistics = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::xdp_statistics>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                self.socket.as_raw_fd(),
+                libc::SOL_XDP,
+                libc::XDP_STATISTICS,
+                ptr::from_mut(&mut stats).cast(),
+                &mut len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(stats.rx_dropped + stats.rx_ring_full + stats.rx_fill_ring_empty_descs)
+    }
+}
+
+impl Drop for XdpSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.umem.as_ptr().cast(), FRAME_SIZE * FRAMES_PER_QUEUE as usize);
+        }
+    }
+}
+
+/// Unmaps a UMEM whose socket failed to come up
+struct UmemGuard(NonNull<u8>);
+
+impl Drop for UmemGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.0.as_ptr().cast(), FRAME_SIZE * FRAMES_PER_QUEUE as usize);
+        }
+    }
+}
+
+/// Opens a socket on every receive queue of an interface, behind one attached program
+pub fn open_interface(name: &str, ifindex: u32) -> io::Result<Vec<XdpSocket>> {
+    let queues = rx_queue_count(name);
+    let attachment = Arc::new(XdpAttachment::attach(ifindex, queues)?);
+    log::info!(
+        "XDP program attached to {name} in {} mode; opening {queues} AF_XDP socket(s)",
+        if attachment.native { "driver" } else { "generic" }
+    );
+    (0..queues).map(|queue| XdpSocket::open(Arc::clone(&attachment), ifindex, queue)).collect()
+}
+
+fn set_option<T>(socket: &OwnedFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_XDP,
+            option,
+            ptr::from_ref(value).cast(),
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redirect_program_encoding() {
+        let program = redirect_program(7);
+        // ldx w r2, [r1 + 16]
+        assert_eq!((program[0].code, program[0].registers, program[0].offset), (0x61, 0x12, 16));
+        // ld_imm64 r1, map fd 7
+        assert_eq!((program[1].code, program[1].registers, program[1].immediate), (0x18, 0x11, 7));
+        assert_eq!(program[5].code, 0x95, "ends with exit");
+        assert_eq!(mem::size_of::<Instruction>(), 8);
+    }
+
+    #[test]
+    fn test_queue_count_defaults_to_one() {
+        assert_eq!(rx_queue_count("kw-no-such-interface"), 1);
+    }
+}
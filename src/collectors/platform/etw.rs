@@ -0,0 +1,533 @@
+// Kernel network events from Event Tracing for Windows (ETW)
+// Without Npcap there is no way to see packets on Windows, but the kernel still reports
+// every TCP and UDP send and receive through the Microsoft-Windows-Kernel-Network provider:
+// the owning process, the payload size and both endpoints. A real-time trace session
+// turns those events into packets, so `kw packets` keeps per-connection and per-process
+// byte counts without a capture driver
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::models::{NetworkPacket, PacketDirection, PacketProtocol, TransportProtocol};
+
+/// Event IDs of the Kernel-Network provider that carry traffic, and what they describe
+const SEND_RECEIVE_EVENTS: [(u16, TransportProtocol, PacketDirection, bool); 8] = [
+    (10, TransportProtocol::Tcp, PacketDirection::Outbound, false),
+    (11, TransportProtocol::Tcp, PacketDirection::Inbound, false),
+    (26, TransportProtocol::Tcp, PacketDirection::Outbound, true),
+    (27, TransportProtocol::Tcp, PacketDirection::Inbound, true),
+    (42, TransportProtocol::Udp, PacketDirection::Outbound, false),
+    (43, TransportProtocol::Udp, PacketDirection::Inbound, false),
+    (58, TransportProtocol::Udp, PacketDirection::Outbound, true),
+    (59, TransportProtocol::Udp, PacketDirection::Inbound, true),
+];
+
+/// One send or receive reported by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelNetworkEvent {
+    /// Process owning the socket
+    pub pid: u32,
+    /// Payload bytes; headers are not included
+    pub size: u32,
+    pub transport: TransportProtocol,
+    pub direction: PacketDirection,
+    pub local: (IpAddr, u16),
+    pub remote: (IpAddr, u16),
+}
+
+impl KernelNetworkEvent {
+    /// Decodes the payload of event `id`, or `None` for events that are not traffic
+    ///
+    /// Every traffic event starts with the process id, the size, the destination and
+    /// source addresses and the destination and source ports in network byte order.
+    /// The kernel names the endpoints from the socket's point of view, so the source
+    /// is the local end for receives as well as sends.
+    pub fn parse(id: u16, payload: &[u8]) -> Option<Self> {
+        let &(_, transport, direction, ipv6) = SEND_RECEIVE_EVENTS.iter().find(|(event, ..)| *event == id)?;
+        let address_len = if ipv6 { 16 } else { 4 };
+        let fields = payload.get(..8 + 2 * address_len + 4)?;
+
+        let u32_at = |offset: usize| u32::from_le_bytes(fields[offset..offset + 4].try_into().unwrap());
+        let port_at = |offset: usize| u16::from_be_bytes(fields[offset..offset + 2].try_into().unwrap());
+        let address_at = |offset: usize| -> IpAddr {
+            if ipv6 {
+                let octets: [u8; 16] = fields[offset..offset + 16].try_into().unwrap();
+                Ipv6Addr::from(octets).into()
+            } else {
+                let octets: [u8; 4] = fields[offset..offset + 4].try_into().unwrap();
+                Ipv4Addr::from(octets).into()
+            }
+        };
+
+        let ports = 8 + 2 * address_len;
+        Some(Self {
+            pid: u32_at(0),
+            size: u32_at(4),
+            transport,
+            direction,
+            remote: (address_at(8), port_at(ports)),
+            local: (address_at(8 + address_len), port_at(ports + 2)),
+        })
+    }
+
+    /// The event as a packet on `interface`, addressed in the direction it travelled
+    pub fn to_packet(self, interface: &str) -> NetworkPacket {
+        let protocol = match self.local.0 {
+            IpAddr::V4(_) => PacketProtocol::IPv4,
+            IpAddr::V6(_) => PacketProtocol::IPv6,
+        };
+        let direction = if self.remote.0.is_loopback() { PacketDirection::Local } else { self.direction };
+        let mut packet = NetworkPacket::new(interface.to_string(), self.size.into(), protocol, direction);
+        let (source, dest) = match self.direction {
+            PacketDirection::Inbound => (self.remote, self.local),
+            _ => (self.local, self.remote),
+        };
+        packet.transport_protocol = self.transport;
+        packet.source_addr = Some(source.0);
+        packet.source_port = Some(source.1);
+        packet.dest_addr = Some(dest.0);
+        packet.dest_port = Some(dest.1);
+        packet
+    }
+}
+
+#[cfg(windows)]
+pub use session::KernelNetworkSession;
+
+#[cfg(windows)]
+mod session {
+    use anyhow::{bail, Result};
+    use log::{debug, warn};
+    use std::mem;
+    use std::ptr;
+    use std::thread::JoinHandle;
+
+    use super::KernelNetworkEvent;
+
+    /// Receives each traffic event on the session's processing thread
+    type Sink = Box<dyn FnMut(KernelNetworkEvent) + Send>;
+
+    /// Microsoft-Windows-Kernel-Network, {7DD42A49-5329-4832-8DFD-43D979153A88}
+    const KERNEL_NETWORK_PROVIDER: ffi::Guid = ffi::Guid {
+        data1: 0x7DD4_2A49,
+        data2: 0x5329,
+        data3: 0x4832,
+        data4: [0x8D, 0xFD, 0x43, 0xD9, 0x79, 0x15, 0x3A, 0x88],
+    };
+
+    /// KERNEL_NETWORK_KEYWORD_IPV4 | KERNEL_NETWORK_KEYWORD_IPV6
+    const IP_KEYWORDS: u64 = 0x10 | 0x20;
+
+    /// A real-time trace session with the Kernel-Network provider enabled
+    ///
+    /// Events are delivered on a thread of its own until the session is dropped, which
+    /// stops the session and waits for the thread. Needs Administrator privileges.
+    pub struct KernelNetworkSession {
+        handle: u64,
+        properties: Box<SessionProperties>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    // SAFETY: the properties are only handed to ControlTrace, from whichever thread drops the
+    // session; the pointer inside them is an output field ETW fills in and nothing reads
+    unsafe impl Send for KernelNetworkSession {}
+
+    /// Trace properties followed by the session name, as StartTrace expects them
+    #[repr(C)]
+    struct SessionProperties {
+        properties: ffi::EventTraceProperties,
+        name: [u16; 64],
+    }
+
+    impl SessionProperties {
+        fn new(name: &[u16]) -> Box<Self> {
+            // SAFETY: all-zero is a valid value for these plain C structs
+            let mut session: Box<Self> = Box::new(unsafe { mem::zeroed() });
+            session.name[..name.len()].copy_from_slice(name);
+            let properties = &mut session.properties;
+            properties.wnode.buffer_size = mem::size_of::<Self>() as u32;
+            properties.wnode.flags = ffi::WNODE_FLAG_TRACED_GUID;
+            properties.wnode.client_context = 1; // QueryPerformanceCounter timestamps
+            properties.log_file_mode = ffi::EVENT_TRACE_REAL_TIME_MODE;
+            properties.logger_name_offset = mem::offset_of!(Self, name) as u32;
+            session
+        }
+    }
+
+    impl KernelNetworkSession {
+        /// Starts a session named after this process and passes every send and receive to `sink`
+        pub fn start(sink: impl FnMut(KernelNetworkEvent) + Send + 'static) -> Result<Self> {
+            let name: Vec<u16> = format!("kaipo-watcher-{}", std::process::id())
+                .encode_utf16()
+                .chain(Some(0))
+                .collect();
+            let mut properties = SessionProperties::new(&name);
+            let mut handle = 0u64;
+
+            // SAFETY: name is NUL-terminated and properties has room for the name after it
+            let mut status = unsafe { ffi::StartTraceW(&mut handle, name.as_ptr(), &mut properties.properties) };
+            if status == ffi::ERROR_ALREADY_EXISTS {
+                // Left behind by an earlier process with the same id that did not shut down
+                debug!("Stopping stale trace session before starting a new one");
+                let mut stale = SessionProperties::new(&name);
+                // SAFETY: as above; stopping by name needs no handle
+                unsafe { ffi::ControlTraceW(0, name.as_ptr(), &mut stale.properties, ffi::EVENT_TRACE_CONTROL_STOP) };
+                properties = SessionProperties::new(&name);
+                // SAFETY: as above
+                status = unsafe { ffi::StartTraceW(&mut handle, name.as_ptr(), &mut properties.properties) };
+            }
+            match status {
+                ffi::ERROR_SUCCESS => {}
+                ffi::ERROR_ACCESS_DENIED => bail!("Kernel network tracing requires Administrator privileges"),
+                _ => bail!("Failed to start kernel network trace session (error {status})"),
+            }
+            let mut session = Self { handle, properties, thread: None };
+
+            // SAFETY: handle is the session just started; no enable parameters are passed
+            let status = unsafe {
+                ffi::EnableTraceEx2(
+                    handle,
+                    &KERNEL_NETWORK_PROVIDER,
+                    ffi::EVENT_CONTROL_CODE_ENABLE_PROVIDER,
+                    ffi::TRACE_LEVEL_INFORMATION,
+                    IP_KEYWORDS,
+                    0,
+                    0,
+                    ptr::null(),
+                )
+            };
+            if status != ffi::ERROR_SUCCESS {
+                bail!("Failed to enable the Kernel-Network provider (error {status})");
+            }
+
+            let sink: *mut Sink = Box::into_raw(Box::new(Box::new(sink)));
+            // SAFETY: all-zero is a valid value for this plain C struct
+            let mut logfile: ffi::EventTraceLogfileW = unsafe { mem::zeroed() };
+            logfile.logger_name = name.as_ptr() as *mut u16;
+            logfile.process_trace_mode = ffi::PROCESS_TRACE_MODE_REAL_TIME | ffi::PROCESS_TRACE_MODE_EVENT_RECORD;
+            logfile.event_record_callback = Some(ffi::on_event);
+            logfile.context = sink.cast();
+            // SAFETY: logfile names the running session; the name is only read during the call
+            let trace = unsafe { ffi::OpenTraceW(&mut logfile) };
+            if trace == ffi::INVALID_PROCESSTRACE_HANDLE {
+                // SAFETY: nothing received the sink, so it is still owned here
+                drop(unsafe { Box::from_raw(sink) });
+                bail!("Failed to open the kernel network trace session");
+            }
+
+            // Raw pointers are not Send; the thread is the only user of the sink from here on
+            let sink = sink as usize;
+            session.thread = Some(
+                std::thread::Builder::new()
+                    .name("etw-kernel-network".to_string())
+                    .spawn(move || {
+                        // SAFETY: trace was opened above and is closed once, here; ProcessTrace
+                        // returns when the session stops, after which no callback uses the sink
+                        unsafe {
+                            let status = ffi::ProcessTrace(&trace, 1, ptr::null(), ptr::null());
+                            if status != ffi::ERROR_SUCCESS && status != ffi::ERROR_CANCELLED {
+                                warn!("Kernel network trace processing ended with error {status}");
+                            }
+                            ffi::CloseTrace(trace);
+                            drop(Box::from_raw(sink as *mut Sink));
+                        }
+                    })?,
+            );
+            Ok(session)
+        }
+    }
+
+    impl Drop for KernelNetworkSession {
+        fn drop(&mut self) {
+            // SAFETY: handle and properties belong to the session started in `start`
+            let status = unsafe {
+                ffi::ControlTraceW(self.handle, ptr::null(), &mut self.properties.properties, ffi::EVENT_TRACE_CONTROL_STOP)
+            };
+            if status != ffi::ERROR_SUCCESS {
+                warn!("Failed to stop kernel network trace session (error {status})");
+            }
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Minimal advapi32 bindings for real-time ETW sessions
+    mod ffi {
+        use std::ffi::c_void;
+
+        use super::{KernelNetworkEvent, Sink, KERNEL_NETWORK_PROVIDER};
+
+        pub const ERROR_SUCCESS: u32 = 0;
+        pub const ERROR_ACCESS_DENIED: u32 = 5;
+        pub const ERROR_ALREADY_EXISTS: u32 = 183;
+        pub const ERROR_CANCELLED: u32 = 1223;
+
+        pub const WNODE_FLAG_TRACED_GUID: u32 = 0x0002_0000;
+        pub const EVENT_TRACE_REAL_TIME_MODE: u32 = 0x100;
+        pub const EVENT_TRACE_CONTROL_STOP: u32 = 1;
+        pub const EVENT_CONTROL_CODE_ENABLE_PROVIDER: u32 = 1;
+        pub const TRACE_LEVEL_INFORMATION: u8 = 4;
+        pub const PROCESS_TRACE_MODE_REAL_TIME: u32 = 0x100;
+        pub const PROCESS_TRACE_MODE_EVENT_RECORD: u32 = 0x1000_0000;
+        pub const INVALID_PROCESSTRACE_HANDLE: u64 = if cfg!(target_pointer_width = "64") { u64::MAX } else { 0xFFFF_FFFF };
+
+        #[repr(C)]
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        pub struct Guid {
+            pub data1: u32,
+            pub data2: u16,
+            pub data3: u16,
+            pub data4: [u8; 8],
+        }
+
+        #[repr(C)]
+        pub struct WnodeHeader {
+            pub buffer_size: u32,
+            pub provider_id: u32,
+            pub historical_context: u64,
+            pub time_stamp: i64,
+            pub guid: Guid,
+            pub client_context: u32,
+            pub flags: u32,
+        }
+
+        #[repr(C)]
+        pub struct EventTraceProperties {
+            pub wnode: WnodeHeader,
+            pub buffer_size: u32,
+            pub minimum_buffers: u32,
+            pub maximum_buffers: u32,
+            pub maximum_file_size: u32,
+            pub log_file_mode: u32,
+            pub flush_timer: u32,
+            pub enable_flags: u32,
+            pub age_limit: i32,
+            pub number_of_buffers: u32,
+            pub free_buffers: u32,
+            pub events_lost: u32,
+            pub buffers_written: u32,
+            pub log_buffers_lost: u32,
+            pub real_time_buffers_lost: u32,
+            pub logger_thread_id: *mut c_void,
+            pub log_file_name_offset: u32,
+            pub logger_name_offset: u32,
+        }
+
+        #[repr(C)]
+        pub struct EventTraceHeader {
+            pub size: u16,
+            pub header_type: u16,
+            pub version: u32,
+            pub thread_id: u32,
+            pub process_id: u32,
+            pub time_stamp: i64,
+            pub guid: Guid,
+            pub processor_time: u64,
+        }
+
+        #[repr(C)]
+        pub struct EventTrace {
+            pub header: EventTraceHeader,
+            pub instance_id: u32,
+            pub parent_instance_id: u32,
+            pub parent_guid: Guid,
+            pub mof_data: *mut c_void,
+            pub mof_length: u32,
+            pub client_context: u32,
+        }
+
+        #[repr(C)]
+        pub struct TraceLogfileHeader {
+            pub buffer_size: u32,
+            pub version: u32,
+            pub provider_version: u32,
+            pub number_of_processors: u32,
+            pub end_time: i64,
+            pub timer_resolution: u32,
+            pub maximum_file_size: u32,
+            pub log_file_mode: u32,
+            pub buffers_written: u32,
+            pub log_instance_guid: Guid,
+            pub logger_name: *mut u16,
+            pub log_file_name: *mut u16,
+            /// TIME_ZONE_INFORMATION, which is never read
+            pub time_zone: [u32; 43],
+            pub boot_time: i64,
+            pub perf_freq: i64,
+            pub start_time: i64,
+            pub reserved_flags: u32,
+            pub buffers_lost: u32,
+        }
+
+        pub type EventRecordCallback = unsafe extern "system" fn(record: *mut EventRecord);
+
+        #[repr(C)]
+        pub struct EventTraceLogfileW {
+            pub log_file_name: *mut u16,
+            pub logger_name: *mut u16,
+            pub current_time: i64,
+            pub buffers_read: u32,
+            pub process_trace_mode: u32,
+            pub current_event: EventTrace,
+            pub logfile_header: TraceLogfileHeader,
+            pub buffer_callback: *mut c_void,
+            pub buffer_size: u32,
+            pub filled: u32,
+            pub events_lost: u32,
+            pub event_record_callback: Option<EventRecordCallback>,
+            pub is_kernel_trace: u32,
+            pub context: *mut c_void,
+        }
+
+        #[repr(C)]
+        pub struct EventDescriptor {
+            pub id: u16,
+            pub version: u8,
+            pub channel: u8,
+            pub level: u8,
+            pub opcode: u8,
+            pub task: u16,
+            pub keyword: u64,
+        }
+
+        #[repr(C)]
+        pub struct EventHeader {
+            pub size: u16,
+            pub header_type: u16,
+            pub flags: u16,
+            pub event_property: u16,
+            pub thread_id: u32,
+            pub process_id: u32,
+            pub time_stamp: i64,
+            pub provider_id: Guid,
+            pub event_descriptor: EventDescriptor,
+            pub processor_time: u64,
+            pub activity_id: Guid,
+        }
+
+        #[repr(C)]
+        pub struct EventRecord {
+            pub event_header: EventHeader,
+            pub buffer_context: u32,
+            pub extended_data_count: u16,
+            pub user_data_length: u16,
+            pub extended_data: *mut c_void,
+            pub user_data: *mut c_void,
+            pub user_context: *mut c_void,
+        }
+
+        #[link(name = "advapi32")]
+        unsafe extern "system" {
+            pub fn StartTraceW(handle: *mut u64, name: *const u16, properties: *mut EventTraceProperties) -> u32;
+            pub fn ControlTraceW(
+                handle: u64,
+                name: *const u16,
+                properties: *mut EventTraceProperties,
+                control_code: u32,
+            ) -> u32;
+            pub fn EnableTraceEx2(
+                handle: u64,
+                provider: *const Guid,
+                control_code: u32,
+                level: u8,
+                match_any_keyword: u64,
+                match_all_keyword: u64,
+                timeout: u32,
+                enable_parameters: *const c_void,
+            ) -> u32;
+            pub fn OpenTraceW(logfile: *mut EventTraceLogfileW) -> u64;
+            pub fn ProcessTrace(handles: *const u64, count: u32, start: *const c_void, end: *const c_void) -> u32;
+            pub fn CloseTrace(handle: u64) -> u32;
+        }
+
+        /// Called by ProcessTrace on the session thread for every event
+        pub unsafe extern "system" fn on_event(record: *mut EventRecord) {
+            let record = unsafe { &*record };
+            if record.event_header.provider_id != KERNEL_NETWORK_PROVIDER || record.user_data.is_null() {
+                return;
+            }
+            let payload = unsafe {
+                std::slice::from_raw_parts(record.user_data as *const u8, record.user_data_length.into())
+            };
+            if let Some(event) = KernelNetworkEvent::parse(record.event_header.event_descriptor.id, payload) {
+                let sink = unsafe { &mut *(record.user_context as *mut Sink) };
+                sink(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Payload of a traffic event, laid out as the kernel writes it
+    fn payload(pid: u32, size: u32, remote: (IpAddr, u16), local: (IpAddr, u16)) -> Vec<u8> {
+        let octets = |address: IpAddr| match address {
+            IpAddr::V4(v4) => v4.octets().to_vec(),
+            IpAddr::V6(v6) => v6.octets().to_vec(),
+        };
+        let mut bytes = Vec::new();
+        bytes.extend(pid.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        bytes.extend(octets(remote.0));
+        bytes.extend(octets(local.0));
+        bytes.extend(remote.1.to_be_bytes());
+        bytes.extend(local.1.to_be_bytes());
+        // Start and end times, sequence number and connection id follow
+        bytes.extend([0u8; 16]);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_tcp_receive_over_ipv4() {
+        let remote = ("93.184.216.34".parse().unwrap(), 443);
+        let local = ("192.168.1.10".parse().unwrap(), 51234);
+        let event = KernelNetworkEvent::parse(11, &payload(4242, 1460, remote, local)).unwrap();
+        assert_eq!(event.pid, 4242);
+        assert_eq!(event.size, 1460);
+        assert_eq!(event.transport, TransportProtocol::Tcp);
+        assert_eq!(event.direction, PacketDirection::Inbound);
+        assert_eq!(event.remote, remote);
+        assert_eq!(event.local, local);
+
+        let packet = event.to_packet("Ethernet");
+        assert_eq!(packet.protocol, PacketProtocol::IPv4);
+        assert_eq!(packet.size_bytes, 1460);
+        assert_eq!((packet.source_addr, packet.source_port), (Some(remote.0), Some(443)));
+        assert_eq!((packet.dest_addr, packet.dest_port), (Some(local.0), Some(51234)));
+    }
+
+    #[test]
+    fn test_parse_udp_send_over_ipv6() {
+        let remote = ("2001:4860:4860::8888".parse().unwrap(), 53);
+        let local = ("2001:db8::10".parse().unwrap(), 60000);
+        let event = KernelNetworkEvent::parse(58, &payload(7, 40, remote, local)).unwrap();
+        assert_eq!(event.transport, TransportProtocol::Udp);
+        assert_eq!(event.direction, PacketDirection::Outbound);
+
+        let packet = event.to_packet("Wi-Fi");
+        assert_eq!(packet.protocol, PacketProtocol::IPv6);
+        assert_eq!(packet.direction, PacketDirection::Outbound);
+        assert_eq!((packet.source_addr, packet.source_port), (Some(local.0), Some(60000)));
+        assert_eq!((packet.dest_addr, packet.dest_port), (Some(remote.0), Some(53)));
+    }
+
+    #[test]
+    fn test_loopback_traffic_is_local() {
+        let loopback = ("127.0.0.1".parse().unwrap(), 8080);
+        let event = KernelNetworkEvent::parse(10, &payload(1, 10, loopback, loopback)).unwrap();
+        assert_eq!(event.to_packet("Loopback").direction, PacketDirection::Local);
+    }
+
+    #[test]
+    fn test_other_events_and_short_payloads_are_ignored() {
+        let remote = ("10.0.0.1".parse().unwrap(), 80);
+        let local = ("10.0.0.2".parse().unwrap(), 40000);
+        // 12 is a TCP connect, which carries no traffic
+        assert!(KernelNetworkEvent::parse(12, &payload(1, 0, remote, local)).is_none());
+        assert!(KernelNetworkEvent::parse(10, &payload(1, 10, remote, local)[..19]).is_none());
+        // An IPv4 layout is too short for an IPv6 event
+        assert!(KernelNetworkEvent::parse(26, &payload(1, 10, remote, local)[..20]).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+// Interface hotplug and change events
+// A background thread waits for the operating system to announce a change to the network
+// interfaces, then compares the interface list with the previous one to name what changed:
+//
+// - Linux: rtnetlink link and address groups (RTMGRP_LINK, RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR)
+// - macOS: a PF_ROUTE socket, the routing messages SystemConfiguration itself is driven by
+// - Windows: NotifyIpInterfaceChange from the IP Helper API
+// - Elsewhere: the interface list is compared once per poll interval
+//
+// Announcements often come in bursts (a link coming up, then its addresses), so the thread
+// waits for them to settle before comparing
+
+use anyhow::Result;
+use log::{debug, info, warn};
+#[cfg(all(feature = "capture", not(windows)))]
+use pnet::datalink;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How long the watcher thread blocks before checking whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Quiet time after an announcement before the interface list is compared
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// A change to the system's network interfaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceEvent {
+    /// An interface appeared, e.g. a USB adapter was plugged in or a VPN connected
+    Added(String),
+    /// An interface disappeared
+    Removed(String),
+    /// An interface came up
+    Up(String),
+    /// An interface went down, e.g. a cable was unplugged or Wi-Fi dropped
+    Down(String),
+    /// An interface's addresses changed
+    Changed(String),
+}
+
+impl InterfaceEvent {
+    pub fn interface_name(&self) -> &str {
+        match self {
+            InterfaceEvent::Added(name)
+            | InterfaceEvent::Removed(name)
+            | InterfaceEvent::Up(name)
+            | InterfaceEvent::Down(name)
+            | InterfaceEvent::Changed(name) => name,
+        }
+    }
+
+    /// What happened, as passed to hooks and stored: added, removed, up, down or changed
+    pub fn change(&self) -> &'static str {
+        match self {
+            InterfaceEvent::Added(_) => "added",
+            InterfaceEvent::Removed(_) => "removed",
+            InterfaceEvent::Up(_) => "up",
+            InterfaceEvent::Down(_) => "down",
+            InterfaceEvent::Changed(_) => "changed",
+        }
+    }
+}
+
+impl std::fmt::Display for InterfaceEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceEvent::Added(name) => write!(f, "interface {name} appeared"),
+            InterfaceEvent::Removed(name) => write!(f, "interface {name} disappeared"),
+            InterfaceEvent::Up(name) => write!(f, "interface {name} came up"),
+            InterfaceEvent::Down(name) => write!(f, "interface {name} went down"),
+            InterfaceEvent::Changed(name) => write!(f, "interface {name} changed"),
+        }
+    }
+}
+
+/// State of one interface that events are derived from
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InterfaceSnapshot {
+    up: bool,
+    addresses: Vec<IpAddr>,
+}
+
+/// Current interfaces by name
+#[cfg(all(feature = "capture", not(windows)))]
+fn snapshot() -> HashMap<String, InterfaceSnapshot> {
+    datalink::interfaces()
+        .into_iter()
+        .map(|interface| {
+            let mut addresses: Vec<IpAddr> = interface.ips.iter().map(|network| network.ip()).collect();
+            addresses.sort();
+            (interface.name.clone(), InterfaceSnapshot { up: interface.is_up(), addresses })
+        })
+        .collect()
+}
+
+/// Current interfaces by name, from sysinfo in builds without libpnet and on Windows, where
+/// libpnet needs Npcap's Packet.dll, which may not be installed
+/// The link state is not known there, so only additions, removals and address changes are seen
+#[cfg(any(not(feature = "capture"), windows))]
+fn snapshot() -> HashMap<String, InterfaceSnapshot> {
+    sysinfo::Networks::new_with_refreshed_list()
+        .iter()
+        .map(|(name, data)| {
+            let mut addresses: Vec<IpAddr> = data.ip_networks().iter().map(|network| network.addr).collect();
+            addresses.sort();
+            (name.clone(), InterfaceSnapshot { up: true, addresses })
+        })
+        .collect()
+}
+
+/// Events that turn `old` into `new`, removals first
+fn diff(old: &HashMap<String, InterfaceSnapshot>, new: &HashMap<String, InterfaceSnapshot>) -> Vec<InterfaceEvent> {
+    let mut removed: Vec<InterfaceEvent> = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| InterfaceEvent::Removed(name.clone()))
+        .collect();
+    let mut current: Vec<InterfaceEvent> = new
+        .iter()
+        .filter_map(|(name, state)| match old.get(name) {
+            None => Some(InterfaceEvent::Added(name.clone())),
+            Some(previous) if previous.up != state.up => Some(if state.up {
+                InterfaceEvent::Up(name.clone())
+            } else {
+                InterfaceEvent::Down(name.clone())
+            }),
+            Some(previous) if previous != state => Some(InterfaceEvent::Changed(name.clone())),
+            Some(_) => None,
+        })
+        .collect();
+    removed.sort_by(|a, b| a.interface_name().cmp(b.interface_name()));
+    current.sort_by(|a, b| a.interface_name().cmp(b.interface_name()));
+    removed.extend(current);
+    removed
+}
+
+/// Watches for interfaces appearing, disappearing or changing state
+/// Events queue up until taken with `try_events`; the thread stops when the watcher is dropped
+#[derive(Debug)]
+pub struct InterfaceWatcher {
+    events: Receiver<InterfaceEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InterfaceWatcher {
+    /// Subscribes to the platform's interface notifications and starts the watcher thread
+    pub fn start() -> Result<Self> {
+        let source = ChangeSource::open()?;
+        let (sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name("interface-watcher".to_string())
+            .spawn(move || watch(source, sender, thread_stop))?;
+        info!("Watching for network interface changes");
+        Ok(Self { events, stop, thread: Some(thread) })
+    }
+
+    /// Events seen since the last call, oldest first
+    pub fn try_events(&self) -> Vec<InterfaceEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for InterfaceWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn watch(mut source: ChangeSource, sender: Sender<InterfaceEvent>, stop: Arc<AtomicBool>) {
+    let mut interfaces = snapshot();
+    while !stop.load(Ordering::Relaxed) {
+        match source.wait(POLL_INTERVAL) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(e) => {
+                warn!("Interface change notifications failed, no longer watching: {e}");
+                return;
+            }
+        }
+        while let Ok(true) = source.wait(SETTLE_TIME) {}
+
+        let current = snapshot();
+        for event in diff(&interfaces, &current) {
+            debug!("Network {event}");
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+        interfaces = current;
+    }
+}
+
+/// Socket subscribed to the kernel's link and address notifications
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+struct ChangeSource {
+    fd: std::os::fd::OwnedFd,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl ChangeSource {
+    #[cfg(target_os = "linux")]
+    fn open() -> Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+        let rc = unsafe {
+            libc::bind(
+                std::os::fd::AsRawFd::as_raw_fd(&fd),
+                &address as *const _ as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { fd })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn open() -> Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        let fd = unsafe { libc::socket(libc::PF_ROUTE, libc::SOCK_RAW, libc::AF_UNSPEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { fd: unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) } })
+    }
+
+    /// Whether a notification arrived within `timeout`; pending messages are consumed
+    fn wait(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        use std::os::fd::AsRawFd;
+
+        let mut poll = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        let rc = unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) };
+        if rc < 0 {
+            let error = std::io::Error::last_os_error();
+            return if error.kind() == std::io::ErrorKind::Interrupted { Ok(false) } else { Err(error) };
+        }
+        if rc == 0 {
+            return Ok(false);
+        }
+
+        // Only that something changed matters; the interface list is read afterwards
+        let mut buffer = [0u8; 8192];
+        let read = unsafe {
+            libc::recv(self.fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), libc::MSG_DONTWAIT)
+        };
+        if read < 0 {
+            let error = std::io::Error::last_os_error();
+            // ENOBUFS: notifications were dropped, which still means something changed
+            if error.raw_os_error() != Some(libc::ENOBUFS) && error.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(error);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Registration for the IP Helper interface change callback
+#[cfg(target_os = "windows")]
+struct ChangeSource {
+    handle: ffi::Handle,
+    notified: Receiver<()>,
+    context: *mut Sender<()>,
+}
+
+// The handle and context are only used to cancel the registration from the watcher thread
+#[cfg(target_os = "windows")]
+unsafe impl Send for ChangeSource {}
+
+#[cfg(target_os = "windows")]
+impl ChangeSource {
+    fn open() -> Result<Self> {
+        let (sender, notified) = mpsc::channel();
+        let context = Box::into_raw(Box::new(sender));
+        let mut handle: ffi::Handle = std::ptr::null_mut();
+        let rc = unsafe {
+            ffi::NotifyIpInterfaceChange(ffi::AF_UNSPEC, ffi::on_change, context as *const _, 0, &mut handle)
+        };
+        if rc != 0 {
+            drop(unsafe { Box::from_raw(context) });
+            anyhow::bail!("NotifyIpInterfaceChange failed with error {rc}");
+        }
+        Ok(Self { handle, notified, context })
+    }
+
+    /// Whether a notification arrived within `timeout`
+    fn wait(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        match self.notified.recv_timeout(timeout) {
+            Ok(()) => Ok(true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for ChangeSource {
+    fn drop(&mut self) {
+        // Cancelling waits for running callbacks, after which the context is no longer used
+        unsafe {
+            ffi::CancelMibChangeNotify2(self.handle);
+            drop(Box::from_raw(self.context));
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod ffi {
+    use std::ffi::c_void;
+    use std::sync::mpsc::Sender;
+
+    pub type Handle = *mut c_void;
+    pub const AF_UNSPEC: u16 = 0;
+
+    #[link(name = "iphlpapi")]
+    unsafe extern "system" {
+        pub fn NotifyIpInterfaceChange(
+            family: u16,
+            callback: unsafe extern "system" fn(*const c_void, *const c_void, u32),
+            context: *const c_void,
+            initial_notification: u8,
+            handle: *mut Handle,
+        ) -> u32;
+        pub fn CancelMibChangeNotify2(handle: Handle) -> u32;
+    }
+
+    /// Called by the IP Helper API on its own thread for every interface change
+    pub unsafe extern "system" fn on_change(context: *const c_void, _row: *const c_void, _kind: u32) {
+        let sender = unsafe { &*(context as *const Sender<()>) };
+        let _ = sender.send(());
+    }
+}
+
+/// Stands in for notifications on platforms without a supported API
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+struct ChangeSource;
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+impl ChangeSource {
+    fn open() -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Reports a possible change after every full poll interval, so the list is compared that often
+    fn wait(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        std::thread::sleep(timeout);
+        Ok(timeout >= POLL_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(up: bool, addresses: &[&str]) -> InterfaceSnapshot {
+        InterfaceSnapshot { up, addresses: addresses.iter().map(|a| a.parse().unwrap()).collect() }
+    }
+
+    #[test]
+    fn test_diff_names_added_removed_down_and_changed_interfaces() {
+        let old = HashMap::from([
+            ("eth0".to_string(), interface(true, &["192.168.1.10"])),
+            ("wlan0".to_string(), interface(true, &["192.168.1.20"])),
+            ("usb0".to_string(), interface(true, &[])),
+            ("lo".to_string(), interface(true, &["127.0.0.1"])),
+        ]);
+        let new = HashMap::from([
+            ("eth0".to_string(), interface(false, &["192.168.1.10"])),
+            ("wlan0".to_string(), interface(true, &["192.168.1.21"])),
+            ("tun0".to_string(), interface(true, &["10.8.0.2"])),
+            ("lo".to_string(), interface(true, &["127.0.0.1"])),
+        ]);
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                InterfaceEvent::Removed("usb0".to_string()),
+                InterfaceEvent::Down("eth0".to_string()),
+                InterfaceEvent::Added("tun0".to_string()),
+                InterfaceEvent::Changed("wlan0".to_string()),
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+        assert_eq!(diff(&new, &old)[1], InterfaceEvent::Up("eth0".to_string()));
+    }
+}
@@ -57,6 +57,29 @@ pub enum VirtualInterfaceType {
     Other,
 }
 
+impl EnhancedInterfaceType {
+    /// Short name of the type, as the interface picker and the live dashboard list it
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Ethernet { subtype: EthernetSubtype::Thunderbolt } => "Thunderbolt",
+            Self::Ethernet { subtype: EthernetSubtype::USB } => "USB Ethernet",
+            Self::Ethernet { .. } => "Ethernet",
+            Self::WiFi { .. } => "Wi-Fi",
+            Self::Loopback => "Loopback",
+            Self::Virtual { virtual_type } => match virtual_type {
+                VirtualInterfaceType::VPN => "VPN",
+                VirtualInterfaceType::Container => "Container",
+                VirtualInterfaceType::VM => "VM",
+                VirtualInterfaceType::Bridge => "Bridge",
+                VirtualInterfaceType::Tunnel => "Tunnel",
+                VirtualInterfaceType::AppleVirtual => "Apple virtual",
+                VirtualInterfaceType::Other => "Virtual",
+            },
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
 /// Interface relevance score for prioritization
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub struct InterfaceRelevance {
@@ -685,4 +708,13 @@ mod tests {
         let (cache_size, _) = manager.get_cache_stats();
         assert_eq!(cache_size, 0);
     }
+
+    #[test]
+    fn test_type_label() {
+        let vpn = EnhancedInterfaceType::Virtual { virtual_type: VirtualInterfaceType::VPN };
+        assert_eq!(vpn.label(), "VPN");
+        let usb = EnhancedInterfaceType::Ethernet { subtype: EthernetSubtype::USB };
+        assert_eq!(usb.label(), "USB Ethernet");
+        assert_eq!(EnhancedInterfaceType::WiFi { standard: None }.label(), "Wi-Fi");
+    }
 }
\ No newline at end of file
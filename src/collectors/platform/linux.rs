@@ -3,8 +3,13 @@ use async_trait::async_trait;
 use log::{debug, info, warn};
 use std::process::Command;
 
+use super::prerequisites::{self, CheckStatus, Fix, Prerequisite};
 use super::PlatformPacketCapture;
 
+/// Bit numbers of the capabilities in the sets of /proc/<pid>/status
+const CAP_NET_ADMIN: u32 = 12;
+const CAP_NET_RAW: u32 = 13;
+
 pub struct LinuxPacketCapture {
     interface: Option<String>,
 }
@@ -14,22 +19,68 @@ impl LinuxPacketCapture {
         Ok(Self { interface: None })
     }
 
+    /// Raw sockets need CAP_NET_RAW in the effective set, whether from root or the binary's file capabilities
     fn check_capabilities() -> Result<bool> {
-        if nix::unistd::getuid().is_root() {
-            return Ok(true);
+        match Self::effective_capabilities() {
+            Some(capabilities) => Ok(capabilities & (1 << CAP_NET_RAW) != 0),
+            // Without /proc only root is known to be able to capture
+            None => Ok(nix::unistd::getuid().is_root()),
         }
+    }
 
-        let output = Command::new("getcap")
-            .arg("/proc/self/exe")
-            .output()
-            .context("Failed to check capabilities")?;
+    fn effective_capabilities() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        parse_capability_set(&status, "CapEff")
+    }
 
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        
-        let has_net_raw = output_str.contains("cap_net_raw");
-        let has_net_admin = output_str.contains("cap_net_admin");
+    /// What `kw doctor` reports for Linux
+    pub fn prerequisites() -> Vec<Prerequisite> {
+        let capabilities = Self::effective_capabilities().unwrap_or(0);
+        let can_capture = Self::check_privileges().unwrap_or(false);
+        let (status, detail) = if can_capture && capabilities & (1 << CAP_NET_ADMIN) != 0 {
+            let source = if nix::unistd::getuid().is_root() { "running as root" } else { "file capabilities" };
+            (CheckStatus::Ok, format!("CAP_NET_RAW and CAP_NET_ADMIN in effect ({source})"))
+        } else if can_capture {
+            (
+                CheckStatus::Warning,
+                "CAP_NET_RAW in effect, but without CAP_NET_ADMIN interfaces cannot be put in promiscuous mode".to_string(),
+            )
+        } else {
+            (
+                CheckStatus::Missing,
+                format!(
+                    "Required: {}; run kw with sudo, use --privileged-helper, or grant them to the binary",
+                    Self::get_required_capabilities().join(", ")
+                ),
+            )
+        };
+
+        let mut capture = Prerequisite::new("Packet capture privileges", status, detail);
+        if status != CheckStatus::Ok {
+            match Self::setcap_fix() {
+                Some(fix) => capture = capture.with_fix(fix),
+                None => capture.detail.push_str("; install setcap (libcap2-bin or libcap) to grant them"),
+            }
+        }
+        vec![capture]
+    }
 
-        Ok(has_net_raw || has_net_admin)
+    /// Grants the capture capabilities to this binary, so it captures without sudo
+    fn setcap_fix() -> Option<Fix> {
+        let setcap = prerequisites::find_program("setcap")?;
+        let exe = std::env::current_exe().ok()?;
+        Some(Fix {
+            description: format!(
+                "Grant CAP_NET_RAW and CAP_NET_ADMIN to {} (repeat after upgrading or rebuilding kw)",
+                exe.display()
+            ),
+            commands: vec![vec![
+                setcap.display().to_string(),
+                "cap_net_raw,cap_net_admin=eip".to_string(),
+                exe.display().to_string(),
+            ]],
+            privileged: true,
+        })
     }
 
     fn set_socket_options() -> Result<()> {
@@ -113,10 +164,26 @@ impl PlatformPacketCapture for LinuxPacketCapture {
     }
 }
 
+/// Capability bits from a `Cap*:` line of /proc/<pid>/status, e.g. `CapEff:\t0000000000003000`
+fn parse_capability_set(status: &str, set: &str) -> Option<u64> {
+    status.lines().find_map(|line| {
+        let value = line.strip_prefix(set)?.strip_prefix(':')?;
+        u64::from_str_radix(value.trim(), 16).ok()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_capability_set() {
+        let status = "Name:\tkw\nCapInh:\t0000000000000000\nCapPrm:\t0000000000003000\nCapEff:\t0000000000002000\n";
+        assert_eq!(parse_capability_set(status, "CapEff"), Some(1 << CAP_NET_RAW));
+        assert_eq!(parse_capability_set(status, "CapPrm"), Some((1 << CAP_NET_RAW) | (1 << CAP_NET_ADMIN)));
+        assert_eq!(parse_capability_set(status, "CapBnd"), None);
+    }
+
     #[test]
     fn test_linux_packet_capture_creation() {
         let capture = LinuxPacketCapture::new();
@@ -3,8 +3,12 @@ use async_trait::async_trait;
 use log::{debug, info, warn};
 use std::process::Command;
 
+use super::prerequisites::{CheckStatus, Fix, Prerequisite};
 use super::PlatformPacketCapture;
 
+/// Group given read and write access to the BPF devices, as Wireshark's ChmodBPF sets up
+const BPF_GROUP: &str = "access_bpf";
+
 pub struct MacOSPacketCapture {
     interface: Option<String>,
 }
@@ -35,6 +39,104 @@ impl MacOSPacketCapture {
         Ok(success)
     }
 
+    /// /dev/bpf0, /dev/bpf1 and so on
+    fn bpf_devices() -> Vec<std::path::PathBuf> {
+        let mut devices: Vec<_> = std::fs::read_dir("/dev")
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.file_name()
+                            .and_then(|name| name.to_str())
+                            .and_then(|name| name.strip_prefix("bpf"))
+                            .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        devices.sort();
+        devices
+    }
+
+    /// Whether this user can open the BPF devices without root
+    fn bpf_devices_accessible() -> bool {
+        Self::bpf_devices().iter().any(|device| {
+            let Ok(path) = std::ffi::CString::new(device.as_os_str().as_encoded_bytes()) else {
+                return false;
+            };
+            // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+            unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) == 0 }
+        })
+    }
+
+    /// What `kw doctor` reports for macOS
+    pub fn prerequisites() -> Vec<Prerequisite> {
+        let devices = Self::bpf_devices();
+        let bpf = if devices.is_empty() {
+            Prerequisite::new("BPF devices", CheckStatus::Missing, "No /dev/bpf* devices found")
+        } else {
+            Prerequisite::new("BPF devices", CheckStatus::Ok, format!("{} devices", devices.len()))
+        };
+
+        let capture = if Self::check_admin_privileges().unwrap_or(false) {
+            Prerequisite::new("Packet capture privileges", CheckStatus::Ok, "Running as root")
+        } else if Self::bpf_devices_accessible() {
+            Prerequisite::new(
+                "Packet capture privileges",
+                CheckStatus::Ok,
+                "BPF devices are readable and writable by this user",
+            )
+        } else {
+            let missing = Prerequisite::new(
+                "Packet capture privileges",
+                CheckStatus::Missing,
+                format!(
+                    "Required: {}; run kw with sudo, use --privileged-helper, or give your user access to the BPF devices",
+                    Self::get_required_capabilities().join(" or ")
+                ),
+            );
+            match Self::bpf_access_fix(&devices) {
+                Some(fix) => missing.with_fix(fix),
+                None => missing,
+            }
+        };
+        vec![capture, bpf]
+    }
+
+    /// Gives the current user's group read and write access to the BPF devices
+    fn bpf_access_fix(devices: &[std::path::PathBuf]) -> Option<Fix> {
+        if devices.is_empty() {
+            return None;
+        }
+        let user = std::env::var("SUDO_USER").or_else(|_| std::env::var("USER")).ok()?;
+        let group_exists = Command::new("dscl")
+            .args([".", "-read", &format!("/Groups/{BPF_GROUP}")])
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+        let devices: Vec<String> = devices.iter().map(|device| device.display().to_string()).collect();
+        let command = |args: &[&str], paths: &[String]| -> Vec<String> {
+            args.iter().map(|arg| arg.to_string()).chain(paths.iter().cloned()).collect()
+        };
+
+        let mut commands = Vec::new();
+        if !group_exists {
+            commands.push(command(&["dseditgroup", "-o", "create", BPF_GROUP], &[]));
+        }
+        commands.push(command(&["dseditgroup", "-o", "edit", "-a", &user, "-t", "user", BPF_GROUP], &[]));
+        commands.push(command(&["chgrp", BPF_GROUP], &devices));
+        commands.push(command(&["chmod", "g+rw"], &devices));
+        Some(Fix {
+            description: format!(
+                "Add {user} to the {BPF_GROUP} group and give it read/write access to the BPF devices \
+(device permissions reset at reboot; log in again for the group to apply)"
+            ),
+            commands,
+            privileged: true,
+        })
+    }
+
     fn get_available_interfaces() -> Result<Vec<String>> {
         let output = Command::new("ifconfig")
             .arg("-l")
@@ -70,9 +172,9 @@ impl PlatformPacketCapture for MacOSPacketCapture {
     async fn start_capture(&mut self, interface_name: &str) -> Result<()> {
         info!("Starting macOS packet capture on interface: {}", interface_name);
         
-        if !Self::check_admin_privileges()? {
+        if !Self::check_privileges()? {
             return Err(anyhow::anyhow!(
-                "Administrator privileges required for packet capture on macOS"
+                "Administrator privileges or BPF device access required for packet capture on macOS"
             ));
         }
 
@@ -106,7 +208,7 @@ impl PlatformPacketCapture for MacOSPacketCapture {
     }
 
     fn check_privileges() -> Result<bool> {
-        Self::check_admin_privileges()
+        Ok(Self::check_admin_privileges()? || Self::bpf_devices_accessible())
     }
 
     fn get_required_capabilities() -> Vec<String> {
@@ -1,5 +1,8 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 // Platform-specific packet capture implementations
 // Provides optimized packet capture for different operating systems
@@ -9,11 +12,42 @@ use async_trait::async_trait;
 /// Provides intelligent interface filtering, type detection, and relevance scoring
 pub mod interface_manager;
 
+/// What capture needs on this platform, for `kw doctor`
+pub mod prerequisites;
+
+/// Interface hotplug and change notifications
+/// Netlink on Linux, the routing socket on macOS and the IP Helper API on Windows
+pub mod interface_events;
+
+/// System suspend and resume notifications
+/// systemd-logind on Linux, IOKit on macOS and the power management API on Windows
+pub mod power_events;
+
 /// Linux packet capture implementation
 /// Uses AF_PACKET sockets with CAP_NET_RAW capability requirements
 #[cfg(target_os = "linux")]
 pub mod linux;
 
+/// TPACKET_V3 memory-mapped receive ring for AF_PACKET sockets
+/// The packet collector's capture path on Linux, with a per-packet fallback through libpnet
+#[cfg(all(target_os = "linux", feature = "capture"))]
+pub mod af_packet;
+
+/// Network namespaces, their host veths and the Docker or Podman containers they belong to
+/// Backs `--namespace` and `--container` on `status` and `packets`
+#[cfg(target_os = "linux")]
+pub mod namespaces;
+
+/// AF_XDP sockets fed by an XDP program, the opt-in capture path for mirror ports
+/// Falls back to AF_PACKET where the kernel, driver or interface does not allow it
+#[cfg(all(target_os = "linux", feature = "capture"))]
+pub mod af_xdp;
+
+/// pktap pseudo-interfaces read through BPF, the packet collector's capture path on macOS
+/// Every frame arrives tagged with the process that sent or received it
+#[cfg(all(feature = "capture", any(target_os = "macos", test)))]
+pub mod pktap;
+
 /// macOS packet capture implementation  
 /// Uses Berkeley Packet Filter (BPF) devices requiring root privileges
 #[cfg(target_os = "macos")]
@@ -24,6 +58,45 @@ pub mod macos;
 #[cfg(target_os = "windows")]
 pub mod windows;
 
+/// Kernel-Network ETW events, the capture path on Windows when Npcap is not installed
+/// Per-process and per-connection byte counts without a capture driver
+#[cfg(all(feature = "capture", any(target_os = "windows", test)))]
+pub mod etw;
+
+/// Kernel interface the capture threads read from on Linux; other platforms always use libpnet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureBackend {
+    /// TPACKET_V3 ring on an AF_PACKET socket; the host keeps receiving the traffic
+    #[default]
+    AfPacket,
+    /// AF_XDP sockets fed by an XDP program on every receive queue. Captured frames never
+    /// reach the host's network stack, so it is only used on interfaces without addresses
+    /// other than IPv6 link-local ones (mirror ports, taps) and AF_PACKET is used everywhere else
+    AfXdp,
+}
+
+impl fmt::Display for CaptureBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CaptureBackend::AfPacket => "af-packet",
+            CaptureBackend::AfXdp => "af-xdp",
+        })
+    }
+}
+
+impl FromStr for CaptureBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "af-packet" => Ok(CaptureBackend::AfPacket),
+            "af-xdp" => Ok(CaptureBackend::AfXdp),
+            _ => Err(anyhow!("Unknown capture backend '{s}' (expected af-packet or af-xdp)")),
+        }
+    }
+}
+
 #[async_trait]
 pub trait PlatformPacketCapture: Send + Sync {
     async fn start_capture(&mut self, interface_name: &str) -> Result<()>;
@@ -94,4 +167,24 @@ pub fn check_packet_capture_support() -> Result<()> {
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Whether the current process can open raw capture channels itself
+/// Used to decide if a capture helper must be launched through sudo
+pub fn has_capture_privileges() -> bool {
+    check_packet_capture_support().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_backend_names_round_trip() {
+        for backend in [CaptureBackend::AfPacket, CaptureBackend::AfXdp] {
+            assert_eq!(backend.to_string().parse::<CaptureBackend>().unwrap(), backend);
+        }
+        assert_eq!(CaptureBackend::default(), CaptureBackend::AfPacket);
+        assert!("xdp".parse::<CaptureBackend>().is_err());
+    }
+}
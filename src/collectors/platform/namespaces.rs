@@ -0,0 +1,642 @@
+// Network namespaces and the containers behind them, on Linux
+// Traffic of a container never crosses a host interface of its own; it leaves its namespace
+// through a veth pair whose host end is an ordinary interface, so watching a container means
+// watching the host ends of its veths:
+//
+// - Namespaces: every process's /proc/<pid>/ns/net, plus the named ones in /var/run/netns
+// - Veths: an rtnetlink link dump names the namespace each veth's peer lives in by its netns id,
+//   and RTM_GETNSID gives the id of each namespace found above
+// - Containers: Docker and Podman answer on their Unix sockets with each container's name and
+//   the pid whose namespace it runs in
+//
+// Other users' namespaces and the runtimes' sockets are usually only readable as root
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::collectors::interface_selection::glob_match;
+
+/// Where `ip netns add` keeps named namespaces
+const NAMED_NETNS_DIR: &str = "/var/run/netns";
+
+/// How long a container runtime gets to answer before it is left out
+const RUNTIME_TIMEOUT: Duration = Duration::from_secs(2);
+
+// rtnetlink values not exported by libc on every target
+const NLMSG_HEADER_LEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const RTGENMSG_LEN: usize = 4;
+const NETNSA_NSID: u16 = 1;
+const NETNSA_FD: u16 = 3;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_INFO_KIND: u16 = 1;
+const IFLA_LINK_NETNSID: u16 = 37;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const RTM_NEWLINK: u16 = 16;
+const RTM_NEWNSID: u16 = 88;
+/// Attribute type bits that only say how the payload is laid out
+const NLA_TYPE_MASK: u16 = !(1 << 15 | 1 << 14);
+
+/// A network namespace other than the host's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkNamespace {
+    /// Inode of the namespace, as in `net:[4026532289]`
+    pub inode: u64,
+    /// Name given by `ip netns add`
+    pub name: Option<String>,
+    /// Lowest pid running in the namespace
+    pub pid: Option<u32>,
+    /// Docker or Podman container the namespace belongs to
+    pub container: Option<Container>,
+    /// Host ends of the veths whose peer is in the namespace
+    pub interfaces: Vec<String>,
+}
+
+/// A container as its runtime names it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub id: String,
+    pub name: String,
+    /// `docker` or `podman`
+    pub runtime: &'static str,
+}
+
+impl NetworkNamespace {
+    /// Whether `--namespace <pattern>` picks this namespace: its name or a glob of it,
+    /// its inode, `net:[inode]`, or a pid running in it
+    pub fn matches_namespace(&self, pattern: &str) -> bool {
+        if let Some(name) = &self.name
+            && (name == pattern || glob_match(pattern, name))
+        {
+            return true;
+        }
+        let number = parse_ns_link(pattern).or_else(|| pattern.parse().ok());
+        number.is_some_and(|number| number == self.inode || Some(number) == self.pid.map(u64::from))
+    }
+
+    /// Whether `--container <pattern>` picks this namespace's container: its name or a glob
+    /// of it, or a prefix of its id
+    pub fn matches_container(&self, pattern: &str) -> bool {
+        self.container.as_ref().is_some_and(|container| {
+            container.name == pattern || glob_match(pattern, &container.name) || container.id.starts_with(pattern)
+        })
+    }
+}
+
+impl fmt::Display for NetworkNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.container, &self.name, self.pid) {
+            (Some(container), _, _) => write!(f, "container {} ({})", container.name, container.runtime),
+            (None, Some(name), _) => write!(f, "netns {name}"),
+            (None, None, Some(pid)) => write!(f, "netns net:[{}] (pid {pid})", self.inode),
+            (None, None, None) => write!(f, "netns net:[{}]", self.inode),
+        }
+    }
+}
+
+/// The network namespaces other than the host's, with their containers and host veths
+pub fn discover() -> Result<Vec<NetworkNamespace>> {
+    let host = std::fs::metadata("/proc/self/ns/net")
+        .context("Cannot read this process's network namespace")?
+        .ino();
+
+    let mut namespaces: BTreeMap<u64, NetworkNamespace> = BTreeMap::new();
+
+    // Pids come in directory order, so keep the lowest per namespace
+    for (pid, inode) in process_namespaces().into_iter().filter(|(_, inode)| *inode != host) {
+        let namespace = entry(&mut namespaces, inode);
+        if namespace.pid.is_none_or(|lowest| pid < lowest) {
+            namespace.pid = Some(pid);
+        }
+    }
+    for (name, inode) in named_namespaces().into_iter().filter(|(_, inode)| *inode != host) {
+        entry(&mut namespaces, inode).name = Some(name);
+    }
+    for (container, pid) in containers() {
+        match namespace_inode(pid) {
+            Some(inode) if inode != host => entry(&mut namespaces, inode).container = Some(container),
+            _ => debug!("Container {} is not in a namespace of its own", container.name),
+        }
+    }
+
+    let mut namespaces: Vec<NetworkNamespace> = namespaces.into_values().collect();
+    if let Err(e) = netlink::Socket::open().and_then(|socket| attach_veths(&socket, &mut namespaces)) {
+        debug!("Veths could not be matched to namespaces: {e:#}");
+    }
+    Ok(namespaces)
+}
+
+fn entry(namespaces: &mut BTreeMap<u64, NetworkNamespace>, inode: u64) -> &mut NetworkNamespace {
+    namespaces.entry(inode).or_insert_with(|| NetworkNamespace {
+        inode,
+        name: None,
+        pid: None,
+        container: None,
+        interfaces: Vec::new(),
+    })
+}
+
+/// Namespaces picked by `--namespace` and `--container`; every pattern has to pick at least one
+pub fn select<'a>(
+    namespaces: &'a [NetworkNamespace],
+    namespace_patterns: &[String],
+    container_patterns: &[String],
+) -> Result<Vec<&'a NetworkNamespace>> {
+    let mut selected: Vec<&NetworkNamespace> = Vec::new();
+    let mut pick = |pattern: &str, kind: &str, matches: &dyn Fn(&NetworkNamespace) -> bool| -> Result<()> {
+        let found: Vec<&NetworkNamespace> = namespaces.iter().filter(|namespace| matches(namespace)).collect();
+        if found.is_empty() {
+            let known: Vec<String> = namespaces.iter().map(|namespace| namespace.to_string()).collect();
+            bail!(
+                "No {kind} matches '{pattern}'{}",
+                if known.is_empty() { " (none found; are you root?)".to_string() } else { format!("; found: {}", known.join(", ")) }
+            );
+        }
+        for namespace in found {
+            if !selected.iter().any(|picked| picked.inode == namespace.inode) {
+                selected.push(namespace);
+            }
+        }
+        Ok(())
+    };
+    for pattern in namespace_patterns {
+        pick(pattern, "network namespace", &|namespace| namespace.matches_namespace(pattern))?;
+    }
+    for pattern in container_patterns {
+        pick(pattern, "container", &|namespace| namespace.matches_container(pattern))?;
+    }
+
+    if let Some(isolated) = selected.iter().find(|namespace| namespace.interfaces.is_empty()) {
+        bail!("{isolated} has no veth to the host, so its traffic cannot be watched from here");
+    }
+    Ok(selected)
+}
+
+/// Host veth name → the namespace it leads into, for labelling interfaces
+pub fn interface_owners<'a>(namespaces: impl IntoIterator<Item = &'a NetworkNamespace>) -> HashMap<String, String> {
+    namespaces
+        .into_iter()
+        .flat_map(|namespace| namespace.interfaces.iter().map(move |interface| (interface.clone(), namespace.to_string())))
+        .collect()
+}
+
+/// Inode from a namespace link such as `net:[4026531840]`
+fn parse_ns_link(link: &str) -> Option<u64> {
+    link.strip_prefix("net:[")?.strip_suffix(']')?.parse().ok()
+}
+
+fn namespace_inode(pid: u32) -> Option<u64> {
+    let link = std::fs::read_link(format!("/proc/{pid}/ns/net")).ok()?;
+    parse_ns_link(link.to_str()?)
+}
+
+/// (pid, namespace inode) of every process whose namespace can be read
+fn process_namespaces() -> Vec<(u32, u64)> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| Some((pid, namespace_inode(pid)?)))
+        .collect()
+}
+
+/// (name, namespace inode) of the namespaces created with `ip netns add`
+fn named_namespaces() -> Vec<(String, u64)> {
+    let Ok(entries) = std::fs::read_dir(NAMED_NETNS_DIR) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            // The files are bind mounts of the namespace, so their inode is the namespace's
+            let inode = std::fs::metadata(entry.path()).ok()?.ino();
+            Some((entry.file_name().to_str()?.to_string(), inode))
+        })
+        .collect()
+}
+
+/// Fills in each namespace's host veths from the link dump and the namespaces' netns ids
+fn attach_veths(socket: &netlink::Socket, namespaces: &mut [NetworkNamespace]) -> Result<()> {
+    let links = socket.links()?;
+    for namespace in namespaces.iter_mut() {
+        let handle = match (&namespace.name, namespace.pid) {
+            (_, Some(pid)) => PathBuf::from(format!("/proc/{pid}/ns/net")),
+            (Some(name), None) => Path::new(NAMED_NETNS_DIR).join(name),
+            (None, None) => continue,
+        };
+        let Some(nsid) = socket.netns_id(&handle)? else {
+            continue;
+        };
+        namespace.interfaces = links
+            .iter()
+            .filter(|link| link.kind.as_deref() == Some("veth") && link.peer_netns_id == Some(nsid))
+            .map(|link| link.name.clone())
+            .collect();
+    }
+    Ok(())
+}
+
+/// A host interface from the rtnetlink link dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Link {
+    name: String,
+    /// `IFLA_INFO_KIND`, e.g. `veth` or `bridge`
+    kind: Option<String>,
+    /// Netns id of the namespace the peer is in, for veths whose peer is elsewhere
+    peer_netns_id: Option<i32>,
+}
+
+/// What a batch of netlink messages held
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Messages {
+    links: Vec<Link>,
+    netns_id: Option<i32>,
+    /// The dump ended, or the request was answered
+    done: bool,
+}
+
+/// Splits a batch of netlink messages and reads the links and netns ids in them
+fn parse_messages(mut buffer: &[u8]) -> Result<Messages> {
+    let mut messages = Messages::default();
+    while buffer.len() >= NLMSG_HEADER_LEN {
+        let length = u32::from_ne_bytes(buffer[0..4].try_into().unwrap()) as usize;
+        let kind = u16::from_ne_bytes(buffer[4..6].try_into().unwrap());
+        if length < NLMSG_HEADER_LEN || length > buffer.len() {
+            bail!("Truncated netlink message");
+        }
+        let payload = &buffer[NLMSG_HEADER_LEN..length];
+        match kind {
+            NLMSG_DONE => messages.done = true,
+            NLMSG_ERROR => {
+                let code = payload.get(0..4).map(|code| i32::from_ne_bytes(code.try_into().unwrap())).unwrap_or(0);
+                if code != 0 {
+                    return Err(std::io::Error::from_raw_os_error(-code)).context("Netlink request failed");
+                }
+                messages.done = true;
+            }
+            RTM_NEWLINK if payload.len() >= IFINFOMSG_LEN => {
+                messages.links.extend(parse_link(&payload[IFINFOMSG_LEN..]));
+            }
+            RTM_NEWNSID if payload.len() >= RTGENMSG_LEN => {
+                messages.netns_id = attributes(&payload[RTGENMSG_LEN..])
+                    .find(|(kind, _)| *kind == NETNSA_NSID)
+                    .and_then(|(_, value)| Some(i32::from_ne_bytes(value.get(0..4)?.try_into().ok()?)))
+                    .filter(|nsid| *nsid >= 0);
+                messages.done = true;
+            }
+            _ => {}
+        }
+        buffer = &buffer[align(length).min(buffer.len())..];
+    }
+    Ok(messages)
+}
+
+fn parse_link(attributes_data: &[u8]) -> Option<Link> {
+    let mut link = Link { name: String::new(), kind: None, peer_netns_id: None };
+    for (kind, value) in attributes(attributes_data) {
+        match kind {
+            IFLA_IFNAME => link.name = c_string(value),
+            IFLA_LINKINFO => {
+                link.kind = attributes(value).find(|(kind, _)| *kind == IFLA_INFO_KIND).map(|(_, value)| c_string(value));
+            }
+            IFLA_LINK_NETNSID => link.peer_netns_id = value.get(0..4).map(|id| i32::from_ne_bytes(id.try_into().unwrap())),
+            _ => {}
+        }
+    }
+    (!link.name.is_empty()).then_some(link)
+}
+
+/// (type, payload) of each route attribute in `data`
+fn attributes(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let length = u16::from_ne_bytes([data[0], data[1]]) as usize;
+        let kind = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+        if length < 4 || length > data.len() {
+            return None;
+        }
+        let value = &data[4..length];
+        data = &data[align(length).min(data.len())..];
+        Some((kind, value))
+    })
+}
+
+fn c_string(value: &[u8]) -> String {
+    let end = value.iter().position(|byte| *byte == 0).unwrap_or(value.len());
+    String::from_utf8_lossy(&value[..end]).into_owned()
+}
+
+fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+mod netlink {
+    use super::*;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    /// A NETLINK_ROUTE socket for one request at a time
+    pub struct Socket {
+        fd: OwnedFd,
+    }
+
+    impl Socket {
+        pub fn open() -> Result<Self> {
+            let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let socket = Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } };
+
+            let timeout = libc::timeval { tv_sec: RUNTIME_TIMEOUT.as_secs() as libc::time_t, tv_usec: 0 };
+            unsafe {
+                libc::setsockopt(
+                    socket.fd.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    &timeout as *const _ as *const libc::c_void,
+                    std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+                );
+            }
+            Ok(socket)
+        }
+
+        /// Every interface in the host's namespace
+        pub fn links(&self) -> Result<Vec<Link>> {
+            let mut request = header(libc::RTM_GETLINK, (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16);
+            request.extend_from_slice(&[0u8; IFINFOMSG_LEN]);
+            Ok(self.exchange(request)?.links)
+        }
+
+        /// The id this namespace knows the namespace at `handle` by, `None` when it has none
+        pub fn netns_id(&self, handle: &Path) -> Result<Option<i32>> {
+            let Ok(file) = std::fs::File::open(handle) else {
+                return Ok(None);
+            };
+            let mut request = header(libc::RTM_GETNSID, libc::NLM_F_REQUEST as u16);
+            request.extend_from_slice(&[0u8; RTGENMSG_LEN]);
+            request.extend_from_slice(&8u16.to_ne_bytes());
+            request.extend_from_slice(&NETNSA_FD.to_ne_bytes());
+            request.extend_from_slice(&(file.as_raw_fd() as u32).to_ne_bytes());
+            Ok(self.exchange(request)?.netns_id)
+        }
+
+        /// Sends `request` and gathers the replies until the kernel says it is done
+        fn exchange(&self, mut request: Vec<u8>) -> Result<Messages> {
+            let length = request.len() as u32;
+            request[0..4].copy_from_slice(&length.to_ne_bytes());
+            let sent = unsafe { libc::send(self.fd.as_raw_fd(), request.as_ptr() as *const libc::c_void, request.len(), 0) };
+            if sent < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            let mut gathered = Messages::default();
+            let mut buffer = vec![0u8; 32 * 1024];
+            while !gathered.done {
+                let read = unsafe { libc::recv(self.fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+                if read < 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                let messages = parse_messages(&buffer[..read as usize])?;
+                gathered.links.extend(messages.links);
+                gathered.netns_id = gathered.netns_id.or(messages.netns_id);
+                gathered.done = messages.done || read == 0;
+            }
+            Ok(gathered)
+        }
+    }
+
+    /// Message header with the length left for `exchange` to fill in
+    fn header(kind: u16, flags: u16) -> Vec<u8> {
+        let mut header = Vec::with_capacity(64);
+        header.extend_from_slice(&0u32.to_ne_bytes());
+        header.extend_from_slice(&kind.to_ne_bytes());
+        header.extend_from_slice(&flags.to_ne_bytes());
+        header.extend_from_slice(&1u32.to_ne_bytes());
+        header.extend_from_slice(&0u32.to_ne_bytes());
+        header
+    }
+}
+
+/// A container from `GET /containers/json`
+#[derive(Debug, Deserialize)]
+struct ContainerSummary {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "Names", default)]
+    names: Vec<String>,
+}
+
+/// The part of `GET /containers/<id>/json` naming the container's main process
+#[derive(Debug, Deserialize)]
+struct ContainerInspect {
+    #[serde(rename = "State")]
+    state: ContainerState,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerState {
+    #[serde(rename = "Pid", default)]
+    pid: u32,
+}
+
+/// Docker and Podman API sockets, the `DOCKER_HOST` one first when it is a Unix socket
+fn runtime_sockets() -> Vec<(&'static str, PathBuf)> {
+    let mut sockets = Vec::new();
+    if let Some(path) = std::env::var("DOCKER_HOST").ok().and_then(|host| host.strip_prefix("unix://").map(PathBuf::from)) {
+        sockets.push(("docker", path));
+    }
+    sockets.push(("docker", PathBuf::from("/var/run/docker.sock")));
+    sockets.push(("podman", PathBuf::from("/run/podman/podman.sock")));
+    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+        sockets.push(("podman", Path::new(&runtime_dir).join("podman/podman.sock")));
+    }
+    sockets
+}
+
+/// Running containers of every runtime that answers, with the pid of each one's main process
+fn containers() -> Vec<(Container, u32)> {
+    let mut found: Vec<(Container, u32)> = Vec::new();
+    for (runtime, socket) in runtime_sockets() {
+        if !socket.exists() {
+            continue;
+        }
+        match runtime_containers(runtime, &socket) {
+            // Podman's Docker-compatible socket can list the same containers twice
+            Ok(containers) => {
+                for (container, pid) in containers {
+                    if !found.iter().any(|(known, _)| known.id == container.id) {
+                        found.push((container, pid));
+                    }
+                }
+            }
+            Err(e) => debug!("No containers from {}: {e:#}", socket.display()),
+        }
+    }
+    found
+}
+
+fn runtime_containers(runtime: &'static str, socket: &Path) -> Result<Vec<(Container, u32)>> {
+    let summaries: Vec<ContainerSummary> = serde_json::from_slice(&http_get(socket, "/containers/json")?)?;
+    let mut containers = Vec::new();
+    for summary in summaries {
+        let inspect: ContainerInspect = serde_json::from_slice(&http_get(socket, &format!("/containers/{}/json", summary.id))?)?;
+        if inspect.state.pid == 0 {
+            continue;
+        }
+        let name = summary.names.first().map(|name| name.trim_start_matches('/').to_string());
+        let name = name.unwrap_or_else(|| summary.id.chars().take(12).collect());
+        containers.push((Container { id: summary.id, name, runtime }, inspect.state.pid));
+    }
+    Ok(containers)
+}
+
+/// Body of a `GET` over a Unix socket; HTTP/1.0 so the runtime neither chunks nor keeps it open
+fn http_get(socket: &Path, path: &str) -> Result<Vec<u8>> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(RUNTIME_TIMEOUT))?;
+    stream.set_write_timeout(Some(RUNTIME_TIMEOUT))?;
+    write!(stream, "GET {path} HTTP/1.0\r\nHost: localhost\r\n\r\n")?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    http_body(&response).map(<[u8]>::to_vec)
+}
+
+/// The body of a `200` response
+fn http_body(response: &[u8]) -> Result<&[u8]> {
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Incomplete HTTP response"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        bail!("Unexpected response: {status}");
+    }
+    Ok(&response[split + 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+        encoded.extend_from_slice(&kind.to_ne_bytes());
+        encoded.extend_from_slice(value);
+        encoded.resize(align(encoded.len()), 0);
+        encoded
+    }
+
+    fn message(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&((NLMSG_HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+        encoded.extend_from_slice(&kind.to_ne_bytes());
+        encoded.extend_from_slice(&[0u8; 10]);
+        encoded.extend_from_slice(payload);
+        encoded
+    }
+
+    fn namespace(inode: u64, name: Option<&str>, pid: Option<u32>, container: Option<&str>) -> NetworkNamespace {
+        NetworkNamespace {
+            inode,
+            name: name.map(str::to_string),
+            pid,
+            container: container.map(|name| Container { id: format!("{name}0123456789abcdef"), name: name.to_string(), runtime: "docker" }),
+            interfaces: vec![format!("veth{inode}")],
+        }
+    }
+
+    #[test]
+    fn test_parse_ns_link() {
+        assert_eq!(parse_ns_link("net:[4026531840]"), Some(4026531840));
+        assert_eq!(parse_ns_link("mnt:[4026531840]"), None);
+        assert_eq!(parse_ns_link("net:[]"), None);
+    }
+
+    #[test]
+    fn test_parse_messages_reads_veths_and_their_peer_namespace() {
+        let mut linkinfo = attribute(IFLA_INFO_KIND, b"veth\0");
+        linkinfo = attribute(IFLA_LINKINFO | 1 << 15, &linkinfo);
+        let mut veth = vec![0u8; IFINFOMSG_LEN];
+        veth.extend(attribute(IFLA_IFNAME, b"veth1a2b3c\0"));
+        veth.extend(linkinfo);
+        veth.extend(attribute(IFLA_LINK_NETNSID, &3i32.to_ne_bytes()));
+        let mut loopback = vec![0u8; IFINFOMSG_LEN];
+        loopback.extend(attribute(IFLA_IFNAME, b"lo\0"));
+
+        let mut batch = message(RTM_NEWLINK, &veth);
+        batch.extend(message(RTM_NEWLINK, &loopback));
+        batch.extend(message(NLMSG_DONE, &0i32.to_ne_bytes()));
+        let messages = parse_messages(&batch).unwrap();
+
+        assert!(messages.done);
+        assert_eq!(
+            messages.links,
+            vec![
+                Link { name: "veth1a2b3c".to_string(), kind: Some("veth".to_string()), peer_netns_id: Some(3) },
+                Link { name: "lo".to_string(), kind: None, peer_netns_id: None },
+            ]
+        );
+
+        let mut reply = vec![0u8; RTGENMSG_LEN];
+        reply.extend(attribute(NETNSA_NSID, &3i32.to_ne_bytes()));
+        assert_eq!(parse_messages(&message(RTM_NEWNSID, &reply)).unwrap().netns_id, Some(3));
+
+        let mut unassigned = vec![0u8; RTGENMSG_LEN];
+        unassigned.extend(attribute(NETNSA_NSID, &(-1i32).to_ne_bytes()));
+        assert_eq!(parse_messages(&message(RTM_NEWNSID, &unassigned)).unwrap().netns_id, None);
+
+        let error = message(NLMSG_ERROR, &(-libc::EPERM).to_ne_bytes());
+        assert!(parse_messages(&error).is_err());
+    }
+
+    #[test]
+    fn test_http_body() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n[{\"Id\":\"abc\",\"Names\":[\"/web\"]}]";
+        let summaries: Vec<ContainerSummary> = serde_json::from_slice(http_body(response).unwrap()).unwrap();
+        assert_eq!(summaries[0].id, "abc");
+        assert_eq!(summaries[0].names, vec!["/web".to_string()]);
+
+        assert!(http_body(b"HTTP/1.0 404 Not Found\r\n\r\n").is_err());
+        assert!(http_body(b"HTTP/1.0 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_select_by_namespace_and_container() {
+        let namespaces = vec![
+            namespace(4026532289, Some("blue"), None, None),
+            namespace(4026532350, None, Some(4242), Some("web")),
+            namespace(4026532411, None, Some(5000), Some("db")),
+        ];
+
+        let picked = select(&namespaces, &["blue".to_string()], &["web".to_string()]).unwrap();
+        assert_eq!(picked.iter().map(|namespace| namespace.inode).collect::<Vec<_>>(), vec![4026532289, 4026532350]);
+        assert_eq!(select(&namespaces, &["net:[4026532411]".to_string()], &[]).unwrap()[0].inode, 4026532411);
+        assert_eq!(select(&namespaces, &["4242".to_string()], &[]).unwrap()[0].inode, 4026532350);
+        assert_eq!(select(&namespaces, &[], &["db0123".to_string()]).unwrap()[0].inode, 4026532411);
+        assert_eq!(select(&namespaces, &[], &["*".to_string()]).unwrap().len(), 2);
+        assert!(select(&namespaces, &[], &["cache".to_string()]).is_err());
+
+        let mut isolated = namespaces.clone();
+        isolated[0].interfaces.clear();
+        assert!(select(&isolated, &["blue".to_string()], &[]).is_err());
+
+        let owners = interface_owners(&namespaces);
+        assert_eq!(owners["veth4026532350"], "container web (docker)");
+        assert_eq!(owners["veth4026532289"], "netns blue");
+    }
+}
@@ -0,0 +1,359 @@
+// pktap capture on macOS: packets tagged with the process that sent or received them
+// A pktap pseudo-interface mirrors the traffic of the real interfaces and prefixes every
+// frame with a header naming the interface and the owning process, the same metadata
+// `tcpdump -k` prints. Reading it through BPF attributes each packet as it is captured,
+// with no socket table lookups
+
+use crate::collectors::packet_ring::FrameProcess;
+
+/// Link type of the frames after the pktap header that are kept (DLT_EN10MB)
+pub const DLT_EN10MB: u32 = 1;
+
+/// Offsets into `struct pktap_header` from <net/pktap.h>
+const PTH_LENGTH: usize = 0;
+const PTH_DLT: usize = 8;
+const PTH_IFNAME: usize = 12;
+const PTH_PID: usize = 52;
+const PTH_COMM: usize = 56;
+const PTH_EPID: usize = 84;
+const PTH_ECOMM: usize = 88;
+/// PKTAP_IFXNAMESIZE and MAXCOMLEN + 1
+const IFNAME_LEN: usize = 24;
+const COMM_LEN: usize = 17;
+/// Bytes of the header up to the last field read
+const MIN_HEADER_LEN: usize = PTH_ECOMM + COMM_LEN;
+
+/// A frame with its pktap header decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PktapFrame<'a> {
+    /// Interface the frame crossed
+    pub interface: &'a str,
+    /// Link type of `data`, a DLT_* value
+    pub dlt: u32,
+    /// Owning process; the effective one when a daemon handles traffic for an app
+    pub process: Option<FrameProcess>,
+    /// Captured bytes after the pktap header
+    pub data: &'a [u8],
+}
+
+/// Splits a captured record into its pktap header and frame
+pub fn parse(record: &[u8]) -> Option<PktapFrame<'_>> {
+    if record.len() < MIN_HEADER_LEN {
+        return None;
+    }
+    let u32_at = |offset: usize| u32::from_ne_bytes(record[offset..offset + 4].try_into().unwrap());
+    let header_len = usize::try_from(u32_at(PTH_LENGTH)).ok().filter(|len| (MIN_HEADER_LEN..=record.len()).contains(len))?;
+
+    let ifname = &record[PTH_IFNAME..PTH_IFNAME + IFNAME_LEN];
+    let ifname = &ifname[..ifname.iter().position(|b| *b == 0).unwrap_or(IFNAME_LEN)];
+    let process = |pid_offset: usize, comm_offset: usize| {
+        // pid_t; -1 or 0 when the kernel does not know the process
+        let pid = u32_at(pid_offset) as i32;
+        (pid > 0).then(|| FrameProcess {
+            pid: pid as u32,
+            name: record[comm_offset..comm_offset + COMM_LEN].try_into().unwrap(),
+        })
+    };
+
+    Some(PktapFrame {
+        interface: std::str::from_utf8(ifname).ok()?,
+        dlt: u32_at(PTH_DLT),
+        process: process(PTH_EPID, PTH_ECOMM).or_else(|| process(PTH_PID, PTH_COMM)),
+        data: &record[header_len..],
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub use capture::PktapCapture;
+
+#[cfg(target_os = "macos")]
+mod capture {
+    use std::ffi::CStr;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time::Duration;
+
+    use super::{parse, PktapFrame, DLT_EN10MB};
+    use crate::collectors::capture_filter::BpfInstruction;
+
+    /// BPF buffer size asked for; the kernel may grant less
+    const BUFFER_SIZE: u32 = 512 * 1024;
+
+    /// Link type of a pktap interface (DLT_USER2 in the kernel's <net/bpf.h>)
+    const DLT_PKTAP: u32 = 149;
+
+    const IOC_OUT: u32 = 0x4000_0000;
+    const IOC_IN: u32 = 0x8000_0000;
+    const IOC_INOUT: u32 = IOC_IN | IOC_OUT;
+
+    const fn ioc(direction: u32, group: u8, number: u8, len: usize) -> libc::c_ulong {
+        (direction | ((len as u32 & 0x1fff) << 16) | ((group as u32) << 8) | number as u32) as libc::c_ulong
+    }
+
+    const BIOCSBLEN: libc::c_ulong = ioc(IOC_INOUT, b'B', 102, 4);
+    const BIOCSETF: libc::c_ulong = ioc(IOC_IN, b'B', 103, std::mem::size_of::<BpfProgram>());
+    const BIOCGDLT: libc::c_ulong = ioc(IOC_OUT, b'B', 106, 4);
+    const BIOCSETIF: libc::c_ulong = ioc(IOC_IN, b'B', 108, IFREQ_LEN);
+    const BIOCIMMEDIATE: libc::c_ulong = ioc(IOC_IN, b'B', 112, 4);
+    const BIOCSWANTPKTAP: libc::c_ulong = ioc(IOC_INOUT, b'B', 127, 4);
+    const SIOCIFCREATE: libc::c_ulong = ioc(IOC_INOUT, b'i', 120, IFREQ_LEN);
+    const SIOCIFDESTROY: libc::c_ulong = ioc(IOC_IN, b'i', 121, IFREQ_LEN);
+
+    const IFREQ_LEN: usize = 32;
+
+    /// `struct bpf_program`
+    #[repr(C)]
+    struct BpfProgram {
+        len: libc::c_uint,
+        instructions: *const BpfInstruction,
+    }
+
+    /// `struct ifreq` with only its name used
+    #[repr(C)]
+    struct IfReq {
+        name: [u8; libc::IFNAMSIZ],
+        data: [u8; IFREQ_LEN - libc::IFNAMSIZ],
+    }
+
+    impl IfReq {
+        fn named(name: &str) -> io::Result<Self> {
+            let mut request = Self { name: [0; libc::IFNAMSIZ], data: [0; IFREQ_LEN - libc::IFNAMSIZ] };
+            if name.len() >= libc::IFNAMSIZ {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Interface name '{name}' is too long")));
+            }
+            request.name[..name.len()].copy_from_slice(name.as_bytes());
+            Ok(request)
+        }
+
+        fn name(&self) -> String {
+            CStr::from_bytes_until_nul(&self.name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        }
+    }
+
+    /// A frame of the watched interface as read from the pktap interface
+    #[derive(Debug, Clone, Copy)]
+    pub struct PktapRecord<'a> {
+        pub frame: PktapFrame<'a>,
+        /// Length on the wire, which may exceed the captured data
+        pub wire_len: u32,
+        /// Capture time in nanoseconds since the Unix epoch
+        pub timestamp_ns: i64,
+    }
+
+    /// A pktap interface created for one capture, read through a BPF device
+    ///
+    /// The interface mirrors every Ethernet interface, so records of other interfaces are
+    /// skipped; it is destroyed again when the capture is dropped.
+    pub struct PktapCapture {
+        bpf: File,
+        pktap: String,
+        interface: String,
+        buffer: Vec<u8>,
+        /// Whether the BPF device runs the capture filter
+        filtered: bool,
+    }
+
+    impl PktapCapture {
+        /// Creates a pktap interface and opens a BPF device on it, for the frames of `interface`
+        /// Fails with PermissionDenied unless root, and with Unsupported where the kernel
+        /// offers no pktap link type
+        ///
+        /// `filter`, compiled for pktap framing, is set on the device once it is attached,
+        /// which discards what was read before; a refused program leaves the device unfiltered.
+        pub fn open(interface: &str, filter: Option<&[BpfInstruction]>) -> io::Result<Self> {
+            let pktap = create_interface()?;
+            match Self::attach(&pktap, interface, filter) {
+                Ok(capture) => Ok(capture),
+                Err(e) => {
+                    destroy_interface(&pktap);
+                    Err(e)
+                }
+            }
+        }
+
+        fn attach(pktap: &str, interface: &str, filter: Option<&[BpfInstruction]>) -> io::Result<Self> {
+            let bpf = open_bpf_device()?;
+            let mut buffer_size = BUFFER_SIZE;
+            ioctl(&bpf, BIOCSBLEN, &mut buffer_size)?;
+            ioctl(&bpf, BIOCSWANTPKTAP, &mut 1u32)?;
+            ioctl(&bpf, BIOCSETIF, &mut IfReq::named(pktap)?)?;
+            ioctl(&bpf, BIOCIMMEDIATE, &mut 1u32)?;
+            let mut dlt = 0u32;
+            ioctl(&bpf, BIOCGDLT, &mut dlt)?;
+            if dlt != DLT_PKTAP {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{pktap} has link type {dlt}, not pktap")));
+            }
+            let filtered = match filter {
+                Some(program) => {
+                    let mut program = BpfProgram { len: program.len() as libc::c_uint, instructions: program.as_ptr() };
+                    match ioctl(&bpf, BIOCSETF, &mut program) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            log::warn!("The kernel refused the capture filter ({e}); filtering in kw instead");
+                            false
+                        }
+                    }
+                }
+                None => false,
+            };
+            Ok(Self {
+                bpf,
+                pktap: pktap.to_string(),
+                interface: interface.to_string(),
+                buffer: vec![0; buffer_size as usize],
+                filtered,
+            })
+        }
+
+        /// Whether every record read has passed the capture filter
+        pub fn filtered(&self) -> bool {
+            self.filtered
+        }
+
+        /// Hands each Ethernet frame of the watched interface to `each`, waiting up to
+        /// `timeout` for traffic; returns the number of frames, 0 when nothing arrived in time
+        pub fn next_batch(&mut self, timeout: Duration, mut each: impl FnMut(PktapRecord<'_>)) -> io::Result<usize> {
+            let mut poll = libc::pollfd { fd: self.bpf.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            let ready = unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                return if error.kind() == io::ErrorKind::Interrupted { Ok(0) } else { Err(error) };
+            }
+            if ready == 0 {
+                return Ok(0);
+            }
+            let read = unsafe { libc::read(self.bpf.as_raw_fd(), self.buffer.as_mut_ptr().cast(), self.buffer.len()) };
+            if read < 0 {
+                let error = io::Error::last_os_error();
+                return if error.kind() == io::ErrorKind::Interrupted { Ok(0) } else { Err(error) };
+            }
+
+            // `struct bpf_hdr`: timeval32, caplen, datalen and hdrlen, records 4-byte aligned
+            let records = &self.buffer[..read as usize];
+            let mut frames = 0;
+            let mut offset = 0;
+            while offset + 18 <= records.len() {
+                let u32_at = |at: usize| u32::from_ne_bytes(records[offset + at..offset + at + 4].try_into().unwrap());
+                let (seconds, micros, captured, wire_len) = (u32_at(0), u32_at(4), u32_at(8) as usize, u32_at(12));
+                let header_len = u16::from_ne_bytes(records[offset + 16..offset + 18].try_into().unwrap()) as usize;
+                let start = offset + header_len;
+                let Some(record) = records.get(start..start + captured) else {
+                    break;
+                };
+                if let Some(frame) = parse(record).filter(|f| f.dlt == DLT_EN10MB && f.interface == self.interface) {
+                    let header_len = record.len() - frame.data.len();
+                    each(PktapRecord {
+                        frame,
+                        wire_len: wire_len.saturating_sub(header_len as u32),
+                        timestamp_ns: i64::from(seconds as i32) * 1_000_000_000 + i64::from(micros) * 1_000,
+                    });
+                    frames += 1;
+                }
+                offset += (header_len + captured).next_multiple_of(4);
+            }
+            Ok(frames)
+        }
+    }
+
+    impl Drop for PktapCapture {
+        fn drop(&mut self) {
+            destroy_interface(&self.pktap);
+        }
+    }
+
+    /// Creates a pktap interface, which by default mirrors every Ethernet interface
+    fn create_interface() -> io::Result<String> {
+        let socket = udp_socket()?;
+        let mut request = IfReq::named("pktap")?;
+        ioctl(&socket, SIOCIFCREATE, &mut request)?;
+        Ok(request.name())
+    }
+
+    fn destroy_interface(name: &str) {
+        if let Ok(socket) = udp_socket()
+            && let Ok(mut request) = IfReq::named(name)
+            && let Err(e) = ioctl(&socket, SIOCIFDESTROY, &mut request)
+        {
+            log::warn!("Failed to remove pktap interface {name}: {e}");
+        }
+    }
+
+    fn udp_socket() -> io::Result<OwnedFd> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// The first BPF device not already in use
+    fn open_bpf_device() -> io::Result<File> {
+        for number in 0.. {
+            match OpenOptions::new().read(true).write(true).open(format!("/dev/bpf{number}")) {
+                Ok(device) => return Ok(device),
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!()
+    }
+
+    fn ioctl<T>(fd: &impl AsRawFd, request: libc::c_ulong, value: &mut T) -> io::Result<()> {
+        if unsafe { libc::ioctl(fd.as_raw_fd(), request, std::ptr::from_mut(value)) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A full-size pktap header for `interface` and the processes, followed by `frame`
+    fn record(interface: &str, pid: i32, comm: &str, epid: i32, ecomm: &str, frame: &[u8]) -> Vec<u8> {
+        let len = 156usize;
+        let mut bytes = vec![0u8; len];
+        bytes[PTH_LENGTH..PTH_LENGTH + 4].copy_from_slice(&(len as u32).to_ne_bytes());
+        bytes[PTH_DLT..PTH_DLT + 4].copy_from_slice(&DLT_EN10MB.to_ne_bytes());
+        bytes[PTH_IFNAME..PTH_IFNAME + interface.len()].copy_from_slice(interface.as_bytes());
+        bytes[PTH_PID..PTH_PID + 4].copy_from_slice(&pid.to_ne_bytes());
+        bytes[PTH_COMM..PTH_COMM + comm.len()].copy_from_slice(comm.as_bytes());
+        bytes[PTH_EPID..PTH_EPID + 4].copy_from_slice(&epid.to_ne_bytes());
+        bytes[PTH_ECOMM..PTH_ECOMM + ecomm.len()].copy_from_slice(ecomm.as_bytes());
+        bytes.extend_from_slice(frame);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_reads_interface_process_and_frame() {
+        let bytes = record("en0", 812, "curl", 812, "curl", &[0xaa; 60]);
+        let frame = parse(&bytes).unwrap();
+        assert_eq!(frame.interface, "en0");
+        assert_eq!(frame.dlt, DLT_EN10MB);
+        assert_eq!(frame.data, &[0xaa; 60][..]);
+        let process = frame.process.unwrap();
+        assert_eq!((process.pid, process.name()), (812, "curl"));
+    }
+
+    #[test]
+    fn test_effective_process_is_preferred() {
+        // A daemon moving data for an app is charged to the app
+        let bytes = record("en0", 301, "nsurlsessiond", 955, "Mail", &[0; 60]);
+        let process = parse(&bytes).unwrap().process.unwrap();
+        assert_eq!((process.pid, process.name()), (955, "Mail"));
+
+        let bytes = record("en0", 301, "nsurlsessiond", 0, "", &[0; 60]);
+        assert_eq!(parse(&bytes).unwrap().process.unwrap().name(), "nsurlsessiond");
+    }
+
+    #[test]
+    fn test_unknown_process_and_short_records() {
+        let bytes = record("en0", -1, "", -1, "", &[0; 60]);
+        assert_eq!(parse(&bytes).unwrap().process, None);
+        assert!(parse(&bytes[..100]).is_none());
+    }
+}
@@ -0,0 +1,733 @@
+// System suspend and resume events
+// Interface counters stand still while the machine sleeps, so the first interval after a
+// resume spans the whole sleep and averages the traffic before it over hours. Rather than
+// guess a sleep from a suspiciously long interval, the collector is told by the system:
+//
+// - Linux: the PrepareForSleep signal of systemd-logind, read from the system D-Bus; without
+//   a system bus, a resume is noticed when CLOCK_BOOTTIME pulls ahead of CLOCK_MONOTONIC
+// - macOS: IORegisterForSystemPower, the IOKit notifications for sleep and wake
+// - Windows: PowerRegisterSuspendResumeNotification from the power management API
+// - Elsewhere: no notifications; the interval heuristics are all there is
+
+use anyhow::Result;
+use log::{debug, info};
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::thread::JoinHandle;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::time::Duration;
+
+/// How long the watcher thread blocks before checking whether it should stop
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of the system's power state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    /// The system is about to suspend or hibernate
+    Suspending,
+    /// The system woke up again
+    Resumed,
+}
+
+impl std::fmt::Display for PowerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PowerEvent::Suspending => "system is suspending",
+            PowerEvent::Resumed => "system resumed from sleep",
+        })
+    }
+}
+
+/// Watches for the system going to sleep and waking up
+/// Events queue up until taken with `try_events`; notifications stop when the watcher is dropped
+#[derive(Debug)]
+pub struct PowerWatcher {
+    events: Receiver<PowerEvent>,
+    _source: Option<PowerSource>,
+}
+
+impl PowerWatcher {
+    /// Subscribes to the platform's power notifications
+    pub fn start() -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let source = PowerSource::start(sender)?;
+        info!("Watching for system suspend and resume");
+        Ok(Self { events, _source: Some(source) })
+    }
+
+    /// A watcher fed through the returned sender rather than by the platform
+    #[cfg(test)]
+    pub(crate) fn manual() -> (Sender<PowerEvent>, Self) {
+        let (sender, events) = mpsc::channel();
+        (sender, Self { events, _source: None })
+    }
+
+    /// Events seen since the last call, oldest first
+    pub fn try_events(&self) -> Vec<PowerEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+fn notify(sender: &Sender<PowerEvent>, event: PowerEvent) -> bool {
+    debug!("Power event: {event}");
+    sender.send(event).is_ok()
+}
+
+/// Background thread delivering the notifications; stopped and joined on drop
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[derive(Debug)]
+struct PowerSource {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl PowerSource {
+    fn spawn(watch: impl FnOnce(Arc<AtomicBool>) + Send + 'static) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name("power-watcher".to_string())
+            .spawn(move || watch(thread_stop))?;
+        Ok(Self { stop, thread: Some(thread) })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl Drop for PowerSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PowerSource {
+    fn start(sender: Sender<PowerEvent>) -> Result<Self> {
+        match logind::subscribe() {
+            Ok(bus) => Self::spawn(move |stop| logind::watch(bus, sender, stop)),
+            Err(e) => {
+                info!("No suspend notifications from logind ({e:#}), watching the boot clock instead");
+                Self::spawn(move |stop| sleep_clock::watch(sender, stop))
+            }
+        }
+    }
+}
+
+/// systemd-logind's PrepareForSleep signal over a minimal D-Bus client
+/// Only what the subscription needs is implemented: EXTERNAL authentication, the Hello and
+/// AddMatch calls, and reading the header fields and boolean argument of incoming signals
+#[cfg(any(target_os = "linux", test))]
+mod logind {
+    use anyhow::{bail, Context, Result};
+
+    /// Socket of the system bus when DBUS_SYSTEM_BUS_ADDRESS does not name another
+    #[cfg(target_os = "linux")]
+    const SYSTEM_BUS_SOCKET: &str = "/var/run/dbus/system_bus_socket";
+
+    const METHOD_CALL: u8 = 1;
+    const ERROR: u8 = 3;
+    const SIGNAL: u8 = 4;
+
+    const FIELD_PATH: u8 = 1;
+    const FIELD_INTERFACE: u8 = 2;
+    const FIELD_MEMBER: u8 = 3;
+    const FIELD_REPLY_SERIAL: u8 = 5;
+    const FIELD_DESTINATION: u8 = 6;
+    const FIELD_SIGNATURE: u8 = 8;
+
+    const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+    const MATCH_RULE: &str =
+        "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'";
+
+    /// Serial of the AddMatch call, whose error reply means no signals will come
+    #[cfg(target_os = "linux")]
+    const ADD_MATCH_SERIAL: u32 = 2;
+
+    /// Header fields and body of a received message
+    #[derive(Debug, Default, PartialEq)]
+    pub(super) struct Message {
+        pub kind: u8,
+        pub interface: Option<String>,
+        pub member: Option<String>,
+        pub reply_serial: Option<u32>,
+        pub signature: Option<String>,
+        pub body: Vec<u8>,
+        pub big_endian: bool,
+    }
+
+    impl Message {
+        /// PrepareForSleep(true) announces a sleep, PrepareForSleep(false) the wake-up
+        pub(super) fn power_event(&self) -> Option<super::PowerEvent> {
+            if self.kind != SIGNAL
+                || self.interface.as_deref() != Some(MANAGER_INTERFACE)
+                || self.member.as_deref() != Some("PrepareForSleep")
+                || self.signature.as_deref() != Some("b")
+            {
+                return None;
+            }
+            let value: [u8; 4] = self.body.get(..4)?.try_into().ok()?;
+            let start = if self.big_endian { u32::from_be_bytes(value) } else { u32::from_le_bytes(value) };
+            Some(if start != 0 { super::PowerEvent::Suspending } else { super::PowerEvent::Resumed })
+        }
+    }
+
+    /// Little-endian message writer
+    #[derive(Default)]
+    struct Writer {
+        buf: Vec<u8>,
+    }
+
+    impl Writer {
+        fn align(&mut self, alignment: usize) {
+            while !self.buf.len().is_multiple_of(alignment) {
+                self.buf.push(0);
+            }
+        }
+
+        fn u32(&mut self, value: u32) {
+            self.align(4);
+            self.buf.extend_from_slice(&value.to_le_bytes());
+        }
+
+        fn string(&mut self, value: &str) {
+            self.u32(value.len() as u32);
+            self.buf.extend_from_slice(value.as_bytes());
+            self.buf.push(0);
+        }
+
+        fn signature(&mut self, value: &str) {
+            self.buf.push(value.len() as u8);
+            self.buf.extend_from_slice(value.as_bytes());
+            self.buf.push(0);
+        }
+
+        /// One header field: its code and a variant holding a string, object path or signature
+        fn field(&mut self, code: u8, kind: &str, value: &str) {
+            self.align(8);
+            self.buf.push(code);
+            self.signature(kind);
+            if kind == "g" {
+                self.signature(value);
+            } else {
+                self.string(value);
+            }
+        }
+    }
+
+    /// Serialises a message; `fields` are (code, type, value), `body` is already marshalled
+    fn message(kind: u8, serial: u32, fields: &[(u8, &str, &str)], body: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.buf.extend_from_slice(&[b'l', kind, 0, 1]);
+        writer.u32(body.len() as u32);
+        writer.u32(serial);
+        writer.u32(0);
+        for &(code, field_kind, value) in fields {
+            writer.field(code, field_kind, value);
+        }
+        let fields_len = (writer.buf.len() - 16) as u32;
+        writer.buf[12..16].copy_from_slice(&fields_len.to_le_bytes());
+        writer.align(8);
+        writer.buf.extend_from_slice(body);
+        writer.buf
+    }
+
+    /// A call to a method of the bus itself, with a single string argument if any
+    fn bus_call(serial: u32, member: &str, argument: Option<&str>) -> Vec<u8> {
+        let mut fields = vec![
+            (FIELD_PATH, "o", "/org/freedesktop/DBus"),
+            (FIELD_INTERFACE, "s", "org.freedesktop.DBus"),
+            (FIELD_MEMBER, "s", member),
+            (FIELD_DESTINATION, "s", "org.freedesktop.DBus"),
+        ];
+        let mut body = Writer::default();
+        if let Some(argument) = argument {
+            fields.push((FIELD_SIGNATURE, "g", "s"));
+            body.string(argument);
+        }
+        message(METHOD_CALL, serial, &fields, &body.buf)
+    }
+
+    /// Reader over one message, honouring its byte order
+    struct Reader<'a> {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    }
+
+    impl Reader<'_> {
+        fn align(&mut self, alignment: usize) {
+            self.pos = self.pos.div_ceil(alignment) * alignment;
+        }
+
+        fn bytes(&mut self, len: usize) -> Result<&[u8]> {
+            let bytes = self.data.get(self.pos..self.pos + len).context("Truncated D-Bus message")?;
+            self.pos += len;
+            Ok(bytes)
+        }
+
+        fn u8(&mut self) -> Result<u8> {
+            Ok(self.bytes(1)?[0])
+        }
+
+        fn u32(&mut self) -> Result<u32> {
+            self.align(4);
+            let bytes: [u8; 4] = self.bytes(4)?.try_into()?;
+            Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+        }
+
+        fn text(&mut self, len: usize) -> Result<String> {
+            let text = String::from_utf8(self.bytes(len)?.to_vec()).context("Invalid D-Bus string")?;
+            self.bytes(1)?;
+            Ok(text)
+        }
+
+        fn string(&mut self) -> Result<String> {
+            let len = self.u32()? as usize;
+            self.text(len)
+        }
+
+        fn signature(&mut self) -> Result<String> {
+            let len = self.u8()? as usize;
+            self.text(len)
+        }
+    }
+
+    /// The first complete message in `buffer` and its length; `None` until one has arrived
+    pub(super) fn parse(buffer: &[u8]) -> Result<Option<(usize, Message)>> {
+        if buffer.len() < 16 {
+            return Ok(None);
+        }
+        let big_endian = match buffer[0] {
+            b'l' => false,
+            b'B' => true,
+            other => bail!("Unknown D-Bus byte order {other:#x}"),
+        };
+        let mut reader = Reader { data: buffer, pos: 4, big_endian };
+        let body_len = reader.u32()? as usize;
+        reader.u32()?;
+        let fields_len = reader.u32()? as usize;
+        let header_len = (16 + fields_len).div_ceil(8) * 8;
+        let total = header_len + body_len;
+        if buffer.len() < total {
+            return Ok(None);
+        }
+
+        let mut message = Message { kind: buffer[1], big_endian, ..Message::default() };
+        reader.data = &buffer[..16 + fields_len];
+        while reader.pos < reader.data.len() {
+            reader.align(8);
+            let code = reader.u8()?;
+            let kind = reader.signature()?;
+            match kind.as_str() {
+                "s" | "o" => {
+                    let value = reader.string()?;
+                    match code {
+                        FIELD_INTERFACE => message.interface = Some(value),
+                        FIELD_MEMBER => message.member = Some(value),
+                        _ => {}
+                    }
+                }
+                "g" => {
+                    let value = reader.signature()?;
+                    if code == FIELD_SIGNATURE {
+                        message.signature = Some(value);
+                    }
+                }
+                "u" => {
+                    let value = reader.u32()?;
+                    if code == FIELD_REPLY_SERIAL {
+                        message.reply_serial = Some(value);
+                    }
+                }
+                other => bail!("Unexpected D-Bus header field type '{other}'"),
+            }
+        }
+        message.body = buffer[header_len..total].to_vec();
+        Ok(Some((total, message)))
+    }
+
+    /// Path of the system bus socket
+    #[cfg(target_os = "linux")]
+    fn bus_path() -> Result<String> {
+        match std::env::var("DBUS_SYSTEM_BUS_ADDRESS") {
+            Ok(address) => address
+                .split(';')
+                .find_map(|entry| entry.strip_prefix("unix:path="))
+                .map(|path| path.split(',').next().unwrap_or(path).to_string())
+                .with_context(|| format!("Unsupported system bus address '{address}'")),
+            Err(_) => Ok(SYSTEM_BUS_SOCKET.to_string()),
+        }
+    }
+
+    /// Connects to the system bus and subscribes to PrepareForSleep
+    #[cfg(target_os = "linux")]
+    pub(super) fn subscribe() -> Result<std::os::unix::net::UnixStream> {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path = bus_path()?;
+        let mut bus = UnixStream::connect(&path).with_context(|| format!("Failed to connect to {path}"))?;
+        bus.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+
+        // The bus knows our user from the socket; EXTERNAL only names it, as hex digits of the decimal uid
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex_uid: String = uid.bytes().map(|b| format!("{b:02x}")).collect();
+        bus.write_all(format!("\0AUTH EXTERNAL {hex_uid}\r\n").as_bytes())?;
+        let mut reply = String::new();
+        BufReader::new(&bus).read_line(&mut reply)?;
+        if !reply.starts_with("OK ") {
+            bail!("System bus refused authentication: {}", reply.trim_end());
+        }
+        bus.write_all(b"BEGIN\r\n")?;
+
+        bus.write_all(&bus_call(1, "Hello", None))?;
+        bus.write_all(&bus_call(ADD_MATCH_SERIAL, "AddMatch", Some(MATCH_RULE)))?;
+        bus.set_read_timeout(Some(super::POLL_INTERVAL))?;
+        Ok(bus)
+    }
+
+    /// Reads messages off the bus until stopped, passing on the power events among them
+    #[cfg(target_os = "linux")]
+    pub(super) fn watch(
+        mut bus: std::os::unix::net::UnixStream,
+        sender: std::sync::mpsc::Sender<super::PowerEvent>,
+        stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    ) {
+        use log::warn;
+        use std::io::{ErrorKind, Read};
+        use std::sync::atomic::Ordering;
+
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while !stop.load(Ordering::Relaxed) {
+            match bus.read(&mut chunk) {
+                Ok(0) => {
+                    warn!("System bus closed the connection, no longer watching for suspend");
+                    return;
+                }
+                Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Reading the system bus failed, no longer watching for suspend: {e}");
+                    return;
+                }
+            }
+
+            loop {
+                let (len, message) = match parse(&buffer) {
+                    Ok(Some(parsed)) => parsed,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("Unreadable message on the system bus, no longer watching for suspend: {e:#}");
+                        return;
+                    }
+                };
+                buffer.drain(..len);
+                if message.kind == ERROR && message.reply_serial == Some(ADD_MATCH_SERIAL) {
+                    warn!("System bus refused the PrepareForSleep subscription, no longer watching for suspend");
+                    return;
+                }
+                if let Some(event) = message.power_event()
+                    && !super::notify(&sender, event)
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// A PrepareForSleep signal as logind sends it
+    #[cfg(test)]
+    pub(super) fn prepare_for_sleep(start: bool) -> Vec<u8> {
+        let fields = [
+            (FIELD_PATH, "o", "/org/freedesktop/login1"),
+            (FIELD_INTERFACE, "s", MANAGER_INTERFACE),
+            (FIELD_MEMBER, "s", "PrepareForSleep"),
+            (FIELD_SIGNATURE, "g", "b"),
+        ];
+        message(SIGNAL, 7, &fields, &u32::from(start).to_le_bytes())
+    }
+
+    #[cfg(test)]
+    pub(super) fn add_match() -> Vec<u8> {
+        bus_call(2, "AddMatch", Some(MATCH_RULE))
+    }
+}
+
+/// Resume detection from the clocks: CLOCK_BOOTTIME keeps counting while the system sleeps,
+/// CLOCK_MONOTONIC does not, so the gap between them grows by the length of every sleep
+#[cfg(target_os = "linux")]
+mod sleep_clock {
+    use super::{PowerEvent, POLL_INTERVAL};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Growth of the gap that counts as a sleep rather than clock jitter
+    const MIN_SLEEP: Duration = Duration::from_secs(2);
+
+    fn read(clock: libc::clockid_t) -> Duration {
+        let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(clock, &mut now) };
+        Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+    }
+
+    fn time_asleep() -> Duration {
+        read(libc::CLOCK_BOOTTIME).saturating_sub(read(libc::CLOCK_MONOTONIC))
+    }
+
+    pub(super) fn watch(sender: Sender<PowerEvent>, stop: Arc<AtomicBool>) {
+        let mut asleep = time_asleep();
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL_INTERVAL);
+            let now = time_asleep();
+            if now.saturating_sub(asleep) >= MIN_SLEEP && !super::notify(&sender, PowerEvent::Resumed) {
+                return;
+            }
+            asleep = now;
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl PowerSource {
+    fn start(sender: Sender<PowerEvent>) -> Result<Self> {
+        // The notification port has to be serviced by the run loop of the thread that registered it
+        let (registered, outcome) = mpsc::sync_channel(1);
+        let source = Self::spawn(move |stop| iokit::watch(sender, stop, registered))?;
+        outcome.recv()??;
+        Ok(source)
+    }
+}
+
+/// IOKit system power notifications, serviced by a CoreFoundation run loop
+#[cfg(target_os = "macos")]
+mod iokit {
+    use super::{PowerEvent, POLL_INTERVAL};
+    use anyhow::Result;
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{Sender, SyncSender};
+    use std::sync::Arc;
+
+    type IoObject = u32;
+    type NotificationPort = *mut c_void;
+    type CfRef = *const c_void;
+
+    const MESSAGE_CAN_SYSTEM_SLEEP: u32 = 0xe000_0270;
+    const MESSAGE_SYSTEM_WILL_SLEEP: u32 = 0xe000_0280;
+    const MESSAGE_SYSTEM_HAS_POWERED_ON: u32 = 0xe000_0300;
+
+    #[link(name = "IOKit", kind = "framework")]
+    unsafe extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut NotificationPort,
+            callback: unsafe extern "C" fn(*mut c_void, IoObject, u32, *mut c_void),
+            notifier: *mut IoObject,
+        ) -> IoObject;
+        fn IODeregisterForSystemPower(notifier: *mut IoObject) -> i32;
+        fn IOAllowPowerChange(root_port: IoObject, notification: isize) -> i32;
+        fn IOServiceClose(connect: IoObject) -> i32;
+        fn IONotificationPortGetRunLoopSource(port: NotificationPort) -> CfRef;
+        fn IONotificationPortDestroy(port: NotificationPort);
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        static kCFRunLoopDefaultMode: CfRef;
+        fn CFRunLoopGetCurrent() -> CfRef;
+        fn CFRunLoopAddSource(run_loop: CfRef, source: CfRef, mode: CfRef);
+        fn CFRunLoopRunInMode(mode: CfRef, seconds: f64, return_after_source_handled: u8) -> i32;
+    }
+
+    /// What the callback needs: where events go, and the connection sleep is acknowledged on
+    struct Context {
+        sender: Sender<PowerEvent>,
+        root_port: IoObject,
+    }
+
+    /// Called on the run loop for every power message; sleep has to be acknowledged,
+    /// otherwise the system waits 30 seconds for us before going to sleep
+    unsafe extern "C" fn on_power(refcon: *mut c_void, _service: IoObject, message: u32, argument: *mut c_void) {
+        let context = unsafe { &*(refcon as *const Context) };
+        match message {
+            MESSAGE_CAN_SYSTEM_SLEEP => unsafe {
+                IOAllowPowerChange(context.root_port, argument as isize);
+            },
+            MESSAGE_SYSTEM_WILL_SLEEP => {
+                super::notify(&context.sender, PowerEvent::Suspending);
+                unsafe { IOAllowPowerChange(context.root_port, argument as isize) };
+            }
+            MESSAGE_SYSTEM_HAS_POWERED_ON => {
+                super::notify(&context.sender, PowerEvent::Resumed);
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn watch(sender: Sender<PowerEvent>, stop: Arc<AtomicBool>, registered: SyncSender<Result<()>>) {
+        let context = Box::into_raw(Box::new(Context { sender, root_port: 0 }));
+        let mut port: NotificationPort = std::ptr::null_mut();
+        let mut notifier: IoObject = 0;
+        let root_port = unsafe { IORegisterForSystemPower(context as *mut c_void, &mut port, on_power, &mut notifier) };
+        if root_port == 0 {
+            drop(unsafe { Box::from_raw(context) });
+            let _ = registered.send(Err(anyhow::anyhow!("IORegisterForSystemPower failed")));
+            return;
+        }
+        unsafe {
+            (*context).root_port = root_port;
+            CFRunLoopAddSource(CFRunLoopGetCurrent(), IONotificationPortGetRunLoopSource(port), kCFRunLoopDefaultMode);
+        }
+        let _ = registered.send(Ok(()));
+
+        while !stop.load(Ordering::Relaxed) {
+            unsafe { CFRunLoopRunInMode(kCFRunLoopDefaultMode, POLL_INTERVAL.as_secs_f64(), 0) };
+        }
+
+        unsafe {
+            IODeregisterForSystemPower(&mut notifier);
+            IOServiceClose(root_port);
+            IONotificationPortDestroy(port);
+            drop(Box::from_raw(context));
+        }
+    }
+}
+
+/// Registration for the suspend and resume callback
+#[cfg(target_os = "windows")]
+#[derive(Debug)]
+struct PowerSource {
+    handle: *mut std::ffi::c_void,
+    parameters: *mut ffi::SubscribeParameters,
+}
+
+// The handle and parameters are only used to cancel the registration on drop
+#[cfg(target_os = "windows")]
+unsafe impl Send for PowerSource {}
+
+#[cfg(target_os = "windows")]
+impl PowerSource {
+    fn start(sender: Sender<PowerEvent>) -> Result<Self> {
+        let context = Box::into_raw(Box::new(sender));
+        let parameters = Box::into_raw(Box::new(ffi::SubscribeParameters {
+            callback: ffi::on_power,
+            context: context as *mut _,
+        }));
+        let mut handle = std::ptr::null_mut();
+        let rc = unsafe {
+            ffi::PowerRegisterSuspendResumeNotification(ffi::DEVICE_NOTIFY_CALLBACK, parameters as *mut _, &mut handle)
+        };
+        if rc != 0 {
+            unsafe {
+                drop(Box::from_raw(parameters));
+                drop(Box::from_raw(context));
+            }
+            anyhow::bail!("PowerRegisterSuspendResumeNotification failed with error {rc}");
+        }
+        Ok(Self { handle, parameters })
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for PowerSource {
+    fn drop(&mut self) {
+        // Unregistering waits for running callbacks, after which the context is no longer used
+        unsafe {
+            ffi::PowerUnregisterSuspendResumeNotification(self.handle);
+            let parameters = Box::from_raw(self.parameters);
+            drop(Box::from_raw(parameters.context as *mut Sender<PowerEvent>));
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod ffi {
+    use super::PowerEvent;
+    use std::ffi::c_void;
+    use std::sync::mpsc::Sender;
+
+    pub const DEVICE_NOTIFY_CALLBACK: u32 = 2;
+    const PBT_APMSUSPEND: u32 = 0x4;
+    const PBT_APMRESUMESUSPEND: u32 = 0x7;
+    const PBT_APMRESUMEAUTOMATIC: u32 = 0x12;
+
+    /// DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS
+    #[repr(C)]
+    pub struct SubscribeParameters {
+        pub callback: unsafe extern "system" fn(*mut c_void, u32, *mut c_void) -> u32,
+        pub context: *mut c_void,
+    }
+
+    #[link(name = "powrprof")]
+    unsafe extern "system" {
+        pub fn PowerRegisterSuspendResumeNotification(flags: u32, recipient: *mut c_void, handle: *mut *mut c_void) -> u32;
+        pub fn PowerUnregisterSuspendResumeNotification(handle: *mut c_void) -> u32;
+    }
+
+    /// Called by the power manager for every power broadcast; a wake-up is announced as an
+    /// automatic resume and, once a user is present, again as a resume from suspend
+    pub unsafe extern "system" fn on_power(context: *mut c_void, kind: u32, _setting: *mut c_void) -> u32 {
+        let sender = unsafe { &*(context as *const Sender<PowerEvent>) };
+        match kind {
+            PBT_APMSUSPEND => {
+                super::notify(sender, PowerEvent::Suspending);
+            }
+            PBT_APMRESUMEAUTOMATIC | PBT_APMRESUMESUSPEND => {
+                super::notify(sender, PowerEvent::Resumed);
+            }
+            _ => {}
+        }
+        0
+    }
+}
+
+/// No power notifications on this platform
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+#[derive(Debug)]
+struct PowerSource;
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+impl PowerSource {
+    fn start(_sender: Sender<PowerEvent>) -> Result<Self> {
+        anyhow::bail!("No suspend and resume notifications on this platform")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_for_sleep_signals_become_power_events() {
+        let mut stream = logind::prepare_for_sleep(true);
+        stream.extend(logind::prepare_for_sleep(false));
+
+        let (len, suspending) = logind::parse(&stream).unwrap().unwrap();
+        assert_eq!(suspending.member.as_deref(), Some("PrepareForSleep"));
+        assert_eq!(suspending.power_event(), Some(PowerEvent::Suspending));
+        let (rest, resumed) = logind::parse(&stream[len..]).unwrap().unwrap();
+        assert_eq!(len + rest, stream.len());
+        assert_eq!(resumed.power_event(), Some(PowerEvent::Resumed));
+
+        // Incomplete messages wait for more bytes, other messages are no power events
+        assert!(logind::parse(&stream[..len - 1]).unwrap().is_none());
+        let (_, call) = logind::parse(&logind::add_match()).unwrap().unwrap();
+        assert_eq!(call.signature.as_deref(), Some("s"));
+        assert_eq!(call.power_event(), None);
+    }
+}
@@ -0,0 +1,117 @@
+// Capture prerequisites: what packet capture needs on this platform and whether it is in place
+// Each platform module reports its own checks, built on the same check_privileges and
+// get_required_capabilities the capture path uses; `kw doctor` lists them and applies fixes
+
+use std::path::PathBuf;
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// Capture works, but with less than it could
+    Warning,
+    /// Capture will not work until this is fixed
+    Missing,
+}
+
+/// A change `kw doctor --fix` can make, after asking
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub description: String,
+    /// Programs and their arguments, run in order until one fails
+    pub commands: Vec<Vec<String>>,
+    /// Whether the commands need root, so sudo is put in front when not running as root
+    pub privileged: bool,
+}
+
+impl Fix {
+    /// The commands as they would be typed
+    pub fn command_lines(&self) -> Vec<String> {
+        self.commands.iter().map(|command| command.join(" ")).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prerequisite {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<Fix>,
+}
+
+impl Prerequisite {
+    pub fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status, detail: detail.into(), fix: None }
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+/// Every check for this platform, capture first
+pub fn check_prerequisites() -> Vec<Prerequisite> {
+    #[cfg(target_os = "linux")]
+    let mut checks = super::linux::LinuxPacketCapture::prerequisites();
+    #[cfg(target_os = "macos")]
+    let mut checks = super::macos::MacOSPacketCapture::prerequisites();
+    #[cfg(target_os = "windows")]
+    let mut checks = super::windows::WindowsPacketCapture::prerequisites();
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    let mut checks = vec![Prerequisite::new(
+        "Packet capture",
+        CheckStatus::Missing,
+        "Unsupported platform for packet capture",
+    )];
+
+    checks.push(ping_check());
+    checks
+}
+
+/// The connectivity monitor runs the system `ping` program
+fn ping_check() -> Prerequisite {
+    match find_program("ping") {
+        Some(path) => Prerequisite::new("ping program", CheckStatus::Ok, path.display().to_string()),
+        None => Prerequisite::new(
+            "ping program",
+            CheckStatus::Warning,
+            "Not found in PATH; connectivity targets will show as down",
+        ),
+    }
+}
+
+/// First `program` in PATH
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let file_name = if cfg!(windows) { format!("{program}.exe") } else { program.to_string() };
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        // sbin directories are often missing from a user's PATH but hold setcap and friends
+        .chain(["/usr/sbin", "/sbin"].map(PathBuf::from).into_iter().filter(|_| cfg!(unix)))
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fix_command_lines() {
+        let fix = Fix {
+            description: "Grant capture capabilities".to_string(),
+            commands: vec![vec!["setcap".to_string(), "cap_net_raw=eip".to_string(), "/usr/bin/kw".to_string()]],
+            privileged: true,
+        };
+        assert_eq!(fix.command_lines(), ["setcap cap_net_raw=eip /usr/bin/kw"]);
+    }
+
+    #[test]
+    fn test_checks_end_with_ping() {
+        let checks = check_prerequisites();
+        assert!(checks.len() >= 2);
+        assert_eq!(checks.last().unwrap().name, "ping program");
+    }
+}
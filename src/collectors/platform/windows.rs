@@ -3,6 +3,7 @@ use async_trait::async_trait;
 use log::{debug, info, warn};
 use std::process::Command;
 
+use super::prerequisites::{CheckStatus, Prerequisite};
 use super::PlatformPacketCapture;
 
 pub struct WindowsPacketCapture {
@@ -57,6 +58,42 @@ impl WindowsPacketCapture {
         Ok(false)
     }
 
+    /// Whether Npcap or WinPcap is installed; without either, packets come from kernel network events
+    pub fn has_capture_driver() -> bool {
+        Self::check_npcap_installation().unwrap_or(false) || Self::check_winpcap_installation().unwrap_or(false)
+    }
+
+    /// What `kw doctor` reports for Windows; neither can be fixed from here
+    pub fn prerequisites() -> Vec<Prerequisite> {
+        let admin = if Self::check_privileges().unwrap_or(false) {
+            Prerequisite::new("Administrator privileges", CheckStatus::Ok, "Running elevated")
+        } else {
+            Prerequisite::new(
+                "Administrator privileges",
+                CheckStatus::Missing,
+                "Run kw from an Administrator prompt (right-click > Run as administrator)",
+            )
+        };
+        let driver = if Self::check_npcap_installation().unwrap_or(false) {
+            Prerequisite::new("Capture driver", CheckStatus::Ok, "Npcap installed")
+        } else if Self::check_winpcap_installation().unwrap_or(false) {
+            Prerequisite::new(
+                "Capture driver",
+                CheckStatus::Warning,
+                "Only WinPcap found, which is no longer maintained; install Npcap from https://npcap.com/",
+            )
+        } else {
+            Prerequisite::new(
+                "Capture driver",
+                CheckStatus::Warning,
+                "Npcap not installed; kw packets counts bytes per connection and process from kernel \
+                 network events, without packet contents or TCP state. Install Npcap from https://npcap.com/ \
+                 with WinPcap API-compatible mode for full capture",
+            )
+        };
+        vec![admin, driver]
+    }
+
     fn get_available_interfaces() -> Result<Vec<String>> {
         let output = Command::new("netsh")
             .args(["interface", "show", "interface"])
@@ -0,0 +1,328 @@
+// Process attribution: maps the local end of TCP/UDP sockets to the owning process
+// so captured packets can be charged to the program that sent or received them
+//
+// - Linux: socket inodes from /proc/net/{tcp,udp}[6], matched against /proc/<pid>/fd
+// - macOS: `lsof -nP -iTCP -iUDP -F pcPn`
+// - Windows: `netstat -ano`, with process names from sysinfo
+//
+// Only sockets the current user may inspect are visible, so traffic of other users'
+// processes stays unattributed unless kw runs with elevated privileges
+
+use anyhow::Result;
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::models::{NetworkPacket, PacketDirection, TransportProtocol};
+
+pub use crate::models::ProcessInfo;
+
+/// A missed lookup refreshes the socket table at most this often
+const MISS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Local socket of a given transport protocol
+type SocketKey = (TransportProtocol, u16);
+
+/// Port-to-process table, refreshed from the operating system on demand
+pub struct ProcessCollector {
+    sockets: HashMap<SocketKey, ProcessInfo>,
+    last_refresh: Option<Instant>,
+}
+
+impl ProcessCollector {
+    pub fn new() -> Self {
+        Self {
+            sockets: HashMap::new(),
+            last_refresh: None,
+        }
+    }
+
+    /// Rebuilds the socket table
+    pub fn refresh(&mut self) -> Result<()> {
+        self.last_refresh = Some(Instant::now());
+        self.sockets = platform_sockets()?;
+        debug!("Process collector knows {} sockets", self.sockets.len());
+        Ok(())
+    }
+
+    /// Process owning local `port`
+    pub fn lookup(&self, protocol: TransportProtocol, port: u16) -> Option<&ProcessInfo> {
+        self.sockets.get(&(protocol, port))
+    }
+
+    /// Process on this host that sent or received `packet`
+    /// The process the capture source tagged the packet with wins; otherwise unknown ports
+    /// trigger a rate-limited refresh, so short-lived sockets are still found
+    pub fn attribute(&mut self, packet: &NetworkPacket) -> Option<ProcessInfo> {
+        if let Some(process) = &packet.process {
+            return Some(process.clone());
+        }
+        if !matches!(packet.transport_protocol, TransportProtocol::Tcp | TransportProtocol::Udp) {
+            return None;
+        }
+
+        let found = self.find(packet);
+        if found.is_some() {
+            return found;
+        }
+
+        let stale = self.last_refresh.is_none_or(|t| t.elapsed() >= MISS_REFRESH_INTERVAL);
+        if stale {
+            if let Err(e) = self.refresh() {
+                debug!("Failed to refresh process sockets: {e}");
+            }
+            return self.find(packet);
+        }
+        None
+    }
+
+    fn find(&self, packet: &NetworkPacket) -> Option<ProcessInfo> {
+        let protocol = packet.transport_protocol;
+        let ports = match packet.direction {
+            PacketDirection::Outbound => [packet.source_port, None],
+            PacketDirection::Inbound => [packet.dest_port, None],
+            PacketDirection::Local => [packet.source_port, packet.dest_port],
+        };
+        ports
+            .into_iter()
+            .flatten()
+            .find_map(|port| self.lookup(protocol, port))
+            .cloned()
+    }
+}
+
+impl Default for ProcessCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn platform_sockets() -> Result<HashMap<SocketKey, ProcessInfo>> {
+    use std::fs;
+
+    // inode -> local socket
+    let mut inodes: HashMap<u64, SocketKey> = HashMap::new();
+    for (file, protocol) in [
+        ("/proc/net/tcp", TransportProtocol::Tcp),
+        ("/proc/net/tcp6", TransportProtocol::Tcp),
+        ("/proc/net/udp", TransportProtocol::Udp),
+        ("/proc/net/udp6", TransportProtocol::Udp),
+    ] {
+        if let Ok(contents) = fs::read_to_string(file) {
+            for (port, inode) in parse_proc_net(&contents) {
+                inodes.insert(inode, (protocol, port));
+            }
+        }
+    }
+
+    let mut sockets = HashMap::new();
+    for entry in fs::read_dir("/proc")?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else {
+            continue;
+        };
+        // Processes of other users are unreadable without privileges; skip them
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+
+        let mut name = None;
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else { continue };
+            let Some(inode) = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:["))
+                .and_then(|t| t.strip_suffix(']'))
+                .and_then(|t| t.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if let Some(key) = inodes.get(&inode) {
+                let name = name.get_or_insert_with(|| {
+                    fs::read_to_string(entry.path().join("comm"))
+                        .map(|s| s.trim().to_string())
+                        .unwrap_or_else(|_| pid.to_string())
+                });
+                sockets.insert(*key, ProcessInfo { pid, name: name.clone() });
+            }
+        }
+    }
+
+    Ok(sockets)
+}
+
+/// Local ports and socket inodes from a /proc/net/{tcp,udp}[6] table
+#[cfg(target_os = "linux")]
+fn parse_proc_net(contents: &str) -> Vec<(u16, u64)> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let port = fields.get(1)?.rsplit_once(':')?.1;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let inode = fields.get(9)?.parse::<u64>().ok()?;
+            // Inode 0 means the socket is already gone (e.g. TIME_WAIT)
+            (inode != 0).then_some((port, inode))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn platform_sockets() -> Result<HashMap<SocketKey, ProcessInfo>> {
+    use anyhow::Context;
+
+    let output = std::process::Command::new("lsof")
+        .args(["-nP", "-iTCP", "-iUDP", "-F", "pcPn"])
+        .output()
+        .context("Failed to run lsof")?;
+    Ok(parse_lsof(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses `lsof -F pcPn` field output
+#[cfg(any(target_os = "macos", test))]
+fn parse_lsof(output: &str) -> HashMap<SocketKey, ProcessInfo> {
+    let mut sockets = HashMap::new();
+    let mut pid = None;
+    let mut name = String::new();
+    let mut protocol = None;
+
+    for line in output.lines() {
+        let (field, value) = line.split_at(line.len().min(1));
+        match field {
+            "p" => {
+                pid = value.parse::<u32>().ok();
+                name.clear();
+            }
+            "c" => name = value.to_string(),
+            "P" => {
+                protocol = match value {
+                    "TCP" => Some(TransportProtocol::Tcp),
+                    "UDP" => Some(TransportProtocol::Udp),
+                    _ => None,
+                }
+            }
+            "n" => {
+                let local = value.split("->").next().unwrap_or(value);
+                let port = local.rsplit_once(':').and_then(|(_, p)| p.parse::<u16>().ok());
+                if let (Some(pid), Some(protocol), Some(port)) = (pid, protocol, port) {
+                    sockets.insert((protocol, port), ProcessInfo { pid, name: name.clone() });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    sockets
+}
+
+#[cfg(target_os = "windows")]
+fn platform_sockets() -> Result<HashMap<SocketKey, ProcessInfo>> {
+    use anyhow::Context;
+    use sysinfo::{Pid, ProcessesToUpdate, System};
+
+    let output = std::process::Command::new("netstat")
+        .args(["-ano"])
+        .output()
+        .context("Failed to run netstat")?;
+
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::All, true);
+
+    Ok(parse_netstat(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(|(key, pid)| {
+            let name = system
+                .process(Pid::from_u32(pid))
+                .map(|p| p.name().to_string_lossy().into_owned())
+                .unwrap_or_else(|| pid.to_string());
+            (key, ProcessInfo { pid, name })
+        })
+        .collect())
+}
+
+/// Local sockets and owning process ids from `netstat -ano`
+#[cfg(any(target_os = "windows", test))]
+fn parse_netstat(output: &str) -> HashMap<SocketKey, u32> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let protocol = match *fields.first()? {
+                "TCP" => TransportProtocol::Tcp,
+                "UDP" => TransportProtocol::Udp,
+                _ => return None,
+            };
+            let port = fields.get(1)?.rsplit_once(':')?.1.parse::<u16>().ok()?;
+            let pid = fields.last()?.parse::<u32>().ok()?;
+            Some(((protocol, port), pid))
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_sockets() -> Result<HashMap<SocketKey, ProcessInfo>> {
+    anyhow::bail!("Process attribution is not supported on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PacketProtocol;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_net() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41237 1 0000000000000000 100 0 0 10 0\n\
+   1: 0100007F:A2B4 0100007F:1F90 06 00000000:00000000 03:00001234 00000000     0        0 0 3 0000000000000000\n";
+        assert_eq!(parse_proc_net(table), vec![(8080, 41237)]);
+    }
+
+    #[test]
+    fn test_parse_lsof_fields() {
+        let output = "p412\ncfirefox\nf31\nPTCP\nn192.168.1.10:52344->93.184.216.34:443\nf40\nPUDP\nn*:5353\np77\ncmDNSResponder\nf5\nPUDP\nn[::1]:53\n";
+        let sockets = parse_lsof(output);
+
+        assert_eq!(sockets.len(), 3);
+        assert_eq!(sockets[&(TransportProtocol::Tcp, 52344)], ProcessInfo { pid: 412, name: "firefox".to_string() });
+        assert_eq!(sockets[&(TransportProtocol::Udp, 5353)].pid, 412);
+        assert_eq!(sockets[&(TransportProtocol::Udp, 53)].name, "mDNSResponder");
+    }
+
+    #[test]
+    fn test_parse_netstat_rows() {
+        let output = "\nActive Connections\n\n  Proto  Local Address          Foreign Address        State           PID\n  \
+TCP    0.0.0.0:135            0.0.0.0:0              LISTENING       1020\n  \
+TCP    [::1]:49712            [::1]:5432             ESTABLISHED     6400\n  \
+UDP    0.0.0.0:500            *:*                                    3900\n";
+        let sockets = parse_netstat(output);
+
+        assert_eq!(sockets.len(), 3);
+        assert_eq!(sockets[&(TransportProtocol::Tcp, 49712)], 6400);
+        assert_eq!(sockets[&(TransportProtocol::Udp, 500)], 3900);
+    }
+
+    #[test]
+    fn test_attribute_uses_local_port_for_direction() {
+        let mut collector = ProcessCollector::new();
+        collector.last_refresh = Some(Instant::now());
+        let firefox = ProcessInfo { pid: 412, name: "firefox".to_string() };
+        collector.sockets.insert((TransportProtocol::Tcp, 52344), firefox.clone());
+
+        let mut packet = NetworkPacket::new("eth0".to_string(), 1500, PacketProtocol::IPv4, PacketDirection::Inbound);
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_port = Some(443);
+        packet.dest_port = Some(52344);
+        assert_eq!(collector.attribute(&packet), Some(firefox));
+
+        // Outbound packets are charged by their source port
+        packet.direction = PacketDirection::Outbound;
+        assert_eq!(collector.attribute(&packet), None);
+
+        // A process reported by the capture source needs no lookup
+        let safari = ProcessInfo { pid: 733, name: "Safari".to_string() };
+        packet.process = Some(safari.clone());
+        assert_eq!(collector.attribute(&packet), Some(safari));
+    }
+}
@@ -0,0 +1,449 @@
+// Kernel statistics of this host's own TCP sockets, the view `ss -ti` gives
+// Packet inspection only sees what crosses the wire; the kernel also knows each socket's
+// congestion window, its smoothed round-trip time and how often it had to retransmit
+//
+// - Linux: a NETLINK_SOCK_DIAG dump of the AF_INET and AF_INET6 TCP sockets (inet_diag),
+//   asking for their tcp_info and congestion control algorithm
+// - Other platforms: nothing is read and no connection is enriched
+//
+// Only connections the host itself is an end of have a socket here; traffic merely passing
+// through a router or mirror port has none
+
+use anyhow::Result;
+use log::warn;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How often sockets are read while capturing
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the kernel knows about a TCP connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpInfo {
+    /// Congestion window, in segments
+    pub congestion_window: u32,
+    /// Smoothed round-trip time, in microseconds
+    pub rtt_us: u32,
+    /// Mean deviation of the round-trip time, in microseconds
+    pub rtt_var_us: u32,
+    /// Segments retransmitted over the socket's lifetime
+    pub retransmits: u32,
+    /// Segments currently presumed lost
+    pub lost: u32,
+    /// Congestion control algorithm, e.g. "cubic" or "bbr"
+    pub congestion_control: Option<String>,
+}
+
+impl TcpInfo {
+    pub fn rtt_ms(&self) -> f64 {
+        self.rtt_us as f64 / 1000.0
+    }
+
+    pub fn rtt_var_ms(&self) -> f64 {
+        self.rtt_var_us as f64 / 1000.0
+    }
+
+    /// e.g. "cwnd 10, rtt 23.4±1.2ms, 3 retransmits, 0 lost (cubic)"
+    pub fn describe(&self) -> String {
+        let summary = format!(
+            "cwnd {}, rtt {:.1}±{:.1}ms, {} retransmits, {} lost",
+            self.congestion_window,
+            self.rtt_ms(),
+            self.rtt_var_ms(),
+            self.retransmits,
+            self.lost
+        );
+        match &self.congestion_control {
+            Some(algorithm) => format!("{summary} ({algorithm})"),
+            None => summary,
+        }
+    }
+}
+
+/// One of the host's TCP sockets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketStats {
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub info: TcpInfo,
+}
+
+/// Reads the host's TCP sockets, giving up for good after the first failure
+#[derive(Debug, Default)]
+pub struct SocketStatsCollector {
+    unavailable: bool,
+}
+
+impl SocketStatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every connected TCP socket the kernel has statistics for; empty where they cannot be read
+    pub fn collect(&mut self) -> Vec<SocketStats> {
+        if self.unavailable {
+            return Vec::new();
+        }
+        match read_tcp_sockets() {
+            Ok(sockets) => sockets,
+            Err(e) => {
+                warn!("Kernel socket statistics are not available: {e}");
+                self.unavailable = true;
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Every connected TCP socket the kernel has statistics for
+#[cfg(target_os = "linux")]
+pub fn read_tcp_sockets() -> Result<Vec<SocketStats>> {
+    let socket = netlink::Socket::open()?;
+    let mut sockets = socket.dump(AF_INET)?;
+    sockets.extend(socket.dump(AF_INET6)?);
+    log::debug!("Read kernel statistics of {} TCP sockets", sockets.len());
+    Ok(sockets)
+}
+
+/// Every connected TCP socket the kernel has statistics for
+#[cfg(not(target_os = "linux"))]
+pub fn read_tcp_sockets() -> Result<Vec<SocketStats>> {
+    Ok(Vec::new())
+}
+
+// inet_diag values from linux/inet_diag.h and linux/sock_diag.h, fixed by the Linux ABI
+#[cfg(any(target_os = "linux", test))]
+mod diag {
+    pub const NLMSG_HEADER_LEN: usize = 16;
+    pub const NLMSG_ERROR: u16 = 2;
+    pub const NLMSG_DONE: u16 = 3;
+    pub const SOCK_DIAG_BY_FAMILY: u16 = 20;
+    /// struct inet_diag_req_v2
+    pub const INET_DIAG_REQ_LEN: usize = 56;
+    /// struct inet_diag_msg, followed by its attributes
+    pub const INET_DIAG_MSG_LEN: usize = 72;
+    pub const INET_DIAG_INFO: u16 = 2;
+    pub const INET_DIAG_CONG: u16 = 4;
+    /// Every TCP state but LISTEN (10) and CLOSE (7), which carry no connection
+    pub const CONNECTED_STATES: u32 = !(1 << 10 | 1 << 7);
+    /// Linux's address family numbers, which other platforms do not share
+    pub const AF_INET: u8 = 2;
+    pub const AF_INET6: u8 = 10;
+    /// Attribute type bits that only say how the payload is laid out
+    pub const NLA_TYPE_MASK: u16 = !(1 << 15 | 1 << 14);
+
+    // Offsets into struct tcp_info from linux/tcp.h
+    pub const TCPI_LOST: usize = 32;
+    pub const TCPI_RTT: usize = 68;
+    pub const TCPI_RTTVAR: usize = 72;
+    pub const TCPI_SND_CWND: usize = 80;
+    pub const TCPI_TOTAL_RETRANS: usize = 100;
+    /// Kernels since 2.6.x report at least this much of tcp_info
+    pub const TCPI_MIN_LEN: usize = 104;
+}
+
+#[cfg(any(target_os = "linux", test))]
+use diag::*;
+
+/// What a batch of netlink messages held
+#[cfg(any(target_os = "linux", test))]
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Messages {
+    sockets: Vec<SocketStats>,
+    /// The dump ended
+    done: bool,
+}
+
+/// Splits a batch of netlink messages and reads the sockets in them
+#[cfg(any(target_os = "linux", test))]
+fn parse_messages(mut buffer: &[u8]) -> Result<Messages> {
+    use anyhow::{bail, Context};
+
+    let mut messages = Messages::default();
+    while buffer.len() >= NLMSG_HEADER_LEN {
+        let length = u32::from_ne_bytes(buffer[0..4].try_into().unwrap()) as usize;
+        let kind = u16::from_ne_bytes(buffer[4..6].try_into().unwrap());
+        if length < NLMSG_HEADER_LEN || length > buffer.len() {
+            bail!("Truncated netlink message");
+        }
+        let payload = &buffer[NLMSG_HEADER_LEN..length];
+        match kind {
+            NLMSG_DONE => messages.done = true,
+            NLMSG_ERROR => {
+                let code = payload.get(0..4).map(|code| i32::from_ne_bytes(code.try_into().unwrap())).unwrap_or(0);
+                if code != 0 {
+                    return Err(std::io::Error::from_raw_os_error(-code)).context("Socket diagnostics request failed");
+                }
+                messages.done = true;
+            }
+            SOCK_DIAG_BY_FAMILY if payload.len() >= INET_DIAG_MSG_LEN => {
+                messages.sockets.extend(parse_socket(payload));
+            }
+            _ => {}
+        }
+        buffer = &buffer[align(length).min(buffer.len())..];
+    }
+    Ok(messages)
+}
+
+/// A socket from an inet_diag_msg; sockets without tcp_info (such as TIME_WAIT) are left out
+#[cfg(any(target_os = "linux", test))]
+fn parse_socket(payload: &[u8]) -> Option<SocketStats> {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    let family = payload[0];
+    let address = |bytes: &[u8]| -> Option<IpAddr> {
+        match family {
+            AF_INET => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&bytes[..4]).ok()?))),
+            // IPv4 peers of dual-stack sockets appear as ::ffff:a.b.c.d, captured packets do not
+            AF_INET6 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)).to_canonical()),
+            _ => None,
+        }
+    };
+    // struct inet_diag_sockid: ports in network order, then the source and destination
+    let local_port = u16::from_be_bytes([payload[4], payload[5]]);
+    let remote_port = u16::from_be_bytes([payload[6], payload[7]]);
+    let local = SocketAddr::new(address(&payload[8..24])?, local_port);
+    let remote = SocketAddr::new(address(&payload[24..40])?, remote_port);
+
+    let mut info = None;
+    let mut congestion_control = None;
+    for (kind, value) in attributes(&payload[INET_DIAG_MSG_LEN..]) {
+        match kind {
+            INET_DIAG_INFO => info = parse_tcp_info(value),
+            INET_DIAG_CONG => congestion_control = Some(c_string(value)).filter(|name| !name.is_empty()),
+            _ => {}
+        }
+    }
+    let info = TcpInfo { congestion_control, ..info? };
+    Some(SocketStats { local, remote, info })
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_tcp_info(value: &[u8]) -> Option<TcpInfo> {
+    if value.len() < TCPI_MIN_LEN {
+        return None;
+    }
+    let field = |offset: usize| u32::from_ne_bytes(value[offset..offset + 4].try_into().unwrap());
+    Some(TcpInfo {
+        congestion_window: field(TCPI_SND_CWND),
+        rtt_us: field(TCPI_RTT),
+        rtt_var_us: field(TCPI_RTTVAR),
+        retransmits: field(TCPI_TOTAL_RETRANS),
+        lost: field(TCPI_LOST),
+        congestion_control: None,
+    })
+}
+
+/// (type, payload) of each attribute in `data`
+#[cfg(any(target_os = "linux", test))]
+fn attributes(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let length = u16::from_ne_bytes([data[0], data[1]]) as usize;
+        let kind = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+        if length < 4 || length > data.len() {
+            return None;
+        }
+        let value = &data[4..length];
+        data = &data[align(length).min(data.len())..];
+        Some((kind, value))
+    })
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn c_string(value: &[u8]) -> String {
+    let end = value.iter().position(|byte| *byte == 0).unwrap_or(value.len());
+    String::from_utf8_lossy(&value[..end]).into_owned()
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+#[cfg(target_os = "linux")]
+mod netlink {
+    use super::*;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    /// How long the kernel gets to answer a dump
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// A NETLINK_SOCK_DIAG socket for one dump at a time
+    pub struct Socket {
+        fd: OwnedFd,
+    }
+
+    impl Socket {
+        pub fn open() -> Result<Self> {
+            let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_SOCK_DIAG) };
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let socket = Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } };
+
+            let timeout = libc::timeval { tv_sec: TIMEOUT.as_secs() as libc::time_t, tv_usec: 0 };
+            unsafe {
+                libc::setsockopt(
+                    socket.fd.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    &timeout as *const _ as *const libc::c_void,
+                    std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+                );
+            }
+            Ok(socket)
+        }
+
+        /// Every connected TCP socket of address family `family`, with its tcp_info and
+        /// congestion control algorithm
+        pub fn dump(&self, family: u8) -> Result<Vec<SocketStats>> {
+            let mut request = Vec::with_capacity(NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN);
+            request.extend_from_slice(&((NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN) as u32).to_ne_bytes());
+            request.extend_from_slice(&SOCK_DIAG_BY_FAMILY.to_ne_bytes());
+            request.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16).to_ne_bytes());
+            request.extend_from_slice(&1u32.to_ne_bytes());
+            request.extend_from_slice(&0u32.to_ne_bytes());
+            // struct inet_diag_req_v2; extensions are asked for as 1 << (attribute - 1)
+            let extensions = (1u8 << (INET_DIAG_INFO - 1)) | (1u8 << (INET_DIAG_CONG - 1));
+            request.extend_from_slice(&[family, libc::IPPROTO_TCP as u8, extensions, 0]);
+            request.extend_from_slice(&CONNECTED_STATES.to_ne_bytes());
+            request.resize(NLMSG_HEADER_LEN + INET_DIAG_REQ_LEN, 0);
+
+            let sent = unsafe { libc::send(self.fd.as_raw_fd(), request.as_ptr() as *const libc::c_void, request.len(), 0) };
+            if sent < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            let mut sockets = Vec::new();
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                let read = unsafe { libc::recv(self.fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+                if read < 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                let messages = parse_messages(&buffer[..read as usize])?;
+                sockets.extend(messages.sockets);
+                if messages.done || read == 0 {
+                    return Ok(sockets);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+        encoded.extend_from_slice(&kind.to_ne_bytes());
+        encoded.extend_from_slice(value);
+        encoded.resize(align(encoded.len()), 0);
+        encoded
+    }
+
+    fn message(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&((NLMSG_HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+        encoded.extend_from_slice(&kind.to_ne_bytes());
+        encoded.extend_from_slice(&[0u8; 10]);
+        encoded.extend_from_slice(payload);
+        encoded
+    }
+
+    /// An inet_diag_msg for a socket between two addresses, with its attributes
+    fn diag_message(family: u8, local: (&[u8], u16), remote: (&[u8], u16), attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = vec![family, 1, 0, 0];
+        payload.extend_from_slice(&local.1.to_be_bytes());
+        payload.extend_from_slice(&remote.1.to_be_bytes());
+        for address in [local.0, remote.0] {
+            let mut padded = address.to_vec();
+            padded.resize(16, 0);
+            payload.extend_from_slice(&padded);
+        }
+        payload.resize(INET_DIAG_MSG_LEN, 0);
+        for attribute in attributes {
+            payload.extend_from_slice(attribute);
+        }
+        message(SOCK_DIAG_BY_FAMILY, &payload)
+    }
+
+    fn tcp_info(cwnd: u32, rtt_us: u32, rtt_var_us: u32, retransmits: u32, lost: u32) -> Vec<u8> {
+        let mut info = vec![0u8; 232];
+        for (offset, value) in [
+            (TCPI_SND_CWND, cwnd),
+            (TCPI_RTT, rtt_us),
+            (TCPI_RTTVAR, rtt_var_us),
+            (TCPI_TOTAL_RETRANS, retransmits),
+            (TCPI_LOST, lost),
+        ] {
+            info[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+        }
+        info
+    }
+
+    #[test]
+    fn test_parse_socket_dump() {
+        let mut batch = diag_message(
+            AF_INET,
+            (&[192, 168, 1, 20], 51000),
+            (&[93, 184, 216, 34], 443),
+            &[attribute(INET_DIAG_INFO, &tcp_info(10, 23_400, 1_200, 3, 1)), attribute(INET_DIAG_CONG, b"cubic\0")],
+        );
+        // A dual-stack socket talking to an IPv4 peer
+        let mapped = |last: u8| [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, last];
+        batch.extend(diag_message(
+            AF_INET6,
+            (&mapped(5), 8080),
+            (&mapped(9), 40000),
+            &[attribute(INET_DIAG_INFO, &tcp_info(42, 300, 100, 0, 0)), attribute(INET_DIAG_CONG, b"bbr\0")],
+        ));
+        // TIME_WAIT sockets come without tcp_info
+        batch.extend(diag_message(AF_INET, (&[127, 0, 0, 1], 5000), (&[127, 0, 0, 1], 6000), &[]));
+        // A short tcp_info from an unexpected kernel is skipped rather than misread
+        batch.extend(diag_message(AF_INET, (&[10, 0, 0, 1], 1), (&[10, 0, 0, 2], 2), &[attribute(INET_DIAG_INFO, &[0u8; 16])]));
+        batch.extend(message(NLMSG_DONE, &0i32.to_ne_bytes()));
+
+        let messages = parse_messages(&batch).unwrap();
+        assert!(messages.done);
+        assert_eq!(messages.sockets.len(), 2);
+
+        let https = &messages.sockets[0];
+        assert_eq!(https.local, "192.168.1.20:51000".parse().unwrap());
+        assert_eq!(https.remote, "93.184.216.34:443".parse().unwrap());
+        assert_eq!(
+            https.info,
+            TcpInfo {
+                congestion_window: 10,
+                rtt_us: 23_400,
+                rtt_var_us: 1_200,
+                retransmits: 3,
+                lost: 1,
+                congestion_control: Some("cubic".to_string()),
+            }
+        );
+        assert_eq!(https.info.describe(), "cwnd 10, rtt 23.4±1.2ms, 3 retransmits, 1 lost (cubic)");
+
+        let dual_stack = &messages.sockets[1];
+        assert_eq!(dual_stack.local, "10.0.0.5:8080".parse().unwrap());
+        assert_eq!(dual_stack.remote, "10.0.0.9:40000".parse().unwrap());
+        assert_eq!(dual_stack.info.congestion_window, 42);
+    }
+
+    #[test]
+    fn test_parse_messages_reports_errors() {
+        let denied = message(NLMSG_ERROR, &(-libc::EPERM).to_ne_bytes());
+        assert!(parse_messages(&denied).is_err());
+
+        let mut truncated = message(NLMSG_DONE, &[0u8; 4]);
+        truncated.truncate(NLMSG_HEADER_LEN + 2);
+        truncated[0..4].copy_from_slice(&64u32.to_ne_bytes());
+        assert!(parse_messages(&truncated).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+// SpeedTester: Active throughput measurement against HTTP endpoints
+// Times small requests for latency, then downloads and uploads payloads for as long as the
+// test allows, measuring what the connection can reach rather than what it happens to carry
+
+use anyhow::{bail, Context, Result};
+use std::time::{Duration, Instant};
+
+use crate::config::SpeedTestConfig;
+
+/// First upload request size; later requests grow while they finish quickly
+const INITIAL_UPLOAD_CHUNK: u64 = 256 * 1024;
+
+/// Upload requests grow until one takes about this long, so per-request overhead stays small
+const TARGET_UPLOAD_REQUEST: Duration = Duration::from_secs(1);
+
+/// Largest single upload request
+const MAX_UPLOAD_CHUNK: u64 = 16 * 1024 * 1024;
+
+/// Median latency and jitter of the timed requests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyMeasurement {
+    pub median_ms: f64,
+    /// Mean difference between consecutive samples
+    pub jitter_ms: f64,
+}
+
+impl LatencyMeasurement {
+    /// None when there are no samples
+    pub fn from_samples(samples_ms: &[f64]) -> Option<Self> {
+        if samples_ms.is_empty() {
+            return None;
+        }
+        let mut sorted = samples_ms.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        let median_ms = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        };
+        let jitter_ms = if samples_ms.len() < 2 {
+            0.0
+        } else {
+            samples_ms.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f64>() / (samples_ms.len() - 1) as f64
+        };
+        Some(Self { median_ms, jitter_ms })
+    }
+}
+
+/// Bytes moved by a download or upload test and the time they took
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transfer {
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+impl Transfer {
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.duration.as_secs_f64().max(0.001)
+    }
+}
+
+/// Runs the phases of a speed test against the configured endpoints
+pub struct SpeedTester {
+    client: reqwest::Client,
+    config: SpeedTestConfig,
+}
+
+impl SpeedTester {
+    pub fn new(config: SpeedTestConfig) -> Result<Self> {
+        for url in [&config.download_url, &config.latency_url] {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                bail!("Speed test endpoint must be an http:// or https:// URL: {url}");
+            }
+        }
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create speed test client")?;
+        Ok(Self { client, config })
+    }
+
+    /// Host the download test runs against, shown with and stored with the results
+    pub fn server(&self) -> String {
+        reqwest::Url::parse(&self.config.download_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| self.config.download_url.clone())
+    }
+
+    pub fn has_upload(&self) -> bool {
+        !self.config.upload_url.is_empty()
+    }
+
+    fn test_duration(&self) -> Duration {
+        Duration::from_secs(self.config.duration_secs.max(1))
+    }
+
+    /// Times `latency_samples` small requests; the first request opens the connection
+    /// (DNS, TCP and TLS) and is not counted
+    pub async fn measure_latency(&self) -> Result<LatencyMeasurement> {
+        let mut samples = Vec::with_capacity(self.config.latency_samples);
+        for attempt in 0..=self.config.latency_samples {
+            let start = Instant::now();
+            let response = self
+                .client
+                .get(&self.config.latency_url)
+                .timeout(self.test_duration())
+                .send()
+                .await
+                .with_context(|| format!("Latency request to {} failed", self.config.latency_url))?;
+            if !response.status().is_success() {
+                bail!("Latency endpoint returned {}", response.status());
+            }
+            response.bytes().await?;
+            if attempt > 0 {
+                samples.push(start.elapsed().as_secs_f64() * 1000.0);
+            }
+        }
+        LatencyMeasurement::from_samples(&samples).context("No latency samples taken")
+    }
+
+    /// Reads the download endpoint until it ends or the test duration is up
+    /// Timing starts at the response headers, so connection setup does not count
+    pub async fn measure_download(&self) -> Result<Transfer> {
+        let mut response = self
+            .client
+            .get(&self.config.download_url)
+            .send()
+            .await
+            .with_context(|| format!("Download from {} failed", self.config.download_url))?;
+        if !response.status().is_success() {
+            bail!("Download endpoint returned {}", response.status());
+        }
+
+        let start = Instant::now();
+        let mut bytes = 0u64;
+        while start.elapsed() < self.test_duration() {
+            let remaining = self.test_duration().saturating_sub(start.elapsed());
+            match tokio::time::timeout(remaining, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => bytes += chunk.len() as u64,
+                Ok(Ok(None)) => break,
+                Ok(Err(e)) => return Err(e).context("Download interrupted"),
+                Err(_) => break,
+            }
+        }
+        Ok(Transfer { bytes, duration: start.elapsed() })
+    }
+
+    /// POSTs growing payloads to the upload endpoint until `upload_bytes` are sent or
+    /// the test duration is up
+    pub async fn measure_upload(&self) -> Result<Transfer> {
+        if !self.has_upload() {
+            bail!("No upload endpoint configured");
+        }
+
+        let start = Instant::now();
+        let mut bytes = 0u64;
+        let mut chunk = INITIAL_UPLOAD_CHUNK.min(self.config.upload_bytes.max(1));
+        while bytes < self.config.upload_bytes && start.elapsed() < self.test_duration() {
+            let size = chunk.min(self.config.upload_bytes - bytes);
+            let request_start = Instant::now();
+            let response = self
+                .client
+                .post(&self.config.upload_url)
+                .timeout(self.test_duration() * 2)
+                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                .body(vec![0u8; size as usize])
+                .send()
+                .await
+                .with_context(|| format!("Upload to {} failed", self.config.upload_url))?;
+            if !response.status().is_success() {
+                bail!("Upload endpoint returned {}", response.status());
+            }
+            response.bytes().await?;
+            bytes += size;
+
+            if request_start.elapsed() < TARGET_UPLOAD_REQUEST {
+                chunk = (chunk * 2).min(MAX_UPLOAD_CHUNK);
+            }
+        }
+        Ok(Transfer { bytes, duration: start.elapsed() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_median_and_jitter() {
+        let latency = LatencyMeasurement::from_samples(&[10.0, 14.0, 12.0, 30.0]).unwrap();
+        assert_eq!(latency.median_ms, 13.0);
+        // |14-10| + |12-14| + |30-12| over three steps
+        assert_eq!(latency.jitter_ms, 8.0);
+
+        let single = LatencyMeasurement::from_samples(&[20.0]).unwrap();
+        assert_eq!((single.median_ms, single.jitter_ms), (20.0, 0.0));
+        assert!(LatencyMeasurement::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn test_endpoints_validated() {
+        let config = SpeedTestConfig { download_url: "ftp://example.net/file".to_string(), ..SpeedTestConfig::default() };
+        assert!(SpeedTester::new(config).is_err());
+
+        let tester = SpeedTester::new(SpeedTestConfig::default()).unwrap();
+        assert_eq!(tester.server(), "speed.cloudflare.com");
+        assert!(tester.has_upload());
+    }
+}
@@ -0,0 +1,229 @@
+// VPN tunnels and the interfaces carrying their encrypted traffic
+//
+// - Tunnels: on Linux the uevent DEVTYPE (wireguard) or a tun_flags file (tun/tap devices);
+//   elsewhere, and for names Linux does not know, the usual names: wg*, tun*, tap*, utun*,
+//   ipsec*, and Windows adapters named after WireGuard, a VPN or a tunnel
+// - Transport: the interface of the default route that is not itself a tunnel. VPN clients
+//   route the internet through the tunnel with narrower or policy routes and leave the
+//   default route on the link the encrypted packets go out of
+//   - Linux: /proc/net/route, then /proc/net/ipv6_route, lowest metric first
+//   - macOS: the `default` lines of `netstat -rn`
+//   - Elsewhere: not detected; tunnels are listed, and counted, on their own
+//
+// A tunnel's traffic crosses its transport a second time, encrypted, so totals count it once,
+// through the transport, whenever both are listed
+
+use std::fmt;
+
+/// What kind of tunnel an interface is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelKind {
+    WireGuard,
+    /// tun/tap devices: OpenVPN, macOS utun (Network Extension VPNs) and the like
+    Tun,
+    Ipsec,
+}
+
+impl fmt::Display for TunnelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunnelKind::WireGuard => write!(f, "wireguard"),
+            TunnelKind::Tun => write!(f, "tun"),
+            TunnelKind::Ipsec => write!(f, "ipsec"),
+        }
+    }
+}
+
+/// A VPN tunnel interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tunnel {
+    pub name: String,
+    pub kind: TunnelKind,
+    /// Interface the encrypted packets leave through, when known
+    pub transport: Option<String>,
+}
+
+impl fmt::Display for Tunnel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.transport {
+            Some(transport) => write!(f, "{} over {transport}", self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// The tunnels among `names`, sorted by name
+pub fn detect_tunnels<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<Tunnel> {
+    let mut tunnels: Vec<(String, TunnelKind)> =
+        names.into_iter().filter_map(|name| Some((name.to_string(), tunnel_kind(name)?))).collect();
+    if tunnels.is_empty() {
+        return Vec::new();
+    }
+    tunnels.sort_by(|a, b| a.0.cmp(&b.0));
+    tunnels.dedup_by(|a, b| a.0 == b.0);
+
+    let transport = default_routes().into_iter().find(|name| tunnel_kind(name).is_none());
+    tunnels
+        .into_iter()
+        .map(|(name, kind)| Tunnel { name, kind, transport: transport.clone() })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn tunnel_kind(name: &str) -> Option<TunnelKind> {
+    let dir = std::path::Path::new("/sys/class/net").join(name);
+    let uevent = std::fs::read_to_string(dir.join("uevent")).unwrap_or_default();
+    match uevent.lines().find_map(|line| line.strip_prefix("DEVTYPE=")) {
+        Some("wireguard") => Some(TunnelKind::WireGuard),
+        _ if dir.join("tun_flags").exists() => Some(TunnelKind::Tun),
+        _ => tunnel_kind_from_name(name),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn tunnel_kind(name: &str) -> Option<TunnelKind> {
+    tunnel_kind_from_name(name)
+}
+
+fn tunnel_kind_from_name(name: &str) -> Option<TunnelKind> {
+    let name = name.to_lowercase();
+    if name.starts_with("wg") || name.contains("wireguard") {
+        Some(TunnelKind::WireGuard)
+    } else if name.starts_with("ipsec") {
+        Some(TunnelKind::Ipsec)
+    } else if ["tun", "tap", "utun"].iter().any(|prefix| name.starts_with(prefix))
+        || name.contains("vpn")
+        || name.contains("tunnel")
+    {
+        Some(TunnelKind::Tun)
+    } else {
+        None
+    }
+}
+
+/// Interfaces with a default route, preferred first
+#[cfg(target_os = "linux")]
+fn default_routes() -> Vec<String> {
+    let ipv4 = std::fs::read_to_string("/proc/net/route").unwrap_or_default();
+    let ipv6 = std::fs::read_to_string("/proc/net/ipv6_route").unwrap_or_default();
+    let mut routes = parse_proc_route(&ipv4);
+    routes.extend(parse_proc_ipv6_route(&ipv6));
+    routes
+}
+
+/// Interfaces with a default route, preferred first
+#[cfg(target_os = "macos")]
+fn default_routes() -> Vec<String> {
+    match std::process::Command::new("netstat").args(["-rn", "-f", "inet"]).output() {
+        Ok(output) => parse_netstat_routes(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Interfaces with a default route, preferred first
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn default_routes() -> Vec<String> {
+    Vec::new()
+}
+
+/// Default routes of /proc/net/route, lowest metric first
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_route(table: &str) -> Vec<String> {
+    // Iface Destination Gateway Flags RefCnt Use Metric Mask MTU Window IRTT
+    let mut routes: Vec<(u32, &str)> = table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (iface, destination, metric, mask) = (fields.first()?, fields.get(1)?, fields.get(6)?, fields.get(7)?);
+            (*destination == "00000000" && *mask == "00000000").then_some((metric.parse().ok()?, *iface))
+        })
+        .collect();
+    routes.sort_by_key(|(metric, _)| *metric);
+    routes.into_iter().map(|(_, iface)| iface.to_string()).collect()
+}
+
+/// Default routes of /proc/net/ipv6_route, lowest metric first
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_ipv6_route(table: &str) -> Vec<String> {
+    // destination prefix source prefix next-hop metric refcnt use flags iface
+    let mut routes: Vec<(u32, &str)> = table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (destination, prefix, metric, iface) = (fields.first()?, fields.get(1)?, fields.get(5)?, fields.get(9)?);
+            let default = destination.bytes().all(|digit| digit == b'0') && *prefix == "00" && *iface != "lo";
+            default.then_some((u32::from_str_radix(metric, 16).ok()?, *iface))
+        })
+        .collect();
+    routes.sort_by_key(|(metric, _)| *metric);
+    routes.into_iter().map(|(_, iface)| iface.to_string()).collect()
+}
+
+/// Interfaces of the `default` lines of `netstat -rn`, in table order
+#[cfg(any(target_os = "macos", test))]
+fn parse_netstat_routes(output: &str) -> Vec<String> {
+    // Destination Gateway Flags Netif Expire
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            (fields.first() == Some(&"default")).then(|| fields.get(3).map(|netif| netif.to_string()))?
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tunnel_kind_from_name() {
+        assert_eq!(tunnel_kind_from_name("wg0"), Some(TunnelKind::WireGuard));
+        assert_eq!(tunnel_kind_from_name("WireGuard Tunnel"), Some(TunnelKind::WireGuard));
+        assert_eq!(tunnel_kind_from_name("utun3"), Some(TunnelKind::Tun));
+        assert_eq!(tunnel_kind_from_name("tun0"), Some(TunnelKind::Tun));
+        assert_eq!(tunnel_kind_from_name("ipsec0"), Some(TunnelKind::Ipsec));
+        assert_eq!(tunnel_kind_from_name("OpenVPN Data Channel Offload"), Some(TunnelKind::Tun));
+        assert_eq!(tunnel_kind_from_name("eth0"), None);
+        assert_eq!(tunnel_kind_from_name("en0"), None);
+    }
+
+    #[test]
+    fn test_parse_proc_route() {
+        let table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                     wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+                     tun0\t00000080\t0100080A\t0003\t0\t0\t0\t00000080\t0\t0\t0\n\
+                     eth0\t00000000\t0100000A\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+                     eth0\t0000000A\t00000000\t0001\t0\t0\t100\t0000FFFF\t0\t0\t0\n";
+        assert_eq!(parse_proc_route(table), vec!["eth0", "wlan0"]);
+    }
+
+    #[test]
+    fn test_parse_proc_ipv6_route() {
+        let table = "00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000400 00000001 00000000 00000003 wlan0\n\
+                     20010db8000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001 wlan0\n\
+                     00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200 lo\n";
+        assert_eq!(parse_proc_ipv6_route(table), vec!["wlan0"]);
+    }
+
+    #[test]
+    fn test_parse_netstat_routes() {
+        let output = "Routing tables\n\nInternet:\n\
+                      Destination        Gateway            Flags               Netif Expire\n\
+                      0/1                10.8.0.1           UGScg               utun4\n\
+                      default            link#21            UCSIg               utun4\n\
+                      default            192.168.1.1        UGScIg                en0\n\
+                      127                127.0.0.1          UCS                   lo0\n";
+        let routes = parse_netstat_routes(output);
+        assert_eq!(routes, vec!["utun4", "en0"]);
+        assert_eq!(routes.into_iter().find(|name| tunnel_kind_from_name(name).is_none()).as_deref(), Some("en0"));
+    }
+
+    #[test]
+    fn test_tunnel_display() {
+        let tunnel = Tunnel { name: "wg0".to_string(), kind: TunnelKind::WireGuard, transport: Some("eth0".to_string()) };
+        assert_eq!(tunnel.to_string(), "wireguard over eth0");
+        assert_eq!(Tunnel { transport: None, ..tunnel }.to_string(), "wireguard");
+    }
+}
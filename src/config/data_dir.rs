@@ -0,0 +1,351 @@
+// Data directories: where each profile keeps its packet database and state
+// The default profile lives in the user's data directory - $XDG_DATA_HOME or ~/.local/share on
+// Linux, ~/Library/Application Support on macOS, %LOCALAPPDATA% on Windows - and named profiles
+// beside it under profiles/, so every command finds the same database wherever it is run from
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use super::DataConfig;
+
+/// Name of the packet database in a data directory
+pub const DATABASE_FILE: &str = "packets.db";
+
+/// Counter baselines the daemon keeps in a data directory across restarts
+pub const COUNTER_STATE_FILE: &str = "counter-state.json";
+
+/// Profile used without `--profile` or `[data] profile`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Where kw kept its data before data directories, relative to the working directory
+const LEGACY_DIR: &str = "./data";
+
+/// Directory under the data directory holding the named profiles
+pub const PROFILES_DIR: &str = "profiles";
+
+/// Longest profile name accepted
+const MAX_PROFILE_LEN: usize = 64;
+
+/// The data directory of one profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDir {
+    profile: String,
+    base: PathBuf,
+    dir: PathBuf,
+}
+
+impl DataDir {
+    /// The directory of `profile`, or of `[data] profile` when none is given
+    ///
+    /// `[data] dir` replaces the platform's data directory; without a home directory to put
+    /// one in, `./data` is used as before
+    pub fn resolve(config: &DataConfig, profile: Option<&str>) -> Result<Self> {
+        let profile = profile.or(config.profile.as_deref()).unwrap_or(DEFAULT_PROFILE);
+        validate_profile(profile)?;
+        let base = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => platform_data_dir().unwrap_or_else(|| PathBuf::from("./data")),
+        };
+        Ok(Self::in_base(base, profile))
+    }
+
+    /// `profile`'s directory under `base`: `base` itself for the default profile
+    fn in_base(base: PathBuf, profile: &str) -> Self {
+        let dir = if profile == DEFAULT_PROFILE { base.clone() } else { base.join(PROFILES_DIR).join(profile) };
+        Self { profile: profile.to_string(), base, dir }
+    }
+
+    /// Every profile in the same data directory, the default first and the others by name
+    pub fn all_profiles(&self) -> Vec<Self> {
+        let mut names: Vec<String> = std::fs::read_dir(self.base.join(PROFILES_DIR))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| validate_profile(name).is_ok())
+            .collect();
+        names.sort();
+        std::iter::once(DEFAULT_PROFILE.to_string())
+            .chain(names)
+            .map(|name| Self::in_base(self.base.clone(), &name))
+            .collect()
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The packet database of this profile
+    pub fn database(&self) -> PathBuf {
+        self.dir.join(DATABASE_FILE)
+    }
+
+    /// Moves the database and counter state an earlier kw left in `./data` into this
+    /// directory, once: only for the default profile while it has no database of its own
+    /// Returns the directory they were moved from
+    pub fn adopt_legacy_data(&self) -> Result<Option<PathBuf>> {
+        let legacy = Path::new(LEGACY_DIR);
+        Ok(self.adopt_data_from(legacy)?.then(|| legacy.to_path_buf()))
+    }
+
+    fn adopt_data_from(&self, legacy: &Path) -> Result<bool> {
+        let database = legacy.join(DATABASE_FILE);
+        if self.profile != DEFAULT_PROFILE || !database.is_file() || self.database().exists() {
+            return Ok(false);
+        }
+        if std::path::absolute(&database).ok() == std::path::absolute(self.database()).ok() {
+            return Ok(false);
+        }
+        create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        // The write-ahead log goes before the database it belongs to, so an interrupted move
+        // leaves the database behind to be moved again on the next run
+        let wal = format!("{DATABASE_FILE}-wal");
+        let shm = format!("{DATABASE_FILE}-shm");
+        for name in [COUNTER_STATE_FILE, wal.as_str(), shm.as_str(), DATABASE_FILE] {
+            let from = legacy.join(name);
+            if from.is_file() {
+                move_file(&from, &self.dir.join(name))?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Renames `from` to `to`, copying it when they are on different file systems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to).with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+        std::fs::remove_file(from).with_context(|| format!("Failed to remove {}", from.display()))?;
+    }
+    give_to_sudo_user(to);
+    Ok(())
+}
+
+/// Profile names become directory names, so they are kept to letters, digits, `-`, `_` and `.`
+fn validate_profile(profile: &str) -> Result<()> {
+    let valid_chars = profile.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if profile.is_empty() || profile.len() > MAX_PROFILE_LEN || !valid_chars || profile.starts_with('.') {
+        bail!(
+            "Invalid profile name: {profile:?} (use up to {MAX_PROFILE_LEN} letters, digits, '-', '_' or '.', not starting with '.')"
+        );
+    }
+    Ok(())
+}
+
+/// kw's directory in the user's data directory, when there is a home to find it in
+fn platform_data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library").join("Application Support"))
+    } else if let Some(home) = sudo_user_home() {
+        // sudo passes XDG_DATA_HOME on, but may not be the invoking user's
+        Some(home.join(".local").join("share"))
+    } else {
+        // The XDG spec asks for relative values to be ignored
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home_dir().map(|home| home.join(".local").join("share")))
+    };
+    base.map(|dir| dir.join("kaipo-watcher"))
+}
+
+/// The home of the invoking user, also when run through sudo, so that `sudo kw packets`
+/// records where their own `kw report` reads
+fn home_dir() -> Option<PathBuf> {
+    sudo_user_home().or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+}
+
+fn sudo_user_home() -> Option<PathBuf> {
+    SudoUser::get().map(|user| user.home.clone())
+}
+
+/// Creates `dir` and its missing parents; under sudo, those in the invoking user's home are
+/// given to that user, so their own kw can still write to them
+pub fn create_dir_all(dir: &Path) -> std::io::Result<()> {
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    std::fs::create_dir_all(dir)?;
+    if let Some(user) = SudoUser::get() {
+        for created in missing.iter().rev() {
+            user.give(created);
+        }
+    }
+    Ok(())
+}
+
+/// Under sudo, gives `path` to the invoking user when it lies in their home, so the database
+/// and state files root creates in their data directory stay writable by their own kw
+pub fn give_to_sudo_user(path: &Path) {
+    if let Some(user) = SudoUser::get() {
+        user.give(path);
+    }
+}
+
+/// The user who ran kw through sudo
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SudoUser {
+    uid: u32,
+    gid: u32,
+    home: PathBuf,
+}
+
+impl SudoUser {
+    /// `SUDO_UID` and its group and home, when running as root through sudo
+    fn get() -> Option<&'static Self> {
+        static SUDO_USER: LazyLock<Option<SudoUser>> = LazyLock::new(SudoUser::lookup);
+        SUDO_USER.as_ref()
+    }
+
+    #[cfg(unix)]
+    fn lookup() -> Option<Self> {
+        use std::ffi::{CStr, OsStr};
+        use std::os::unix::ffi::OsStrExt;
+
+        let uid: libc::uid_t = std::env::var("SUDO_UID").ok()?.parse().ok()?;
+        if unsafe { libc::geteuid() } != 0 {
+            return None;
+        }
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; 4096];
+        let mut result = std::ptr::null_mut();
+        let rc = unsafe { libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        if rc != 0 || result.is_null() || passwd.pw_dir.is_null() {
+            return None;
+        }
+        let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+        let gid = std::env::var("SUDO_GID").ok().and_then(|gid| gid.parse().ok()).unwrap_or(passwd.pw_gid);
+        Some(Self { uid, gid, home: PathBuf::from(OsStr::from_bytes(dir.to_bytes())) })
+    }
+
+    #[cfg(not(unix))]
+    fn lookup() -> Option<Self> {
+        None
+    }
+
+    /// Makes the user the owner of `path`, if it is in their home
+    fn give(&self, path: &Path) {
+        let Ok(path) = std::path::absolute(path) else {
+            return;
+        };
+        if !path.starts_with(&self.home) || !path.exists() {
+            return;
+        }
+        #[cfg(unix)]
+        if let Err(e) = std::os::unix::fs::chown(&path, Some(self.uid), Some(self.gid)) {
+            warn!("Failed to give {} to uid {}: {e}", path.display(), self.uid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_live_beside_the_default() {
+        let config = DataConfig { dir: Some(PathBuf::from("/srv/kw")), profile: None };
+        let default = DataDir::resolve(&config, None).unwrap();
+        assert_eq!(default.profile(), DEFAULT_PROFILE);
+        assert_eq!(default.database(), Path::new("/srv/kw/packets.db"));
+
+        let work = DataDir::resolve(&config, Some("work")).unwrap();
+        assert_eq!(work.dir(), Path::new("/srv/kw/profiles/work"));
+
+        // --profile wins over [data] profile, which wins over the default
+        let config = DataConfig { profile: Some("lab".to_string()), ..config };
+        assert_eq!(DataDir::resolve(&config, None).unwrap().profile(), "lab");
+        assert_eq!(DataDir::resolve(&config, Some("default")).unwrap().dir(), Path::new("/srv/kw"));
+    }
+
+    #[test]
+    fn test_all_profiles_are_found() {
+        let base = tempfile::tempdir().unwrap();
+        for name in ["work", "home", ".hidden"] {
+            std::fs::create_dir_all(base.path().join(PROFILES_DIR).join(name)).unwrap();
+        }
+        std::fs::write(base.path().join(PROFILES_DIR).join("notes.txt"), b"").unwrap();
+
+        let work = DataDir::in_base(base.path().to_path_buf(), "work");
+        let names: Vec<String> = work.all_profiles().iter().map(|data| data.profile().to_string()).collect();
+        assert_eq!(names, ["default", "home", "work"]);
+        assert_eq!(work.all_profiles()[0].dir(), base.path());
+    }
+
+    #[test]
+    fn test_data_from_dot_data_moves_once() {
+        let legacy = tempfile::tempdir().unwrap();
+        let base = tempfile::tempdir().unwrap();
+        for name in [DATABASE_FILE, "packets.db-wal", COUNTER_STATE_FILE] {
+            std::fs::write(legacy.path().join(name), name).unwrap();
+        }
+        std::fs::write(legacy.path().join("notes.txt"), b"mine").unwrap();
+
+        // Named profiles start afresh
+        let work = DataDir::in_base(base.path().to_path_buf(), "work");
+        assert!(!work.adopt_data_from(legacy.path()).unwrap());
+
+        let data = DataDir::in_base(base.path().join("kaipo-watcher"), DEFAULT_PROFILE);
+        assert!(data.adopt_data_from(legacy.path()).unwrap());
+        for name in [DATABASE_FILE, "packets.db-wal", COUNTER_STATE_FILE] {
+            assert_eq!(std::fs::read_to_string(data.dir().join(name)).unwrap(), name);
+            assert!(!legacy.path().join(name).exists());
+        }
+        assert!(legacy.path().join("notes.txt").exists());
+
+        // Once the profile has a database, a new one in ./data is left alone
+        std::fs::write(legacy.path().join(DATABASE_FILE), b"newer").unwrap();
+        assert!(!data.adopt_data_from(legacy.path()).unwrap());
+        assert_eq!(std::fs::read_to_string(data.database()).unwrap(), DATABASE_FILE);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_files_created_under_sudo_go_to_the_user() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let home = tempfile::tempdir().unwrap();
+        let user = SudoUser { uid: 65534, gid: 65534, home: home.path().to_path_buf() };
+        let dir = home.path().join(".local/share/kaipo-watcher");
+        std::fs::create_dir(home.path().join(".local")).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join(DATABASE_FILE);
+        std::fs::write(&database, b"db").unwrap();
+        let elsewhere = tempfile::NamedTempFile::new().unwrap();
+
+        for path in [home.path().join(".local/share"), dir.clone(), database.clone()] {
+            user.give(&path);
+        }
+        user.give(elsewhere.path());
+
+        use std::os::unix::fs::MetadataExt;
+        for path in [&dir, &database] {
+            let metadata = std::fs::metadata(path).unwrap();
+            assert_eq!((metadata.uid(), metadata.gid()), (65534, 65534), "{}", path.display());
+        }
+        // Only paths in the user's home are handed over
+        assert_eq!(std::fs::metadata(elsewhere.path()).unwrap().uid(), 0);
+    }
+
+    #[test]
+    fn test_profile_names_stay_inside_the_data_directory() {
+        for name in ["work", "home-lab", "client_2", "v1.2"] {
+            assert!(validate_profile(name).is_ok(), "{name}");
+        }
+        for name in ["", "..", ".hidden", "a/b", "a\\b", "c:", &"x".repeat(MAX_PROFILE_LEN + 1)] {
+            assert!(validate_profile(name).is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,1171 @@
+// Application configuration: an optional TOML file plus KW_* environment overrides
+// Every key has a default, so kw runs unchanged when no configuration exists
+
+pub mod data_dir;
+pub mod resources;
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::collectors::bandwidth::SamplingPlan;
+use crate::collectors::platform::CaptureBackend;
+use crate::collectors::packet_sampling::{SampleRate, DEFAULT_SAMPLE_THRESHOLD_PPS};
+use crate::models::{PrivacyMode, Redactor, ServiceMap};
+
+pub use data_dir::DataDir;
+pub use resources::ResourceProfile;
+
+/// Environment variable naming an explicit configuration file
+pub const CONFIG_PATH_ENV: &str = "KW_CONFIG";
+
+/// Top-level configuration
+///
+/// ```toml
+/// [data]                   # where the packet database and state are kept
+/// dir = "/srv/kw"          # the default profile's directory, named profiles in its profiles/; the platform's data directory when unset
+/// profile = "work"         # profile used without --profile
+///
+/// [encryption]             # packet databases encrypted at rest (the `encryption` build feature)
+/// key = "<passphrase>"     # better given as KW_ENCRYPTION__KEY than written here
+/// keychain = true          # else read the profile's key from the OS keychain (`kw encryption enable --generate-key`)
+///
+/// [storage]                # where `kw service run` writes its records
+/// backend = "sqlite"       # sqlite | postgres (the `postgres` build feature)
+/// url = "postgres://kw@db.lan/kw?sslmode=require"  # needed by postgres; better given as KW_STORAGE__URL
+/// name = "office-pi"       # machine column of the rows written to PostgreSQL; the host name by default
+///
+/// [low_memory]
+/// mode = "auto"          # auto | on | off
+/// ram_threshold_mb = 1024
+/// disable_analyzer = false
+///
+/// [capture]
+/// backend = "af-packet"  # af-packet | af-xdp (Linux; af-xdp only on interfaces without addresses)
+/// sample = "1/100"       # analyze one packet in 100 while over the threshold; all when unset
+/// sample_threshold_pps = 50000
+///
+/// [dns]
+/// backend = "system"     # system | doh | server
+/// doh_url = "https://cloudflare-dns.com/dns-query"
+/// server = "192.168.1.1" # used by backend = "server", port 53 unless given
+/// timeout_ms = 2000
+/// cache_ttl_secs = 3600
+///
+/// [geoip]                  # MaxMind DB files for `kw report --geo`; GeoLite2 or DB-IP Lite
+/// country_database = "/var/lib/GeoIP/GeoLite2-Country.mmdb"  # a City database works too
+/// asn_database = "/var/lib/GeoIP/GeoLite2-ASN.mmdb"
+/// expected_countries = ["NZ", "AU", "US"]  # ISO codes; traffic to others is flagged when set
+///
+/// [contention]
+/// saturation_percent = 80 # link utilisation that opens the contention panel and fires on_link_saturated
+/// link_speed_mbps = 100    # overrides the detected link speed
+///
+/// [refresh]                # how `kw live` slows its update interval when collecting is costly
+/// auto = true              # false keeps --interval whatever the load (as does --fixed-interval)
+/// max_interval_secs = 10   # longest interval it backs off to
+/// slow_collection_percent = 25 # share of the interval one collection may take
+/// cpu_percent = 50         # kw's own CPU use, in percent of one core, that counts as busy
+///
+/// [smoothing]
+/// window_secs = 5          # moving average over the speeds `kw live` shows; 0 (default) shows raw readings
+///
+/// [sampling.intervals]     # seconds between readings, per interface
+/// "eth0" = 1               # exact name
+/// "docker*" = 30           # glob
+/// "@container" = 30        # interface group
+///
+/// [services.ports]        # service names shown for ports, ahead of the built-in table
+/// 8443 = "unifi"           # any transport
+/// "51821/udp" = "wg-guest" # one transport: tcp or udp
+///
+/// [web]
+/// listen = "0.0.0.0:8080"  # serve the browser UI from `kw service run`
+///
+/// [security]
+/// window_secs = 60         # sliding window the thresholds below are counted over
+/// port_scan_ports = 20     # ports probed on one host by one source
+/// syn_flood_syns = 1000    # connection attempts to one host from distinct sources
+/// icmp_sweep_hosts = 16    # hosts pinged by one source
+/// unknown_traffic_percent = 20 # rise in the unclassified share of a minute's traffic
+///
+/// [anomaly]
+/// sensitivity = 3.0        # standard deviations from the hourly baseline that count as unusual
+/// smoothing = 0.05         # EWMA weight of each new sample in the baseline
+/// min_samples = 30         # samples an hour needs before it is judged
+/// history_days = 14        # stored history the baseline is learned from
+/// min_change_bytes_per_sec = 50000 # smaller deviations are never flagged
+///
+/// [congestion]             # ping latency that rises with throughput, as with bufferbloat
+/// latency_increase_ms = 30 # rise over the idle round-trip time that counts as congestion
+/// load_percent = 50        # share of the busiest throughput seen that counts as under load
+/// idle_percent = 10        # below this share the link counts as idle, for the baseline
+/// min_load_bytes_per_sec = 125000 # less throughput is never taken as load
+///
+/// [quota]                  # data allowance that `kw quota` and `kw report` forecast usage against
+/// monthly_gb = 500         # per billing period; [hooks] monthly_cap_gb when unset
+/// reset_day = 1            # day of the month the allowance resets, 1-31 (the last day in shorter months)
+///
+/// [speedtest]
+/// download_url = "https://speed.cloudflare.com/__down?bytes=100000000"
+/// upload_url = "https://speed.cloudflare.com/__up"  # POST target; empty skips the upload test
+/// latency_url = "https://speed.cloudflare.com/__down?bytes=0"
+/// duration_secs = 10       # longest each of download and upload runs
+/// upload_bytes = 50000000  # most bytes sent by the upload test
+/// latency_samples = 10     # latency requests timed
+///
+/// [ping]
+/// targets = ["gateway", "1.1.1.1"] # "gateway" is the default gateway; empty turns pinging off
+/// interval_secs = 2        # seconds between probes of each target
+/// timeout_ms = 1000        # probes unanswered this long count as lost
+///
+/// [display]
+/// units = "bytes"          # bytes (KB/s, MB/s) | bits (kbit/s, Mbit/s) for rates in reports and graphs
+/// locale = "auto"          # thousands separator and decimal mark, e.g. "de-DE"; auto follows LC_ALL/LC_NUMERIC/LANG
+///
+/// [privacy]                # redact IP addresses and host names in output, as --privacy does
+/// mode = "off"             # off | hash (ip-3fa2c1, host-8b1e0d) | truncate (192.168.x.x, *.example.com)
+/// salt = "team-reports"    # keeps hashes the same across runs; a new one is made up each run when unset
+///
+/// [retention]
+/// auto = true              # apply the policy from `kw service run`; `kw maintain` applies it on demand
+/// interval_hours = 6       # hours between automatic runs
+/// raw_days = 31            # raw packet records and per-minute samples; older samples become hourly rollups
+/// hourly_days = 180        # hourly rollups; older ones are merged into daily rollups
+/// daily_days = 1095        # daily rollups; 0 keeps them, and any stage set to 0 is skipped
+///
+/// [link_aggregation]
+/// group = true             # list bond, bridge and team interfaces once, with their member links beneath
+/// show_members = true      # false shows only the aggregate's own line
+///
+/// [hooks]                  # shell commands run with a JSON payload on stdin and KW_EVENT set
+/// on_alert = "notify-send 'kw alert'"      # port scan, SYN flood, ICMP sweep, unknown-traffic surge
+/// on_interface_change = "/usr/local/bin/vpn-check"
+/// on_cap_reached = "/usr/local/bin/plug off"
+/// on_link_saturated = "notify-send 'link busy'"  # throughput reached [contention] saturation_percent
+/// monthly_cap_gb = 500     # this calendar month's captured traffic that fires on_cap_reached
+/// timeout_secs = 30        # hooks still running after this are killed
+///
+/// [webhook]                # POST bandwidth and protocol summaries from `kw service run`
+/// url = "http://homeassistant.local:8123/api/webhook/kaipo-watcher"
+/// auth_header = "Authorization: Bearer <token>"   # optional, sent with every request
+/// interval_secs = 300      # period each summary covers
+/// timeout_secs = 10        # per request
+/// retries = 3              # further attempts after a failed request
+/// backoff_secs = 2         # wait before the first retry, doubled for each one after
+///
+/// [mqtt]                   # publish each interface's speed and today's usage from `kw service run`
+/// broker = "mqtt://192.168.1.10:1883"
+/// topic_prefix = "kaipo-watcher"   # topics are <prefix>/<interface>/download_bps and so on
+/// qos = 0                  # 0, 1 or 2
+/// retain = true            # dashboards show the last value straight after subscribing
+/// client_id = "kaipo-watcher"
+/// username = "kw"          # optional, as is password
+/// password = "secret"
+/// keep_alive_secs = 60
+/// insecure = false         # send username and password to a broker beyond this machine in the clear
+///
+/// [remote]                 # `kw agent` streams to `kw hub` over gRPC
+/// hub = "https://hub.lan:50051"    # where `kw agent` reports; https or ca_cert turns TLS on
+/// listen = "0.0.0.0:50051" # where `kw hub` accepts agents
+/// token = "change-me"      # shared bearer token; agents without it are refused
+/// name = "nas"             # this machine in the hub's listings; the host name by default
+/// interval_secs = 10       # seconds between an agent's reports
+/// tls_cert = "/etc/kaipo-watcher/hub.pem"  # hub certificate and key; plain gRPC without them
+/// tls_key = "/etc/kaipo-watcher/hub.key"
+/// ca_cert = "/etc/kaipo-watcher/ca.pem"    # agents trust this CA instead of the system roots
+/// tls_domain = "hub.lan"   # name the hub's certificate is checked against, if not the hub's host
+///
+/// [api]
+/// listen = "127.0.0.1:8081" # where `kw api` serves the read-only JSON API
+/// ```
+///
+/// Any key can be overridden from the environment, e.g. `KW_LOW_MEMORY__MODE=on`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub data: DataConfig,
+    pub encryption: EncryptionConfig,
+    pub storage: StorageConfig,
+    pub low_memory: LowMemoryConfig,
+    pub capture: CaptureConfig,
+    pub dns: DnsConfig,
+    pub geoip: GeoIpConfig,
+    pub contention: ContentionConfig,
+    pub refresh: RefreshConfig,
+    pub smoothing: SmoothingConfig,
+    pub sampling: SamplingConfig,
+    pub services: ServicesConfig,
+    pub web: WebConfig,
+    pub security: SecurityConfig,
+    pub anomaly: AnomalyConfig,
+    pub congestion: CongestionConfig,
+    pub quota: QuotaConfig,
+    pub speedtest: SpeedTestConfig,
+    pub ping: PingConfig,
+    pub display: DisplayConfig,
+    pub privacy: PrivacyConfig,
+    pub retention: RetentionConfig,
+    pub link_aggregation: LinkAggregationConfig,
+    pub hooks: HooksConfig,
+    pub webhook: WebhookConfig,
+    pub mqtt: MqttConfig,
+    pub remote: RemoteConfig,
+    pub api: ApiConfig,
+}
+
+/// Where data is kept; `--profile` overrides `profile`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DataConfig {
+    /// Directory of the default profile, in place of the platform's data directory
+    pub dir: Option<PathBuf>,
+    /// Profile used without `--profile`
+    pub profile: Option<String>,
+}
+
+/// Key of encrypted packet databases; `key` wins over the keychain
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub key: Option<String>,
+    /// Read the key from the OS keychain, stored there under the profile's name
+    pub keychain: bool,
+}
+
+/// Database the monitoring daemon writes its records to
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: DatabaseBackend,
+    /// Connection string of the PostgreSQL database, as a URL or `key=value` pairs
+    pub url: Option<String>,
+    /// Name the rows written to PostgreSQL carry, telling machines apart; the host name when unset
+    pub name: Option<String>,
+}
+
+/// Kind of database behind `[storage]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    /// The profile's packet database
+    #[default]
+    Sqlite,
+    /// A PostgreSQL server several daemons can share
+    Postgres,
+}
+
+/// When to trade buffer sizes and analysis depth for a smaller memory footprint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowMemoryMode {
+    /// Enabled when total RAM is below `ram_threshold_mb`
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+/// Settings for the low-memory runtime mode
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LowMemoryConfig {
+    pub mode: LowMemoryMode,
+    /// Total RAM (MiB) below which `auto` switches low-memory mode on
+    pub ram_threshold_mb: u64,
+    /// Skip protocol analysis of captured packets while in low-memory mode
+    pub disable_analyzer: bool,
+}
+
+impl Default for LowMemoryConfig {
+    fn default() -> Self {
+        Self {
+            mode: LowMemoryMode::Auto,
+            ram_threshold_mb: 1024,
+            disable_analyzer: false,
+        }
+    }
+}
+
+/// How packets are captured; `--capture-backend`, `--sample` and `--sample-threshold`
+/// override these for `kw packets`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Kernel interface capture reads from on Linux
+    pub backend: CaptureBackend,
+    /// Fraction of packets analyzed and stored while traffic is over `sample_threshold_pps`;
+    /// every packet is when unset
+    pub sample: Option<SampleRate>,
+    pub sample_threshold_pps: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            backend: CaptureBackend::default(),
+            sample: None,
+            sample_threshold_pps: DEFAULT_SAMPLE_THRESHOLD_PPS,
+        }
+    }
+}
+
+/// Where reverse DNS lookups for host name enrichment are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsBackend {
+    /// The operating system resolver (`getnameinfo`)
+    #[default]
+    System,
+    /// A DNS-over-HTTPS endpoint (RFC 8484)
+    Doh,
+    /// A specific DNS server queried directly over UDP
+    Server,
+}
+
+/// Settings for reverse DNS enrichment
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    pub backend: DnsBackend,
+    /// Endpoint used by the `doh` backend
+    pub doh_url: String,
+    /// Address (optionally `ip:port`) used by the `server` backend
+    pub server: Option<String>,
+    /// Give up on a single lookup after this many milliseconds
+    pub timeout_ms: u64,
+    /// How long answers, including "no name", are reused
+    pub cache_ttl_secs: u64,
+    /// Addresses kept in the lookup cache
+    pub cache_size: usize,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            backend: DnsBackend::System,
+            doh_url: "https://cloudflare-dns.com/dns-query".to_string(),
+            server: None,
+            timeout_ms: 2000,
+            cache_ttl_secs: 3600,
+            cache_size: 4096,
+        }
+    }
+}
+
+/// MaxMind DB files that place remote addresses in countries and autonomous systems
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GeoIpConfig {
+    /// GeoLite2-Country, GeoLite2-City or DB-IP's country database
+    pub country_database: Option<PathBuf>,
+    /// GeoLite2-ASN or DB-IP's ASN database
+    pub asn_database: Option<PathBuf>,
+    /// ISO 3166 country codes traffic is expected to reach; empty flags none
+    pub expected_countries: Vec<String>,
+}
+
+impl GeoIpConfig {
+    /// Whether traffic to `country` (an ISO code) is outside the expected countries
+    pub fn is_unexpected(&self, country: &str) -> bool {
+        !self.expected_countries.is_empty()
+            && !self.expected_countries.iter().any(|expected| expected.eq_ignore_ascii_case(country))
+    }
+}
+
+/// Settings for the live dashboard's bandwidth contention panel, also used for link
+/// utilisation in `kw status` and the `on_link_saturated` hook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ContentionConfig {
+    /// Utilisation of the link speed, in percent, at which a link counts as saturated
+    pub saturation_percent: f64,
+    /// Link speed used instead of the one reported by the operating system
+    /// Needed for Wi-Fi and virtual interfaces, or when an upstream link is the real bottleneck
+    pub link_speed_mbps: Option<u64>,
+}
+
+impl Default for ContentionConfig {
+    fn default() -> Self {
+        Self {
+            saturation_percent: 80.0,
+            link_speed_mbps: None,
+        }
+    }
+}
+
+/// When the live dashboard backs off its update interval, and how far
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RefreshConfig {
+    /// Lengthen the interval under load and shorten it again once things calm down
+    pub auto: bool,
+    /// Longest interval backing off goes to, in seconds
+    pub max_interval_secs: u64,
+    /// Share of the interval, in percent, a collection may take before it counts as slow
+    pub slow_collection_percent: f64,
+    /// CPU use of kw itself, in percent of one core, that counts as busy
+    pub cpu_percent: f64,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            max_interval_secs: 10,
+            slow_collection_percent: 25.0,
+            cpu_percent: 50.0,
+        }
+    }
+}
+
+/// Smoothing of the speeds the live dashboard shows; stored readings are never smoothed
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SmoothingConfig {
+    /// Window of the moving average, in seconds; 0 turns smoothing off
+    pub window_secs: f64,
+}
+
+/// Per-interface collection intervals for the dashboard and daemon
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SamplingConfig {
+    /// Interface name, glob (`br-*`) or `@group` mapped to an interval in seconds
+    pub intervals: HashMap<String, u64>,
+}
+
+impl SamplingConfig {
+    /// Validated sampling plan; empty when no intervals are configured
+    pub fn plan(&self) -> Result<SamplingPlan> {
+        SamplingPlan::from_intervals(&self.intervals).context("Invalid [sampling] configuration")
+    }
+}
+
+/// Service names for ports, on top of the built-in table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServicesConfig {
+    /// `port` or `port/tcp`, `port/udp` mapped to a service name
+    pub ports: HashMap<String, String>,
+}
+
+impl ServicesConfig {
+    /// Validated service map; the built-in table alone when no overrides are configured
+    pub fn map(&self) -> Result<ServiceMap> {
+        ServiceMap::from_overrides(&self.ports).context("Invalid [services] configuration")
+    }
+}
+
+/// Browser UI served by the background service
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WebConfig {
+    /// Address to serve the web UI on; the UI is off when unset
+    pub listen: Option<String>,
+}
+
+impl WebConfig {
+    /// Validated listen address, if the web UI is enabled
+    pub fn address(&self) -> Result<Option<SocketAddr>> {
+        self.listen
+            .as_deref()
+            .map(|listen| {
+                listen
+                    .parse()
+                    .with_context(|| format!("Invalid [web] listen address '{listen}' (expected ip:port)"))
+            })
+            .transpose()
+    }
+}
+
+/// Thresholds for the port scan, SYN flood and ICMP sweep detectors
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Length of the sliding window each threshold is counted over
+    pub window_secs: u64,
+    /// Distinct ports one source may probe on one host before it counts as a port scan
+    pub port_scan_ports: usize,
+    /// Connection attempts to one host, from distinct source addresses and ports, that count as a SYN flood
+    pub syn_flood_syns: usize,
+    /// Distinct hosts one source may ping before it counts as an ICMP sweep
+    pub icmp_sweep_hosts: usize,
+    /// Percentage points the share of an interface's traffic no protocol was identified for
+    /// may rise above its usual share within one minute before it is flagged
+    pub unknown_traffic_percent: f64,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60,
+            port_scan_ports: 20,
+            syn_flood_syns: 1000,
+            icmp_sweep_hosts: 16,
+            unknown_traffic_percent: 20.0,
+        }
+    }
+}
+
+/// Sensitivity of the bandwidth anomaly detector
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// Standard deviations a reading must be from its hourly baseline to be flagged;
+    /// lower values flag more
+    pub sensitivity: f64,
+    /// Weight of each new sample in the moving average, 0.0 - 1.0
+    pub smoothing: f64,
+    /// Samples an interface needs in an hour of the day before that hour is judged
+    pub min_samples: u64,
+    /// Days of stored bandwidth history the baseline is learned from
+    pub history_days: u32,
+    /// Deviations smaller than this are never flagged, however steady the baseline
+    pub min_change_bytes_per_sec: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 3.0,
+            smoothing: 0.05,
+            min_samples: 30,
+            history_days: 14,
+            min_change_bytes_per_sec: 50_000.0,
+        }
+    }
+}
+
+/// When ping latency rising with throughput is flagged as congestion
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CongestionConfig {
+    /// Round-trip time above the idle baseline that counts as congestion
+    pub latency_increase_ms: f64,
+    /// Throughput, in percent of the busiest seen, from which the link counts as under load
+    pub load_percent: f64,
+    /// Throughput, in percent of the busiest seen, below which latency counts towards the idle baseline
+    pub idle_percent: f64,
+    /// Throughput below this never counts as load, however quiet the link usually is
+    pub min_load_bytes_per_sec: f64,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            latency_increase_ms: 30.0,
+            load_percent: 50.0,
+            idle_percent: 10.0,
+            min_load_bytes_per_sec: 125_000.0,
+        }
+    }
+}
+
+/// Data allowance per billing period, for the usage forecast
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// Traffic per billing period, in GB
+    pub monthly_gb: Option<f64>,
+    /// Day of the month each billing period starts on
+    pub reset_day: u32,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            monthly_gb: None,
+            reset_day: 1,
+        }
+    }
+}
+
+/// Endpoints and limits of the active speed test (`kw speedtest`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SpeedTestConfig {
+    /// Downloaded to measure download speed; should be larger than a test can fetch
+    pub download_url: String,
+    /// Receives the upload payload in HTTP POSTs; the upload test is skipped when empty
+    pub upload_url: String,
+    /// Requested repeatedly to measure latency; should answer with a tiny body
+    pub latency_url: String,
+    /// Longest the download and the upload test each run before they are cut short
+    pub duration_secs: u64,
+    /// Most bytes the upload test sends
+    pub upload_bytes: u64,
+    /// Latency requests timed, after one that opens the connection
+    pub latency_samples: usize,
+}
+
+impl Default for SpeedTestConfig {
+    fn default() -> Self {
+        Self {
+            download_url: "https://speed.cloudflare.com/__down?bytes=100000000".to_string(),
+            upload_url: "https://speed.cloudflare.com/__up".to_string(),
+            latency_url: "https://speed.cloudflare.com/__down?bytes=0".to_string(),
+            duration_secs: 10,
+            upload_bytes: 50_000_000,
+            latency_samples: 10,
+        }
+    }
+}
+
+/// Connectivity probes run by the live dashboard and `kw service run`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PingConfig {
+    /// Hosts or addresses to ping; "gateway" stands for the default gateway
+    pub targets: Vec<String>,
+    /// Seconds between echo requests to each target
+    pub interval_secs: u64,
+    /// Echo requests not answered within this many milliseconds count as lost
+    pub timeout_ms: u64,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec!["gateway".to_string(), "1.1.1.1".to_string()],
+            interval_secs: 2,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+/// Whether rates are shown per byte or per bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateUnits {
+    /// KB/s, MB/s and GB/s in multiples of 1024
+    #[default]
+    Bytes,
+    /// kbit/s, Mbit/s and Gbit/s in multiples of 1000, as line speeds are quoted
+    Bits,
+}
+
+/// How reports and graphs show rates and numbers
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Unit of rates; data volumes are always shown in bytes
+    pub units: RateUnits,
+    /// Locale tag whose separators numbers use, or `auto` for the environment's
+    pub locale: String,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            units: RateUnits::Bytes,
+            locale: "auto".to_string(),
+        }
+    }
+}
+
+/// Redaction of addresses and host names in output
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub mode: PrivacyMode,
+    /// Key mixed into hashes; without one they differ from run to run
+    pub salt: Option<String>,
+}
+
+impl PrivacyConfig {
+    /// The redactor for `--privacy`'s mode when given, else this section's
+    pub fn redactor(&self, mode: Option<PrivacyMode>) -> Redactor {
+        Redactor::new(mode.unwrap_or(self.mode), self.salt.as_deref())
+    }
+}
+
+/// How long stored data is kept, and at what resolution
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Apply the policy periodically while `kw service run` is running
+    pub auto: bool,
+    /// Hours between automatic runs
+    pub interval_hours: u64,
+    /// Days raw records are kept; bandwidth and protocol samples are rolled up into hours
+    pub raw_days: u32,
+    /// Days hourly rollups are kept before they are merged into days
+    pub hourly_days: u32,
+    /// Days daily rollups are kept
+    pub daily_days: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            interval_hours: 6,
+            // A month report still finds every connection
+            raw_days: 31,
+            hourly_days: 180,
+            daily_days: 1095,
+        }
+    }
+}
+
+/// How bond, bridge and team interfaces are shown in `kw status` and the live dashboard
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LinkAggregationConfig {
+    /// Show an aggregate once, with its member links folded beneath it
+    pub group: bool,
+    /// List the member links beneath their aggregate
+    pub show_members: bool,
+}
+
+impl Default for LinkAggregationConfig {
+    fn default() -> Self {
+        Self {
+            group: true,
+            show_members: true,
+        }
+    }
+}
+
+/// Commands run when alerts, interface changes, the monthly cap or a saturated link happen
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_alert: Option<String>,
+    pub on_interface_change: Option<String>,
+    pub on_cap_reached: Option<String>,
+    /// Run when a minute's throughput reaches `[contention] saturation_percent` of the link speed
+    pub on_link_saturated: Option<String>,
+    /// Traffic per calendar month, in GB, that fires `on_cap_reached`
+    pub monthly_cap_gb: Option<f64>,
+    /// Seconds a hook may run before it is killed
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_alert: None,
+            on_interface_change: None,
+            on_cap_reached: None,
+            on_link_saturated: None,
+            monthly_cap_gb: None,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Endpoint periodic bandwidth and protocol summaries are posted to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Nothing is posted while unset
+    pub url: Option<String>,
+    /// `Name: value` header sent with every request, such as a bearer token
+    pub auth_header: Option<String>,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    /// Attempts after the first failed one; client errors other than 408 and 429 are not retried
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub backoff_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            auth_header: None,
+            interval_secs: 300,
+            timeout_secs: 10,
+            retries: 3,
+            backoff_secs: 2,
+        }
+    }
+}
+
+/// MQTT broker each interface's speed and today's usage are published to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// `host`, `host:port` or `mqtt://host:port`; nothing is published while unset
+    pub broker: Option<String>,
+    pub topic_prefix: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive_secs: u64,
+    /// Send the username and password to a broker beyond this machine, in the clear
+    pub insecure: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            topic_prefix: "kaipo-watcher".to_string(),
+            qos: 0,
+            retain: true,
+            client_id: "kaipo-watcher".to_string(),
+            username: None,
+            password: None,
+            keep_alive_secs: 60,
+            insecure: false,
+        }
+    }
+}
+
+/// Agents reporting to a hub, and the hub they report to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// `host`, `host:port` or a URL; `kw agent` needs it or `--hub`
+    pub hub: Option<String>,
+    pub listen: String,
+    pub token: Option<String>,
+    pub name: Option<String>,
+    pub interval_secs: u64,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub ca_cert: Option<PathBuf>,
+    pub tls_domain: Option<String>,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            hub: None,
+            listen: "0.0.0.0:50051".to_string(),
+            token: None,
+            name: None,
+            interval_secs: 10,
+            tls_cert: None,
+            tls_key: None,
+            ca_cert: None,
+            tls_domain: None,
+        }
+    }
+}
+
+/// Read-only JSON API over the packet database
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub listen: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8081".to_string(),
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Validated listen address
+    pub fn address(&self) -> Result<SocketAddr> {
+        self.listen
+            .parse()
+            .with_context(|| format!("Invalid [api] listen address '{}' (expected ip:port)", self.listen))
+    }
+}
+
+impl AppConfig {
+    /// Loads the first configuration file found, then applies environment overrides
+    ///
+    /// Search order: `$KW_CONFIG`, `./kaipo-watcher.toml`, then the per-user config
+    /// directory (`~/.config/kaipo-watcher/config.toml`, `%APPDATA%\kaipo-watcher\config.toml`).
+    pub fn load() -> Result<Self> {
+        let mut builder = ::config::Config::builder();
+
+        if let Some((path, required)) = config_file() {
+            debug!("Loading configuration from {}", path.display());
+            builder = builder.add_source(::config::File::from(path).required(required));
+        }
+
+        builder
+            .add_source(
+                ::config::Environment::with_prefix("KW")
+                    .prefix_separator("_")
+                    .separator("__"),
+            )
+            .build()
+            .and_then(|settings| settings.try_deserialize())
+            .context("Failed to load configuration")
+    }
+}
+
+/// Identifies the configuration in effect, so the audit log can note when it changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFingerprint {
+    /// Configuration file read, if any
+    pub path: Option<PathBuf>,
+    /// SHA-256 of the file's contents and the `KW_` environment overrides, in hex
+    pub digest: String,
+}
+
+impl ConfigFingerprint {
+    /// Fingerprints the file and overrides `AppConfig::load` reads
+    pub fn current() -> Self {
+        let path = config_file().map(|(path, _)| path);
+        let contents = path.as_ref().and_then(|path| std::fs::read(path).ok()).unwrap_or_default();
+        let overrides: Vec<_> = std::env::vars_os()
+            .filter(|(name, _)| name.to_string_lossy().starts_with("KW_"))
+            .collect();
+        Self::from_parts(path, &contents, overrides)
+    }
+
+    fn from_parts(path: Option<PathBuf>, contents: &[u8], mut overrides: Vec<(OsString, OsString)>) -> Self {
+        overrides.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(contents);
+        for (name, value) in &overrides {
+            hasher.update(format!("\n{}={}", name.to_string_lossy(), value.to_string_lossy()));
+        }
+        Self {
+            path,
+            digest: format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} ({})", self.digest, path.display()),
+            None => write!(f, "{} (defaults)", self.digest),
+        }
+    }
+}
+
+/// Absolute path of the configuration file in use, for the service definitions
+/// `kw service install` writes, since the service would not find the same one
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn config_path() -> Option<PathBuf> {
+    config_file()
+        .map(|(path, _)| path)
+        .filter(|path| path.is_file())
+        .and_then(|path| std::path::absolute(path).ok())
+}
+
+/// The configuration file `AppConfig::load` reads, and whether it must exist
+fn config_file() -> Option<(PathBuf, bool)> {
+    match std::env::var_os(CONFIG_PATH_ENV) {
+        Some(path) => Some((PathBuf::from(path), true)),
+        None => default_config_paths().into_iter().find(|p| p.is_file()).map(|path| (path, false)),
+    }
+}
+
+/// Candidate configuration files, most specific first
+fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("kaipo-watcher.toml")];
+
+    let user_config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    if let Some(dir) = user_config_dir {
+        paths.push(dir.join("kaipo-watcher").join("config.toml"));
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_when_keys_are_missing() {
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str("", ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.data.dir, None);
+        assert_eq!(config.data.profile, None);
+        assert_eq!(config.low_memory.mode, LowMemoryMode::Auto);
+        assert_eq!(config.low_memory.ram_threshold_mb, 1024);
+        assert!(!config.low_memory.disable_analyzer);
+        assert_eq!(config.capture.backend, CaptureBackend::AfPacket);
+        assert_eq!(config.capture.sample, None);
+        assert_eq!(config.capture.sample_threshold_pps, DEFAULT_SAMPLE_THRESHOLD_PPS);
+        assert_eq!(config.dns.backend, DnsBackend::System);
+        assert_eq!(config.geoip.country_database, None);
+        assert!(config.geoip.expected_countries.is_empty());
+        assert_eq!(config.contention.saturation_percent, 80.0);
+        assert_eq!(config.contention.link_speed_mbps, None);
+        assert!(config.refresh.auto);
+        assert_eq!(config.refresh.max_interval_secs, 10);
+        assert_eq!(config.refresh.cpu_percent, 50.0);
+        assert_eq!(config.smoothing.window_secs, 0.0);
+        assert!(config.sampling.plan().unwrap().is_empty());
+        assert!(config.services.ports.is_empty());
+        assert_eq!(config.web.address().unwrap(), None);
+        assert_eq!(config.security.window_secs, 60);
+        assert_eq!(config.security.port_scan_ports, 20);
+        assert_eq!(config.security.unknown_traffic_percent, 20.0);
+        assert_eq!(config.anomaly.sensitivity, 3.0);
+        assert_eq!(config.anomaly.history_days, 14);
+        assert_eq!(config.congestion.latency_increase_ms, 30.0);
+        assert_eq!(config.speedtest.duration_secs, 10);
+        assert!(config.speedtest.upload_url.starts_with("https://"));
+        assert_eq!(config.ping.targets, ["gateway", "1.1.1.1"]);
+        assert_eq!(config.ping.interval_secs, 2);
+        assert_eq!(config.display.units, RateUnits::Bytes);
+        assert_eq!(config.display.locale, "auto");
+        assert_eq!(config.privacy.mode, PrivacyMode::Off);
+        assert!(!config.privacy.redactor(None).is_active());
+        assert!(config.privacy.redactor(Some(PrivacyMode::Hash)).is_active());
+        assert!(config.retention.auto);
+        assert_eq!(config.retention.raw_days, 31);
+        assert!(config.link_aggregation.group);
+        assert!(config.link_aggregation.show_members);
+        assert_eq!(config.hooks.on_alert, None);
+        assert_eq!(config.hooks.timeout_secs, 30);
+        assert_eq!(config.webhook.url, None);
+        assert_eq!(config.webhook.interval_secs, 300);
+        assert_eq!(config.webhook.retries, 3);
+        assert_eq!(config.mqtt.broker, None);
+        assert_eq!(config.mqtt.topic_prefix, "kaipo-watcher");
+        assert_eq!(config.mqtt.qos, 0);
+        assert_eq!(config.remote.hub, None);
+        assert_eq!(config.remote.listen, "0.0.0.0:50051");
+        assert_eq!(config.remote.interval_secs, 10);
+        assert_eq!(config.api.address().unwrap().port(), 8081);
+    }
+
+    #[test]
+    fn test_sampling_section_from_toml() {
+        let toml = "[sampling.intervals]
+\"eth0\" = 1
+\"docker*\" = 30
+\"@container\" = 60
+";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.sampling.intervals.len(), 3);
+        assert_eq!(config.sampling.intervals["docker*"], 30);
+        assert_eq!(config.sampling.plan().unwrap().min_interval(), Some(std::time::Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_services_section_from_toml() {
+        let toml = "[services.ports]
+8443 = \"unifi\"
+\"51821/udp\" = \"wg-guest\"
+";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        let services = config.services.map().unwrap();
+        assert_eq!(services.name(8443, crate::models::TransportProtocol::Tcp), Some("unifi"));
+        assert_eq!(services.name(51821, crate::models::TransportProtocol::Udp), Some("wg-guest"));
+    }
+
+    #[test]
+    fn test_web_listen_address() {
+        let web = WebConfig { listen: Some("0.0.0.0:8080".to_string()) };
+        assert_eq!(web.address().unwrap().map(|a| a.port()), Some(8080));
+
+        let web = WebConfig { listen: Some("router:8080".to_string()) };
+        assert!(web.address().is_err());
+    }
+
+    #[test]
+    fn test_low_memory_section_from_toml() {
+        let toml = "[low_memory]\nmode = \"on\"\ndisable_analyzer = true\n";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.low_memory.mode, LowMemoryMode::On);
+        assert_eq!(config.low_memory.ram_threshold_mb, 1024);
+        assert!(config.low_memory.disable_analyzer);
+    }
+
+    #[test]
+    fn test_capture_section_from_toml() {
+        let toml = "[capture]\nbackend = \"af-xdp\"\nsample = \"1/100\"\nsample_threshold_pps = 20000\n";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.capture.backend, CaptureBackend::AfXdp);
+        assert_eq!(config.capture.sample, Some(SampleRate::one_in(100)));
+        assert_eq!(config.capture.sample_threshold_pps, 20_000);
+    }
+
+    #[test]
+    fn test_dns_section_from_toml() {
+        let toml = "[dns]\nbackend = \"server\"\nserver = \"10.0.0.1:5353\"\n";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.dns.backend, DnsBackend::Server);
+        assert_eq!(config.dns.server.as_deref(), Some("10.0.0.1:5353"));
+        assert_eq!(config.dns.timeout_ms, 2000);
+    }
+
+    #[test]
+    fn test_storage_section_from_toml() {
+        let toml = "[storage]\nbackend = \"postgres\"\nurl = \"postgres://kw@db/kw\"\n";
+        let config: AppConfig = ::config::Config::builder()
+            .add_source(::config::File::from_str(toml, ::config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(config.storage.backend, DatabaseBackend::Postgres);
+        assert_eq!(config.storage.url.as_deref(), Some("postgres://kw@db/kw"));
+        assert_eq!(config.storage.name, None);
+        assert_eq!(AppConfig::default().storage.backend, DatabaseBackend::Sqlite);
+    }
+
+    #[test]
+    fn test_fingerprint_follows_file_and_overrides() {
+        let override_of = |value: &str| vec![(OsString::from("KW_PING__INTERVAL_SECS"), OsString::from(value))];
+        let path = Some(PathBuf::from("kaipo-watcher.toml"));
+        let base = ConfigFingerprint::from_parts(path.clone(), b"[ping]\n", override_of("5"));
+
+        assert_eq!(base, ConfigFingerprint::from_parts(path.clone(), b"[ping]\n", override_of("5")));
+        assert_ne!(base.digest, ConfigFingerprint::from_parts(path.clone(), b"[ping]\n", override_of("2")).digest);
+        assert_ne!(base.digest, ConfigFingerprint::from_parts(path, b"[web]\n", override_of("5")).digest);
+        assert!(base.to_string().ends_with("(kaipo-watcher.toml)"));
+    }
+}
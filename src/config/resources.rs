@@ -0,0 +1,142 @@
+use log::info;
+use std::time::Duration;
+use sysinfo::{MemoryRefreshKind, RefreshKind, System};
+
+use super::{LowMemoryConfig, LowMemoryMode};
+
+/// Buffer sizes and feature switches used by the capture pipeline
+///
+/// `standard()` matches the long-standing defaults; `low_memory()` shrinks every
+/// buffer so the daemon stays comfortable on small devices such as a Raspberry Pi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceProfile {
+    pub low_memory: bool,
+    /// Frames the packet ring holds between the capture threads and their consumer
+    pub packet_channel_capacity: usize,
+    /// Records buffered before a database write
+    pub storage_batch_size: usize,
+    /// Longest a part batch waits in the write buffer before it is written anyway
+    pub storage_flush_interval: Duration,
+    /// Merge buffered records per protocol/connection before writing
+    pub storage_rollup: bool,
+    /// Connections kept by the protocol analyzer's tracker
+    pub tracked_connections: usize,
+    /// Flows kept by the live dashboard's connection table
+    pub dashboard_flows: usize,
+    /// Run captured packets through the protocol analyzer
+    pub analyzer_enabled: bool,
+}
+
+impl ResourceProfile {
+    pub fn standard() -> Self {
+        Self {
+            low_memory: false,
+            packet_channel_capacity: 10_000,
+            storage_batch_size: 1_000,
+            storage_flush_interval: Duration::from_secs(5),
+            storage_rollup: false,
+            tracked_connections: 10_000,
+            dashboard_flows: 5_000,
+            analyzer_enabled: true,
+        }
+    }
+
+    pub fn low_memory(disable_analyzer: bool) -> Self {
+        Self {
+            low_memory: true,
+            packet_channel_capacity: 1_000,
+            storage_batch_size: 100,
+            storage_flush_interval: Duration::from_secs(2),
+            storage_rollup: true,
+            tracked_connections: 1_000,
+            dashboard_flows: 500,
+            analyzer_enabled: !disable_analyzer,
+        }
+    }
+
+    /// Picks the profile for this machine from the configuration and total RAM
+    pub fn detect(config: &LowMemoryConfig) -> Self {
+        let profile = Self::resolve(config, total_memory_mb());
+        if profile.low_memory {
+            info!(
+                "Low-memory mode enabled (mode: {:?}, threshold: {} MiB, analyzer: {})",
+                config.mode,
+                config.ram_threshold_mb,
+                if profile.analyzer_enabled { "on" } else { "off" }
+            );
+        }
+        profile
+    }
+
+    fn resolve(config: &LowMemoryConfig, total_memory_mb: Option<u64>) -> Self {
+        let enabled = match config.mode {
+            LowMemoryMode::On => true,
+            LowMemoryMode::Off => false,
+            // Stay on the standard profile when RAM cannot be determined
+            LowMemoryMode::Auto => total_memory_mb.is_some_and(|mb| mb < config.ram_threshold_mb),
+        };
+
+        if enabled {
+            Self::low_memory(config.disable_analyzer)
+        } else {
+            Self::standard()
+        }
+    }
+}
+
+impl Default for ResourceProfile {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+/// Total physical memory in MiB, `None` if the platform does not report it
+pub fn total_memory_mb() -> Option<u64> {
+    let system = System::new_with_specifics(
+        RefreshKind::nothing().with_memory(MemoryRefreshKind::nothing().with_ram()),
+    );
+    match system.total_memory() {
+        0 => None,
+        bytes => Some(bytes / (1024 * 1024)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: LowMemoryMode) -> LowMemoryConfig {
+        LowMemoryConfig {
+            mode,
+            ..LowMemoryConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_auto_mode_follows_ram_threshold() {
+        let auto = config(LowMemoryMode::Auto);
+        assert!(ResourceProfile::resolve(&auto, Some(512)).low_memory);
+        assert!(!ResourceProfile::resolve(&auto, Some(8192)).low_memory);
+        assert!(!ResourceProfile::resolve(&auto, None).low_memory);
+    }
+
+    #[test]
+    fn test_explicit_mode_overrides_ram() {
+        assert!(ResourceProfile::resolve(&config(LowMemoryMode::On), Some(65536)).low_memory);
+        assert!(!ResourceProfile::resolve(&config(LowMemoryMode::Off), Some(256)).low_memory);
+    }
+
+    #[test]
+    fn test_low_memory_profile_is_smaller() {
+        let standard = ResourceProfile::standard();
+        let low = ResourceProfile::low_memory(true);
+
+        assert!(low.packet_channel_capacity < standard.packet_channel_capacity);
+        assert!(low.storage_batch_size < standard.storage_batch_size);
+        assert!(low.tracked_connections < standard.tracked_connections);
+        assert!(low.dashboard_flows < standard.dashboard_flows);
+        assert!(low.storage_rollup);
+        assert!(!low.analyzer_enabled);
+        assert!(ResourceProfile::low_memory(false).analyzer_enabled);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::collectors::bandwidth_collector::BandwidthStats;
+
+/// A single speed reading for one interface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistorySample {
+    /// Seconds since the history was created (used as the chart x coordinate)
+    pub elapsed_secs: f64,
+    /// Download speed in bytes per second
+    pub download_bps: f64,
+    /// Upload speed in bytes per second
+    pub upload_bps: f64,
+}
+
+/// In-memory ring buffer of per-interface bandwidth readings
+///
+/// Samples older than the configured window are dropped on every insert, so the
+/// memory footprint stays bounded by `window / update_interval` per interface.
+#[derive(Debug)]
+pub struct BandwidthHistory {
+    /// How much history to retain
+    window: Duration,
+    /// Reference point for sample timestamps
+    started_at: Instant,
+    /// Samples per interface, oldest first
+    series: HashMap<String, VecDeque<HistorySample>>,
+}
+
+impl BandwidthHistory {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            started_at: Instant::now(),
+            series: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Appends the current speed of every interface in `stats`
+    pub fn record(&mut self, stats: &[BandwidthStats]) {
+        self.record_at(Instant::now(), stats);
+    }
+
+    fn record_at(&mut self, now: Instant, stats: &[BandwidthStats]) {
+        let elapsed_secs = now.saturating_duration_since(self.started_at).as_secs_f64();
+
+        for stat in stats {
+            self.series
+                .entry(stat.interface_name.clone())
+                .or_default()
+                .push_back(HistorySample {
+                    elapsed_secs,
+                    download_bps: stat.download_speed_bps,
+                    upload_bps: stat.upload_speed_bps,
+                });
+        }
+
+        // Trim everything that fell out of the window and forget interfaces that went away
+        let cutoff = elapsed_secs - self.window.as_secs_f64();
+        self.series.retain(|_, samples| {
+            while samples.front().is_some_and(|s| s.elapsed_secs < cutoff) {
+                samples.pop_front();
+            }
+            !samples.is_empty()
+        });
+    }
+
+    /// Samples for an interface, oldest first
+    pub fn samples(&self, interface: &str) -> Option<&VecDeque<HistorySample>> {
+        self.series.get(interface)
+    }
+
+    /// Mean download + upload speed of an interface over the last `secs` seconds
+    /// None until the history reaches back at least half that long
+    pub fn average_total_bps(&self, interface: &str, secs: f64) -> Option<f64> {
+        let samples = self.series.get(interface)?;
+        let last = samples.back()?.elapsed_secs;
+        if last - samples.front()?.elapsed_secs < secs / 2.0 {
+            return None;
+        }
+        let recent: Vec<f64> = samples
+            .iter()
+            .filter(|s| s.elapsed_secs >= last - secs)
+            .map(|s| s.download_bps + s.upload_bps)
+            .collect();
+        Some(recent.iter().sum::<f64>() / recent.len() as f64)
+    }
+
+    /// Interfaces with recorded history, sorted by name for stable selection
+    pub fn interfaces(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.series.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::bandwidth_collector::{CalculationConfidence, InterfaceState, InterfaceType};
+    use chrono::Utc;
+
+    fn make_stats(name: &str, download: f64, upload: f64) -> BandwidthStats {
+        BandwidthStats {
+            timestamp: Utc::now(),
+            interface_name: name.to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            packets_sent: 0,
+            download_speed_bps: download,
+            upload_speed_bps: upload,
+            calculation_confidence: CalculationConfidence::High,
+            time_since_last_update: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_samples_are_kept_per_interface() {
+        let mut history = BandwidthHistory::new(Duration::from_secs(60));
+        let start = history.started_at;
+
+        history.record_at(start, &[make_stats("eth0", 100.0, 10.0), make_stats("wlan0", 5.0, 1.0)]);
+        history.record_at(start + Duration::from_secs(1), &[make_stats("eth0", 200.0, 20.0)]);
+
+        assert_eq!(history.interfaces(), vec!["eth0", "wlan0"]);
+        let eth0 = history.samples("eth0").unwrap();
+        assert_eq!(eth0.len(), 2);
+        assert_eq!(eth0[1].download_bps, 200.0);
+        assert_eq!(eth0[1].elapsed_secs, 1.0);
+        assert_eq!(history.samples("wlan0").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_old_samples_fall_out_of_window() {
+        let mut history = BandwidthHistory::new(Duration::from_secs(10));
+        let start = history.started_at;
+
+        for second in 0..30 {
+            history.record_at(start + Duration::from_secs(second), &[make_stats("eth0", 1.0, 1.0)]);
+        }
+
+        let samples = history.samples("eth0").unwrap();
+        assert_eq!(samples.len(), 11);
+        assert_eq!(samples.front().unwrap().elapsed_secs, 19.0);
+    }
+
+    #[test]
+    fn test_average_needs_enough_history() {
+        let mut history = BandwidthHistory::new(Duration::from_secs(120));
+        let start = history.started_at;
+
+        history.record_at(start, &[make_stats("eth0", 1_000.0, 0.0)]);
+        history.record_at(start + Duration::from_secs(10), &[make_stats("eth0", 100.0, 100.0)]);
+        assert_eq!(history.average_total_bps("eth0", 60.0), None);
+
+        for second in [40, 70] {
+            history.record_at(start + Duration::from_secs(second), &[make_stats("eth0", 100.0, 100.0)]);
+        }
+        // The first reading is older than the averaging window
+        assert_eq!(history.average_total_bps("eth0", 60.0), Some(200.0));
+        assert_eq!(history.average_total_bps("wlan0", 60.0), None);
+    }
+
+    #[test]
+    fn test_vanished_interfaces_are_dropped() {
+        let mut history = BandwidthHistory::new(Duration::from_secs(5));
+        let start = history.started_at;
+
+        history.record_at(start, &[make_stats("tun0", 1.0, 1.0)]);
+        history.record_at(start + Duration::from_secs(10), &[make_stats("eth0", 1.0, 1.0)]);
+
+        assert!(history.samples("tun0").is_none());
+        assert_eq!(history.interfaces(), vec!["eth0"]);
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use crate::models::{NetworkPacket, PacketDirection, TagBook, TransportProtocol};
+
+/// Column used to order the connection table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowSortKey {
+    /// Largest byte count first
+    Bytes,
+    /// Largest packet count first
+    Packets,
+}
+
+impl FlowSortKey {
+    /// Human-readable name shown in the table title
+    pub fn label(&self) -> &'static str {
+        match self {
+            FlowSortKey::Bytes => "bytes",
+            FlowSortKey::Packets => "packets",
+        }
+    }
+}
+
+/// A single network flow aggregated from captured packets
+#[derive(Debug, Clone)]
+pub struct FlowEntry {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub source_port: Option<u16>,
+    pub dest_port: Option<u16>,
+    pub protocol: TransportProtocol,
+    pub direction: PacketDirection,
+    pub packets: u64,
+    pub bytes: u64,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+}
+
+impl FlowEntry {
+    /// Formats an address/port pair as "IP:port" (or just "IP" for portless protocols), with
+    /// the address as `tags` show it
+    pub fn endpoint(addr: &IpAddr, port: Option<u16>, tags: &TagBook) -> String {
+        let addr = tags.address(*addr);
+        match port {
+            Some(port) if addr.contains(':') => format!("[{addr}]:{port}"),
+            Some(port) => format!("{addr}:{port}"),
+            None => addr,
+        }
+    }
+}
+
+/// Scrollable, sortable table of active flows for the live dashboard
+///
+/// Packets are folded into flows keyed by their 5-tuple. The display order is
+/// recomputed on demand so that the selection stays on the same flow while the
+/// table is re-sorted underneath it.
+#[derive(Debug)]
+pub struct ConnectionTable {
+    /// Aggregated flows keyed by connection string
+    flows: HashMap<String, FlowEntry>,
+    /// Flow keys in display order
+    order: Vec<String>,
+    /// Current sort column
+    sort_key: FlowSortKey,
+    /// Index of the selected row within `order`
+    selected: usize,
+    /// Upper bound on tracked flows before idle ones are evicted
+    #[cfg_attr(not(feature = "capture"), allow(dead_code))] // Flows come from captured packets
+    max_flows: usize,
+}
+
+impl ConnectionTable {
+    pub fn new(max_flows: usize) -> Self {
+        Self {
+            flows: HashMap::new(),
+            order: Vec::new(),
+            sort_key: FlowSortKey::Bytes,
+            selected: 0,
+            max_flows,
+        }
+    }
+
+    /// Folds a captured packet into its flow, creating the flow if needed
+    /// Packets without IP addresses (ARP, etc.) are ignored
+    #[cfg_attr(not(feature = "capture"), allow(dead_code))]
+    pub fn record_packet(&mut self, packet: &NetworkPacket) {
+        let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) else {
+            return;
+        };
+
+        let key = format!(
+            "{:?}|{}:{}-{}:{}",
+            packet.transport_protocol,
+            src,
+            packet.source_port.unwrap_or(0),
+            dst,
+            packet.dest_port.unwrap_or(0)
+        );
+
+        if let Some(flow) = self.flows.get_mut(&key) {
+            flow.packets += 1;
+            flow.bytes += packet.size_bytes;
+            flow.last_seen = packet.timestamp;
+            return;
+        }
+
+        if self.flows.len() >= self.max_flows {
+            self.evict_oldest();
+        }
+
+        self.flows.insert(
+            key,
+            FlowEntry {
+                source: src,
+                destination: dst,
+                source_port: packet.source_port,
+                dest_port: packet.dest_port,
+                protocol: packet.transport_protocol,
+                direction: packet.direction,
+                packets: 1,
+                bytes: packet.size_bytes,
+                first_seen: packet.timestamp,
+                last_seen: packet.timestamp,
+            },
+        );
+    }
+
+    /// Recomputes the display order, keeping the current selection on the same flow
+    pub fn refresh_order(&mut self) {
+        let selected_key = self.order.get(self.selected).cloned();
+
+        let mut order: Vec<String> = self.flows.keys().cloned().collect();
+        let flows = &self.flows;
+        match self.sort_key {
+            FlowSortKey::Bytes => {
+                order.sort_by(|a, b| flows[b].bytes.cmp(&flows[a].bytes).then_with(|| a.cmp(b)))
+            }
+            FlowSortKey::Packets => {
+                order.sort_by(|a, b| flows[b].packets.cmp(&flows[a].packets).then_with(|| a.cmp(b)))
+            }
+        }
+        self.order = order;
+
+        self.selected = selected_key
+            .and_then(|key| self.order.iter().position(|k| *k == key))
+            .unwrap_or(self.selected)
+            .min(self.order.len().saturating_sub(1));
+    }
+
+    /// Switches between byte and packet ordering
+    pub fn toggle_sort(&mut self) {
+        self.sort_key = match self.sort_key {
+            FlowSortKey::Bytes => FlowSortKey::Packets,
+            FlowSortKey::Packets => FlowSortKey::Bytes,
+        };
+        self.refresh_order();
+    }
+
+    pub fn sort_key(&self) -> FlowSortKey {
+        self.sort_key
+    }
+
+    /// Flows in display order
+    pub fn flows(&self) -> Vec<&FlowEntry> {
+        self.order.iter().filter_map(|key| self.flows.get(key)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        if self.order.is_empty() {
+            None
+        } else {
+            Some(self.selected)
+        }
+    }
+
+    pub fn selected_flow(&self) -> Option<&FlowEntry> {
+        self.order.get(self.selected).and_then(|key| self.flows.get(key))
+    }
+
+    pub fn select_next(&mut self) {
+        self.scroll_down(1);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.scroll_up(1);
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        let last = self.order.len().saturating_sub(1);
+        self.selected = (self.selected + rows).min(last);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.selected = self.selected.saturating_sub(rows);
+    }
+
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.order.len().saturating_sub(1);
+    }
+
+    /// Drops flows that have not seen traffic since the cutoff
+    pub fn expire_idle(&mut self, cutoff: DateTime<Local>) {
+        self.flows.retain(|_, flow| flow.last_seen >= cutoff);
+    }
+
+    #[cfg_attr(not(feature = "capture"), allow(dead_code))]
+    fn evict_oldest(&mut self) {
+        if let Some(oldest) = self
+            .flows
+            .iter()
+            .min_by_key(|(_, flow)| flow.last_seen)
+            .map(|(key, _)| key.clone())
+        {
+            self.flows.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PacketProtocol;
+    use std::net::Ipv4Addr;
+
+    fn make_packet(dest_last_octet: u8, size: u64) -> NetworkPacket {
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            size,
+            PacketProtocol::IPv4,
+            PacketDirection::Outbound,
+        );
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, dest_last_octet)));
+        packet.source_port = Some(50000);
+        packet.dest_port = Some(443);
+        packet
+    }
+
+    #[test]
+    fn test_packets_aggregate_into_flows() {
+        let mut table = ConnectionTable::new(100);
+        table.record_packet(&make_packet(1, 100));
+        table.record_packet(&make_packet(1, 200));
+        table.record_packet(&make_packet(2, 50));
+        table.refresh_order();
+
+        assert_eq!(table.len(), 2);
+        let flows = table.flows();
+        assert_eq!(flows[0].bytes, 300);
+        assert_eq!(flows[0].packets, 2);
+        assert_eq!(flows[1].bytes, 50);
+    }
+
+    #[test]
+    fn test_sort_toggle_and_selection_tracking() {
+        let mut table = ConnectionTable::new(100);
+        // Flow 1: one big packet, flow 2: three small packets
+        table.record_packet(&make_packet(1, 1500));
+        for _ in 0..3 {
+            table.record_packet(&make_packet(2, 60));
+        }
+        table.refresh_order();
+
+        assert_eq!(table.sort_key(), FlowSortKey::Bytes);
+        assert_eq!(table.selected_flow().unwrap().bytes, 1500);
+
+        table.toggle_sort();
+        assert_eq!(table.sort_key(), FlowSortKey::Packets);
+        assert_eq!(table.flows()[0].packets, 3);
+        // Selection follows the previously selected flow
+        assert_eq!(table.selected_index(), Some(1));
+        assert_eq!(table.selected_flow().unwrap().bytes, 1500);
+    }
+
+    #[test]
+    fn test_scrolling_is_clamped() {
+        let mut table = ConnectionTable::new(100);
+        for octet in 1..=5 {
+            table.record_packet(&make_packet(octet, octet as u64 * 10));
+        }
+        table.refresh_order();
+
+        table.select_previous();
+        assert_eq!(table.selected_index(), Some(0));
+        table.scroll_down(10);
+        assert_eq!(table.selected_index(), Some(4));
+        table.scroll_up(2);
+        assert_eq!(table.selected_index(), Some(2));
+        table.select_first();
+        assert_eq!(table.selected_index(), Some(0));
+        table.select_last();
+        assert_eq!(table.selected_index(), Some(4));
+    }
+
+    #[test]
+    fn test_flow_limit_evicts_oldest() {
+        let mut table = ConnectionTable::new(2);
+        let mut first = make_packet(1, 10);
+        first.timestamp = Local::now() - chrono::Duration::seconds(30);
+        table.record_packet(&first);
+        table.record_packet(&make_packet(2, 10));
+        table.record_packet(&make_packet(3, 10));
+
+        assert_eq!(table.len(), 2);
+        table.refresh_order();
+        assert!(table.flows().iter().all(|f| f.destination != first.dest_addr.unwrap()));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::collectors::bandwidth_collector::BandwidthStats;
+use crate::collectors::link_speed::LinkInfo;
+use crate::collectors::process_collector::ProcessInfo;
+
+/// Name shown for traffic that could not be tied to a local process
+#[cfg_attr(not(feature = "capture"), allow(dead_code))] // Traffic is charged from captured packets
+pub const UNATTRIBUTED: &str = "(unattributed)";
+
+/// Processes using at least this share of the traffic get throttle suggestions
+const SUGGESTION_MIN_SHARE: f64 = 0.25;
+
+/// Bandwidth used by one process during the last sample period
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessShare {
+    /// Process id, `None` for unattributed traffic
+    pub pid: Option<u32>,
+    pub name: String,
+    /// Bytes per second, both directions combined
+    pub bytes_per_sec: f64,
+    /// Fraction of all captured traffic, 0.0 - 1.0
+    pub share: f64,
+}
+
+/// Utilisation of one interface relative to its link speed
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkUtilisation {
+    pub interface: String,
+    pub link: LinkInfo,
+    /// Throughput as a fraction of the link's capacity, see `LinkInfo::utilisation`
+    pub utilisation: f64,
+}
+
+impl LinkUtilisation {
+    /// Utilisation of every interface whose link speed is known
+    pub fn from_stats<F>(stats: &[BandwidthStats], mut link: F) -> Vec<Self>
+    where
+        F: FnMut(&str) -> Option<LinkInfo>,
+    {
+        stats
+            .iter()
+            .filter_map(|stat| {
+                let link = link(&stat.interface_name)?;
+                Some(Self {
+                    interface: stat.interface_name.clone(),
+                    link,
+                    utilisation: link.utilisation(stat.download_speed_bps, stat.upload_speed_bps),
+                })
+            })
+            .collect()
+    }
+
+    pub fn is_saturated(&self, threshold_percent: f64) -> bool {
+        self.utilisation * 100.0 >= threshold_percent
+    }
+}
+
+/// Accumulates captured bytes per process and turns them into per-period shares
+#[derive(Debug)]
+pub struct ContentionTracker {
+    /// Bytes seen since the last sample, keyed by pid (`None` = unattributed)
+    pending: HashMap<Option<u32>, (String, u64)>,
+    last_sample: Instant,
+    shares: Vec<ProcessShare>,
+}
+
+impl ContentionTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: HashMap::new(),
+            last_sample: Instant::now(),
+            shares: Vec::new(),
+        }
+    }
+
+    /// Charges `bytes` to `process`, or to the unattributed bucket
+    #[cfg_attr(not(feature = "capture"), allow(dead_code))]
+    pub fn record(&mut self, process: Option<&ProcessInfo>, bytes: u64) {
+        let (key, name) = match process {
+            Some(p) => (Some(p.pid), p.name.as_str()),
+            None => (None, UNATTRIBUTED),
+        };
+        self.pending.entry(key).or_insert_with(|| (name.to_string(), 0)).1 += bytes;
+    }
+
+    /// Closes the current period and recomputes the shares, busiest process first
+    pub fn sample(&mut self) {
+        let elapsed = self.last_sample.elapsed();
+        self.last_sample = Instant::now();
+        self.sample_over(elapsed);
+    }
+
+    fn sample_over(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64().max(0.001);
+        let total: u64 = self.pending.values().map(|(_, bytes)| bytes).sum();
+
+        self.shares = self
+            .pending
+            .drain()
+            .map(|(pid, (name, bytes))| ProcessShare {
+                pid,
+                name,
+                bytes_per_sec: bytes as f64 / seconds,
+                share: if total == 0 { 0.0 } else { bytes as f64 / total as f64 },
+            })
+            .collect();
+        self.shares.sort_by(|a, b| b.bytes_per_sec.total_cmp(&a.bytes_per_sec));
+    }
+
+    pub fn shares(&self) -> &[ProcessShare] {
+        &self.shares
+    }
+}
+
+impl Default for ContentionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Commands that would rein in the heaviest consumers of a saturated link
+/// Each process is offered an equal share of the link, as a `trickle` rate limit
+pub fn throttle_suggestions(shares: &[ProcessShare], link_mbps: u64) -> Vec<String> {
+    let active = shares.iter().filter(|s| s.bytes_per_sec > 0.0).count().max(2);
+    let fair_kbps = link_mbps * 1_000_000 / 8 / 1024 / active as u64;
+
+    shares
+        .iter()
+        .filter(|s| s.share >= SUGGESTION_MIN_SHARE)
+        .filter_map(|s| {
+            let pid = s.pid?;
+            Some(if cfg!(windows) {
+                format!("(Get-Process -Id {pid}).PriorityClass = 'BelowNormal'  # {}", s.name)
+            } else {
+                format!(
+                    "renice -n 10 -p {pid}  # {name}, or restart it as: trickle -s -d {fair_kbps} -u {fair_kbps} {name}",
+                    name = s.name
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, name: &str) -> ProcessInfo {
+        ProcessInfo { pid, name: name.to_string() }
+    }
+
+    #[test]
+    fn test_shares_sorted_by_rate() {
+        let mut tracker = ContentionTracker::new();
+        tracker.record(Some(&process(10, "curl")), 6000);
+        tracker.record(None, 1000);
+        tracker.record(Some(&process(20, "rsync")), 2000);
+        tracker.record(Some(&process(10, "curl")), 1000);
+        tracker.sample_over(Duration::from_secs(2));
+
+        let shares = tracker.shares();
+        assert_eq!(shares.len(), 3);
+        assert_eq!(shares[0].name, "curl");
+        assert_eq!(shares[0].bytes_per_sec, 3500.0);
+        assert!((shares[0].share - 0.7).abs() < 1e-9);
+        assert_eq!(shares[2].name, UNATTRIBUTED);
+        assert_eq!(shares[2].pid, None);
+
+        // A quiet period clears the panel
+        tracker.sample_over(Duration::from_secs(1));
+        assert!(tracker.shares().is_empty());
+    }
+
+    #[test]
+    fn test_throttle_suggestions_target_heavy_processes() {
+        let shares = vec![
+            ProcessShare { pid: Some(10), name: "curl".to_string(), bytes_per_sec: 9e6, share: 0.9 },
+            ProcessShare { pid: Some(20), name: "ssh".to_string(), bytes_per_sec: 1e6, share: 0.1 },
+        ];
+
+        let suggestions = throttle_suggestions(&shares, 100);
+        assert_eq!(suggestions.len(), 1);
+        assert!(suggestions[0].contains("10"));
+        assert!(suggestions[0].contains("curl"));
+    }
+}
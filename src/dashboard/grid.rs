@@ -0,0 +1,106 @@
+// Grid layout of the live dashboard
+// `kw live --layout grid` gives every listed interface a small panel of its own, with its
+// speeds, state and a sparkline of its recent traffic, in place of the combined speed and
+// trend panels over the interface list. Panels take as many columns as fit the terminal
+// and as many rows as fit below the header; the rest are counted in the grid's title
+
+use anyhow::Result;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+/// Narrowest panel: room for both speeds and the confidence dots
+pub const PANEL_MIN_WIDTH: u16 = 30;
+
+/// Shortest panel: borders, the speed line and a three-line sparkline
+pub const PANEL_MIN_HEIGHT: u16 = 6;
+
+/// How the interfaces view of the live dashboard is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DashboardLayout {
+    /// Combined speed and trend panels over a list of interfaces
+    #[default]
+    Single,
+    /// A speed panel per interface
+    Grid,
+}
+
+impl DashboardLayout {
+    /// Maps a `--layout` value to a layout
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "single" => Ok(Self::Single),
+            "grid" => Ok(Self::Grid),
+            _ => Err(anyhow::anyhow!("Invalid layout: {} (expected single or grid)", value)),
+        }
+    }
+}
+
+/// Columns and rows for `panels` panels in `area`; rows stop at what fits, so there may
+/// be fewer cells than panels
+pub fn grid_shape(panels: usize, area: Rect) -> (usize, usize) {
+    if panels == 0 {
+        return (0, 0);
+    }
+    let columns = usize::from((area.width / PANEL_MIN_WIDTH).max(1)).min(panels);
+    let rows = panels.div_ceil(columns).min(usize::from((area.height / PANEL_MIN_HEIGHT).max(1)));
+    (columns, rows)
+}
+
+/// Areas of the panels that fit in `area`, row by row; rows and columns share the space evenly
+pub fn panel_areas(area: Rect, panels: usize) -> Vec<Rect> {
+    let (columns, rows) = grid_shape(panels, area);
+    let row_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, rows.max(1) as u32); rows])
+        .split(area);
+    row_areas
+        .iter()
+        .flat_map(|row| {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Ratio(1, columns as u32); columns])
+                .split(*row)
+                .to_vec()
+        })
+        .take(panels)
+        .collect()
+}
+
+/// The newest `width` total speeds in KB/s, as the trend sparklines scale them
+pub fn sparkline_values(total_bps: impl DoubleEndedIterator<Item = f64>, width: usize) -> Vec<u64> {
+    let mut values: Vec<u64> = total_bps.rev().take(width).map(|bps| (bps / 1024.0) as u64).collect();
+    values.reverse();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panels_fill_columns_then_rows_that_fit() {
+        let area = Rect::new(0, 0, 100, 20);
+        assert_eq!(grid_shape(0, area), (0, 0));
+        assert_eq!(grid_shape(2, area), (2, 1));
+        assert_eq!(grid_shape(7, area), (3, 3));
+        // Twelve panels need four rows of three, but only three fit
+        assert_eq!(grid_shape(12, area), (3, 3));
+        // A terminal narrower or shorter than one panel still shows one
+        assert_eq!(grid_shape(4, Rect::new(0, 0, 20, 4)), (1, 1));
+
+        let areas = panel_areas(area, 4);
+        assert_eq!(areas.len(), 4);
+        assert_eq!((areas[0].x, areas[0].y), (0, 0));
+        assert_eq!(areas[3].y, areas[0].y + areas[0].height);
+        assert_eq!(areas.iter().map(|a| a.width).max(), Some(34));
+        assert_eq!(panel_areas(area, 12).len(), 9);
+    }
+
+    #[test]
+    fn test_sparkline_keeps_the_newest_values() {
+        let speeds = [1024.0, 2048.0, 4096.0, 512.0];
+        assert_eq!(sparkline_values(speeds.into_iter(), 3), vec![2, 4, 0]);
+        assert_eq!(sparkline_values(speeds.into_iter(), 10).len(), 4);
+        assert_eq!(DashboardLayout::parse("Grid").unwrap(), DashboardLayout::Grid);
+        assert!(DashboardLayout::parse("tiles").is_err());
+    }
+}
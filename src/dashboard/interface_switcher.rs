@@ -0,0 +1,202 @@
+// Interface switching in the live dashboard
+// n and N step the dashboard through the listed interfaces one at a time and back to all of
+// them, the number keys jump straight to one, and i opens a list of the interfaces ranked by
+// InterfaceManager relevance to pick from. The focused interface narrows the speed panel,
+// trends, interface list and history chart; packets are still captured as started
+
+use crate::collectors::platform::interface_manager::InterfaceManager;
+
+/// One row of the interface list, numbered from 1 in this order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchEntry {
+    pub name: String,
+    pub kind: &'static str,
+    pub score: u8,
+    pub reason: String,
+}
+
+/// The interface the dashboard is focused on, if any, and the list it is picked from
+#[derive(Debug, Default)]
+pub struct InterfaceSwitcher {
+    /// Classifies interfaces once and caches them, as the list is ranked every update
+    manager: InterfaceManager,
+    /// Listed interfaces, most relevant first
+    entries: Vec<SwitchEntry>,
+    focused: Option<String>,
+    /// Row highlighted in the open list, 0 being all interfaces; None while the list is closed
+    popup_row: Option<usize>,
+}
+
+impl InterfaceSwitcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ranks the interfaces now listed, most relevant first and by name on a tie
+    /// A focused interface that is no longer listed is let go of, showing all of them again
+    pub fn refresh(&mut self, names: impl IntoIterator<Item = String>) {
+        let manager = &mut self.manager;
+        self.entries = names
+            .into_iter()
+            .map(|name| {
+                let info = manager.analyze_interface(&name);
+                SwitchEntry {
+                    kind: info.interface_type.label(),
+                    score: info.relevance.score,
+                    reason: info.relevance.reason,
+                    name,
+                }
+            })
+            .collect();
+        self.entries.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+        if let Some(name) = &self.focused
+            && !self.entries.iter().any(|entry| entry.name == *name)
+        {
+            self.focused = None;
+        }
+        if let Some(row) = self.popup_row {
+            self.popup_row = Some(row.min(self.entries.len()));
+        }
+    }
+
+    pub fn entries(&self) -> &[SwitchEntry] {
+        &self.entries
+    }
+
+    pub fn focused(&self) -> Option<&str> {
+        self.focused.as_deref()
+    }
+
+    /// Whether an interface is shown: it is the focused one, or nothing is focused
+    pub fn shows(&self, name: &str) -> bool {
+        self.focused.as_deref().is_none_or(|focused| focused == name)
+    }
+
+    /// List number of the focused interface and how many are listed
+    pub fn position(&self) -> Option<(usize, usize)> {
+        let name = self.focused.as_deref()?;
+        let index = self.entries.iter().position(|entry| entry.name == name)?;
+        Some((index + 1, self.entries.len()))
+    }
+
+    /// Focuses the next interface in the list, or the previous one, passing through all
+    /// interfaces between the last and the first; returns whether the focus changed
+    pub fn step(&mut self, forward: bool) -> bool {
+        if self.entries.is_empty() {
+            return false;
+        }
+        let rows = self.entries.len() + 1;
+        let current = self.focused_row();
+        let next = if forward { (current + 1) % rows } else { (current + rows - 1) % rows };
+        self.select(next)
+    }
+
+    /// Focuses list number `number`, 0 being all interfaces; false when there is no such
+    /// number or it is already focused
+    pub fn select(&mut self, number: usize) -> bool {
+        let focused = match number {
+            0 => None,
+            number => match self.entries.get(number - 1) {
+                Some(entry) => Some(entry.name.clone()),
+                None => return false,
+            },
+        };
+        let changed = focused != self.focused;
+        self.focused = focused;
+        changed
+    }
+
+    /// Opens the list with the focused interface highlighted
+    pub fn open_popup(&mut self) {
+        self.popup_row = Some(self.focused_row());
+    }
+
+    pub fn close_popup(&mut self) {
+        self.popup_row = None;
+    }
+
+    pub fn popup_row(&self) -> Option<usize> {
+        self.popup_row
+    }
+
+    /// Moves the highlight down or up the open list, wrapping around
+    pub fn move_popup(&mut self, down: bool) {
+        let rows = self.entries.len() + 1;
+        if let Some(row) = &mut self.popup_row {
+            *row = if down { (*row + 1) % rows } else { (*row + rows - 1) % rows };
+        }
+    }
+
+    /// Focuses the highlighted row and closes the list; returns whether the focus changed
+    pub fn choose_popup_row(&mut self) -> bool {
+        match self.popup_row.take() {
+            Some(row) => self.select(row),
+            None => false,
+        }
+    }
+
+    /// Row of the focused interface in the list, 0 for all interfaces
+    fn focused_row(&self) -> usize {
+        self.position().map_or(0, |(number, _)| number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn switcher(names: &[&str]) -> InterfaceSwitcher {
+        let mut switcher = InterfaceSwitcher::new();
+        switcher.refresh(names.iter().map(|name| name.to_string()));
+        switcher
+    }
+
+    #[test]
+    fn test_step_cycles_through_interfaces_and_back_to_all() {
+        let mut switcher = switcher(&["docker0", "eth0", "wlan0"]);
+        let names: Vec<String> = switcher.entries().iter().map(|entry| entry.name.clone()).collect();
+        assert_eq!(names.len(), 3);
+        assert!(switcher.entries().windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let (first, last) = (names[0].clone(), names[2].clone());
+        assert!(switcher.step(true));
+        assert_eq!(switcher.focused(), Some(first.as_str()));
+        assert_eq!(switcher.position(), Some((1, 3)));
+        assert!(switcher.shows(&first) && !switcher.shows(&last));
+        assert!(switcher.step(false));
+        assert_eq!(switcher.focused(), None);
+        assert!(switcher.step(false));
+        assert_eq!(switcher.focused(), Some(last.as_str()));
+        assert!(switcher.step(true));
+        assert_eq!(switcher.focused(), None);
+
+        assert!(switcher.select(3));
+        assert!(!switcher.select(3));
+        assert!(!switcher.select(4));
+        assert_eq!(switcher.focused(), Some(last.as_str()));
+
+        // The focused interface going away shows all of them again
+        switcher.refresh(names.into_iter().take(2));
+        assert_eq!(switcher.focused(), None);
+        assert!(!InterfaceSwitcher::new().step(true));
+    }
+
+    #[test]
+    fn test_popup_highlights_and_chooses_a_row() {
+        let mut switcher = switcher(&["eth0", "wlan0"]);
+        switcher.select(2);
+        switcher.open_popup();
+        assert_eq!(switcher.popup_row(), Some(2));
+        switcher.move_popup(true);
+        assert_eq!(switcher.popup_row(), Some(0));
+        switcher.move_popup(false);
+        switcher.move_popup(false);
+        assert!(switcher.choose_popup_row());
+        assert_eq!(switcher.popup_row(), None);
+        assert_eq!(switcher.position(), Some((1, 2)));
+
+        switcher.open_popup();
+        switcher.close_popup();
+        assert!(!switcher.choose_popup_row());
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use chrono::Local;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,19 +11,140 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline},
+    symbols,
+    widgets::{
+        Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, LineGauge, List, ListItem, Paragraph, Row,
+        Sparkline, Table, TableState,
+    },
     Frame, Terminal,
 };
 use std::{
-    collections::VecDeque,
-    io,
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use crate::collectors::{
-    bandwidth_collector::{format_bytes, format_speed, BandwidthStats, CalculationConfidence, BandwidthError},
+    bandwidth::SamplingPlan,
+    bandwidth_collector::{
+        format_bytes, format_speed, BandwidthStats, CalculationConfidence, BandwidthError, InterfaceState, InterfaceType,
+    },
+    interface_selection::InterfaceSelection,
+    link_aggregation::{
+        counted_groups, detect_link_aggregates, group_interfaces, total_stats, FailoverTracker, InterfaceGroup,
+        LinkAggregate, TOTAL_INTERFACE,
+    },
+    link_speed::{detect_link, LinkInfo},
+    ping_collector::{ConnectivityQuality, PingCollector},
+    tunnels::{detect_tunnels, Tunnel},
     BandwidthCollector,
 };
+#[cfg(feature = "capture")]
+use crate::collectors::{
+    interface_selection::ANY_INTERFACE, platform::CaptureBackend, process_collector::ProcessCollector, PacketCollector,
+};
+use crate::analyzers::anomaly::{Anomaly, AnomalyKind, BaselineModel};
+use crate::analyzers::congestion::{Congestion, CongestionMonitor};
+#[cfg(feature = "capture")]
+use crate::analyzers::discovery::DeviceDirectory;
+use crate::analyzers::tcp_state::{LossStats, TcpStateTracker};
+use crate::analyzers::threat_detector::ThreatDetector;
+#[cfg(feature = "capture")]
+use crate::analyzers::SecurityFlag;
+use crate::config::{
+    CongestionConfig, ContentionConfig, LinkAggregationConfig, PingConfig, RefreshConfig, ResourceProfile, SecurityConfig,
+};
+use crate::dashboard::bandwidth_history::BandwidthHistory;
+use crate::dashboard::connection_table::{ConnectionTable, FlowEntry};
+use crate::dashboard::contention::{throttle_suggestions, ContentionTracker, LinkUtilisation};
+use crate::dashboard::grid::{panel_areas, sparkline_values, DashboardLayout};
+use crate::dashboard::interface_switcher::InterfaceSwitcher;
+use crate::dashboard::ndjson::LiveRecord;
+use crate::dashboard::refresh_tuner::{CpuMeter, RefreshTuner};
+use crate::dashboard::session::{SessionRecorder, SessionReplay, SessionSample};
+use crate::dashboard::smoothing::SpeedSmoother;
+use crate::dashboard::snapshots::{export_snapshot, Snapshot, SnapshotHistory, SNAPSHOT_HISTORY};
+#[cfg(feature = "capture")]
+use crate::models::NetworkPacket;
+use crate::models::{PacketDirection, ServiceMap, TagBook, TransportProtocol};
+use crate::shutdown::{self, SessionSummary};
+#[cfg(feature = "capture")]
+use crate::storage::audit;
+use crate::storage::PacketStorage;
+
+/// Maximum number of packets drained from the capture channel per loop iteration
+/// Keeps the UI responsive under heavy traffic
+#[cfg(feature = "capture")]
+const MAX_PACKETS_PER_TICK: usize = 1000;
+
+/// Flows idle for longer than this are removed from the connection table
+const FLOW_IDLE_TIMEOUT_SECS: i64 = 120;
+
+/// How long a port scan, SYN flood or ICMP sweep alert stays in the status bar
+const ALERT_DISPLAY_SECS: u64 = 60;
+
+/// Alerts shown in the status bar at once; older ones give way to newer
+const MAX_ALERTS: usize = 3;
+
+/// How long an interface appearing, disappearing or changing stays in the status bar
+const INTERFACE_CHANGE_DISPLAY_SECS: u64 = 30;
+
+/// Interface changes shown in the status bar at once
+const MAX_INTERFACE_CHANGES: usize = 3;
+
+/// How long the result of exporting a snapshot stays in the status bar
+const NOTICE_DISPLAY_SECS: u64 = 10;
+
+/// Readings the download and upload trend sparklines keep
+const TREND_POINTS: usize = 50;
+
+/// Keys listed by the help overlay, grouped by the view they work in
+const HELP_KEYS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Any view",
+        &[
+            ("q, Esc, Ctrl+C", "quit"),
+            ("Tab", "next view"),
+            ("p, Space", "pause or resume live updates"),
+            ("[ and ]", "step back or forward through recent updates"),
+            ("x", "export what is shown as JSON and PNG"),
+            ("n, N", "focus the next or previous interface"),
+            ("1-9, 0", "focus interface 1-9 of the list, or all"),
+            ("i", "list interfaces by relevance to pick one"),
+            ("?, F1", "show or hide this help"),
+        ],
+    ),
+    (
+        "Connections",
+        &[
+            ("↑/↓, j/k", "select a flow"),
+            ("PgUp/PgDn", "scroll ten flows"),
+            ("Home/End, g/G", "first or last flow"),
+            ("s", "sort by bytes or packets"),
+        ],
+    ),
+    ("History", &[("←/→, h/l", "previous or next interface")]),
+];
+
+/// Seconds of live readings averaged before comparing with the hourly baseline,
+/// matching the interval the background service records samples at
+const ANOMALY_WINDOW_SECS: f64 = 60.0;
+
+/// Which panel occupies the main area of the dashboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DashboardView {
+    /// Per-interface bandwidth list
+    Interfaces,
+    /// Active flows captured by the packet collector
+    Connections,
+    /// Per-process share of traffic on saturated links
+    Contention,
+    /// Bandwidth history chart for a single interface
+    History,
+}
 
 /// Real-time terminal dashboard for network monitoring
 /// Displays live bandwidth statistics using ratatui with enhanced error handling and confidence indicators
@@ -32,8 +153,8 @@ pub struct Dashboard {
     bandwidth_collector: BandwidthCollector,
     /// How often to refresh the display
     update_interval: Duration,
-    /// Optional filter to show only specific interface
-    interface_filter: Option<String>,
+    /// Optional selection of the interfaces to show, by name or glob
+    interface_filter: Option<InterfaceSelection>,
     /// Historical data for sparkline graphs (actual speed values)
     download_history: VecDeque<f64>,
     upload_history: VecDeque<f64>,
@@ -47,44 +168,373 @@ pub struct Dashboard {
     successful_collections: u32,
     /// Last successful collection time
     last_successful_collection: Option<Instant>,
+    /// Bytes received and sent when the baseline reading was taken, for the session summary
+    session_start_usage: Option<(f64, f64)>,
     /// Show only important interfaces
     important_only: bool,
     /// Show all interfaces including virtual
     show_all: bool,
+    /// Packet collector feeding the connection table (only when packet details are requested)
+    #[cfg(feature = "capture")]
+    packet_collector: Option<PacketCollector>,
+    /// Active flows aggregated from captured packets
+    connection_table: ConnectionTable,
+    /// TCP connection states behind the loss figures
+    tcp_tracker: TcpStateTracker,
+    /// Port scan, SYN flood and ICMP sweep heuristics over captured packets
+    threat_detector: ThreatDetector,
+    /// LAN devices named by their mDNS and SSDP announcements, labelled in the connection table
+    #[cfg(feature = "capture")]
+    devices: DeviceDirectory,
+    /// Recent detections with the time they were raised, oldest first
+    security_alerts: VecDeque<(Instant, String)>,
+    /// Recent interface hotplug and state changes with the time they were seen, oldest first
+    interface_changes: VecDeque<(Instant, String)>,
+    /// Scroll state for the connection table widget
+    connection_table_state: TableState,
+    /// Panel currently shown in the main area
+    active_view: DashboardView,
+    /// Per-interface speed history backing the history chart
+    bandwidth_history: BandwidthHistory,
+    /// Interface shown in the history chart
+    history_interface: Option<String>,
+    /// Maps captured packets to local processes for the contention view
+    #[cfg(feature = "capture")]
+    process_collector: ProcessCollector,
+    /// Per-process traffic shares
+    contention: ContentionTracker,
+    /// Saturation threshold and link speed override
+    contention_config: ContentionConfig,
+    /// Detected link speed and duplex per interface, looked up once
+    links: HashMap<String, Option<LinkInfo>>,
+    /// Utilisation of interfaces with a known link speed
+    link_utilisation: Vec<LinkUtilisation>,
+    /// Whether any link was saturated at the last update
+    saturated: bool,
+    /// Show renice/trickle suggestions in the contention view
+    suggest_throttle: bool,
+    /// User tags shown next to interface names and connection addresses
+    tags: TagBook,
+    /// Service names shown after connection ports, e.g. "443/https"
+    services: ServiceMap,
+    /// Hourly bandwidth baselines learned from the service's stored history
+    baseline: BaselineModel,
+    /// Interfaces whose recent throughput is outside their baseline
+    bandwidth_anomalies: HashMap<String, Anomaly>,
+    /// Connectivity probes behind the quality shown in the header
+    ping_collector: Option<PingCollector>,
+    /// Holds the ping latency against the load, once it has been seen idle
+    congestion_monitor: CongestionMonitor,
+    /// Ping targets whose latency was well above idle under load at the last update
+    congestion: Vec<Congestion>,
+    /// Where ping statistics are recorded, if anywhere
+    storage: Option<Arc<PacketStorage>>,
+    /// Whether bond, bridge and team interfaces are folded together with their members
+    aggregation: LinkAggregationConfig,
+    /// Bond, bridge and team interfaces found at the last update
+    link_aggregates: Vec<LinkAggregate>,
+    /// Turns changes between detections into failover events for the status bar
+    failover_tracker: FailoverTracker,
+    /// VPN tunnels among the interfaces and their transports, found at the last update
+    tunnels: Vec<Tunnel>,
+    /// Whether a synthetic "total" interface sums the listed ones (`--aggregate`)
+    show_total: bool,
+    /// Readings of recent updates, and which one is shown while paused
+    snapshots: SnapshotHistory,
+    /// Where exported snapshots are written
+    snapshot_dir: PathBuf,
+    /// Whether the key help overlay is open
+    show_help: bool,
+    /// Outcome of the last snapshot export with the time it happened
+    notice: Option<(Instant, String)>,
+    /// When and how far the update interval backs off under load
+    refresh_config: RefreshConfig,
+    /// Lengthens the update interval while collecting is costly; set up when the dashboard starts
+    refresh_tuner: Option<RefreshTuner>,
+    /// kw's own CPU use, one of the tuner's inputs
+    cpu_meter: CpuMeter,
+    /// Averages the speeds drawn; None when `[smoothing]` is off
+    smoother: Option<SpeedSmoother>,
+    /// Readings with smoothed speeds, drawn in place of the raw ones while smoothing
+    smoothed_stats: Vec<BandwidthStats>,
+    /// Whether the interfaces view is one list or a panel per interface
+    layout: DashboardLayout,
+    /// Interface picked with n/N, the number keys or the interface list, if any
+    switcher: InterfaceSwitcher,
+    /// Session file every update's readings are written to (`--record`)
+    recorder: Option<SessionRecorder>,
+    /// Recorded session read in place of the interfaces (`kw replay`)
+    replay: Option<SessionReplay>,
 }
 
 impl Dashboard {
     /// Creates a new dashboard instance with enhanced initialization
-    pub fn new(update_interval: u64, interface_filter: Option<String>, important_only: bool, show_all: bool) -> Self {
+    /// When `capture_packets` is set, a packet collector is attached to feed the connection view
+    /// `history_minutes` controls how much per-interface history the chart view keeps
+    pub fn new(
+        update_interval: u64,
+        interface_filter: Option<InterfaceSelection>,
+        important_only: bool,
+        show_all: bool,
+        capture_packets: bool,
+        history_minutes: u64,
+    ) -> Self {
+        #[cfg(feature = "capture")]
+        let packet_collector = if capture_packets {
+            let interface_name = interface_filter.as_ref().map_or_else(|| ANY_INTERFACE.to_string(), ToString::to_string);
+            match PacketCollector::new(interface_name) {
+                Ok(collector) => Some(collector),
+                Err(e) => {
+                    warn!("Failed to create packet collector for dashboard: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        // `kw live` refuses --packets itself when built without capture
+        #[cfg(not(feature = "capture"))]
+        let _ = capture_packets;
+
         Self {
-            bandwidth_collector: BandwidthCollector::new(),
+            bandwidth_collector: BandwidthCollector::new().with_interface_watcher().with_power_watcher(),
             update_interval: Duration::from_secs(update_interval),
             interface_filter,
-            download_history: VecDeque::with_capacity(50),
-            upload_history: VecDeque::with_capacity(50),
+            download_history: VecDeque::with_capacity(TREND_POINTS),
+            upload_history: VecDeque::with_capacity(TREND_POINTS),
             current_stats: Vec::new(),
             error_message: None,
             is_initialized: false,
             successful_collections: 0,
             last_successful_collection: None,
+            session_start_usage: None,
             important_only,
             show_all,
+            #[cfg(feature = "capture")]
+            packet_collector,
+            connection_table: ConnectionTable::new(5000),
+            tcp_tracker: TcpStateTracker::new(5000),
+            threat_detector: ThreatDetector::new(5000),
+            #[cfg(feature = "capture")]
+            devices: DeviceDirectory::default(),
+            security_alerts: VecDeque::new(),
+            interface_changes: VecDeque::new(),
+            connection_table_state: TableState::default(),
+            active_view: DashboardView::Interfaces,
+            bandwidth_history: BandwidthHistory::new(Duration::from_secs(history_minutes.max(1) * 60)),
+            history_interface: None,
+            #[cfg(feature = "capture")]
+            process_collector: ProcessCollector::new(),
+            contention: ContentionTracker::new(),
+            contention_config: ContentionConfig::default(),
+            links: HashMap::new(),
+            link_utilisation: Vec::new(),
+            saturated: false,
+            suggest_throttle: false,
+            tags: TagBook::default(),
+            services: ServiceMap::new(),
+            baseline: BaselineModel::default(),
+            bandwidth_anomalies: HashMap::new(),
+            ping_collector: None,
+            congestion_monitor: CongestionMonitor::new(CongestionConfig::default()),
+            congestion: Vec::new(),
+            storage: None,
+            aggregation: LinkAggregationConfig::default(),
+            link_aggregates: Vec::new(),
+            tunnels: Vec::new(),
+            failover_tracker: FailoverTracker::new(),
+            show_total: false,
+            snapshots: SnapshotHistory::new(SNAPSHOT_HISTORY),
+            snapshot_dir: PathBuf::from("."),
+            show_help: false,
+            notice: None,
+            refresh_config: RefreshConfig::default(),
+            refresh_tuner: None,
+            cpu_meter: CpuMeter::new(),
+            smoother: None,
+            smoothed_stats: Vec::new(),
+            layout: DashboardLayout::default(),
+            switcher: InterfaceSwitcher::new(),
+            recorder: None,
+            replay: None,
         }
     }
 
+    /// Writes the readings of every update to a session file for `kw replay`
+    pub fn with_recording(mut self, recorder: SessionRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Shows a recorded session instead of reading the interfaces; nothing is collected,
+    /// captured or pinged, and bonds and tunnels are not grouped
+    pub fn with_replay(mut self, replay: SessionReplay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Samples interfaces at their configured intervals; interfaces without a rule keep the update interval
+    /// The display then refreshes as often as the fastest interface is sampled
+    pub fn with_sampling(mut self, plan: SamplingPlan) -> Self {
+        if plan.is_empty() {
+            return self;
+        }
+        let plan = plan.with_default(self.update_interval);
+        if let Some(interval) = plan.min_interval() {
+            self.update_interval = interval;
+        }
+        self.bandwidth_collector = std::mem::take(&mut self.bandwidth_collector).with_sampling_plan(plan);
+        self
+    }
+
+    /// Sets the saturation threshold and link speed used by the contention view
+    pub fn with_contention(mut self, config: ContentionConfig) -> Self {
+        self.contention_config = config;
+        self
+    }
+
+    /// Thresholds for the port scan, SYN flood and ICMP sweep alerts
+    pub fn with_security_config(mut self, config: SecurityConfig) -> Self {
+        self.threat_detector = std::mem::take(&mut self.threat_detector).with_config(config);
+        self
+    }
+
+    /// Lists renice/trickle commands for the heaviest processes while a link is saturated
+    pub fn with_throttle_suggestions(mut self, enabled: bool) -> Self {
+        self.suggest_throttle = enabled;
+        self
+    }
+
+    /// Adds a "total" row to the interface list and a "total" series to the history chart,
+    /// summing the listed interfaces
+    pub fn with_aggregate_total(mut self, enabled: bool) -> Self {
+        self.show_total = enabled;
+        self
+    }
+
+    /// Shows user tags next to interface names and connection addresses
+    pub fn with_tags(mut self, tags: TagBook) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Port names from the built-in table plus the `[services]` overrides
+    pub fn with_services(mut self, services: ServiceMap) -> Self {
+        self.services = services;
+        self
+    }
+
+    /// Flags interfaces whose traffic is unusual for the hour against these baselines
+    pub fn with_baseline(mut self, baseline: BaselineModel) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Pings the configured targets and shows connectivity quality in the header
+    pub fn with_ping(mut self, config: PingConfig) -> Self {
+        self.ping_collector = (!config.targets.is_empty()).then(|| PingCollector::new(config));
+        self
+    }
+
+    /// When latency rising under load is flagged as congestion in the status bar
+    pub fn with_congestion(mut self, config: CongestionConfig) -> Self {
+        self.congestion_monitor = CongestionMonitor::new(config);
+        self
+    }
+
+    /// Records the ping statistics gathered while the dashboard runs, and the alerts it shows in the audit log
+    pub fn with_storage(mut self, storage: Arc<PacketStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Whether bond, bridge and team interfaces are listed once with their member links
+    pub fn with_link_aggregation(mut self, config: LinkAggregationConfig) -> Self {
+        self.aggregation = config;
+        self
+    }
+
+    /// Backs the update interval off while collection is slow or kw is busy, unless `auto` is off
+    pub fn with_refresh(mut self, config: RefreshConfig) -> Self {
+        self.refresh_config = config;
+        self
+    }
+
+    /// Smooths the speeds drawn over a window of `window_secs`; 0 shows every reading as it is
+    pub fn with_smoothing(mut self, window_secs: f64) -> Self {
+        // `[smoothing] window_secs` is not checked like `--smooth`, and TOML allows inf and nan
+        self.smoother = match Duration::try_from_secs_f64(window_secs) {
+            Ok(window) if !window.is_zero() => Some(SpeedSmoother::new(window)),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Ignoring the smoothing window of {window_secs} seconds: {e}");
+                None
+            }
+        };
+        self
+    }
+
+    /// Directory the `x` key writes snapshots to
+    /// Shows the interfaces view as one list, or as a panel per interface with its own sparkline
+    pub fn with_layout(mut self, layout: DashboardLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_snapshot_dir(mut self, dir: PathBuf) -> Self {
+        self.snapshot_dir = dir;
+        self
+    }
+
+    /// Sizes the packet channel and connection table from the resource profile
+    pub fn with_resource_profile(mut self, resources: ResourceProfile) -> Self {
+        #[cfg(feature = "capture")]
+        {
+            self.packet_collector = self
+                .packet_collector
+                .map(|collector| collector.with_channel_capacity(resources.packet_channel_capacity));
+        }
+        self.connection_table = ConnectionTable::new(resources.dashboard_flows);
+        self.tcp_tracker = TcpStateTracker::new(resources.dashboard_flows);
+        self
+    }
+
+    /// Kernel interface the packet collector captures through
+    #[cfg(feature = "capture")]
+    pub fn with_capture_backend(mut self, backend: CaptureBackend) -> Self {
+        self.packet_collector = self
+            .packet_collector
+            .map(|collector| collector.with_capture_backend(backend));
+        self
+    }
+
+    /// Reads captured packets from a privileged capture helper instead of capturing in-process
+    #[cfg(all(unix, feature = "capture"))]
+    pub fn with_capture_helper(mut self, socket_path: std::path::PathBuf) -> Self {
+        self.packet_collector = self
+            .packet_collector
+            .map(|collector| collector.with_capture_helper(socket_path));
+        self
+    }
+
     /// Collects bandwidth data using the appropriate filtering method
     /// Uses the filtering mode specified when creating the dashboard
-    fn collect_bandwidth_data(&mut self) -> Result<Vec<BandwidthStats>> {
+    async fn collect_bandwidth_data(&mut self) -> Result<Vec<BandwidthStats>> {
+        if let Some(replay) = &mut self.replay {
+            let sample = replay.next_due().ok_or_else(|| anyhow::anyhow!("No recorded sample is due"))?;
+            self.replay_notes(sample.interface_changes, sample.alerts);
+            return Ok(sample.stats);
+        }
         if self.show_all {
             // Show all interfaces including virtual and system interfaces
-            self.bandwidth_collector.collect()
+            self.bandwidth_collector.collect_async().await
         } else if self.important_only {
             // Show only important interfaces (physical ethernet, wifi, VPN)
-            self.bandwidth_collector.collect_important()
+            self.bandwidth_collector.collect_important_async().await
         } else {
             // Use default filtering for dashboard to provide a clean view
             // This excludes most virtual interfaces while keeping important ones like VPN
-            self.bandwidth_collector.collect_default()
+            self.bandwidth_collector.collect_default_async().await
         }
     }
 
@@ -92,8 +542,15 @@ impl Dashboard {
     /// Sets up terminal, runs the UI loop, and cleans up on exit
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting live dashboard with update interval: {}s", self.update_interval.as_secs());
+        let started = Instant::now();
         if let Some(ref filter) = self.interface_filter {
-            info!("Dashboard filtering to interface: '{}'", filter);
+            info!("Dashboard filtering to interfaces: '{}'", filter);
+        }
+
+        // Start packet capture before entering raw mode so privilege errors are logged cleanly
+        self.start_packet_capture().await;
+        if let Some(ping) = &mut self.ping_collector {
+            ping.start();
         }
 
         // Setup terminal for full-screen UI
@@ -117,6 +574,13 @@ impl Dashboard {
         )?;
         terminal.show_cursor()?;
 
+        self.stop_packet_capture().await?;
+        if let Some(ping) = &mut self.ping_collector {
+            ping.stop();
+        }
+        self.store_ping_samples(true);
+        println!("{}", self.finish_session(started));
+
         if let Err(err) = res {
             error!("Dashboard error: {err:?}");
             eprintln!("Error: {err:?}");
@@ -127,6 +591,99 @@ impl Dashboard {
         Ok(())
     }
 
+    /// Writes one JSON object per update interval to stdout instead of drawing the dashboard
+    /// Runs until Ctrl+C, or until stdout is closed (e.g. by `| head`)
+    pub async fn stream(&mut self) -> Result<()> {
+        info!("Streaming live readings as NDJSON every {}s", self.update_interval.as_secs_f64());
+        let started = Instant::now();
+        self.start_packet_capture().await;
+        if let Some(ping) = &mut self.ping_collector {
+            ping.start();
+        }
+
+        let res = self.stream_records().await;
+
+        self.stop_packet_capture().await?;
+        if let Some(ping) = &mut self.ping_collector {
+            ping.stop();
+        }
+        self.store_ping_samples(true);
+        // stdout carries only the records
+        eprintln!("{}", self.finish_session(started));
+        res
+    }
+
+    /// Writes the records storage still buffers and sums up the session
+    fn finish_session(&self, started: Instant) -> SessionSummary {
+        let mut summary = SessionSummary::new("Live session summary", started);
+        if let Some((received, sent)) = self.session_start_usage {
+            let (total_received, total_sent) = self.total_usage();
+            summary.add("Downloaded", format_bytes((total_received - received).max(0.0)));
+            summary.add("Uploaded", format_bytes((total_sent - sent).max(0.0)));
+        }
+        summary.add("Readings", self.successful_collections);
+        if self.is_capturing() {
+            summary.add("Connections", self.connection_table.len());
+        }
+        if let Some(recorder) = &self.recorder {
+            summary.add("Recorded", format!("{} samples", recorder.samples()));
+        }
+        if let Some(storage) = &self.storage {
+            let pending = storage.pending_records();
+            match storage.flush_all() {
+                Ok(()) => summary.add("Written on exit", format!("{pending} buffered records")),
+                Err(e) => summary.add("Written on exit", format!("failed ({e})")),
+            }
+        }
+        summary
+    }
+
+    async fn stream_records(&mut self) -> Result<()> {
+        self.perform_initialization().await;
+        // Without a first reading there is nothing to retry from; a failed second one is retried
+        if self.successful_collections == 0 {
+            anyhow::bail!(self.error_message.clone().unwrap_or_else(|| "Bandwidth monitoring failed to start".to_string()));
+        }
+        if self.is_initialized && self.emit_record().is_err() {
+            return Ok(());
+        }
+
+        let mut last_update = Instant::now();
+        loop {
+            tokio::select! {
+                _ = shutdown::requested() => return Ok(()),
+                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+            }
+
+            self.drain_captured_packets().await;
+
+            if self.handle_interface_events() || last_update.elapsed() >= self.update_interval {
+                self.update_bandwidth_data().await;
+                last_update = Instant::now();
+                match &self.error_message {
+                    Some(error) => warn!("Skipping record: {error}"),
+                    None => {
+                        if let Err(e) = self.emit_record() {
+                            // The reader went away; nothing more to write to
+                            debug!("Stopping NDJSON output: {e}");
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the current readings as one line of JSON
+    fn emit_record(&self) -> io::Result<()> {
+        let flows = self.is_capturing().then(|| self.connection_table.flows());
+        let record = LiveRecord::new(&self.interface_groups(), flows, &self.tags);
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &record)?;
+        writeln!(stdout)?;
+        stdout.flush()
+    }
+
     /// Main application loop with enhanced error handling and initialization
     /// Handles UI rendering, keyboard input, and periodic data updates
     async fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> 
@@ -134,11 +691,20 @@ impl Dashboard {
         B::Error: Send + Sync + 'static,
     {
         let mut last_update = Instant::now();
+        // The interval asked for, after any sampling plan, is the one the tuner comes back to
+        if self.refresh_config.auto {
+            self.refresh_tuner = Some(RefreshTuner::new(self.update_interval, &self.refresh_config));
+        }
         
         // Perform initial baseline collection to establish proper speed calculation
         self.perform_initialization().await;
 
         loop {
+            // SIGTERM, as Ctrl+C arrives as a key in raw mode
+            if shutdown::is_requested() {
+                return Ok(());
+            }
+
             // Render the current UI state
             terminal.draw(|f| self.ui(f))?;
 
@@ -146,33 +712,401 @@ impl Dashboard {
             if event::poll(Duration::from_millis(100))? {
                 if let Event::Key(key) = event::read()? {
                     match key.code {
-                        // Exit on 'q' or Escape key
-                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('q') => return Ok(()),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                        // Any other key closes the help overlay
+                        _ if self.show_help => self.show_help = false,
+                        code if self.switcher.popup_row().is_some() => self.handle_switcher_key(code),
+                        KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('?') | KeyCode::F(1) => self.show_help = true,
+                        // Freeze the display, or step through the last updates
+                        KeyCode::Char('p') | KeyCode::Char(' ') => self.snapshots.toggle_pause(),
+                        KeyCode::Char('[') => self.snapshots.step_back(),
+                        KeyCode::Char(']') => self.snapshots.step_forward(),
+                        KeyCode::Char('x') => self.export_shown_snapshot(),
+                        // Focus one interface, or all of them again
+                        KeyCode::Char('n') => self.switch_interface(|switcher| switcher.step(true)),
+                        KeyCode::Char('N') => self.switch_interface(|switcher| switcher.step(false)),
+                        KeyCode::Char(digit @ '0'..='9') => {
+                            let number = digit.to_digit(10).unwrap_or(0) as usize;
+                            self.switch_interface(|switcher| switcher.select(number))
+                        }
+                        KeyCode::Char('i') => self.switcher.open_popup(),
+                        // Cycle through interface, connection and history views
+                        KeyCode::Tab => self.toggle_view(),
+                        code if self.active_view == DashboardView::Connections => {
+                            self.handle_connection_key(code)
+                        }
+                        code if self.active_view == DashboardView::History => {
+                            self.handle_history_key(code)
+                        }
                         _ => {}
                     }
                 }
             }
 
-            // Update network data at the specified interval
-            if last_update.elapsed() >= self.update_interval {
-                self.update_bandwidth_data();
+            // Fold any newly captured packets into the connection table
+            self.drain_captured_packets().await;
+
+            // Update network data at the specified interval, or straight away when interfaces change;
+            // a replay updates as its recorded samples fall due
+            let due = match &self.replay {
+                Some(replay) => replay.is_due_now(),
+                None => self.handle_interface_events() || last_update.elapsed() >= self.update_interval,
+            };
+            if due {
+                self.update_bandwidth_data().await;
                 last_update = Instant::now();
             }
         }
     }
 
+    /// Notes interfaces that appeared, disappeared or changed since the last tick
+    /// Their link speeds are looked up again; returns whether there were any changes
+    fn handle_interface_events(&mut self) -> bool {
+        let events = self.bandwidth_collector.poll_interface_events();
+        for event in &events {
+            self.links.remove(event.interface_name());
+            if self.interface_changes.len() == MAX_INTERFACE_CHANGES {
+                self.interface_changes.pop_front();
+            }
+            self.interface_changes.push_back((Instant::now(), event.to_string()));
+        }
+        !events.is_empty()
+    }
+
+    /// Looks up bond, bridge and team interfaces and VPN tunnels again, and notes failovers
+    /// since the last update
+    /// A replay leaves them out, as this machine's interfaces say nothing of the recorded ones
+    fn update_link_aggregates(&mut self) {
+        if self.replay.is_some() {
+            return;
+        }
+        self.link_aggregates = detect_link_aggregates();
+        self.tunnels = detect_tunnels(self.current_stats.iter().map(|stat| stat.interface_name.as_str()));
+        for event in self.failover_tracker.observe(&self.link_aggregates) {
+            info!("Link aggregate change: {event}");
+            if self.interface_changes.len() == MAX_INTERFACE_CHANGES {
+                self.interface_changes.pop_front();
+            }
+            self.interface_changes.push_back((Instant::now(), event.to_string()));
+        }
+    }
+
+    /// Puts the interface changes and alerts of a replayed sample in the status bar
+    fn replay_notes(&mut self, interface_changes: Vec<String>, alerts: Vec<String>) {
+        for change in interface_changes {
+            if self.interface_changes.len() == MAX_INTERFACE_CHANGES {
+                self.interface_changes.pop_front();
+            }
+            self.interface_changes.push_back((Instant::now(), change));
+        }
+        for alert in alerts {
+            if self.security_alerts.len() == MAX_ALERTS {
+                self.security_alerts.pop_front();
+            }
+            self.security_alerts.push_back((Instant::now(), alert));
+        }
+    }
+
+    /// Writes the readings just taken, with the changes and alerts noted since the last ones,
+    /// to the session file; a write error stops recording rather than the dashboard
+    fn record_session_sample(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let since = recorder.last_written();
+        let noted_since = |notes: &VecDeque<(Instant, String)>| -> Vec<String> {
+            notes.iter().filter(|(at, _)| *at > since).map(|(_, note)| note.clone()).collect()
+        };
+        let sample = SessionSample {
+            taken_at: Local::now(),
+            stats: self.current_stats.clone(),
+            interface_changes: noted_since(&self.interface_changes),
+            alerts: noted_since(&self.security_alerts),
+        };
+        if let Err(e) = recorder.record(&sample) {
+            error!("Recording stopped after {} samples: {e:#}", recorder.samples());
+            self.error_message = Some(format!("Recording stopped: {e}"));
+            self.recorder = None;
+        }
+    }
+
+    /// Interfaces as listed: aggregates with their members, or each on its own when grouping is off
+    fn interface_groups(&self) -> Vec<InterfaceGroup<'_>> {
+        let aggregates = if self.aggregation.group { self.link_aggregates.as_slice() } else { &[] };
+        group_interfaces(&self.current_stats, aggregates, &self.tunnels)
+    }
+
+    /// Interface groups that pass the `--interface` selection, the ones that can be focused
+    fn selectable_groups(&self) -> Vec<InterfaceGroup<'_>> {
+        self.interface_groups()
+            .into_iter()
+            .filter(|group| {
+                self.interface_filter.as_ref().is_none_or(|f| f.matches(&group.stats.interface_name))
+            })
+            .collect()
+    }
+
+    /// Selectable interface groups narrowed to the focused one, as shown in the interface list
+    fn listed_groups(&self) -> Vec<InterfaceGroup<'_>> {
+        self.selectable_groups()
+            .into_iter()
+            .filter(|group| self.switcher.shows(&group.stats.interface_name))
+            .collect()
+    }
+
+    /// Download and upload speed of all listed interfaces
+    /// Members of a listed aggregate are not counted again, nor tunnels whose transport is listed
+    fn total_speeds(&self) -> (f64, f64) {
+        counted_groups(&self.interface_groups()).fold((0.0, 0.0), |(download, upload), group| {
+            (download + group.stats.download_speed_bps, upload + group.stats.upload_speed_bps)
+        })
+    }
+
+    /// Download and upload speed drawn in the speed panel: the focused interface's, or the total
+    fn shown_speeds(&self) -> (f64, f64) {
+        let focused = self.switcher.focused();
+        match focused.and_then(|name| self.current_stats.iter().find(|stats| stats.interface_name == name)) {
+            Some(stats) => (stats.download_speed_bps, stats.upload_speed_bps),
+            None => self.total_speeds(),
+        }
+    }
+
+    /// Bytes received and sent by the interfaces read since they came up
+    fn total_usage(&self) -> (f64, f64) {
+        match &self.replay {
+            // A replay reads no counters of its own; the recorded ones stand in
+            Some(_) => self.current_stats.iter().fold((0.0, 0.0), |(received, sent), stats| {
+                (received + stats.bytes_received as f64, sent + stats.bytes_sent as f64)
+            }),
+            None => self.bandwidth_collector.get_total_bandwidth(),
+        }
+    }
+
+    /// Applies `change` to the interface focus; the history chart follows a new focus
+    fn switch_interface(&mut self, change: impl FnOnce(&mut InterfaceSwitcher) -> bool) {
+        if change(&mut self.switcher) {
+            self.history_interface = None;
+            info!("Dashboard focused on {}", self.switcher.focused().unwrap_or("all interfaces"));
+        }
+    }
+
+    /// Handles keys while the interface list is open
+    fn handle_switcher_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => self.switcher.move_popup(true),
+            KeyCode::Up | KeyCode::Char('k') => self.switcher.move_popup(false),
+            KeyCode::Enter => self.switch_interface(InterfaceSwitcher::choose_popup_row),
+            KeyCode::Char(digit @ '0'..='9') => {
+                let number = digit.to_digit(10).unwrap_or(0) as usize;
+                self.switcher.close_popup();
+                self.switch_interface(|switcher| switcher.select(number));
+            }
+            KeyCode::Esc | KeyCode::Char('i') => self.switcher.close_popup(),
+            _ => {}
+        }
+    }
+
+    /// Whether packets are being captured for the connection and contention views
+    fn is_capturing(&self) -> bool {
+        #[cfg(feature = "capture")]
+        return self.packet_collector.is_some();
+        #[cfg(not(feature = "capture"))]
+        false
+    }
+
+    /// Starts the attached packet collector, if any
+    /// A failure (usually missing privileges) disables the connection view instead of aborting
+    #[cfg(feature = "capture")]
+    async fn start_packet_capture(&mut self) {
+        let Some(collector) = &self.packet_collector else {
+            return;
+        };
+
+        if let Err(e) = collector.start().await {
+            warn!("Packet capture unavailable in dashboard: {}", e);
+            self.error_message = Some(format!("Packet capture unavailable: {}", e));
+            self.packet_collector = None;
+        } else {
+            info!("Dashboard packet capture started");
+        }
+    }
+
+    #[cfg(not(feature = "capture"))]
+    async fn start_packet_capture(&mut self) {}
+
+    /// Stops the attached packet collector, if any
+    async fn stop_packet_capture(&self) -> Result<()> {
+        #[cfg(feature = "capture")]
+        if let Some(collector) = &self.packet_collector {
+            collector.stop().await?;
+        }
+        Ok(())
+    }
+
+    /// Moves queued packets from the collector into the connection table
+    #[cfg(feature = "capture")]
+    async fn drain_captured_packets(&mut self) {
+        let Some(collector) = &self.packet_collector else {
+            return;
+        };
+
+        let mut drained = 0;
+        while drained < MAX_PACKETS_PER_TICK {
+            match collector.try_receive_packet().await {
+                Some(packet) => {
+                    self.connection_table.record_packet(&packet);
+                    self.tcp_tracker.observe(&packet);
+                    if let Some(device) = self.devices.observe(&packet) {
+                        self.tags.add_discovered(device.address, device.label());
+                    }
+                    for flag in self.threat_detector.observe(&packet) {
+                        let alert = alert_text(&flag, &packet, &self.tags);
+                        warn!("Security alert: {alert}");
+                        if let Some(storage) = &self.storage
+                            && let Err(e) = storage.append_audit(audit::ALERT, "live dashboard", &alert)
+                        {
+                            warn!("Failed to record alert in the audit log: {e}");
+                        }
+                        if self.security_alerts.len() == MAX_ALERTS {
+                            self.security_alerts.pop_front();
+                        }
+                        self.security_alerts.push_back((Instant::now(), alert));
+                    }
+                    let process = self.process_collector.attribute(&packet);
+                    self.contention.record(process.as_ref(), packet.size_bytes);
+                    drained += 1;
+                }
+                None => break,
+            }
+        }
+
+        // Rows keep their places while paused so they can be read
+        if drained > 0 && !self.snapshots.is_paused() {
+            self.connection_table.refresh_order();
+            // Only the running totals are shown; closed connections need not be kept
+            self.tcp_tracker.take_finished();
+        }
+
+        let devices = self.devices.take_updates();
+        if let Some(storage) = &self.storage
+            && let Err(e) = storage.store_discovered_devices(&devices)
+        {
+            warn!("Failed to store discovered devices: {e}");
+        }
+    }
+
+    #[cfg(not(feature = "capture"))]
+    async fn drain_captured_packets(&mut self) {}
+
+    /// Cycles through the interface list, connection table and history chart
+    fn toggle_view(&mut self) {
+        self.active_view = match self.active_view {
+            DashboardView::Interfaces => DashboardView::Connections,
+            DashboardView::Connections => DashboardView::Contention,
+            DashboardView::Contention => DashboardView::History,
+            DashboardView::History => DashboardView::Interfaces,
+        };
+    }
+
+    /// Handles interface selection keys while the history view is active
+    fn handle_history_key(&mut self, code: KeyCode) {
+        let interfaces = self.bandwidth_history.interfaces();
+        if interfaces.is_empty() {
+            return;
+        }
+
+        let current = self
+            .history_interface
+            .as_deref()
+            .and_then(|name| interfaces.iter().position(|i| *i == name))
+            .unwrap_or(0);
+
+        let next = match code {
+            KeyCode::Right | KeyCode::Down | KeyCode::Char('l') | KeyCode::Char('j') => {
+                (current + 1) % interfaces.len()
+            }
+            KeyCode::Left | KeyCode::Up | KeyCode::Char('h') | KeyCode::Char('k') => {
+                (current + interfaces.len() - 1) % interfaces.len()
+            }
+            _ => return,
+        };
+
+        self.history_interface = Some(interfaces[next].to_string());
+    }
+
+    /// Interface currently plotted in the history view
+    /// Falls back to the focused interface, the interface filter, then the busiest interface,
+    /// when nothing was picked
+    fn selected_history_interface(&self) -> Option<String> {
+        let interfaces = self.bandwidth_history.interfaces();
+
+        if let Some(name) = &self.history_interface
+            && interfaces.contains(&name.as_str())
+        {
+            return Some(name.clone());
+        }
+
+        if let Some(name) = self.switcher.focused()
+            && interfaces.contains(&name)
+        {
+            return Some(name.to_string());
+        }
+
+        if self.show_total && interfaces.contains(&TOTAL_INTERFACE) {
+            return Some(TOTAL_INTERFACE.to_string());
+        }
+
+        if let Some(filter) = &self.interface_filter
+            && let Some(name) = interfaces.iter().find(|i| filter.matches(i))
+        {
+            return Some(name.to_string());
+        }
+
+        self.current_stats
+            .iter()
+            .filter(|s| interfaces.contains(&s.interface_name.as_str()))
+            .max_by(|a, b| {
+                (a.download_speed_bps + a.upload_speed_bps)
+                    .total_cmp(&(b.download_speed_bps + b.upload_speed_bps))
+            })
+            .map(|s| s.interface_name.clone())
+            .or_else(|| interfaces.first().map(|i| i.to_string()))
+    }
+
+    /// Handles scrolling and sorting keys while the connection view is active
+    fn handle_connection_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => self.connection_table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.connection_table.select_previous(),
+            KeyCode::PageDown => self.connection_table.scroll_down(10),
+            KeyCode::PageUp => self.connection_table.scroll_up(10),
+            KeyCode::Home | KeyCode::Char('g') => self.connection_table.select_first(),
+            KeyCode::End | KeyCode::Char('G') => self.connection_table.select_last(),
+            KeyCode::Char('s') => self.connection_table.toggle_sort(),
+            _ => {}
+        }
+    }
+
     /// Performs proper initialization sequence to establish baseline readings
     /// This is critical for accurate speed calculations from the start
     async fn perform_initialization(&mut self) {
+        // Recorded speeds need no baseline; the first sample is due straight away
+        if self.replay.is_some() {
+            self.is_initialized = true;
+            return;
+        }
         info!("Starting dashboard initialization sequence");
         let init_start = Instant::now();
 
         // Take initial baseline reading
         debug!("Taking initial baseline reading");
-        match self.collect_bandwidth_data() {
+        match self.collect_bandwidth_data().await {
             Ok(initial_stats) => {
                 info!("Initial baseline reading successful: {} interfaces found", initial_stats.len());
                 self.successful_collections = 1;
+                self.session_start_usage = Some(self.total_usage());
                 self.error_message = Some("Initializing... Taking baseline reading".to_string());
             }
             Err(e) => {
@@ -217,7 +1151,7 @@ impl Dashboard {
 
         // Take second reading to enable speed calculation
         debug!("Taking second reading for speed calculation");
-        match self.collect_bandwidth_data() {
+        match self.collect_bandwidth_data().await {
             Ok(stats) => {
                 let init_duration = init_start.elapsed();
                 info!("Dashboard initialization completed successfully in {:.3}ms: {} interfaces ready", 
@@ -228,6 +1162,10 @@ impl Dashboard {
                 self.is_initialized = true;
                 self.error_message = None;
                 self.last_successful_collection = Some(Instant::now());
+                self.update_link_aggregates();
+                self.smooth_speeds();
+                self.record_snapshot();
+                self.record_session_sample();
                 
                 // Log interface summary for debugging
                 if log::log_enabled!(log::Level::Debug) {
@@ -259,29 +1197,61 @@ impl Dashboard {
 
     /// Updates bandwidth data with graceful error handling
     /// Does not crash the dashboard on collection errors
-    fn update_bandwidth_data(&mut self) {
-        match self.collect_bandwidth_data() {
+    async fn update_bandwidth_data(&mut self) {
+        match self.collect_bandwidth_data().await {
             Ok(stats) => {
                 // Successful collection - update data and clear any error
                 self.current_stats = stats;
                 self.error_message = None;
                 self.successful_collections += 1;
                 self.last_successful_collection = Some(Instant::now());
+                self.update_link_aggregates();
+                let selectable: Vec<String> =
+                    self.selectable_groups().iter().map(|group| group.stats.interface_name.clone()).collect();
+                self.switcher.refresh(selectable);
                 
                 // Update historical data for sparklines with actual speed values
-                let total_download: f64 = self.current_stats.iter().map(|s| s.download_speed_bps).sum();
-                let total_upload: f64 = self.current_stats.iter().map(|s| s.upload_speed_bps).sum();
+                let (total_download, total_upload) = self.total_speeds();
                 
                 self.download_history.push_back(total_download);
                 self.upload_history.push_back(total_upload);
                 
-                // Keep only last 50 data points
-                if self.download_history.len() > 50 {
+                // Keep only the last TREND_POINTS data points
+                if self.download_history.len() > TREND_POINTS {
                     self.download_history.pop_front();
                 }
-                if self.upload_history.len() > 50 {
+                if self.upload_history.len() > TREND_POINTS {
                     self.upload_history.pop_front();
                 }
+
+                // Per-interface history for the chart view, plus the total when aggregating
+                match total_stats(&self.selectable_groups()).filter(|_| self.show_total) {
+                    Some(total) => {
+                        let mut recorded = self.current_stats.clone();
+                        recorded.push(total);
+                        self.bandwidth_history.record(&recorded);
+                    }
+                    None => self.bandwidth_history.record(&self.current_stats),
+                }
+                self.update_anomalies();
+                self.update_congestion();
+
+                // Drop flows that have gone quiet
+                if self.is_capturing() {
+                    let cutoff = Local::now() - chrono::Duration::seconds(FLOW_IDLE_TIMEOUT_SECS);
+                    self.connection_table.expire_idle(cutoff);
+                    if !self.snapshots.is_paused() {
+                        self.connection_table.refresh_order();
+                    }
+                    self.contention.sample();
+                }
+
+                self.update_saturation();
+                self.store_ping_samples(false);
+                self.smooth_speeds();
+                self.record_snapshot();
+                self.record_session_sample();
+                self.tune_refresh_interval();
             }
             Err(e) => {
                 // Collection failed - set error message but don't crash
@@ -291,9 +1261,184 @@ impl Dashboard {
         }
     }
 
+    /// Lets the tuner lengthen or shorten the update interval after the collection just made
+    fn tune_refresh_interval(&mut self) {
+        let Some(tuner) = &mut self.refresh_tuner else {
+            return;
+        };
+        let Some(metrics) = self.bandwidth_collector.last_collection_metrics() else {
+            return;
+        };
+        let cpu = self.cpu_meter.sample();
+        if let Some(interval) = tuner.observe(metrics.duration, cpu) {
+            match tuner.pressure() {
+                Some(pressure) => info!("Update interval now {:.1}s ({pressure})", interval.as_secs_f64()),
+                None => info!("Update interval back to {:.1}s", interval.as_secs_f64()),
+            }
+            self.update_interval = interval;
+        }
+    }
+
+    /// Takes the readings just collected into the moving averages, when smoothing
+    fn smooth_speeds(&mut self) {
+        if let Some(smoother) = &mut self.smoother {
+            self.smoothed_stats = smoother.smooth(&self.current_stats);
+        }
+    }
+
+    /// Keeps the readings just taken for pausing and stepping back, as they are drawn
+    fn record_snapshot(&mut self) {
+        let stats = match self.smoother {
+            Some(_) => self.smoothed_stats.clone(),
+            None => self.current_stats.clone(),
+        };
+        self.snapshots.record(Snapshot {
+            taken_at: Local::now(),
+            stats,
+            link_aggregates: self.link_aggregates.clone(),
+            link_utilisation: self.link_utilisation.clone(),
+            download_history: self.download_history.clone(),
+            upload_history: self.upload_history.clone(),
+        });
+    }
+
+    /// Swaps the readings of the snapshot shown while paused, or the smoothed ones, with the live ones
+    /// Called in pairs around drawing, so the live readings are back in place afterwards
+    fn swap_shown_snapshot(&mut self) {
+        if let Some(snapshot) = self.snapshots.shown_mut() {
+            std::mem::swap(&mut self.current_stats, &mut snapshot.stats);
+            std::mem::swap(&mut self.link_aggregates, &mut snapshot.link_aggregates);
+            std::mem::swap(&mut self.link_utilisation, &mut snapshot.link_utilisation);
+            std::mem::swap(&mut self.download_history, &mut snapshot.download_history);
+            std::mem::swap(&mut self.upload_history, &mut snapshot.upload_history);
+        } else if self.smoother.is_some() {
+            std::mem::swap(&mut self.current_stats, &mut self.smoothed_stats);
+        }
+    }
+
+    /// Writes the readings on screen to the snapshot directory, with a chart of the speeds before them
+    fn export_shown_snapshot(&mut self) {
+        self.swap_shown_snapshot();
+        let flows = self.is_capturing().then(|| self.connection_table.flows());
+        let mut record = LiveRecord::new(&self.interface_groups(), flows, &self.tags);
+        self.swap_shown_snapshot();
+        if let Some(shown) = self.snapshots.shown() {
+            record.timestamp = shown.taken_at;
+        }
+
+        let notice = match export_snapshot(&self.snapshot_dir, &record, &self.snapshots) {
+            Ok(written) => {
+                let files: Vec<String> = written.iter().map(|path| path.display().to_string()).collect();
+                info!("Dashboard snapshot written to {}", files.join(", "));
+                format!("Snapshot saved: {}", files.join(", "))
+            }
+            Err(e) => {
+                warn!("Failed to export dashboard snapshot: {e:#}");
+                format!("Snapshot failed: {e:#}")
+            }
+        };
+        self.notice = Some((Instant::now(), notice));
+    }
+
+    /// Writes finished minutes of ping statistics to storage; `all` includes the current ones
+    fn store_ping_samples(&self, all: bool) {
+        let (Some(ping), Some(storage)) = (&self.ping_collector, &self.storage) else {
+            return;
+        };
+        let samples = if all { ping.drain() } else { ping.take_samples() };
+        if let Err(e) = storage.store_ping_samples(&samples) {
+            warn!("Failed to store ping statistics: {e}");
+        }
+    }
+
+    /// Compares the last minute of each interface with its baseline for the hour
+    fn update_anomalies(&mut self) {
+        if self.baseline.is_empty() {
+            return;
+        }
+        let now = Local::now();
+        let history = &self.bandwidth_history;
+        let baseline = &self.baseline;
+        let anomalies: HashMap<String, Anomaly> = self
+            .current_stats
+            .iter()
+            .filter_map(|stat| {
+                let average = history.average_total_bps(&stat.interface_name, ANOMALY_WINDOW_SECS)?;
+                let anomaly = baseline.check(&stat.interface_name, now, average)?;
+                Some((stat.interface_name.clone(), anomaly))
+            })
+            .collect();
+        for anomaly in anomalies.values().filter(|a| !self.bandwidth_anomalies.contains_key(&a.interface)) {
+            info!("Unusual traffic on {}: {} ({})", anomaly.interface, anomaly.kind(), anomaly.describe());
+        }
+        self.bandwidth_anomalies = anomalies;
+    }
+
+    /// Holds each ping target's latency against the busiest interface's throughput
+    fn update_congestion(&mut self) {
+        let Some(ping) = &self.ping_collector else {
+            return;
+        };
+        let busiest = counted_groups(&self.interface_groups())
+            .filter(|group| group.stats.interface_type != InterfaceType::Loopback)
+            .map(|group| group.stats.download_speed_bps + group.stats.upload_speed_bps)
+            .fold(0.0, f64::max);
+        let congestion = self.congestion_monitor.observe(busiest, &ping.status());
+        for target in congestion.iter().filter(|c| !self.congestion.iter().any(|known| known.target == c.target)) {
+            info!(
+                "Congestion towards {}: {:.1}ms under load, {:.1}ms idle",
+                target.target, target.latency_ms, target.baseline_ms
+            );
+        }
+        self.congestion = congestion;
+    }
+
+    /// Compares interface throughput against link speed
+    /// Switches from the interface list to the contention view when a link becomes saturated
+    fn update_saturation(&mut self) {
+        let links = &mut self.links;
+        let override_mbps = self.contention_config.link_speed_mbps;
+        self.link_utilisation = LinkUtilisation::from_stats(&self.current_stats, |interface| {
+            let detected = *links.entry(interface.to_string()).or_insert_with(|| detect_link(interface));
+            match override_mbps {
+                Some(speed_mbps) => Some(LinkInfo { speed_mbps, duplex: detected.and_then(|link| link.duplex) }),
+                None => detected,
+            }
+        });
+
+        let threshold = self.contention_config.saturation_percent;
+        let saturated = self.link_utilisation.iter().any(|l| l.is_saturated(threshold));
+        if saturated && !self.saturated {
+            info!("Link saturation detected (threshold {threshold}%)");
+            if self.is_capturing() && self.active_view == DashboardView::Interfaces {
+                self.active_view = DashboardView::Contention;
+            }
+        }
+        self.saturated = saturated;
+    }
+
     /// Main UI layout function with enhanced error display
     /// Divides the terminal into sections and renders each component
     fn ui(&mut self, frame: &mut Frame) {
+        // A paused dashboard draws the snapshot being looked at in place of the live readings
+        self.swap_shown_snapshot();
+
+        // The grid's panels take the place of the speed, trend and interface list sections
+        if self.layout == DashboardLayout::Grid && self.active_view == DashboardView::Interfaces {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Min(10), Constraint::Length(3)])
+                .split(frame.area());
+            self.render_header(frame, chunks[0]);
+            self.render_status(frame, chunks[1]);
+            self.render_interface_grid(frame, chunks[2]);
+            self.render_footer(frame, chunks[3]);
+            self.render_overlays(frame);
+            self.swap_shown_snapshot();
+            return;
+        }
+
         // Create a 6-section vertical layout to include error/status section
         let chunks = Layout::default()
             .direction(Direction::Vertical)
@@ -304,7 +1449,7 @@ impl Dashboard {
                     Constraint::Length(3),   // Status/Error section
                     Constraint::Length(5),   // Current speed section
                     Constraint::Length(5),   // Sparkline graphs section
-                    Constraint::Min(10),     // Interface list (takes remaining space)
+                    Constraint::Min(10),     // Interface list or connection table (takes remaining space)
                     Constraint::Length(3),   // Footer section
                 ]
                 .as_ref(),
@@ -316,20 +1461,53 @@ impl Dashboard {
         self.render_status(frame, chunks[1]);
         self.render_current_speed(frame, chunks[2]);
         self.render_sparklines(frame, chunks[3]);
-        self.render_interface_list(frame, chunks[4]);
+        match self.active_view {
+            DashboardView::Interfaces => self.render_interface_list(frame, chunks[4]),
+            DashboardView::Connections => self.render_connections(frame, chunks[4]),
+            DashboardView::Contention => self.render_contention(frame, chunks[4]),
+            DashboardView::History => self.render_history_chart(frame, chunks[4]),
+        }
         self.render_footer(frame, chunks[5]);
+        self.render_overlays(frame);
+
+        self.swap_shown_snapshot();
     }
 
-    /// Renders the header section with title and current timestamp
+    /// Renders the header section with title, current timestamp and connectivity quality
     fn render_header(&self, frame: &mut Frame, area: Rect) {
-        let header = vec![Line::from(vec![
-            Span::raw("Internet Monitor - Live Dashboard"),
-            Span::raw("    "),
-            Span::styled(
-                Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                Style::default().fg(Color::Yellow),
-            ),
-        ])];
+        let mut spans = match &self.replay {
+            Some(replay) => self.replay_spans(replay),
+            None => vec![
+                Span::raw("Internet Monitor - Live Dashboard"),
+                Span::raw("    "),
+                Span::styled(
+                    Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    Style::default().fg(Color::Yellow),
+                ),
+            ],
+        };
+        if let Some(recorder) = &self.recorder {
+            spans.push(Span::styled(
+                format!("    ● REC {}", recorder.samples()),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let (Some(shown), Some((position, kept))) = (self.snapshots.shown(), self.snapshots.position()) {
+            spans.push(Span::styled(
+                format!("    ⏸ Paused at {} ({position}/{kept})", shown.taken_at.format("%H:%M:%S")),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let (Some(name), Some((number, listed))) = (self.switcher.focused(), self.switcher.position()) {
+            spans.push(Span::styled(
+                format!("    ◉ {name} ({number}/{listed})"),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let Some(ping) = &self.ping_collector {
+            spans.extend(self.connectivity_spans(ping));
+        }
+        let header = vec![Line::from(spans)];
 
         let block = Block::default()
             .borders(Borders::ALL)
@@ -339,6 +1517,56 @@ impl Dashboard {
         frame.render_widget(paragraph, area);
     }
 
+    /// Title of a replay: when the sample shown was taken, how far through the session it is and
+    /// how fast it plays
+    fn replay_spans(&self, replay: &SessionReplay) -> Vec<Span<'static>> {
+        let (played, total) = replay.progress();
+        let shown_at = replay.shown_at().unwrap_or(replay.header().started_at);
+        let state = if replay.is_finished() { "⏹ Replay finished" } else { "▶ Replay" };
+        vec![
+            Span::raw(format!("{state} - recorded {}", replay.header().started_at.format("%Y-%m-%d"))),
+            Span::raw("    "),
+            Span::styled(shown_at.format("%H:%M:%S").to_string(), Style::default().fg(Color::Yellow)),
+            Span::styled(
+                format!("    {played}/{total} at {}x", replay.speed()),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]
+    }
+
+    /// Overall connectivity quality followed by latency, jitter and loss per target
+    fn connectivity_spans(&self, ping: &PingCollector) -> Vec<Span<'static>> {
+        let status = ping.status();
+        let Some(quality) = ConnectivityQuality::overall(&status) else {
+            return vec![Span::raw("    "), Span::styled("● Pinging...", Style::default().fg(Color::Gray))];
+        };
+        let color = match quality {
+            ConnectivityQuality::Good => Color::Green,
+            ConnectivityQuality::Fair => Color::Yellow,
+            ConnectivityQuality::Poor | ConnectivityQuality::Down => Color::Red,
+        };
+
+        let mut spans = vec![
+            Span::raw("    "),
+            Span::styled(format!("● {}", quality.as_str()), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+        ];
+        for target in status.iter().filter(|s| s.probes > 0) {
+            let mut text = format!("  {} ", self.tags.describe(&target.target));
+            match target.latency_ms {
+                Some(latency) => text.push_str(&format!("{latency:.1}ms")),
+                None => text.push_str("no reply"),
+            }
+            if let Some(jitter) = target.jitter_ms {
+                text.push_str(&format!(" ±{jitter:.1}"));
+            }
+            if target.loss_percent > 0.0 && target.latency_ms.is_some() {
+                text.push_str(&format!(" {:.0}% loss", target.loss_percent));
+            }
+            spans.push(Span::raw(text));
+        }
+        spans
+    }
+
     /// Renders status/error information section
     fn render_status(&self, frame: &mut Frame, area: Rect) {
         let status_text = if let Some(error) = &self.error_message {
@@ -358,11 +1586,67 @@ impl Dashboard {
                     .map(|t| format!("{:.1}s ago", t.elapsed().as_secs_f32()))
                     .unwrap_or_else(|| "Never".to_string())
             );
-            vec![Line::from(vec![
+            let mut spans = vec![
                 Span::styled("✓ ", Style::default().fg(Color::Green)),
                 Span::raw("Monitoring active | "),
                 Span::styled(collections_text, Style::default().fg(Color::DarkGray)),
-            ])]
+            ];
+            let threshold = self.contention_config.saturation_percent;
+            for link in self.link_utilisation.iter().filter(|l| l.is_saturated(threshold)) {
+                spans.push(Span::styled(
+                    format!(" | {} saturated {:.0}%", link.interface, link.utilisation * 100.0),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            let mut anomalies: Vec<&Anomaly> = self.bandwidth_anomalies.values().collect();
+            anomalies.sort_by(|a, b| a.interface.cmp(&b.interface));
+            for anomaly in anomalies {
+                spans.push(Span::styled(
+                    format!(" | {} unusual {} ({})", anomaly.interface, anomaly.kind(), anomaly.describe()),
+                    Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                ));
+            }
+            for congestion in &self.congestion {
+                spans.push(Span::styled(
+                    format!(
+                        " | {} congested +{:.0}ms under load",
+                        self.tags.describe(&congestion.target),
+                        congestion.added_latency_ms()
+                    ),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            let shown_for = Duration::from_secs(ALERT_DISPLAY_SECS);
+            for (_, alert) in self.security_alerts.iter().rev().filter(|(at, _)| at.elapsed() < shown_for) {
+                spans.push(Span::styled(
+                    format!(" | ⚠ {alert}"),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            let shown_for = Duration::from_secs(INTERFACE_CHANGE_DISPLAY_SECS);
+            for (_, change) in self.interface_changes.iter().rev().filter(|(at, _)| at.elapsed() < shown_for) {
+                spans.push(Span::styled(format!(" | {change}"), Style::default().fg(Color::Cyan)));
+            }
+            if let Some(smoother) = &self.smoother {
+                spans.push(Span::styled(
+                    format!(" | Speeds smoothed over {:.0}s", smoother.window().as_secs_f64()),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if let Some(tuner) = &self.refresh_tuner
+                && let Some(pressure) = tuner.pressure()
+            {
+                spans.push(Span::styled(
+                    format!(" | Refresh slowed to {:.0}s ({pressure})", tuner.interval().as_secs_f64()),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            if let Some((at, notice)) = &self.notice
+                && at.elapsed() < Duration::from_secs(NOTICE_DISPLAY_SECS)
+            {
+                spans.push(Span::styled(format!(" | {notice}"), Style::default().fg(Color::Green)));
+            }
+            vec![Line::from(spans)]
         };
 
         let block = Block::default()
@@ -379,9 +1663,9 @@ impl Dashboard {
         // Use cached stats instead of calling collect() again
         let stats = &self.current_stats;
 
-        // Calculate total speeds across all interfaces
-        let total_download: f64 = stats.iter().map(|s| s.download_speed_bps).sum();
-        let total_upload: f64 = stats.iter().map(|s| s.upload_speed_bps).sum();
+        // Total speeds across all interfaces, or the focused interface's
+        let (total_download, total_upload) = self.shown_speeds();
+        let (total_received, total_sent) = self.total_usage();
 
         // Calculate overall confidence level
         let overall_confidence = self.calculate_overall_confidence(stats);
@@ -409,45 +1693,83 @@ impl Dashboard {
             Line::from(""),
             Line::from(vec![
                 Span::raw("Total Usage: "),
-                Span::raw(format!(
-                    "↓ {} ↑ {}",
-                    format_bytes(self.bandwidth_collector.get_total_bandwidth().0),
-                    format_bytes(self.bandwidth_collector.get_total_bandwidth().1)
-                )),
+                Span::raw(format!("↓ {} ↑ {}", format_bytes(total_received), format_bytes(total_sent))),
             ]),
         ];
 
         let block = Block::default()
             .borders(Borders::ALL)
-            .title("Network Statistics")
+            .title(match self.switcher.focused() {
+                Some(name) => format!("Network Statistics - {name}"),
+                None => "Network Statistics".to_string(),
+            })
             .style(Style::default().fg(Color::White));
 
         let paragraph = Paragraph::new(speed_text).block(block);
-        frame.render_widget(paragraph, area);
+        if self.link_utilisation.is_empty() {
+            frame.render_widget(paragraph, area);
+            return;
+        }
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)].as_ref())
+            .split(area);
+        frame.render_widget(paragraph, chunks[0]);
+        self.render_link_gauges(frame, chunks[1]);
+    }
+
+    /// Renders a gauge of link utilisation for the busiest links that report a speed
+    fn render_link_gauges(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Link Utilisation")
+            .style(Style::default().fg(Color::White));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let mut links: Vec<&LinkUtilisation> = self.link_utilisation.iter().collect();
+        links.sort_by(|a, b| b.utilisation.total_cmp(&a.utilisation));
+        let threshold = self.contention_config.saturation_percent;
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(1); inner.height as usize])
+            .split(inner);
+        for (link, row) in links.into_iter().zip(rows.iter()) {
+            let color = if link.is_saturated(threshold) {
+                Color::Red
+            } else if link.is_saturated(threshold / 2.0) {
+                Color::Yellow
+            } else {
+                Color::Green
+            };
+            let gauge = LineGauge::default()
+                .ratio(link.utilisation.clamp(0.0, 1.0))
+                .label(format!("{:<10.10} {:>5.1}%", link.interface, link.utilisation * 100.0))
+                .filled_style(Style::default().fg(color))
+                .unfilled_style(Style::default().fg(Color::DarkGray));
+            frame.render_widget(gauge, *row);
+        }
     }
 
     /// Renders the list of network interfaces with their statistics and confidence indicators
     /// Applies interface filter if specified
     fn render_interface_list(&mut self, frame: &mut Frame, area: Rect) {
         // Use cached stats instead of calling collect() again
-        let stats = &self.current_stats;
+        let interface_loss: HashMap<String, LossStats> = self.tcp_tracker.loss_by_interface().into_iter().collect();
 
         // Create list items for each interface (filtered if needed)
-        let items: Vec<ListItem> = stats
-            .iter()
-            .filter(|s| {
-                // Apply interface filter if specified
-                self.interface_filter.as_ref()
-                    .map(|f| s.interface_name.contains(f))
-                    .unwrap_or(true)
-            })
-            .map(|stat| {
+        let groups = self.listed_groups();
+        let total = total_stats(&groups).filter(|_| self.show_total && self.switcher.focused().is_none());
+        let mut items: Vec<ListItem> = groups
+            .into_iter()
+            .map(|group| {
+                let stat = group.stats;
                 let confidence_indicator = self.get_confidence_indicator(&stat.calculation_confidence);
                 let confidence_color = self.get_confidence_color(&stat.calculation_confidence);
                 
-                let content = vec![Line::from(vec![
+                let mut content = vec![Line::from(vec![
                     Span::styled(
-                        format!("{:<15}", stat.interface_name),
+                        format!("{:<15}", self.tags.describe(&stat.interface_name)),
                         Style::default().fg(Color::Cyan),
                     ),
                     Span::raw(format!(
@@ -465,11 +1787,99 @@ impl Dashboard {
                         stat.packets_received,
                         stat.packets_sent
                     )),
+                    Span::raw(
+                        interface_loss
+                            .get(&stat.interface_name)
+                            .map(|loss| format!(" | TCP loss {:.2}%", loss.loss_rate() * 100.0))
+                            .unwrap_or_default(),
+                    ),
+                    Span::styled(
+                        self.bandwidth_anomalies
+                            .get(&stat.interface_name)
+                            .map(|anomaly| {
+                                let arrow = match anomaly.kind() {
+                                    AnomalyKind::Spike => "▲",
+                                    AnomalyKind::Drop => "▼",
+                                };
+                                format!(" | {arrow} {}", anomaly.describe())
+                            })
+                            .unwrap_or_default(),
+                        Style::default().fg(Color::Magenta),
+                    ),
+                    Span::styled(
+                        group
+                            .aggregate
+                            .map(|aggregate| {
+                                let up = aggregate.members.iter().filter(|member| member.up).count();
+                                format!(" | {} {up}/{} up", aggregate.kind, aggregate.members.len())
+                            })
+                            .unwrap_or_default(),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        group.tunnel.map(|tunnel| format!(" | {tunnel}")).unwrap_or_default(),
+                        Style::default().fg(Color::Yellow),
+                    ),
                 ])];
+                if let Some(aggregate) = group.aggregate.filter(|_| self.aggregation.show_members) {
+                    for (member, member_stats) in &group.members {
+                        let (traffic, color) = match member_stats {
+                            _ if !member.up => ("down".to_string(), Color::Red),
+                            Some(member_stats) => (
+                                format!(
+                                    "↓ {:<12} ↑ {:<12}",
+                                    format_speed(member_stats.download_speed_bps),
+                                    format_speed(member_stats.upload_speed_bps),
+                                ),
+                                Color::White,
+                            ),
+                            None => ("up".to_string(), Color::White),
+                        };
+                        let active = if aggregate.active_member.as_ref() == Some(&member.name) { " (active)" } else { "" };
+                        content.push(Line::from(vec![
+                            Span::styled(
+                                format!("  └ {:<11}", self.tags.describe(&member.name)),
+                                Style::default().fg(Color::DarkGray),
+                            ),
+                            Span::styled(format!(" {traffic}{active}"), Style::default().fg(color)),
+                        ]));
+                    }
+                }
                 ListItem::new(content)
             })
             .collect();
 
+        // The total heads the list, summing the rows below it
+        if let Some(total) = total {
+            let count = items.len();
+            items.insert(
+                0,
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("{:<15}", TOTAL_INTERFACE),
+                        Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        format!(
+                            " ↓ {:<12} ↑ {:<12}",
+                            format_speed(total.download_speed_bps),
+                            format_speed(total.upload_speed_bps),
+                        ),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(" "),
+                    Span::styled(
+                        self.get_confidence_indicator(&total.calculation_confidence),
+                        Style::default().fg(self.get_confidence_color(&total.calculation_confidence)),
+                    ),
+                    Span::raw(format!(
+                        " | Packets: ↓ {} ↑ {} | {count} interfaces",
+                        total.packets_received, total.packets_sent
+                    )),
+                ])),
+            );
+        }
+
         let title = if items.is_empty() {
             "Network Interfaces (No data available)"
         } else {
@@ -492,6 +1902,389 @@ impl Dashboard {
         frame.render_widget(interfaces, area);
     }
 
+    /// Renders a panel per listed interface, the total first when aggregating, each with its
+    /// speeds, state and a sparkline of its history
+    fn render_interface_grid(&self, frame: &mut Frame, area: Rect) {
+        let groups = self.listed_groups();
+        let mut panels: Vec<(BandwidthStats, bool)> = Vec::new();
+        if let Some(total) = total_stats(&groups).filter(|_| self.show_total && self.switcher.focused().is_none()) {
+            panels.push((total, true));
+        }
+        panels.extend(groups.iter().map(|group| (group.stats.clone(), false)));
+
+        let outer = Block::default().borders(Borders::ALL);
+        let inner = outer.inner(area);
+        let areas = panel_areas(inner, panels.len());
+        let title = match panels.len() - areas.len() {
+            _ if panels.is_empty() => "Network Interfaces (No data available)".to_string(),
+            0 => "Network Interfaces".to_string(),
+            hidden => format!("Network Interfaces ({hidden} more do not fit; narrow with --interface)"),
+        };
+        frame.render_widget(outer.title(title), area);
+
+        for ((stat, is_total), panel) in panels.iter().zip(areas) {
+            let (state, state_color) = match stat.interface_state {
+                _ if *is_total => ("Σ", Color::Cyan),
+                InterfaceState::Up => ("● up", Color::Green),
+                InterfaceState::Down => ("● down", Color::Red),
+                InterfaceState::Unknown => ("○ unknown", Color::DarkGray),
+            };
+            let name = if *is_total { TOTAL_INTERFACE.to_string() } else { self.tags.describe(&stat.interface_name) };
+            let mut name_style = Style::default().fg(Color::Cyan);
+            if *is_total {
+                name_style = name_style.add_modifier(Modifier::BOLD);
+            }
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(state_color))
+                .title(Line::from(vec![
+                    Span::styled(format!(" {name} "), name_style),
+                    Span::styled(format!("{state} "), Style::default().fg(state_color)),
+                ]));
+            let content = block.inner(panel);
+            frame.render_widget(block, panel);
+
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(1)])
+                .split(content);
+            let mut speeds = vec![
+                Span::raw(format!(
+                    "↓ {} ↑ {} ",
+                    format_speed(stat.download_speed_bps),
+                    format_speed(stat.upload_speed_bps)
+                )),
+                Span::styled(
+                    self.get_confidence_indicator(&stat.calculation_confidence),
+                    Style::default().fg(self.get_confidence_color(&stat.calculation_confidence)),
+                ),
+            ];
+            if let Some(anomaly) = self.bandwidth_anomalies.get(&stat.interface_name) {
+                let arrow = match anomaly.kind() {
+                    AnomalyKind::Spike => " ▲",
+                    AnomalyKind::Drop => " ▼",
+                };
+                speeds.push(Span::styled(arrow, Style::default().fg(Color::Magenta)));
+            }
+            frame.render_widget(Paragraph::new(Line::from(speeds)), rows[0]);
+
+            let history = self
+                .bandwidth_history
+                .samples(&stat.interface_name)
+                .map(|samples| sparkline_values(samples.iter().map(|s| s.download_bps + s.upload_bps), usize::from(rows[1].width)))
+                .unwrap_or_default();
+            let sparkline = Sparkline::default()
+                .data(&history)
+                .max(history.iter().max().copied().unwrap_or(1).max(1))
+                .style(Style::default().fg(if stat.interface_state == InterfaceState::Down { Color::DarkGray } else { state_color }));
+            frame.render_widget(sparkline, rows[1]);
+        }
+    }
+
+    /// Renders the connection table with a detail pane for the selected flow
+    fn render_connections(&mut self, frame: &mut Frame, area: Rect) {
+        if !self.is_capturing() {
+            let message = Paragraph::new(vec![
+                Line::from("Packet capture is not active."),
+                Line::from(""),
+                Line::from("Restart with `kw live --packets` (requires elevated privileges) to list active connections."),
+            ])
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title("Connections"));
+            frame.render_widget(message, area);
+            return;
+        }
+
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+            .split(area);
+
+        let (tags, services) = (&self.tags, &self.services);
+        let endpoint = |addr: &IpAddr, port: Option<u16>, protocol: TransportProtocol| {
+            let mut endpoint = FlowEntry::endpoint(addr, port, tags);
+            if let Some(service) = port.and_then(|port| services.name(port, protocol)) {
+                endpoint = format!("{endpoint}/{service}");
+            }
+            match tags.label(&addr.to_string()) {
+                Some(label) => format!("{endpoint} ({label})"),
+                None => endpoint,
+            }
+        };
+        let flows = self.connection_table.flows();
+        let rows: Vec<Row> = flows
+            .iter()
+            .map(|flow| {
+                Row::new(vec![
+                    Cell::from(endpoint(&flow.source, flow.source_port, flow.protocol)),
+                    Cell::from(endpoint(&flow.destination, flow.dest_port, flow.protocol)),
+                    Cell::from(format!("{:?}", flow.protocol)),
+                    Cell::from(direction_arrow(flow.direction)),
+                    Cell::from(format_bytes(flow.bytes as f64)),
+                    Cell::from(flow.packets.to_string()),
+                ])
+            })
+            .collect();
+
+        let header = Row::new(vec!["Source", "Destination", "Proto", "Dir", "Bytes", "Packets"])
+            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+
+        let loss = self.tcp_tracker.summary().loss;
+        let title = format!(
+            "Connections ({} flows, sorted by {}, TCP loss {:.2}%)",
+            self.connection_table.len(),
+            self.connection_table.sort_key().label(),
+            loss.loss_rate() * 100.0
+        );
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(28),
+                Constraint::Percentage(28),
+                Constraint::Length(6),
+                Constraint::Length(3),
+                Constraint::Length(11),
+                Constraint::Length(8),
+            ],
+        )
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .style(Style::default().fg(Color::White))
+        .row_highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD))
+        .highlight_symbol("▶ ");
+
+        self.connection_table_state.select(self.connection_table.selected_index());
+        frame.render_stateful_widget(table, chunks[0], &mut self.connection_table_state);
+
+        let detail = match self.connection_table.selected_flow() {
+            _ if self.connection_table.is_empty() => vec![Line::from("Waiting for captured traffic...")],
+            Some(flow) => {
+                let mut lines = vec![
+                    Line::from(vec![Span::raw("Source:      "), Span::styled(endpoint(&flow.source, flow.source_port, flow.protocol), Style::default().fg(Color::Cyan))]),
+                    Line::from(vec![Span::raw("Destination: "), Span::styled(endpoint(&flow.destination, flow.dest_port, flow.protocol), Style::default().fg(Color::Cyan))]),
+                    Line::from(format!("Protocol:    {:?}", flow.protocol)),
+                    Line::from(format!("Direction:   {:?}", flow.direction)),
+                    Line::from(""),
+                    Line::from(format!("Bytes:       {}", format_bytes(flow.bytes as f64))),
+                    Line::from(format!("Packets:     {}", flow.packets)),
+                    Line::from(format!(
+                        "Avg size:    {}",
+                        format_bytes(flow.bytes as f64 / flow.packets.max(1) as f64)
+                    )),
+                    Line::from(""),
+                    Line::from(format!("First seen:  {}", flow.first_seen.format("%H:%M:%S"))),
+                    Line::from(format!("Last seen:   {}", flow.last_seen.format("%H:%M:%S"))),
+                    Line::from(format!(
+                        "Duration:    {}s",
+                        (flow.last_seen - flow.first_seen).num_seconds()
+                    )),
+                ];
+                if let Some(loss) = flow_loss(&self.tcp_tracker, flow) {
+                    lines.extend([
+                        Line::from(""),
+                        Line::from(format!("Retransmits: {} ({:.2}% loss)", loss.retransmissions, loss.loss_rate() * 100.0)),
+                        Line::from(format!("Dup ACKs:    {}", loss.duplicate_acks)),
+                        Line::from(format!("Out-of-order: {}", loss.out_of_order)),
+                    ]);
+                }
+                lines
+            }
+            None => vec![Line::from("No flow selected")],
+        };
+
+        let detail_pane = Paragraph::new(detail)
+            .block(Block::default().borders(Borders::ALL).title("Flow Details"));
+        frame.render_widget(detail_pane, chunks[1]);
+    }
+
+    /// Renders link utilisation and the per-process share of captured traffic
+    fn render_contention(&self, frame: &mut Frame, area: Rect) {
+        if !self.is_capturing() {
+            let message = Paragraph::new(vec![
+                Line::from("Packet capture is not active."),
+                Line::from(""),
+                Line::from("Restart with `kw live --packets` (requires elevated privileges) to see which processes use the bandwidth."),
+            ])
+            .style(Style::default().fg(Color::DarkGray))
+            .block(Block::default().borders(Borders::ALL).title("Contention"));
+            frame.render_widget(message, area);
+            return;
+        }
+
+        let threshold = self.contention_config.saturation_percent;
+        let saturated: Vec<&LinkUtilisation> =
+            self.link_utilisation.iter().filter(|l| l.is_saturated(threshold)).collect();
+        let suggestions = match saturated.iter().map(|l| l.link.speed_mbps).min() {
+            Some(link_mbps) if self.suggest_throttle => throttle_suggestions(self.contention.shares(), link_mbps),
+            _ => Vec::new(),
+        };
+
+        let link_lines = self.link_utilisation.len().max(1) as u16 + 2;
+        let suggestion_lines = if suggestions.is_empty() { 0 } else { suggestions.len() as u16 + 2 };
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(link_lines),
+                    Constraint::Min(4),
+                    Constraint::Length(suggestion_lines),
+                ]
+                .as_ref(),
+            )
+            .split(area);
+
+        let links: Vec<Line> = if self.link_utilisation.is_empty() {
+            vec![Line::from(Span::styled(
+                "No interface reports a link speed; set [contention] link_speed_mbps in the config",
+                Style::default().fg(Color::DarkGray),
+            ))]
+        } else {
+            self.link_utilisation
+                .iter()
+                .map(|link| {
+                    let color = if link.is_saturated(threshold) { Color::Red } else { Color::Green };
+                    Line::from(vec![
+                        Span::styled(format!("{:<15}", link.interface), Style::default().fg(Color::Cyan)),
+                        Span::styled(format!(" {} ", share_bar(link.utilisation, 20)), Style::default().fg(color)),
+                        Span::raw(format!("{:>5.1}% of {}", link.utilisation * 100.0, link.link.describe())),
+                    ])
+                })
+                .collect()
+        };
+        let links_title = format!("Link Utilisation (saturated at {threshold:.0}%)");
+        frame.render_widget(
+            Paragraph::new(links).block(Block::default().borders(Borders::ALL).title(links_title)),
+            chunks[0],
+        );
+
+        let rows: Vec<Row> = self
+            .contention
+            .shares()
+            .iter()
+            .map(|share| {
+                Row::new(vec![
+                    Cell::from(share.name.clone()),
+                    Cell::from(share.pid.map(|p| p.to_string()).unwrap_or_default()),
+                    Cell::from(format_speed(share.bytes_per_sec)),
+                    Cell::from(format!("{} {:>5.1}%", share_bar(share.share, 20), share.share * 100.0)),
+                ])
+            })
+            .collect();
+        let header = Row::new(vec!["Process", "PID", "Rate", "Share"])
+            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+        let title = if saturated.is_empty() {
+            "Bandwidth by Process".to_string()
+        } else {
+            format!("Bandwidth by Process - {} saturated", saturated.iter().map(|l| l.interface.as_str()).collect::<Vec<_>>().join(", "))
+        };
+        let table = Table::new(
+            rows,
+            [Constraint::Percentage(35), Constraint::Length(8), Constraint::Length(12), Constraint::Min(28)],
+        )
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .style(Style::default().fg(Color::White));
+        frame.render_widget(table, chunks[1]);
+
+        if !suggestions.is_empty() {
+            let lines: Vec<Line> = suggestions.into_iter().map(Line::from).collect();
+            let pane = Paragraph::new(lines)
+                .style(Style::default().fg(Color::Yellow))
+                .block(Block::default().borders(Borders::ALL).title("Throttle Suggestions"));
+            frame.render_widget(pane, chunks[2]);
+        }
+    }
+
+    /// Renders the download/upload history of the selected interface as a line chart
+    fn render_history_chart(&self, frame: &mut Frame, area: Rect) {
+        let window_secs = self.bandwidth_history.window().as_secs_f64();
+        let window_minutes = self.bandwidth_history.window().as_secs() / 60;
+
+        let Some(interface) = self.selected_history_interface() else {
+            let message = Paragraph::new("Collecting bandwidth history...")
+                .style(Style::default().fg(Color::DarkGray))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!("Bandwidth History (last {} min)", window_minutes)),
+                );
+            frame.render_widget(message, area);
+            return;
+        };
+
+        let samples = self.bandwidth_history.samples(&interface);
+        let download_points: Vec<(f64, f64)> = samples
+            .map(|s| s.iter().map(|p| (p.elapsed_secs, p.download_bps)).collect())
+            .unwrap_or_default();
+        let upload_points: Vec<(f64, f64)> = samples
+            .map(|s| s.iter().map(|p| (p.elapsed_secs, p.upload_bps)).collect())
+            .unwrap_or_default();
+
+        // Anchor the x axis to the newest sample so the chart scrolls left over time
+        let x_max = download_points.last().map(|p| p.0).unwrap_or(0.0).max(window_secs);
+        let x_min = x_max - window_secs;
+        let y_max = download_points
+            .iter()
+            .chain(upload_points.iter())
+            .map(|p| p.1)
+            .fold(0.0, f64::max)
+            .max(1024.0)
+            * 1.1;
+
+        let current = samples.and_then(|s| s.back());
+        let title = match current {
+            Some(sample) => format!(
+                "Bandwidth History: {} (last {} min) ↓ {} ↑ {}",
+                interface,
+                window_minutes,
+                format_speed(sample.download_bps),
+                format_speed(sample.upload_bps)
+            ),
+            None => format!("Bandwidth History: {} (last {} min)", interface, window_minutes),
+        };
+
+        let datasets = vec![
+            Dataset::default()
+                .name("Download")
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green))
+                .data(&download_points),
+            Dataset::default()
+                .name("Upload")
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Blue))
+                .data(&upload_points),
+        ];
+
+        let chart = Chart::new(datasets)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .x_axis(
+                Axis::default()
+                    .style(Style::default().fg(Color::DarkGray))
+                    .bounds([x_min, x_max])
+                    .labels(vec![
+                        format!("-{}m", window_minutes),
+                        format!("-{:.1}m", window_secs / 120.0),
+                        "now".to_string(),
+                    ]),
+            )
+            .y_axis(
+                Axis::default()
+                    .style(Style::default().fg(Color::DarkGray))
+                    .bounds([0.0, y_max])
+                    .labels(vec![
+                        "0".to_string(),
+                        format_speed(y_max / 2.0),
+                        format_speed(y_max),
+                    ]),
+            );
+
+        frame.render_widget(chart, area);
+    }
+
     /// Renders sparkline graphs for bandwidth trends using actual speed values
     fn render_sparklines(&self, frame: &mut Frame, area: Rect) {
         // Split the area into two columns for download and upload sparklines
@@ -500,14 +2293,25 @@ impl Dashboard {
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .split(area);
 
+        // The focused interface's trend comes from its history, as many readings as the totals keep
+        let focused = self.switcher.focused().and_then(|name| self.bandwidth_history.samples(name));
+        let (download_history, upload_history): (VecDeque<f64>, VecDeque<f64>) = match focused {
+            Some(samples) => samples
+                .iter()
+                .skip(samples.len().saturating_sub(TREND_POINTS))
+                .map(|sample| (sample.download_bps, sample.upload_bps))
+                .unzip(),
+            None => (self.download_history.clone(), self.upload_history.clone()),
+        };
+
         // Convert f64 speed values to u64 for sparkline (ratatui requirement)
         // Scale values to make them more visible in sparkline
-        let download_data: Vec<u64> = self.download_history
+        let download_data: Vec<u64> = download_history
             .iter()
             .map(|&speed| (speed / 1024.0) as u64) // Convert to KB/s for better scaling
             .collect();
             
-        let upload_data: Vec<u64> = self.upload_history
+        let upload_data: Vec<u64> = upload_history
             .iter()
             .map(|&speed| (speed / 1024.0) as u64) // Convert to KB/s for better scaling
             .collect();
@@ -517,7 +2321,7 @@ impl Dashboard {
         let max_upload = upload_data.iter().max().copied().unwrap_or(1);
 
         // Download sparkline with current value display
-        let download_title = if let Some(&current) = self.download_history.back() {
+        let download_title = if let Some(&current) = download_history.back() {
             format!("Download Trend (Current: {})", format_speed(current))
         } else {
             "Download Trend (No data)".to_string()
@@ -535,7 +2339,7 @@ impl Dashboard {
             .style(Style::default().fg(Color::Green));
 
         // Upload sparkline with current value display
-        let upload_title = if let Some(&current) = self.upload_history.back() {
+        let upload_title = if let Some(&current) = upload_history.back() {
             format!("Upload Trend (Current: {})", format_speed(current))
         } else {
             "Upload Trend (No data)".to_string()
@@ -558,13 +2362,146 @@ impl Dashboard {
 
     /// Renders the footer with keyboard shortcuts
     fn render_footer(&self, frame: &mut Frame, area: Rect) {
-        let footer = Paragraph::new("Press 'q' or ESC to quit")
+        let help = match self.active_view {
+            DashboardView::Interfaces => "Press 'q' or ESC to quit | ?: keys | p: pause | n/N, i: interface | Tab: connections view",
+            DashboardView::Connections => {
+                "Press 'q' or ESC to quit | ?: keys | p: pause | Tab: contention view | ↑/↓ PgUp/PgDn: scroll | s: sort bytes/packets"
+            }
+            DashboardView::Contention => "Press 'q' or ESC to quit | ?: keys | p: pause | n/N, i: interface | Tab: history view",
+            DashboardView::History => {
+                "Press 'q' or ESC to quit | ?: keys | p: pause | n/N, i: interface | Tab: interfaces view | ←/→: select interface"
+            }
+        };
+        let footer = Paragraph::new(help)
             .style(Style::default().fg(Color::DarkGray))
             .block(Block::default().borders(Borders::TOP));
 
         frame.render_widget(footer, area);
     }
 
+    /// Draws the help overlay or the interface list, whichever is open
+    fn render_overlays(&self, frame: &mut Frame) {
+        if self.show_help {
+            self.render_help(frame);
+        } else if let Some(row) = self.switcher.popup_row() {
+            self.render_interface_popup(frame, row);
+        }
+    }
+
+    /// Draws the interfaces most relevant first over the middle of the dashboard, with their
+    /// type, relevance score and speed, highlighting `selected` (0 being all interfaces)
+    fn render_interface_popup(&self, frame: &mut Frame, selected: usize) {
+        let entries = self.switcher.entries();
+        let focused_row = self.switcher.position().map_or(0, |(number, _)| number);
+        let marker = |row: usize| if row == focused_row { "◉" } else { " " };
+        let (total_download, total_upload) = self.total_speeds();
+        let mut cells = vec![vec![
+            format!("{} 0", marker(0)),
+            "All interfaces".to_string(),
+            String::new(),
+            String::new(),
+            format_speed(total_download + total_upload),
+            String::new(),
+        ]];
+        for (index, entry) in entries.iter().enumerate() {
+            let speed = self
+                .current_stats
+                .iter()
+                .find(|stats| stats.interface_name == entry.name)
+                .map_or(0.0, |stats| stats.download_speed_bps + stats.upload_speed_bps);
+            cells.push(vec![
+                format!("{} {}", marker(index + 1), index + 1),
+                entry.name.clone(),
+                entry.kind.to_string(),
+                entry.score.to_string(),
+                format_speed(speed),
+                entry.reason.clone(),
+            ]);
+        }
+        let rows: Vec<Row> = cells
+            .into_iter()
+            .enumerate()
+            .map(|(row, cells)| {
+                let style = if row == selected {
+                    Style::default().fg(Color::Black).bg(Color::Cyan)
+                } else {
+                    Style::default()
+                };
+                Row::new(cells).style(style)
+            })
+            .collect();
+
+        let screen = frame.area();
+        let width = 96.min(screen.width);
+        let height = (rows.len() as u16 + 3).min(screen.height);
+        let area = Rect::new(
+            screen.x + (screen.width - width) / 2,
+            screen.y + (screen.height - height) / 2,
+            width,
+            height,
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                Constraint::Length(16),
+                Constraint::Length(13),
+                Constraint::Length(5),
+                Constraint::Length(11),
+                Constraint::Min(10),
+            ],
+        )
+        .header(
+            Row::new(vec!["#", "Interface", "Type", "Score", "Traffic", "Why"])
+                .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        )
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Interfaces by relevance (↑/↓ Enter or 0-9 to focus, Esc to close)")
+                .style(Style::default().fg(Color::White)),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(table, area);
+    }
+
+    /// Draws the list of keys over the middle of the dashboard
+    fn render_help(&self, frame: &mut Frame) {
+        let mut lines = Vec::new();
+        for (view, keys) in HELP_KEYS {
+            if !lines.is_empty() {
+                lines.push(Line::from(""));
+            }
+            lines.push(Line::from(Span::styled(*view, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))));
+            for (key, action) in *keys {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("  {key:<14}"), Style::default().fg(Color::Yellow)),
+                    Span::raw(*action),
+                ]));
+            }
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!("Snapshots are written to {}", self.snapshot_dir.display()),
+            Style::default().fg(Color::DarkGray),
+        )));
+
+        let screen = frame.area();
+        let width = 64.min(screen.width);
+        let height = (lines.len() as u16 + 2).min(screen.height);
+        let area = Rect::new(
+            screen.x + (screen.width - width) / 2,
+            screen.y + (screen.height - height) / 2,
+            width,
+            height,
+        );
+        let help = Paragraph::new(lines).block(
+            Block::default().borders(Borders::ALL).title("Keys (any key to close)").style(Style::default().fg(Color::White)),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(help, area);
+    }
+
     /// Calculates overall confidence level across all interfaces
     /// Returns the lowest confidence level found, as overall reliability is limited by the weakest link
     fn calculate_overall_confidence(&self, stats: &[BandwidthStats]) -> CalculationConfidence {
@@ -607,4 +2544,40 @@ impl Dashboard {
             CalculationConfidence::None => Color::DarkGray,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Loss counters of the TCP connection behind a flow, in either direction
+fn flow_loss(tracker: &TcpStateTracker, flow: &FlowEntry) -> Option<LossStats> {
+    let source = SocketAddr::new(flow.source, flow.source_port?);
+    let destination = SocketAddr::new(flow.destination, flow.dest_port?);
+    tracker.session(source, destination).map(|session| session.loss)
+}
+
+/// Status bar text for a port scan, SYN flood or ICMP sweep detection
+#[cfg(feature = "capture")]
+fn alert_text(flag: &SecurityFlag, packet: &NetworkPacket, tags: &TagBook) -> String {
+    let host = |addr: Option<IpAddr>| addr.map(|ip| tags.describe(&ip.to_string())).unwrap_or_else(|| "?".to_string());
+    match flag {
+        SecurityFlag::PortScan(ports) => {
+            format!("Port scan {} -> {} ({ports} ports)", host(packet.source_addr), host(packet.dest_addr))
+        }
+        SecurityFlag::SynFlood(sources) => format!("SYN flood on {} ({sources} sources)", host(packet.dest_addr)),
+        SecurityFlag::IcmpSweep(hosts) => format!("ICMP sweep from {} ({hosts} hosts)", host(packet.source_addr)),
+        other => other.event_type().to_string(),
+    }
+}
+
+/// Short arrow describing traffic direction in the connection table
+fn direction_arrow(direction: PacketDirection) -> &'static str {
+    match direction {
+        PacketDirection::Inbound => "↓",
+        PacketDirection::Outbound => "↑",
+        PacketDirection::Local => "↔",
+    }
+}
+
+/// Horizontal bar of `width` cells filled to `fraction`
+fn share_bar(fraction: f64, width: usize) -> String {
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f64).round() as usize).min(width);
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
@@ -1,3 +1,25 @@
+mod bandwidth_history;
+mod connection_table;
+mod contention;
+mod grid;
+mod interface_switcher;
 mod live_dashboard;
+mod ndjson;
+#[cfg(feature = "capture")]
+mod packet_inspector;
+mod refresh_tuner;
+mod session;
+mod smoothing;
+mod snapshots;
+#[cfg(feature = "capture")]
+mod talkers;
+#[cfg(feature = "capture")]
+mod top_view;
 
-pub use live_dashboard::Dashboard;
\ No newline at end of file
+pub use grid::DashboardLayout;
+pub use live_dashboard::Dashboard;
+#[cfg(feature = "capture")]
+pub use packet_inspector::PacketInspector;
+pub use session::{Session, SessionRecorder, SessionReplay};
+#[cfg(feature = "capture")]
+pub use top_view::TopView;
@@ -0,0 +1,167 @@
+// Newline-delimited JSON records of the live dashboard's readings
+// `kw live --output ndjson` writes one record per update interval to stdout instead of
+// drawing the dashboard, for jq, telegraf or other tooling. Rates are bytes per second
+// whatever `[display] units` says, so consumers need not parse units
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::collectors::bandwidth_collector::{CalculationConfidence, InterfaceState, InterfaceType};
+use crate::collectors::link_aggregation::{counted_groups, InterfaceGroup};
+use crate::dashboard::connection_table::FlowEntry;
+use crate::models::{PacketDirection, TagBook, TransportProtocol};
+
+/// Busiest connections included in each record
+pub const TOP_CONNECTIONS: usize = 10;
+
+/// Readings of one update interval
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveRecord {
+    pub timestamp: DateTime<Local>,
+    pub interfaces: Vec<InterfaceRecord>,
+    pub totals: Totals,
+    /// Busiest flows first; absent unless packets are captured (`--packets`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connections: Option<Vec<ConnectionRecord>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceRecord {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(rename = "type")]
+    pub interface_type: InterfaceType,
+    pub state: InterfaceState,
+    pub download_bps: f64,
+    pub upload_bps: f64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub packets_sent: u64,
+    pub confidence: CalculationConfidence,
+    /// Member links, when the interface is a bond, bridge or team
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+    /// Interface carrying the traffic encrypted, when the interface is a VPN tunnel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+    /// Whether `totals` leaves the interface out because its transport is listed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub counted_in_transport: bool,
+}
+
+/// Speed of all listed interfaces; members of a listed aggregate are not counted again, and
+/// neither are tunnels whose transport is listed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Totals {
+    pub download_bps: f64,
+    pub upload_bps: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionRecord {
+    pub protocol: TransportProtocol,
+    pub direction: PacketDirection,
+    pub source: String,
+    pub destination: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+}
+
+impl LiveRecord {
+    /// Record of the interfaces as listed and, when capturing, the flows in table order
+    pub fn new(groups: &[InterfaceGroup<'_>], flows: Option<Vec<&FlowEntry>>, tags: &TagBook) -> Self {
+        let interfaces: Vec<InterfaceRecord> = groups
+            .iter()
+            .map(|group| {
+                let stats = group.stats;
+                InterfaceRecord {
+                    name: stats.interface_name.clone(),
+                    label: tags.label(&stats.interface_name).map(str::to_string),
+                    interface_type: stats.interface_type.clone(),
+                    state: stats.interface_state.clone(),
+                    download_bps: stats.download_speed_bps,
+                    upload_bps: stats.upload_speed_bps,
+                    bytes_received: stats.bytes_received,
+                    bytes_sent: stats.bytes_sent,
+                    packets_received: stats.packets_received,
+                    packets_sent: stats.packets_sent,
+                    confidence: stats.calculation_confidence.clone(),
+                    members: group.members.iter().map(|(member, _)| member.name.clone()).collect(),
+                    transport: group.tunnel.and_then(|tunnel| tunnel.transport.clone()),
+                    counted_in_transport: group.carried_by_listed(groups).is_some(),
+                }
+            })
+            .collect();
+        let totals = counted_groups(groups).fold(Totals { download_bps: 0.0, upload_bps: 0.0 }, |totals, group| {
+            Totals {
+                download_bps: totals.download_bps + group.stats.download_speed_bps,
+                upload_bps: totals.upload_bps + group.stats.upload_speed_bps,
+            }
+        });
+        let connections = flows.map(|flows| {
+            flows
+                .into_iter()
+                .take(TOP_CONNECTIONS)
+                .map(|flow| ConnectionRecord {
+                    protocol: flow.protocol,
+                    direction: flow.direction,
+                    source: FlowEntry::endpoint(&flow.source, flow.source_port, tags),
+                    destination: FlowEntry::endpoint(&flow.destination, flow.dest_port, tags),
+                    packets: flow.packets,
+                    bytes: flow.bytes,
+                    first_seen: flow.first_seen,
+                    last_seen: flow.last_seen,
+                })
+                .collect()
+        });
+
+        Self { timestamp: Local::now(), interfaces, totals, connections }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::bandwidth_collector::BandwidthStats;
+    use crate::collectors::link_aggregation::group_interfaces;
+    use crate::models::Tag;
+
+    fn stats(name: &str, download: f64, upload: f64) -> BandwidthStats {
+        BandwidthStats {
+            timestamp: chrono::Utc::now(),
+            interface_name: name.to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: 1000,
+            bytes_sent: 500,
+            packets_received: 10,
+            packets_sent: 5,
+            download_speed_bps: download,
+            upload_speed_bps: upload,
+            calculation_confidence: CalculationConfidence::High,
+            time_since_last_update: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_record_is_one_json_line() {
+        let collected = vec![stats("eth0", 1000.0, 200.0), stats("wlan0", 50.0, 25.0)];
+        let tags = TagBook::new(vec![Tag::new("eth0", "uplink", None)]);
+
+        let record = LiveRecord::new(&group_interfaces(&collected, &[], &[]), None, &tags);
+        assert_eq!(record.totals, Totals { download_bps: 1050.0, upload_bps: 225.0 });
+
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["interfaces"][0]["name"], "eth0");
+        assert_eq!(value["interfaces"][0]["label"], "uplink");
+        assert_eq!(value["interfaces"][0]["state"], "Up");
+        assert!(value["interfaces"][1].get("label").is_none());
+        assert!(value.get("connections").is_none());
+    }
+}
@@ -0,0 +1,168 @@
+// Packet inspector for `kw packets --inspect`
+// Once capture stops, lists the packets kept and shows the selected one as a hex and ASCII
+// dump, scrollable when it is longer than the pane, for a quick look without Wireshark
+
+use anyhow::Result;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use log::debug;
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    Frame, Terminal,
+};
+use std::io;
+
+use crate::models::packet_dump::{hex_dump, InspectedPacket, PRIVACY_WARNING};
+
+/// Full-screen list of captured packets over a hex dump of the selected one
+pub struct PacketInspector {
+    packets: Vec<InspectedPacket>,
+    table_state: TableState,
+    /// First dump line shown
+    scroll: u16,
+    /// Dump lines that fit in the pane at the last draw, the distance PgUp/PgDn move
+    page: u16,
+}
+
+impl PacketInspector {
+    /// Starts on the newest packet
+    pub fn new(packets: Vec<InspectedPacket>) -> Self {
+        let mut table_state = TableState::default();
+        table_state.select(packets.len().checked_sub(1));
+        Self { packets, table_state, scroll: 0, page: 1 }
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        debug!("Opening the packet inspector on {} packets", self.packets.len());
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+        let res = self.run_app(&mut terminal);
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        res
+    }
+
+    fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()>
+    where
+        B::Error: Send + Sync + 'static,
+    {
+        loop {
+            terminal.draw(|f| self.ui(f))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                KeyCode::Home | KeyCode::Char('g') => self.select(isize::MIN),
+                KeyCode::End | KeyCode::Char('G') => self.select(isize::MAX),
+                KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_dump(self.page as i32),
+                KeyCode::PageUp => self.scroll_dump(-(self.page as i32)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the selection `by` packets, staying within the list, and shows the new dump from the top
+    fn select(&mut self, by: isize) {
+        let Some(last) = self.packets.len().checked_sub(1) else {
+            return;
+        };
+        let current = self.table_state.selected().unwrap_or(0);
+        self.table_state.select(Some(current.saturating_add_signed(by).min(last)));
+        self.scroll = 0;
+    }
+
+    fn scroll_dump(&mut self, lines: i32) {
+        let lines_in_dump = self.selected().map_or(0, |packet| packet.bytes.len().div_ceil(16)) as i32;
+        let max = (lines_in_dump - self.page as i32).max(0);
+        self.scroll = (self.scroll as i32 + lines).clamp(0, max) as u16;
+    }
+
+    fn selected(&self) -> Option<&InspectedPacket> {
+        self.table_state.selected().and_then(|index| self.packets.get(index))
+    }
+
+    fn ui(&mut self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(40), Constraint::Min(6), Constraint::Length(2)])
+            .split(frame.area());
+
+        let first = self.packets.first().map(|packet| packet.timestamp);
+        let rows: Vec<Row> = self
+            .packets
+            .iter()
+            .enumerate()
+            .map(|(index, packet)| {
+                let offset = first.map_or(0.0, |first| (packet.timestamp - first).as_seconds_f64());
+                Row::new(vec![
+                    (index + 1).to_string(),
+                    format!("{offset:.6}"),
+                    packet.wire_len.to_string(),
+                    packet.summary.clone(),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [Constraint::Length(6), Constraint::Length(12), Constraint::Length(6), Constraint::Min(20)],
+        )
+        .header(
+            Row::new(vec!["#", "Time", "Length", "Packet"])
+                .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title(format!("Captured packets ({})", self.packets.len())))
+        .row_highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+        frame.render_stateful_widget(table, chunks[0], &mut self.table_state);
+
+        self.page = chunks[1].height.saturating_sub(2).max(1);
+        let (title, lines) = match self.selected() {
+            Some(packet) => {
+                let title = format!(
+                    "Packet {} at {} - {}",
+                    self.table_state.selected().unwrap_or(0) + 1,
+                    packet.timestamp.format("%H:%M:%S%.6f"),
+                    packet.coverage()
+                );
+                let lines: Vec<Line> = if packet.bytes.is_empty() {
+                    vec![Line::from(Span::styled(
+                        "Contents were not captured: a capture helper or kernel network events deliver packets already decoded",
+                        Style::default().fg(Color::DarkGray),
+                    ))]
+                } else {
+                    hex_dump(&packet.bytes).into_iter().map(Line::from).collect()
+                };
+                (title, lines)
+            }
+            None => ("No packets".to_string(), Vec::new()),
+        };
+        let dump = Paragraph::new(lines).scroll((self.scroll, 0)).block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(dump, chunks[1]);
+
+        let footer = vec![
+            Line::from(Span::styled(
+                "q/Esc: quit | ↑/↓: select packet | Home/End: first/last | PgUp/PgDn: scroll the dump",
+                Style::default().fg(Color::DarkGray),
+            )),
+            Line::from(Span::styled(format!("⚠ {PRIVACY_WARNING}"), Style::default().fg(Color::Yellow))),
+        ];
+        frame.render_widget(Paragraph::new(footer), chunks[2]);
+    }
+}
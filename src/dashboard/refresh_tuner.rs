@@ -0,0 +1,226 @@
+// Adaptive update interval for the live dashboard
+// Slow collections or a busy kw process double the interval, up to `[refresh] max_interval_secs`;
+// once both stay well below their limits for a while it is halved again, back to `--interval`
+
+use std::fmt;
+use std::time::Duration;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+use crate::config::RefreshConfig;
+
+/// Consecutive overloaded updates before the interval is lengthened, so one spike is ignored
+const BACK_OFF_AFTER: u32 = 3;
+
+/// Consecutive calm updates before the interval is shortened again
+const RECOVER_AFTER: u32 = 10;
+
+/// Share of the limits an update must stay under to count as calm; halving the interval
+/// roughly doubles both collection share and CPU use, which then stays below the limits
+const CALM_SHARE: f64 = 0.4;
+
+/// What made the dashboard slow down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pressure {
+    /// A collection took this long
+    SlowCollection(Duration),
+    /// kw used this much CPU, in percent of one core
+    Cpu(f32),
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pressure::SlowCollection(took) => write!(f, "collection took {}ms", took.as_millis()),
+            Pressure::Cpu(percent) => write!(f, "CPU {percent:.0}%"),
+        }
+    }
+}
+
+/// Update interval adjusted to how costly collecting has been
+#[derive(Debug)]
+pub struct RefreshTuner {
+    /// Interval asked for, which the tuner never goes below
+    base: Duration,
+    max: Duration,
+    current: Duration,
+    /// Share of the interval a collection may take
+    slow_share: f64,
+    cpu_percent: f32,
+    overloaded_streak: u32,
+    calm_streak: u32,
+    /// Why the interval was last lengthened, while it is above `base`
+    pressure: Option<Pressure>,
+}
+
+impl RefreshTuner {
+    pub fn new(base: Duration, config: &RefreshConfig) -> Self {
+        Self {
+            base,
+            max: Duration::from_secs(config.max_interval_secs).max(base),
+            current: base,
+            slow_share: config.slow_collection_percent / 100.0,
+            cpu_percent: config.cpu_percent as f32,
+            overloaded_streak: 0,
+            calm_streak: 0,
+            pressure: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.current
+    }
+
+    /// Why the interval is longer than asked for, if it is
+    pub fn pressure(&self) -> Option<Pressure> {
+        self.pressure
+    }
+
+    /// Notes how long the last collection took and, when known, kw's CPU use since the
+    /// update before; returns the new interval when it changes
+    pub fn observe(&mut self, collection: Duration, cpu_percent: Option<f32>) -> Option<Duration> {
+        let share = collection.as_secs_f64() / self.current.as_secs_f64();
+        let cpu = cpu_percent.unwrap_or(0.0);
+        let pressure = if share > self.slow_share {
+            Some(Pressure::SlowCollection(collection))
+        } else if cpu > self.cpu_percent {
+            Some(Pressure::Cpu(cpu))
+        } else {
+            None
+        };
+
+        match pressure {
+            Some(pressure) => {
+                self.calm_streak = 0;
+                self.overloaded_streak += 1;
+                if self.overloaded_streak < BACK_OFF_AFTER || self.current >= self.max {
+                    return None;
+                }
+                self.overloaded_streak = 0;
+                self.pressure = Some(pressure);
+                self.current = (self.current * 2).min(self.max);
+                Some(self.current)
+            }
+            None => {
+                self.overloaded_streak = 0;
+                let calm = share < self.slow_share * CALM_SHARE
+                    && f64::from(cpu) < f64::from(self.cpu_percent) * CALM_SHARE;
+                if !calm || self.current <= self.base {
+                    self.calm_streak = 0;
+                    return None;
+                }
+                self.calm_streak += 1;
+                if self.calm_streak < RECOVER_AFTER {
+                    return None;
+                }
+                self.calm_streak = 0;
+                self.current = (self.current / 2).max(self.base);
+                if self.current == self.base {
+                    self.pressure = None;
+                }
+                Some(self.current)
+            }
+        }
+    }
+}
+
+/// CPU use of the kw process between samples
+pub struct CpuMeter {
+    system: System,
+    pid: Option<Pid>,
+    /// Whether a first sample was taken; CPU use is only known from the second on
+    primed: bool,
+}
+
+impl CpuMeter {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+            pid: sysinfo::get_current_pid().ok(),
+            primed: false,
+        }
+    }
+
+    /// Percent of one core used since the last sample; `None` on the first or when unavailable
+    pub fn sample(&mut self) -> Option<f32> {
+        let pid = self.pid?;
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            false,
+            ProcessRefreshKind::nothing().with_cpu(),
+        );
+        let usage = self.system.process(pid).map(|process| process.cpu_usage());
+        std::mem::replace(&mut self.primed, true).then_some(usage).flatten()
+    }
+}
+
+impl fmt::Debug for CpuMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CpuMeter").field("pid", &self.pid).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner() -> RefreshTuner {
+        RefreshTuner::new(Duration::from_secs(1), &RefreshConfig::default())
+    }
+
+    #[test]
+    fn test_backs_off_after_sustained_slow_collections() {
+        let mut tuner = tuner();
+        let slow = Duration::from_millis(400);
+        assert_eq!(tuner.observe(slow, None), None);
+        assert_eq!(tuner.observe(slow, None), None);
+        assert_eq!(tuner.observe(slow, None), Some(Duration::from_secs(2)));
+        assert_eq!(tuner.pressure(), Some(Pressure::SlowCollection(slow)));
+
+        // 400ms is a fifth of two seconds, under the 25% limit but not calm either
+        for _ in 0..20 {
+            assert_eq!(tuner.observe(slow, None), None);
+        }
+        assert_eq!(tuner.interval(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_busy_cpu_backs_off_up_to_the_maximum() {
+        let mut tuner = tuner();
+        let fast = Duration::from_millis(5);
+        for _ in 0..30 {
+            tuner.observe(fast, Some(90.0));
+        }
+        assert_eq!(tuner.interval(), Duration::from_secs(10));
+        assert_eq!(tuner.pressure(), Some(Pressure::Cpu(90.0)));
+        assert_eq!(tuner.pressure().unwrap().to_string(), "CPU 90%");
+    }
+
+    #[test]
+    fn test_recovers_to_the_base_interval_when_calm() {
+        let mut tuner = tuner();
+        for _ in 0..6 {
+            tuner.observe(Duration::from_millis(5), Some(80.0));
+        }
+        assert_eq!(tuner.interval(), Duration::from_secs(4));
+
+        // A single busy update restarts the count
+        for _ in 0..9 {
+            assert_eq!(tuner.observe(Duration::from_millis(5), Some(2.0)), None);
+        }
+        tuner.observe(Duration::from_millis(5), Some(60.0));
+        for _ in 0..9 {
+            assert_eq!(tuner.observe(Duration::from_millis(5), Some(2.0)), None);
+        }
+        assert_eq!(tuner.observe(Duration::from_millis(5), Some(2.0)), Some(Duration::from_secs(2)));
+        for _ in 0..10 {
+            tuner.observe(Duration::from_millis(5), Some(2.0));
+        }
+        assert_eq!(tuner.interval(), Duration::from_secs(1));
+        assert_eq!(tuner.pressure(), None);
+
+        // Never faster than asked for
+        for _ in 0..20 {
+            assert_eq!(tuner.observe(Duration::from_millis(5), None), None);
+        }
+    }
+}
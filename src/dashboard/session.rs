@@ -0,0 +1,276 @@
+// Recorded dashboard sessions
+// `kw live --record FILE` writes the readings behind every update to FILE, one JSON object per
+// line after a header line, and `kw replay FILE` feeds them back through the dashboard at the
+// pace they were taken, or faster with --speed. Interface changes and alerts noted in the
+// status bar are kept with the update they came in; captured flows are not recorded
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::collectors::bandwidth_collector::BandwidthStats;
+
+/// Version written in the header; files from a newer kw are refused
+pub const SESSION_VERSION: u32 = 1;
+
+/// First line of a session file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionHeader {
+    /// Format version, doubling as the marker of a session file
+    pub kw_session: u32,
+    pub started_at: DateTime<Local>,
+    /// Update interval of the recorded dashboard, in seconds
+    pub interval_secs: u64,
+}
+
+/// Readings of one dashboard update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSample {
+    pub taken_at: DateTime<Local>,
+    pub stats: Vec<BandwidthStats>,
+    /// Interfaces appearing, disappearing or changing since the previous sample
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interface_changes: Vec<String>,
+    /// Port scans, SYN floods and other detections since the previous sample
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<String>,
+}
+
+/// Appends samples to a session file as they are taken
+/// Each sample is flushed, so a dashboard that is killed leaves a file that replays up to then
+pub struct SessionRecorder {
+    writer: BufWriter<File>,
+    /// When the last sample was written, or the file created
+    last_written: Instant,
+    samples: usize,
+}
+
+impl SessionRecorder {
+    /// Creates `path`, replacing any file there, and writes the header
+    pub fn create(path: &Path, interval_secs: u64) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create session file {}", path.display()))?;
+        let mut recorder = Self { writer: BufWriter::new(file), last_written: Instant::now(), samples: 0 };
+        let header = SessionHeader { kw_session: SESSION_VERSION, started_at: Local::now(), interval_secs };
+        recorder.write_line(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, sample: &SessionSample) -> Result<()> {
+        self.write_line(sample)?;
+        self.last_written = Instant::now();
+        self.samples += 1;
+        Ok(())
+    }
+
+    pub fn last_written(&self) -> Instant {
+        self.last_written
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A session file read back
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub header: SessionHeader,
+    /// Oldest first
+    pub samples: Vec<SessionSample>,
+}
+
+impl Session {
+    /// Reads a session file; a truncated last line, as a killed recording leaves, is skipped
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open session file {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let header_line = lines.next().transpose()?.unwrap_or_default();
+        let header: SessionHeader = serde_json::from_str(&header_line)
+            .with_context(|| format!("{} is not a kw session recording", path.display()))?;
+        if header.kw_session > SESSION_VERSION {
+            anyhow::bail!(
+                "{} was recorded by a newer kw (session format {}, this kw reads up to {})",
+                path.display(),
+                header.kw_session,
+                SESSION_VERSION
+            );
+        }
+
+        let lines: Vec<String> = lines.collect::<std::io::Result<_>>()?;
+        let mut samples = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<SessionSample>(line) {
+                Ok(sample) => samples.push(sample),
+                Err(_) if index + 1 == lines.len() => log::warn!("Skipping the truncated last sample of {}", path.display()),
+                Err(e) => return Err(e).with_context(|| format!("Bad sample on line {} of {}", index + 2, path.display())),
+            }
+        }
+        if samples.is_empty() {
+            anyhow::bail!("{} has no recorded samples", path.display());
+        }
+        Ok(Self { header, samples })
+    }
+}
+
+/// Hands out a session's samples as they fall due, `speed` times faster than recorded
+#[derive(Debug)]
+pub struct SessionReplay {
+    header: SessionHeader,
+    pending: VecDeque<SessionSample>,
+    total: usize,
+    speed: f64,
+    first_taken_at: DateTime<Local>,
+    started: Instant,
+    /// Time the last sample handed out was taken
+    shown_at: Option<DateTime<Local>>,
+}
+
+impl SessionReplay {
+    pub fn new(session: Session, speed: f64) -> Result<Self> {
+        if !(speed.is_finite() && speed > 0.0) {
+            anyhow::bail!("Invalid replay speed: {speed} (expected a number above 0, e.g. 1 or 4)");
+        }
+        let first_taken_at = session.samples.first().map_or(session.header.started_at, |sample| sample.taken_at);
+        Ok(Self {
+            header: session.header,
+            total: session.samples.len(),
+            pending: session.samples.into(),
+            speed,
+            first_taken_at,
+            started: Instant::now(),
+            shown_at: None,
+        })
+    }
+
+    pub fn header(&self) -> &SessionHeader {
+        &self.header
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Whether the next sample is due, `elapsed` after the replay started
+    pub fn is_due(&self, elapsed: Duration) -> bool {
+        self.pending.front().is_some_and(|sample| {
+            let offset = (sample.taken_at - self.first_taken_at).to_std().unwrap_or_default();
+            elapsed.as_secs_f64() * self.speed >= offset.as_secs_f64()
+        })
+    }
+
+    pub fn is_due_now(&self) -> bool {
+        self.is_due(self.started.elapsed())
+    }
+
+    /// The next sample if it is due now
+    pub fn next_due(&mut self) -> Option<SessionSample> {
+        if !self.is_due_now() {
+            return None;
+        }
+        let sample = self.pending.pop_front()?;
+        self.shown_at = Some(sample.taken_at);
+        Some(sample)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Samples handed out so far and how many there are
+    pub fn progress(&self) -> (usize, usize) {
+        (self.total - self.pending.len(), self.total)
+    }
+
+    pub fn shown_at(&self) -> Option<DateTime<Local>> {
+        self.shown_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::bandwidth_collector::{CalculationConfidence, InterfaceState, InterfaceType};
+
+    fn sample(taken_at: DateTime<Local>, download_bps: f64) -> SessionSample {
+        SessionSample {
+            taken_at,
+            stats: vec![BandwidthStats {
+                timestamp: taken_at.to_utc(),
+                interface_name: "eth0".to_string(),
+                interface_type: InterfaceType::Ethernet,
+                interface_state: InterfaceState::Up,
+                bytes_received: 1000,
+                bytes_sent: 500,
+                packets_received: 10,
+                packets_sent: 5,
+                download_speed_bps: download_bps,
+                upload_speed_bps: 0.0,
+                calculation_confidence: CalculationConfidence::High,
+                time_since_last_update: 1.0,
+            }],
+            interface_changes: Vec::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_recorded_session_loads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.kw");
+        let start = Local::now();
+        let mut recorder = SessionRecorder::create(&path, 2).unwrap();
+        recorder.record(&sample(start, 100.0)).unwrap();
+        let mut second = sample(start + chrono::Duration::seconds(2), 200.0);
+        second.interface_changes.push("eth0 down".to_string());
+        recorder.record(&second).unwrap();
+        assert_eq!(recorder.samples(), 2);
+        drop(recorder);
+
+        // A recording cut off mid-line still loads up to its last whole sample
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"taken_at\":\"20").unwrap();
+        let session = Session::load(&path).unwrap();
+        assert_eq!(session.header.interval_secs, 2);
+        assert_eq!(session.samples.len(), 2);
+        assert_eq!(session.samples[1].stats[0].download_speed_bps, 200.0);
+        assert_eq!(session.samples[1].interface_changes, vec!["eth0 down"]);
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(Session::load(&path).is_err());
+        std::fs::write(&path, "{\"kw_session\":1,\"started_at\":\"2026-10-16T10:00:00+00:00\",\"interval_secs\":1}\n").unwrap();
+        assert!(Session::load(&path).unwrap_err().to_string().contains("no recorded samples"));
+    }
+
+    #[test]
+    fn test_samples_fall_due_at_the_replay_speed() {
+        let start = Local::now();
+        let header = SessionHeader { kw_session: SESSION_VERSION, started_at: start, interval_secs: 2 };
+        let samples = (0..3).map(|i| sample(start + chrono::Duration::seconds(i * 2), 0.0)).collect();
+        let mut replay = SessionReplay::new(Session { header: header.clone(), samples }, 4.0).unwrap();
+
+        assert!(replay.is_due(Duration::ZERO));
+        assert!(replay.next_due().is_some());
+        assert_eq!(replay.progress(), (1, 3));
+        // The second sample was taken 2s in, so at four times the speed it is due after half a second
+        assert!(!replay.is_due(Duration::from_millis(400)));
+        assert!(replay.is_due(Duration::from_millis(500)));
+        assert_eq!(replay.shown_at(), Some(start));
+        assert!(!replay.is_finished());
+
+        let session = Session { header, samples: vec![sample(start, 0.0)] };
+        assert!(SessionReplay::new(session.clone(), 0.0).is_err());
+        assert!(SessionReplay::new(session, f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+// Smoothing of the speeds the live dashboard shows
+// An exponentially weighted moving average over `[smoothing] window_secs`, so figures do not
+// jump with every reading. Each reading counts by how far its speed can be trusted: a reading
+// after a counter reset or from a very short interval moves the average less, and one with no
+// confidence at all not at all. Only what is drawn is smoothed; charts, saturation and
+// anomaly checks, and everything stored keep the raw readings
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::collectors::bandwidth_collector::{BandwidthStats, CalculationConfidence};
+
+/// How much a reading of each confidence counts towards the average, as a share of a trusted one
+fn confidence_weight(confidence: &CalculationConfidence) -> f64 {
+    match confidence {
+        CalculationConfidence::High => 1.0,
+        CalculationConfidence::Medium => 0.5,
+        CalculationConfidence::Low => 0.2,
+        CalculationConfidence::None => 0.0,
+    }
+}
+
+/// Smoothed speeds of one interface and the reading they last took in
+#[derive(Debug, Clone, Copy)]
+struct Smoothed {
+    download_bps: f64,
+    upload_bps: f64,
+    reading: DateTime<Utc>,
+}
+
+/// Per-interface moving averages of download and upload speed
+#[derive(Debug)]
+pub struct SpeedSmoother {
+    window: Duration,
+    interfaces: HashMap<String, Smoothed>,
+}
+
+impl SpeedSmoother {
+    /// Averages over roughly `window`; a reading that old still counts for about a third
+    pub fn new(window: Duration) -> Self {
+        Self { window, interfaces: HashMap::new() }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Takes in the readings of one update and returns them with smoothed speeds
+    /// The first trusted reading of an interface is taken as it is; readings repeated from the
+    /// sampling cache are not counted again
+    pub fn smooth(&mut self, stats: &[BandwidthStats]) -> Vec<BandwidthStats> {
+        self.interfaces.retain(|name, _| stats.iter().any(|stat| &stat.interface_name == name));
+        stats
+            .iter()
+            .map(|stat| {
+                let weight = confidence_weight(&stat.calculation_confidence);
+                let smoothed = match self.interfaces.get(&stat.interface_name) {
+                    Some(previous) if previous.reading == stat.timestamp => *previous,
+                    Some(previous) => {
+                        let elapsed = stat.time_since_last_update.max(0.0);
+                        let alpha = weight * (1.0 - (-elapsed / self.window.as_secs_f64()).exp());
+                        Smoothed {
+                            download_bps: previous.download_bps + alpha * (stat.download_speed_bps - previous.download_bps),
+                            upload_bps: previous.upload_bps + alpha * (stat.upload_speed_bps - previous.upload_bps),
+                            reading: stat.timestamp,
+                        }
+                    }
+                    None if weight > 0.0 => Smoothed {
+                        download_bps: stat.download_speed_bps,
+                        upload_bps: stat.upload_speed_bps,
+                        reading: stat.timestamp,
+                    },
+                    None => return stat.clone(),
+                };
+                self.interfaces.insert(stat.interface_name.clone(), smoothed);
+                BandwidthStats {
+                    download_speed_bps: smoothed.download_bps,
+                    upload_speed_bps: smoothed.upload_bps,
+                    ..stat.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::bandwidth_collector::{InterfaceState, InterfaceType};
+
+    fn reading(seconds: i64, download_bps: f64, confidence: CalculationConfidence) -> BandwidthStats {
+        BandwidthStats {
+            timestamp: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            interface_name: "eth0".to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: 0,
+            bytes_sent: 0,
+            packets_received: 0,
+            packets_sent: 0,
+            download_speed_bps: download_bps,
+            upload_speed_bps: 0.0,
+            calculation_confidence: confidence,
+            time_since_last_update: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_readings_count_by_confidence() {
+        let mut smoother = SpeedSmoother::new(Duration::from_secs(5));
+        let first = smoother.smooth(&[reading(0, 1000.0, CalculationConfidence::High)]);
+        assert_eq!(first[0].download_speed_bps, 1000.0);
+
+        // One second into a five second window moves the average about 18% of the way
+        let alpha = 1.0 - (-0.2f64).exp();
+        let trusted = smoother.smooth(&[reading(1, 2000.0, CalculationConfidence::High)]);
+        let expected = 1000.0 + alpha * 1000.0;
+        assert!((trusted[0].download_speed_bps - expected).abs() < 1e-9);
+
+        // A doubtful reading moves it a fifth as far, one without confidence not at all
+        let doubtful = smoother.smooth(&[reading(2, 10_000.0, CalculationConfidence::Low)]);
+        let expected = expected + 0.2 * alpha * (10_000.0 - expected);
+        assert!((doubtful[0].download_speed_bps - expected).abs() < 1e-9);
+        let unknown = smoother.smooth(&[reading(3, 0.0, CalculationConfidence::None)]);
+        assert!((unknown[0].download_speed_bps - expected).abs() < 1e-9);
+
+        // A cached reading repeated by the sampling plan is not counted twice
+        let repeated = smoother.smooth(&[reading(3, 0.0, CalculationConfidence::High)]);
+        assert!((repeated[0].download_speed_bps - expected).abs() < 1e-9);
+        assert_eq!(repeated[0].calculation_confidence, CalculationConfidence::High);
+    }
+
+    #[test]
+    fn test_interfaces_start_over_when_they_return() {
+        let mut smoother = SpeedSmoother::new(Duration::from_secs(5));
+        // Without a trusted reading there is nothing to average yet
+        let untrusted = smoother.smooth(&[reading(0, 500.0, CalculationConfidence::None)]);
+        assert_eq!(untrusted[0].download_speed_bps, 500.0);
+
+        smoother.smooth(&[reading(1, 1000.0, CalculationConfidence::High)]);
+        smoother.smooth(&[]);
+        let back = smoother.smooth(&[reading(5, 3000.0, CalculationConfidence::High)]);
+        assert_eq!(back[0].download_speed_bps, 3000.0);
+    }
+}
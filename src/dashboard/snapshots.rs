@@ -0,0 +1,234 @@
+// Short history of the live dashboard's readings, for pausing, stepping back and exporting
+// Each update is kept as a snapshot; a paused dashboard draws the one being looked at while
+// collection carries on underneath, and `x` writes it out as JSON plus, with the `graphs`
+// feature, a PNG of the speeds
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+#[cfg(feature = "graphs")]
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::collectors::bandwidth_collector::BandwidthStats;
+use crate::collectors::link_aggregation::LinkAggregate;
+use crate::dashboard::contention::LinkUtilisation;
+use crate::dashboard::ndjson::LiveRecord;
+#[cfg(feature = "graphs")]
+use crate::graphs::bandwidth_graphs::{BandwidthDataPoint, BandwidthGraph};
+#[cfg(feature = "graphs")]
+use crate::graphs::{GraphConfig, ImageFormat};
+
+/// Updates kept for stepping back; two minutes at the default interval
+pub const SNAPSHOT_HISTORY: usize = 120;
+
+/// Readings the dashboard showed after one update
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Local>,
+    pub stats: Vec<BandwidthStats>,
+    pub link_aggregates: Vec<LinkAggregate>,
+    pub link_utilisation: Vec<LinkUtilisation>,
+    /// Total speeds behind the sparklines, oldest first
+    pub download_history: VecDeque<f64>,
+    pub upload_history: VecDeque<f64>,
+}
+
+#[cfg(feature = "graphs")]
+impl Snapshot {
+    /// Total speeds and byte counts at the time of the snapshot, for the exported chart
+    fn data_point(&self) -> BandwidthDataPoint {
+        BandwidthDataPoint {
+            timestamp: self.taken_at.with_timezone(&Utc),
+            download_speed: self.download_history.back().copied().unwrap_or_default(),
+            upload_speed: self.upload_history.back().copied().unwrap_or_default(),
+            total_rx: self.stats.iter().map(|s| s.bytes_received).sum(),
+            total_tx: self.stats.iter().map(|s| s.bytes_sent).sum(),
+        }
+    }
+}
+
+/// Ring buffer of snapshots with the position a paused dashboard is showing
+#[derive(Debug)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+    /// Index of the snapshot shown while paused; `None` while live
+    cursor: Option<usize>,
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            cursor: None,
+        }
+    }
+
+    /// Adds the latest readings; a paused dashboard keeps showing the same snapshot
+    /// until it falls out of the buffer, and then the oldest one left
+    pub fn record(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+            self.cursor = self.cursor.map(|index| index.saturating_sub(1));
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Freezes on the latest snapshot, or goes back to live readings
+    pub fn toggle_pause(&mut self) {
+        self.cursor = match self.cursor {
+            Some(_) => None,
+            None => self.snapshots.len().checked_sub(1),
+        };
+    }
+
+    /// Shows the snapshot before the one shown, pausing first if live
+    pub fn step_back(&mut self) {
+        let Some(latest) = self.snapshots.len().checked_sub(1) else {
+            return;
+        };
+        self.cursor = Some(self.cursor.unwrap_or(latest).saturating_sub(1));
+    }
+
+    /// Shows the snapshot after the one shown, stopping at the latest; stays paused
+    pub fn step_forward(&mut self) {
+        if let Some(index) = self.cursor {
+            self.cursor = Some((index + 1).min(self.snapshots.len() - 1));
+        }
+    }
+
+    /// Snapshot shown while paused
+    pub fn shown(&self) -> Option<&Snapshot> {
+        self.cursor.and_then(|index| self.snapshots.get(index))
+    }
+
+    pub fn shown_mut(&mut self) -> Option<&mut Snapshot> {
+        self.cursor.and_then(|index| self.snapshots.get_mut(index))
+    }
+
+    /// One-based position of the shown snapshot and the number kept, e.g. (118, 120)
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.cursor.map(|index| (index + 1, self.snapshots.len()))
+    }
+
+    /// Total speeds from the oldest snapshot up to the shown one, or the latest while live
+    #[cfg(feature = "graphs")]
+    pub fn data_points(&self) -> Vec<BandwidthDataPoint> {
+        let end = self.cursor.map_or(self.snapshots.len(), |index| index + 1);
+        self.snapshots.range(..end).map(Snapshot::data_point).collect()
+    }
+}
+
+/// Writes `record` to `kw-snapshot-<time>.json` in `dir`, and the speeds of `history` leading
+/// up to it to a PNG of the same name when there are at least two; returns the files written
+pub fn export_snapshot(dir: &Path, record: &LiveRecord, history: &SnapshotHistory) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let stem = dir.join(format!("kw-snapshot-{}", record.timestamp.format("%Y%m%d-%H%M%S")));
+
+    let json_path = stem.with_extension("json");
+    let json = serde_json::to_string_pretty(record)?;
+    fs::write(&json_path, json).with_context(|| format!("Failed to write {}", json_path.display()))?;
+    let mut written = vec![json_path];
+    written.extend(export_chart(&stem, history)?);
+    Ok(written)
+}
+
+/// Draws the speeds leading up to a snapshot to `<stem>.png`, when there are at least two
+#[cfg(feature = "graphs")]
+fn export_chart(stem: &Path, history: &SnapshotHistory) -> Result<Option<PathBuf>> {
+    let history = history.data_points();
+    if history.len() < 2 {
+        return Ok(None);
+    }
+    let png_path = stem.with_extension("png");
+    BandwidthGraph::new(GraphConfig::default())
+        .with_data(history)
+        .render_speed_chart(&png_path, ImageFormat::Png)
+        .with_context(|| format!("Failed to draw {}", png_path.display()))?;
+    Ok(Some(png_path))
+}
+
+/// Without the `graphs` feature a snapshot is the JSON alone
+#[cfg(not(feature = "graphs"))]
+fn export_chart(_stem: &Path, _history: &SnapshotHistory) -> Result<Option<PathBuf>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "graphs")]
+    use crate::models::TagBook;
+
+    fn snapshot(seconds: i64, download: f64) -> Snapshot {
+        Snapshot {
+            taken_at: Local::now() + chrono::Duration::seconds(seconds),
+            download_history: VecDeque::from([download]),
+            upload_history: VecDeque::from([download / 10.0]),
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    fn test_pausing_and_stepping_through_history() {
+        let mut history = SnapshotHistory::new(3);
+        history.step_back();
+        assert!(!history.is_paused(), "nothing to show yet");
+
+        for (seconds, download) in [(0, 100.0), (1, 200.0), (2, 300.0)] {
+            history.record(snapshot(seconds, download));
+        }
+        history.toggle_pause();
+        assert_eq!(history.position(), Some((3, 3)));
+
+        history.step_back();
+        history.step_back();
+        history.step_back();
+        assert_eq!(history.position(), Some((1, 3)));
+        #[cfg(feature = "graphs")]
+        assert_eq!(history.data_points().len(), 1);
+
+        history.step_forward();
+        assert_eq!(history.shown().unwrap().download_history.back(), Some(&200.0));
+
+        // New readings push the oldest out without moving what is shown
+        history.record(snapshot(3, 400.0));
+        assert_eq!(history.position(), Some((1, 3)));
+        assert_eq!(history.shown().unwrap().download_history.back(), Some(&200.0));
+
+        history.toggle_pause();
+        assert!(history.shown().is_none());
+        #[cfg(feature = "graphs")]
+        assert_eq!(history.data_points().len(), 3);
+
+        history.step_back();
+        assert_eq!(history.position(), Some((2, 3)));
+    }
+
+    #[test]
+    #[cfg(feature = "graphs")]
+    fn test_export_writes_json_and_chart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SnapshotHistory::new(10);
+        for seconds in 0..5 {
+            history.record(snapshot(seconds, 1000.0 * seconds as f64));
+        }
+        let record = LiveRecord::new(&[], None, &TagBook::default());
+
+        let written = export_snapshot(dir.path(), &record, &history).unwrap();
+        assert_eq!(written.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&written[0]).unwrap()).unwrap();
+        assert!(json["totals"]["download_bps"].is_number());
+        assert!(fs::metadata(&written[1]).unwrap().len() > 0);
+
+        let written = export_snapshot(dir.path(), &record, &SnapshotHistory::new(10)).unwrap();
+        assert_eq!(written.len(), 1, "no chart without a history");
+    }
+}
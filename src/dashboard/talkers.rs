@@ -0,0 +1,273 @@
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+
+use crate::models::{NetworkPacket, PacketDirection, TransportProtocol};
+
+/// Column used to rank remote hosts in `kw top`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TalkerSortKey {
+    /// Received plus sent
+    Total,
+    /// Traffic from the host
+    Received,
+    /// Traffic to the host
+    Sent,
+}
+
+impl TalkerSortKey {
+    /// Human-readable name shown in the table title
+    pub fn label(&self) -> &'static str {
+        match self {
+            TalkerSortKey::Total => "total",
+            TalkerSortKey::Received => "received",
+            TalkerSortKey::Sent => "sent",
+        }
+    }
+
+    /// The next key in the `s` key's cycle
+    pub fn next(&self) -> Self {
+        match self {
+            TalkerSortKey::Total => TalkerSortKey::Received,
+            TalkerSortKey::Received => TalkerSortKey::Sent,
+            TalkerSortKey::Sent => TalkerSortKey::Total,
+        }
+    }
+}
+
+/// One remote host's traffic within the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Talker {
+    pub host: IpAddr,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+    pub packets: u64,
+    /// Distinct protocol/port pairs seen within the window
+    pub flows: usize,
+    pub last_seen: DateTime<Local>,
+}
+
+impl Talker {
+    pub fn total_bytes(&self) -> u64 {
+        self.received_bytes + self.sent_bytes
+    }
+}
+
+/// Bytes and packets seen in one wall-clock second
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    second: i64,
+    received: u64,
+    sent: u64,
+    packets: u64,
+}
+
+#[derive(Debug, Default)]
+struct HostTraffic {
+    buckets: VecDeque<Bucket>,
+    /// Last second each flow was seen in, keyed by protocol, local port and remote port
+    flows: HashMap<(TransportProtocol, Option<u16>, Option<u16>), i64>,
+    last_seen: Option<DateTime<Local>>,
+}
+
+/// Per-remote-host traffic over a sliding window of whole seconds
+///
+/// Packets are counted against the host on the far side: the source of inbound
+/// packets and the destination of outbound ones. Local traffic has no remote
+/// host and is left out. Rates are the window's bytes divided by its length, so
+/// a host that stops talking fades out of the ranking over the window.
+#[derive(Debug)]
+pub struct TalkerTable {
+    window_secs: u64,
+    hosts: HashMap<IpAddr, HostTraffic>,
+    sort_key: TalkerSortKey,
+}
+
+impl TalkerTable {
+    pub fn new(window_secs: u64) -> Self {
+        Self {
+            window_secs: window_secs.max(1),
+            hosts: HashMap::new(),
+            sort_key: TalkerSortKey::Total,
+        }
+    }
+
+    pub fn window_secs(&self) -> u64 {
+        self.window_secs
+    }
+
+    /// Counts a captured packet against its remote host
+    pub fn record_packet(&mut self, packet: &NetworkPacket) {
+        let (host, received, local_port, remote_port) = match packet.direction {
+            PacketDirection::Inbound => (packet.source_addr, true, packet.dest_port, packet.source_port),
+            PacketDirection::Outbound => (packet.dest_addr, false, packet.source_port, packet.dest_port),
+            PacketDirection::Local => return,
+        };
+        let Some(host) = host else {
+            return;
+        };
+
+        let second = packet.timestamp.timestamp();
+        let traffic = self.hosts.entry(host).or_default();
+        // Packets from several capture threads can arrive slightly out of order; a late one
+        // is counted in the newest second rather than reopening an older one
+        if traffic.buckets.back().is_none_or(|bucket| bucket.second < second) {
+            traffic.buckets.push_back(Bucket { second, ..Bucket::default() });
+        }
+        let bucket = traffic.buckets.back_mut().expect("bucket was just ensured");
+        if received {
+            bucket.received += packet.size_bytes;
+        } else {
+            bucket.sent += packet.size_bytes;
+        }
+        bucket.packets += 1;
+
+        let flow_second = traffic.flows.entry((packet.transport_protocol, local_port, remote_port)).or_insert(second);
+        *flow_second = (*flow_second).max(second);
+        traffic.last_seen = traffic.last_seen.max(Some(packet.timestamp));
+    }
+
+    /// Drops seconds that have left the window ending at `now`, and hosts left with none
+    pub fn expire(&mut self, now: DateTime<Local>) {
+        let cutoff = now.timestamp() - self.window_secs as i64;
+        self.hosts.retain(|_, traffic| {
+            while traffic.buckets.front().is_some_and(|bucket| bucket.second <= cutoff) {
+                traffic.buckets.pop_front();
+            }
+            traffic.flows.retain(|_, second| *second > cutoff);
+            !traffic.buckets.is_empty()
+        });
+    }
+
+    /// Hosts within the window, ranked by the current sort key, largest first
+    pub fn ranked(&self) -> Vec<Talker> {
+        let mut talkers: Vec<Talker> = self
+            .hosts
+            .iter()
+            .filter_map(|(host, traffic)| {
+                let mut talker = Talker {
+                    host: *host,
+                    received_bytes: 0,
+                    sent_bytes: 0,
+                    packets: 0,
+                    flows: traffic.flows.len(),
+                    last_seen: traffic.last_seen?,
+                };
+                for bucket in &traffic.buckets {
+                    talker.received_bytes += bucket.received;
+                    talker.sent_bytes += bucket.sent;
+                    talker.packets += bucket.packets;
+                }
+                Some(talker)
+            })
+            .collect();
+
+        let key = |talker: &Talker| match self.sort_key {
+            TalkerSortKey::Total => talker.total_bytes(),
+            TalkerSortKey::Received => talker.received_bytes,
+            TalkerSortKey::Sent => talker.sent_bytes,
+        };
+        talkers.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.host.cmp(&b.host)));
+        talkers
+    }
+
+    /// Bytes per second over the window
+    pub fn rate(&self, bytes: u64) -> f64 {
+        bytes as f64 / self.window_secs as f64
+    }
+
+    /// Cycles total → received → sent
+    pub fn cycle_sort(&mut self) {
+        self.sort_key = self.sort_key.next();
+    }
+
+    pub fn sort_key(&self) -> TalkerSortKey {
+        self.sort_key
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PacketProtocol;
+    use std::net::Ipv4Addr;
+
+    const LOCAL: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+
+    fn remote(last_octet: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, last_octet))
+    }
+
+    fn make_packet(host: IpAddr, direction: PacketDirection, size: u64, seconds_ago: i64) -> NetworkPacket {
+        let mut packet = NetworkPacket::new("eth0".to_string(), size, PacketProtocol::IPv4, direction);
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.timestamp = Local::now() - chrono::Duration::seconds(seconds_ago);
+        let (source, dest) = match direction {
+            PacketDirection::Inbound => (host, LOCAL),
+            _ => (LOCAL, host),
+        };
+        packet.source_addr = Some(source);
+        packet.dest_addr = Some(dest);
+        let (local_port, remote_port) = (Some(50000), Some(443));
+        (packet.source_port, packet.dest_port) = match direction {
+            PacketDirection::Inbound => (remote_port, local_port),
+            _ => (local_port, remote_port),
+        };
+        packet
+    }
+
+    #[test]
+    fn test_traffic_is_counted_against_the_remote_host() {
+        let mut table = TalkerTable::new(10);
+        table.record_packet(&make_packet(remote(1), PacketDirection::Inbound, 1500, 0));
+        table.record_packet(&make_packet(remote(1), PacketDirection::Outbound, 100, 0));
+        table.record_packet(&make_packet(remote(2), PacketDirection::Outbound, 400, 0));
+        table.record_packet(&make_packet(remote(3), PacketDirection::Local, 9000, 0));
+
+        let ranked = table.ranked();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].host, remote(1));
+        assert_eq!((ranked[0].received_bytes, ranked[0].sent_bytes, ranked[0].packets), (1500, 100, 2));
+        assert_eq!(ranked[0].flows, 1);
+        assert_eq!(table.rate(ranked[0].total_bytes()), 160.0);
+    }
+
+    #[test]
+    fn test_sort_cycle() {
+        let mut table = TalkerTable::new(10);
+        table.record_packet(&make_packet(remote(1), PacketDirection::Inbound, 1000, 0));
+        table.record_packet(&make_packet(remote(2), PacketDirection::Outbound, 600, 0));
+        table.record_packet(&make_packet(remote(2), PacketDirection::Inbound, 500, 0));
+
+        assert_eq!(table.ranked()[0].host, remote(2));
+        table.cycle_sort();
+        assert_eq!(table.sort_key(), TalkerSortKey::Received);
+        assert_eq!(table.ranked()[0].host, remote(1));
+        table.cycle_sort();
+        assert_eq!(table.ranked()[0].host, remote(2));
+        table.cycle_sort();
+        assert_eq!(table.sort_key(), TalkerSortKey::Total);
+    }
+
+    #[test]
+    fn test_window_expiry() {
+        let mut table = TalkerTable::new(5);
+        table.record_packet(&make_packet(remote(1), PacketDirection::Inbound, 1000, 20));
+        table.record_packet(&make_packet(remote(2), PacketDirection::Inbound, 700, 20));
+        table.record_packet(&make_packet(remote(2), PacketDirection::Inbound, 300, 1));
+        table.expire(Local::now());
+
+        assert_eq!(table.len(), 1);
+        let ranked = table.ranked();
+        assert_eq!(ranked[0].host, remote(2));
+        assert_eq!(ranked[0].received_bytes, 300);
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::Result;
+use chrono::Local;
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use log::{debug, error, info};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    Frame, Terminal,
+};
+use std::{
+    io,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::collectors::{
+    bandwidth_collector::format_speed, capture_filter::CaptureFilter, interface_selection::InterfaceSelection,
+    interface_selection::ANY_INTERFACE, PacketCollector,
+};
+use crate::dashboard::talkers::{Talker, TalkerTable};
+use crate::enrichment::{BackgroundLookups, CachingResolver};
+use crate::models::Redactor;
+use crate::shutdown;
+
+/// Maximum number of packets drained from the capture channel per loop iteration
+const MAX_PACKETS_PER_TICK: usize = 5000;
+
+/// Hosts ranked per refresh; only these are shown and looked up
+const MAX_TALKERS: usize = 100;
+
+/// Full-screen ranking of remote hosts by bandwidth, in the manner of iftop
+///
+/// Captured packets are folded into a `TalkerTable` continuously, but the ranking
+/// on screen is only recomputed once per refresh interval so it can be read.
+/// Host names are looked up in the background and shown as they arrive.
+pub struct TopView {
+    packet_collector: PacketCollector,
+    /// The `--interface` selection as given, for the header
+    interface_label: String,
+    talkers: TalkerTable,
+    refresh_interval: Duration,
+    /// Ranking as of the last refresh
+    ranking: Vec<Talker>,
+    table_state: TableState,
+    host_names: Option<BackgroundLookups>,
+    show_host_names: bool,
+    /// Redacts the hosts shown, in privacy mode
+    redactor: Redactor,
+}
+
+impl TopView {
+    /// Capture on the selected interfaces (all of them without a selection), ranking hosts by
+    /// their traffic over the last `window_secs` and redrawing the ranking every `refresh_secs`
+    pub fn new(interface: Option<InterfaceSelection>, window_secs: u64, refresh_secs: u64) -> Result<Self> {
+        let interface_label = interface.as_ref().map_or_else(|| ANY_INTERFACE.to_string(), ToString::to_string);
+        Ok(Self {
+            packet_collector: PacketCollector::new(interface_label.clone())?,
+            interface_label,
+            talkers: TalkerTable::new(window_secs),
+            refresh_interval: Duration::from_secs(refresh_secs.max(1)),
+            ranking: Vec::new(),
+            table_state: TableState::default(),
+            host_names: None,
+            show_host_names: false,
+            redactor: Redactor::default(),
+        })
+    }
+
+    /// Looks up host names with `resolver`; `show` sets whether they are shown from the start
+    /// (the `r` key toggles them either way)
+    pub fn with_resolver(mut self, resolver: CachingResolver, show: bool) -> Self {
+        self.host_names = Some(BackgroundLookups::new(Arc::new(resolver)));
+        self.show_host_names = show;
+        self
+    }
+
+    /// Shows hosts and their names as `redactor` rewrites them
+    pub fn with_privacy(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Only packets matching `filter` are counted
+    pub fn with_filter(mut self, filter: CaptureFilter) -> Self {
+        self.packet_collector = self.packet_collector.with_filter(filter);
+        self
+    }
+
+    /// Capture through the privileged helper listening on `socket_path`
+    #[cfg(unix)]
+    pub fn with_capture_helper(mut self, socket_path: std::path::PathBuf) -> Self {
+        self.packet_collector = self.packet_collector.with_capture_helper(socket_path);
+        self
+    }
+
+    /// Runs until `q`, Escape or Ctrl+C is pressed, or a shutdown is requested
+    pub async fn run(&mut self) -> Result<()> {
+        info!(
+            "Starting top talkers on {} over {}s windows",
+            self.interface_label,
+            self.talkers.window_secs()
+        );
+        // Start capture before entering raw mode so privilege errors are reported cleanly
+        self.packet_collector.start().await?;
+
+        debug!("Setting up terminal for full-screen UI");
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+
+        let res = self.run_app(&mut terminal).await;
+
+        debug!("Cleaning up terminal state");
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
+        terminal.show_cursor()?;
+        self.packet_collector.stop().await?;
+
+        if let Err(err) = res {
+            error!("Top talkers error: {err:?}");
+            eprintln!("Error: {err:?}");
+        }
+        Ok(())
+    }
+
+    async fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()>
+    where
+        B::Error: Send + Sync + 'static,
+    {
+        let mut last_refresh = Instant::now();
+
+        loop {
+            // SIGTERM, as Ctrl+C arrives as a key in raw mode
+            if shutdown::is_requested() {
+                return Ok(());
+            }
+            terminal.draw(|f| self.ui(f))?;
+
+            if event::poll(Duration::from_millis(100))?
+                && let Event::Key(key) = event::read()?
+            {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('s') => {
+                        self.talkers.cycle_sort();
+                        self.refresh();
+                    }
+                    KeyCode::Char('r') => self.toggle_host_names(),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                    _ => {}
+                }
+            }
+
+            self.drain_captured_packets().await;
+            if let Some(host_names) = &mut self.host_names {
+                host_names.collect();
+            }
+
+            if last_refresh.elapsed() >= self.refresh_interval {
+                self.refresh();
+                last_refresh = Instant::now();
+            }
+        }
+    }
+
+    async fn drain_captured_packets(&mut self) {
+        for _ in 0..MAX_PACKETS_PER_TICK {
+            match self.packet_collector.try_receive_packet().await {
+                Some(packet) => self.talkers.record_packet(&packet),
+                None => break,
+            }
+        }
+    }
+
+    /// Re-ranks the hosts and starts lookups for newly shown ones
+    fn refresh(&mut self) {
+        self.talkers.expire(Local::now());
+        let mut ranking = self.talkers.ranked();
+        ranking.truncate(MAX_TALKERS);
+        self.ranking = ranking;
+
+        let selected = self.table_state.selected().unwrap_or(0);
+        self.table_state
+            .select((!self.ranking.is_empty()).then(|| selected.min(self.ranking.len() - 1)));
+        self.request_lookups();
+    }
+
+    fn toggle_host_names(&mut self) {
+        if self.host_names.is_none() {
+            return;
+        }
+        self.show_host_names = !self.show_host_names;
+        self.request_lookups();
+    }
+
+    /// Starts lookups for the ranked hosts; the resolver's cache keeps repeats cheap
+    fn request_lookups(&mut self) {
+        let Some(host_names) = &mut self.host_names else {
+            return;
+        };
+        if !self.show_host_names {
+            return;
+        }
+        for talker in &self.ranking {
+            host_names.request(talker.host);
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.ranking.is_empty() {
+            return;
+        }
+        let selected = self.table_state.selected().unwrap_or(0).saturating_add_signed(delta);
+        self.table_state.select(Some(selected.min(self.ranking.len() - 1)));
+    }
+
+    fn host_label(&self, host: &IpAddr) -> String {
+        match self.host_names.as_ref().and_then(|names| names.name(host)) {
+            Some(name) if self.show_host_names => self.redactor.host_name(name),
+            _ => self.redactor.address(*host),
+        }
+    }
+
+    fn ui(&mut self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(frame.area());
+
+        let (received, sent) = self
+            .ranking
+            .iter()
+            .fold((0, 0), |(received, sent), talker| (received + talker.received_bytes, sent + talker.sent_bytes));
+        let header = Paragraph::new(Line::from(vec![
+            Span::styled(
+                format!("{} hosts on {}", self.talkers.len(), self.interface_label),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                "   ↓ {}   ↑ {}   averaged over {}s",
+                format_speed(self.talkers.rate(received)),
+                format_speed(self.talkers.rate(sent)),
+                self.talkers.window_secs()
+            )),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Top Talkers"));
+        frame.render_widget(header, chunks[0]);
+
+        let busiest = self.ranking.iter().map(Talker::total_bytes).max().unwrap_or(0).max(1);
+        let rows: Vec<Row> = self
+            .ranking
+            .iter()
+            .map(|talker| {
+                Row::new(vec![
+                    Cell::from(self.host_label(&talker.host)),
+                    Cell::from(format_speed(self.talkers.rate(talker.received_bytes))),
+                    Cell::from(format_speed(self.talkers.rate(talker.sent_bytes))),
+                    Cell::from(format_speed(self.talkers.rate(talker.total_bytes()))),
+                    Cell::from(talker.packets.to_string()),
+                    Cell::from(talker.flows.to_string()),
+                    Cell::from(share_bar(talker.total_bytes(), busiest)),
+                ])
+            })
+            .collect();
+        let header = Row::new(vec!["Host", "Received", "Sent", "Total", "Packets", "Flows", ""])
+            .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD));
+        let title = format!(
+            "Remote hosts (sorted by {}, names {})",
+            self.talkers.sort_key().label(),
+            match (&self.host_names, self.show_host_names) {
+                (None, _) => "unavailable",
+                (Some(_), true) => "on",
+                (Some(_), false) => "off",
+            }
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(35),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(8),
+                Constraint::Length(6),
+                Constraint::Min(10),
+            ],
+        )
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title.clone()))
+        .row_highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD))
+        .highlight_symbol("▶ ");
+        if self.talkers.is_empty() {
+            let waiting = Paragraph::new("Waiting for captured traffic...")
+                .style(Style::default().fg(Color::DarkGray))
+                .block(Block::default().borders(Borders::ALL).title(title));
+            frame.render_widget(waiting, chunks[1]);
+        } else {
+            frame.render_stateful_widget(table, chunks[1], &mut self.table_state);
+        }
+
+        let help = Paragraph::new("q: quit   s: sort by total/received/sent   r: host names on/off   ↑/↓: select")
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[2]);
+    }
+}
+
+/// Bar proportional to a host's share of the busiest host's traffic
+fn share_bar(bytes: u64, busiest: u64) -> String {
+    const WIDTH: u64 = 20;
+    "█".repeat((bytes * WIDTH).div_ceil(busiest).min(WIDTH) as usize)
+}
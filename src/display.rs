@@ -0,0 +1,240 @@
+// Unit and number formatting shared by CLI output and graphs
+// Rates follow the `[display] units` preference (bytes or bits per second) while data
+// volumes are always shown in bytes; numbers use the thousands separator and decimal mark
+// of the configured or environment locale
+
+use crate::config::{DisplayConfig, RateUnits};
+
+/// Environment variables consulted for the locale, in order of precedence
+const LOCALE_ENV: [&str; 3] = ["LC_ALL", "LC_NUMERIC", "LANG"];
+
+/// Thousands separator and decimal mark of a locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberLocale {
+    decimal: char,
+    grouping: Option<char>,
+}
+
+impl Default for NumberLocale {
+    fn default() -> Self {
+        Self::ENGLISH
+    }
+}
+
+impl NumberLocale {
+    /// 1,234.5
+    pub const ENGLISH: Self = Self { decimal: '.', grouping: Some(',') };
+
+    /// Separators for a locale tag such as `de-DE`, `fr_FR.UTF-8` or `C`
+    /// Unknown languages get English separators; `C` and `POSIX` do not group digits
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default();
+        let mut parts = tag.split(['_', '-']);
+        let language = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts.next().unwrap_or_default().to_uppercase();
+
+        let (decimal, grouping) = match (language.as_str(), region.as_str()) {
+            ("c" | "posix", _) => ('.', None),
+            ("de" | "it" | "fr" | "rm", "CH" | "LI") => ('.', Some('\'')),
+            ("es", "MX" | "US") => ('.', Some(',')),
+            (
+                "de" | "nl" | "it" | "es" | "pt" | "id" | "da" | "tr" | "el" | "ro" | "hr" | "sl" | "sr" | "vi",
+                _,
+            ) => (',', Some('.')),
+            (
+                "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "uk" | "hu" | "bg" | "lt"
+                | "lv" | "et" | "be" | "kk",
+                _,
+            ) => (',', Some('\u{a0}')),
+            _ => return Self::ENGLISH,
+        };
+        Self { decimal, grouping }
+    }
+
+    /// Separators of the first locale set in LC_ALL, LC_NUMERIC or LANG, else English
+    pub fn from_environment() -> Self {
+        LOCALE_ENV
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map(|tag| Self::from_tag(&tag))
+            .unwrap_or_default()
+    }
+
+    /// `value` rounded to `decimals` places with this locale's separators
+    pub fn format(&self, value: f64, decimals: usize) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let text = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = match text.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (text.as_str(), None),
+        };
+
+        let mut out = String::with_capacity(text.len() + whole.len() / 3 + 1);
+        // A value that rounds to zero is shown without a sign
+        if value.is_sign_negative() && text.bytes().any(|b| (b'1'..=b'9').contains(&b)) {
+            out.push('-');
+        }
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0
+                && (whole.len() - index) % 3 == 0
+                && let Some(separator) = self.grouping
+            {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+}
+
+/// Unit a set of values is shown in, e.g. the y axis of a chart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    /// Multiplier from bytes (or bytes per second) to this unit
+    factor: f64,
+    label: &'static str,
+}
+
+impl Scale {
+    /// Converts a byte or bytes-per-second value into this unit
+    pub fn scale(&self, value: f64) -> f64 {
+        value * self.factor
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+}
+
+const BYTE_RATES: [&str; 4] = ["B/s", "KB/s", "MB/s", "GB/s"];
+const BIT_RATES: [&str; 4] = ["bit/s", "kbit/s", "Mbit/s", "Gbit/s"];
+const VOLUMES: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+/// Largest unit of `labels`, stepping by `step`, in which `value` is still at least 1
+fn scale_for(value: f64, step: f64, labels: &'static [&'static str]) -> (usize, Scale) {
+    let mut index = 0;
+    let mut divisor = 1.0;
+    while index + 1 < labels.len() && value >= divisor * step {
+        divisor *= step;
+        index += 1;
+    }
+    (index, Scale { factor: 1.0 / divisor, label: labels[index] })
+}
+
+/// Unit preference and locale applied to rates, byte counts and plain numbers
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Units {
+    pub rates: RateUnits,
+    pub locale: NumberLocale,
+}
+
+impl Units {
+    /// Units from the `[display]` section; `locale = "auto"` follows the environment
+    pub fn from_config(config: &DisplayConfig) -> Self {
+        let locale = match config.locale.trim() {
+            "" | "auto" => NumberLocale::from_environment(),
+            tag => NumberLocale::from_tag(tag),
+        };
+        Self { rates: config.units, locale }
+    }
+
+    /// Unit for rates up to `max_bytes_per_sec`: binary multiples of bytes, or decimal multiples of bits
+    pub fn rate_scale(&self, max_bytes_per_sec: f64) -> Scale {
+        match self.rates {
+            RateUnits::Bytes => scale_for(max_bytes_per_sec, 1024.0, &BYTE_RATES).1,
+            RateUnits::Bits => {
+                let scale = scale_for(max_bytes_per_sec * 8.0, 1000.0, &BIT_RATES).1;
+                Scale { factor: scale.factor * 8.0, ..scale }
+            }
+        }
+    }
+
+    /// Unit for byte counts up to `max_bytes`
+    pub fn volume_scale(&self, max_bytes: f64) -> Scale {
+        scale_for(max_bytes, 1024.0, &VOLUMES).1
+    }
+
+    /// A rate in its own unit, e.g. "1.50 MB/s" or "12.6 Mbit/s"
+    pub fn rate(&self, bytes_per_sec: f64) -> String {
+        let scale = self.rate_scale(bytes_per_sec);
+        let decimals = match self.rates {
+            RateUnits::Bytes => 2,
+            RateUnits::Bits => 1,
+        };
+        format!("{} {}", self.locale.format(scale.scale(bytes_per_sec), decimals), scale.label())
+    }
+
+    /// A byte count in its own unit, e.g. "512 B" or "1.50 GB"
+    pub fn bytes(&self, bytes: f64) -> String {
+        let (index, scale) = scale_for(bytes, 1024.0, &VOLUMES);
+        let decimals = if index == 0 { 0 } else { 2 };
+        format!("{} {}", self.locale.format(scale.scale(bytes), decimals), scale.label())
+    }
+
+    /// A plain number with the locale's separators
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        self.locale.format(value, decimals)
+    }
+
+    /// An axis tick label, with fewer decimals the larger the value
+    pub fn axis(&self, value: f64) -> String {
+        let decimals = if value.fract() == 0.0 || value.abs() >= 100.0 {
+            0
+        } else if value.abs() >= 1.0 {
+            1
+        } else {
+            2
+        };
+        self.locale.format(value, decimals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_separators() {
+        assert_eq!(NumberLocale::ENGLISH.format(1234567.891, 2), "1,234,567.89");
+        assert_eq!(NumberLocale::from_tag("de_DE.UTF-8").format(1234567.891, 2), "1.234.567,89");
+        assert_eq!(NumberLocale::from_tag("fr-FR").format(1234.5, 1), "1\u{a0}234,5");
+        assert_eq!(NumberLocale::from_tag("de-CH").format(1234.5, 1), "1'234.5");
+        assert_eq!(NumberLocale::from_tag("C").format(1234.5, 1), "1234.5");
+        assert_eq!(NumberLocale::from_tag("ja_JP").format(999.0, 0), "999");
+        assert_eq!(NumberLocale::ENGLISH.format(-1234.0, 0), "-1,234");
+        assert_eq!(NumberLocale::ENGLISH.format(-0.001, 1), "0.0");
+    }
+
+    #[test]
+    fn test_rates_follow_unit_preference() {
+        let bytes = Units::default();
+        assert_eq!(bytes.rate(512.0), "512.00 B/s");
+        assert_eq!(bytes.rate(1_572_864.0), "1.50 MB/s");
+        assert_eq!(bytes.bytes(512.0), "512 B");
+        assert_eq!(bytes.bytes(1_610_612_736.0), "1.50 GB");
+
+        let bits = Units { rates: RateUnits::Bits, locale: NumberLocale::from_tag("de") };
+        assert_eq!(bits.rate(12_500_000.0), "100,0 Mbit/s");
+        assert_eq!(bits.rate(100.0), "800,0 bit/s");
+        assert_eq!(bits.rate_scale(125.0).label(), "kbit/s");
+        assert_eq!(bits.rate_scale(250_000_000.0).label(), "Gbit/s");
+        assert_eq!(bits.rate_scale(1_250_000.0).scale(1_250_000.0), 10.0);
+        // Volumes stay in bytes whatever the rate preference
+        assert_eq!(bits.bytes(1536.0), "1,50 KB");
+    }
+
+    #[test]
+    fn test_axis_labels_shrink_decimals_with_size() {
+        let units = Units { rates: RateUnits::Bytes, locale: NumberLocale::from_tag("de") };
+        assert_eq!(units.axis(2500.0), "2.500");
+        assert_eq!(units.axis(12.5), "12,5");
+        assert_eq!(units.axis(0.25), "0,25");
+    }
+}
@@ -0,0 +1,113 @@
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::resolver::{CachingResolver, Resolver};
+
+/// Answers kept before they are dropped and looked up again, through the resolver's cache
+const MAX_NAMES: usize = 4096;
+
+/// Reverse lookups run in spawned tasks, for views that keep redrawing while they are in flight
+///
+/// `request` starts a lookup and returns at once; `collect` takes in the answers that
+/// have arrived since, and `name` serves them. An address whose lookup failed is shown
+/// without a name until the answers are next dropped.
+pub struct BackgroundLookups {
+    resolver: Arc<CachingResolver>,
+    names: HashMap<IpAddr, Option<String>>,
+    /// Addresses with a lookup in flight
+    pending: HashSet<IpAddr>,
+    answer_tx: mpsc::UnboundedSender<(IpAddr, Option<String>)>,
+    answer_rx: mpsc::UnboundedReceiver<(IpAddr, Option<String>)>,
+}
+
+impl BackgroundLookups {
+    pub fn new(resolver: Arc<CachingResolver>) -> Self {
+        let (answer_tx, answer_rx) = mpsc::unbounded_channel();
+        Self {
+            resolver,
+            names: HashMap::new(),
+            pending: HashSet::new(),
+            answer_tx,
+            answer_rx,
+        }
+    }
+
+    /// Starts a lookup of `addr` unless it has been answered or is already in flight
+    pub fn request(&mut self, addr: IpAddr) {
+        if self.names.contains_key(&addr) || !self.pending.insert(addr) {
+            return;
+        }
+        let resolver = self.resolver.clone();
+        let answer_tx = self.answer_tx.clone();
+        tokio::spawn(async move {
+            let name = resolver.reverse_lookup(addr).await.unwrap_or_else(|e| {
+                debug!("{e:#}");
+                None
+            });
+            let _ = answer_tx.send((addr, name));
+        });
+    }
+
+    /// Takes in the answers of lookups that finished since the last call
+    pub fn collect(&mut self) {
+        while let Ok((addr, name)) = self.answer_rx.try_recv() {
+            if self.names.len() >= MAX_NAMES {
+                self.names.clear();
+            }
+            self.pending.remove(&addr);
+            self.names.insert(addr, name);
+        }
+    }
+
+    /// Host name of `addr`, once its lookup has finished and found one
+    pub fn name(&self, addr: &IpAddr) -> Option<&str> {
+        self.names.get(addr).and_then(Option::as_deref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    struct LoopbackResolver;
+
+    #[async_trait]
+    impl Resolver for LoopbackResolver {
+        async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+            Ok(addr.is_loopback().then(|| "localhost".to_string()))
+        }
+
+        fn describe(&self) -> String {
+            "test".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_answers_arrive_after_collect() {
+        let resolver = CachingResolver::new(Box::new(LoopbackResolver), Duration::from_secs(1), Duration::from_secs(60), 16);
+        let mut lookups = BackgroundLookups::new(Arc::new(resolver));
+        let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.1".parse().unwrap();
+
+        lookups.request(loopback);
+        lookups.request(other);
+        assert_eq!(lookups.name(&loopback), None);
+
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            lookups.collect();
+            if lookups.pending.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(lookups.name(&loopback), Some("localhost"));
+        assert_eq!(lookups.name(&other), None);
+        assert!(lookups.names.contains_key(&other));
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::resolver::Resolver;
+use super::wire;
+
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Sends lookups to a DNS-over-HTTPS endpoint (RFC 8484, POST)
+pub struct DohResolver {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl DohResolver {
+    pub fn new(url: String, timeout: Duration) -> Result<Self> {
+        if !url.starts_with("https://") {
+            bail!("DNS-over-HTTPS endpoint must be an https:// URL: {url}");
+        }
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to create DNS-over-HTTPS client")?;
+        Ok(Self { client, url })
+    }
+}
+
+#[async_trait]
+impl Resolver for DohResolver {
+    async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+        // RFC 8484 asks for ID 0 so responses stay cacheable by HTTP caches
+        let response = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, DNS_MESSAGE)
+            .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+            .body(wire::build_ptr_query(0, addr))
+            .send()
+            .await
+            .with_context(|| format!("DNS-over-HTTPS request to {} failed", self.url))?;
+
+        if !response.status().is_success() {
+            bail!("DNS-over-HTTPS endpoint returned {}", response.status());
+        }
+        let body = response.bytes().await?;
+        wire::parse_ptr_response(0, &body)
+    }
+
+    fn describe(&self) -> String {
+        format!("DNS-over-HTTPS {}", self.url)
+    }
+}
@@ -0,0 +1,104 @@
+// GeoIP enrichment: country and autonomous system of remote addresses, read from the
+// MaxMind DB files named in [geoip]. Both files are optional and loaded into memory once;
+// lookups are local, so unlike host names they never leave the machine
+
+use anyhow::{Context, Result};
+use log::debug;
+use maxminddb::{geoip2, Reader};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::config::GeoIpConfig;
+
+/// Where an address is, as far as the databases know
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    /// ISO 3166 code, e.g. "NZ"
+    pub country: Option<String>,
+    /// English country name
+    pub country_name: Option<String>,
+    /// Autonomous system number
+    pub asn: Option<u32>,
+    /// Organization the autonomous system is registered to
+    pub as_organization: Option<String>,
+}
+
+/// The country and ASN databases of the [geoip] section
+pub struct GeoIpDatabases {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIpDatabases {
+    /// Opens the configured databases; `None` when neither is configured
+    pub fn open(config: &GeoIpConfig) -> Result<Option<Self>> {
+        if config.country_database.is_none() && config.asn_database.is_none() {
+            return Ok(None);
+        }
+        let open = |path: &Option<std::path::PathBuf>| path.as_deref().map(open_database).transpose();
+        Ok(Some(Self {
+            country: open(&config.country_database)?,
+            asn: open(&config.asn_database)?,
+        }))
+    }
+
+    /// Whether countries can be looked up
+    pub fn has_countries(&self) -> bool {
+        self.country.is_some()
+    }
+
+    /// Whether autonomous systems can be looked up
+    pub fn has_asns(&self) -> bool {
+        self.asn.is_some()
+    }
+
+    /// Country and autonomous system of `ip`; fields the databases lack are left empty
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        if let Some(reader) = &self.country {
+            match reader.lookup(ip).and_then(|result| result.decode::<geoip2::Country>()) {
+                Ok(Some(record)) => {
+                    info.country = record.country.iso_code.map(str::to_string);
+                    info.country_name = record.country.names.english.map(str::to_string);
+                }
+                Ok(None) => {}
+                Err(e) => debug!("Country lookup of {ip} failed: {e}"),
+            }
+        }
+        if let Some(reader) = &self.asn {
+            match reader.lookup(ip).and_then(|result| result.decode::<geoip2::Asn>()) {
+                Ok(Some(record)) => {
+                    info.asn = record.autonomous_system_number;
+                    info.as_organization = record.autonomous_system_organization.map(str::to_string);
+                }
+                Ok(None) => {}
+                Err(e) => debug!("ASN lookup of {ip} failed: {e}"),
+            }
+        }
+        info
+    }
+}
+
+fn open_database(path: &Path) -> Result<Reader<Vec<u8>>> {
+    Reader::open_readfile(path).with_context(|| format!("Failed to open GeoIP database {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_without_databases() {
+        assert!(GeoIpDatabases::open(&GeoIpConfig::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_open_reports_unreadable_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("GeoLite2-ASN.mmdb");
+        std::fs::write(&path, b"not a database").unwrap();
+        let config = GeoIpConfig { asn_database: Some(path), ..GeoIpConfig::default() };
+        let error = GeoIpDatabases::open(&config).err().unwrap();
+        assert!(error.to_string().contains("GeoLite2-ASN.mmdb"));
+    }
+}
@@ -0,0 +1,112 @@
+// Host name enrichment: reverse DNS for addresses shown in reports
+// Lookups only go to the backend chosen in the [dns] configuration section,
+// so privacy-sensitive setups can keep them off unexpected resolvers.
+// Countries and autonomous systems come from local GeoIP databases instead
+
+pub mod background;
+pub mod doh;
+pub mod geoip;
+pub mod resolver;
+pub mod server;
+pub mod system;
+pub mod wire;
+
+use anyhow::{Context, Result};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{DnsBackend, DnsConfig};
+
+pub use background::BackgroundLookups;
+pub use doh::DohResolver;
+pub use geoip::{GeoInfo, GeoIpDatabases};
+pub use resolver::{CachingResolver, Resolver};
+pub use server::ServerResolver;
+pub use system::SystemResolver;
+
+/// Builds the configured backend wrapped in the lookup cache
+pub fn build_resolver(config: &DnsConfig) -> Result<CachingResolver> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let backend: Box<dyn Resolver> = match config.backend {
+        DnsBackend::System => Box::new(SystemResolver),
+        DnsBackend::Doh => Box::new(DohResolver::new(config.doh_url.clone(), timeout)?),
+        DnsBackend::Server => {
+            let server = config
+                .server
+                .as_deref()
+                .context("dns.server must be set when dns.backend = \"server\"")?;
+            Box::new(ServerResolver::new(ServerResolver::parse_server(server)?))
+        }
+    };
+
+    Ok(CachingResolver::new(
+        backend,
+        timeout,
+        Duration::from_secs(config.cache_ttl_secs),
+        config.cache_size,
+    ))
+}
+
+/// Looks up the given addresses concurrently, keeping only those that have a host name
+/// Entries that are not IP addresses are skipped
+pub async fn resolve_host_names<'a>(
+    resolver: &Arc<CachingResolver>,
+    addresses: impl Iterator<Item = &'a str>,
+) -> HashMap<String, String> {
+    let mut lookups = tokio::task::JoinSet::new();
+    let mut seen = HashSet::new();
+    for address in addresses {
+        let Ok(ip) = address.parse::<IpAddr>() else { continue };
+        if !seen.insert(address) {
+            continue;
+        }
+        let resolver = resolver.clone();
+        let address = address.to_string();
+        lookups.spawn(async move { (address, resolver.reverse_lookup(ip).await) });
+    }
+
+    let mut host_names = HashMap::new();
+    while let Some(Ok((address, result))) = lookups.join_next().await {
+        match result {
+            Ok(Some(host)) => {
+                host_names.insert(address, host);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+    host_names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_backend_requires_address() {
+        let config = DnsConfig {
+            backend: DnsBackend::Server,
+            ..DnsConfig::default()
+        };
+        assert!(build_resolver(&config).is_err());
+
+        let config = DnsConfig {
+            server: Some("192.0.2.53".to_string()),
+            ..config
+        };
+        assert_eq!(build_resolver(&config).unwrap().describe(), "DNS server 192.0.2.53:53");
+    }
+
+    #[test]
+    fn test_doh_backend_requires_https() {
+        let config = DnsConfig {
+            backend: DnsBackend::Doh,
+            doh_url: "http://dns.example/dns-query".to_string(),
+            ..DnsConfig::default()
+        };
+        assert!(build_resolver(&config).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::debug;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A reverse DNS backend
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Host name for `addr`, `None` when the address has no PTR record
+    async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>>;
+
+    /// Where lookups are sent, shown so users can see which resolver sees their traffic
+    fn describe(&self) -> String;
+}
+
+/// Wraps a backend with a per-lookup timeout and an LRU cache whose answers expire after a TTL
+///
+/// Answers, including "no name", are cached; failures and timeouts are not, so a
+/// flaky resolver is retried on the next lookup. When the cache is full, expired
+/// answers go first, then the one looked up least recently.
+pub struct CachingResolver {
+    inner: Box<dyn Resolver>,
+    timeout: Duration,
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<IpAddr, CacheEntry>>,
+}
+
+struct CacheEntry {
+    name: Option<String>,
+    stored: Instant,
+    used: Instant,
+}
+
+impl CachingResolver {
+    pub fn new(inner: Box<dyn Resolver>, timeout: Duration, ttl: Duration, capacity: usize) -> Self {
+        Self {
+            inner,
+            timeout,
+            ttl,
+            capacity: capacity.max(1),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, addr: IpAddr) -> Option<Option<String>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&addr).filter(|entry| entry.stored.elapsed() < self.ttl)?;
+        entry.used = Instant::now();
+        Some(entry.name.clone())
+    }
+
+    fn store(&self, addr: IpAddr, name: Option<String>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&addr) {
+            entries.retain(|_, entry| entry.stored.elapsed() < self.ttl);
+            if entries.len() >= self.capacity
+                && let Some(least_used) = entries.iter().min_by_key(|(_, entry)| entry.used).map(|(a, _)| *a)
+            {
+                entries.remove(&least_used);
+            }
+        }
+        let now = Instant::now();
+        entries.insert(addr, CacheEntry { name, stored: now, used: now });
+    }
+}
+
+#[async_trait]
+impl Resolver for CachingResolver {
+    async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+        if let Some(name) = self.cached(addr) {
+            return Ok(name);
+        }
+
+        let name = tokio::time::timeout(self.timeout, self.inner.reverse_lookup(addr))
+            .await
+            .map_err(|_| anyhow!("Reverse lookup of {addr} timed out after {:?}", self.timeout))??;
+        debug!("Resolved {addr} -> {name:?} via {}", self.inner.describe());
+
+        self.store(addr, name.clone());
+        Ok(name)
+    }
+
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CountingResolver {
+        calls: Arc<AtomicUsize>,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Resolver for CountingResolver {
+        async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            Ok(addr.is_loopback().then(|| "localhost".to_string()))
+        }
+
+        fn describe(&self) -> String {
+            "test".to_string()
+        }
+    }
+
+    fn resolver(delay: Duration, capacity: usize) -> (CachingResolver, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let inner = CountingResolver { calls: calls.clone(), delay };
+        let resolver = CachingResolver::new(Box::new(inner), Duration::from_millis(200), Duration::from_secs(60), capacity);
+        (resolver, calls)
+    }
+
+    #[tokio::test]
+    async fn test_answers_are_cached() {
+        let (resolver, calls) = resolver(Duration::ZERO, 16);
+        let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.1".parse().unwrap();
+
+        assert_eq!(resolver.reverse_lookup(loopback).await.unwrap().as_deref(), Some("localhost"));
+        assert_eq!(resolver.reverse_lookup(loopback).await.unwrap().as_deref(), Some("localhost"));
+        assert_eq!(resolver.reverse_lookup(other).await.unwrap(), None);
+        assert_eq!(resolver.reverse_lookup(other).await.unwrap(), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_lookup_times_out() {
+        let (resolver, _) = resolver(Duration::from_secs(5), 16);
+        assert!(resolver.reverse_lookup("127.0.0.1".parse().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_bounded() {
+        let (resolver, calls) = resolver(Duration::ZERO, 1);
+        let first: IpAddr = "192.0.2.1".parse().unwrap();
+        let second: IpAddr = "192.0.2.2".parse().unwrap();
+
+        resolver.reverse_lookup(first).await.unwrap();
+        resolver.reverse_lookup(second).await.unwrap();
+        resolver.reverse_lookup(first).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_is_evicted() {
+        let (resolver, calls) = resolver(Duration::ZERO, 2);
+        let [first, second, third]: [IpAddr; 3] =
+            ["192.0.2.1", "192.0.2.2", "192.0.2.3"].map(|addr| addr.parse().unwrap());
+
+        resolver.reverse_lookup(first).await.unwrap();
+        resolver.reverse_lookup(second).await.unwrap();
+        // Looking `first` up again makes `second` the least recently used
+        resolver.reverse_lookup(first).await.unwrap();
+        resolver.reverse_lookup(third).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        resolver.reverse_lookup(first).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        resolver.reverse_lookup(second).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+use super::resolver::Resolver;
+use super::wire;
+
+/// Queries one user-specified DNS server directly over UDP
+pub struct ServerResolver {
+    server: SocketAddr,
+}
+
+impl ServerResolver {
+    pub fn new(server: SocketAddr) -> Self {
+        Self { server }
+    }
+
+    /// Parses `ip` or `ip:port` (`[v6]:port` for IPv6), defaulting to port 53
+    pub fn parse_server(value: &str) -> Result<SocketAddr> {
+        value
+            .parse::<SocketAddr>()
+            .or_else(|_| value.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .with_context(|| format!("Invalid DNS server address: {value}"))
+    }
+}
+
+#[async_trait]
+impl Resolver for ServerResolver {
+    async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+        let local: SocketAddr = if self.server.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local).await.context("Failed to open DNS socket")?;
+        socket.connect(self.server).await.context("Failed to reach DNS server")?;
+
+        let id = rand_id();
+        socket.send(&wire::build_ptr_query(id, addr)).await?;
+
+        // The caller's timeout bounds this loop; datagrams for other IDs are ignored
+        let mut buf = [0u8; 1500];
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            if wire::response_id(&buf[..len]) == Some(id) {
+                return wire::parse_ptr_response(id, &buf[..len]);
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("DNS server {}", self.server)
+    }
+}
+
+/// Transaction ID that is hard for an off-path sender to guess
+fn rand_id() -> u16 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_server_defaults_port() {
+        assert_eq!(ServerResolver::parse_server("192.0.2.53").unwrap(), "192.0.2.53:53".parse().unwrap());
+        assert_eq!(ServerResolver::parse_server("192.0.2.53:5353").unwrap(), "192.0.2.53:5353".parse().unwrap());
+        assert_eq!(ServerResolver::parse_server("[2001:db8::1]:53").unwrap(), "[2001:db8::1]:53".parse().unwrap());
+        assert!(ServerResolver::parse_server("dns.example").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::net::{IpAddr, SocketAddr};
+
+use super::resolver::Resolver;
+
+/// Uses the operating system resolver via `getnameinfo`
+///
+/// Lookups follow the host's own configuration (hosts file, nsswitch, VPN DNS).
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn reverse_lookup(&self, addr: IpAddr) -> Result<Option<String>> {
+        // getnameinfo blocks, so keep it off the async worker threads
+        let name = tokio::task::spawn_blocking(move || lookup(SocketAddr::new(addr, 0))).await??;
+        // Without a PTR record some platforms echo the address back instead of failing
+        Ok(name.filter(|name| name.parse::<IpAddr>().is_err()))
+    }
+
+    fn describe(&self) -> String {
+        "system resolver".to_string()
+    }
+}
+
+#[cfg(unix)]
+fn lookup(addr: SocketAddr) -> Result<Option<String>> {
+    use std::ffi::CStr;
+    use std::mem;
+
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(v4) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_addr.s_addr = u32::from_ne_bytes(v4.ip().octets());
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(v6) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_addr.s6_addr = v6.ip().octets();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    {
+        storage.ss_len = len as u8;
+    }
+
+    let mut host = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    let rc = unsafe {
+        libc::getnameinfo(
+            &storage as *const _ as *const libc::sockaddr,
+            len as libc::socklen_t,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+
+    match rc {
+        0 => Ok(Some(unsafe { CStr::from_ptr(host.as_ptr()) }.to_string_lossy().into_owned())),
+        libc::EAI_NONAME => Ok(None),
+        rc => {
+            let message = unsafe { CStr::from_ptr(libc::gai_strerror(rc)) };
+            bail!("getnameinfo failed: {}", message.to_string_lossy())
+        }
+    }
+}
+
+#[cfg(windows)]
+fn lookup(addr: SocketAddr) -> Result<Option<String>> {
+    // The standard library initialises Winsock lazily on first socket use;
+    // binding a throwaway socket guarantees it before calling getnameinfo directly
+    let _ = std::net::UdpSocket::bind("127.0.0.1:0");
+
+    let (storage, len) = ffi::sockaddr_from(addr);
+    let mut host = [0u8; ffi::NI_MAXHOST];
+    let rc = unsafe {
+        ffi::getnameinfo(
+            storage.as_ptr(),
+            len,
+            host.as_mut_ptr(),
+            host.len() as u32,
+            std::ptr::null_mut(),
+            0,
+            ffi::NI_NAMEREQD,
+        )
+    };
+
+    match rc {
+        0 => {
+            let end = host.iter().position(|&b| b == 0).unwrap_or(host.len());
+            Ok(Some(String::from_utf8_lossy(&host[..end]).into_owned()))
+        }
+        ffi::WSAHOST_NOT_FOUND => Ok(None),
+        rc => bail!("getnameinfo failed with error {rc}"),
+    }
+}
+
+#[cfg(windows)]
+mod ffi {
+    use std::net::SocketAddr;
+
+    pub const NI_MAXHOST: usize = 1025;
+    pub const NI_NAMEREQD: i32 = 0x04;
+    pub const WSAHOST_NOT_FOUND: i32 = 11001;
+    const AF_INET: u16 = 2;
+    const AF_INET6: u16 = 23;
+
+    #[link(name = "ws2_32")]
+    unsafe extern "system" {
+        pub fn getnameinfo(
+            addr: *const u8,
+            addr_len: i32,
+            host: *mut u8,
+            host_len: u32,
+            serv: *mut u8,
+            serv_len: u32,
+            flags: i32,
+        ) -> i32;
+    }
+
+    /// Encodes a SOCKADDR_IN / SOCKADDR_IN6 into a byte buffer
+    pub fn sockaddr_from(addr: SocketAddr) -> ([u8; 28], i32) {
+        let mut buf = [0u8; 28];
+        match addr {
+            SocketAddr::V4(v4) => {
+                buf[0..2].copy_from_slice(&AF_INET.to_ne_bytes());
+                buf[4..8].copy_from_slice(&v4.ip().octets());
+                (buf, 16)
+            }
+            SocketAddr::V6(v6) => {
+                buf[0..2].copy_from_slice(&AF_INET6.to_ne_bytes());
+                buf[8..24].copy_from_slice(&v6.ip().octets());
+                (buf, 28)
+            }
+        }
+    }
+}
+
@@ -0,0 +1,206 @@
+// DNS wire format for PTR lookups, shared by the DoH and direct-server backends
+// Only what a reverse lookup needs: one question out, the first PTR answer back
+
+use anyhow::{bail, Result};
+use std::net::IpAddr;
+
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_NXDOMAIN: u16 = 3;
+const HEADER_LEN: usize = 12;
+/// Compression pointers followed before a name is rejected as looping
+const MAX_POINTERS: usize = 16;
+
+/// Reverse-lookup name for `addr`, e.g. `4.3.2.1.in-addr.arpa`
+pub fn ptr_name(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(v6) => {
+            let mut name = String::with_capacity(72);
+            for byte in v6.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0f, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// Encodes a recursive PTR query for `addr`
+pub fn build_ptr_query(id: u16, addr: IpAddr) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(HEADER_LEN + 80);
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    msg.extend_from_slice(&[0; 6]); // ANCOUNT, NSCOUNT, ARCOUNT
+
+    for label in ptr_name(addr).split('.') {
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    msg
+}
+
+/// Transaction ID of a response, used to skip stray datagrams
+pub fn response_id(msg: &[u8]) -> Option<u16> {
+    msg.get(..2).map(|id| u16::from_be_bytes([id[0], id[1]]))
+}
+
+/// Extracts the host name from a PTR response
+///
+/// Returns `Ok(None)` for NXDOMAIN or an answer without a PTR record.
+pub fn parse_ptr_response(id: u16, msg: &[u8]) -> Result<Option<String>> {
+    if msg.len() < HEADER_LEN {
+        bail!("DNS response too short ({} bytes)", msg.len());
+    }
+    let flags = read_u16(msg, 2)?;
+    if read_u16(msg, 0)? != id || flags & FLAG_RESPONSE == 0 {
+        bail!("DNS response does not match the query");
+    }
+    if flags & FLAG_TRUNCATED != 0 {
+        bail!("DNS response was truncated");
+    }
+    match flags & 0x000f {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(None),
+        rcode => bail!("DNS server returned error code {rcode}"),
+    }
+
+    let questions = read_u16(msg, 4)?;
+    let answers = read_u16(msg, 6)?;
+    let mut offset = HEADER_LEN;
+
+    for _ in 0..questions {
+        offset = skip_name(msg, offset)? + 4; // QTYPE, QCLASS
+    }
+
+    for _ in 0..answers {
+        offset = skip_name(msg, offset)?;
+        let record_type = read_u16(msg, offset)?;
+        let data_len = read_u16(msg, offset + 8)? as usize;
+        let data_start = offset + 10;
+        if data_start + data_len > msg.len() {
+            bail!("DNS record runs past the end of the response");
+        }
+        if record_type == TYPE_PTR {
+            return read_name(msg, data_start).map(Some);
+        }
+        offset = data_start + data_len;
+    }
+
+    Ok(None)
+}
+
+fn read_u16(msg: &[u8], offset: usize) -> Result<u16> {
+    match msg.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => bail!("DNS response truncated at offset {offset}"),
+    }
+}
+
+/// Offset just past the (possibly compressed) name starting at `offset`
+fn skip_name(msg: &[u8], mut offset: usize) -> Result<usize> {
+    loop {
+        let Some(&len) = msg.get(offset) else {
+            bail!("DNS name runs past the end of the response");
+        };
+        match len {
+            0 => return Ok(offset + 1),
+            len if len & 0xc0 == 0xc0 => return Ok(offset + 2),
+            len => offset += 1 + len as usize,
+        }
+    }
+}
+
+/// Decodes the name at `offset`, following compression pointers
+fn read_name(msg: &[u8], mut offset: usize) -> Result<String> {
+    let mut labels = Vec::new();
+    let mut pointers = 0;
+
+    loop {
+        let Some(&len) = msg.get(offset) else {
+            bail!("DNS name runs past the end of the response");
+        };
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 == 0xc0 {
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                bail!("DNS name compression loop");
+            }
+            offset = (read_u16(msg, offset)? & 0x3fff) as usize;
+            continue;
+        }
+        let start = offset + 1;
+        let Some(label) = msg.get(start..start + len as usize) else {
+            bail!("DNS label runs past the end of the response");
+        };
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset = start + len as usize;
+    }
+
+    Ok(labels.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    /// Builds a response to `query` with one PTR answer pointing back at the question name
+    fn ptr_response(query: &[u8], host: &str) -> Vec<u8> {
+        let mut msg = query.to_vec();
+        msg[2] |= 0x80; // QR
+        msg[7] = 1; // ANCOUNT
+        msg.extend_from_slice(&[0xc0, 0x0c]); // name -> question
+        msg.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&300u32.to_be_bytes());
+        let mut rdata = Vec::new();
+        for label in host.split('.') {
+            rdata.push(label.len() as u8);
+            rdata.extend_from_slice(label.as_bytes());
+        }
+        rdata.push(0);
+        msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&rdata);
+        msg
+    }
+
+    #[test]
+    fn test_ptr_names() {
+        assert_eq!(ptr_name(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))), "1.2.0.192.in-addr.arpa");
+        let v6 = ptr_name(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)));
+        assert!(v6.starts_with("1.0.0.0.0.0.0.0."));
+        assert!(v6.ends_with("8.b.d.0.1.0.0.2.ip6.arpa"));
+    }
+
+    #[test]
+    fn test_parse_ptr_answer() {
+        let query = build_ptr_query(0x1234, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        let response = ptr_response(&query, "host.example.net");
+
+        assert_eq!(response_id(&response), Some(0x1234));
+        assert_eq!(parse_ptr_response(0x1234, &response).unwrap().as_deref(), Some("host.example.net"));
+        assert!(parse_ptr_response(0x4321, &response).is_err());
+    }
+
+    #[test]
+    fn test_nxdomain_is_no_name() {
+        let mut response = build_ptr_query(7, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        response[2] |= 0x80;
+        response[3] |= RCODE_NXDOMAIN as u8;
+
+        assert_eq!(parse_ptr_response(7, &response).unwrap(), None);
+    }
+}
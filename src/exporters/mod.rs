@@ -0,0 +1,6 @@
+// Exporters: push collected statistics to systems outside kaipo-watcher, and write
+// security reports for them to pick up
+
+pub mod mqtt;
+pub mod security_report;
+pub mod webhook;
@@ -0,0 +1,552 @@
+// MQTT publishing: each interface's speed and today's usage as plain-value topics under
+// `[mqtt] topic_prefix`, for Home Assistant, Node-RED or Grafana through an MQTT broker.
+// Speaks just enough MQTT 3.1.1 over TCP to publish: connect with a last will, publish
+// at QoS 0-2 and keep the connection alive. A background task owns the connection and
+// reconnects on the next batch after a failure, so an unreachable broker never holds up capture
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::MqttConfig;
+use crate::storage::packet_storage::BandwidthSample;
+
+const DEFAULT_PORT: u16 = 1883;
+
+/// Longest wait for the broker to accept a connection or answer a packet
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Batches waiting while the broker is slow; later ones are dropped beyond this
+const QUEUED_BATCHES: usize = 4;
+
+/// Topic under the prefix that reads `online` while connected and `offline` otherwise
+const STATUS_TOPIC: &str = "status";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Message {
+    topic: String,
+    payload: String,
+}
+
+/// Connection settings checked once, when publishing starts
+#[derive(Debug, Clone)]
+struct ConnectOptions {
+    address: String,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    keep_alive: Duration,
+    insecure: bool,
+    qos: u8,
+    retain: bool,
+    status_topic: String,
+}
+
+/// Publishes bandwidth samples to the configured broker
+pub struct MqttPublisher {
+    prefix: String,
+    batches: mpsc::Sender<Vec<Message>>,
+    task: JoinHandle<()>,
+    usage: DailyUsage,
+}
+
+impl MqttPublisher {
+    /// `None` when no broker is configured; the connection is made on the first batch
+    pub fn start(config: &MqttConfig) -> Result<Option<Self>> {
+        let Some(broker) = config.broker.as_deref().map(str::trim).filter(|broker| !broker.is_empty()) else {
+            return Ok(None);
+        };
+        if config.qos > 2 {
+            bail!("MQTT qos must be 0, 1 or 2, not {}", config.qos);
+        }
+        let prefix = config.topic_prefix.trim_matches('/').to_string();
+        if prefix.is_empty() || prefix.contains(['+', '#']) {
+            bail!("MQTT topic_prefix must be a topic name without wildcards: {:?}", config.topic_prefix);
+        }
+        let options = ConnectOptions {
+            address: broker_address(broker)?,
+            client_id: config.client_id.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+            keep_alive: Duration::from_secs(config.keep_alive_secs.clamp(5, u16::MAX as u64)),
+            insecure: config.insecure,
+            qos: config.qos,
+            retain: config.retain,
+            status_topic: format!("{prefix}/{STATUS_TOPIC}"),
+        };
+        let (batches, receiver) = mpsc::channel(QUEUED_BATCHES);
+        let task = tokio::spawn(run_connection(options, receiver));
+        Ok(Some(Self { prefix, batches, task, usage: DailyUsage::default() }))
+    }
+
+    /// Counts traffic already recorded today, so usage topics survive a restart
+    pub fn seed_usage(&mut self, samples: &[BandwidthSample]) {
+        for sample in samples {
+            self.usage.add(sample);
+        }
+    }
+
+    /// Queues the speed and today's usage of every sampled interface
+    pub fn publish_samples(&mut self, samples: &[BandwidthSample]) {
+        let mut batch = Vec::with_capacity(samples.len() * 4);
+        for sample in samples {
+            let (received, sent) = self.usage.add(sample);
+            let base = format!("{}/{}", self.prefix, topic_level(&sample.interface_name));
+            batch.extend([
+                Message { topic: format!("{base}/download_bps"), payload: format!("{:.0}", sample.download_bps) },
+                Message { topic: format!("{base}/upload_bps"), payload: format!("{:.0}", sample.upload_bps) },
+                Message { topic: format!("{base}/today_received_bytes"), payload: received.to_string() },
+                Message { topic: format!("{base}/today_sent_bytes"), payload: sent.to_string() },
+            ]);
+        }
+        if batch.is_empty() {
+            return;
+        }
+        if self.batches.try_send(batch).is_err() {
+            debug!("MQTT broker is not keeping up; dropped {} samples", samples.len());
+        }
+    }
+
+    /// Marks the publisher offline and disconnects, waiting at most `NETWORK_TIMEOUT`
+    pub async fn shutdown(self) {
+        drop(self.batches);
+        if tokio::time::timeout(NETWORK_TIMEOUT, self.task).await.is_err() {
+            warn!("MQTT connection did not close in time");
+        }
+    }
+}
+
+/// Bytes each interface received and sent today, estimated from its average rates
+#[derive(Debug, Default)]
+struct DailyUsage {
+    day: Option<NaiveDate>,
+    totals: HashMap<String, (u64, u64)>,
+}
+
+impl DailyUsage {
+    /// Adds the sample to its day's totals, starting over at midnight, and returns them
+    fn add(&mut self, sample: &BandwidthSample) -> (u64, u64) {
+        let day = sample.timestamp.date_naive();
+        if self.day != Some(day) {
+            self.day = Some(day);
+            self.totals.clear();
+        }
+        let totals = self.totals.entry(sample.interface_name.clone()).or_default();
+        totals.0 += (sample.download_bps * sample.duration_secs).round() as u64;
+        totals.1 += (sample.upload_bps * sample.duration_secs).round() as u64;
+        *totals
+    }
+}
+
+/// Midnight at the start of today, where today's usage is counted from
+pub fn today_start() -> chrono::DateTime<Local> {
+    let now = Local::now();
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .unwrap_or(now)
+}
+
+/// `host:port` to connect to, from `host`, `host:port` or `mqtt://host:port`
+fn broker_address(broker: &str) -> Result<String> {
+    if broker.starts_with("mqtts://") || broker.starts_with("ssl://") {
+        bail!("MQTT over TLS is not supported; use a plain mqtt:// broker address: {broker}");
+    }
+    let address = broker.strip_prefix("mqtt://").or_else(|| broker.strip_prefix("tcp://")).unwrap_or(broker);
+    let address = address.trim_end_matches('/');
+    let has_port = match address.rsplit_once(':') {
+        // A bare IPv6 address has colons but no port
+        Some((host, port)) => port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')),
+        None => false,
+    };
+    if address.is_empty() {
+        bail!("MQTT broker address is empty");
+    }
+    Ok(if has_port {
+        address.to_string()
+    } else if address.contains(':') && !address.starts_with('[') {
+        format!("[{address}]:{DEFAULT_PORT}")
+    } else {
+        format!("{address}:{DEFAULT_PORT}")
+    })
+}
+
+/// Refuses to send a username or password over plain TCP beyond this machine, where they
+/// could be read on the way, unless `[mqtt] insecure` asks for it; checked against the
+/// address connected to, so a host name resolving to loopback counts as local
+fn check_plaintext(peer: SocketAddr, has_credentials: bool, insecure: bool) -> Result<()> {
+    if !has_credentials || peer.ip().is_loopback() {
+        return Ok(());
+    }
+    if !insecure {
+        bail!(
+            "Refusing to send the MQTT username and password to {peer} in the clear; \
+             use a broker on this machine, leave out the credentials, or set [mqtt] insecure = true"
+        );
+    }
+    warn!("Sending the MQTT username and password to {peer} in the clear");
+    Ok(())
+}
+
+/// Interface name as a single topic level; `/`, `+` and `#` would change the topic's meaning
+fn topic_level(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
+
+/// Owns the broker connection until the publisher is dropped
+async fn run_connection(options: ConnectOptions, mut batches: mpsc::Receiver<Vec<Message>>) {
+    let mut connection: Option<Connection> = None;
+    // Something is sent at least this often, well inside the broker's 1.5x keep-alive allowance
+    let idle = options.keep_alive / 2;
+    loop {
+        let batch = match tokio::time::timeout(idle, batches.recv()).await {
+            Ok(Some(batch)) => batch,
+            Ok(None) => break,
+            Err(_) => {
+                let Some(open) = &mut connection else {
+                    continue;
+                };
+                if let Err(e) = open.ping().await {
+                    warn!("MQTT broker {} stopped answering: {e:#}", options.address);
+                    connection = None;
+                }
+                continue;
+            }
+        };
+        if connection.is_none() {
+            match Connection::open(&options).await {
+                Ok(open) => {
+                    info!("Connected to MQTT broker {}", options.address);
+                    connection = Some(open);
+                }
+                Err(e) => {
+                    warn!("Failed to connect to MQTT broker {}: {e:#}", options.address);
+                    continue;
+                }
+            }
+        }
+        if let Some(open) = &mut connection {
+            for message in &batch {
+                let published = open.publish(&message.topic, message.payload.as_bytes(), options.qos, options.retain);
+                if let Err(e) = published.await {
+                    warn!("Failed to publish to MQTT broker {}: {e:#}", options.address);
+                    connection = None;
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(mut open) = connection {
+        // A clean disconnect discards the last will, so say offline first
+        let closed = async {
+            open.publish(&options.status_topic, b"offline", options.qos, true).await?;
+            open.write(&[DISCONNECT, 0]).await
+        };
+        if let Err(e) = closed.await {
+            debug!("MQTT disconnect failed: {e:#}");
+        }
+    }
+}
+
+// Control packet types, shifted into the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+/// PUBREL carries the reserved flags 0b0010
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+
+struct Connection {
+    stream: TcpStream,
+    next_packet_id: u16,
+}
+
+impl Connection {
+    /// Connects with a retained `offline` last will, then publishes `online`
+    async fn open(options: &ConnectOptions) -> Result<Self> {
+        let stream = tokio::time::timeout(NETWORK_TIMEOUT, TcpStream::connect(&options.address))
+            .await
+            .context("Timed out connecting")?
+            .context("Failed to connect")?;
+        let has_credentials = options.username.is_some() || options.password.is_some();
+        check_plaintext(stream.peer_addr()?, has_credentials, options.insecure)?;
+        let mut connection = Self { stream, next_packet_id: 1 };
+        connection.write(&encode_connect(options)).await?;
+        let (packet_type, body) = connection.read_packet().await?;
+        if packet_type != CONNACK || body.len() != 2 {
+            bail!("Expected CONNACK, got packet type {:#04x}", packet_type);
+        }
+        match body[1] {
+            0 => {}
+            1 => bail!("Broker does not support MQTT 3.1.1"),
+            2 => bail!("Broker rejected client_id {:?}", options.client_id),
+            3 => bail!("Broker is unavailable"),
+            4 => bail!("Broker rejected the username or password"),
+            5 => bail!("Client is not authorized"),
+            code => bail!("Broker refused the connection (code {code})"),
+        }
+        connection.publish(&options.status_topic, b"online", options.qos, true).await?;
+        Ok(connection)
+    }
+
+    async fn publish(&mut self, topic: &str, payload: &[u8], qos: u8, retain: bool) -> Result<()> {
+        let packet_id = (qos > 0).then(|| self.take_packet_id());
+        self.write(&encode_publish(topic, payload, qos, retain, packet_id)).await?;
+        let Some(id) = packet_id else {
+            return Ok(());
+        };
+        if qos == 1 {
+            return self.expect(PUBACK, id).await;
+        }
+        self.expect(PUBREC, id).await?;
+        self.write(&[PUBREL, 2, (id >> 8) as u8, id as u8]).await?;
+        self.expect(PUBCOMP, id).await
+    }
+
+    async fn ping(&mut self) -> Result<()> {
+        self.write(&[PINGREQ, 0]).await?;
+        let (packet_type, _) = self.read_packet().await?;
+        if packet_type != PINGRESP {
+            bail!("Expected PINGRESP, got packet type {packet_type:#04x}");
+        }
+        Ok(())
+    }
+
+    fn take_packet_id(&mut self) -> u16 {
+        let id = self.next_packet_id;
+        // Packet identifier 0 is not allowed
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    /// Waits for the acknowledgement `packet_type` of packet `id`
+    async fn expect(&mut self, packet_type: u8, id: u16) -> Result<()> {
+        let (received, body) = self.read_packet().await?;
+        if received & 0xF0 != packet_type & 0xF0 || body.len() < 2 || u16::from_be_bytes([body[0], body[1]]) != id {
+            bail!("Expected acknowledgement {packet_type:#04x} of packet {id}, got packet type {received:#04x}");
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, packet: &[u8]) -> Result<()> {
+        tokio::time::timeout(NETWORK_TIMEOUT, self.stream.write_all(packet))
+            .await
+            .context("Timed out sending to the broker")?
+            .context("Failed to send to the broker")
+    }
+
+    /// Reads one control packet: its first byte and its body
+    async fn read_packet(&mut self) -> Result<(u8, Vec<u8>)> {
+        let read = async {
+            let packet_type = self.stream.read_u8().await?;
+            let mut length = 0usize;
+            for shift in (0..28).step_by(7) {
+                let byte = self.stream.read_u8().await?;
+                length |= ((byte & 0x7F) as usize) << shift;
+                if byte & 0x80 == 0 {
+                    let mut body = vec![0; length];
+                    self.stream.read_exact(&mut body).await?;
+                    return Ok((packet_type, body));
+                }
+            }
+            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed remaining length"))
+        };
+        tokio::time::timeout(NETWORK_TIMEOUT, read)
+            .await
+            .context("Timed out waiting for the broker")?
+            .context("Failed to read from the broker")
+    }
+}
+
+fn encode_connect(options: &ConnectOptions) -> Vec<u8> {
+    // Clean session, with a retained last will at the configured QoS
+    let mut flags = 0b0010_0110 | (options.qos << 3);
+    if options.username.is_some() {
+        flags |= 0b1000_0000;
+    }
+    if options.password.is_some() {
+        flags |= 0b0100_0000;
+    }
+    let mut body = Vec::new();
+    put_string(&mut body, b"MQTT");
+    body.push(4);
+    body.push(flags);
+    body.extend_from_slice(&(options.keep_alive.as_secs() as u16).to_be_bytes());
+    put_string(&mut body, options.client_id.as_bytes());
+    put_string(&mut body, options.status_topic.as_bytes());
+    put_string(&mut body, b"offline");
+    for credential in [&options.username, &options.password].into_iter().flatten() {
+        put_string(&mut body, credential.as_bytes());
+    }
+    packet(CONNECT, body)
+}
+
+fn encode_publish(topic: &str, payload: &[u8], qos: u8, retain: bool, packet_id: Option<u16>) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+    put_string(&mut body, topic.as_bytes());
+    if let Some(id) = packet_id {
+        body.extend_from_slice(&id.to_be_bytes());
+    }
+    body.extend_from_slice(payload);
+    packet(PUBLISH | (qos << 1) | retain as u8, body)
+}
+
+/// Length-prefixed UTF-8 string, as every string in a control packet is written
+fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+/// Fixed header (first byte and variable-length remaining length) followed by `body`
+fn packet(first_byte: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(first_byte);
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn sample(interface: &str, timestamp: chrono::DateTime<Local>, download_bps: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp,
+            interface_name: interface.to_string(),
+            duration_secs: 60.0,
+            download_bps,
+            upload_bps: 10.0,
+        }
+    }
+
+    #[test]
+    fn test_broker_address() {
+        assert_eq!(broker_address("broker.local").unwrap(), "broker.local:1883");
+        assert_eq!(broker_address("mqtt://10.0.0.2:1884/").unwrap(), "10.0.0.2:1884");
+        assert_eq!(broker_address("[::1]:1883").unwrap(), "[::1]:1883");
+        assert_eq!(broker_address("fd00::2").unwrap(), "[fd00::2]:1883");
+        assert!(broker_address("mqtts://broker.local").is_err());
+        assert_eq!(topic_level("tun/0+#"), "tun_0__");
+    }
+
+    #[test]
+    fn test_credentials_stay_on_this_machine() {
+        let local: SocketAddr = "127.0.0.1:1883".parse().unwrap();
+        let remote: SocketAddr = "192.0.2.10:1883".parse().unwrap();
+        assert!(check_plaintext(local, true, false).is_ok());
+        assert!(check_plaintext("[::1]:1883".parse().unwrap(), true, false).is_ok());
+        assert!(check_plaintext(remote, false, false).is_ok());
+        assert!(check_plaintext(remote, true, false).is_err());
+        assert!(check_plaintext(remote, true, true).is_ok());
+    }
+
+    #[test]
+    fn test_remaining_length_encoding() {
+        assert_eq!(packet(PINGREQ, Vec::new()), [PINGREQ, 0]);
+        let long = packet(PUBLISH, vec![0; 321]);
+        assert_eq!(&long[..3], [PUBLISH, 0xC1, 0x02]);
+        assert_eq!(long.len(), 3 + 321);
+    }
+
+    #[test]
+    fn test_daily_usage_starts_over_at_midnight() {
+        let mut usage = DailyUsage::default();
+        let evening = Local::now().with_time(chrono::NaiveTime::from_hms_opt(23, 0, 0).unwrap()).unwrap();
+        assert_eq!(usage.add(&sample("eth0", evening, 100.0)), (6000, 600));
+        assert_eq!(usage.add(&sample("eth0", evening, 50.0)), (9000, 1200));
+        let next_day = evening + chrono::Duration::hours(2);
+        assert_eq!(usage.add(&sample("eth0", next_day, 100.0)), (6000, 600));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(MqttPublisher::start(&MqttConfig::default()).unwrap().is_none());
+        let config = MqttConfig { broker: Some("localhost".to_string()), qos: 3, ..MqttConfig::default() };
+        assert!(MqttPublisher::start(&config).is_err());
+        let config = MqttConfig {
+            broker: Some("localhost".to_string()),
+            topic_prefix: "home/#".to_string(),
+            ..MqttConfig::default()
+        };
+        assert!(MqttPublisher::start(&config).is_err());
+    }
+
+    /// Reads one packet from the client side of a test broker
+    async fn read(socket: &mut TcpStream) -> (u8, Vec<u8>) {
+        let packet_type = socket.read_u8().await.unwrap();
+        let length = socket.read_u8().await.unwrap() as usize;
+        let mut body = vec![0; length];
+        socket.read_exact(&mut body).await.unwrap();
+        (packet_type, body)
+    }
+
+    /// Topic and payload of a PUBLISH body with a packet identifier
+    fn published(body: &[u8]) -> (String, u16, String) {
+        let topic_length = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let topic = String::from_utf8(body[2..2 + topic_length].to_vec()).unwrap();
+        let id = u16::from_be_bytes([body[2 + topic_length], body[3 + topic_length]]);
+        (topic, id, String::from_utf8(body[4 + topic_length..].to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_publishes_samples_at_qos_1() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let broker = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let (packet_type, connect) = read(&mut socket).await;
+            assert_eq!(packet_type, CONNECT);
+            assert_eq!(&connect[..7], b"\x00\x04MQTT\x04");
+            socket.write_all(&[CONNACK, 2, 0, 0]).await.unwrap();
+            let mut messages = Vec::new();
+            loop {
+                let (packet_type, body) = read(&mut socket).await;
+                if packet_type == DISCONNECT {
+                    return messages;
+                }
+                // QoS 1 and retained
+                assert_eq!(packet_type, PUBLISH | 0b011);
+                let (topic, id, payload) = published(&body);
+                socket.write_all(&[PUBACK, 2, (id >> 8) as u8, id as u8]).await.unwrap();
+                messages.push((topic, payload));
+            }
+        });
+
+        let config = MqttConfig { broker: Some(broker), qos: 1, ..MqttConfig::default() };
+        let mut publisher = MqttPublisher::start(&config).unwrap().unwrap();
+        publisher.publish_samples(&[sample("eth0", Local::now(), 1250.4)]);
+        publisher.shutdown().await;
+
+        let messages = server.await.unwrap();
+        let value = |topic: &str| messages.iter().find(|(t, _)| t == topic).map(|(_, p)| p.as_str());
+        assert_eq!(messages.first().map(|(t, p)| (t.as_str(), p.as_str())), Some(("kaipo-watcher/status", "online")));
+        assert_eq!(value("kaipo-watcher/eth0/download_bps"), Some("1250"));
+        assert_eq!(value("kaipo-watcher/eth0/today_received_bytes"), Some("75024"));
+        assert_eq!(messages.last().map(|(_, p)| p.as_str()), Some("offline"));
+    }
+}
@@ -0,0 +1,376 @@
+// Security reports: the stored security events of a period, each with the connections that
+// carried it as evidence, written by `kw analyze --security --export` for SIEM tooling. The
+// report is either kaipo-watcher's own JSON document or a SARIF 2.1.0 log, which code
+// scanning dashboards and most SIEM importers read without a custom parser
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::models::Redactor;
+use crate::storage::packet_storage::{ConnectionRecord, SecurityEvent};
+
+/// Schema of the SARIF logs written by `SecurityReport::to_sarif`
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Layout of an exported security report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityReportFormat {
+    Json,
+    Sarif,
+}
+
+impl SecurityReportFormat {
+    /// SARIF for `.sarif` files, JSON for anything else
+    pub fn for_path(path: &Path) -> Self {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if name.ends_with(".sarif") || name.ends_with(".sarif.json") {
+            Self::Sarif
+        } else {
+            Self::Json
+        }
+    }
+}
+
+impl std::str::FromStr for SecurityReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            other => bail!("Unknown security report format '{other}' (expected json or sarif)"),
+        }
+    }
+}
+
+/// Security events of one period, as written by `kw analyze --security --export`
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityReport {
+    pub generated_at: DateTime<Local>,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    /// Interface the events were detected on, or `all`
+    pub interface: String,
+    /// Number of events per severity
+    pub severities: BTreeMap<String, u64>,
+    /// Number of events per type
+    pub event_types: BTreeMap<String, u64>,
+    /// Oldest first
+    pub events: Vec<ReportedEvent>,
+}
+
+/// One security event with its evidence
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportedEvent {
+    pub timestamp: DateTime<Local>,
+    pub event_type: String,
+    pub severity: String,
+    pub interface: String,
+    pub source_ip: Option<String>,
+    pub dest_ip: Option<String>,
+    pub port: Option<u16>,
+    pub protocol: Option<String>,
+    pub description: String,
+    /// Connections between the event's addresses around the time it was detected, busiest first
+    pub evidence: Vec<Evidence>,
+}
+
+/// A recorded connection backing an event; packets themselves are not stored
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evidence {
+    pub source: String,
+    pub destination: String,
+    pub protocol: String,
+    pub application_protocol: Option<String>,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl From<&ConnectionRecord> for Evidence {
+    fn from(connection: &ConnectionRecord) -> Self {
+        let endpoint = |ip: &str, port: Option<u16>| match (ip.contains(':'), port) {
+            (true, Some(port)) => format!("[{ip}]:{port}"),
+            (false, Some(port)) => format!("{ip}:{port}"),
+            (_, None) => ip.to_string(),
+        };
+        Self {
+            source: endpoint(&connection.source_ip, connection.source_port),
+            destination: endpoint(&connection.dest_ip, connection.dest_port),
+            protocol: connection.protocol.clone(),
+            application_protocol: connection.application_protocol.clone(),
+            first_seen: connection.first_seen,
+            last_seen: connection.last_seen,
+            packets: connection.packet_count,
+            bytes: connection.byte_count,
+        }
+    }
+}
+
+impl SecurityReport {
+    /// Builds the report from events and the evidence `evidence` finds for each of them
+    pub fn new(
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+        interface: String,
+        events: Vec<SecurityEvent>,
+        mut evidence: impl FnMut(&SecurityEvent) -> Result<Vec<ConnectionRecord>>,
+    ) -> Result<Self> {
+        let mut severities = BTreeMap::new();
+        let mut event_types = BTreeMap::new();
+        let mut reported = Vec::with_capacity(events.len());
+        for event in events {
+            *severities.entry(event.severity.clone()).or_insert(0) += 1;
+            *event_types.entry(event.event_type.clone()).or_insert(0) += 1;
+            let connections = evidence(&event)?;
+            reported.push(ReportedEvent {
+                timestamp: event.timestamp,
+                event_type: event.event_type,
+                severity: event.severity,
+                interface: event.interface_name,
+                source_ip: event.source_ip,
+                dest_ip: event.dest_ip,
+                port: event.port,
+                protocol: event.protocol,
+                description: event.description,
+                evidence: connections.iter().map(Evidence::from).collect(),
+            });
+        }
+        Ok(Self {
+            generated_at: Local::now(),
+            start,
+            end,
+            interface,
+            severities,
+            event_types,
+            events: reported,
+        })
+    }
+
+    /// The report with its addresses redacted as `redactor` asks, in events, their
+    /// descriptions and their evidence
+    pub fn redacted(mut self, redactor: &Redactor) -> Self {
+        if !redactor.is_active() {
+            return self;
+        }
+        for event in &mut self.events {
+            for ip in [&mut event.source_ip, &mut event.dest_ip].into_iter().flatten() {
+                *ip = redactor.target(ip).into_owned();
+            }
+            event.description = redactor.text(&event.description).into_owned();
+            for evidence in &mut event.evidence {
+                evidence.source = redactor.target(&evidence.source).into_owned();
+                evidence.destination = redactor.target(&evidence.destination).into_owned();
+            }
+        }
+        self
+    }
+
+    /// The report as a SARIF 2.1.0 log: one rule per event type and one result per event
+    pub fn to_sarif(&self) -> Value {
+        let mut rules: BTreeMap<&str, &str> = BTreeMap::new();
+        for event in &self.events {
+            // Rules take the highest severity seen for their type as the default level
+            let level = rules.entry(&event.event_type).or_insert("note");
+            if level_rank(sarif_level(&event.severity)) > level_rank(level) {
+                *level = sarif_level(&event.severity);
+            }
+        }
+        let rule_index: BTreeMap<&str, usize> = rules.keys().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        let results: Vec<Value> = self
+            .events
+            .iter()
+            .map(|event| {
+                let mut properties = json!({
+                    "timestamp": event.timestamp.to_rfc3339(),
+                    "severity": event.severity,
+                    "interface": event.interface,
+                    "evidence": event.evidence,
+                });
+                for (key, value) in [
+                    ("sourceIp", event.source_ip.clone().map(Value::from)),
+                    ("destIp", event.dest_ip.clone().map(Value::from)),
+                    ("port", event.port.map(Value::from)),
+                    ("protocol", event.protocol.clone().map(Value::from)),
+                ] {
+                    if let Some(value) = value {
+                        properties[key] = value;
+                    }
+                }
+                let location = event.dest_ip.as_ref().or(event.source_ip.as_ref()).map(|ip| {
+                    json!([{ "logicalLocations": [{ "name": ip, "kind": "host" }] }])
+                });
+                let mut result = json!({
+                    "ruleId": event.event_type,
+                    "ruleIndex": rule_index[event.event_type.as_str()],
+                    "level": sarif_level(&event.severity),
+                    "message": { "text": event.description },
+                    "properties": properties,
+                });
+                if let Some(location) = location {
+                    result["locations"] = location;
+                }
+                result
+            })
+            .collect();
+
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "kaipo-watcher",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules.iter().map(|(id, level)| json!({
+                            "id": id,
+                            "name": id,
+                            "shortDescription": { "text": format!("{id} security event") },
+                            "defaultConfiguration": { "level": level },
+                        })).collect::<Vec<_>>(),
+                    }
+                },
+                "invocations": [{
+                    "executionSuccessful": true,
+                    "startTimeUtc": self.start.to_utc().to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "endTimeUtc": self.end.to_utc().to_rfc3339_opts(SecondsFormat::Secs, true),
+                }],
+                "properties": { "interface": self.interface },
+                "results": results,
+            }]
+        })
+    }
+
+    /// The report in `format`, pretty-printed
+    pub fn render(&self, format: SecurityReportFormat) -> Result<String> {
+        Ok(match format {
+            SecurityReportFormat::Json => serde_json::to_string_pretty(self)?,
+            SecurityReportFormat::Sarif => serde_json::to_string_pretty(&self.to_sarif())?,
+        })
+    }
+}
+
+/// SARIF level of a stored severity: high and critical are errors, warnings stay warnings
+fn sarif_level(severity: &str) -> &'static str {
+    match severity.to_ascii_lowercase().as_str() {
+        "critical" | "high" => "error",
+        "warning" | "medium" => "warning",
+        _ => "note",
+    }
+}
+
+fn level_rank(level: &str) -> u8 {
+    match level {
+        "error" => 2,
+        "warning" => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event_type: &str, severity: &str, source: Option<&str>) -> SecurityEvent {
+        SecurityEvent {
+            timestamp: Local::now(),
+            interface_name: "eth0".to_string(),
+            event_type: event_type.to_string(),
+            source_ip: source.map(str::to_string),
+            dest_ip: source.map(|_| "10.0.0.5".to_string()),
+            port: source.map(|_| 22),
+            protocol: source.map(|_| "Tcp".to_string()),
+            description: format!("{event_type} detected"),
+            severity: severity.to_string(),
+        }
+    }
+
+    fn connection(source: &str) -> ConnectionRecord {
+        let now = Local::now();
+        ConnectionRecord {
+            connection_key: format!("{source}-10.0.0.5"),
+            source_ip: source.to_string(),
+            dest_ip: "10.0.0.5".to_string(),
+            source_port: Some(50000),
+            dest_port: Some(22),
+            protocol: "TCP".to_string(),
+            application_protocol: Some("SSH".to_string()),
+            first_seen: now,
+            last_seen: now,
+            packet_count: 12,
+            byte_count: 3400,
+            is_active: false,
+            classification_confidence: "high".to_string(),
+            sample_rate: 1,
+        }
+    }
+
+    fn report() -> SecurityReport {
+        let events = vec![
+            event("SuspiciousPort", "warning", Some("192.168.1.20")),
+            event("PortScan", "high", Some("192.168.1.20")),
+            event("UnknownTrafficSurge", "warning", None),
+            event("SuspiciousPort", "high", Some("fe80::1")),
+        ];
+        let now = Local::now();
+        SecurityReport::new(now, now, "all".to_string(), events, |event| {
+            Ok(event.source_ip.iter().map(|ip| connection(ip)).collect())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_report_counts_and_evidence() {
+        let report = report();
+        assert_eq!(report.severities, BTreeMap::from([("high".to_string(), 2), ("warning".to_string(), 2)]));
+        assert_eq!(report.event_types["SuspiciousPort"], 2);
+        assert_eq!(report.events[0].evidence[0].source, "192.168.1.20:50000");
+        assert_eq!(report.events[3].evidence[0].source, "[fe80::1]:50000");
+        assert!(report.events[2].evidence.is_empty());
+
+        let json: Value = serde_json::from_str(&report.render(SecurityReportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["events"][1]["event_type"], "PortScan");
+        assert_eq!(json["events"][1]["evidence"][0]["bytes"], 3400);
+
+        let redacted = report.redacted(&Redactor::new(crate::models::PrivacyMode::Truncate, None));
+        assert_eq!(redacted.events[0].evidence[0].source, "192.168.x.x:50000");
+        assert_eq!(redacted.events[3].evidence[0].source, "[fe80:0:…]:50000");
+        assert_eq!(redacted.events[0].dest_ip.as_deref(), Some("10.0.x.x"));
+    }
+
+    #[test]
+    fn test_sarif_rules_and_levels() {
+        let sarif = report().to_sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        assert!(sarif["runs"][0]["invocations"][0]["endTimeUtc"].as_str().unwrap().ends_with('Z'));
+        let run = &sarif["runs"][0];
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|r| (r["id"].as_str().unwrap(), r["defaultConfiguration"]["level"].as_str().unwrap()))
+            .collect();
+        assert_eq!(rules, vec![("PortScan", "error"), ("SuspiciousPort", "error"), ("UnknownTrafficSurge", "warning")]);
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0]["ruleIndex"], 1);
+        assert_eq!(results[0]["level"], "warning");
+        assert_eq!(results[0]["locations"][0]["logicalLocations"][0]["name"], "10.0.0.5");
+        assert_eq!(results[0]["properties"]["port"], 22);
+        assert!(results[2].get("locations").is_none());
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(SecurityReportFormat::for_path(Path::new("security.json")), SecurityReportFormat::Json);
+        assert_eq!(SecurityReportFormat::for_path(Path::new("out/scan.sarif")), SecurityReportFormat::Sarif);
+        assert_eq!(SecurityReportFormat::for_path(Path::new("scan.SARIF.json")), SecurityReportFormat::Sarif);
+        assert!("xml".parse::<SecurityReportFormat>().is_err());
+    }
+}
@@ -0,0 +1,368 @@
+// Webhook push: posts a summary of each period's bandwidth and protocols as JSON to the
+// URL under `[webhook]`, for Home Assistant, Node-RED or a dashboard's HTTP input. Failed
+// requests are retried with exponential backoff; a summary that still fails is dropped
+// rather than queued, since the next one covers the period after it anyway
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use log::{debug, warn};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config::WebhookConfig;
+use crate::storage::packet_storage::{BandwidthSample, UsageSummary};
+
+/// Longest wait between two attempts, however many retries are configured
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// What one summary reports
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSummary {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    /// Interface packets were captured on; protocols and traffic describe it alone
+    pub interface: String,
+    /// Throughput of every sampled interface, by name
+    pub bandwidth: Vec<InterfaceBandwidth>,
+    pub traffic: TrafficTotals,
+    /// Application protocol where one was recognized, else transport; busiest first
+    pub protocols: Vec<ProtocolTotals>,
+}
+
+/// Average and peak rates, in bytes per second, over the summary's period
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InterfaceBandwidth {
+    pub name: String,
+    pub download_bps: f64,
+    pub upload_bps: f64,
+    pub peak_download_bps: f64,
+    pub peak_upload_bps: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TrafficTotals {
+    pub connections: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtocolTotals {
+    pub protocol: String,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl StatsSummary {
+    pub fn new(
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+        interface: String,
+        samples: &[BandwidthSample],
+        usage: &UsageSummary,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            interface,
+            bandwidth: interface_bandwidth(samples),
+            traffic: TrafficTotals {
+                connections: usage.connections,
+                packets: usage.packets,
+                bytes: usage.bytes,
+            },
+            protocols: usage
+                .applications
+                .iter()
+                .map(|(protocol, packets, bytes)| ProtocolTotals {
+                    protocol: protocol.clone(),
+                    packets: *packets,
+                    bytes: *bytes,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Duration-weighted average and peak of each interface's samples
+fn interface_bandwidth(samples: &[BandwidthSample]) -> Vec<InterfaceBandwidth> {
+    let mut by_interface: BTreeMap<&str, Vec<&BandwidthSample>> = BTreeMap::new();
+    for sample in samples {
+        by_interface.entry(&sample.interface_name).or_default().push(sample);
+    }
+    by_interface
+        .into_iter()
+        .map(|(name, samples)| {
+            let duration: f64 = samples.iter().map(|s| s.duration_secs).sum();
+            let average = |rate: fn(&BandwidthSample) -> f64| {
+                if duration > 0.0 {
+                    samples.iter().map(|s| rate(s) * s.duration_secs).sum::<f64>() / duration
+                } else {
+                    0.0
+                }
+            };
+            let peak = |rate: fn(&BandwidthSample) -> f64| samples.iter().map(|s| rate(s)).fold(0.0, f64::max);
+            InterfaceBandwidth {
+                name: name.to_string(),
+                download_bps: average(|s| s.download_bps),
+                upload_bps: average(|s| s.upload_bps),
+                peak_download_bps: peak(|s| s.download_bps),
+                peak_upload_bps: peak(|s| s.upload_bps),
+            }
+        })
+        .collect()
+}
+
+/// Posts summaries to the configured webhook
+#[derive(Debug, Clone)]
+pub struct WebhookExporter {
+    client: reqwest::Client,
+    url: String,
+    auth_header: Option<(HeaderName, HeaderValue)>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl WebhookExporter {
+    /// `None` when no URL is configured
+    pub fn new(config: &WebhookConfig) -> Result<Option<Self>> {
+        let Some(url) = config.url.as_deref().map(str::trim).filter(|url| !url.is_empty()) else {
+            return Ok(None);
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("Webhook URL must be an http:// or https:// URL: {url}");
+        }
+        let auth_header = config.auth_header.as_deref().map(parse_header).transpose()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+            .build()
+            .context("Failed to create webhook client")?;
+        Ok(Some(Self {
+            client,
+            url: url.to_string(),
+            auth_header,
+            retries: config.retries,
+            backoff: Duration::from_secs(config.backoff_secs),
+        }))
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Posts `summary`, retrying failures that may be temporary
+    pub async fn push(&self, summary: &StatsSummary) -> Result<()> {
+        let body = serde_json::to_vec(summary)?;
+        let mut attempt = 0;
+        loop {
+            let error = match self.post(body.clone()).await {
+                Ok(()) => {
+                    debug!("Posted summary to {}", self.url);
+                    return Ok(());
+                }
+                Err(PostError::Permanent(e)) => return Err(e),
+                Err(PostError::Temporary(e)) => e,
+            };
+            if attempt >= self.retries {
+                return Err(error.context(format!("giving up after {} attempts", attempt + 1)));
+            }
+            let delay = backoff_delay(self.backoff, attempt);
+            warn!("Webhook request to {} failed, retrying in {}s: {error:#}", self.url, delay.as_secs());
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, body: Vec<u8>) -> Result<(), PostError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some((name, value)) = &self.auth_header {
+            request = request.header(name.clone(), value.clone());
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Request to {} failed", self.url))
+            .map_err(PostError::Temporary)?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if is_retryable(status) {
+            Err(PostError::Temporary(anyhow::anyhow!("Webhook returned {status}")))
+        } else {
+            Err(PostError::Permanent(anyhow::anyhow!("Webhook returned {status}")))
+        }
+    }
+}
+
+enum PostError {
+    /// Worth another attempt: connection failures, timeouts, server errors and rate limits
+    Temporary(anyhow::Error),
+    Permanent(anyhow::Error),
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Wait before retry `attempt` (counting from 0): `base` doubled per attempt, capped
+fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF)
+}
+
+/// Splits a `Name: value` header line
+fn parse_header(line: &str) -> Result<(HeaderName, HeaderValue)> {
+    let Some((name, value)) = line.split_once(':') else {
+        bail!("Webhook auth_header must look like `Name: value`");
+    };
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).context("Invalid webhook auth_header name")?;
+    let mut value = HeaderValue::from_str(value.trim()).context("Invalid webhook auth_header value")?;
+    // Keeps the token out of debug output
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn sample(interface: &str, duration_secs: f64, download_bps: f64, upload_bps: f64) -> BandwidthSample {
+        BandwidthSample {
+            timestamp: Local::now(),
+            interface_name: interface.to_string(),
+            duration_secs,
+            download_bps,
+            upload_bps,
+        }
+    }
+
+    #[test]
+    fn test_summary_averages_by_duration() {
+        let samples = [
+            sample("eth0", 60.0, 1000.0, 100.0),
+            sample("eth0", 30.0, 4000.0, 400.0),
+            sample("wlan0", 60.0, 10.0, 5.0),
+        ];
+        let usage = UsageSummary {
+            connections: 3,
+            packets: 40,
+            bytes: 9000,
+            applications: vec![("HTTPS".to_string(), 30, 8000), ("DNS".to_string(), 10, 1000)],
+            hosts: Vec::new(),
+        };
+        let now = Local::now();
+        let summary = StatsSummary::new(now, now, "eth0".to_string(), &samples, &usage);
+
+        assert_eq!(
+            summary.bandwidth[0],
+            InterfaceBandwidth {
+                name: "eth0".to_string(),
+                download_bps: 2000.0,
+                upload_bps: 200.0,
+                peak_download_bps: 4000.0,
+                peak_upload_bps: 400.0,
+            }
+        );
+        assert_eq!(summary.bandwidth[1].name, "wlan0");
+        assert_eq!(summary.traffic.bytes, 9000);
+        assert_eq!(summary.protocols[0].protocol, "HTTPS");
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let base = Duration::from_secs(2);
+        assert_eq!(backoff_delay(base, 0), Duration::from_secs(2));
+        assert_eq!(backoff_delay(base, 3), Duration::from_secs(16));
+        assert_eq!(backoff_delay(base, 40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(WebhookExporter::new(&WebhookConfig::default()).unwrap().is_none());
+        let config = |url: &str, auth: Option<&str>| WebhookConfig {
+            url: Some(url.to_string()),
+            auth_header: auth.map(str::to_string),
+            ..WebhookConfig::default()
+        };
+        assert!(WebhookExporter::new(&config("ftp://example.com", None)).is_err());
+        assert!(WebhookExporter::new(&config("https://example.com", Some("Bearer abc"))).is_err());
+        let exporter = WebhookExporter::new(&config("https://example.com/hook", Some("X-Api-Key: abc")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(exporter.auth_header.unwrap().0, "x-api-key");
+    }
+
+    /// Answers each connection with the next status and returns the requests it read
+    async fn serve(statuses: Vec<u16>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                // Read until the JSON body is complete
+                while !request.ends_with(b"}") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let response = format!("HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                socket.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request).to_string());
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_push_retries_server_errors_with_auth_header() {
+        let (url, server) = serve(vec![503, 200]).await;
+        let exporter = WebhookExporter::new(&WebhookConfig {
+            url: Some(url),
+            auth_header: Some("Authorization: Bearer secret".to_string()),
+            backoff_secs: 0,
+            ..WebhookConfig::default()
+        })
+        .unwrap()
+        .unwrap();
+        let now = Local::now();
+        let summary = StatsSummary::new(now, now, "eth0".to_string(), &[], &UsageSummary::default());
+
+        exporter.push(&summary).await.unwrap();
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].to_lowercase().contains("authorization: bearer secret"));
+        assert!(requests[1].contains("\"interface\":\"eth0\""));
+    }
+
+    #[tokio::test]
+    async fn test_push_does_not_retry_client_errors() {
+        let (url, server) = serve(vec![404]).await;
+        let exporter = WebhookExporter::new(&WebhookConfig {
+            url: Some(url),
+            backoff_secs: 0,
+            ..WebhookConfig::default()
+        })
+        .unwrap()
+        .unwrap();
+        let now = Local::now();
+        let summary = StatsSummary::new(now, now, "eth0".to_string(), &[], &UsageSummary::default());
+
+        let error = exporter.push(&summary).await.unwrap_err();
+        assert!(error.to_string().contains("404"));
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+}
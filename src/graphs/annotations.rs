@@ -0,0 +1,203 @@
+// Event markers on time-based charts
+// Interface outages, alerts and security events stored alongside the traffic are drawn as
+// vertical markers over the chart, so a spike or a dip can be put next to what caused it.
+// Events are counted per bucket of the graph window, like the traffic they are drawn over
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use plotters::coord::types::{RangedCoordf64, RangedDateTime};
+use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::graphs::terminal::{TerminalColor, TerminalMarker};
+
+/// Longest marker label drawn on an image; the full text is in the JSON export
+const MAX_LABEL_CHARS: usize = 32;
+
+/// Rows marker labels are staggered over so neighbouring labels do not overlap
+const LABEL_ROWS: usize = 4;
+
+/// Events listed under a terminal chart before the rest are only counted
+const MAX_TERMINAL_EVENTS: usize = 10;
+
+/// What kind of stored event a marker stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationKind {
+    /// An interface went down or disappeared, from `interface_events`
+    InterfaceDown,
+    /// An alert shown by the live dashboard, from the audit log
+    Alert,
+    /// A port scan, SYN flood or other flag raised by the analyzer, from `security_events`
+    SecurityEvent,
+}
+
+impl AnnotationKind {
+    pub const ALL: [AnnotationKind; 3] = [Self::InterfaceDown, Self::Alert, Self::SecurityEvent];
+
+    /// Maps the kind column of `DatabaseManager::event_annotations`
+    pub fn from_sql(kind: &str) -> Option<Self> {
+        match kind {
+            "interface" => Some(Self::InterfaceDown),
+            "alert" => Some(Self::Alert),
+            "security" => Some(Self::SecurityEvent),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InterfaceDown => "Interface down",
+            Self::Alert => "Alert",
+            Self::SecurityEvent => "Security event",
+        }
+    }
+
+    fn color(&self) -> RGBColor {
+        match self {
+            Self::InterfaceDown => RGBColor(96, 96, 96),
+            Self::Alert => RGBColor(255, 140, 0),
+            Self::SecurityEvent => RGBColor(178, 34, 34),
+        }
+    }
+
+    fn terminal_color(&self) -> TerminalColor {
+        match self {
+            Self::InterfaceDown => TerminalColor::Cyan,
+            Self::Alert => TerminalColor::Yellow,
+            Self::SecurityEvent => TerminalColor::Red,
+        }
+    }
+
+    /// Letter marking the event under a terminal chart
+    fn glyph(&self) -> char {
+        match self {
+            Self::InterfaceDown => 'D',
+            Self::Alert => 'A',
+            Self::SecurityEvent => 'S',
+        }
+    }
+}
+
+/// Events of one kind and label in one bucket of the graph window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphAnnotation {
+    /// Start of the bucket
+    pub timestamp: DateTime<Utc>,
+    pub kind: AnnotationKind,
+    /// e.g. "eth0 down", "PortScan" or the alert's text
+    pub label: String,
+    pub count: u64,
+}
+
+impl GraphAnnotation {
+    /// The label with the number of events when there were several
+    pub fn text(&self) -> String {
+        if self.count > 1 {
+            format!("{} ×{}", self.label, self.count)
+        } else {
+            self.label.clone()
+        }
+    }
+}
+
+/// Draws `annotations` within the chart's time range as vertical lines up to `y_max`, each
+/// labelled near the top, with one legend entry per kind drawn
+pub fn draw_annotations<DB: DrawingBackend>(
+    chart: &mut ChartContext<'_, DB, Cartesian2d<RangedDateTime<DateTime<Utc>>, RangedCoordf64>>,
+    annotations: &[GraphAnnotation],
+    y_max: f64,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let range = chart.x_range();
+    let visible: Vec<&GraphAnnotation> =
+        annotations.iter().filter(|a| a.timestamp >= range.start && a.timestamp <= range.end).collect();
+
+    for (index, annotation) in visible.iter().enumerate() {
+        let color = annotation.kind.color();
+        let row = (index % LABEL_ROWS) as f64;
+        let mut text: String = annotation.text().chars().take(MAX_LABEL_CHARS).collect();
+        if text.chars().count() < annotation.text().chars().count() {
+            text.push('…');
+        }
+        chart.draw_series(std::iter::once(PathElement::new(
+            vec![(annotation.timestamp, 0.0), (annotation.timestamp, y_max)],
+            color.mix(0.6).stroke_width(1),
+        )))?;
+        chart.draw_series(std::iter::once(Text::new(
+            text,
+            (annotation.timestamp, y_max * (0.97 - row * 0.05)),
+            ("sans-serif", 13).into_font().color(&color),
+        )))?;
+    }
+
+    for kind in AnnotationKind::ALL {
+        if visible.iter().any(|a| a.kind == kind) {
+            let color = kind.color();
+            chart
+                .draw_series(std::iter::empty::<PathElement<(DateTime<Utc>, f64)>>())?
+                .label(kind.name())
+                .legend(move |(x, y)| PathElement::new(vec![(x + 5, y - 5), (x + 5, y + 5)], color));
+        }
+    }
+    Ok(())
+}
+
+/// Markers for the row under a terminal chart, at x positions in seconds like its points
+pub fn terminal_markers(annotations: &[GraphAnnotation]) -> Vec<TerminalMarker> {
+    annotations
+        .iter()
+        .map(|a| TerminalMarker {
+            x: a.timestamp.timestamp() as f64,
+            glyph: a.kind.glyph(),
+            color: a.kind.terminal_color(),
+        })
+        .collect()
+}
+
+/// One line per event under a terminal chart, oldest first
+pub fn terminal_event_list(annotations: &[GraphAnnotation]) -> String {
+    if annotations.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("Events:\n");
+    for annotation in annotations.iter().take(MAX_TERMINAL_EVENTS) {
+        out.push_str(&format!(
+            "  {} {}  {}\n",
+            annotation.kind.glyph(),
+            annotation.timestamp.with_timezone(&chrono::Local).format("%m-%d %H:%M"),
+            annotation.text()
+        ));
+    }
+    if annotations.len() > MAX_TERMINAL_EVENTS {
+        out.push_str(&format!("  ... and {} more\n", annotations.len() - MAX_TERMINAL_EVENTS));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_list_counts_repeats_and_is_capped() {
+        let annotation = |minute: i64, kind, count| GraphAnnotation {
+            timestamp: DateTime::from_timestamp(1_714_557_600 + minute * 60, 0).unwrap(),
+            kind,
+            label: "eth0 down".to_string(),
+            count,
+        };
+        assert_eq!(annotation(0, AnnotationKind::InterfaceDown, 1).text(), "eth0 down");
+        assert_eq!(annotation(0, AnnotationKind::SecurityEvent, 3).text(), "eth0 down ×3");
+        assert_eq!(terminal_event_list(&[]), "");
+
+        let many: Vec<GraphAnnotation> = (0..12).map(|m| annotation(m, AnnotationKind::Alert, 1)).collect();
+        let list = terminal_event_list(&many);
+        assert_eq!(list.lines().count(), 1 + MAX_TERMINAL_EVENTS + 1);
+        assert!(list.lines().nth(1).unwrap().starts_with("  A "));
+        assert!(list.ends_with("... and 2 more\n"));
+        assert_eq!(terminal_markers(&many)[1].x - terminal_markers(&many)[0].x, 60.0);
+    }
+}
@@ -1,14 +1,28 @@
-use crate::graphs::{GraphConfig, GraphRenderer};
-use crate::cli::graph_commands::DatabaseManager;
+use crate::graphs::annotations::{draw_annotations, terminal_event_list, terminal_markers, GraphAnnotation};
+use crate::graphs::terminal::{TerminalCanvas, TerminalColor, TerminalSeries};
+use crate::graphs::time_range::GraphWindow;
+use crate::graphs::{
+    time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, TimeSeries, SERIES_COLORS,
+    TERMINAL_SERIES_COLORS,
+};
+use crate::graphs::DatabaseManager;
+use crate::display::Scale;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use std::path::Path;
 
 pub struct BandwidthGraph {
     pub config: GraphConfig,
     pub data: Vec<BandwidthDataPoint>,
+    /// Lines drawn by the speed chart, split according to `split`
+    pub series: Vec<TimeSeries>,
     pub interface: Option<String>,
+    /// Stored events marked on the speed chart
+    pub annotations: Vec<GraphAnnotation>,
+    split: SeriesSplit,
+    event_markers: bool,
 }
 
 #[derive(Clone)]
@@ -20,126 +34,166 @@ pub struct BandwidthDataPoint {
     pub total_tx: u64,
 }
 
+/// How the speed chart splits traffic into lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeriesSplit {
+    /// Download and upload as two lines
+    #[default]
+    Direction,
+    /// One line per interface
+    Interface,
+}
+
+impl SeriesSplit {
+    /// Maps a `--series` value to a split
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "direction" => Ok(Self::Direction),
+            "interface" => Ok(Self::Interface),
+            _ => Err(anyhow::anyhow!("Invalid series split: {} (expected direction or interface)", value)),
+        }
+    }
+}
+
 impl BandwidthGraph {
     pub fn new(config: GraphConfig) -> Self {
         Self {
             config,
             data: Vec::new(),
+            series: Vec::new(),
             interface: None,
+            annotations: Vec::new(),
+            split: SeriesSplit::default(),
+            event_markers: true,
         }
     }
 
+    pub fn with_series_split(mut self, split: SeriesSplit) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Whether `load_data` also loads interface outages, alerts and security events to mark
+    pub fn with_event_markers(mut self, enabled: bool) -> Self {
+        self.event_markers = enabled;
+        self
+    }
+
+    /// Total download and upload readings gathered elsewhere, such as the live dashboard's
+    pub fn with_data(mut self, data: Vec<BandwidthDataPoint>) -> Self {
+        self.series = direction_series(&data);
+        self.data = data;
+        self
+    }
+
+    /// Loads traffic for `interface`, which may list several interfaces separated by commas,
+    /// aggregated by the database into buckets of the window's resolution
     pub async fn load_data(
         &mut self,
         db: &DatabaseManager,
-        start_time: DateTime<Utc>,
-        end_time: DateTime<Utc>,
+        window: &GraphWindow,
         interface: Option<String>,
     ) -> Result<()> {
         self.interface = interface.clone();
-        
-        let query = if let Some(ref _iface) = interface {
-            "SELECT timestamp, bytes_per_second, bytes_per_second, total_bytes, total_bytes 
-             FROM packet_stats 
-             WHERE interface_name = ? AND timestamp BETWEEN ? AND ?
-             ORDER BY timestamp"
-        } else {
-            "SELECT timestamp, SUM(bytes_per_second) as bytes_per_second, SUM(bytes_per_second) as bytes_per_second, 
-                    SUM(total_bytes) as total_bytes, SUM(total_bytes) as total_bytes
-             FROM packet_stats 
-             WHERE timestamp BETWEEN ? AND ?
-             GROUP BY timestamp
-             ORDER BY timestamp"
-        };
 
-        let conn = db.connection.lock().unwrap();
-        let mut stmt = conn.prepare(query)?;
-        
-        let mapper = |row: &rusqlite::Row| {
-            Ok(BandwidthDataPoint {
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(0)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
-                download_speed: row.get(1)?,
-                upload_speed: row.get(2)?,
-                total_rx: row.get(3)?,
-                total_tx: row.get(4)?,
-            })
-        };
-        
-        let rows = if let Some(ref iface) = interface {
-            stmt.query_map(
-                [iface.as_str(), &start_time.to_rfc3339(), &end_time.to_rfc3339()],
-                mapper,
-            )?
-        } else {
-            stmt.query_map(
-                [&start_time.to_rfc3339(), &end_time.to_rfc3339()],
-                mapper,
-            )?
+        let interfaces = parse_interface_list(interface.as_deref());
+        self.data = db.bandwidth_buckets(window, &interfaces)?;
+
+        self.series = match self.split {
+            SeriesSplit::Direction => direction_series(&self.data),
+            SeriesSplit::Interface => db.interface_rate_buckets(window, &interfaces)?,
         };
+        if self.event_markers {
+            self.annotations = db.event_annotations(window, &interfaces)?;
+        }
 
-        self.data = rows.collect::<Result<Vec<_>, _>>()?;
         Ok(())
     }
 
-    pub fn render_speed_chart(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
-        root.fill(&WHITE)?;
-
-        let title = if let Some(ref iface) = self.interface {
+    fn speed_title(&self) -> String {
+        if let Some(ref iface) = self.interface {
             format!("Bandwidth Usage - {iface}")
+        } else if self.split == SeriesSplit::Interface {
+            "Bandwidth Usage by Interface".to_string()
         } else {
             "Total Bandwidth Usage".to_string()
-        };
+        }
+    }
 
-        let max_speed = self.data.iter()
-            .map(|d| d.download_speed.max(d.upload_speed))
-            .fold(0.0, f64::max);
+    fn speed_unit(&self) -> Scale {
+        self.config.units.rate_scale(self.series.iter().map(TimeSeries::max_value).fold(0.0, f64::max))
+    }
+
+    pub fn render_speed_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_speed_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_speed_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_speed_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let unit = self.speed_unit();
+        let max_speed = self.series.iter().map(|s| unit.scale(s.max_value())).fold(0.0, f64::max);
+        let y_max = (max_speed * 1.1).max(1.0);
 
         let mut chart = ChartBuilder::on(&root)
-            .caption(&title, ("sans-serif", 50).into_font())
+            .caption(self.speed_title(), ("sans-serif", 50).into_font())
             .margin(10)
             .x_label_area_size(40)
             .y_label_area_size(60)
             .build_cartesian_2d(
                 self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
                     ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
-                0f64..max_speed * 1.1,
+                0f64..y_max,
             )?;
 
         chart
             .configure_mesh()
             .x_desc("Time")
-            .y_desc("Speed (bytes/s)")
+            .y_desc(format!("Speed ({})", unit.label()))
+            .y_label_formatter(&|v| self.config.units.axis(*v))
             .draw()?;
 
-        chart
-            .draw_series(LineSeries::new(
-                self.data.iter().map(|d| (d.timestamp, d.download_speed)),
-                &BLUE,
-            ))?
-            .label("Download Speed")
-            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], BLUE));
+        for (index, series) in self.series.iter().enumerate() {
+            let color = SERIES_COLORS[index % SERIES_COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(
+                    series.points.iter().map(|&(t, v)| (t, unit.scale(v))),
+                    &color,
+                ))?
+                .label(&series.label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], color));
+        }
+        draw_annotations(&mut chart, &self.annotations, y_max)?;
 
         chart
-            .draw_series(LineSeries::new(
-                self.data.iter().map(|d| (d.timestamp, d.upload_speed)),
-                &RED,
-            ))?
-            .label("Upload Speed")
-            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
-
-        chart.configure_series_labels().draw()?;
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
         root.present()?;
 
         Ok(())
     }
 
-    pub fn render_total_usage_chart(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_total_usage_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_total_usage_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_total_usage_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_total_usage_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -151,6 +205,7 @@ impl BandwidthGraph {
         let max_bytes = self.data.iter()
             .map(|d| (d.total_rx as f64).max(d.total_tx as f64))
             .fold(0.0, f64::max);
+        let unit = self.config.units.volume_scale(max_bytes);
 
         let mut chart = ChartBuilder::on(&root)
             .caption(&title, ("sans-serif", 50).into_font())
@@ -160,18 +215,19 @@ impl BandwidthGraph {
             .build_cartesian_2d(
                 self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
                     ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
-                0f64..max_bytes * 1.1,
+                0f64..unit.scale(max_bytes) * 1.1,
             )?;
 
         chart
             .configure_mesh()
             .x_desc("Time")
-            .y_desc("Total Bytes")
+            .y_desc(format!("Total ({})", unit.label()))
+            .y_label_formatter(&|v| self.config.units.axis(*v))
             .draw()?;
 
         chart
             .draw_series(LineSeries::new(
-                self.data.iter().map(|d| (d.timestamp, d.total_rx as f64)),
+                self.data.iter().map(|d| (d.timestamp, unit.scale(d.total_rx as f64))),
                 &GREEN,
             ))?
             .label("Total Download")
@@ -179,7 +235,7 @@ impl BandwidthGraph {
 
         chart
             .draw_series(LineSeries::new(
-                self.data.iter().map(|d| (d.timestamp, d.total_tx as f64)),
+                self.data.iter().map(|d| (d.timestamp, unit.scale(d.total_tx as f64))),
                 &MAGENTA,
             ))?
             .label("Total Upload")
@@ -190,26 +246,85 @@ impl BandwidthGraph {
 
         Ok(())
     }
+
+    /// Renders the speed series as a text chart for the terminal
+    pub fn render_speed_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let series: Vec<TerminalSeries> = self.series.iter().enumerate()
+            .map(|(index, s)| TerminalSeries {
+                label: s.label.clone(),
+                points: s.points.iter().map(|(t, v)| (t.timestamp() as f64, *v)).collect(),
+                color: TERMINAL_SERIES_COLORS[index % TERMINAL_SERIES_COLORS.len()],
+            })
+            .collect();
+
+        let unit = self.speed_unit();
+        let chart = canvas.line_chart_with_markers(
+            &self.speed_title(),
+            &series,
+            &terminal_markers(&self.annotations),
+            self.time_labels(),
+            |v| format!("{} {}", self.config.units.number(unit.scale(v), 1), unit.label()),
+        );
+        chart + &terminal_event_list(&self.annotations)
+    }
+
+    /// Renders cumulative download/upload totals as a text chart for the terminal
+    pub fn render_total_usage_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Total Data Usage - {iface}")
+        } else {
+            "Total Data Usage".to_string()
+        };
+
+        let series = vec![
+            TerminalSeries {
+                label: "Total Download".to_string(),
+                points: self.data.iter().map(|d| (d.timestamp.timestamp() as f64, d.total_rx as f64)).collect(),
+                color: TerminalColor::Green,
+            },
+            TerminalSeries {
+                label: "Total Upload".to_string(),
+                points: self.data.iter().map(|d| (d.timestamp.timestamp() as f64, d.total_tx as f64)).collect(),
+                color: TerminalColor::Magenta,
+            },
+        ];
+
+        canvas.line_chart(&title, &series, self.time_labels(), |v| self.config.units.bytes(v))
+    }
+
+    fn time_labels(&self) -> (String, String) {
+        time_axis_labels(
+            self.data.first().map(|d| d.timestamp),
+            self.data.last().map(|d| d.timestamp),
+        )
+    }
 }
 
 impl GraphRenderer for BandwidthGraph {
     fn render(&self, output_path: &Path) -> Result<()> {
-        self.render_speed_chart(output_path)
+        self.render_speed_chart(output_path, ImageFormat::from_path(output_path))
     }
 }
 
-#[allow(dead_code)]
-pub fn format_bytes(bytes: f64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size = bytes;
-    let mut unit_index = 0;
-    
-    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size /= 1024.0;
-        unit_index += 1;
-    }
-    
-    format!("{:.1} {}", size, UNITS[unit_index])
+/// Splits a `--interface` value such as `eth0,wlan0`; empty means every interface
+fn parse_interface_list(interface: Option<&str>) -> Vec<String> {
+    interface
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Download and upload speed as two lines
+fn direction_series(data: &[BandwidthDataPoint]) -> Vec<TimeSeries> {
+    vec![
+        TimeSeries::new("Download Speed", data.iter().map(|d| (d.timestamp, d.download_speed)).collect()),
+        TimeSeries::new("Upload Speed", data.iter().map(|d| (d.timestamp, d.upload_speed)).collect()),
+    ]
 }
 
 #[allow(dead_code)]
@@ -232,4 +347,61 @@ pub fn create_bandwidth_sparkline(data: &[BandwidthDataPoint]) -> String {
             sparkline_chars[normalized.min(7)]
         })
         .collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interface_list() {
+        assert!(parse_interface_list(None).is_empty());
+        assert_eq!(parse_interface_list(Some("eth0")), vec!["eth0"]);
+        assert_eq!(parse_interface_list(Some("eth0, wlan0,")), vec!["eth0", "wlan0"]);
+    }
+
+    #[test]
+    fn test_series_split_parse() {
+        assert_eq!(SeriesSplit::parse("direction").unwrap(), SeriesSplit::Direction);
+        assert_eq!(SeriesSplit::parse("Interface").unwrap(), SeriesSplit::Interface);
+        assert!(SeriesSplit::parse("protocol").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_event_markers_land_on_traffic_buckets() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DatabaseManager::new(dir.path().join("packets.db")).await.unwrap();
+        crate::storage::Database::open(dir.path().join("packets.db"))
+            .unwrap()
+            .connection()
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps) VALUES
+                    ('2024-05-01 10:01:00', 'eth0', 60.0, 1000.0, 100.0),
+                    ('2024-05-01 10:02:00', 'eth0', 60.0, 5000.0, 200.0),
+                    ('2024-05-01 10:03:00', 'eth0', 60.0, 0.0, 0.0);
+                 INSERT INTO interface_events (timestamp, interface_name, change) VALUES
+                    ('2024-05-01 10:02:30', 'eth0', 'down');
+                 INSERT INTO security_events (timestamp, interface_name, event_type, description) VALUES
+                    ('2024-05-01 10:02:10', 'eth0', 'PortScan', '20 ports');",
+            )
+            .unwrap();
+        let window =
+            GraphWindow::resolve("1h", Some("2024-05-01 10:00"), Some("2024-05-01 10:10"), Some("1m"), Utc::now())
+                .unwrap();
+
+        for split in [SeriesSplit::Direction, SeriesSplit::Interface] {
+            let mut graph = BandwidthGraph::new(GraphConfig::default()).with_series_split(split);
+            graph.load_data(&db, &window, None).await.unwrap();
+
+            assert_eq!(graph.annotations.len(), 2);
+            for annotation in &graph.annotations {
+                // The spike the events explain is in the bucket they are drawn on
+                let bucket = graph.data.iter().find(|d| d.timestamp == annotation.timestamp).unwrap();
+                assert_eq!(bucket.download_speed, 5000.0);
+                assert!(graph.series.iter().all(|s| s.points.iter().any(|p| p.0 == annotation.timestamp)));
+            }
+        }
+    }
+}
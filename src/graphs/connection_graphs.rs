@@ -1,16 +1,64 @@
-use crate::graphs::{GraphConfig, GraphRenderer};
-use crate::cli::graph_commands::DatabaseManager;
-use chrono::Timelike;
+use crate::graphs::terminal::{TerminalCanvas, TerminalColor, TerminalSeries};
+use crate::graphs::{time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, SERIES_COLORS};
+use crate::graphs::DatabaseManager;
+use crate::models::packet::is_private;
+use crate::graphs::time_range::{sql_time, GraphWindow};
+use crate::models::service::transport_from_name;
+use crate::models::{ServiceMap, TagBook};
+use crate::storage::packet_storage::parse_local_timestamp;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use plotters::coord::Shift;
 use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
 use std::collections::HashMap;
 use std::path::Path;
 
+/// Links drawn in the flow diagram; quieter ones are left out
+pub const FLOW_LINKS: usize = 30;
+
+/// Width of a node bar in the flow diagram, in pixels
+const FLOW_NODE_WIDTH: i32 = 14;
+
+/// Vertical space between the nodes of a flow diagram column, in pixels
+const FLOW_NODE_GAP: i32 = 8;
+
+/// Nodes down one side of the flow diagram and the traffic through each
+type FlowColumn = Vec<(String, u64)>;
+
 pub struct ConnectionGraph {
     pub config: GraphConfig,
     pub data: Vec<ConnectionDataPoint>,
     pub interface: Option<String>,
+    /// Host names of connection addresses, for those that were looked up and have one
+    pub host_names: HashMap<String, String>,
+    /// Service names shown after ports, e.g. "443/https"
+    pub services: ServiceMap,
+    /// User tags shown next to remote addresses
+    pub tags: TagBook,
+    /// New connections and their traffic per bucket, in time order
+    pub timeline: Vec<ConnectionBucket>,
+}
+
+/// Connections first seen within one bucket of the graph's resolution
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionBucket {
+    pub timestamp: DateTime<Utc>,
+    pub connections: u64,
+    /// Bytes carried by those connections
+    pub bytes: u64,
+}
+
+/// Traffic between a local endpoint and a remote host over the graph's window,
+/// one link of the flow diagram
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrafficFlow {
+    /// Local address and port, e.g. "192.168.1.10:22/ssh"; ports without a service name are
+    /// folded into "192.168.1.10:ephemeral" since clients pick them at random
+    pub local: String,
+    pub remote_ip: String,
+    pub connections: u64,
+    pub bytes: u64,
 }
 
 #[derive(Clone)]
@@ -35,44 +83,49 @@ impl ConnectionGraph {
             config,
             data: Vec::new(),
             interface: None,
+            host_names: HashMap::new(),
+            services: ServiceMap::new(),
+            tags: TagBook::default(),
+            timeline: Vec::new(),
         }
     }
 
+    pub fn with_tags(mut self, tags: TagBook) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Host name looked up for `address`, if any
+    pub fn host_name(&self, address: &str) -> Option<&str> {
+        self.host_names.get(address).map(String::as_str)
+    }
+
+    /// Loads the window's connections for the port and busiest-connection views, and their
+    /// counts over time bucketed by the database at the window's resolution
     pub async fn load_data(
         &mut self,
         db: &DatabaseManager,
-        start_time: DateTime<Utc>,
-        end_time: DateTime<Utc>,
+        window: &GraphWindow,
         interface: Option<String>,
     ) -> Result<()> {
         self.interface = interface.clone();
-        
-        let query = if let Some(ref _iface) = interface {
-            "SELECT first_seen, source_ip, dest_ip, source_port, dest_port, protocol, 'active', 
-                    packet_count, packet_count, byte_count, byte_count
-             FROM connections 
-             WHERE first_seen BETWEEN ? AND ?
-             ORDER BY first_seen"
-        } else {
+        self.timeline = db.connection_buckets(window)?;
+
+        let conn = db.reader();
+        let mut stmt = conn.prepare(
             "SELECT first_seen, source_ip, dest_ip, source_port, dest_port, protocol, 'active',
                     packet_count, packet_count, byte_count, byte_count
-             FROM connections 
+             FROM connections
              WHERE first_seen BETWEEN ? AND ?
-             ORDER BY first_seen"
-        };
-
-        let conn = db.connection.lock().unwrap();
-        let mut stmt = conn.prepare(query)?;
-        
-        let mapper = |row: &rusqlite::Row| {
+             ORDER BY first_seen",
+        )?;
+        let rows = stmt.query_map([sql_time(window.start), sql_time(window.end)], |row| {
             Ok(ConnectionDataPoint {
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(0)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
+                timestamp: parse_local_timestamp(&row.get::<_, String>(0)?).with_timezone(&Utc),
                 source_ip: row.get(1)?,
                 dest_ip: row.get(2)?,
-                source_port: row.get(3)?,
-                dest_port: row.get(4)?,
+                source_port: row.get::<_, Option<u16>>(3)?.unwrap_or(0),
+                dest_port: row.get::<_, Option<u16>>(4)?.unwrap_or(0),
                 protocol: row.get(5)?,
                 state: row.get(6)?,
                 packets_sent: row.get(7)?,
@@ -80,20 +133,24 @@ impl ConnectionGraph {
                 bytes_sent: row.get(9)?,
                 bytes_received: row.get(10)?,
             })
-        };
-        
-        let rows = stmt.query_map(
-            [&start_time.to_rfc3339(), &end_time.to_rfc3339()],
-            mapper,
-        )?;
+        })?;
 
         self.data = rows.collect::<Result<Vec<_>, _>>()?;
         Ok(())
     }
 
-    pub fn render_connection_timeline(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_connection_timeline(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_connection_timeline(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_connection_timeline(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_connection_timeline<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -102,8 +159,8 @@ impl ConnectionGraph {
             "Connection Timeline".to_string()
         };
 
-        let connections_per_minute = self.get_connections_per_minute();
-        let max_connections = connections_per_minute.values().max().unwrap_or(&0);
+        let timeline_data = self.get_connections_over_time();
+        let max_connections = timeline_data.iter().map(|(_, count)| *count).max().unwrap_or(0);
 
         let mut chart = ChartBuilder::on(&root)
             .caption(&title, ("sans-serif", 50).into_font())
@@ -111,21 +168,18 @@ impl ConnectionGraph {
             .x_label_area_size(40)
             .y_label_area_size(60)
             .build_cartesian_2d(
-                self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
-                    ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
-                0u64..*max_connections,
+                self.timeline.first().map(|b| b.timestamp).unwrap_or_else(Utc::now)
+                    ..self.timeline.last().map(|b| b.timestamp).unwrap_or_else(Utc::now),
+                0u64..max_connections,
             )?;
 
         chart
             .configure_mesh()
             .x_desc("Time")
             .y_desc("Active Connections")
+            .y_label_formatter(&|v| self.config.units.number(*v as f64, 0))
             .draw()?;
 
-        let timeline_data: Vec<_> = connections_per_minute.iter()
-            .map(|(timestamp, count)| (*timestamp, *count))
-            .collect();
-
         chart
             .draw_series(LineSeries::new(timeline_data, &BLUE))?
             .label("Active Connections")
@@ -136,9 +190,18 @@ impl ConnectionGraph {
         Ok(())
     }
 
-    pub fn render_port_distribution(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_port_distribution(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_port_distribution(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_port_distribution(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_port_distribution<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -172,6 +235,7 @@ impl ConnectionGraph {
             .configure_mesh()
             .x_desc("Port")
             .y_desc("Connection Count")
+            .y_label_formatter(&|v| self.config.units.number(*v as f64, 0))
             .x_label_formatter(&|x| {
                 if *x as usize >= sorted_ports.len() {
                     String::new()
@@ -190,9 +254,18 @@ impl ConnectionGraph {
         Ok(())
     }
 
-    pub fn render_traffic_flow(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_traffic_flow(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_traffic_flow(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_traffic_flow(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_traffic_flow<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -202,7 +275,8 @@ impl ConnectionGraph {
         };
 
         let traffic_data = self.get_traffic_over_time();
-        let max_traffic = traffic_data.iter().map(|(_, bytes)| *bytes).max().unwrap_or(0);
+        let max_traffic = traffic_data.iter().map(|(_, bytes)| *bytes).max().unwrap_or(0) as f64;
+        let unit = self.config.units.volume_scale(max_traffic);
 
         let mut chart = ChartBuilder::on(&root)
             .caption(&title, ("sans-serif", 50).into_font())
@@ -210,19 +284,23 @@ impl ConnectionGraph {
             .x_label_area_size(40)
             .y_label_area_size(60)
             .build_cartesian_2d(
-                self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
-                    ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
-                0u64..max_traffic,
+                self.timeline.first().map(|b| b.timestamp).unwrap_or_else(Utc::now)
+                    ..self.timeline.last().map(|b| b.timestamp).unwrap_or_else(Utc::now),
+                0f64..unit.scale(max_traffic),
             )?;
 
         chart
             .configure_mesh()
             .x_desc("Time")
-            .y_desc("Bytes Transferred")
+            .y_desc(format!("Transferred ({})", unit.label()))
+            .y_label_formatter(&|v| self.config.units.axis(*v))
             .draw()?;
 
         chart
-            .draw_series(LineSeries::new(traffic_data, &RED))?
+            .draw_series(LineSeries::new(
+                traffic_data.into_iter().map(|(t, bytes)| (t, unit.scale(bytes as f64))),
+                &RED,
+            ))?
             .label("Total Traffic")
             .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
 
@@ -231,29 +309,238 @@ impl ConnectionGraph {
         Ok(())
     }
 
-    fn get_connections_per_minute(&self) -> HashMap<DateTime<Utc>, u64> {
-        let mut connections_per_minute = HashMap::new();
-        
-        for conn in &self.data {
-            let minute = conn.timestamp.with_second(0).unwrap().with_nanosecond(0).unwrap();
-            *connections_per_minute.entry(minute).or_insert(0) += 1;
+    pub fn render_traffic_flows(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_traffic_flows(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_traffic_flows(SVGBackend::new(output_path, size).into_drawing_area()),
         }
-        
-        connections_per_minute
     }
 
-    fn get_traffic_over_time(&self) -> Vec<(DateTime<Utc>, u64)> {
-        let mut traffic_per_minute = HashMap::new();
-        
+    /// Sankey diagram: local endpoints on the left, remote hosts on the right, and a band
+    /// between them as wide as the traffic they exchanged
+    fn draw_traffic_flows<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let title = if let Some(ref iface) = self.interface {
+            format!("Traffic Flows - {iface}")
+        } else {
+            "Traffic Flows".to_string()
+        };
+        let root = root.titled(&title, ("sans-serif", 50).into_font())?;
+
+        let flows = self.get_traffic_flows(FLOW_LINKS);
+        let (locals, remotes) = flow_columns(&flows);
+        let (width, height) = root.dim_in_pixel();
+        let (width, height) = (width as i32, height as i32);
+        let left_x = width * 3 / 10;
+        let right_x = width * 7 / 10 - FLOW_NODE_WIDTH;
+        let label_style = ("sans-serif", 14).into_font();
+
+        // Pixels per byte in each column, after the gaps between its nodes
+        let total = flows.iter().map(|f| f.bytes).sum::<u64>().max(1) as f64;
+        let scale = |nodes: usize| {
+            (height - 2 * FLOW_NODE_GAP - FLOW_NODE_GAP * nodes.saturating_sub(1) as i32).max(nodes as i32) as f64 / total
+        };
+        let (left_scale, right_scale) = (scale(locals.len()), scale(remotes.len()));
+        let node_tops = |nodes: &[(String, u64)], scale: f64| {
+            let mut top = FLOW_NODE_GAP as f64;
+            nodes
+                .iter()
+                .map(|(_, bytes)| {
+                    let node_top = top;
+                    top += (*bytes as f64 * scale).max(1.0) + FLOW_NODE_GAP as f64;
+                    node_top
+                })
+                .collect::<Vec<_>>()
+        };
+        let left_tops = node_tops(&locals, left_scale);
+        let right_tops = node_tops(&remotes, right_scale);
+
+        // Bands leave each local node in the order of the remote nodes, and arrive at each
+        // remote node in the order of the local nodes, so they cross as little as possible
+        let index = |nodes: &[(String, u64)], label: &str| nodes.iter().position(|(l, _)| l == label).unwrap_or(0);
+        let mut links: Vec<(usize, usize, u64)> = flows
+            .iter()
+            .map(|f| (index(&locals, &f.local), index(&remotes, &f.remote_ip), f.bytes))
+            .collect();
+        links.sort();
+        let mut left_offsets = vec![0.0; locals.len()];
+        let mut right_offsets = vec![0.0; remotes.len()];
+        for (local, remote, bytes) in links {
+            let (left_height, right_height) = (bytes as f64 * left_scale, bytes as f64 * right_scale);
+            let left_top = left_tops[local] + left_offsets[local];
+            let right_top = right_tops[remote] + right_offsets[remote];
+            left_offsets[local] += left_height;
+            right_offsets[remote] += right_height;
+
+            let (x0, x1) = (left_x + FLOW_NODE_WIDTH, right_x);
+            let mut outline = flow_curve((x0, left_top), (x1, right_top));
+            outline.extend(flow_curve((x1, right_top + right_height), (x0, left_top + left_height)));
+            let color = SERIES_COLORS[local % SERIES_COLORS.len()];
+            root.draw(&Polygon::new(outline, color.mix(0.35).filled()))?;
+        }
+
+        for (column, nodes, tops, scale) in [(0, &locals, &left_tops, left_scale), (1, &remotes, &right_tops, right_scale)] {
+            for (i, ((label, bytes), top)) in nodes.iter().zip(tops.iter()).enumerate() {
+                let bottom = top + (*bytes as f64 * scale).max(1.0);
+                let (x, color) = if column == 0 {
+                    (left_x, SERIES_COLORS[i % SERIES_COLORS.len()])
+                } else {
+                    (right_x, RGBColor(96, 96, 96))
+                };
+                root.draw(&Rectangle::new([(x, *top as i32), (x + FLOW_NODE_WIDTH, bottom as i32)], color.filled()))?;
+
+                let label = if column == 0 { label.clone() } else { self.remote_label(label) };
+                let text = format!("{label}  {}", self.config.units.bytes(*bytes as f64));
+                let middle = ((top + bottom) / 2.0) as i32;
+                let (anchor, text_x) = if column == 0 {
+                    (Pos::new(HPos::Right, VPos::Center), x - 6)
+                } else {
+                    (Pos::new(HPos::Left, VPos::Center), x + FLOW_NODE_WIDTH + 6)
+                };
+                root.draw(&Text::new(text, (text_x, middle), label_style.clone().into_text_style(&root).pos(anchor)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders the flow diagram as text: each local endpoint with the remote hosts it
+    /// exchanged traffic with beneath it
+    pub fn render_traffic_flows_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Traffic Flows - {iface}")
+        } else {
+            "Traffic Flows".to_string()
+        };
+
+        let flows = self.get_traffic_flows(FLOW_LINKS);
+        let (locals, _) = flow_columns(&flows);
+        let groups: Vec<(String, Vec<(String, f64)>)> = locals
+            .into_iter()
+            .map(|(local, _)| {
+                let links = flows
+                    .iter()
+                    .filter(|f| f.local == local)
+                    .map(|f| (self.remote_label(&f.remote_ip), f.bytes as f64))
+                    .collect();
+                (local, links)
+            })
+            .collect();
+
+        canvas.grouped_bar_chart(&title, &groups, |v| self.config.units.bytes(v))
+    }
+
+    /// Renders new connections per bucket as a text chart for the terminal
+    pub fn render_connection_timeline_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Connection Timeline - {iface}")
+        } else {
+            "Connection Timeline".to_string()
+        };
+
+        let series = vec![TerminalSeries {
+            label: "Active Connections".to_string(),
+            points: self
+                .get_connections_over_time()
+                .into_iter()
+                .map(|(t, count)| (t.timestamp() as f64, count as f64))
+                .collect(),
+            color: TerminalColor::Blue,
+        }];
+
+        canvas.line_chart(&title, &series, self.time_labels(), |v| self.config.units.number(v, 0))
+    }
+
+    /// Renders connection counts for the busiest destination ports as a text chart
+    pub fn render_port_distribution_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Port Distribution - {iface}")
+        } else {
+            "Port Distribution".to_string()
+        };
+
+        let mut port_counts: HashMap<(u16, String), u64> = HashMap::new();
         for conn in &self.data {
-            let minute = conn.timestamp.with_second(0).unwrap().with_nanosecond(0).unwrap();
-            let total_bytes = conn.bytes_sent + conn.bytes_received;
-            *traffic_per_minute.entry(minute).or_insert(0) += total_bytes;
+            *port_counts.entry((conn.dest_port, conn.protocol.clone())).or_insert(0) += 1;
         }
-        
-        let mut sorted_traffic: Vec<_> = traffic_per_minute.into_iter().collect();
-        sorted_traffic.sort_by(|a, b| a.0.cmp(&b.0));
-        sorted_traffic
+
+        let mut sorted_ports: Vec<_> = port_counts.into_iter().collect();
+        sorted_ports.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sorted_ports.truncate(20);
+
+        let bars: Vec<(String, f64)> = sorted_ports
+            .into_iter()
+            .map(|((port, protocol), count)| (self.services.label(port, transport_from_name(&protocol)), count as f64))
+            .collect();
+
+        canvas.bar_chart(&title, &bars, TerminalColor::Green, |v| self.config.units.number(v, 0))
+    }
+
+    /// Renders bytes transferred per bucket as a text chart for the terminal
+    pub fn render_traffic_flow_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Traffic Flow - {iface}")
+        } else {
+            "Traffic Flow".to_string()
+        };
+
+        let series = vec![TerminalSeries {
+            label: "Total Traffic".to_string(),
+            points: self
+                .get_traffic_over_time()
+                .into_iter()
+                .map(|(t, bytes)| (t.timestamp() as f64, bytes as f64))
+                .collect(),
+            color: TerminalColor::Red,
+        }];
+
+        canvas.line_chart(&title, &series, self.time_labels(), |v| self.config.units.bytes(v))
+    }
+
+    /// Lists the busiest connections below a terminal chart, with host names where known
+    pub fn render_top_connections_terminal(&self, limit: usize) -> String {
+        let mut rendered = String::from("\nTop Connections:\n");
+        for (i, connection) in self.get_top_connections(limit).iter().enumerate() {
+            let transport = transport_from_name(&connection.protocol);
+            let redactor = self.tags.redactor();
+            let endpoint = |address: &str, port: u16| {
+                let port = self.services.label(port, transport);
+                let shown = redactor.target(address);
+                match self.host_name(address) {
+                    Some(host) => format!("{shown}:{port} ({})", redactor.host_name(host)),
+                    None => format!("{shown}:{port}"),
+                }
+            };
+            rendered.push_str(&format!(
+                "  {}. {} -> {} {} ({})\n",
+                i + 1,
+                endpoint(&connection.source_ip, connection.source_port),
+                endpoint(&connection.dest_ip, connection.dest_port),
+                connection.protocol,
+                self.config.units.bytes(connection.total_bytes as f64)
+            ));
+        }
+        rendered
+    }
+
+    fn time_labels(&self) -> (String, String) {
+        time_axis_labels(
+            self.timeline.first().map(|b| b.timestamp),
+            self.timeline.last().map(|b| b.timestamp),
+        )
+    }
+
+    fn get_connections_over_time(&self) -> Vec<(DateTime<Utc>, u64)> {
+        self.timeline.iter().map(|bucket| (bucket.timestamp, bucket.connections)).collect()
+    }
+
+    fn get_traffic_over_time(&self) -> Vec<(DateTime<Utc>, u64)> {
+        self.timeline.iter().map(|bucket| (bucket.timestamp, bucket.bytes)).collect()
     }
 
     pub fn get_top_connections(&self, limit: usize) -> Vec<ConnectionSummary> {
@@ -288,6 +575,53 @@ impl ConnectionGraph {
         summaries.truncate(limit);
         summaries
     }
+
+    /// Traffic between local endpoints and remote hosts, busiest `limit` links first
+    ///
+    /// The local end of a connection is its private address when only one end has one,
+    /// and otherwise its source.
+    pub fn get_traffic_flows(&self, limit: usize) -> Vec<TrafficFlow> {
+        let mut flows: HashMap<(String, String), TrafficFlow> = HashMap::new();
+
+        for conn in &self.data {
+            let (local_ip, local_port, remote_ip) = if is_private_address(&conn.dest_ip) && !is_private_address(&conn.source_ip) {
+                (&conn.dest_ip, conn.dest_port, &conn.source_ip)
+            } else {
+                (&conn.source_ip, conn.source_port, &conn.dest_ip)
+            };
+            let transport = transport_from_name(&conn.protocol);
+            let local_ip = self.tags.redactor().target(local_ip);
+            let local = match (local_port, self.services.name(local_port, transport)) {
+                (0, _) => format!("{local_ip}:{}", conn.protocol.to_lowercase()),
+                (_, Some(_)) => format!("{local_ip}:{}", self.services.label(local_port, transport)),
+                (_, None) => format!("{local_ip}:ephemeral"),
+            };
+
+            let flow = flows.entry((local.clone(), remote_ip.clone())).or_insert_with(|| TrafficFlow {
+                local,
+                remote_ip: remote_ip.clone(),
+                connections: 0,
+                bytes: 0,
+            });
+            flow.connections += 1;
+            flow.bytes += conn.bytes_sent + conn.bytes_received;
+        }
+
+        let mut flows: Vec<_> = flows.into_values().collect();
+        flows.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.local.cmp(&b.local))
+                .then_with(|| a.remote_ip.cmp(&b.remote_ip))
+        });
+        flows.truncate(limit);
+        flows
+    }
+
+    /// Remote address with its tag and host name, if one was looked up
+    fn remote_label(&self, address: &str) -> String {
+        self.tags.describe_with(address, self.host_name(address))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -305,30 +639,99 @@ pub struct ConnectionSummary {
     pub last_seen: DateTime<Utc>,
 }
 
+/// Local endpoints and remote hosts of the flow diagram with their traffic, busiest first
+fn flow_columns(flows: &[TrafficFlow]) -> (FlowColumn, FlowColumn) {
+    let mut locals: HashMap<&str, u64> = HashMap::new();
+    let mut remotes: HashMap<&str, u64> = HashMap::new();
+    for flow in flows {
+        *locals.entry(&flow.local).or_default() += flow.bytes;
+        *remotes.entry(&flow.remote_ip).or_default() += flow.bytes;
+    }
+    let sorted = |nodes: HashMap<&str, u64>| {
+        let mut nodes: Vec<(String, u64)> = nodes.into_iter().map(|(label, bytes)| (label.to_string(), bytes)).collect();
+        nodes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        nodes
+    };
+    (sorted(locals), sorted(remotes))
+}
+
+/// Points along an S-shaped curve from `from` to `to`, level at both ends
+fn flow_curve(from: (i32, f64), to: (i32, f64)) -> Vec<(i32, i32)> {
+    const STEPS: i32 = 24;
+    (0..=STEPS)
+        .map(|step| {
+            let t = step as f64 / STEPS as f64;
+            let eased = t * t * (3.0 - 2.0 * t);
+            let x = from.0 as f64 + (to.0 - from.0) as f64 * t;
+            let y = from.1 + (to.1 - from.1) * eased;
+            (x.round() as i32, y.round() as i32)
+        })
+        .collect()
+}
+
+fn is_private_address(address: &str) -> bool {
+    address.parse().is_ok_and(is_private)
+}
+
 impl GraphRenderer for ConnectionGraph {
     fn render(&self, output_path: &Path) -> Result<()> {
-        self.render_connection_timeline(output_path)
+        self.render_connection_timeline(output_path, ImageFormat::from_path(output_path))
     }
 }
 
-#[allow(dead_code)]
-pub fn get_well_known_port_name(port: u16) -> &'static str {
-    match port {
-        80 => "HTTP",
-        443 => "HTTPS",
-        22 => "SSH",
-        21 => "FTP",
-        25 => "SMTP",
-        53 => "DNS",
-        110 => "POP3",
-        143 => "IMAP",
-        993 => "IMAPS",
-        995 => "POP3S",
-        3389 => "RDP",
-        5432 => "PostgreSQL",
-        3306 => "MySQL",
-        6379 => "Redis",
-        27017 => "MongoDB",
-        _ => "Unknown",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(source: (&str, u16), dest: (&str, u16), bytes: u64) -> ConnectionDataPoint {
+        ConnectionDataPoint {
+            timestamp: Utc::now(),
+            source_ip: source.0.to_string(),
+            dest_ip: dest.0.to_string(),
+            source_port: source.1,
+            dest_port: dest.1,
+            protocol: "Tcp".to_string(),
+            state: "active".to_string(),
+            packets_sent: 1,
+            packets_received: 1,
+            bytes_sent: bytes,
+            bytes_received: bytes,
+        }
+    }
+
+    #[test]
+    fn test_traffic_flows_group_local_ports_and_remote_hosts() {
+        let mut graph = ConnectionGraph::new(GraphConfig::default());
+        graph.data = vec![
+            connection(("192.168.1.10", 50001), ("203.0.113.5", 443), 100),
+            connection(("192.168.1.10", 50002), ("203.0.113.5", 443), 50),
+            connection(("198.51.100.9", 40000), ("192.168.1.10", 22), 400),
+            connection(("192.168.1.10", 50003), ("203.0.113.6", 443), 10),
+        ];
+
+        let flows = graph.get_traffic_flows(10);
+        let links: Vec<_> = flows.iter().map(|f| (f.local.as_str(), f.remote_ip.as_str(), f.connections, f.bytes)).collect();
+        assert_eq!(
+            links,
+            vec![
+                ("192.168.1.10:22/ssh", "198.51.100.9", 1, 800),
+                ("192.168.1.10:ephemeral", "203.0.113.5", 2, 300),
+                ("192.168.1.10:ephemeral", "203.0.113.6", 1, 20),
+            ]
+        );
+        assert_eq!(graph.get_traffic_flows(1).len(), 1);
+
+        let (locals, remotes) = flow_columns(&flows);
+        assert_eq!(locals, vec![("192.168.1.10:22/ssh".to_string(), 800), ("192.168.1.10:ephemeral".to_string(), 320)]);
+        assert_eq!(remotes.len(), 3);
+    }
+
+    #[test]
+    fn test_flow_curve_is_level_at_both_ends() {
+        let curve = flow_curve((0, 10.0), (100, 50.0));
+        assert_eq!(curve.first(), Some(&(0, 10)));
+        assert_eq!(curve.last(), Some(&(100, 50)));
+        assert_eq!(curve[1].1 - curve[0].1, 0);
+        assert_eq!(curve[curve.len() / 2], (50, 30));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,422 @@
+// Queries behind the graphs: packet database rows grouped into the time buckets of a
+// graph window
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::MutexGuard;
+
+use crate::graphs::annotations::{AnnotationKind, GraphAnnotation};
+use crate::graphs::bandwidth_graphs::BandwidthDataPoint;
+use crate::graphs::connection_graphs::ConnectionBucket;
+use crate::graphs::protocol_graphs::ProtocolDataPoint;
+use crate::graphs::speedtest_graphs::SpeedTestDataPoint;
+use crate::graphs::time_range::{bucket_sql, bucket_time, sql_time, GraphWindow};
+use crate::graphs::TimeSeries;
+use crate::models::{Redactor, TagBook};
+use crate::storage::audit;
+use crate::storage::packet_storage::read_tags;
+use crate::storage::Database;
+
+/// Simple database wrapper for graph operations
+pub struct DatabaseManager {
+    database: Database,
+    /// Redacts the addresses and host names graphs print through `tag_book`, in privacy mode
+    redactor: Redactor,
+}
+
+impl DatabaseManager {
+    /// Opens the packet database, migrated and shared like `PacketStorage`'s
+    pub async fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            database: Database::open(path)?,
+            redactor: Redactor::default(),
+        })
+    }
+
+    /// Opens an existing database without write access or schema changes
+    pub async fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            database: Database::open_read_only(path)?,
+            redactor: Redactor::default(),
+        })
+    }
+
+    /// Prints addresses and host names redacted through the tag books handed out
+    pub fn with_privacy(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// A read-only connection from the database's pool, for one query
+    pub fn reader(&self) -> MutexGuard<'_, Connection> {
+        self.database.reader()
+    }
+
+    /// User tags for labelling hosts; empty when the database has no tags table yet
+    pub fn tag_book(&self) -> TagBook {
+        let conn = self.database.reader();
+        TagBook::new(read_tags(&conn).unwrap_or_default()).with_redactor(self.redactor.clone())
+    }
+
+    /// Traffic per bucket across `interfaces` (the non-loopback ones when empty): download
+    /// and upload each as the sum of the interfaces' average rates over the time sampled in
+    /// the bucket, and the bytes those rates carried
+    pub fn bandwidth_buckets(&self, window: &GraphWindow, interfaces: &[String]) -> Result<Vec<BandwidthDataPoint>> {
+        let conn = self.database.reader();
+        let query = format!(
+            "SELECT bucket, SUM(download), SUM(upload), CAST(ROUND(SUM(received)) AS INTEGER), CAST(ROUND(SUM(sent)) AS INTEGER)
+             FROM ({per_interface})
+             GROUP BY bucket
+             ORDER BY bucket",
+            per_interface = interface_rates_sql(window, interfaces),
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(window_params(window, interfaces)), |row| {
+            Ok(BandwidthDataPoint {
+                timestamp: bucket_time(row.get(0)?),
+                download_speed: row.get(1)?,
+                upload_speed: row.get(2)?,
+                total_rx: row.get::<_, i64>(3)?.max(0) as u64,
+                total_tx: row.get::<_, i64>(4)?.max(0) as u64,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Average rate per bucket of each of `interfaces` (the non-loopback ones when empty),
+    /// download and upload combined, one series per interface in name order
+    pub fn interface_rate_buckets(&self, window: &GraphWindow, interfaces: &[String]) -> Result<Vec<TimeSeries>> {
+        let conn = self.database.reader();
+        let query = format!(
+            "SELECT bucket, interface_name, download + upload
+             FROM ({per_interface})
+             ORDER BY bucket",
+            per_interface = interface_rates_sql(window, interfaces),
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(window_params(window, interfaces)), |row| {
+            Ok((bucket_time(row.get(0)?), row.get::<_, String>(1)?, row.get::<_, f64>(2)?))
+        })?;
+
+        let mut by_interface: BTreeMap<String, Vec<(DateTime<Utc>, f64)>> = BTreeMap::new();
+        for row in rows {
+            let (timestamp, name, rate) = row?;
+            by_interface.entry(name).or_default().push((timestamp, rate));
+        }
+        Ok(by_interface.into_iter().map(|(name, points)| TimeSeries::new(name, points)).collect())
+    }
+
+    /// Stored events per bucket for marking charts: interfaces among `interfaces` (all when
+    /// empty) going down or disappearing, security events on them, and the alerts in the
+    /// audit log, which are not tied to an interface; bucketed like `bandwidth_buckets`, so
+    /// each marker stands on the bucket of the speed samples around it
+    pub fn event_annotations(&self, window: &GraphWindow, interfaces: &[String]) -> Result<Vec<GraphAnnotation>> {
+        let conn = self.database.reader();
+        let filter = if interfaces.is_empty() {
+            String::new()
+        } else {
+            format!(" AND (interface_name IS NULL OR interface_name IN ({}))", vec!["?"; interfaces.len()].join(", "))
+        };
+        let query = format!(
+            "SELECT {bucket} AS bucket, kind, label, COUNT(*)
+             FROM (
+                 SELECT timestamp, 'interface' AS kind, interface_name || ' ' || change AS label, interface_name
+                 FROM interface_events
+                 WHERE change IN ('down', 'removed')
+                 UNION ALL
+                 SELECT timestamp, 'alert', detail, NULL
+                 FROM audit_log
+                 WHERE category = '{alert}'
+                 UNION ALL
+                 SELECT timestamp, 'security', event_type, interface_name
+                 FROM security_events
+             )
+             WHERE timestamp BETWEEN ? AND ?{filter}
+             GROUP BY bucket, kind, label
+             ORDER BY bucket, kind, label",
+            bucket = bucket_sql("timestamp", window.resolution_secs()),
+            alert = audit::ALERT,
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(window_params(window, interfaces)), |row| {
+            Ok((bucket_time(row.get(0)?), row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, u64>(3)?))
+        })?;
+
+        let mut annotations = Vec::new();
+        for row in rows {
+            let (timestamp, kind, label, count) = row?;
+            if let Some(kind) = AnnotationKind::from_sql(&kind) {
+                annotations.push(GraphAnnotation { timestamp, kind, label, count });
+            }
+        }
+        Ok(annotations)
+    }
+
+    /// Packets and bytes per bucket and protocol, on `interface` or all of them
+    pub fn protocol_buckets(&self, window: &GraphWindow, interface: Option<&str>) -> Result<Vec<ProtocolDataPoint>> {
+        let conn = self.database.reader();
+        let query = format!(
+            "SELECT {bucket} AS bucket, protocol_name, SUM(packet_count), SUM(byte_count)
+             FROM protocol_distribution
+             WHERE timestamp BETWEEN ?1 AND ?2 AND (?3 IS NULL OR interface_name = ?3)
+             GROUP BY bucket, protocol_name
+             ORDER BY bucket, protocol_name",
+            bucket = bucket_sql("timestamp", window.resolution_secs()),
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(
+            rusqlite::params![sql_time(window.start), sql_time(window.end), interface],
+            |row| {
+                Ok(ProtocolDataPoint {
+                    timestamp: bucket_time(row.get(0)?),
+                    protocol: row.get(1)?,
+                    packet_count: row.get(2)?,
+                    byte_count: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Connections first seen per bucket and the bytes they carried
+    pub fn connection_buckets(&self, window: &GraphWindow) -> Result<Vec<ConnectionBucket>> {
+        let conn = self.database.reader();
+        let query = format!(
+            "SELECT {bucket} AS bucket, COUNT(*), SUM(byte_count)
+             FROM connections
+             WHERE first_seen BETWEEN ? AND ?
+             GROUP BY bucket
+             ORDER BY bucket",
+            bucket = bucket_sql("first_seen", window.resolution_secs()),
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map([sql_time(window.start), sql_time(window.end)], |row| {
+            Ok(ConnectionBucket {
+                timestamp: bucket_time(row.get(0)?),
+                connections: row.get(1)?,
+                bytes: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Speed test results averaged per bucket; servers tested in a bucket are listed together
+    pub fn speed_test_buckets(&self, window: &GraphWindow) -> Result<Vec<SpeedTestDataPoint>> {
+        let conn = self.database.reader();
+        let query = format!(
+            "SELECT {bucket} AS bucket, GROUP_CONCAT(DISTINCT server),
+                    AVG(latency_ms), AVG(jitter_ms), AVG(download_bps), AVG(upload_bps)
+             FROM speed_tests
+             WHERE timestamp BETWEEN ? AND ?
+             GROUP BY bucket
+             ORDER BY bucket",
+            bucket = bucket_sql("timestamp", window.resolution_secs()),
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map([sql_time(window.start), sql_time(window.end)], |row| {
+            Ok(SpeedTestDataPoint {
+                timestamp: bucket_time(row.get(0)?),
+                server: row.get(1)?,
+                latency_ms: row.get(2)?,
+                jitter_ms: row.get(3)?,
+                download_bps: row.get(4)?,
+                upload_bps: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+/// Loopback interfaces, judged by name as `BandwidthSample::is_loopback` does; a hub's
+/// `<agent>/<interface>` names by the interface part
+const NOT_LOOPBACK: &str = " AND interface_name NOT IN ('lo', 'lo0') AND interface_name NOT LIKE '%/lo'
+     AND interface_name NOT LIKE '%/lo0' AND interface_name NOT LIKE '%loopback%'";
+
+/// Speed samples and, for the time the retention policy has already rolled up, the hourly
+/// and daily rollups, which stand at the start of their hour or day; samples are removed as
+/// they are rolled up, so no time is counted twice
+const BANDWIDTH_HISTORY: &str = "(
+    SELECT timestamp, interface_name, duration_secs, download_bps, upload_bps FROM bandwidth_samples
+    UNION ALL
+    SELECT period_start, interface_name, duration_secs, download_bps, upload_bps FROM bandwidth_rollups
+)";
+
+/// Each interface's download and upload rate per bucket, averaged over the time its
+/// samples cover, and the bytes received and sent at those rates; takes `window_params`
+fn interface_rates_sql(window: &GraphWindow, interfaces: &[String]) -> String {
+    let filter = if interfaces.is_empty() { NOT_LOOPBACK.to_string() } else { interface_filter(interfaces) };
+    format!(
+        "SELECT {bucket} AS bucket, interface_name,
+                COALESCE(SUM(download_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(download_bps)) AS download,
+                COALESCE(SUM(upload_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(upload_bps)) AS upload,
+                SUM(download_bps * duration_secs) AS received,
+                SUM(upload_bps * duration_secs) AS sent
+         FROM {BANDWIDTH_HISTORY}
+         WHERE timestamp BETWEEN ? AND ?{filter}
+         GROUP BY bucket, interface_name",
+        bucket = bucket_sql("timestamp", window.resolution_secs()),
+    )
+}
+
+/// ` AND interface_name IN (?, ...)` for a non-empty interface list
+fn interface_filter(interfaces: &[String]) -> String {
+    if interfaces.is_empty() {
+        String::new()
+    } else {
+        format!(" AND interface_name IN ({})", vec!["?"; interfaces.len()].join(", "))
+    }
+}
+
+/// Parameters for `timestamp BETWEEN ? AND ?` followed by `interface_filter`'s
+fn window_params(window: &GraphWindow, interfaces: &[String]) -> Vec<String> {
+    let mut params = vec![sql_time(window.start), sql_time(window.end)];
+    params.extend(interfaces.iter().cloned());
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::period::parse_time;
+    use chrono::Local;
+
+    async fn database_with_rows(sql: &str) -> (tempfile::TempDir, DatabaseManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DatabaseManager::new(dir.path().join("packets.db").to_str().unwrap()).await.unwrap();
+        db.database.connection().lock().unwrap().execute_batch(sql).unwrap();
+        (dir, db)
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        parse_time(value, Local::now()).unwrap().with_timezone(&Utc)
+    }
+
+    fn window(from: &str, to: &str, resolution: &str) -> GraphWindow {
+        GraphWindow::resolve("1h", Some(from), Some(to), Some(resolution), Utc::now()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_is_aggregated_per_bucket() {
+        let (_dir, db) = database_with_rows(
+            "INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps) VALUES
+                ('2024-05-01 10:00:10', 'eth0', 10.0, 100.0, 10.0),
+                ('2024-05-01 10:00:10', 'wlan0', 10.0, 50.0, 5.0),
+                ('2024-05-01 10:00:40', 'eth0', 30.0, 300.0, 30.0),
+                ('2024-05-01 10:00:40', 'lo', 30.0, 9000.0, 9000.0),
+                ('2024-05-01 10:01:05', 'eth0', 20.0, 20.0, 2.0),
+                ('2024-05-01 11:30:00', 'eth0', 60.0, 999.0, 99.0);",
+        )
+        .await;
+        let window = window("2024-05-01 10:00", "2024-05-01 11:00", "1m");
+
+        // eth0 averages 250 B/s down over its 40 s in the first minute, wlan0 adds 50;
+        // loopback traffic is left out of the total
+        let buckets = db.bandwidth_buckets(&window, &[]).unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].timestamp, at("2024-05-01 10:00"));
+        assert_eq!((buckets[0].download_speed, buckets[0].upload_speed), (300.0, 30.0));
+        assert_eq!((buckets[0].total_rx, buckets[0].total_tx), (10500, 1050));
+        assert_eq!((buckets[1].total_rx, buckets[1].total_tx), (400, 40));
+
+        let eth0 = db.bandwidth_buckets(&window, &["eth0".to_string()]).unwrap();
+        assert_eq!((eth0[0].download_speed, eth0[0].total_rx), (250.0, 10000));
+        let lo = db.bandwidth_buckets(&window, &["lo".to_string()]).unwrap();
+        assert_eq!(lo[0].download_speed, 9000.0);
+
+        let by_interface = db.interface_rate_buckets(&window, &[]).unwrap();
+        let labels: Vec<_> = by_interface.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, ["eth0", "wlan0"]);
+        assert_eq!(by_interface[0].points.len(), 2);
+        assert_eq!(by_interface[0].points[0].1, 275.0);
+        assert_eq!(by_interface[1].points[0].1, 55.0);
+    }
+
+    #[tokio::test]
+    async fn test_rolled_up_history_is_bucketed_with_the_samples() {
+        let (_dir, db) = database_with_rows(
+            "INSERT INTO bandwidth_rollups (period_start, resolution, interface_name, duration_secs, download_bps, upload_bps) VALUES
+                ('2024-04-01 00:00:00', 'day', 'eth0', 86400.0, 1000.0, 100.0),
+                ('2024-04-02 00:00:00', 'day', 'eth0', 86400.0, 3000.0, 300.0),
+                ('2024-04-30 10:00:00', 'hour', 'eth0', 3600.0, 500.0, 50.0);
+             INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps) VALUES
+                ('2024-05-01 10:01:00', 'eth0', 60.0, 200.0, 20.0);",
+        )
+        .await;
+
+        let days = db.bandwidth_buckets(&window("2024-04-01", "2024-05-02", "1d"), &[]).unwrap();
+        let rates: Vec<_> = days.iter().map(|d| (d.download_speed, d.total_rx)).collect();
+        assert_eq!(rates, [(1000.0, 86_400_000), (3000.0, 259_200_000), (500.0, 1_800_000), (200.0, 12_000)]);
+        assert_eq!(days[2].timestamp, at("2024-04-30"));
+
+        // A window reaching only the raw samples leaves the rollups out
+        let recent = db.bandwidth_buckets(&window("2024-05-01 10:00", "2024-05-01 11:00", "1h"), &[]).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].total_tx, 1200);
+    }
+
+    #[tokio::test]
+    async fn test_protocols_and_connections_are_aggregated_per_bucket() {
+        let (_dir, db) = database_with_rows(
+            "INSERT INTO protocol_distribution (timestamp, interface_name, protocol_name, packet_count, byte_count) VALUES
+                ('2024-05-01 10:05:00', 'eth0', 'HTTPS', 10, 1000),
+                ('2024-05-01 10:50:00', 'eth0', 'HTTPS', 5, 500),
+                ('2024-05-01 10:50:00', 'wlan0', 'DNS', 2, 120);
+             INSERT INTO connections (connection_key, source_ip, dest_ip, protocol, first_seen, last_seen, byte_count) VALUES
+                ('a', '10.0.0.2', '203.0.113.1', 'Tcp', '2024-05-01 10:01:00', '2024-05-01 10:02:00', 100),
+                ('b', '10.0.0.2', '203.0.113.2', 'Tcp', '2024-05-01 10:20:00', '2024-05-01 10:21:00', 300),
+                ('c', '10.0.0.3', '203.0.113.3', 'Udp', '2024-05-01 11:10:00', '2024-05-01 11:11:00', 50);",
+        )
+        .await;
+        let window = window("2024-05-01 10:00", "2024-05-01 12:00", "1h");
+
+        let protocols = db.protocol_buckets(&window, None).unwrap();
+        let counts: Vec<_> = protocols.iter().map(|p| (p.protocol.as_str(), p.packet_count, p.byte_count)).collect();
+        assert_eq!(counts, vec![("DNS", 2, 120), ("HTTPS", 15, 1500)]);
+        assert_eq!(db.protocol_buckets(&window, Some("wlan0")).unwrap().len(), 1);
+
+        let connections = db.connection_buckets(&window).unwrap();
+        let counts: Vec<_> = connections.iter().map(|c| (c.connections, c.bytes)).collect();
+        assert_eq!(counts, vec![(2, 400), (1, 50)]);
+        assert_eq!(connections[1].timestamp, at("2024-05-01 11:00"));
+    }
+
+    #[tokio::test]
+    async fn test_events_are_joined_and_counted_per_bucket() {
+        let (_dir, db) = database_with_rows(
+            "INSERT INTO interface_events (timestamp, interface_name, change) VALUES
+                ('2024-05-01 10:02:00', 'eth0', 'down'),
+                ('2024-05-01 10:04:00', 'eth0', 'up'),
+                ('2024-05-01 10:20:00', 'wlan0', 'removed');
+             INSERT INTO security_events (timestamp, interface_name, event_type, description) VALUES
+                ('2024-05-01 10:03:00', 'eth0', 'PortScan', '20 ports'),
+                ('2024-05-01 10:08:00', 'eth0', 'PortScan', '25 ports'),
+                ('2024-05-01 11:30:00', 'eth0', 'SynFlood', 'outside the window');
+             INSERT INTO audit_log (id, timestamp, category, action, detail, prev_hash, hash) VALUES
+                (1, '2024-05-01 10:25:00', 'alert', 'live dashboard', 'Port scan from 10.0.0.9', '', ''),
+                (2, '2024-05-01 10:26:00', 'admin', 'tag set', 'not an event', '', '');",
+        )
+        .await;
+        let window = window("2024-05-01 10:00", "2024-05-01 11:00", "15m");
+
+        let events = db.event_annotations(&window, &[]).unwrap();
+        let summary: Vec<_> = events.iter().map(|e| (e.kind, e.label.as_str(), e.count)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (AnnotationKind::InterfaceDown, "eth0 down", 1),
+                (AnnotationKind::SecurityEvent, "PortScan", 2),
+                (AnnotationKind::Alert, "Port scan from 10.0.0.9", 1),
+                (AnnotationKind::InterfaceDown, "wlan0 removed", 1),
+            ]
+        );
+        assert_eq!(events[2].timestamp, at("2024-05-01 10:15"));
+
+        // Alerts are not tied to an interface and stay when filtering
+        let eth0 = db.event_annotations(&window, &["eth0".to_string()]).unwrap();
+        assert_eq!(eth0.len(), 3);
+        assert!(eth0.iter().all(|e| e.label != "wlan0 removed"));
+    }
+}
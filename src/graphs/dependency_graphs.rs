@@ -0,0 +1,537 @@
+use crate::graphs::DatabaseManager;
+use crate::graphs::time_range::{sql_time, GraphWindow};
+use crate::graphs::GraphConfig;
+use crate::models::service::transport_from_name;
+use crate::models::{ServiceMap, TagBook};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Output formats of the dependency map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyFormat {
+    /// Graphviz source, render with `dot -Tsvg`
+    Dot,
+    /// Self-contained page with an interactive force-directed layout
+    Html,
+    Json,
+}
+
+impl DependencyFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_lowercase().as_str() {
+            "dot" | "gv" | "graphviz" => Some(Self::Dot),
+            "html" => Some(Self::Html),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Dot => "dot",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    /// The side of a flow that opened it
+    Host,
+    /// The address and port a host talks to
+    Service,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyNode {
+    pub id: String,
+    pub label: String,
+    pub kind: NodeKind,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyEdge {
+    pub source: String,
+    pub target: String,
+    /// Application protocol or well-known port name, transport protocol otherwise
+    pub service: String,
+    pub connections: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// One stored flow, before client and service sides are told apart
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub source_ip: String,
+    pub dest_ip: String,
+    pub source_port: u16,
+    pub dest_port: u16,
+    pub protocol: String,
+    pub application_protocol: Option<String>,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// "Who talks to whom" map: hosts linked to the remote services they use
+pub struct DependencyGraph {
+    pub config: GraphConfig,
+    pub nodes: Vec<DependencyNode>,
+    pub edges: Vec<DependencyEdge>,
+    /// User tags shown next to host and service addresses
+    pub tags: TagBook,
+    /// Names of service ports without a recognised application protocol
+    pub services: ServiceMap,
+}
+
+impl DependencyGraph {
+    pub fn new(config: GraphConfig) -> Self {
+        Self {
+            config,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            tags: TagBook::default(),
+            services: ServiceMap::new(),
+        }
+    }
+
+    pub fn with_tags(mut self, tags: TagBook) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_services(mut self, services: ServiceMap) -> Self {
+        self.services = services;
+        self
+    }
+
+    /// Loads flows seen between `start_time` and `end_time`, keeping the `limit` busiest edges
+    pub async fn load_data(
+        &mut self,
+        db: &DatabaseManager,
+        window: &GraphWindow,
+        limit: usize,
+    ) -> Result<()> {
+
+        let flows = {
+            let conn = db.reader();
+            let mut stmt = conn.prepare(
+                "SELECT source_ip, dest_ip, source_port, dest_port, protocol, application_protocol,
+                        packet_count, byte_count
+                 FROM connections
+                 WHERE last_seen >= ? AND first_seen <= ?",
+            )?;
+            let rows = stmt.query_map([sql_time(window.start), sql_time(window.end)], |row| {
+                Ok(FlowRecord {
+                    source_ip: row.get(0)?,
+                    dest_ip: row.get(1)?,
+                    source_port: row.get::<_, Option<u16>>(2)?.unwrap_or(0),
+                    dest_port: row.get::<_, Option<u16>>(3)?.unwrap_or(0),
+                    protocol: row.get(4)?,
+                    application_protocol: row.get(5)?,
+                    packets: row.get(6)?,
+                    bytes: row.get(7)?,
+                })
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        self.build(&flows, limit);
+        Ok(())
+    }
+
+    /// Aggregates flows into host -> service edges
+    pub fn build(&mut self, flows: &[FlowRecord], limit: usize) {
+        let mut edges: HashMap<(String, String), DependencyEdge> = HashMap::new();
+        let mut hosts: HashMap<String, String> = HashMap::new();
+        let mut services: HashMap<String, String> = HashMap::new();
+
+        for flow in flows {
+            // The listening side usually has the lower port; the other side picked an ephemeral one
+            let (client, server, port) = if flow.dest_port <= flow.source_port || flow.source_port == 0 {
+                (&flow.source_ip, &flow.dest_ip, flow.dest_port)
+            } else {
+                (&flow.dest_ip, &flow.source_ip, flow.source_port)
+            };
+
+            let service = service_name(flow, port, &self.services);
+            // Node ids end up in the exported files, so they carry addresses as the privacy mode shows them
+            let redactor = self.tags.redactor();
+            let source = format!("host:{}", redactor.target(client));
+            let server_address = redactor.target(server);
+            let target = format!("svc:{server_address}:{port}");
+            hosts.entry(source.clone()).or_insert_with(|| self.tags.describe(client));
+            services
+                .entry(target.clone())
+                .or_insert_with(|| match self.tags.label(server) {
+                    Some(label) => format!("{} ({label})\n{service}", socket_label(&server_address, port)),
+                    None => format!("{}\n{service}", socket_label(&server_address, port)),
+                });
+
+            let edge = edges
+                .entry((source.clone(), target.clone()))
+                .or_insert_with(|| DependencyEdge {
+                    source,
+                    target,
+                    service,
+                    connections: 0,
+                    packets: 0,
+                    bytes: 0,
+                });
+            edge.connections += 1;
+            edge.packets += flow.packets;
+            edge.bytes += flow.bytes;
+        }
+
+        let mut edges: Vec<_> = edges.into_values().collect();
+        edges.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.target.cmp(&b.target)));
+        edges.truncate(limit);
+
+        let mut nodes: HashMap<String, DependencyNode> = HashMap::new();
+        for edge in &edges {
+            for (id, kind) in [(&edge.source, NodeKind::Host), (&edge.target, NodeKind::Service)] {
+                let node = nodes.entry(id.clone()).or_insert_with(|| DependencyNode {
+                    id: id.clone(),
+                    label: match kind {
+                        NodeKind::Host => hosts[id].clone(),
+                        NodeKind::Service => services[id].clone(),
+                    },
+                    kind,
+                    bytes: 0,
+                });
+                node.bytes += edge.bytes;
+            }
+        }
+
+        let mut nodes: Vec<_> = nodes.into_values().collect();
+        nodes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.id.cmp(&b.id)));
+
+        self.nodes = nodes;
+        self.edges = edges;
+    }
+
+    pub fn render(&self, output_path: &Path, format: DependencyFormat) -> Result<()> {
+        let contents = match format {
+            DependencyFormat::Dot => self.to_dot(),
+            DependencyFormat::Html => self.to_html()?,
+            DependencyFormat::Json => serde_json::to_string_pretty(&self.to_json())?,
+        };
+        std::fs::write(output_path, contents)?;
+        Ok(())
+    }
+
+    /// Graphviz source; edge thickness follows the bytes exchanged
+    pub fn to_dot(&self) -> String {
+        let max_bytes = self.edges.iter().map(|e| e.bytes).max().unwrap_or(1).max(1) as f64;
+
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph dependencies {{");
+        let _ = writeln!(dot, "  label=\"{}\";", escape_dot(&self.config.title));
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let _ = writeln!(dot, "  node [fontname=\"sans-serif\", fontsize=10];");
+        let _ = writeln!(dot, "  edge [fontname=\"sans-serif\", fontsize=9, color=\"#555555\"];");
+
+        for node in &self.nodes {
+            let style = match node.kind {
+                NodeKind::Host => "shape=box, style=\"rounded,filled\", fillcolor=\"#cfe2ff\"",
+                NodeKind::Service => "shape=ellipse, style=filled, fillcolor=\"#fde2c8\"",
+            };
+            let _ = writeln!(dot, "  \"{}\" [label=\"{}\", {style}];", escape_dot(&node.id), escape_dot(&node.label));
+        }
+        for edge in &self.edges {
+            let width = 1.0 + 4.0 * edge.bytes as f64 / max_bytes;
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\", penwidth={width:.1}];",
+                escape_dot(&edge.source),
+                escape_dot(&edge.target),
+                escape_dot(&format!("{} ({})", self.config.units.bytes(edge.bytes as f64), edge.connections)),
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Standalone HTML page; the layout runs in the browser with no external scripts
+    pub fn to_html(&self) -> Result<String> {
+        // "</" inside the embedded JSON would end the script element early
+        let data = serde_json::to_string(&self.to_json())?.replace("</", "<\\/");
+        Ok(HTML_TEMPLATE
+            .replace("{{title}}", &escape_html(&self.config.title))
+            .replace("{{width}}", &self.config.width.to_string())
+            .replace("{{height}}", &self.config.height.to_string())
+            .replace("{{data}}", &data))
+    }
+
+    /// Text listing grouped by host, for printing in the terminal
+    pub fn render_terminal(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", self.config.title);
+        for host in self.nodes.iter().filter(|n| n.kind == NodeKind::Host) {
+            let _ = writeln!(out, "\n{} ({})", host.label, self.config.units.bytes(host.bytes as f64));
+            let edges: Vec<_> = self.edges.iter().filter(|e| e.source == host.id).collect();
+            for (i, edge) in edges.iter().enumerate() {
+                let branch = if i + 1 == edges.len() { "`--" } else { "|--" };
+                // The service node's label is its endpoint over its service name
+                let target = self
+                    .nodes
+                    .iter()
+                    .find(|n| n.id == edge.target)
+                    .and_then(|n| n.label.lines().next())
+                    .unwrap_or_else(|| edge.target.trim_start_matches("svc:"));
+                let _ = writeln!(
+                    out,
+                    "  {branch} {target} [{}] {} in {} connection(s)",
+                    edge.service,
+                    self.config.units.bytes(edge.bytes as f64),
+                    edge.connections
+                );
+            }
+        }
+        out
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "title": self.config.title,
+            "nodes": self.nodes,
+            "edges": self.edges,
+        })
+    }
+}
+
+fn service_name(flow: &FlowRecord, port: u16, services: &ServiceMap) -> String {
+    if let Some(ref app) = flow.application_protocol {
+        return app.clone();
+    }
+    match services.name(port, transport_from_name(&flow.protocol)) {
+        Some(name) => name.to_string(),
+        None => flow.protocol.to_uppercase(),
+    }
+}
+
+fn socket_label(ip: &str, port: u16) -> String {
+    match (port, ip.contains(':')) {
+        (0, _) => ip.to_string(),
+        (_, true) => format!("[{ip}]:{port}"),
+        (_, false) => format!("{ip}:{port}"),
+    }
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+  body { font-family: sans-serif; margin: 16px; }
+  canvas { border: 1px solid #ccc; cursor: grab; }
+  #info { color: #555; font-size: 13px; min-height: 1.5em; }
+</style>
+</head>
+<body>
+<h2>{{title}}</h2>
+<div id="info">Drag nodes to rearrange; hover an edge end for details.</div>
+<canvas id="graph" width="{{width}}" height="{{height}}"></canvas>
+<script>
+const data = {{data}};
+const canvas = document.getElementById("graph");
+const ctx = canvas.getContext("2d");
+const info = document.getElementById("info");
+const W = canvas.width, H = canvas.height;
+const index = new Map();
+const nodes = data.nodes.map((n, i) => {
+  const angle = 2 * Math.PI * i / Math.max(data.nodes.length, 1);
+  const node = Object.assign({}, n, {
+    x: W / 2 + (n.kind === "host" ? 0.2 : 0.4) * W * Math.cos(angle),
+    y: H / 2 + (n.kind === "host" ? 0.2 : 0.4) * H * Math.sin(angle),
+    vx: 0, vy: 0,
+  });
+  index.set(n.id, node);
+  return node;
+});
+const edges = data.edges.map(e => Object.assign({}, e, { s: index.get(e.source), t: index.get(e.target) }));
+const maxBytes = Math.max(1, ...edges.map(e => e.bytes));
+const fmt = b => b >= 1e9 ? (b / 1e9).toFixed(1) + " GB" : b >= 1e6 ? (b / 1e6).toFixed(1) + " MB"
+  : b >= 1e3 ? (b / 1e3).toFixed(1) + " KB" : b + " B";
+let dragged = null;
+
+function step() {
+  for (const a of nodes) {
+    for (const b of nodes) {
+      if (a === b) continue;
+      const dx = a.x - b.x, dy = a.y - b.y, d2 = Math.max(dx * dx + dy * dy, 25);
+      a.vx += 900 * dx / d2; a.vy += 900 * dy / d2;
+    }
+    a.vx += (W / 2 - a.x) * 0.002; a.vy += (H / 2 - a.y) * 0.002;
+  }
+  for (const e of edges) {
+    const dx = e.t.x - e.s.x, dy = e.t.y - e.s.y, d = Math.max(Math.hypot(dx, dy), 1);
+    const f = (d - 140) * 0.01;
+    e.s.vx += f * dx / d; e.s.vy += f * dy / d;
+    e.t.vx -= f * dx / d; e.t.vy -= f * dy / d;
+  }
+  for (const n of nodes) {
+    if (n === dragged) continue;
+    n.vx *= 0.85; n.vy *= 0.85;
+    n.x = Math.min(W - 20, Math.max(20, n.x + n.vx));
+    n.y = Math.min(H - 20, Math.max(20, n.y + n.vy));
+  }
+}
+
+function draw() {
+  ctx.clearRect(0, 0, W, H);
+  for (const e of edges) {
+    ctx.strokeStyle = "#888";
+    ctx.lineWidth = 1 + 5 * e.bytes / maxBytes;
+    ctx.beginPath(); ctx.moveTo(e.s.x, e.s.y); ctx.lineTo(e.t.x, e.t.y); ctx.stroke();
+  }
+  ctx.font = "11px sans-serif";
+  for (const n of nodes) {
+    ctx.fillStyle = n.kind === "host" ? "#3d7be0" : "#e07b3d";
+    ctx.beginPath(); ctx.arc(n.x, n.y, n.kind === "host" ? 9 : 6, 0, 2 * Math.PI); ctx.fill();
+    ctx.fillStyle = "#222";
+    n.label.split("\n").forEach((line, i) => ctx.fillText(line, n.x + 11, n.y + 4 + 12 * i));
+  }
+}
+
+function nodeAt(ev) {
+  const r = canvas.getBoundingClientRect(), x = ev.clientX - r.left, y = ev.clientY - r.top;
+  return nodes.find(n => Math.hypot(n.x - x, n.y - y) < 12);
+}
+canvas.addEventListener("mousedown", ev => { dragged = nodeAt(ev) || null; });
+canvas.addEventListener("mouseup", () => { dragged = null; });
+canvas.addEventListener("mousemove", ev => {
+  if (dragged) {
+    const r = canvas.getBoundingClientRect();
+    dragged.x = ev.clientX - r.left; dragged.y = ev.clientY - r.top;
+    return;
+  }
+  const n = nodeAt(ev);
+  if (!n) return;
+  const links = edges.filter(e => e.s === n || e.t === n)
+    .map(e => `${e.s.label.split("\n")[0]} -> ${e.t.label.replace("\n", " ")}: ${fmt(e.bytes)}, ${e.connections} connection(s)`);
+  info.textContent = links.join(" | ");
+});
+
+(function loop() { step(); draw(); requestAnimationFrame(loop); })();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PrivacyMode, Redactor, Tag};
+
+    fn flow(src: &str, sport: u16, dst: &str, dport: u16, app: Option<&str>, bytes: u64) -> FlowRecord {
+        FlowRecord {
+            source_ip: src.to_string(),
+            dest_ip: dst.to_string(),
+            source_port: sport,
+            dest_port: dport,
+            protocol: "Tcp".to_string(),
+            application_protocol: app.map(str::to_string),
+            packets: 1,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_flows_point_from_client_to_service() {
+        let mut graph = DependencyGraph::new(GraphConfig::default());
+        graph.build(
+            &[
+                flow("192.168.1.10", 50000, "8.8.8.8", 53, Some("DNS"), 100),
+                // Reply direction of the same service, and a second connection to it
+                flow("8.8.8.8", 53, "192.168.1.10", 50001, None, 300),
+                flow("192.168.1.10", 50002, "10.0.0.5", 5432, None, 50),
+            ],
+            10,
+        );
+
+        assert_eq!(graph.edges.len(), 2);
+        let dns = &graph.edges[0];
+        assert_eq!(dns.source, "host:192.168.1.10");
+        assert_eq!(dns.target, "svc:8.8.8.8:53");
+        assert_eq!(dns.service, "DNS");
+        assert_eq!((dns.connections, dns.bytes), (2, 400));
+        assert_eq!(graph.edges[1].service, "postgresql");
+
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[0].kind, NodeKind::Host);
+        assert_eq!(graph.nodes[0].bytes, 450);
+    }
+
+    #[test]
+    fn test_tags_label_hosts_and_services() {
+        let tags = TagBook::new(vec![
+            Tag::new("192.168.1.10", "laptop", None),
+            Tag::new("10.0.0.5", "nas", None),
+        ]);
+        let mut graph = DependencyGraph::new(GraphConfig::default()).with_tags(tags);
+        graph.build(&[flow("192.168.1.10", 50002, "10.0.0.5", 5432, None, 50)], 10);
+
+        let label = |id: &str| graph.nodes.iter().find(|n| n.id == id).unwrap().label.clone();
+        assert_eq!(label("host:192.168.1.10"), "192.168.1.10 (laptop)");
+        assert_eq!(label("svc:10.0.0.5:5432"), "10.0.0.5:5432 (nas)\npostgresql");
+        assert!(graph.render_terminal().contains("10.0.0.5:5432 (nas) [postgresql]"));
+
+        // In privacy mode ids and labels carry the redacted addresses, user labels still shown
+        let tags = graph.tags.clone().with_redactor(Redactor::new(PrivacyMode::Truncate, None));
+        let mut graph = DependencyGraph::new(GraphConfig::default()).with_tags(tags);
+        graph.build(&[flow("192.168.1.10", 50002, "10.0.0.5", 5432, None, 50)], 10);
+        assert_eq!(graph.edges[0].source, "host:192.168.x.x");
+        assert!(graph.render_terminal().contains("10.0.x.x:5432 (nas) [postgresql]"));
+        assert!(!graph.to_json().to_string().contains("10.0.0.5"));
+    }
+
+    #[test]
+    fn test_limit_keeps_busiest_edges() {
+        let mut graph = DependencyGraph::new(GraphConfig::default());
+        graph.build(
+            &[
+                flow("10.0.0.1", 40000, "1.1.1.1", 443, None, 10),
+                flow("10.0.0.1", 40001, "2.2.2.2", 443, None, 20),
+                flow("10.0.0.2", 40002, "3.3.3.3", 80, None, 30),
+            ],
+            2,
+        );
+
+        assert_eq!(graph.edges.len(), 2);
+        assert!(graph.nodes.iter().all(|n| n.id != "svc:1.1.1.1:443"));
+    }
+
+    #[test]
+    fn test_exports_escape_labels() {
+        let mut graph = DependencyGraph::new(GraphConfig {
+            title: "Map \"lab\"".to_string(),
+            ..GraphConfig::default()
+        });
+        graph.build(&[flow("10.0.0.1", 40000, "fe80::1", 443, Some("</script>"), 10)], 10);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("label=\"Map \\\"lab\\\"\""));
+        assert!(dot.contains("\"host:10.0.0.1\" -> \"svc:fe80::1:443\""));
+        assert!(dot.contains("[fe80::1]:443\\n</script>"));
+
+        let html = graph.to_html().unwrap();
+        assert!(!html.contains("\"</script>"));
+        assert!(html.contains("Map \"lab\""));
+    }
+}
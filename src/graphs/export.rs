@@ -1,7 +1,12 @@
+use crate::graphs::annotations::GraphAnnotation;
 use crate::graphs::bandwidth_graphs::BandwidthGraph;
 use crate::graphs::protocol_graphs::ProtocolGraph;
-use crate::graphs::connection_graphs::ConnectionGraph;
+use crate::graphs::connection_graphs::{ConnectionGraph, FLOW_LINKS};
+use crate::graphs::latency_graphs::LatencyGraph;
+use crate::graphs::packet_size_graphs::PacketSizeGraph;
+use crate::graphs::speedtest_graphs::SpeedTestGraph;
 use crate::graphs::GraphRenderer;
+use crate::models::PACKET_SIZE_BUCKETS;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -33,12 +38,21 @@ pub struct ExportData {
     pub bandwidth_data: Option<BandwidthExportData>,
     pub protocol_data: Option<ProtocolExportData>,
     pub connection_data: Option<ConnectionExportData>,
+    #[serde(default)]
+    pub latency_data: Option<LatencyExportData>,
+    #[serde(default)]
+    pub speed_test_data: Option<Vec<SpeedTestTimePoint>>,
+    #[serde(default)]
+    pub packet_size_data: Option<PacketSizeExportData>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BandwidthExportData {
     pub summary: BandwidthSummary,
     pub time_series: Vec<BandwidthTimePoint>,
+    /// Interface outages, alerts and security events in the period
+    #[serde(default)]
+    pub events: Vec<GraphAnnotation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,6 +102,19 @@ pub struct ConnectionExportData {
     pub summary: ConnectionSummaryData,
     pub top_connections: Vec<ConnectionDetail>,
     pub time_series: Vec<ConnectionTimePoint>,
+    /// Traffic between local endpoints and remote hosts, as in the flow diagram
+    #[serde(default)]
+    pub flows: Vec<FlowDetail>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowDetail {
+    pub local: String,
+    pub remote_ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_host: Option<String>,
+    pub connections: u64,
+    pub bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,6 +130,11 @@ pub struct ConnectionSummaryData {
 pub struct ConnectionDetail {
     pub source_ip: String,
     pub dest_ip: String,
+    /// Host names, when looked up and found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest_host: Option<String>,
     pub source_port: u16,
     pub dest_port: u16,
     pub protocol: String,
@@ -118,6 +150,65 @@ pub struct ConnectionTimePoint {
     pub total_traffic: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyExportData {
+    pub hosts: Vec<LatencyHostSummary>,
+    pub samples: Vec<LatencyTimePoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHostSummary {
+    pub host: String,
+    pub samples: usize,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyTimePoint {
+    pub timestamp: DateTime<Utc>,
+    pub host: String,
+    pub rtt_ms: f64,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketSizeExportData {
+    /// Size bucket labels, in the order of each count list
+    pub buckets: Vec<String>,
+    pub summary: Vec<PacketSizeSummary>,
+    pub samples: Vec<PacketSizeTimePoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketSizeSummary {
+    pub interface: String,
+    pub protocol: String,
+    pub packets: u64,
+    pub counts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketSizeTimePoint {
+    pub timestamp: DateTime<Utc>,
+    pub interface: String,
+    pub protocol: String,
+    pub counts: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedTestTimePoint {
+    pub timestamp: DateTime<Utc>,
+    pub server: String,
+    pub latency_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    pub download_bps: Option<f64>,
+    pub upload_bps: Option<f64>,
+}
+
 pub struct ExportManager {
     config: ExportConfig,
 }
@@ -135,10 +226,7 @@ impl ExportManager {
             ExportFormat::Csv => self.export_bandwidth_csv(graph)?,
             ExportFormat::Html => self.export_bandwidth_html(&export_data)?,
             ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
-            ExportFormat::Svg => {
-                // SVG export would require different backend
-                return Err(anyhow::anyhow!("SVG export not yet implemented"));
-            }
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
         }
         
         Ok(())
@@ -152,9 +240,7 @@ impl ExportManager {
             ExportFormat::Csv => self.export_protocol_csv(graph)?,
             ExportFormat::Html => self.export_protocol_html(&export_data)?,
             ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
-            ExportFormat::Svg => {
-                return Err(anyhow::anyhow!("SVG export not yet implemented"));
-            }
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
         }
         
         Ok(())
@@ -168,14 +254,54 @@ impl ExportManager {
             ExportFormat::Csv => self.export_connection_csv(graph)?,
             ExportFormat::Html => self.export_connection_html(&export_data)?,
             ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
-            ExportFormat::Svg => {
-                return Err(anyhow::anyhow!("SVG export not yet implemented"));
-            }
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
         }
         
         Ok(())
     }
 
+    pub fn export_latency_data(&self, graph: &LatencyGraph) -> Result<()> {
+        let export_data = self.prepare_latency_export(graph);
+
+        match self.config.format {
+            ExportFormat::Json => self.export_json(&export_data)?,
+            ExportFormat::Csv => self.export_latency_csv(graph)?,
+            ExportFormat::Html => return Err(anyhow::anyhow!("HTML export not yet implemented")),
+            ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
+        }
+
+        Ok(())
+    }
+
+    pub fn export_packet_size_data(&self, graph: &PacketSizeGraph) -> Result<()> {
+        let export_data = self.prepare_packet_size_export(graph);
+
+        match self.config.format {
+            ExportFormat::Json => self.export_json(&export_data)?,
+            ExportFormat::Csv => self.export_packet_size_csv(graph)?,
+            ExportFormat::Html => return Err(anyhow::anyhow!("HTML export not yet implemented")),
+            ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
+        }
+
+        Ok(())
+    }
+
+    pub fn export_speed_test_data(&self, graph: &SpeedTestGraph) -> Result<()> {
+        let export_data = self.prepare_speed_test_export(graph);
+
+        match self.config.format {
+            ExportFormat::Json => self.export_json(&export_data)?,
+            ExportFormat::Csv => self.export_speed_test_csv(graph)?,
+            ExportFormat::Html => return Err(anyhow::anyhow!("HTML export not yet implemented")),
+            ExportFormat::Png => graph.render(Path::new(&self.config.output_path))?,
+            ExportFormat::Svg => graph.render(Path::new(&self.config.output_path))?,
+        }
+
+        Ok(())
+    }
+
     fn prepare_bandwidth_export(&self, graph: &BandwidthGraph) -> Result<ExportData> {
         let summary = self.calculate_bandwidth_summary(graph);
         let time_series = graph.data.iter().map(|d| BandwidthTimePoint {
@@ -193,9 +319,13 @@ impl ExportManager {
             bandwidth_data: Some(BandwidthExportData {
                 summary,
                 time_series,
+                events: graph.annotations.clone(),
             }),
             protocol_data: None,
             connection_data: None,
+            latency_data: None,
+            speed_test_data: None,
+            packet_size_data: None,
         })
     }
 
@@ -229,6 +359,9 @@ impl ExportManager {
                 time_series,
             }),
             connection_data: None,
+            latency_data: None,
+            speed_test_data: None,
+            packet_size_data: None,
         })
     }
 
@@ -237,6 +370,8 @@ impl ExportManager {
         let connection_details = top_connections.iter().map(|c| ConnectionDetail {
             source_ip: c.source_ip.clone(),
             dest_ip: c.dest_ip.clone(),
+            source_host: graph.host_name(&c.source_ip).map(str::to_string),
+            dest_host: graph.host_name(&c.dest_ip).map(str::to_string),
             source_port: c.source_port,
             dest_port: c.dest_port,
             protocol: c.protocol.clone(),
@@ -245,6 +380,14 @@ impl ExportManager {
             duration_seconds: (c.last_seen - c.first_seen).num_seconds(),
         }).collect();
 
+        let flows = graph.get_traffic_flows(FLOW_LINKS).into_iter().map(|f| FlowDetail {
+            remote_host: graph.host_name(&f.remote_ip).map(str::to_string),
+            local: f.local,
+            remote_ip: f.remote_ip,
+            connections: f.connections,
+            bytes: f.bytes,
+        }).collect();
+
         let unique_ips = graph.data.iter()
             .map(|d| d.source_ip.clone())
             .collect::<std::collections::HashSet<_>>()
@@ -273,10 +416,100 @@ impl ExportManager {
                 summary,
                 top_connections: connection_details,
                 time_series: vec![], // Could be implemented
+                flows,
             }),
+            latency_data: None,
+            speed_test_data: None,
+            packet_size_data: None,
         })
     }
 
+    fn prepare_latency_export(&self, graph: &LatencyGraph) -> ExportData {
+        let hosts = graph.host_stats().into_iter().map(|s| LatencyHostSummary {
+            host: s.host,
+            samples: s.samples,
+            min_ms: s.min_ms,
+            p50_ms: s.p50_ms,
+            p90_ms: s.p90_ms,
+            p99_ms: s.p99_ms,
+            max_ms: s.max_ms,
+        }).collect();
+        let samples = graph.data.iter().map(|d| LatencyTimePoint {
+            timestamp: d.timestamp,
+            host: d.host.clone(),
+            rtt_ms: d.rtt_ms,
+            source: d.source.clone(),
+        }).collect();
+
+        ExportData {
+            timestamp: Utc::now(),
+            export_type: "latency".to_string(),
+            interface: graph.interface.clone(),
+            bandwidth_data: None,
+            protocol_data: None,
+            connection_data: None,
+            latency_data: Some(LatencyExportData { hosts, samples }),
+            speed_test_data: None,
+            packet_size_data: None,
+        }
+    }
+
+    fn prepare_packet_size_export(&self, graph: &PacketSizeGraph) -> ExportData {
+        let summary = graph.distribution().histograms.into_iter().flat_map(|(interface, protocols)| {
+            protocols.into_iter().map(move |(protocol, histogram)| PacketSizeSummary {
+                interface: interface.clone(),
+                protocol,
+                packets: histogram.total(),
+                counts: histogram.counts().to_vec(),
+            })
+        }).collect();
+        let samples = graph.data.iter().map(|d| PacketSizeTimePoint {
+            timestamp: d.timestamp,
+            interface: d.interface.clone(),
+            protocol: d.protocol.clone(),
+            counts: d.histogram.counts().to_vec(),
+        }).collect();
+
+        ExportData {
+            timestamp: Utc::now(),
+            export_type: "packet_sizes".to_string(),
+            interface: graph.interface.clone(),
+            bandwidth_data: None,
+            protocol_data: None,
+            connection_data: None,
+            latency_data: None,
+            speed_test_data: None,
+            packet_size_data: Some(PacketSizeExportData {
+                buckets: PACKET_SIZE_BUCKETS.iter().map(|bucket| bucket.to_string()).collect(),
+                summary,
+                samples,
+            }),
+        }
+    }
+
+    fn prepare_speed_test_export(&self, graph: &SpeedTestGraph) -> ExportData {
+        let results = graph.data.iter().map(|d| SpeedTestTimePoint {
+            timestamp: d.timestamp,
+            server: d.server.clone(),
+            latency_ms: d.latency_ms,
+            jitter_ms: d.jitter_ms,
+            download_bps: d.download_bps,
+            upload_bps: d.upload_bps,
+        }).collect();
+
+        ExportData {
+            timestamp: Utc::now(),
+            export_type: "speedtest".to_string(),
+            interface: None,
+            bandwidth_data: None,
+            protocol_data: None,
+            connection_data: None,
+            latency_data: None,
+            speed_test_data: Some(results),
+            packet_size_data: None,
+        }
+    }
+
     fn calculate_bandwidth_summary(&self, graph: &BandwidthGraph) -> BandwidthSummary {
         if graph.data.is_empty() {
             return BandwidthSummary {
@@ -380,6 +613,64 @@ impl ExportManager {
         Ok(())
     }
 
+    fn export_latency_csv(&self, graph: &LatencyGraph) -> Result<()> {
+        let mut csv_content = "timestamp,host,rtt_ms,source\n".to_string();
+
+        for data_point in &graph.data {
+            csv_content.push_str(&format!(
+                "{},{},{},{}\n",
+                data_point.timestamp.to_rfc3339(),
+                data_point.host,
+                data_point.rtt_ms,
+                data_point.source
+            ));
+        }
+
+        fs::write(&self.config.output_path, csv_content)?;
+        Ok(())
+    }
+
+    fn export_packet_size_csv(&self, graph: &PacketSizeGraph) -> Result<()> {
+        let mut csv_content = "timestamp,interface,protocol,tiny_packets,small_packets,large_packets,jumbo_packets\n".to_string();
+
+        for data_point in &graph.data {
+            csv_content.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                data_point.timestamp.to_rfc3339(),
+                data_point.interface,
+                data_point.protocol,
+                data_point.histogram.tiny,
+                data_point.histogram.small,
+                data_point.histogram.large,
+                data_point.histogram.jumbo
+            ));
+        }
+
+        fs::write(&self.config.output_path, csv_content)?;
+        Ok(())
+    }
+
+    fn export_speed_test_csv(&self, graph: &SpeedTestGraph) -> Result<()> {
+        let mut csv_content = "timestamp,server,latency_ms,jitter_ms,download_bps,upload_bps\n".to_string();
+        // Phases that failed or were skipped are left empty
+        let value = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+
+        for data_point in &graph.data {
+            csv_content.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                data_point.timestamp.to_rfc3339(),
+                data_point.server,
+                value(data_point.latency_ms),
+                value(data_point.jitter_ms),
+                value(data_point.download_bps),
+                value(data_point.upload_bps)
+            ));
+        }
+
+        fs::write(&self.config.output_path, csv_content)?;
+        Ok(())
+    }
+
     fn export_bandwidth_html(&self, _data: &ExportData) -> Result<()> {
         // HTML export implementation would go here
         Err(anyhow::anyhow!("HTML export not yet implemented"))
@@ -0,0 +1,319 @@
+use crate::analyzers::LatencyStats;
+use crate::graphs::DatabaseManager;
+use crate::graphs::terminal::{TerminalCanvas, TerminalColor, TerminalSeries};
+use crate::graphs::time_range::{bucket_start, sql_time, GraphWindow};
+use crate::graphs::{
+    time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, TimeSeries, SERIES_COLORS, TERMINAL_SERIES_COLORS,
+};
+use crate::models::TagBook;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Hosts drawn as separate lines on the timeline chart
+const TIMELINE_HOSTS: usize = 6;
+
+/// Hosts shown on the percentile bar chart
+const CHART_HOSTS: usize = 10;
+
+/// Columns per host on the percentile chart: p50, p90, p99 and a gap
+const PERCENTILE_SLOTS: u32 = 4;
+
+pub struct LatencyGraph {
+    pub config: GraphConfig,
+    pub data: Vec<LatencyDataPoint>,
+    pub interface: Option<String>,
+    /// User tags shown next to host addresses
+    pub tags: TagBook,
+    /// Bucket size of the median timeline, in seconds
+    pub resolution_secs: i64,
+}
+
+#[derive(Clone)]
+pub struct LatencyDataPoint {
+    pub timestamp: DateTime<Utc>,
+    pub host: String,
+    pub rtt_ms: f64,
+    pub source: String,
+}
+
+impl LatencyGraph {
+    pub fn new(config: GraphConfig) -> Self {
+        Self {
+            config,
+            data: Vec::new(),
+            interface: None,
+            tags: TagBook::default(),
+            resolution_secs: 60,
+        }
+    }
+
+    pub fn with_tags(mut self, tags: TagBook) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Loads every sample in the window, since percentiles cannot be taken from buckets;
+    /// the timeline's medians are bucketed at the window's resolution instead
+    pub async fn load_data(
+        &mut self,
+        db: &DatabaseManager,
+        window: &GraphWindow,
+        interface: Option<String>,
+    ) -> Result<()> {
+        self.interface = interface.clone();
+        self.resolution_secs = window.resolution_secs();
+
+        let conn = db.reader();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, host, rtt_ms, source
+             FROM latency_samples
+             WHERE timestamp BETWEEN ?1 AND ?2 AND (?3 IS NULL OR interface_name = ?3)
+             ORDER BY timestamp",
+        )?;
+        let rows = stmt.query_map(
+            rusqlite::params![sql_time(window.start), sql_time(window.end), interface],
+            |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(LatencyDataPoint {
+                    timestamp: NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")
+                        .ok()
+                        .and_then(|naive| naive.and_local_timezone(Local).earliest())
+                        .map(|t| t.with_timezone(&Utc))
+                        .unwrap_or(window.start),
+                    host: row.get(1)?,
+                    rtt_ms: row.get(2)?,
+                    source: row.get(3)?,
+                })
+            },
+        )?;
+        self.data = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Percentiles per host over the whole period, most sampled host first
+    pub fn host_stats(&self) -> Vec<LatencyStats> {
+        LatencyStats::per_host(self.data.iter().map(|d| (d.host.clone(), d.rtt_ms)))
+    }
+
+    /// Median RTT per bucket for the most sampled hosts
+    pub fn median_series(&self) -> Vec<TimeSeries> {
+        self.host_stats()
+            .into_iter()
+            .take(TIMELINE_HOSTS)
+            .map(|stats| {
+                let mut per_bucket: BTreeMap<DateTime<Utc>, Vec<f64>> = BTreeMap::new();
+                for point in self.data.iter().filter(|d| d.host == stats.host) {
+                    let bucket = bucket_start(point.timestamp, self.resolution_secs);
+                    per_bucket.entry(bucket).or_default().push(point.rtt_ms);
+                }
+                let points = per_bucket
+                    .into_iter()
+                    .filter_map(|(bucket, rtts)| LatencyStats::from_samples("", &rtts).map(|s| (bucket, s.p50_ms)))
+                    .collect();
+                TimeSeries::new(self.tags.describe(&stats.host), points)
+            })
+            .collect()
+    }
+
+    fn title(&self, chart: &str) -> String {
+        match self.interface {
+            Some(ref iface) => format!("{chart} - {iface}"),
+            None => chart.to_string(),
+        }
+    }
+
+    pub fn render_timeline_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_timeline_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_timeline_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_timeline_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let series = self.median_series();
+        let max_rtt = series.iter().map(TimeSeries::max_value).fold(0.0, f64::max);
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(self.title("Median Round-Trip Time"), ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
+                    ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
+                0f64..(max_rtt * 1.1).max(1.0),
+            )?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Time")
+            .y_desc("RTT (ms)")
+            .y_label_formatter(&|v| self.config.units.axis(*v))
+            .draw()?;
+
+        for (index, series) in series.iter().enumerate() {
+            let color = SERIES_COLORS[index % SERIES_COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(series.points.iter().copied(), &color))?
+                .label(&series.label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], color));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    pub fn render_percentile_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_percentile_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_percentile_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    /// Side-by-side p50/p90/p99 bars per host
+    fn draw_percentile_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let mut stats = self.host_stats();
+        stats.truncate(CHART_HOSTS);
+        let max_rtt = stats.iter().map(|s| s.p99_ms).fold(0.0, f64::max);
+
+        // Each host gets PERCENTILE_SLOTS columns: one per percentile plus a gap
+        let slots = stats.len() as u32 * PERCENTILE_SLOTS;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(self.title("Round-Trip Time by Host"), ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(60)
+            .y_label_area_size(60)
+            .build_cartesian_2d(0u32..slots, 0f64..(max_rtt * 1.1).max(1.0))?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(slots as usize + 1)
+            .x_desc("Host")
+            .y_desc("RTT (ms)")
+            .y_label_formatter(&|v| self.config.units.axis(*v))
+            .x_label_formatter(&|x| match x % PERCENTILE_SLOTS {
+                1 => stats
+                    .get((x / PERCENTILE_SLOTS) as usize)
+                    .map(|s| self.tags.describe(&s.host))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            })
+            .draw()?;
+
+        for (offset, label) in ["p50", "p90", "p99"].into_iter().enumerate() {
+            let color = SERIES_COLORS[offset];
+            chart
+                .draw_series(stats.iter().enumerate().map(|(i, s)| {
+                    let x = i as u32 * PERCENTILE_SLOTS + offset as u32;
+                    let value = [s.p50_ms, s.p90_ms, s.p99_ms][offset];
+                    Rectangle::new([(x, 0.0), (x + 1, value)], color.filled())
+                }))?
+                .label(label)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    /// Renders the per-minute median RTT of the busiest hosts as a text chart
+    pub fn render_timeline_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let series: Vec<TerminalSeries> = self
+            .median_series()
+            .into_iter()
+            .enumerate()
+            .map(|(index, s)| TerminalSeries {
+                label: s.label,
+                points: s.points.iter().map(|(t, v)| (t.timestamp() as f64, *v)).collect(),
+                color: TERMINAL_SERIES_COLORS[index % TERMINAL_SERIES_COLORS.len()],
+            })
+            .collect();
+
+        let labels = time_axis_labels(self.data.first().map(|d| d.timestamp), self.data.last().map(|d| d.timestamp));
+        canvas.line_chart(&self.title("Median Round-Trip Time"), &series, labels, |v| format!("{} ms", self.config.units.number(v, 1)))
+    }
+
+    /// Renders median RTT per host as bars, followed by the full percentile table
+    pub fn render_percentile_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let stats: Vec<LatencyStats> = self.host_stats().into_iter().take(CHART_HOSTS).collect();
+        let bars: Vec<(String, f64)> = stats.iter().map(|s| (self.tags.describe(&s.host), s.p50_ms)).collect();
+
+        let mut out = canvas.bar_chart(&self.title("Round-Trip Time by Host (p50)"), &bars, TerminalColor::Cyan, |v| {
+            format!("{} ms", self.config.units.number(v, 2))
+        });
+        out.push_str(&format!("\n{:<40} {:>7} {:>8} {:>8} {:>8}\n", "Host", "Samples", "p50", "p90", "p99"));
+        let ms = |value: f64| self.config.units.number(value, 2);
+        for s in &stats {
+            out.push_str(&format!(
+                "{:<40} {:>7} {:>8} {:>8} {:>8}\n",
+                self.tags.describe(&s.host), self.config.units.number(s.samples as f64, 0), ms(s.p50_ms), ms(s.p90_ms), ms(s.p99_ms)
+            ));
+        }
+        out
+    }
+}
+
+impl GraphRenderer for LatencyGraph {
+    fn render(&self, output_path: &Path) -> Result<()> {
+        self.render_timeline_chart(output_path, ImageFormat::from_path(output_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Timelike};
+
+    #[test]
+    fn test_median_series_per_host_and_minute() {
+        let start = Utc::now().with_second(0).unwrap().with_nanosecond(0).unwrap();
+        let point = |host: &str, offset_secs: i64, rtt_ms: f64| LatencyDataPoint {
+            timestamp: start + Duration::seconds(offset_secs),
+            host: host.to_string(),
+            rtt_ms,
+            source: "timestamp".to_string(),
+        };
+        let mut graph = LatencyGraph::new(GraphConfig::default());
+        graph.data = vec![
+            point("10.0.0.1", 0, 10.0),
+            point("10.0.0.1", 10, 30.0),
+            point("10.0.0.1", 20, 20.0),
+            point("10.0.0.1", 70, 50.0),
+            point("10.0.0.2", 5, 5.0),
+        ];
+
+        let series = graph.median_series();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].label, "10.0.0.1");
+        assert_eq!(series[0].points, vec![(start, 20.0), (start + Duration::minutes(1), 50.0)]);
+    }
+}
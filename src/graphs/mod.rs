@@ -1,13 +1,63 @@
+pub mod annotations;
 pub mod bandwidth_graphs;
 pub mod protocol_graphs;
 pub mod connection_graphs;
+pub mod database;
+pub mod dependency_graphs;
 pub mod export;
+pub mod latency_graphs;
+pub mod packet_size_graphs;
+pub mod speedtest_graphs;
+pub mod terminal;
+pub mod time_range;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use plotters::prelude::*;
 use std::path::Path;
 
+use crate::display::Units;
+use terminal::TerminalColor;
+
+pub use database::DatabaseManager;
+
+/// Image file formats supported by the plotters backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raster image via `BitMapBackend`
+    Png,
+    /// Vector image via `SVGBackend`, suitable for embedding in docs
+    Svg,
+}
+
+impl ImageFormat {
+    /// Maps a `--format` value to an image format, `None` for data/terminal formats
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "svg" => Some(Self::Svg),
+            _ => None,
+        }
+    }
+
+    /// Picks the format from the file extension, defaulting to PNG
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("svg") => Self::Svg,
+            _ => Self::Png,
+        }
+    }
+}
+
+/// Start/end labels for a time-based x axis in terminal charts
+pub fn time_axis_labels(first: Option<DateTime<Utc>>, last: Option<DateTime<Utc>>) -> (String, String) {
+    let format = |t: Option<DateTime<Utc>>| {
+        t.map(|t| t.with_timezone(&chrono::Local).format("%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    };
+    (format(first), format(last))
+}
+
 pub trait GraphRenderer {
     fn render(&self, output_path: &Path) -> Result<()>;
 }
@@ -21,6 +71,8 @@ pub struct GraphConfig {
     pub x_label: String,
     #[allow(dead_code)]
     pub y_label: String,
+    /// Rate unit and number separators for axis labels and legends
+    pub units: Units,
 }
 
 impl Default for GraphConfig {
@@ -31,53 +83,43 @@ impl Default for GraphConfig {
             title: "Network Monitor".to_string(),
             x_label: "Time".to_string(),
             y_label: "Value".to_string(),
+            units: Units::default(),
         }
     }
 }
 
-#[allow(dead_code)]
-pub struct TimeSeriesData {
-    pub timestamp: DateTime<Utc>,
-    pub value: f64,
+/// A named line in a multi-series chart
+#[derive(Debug, Clone)]
+pub struct TimeSeries {
     pub label: String,
+    /// (timestamp, value) points in time order
+    pub points: Vec<(DateTime<Utc>, f64)>,
 }
 
-#[allow(dead_code)]
-pub fn create_time_series_chart(
-    data: Vec<TimeSeriesData>,
-    config: GraphConfig,
-    output_path: &Path,
-) -> Result<()> {
-    let root = BitMapBackend::new(output_path, (config.width, config.height)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let mut chart = ChartBuilder::on(&root)
-        .caption(&config.title, ("sans-serif", 50).into_font())
-        .margin(10)
-        .x_label_area_size(40)
-        .y_label_area_size(50)
-        .build_cartesian_2d(
-            data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
-                ..data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
-            0f64..data.iter().map(|d| d.value).fold(0.0, f64::max),
-        )?;
-
-    chart
-        .configure_mesh()
-        .x_desc(&config.x_label)
-        .y_desc(&config.y_label)
-        .draw()?;
+impl TimeSeries {
+    pub fn new(label: impl Into<String>, points: Vec<(DateTime<Utc>, f64)>) -> Self {
+        Self {
+            label: label.into(),
+            points,
+        }
+    }
 
-    chart
-        .draw_series(LineSeries::new(
-            data.iter().map(|d| (d.timestamp, d.value)),
-            &RED,
-        ))?
-        .label("Value")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], RED));
+    /// Largest value in the series, 0 when empty
+    pub fn max_value(&self) -> f64 {
+        self.points.iter().map(|(_, v)| *v).fold(0.0, f64::max)
+    }
+}
 
-    chart.configure_series_labels().draw()?;
-    root.present()?;
+/// Colours cycled through for the series of a multi-series chart
+/// Download and upload keep their long-standing blue and red as the first two
+pub const SERIES_COLORS: [RGBColor; 6] = [BLUE, RED, GREEN, MAGENTA, CYAN, RGBColor(255, 140, 0)];
 
-    Ok(())
-}
\ No newline at end of file
+/// Terminal counterparts of `SERIES_COLORS`, in the same order
+pub const TERMINAL_SERIES_COLORS: [TerminalColor; 6] = [
+    TerminalColor::Blue,
+    TerminalColor::Red,
+    TerminalColor::Green,
+    TerminalColor::Magenta,
+    TerminalColor::Cyan,
+    TerminalColor::Yellow,
+];
@@ -0,0 +1,329 @@
+use crate::graphs::DatabaseManager;
+use crate::graphs::terminal::{TerminalCanvas, TerminalColor, TerminalSeries};
+use crate::graphs::time_range::{bucket_start, sql_time, GraphWindow};
+use crate::graphs::{
+    time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, TimeSeries, SERIES_COLORS, TERMINAL_SERIES_COLORS,
+};
+use crate::models::{PacketSizeDistribution, PacketSizeHistogram, TagBook, PACKET_SIZE_BUCKETS};
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Columns per protocol on the histogram chart: one per size bucket and a gap
+const BUCKET_SLOTS: u32 = PACKET_SIZE_BUCKETS.len() as u32 + 1;
+
+pub struct PacketSizeGraph {
+    pub config: GraphConfig,
+    pub data: Vec<PacketSizeDataPoint>,
+    pub interface: Option<String>,
+    /// User tags shown next to interface names
+    pub tags: TagBook,
+    /// Bucket size of the timeline, in seconds
+    pub resolution_secs: i64,
+}
+
+#[derive(Clone)]
+pub struct PacketSizeDataPoint {
+    pub timestamp: DateTime<Utc>,
+    pub interface: String,
+    pub protocol: String,
+    pub histogram: PacketSizeHistogram,
+}
+
+impl PacketSizeGraph {
+    pub fn new(config: GraphConfig) -> Self {
+        Self {
+            config,
+            data: Vec::new(),
+            interface: None,
+            tags: TagBook::default(),
+            resolution_secs: 60,
+        }
+    }
+
+    pub fn with_tags(mut self, tags: TagBook) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Loads the stored histograms of the window, one row per sample, interface and protocol
+    pub async fn load_data(
+        &mut self,
+        db: &DatabaseManager,
+        window: &GraphWindow,
+        interface: Option<String>,
+    ) -> Result<()> {
+        self.interface = interface.clone();
+        self.resolution_secs = window.resolution_secs();
+
+        let conn = db.reader();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, protocol, tiny_packets, small_packets, large_packets, jumbo_packets
+             FROM packet_size_stats
+             WHERE timestamp BETWEEN ?1 AND ?2 AND (?3 IS NULL OR interface_name = ?3)
+             ORDER BY timestamp",
+        )?;
+        let rows = stmt.query_map(
+            rusqlite::params![sql_time(window.start), sql_time(window.end), interface],
+            |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(PacketSizeDataPoint {
+                    timestamp: NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")
+                        .ok()
+                        .and_then(|naive| naive.and_local_timezone(Local).earliest())
+                        .map(|t| t.with_timezone(&Utc))
+                        .unwrap_or(window.start),
+                    interface: row.get(1)?,
+                    protocol: row.get(2)?,
+                    histogram: PacketSizeHistogram {
+                        tiny: row.get::<_, i64>(3)? as u64,
+                        small: row.get::<_, i64>(4)? as u64,
+                        large: row.get::<_, i64>(5)? as u64,
+                        jumbo: row.get::<_, i64>(6)? as u64,
+                    },
+                })
+            },
+        )?;
+        self.data = rows.collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Every loaded row added up per interface and protocol
+    pub fn distribution(&self) -> PacketSizeDistribution {
+        let mut sizes = PacketSizeDistribution::default();
+        for point in &self.data {
+            sizes.merge(&point.interface, &point.protocol, &point.histogram);
+        }
+        sizes
+    }
+
+    /// Packets per size bucket and timeline bucket, one series per size bucket
+    pub fn bucket_series(&self) -> Vec<TimeSeries> {
+        let mut per_bucket: BTreeMap<DateTime<Utc>, PacketSizeHistogram> = BTreeMap::new();
+        for point in &self.data {
+            per_bucket
+                .entry(bucket_start(point.timestamp, self.resolution_secs))
+                .or_default()
+                .merge(&point.histogram);
+        }
+        PACKET_SIZE_BUCKETS
+            .iter()
+            .enumerate()
+            .map(|(index, label)| {
+                let points = per_bucket
+                    .iter()
+                    .map(|(bucket, histogram)| (*bucket, histogram.counts()[index] as f64))
+                    .collect();
+                TimeSeries::new(format!("{label} bytes"), points)
+            })
+            .collect()
+    }
+
+    fn title(&self, chart: &str) -> String {
+        match self.interface {
+            Some(ref iface) => format!("{chart} - {}", self.tags.describe(iface)),
+            None => chart.to_string(),
+        }
+    }
+
+    pub fn render_timeline_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_timeline_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_timeline_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_timeline_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let series = self.bucket_series();
+        let max_packets = series.iter().map(TimeSeries::max_value).fold(0.0, f64::max);
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(self.title("Packets by Size"), ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now)
+                    ..self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now),
+                0f64..(max_packets * 1.1).max(1.0),
+            )?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Time")
+            .y_desc("Packets")
+            .y_label_formatter(&|v| self.config.units.axis(*v))
+            .draw()?;
+
+        for (index, series) in series.iter().enumerate() {
+            let color = SERIES_COLORS[index % SERIES_COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(series.points.iter().copied(), &color))?
+                .label(&series.label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], color));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    pub fn render_histogram_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_histogram_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_histogram_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    /// Side-by-side size bucket bars per transport protocol, as shares of its packets
+    fn draw_histogram_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let protocols = self.distribution().by_protocol();
+
+        // Each protocol gets BUCKET_SLOTS columns: one per size bucket plus a gap
+        let slots = protocols.len() as u32 * BUCKET_SLOTS;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(self.title("Packet Sizes by Protocol"), ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(60)
+            .y_label_area_size(60)
+            .build_cartesian_2d(0u32..slots, 0f64..100.0)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(slots as usize + 1)
+            .x_desc("Protocol")
+            .y_desc("Packets (%)")
+            .x_label_formatter(&|x| match x % BUCKET_SLOTS {
+                2 => protocols.get((x / BUCKET_SLOTS) as usize).map(|(p, _)| p.clone()).unwrap_or_default(),
+                _ => String::new(),
+            })
+            .draw()?;
+
+        for (offset, label) in PACKET_SIZE_BUCKETS.into_iter().enumerate() {
+            let color = SERIES_COLORS[offset];
+            chart
+                .draw_series(protocols.iter().enumerate().map(|(i, (_, histogram))| {
+                    let x = i as u32 * BUCKET_SLOTS + offset as u32;
+                    let share = histogram.counts()[offset] as f64 / histogram.total().max(1) as f64 * 100.0;
+                    Rectangle::new([(x, 0.0), (x + 1, share)], color.filled())
+                }))?
+                .label(format!("{label} bytes"))
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    /// Renders packets per size bucket over time as a text chart
+    pub fn render_timeline_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let series: Vec<TerminalSeries> = self
+            .bucket_series()
+            .into_iter()
+            .enumerate()
+            .map(|(index, s)| TerminalSeries {
+                label: s.label,
+                points: s.points.iter().map(|(t, v)| (t.timestamp() as f64, *v)).collect(),
+                color: TERMINAL_SERIES_COLORS[index % TERMINAL_SERIES_COLORS.len()],
+            })
+            .collect();
+
+        let labels = time_axis_labels(self.data.first().map(|d| d.timestamp), self.data.last().map(|d| d.timestamp));
+        canvas.line_chart(&self.title("Packets by Size"), &series, labels, |v| self.config.units.number(v, 0))
+    }
+
+    /// Renders all packets per size bucket as bars, followed by the shares per protocol
+    pub fn render_histogram_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let sizes = self.distribution();
+        let bars: Vec<(String, f64)> = PACKET_SIZE_BUCKETS
+            .iter()
+            .zip(sizes.total().counts())
+            .map(|(label, count)| (format!("{label} bytes"), count as f64))
+            .collect();
+
+        let mut out = canvas.bar_chart(&self.title("Packets by Size"), &bars, TerminalColor::Cyan, |v| {
+            self.config.units.number(v, 0)
+        });
+        out.push_str(&format!("\n{:<10} {:>10}", "Protocol", "Packets"));
+        for label in PACKET_SIZE_BUCKETS {
+            out.push_str(&format!(" {label:>9}"));
+        }
+        out.push('\n');
+        for (protocol, histogram) in sizes.by_protocol() {
+            let total = histogram.total().max(1) as f64;
+            out.push_str(&format!("{:<10} {:>10}", protocol, self.config.units.number(histogram.total() as f64, 0)));
+            for count in histogram.counts() {
+                out.push_str(&format!(" {:>8.1}%", count as f64 / total * 100.0));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl GraphRenderer for PacketSizeGraph {
+    fn render(&self, output_path: &Path) -> Result<()> {
+        self.render_histogram_chart(output_path, ImageFormat::from_path(output_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Timelike};
+
+    #[test]
+    fn test_bucket_series_per_size_and_minute() {
+        let start = Utc::now().with_second(0).unwrap().with_nanosecond(0).unwrap();
+        let point = |interface: &str, protocol: &str, offset_secs: i64, tiny: u64, large: u64| PacketSizeDataPoint {
+            timestamp: start + Duration::seconds(offset_secs),
+            interface: interface.to_string(),
+            protocol: protocol.to_string(),
+            histogram: PacketSizeHistogram { tiny, large, ..Default::default() },
+        };
+        let mut graph = PacketSizeGraph::new(GraphConfig::default());
+        graph.data = vec![
+            point("eth0", "TCP", 0, 10, 5),
+            point("eth0", "UDP", 0, 2, 0),
+            point("eth0", "TCP", 10, 1, 1),
+            point("wlan0", "TCP", 70, 0, 4),
+        ];
+
+        let series = graph.bucket_series();
+        assert_eq!(series.len(), PACKET_SIZE_BUCKETS.len());
+        assert_eq!(series[0].label, "0-64 bytes");
+        assert_eq!(series[0].points, vec![(start, 13.0), (start + Duration::minutes(1), 0.0)]);
+        assert_eq!(series[2].points, vec![(start, 6.0), (start + Duration::minutes(1), 4.0)]);
+
+        let sizes = graph.distribution();
+        assert_eq!(sizes.total(), PacketSizeHistogram { tiny: 13, large: 10, ..Default::default() });
+        assert_eq!(sizes.by_interface().len(), 2);
+    }
+}
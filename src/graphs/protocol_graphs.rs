@@ -1,11 +1,20 @@
-use crate::graphs::{GraphConfig, GraphRenderer};
-use crate::cli::graph_commands::DatabaseManager;
+use crate::graphs::terminal::{TerminalCanvas, TerminalColor, TerminalSeries};
+use crate::graphs::time_range::GraphWindow;
+use crate::graphs::{time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, SERIES_COLORS, TERMINAL_SERIES_COLORS};
+use crate::graphs::DatabaseManager;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use plotters::coord::Shift;
 use plotters::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 
+/// Layers in the stacked chart; protocols beyond the busiest few are merged into "Other"
+const STACKED_LAYERS: usize = SERIES_COLORS.len();
+
+/// Protocol (or "Other") and the top edge of its band at each sample time
+type StackedLayer = (String, Vec<u64>);
+
 pub struct ProtocolGraph {
     pub config: GraphConfig,
     pub data: Vec<ProtocolDataPoint>,
@@ -29,61 +38,31 @@ impl ProtocolGraph {
         }
     }
 
+    /// Loads packet and byte counts per protocol, summed by the database into buckets
+    /// of the window's resolution
     pub async fn load_data(
         &mut self,
         db: &DatabaseManager,
-        start_time: DateTime<Utc>,
-        end_time: DateTime<Utc>,
+        window: &GraphWindow,
         interface: Option<String>,
     ) -> Result<()> {
         self.interface = interface.clone();
-        
-        let query = if let Some(ref _iface) = interface {
-            "SELECT timestamp, protocol_name, packet_count, byte_count 
-             FROM protocol_distribution 
-             WHERE interface_name = ? AND timestamp BETWEEN ? AND ?
-             ORDER BY timestamp, protocol_name"
-        } else {
-            "SELECT timestamp, protocol_name, SUM(packet_count) as packet_count, SUM(byte_count) as byte_count
-             FROM protocol_distribution 
-             WHERE timestamp BETWEEN ? AND ?
-             GROUP BY timestamp, protocol_name
-             ORDER BY timestamp, protocol_name"
-        };
-
-        let conn = db.connection.lock().unwrap();
-        let mut stmt = conn.prepare(query)?;
-        
-        let mapper = |row: &rusqlite::Row| {
-            Ok(ProtocolDataPoint {
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<_, String>(0)?)
-                    .unwrap()
-                    .with_timezone(&Utc),
-                protocol: row.get(1)?,
-                packet_count: row.get(2)?,
-                byte_count: row.get(3)?,
-            })
-        };
-        
-        let rows = if let Some(ref iface) = interface {
-            stmt.query_map(
-                [iface.as_str(), &start_time.to_rfc3339(), &end_time.to_rfc3339()],
-                mapper,
-            )?
-        } else {
-            stmt.query_map(
-                [&start_time.to_rfc3339(), &end_time.to_rfc3339()],
-                mapper,
-            )?
-        };
-
-        self.data = rows.collect::<Result<Vec<_>, _>>()?;
+        self.data = db.protocol_buckets(window, interface.as_deref())?;
         Ok(())
     }
 
-    pub fn render_pie_chart(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_pie_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_pie_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_pie_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_pie_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -127,9 +106,18 @@ impl ProtocolGraph {
         Ok(())
     }
 
-    pub fn render_bar_chart(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_bar_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_bar_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_bar_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_bar_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -163,6 +151,7 @@ impl ProtocolGraph {
             .configure_mesh()
             .x_desc("Protocol")
             .y_desc("Packet Count")
+            .y_label_formatter(&|v| self.config.units.number(*v as f64, 0))
             .x_label_formatter(&|x| {
                 if *x as usize >= sorted_protocols.len() {
                     String::new()
@@ -181,9 +170,18 @@ impl ProtocolGraph {
         Ok(())
     }
 
-    pub fn render_timeline_chart(&self, output_path: &Path) -> Result<()> {
-        let root = BitMapBackend::new(output_path, (self.config.width, self.config.height))
-            .into_drawing_area();
+    pub fn render_timeline_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_timeline_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_timeline_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    fn draw_timeline_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
         root.fill(&WHITE)?;
 
         let title = if let Some(ref iface) = self.interface {
@@ -215,6 +213,7 @@ impl ProtocolGraph {
             .configure_mesh()
             .x_desc("Time")
             .y_desc("Packet Count")
+            .y_label_formatter(&|v| self.config.units.number(*v as f64, 0))
             .draw()?;
 
         let colors = [&RED, &BLUE, &GREEN, &MAGENTA, &CYAN, &BLACK];
@@ -240,6 +239,220 @@ impl ProtocolGraph {
         Ok(())
     }
 
+    pub fn render_stacked_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => self.draw_stacked_chart(BitMapBackend::new(output_path, size).into_drawing_area()),
+            ImageFormat::Svg => self.draw_stacked_chart(SVGBackend::new(output_path, size).into_drawing_area()),
+        }
+    }
+
+    /// Stacked area chart of packet counts, busiest protocol at the bottom
+    fn draw_stacked_chart<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let title = if let Some(ref iface) = self.interface {
+            format!("Protocol Composition - {iface}")
+        } else {
+            "Protocol Composition".to_string()
+        };
+
+        let (timestamps, layers) = self.stacked_layers();
+        let max_total = layers.last().map(|(_, top)| top.iter().copied().max().unwrap_or(0)).unwrap_or(0);
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&title, ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                timestamps.first().copied().unwrap_or_else(Utc::now)
+                    ..timestamps.last().copied().unwrap_or_else(Utc::now),
+                0u64..max_total.max(1),
+            )?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Time")
+            .y_desc("Packet Count")
+            .y_label_formatter(&|v| self.config.units.number(*v as f64, 0))
+            .draw()?;
+
+        // Each band spans from the previous layer's top edge up to its own
+        let zeros = vec![0u64; timestamps.len()];
+        for (i, (protocol, tops)) in layers.iter().enumerate() {
+            let color = SERIES_COLORS[i % SERIES_COLORS.len()];
+            let bottoms = if i == 0 { &zeros } else { &layers[i - 1].1 };
+            let outline: Vec<(DateTime<Utc>, u64)> = timestamps.iter().copied().zip(tops.iter().copied())
+                .chain(timestamps.iter().copied().zip(bottoms.iter().copied()).rev())
+                .collect();
+
+            chart
+                .draw_series(std::iter::once(Polygon::new(outline, color.mix(0.6).filled())))?
+                .label(protocol)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+            chart.draw_series(LineSeries::new(timestamps.iter().copied().zip(tops.iter().copied()), color))?;
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    /// Cumulative packet counts per layer at every sample time
+    ///
+    /// Layers are ordered busiest first; layer `i` holds the sum of layers `0..=i`,
+    /// i.e. the top edge of its band in the stacked chart.
+    fn stacked_layers(&self) -> (Vec<DateTime<Utc>>, Vec<StackedLayer>) {
+        let timestamps: Vec<DateTime<Utc>> = self.data.iter()
+            .map(|d| d.timestamp)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let totals = self.protocol_totals();
+        let named = if totals.len() > STACKED_LAYERS { STACKED_LAYERS - 1 } else { totals.len() };
+        let mut layer_names: Vec<String> = totals.iter().take(named).map(|(p, _)| p.clone()).collect();
+        if totals.len() > named {
+            layer_names.push("Other".to_string());
+        }
+        let layer_of: HashMap<&str, usize> = layer_names.iter()
+            .take(named)
+            .enumerate()
+            .map(|(i, p)| (p.as_str(), i))
+            .collect();
+
+        let mut counts = vec![vec![0u64; timestamps.len()]; layer_names.len()];
+        for data_point in &self.data {
+            let layer = layer_of.get(data_point.protocol.as_str()).copied().unwrap_or(named);
+            if let Ok(t) = timestamps.binary_search(&data_point.timestamp) {
+                counts[layer][t] += data_point.packet_count;
+            }
+        }
+
+        for layer in 1..counts.len() {
+            let (below, rest) = counts.split_at_mut(layer);
+            for (top, lower) in rest[0].iter_mut().zip(&below[layer - 1]) {
+                *top += lower;
+            }
+        }
+
+        (timestamps, layer_names.into_iter().zip(counts).collect())
+    }
+
+    /// Renders packet share per protocol as a text chart for the terminal
+    /// A pie does not translate to text, so shares are drawn as percentage bars
+    pub fn render_pie_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Protocol Distribution - {iface}")
+        } else {
+            "Protocol Distribution".to_string()
+        };
+
+        let totals = self.protocol_totals();
+        let total_packets: u64 = totals.iter().map(|(_, count)| count).sum();
+        let shares: Vec<(String, f64)> = totals
+            .into_iter()
+            .map(|(protocol, count)| (protocol, count as f64 / total_packets.max(1) as f64 * 100.0))
+            .collect();
+
+        canvas.bar_chart(&title, &shares, TerminalColor::Magenta, |v| format!("{}%", self.config.units.number(v, 1)))
+    }
+
+    /// Renders packet counts for the top protocols as a text chart for the terminal
+    pub fn render_bar_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Protocol Usage - {iface}")
+        } else {
+            "Protocol Usage".to_string()
+        };
+
+        let bars: Vec<(String, f64)> = self
+            .protocol_totals()
+            .into_iter()
+            .take(10)
+            .map(|(protocol, count)| (protocol, count as f64))
+            .collect();
+
+        canvas.bar_chart(&title, &bars, TerminalColor::Blue, |v| format!("{} packets", self.config.units.number(v, 0)))
+    }
+
+    /// Renders per-protocol packet counts over time as a text chart for the terminal
+    pub fn render_timeline_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Protocol Timeline - {iface}")
+        } else {
+            "Protocol Timeline".to_string()
+        };
+
+        let series: Vec<TerminalSeries> = self
+            .protocol_totals()
+            .into_iter()
+            .take(TerminalColor::PALETTE.len())
+            .enumerate()
+            .map(|(i, (protocol, _))| TerminalSeries {
+                points: self
+                    .data
+                    .iter()
+                    .filter(|d| d.protocol == protocol)
+                    .map(|d| (d.timestamp.timestamp() as f64, d.packet_count as f64))
+                    .collect(),
+                label: protocol,
+                color: TerminalColor::PALETTE[i],
+            })
+            .collect();
+
+        let labels = time_axis_labels(
+            self.data.first().map(|d| d.timestamp),
+            self.data.last().map(|d| d.timestamp),
+        );
+        canvas.line_chart(&title, &series, labels, |v| self.config.units.number(v, 0))
+    }
+
+    /// Renders the stacked composition as a text chart for the terminal
+    /// Text cannot fill areas, so each layer is drawn as the line along its top edge
+    pub fn render_stacked_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let title = if let Some(ref iface) = self.interface {
+            format!("Protocol Composition - {iface}")
+        } else {
+            "Protocol Composition".to_string()
+        };
+
+        let (timestamps, layers) = self.stacked_layers();
+        let series: Vec<TerminalSeries> = layers
+            .into_iter()
+            .enumerate()
+            .map(|(i, (protocol, tops))| TerminalSeries {
+                label: protocol,
+                points: timestamps.iter().zip(tops).map(|(t, top)| (t.timestamp() as f64, top as f64)).collect(),
+                color: TERMINAL_SERIES_COLORS[i % TERMINAL_SERIES_COLORS.len()],
+            })
+            .collect();
+
+        let labels = time_axis_labels(timestamps.first().copied(), timestamps.last().copied());
+        canvas.line_chart(&title, &series, labels, |v| self.config.units.number(v, 0))
+    }
+
+    /// Total packets per protocol, busiest first
+    fn protocol_totals(&self) -> Vec<(String, u64)> {
+        let mut protocol_totals: HashMap<String, u64> = HashMap::new();
+        for data_point in &self.data {
+            *protocol_totals.entry(data_point.protocol.clone()).or_insert(0) += data_point.packet_count;
+        }
+
+        let mut sorted_protocols: Vec<_> = protocol_totals.into_iter().collect();
+        sorted_protocols.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sorted_protocols
+    }
+
     pub fn get_protocol_summary(&self) -> HashMap<String, ProtocolSummary> {
         let mut summaries: HashMap<String, ProtocolSummary> = HashMap::new();
         
@@ -274,7 +487,7 @@ pub struct ProtocolSummary {
 
 impl GraphRenderer for ProtocolGraph {
     fn render(&self, output_path: &Path) -> Result<()> {
-        self.render_bar_chart(output_path)
+        self.render_bar_chart(output_path, ImageFormat::from_path(output_path))
     }
 }
 
@@ -302,4 +515,45 @@ pub fn create_protocol_sparkline(data: &[ProtocolDataPoint], protocol: &str) ->
             sparkline_chars[normalized.min(7)]
         })
         .collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn point(minute: u32, protocol: &str, packets: u64) -> ProtocolDataPoint {
+        ProtocolDataPoint {
+            timestamp: Utc.with_ymd_and_hms(2025, 1, 1, 12, minute, 0).unwrap(),
+            protocol: protocol.to_string(),
+            packet_count: packets,
+            byte_count: packets * 100,
+        }
+    }
+
+    #[test]
+    fn test_stacked_layers_are_cumulative() {
+        let mut graph = ProtocolGraph::new(GraphConfig::default());
+        graph.data = vec![point(0, "TCP", 10), point(0, "UDP", 5), point(1, "TCP", 20)];
+
+        let (timestamps, layers) = graph.stacked_layers();
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(layers[0], ("TCP".to_string(), vec![10, 20]));
+        assert_eq!(layers[1], ("UDP".to_string(), vec![15, 20]));
+    }
+
+    #[test]
+    fn test_stacked_layers_merge_quiet_protocols() {
+        let mut graph = ProtocolGraph::new(GraphConfig::default());
+        graph.data = ["TCP", "UDP", "ICMP", "QUIC", "DNS", "ARP", "NTP"]
+            .iter()
+            .enumerate()
+            .map(|(i, protocol)| point(0, protocol, 100 - i as u64))
+            .collect();
+
+        let (_, layers) = graph.stacked_layers();
+        assert_eq!(layers.len(), STACKED_LAYERS);
+        assert_eq!(layers.last().unwrap().0, "Other");
+        assert_eq!(layers.last().unwrap().1, vec![(94..=100).sum::<u64>()]);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::graphs::DatabaseManager;
+use crate::display::Scale;
+use crate::graphs::terminal::{TerminalCanvas, TerminalSeries};
+use crate::graphs::time_range::GraphWindow;
+use crate::graphs::{
+    time_axis_labels, GraphConfig, GraphRenderer, ImageFormat, TimeSeries, SERIES_COLORS,
+    TERMINAL_SERIES_COLORS,
+};
+use crate::storage::packet_storage::read_speed_tests;
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::Path;
+
+/// Most stored results loaded into one graph
+const MAX_RESULTS: usize = 10_000;
+
+pub struct SpeedTestGraph {
+    pub config: GraphConfig,
+    /// Results in time order
+    pub data: Vec<SpeedTestDataPoint>,
+}
+
+#[derive(Clone)]
+pub struct SpeedTestDataPoint {
+    pub timestamp: DateTime<Utc>,
+    pub server: String,
+    pub latency_ms: Option<f64>,
+    pub jitter_ms: Option<f64>,
+    /// Bytes per second
+    pub download_bps: Option<f64>,
+    pub upload_bps: Option<f64>,
+}
+
+impl SpeedTestGraph {
+    pub fn new(config: GraphConfig) -> Self {
+        Self {
+            config,
+            data: Vec::new(),
+        }
+    }
+
+    /// Loads each test in the window, or with an explicit `--resolution` their averages per
+    /// bucket, as tests are usually too sparse for an automatic bucket size to help
+    pub async fn load_data(&mut self, db: &DatabaseManager, window: &GraphWindow) -> Result<()> {
+        if window.explicit_resolution {
+            self.data = db.speed_test_buckets(window)?;
+            return Ok(());
+        }
+
+        let conn = db.reader();
+        let mut results = read_speed_tests(&conn, Some(window.start.with_timezone(&Local)), MAX_RESULTS)?;
+        results.reverse();
+        self.data = results
+            .into_iter()
+            .filter(|result| result.timestamp.with_timezone(&Utc) <= window.end)
+            .map(|result| SpeedTestDataPoint {
+                timestamp: result.timestamp.with_timezone(&Utc),
+                server: result.server,
+                latency_ms: result.latency_ms,
+                jitter_ms: result.jitter_ms,
+                download_bps: result.download_bps,
+                upload_bps: result.upload_bps,
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn series(&self, label: &str, value: impl Fn(&SpeedTestDataPoint) -> Option<f64>) -> TimeSeries {
+        TimeSeries::new(label, self.data.iter().filter_map(|d| value(d).map(|v| (d.timestamp, v))).collect())
+    }
+
+    /// Download and upload speed (bytes/s) per test
+    pub fn speed_series(&self) -> Vec<TimeSeries> {
+        vec![self.series("Download", |d| d.download_bps), self.series("Upload", |d| d.upload_bps)]
+    }
+
+    /// Latency and jitter (ms) per test
+    pub fn latency_series(&self) -> Vec<TimeSeries> {
+        vec![self.series("Latency", |d| d.latency_ms), self.series("Jitter", |d| d.jitter_ms)]
+    }
+
+    fn speed_unit(&self) -> Scale {
+        self.config.units.rate_scale(self.speed_series().iter().map(TimeSeries::max_value).fold(0.0, f64::max))
+    }
+
+    fn time_range(&self) -> std::ops::Range<DateTime<Utc>> {
+        let first = self.data.first().map(|d| d.timestamp).unwrap_or_else(Utc::now);
+        let last = self.data.last().map(|d| d.timestamp).unwrap_or_else(Utc::now);
+        // A single test still needs a range to plot on
+        if last > first {
+            first..last
+        } else {
+            first - chrono::Duration::hours(1)..first + chrono::Duration::hours(1)
+        }
+    }
+
+    pub fn render_speed_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        let unit = self.speed_unit();
+        let series: Vec<TimeSeries> = self
+            .speed_series()
+            .into_iter()
+            .map(|s| TimeSeries::new(s.label, s.points.iter().map(|(t, v)| (*t, unit.scale(*v))).collect()))
+            .collect();
+        self.render_chart(output_path, format, "Speed Test Results", &format!("Speed ({})", unit.label()), &series)
+    }
+
+    pub fn render_latency_chart(&self, output_path: &Path, format: ImageFormat) -> Result<()> {
+        self.render_chart(output_path, format, "Speed Test Latency", "Time (ms)", &self.latency_series())
+    }
+
+    fn render_chart(
+        &self,
+        output_path: &Path,
+        format: ImageFormat,
+        title: &str,
+        y_desc: &str,
+        series: &[TimeSeries],
+    ) -> Result<()> {
+        let size = (self.config.width, self.config.height);
+        match format {
+            ImageFormat::Png => {
+                self.draw_chart(BitMapBackend::new(output_path, size).into_drawing_area(), title, y_desc, series)
+            }
+            ImageFormat::Svg => {
+                self.draw_chart(SVGBackend::new(output_path, size).into_drawing_area(), title, y_desc, series)
+            }
+        }
+    }
+
+    /// Lines through the results, with a marker on each test since tests are far apart
+    fn draw_chart<DB: DrawingBackend>(
+        &self,
+        root: DrawingArea<DB, Shift>,
+        title: &str,
+        y_desc: &str,
+        series: &[TimeSeries],
+    ) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let max_value = series.iter().map(TimeSeries::max_value).fold(0.0, f64::max);
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 50).into_font())
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(self.time_range(), 0f64..(max_value * 1.1).max(1.0))?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Time")
+            .y_desc(y_desc)
+            .y_label_formatter(&|v| self.config.units.axis(*v))
+            .draw()?;
+
+        for (index, series) in series.iter().enumerate() {
+            let color = SERIES_COLORS[index % SERIES_COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(series.points.iter().copied(), &color))?
+                .label(&series.label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 10, y)], color));
+            chart.draw_series(series.points.iter().map(|point| Circle::new(*point, 3, color.filled())))?;
+        }
+
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+
+        Ok(())
+    }
+
+    /// Renders download and upload speed over time as a text chart
+    pub fn render_speed_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let unit = self.speed_unit();
+        let series = self.terminal_series(self.speed_series(), |v| unit.scale(v));
+        canvas.line_chart("Speed Test Results", &series, self.time_labels(), |v| {
+            format!("{} {}", self.config.units.number(v, 1), unit.label())
+        })
+    }
+
+    /// Renders latency and jitter over time as a text chart
+    pub fn render_latency_terminal(&self, canvas: &TerminalCanvas) -> String {
+        let series = self.terminal_series(self.latency_series(), |v| v);
+        canvas.line_chart("Speed Test Latency", &series, self.time_labels(), |v| format!("{} ms", self.config.units.number(v, 1)))
+    }
+
+    fn terminal_series(&self, series: Vec<TimeSeries>, scale: impl Fn(f64) -> f64) -> Vec<TerminalSeries> {
+        series
+            .into_iter()
+            .enumerate()
+            .map(|(index, s)| TerminalSeries {
+                label: s.label,
+                points: s.points.iter().map(|(t, v)| (t.timestamp() as f64, scale(*v))).collect(),
+                color: TERMINAL_SERIES_COLORS[index % TERMINAL_SERIES_COLORS.len()],
+            })
+            .collect()
+    }
+
+    fn time_labels(&self) -> (String, String) {
+        time_axis_labels(self.data.first().map(|d| d.timestamp), self.data.last().map(|d| d.timestamp))
+    }
+}
+
+impl GraphRenderer for SpeedTestGraph {
+    fn render(&self, output_path: &Path) -> Result<()> {
+        self.render_speed_chart(output_path, ImageFormat::from_path(output_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_series_skip_missing_phases() {
+        let start = Utc::now();
+        let point = |hours: i64, upload_bps: Option<f64>| SpeedTestDataPoint {
+            timestamp: start + Duration::hours(hours),
+            server: "speed.example.net".to_string(),
+            latency_ms: Some(12.0),
+            jitter_ms: None,
+            download_bps: Some(12_500_000.0),
+            upload_bps,
+        };
+        let mut graph = SpeedTestGraph::new(GraphConfig::default());
+        graph.data = vec![point(0, Some(2_500_000.0)), point(1, None)];
+
+        let speed = graph.speed_series();
+        assert_eq!(speed[0].points.len(), 2);
+        assert_eq!(speed[1].points, vec![(start, 2_500_000.0)]);
+        assert_eq!(graph.speed_unit().label(), "MB/s");
+        assert!(graph.latency_series()[1].points.is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+use std::io::IsTerminal;
+
+/// Character set and colouring used when drawing graphs in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalStyle {
+    /// Plain 7-bit ASCII, safe for logs, pipes and any terminal
+    Ascii,
+    /// Unicode block characters with ANSI colours
+    Ansi,
+}
+
+impl TerminalStyle {
+    /// Maps a `--format` value to a terminal style
+    /// `terminal` picks ANSI when stdout is a TTY and plain ASCII otherwise
+    pub fn parse(format: &str) -> Option<Self> {
+        match format.to_lowercase().as_str() {
+            "ascii" => Some(Self::Ascii),
+            "ansi" => Some(Self::Ansi),
+            "terminal" | "term" => Some(if std::io::stdout().is_terminal() {
+                Self::Ansi
+            } else {
+                Self::Ascii
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Series colours, matching the palette of the PNG/SVG charts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalColor {
+    Red,
+    Green,
+    Blue,
+    Magenta,
+    Cyan,
+    Yellow,
+}
+
+impl TerminalColor {
+    /// Palette cycled through for multi-series charts
+    pub const PALETTE: [TerminalColor; 6] = [
+        TerminalColor::Red,
+        TerminalColor::Blue,
+        TerminalColor::Green,
+        TerminalColor::Magenta,
+        TerminalColor::Cyan,
+        TerminalColor::Yellow,
+    ];
+
+    fn ansi_code(&self) -> &'static str {
+        match self {
+            TerminalColor::Red => "\x1b[31m",
+            TerminalColor::Green => "\x1b[32m",
+            TerminalColor::Yellow => "\x1b[33m",
+            TerminalColor::Blue => "\x1b[34m",
+            TerminalColor::Magenta => "\x1b[35m",
+            TerminalColor::Cyan => "\x1b[36m",
+        }
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Glyphs used for successive series in ASCII mode, where colour is not available
+const ASCII_MARKERS: [char; 6] = ['*', '+', 'o', 'x', '#', '@'];
+
+/// A named line series for `TerminalCanvas::line_chart`
+pub struct TerminalSeries {
+    pub label: String,
+    /// (x, y) points, x increasing
+    pub points: Vec<(f64, f64)>,
+    pub color: TerminalColor,
+}
+
+/// A glyph drawn in the row under a line chart's plot, such as an event marker
+pub struct TerminalMarker {
+    /// Position on the same x scale as the chart's points
+    pub x: f64,
+    pub glyph: char,
+    pub color: TerminalColor,
+}
+
+/// Fixed-size character canvas that renders charts to a string
+pub struct TerminalCanvas {
+    width: usize,
+    height: usize,
+    style: TerminalStyle,
+}
+
+impl TerminalCanvas {
+    /// Width reserved for y-axis labels
+    const Y_LABEL_WIDTH: usize = 12;
+
+    pub fn new(width: usize, height: usize, style: TerminalStyle) -> Self {
+        Self {
+            width: width.max(Self::Y_LABEL_WIDTH + 20),
+            height: height.max(5),
+            style,
+        }
+    }
+
+    /// Canvas sized to the current terminal, falling back to 100x24
+    pub fn for_terminal(style: TerminalStyle) -> Self {
+        let (columns, rows) = crossterm::terminal::size().unwrap_or((100, 24));
+        Self::new(columns as usize, (rows as usize).saturating_sub(8).min(30), style)
+    }
+
+    /// Draws one or more line series sharing the same axes
+    /// `x_labels` are printed under the left and right edge of the plot area
+    pub fn line_chart(
+        &self,
+        title: &str,
+        series: &[TerminalSeries],
+        x_labels: (String, String),
+        y_format: impl Fn(f64) -> String,
+    ) -> String {
+        self.line_chart_with_markers(title, series, &[], x_labels, y_format)
+    }
+
+    /// Draws a line chart with `markers` in a row under the x axis; markers outside the
+    /// points' x range are left out, and where two share a column the later one is shown
+    pub fn line_chart_with_markers(
+        &self,
+        title: &str,
+        series: &[TerminalSeries],
+        markers: &[TerminalMarker],
+        x_labels: (String, String),
+        y_format: impl Fn(f64) -> String,
+    ) -> String {
+        let plot_width = self.width - Self::Y_LABEL_WIDTH - 2;
+        let plot_height = self.height;
+
+        let all_points = series.iter().flat_map(|s| s.points.iter());
+        let (x_min, x_max) = all_points
+            .clone()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+        let y_max = all_points.map(|p| p.1).fold(0.0, f64::max).max(1.0) * 1.1;
+        let x_span = if x_max > x_min { x_max - x_min } else { 1.0 };
+
+        // Each cell holds the index of the series drawn there (last one wins)
+        let mut grid: Vec<Vec<Option<usize>>> = vec![vec![None; plot_width]; plot_height];
+        let to_cell = |(x, y): (f64, f64)| -> (f64, f64) {
+            let col = (x - x_min) / x_span * (plot_width - 1) as f64;
+            let row = (1.0 - y / y_max) * (plot_height - 1) as f64;
+            (col, row)
+        };
+
+        for (index, s) in series.iter().enumerate() {
+            let cells: Vec<(f64, f64)> = s.points.iter().copied().map(to_cell).collect();
+            if let [only] = cells.as_slice() {
+                grid[only.1.round() as usize][only.0.round() as usize] = Some(index);
+            }
+            for pair in cells.windows(2) {
+                let (c0, r0) = pair[0];
+                let (c1, r1) = pair[1];
+                let steps = (c1 - c0).abs().max((r1 - r0).abs()).ceil().max(1.0) as usize;
+                for step in 0..=steps {
+                    let t = step as f64 / steps as f64;
+                    let col = (c0 + (c1 - c0) * t).round() as usize;
+                    let row = (r0 + (r1 - r0) * t).round() as usize;
+                    grid[row.min(plot_height - 1)][col.min(plot_width - 1)] = Some(index);
+                }
+            }
+        }
+
+        let mut out = format!("{title}\n");
+        for (row_index, row) in grid.iter().enumerate() {
+            let label = if row_index == 0 {
+                y_format(y_max)
+            } else if row_index == plot_height / 2 {
+                y_format(y_max / 2.0)
+            } else if row_index == plot_height - 1 {
+                y_format(0.0)
+            } else {
+                String::new()
+            };
+            out.push_str(&format!("{:>width$} |", label, width = Self::Y_LABEL_WIDTH));
+            for cell in row {
+                match cell {
+                    Some(index) => out.push_str(&self.marker(*index, series[*index].color)),
+                    None => out.push(' '),
+                }
+            }
+            out.push('\n');
+        }
+
+        out.push_str(&format!("{:>width$} +{}\n", "", "-".repeat(plot_width), width = Self::Y_LABEL_WIDTH));
+        if !markers.is_empty() {
+            let mut row: Vec<Option<&TerminalMarker>> = vec![None; plot_width];
+            for marker in markers.iter().filter(|m| m.x >= x_min && m.x <= x_max) {
+                row[((marker.x - x_min) / x_span * (plot_width - 1) as f64).round() as usize] = Some(marker);
+            }
+            out.push_str(&format!("{:>width$}  ", "", width = Self::Y_LABEL_WIDTH));
+            for cell in row {
+                match cell {
+                    Some(marker) => out.push_str(&self.glyph(marker.glyph, marker.color)),
+                    None => out.push(' '),
+                }
+            }
+            out.push('\n');
+        }
+        let (left, right) = x_labels;
+        let gap = plot_width.saturating_sub(left.chars().count() + right.chars().count());
+        out.push_str(&format!(
+            "{:>width$}  {}{}{}\n",
+            "",
+            left,
+            " ".repeat(gap),
+            right,
+            width = Self::Y_LABEL_WIDTH
+        ));
+
+        let legend: Vec<String> = series
+            .iter()
+            .enumerate()
+            .map(|(index, s)| format!("{} {}", self.marker(index, s.color), s.label))
+            .collect();
+        out.push_str(&format!("{:>width$}  {}\n", "", legend.join("   "), width = Self::Y_LABEL_WIDTH));
+
+        out
+    }
+
+    /// Draws a horizontal bar chart, one row per entry in the given order
+    pub fn bar_chart(
+        &self,
+        title: &str,
+        bars: &[(String, f64)],
+        color: TerminalColor,
+        value_format: impl Fn(f64) -> String,
+    ) -> String {
+        let mut out = format!("{title}\n");
+        if bars.is_empty() {
+            out.push_str("(no data)\n");
+            return out;
+        }
+
+        let label_width = bars.iter().map(|(l, _)| l.chars().count()).max().unwrap_or(0).min(24);
+        let values: Vec<String> = bars.iter().map(|(_, v)| value_format(*v)).collect();
+        let value_width = values.iter().map(|v| v.chars().count()).max().unwrap_or(0);
+        let bar_width = self.width.saturating_sub(label_width + value_width + 4).max(10);
+        let max_value = bars.iter().map(|(_, v)| *v).fold(0.0, f64::max).max(f64::MIN_POSITIVE);
+
+        for ((label, value), formatted) in bars.iter().zip(&values) {
+            let label: String = label.chars().take(label_width).collect();
+            let length = ((value / max_value) * bar_width as f64).round() as usize;
+            let bar = match self.style {
+                TerminalStyle::Ascii => "#".repeat(length),
+                TerminalStyle::Ansi => format!("{}{}{}", color.ansi_code(), "█".repeat(length), ANSI_RESET),
+            };
+            out.push_str(&format!(
+                "{:<label_width$} | {}{} {}\n",
+                label,
+                bar,
+                " ".repeat(bar_width - length.min(bar_width)),
+                formatted
+            ));
+        }
+
+        out
+    }
+
+    /// Draws bars under group headings, such as the links leaving each node of a flow diagram
+    /// All bars share one scale so they can be compared across groups; each group has its own colour
+    pub fn grouped_bar_chart(
+        &self,
+        title: &str,
+        groups: &[(String, Vec<(String, f64)>)],
+        value_format: impl Fn(f64) -> String,
+    ) -> String {
+        let mut out = format!("{title}\n");
+        if groups.is_empty() {
+            out.push_str("(no data)\n");
+            return out;
+        }
+
+        let bars = groups.iter().flat_map(|(_, bars)| bars);
+        let label_width = bars.clone().map(|(l, _)| l.chars().count()).max().unwrap_or(0).min(40);
+        let value_width = bars.clone().map(|(_, v)| value_format(*v).chars().count()).max().unwrap_or(0);
+        let bar_width = self.width.saturating_sub(label_width + value_width + 9).max(10);
+        let max_value = bars.map(|(_, v)| *v).fold(0.0, f64::max).max(f64::MIN_POSITIVE);
+
+        for (index, (heading, bars)) in groups.iter().enumerate() {
+            let total: f64 = bars.iter().map(|(_, v)| v).sum();
+            out.push_str(&format!("{heading} ({})\n", value_format(total)));
+            let color = TerminalColor::PALETTE[index % TerminalColor::PALETTE.len()];
+            for (label, value) in bars {
+                let label: String = label.chars().take(label_width).collect();
+                let length = ((value / max_value) * bar_width as f64).round() as usize;
+                let bar = match self.style {
+                    TerminalStyle::Ascii => "#".repeat(length),
+                    TerminalStyle::Ansi => format!("{}{}{}", color.ansi_code(), "█".repeat(length), ANSI_RESET),
+                };
+                out.push_str(&format!(
+                    "  -> {:<label_width$} | {}{} {}\n",
+                    label,
+                    bar,
+                    " ".repeat(bar_width - length.min(bar_width)),
+                    value_format(*value)
+                ));
+            }
+        }
+
+        out
+    }
+
+    fn marker(&self, index: usize, color: TerminalColor) -> String {
+        match self.style {
+            TerminalStyle::Ascii => ASCII_MARKERS[index % ASCII_MARKERS.len()].to_string(),
+            TerminalStyle::Ansi => format!("{}●{}", color.ansi_code(), ANSI_RESET),
+        }
+    }
+
+    fn glyph(&self, glyph: char, color: TerminalColor) -> String {
+        match self.style {
+            TerminalStyle::Ascii => glyph.to_string(),
+            TerminalStyle::Ansi => format!("{}{glyph}{}", color.ansi_code(), ANSI_RESET),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_terminal_formats() {
+        assert_eq!(TerminalStyle::parse("ascii"), Some(TerminalStyle::Ascii));
+        assert_eq!(TerminalStyle::parse("ANSI"), Some(TerminalStyle::Ansi));
+        assert!(TerminalStyle::parse("terminal").is_some());
+        assert_eq!(TerminalStyle::parse("png"), None);
+    }
+
+    #[test]
+    fn test_ascii_line_chart_layout() {
+        let canvas = TerminalCanvas::new(60, 10, TerminalStyle::Ascii);
+        let series = vec![
+            TerminalSeries {
+                label: "Download".to_string(),
+                points: vec![(0.0, 0.0), (1.0, 50.0), (2.0, 100.0)],
+                color: TerminalColor::Blue,
+            },
+            TerminalSeries {
+                label: "Upload".to_string(),
+                points: vec![(0.0, 10.0), (2.0, 10.0)],
+                color: TerminalColor::Red,
+            },
+        ];
+
+        let chart = canvas.line_chart("Speed", &series, ("start".into(), "end".into()), |v| format!("{v:.0}"));
+        let lines: Vec<&str> = chart.lines().collect();
+
+        assert_eq!(lines[0], "Speed");
+        // title + plot rows + axis + x labels + legend
+        assert_eq!(lines.len(), 1 + 10 + 3);
+        assert!(chart.is_ascii());
+        assert!(chart.contains('*') && chart.contains('+'));
+        assert!(lines[lines.len() - 2].contains("start") && lines[lines.len() - 2].ends_with("end"));
+        assert!(lines[lines.len() - 1].contains("* Download") && lines[lines.len() - 1].contains("+ Upload"));
+
+        // Markers get a row under the axis, aligned with the plot; ones off the chart are left out
+        let markers = [
+            TerminalMarker { x: 0.0, glyph: 'D', color: TerminalColor::Cyan },
+            TerminalMarker { x: 2.0, glyph: 'S', color: TerminalColor::Red },
+            TerminalMarker { x: 5.0, glyph: 'A', color: TerminalColor::Yellow },
+        ];
+        let chart = canvas.line_chart_with_markers("Speed", &series, &markers, ("start".into(), "end".into()), |v| {
+            format!("{v:.0}")
+        });
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines.len(), 1 + 10 + 4);
+        let axis = lines[11];
+        let row = lines[12];
+        assert_eq!(row.find('D'), Some(axis.find('+').unwrap() + 1));
+        assert_eq!(row.chars().count(), axis.chars().count());
+        assert!(row.ends_with('S') && !row.contains('A'));
+    }
+
+    #[test]
+    fn test_bar_chart_scales_to_largest_value() {
+        let canvas = TerminalCanvas::new(50, 10, TerminalStyle::Ascii);
+        let bars = vec![("TCP".to_string(), 100.0), ("UDP".to_string(), 50.0)];
+
+        let chart = canvas.bar_chart("Protocols", &bars, TerminalColor::Blue, |v| format!("{v}"));
+        let lines: Vec<&str> = chart.lines().collect();
+
+        let tcp = lines[1].matches('#').count();
+        let udp = lines[2].matches('#').count();
+        assert!(tcp > 0);
+        assert_eq!(udp, (tcp as f64 / 2.0).round() as usize);
+        assert!(lines[1].ends_with("100"));
+    }
+
+    #[test]
+    fn test_ansi_output_uses_colour_codes() {
+        let canvas = TerminalCanvas::new(50, 10, TerminalStyle::Ansi);
+        let chart = canvas.bar_chart("Ports", &[("443".to_string(), 3.0)], TerminalColor::Green, |v| format!("{v}"));
+        assert!(chart.contains("\x1b[32m"));
+        assert!(chart.contains(ANSI_RESET));
+    }
+
+    #[test]
+    fn test_grouped_bars_share_one_scale() {
+        let canvas = TerminalCanvas::new(60, 10, TerminalStyle::Ascii);
+        let groups = vec![
+            ("10.0.0.2:ephemeral".to_string(), vec![("203.0.113.1".to_string(), 100.0), ("203.0.113.2".to_string(), 20.0)]),
+            ("10.0.0.2:22/ssh".to_string(), vec![("198.51.100.7".to_string(), 50.0)]),
+        ];
+
+        let chart = canvas.grouped_bar_chart("Flows", &groups, |v| format!("{v}"));
+        let lines: Vec<&str> = chart.lines().collect();
+
+        assert_eq!(lines.len(), 1 + 2 + 3);
+        assert_eq!(lines[1], "10.0.0.2:ephemeral (120)");
+        let widest = lines[2].matches('#').count();
+        assert_eq!(lines[5].matches('#').count(), (widest as f64 / 2.0).round() as usize);
+        assert!(lines[5].starts_with("  -> 198.51.100.7"));
+    }
+}
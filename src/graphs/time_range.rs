@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+
+use crate::cli::period::{parse_span, parse_time, Period};
+
+/// Points a time series is aimed at when no `--resolution` is given
+const TARGET_POINTS: i64 = 300;
+
+/// Bucket sizes picked from when no `--resolution` is given, in seconds
+const NICE_RESOLUTIONS: [i64; 14] = [
+    1, 5, 10, 30, 60, 300, 600, 900, 1800, 3600, 3 * 3600, 6 * 3600, 12 * 3600, 86400,
+];
+
+/// Time span a graph covers and the bucket size its time series are aggregated into
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Bucket size; picked from the span unless `--resolution` was given
+    pub resolution: Duration,
+    /// Whether `resolution` was asked for rather than picked
+    pub explicit_resolution: bool,
+    /// How the span was asked for, e.g. "last 24h", for titles
+    pub label: String,
+}
+
+impl GraphWindow {
+    /// Resolves `--last`/`--period`, `--from`, `--to` and `--resolution` at `now`
+    ///
+    /// Without `--from` and `--to` the period is read like every other `--period`, so a
+    /// range or a calendar month works too. `--from` alone runs up to now, and `--to` alone
+    /// reaches back `period` from it, which must then be a span.
+    pub fn resolve(
+        period: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        resolution: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let now = now.with_timezone(&Local);
+        let (start, end, label) = match (from, to) {
+            (None, None) => {
+                let period = Period::parse(period, now)?;
+                (period.start, period.end, period.label)
+            }
+            _ => {
+                let end = match to {
+                    Some(to) => parse_time(to, now).context("Invalid --to")?,
+                    None => now,
+                };
+                let start = match from {
+                    Some(from) => parse_time(from, now).context("Invalid --from")?,
+                    None => end - parse_span(period).with_context(|| format!("Invalid period '{period}' before --to"))?,
+                };
+                (start, end, format!("{} to {}", format_label(start), format_label(end)))
+            }
+        };
+        if start >= end {
+            bail!("The graph would start after it ends; check --from and --to");
+        }
+        let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+
+        let (resolution, explicit_resolution) = match resolution {
+            Some(value) if !value.eq_ignore_ascii_case("auto") => {
+                (parse_span(value).with_context(|| format!("Invalid resolution '{value}'"))?, true)
+            }
+            _ => (auto_resolution(end - start), false),
+        };
+
+        Ok(Self { start, end, resolution, explicit_resolution, label })
+    }
+
+    /// Bucket size in whole seconds
+    pub fn resolution_secs(&self) -> i64 {
+        self.resolution.num_seconds().max(1)
+    }
+}
+
+/// The smallest of the usual bucket sizes that keeps `span` within about 300 points
+pub fn auto_resolution(span: Duration) -> Duration {
+    let wanted = span.num_seconds() / TARGET_POINTS;
+    let seconds = NICE_RESOLUTIONS
+        .iter()
+        .copied()
+        .find(|&seconds| seconds >= wanted)
+        .unwrap_or(NICE_RESOLUTIONS[NICE_RESOLUTIONS.len() - 1]);
+    Duration::seconds(seconds)
+}
+
+/// `time` as stored in the database: local time without an offset
+pub fn sql_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// SQL expression for the bucket a stored timestamp `column` falls in
+///
+/// Buckets are counted in seconds on the local clock, so a day bucket starts at local
+/// midnight; `bucket_time` turns one back into a point in time.
+pub fn bucket_sql(column: &str, resolution_secs: i64) -> String {
+    format!("(CAST(strftime('%s', {column}) AS INTEGER) / {resolution_secs}) * {resolution_secs}")
+}
+
+/// Start of a bucket returned by `bucket_sql`
+pub fn bucket_time(local_seconds: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(local_seconds, 0)
+        .and_then(|time| time.naive_utc().and_local_timezone(Local).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+/// Start of the bucket `time` falls in, aligned on the local clock like `bucket_sql`
+pub fn bucket_start(time: DateTime<Utc>, resolution_secs: i64) -> DateTime<Utc> {
+    let local_seconds = time.with_timezone(&Local).naive_local().and_utc().timestamp();
+    bucket_time(local_seconds.div_euclid(resolution_secs) * resolution_secs)
+}
+
+fn format_label(time: DateTime<Local>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        parse_time(value, Local::now()).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_resolve_window() {
+        let now = Utc::now();
+        let last = GraphWindow::resolve("24h", None, None, None, now).unwrap();
+        assert_eq!((last.start, last.end), (now - Duration::hours(24), now));
+        assert_eq!(last.resolution, Duration::minutes(5));
+        assert!(!last.explicit_resolution);
+        assert_eq!(last.label, "last 24h");
+
+        let range = GraphWindow::resolve("1h", Some("3d"), Some("1d"), Some("1h"), now).unwrap();
+        assert_eq!((range.start, range.end), (now - Duration::days(3), now - Duration::days(1)));
+        assert_eq!(range.resolution_secs(), 3600);
+        assert!(range.explicit_resolution);
+
+        let before = GraphWindow::resolve("2h", None, Some("1d"), Some("auto"), now).unwrap();
+        assert_eq!(before.start, now - Duration::days(1) - Duration::hours(2));
+        assert_eq!(before.resolution, Duration::seconds(30));
+
+        assert!(GraphWindow::resolve("1h", Some("1d"), Some("2d"), None, now).is_err());
+        assert!(GraphWindow::resolve("1h", None, None, Some("0m"), now).is_err());
+    }
+
+    #[test]
+    fn test_periods_read_like_every_other_command() {
+        let now = Utc::now();
+        let weeks = GraphWindow::resolve("2w", None, None, Some("1d"), now).unwrap();
+        assert_eq!(weeks.start, now - Duration::weeks(2));
+
+        let range = GraphWindow::resolve("2024-04-01..2024-04-30", None, None, None, now).unwrap();
+        assert_eq!((range.start, range.end), (at("2024-04-01"), at("2024-05-01")));
+        assert_eq!(range.label, "2024-04-01 00:00 → 2024-05-01 00:00");
+        let month = GraphWindow::resolve("2024-04", None, None, None, now).unwrap();
+        assert_eq!((month.start, month.end), (range.start, range.end));
+
+        // A number needs its unit, as with --period anywhere else
+        assert!(GraphWindow::resolve("2", None, None, None, now).is_err());
+        assert!(GraphWindow::resolve("1h", None, None, Some("5"), now).is_err());
+        assert!(GraphWindow::resolve("yesterday", None, Some("1d"), None, now).is_err());
+    }
+
+    #[test]
+    fn test_auto_resolution_bounds_points() {
+        assert_eq!(auto_resolution(Duration::minutes(5)), Duration::seconds(1));
+        assert_eq!(auto_resolution(Duration::hours(1)), Duration::seconds(30));
+        assert_eq!(auto_resolution(Duration::days(7)), Duration::hours(1));
+        assert_eq!(auto_resolution(Duration::days(30)), Duration::hours(3));
+        assert_eq!(auto_resolution(Duration::days(3650)), Duration::days(1));
+    }
+
+    #[test]
+    fn test_bucket_start_matches_local_clock() {
+        let time = at("2024-05-01 14:37:20");
+        assert_eq!(bucket_start(time, 900), at("2024-05-01 14:30"));
+        assert_eq!(bucket_start(time, 86400), at("2024-05-01"));
+    }
+}
@@ -0,0 +1,208 @@
+// CSV import: one row per interface and interval, with a header naming the columns
+// Recognised columns, in any order and case: timestamp (or date, time, start), interface
+// (or iface), rx_bytes (or rx, download_bytes, received) and tx_bytes (or tx, upload_bytes,
+// sent), plus an optional duration_secs (or duration). Timestamps mark the start of each
+// interval; without a duration, a date covers its day, a month (2024-03) its month, and a
+// date and time runs until that interface's next row
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+
+use super::{local_date, local_time, next_month, UsageRecord};
+
+const TIMESTAMP_COLUMNS: [&str; 4] = ["timestamp", "date", "time", "start"];
+const INTERFACE_COLUMNS: [&str; 2] = ["interface", "iface"];
+const RX_COLUMNS: [&str; 5] = ["rx_bytes", "rx", "download_bytes", "download", "received"];
+const TX_COLUMNS: [&str; 5] = ["tx_bytes", "tx", "upload_bytes", "upload", "sent"];
+const DURATION_COLUMNS: [&str; 3] = ["duration_secs", "duration", "seconds"];
+
+/// When a row starts, and how long it covers when its timestamp alone can tell
+enum Start {
+    /// A date or a month: the whole of it
+    Whole(DateTime<Local>, DateTime<Local>),
+    /// A point in time
+    At(DateTime<Local>),
+}
+
+/// A row with a start time but no duration, which lasts until its interface's next row
+struct OpenRow {
+    line: usize,
+    start: DateTime<Local>,
+    rx_bytes: f64,
+    tx_bytes: f64,
+}
+
+/// Usage records of the rows in `content`; rows are stored under `default_interface` when
+/// there is no interface column
+pub fn parse(content: &str, default_interface: Option<&str>) -> Result<Vec<UsageRecord>> {
+    let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or_else(|| anyhow!("The CSV file is empty"))?;
+    let delimiter = if header.contains(';') && !header.contains(',') { ';' } else { ',' };
+    let columns: Vec<String> = split_fields(header, delimiter).iter().map(|name| name.trim().to_lowercase()).collect();
+    let find = |names: &[&str]| columns.iter().position(|column| names.contains(&column.as_str()));
+
+    let timestamp = find(&TIMESTAMP_COLUMNS).ok_or_else(|| anyhow!("No timestamp column in the CSV header"))?;
+    let rx = find(&RX_COLUMNS).ok_or_else(|| anyhow!("No rx_bytes column in the CSV header"))?;
+    let tx = find(&TX_COLUMNS).ok_or_else(|| anyhow!("No tx_bytes column in the CSV header"))?;
+    let duration = find(&DURATION_COLUMNS);
+    let interface = find(&INTERFACE_COLUMNS);
+    if interface.is_none() && default_interface.is_none() {
+        bail!("No interface column in the CSV header; name the interface with --interface");
+    }
+
+    let mut open: HashMap<String, Vec<OpenRow>> = HashMap::new();
+    let mut records = Vec::new();
+    for (index, line) in lines {
+        let line_number = index + 1;
+        let fields = split_fields(line, delimiter);
+        let field = |column: usize| fields.get(column).map(|value| value.trim()).unwrap_or("");
+        let bytes = |column: usize| -> Result<f64> {
+            field(column)
+                .parse::<f64>()
+                .ok()
+                .filter(|bytes| *bytes >= 0.0)
+                .ok_or_else(|| anyhow!("Line {line_number}: '{}' is not a byte count", field(column)))
+        };
+        let name = interface
+            .map(|column| field(column).to_string())
+            .filter(|name| !name.is_empty())
+            .or(default_interface.map(str::to_string))
+            .ok_or_else(|| anyhow!("Line {line_number}: no interface"))?;
+        let (rx_bytes, tx_bytes) = (bytes(rx)?, bytes(tx)?);
+        let start = parse_start(field(timestamp)).with_context(|| format!("Line {line_number}"))?;
+
+        let seconds = match duration.map(field).filter(|value| !value.is_empty()) {
+            Some(value) => Some(
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| *secs > 0.0)
+                    .ok_or_else(|| anyhow!("Line {line_number}: '{value}' is not a duration in seconds"))?,
+            ),
+            None => None,
+        };
+        match (start, seconds) {
+            (Start::Whole(start, _) | Start::At(start), Some(secs)) => records.push(UsageRecord {
+                interface: name,
+                start,
+                end: start + Duration::milliseconds((secs * 1000.0) as i64),
+                rx_bytes,
+                tx_bytes,
+            }),
+            (Start::Whole(start, end), None) => records.push(UsageRecord { interface: name, start, end, rx_bytes, tx_bytes }),
+            (Start::At(start), None) => {
+                open.entry(name).or_default().push(OpenRow { line: line_number, start, rx_bytes, tx_bytes })
+            }
+        }
+    }
+
+    // The last row of an interface lasts as long as the one before it
+    for (name, mut rows) in open {
+        rows.sort_by_key(|row| row.start);
+        let mut previous_gap = None;
+        for (i, row) in rows.iter().enumerate() {
+            let gap = match rows.get(i + 1) {
+                Some(next) if next.start > row.start => next.start - row.start,
+                Some(_) => bail!("Line {}: {name} has two rows for {}", row.line, row.start.format("%Y-%m-%d %H:%M:%S")),
+                None => previous_gap.ok_or_else(|| {
+                    anyhow!("Line {}: cannot tell how long the row covers; add a duration_secs column", row.line)
+                })?,
+            };
+            previous_gap = Some(gap);
+            records.push(UsageRecord {
+                interface: name.clone(),
+                start: row.start,
+                end: row.start + gap,
+                rx_bytes: row.rx_bytes,
+                tx_bytes: row.tx_bytes,
+            });
+        }
+    }
+    Ok(records)
+}
+
+/// A date, a month, a local date and time, an RFC 3339 time or Unix seconds
+fn parse_start(value: &str) -> Result<Start> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let whole = local_date(date).zip(date.succ_opt().and_then(local_date));
+        return whole.map(|(start, end)| Start::Whole(start, end)).ok_or_else(|| anyhow!("{value} does not exist here"));
+    }
+    if let Ok(first) = NaiveDate::parse_from_str(&format!("{value}-01"), "%Y-%m-%d") {
+        let whole = local_date(first).zip(next_month(first).and_then(local_date));
+        return whole.map(|(start, end)| Start::Whole(start, end)).ok_or_else(|| anyhow!("{value} does not exist here"));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(Start::At(time.with_timezone(&Local)));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return local_time(time).map(Start::At).ok_or_else(|| anyhow!("{value} does not exist here"));
+        }
+    }
+    if let Some(time) = value.parse::<i64>().ok().and_then(|secs| DateTime::from_timestamp(secs, 0)) {
+        return Ok(Start::At(time.with_timezone(&Local)));
+    }
+    bail!("'{value}' is not a date or time")
+}
+
+/// Fields of one line; double quotes may enclose delimiters, with "" for a quote
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_days_months_and_durations() {
+        let csv = "Date,Interface,RX_Bytes,TX_Bytes\n\
+                   2024-03-01,eth0,1000,100\n\
+                   2024-02,eth0,50000,5000\n\
+                   \n\
+                   2024-03-02,\"wlan,0\",10,1\n";
+        let mut records = parse(csv, None).unwrap();
+        records.sort_by_key(|record| record.start);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].duration_secs(), 29.0 * 86_400.0);
+        assert_eq!(records[1].duration_secs(), 86_400.0);
+        assert_eq!(records[2].interface, "wlan,0");
+
+        let csv = "start;rx;tx;duration_secs\n2024-03-01 10:00:00;600;60;60\n";
+        let records = parse(csv, Some("eth1")).unwrap();
+        assert_eq!(records[0].interface, "eth1");
+        assert_eq!(records[0].duration_secs(), 60.0);
+    }
+
+    #[test]
+    fn test_times_without_duration_run_to_the_next_row() {
+        let csv = "timestamp,rx_bytes,tx_bytes\n\
+                   2024-03-01 10:10,1,1\n\
+                   2024-03-01 10:00,1,1\n\
+                   2024-03-01 10:05,1,1\n";
+        let records = parse(csv, Some("eth0")).unwrap();
+        assert!(records.iter().all(|record| record.duration_secs() == 300.0));
+
+        assert!(parse("timestamp,rx_bytes,tx_bytes\n2024-03-01 10:00,1,1\n", Some("eth0")).is_err());
+        assert!(parse("timestamp,rx_bytes,tx_bytes\n2024-03-01,1,1\n", None).is_err());
+        let error = parse("date,interface,rx,tx\n2024-03-01,eth0,lots,1\n", None).unwrap_err();
+        assert!(error.to_string().contains("Line 2"), "{error}");
+    }
+}
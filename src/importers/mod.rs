@@ -0,0 +1,105 @@
+// Importers: usage history recorded by other tools, read into bandwidth samples so that
+// reports, anomaly baselines and forecasts reach back before kaipo-watcher was installed
+
+pub mod csv;
+pub mod vnstat;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime};
+
+use crate::storage::packet_storage::BandwidthSample;
+
+/// Formats `kw import` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// `vnstat --json` output, from vnStat 1.x or 2.x
+    Vnstat,
+    /// Comma- or semicolon-separated values with a header row
+    Csv,
+}
+
+impl ImportFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "vnstat" => Ok(Self::Vnstat),
+            "csv" => Ok(Self::Csv),
+            other => bail!("Unsupported import format '{other}' (expected vnstat or csv)"),
+        }
+    }
+
+    /// Records in `content`; CSV rows without an interface column are given `default_interface`
+    pub fn read(self, content: &str, default_interface: Option<&str>) -> Result<Vec<UsageRecord>> {
+        match self {
+            Self::Vnstat => vnstat::parse(content),
+            Self::Csv => csv::parse(content, default_interface),
+        }
+    }
+}
+
+/// Traffic of one interface over one interval, as another tool recorded it
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub interface: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+}
+
+impl UsageRecord {
+    pub fn duration_secs(&self) -> f64 {
+        (self.end - self.start).num_seconds() as f64
+    }
+
+    /// The record as a sample of its average rates. Samples are stamped with the end of their
+    /// interval; a second before it here, so a day's traffic is counted in that day
+    pub fn to_sample(&self) -> BandwidthSample {
+        let duration_secs = self.duration_secs().max(1.0);
+        BandwidthSample {
+            timestamp: self.end - Duration::seconds(1),
+            interface_name: self.interface.clone(),
+            duration_secs,
+            download_bps: self.rx_bytes / duration_secs,
+            upload_bps: self.tx_bytes / duration_secs,
+        }
+    }
+}
+
+/// Local midnight starting `date`
+fn local_date(date: NaiveDate) -> Option<DateTime<Local>> {
+    local_time(date.and_hms_opt(0, 0, 0)?)
+}
+
+/// `time` in the local timezone; the earlier reading where clocks went back
+fn local_time(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    time.and_local_timezone(Local).earliest()
+}
+
+/// First day of the month after `date`'s
+fn next_month(date: NaiveDate) -> Option<NaiveDate> {
+    date.with_day0(0)?.checked_add_months(Months::new(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_becomes_sample_within_its_interval() {
+        let start = local_date(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()).unwrap();
+        let record = UsageRecord {
+            interface: "eth0".to_string(),
+            start,
+            end: start + Duration::days(1),
+            rx_bytes: 86_400_000.0,
+            tx_bytes: 8_640_000.0,
+        };
+        let sample = record.to_sample();
+        assert_eq!(sample.timestamp.date_naive(), start.date_naive());
+        assert_eq!(sample.duration_secs, 86_400.0);
+        assert_eq!(sample.download_bps, 1000.0);
+        assert_eq!(sample.upload_bps, 100.0);
+        assert_eq!(next_month(NaiveDate::from_ymd_opt(2024, 12, 15).unwrap()), NaiveDate::from_ymd_opt(2025, 1, 1));
+        assert!(ImportFormat::parse("rrd").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+// vnStat import: reads `vnstat --json` from vnStat 2.x (bytes) or 1.x (KiB)
+// vnStat keeps hours for a few days, days for a few months and months for years, so each
+// stretch of time is taken from the finest entries that cover it in full
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+use super::{local_date, local_time, next_month, UsageRecord};
+
+/// Hours that add up to this share of their day's total stand in for the day
+const HOURS_COVER_DAY: f64 = 0.99;
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    /// "1" for vnStat 1.x, whose counters are in KiB; "2" for 2.x, in bytes
+    jsonversion: serde_json::Value,
+    interfaces: Vec<Interface>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Interface {
+    /// vnStat 2.x
+    name: Option<String>,
+    /// vnStat 1.x
+    id: Option<String>,
+    traffic: Traffic,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Traffic {
+    #[serde(alias = "hours")]
+    hour: Vec<Entry>,
+    #[serde(alias = "days")]
+    day: Vec<Entry>,
+    #[serde(alias = "months")]
+    month: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    /// The hour of day in vnStat 1.x hour entries; a database row id otherwise
+    id: Option<u64>,
+    date: EntryDate,
+    time: Option<EntryTime>,
+    rx: f64,
+    tx: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntryDate {
+    year: i32,
+    month: u32,
+    day: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntryTime {
+    hour: u32,
+}
+
+impl Entry {
+    fn day(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.date.year, self.date.month, self.date.day.unwrap_or(1))
+    }
+
+    fn total(&self) -> f64 {
+        self.rx + self.tx
+    }
+}
+
+/// Usage records of every interface in a `vnstat --json` export
+pub fn parse(content: &str) -> Result<Vec<UsageRecord>> {
+    let export: Export = serde_json::from_str(content).context("Not a vnstat --json export")?;
+    let unit = if export.jsonversion == "1" || export.jsonversion == 1 { 1024.0 } else { 1.0 };
+
+    let mut records = Vec::new();
+    for interface in export.interfaces {
+        let Some(name) = interface.name.or(interface.id) else {
+            continue;
+        };
+        records.extend(interface_records(&name, &interface.traffic, unit));
+    }
+    Ok(records)
+}
+
+fn interface_records(name: &str, traffic: &Traffic, unit: f64) -> Vec<UsageRecord> {
+    let record = |start, end, entry: &Entry| UsageRecord {
+        interface: name.to_string(),
+        start,
+        end,
+        rx_bytes: entry.rx * unit,
+        tx_bytes: entry.tx * unit,
+    };
+
+    let mut hours: BTreeMap<NaiveDate, Vec<UsageRecord>> = BTreeMap::new();
+    for entry in &traffic.hour {
+        let hour = entry.time.as_ref().map(|time| time.hour).or(entry.id.map(|id| id as u32));
+        let Some(start) = entry.day().zip(hour).and_then(|(day, hour)| local_time(day.and_hms_opt(hour, 0, 0)?)) else {
+            continue;
+        };
+        hours.entry(start.date_naive()).or_default().push(record(start, start + Duration::hours(1), entry));
+    }
+
+    let mut records = Vec::new();
+    let mut days = HashSet::new();
+    for entry in &traffic.day {
+        let Some(day) = entry.day() else { continue };
+        let (Some(start), Some(end)) = (local_date(day), day.succ_opt().and_then(local_date)) else {
+            continue;
+        };
+        days.insert((day.year(), day.month()));
+        match hours.remove(&day) {
+            Some(hourly) if hourly.iter().map(|r| r.rx_bytes + r.tx_bytes).sum::<f64>() >= entry.total() * unit * HOURS_COVER_DAY => {
+                records.extend(hourly)
+            }
+            _ => records.push(record(start, end, entry)),
+        }
+    }
+    // Hours of days the export has no total for
+    records.extend(hours.into_values().flatten());
+
+    for entry in &traffic.month {
+        let Some(first) = entry.day() else { continue };
+        if days.contains(&(first.year(), first.month())) {
+            continue;
+        }
+        let (Some(start), Some(end)) = (local_date(first), next_month(first).and_then(local_date)) else {
+            continue;
+        };
+        records.push(record(start, end, entry));
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VNSTAT_2: &str = r#"{"vnstatversion":"2.9","jsonversion":"2","interfaces":[{"name":"eth0","alias":"",
+        "traffic":{"total":{"rx":0,"tx":0},
+        "hour":[
+            {"id":1,"date":{"year":2024,"month":3,"day":14},"time":{"hour":22,"minute":0},"rx":100,"tx":10},
+            {"id":2,"date":{"year":2024,"month":3,"day":15},"time":{"hour":0,"minute":0},"rx":600,"tx":60},
+            {"id":3,"date":{"year":2024,"month":3,"day":15},"time":{"hour":1,"minute":0},"rx":400,"tx":40}],
+        "day":[
+            {"id":4,"date":{"year":2024,"month":3,"day":14},"rx":5000,"tx":500},
+            {"id":5,"date":{"year":2024,"month":3,"day":15},"rx":1000,"tx":100}],
+        "month":[
+            {"id":6,"date":{"year":2024,"month":2},"rx":90000,"tx":9000},
+            {"id":7,"date":{"year":2024,"month":3},"rx":6000,"tx":600}]}}]}"#;
+
+    #[test]
+    fn test_finest_complete_entries_are_used() {
+        let mut records = parse(VNSTAT_2).unwrap();
+        records.sort_by_key(|record| record.start);
+        let spans: Vec<(String, f64, f64)> = records
+            .iter()
+            .map(|r| (r.start.format("%Y-%m-%d %H").to_string(), r.duration_secs() / 3600.0, r.rx_bytes))
+            .collect();
+        // March 14 only has one of its hours, so its day total is used; March 15's hours
+        // account for all of it; March has days, so only February comes from the months
+        assert_eq!(spans[0].0, "2024-02-01 00");
+        assert_eq!(spans[0].2, 90_000.0);
+        assert_eq!(spans[1], ("2024-03-14 00".to_string(), 24.0, 5000.0));
+        assert_eq!(spans[2], ("2024-03-15 00".to_string(), 1.0, 600.0));
+        assert_eq!(spans[3], ("2024-03-15 01".to_string(), 1.0, 400.0));
+        assert_eq!(records.len(), 4);
+        let total: f64 = records.iter().map(|r| r.rx_bytes).sum();
+        assert_eq!(total, 90_000.0 + 5000.0 + 1000.0);
+    }
+
+    #[test]
+    fn test_vnstat_1_counts_kib() {
+        let export = r#"{"vnstatversion":"1.18","jsonversion":"1","interfaces":[{"id":"wlan0","nick":"wlan0",
+            "traffic":{"days":[{"id":0,"date":{"year":2023,"month":12,"day":31},"rx":2,"tx":1}],
+            "hours":[{"id":23,"date":{"year":2023,"month":12,"day":31},"rx":2,"tx":1}]}}]}"#;
+        let records = parse(export).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].interface, "wlan0");
+        assert_eq!(records[0].start.format("%H").to_string(), "23");
+        assert_eq!(records[0].rx_bytes, 2048.0);
+        assert!(parse("{}").is_err());
+    }
+}
@@ -1,8 +1,112 @@
+//! Network monitoring building blocks behind the `kw` command
+//!
+//! The collectors read interface counters and capture packets, the analyzers turn
+//! packets into protocols, connections, TCP sessions and security flags, and storage
+//! keeps the results in a SQLite database. None of them need the command-line
+//! interface, so another program can embed them with the CLI, terminal UI and graph
+//! dependencies left out, adding `capture` back for packets:
+//!
+//! ```toml
+//! kaipo-watcher = { version = "0.2", default-features = false, features = ["capture"] }
+//! ```
+//!
+//! | Feature     | Default | Adds |
+//! |-------------|---------|------|
+//! | `cli`       | yes     | The `kw` binary and the `cli` module |
+//! | `capture`   | yes     | Packet capture and pcap import (libpnet), `PacketCollector`, the monitoring daemon |
+//! | `dashboard` | yes     | The `dashboard` module: terminal dashboards (ratatui, crossterm) for `kw live` and `kw top` |
+//! | `graphs`    | yes     | The `graphs` module: PNG, SVG and terminal graphs |
+//! | `remote`    | yes     | The `remote` module: agents streaming bandwidth to a hub over gRPC (tonic) |
+//! | `api`       | yes     | The `api` module: a read-only JSON API over the packet database, and `service::web`, the web UI (axum) |
+//!
+//! Reading interface speeds:
+//!
+//! ```no_run
+//! use kaipo_watcher::BandwidthCollector;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut collector = BandwidthCollector::new();
+//! collector.collect()?; // Speeds are measured between two readings
+//! std::thread::sleep(std::time::Duration::from_secs(1));
+//! for stats in collector.collect()? {
+//!     println!("{}: {:.0} B/s down, {:.0} B/s up", stats.interface_name, stats.download_speed_bps, stats.upload_speed_bps);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Capturing, analyzing and storing packets (needs capture privileges and `capture`):
+//!
+//! ```no_run
+//! # #[cfg(feature = "capture")]
+//! use kaipo_watcher::{PacketCollector, PacketStorage, ProtocolAnalyzer};
+//!
+//! # #[cfg(feature = "capture")]
+//! # async fn run() -> anyhow::Result<()> {
+//! let collector = PacketCollector::new("eth0".to_string())?;
+//! let storage = PacketStorage::new("packets.db", 100)?;
+//! let mut analyzer = ProtocolAnalyzer::new();
+//!
+//! collector.start().await?;
+//! while let Some(packet) = collector.receive_packet().await {
+//!     let analysis = analyzer.analyze_packet(&packet)?;
+//!     storage.analyze_packet_for_storage(&packet, &analysis)?;
+//! }
+//! storage.flush_all()?;
+//! # Ok(())
+//! # }
+//! ```
+
+/// `kw api`: read-only JSON endpoints over the packet database
+#[cfg(feature = "api")]
+pub mod api;
+/// Protocol analysis, connection and TCP state tracking, latency, classification and threat detection
 pub mod analyzers;
+/// Command-line definitions and the handlers behind each `kw` command (with `cli`), and
+/// the `--period` parser shared with graphs and the web UI
 pub mod cli;
+/// Interface bandwidth, packet capture, ping and speed test collectors
 pub mod collectors;
+/// The configuration file, `KW_` overrides and resource profiles
 pub mod config;
+/// Unit and locale-aware number formatting
+pub mod display;
+/// Terminal dashboards: `kw live`, and `kw top` with `capture`
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+/// Reverse DNS host name enrichment
+pub mod enrichment;
+/// Webhook and MQTT publishing of statistics, and security report exports
 pub mod exporters;
+/// Bandwidth, protocol, connection and latency graphs
+#[cfg(feature = "graphs")]
 pub mod graphs;
+/// Usage history from vnStat exports and CSV files, read into bandwidth samples
+pub mod importers;
+/// Packets, statistics, service names and tags
 pub mod models;
-pub mod storage;
\ No newline at end of file
+/// `kw agent` and `kw hub`: bandwidth and protocol reports streamed between machines over gRPC
+#[cfg(feature = "remote")]
+pub mod remote;
+/// The monitoring daemon and platform service manager integration (daemon with `capture`)
+pub mod service;
+/// Ctrl+C and SIGTERM as one shutdown request, and the summaries commands print as they stop
+pub mod shutdown;
+/// The SQLite packet database: schema, buffered writes, queries and retention
+pub mod storage;
+
+pub use analyzers::protocol_analyzer::{AnalysisResult, FlowDirection, ProtocolAnalyzer, SecurityFlag, TrafficType};
+pub use collectors::bandwidth::{
+    BandwidthError, BandwidthStats, CalculationConfidence, InterfaceState, InterfaceType, SamplingPlan,
+};
+#[cfg(feature = "capture")]
+pub use collectors::capture_filter::CaptureFilter;
+pub use collectors::collector_service::{BandwidthSnapshot, CollectorService};
+pub use collectors::interface_selection::InterfaceSelection;
+pub use collectors::platform::CaptureBackend;
+pub use collectors::BandwidthCollector;
+#[cfg(feature = "capture")]
+pub use collectors::PacketCollector;
+pub use config::{AppConfig, ResourceProfile};
+pub use models::{NetworkPacket, PacketDirection, PacketProtocol, PacketStatistics, TransportProtocol};
+pub use storage::{Database, PacketStorage, StorageBackend};
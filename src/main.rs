@@ -1,32 +1,78 @@
-// Application modules
-mod cli;        // Command-line interface definitions
-mod collectors; // Network data collection modules
-mod models;     // Data models and types
-mod analyzers;  // Protocol analysis modules
-mod storage;    // Data persistence layer
-mod dashboard;  // Terminal UI dashboard
-mod graphs;     // Graph generation and visualization
+// Application modules, from the library
+use kaipo_watcher::{cli, collectors, config, display, importers, models, storage};
+#[cfg(feature = "api")]
+use kaipo_watcher::service;
+#[cfg(any(feature = "api", feature = "remote"))]
+use kaipo_watcher::shutdown;
+#[cfg(any(feature = "capture", feature = "graphs"))]
+use kaipo_watcher::enrichment;
+#[cfg(feature = "capture")]
+use kaipo_watcher::exporters;
+#[cfg(feature = "api")]
+use kaipo_watcher::api;
+#[cfg(feature = "dashboard")]
+use kaipo_watcher::dashboard;
+#[cfg(feature = "graphs")]
+use kaipo_watcher::graphs;
+#[cfg(feature = "remote")]
+use kaipo_watcher::remote;
 
 use anyhow::Result;
+#[cfg(feature = "capture")]
+use anyhow::Context;
 use clap::Parser;
-use cli::{commands::Commands, Cli, PacketCommandHandler, GraphCommandHandler};
+use cli::{commands::{Commands, ExportAction, StatsAction}, AuditCommandHandler, CaptureStatsCommandHandler, Cli, DoctorCommandHandler, EncryptionCommandHandler, ExportCommandHandler, ImportCommandHandler, ShapingCommandHandler, MaintainCommandHandler, OutputStyle, PurgeCommandHandler, ReportCommandHandler, ReportComparison, SpeedTestCommandHandler, StatsCommandHandler, TagCommandHandler};
+#[cfg(feature = "capture")]
+use cli::{PacketCommandHandler, ServiceCommandHandler};
+#[cfg(feature = "graphs")]
+use cli::GraphCommandHandler;
+use importers::ImportFormat;
 use storage::PacketStorage;
-use cli::graph_commands::DatabaseManager;
+#[cfg(feature = "graphs")]
+use graphs::DatabaseManager;
+#[cfg(feature = "dashboard")]
+use cli::interface_picker;
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use dashboard::Dashboard;
+#[cfg(feature = "dashboard")]
+use dashboard::{Dashboard, DashboardLayout, Session, SessionRecorder, SessionReplay};
+#[cfg(all(feature = "dashboard", feature = "capture"))]
+use dashboard::TopView;
 use collectors::bandwidth_collector::CalculationConfidence;
+#[cfg(feature = "capture")]
+use collectors::capture_filter::CaptureFilter;
+use collectors::interface_selection::InterfaceSelection;
+use collectors::link_aggregation::{detect_link_aggregates, group_interfaces, total_stats, LinkAggregate};
+use collectors::bandwidth::bursts::{sample_bursts, BurstStats, BURST_FACTOR, HIRES_INTERVAL};
+use collectors::link_speed::{detect_link, LinkInfo};
+use collectors::tunnels::{detect_tunnels, Tunnel};
+#[cfg(feature = "capture")]
+use collectors::packet_sampling::PacketSampler;
+#[cfg(feature = "capture")]
+use exporters::security_report::SecurityReportFormat;
+#[cfg(all(unix, feature = "capture"))]
+use collectors::capture_helper::CaptureHelperProcess;
 
 /// Handles the status command with persistent collector instance for accurate speed measurement
 /// Creates a collector, takes initial reading, waits for specified duration, then takes second reading
+/// With `hires`, the wait is spent reading the counters every 100 ms to find micro-bursts
+#[allow(clippy::too_many_arguments)] // one per `kw status` flag, plus the aggregation settings
 async fn handle_status_command(
     detailed: bool,
     measurement_duration: u64,
+    hires: bool,
     active_only: bool,
-    interface_filter: Option<String>,
+    interface_filter: Option<InterfaceSelection>,
+    namespace_owners: Option<HashMap<String, String>>,
     important_only: bool,
     show_all: bool,
+    aggregate: bool,
     interface_analysis: bool,
+    aggregation: &config::LinkAggregationConfig,
+    contention: &config::ContentionConfig,
+    database: &Path,
 ) -> Result<()> {
     // Validate measurement duration
     let duration_secs = measurement_duration.clamp(1, 60);
@@ -34,6 +80,9 @@ async fn handle_status_command(
         println!("Warning: Measurement duration clamped to {} seconds (valid range: 1-60)", duration_secs);
     }
 
+    // Veths are virtual interfaces, which the default and important-only lists leave out
+    let show_all = show_all || namespace_owners.is_some();
+
     println!("Internet Usage Status");
     println!("====================");
     println!("Measuring bandwidth for {} seconds...\n", duration_secs);
@@ -107,8 +156,17 @@ async fn handle_status_command(
 
     println!("Initial reading taken, waiting {} seconds for measurement...", duration_secs);
 
-    // Wait for the specified measurement duration
-    tokio::time::sleep(Duration::from_secs(duration_secs)).await;
+    // Wait for the specified measurement duration, sampling it finely with --hires
+    let bursts: HashMap<String, BurstStats> = if hires {
+        sample_bursts(Duration::from_secs(duration_secs), HIRES_INTERVAL)
+            .await
+            .into_iter()
+            .map(|stats| (stats.interface_name.clone(), stats))
+            .collect()
+    } else {
+        tokio::time::sleep(Duration::from_secs(duration_secs)).await;
+        HashMap::new()
+    };
 
     // Handle interface analysis export if requested
     if interface_analysis {
@@ -178,11 +236,11 @@ async fn handle_status_command(
     };
 
     // Filter interfaces based on user preferences
-    let filtered_stats = filter_interfaces(final_stats, active_only, interface_filter.as_deref())?;
+    let filtered_stats = filter_interfaces(final_stats, active_only, interface_filter.as_ref())?;
 
     if filtered_stats.is_empty() {
-        if let Some(interface_name) = interface_filter {
-            println!("No data available for interface '{}'", interface_name);
+        if let Some(selection) = interface_filter {
+            println!("No data available for interface '{}'", selection);
             println!("Available interfaces from initial reading:");
             for stat in initial_stats {
                 println!("  - {}", stat.interface_name);
@@ -197,35 +255,170 @@ async fn handle_status_command(
     }
 
     // Determine filtering information for display
-    let filtering_info = if show_all {
+    let filtering_info = if namespace_owners.is_some() {
+        Some("Namespace and container selection applied (host ends of their veths)")
+    } else if show_all {
         Some("All interfaces (including virtual and system interfaces)")
     } else if important_only {
         Some("Important interfaces only (physical ethernet, wifi, VPN)")
     } else if active_only {
         Some("Active interfaces only")
     } else if interface_filter.is_some() {
-        Some("Specific interface filter applied")
+        Some("Interface selection applied")
     } else {
         Some("Default interface filtering (excludes most virtual interfaces)")
     };
 
     // Display results with enhanced error reporting
-    display_bandwidth_results(&filtered_stats, detailed, duration_secs, filtering_info)?;
+    let tags = storage::packet_storage::load_tag_book(database);
+    let aggregates = if aggregation.group { detect_link_aggregates() } else { Vec::new() };
+    let owners = namespace_owners.unwrap_or_else(namespace_interface_owners);
+    let tunnels = detect_tunnels(filtered_stats.iter().map(|stat| stat.interface_name.as_str()));
+    let links = detect_links(&filtered_stats, contention.link_speed_mbps);
+    display_bandwidth_results(
+        &filtered_stats,
+        &aggregates,
+        &tunnels,
+        &links,
+        &bursts,
+        contention.saturation_percent,
+        aggregation.show_members,
+        aggregate,
+        detailed,
+        duration_secs,
+        filtering_info,
+        &tags,
+        &owners,
+    )?;
 
     Ok(())
 }
 
+/// Link speed and duplex of the interfaces that report one; `override_mbps` replaces the
+/// detected speed, as `[contention] link_speed_mbps` does in the dashboard
+fn detect_links(
+    stats: &[collectors::bandwidth_collector::BandwidthStats],
+    override_mbps: Option<u64>,
+) -> HashMap<String, LinkInfo> {
+    stats
+        .iter()
+        .filter_map(|stat| {
+            let detected = detect_link(&stat.interface_name);
+            let link = match override_mbps {
+                Some(speed_mbps) => LinkInfo { speed_mbps, duplex: detected.and_then(|link| link.duplex) },
+                None => detected?,
+            };
+            Some((stat.interface_name.clone(), link))
+        })
+        .collect()
+}
+
+/// Host ends of the veths into the namespaces and containers picked by `--namespace` and
+/// `--container`, narrowed by `--interface` when that is given too, each with who it leads to;
+/// `None` when neither option is used
+#[cfg(target_os = "linux")]
+fn select_namespaces(
+    interface: Option<&InterfaceSelection>,
+    namespace: &[String],
+    container: &[String],
+) -> Result<Option<(InterfaceSelection, HashMap<String, String>)>> {
+    use collectors::platform::namespaces;
+
+    if namespace.is_empty() && container.is_empty() {
+        return Ok(None);
+    }
+    let found = namespaces::discover()?;
+    let mut owners = namespaces::interface_owners(namespaces::select(&found, namespace, container)?);
+    if let Some(interface) = interface {
+        owners.retain(|name, _| interface.matches(name));
+    }
+    let names: Vec<&String> = owners.keys().collect();
+    let selection = InterfaceSelection::new(&names)
+        .ok_or_else(|| anyhow::anyhow!("--interface '{}' matches none of the selected namespaces' veths", interface.map(ToString::to_string).unwrap_or_default()))?;
+    Ok(Some((selection, owners)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn select_namespaces(
+    _interface: Option<&InterfaceSelection>,
+    namespace: &[String],
+    container: &[String],
+) -> Result<Option<(InterfaceSelection, HashMap<String, String>)>> {
+    if namespace.is_empty() && container.is_empty() {
+        return Ok(None);
+    }
+    anyhow::bail!("--namespace and --container are only supported on Linux");
+}
+
+/// Every host veth with the namespace or container it leads into, for labelling `kw status`
+fn namespace_interface_owners() -> HashMap<String, String> {
+    #[cfg(target_os = "linux")]
+    match collectors::platform::namespaces::discover() {
+        Ok(found) => return collectors::platform::namespaces::interface_owners(&found),
+        Err(e) => log::debug!("Network namespaces unavailable: {e:#}"),
+    }
+    HashMap::new()
+}
+
+/// Opens the packet database, without writing to it or changing its schema when `read_only` is set,
+/// with addresses and host names in what it hands out redacted as the privacy mode asks
+fn open_storage(database: &Path, read_only: bool, batch_size: usize, redactor: &models::Redactor) -> Result<PacketStorage> {
+    let storage = if read_only {
+        PacketStorage::open_read_only(database)?
+    } else {
+        PacketStorage::new(database, batch_size)?
+    };
+    Ok(storage.with_privacy(redactor.clone()))
+}
+
+/// The `[quota]` allowance, falling back to the `[hooks]` monthly cap when none is set
+fn quota_config(app_config: &config::AppConfig) -> config::QuotaConfig {
+    let mut quota = app_config.quota.clone();
+    quota.monthly_gb = quota.monthly_gb.or(app_config.hooks.monthly_cap_gb);
+    quota
+}
+
+/// Sampler for `kw packets` from `--sample`/`--sample-threshold`, else `[capture]`; `None`
+/// when neither asks for sampling
+#[cfg(feature = "capture")]
+fn packet_sampler(config: &config::CaptureConfig, sample: Option<String>, threshold: Option<u64>) -> Result<Option<PacketSampler>> {
+    let rate = match sample {
+        Some(rate) => rate.parse().context("Invalid --sample rate")?,
+        None => match config.sample {
+            Some(rate) => rate,
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(PacketSampler::new(rate, threshold.unwrap_or(config.sample_threshold_pps))))
+}
+
+/// Packets kept by `kw packets --inspect`, in the packet inspector when run in a terminal,
+/// otherwise printed as hex dumps
+#[cfg(feature = "capture")]
+fn show_inspected(packets: Vec<models::packet_dump::InspectedPacket>) -> Result<()> {
+    if packets.is_empty() {
+        println!("🔬 No packets were captured to inspect");
+        return Ok(());
+    }
+    #[cfg(feature = "dashboard")]
+    if interface_picker::is_interactive() {
+        return dashboard::PacketInspector::new(packets).run();
+    }
+    cli::packet_commands::print_inspected(&packets);
+    Ok(())
+}
+
 /// Filters network interfaces based on user criteria
 fn filter_interfaces(
     stats: Vec<collectors::bandwidth_collector::BandwidthStats>,
     active_only: bool,
-    interface_filter: Option<&str>,
+    interface_filter: Option<&InterfaceSelection>,
 ) -> Result<Vec<collectors::bandwidth_collector::BandwidthStats>> {
     let mut filtered = stats;
 
-    // Filter by specific interface if requested
-    if let Some(interface_name) = interface_filter {
-        filtered.retain(|stat| stat.interface_name == interface_name);
+    // Keep the interfaces matching any of the requested names and globs
+    if let Some(selection) = interface_filter {
+        filtered.retain(|stat| selection.matches(&stat.interface_name));
     }
 
     // Filter to show only active interfaces if requested
@@ -242,11 +435,24 @@ fn filter_interfaces(
 }
 
 /// Displays bandwidth measurement results with detailed error reporting and confidence indicators
+/// Bond, bridge and team interfaces are shown once, with their member links beneath them, and
+/// VPN tunnels with the interface carrying them; with `show_total`, a synthetic "total" row
+/// sums the listed interfaces at the end, counting tunnel traffic once
+#[allow(clippy::too_many_arguments)]
 fn display_bandwidth_results(
     stats: &[collectors::bandwidth_collector::BandwidthStats],
+    aggregates: &[LinkAggregate],
+    tunnels: &[Tunnel],
+    links: &HashMap<String, LinkInfo>,
+    bursts: &HashMap<String, BurstStats>,
+    saturation_percent: f64,
+    show_members: bool,
+    show_total: bool,
     detailed: bool,
     measurement_duration: u64,
     filtering_info: Option<&str>,
+    tags: &models::TagBook,
+    namespace_owners: &HashMap<String, String>,
 ) -> Result<()> {
     println!("Bandwidth Measurement Results ({}s measurement period):", measurement_duration);
     if let Some(info) = filtering_info {
@@ -254,16 +460,29 @@ fn display_bandwidth_results(
     }
     println!("{}", "=".repeat(60));
 
-    for stat in stats {
-        println!("\nInterface: {}", stat.interface_name);
+    let groups = group_interfaces(stats, aggregates, tunnels);
+    for group in &groups {
+        let stat = group.stats;
+        println!("\nInterface: {}", tags.describe(&stat.interface_name));
+        // The host end of a veth receives what the namespace sends
+        if let Some(owner) = namespace_owners.get(&stat.interface_name) {
+            println!("  Namespace: {owner} (host end of its veth: Download is what it sends)");
+        }
+        // Inner traffic here, and again encrypted on the transport
+        if let Some(tunnel) = group.tunnel {
+            println!("  Tunnel: {tunnel}");
+        }
+        let carried: Vec<&str> = tunnels
+            .iter()
+            .filter(|tunnel| tunnel.transport.as_deref() == Some(stat.interface_name.as_str()))
+            .map(|tunnel| tunnel.name.as_str())
+            .collect();
+        if !carried.is_empty() {
+            println!("  Carries: {} (encrypted, included in this interface's traffic)", carried.join(", "));
+        }
         
         // Display speeds with confidence indicators
-        let confidence_indicator = match stat.calculation_confidence {
-            CalculationConfidence::High => "✓",
-            CalculationConfidence::Medium => "~",
-            CalculationConfidence::Low => "!",
-            CalculationConfidence::None => "?",
-        };
+        let confidence_indicator = confidence_indicator(&stat.calculation_confidence);
 
         println!("  Download: {} {}", 
             collectors::bandwidth_collector::format_speed(stat.download_speed_bps),
@@ -273,6 +492,54 @@ fn display_bandwidth_results(
             collectors::bandwidth_collector::format_speed(stat.upload_speed_bps),
             confidence_indicator
         );
+        if let Some(link) = links.get(&stat.interface_name) {
+            let utilisation = link.utilisation(stat.download_speed_bps, stat.upload_speed_bps) * 100.0;
+            let saturated = if utilisation >= saturation_percent { " - saturated" } else { "" };
+            println!("  Link:     {}, {utilisation:.1}% used{saturated}", link.describe());
+        }
+        if let Some(burst) = bursts.get(&stat.interface_name) {
+            println!(
+                "  Peak {}ms: ↓ {} ↑ {}",
+                HIRES_INTERVAL.as_millis(),
+                collectors::bandwidth_collector::format_speed(burst.peak_download_bps),
+                collectors::bandwidth_collector::format_speed(burst.peak_upload_bps)
+            );
+            // A burst that fills the link overflows buffers even when the second's average looks fine
+            if let Some(link) = links.get(&stat.interface_name) {
+                let utilisation = link.utilisation(burst.peak_download_bps, burst.peak_upload_bps) * 100.0;
+                let saturated = if utilisation >= saturation_percent { " - saturated" } else { "" };
+                println!("  Peak Link: {utilisation:.1}% used{saturated}");
+            }
+            if burst.bursts > 0 {
+                println!(
+                    "  Bursts:   {} (longest {}ms, {:.1}% of the time above {}x the average)",
+                    burst.bursts,
+                    burst.longest_burst.as_millis(),
+                    burst.burst_share(Duration::from_secs(measurement_duration)) * 100.0,
+                    BURST_FACTOR
+                );
+            } else {
+                println!("  Bursts:   none");
+            }
+        }
+
+        if let Some(aggregate) = group.aggregate {
+            let up = aggregate.members.iter().filter(|member| member.up).count();
+            println!("  {} of {} links ({} up)", aggregate.kind, aggregate.members.len(), up);
+            for (member, member_stats) in group.members.iter().filter(|_| show_members) {
+                let traffic = match member_stats {
+                    Some(member_stats) if member.up => format!(
+                        "↓ {} ↑ {}",
+                        collectors::bandwidth_collector::format_speed(member_stats.download_speed_bps),
+                        collectors::bandwidth_collector::format_speed(member_stats.upload_speed_bps)
+                    ),
+                    _ if member.up => "up".to_string(),
+                    _ => "down".to_string(),
+                };
+                let active = if aggregate.active_member.as_ref() == Some(&member.name) { " (active)" } else { "" };
+                println!("    └ {:<15} {traffic}{active}", tags.describe(&member.name));
+            }
+        }
 
         // Show confidence explanation
         match stat.calculation_confidence {
@@ -305,6 +572,29 @@ fn display_bandwidth_results(
         }
     }
 
+    if let Some(total) = total_stats(&groups).filter(|_| show_total) {
+        let indicator = confidence_indicator(&total.calculation_confidence);
+        println!("\n{}", "-".repeat(60));
+        let carried: Vec<&str> = groups
+            .iter()
+            .filter(|group| group.carried_by_listed(&groups).is_some())
+            .map(|group| group.stats.interface_name.as_str())
+            .collect();
+        if carried.is_empty() {
+            println!("Total ({} interfaces):", groups.len());
+        } else {
+            println!("Total ({} interfaces, tunnels counted through their transport: {}):", groups.len(), carried.join(", "));
+        }
+        println!("  Download: {} {}", collectors::bandwidth_collector::format_speed(total.download_speed_bps), indicator);
+        println!("  Upload:   {} {}", collectors::bandwidth_collector::format_speed(total.upload_speed_bps), indicator);
+        if detailed {
+            println!("  Total Received: {}", collectors::bandwidth_collector::format_bytes(total.bytes_received as f64));
+            println!("  Total Sent: {}", collectors::bandwidth_collector::format_bytes(total.bytes_sent as f64));
+            println!("  Packets Received: {}", total.packets_received);
+            println!("  Packets Sent: {}", total.packets_sent);
+        }
+    }
+
     // Display legend for confidence indicators
     println!("\nConfidence Indicators:");
     println!("  ✓ High confidence    ~ Medium confidence    ! Low confidence    ? No data");
@@ -316,6 +606,15 @@ fn display_bandwidth_results(
     Ok(())
 }
 
+fn confidence_indicator(confidence: &CalculationConfidence) -> &'static str {
+    match confidence {
+        CalculationConfidence::High => "✓",
+        CalculationConfidence::Medium => "~",
+        CalculationConfidence::Low => "!",
+        CalculationConfidence::None => "?",
+    }
+}
+
 /// Helper function to handle collection errors with comprehensive error reporting
 fn handle_collection_error(
     collector: &mut collectors::BandwidthCollector,
@@ -376,70 +675,557 @@ fn handle_collection_error(
 /// Handles command-line parsing and dispatches to appropriate handlers
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging based on RUST_LOG environment variable
-    env_logger::init();
-    
     // Parse command-line arguments using clap
     let cli = Cli::parse();
 
+    // Initialize logging based on RUST_LOG environment variable
+    // The Windows service sets up event log logging itself
+    #[cfg(all(windows, feature = "capture"))]
+    let service_logs_itself = matches!(
+        cli.command,
+        Commands::Service { action: cli::commands::ServiceAction::Run { .. } }
+    );
+    #[cfg(not(all(windows, feature = "capture")))]
+    let service_logs_itself = false;
+    if !service_logs_itself {
+        env_logger::init();
+    }
+
+    // Buffer sizes and low-memory mode come from the optional configuration file
+    let app_config = config::AppConfig::load()?;
+    let resources = config::ResourceProfile::detect(&app_config.low_memory);
+    let data = config::DataDir::resolve(&app_config.data, cli.profile.as_deref())?;
+    let database = data.database();
+    log::debug!("Using the {} profile in {}", data.profile(), data.dir().display());
+    match data.adopt_legacy_data() {
+        Ok(Some(legacy)) => eprintln!(
+            "📦 Moved the database and counter state from {} into {}",
+            legacy.display(),
+            data.dir().display()
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!(
+            "⚠️  Could not move the data kept in ./data into {}: {e:#}. Move the files there to keep that history, or set [data] dir = \"./data\"",
+            data.dir().display()
+        ),
+    }
+    // Every database opened from here on is keyed; `kw encryption` converts with keys of its own
+    if !matches!(cli.command, Commands::Encryption { .. })
+        && let Some((key, _)) = storage::encryption::configured_key(&app_config.encryption, data.profile())?
+    {
+        storage::encryption::set_key(key)?;
+    }
+    let redactor = app_config.privacy.redactor(cli.privacy.as_deref().map(models::PrivacyMode::parse).transpose()?);
+
     // Match on the parsed command and execute appropriate handler
     match cli.command {
         // Live monitoring with real-time dashboard
-        Commands::Live { interface, packets: _, interval, important_only, show_all } => {
-            let mut dashboard = Dashboard::new(interval, interface, important_only, show_all);
-            dashboard.run().await?;
+        #[cfg(feature = "dashboard")]
+        Commands::Live { interface, packets, interval, fixed_interval, smooth, important_only, show_all, aggregate, history_minutes, privileged_helper, suggest_throttle, output, layout, snapshot_dir, record } => {
+            #[cfg(not(feature = "capture"))]
+            if packets || privileged_helper {
+                anyhow::bail!("--packets needs packet capture, which this kw was built without (the `capture` feature)");
+            }
+            // Capturing without --interface asks which one, where someone is there to answer
+            let interface = match InterfaceSelection::new(&interface) {
+                None if packets && output != "ndjson" && interface_picker::is_interactive() => {
+                    interface_picker::pick_live_interface().await.as_deref().and_then(InterfaceSelection::parse)
+                }
+                interface => interface,
+            };
+            let mut dashboard =
+                Dashboard::new(interval, interface, important_only, show_all, packets, history_minutes)
+                    .with_resource_profile(resources)
+                    .with_contention(app_config.contention.clone())
+                    .with_security_config(app_config.security.clone())
+                    .with_throttle_suggestions(suggest_throttle)
+                    .with_aggregate_total(aggregate)
+                    .with_sampling(app_config.sampling.plan()?)
+                    .with_tags(storage::packet_storage::load_tag_book(&database).with_redactor(redactor.clone()))
+                    .with_services(app_config.services.map()?)
+                    .with_baseline(storage::packet_storage::load_bandwidth_baseline(
+                        &database,
+                        &app_config.anomaly,
+                    ))
+                    .with_ping(app_config.ping.clone())
+                    .with_congestion(app_config.congestion.clone())
+                    .with_link_aggregation(app_config.link_aggregation.clone())
+                    .with_layout(DashboardLayout::parse(&layout)?)
+                    .with_snapshot_dir(snapshot_dir)
+                    .with_refresh(config::RefreshConfig {
+                        auto: app_config.refresh.auto && !fixed_interval,
+                        ..app_config.refresh.clone()
+                    })
+                    .with_smoothing(smooth.unwrap_or(app_config.smoothing.window_secs));
+            // Without a writable database the dashboard still pings, it just keeps nothing
+            match PacketStorage::new(&database, resources.storage_batch_size) {
+                Ok(storage) => dashboard = dashboard.with_storage(Arc::new(storage.with_privacy(redactor.clone()))),
+                Err(e) => log::warn!("Ping statistics will not be recorded: {e:#}"),
+            }
+            if let Some(path) = record {
+                dashboard = dashboard.with_recording(SessionRecorder::create(&path, interval)?);
+            }
+
+            #[cfg(feature = "capture")]
+            {
+                dashboard = dashboard.with_capture_backend(app_config.capture.backend);
+            }
+
+            // Launch the privileged capture helper before the dashboard takes over the terminal
+            #[cfg(all(unix, feature = "capture"))]
+            let helper = if privileged_helper {
+                let helper = CaptureHelperProcess::spawn()?;
+                dashboard = dashboard.with_capture_helper(helper.socket_path().to_path_buf());
+                Some(helper)
+            } else {
+                None
+            };
+            #[cfg(all(not(unix), feature = "capture"))]
+            if privileged_helper {
+                anyhow::bail!("--privileged-helper is only supported on Unix platforms");
+            }
+
+            if output == "ndjson" {
+                dashboard.stream().await?;
+            } else {
+                dashboard.run().await?;
+            }
+
+            #[cfg(all(unix, feature = "capture"))]
+            if let Some(helper) = helper {
+                helper.shutdown().await;
+            }
+        }
+        // Play back a recorded dashboard session
+        #[cfg(feature = "dashboard")]
+        Commands::Replay { file, speed, interface, aggregate, history_minutes, layout } => {
+            let session = Session::load(&file)?;
+            let interval = session.header.interval_secs;
+            // The recording kept the interfaces its dashboard showed, so none are filtered out again
+            Dashboard::new(interval, InterfaceSelection::new(&interface), false, true, false, history_minutes)
+                .with_replay(SessionReplay::new(session, speed)?)
+                .with_aggregate_total(aggregate)
+                .with_tags(storage::packet_storage::load_tag_book(&database).with_redactor(redactor.clone()))
+                .with_layout(DashboardLayout::parse(&layout)?)
+                .with_smoothing(app_config.smoothing.window_secs)
+                .run()
+                .await?;
+        }
+        // Live ranking of remote hosts by bandwidth
+        #[cfg(all(feature = "dashboard", feature = "capture"))]
+        Commands::Top { interface, window, interval, filter, no_resolve, privileged_helper } => {
+            let interface = match InterfaceSelection::new(&interface) {
+                None if interface_picker::is_interactive() => {
+                    interface_picker::pick_live_interface().await.as_deref().and_then(InterfaceSelection::parse)
+                }
+                interface => interface,
+            };
+            let mut view = TopView::new(interface, window, interval)?.with_privacy(redactor.clone());
+            // A resolver that cannot be built leaves the view showing addresses only
+            match enrichment::build_resolver(&app_config.dns) {
+                Ok(resolver) => view = view.with_resolver(resolver, !no_resolve),
+                Err(e) => log::warn!("Host names unavailable: {e:#}"),
+            }
+            if let Some(expression) = filter {
+                let filter = CaptureFilter::parse(&expression).context("Invalid --filter expression")?;
+                view = view.with_filter(filter);
+            }
+
+            #[cfg(unix)]
+            let helper = if privileged_helper {
+                let helper = CaptureHelperProcess::spawn()?;
+                view = view.with_capture_helper(helper.socket_path().to_path_buf());
+                Some(helper)
+            } else {
+                None
+            };
+            #[cfg(not(unix))]
+            if privileged_helper {
+                anyhow::bail!("--privileged-helper is only supported on Unix platforms");
+            }
+
+            view.run().await?;
+
+            #[cfg(unix)]
+            if let Some(helper) = helper {
+                helper.shutdown().await;
+            }
         }
         // Display current network status (one-time snapshot)
-        Commands::Status { detailed, measurement_duration, active_only, interface, important_only, show_all, interface_analysis } => {
-            handle_status_command(detailed, measurement_duration, active_only, interface, important_only, show_all, interface_analysis).await?;
+        Commands::Status { detailed, measurement_duration, hires, active_only, interface, namespace, container, important_only, show_all, aggregate, interface_analysis, style } => {
+            let interface = InterfaceSelection::new(&interface);
+            if OutputStyle::parse(&style)? == OutputStyle::Vnstat {
+                // vnStat's summary comes from the stored history, nothing is measured
+                let storage = Arc::new(open_storage(&database, true, resources.storage_batch_size, &redactor)?);
+                ReportCommandHandler::new(storage).handle_vnstat_status(interface.as_ref()).await?;
+            } else {
+                let (interface, namespace_owners) = match select_namespaces(interface.as_ref(), &namespace, &container)? {
+                    Some((selection, owners)) => (Some(selection), Some(owners)),
+                    None => (interface, None),
+                };
+                handle_status_command(
+                    detailed,
+                    measurement_duration,
+                    hires,
+                    active_only,
+                    interface,
+                    namespace_owners,
+                    important_only,
+                    show_all,
+                    aggregate,
+                    interface_analysis,
+                    &app_config.link_aggregation,
+                    &app_config.contention,
+                    &database,
+                )
+                .await?;
+            }
+        }
+        // Usage report with a data-quality section
+        Commands::Report { period, app_breakdown, geo, compare, style, read_only } => {
+            let compare = compare.as_deref().map(ReportComparison::parse).transpose()?;
+            let style = OutputStyle::parse(&style)?;
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = ReportCommandHandler::new(storage)
+                .with_anomaly_config(app_config.anomaly.clone())
+                .with_congestion_config(app_config.congestion.clone())
+                .with_geoip_config(app_config.geoip.clone())
+                .with_quota_config(quota_config(&app_config))
+                .with_units(display::Units::from_config(&app_config.display));
+            handler.handle_report_command(&period, app_breakdown, geo, compare, style).await?;
         }
-        // Future feature: Generate usage reports
-        Commands::Report { period, app_breakdown: _ } => {
-            println!("Report generation for period '{period}' is not yet implemented.");
+        // Billing-period usage against the allowance, with its forecast
+        Commands::Quota { read_only } => {
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = ReportCommandHandler::new(storage)
+                .with_quota_config(quota_config(&app_config))
+                .with_units(display::Units::from_config(&app_config.display));
+            handler.handle_quota_command().await?;
+        }
+        // Capture performance of the current or last session
+        Commands::CaptureStats { sessions, limit, read_only } => {
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = CaptureStatsCommandHandler::new(storage);
+            handler.handle_capture_stats_command(sessions, limit).await?;
+        }
+        // kw's own health, as recorded by the daemon
+        Commands::Stats { action } => {
+            let read_only = match &action {
+                StatsAction::Collector { read_only, .. } => *read_only,
+            };
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = StatsCommandHandler::new(storage);
+            handler.handle_stats_command(action).await?;
+        }
+        // Active throughput test against HTTP endpoints
+        Commands::Speedtest { download_url, upload_url, no_upload, history } => {
+            let storage = Arc::new(PacketStorage::new(&database, resources.storage_batch_size)?);
+            let handler = SpeedTestCommandHandler::new(storage).with_config(app_config.speedtest.clone());
+            handler.handle_speedtest_command(download_url, upload_url, no_upload, history).await?;
+        }
+        // Friendly names and notes for hosts, devices and interfaces
+        Commands::Tag { target, label, note, remove } => {
+            let storage = Arc::new(PacketStorage::new(&database, resources.storage_batch_size)?);
+            let handler = TagCommandHandler::new(storage);
+            handler.handle_tag_command(target, label, note, remove).await?;
+        }
+        // Usage history from other tools, stored as bandwidth samples
+        Commands::Import { file, format, interface } => {
+            let format = ImportFormat::parse(&format)?;
+            let storage = Arc::new(PacketStorage::new(&database, resources.storage_batch_size)?);
+            let handler = ImportCommandHandler::new(storage).with_units(display::Units::from_config(&app_config.display));
+            handler.handle_import_command(&file, format, &interface).await?;
+        }
+        Commands::Shaping { period, interface, format, download_mbit, upload_mbit, output, read_only } => {
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = ShapingCommandHandler::new(storage);
+            handler
+                .handle_shaping_command(&period, interface, &format, download_mbit, upload_mbit, output)
+                .await?;
+        }
+        Commands::Audit { action } => {
+            // Verifying evidence must not change it
+            let storage = Arc::new(PacketStorage::open_read_only(&database)?);
+            let handler = AuditCommandHandler::new(storage);
+            handler.handle_audit_command(action).await?;
         }
         // Future feature: Show historical usage data
         Commands::History { days } => {
             println!("History display for {days:?} days is not yet implemented.");
         }
         // Future feature: Export data to various formats
-        Commands::Export { format, output } => {
-            println!("Export to format '{format}' (output: {output:?}) is not yet implemented.");
+        // Usage summaries and what changed between them
+        Commands::Export { action } => {
+            let read_only = match &action {
+                ExportAction::Summary { read_only, .. } | ExportAction::Diff { read_only, .. } => *read_only,
+            };
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let handler = ExportCommandHandler::new(storage).with_units(display::Units::from_config(&app_config.display));
+            handler.handle_export_command(action).await?;
         }
         // Real-time packet monitoring
-        Commands::Packets { interface, protocol, capture, detailed, max_connections } => {
+        #[cfg(feature = "capture")]
+        Commands::Packets { interface, namespace, container, protocol, filter, capture, detailed, max_connections, no_resolve, by_service, privileged_helper, capture_backend, sample, sample_threshold, inspect } => {
+            if inspect.is_some() && redactor.is_active() {
+                anyhow::bail!("--inspect shows packet contents, addresses included, and cannot be used with --privacy");
+            }
             // Initialize packet storage
-            let storage = Arc::new(PacketStorage::new("./data/packets.db", 100)?);
-            let handler = PacketCommandHandler::new(storage);
-            
+            let storage = Arc::new(
+                PacketStorage::new(&database, resources.storage_batch_size)?
+                    .with_privacy(redactor.clone())
+                    .with_rollup(resources.storage_rollup)
+                    .with_flush_interval(resources.storage_flush_interval),
+            );
+            let mut handler = PacketCommandHandler::new(storage)
+                .with_resource_profile(resources)
+                .with_security_config(app_config.security.clone())
+                .with_services(app_config.services.map()?)
+                .with_capture_backend(match capture_backend {
+                    Some(backend) => backend.parse()?,
+                    None => app_config.capture.backend,
+                });
+            if let Some(sampler) = packet_sampler(&app_config.capture, sample, sample_threshold)? {
+                handler = handler.with_sampler(sampler);
+            }
+            if let Some(count) = inspect {
+                handler = handler.with_inspector(count);
+            }
+            if let Some(expression) = filter {
+                let filter = CaptureFilter::parse(&expression).context("Invalid --filter expression")?;
+                handler = handler.with_capture_filter(filter);
+            }
+            // Host names are shown by default here, so a broken [dns] section only loses them
+            if !no_resolve {
+                match enrichment::build_resolver(&app_config.dns) {
+                    Ok(resolver) => handler = handler.with_resolver(resolver),
+                    Err(e) => log::warn!("Host names unavailable: {e:#}"),
+                }
+            }
+
+            #[cfg(unix)]
+            let (handler, helper) = if privileged_helper {
+                let helper = CaptureHelperProcess::spawn()?;
+                (handler.with_capture_helper(helper.socket_path().to_path_buf()), Some(helper))
+            } else {
+                (handler, None)
+            };
+            #[cfg(not(unix))]
+            if privileged_helper {
+                anyhow::bail!("--privileged-helper is only supported on Unix platforms");
+            }
+
+            let interface = InterfaceSelection::new(&interface);
+            let interface = match select_namespaces(interface.as_ref(), &namespace, &container)? {
+                Some((selection, owners)) => {
+                    let mut owners: Vec<_> = owners.into_iter().collect();
+                    owners.sort();
+                    for (veth, owner) in owners {
+                        println!("Watching {owner} on {veth} (received here is what it sends)");
+                    }
+                    Some(selection)
+                }
+                None => interface,
+            };
+
             handler.handle_packets_command(
                 interface,
                 protocol,
                 capture,
                 detailed,
                 max_connections,
+                by_service,
             ).await?;
+
+            #[cfg(unix)]
+            if let Some(helper) = helper {
+                helper.shutdown().await;
+            }
+            if inspect.is_some() {
+                show_inspected(handler.take_inspected())?;
+            }
         }
         // Traffic pattern analysis
-        Commands::Analyze { period, interface, security, protocols } => {
+        #[cfg(feature = "capture")]
+        Commands::Analyze { period, interface, security, protocols, diagnostics, limit, format, export, export_format, resolve, from_pcap, read_only, sample, sample_threshold } => {
             // Initialize packet storage
-            let storage = Arc::new(PacketStorage::new("./data/packets.db", 100)?);
-            let handler = PacketCommandHandler::new(storage);
-            
-            handler.handle_analyze_command(
-                period,
-                interface,
-                security,
-                protocols,
-            ).await?;
+            let storage = Arc::new(
+                open_storage(&database, read_only, resources.storage_batch_size, &redactor)?
+                    .with_rollup(resources.storage_rollup)
+                    .with_flush_interval(resources.storage_flush_interval),
+            );
+            let mut handler = PacketCommandHandler::new(storage)
+                .with_resource_profile(resources)
+                .with_security_config(app_config.security.clone());
+            if resolve {
+                handler = handler.with_resolver(enrichment::build_resolver(&app_config.dns)?);
+            }
+            // The [capture] sample setting is for live capture; imports are only sampled on request
+            if let Some(rate) = sample {
+                let threshold = sample_threshold.unwrap_or(app_config.capture.sample_threshold_pps);
+                let rate = rate.parse().context("Invalid --sample rate")?;
+                handler = handler.with_sampler(PacketSampler::new(rate, threshold));
+            }
+
+            if let Some(path) = export {
+                let format = match export_format {
+                    Some(format) => format.parse()?,
+                    None => SecurityReportFormat::for_path(&path),
+                };
+                handler = handler.with_security_export(path, format);
+            }
+
+            match from_pcap {
+                Some(path) => {
+                    handler.handle_pcap_import(&path, interface, security, protocols, diagnostics).await?;
+                }
+                None => {
+                    handler.handle_analyze_command(
+                        period,
+                        interface,
+                        security,
+                        protocols,
+                        diagnostics,
+                        limit,
+                        &format,
+                    ).await?;
+                }
+            }
         }
         // Graph generation
-        Commands::Graph { graph_type } => {
+        #[cfg(feature = "graphs")]
+        Commands::Graph { graph_type, read_only } => {
             // Initialize database manager
-            let db = Arc::new(DatabaseManager::new("./data/packets.db").await?);
-            let handler = GraphCommandHandler::new(db);
+            let db = if read_only {
+                Arc::new(DatabaseManager::open_read_only(&database).await?.with_privacy(redactor.clone()))
+            } else {
+                Arc::new(DatabaseManager::new(&database).await?.with_privacy(redactor.clone()))
+            };
+            let mut handler = GraphCommandHandler::new(db)
+                .with_units(display::Units::from_config(&app_config.display))
+                .with_services(app_config.services.map()?);
+            match enrichment::build_resolver(&app_config.dns) {
+                Ok(resolver) => handler = handler.with_resolver(resolver),
+                Err(e) => log::warn!("Host names unavailable: {e:#}"),
+            }
             
             handler.handle_graph_command(graph_type).await?;
         }
+        // Retention policy and compaction on demand
+        Commands::Maintain { no_vacuum } => {
+            let storage = Arc::new(PacketStorage::new(&database, resources.storage_batch_size)?);
+            let handler = MaintainCommandHandler::new(storage)
+                .with_retention_config(app_config.retention.clone())
+                .with_units(display::Units::from_config(&app_config.display));
+            handler.handle_maintain_command(no_vacuum).await?;
+        }
+        // Remove everything kaipo-watcher has written to disk
+        Commands::Purge { dry_run, yes, all_profiles } => {
+            let profiles = if all_profiles { data.all_profiles() } else { vec![data.clone()] };
+            let handler = PurgeCommandHandler::new(data.dir())
+                .with_data_dirs(profiles.iter().map(|profile| profile.dir().to_path_buf()).collect())
+                .with_keychain_profiles(profiles.iter().map(|profile| profile.profile().to_string()).collect());
+            handler.handle_purge_command(dry_run, yes).await?;
+        }
+        // Encryption of the packet database at rest
+        Commands::Encryption { action } => {
+            let handler = EncryptionCommandHandler::new(&database, data.profile())
+                .with_config(app_config.encryption.clone());
+            handler.handle_encryption_command(action).await?;
+        }
+        // Capture prerequisites and their fixes
+        Commands::Doctor { fix, yes } => {
+            DoctorCommandHandler::new().handle_doctor_command(fix, yes).await?;
+        }
+        // The web UI on its own
+        #[cfg(feature = "api")]
+        Commands::Serve { web, read_only } => {
+            let address = match web {
+                Some(address) => address,
+                None => app_config.web.address()?.unwrap_or(service::web::DEFAULT_ADDRESS),
+            };
+            let storage = Arc::new(open_storage(&database, read_only, resources.storage_batch_size, &redactor)?);
+            let bandwidth = collectors::collector_service::CollectorService::start(
+                collectors::BandwidthCollector::new()
+                    .with_sampling_plan(app_config.sampling.plan()?)
+                    .with_interface_watcher()
+                    .with_power_watcher(),
+                service::web::SAMPLE_INTERVAL,
+            )
+            .await;
+            let web_ui = service::web::WebUi::bind(address, storage, bandwidth.subscribe()).await?;
+            println!("Web UI on http://{address}; press Ctrl+C to stop");
+            tokio::select! {
+                _ = web_ui.run() => {}
+                _ = shutdown::requested() => {}
+            }
+        }
+        // Report this machine to a hub
+        #[cfg(feature = "remote")]
+        Commands::Agent { hub, name, interval } => {
+            let mut agent = remote::Agent::new(&app_config.remote, &data);
+            if let Some(hub) = hub {
+                agent = agent.with_hub(hub);
+            }
+            if let Some(name) = name {
+                agent = agent.with_name(name);
+            }
+            if let Some(interval) = interval {
+                agent = agent.with_interval(Duration::from_secs(interval));
+            }
+            println!("Reporting as {}; press Ctrl+C to stop", agent.name());
+            agent.run(shutdown::requested()).await?;
+        }
+        // Collect agents' reports
+        #[cfg(feature = "remote")]
+        Commands::Hub { listen, insecure } => {
+            let mut hub = remote::Hub::new(&app_config.remote, &data)
+                .with_batch_size(resources.storage_batch_size)
+                .with_insecure(insecure);
+            if let Some(listen) = listen {
+                hub = hub.with_listen(listen);
+            }
+            hub.run(shutdown::requested()).await?;
+        }
+        // Read-only JSON API over the packet database
+        #[cfg(feature = "api")]
+        Commands::Api { listen } => {
+            let address = match listen {
+                Some(address) => address,
+                None => app_config.api.address()?,
+            };
+            let storage = Arc::new(open_storage(&database, true, resources.storage_batch_size, &redactor)?);
+            let server = api::ApiServer::bind(address, storage).await?;
+            println!("API on http://{address}/api/v1; press Ctrl+C to stop");
+            server.run(shutdown::requested()).await?;
+        }
+        // Background service management and the daemon it runs
+        #[cfg(feature = "capture")]
+        Commands::Service { action } => {
+            let handler = ServiceCommandHandler::new()
+                .with_resource_profile(resources)
+                .with_capture(app_config.capture.clone())
+                .with_sampling(app_config.sampling.plan()?)
+                .with_web_ui(app_config.web.address()?)
+                .with_security_config(app_config.security.clone())
+                .with_ping(app_config.ping.clone())
+                .with_retention(app_config.retention.clone())
+                .with_hooks(app_config.hooks.clone())
+                .with_contention(app_config.contention.clone())
+                .with_webhook(app_config.webhook.clone())
+                .with_mqtt(app_config.mqtt.clone())
+                .with_storage(app_config.storage.clone())
+                .with_config_fingerprint(config::ConfigFingerprint::current())
+                .with_data_dir(data.dir().to_path_buf());
+            handler.handle_service_command(action).await?;
+        }
+        // Privileged capture helper spawned by --privileged-helper
+        #[cfg(feature = "capture")]
+        Commands::CaptureHelper { socket } => {
+            #[cfg(unix)]
+            collectors::capture_helper::run_helper(&socket).await?;
+            #[cfg(not(unix))]
+            anyhow::bail!("Capture helper is only supported on Unix platforms (socket: {})", socket.display());
+        }
     }
 
     Ok(())
@@ -1,6 +1,14 @@
 pub mod packet;
+pub mod packet_dump;
+pub mod privacy;
+pub mod service;
+pub mod tag;
 
 pub use packet::{
-    common_application_protocols, ApplicationProtocol, NetworkPacket, PacketDirection,
-    PacketProtocol, PacketStatistics, ProtocolDistribution, TransportProtocol,
-};
\ No newline at end of file
+    common_application_protocols, ApplicationProtocol, IpFragment, IpHeader, NetworkPacket,
+    PacketDirection, PacketProtocol, PacketSizeDistribution, PacketSizeHistogram, PacketStatistics, PacketTooBig,
+    ProcessInfo, ProtocolDistribution, TcpSegment, TransportProtocol, PACKET_SIZE_BUCKETS, SMALL_PACKET_OVERLOAD_SHARE,
+};
+pub use privacy::{PrivacyMode, Redactor};
+pub use service::ServiceMap;
+pub use tag::{Tag, TagBook, TagKind};
@@ -1,9 +1,10 @@
 // Core packet data models for network monitoring
 // Defines structures for representing captured network packets and related statistics
 
+use crate::analyzers::discovery::DeviceAnnouncement;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
 
 /// Core data structure representing a captured network packet
@@ -12,6 +13,8 @@ use std::net::IpAddr;
 /// # Examples
 /// 
 /// ```rust
+/// use kaipo_watcher::{NetworkPacket, PacketDirection, PacketProtocol};
+///
 /// let packet = NetworkPacket::new(
 ///     "eth0".to_string(),
 ///     1500,
@@ -41,6 +44,109 @@ pub struct NetworkPacket {
     pub dest_port: Option<u16>,
     /// Traffic direction relative to the monitoring system
     pub direction: PacketDirection,
+    /// Shannon entropy (bits/byte) of the start of the TCP/UDP payload
+    /// (None when the payload is too short to score)
+    #[serde(default)]
+    pub payload_entropy: Option<f64>,
+    /// TCP header fields for connection state tracking (None for non-TCP packets)
+    #[serde(default)]
+    pub tcp: Option<TcpSegment>,
+    /// ICMP or ICMPv6 message type (None for other protocols)
+    #[serde(default)]
+    pub icmp_type: Option<u8>,
+    /// Local process that sent or received the packet, when the capture source reports it
+    /// (pktap on macOS, kernel network events on Windows)
+    #[serde(default)]
+    pub process: Option<ProcessInfo>,
+    /// Name and type the sender announced about itself over mDNS or SSDP
+    #[serde(default)]
+    pub announcement: Option<DeviceAnnouncement>,
+    /// IP header fields for recognising the same packet seen more than once (None for non-IP packets)
+    #[serde(default)]
+    pub ip: Option<IpHeader>,
+    /// Path MTU report carried by an ICMP or ICMPv6 packet
+    #[serde(default)]
+    pub too_big: Option<PacketTooBig>,
+    /// Leading bytes of the frame from its link-layer header, kept only when the collector is
+    /// asked to (`kw packets --inspect`); never sent on by a capture helper
+    #[serde(skip)]
+    pub raw: Vec<u8>,
+}
+
+/// Process owning a socket
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// IP header fields that tell copies of one packet apart from other packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpHeader {
+    /// IPv4 time to live or IPv6 hop limit
+    pub ttl: u8,
+    /// Hash of the addresses, IPv4 identification and start of the IP payload; the TTL and
+    /// header checksum are left out, so a copy forwarded around a loop hashes the same
+    pub fingerprint: u64,
+    /// Where the packet sits in the original when it is a fragment of a larger one
+    #[serde(default)]
+    pub fragment: Option<IpFragment>,
+}
+
+/// Fragment of an IP packet split to fit a link's MTU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpFragment {
+    /// Bytes of the original payload before this fragment
+    pub offset: u16,
+    /// More fragments follow; clear on the last one
+    pub more: bool,
+    /// Length of the fragment as an IP packet, header included
+    pub length: u16,
+}
+
+/// An ICMP "fragmentation needed" or ICMPv6 "packet too big" message: a packet to
+/// `destination` was dropped for being larger than a link on the way allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketTooBig {
+    /// Destination of the dropped packet, from the header quoted in the message
+    pub destination: IpAddr,
+    /// MTU of the link that could not carry it; None when the router left it out
+    pub mtu: Option<u32>,
+}
+
+/// TCP header fields needed to follow a connection through its handshake and teardown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpSegment {
+    /// Control flags as they appear in the header (FIN = 0x01 ... CWR = 0x80)
+    pub flags: u8,
+    pub sequence: u32,
+    /// Next sequence number the sender expects from its peer (meaningful with ACK set)
+    #[serde(default)]
+    pub acknowledgement: u32,
+    /// Receive window advertised by the sender, unscaled
+    #[serde(default)]
+    pub window: u16,
+    /// Bytes of payload carried by the segment
+    pub payload_len: u32,
+    /// TCP timestamp option as (TSval, TSecr), when the sender uses it
+    #[serde(default)]
+    pub timestamps: Option<(u32, u32)>,
+}
+
+impl TcpSegment {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const ACK: u8 = 0x10;
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Sequence space the segment occupies; SYN and FIN each count as one byte
+    pub fn sequence_len(&self) -> u32 {
+        self.payload_len + u32::from(self.has(Self::SYN)) + u32::from(self.has(Self::FIN))
+    }
 }
 
 /// Represents the network layer protocol of a captured packet
@@ -87,6 +193,18 @@ pub enum PacketDirection {
     Local,
 }
 
+/// Private, loopback or link-local address, i.e. one on the monitored network
+pub(crate) fn is_private(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local(),
+        IpAddr::V6(v6) => {
+            v6.is_loopback()
+                || v6.segments()[0] & 0xfe00 == 0xfc00 // Unique local addresses
+                || v6.segments()[0] & 0xffc0 == 0xfe80 // Link-local addresses
+        }
+    }
+}
+
 /// Comprehensive statistics for a time period of packet capture
 /// Aggregates multiple metrics for performance analysis and reporting
 /// 
@@ -100,6 +218,9 @@ pub struct PacketStatistics {
     pub total_packets: u64,
     /// Total bytes captured in this period
     pub total_bytes: u64,
+    /// Packets discarded because the consumer fell behind and the capture queue was full
+    #[serde(default)]
+    pub dropped_packets: u64,
     /// Average packets per second during this period
     pub packets_per_second: f64,
     /// Average bytes per second during this period (bandwidth)
@@ -108,6 +229,9 @@ pub struct PacketStatistics {
     pub protocol_distribution: ProtocolDistribution,
     /// Most active network connections during this period
     pub top_connections: Vec<ConnectionInfo>,
+    /// Packet counts by size per interface and transport protocol
+    #[serde(default)]
+    pub packet_sizes: PacketSizeDistribution,
     /// Start of the measurement period
     pub start_time: DateTime<Local>,
     /// End of the measurement period
@@ -140,6 +264,177 @@ pub struct ProtocolDistribution {
     pub other_bytes: u64,
 }
 
+/// Labels of the packet size buckets, smallest first
+/// Sizes are of the frame as captured, link header included, so a full 1500-byte IP packet on
+/// Ethernet is a 1514-byte frame; anything larger than a VLAN-tagged standard frame is jumbo
+pub const PACKET_SIZE_BUCKETS: [&str; 4] = ["0-64", "65-512", "513-1522", "jumbo"];
+
+/// Share of minimum-size packets from which a link is considered to carry mostly overhead
+pub const SMALL_PACKET_OVERLOAD_SHARE: f64 = 0.6;
+
+/// Packets needed before the share of minimum-size packets is judged
+const SMALL_PACKET_OVERLOAD_MIN_PACKETS: u64 = 1000;
+
+/// Packet counts by size, in the buckets of `PACKET_SIZE_BUCKETS`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketSizeHistogram {
+    /// Minimum-size frames: bare ACKs, SYNs, ARP and other control messages
+    pub tiny: u64,
+    pub small: u64,
+    /// Up to a full standard Ethernet frame
+    pub large: u64,
+    pub jumbo: u64,
+}
+
+impl PacketSizeHistogram {
+    /// Counts `count` packets of `size_bytes`
+    pub fn add(&mut self, size_bytes: u64, count: u64) {
+        match size_bytes {
+            0..=64 => self.tiny += count,
+            65..=512 => self.small += count,
+            513..=1522 => self.large += count,
+            _ => self.jumbo += count,
+        }
+    }
+
+    /// Counts in the order of `PACKET_SIZE_BUCKETS`
+    pub fn counts(&self) -> [u64; 4] {
+        [self.tiny, self.small, self.large, self.jumbo]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts().iter().sum()
+    }
+
+    pub fn merge(&mut self, other: &PacketSizeHistogram) {
+        self.tiny += other.tiny;
+        self.small += other.small;
+        self.large += other.large;
+        self.jumbo += other.jumbo;
+    }
+
+    /// Share of the packets in the smallest bucket, 0.0 - 1.0
+    pub fn tiny_share(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.tiny as f64 / total as f64,
+        }
+    }
+
+    /// Whether enough packets were seen and most of them were minimum-size, the pattern of
+    /// ACK storms, floods and chatty small-message protocols that exhaust packet rates first
+    pub fn small_packet_overload(&self) -> bool {
+        self.total() >= SMALL_PACKET_OVERLOAD_MIN_PACKETS && self.tiny_share() >= SMALL_PACKET_OVERLOAD_SHARE
+    }
+
+    /// Counts added since `earlier`, a snapshot of the same running histogram
+    fn since(&self, earlier: &PacketSizeHistogram) -> PacketSizeHistogram {
+        PacketSizeHistogram {
+            tiny: self.tiny.saturating_sub(earlier.tiny),
+            small: self.small.saturating_sub(earlier.small),
+            large: self.large.saturating_sub(earlier.large),
+            jumbo: self.jumbo.saturating_sub(earlier.jumbo),
+        }
+    }
+}
+
+/// Packet size histograms per interface and transport protocol
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketSizeDistribution {
+    /// Interface -> transport protocol ("TCP", "UDP", "ICMP" or "Other") -> histogram
+    pub histograms: BTreeMap<String, BTreeMap<String, PacketSizeHistogram>>,
+}
+
+impl PacketSizeDistribution {
+    pub fn add_packet(&mut self, packet: &NetworkPacket) {
+        self.add_packets(packet, 1);
+    }
+
+    /// Counts `packet` `count` times, for a sampled packet standing in for others
+    pub fn add_packets(&mut self, packet: &NetworkPacket, count: u64) {
+        // Looked up by reference first, so only a new interface or protocol allocates
+        if !self.histograms.contains_key(packet.interface.as_str()) {
+            self.histograms.insert(packet.interface.clone(), BTreeMap::new());
+        }
+        let protocols = self.histograms.get_mut(packet.interface.as_str()).unwrap();
+        let protocol = match packet.transport_protocol {
+            TransportProtocol::Tcp => "TCP",
+            TransportProtocol::Udp => "UDP",
+            TransportProtocol::Icmp | TransportProtocol::ICMPv6 => "ICMP",
+            TransportProtocol::Other(_) => "Other",
+        };
+        if !protocols.contains_key(protocol) {
+            protocols.insert(protocol.to_string(), PacketSizeHistogram::default());
+        }
+        protocols.get_mut(protocol).unwrap().add(packet.size_bytes, count);
+    }
+
+    /// Adds the counts of `histogram` to those of `interface` and `protocol`
+    pub fn merge(&mut self, interface: &str, protocol: &str, histogram: &PacketSizeHistogram) {
+        self.histograms
+            .entry(interface.to_string())
+            .or_default()
+            .entry(protocol.to_string())
+            .or_default()
+            .merge(histogram);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total().total() == 0
+    }
+
+    /// Every interface and protocol together
+    pub fn total(&self) -> PacketSizeHistogram {
+        let mut total = PacketSizeHistogram::default();
+        for histogram in self.histograms.values().flat_map(BTreeMap::values) {
+            total.merge(histogram);
+        }
+        total
+    }
+
+    /// Histograms per interface, sorted by interface name
+    pub fn by_interface(&self) -> Vec<(String, PacketSizeHistogram)> {
+        self.histograms
+            .iter()
+            .map(|(interface, protocols)| {
+                let mut total = PacketSizeHistogram::default();
+                protocols.values().for_each(|histogram| total.merge(histogram));
+                (interface.clone(), total)
+            })
+            .collect()
+    }
+
+    /// Histograms per transport protocol across interfaces, sorted by protocol name
+    pub fn by_protocol(&self) -> Vec<(String, PacketSizeHistogram)> {
+        let mut protocols: BTreeMap<&str, PacketSizeHistogram> = BTreeMap::new();
+        for (protocol, histogram) in self.histograms.values().flatten() {
+            protocols.entry(protocol).or_default().merge(histogram);
+        }
+        protocols.into_iter().map(|(protocol, histogram)| (protocol.to_string(), histogram)).collect()
+    }
+
+    /// Packets counted since `earlier`, a snapshot of the same running distribution;
+    /// interfaces and protocols without new packets are left out
+    pub fn since(&self, earlier: &PacketSizeDistribution) -> PacketSizeDistribution {
+        let mut added = PacketSizeDistribution::default();
+        for (interface, protocols) in &self.histograms {
+            for (protocol, histogram) in protocols {
+                let before = earlier
+                    .histograms
+                    .get(interface)
+                    .and_then(|protocols| protocols.get(protocol))
+                    .copied()
+                    .unwrap_or_default();
+                let delta = histogram.since(&before);
+                if delta.total() > 0 {
+                    added.merge(interface, protocol, &delta);
+                }
+            }
+        }
+        added
+    }
+}
+
 /// Information about a network connection derived from packet analysis
 /// Used to track and display the most active connections
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -199,9 +494,48 @@ impl NetworkPacket {
             source_port: None,
             dest_port: None,
             direction,
+            payload_entropy: None,
+            tcp: None,
+            icmp_type: None,
+            process: None,
+            announcement: None,
+            ip: None,
+            too_big: None,
+            raw: Vec::new(),
         }
     }
 
+    /// Reinitialises the packet as `new` would, reusing the interface name's buffer
+    /// Lets a consumer decode packet after packet into one value without allocating
+    pub fn reset(
+        &mut self,
+        timestamp: DateTime<Local>,
+        interface: &str,
+        size_bytes: u64,
+        protocol: PacketProtocol,
+        direction: PacketDirection,
+    ) {
+        self.timestamp = timestamp;
+        self.interface.clear();
+        self.interface.push_str(interface);
+        self.size_bytes = size_bytes;
+        self.protocol = protocol;
+        self.transport_protocol = TransportProtocol::Other(0);
+        self.source_addr = None;
+        self.dest_addr = None;
+        self.source_port = None;
+        self.dest_port = None;
+        self.direction = direction;
+        self.payload_entropy = None;
+        self.tcp = None;
+        self.icmp_type = None;
+        self.process = None;
+        self.announcement = None;
+        self.ip = None;
+        self.too_big = None;
+        self.raw.clear();
+    }
+
     #[allow(dead_code)]
     pub fn is_tcp(&self) -> bool {
         matches!(self.transport_protocol, TransportProtocol::Tcp)
@@ -338,6 +672,17 @@ pub fn common_application_protocols() -> HashMap<u16, ApplicationProtocol> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_private_addresses_are_on_the_monitored_network() {
+        let private = |address: &str| is_private(address.parse().unwrap());
+        for address in ["10.1.2.3", "172.16.0.1", "192.168.1.1", "127.0.0.1", "169.254.10.1", "::1", "fd00::1", "fe80::1"] {
+            assert!(private(address), "{address}");
+        }
+        for address in ["8.8.8.8", "172.32.0.1", "2001:4860:4860::8888", "fec0::1"] {
+            assert!(!private(address), "{address}");
+        }
+    }
+
     #[test]
     fn test_packet_creation() {
         let packet = NetworkPacket::new(
@@ -373,6 +718,45 @@ mod tests {
         assert_eq!(dist.total_bytes(), 100);
     }
 
+    #[test]
+    fn test_packet_size_distribution() {
+        let packet = |interface: &str, size, transport| {
+            let mut packet = NetworkPacket::new(interface.to_string(), size, PacketProtocol::IPv4, PacketDirection::Inbound);
+            packet.transport_protocol = transport;
+            packet
+        };
+        let mut sizes = PacketSizeDistribution::default();
+        for size in [60, 64, 66, 1514] {
+            sizes.add_packet(&packet("eth0", size, TransportProtocol::Tcp));
+        }
+        sizes.add_packet(&packet("eth0", 9014, TransportProtocol::Udp));
+        sizes.add_packet(&packet("wlan0", 120, TransportProtocol::Icmp));
+        let earlier = sizes.clone();
+        sizes.add_packet(&packet("eth0", 60, TransportProtocol::Tcp));
+
+        assert_eq!(sizes.total().counts(), [3, 2, 1, 1]);
+        assert_eq!(
+            sizes.by_interface(),
+            vec![
+                ("eth0".to_string(), PacketSizeHistogram { tiny: 3, small: 1, large: 1, jumbo: 1 }),
+                ("wlan0".to_string(), PacketSizeHistogram { small: 1, ..Default::default() }),
+            ]
+        );
+        let protocols: Vec<_> = sizes.by_protocol().into_iter().map(|(protocol, h)| (protocol, h.total())).collect();
+        assert_eq!(protocols, vec![("ICMP".to_string(), 1), ("TCP".to_string(), 5), ("UDP".to_string(), 1)]);
+        assert_eq!(sizes.by_interface()[0].1.tiny_share(), 0.5);
+
+        // Only what came after the snapshot
+        let added = sizes.since(&earlier);
+        assert_eq!(added.total(), PacketSizeHistogram { tiny: 1, ..Default::default() });
+        assert_eq!(added.by_protocol().len(), 1);
+
+        // A sampled packet stands for several
+        assert!(!sizes.total().small_packet_overload());
+        sizes.add_packets(&packet("eth0", 54, TransportProtocol::Tcp), 2000);
+        assert!(sizes.total().small_packet_overload());
+    }
+
     #[test]
     fn test_connection_string() {
         use std::net::{IpAddr, Ipv4Addr};
@@ -0,0 +1,137 @@
+// Hex and ASCII dumps of captured packets for `kw packets --inspect`
+// The first bytes of each frame are kept in memory only, never stored, and shown sixteen to a
+// line as an offset, the bytes in hex and the printable ones as ASCII, like `tcpdump -X`
+
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+
+use crate::models::NetworkPacket;
+
+/// Leading bytes of each frame kept for inspection: the headers and the start of the payload
+pub const INSPECT_BYTES: usize = 256;
+
+/// Shown wherever packet contents are
+pub const PRIVACY_WARNING: &str =
+    "Packet contents are shown as captured: passwords, cookies or messages sent unencrypted can appear";
+
+/// Bytes per dump line
+const BYTES_PER_LINE: usize = 16;
+
+/// A captured packet kept for the inspector
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectedPacket {
+    pub timestamp: DateTime<Local>,
+    /// Protocol and endpoints, e.g. "Tcp 10.0.0.5:51234 → 93.184.216.34:443/https"
+    pub summary: String,
+    /// Length of the frame on the wire
+    pub wire_len: u64,
+    /// Up to `INSPECT_BYTES` of the frame from its link-layer header; empty when the capture
+    /// source delivers packets already decoded
+    pub bytes: Vec<u8>,
+}
+
+impl InspectedPacket {
+    pub fn new(packet: &NetworkPacket, summary: String) -> Self {
+        Self { timestamp: packet.timestamp, summary, wire_len: packet.size_bytes, bytes: packet.raw.clone() }
+    }
+
+    /// Whether fewer bytes were kept than the frame had
+    pub fn is_truncated(&self) -> bool {
+        (self.bytes.len() as u64) < self.wire_len
+    }
+
+    /// How much of the frame the dump covers, e.g. "256 of 1514 bytes"
+    pub fn coverage(&self) -> String {
+        if self.bytes.is_empty() {
+            format!("{} bytes, contents not captured", self.wire_len)
+        } else if self.is_truncated() {
+            format!("{} of {} bytes", self.bytes.len(), self.wire_len)
+        } else {
+            format!("{} bytes", self.wire_len)
+        }
+    }
+}
+
+/// The most recent captured packets, oldest first, up to a limit
+#[derive(Debug)]
+pub struct InspectBuffer {
+    packets: VecDeque<InspectedPacket>,
+    capacity: usize,
+}
+
+impl InspectBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { packets: VecDeque::with_capacity(capacity.min(1024)), capacity: capacity.max(1) }
+    }
+
+    /// Keeps `packet`, letting go of the oldest once full
+    pub fn push(&mut self, packet: InspectedPacket) {
+        if self.packets.len() == self.capacity {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(packet);
+    }
+
+    /// The packets kept, leaving the buffer empty
+    pub fn take(&mut self) -> Vec<InspectedPacket> {
+        self.packets.drain(..).collect()
+    }
+}
+
+/// Lines of `bytes` as "0010  45 00 00 3c 1c 46 40 00  40 06 b1 e6 ac 10 0a 63  E..<.F@.@......c"
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let mut hex = String::with_capacity(BYTES_PER_LINE * 3 + 1);
+            for index in 0..BYTES_PER_LINE {
+                if index == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(index) {
+                    Some(byte) => hex.push_str(&format!("{byte:02x} ")),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii: String =
+                chunk.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+            format!("{:04x}  {hex} {ascii}", line * BYTES_PER_LINE)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PacketDirection, PacketProtocol};
+
+    #[test]
+    fn test_hex_dump_lines() {
+        let bytes: Vec<u8> = (0x41..0x41 + 20).collect();
+        let lines = hex_dump(&bytes);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "0000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  ABCDEFGHIJKLMNOP");
+        assert!(lines[1].starts_with("0010  51 52 53 54    "));
+        assert!(lines[1].ends_with(" QRST"));
+        assert_eq!(hex_dump(&[0x00, 0x7f, b'a']), vec![format!("0000  00 7f 61 {} ..a", " ".repeat(3 * 13 + 1))]);
+        assert!(hex_dump(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_buffer_keeps_the_latest_packets() {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 1514, PacketProtocol::IPv4, PacketDirection::Inbound);
+        packet.raw = vec![0; INSPECT_BYTES];
+        let mut buffer = InspectBuffer::new(2);
+        for summary in ["first", "second", "third"] {
+            buffer.push(InspectedPacket::new(&packet, summary.to_string()));
+        }
+        let kept = buffer.take();
+        assert_eq!(kept.iter().map(|p| p.summary.as_str()).collect::<Vec<_>>(), vec!["second", "third"]);
+        assert!(buffer.take().is_empty());
+        assert_eq!(kept[0].coverage(), "256 of 1514 bytes");
+
+        packet.raw.clear();
+        assert_eq!(InspectedPacket::new(&packet, String::new()).coverage(), "1514 bytes, contents not captured");
+    }
+}
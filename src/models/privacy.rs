@@ -0,0 +1,222 @@
+// Privacy mode: IP addresses and host names redacted in output
+// With `--privacy` or `[privacy] mode`, addresses and names in console output, exports and
+// graphs are replaced by a short keyed hash ("ip-3fa2c1", "host-8b1e0d") or cut down to their
+// network and domain ("192.168.x.x", "*.example.com"), so screenshots and reports can be shared
+// without giving away internal addressing. Stored data is left as captured
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+
+/// Hex digits of the hash shown in place of an address or name
+const HASH_DIGITS: usize = 6;
+
+/// How addresses and host names are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyMode {
+    /// As they are
+    #[default]
+    Off,
+    /// A short hash, the same for the same address or name throughout the output
+    Hash,
+    /// The network and domain only: the first two octets of IPv4 addresses, the first 32 bits
+    /// of IPv6 ones and the last two labels of host names
+    Truncate,
+}
+
+impl PrivacyMode {
+    /// Maps a `--privacy` value to a mode
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "hash" => Ok(Self::Hash),
+            "truncate" => Ok(Self::Truncate),
+            _ => Err(anyhow::anyhow!("Invalid privacy mode: {} (expected hash, truncate or off)", value)),
+        }
+    }
+}
+
+/// Rewrites addresses and host names for output as the privacy mode asks
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    mode: PrivacyMode,
+    /// Mixed into every hash, so a hashed IPv4 address cannot be found by hashing them all
+    key: Vec<u8>,
+}
+
+impl Redactor {
+    /// Redacts with `mode`; hashes are keyed with `salt`, or with a key made up for this run
+    /// when there is none, so the same address hashes alike across runs only with a salt
+    pub fn new(mode: PrivacyMode, salt: Option<&str>) -> Self {
+        let key = match salt {
+            Some(salt) => salt.as_bytes().to_vec(),
+            // The standard library seeds every RandomState at random
+            None => RandomState::new().build_hasher().finish().to_le_bytes().to_vec(),
+        };
+        Self { mode, key }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mode != PrivacyMode::Off
+    }
+
+    pub fn address(&self, address: IpAddr) -> String {
+        match self.mode {
+            PrivacyMode::Off => address.to_string(),
+            PrivacyMode::Hash => match address {
+                IpAddr::V4(_) => format!("ip-{}", self.hash(&address.to_string())),
+                IpAddr::V6(_) => format!("ip6-{}", self.hash(&address.to_string())),
+            },
+            PrivacyMode::Truncate => match address {
+                IpAddr::V4(v4) => {
+                    let [a, b, _, _] = v4.octets();
+                    format!("{a}.{b}.x.x")
+                }
+                IpAddr::V6(v6) => {
+                    let segments = v6.segments();
+                    format!("{:x}:{:x}:…", segments[0], segments[1])
+                }
+            },
+        }
+    }
+
+    pub fn host_name(&self, name: &str) -> String {
+        match self.mode {
+            PrivacyMode::Off => name.to_string(),
+            PrivacyMode::Hash => format!("host-{}", self.hash(&name.to_lowercase())),
+            PrivacyMode::Truncate => {
+                let labels: Vec<&str> = name.trim_end_matches('.').split('.').collect();
+                match labels.len() {
+                    0 | 1 => "*".to_string(),
+                    2 => format!("*.{}", labels[1]),
+                    n => format!("*.{}.{}", labels[n - 2], labels[n - 1]),
+                }
+            }
+        }
+    }
+
+    /// An address, `ip:port` or `[ip]:port` redacted, keeping the port; anything else, such as
+    /// an interface name, unchanged
+    pub fn target<'a>(&self, target: &'a str) -> Cow<'a, str> {
+        if !self.is_active() {
+            return Cow::Borrowed(target);
+        }
+        let unbracketed = target.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(address) = unbracketed.parse::<IpAddr>() {
+            return Cow::Owned(self.address(address));
+        }
+        if let Ok(socket) = target.parse::<SocketAddr>() {
+            return Cow::Owned(self.with_port(socket.ip(), &socket.port().to_string()));
+        }
+        // ip:port followed by a service name, e.g. "10.0.0.5:443/https"
+        if let Some((address, port)) = target.rsplit_once(':')
+            && let Ok(address) = address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()
+            && port.split('/').next().is_some_and(|port| port.parse::<u16>().is_ok())
+        {
+            return Cow::Owned(self.with_port(address, port));
+        }
+        Cow::Borrowed(target)
+    }
+
+    /// `address:port`, bracketing a redacted address that still has colons in it
+    fn with_port(&self, address: IpAddr, port: &str) -> String {
+        let address = self.address(address);
+        if address.contains(':') { format!("[{address}]:{port}") } else { format!("{address}:{port}") }
+    }
+
+    /// `text` with every IP address in it redacted, for free text such as event descriptions
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.is_active() {
+            return Cow::Borrowed(text);
+        }
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(is_address_char) {
+            redacted.push_str(&rest[..start]);
+            let run = &rest[start..];
+            let end = run.find(|c: char| !is_address_char(c)).unwrap_or(run.len());
+            // A sentence may end straight after an address, and a port may follow one
+            let candidate = run[..end].trim_end_matches(['.', ':']);
+            let (address, port) = match candidate.parse::<IpAddr>() {
+                Ok(address) => (Some(address), ""),
+                Err(_) => match candidate.rsplit_once(':') {
+                    Some((address, _)) if address.contains('.') => {
+                        (address.parse::<IpAddr>().ok(), &candidate[address.len()..])
+                    }
+                    _ => (None, ""),
+                },
+            };
+            match address {
+                Some(address) if candidate.contains(|c: char| c.is_ascii_digit()) => {
+                    redacted.push_str(&self.address(address));
+                    redacted.push_str(port);
+                    rest = &run[candidate.len()..];
+                }
+                _ => {
+                    redacted.push_str(&run[..end]);
+                    rest = &run[end..];
+                }
+            }
+        }
+        redacted.push_str(rest);
+        Cow::Owned(redacted)
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.key);
+        hasher.update(value.as_bytes());
+        let digest = format!("{:x}", hasher.finalize());
+        digest[..HASH_DIGITS].to_string()
+    }
+}
+
+fn is_address_char(c: char) -> bool {
+    c.is_ascii_hexdigit() || c == '.' || c == ':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_are_stable_for_a_salt() {
+        let redactor = Redactor::new(PrivacyMode::Hash, Some("shared"));
+        let address: IpAddr = "192.168.1.34".parse().unwrap();
+        let hashed = redactor.address(address);
+        assert!(hashed.starts_with("ip-") && hashed.len() == 3 + HASH_DIGITS);
+        assert_eq!(Redactor::new(PrivacyMode::Hash, Some("shared")).address(address), hashed);
+        assert_ne!(Redactor::new(PrivacyMode::Hash, Some("other")).address(address), hashed);
+        assert!(redactor.address("fe80::1".parse().unwrap()).starts_with("ip6-"));
+        assert_eq!(redactor.host_name("NAS.lan"), redactor.host_name("nas.lan"));
+        assert_eq!(redactor.target("eth0"), "eth0");
+        assert_eq!(redactor.target("192.168.1.34:443/https"), format!("{hashed}:443/https"));
+
+        let off = Redactor::default();
+        assert!(!off.is_active());
+        assert_eq!(off.address(address), "192.168.1.34");
+        assert_eq!(off.text("from 10.0.0.1"), "from 10.0.0.1");
+    }
+
+    #[test]
+    fn test_truncate_keeps_network_and_domain() {
+        let redactor = Redactor::new(PrivacyMode::Truncate, None);
+        assert_eq!(redactor.address("192.168.1.34".parse().unwrap()), "192.168.x.x");
+        assert_eq!(redactor.address("2001:db8::1".parse().unwrap()), "2001:db8:…");
+        assert_eq!(redactor.host_name("printer.office.example.com"), "*.example.com");
+        assert_eq!(redactor.host_name("nas.lan"), "*.lan");
+        assert_eq!(redactor.host_name("localhost"), "*");
+        assert_eq!(redactor.target("[2001:db8::1]:443"), "[2001:db8:…]:443");
+        assert_eq!(redactor.target("10.1.2.3:51000"), "10.1.x.x:51000");
+        assert_eq!(
+            redactor.text("Port scan from 10.1.2.3 to 10.1.9.9:22, see fe80::1. At 12:30:45 v1.2"),
+            "Port scan from 10.1.x.x to 10.1.x.x:22, see fe80:0:…. At 12:30:45 v1.2"
+        );
+        assert!(PrivacyMode::parse("Hash").is_ok());
+        assert!(PrivacyMode::parse("blur").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+// Service names for ports: a built-in table of well-known assignments plus user overrides
+// Lets connection listings read "203.0.113.7:443/https" instead of a bare port number
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+use super::packet::TransportProtocol;
+
+/// Transport a built-in service is registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Registered {
+    Tcp,
+    Udp,
+    Both,
+}
+
+use Registered::{Both, Tcp, Udp};
+
+/// Well-known ports, following the IANA service names where they read naturally and
+/// adding common unofficial assignments such as WireGuard and Tailscale
+const WELL_KNOWN_SERVICES: &[(u16, Registered, &str)] = &[
+    (20, Tcp, "ftp-data"),
+    (21, Tcp, "ftp"),
+    (22, Tcp, "ssh"),
+    (23, Tcp, "telnet"),
+    (25, Tcp, "smtp"),
+    (53, Both, "dns"),
+    (67, Udp, "dhcp"),
+    (68, Udp, "dhcp"),
+    (69, Udp, "tftp"),
+    (80, Tcp, "http"),
+    (88, Both, "kerberos"),
+    (110, Tcp, "pop3"),
+    (119, Tcp, "nntp"),
+    (123, Udp, "ntp"),
+    (135, Tcp, "msrpc"),
+    (137, Udp, "netbios-ns"),
+    (138, Udp, "netbios-dgm"),
+    (139, Tcp, "netbios-ssn"),
+    (143, Tcp, "imap"),
+    (161, Udp, "snmp"),
+    (162, Udp, "snmptrap"),
+    (179, Tcp, "bgp"),
+    (389, Both, "ldap"),
+    (443, Tcp, "https"),
+    (443, Udp, "quic"),
+    (445, Tcp, "microsoft-ds"),
+    (465, Tcp, "submissions"),
+    (500, Udp, "isakmp"),
+    (514, Udp, "syslog"),
+    (515, Tcp, "printer"),
+    (546, Udp, "dhcpv6-client"),
+    (547, Udp, "dhcpv6-server"),
+    (587, Tcp, "submission"),
+    (631, Both, "ipp"),
+    (636, Tcp, "ldaps"),
+    (853, Both, "dns-over-tls"),
+    (873, Tcp, "rsync"),
+    (989, Tcp, "ftps-data"),
+    (990, Tcp, "ftps"),
+    (993, Tcp, "imaps"),
+    (995, Tcp, "pop3s"),
+    (1194, Both, "openvpn"),
+    (1433, Tcp, "ms-sql"),
+    (1701, Udp, "l2tp"),
+    (1723, Tcp, "pptp"),
+    (1812, Udp, "radius"),
+    (1883, Tcp, "mqtt"),
+    (1900, Udp, "ssdp"),
+    (2049, Both, "nfs"),
+    (3306, Tcp, "mysql"),
+    (3389, Both, "rdp"),
+    (3478, Udp, "stun"),
+    (4500, Udp, "ipsec-nat-t"),
+    (5060, Both, "sip"),
+    (5061, Tcp, "sips"),
+    (5222, Tcp, "xmpp-client"),
+    (5353, Udp, "mdns"),
+    (5355, Udp, "llmnr"),
+    (5432, Tcp, "postgresql"),
+    (5672, Tcp, "amqp"),
+    (5900, Tcp, "vnc"),
+    (6379, Tcp, "redis"),
+    (6443, Tcp, "kubernetes"),
+    (8080, Tcp, "http-alt"),
+    (8443, Tcp, "https-alt"),
+    (8883, Tcp, "mqtts"),
+    (9100, Tcp, "jetdirect"),
+    (9200, Tcp, "elasticsearch"),
+    (11211, Both, "memcached"),
+    (27017, Tcp, "mongodb"),
+    (41641, Udp, "tailscale"),
+    (51820, Udp, "wireguard"),
+];
+
+/// Port-to-service lookup: user overrides from `[services.ports]` first, then the built-in table
+///
+/// An override keyed `"port"` applies to every transport, `"port/tcp"` or `"port/udp"` to one.
+/// Packets of other transports (and connections whose transport is unknown) match any entry.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceMap {
+    overrides: HashMap<(u16, Option<TransportProtocol>), String>,
+}
+
+impl ServiceMap {
+    /// Built-in table only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Built-in table with `overrides` taking precedence, e.g. `"8443/tcp" = "unifi"`
+    pub fn from_overrides(overrides: &HashMap<String, String>) -> Result<Self> {
+        let mut map = Self::new();
+        for (key, name) in overrides {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("Service name for port {key:?} is empty");
+            }
+            let (port, transport) = match key.split_once('/') {
+                Some((port, transport)) => (port, Some(parse_transport(transport)?)),
+                None => (key.as_str(), None),
+            };
+            let port: u16 = port.trim().parse().with_context(|| format!("Invalid port {key:?}"))?;
+            map.overrides.insert((port, transport), name.to_string());
+        }
+        Ok(map)
+    }
+
+    /// Service name of `port` over `transport`, if it has one
+    pub fn name(&self, port: u16, transport: TransportProtocol) -> Option<&str> {
+        let wanted = match transport {
+            TransportProtocol::Tcp | TransportProtocol::Udp => Some(transport),
+            _ => None,
+        };
+        if let Some(name) = self.overrides.get(&(port, wanted)).or_else(|| self.overrides.get(&(port, None))) {
+            return Some(name);
+        }
+        if wanted.is_none()
+            && let Some(((_, _), name)) = self.overrides.iter().find(|((p, _), _)| *p == port)
+        {
+            return Some(name);
+        }
+        WELL_KNOWN_SERVICES
+            .iter()
+            .find(|(p, registered, _)| {
+                *p == port
+                    && match (registered, transport) {
+                        (Both, _) => true,
+                        (Tcp, TransportProtocol::Tcp) | (Udp, TransportProtocol::Udp) => true,
+                        (_, TransportProtocol::Tcp | TransportProtocol::Udp) => false,
+                        _ => true,
+                    }
+            })
+            .map(|(_, _, name)| *name)
+    }
+
+    /// `port` with its service name appended, e.g. "443/https", or just "50322"
+    pub fn label(&self, port: u16, transport: TransportProtocol) -> String {
+        match self.name(port, transport) {
+            Some(name) => format!("{port}/{name}"),
+            None => port.to_string(),
+        }
+    }
+
+    /// Service a connection belongs to: the destination port's, or the source port's for
+    /// replies sent from a service port back to an ephemeral one
+    pub fn connection_service(
+        &self,
+        source_port: Option<u16>,
+        dest_port: Option<u16>,
+        transport: TransportProtocol,
+    ) -> Option<&str> {
+        dest_port
+            .and_then(|port| self.name(port, transport))
+            .or_else(|| source_port.and_then(|port| self.name(port, transport)))
+    }
+}
+
+/// Transport named by a stored or configured protocol string ("tcp", "Tcp", "UDP", ...)
+pub fn transport_from_name(name: &str) -> TransportProtocol {
+    match name.to_ascii_lowercase().as_str() {
+        "tcp" => TransportProtocol::Tcp,
+        "udp" => TransportProtocol::Udp,
+        "icmp" => TransportProtocol::Icmp,
+        "icmpv6" => TransportProtocol::ICMPv6,
+        _ => TransportProtocol::Other(0),
+    }
+}
+
+fn parse_transport(transport: &str) -> Result<TransportProtocol> {
+    match transport_from_name(transport.trim()) {
+        transport @ (TransportProtocol::Tcp | TransportProtocol::Udp) => Ok(transport),
+        _ => bail!("Unknown transport {transport:?}, expected tcp or udp"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_names_depend_on_transport() {
+        let services = ServiceMap::new();
+        assert_eq!(services.name(443, TransportProtocol::Tcp), Some("https"));
+        assert_eq!(services.name(443, TransportProtocol::Udp), Some("quic"));
+        assert_eq!(services.name(53, TransportProtocol::Udp), Some("dns"));
+        assert_eq!(services.name(51820, TransportProtocol::Udp), Some("wireguard"));
+        assert_eq!(services.name(51820, TransportProtocol::Tcp), None);
+        assert_eq!(services.name(51820, TransportProtocol::Other(0)), Some("wireguard"));
+        assert_eq!(services.label(443, TransportProtocol::Tcp), "443/https");
+        assert_eq!(services.label(50322, TransportProtocol::Tcp), "50322");
+    }
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let overrides = HashMap::from([
+            ("8443/tcp".to_string(), "unifi".to_string()),
+            ("9999".to_string(), "backup".to_string()),
+            ("443/udp".to_string(), "http3".to_string()),
+        ]);
+        let services = ServiceMap::from_overrides(&overrides).unwrap();
+        assert_eq!(services.name(8443, TransportProtocol::Tcp), Some("unifi"));
+        assert_eq!(services.name(9999, TransportProtocol::Udp), Some("backup"));
+        assert_eq!(services.name(443, TransportProtocol::Udp), Some("http3"));
+        assert_eq!(services.name(443, TransportProtocol::Tcp), Some("https"));
+    }
+
+    #[test]
+    fn test_invalid_overrides_are_rejected() {
+        for (key, name) in [("https", "web"), ("70000", "big"), ("22/sctp", "ssh"), ("22", " ")] {
+            let overrides = HashMap::from([(key.to_string(), name.to_string())]);
+            assert!(ServiceMap::from_overrides(&overrides).is_err(), "{key} = {name:?}");
+        }
+    }
+
+    #[test]
+    fn test_connection_service_prefers_the_destination() {
+        let services = ServiceMap::new();
+        assert_eq!(services.connection_service(Some(50000), Some(22), TransportProtocol::Tcp), Some("ssh"));
+        assert_eq!(services.connection_service(Some(443), Some(50000), TransportProtocol::Tcp), Some("https"));
+        assert_eq!(services.connection_service(Some(50000), Some(50001), TransportProtocol::Tcp), None);
+    }
+}
@@ -0,0 +1,235 @@
+// User tags: friendly names and notes for devices, interfaces and remote hosts
+// Lets output read "192.168.1.34 (kid's tablet)" instead of a bare address
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::models::Redactor;
+
+/// What a tag is attached to, inferred from how the target is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TagKind {
+    /// An IP address, local or remote
+    Host,
+    /// A hardware (MAC) address
+    Device,
+    /// A network interface name such as `eth0`
+    Interface,
+}
+
+impl TagKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagKind::Host => "host",
+            TagKind::Device => "device",
+            TagKind::Interface => "interface",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "host" => TagKind::Host,
+            "device" => TagKind::Device,
+            _ => TagKind::Interface,
+        }
+    }
+}
+
+impl fmt::Display for TagKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A user-supplied name and optional note for one target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    /// Normalized address or interface name
+    pub target: String,
+    pub kind: TagKind,
+    pub label: String,
+    pub note: Option<String>,
+    pub updated_at: DateTime<Local>,
+}
+
+impl Tag {
+    pub fn new(target: &str, label: impl Into<String>, note: Option<String>) -> Self {
+        let (target, kind) = normalize_target(target);
+        Self {
+            target,
+            kind,
+            label: label.into(),
+            note,
+            updated_at: Local::now(),
+        }
+    }
+}
+
+/// Canonical spelling of a tag target, so `AA-BB-...` and `aa:bb:...` MACs, or
+/// `[::1]` and `::1`, find the same tag
+pub fn normalize_target(target: &str) -> (String, TagKind) {
+    let trimmed = target.trim();
+    let unbracketed = trimmed.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return (ip.to_string(), TagKind::Host);
+    }
+    if let Some(mac) = parse_mac(trimmed) {
+        return (mac, TagKind::Device);
+    }
+    (trimmed.to_string(), TagKind::Interface)
+}
+
+/// Lower-case, colon-separated form of a MAC written with `:` or `-`
+fn parse_mac(text: &str) -> Option<String> {
+    let octets: Vec<&str> = text.split([':', '-']).collect();
+    let valid = octets.len() == 6
+        && octets.iter().all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()));
+    valid.then(|| octets.join(":").to_lowercase())
+}
+
+/// All tags, looked up by target when printing addresses and interface names
+/// Addresses and host names printed through the book are redacted in privacy mode; user
+/// labels are kept, as the user chose them, but the names devices announced are not shown
+#[derive(Debug, Clone, Default)]
+pub struct TagBook {
+    tags: HashMap<String, Tag>,
+    /// Labels of devices that announced themselves over mDNS or SSDP, by address;
+    /// shown for addresses the user has not tagged
+    discovered: HashMap<String, String>,
+    redactor: Redactor,
+}
+
+impl TagBook {
+    pub fn new(tags: Vec<Tag>) -> Self {
+        Self {
+            tags: tags.into_iter().map(|tag| (tag.target.clone(), tag)).collect(),
+            discovered: HashMap::new(),
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Redacts the addresses and host names the book prints
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// An address as output shows it, which is redacted in privacy mode
+    pub fn address(&self, address: IpAddr) -> String {
+        self.redactor.address(address)
+    }
+
+    /// Adds or replaces the label of a discovered device, e.g. "Office Printer, printer"
+    pub fn add_discovered(&mut self, address: IpAddr, label: String) {
+        self.discovered.insert(address.to_string(), label);
+    }
+
+    pub fn get(&self, target: &str) -> Option<&Tag> {
+        self.tags
+            .get(target)
+            .or_else(|| self.tags.get(&normalize_target(target).0))
+    }
+
+    /// The user's tag for `target`, or else the name it announced on the LAN
+    pub fn label(&self, target: &str) -> Option<&str> {
+        self.get(target).map(|tag| tag.label.as_str()).or_else(|| {
+            let announced = self.discovered.get(target).map(String::as_str);
+            announced.filter(|_| !self.redactor.is_active())
+        })
+    }
+
+    /// `target (label)`, or just `target` when it has no tag
+    pub fn describe(&self, target: &str) -> String {
+        self.describe_with(target, None)
+    }
+
+    /// Like `describe`, adding a resolved host name after the label
+    pub fn describe_with(&self, target: &str, host_name: Option<&str>) -> String {
+        let host_name = host_name.map(|host| self.redactor.host_name(host));
+        let label = self.label(target);
+        let target = self.redactor.target(target);
+        match (label, host_name) {
+            (Some(label), Some(host)) => format!("{target} ({label}, {host})"),
+            (Some(label), None) => format!("{target} ({label})"),
+            (None, Some(host)) => format!("{target} ({host})"),
+            (None, None) => target.to_string(),
+        }
+    }
+
+    /// `describe` for `ip:port` endpoints, looking up the tag of the address part
+    pub fn describe_endpoint(&self, endpoint: &str) -> String {
+        let address = endpoint
+            .parse::<SocketAddr>()
+            .map(|socket| socket.ip().to_string())
+            .unwrap_or_else(|_| endpoint.to_string());
+        let label = self.label(&address);
+        let endpoint = self.redactor.target(endpoint);
+        match label {
+            Some(label) => format!("{endpoint} ({label})"),
+            None => endpoint.into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets_are_normalized() {
+        assert_eq!(normalize_target(" 192.168.1.34 "), ("192.168.1.34".to_string(), TagKind::Host));
+        assert_eq!(normalize_target("[::1]"), ("::1".to_string(), TagKind::Host));
+        assert_eq!(
+            normalize_target("AA-BB-CC-00-11-22"),
+            ("aa:bb:cc:00:11:22".to_string(), TagKind::Device)
+        );
+        assert_eq!(normalize_target("wlan0"), ("wlan0".to_string(), TagKind::Interface));
+    }
+
+    #[test]
+    fn test_describe() {
+        let book = TagBook::new(vec![
+            Tag::new("192.168.1.34", "kid's tablet", None),
+            Tag::new("AA:BB:CC:00:11:22", "printer", Some("upstairs".to_string())),
+        ]);
+        assert_eq!(book.describe("192.168.1.34"), "192.168.1.34 (kid's tablet)");
+        assert_eq!(book.describe("10.0.0.1"), "10.0.0.1");
+        assert_eq!(book.describe("aa-bb-cc-00-11-22"), "aa-bb-cc-00-11-22 (printer)");
+        assert_eq!(
+            book.describe_with("192.168.1.34", Some("tablet.lan")),
+            "192.168.1.34 (kid's tablet, tablet.lan)"
+        );
+        assert_eq!(book.describe_endpoint("192.168.1.34:51000"), "192.168.1.34:51000 (kid's tablet)");
+        assert_eq!(book.describe_endpoint("example.org:443"), "example.org:443");
+    }
+
+    #[test]
+    fn test_discovered_labels_yield_to_tags() {
+        let mut book = TagBook::new(vec![Tag::new("192.168.1.34", "kid's tablet", None)]);
+        book.add_discovered("192.168.1.34".parse().unwrap(), "iPad, phone".to_string());
+        book.add_discovered("192.168.1.40".parse().unwrap(), "Office Printer, printer".to_string());
+        assert_eq!(book.describe("192.168.1.34"), "192.168.1.34 (kid's tablet)");
+        assert_eq!(book.describe("192.168.1.40"), "192.168.1.40 (Office Printer, printer)");
+    }
+
+    #[test]
+    fn test_privacy_keeps_user_labels_only() {
+        use crate::models::PrivacyMode;
+
+        let mut book = TagBook::new(vec![Tag::new("192.168.1.34", "kid's tablet", None)])
+            .with_redactor(Redactor::new(PrivacyMode::Truncate, None));
+        book.add_discovered("192.168.1.40".parse().unwrap(), "Office Printer, printer".to_string());
+        assert_eq!(book.describe("192.168.1.34"), "192.168.x.x (kid's tablet)");
+        assert_eq!(book.describe("192.168.1.40"), "192.168.x.x");
+        assert_eq!(book.describe_with("10.0.0.8", Some("nas.office.lan")), "10.0.x.x (*.office.lan)");
+        assert_eq!(book.describe_endpoint("192.168.1.34:51000"), "192.168.x.x:51000 (kid's tablet)");
+        assert_eq!(book.describe("wlan0"), "wlan0");
+    }
+}
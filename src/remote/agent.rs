@@ -0,0 +1,283 @@
+// `kw agent`: reads interface speeds every interval and streams them to the hub, with the
+// protocols `kw service run` captured on this machine in the same period when its packet
+// database is there. Reports made while the hub is unreachable are dropped; the agent
+// reconnects with a growing delay and carries on from the next reading
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, info, warn};
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::http::Uri;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+
+use super::proto::hub_client::HubClient;
+use super::proto::{AgentReport, InterfaceReading, ProtocolSummary};
+use super::{bearer, AGENT_SEPARATOR, DEFAULT_PORT};
+use crate::collectors::collector_service::{BandwidthSnapshot, CollectorService};
+use crate::collectors::BandwidthCollector;
+use crate::config::{DataDir, RemoteConfig};
+use crate::storage::PacketStorage;
+
+/// Reports queued while the hub is slow to read them; newer ones are dropped beyond this
+const QUEUED_REPORTS: usize = 8;
+
+/// First wait before reconnecting, doubled after each failure up to `MAX_RECONNECT_DELAY`
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Streams this machine's readings to a hub
+pub struct Agent {
+    hub: Option<String>,
+    name: String,
+    interval: Duration,
+    token: Option<String>,
+    ca_cert: Option<PathBuf>,
+    tls_domain: Option<String>,
+    database: PathBuf,
+}
+
+impl Agent {
+    /// An agent reading the captured protocols from the packet database of `data`
+    pub fn new(config: &RemoteConfig, data: &DataDir) -> Self {
+        Self {
+            hub: config.hub.clone(),
+            name: config.name.clone().unwrap_or_else(default_name),
+            interval: Duration::from_secs(config.interval_secs.max(1)),
+            token: config.token.clone().filter(|token| !token.is_empty()),
+            ca_cert: config.ca_cert.clone(),
+            tls_domain: config.tls_domain.clone(),
+            database: data.database(),
+        }
+    }
+
+    /// Hub to report to instead of `[remote] hub`
+    pub fn with_hub(mut self, hub: String) -> Self {
+        self.hub = Some(hub);
+        self
+    }
+
+    /// Name in the hub's listings instead of `[remote] name` or the host name
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Time between reports
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_secs(1));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reports until `shutdown` completes
+    pub async fn run<F: Future<Output = ()>>(self, shutdown: F) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(AGENT_SEPARATOR) {
+            bail!("The agent name must be non-empty and without '{AGENT_SEPARATOR}': {:?}", self.name);
+        }
+        let endpoint = self.endpoint().await?;
+        let authorization = self.token.as_deref().map(bearer).transpose()?;
+        let collector = BandwidthCollector::new().with_interface_watcher().with_power_watcher();
+        let bandwidth = CollectorService::start(collector, self.interval).await;
+        let mut snapshots = bandwidth.subscribe();
+        let mut protocols = ProtocolSource::new(self.database.clone());
+        let mut delay = RECONNECT_DELAY;
+        tokio::pin!(shutdown);
+
+        loop {
+            let connected = tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                connected = endpoint.connect() => connected,
+            };
+            match connected {
+                Ok(channel) => {
+                    info!("Reporting to {} as {}", endpoint.uri(), self.name);
+                    delay = RECONNECT_DELAY;
+                    let stream = self.stream(channel, authorization.clone(), &mut snapshots, &mut protocols);
+                    tokio::select! {
+                        _ = &mut shutdown => return Ok(()),
+                        result = stream => match result {
+                            Ok(reports) => warn!("The hub closed the stream after {reports} reports"),
+                            Err(e) => warn!("Reporting to {} failed: {}: {}", endpoint.uri(), e.code(), e.message()),
+                        },
+                    }
+                }
+                Err(e) => warn!("Cannot reach the hub at {}: {:#}", endpoint.uri(), anyhow::Error::new(e)),
+            }
+            info!("Reconnecting in {}s", delay.as_secs());
+            tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                _ = tokio::time::sleep(delay) => {}
+            }
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Sends a report for every reading until the stream fails; the number the hub acknowledged
+    async fn stream(
+        &self,
+        channel: Channel,
+        authorization: Option<MetadataValue<Ascii>>,
+        snapshots: &mut watch::Receiver<BandwidthSnapshot>,
+        protocols: &mut ProtocolSource,
+    ) -> Result<u64, tonic::Status> {
+        let mut client = HubClient::with_interceptor(channel, move |mut request: tonic::Request<()>| {
+            if let Some(authorization) = &authorization {
+                request.metadata_mut().insert("authorization", authorization.clone());
+            }
+            Ok(request)
+        });
+        let (reports, receiver) = mpsc::channel(QUEUED_REPORTS);
+        let call = client.report(ReceiverStream::new(receiver));
+        tokio::pin!(call);
+        snapshots.mark_unchanged();
+
+        loop {
+            tokio::select! {
+                result = &mut call => return result.map(|ack| ack.into_inner().reports),
+                changed = snapshots.changed() => {
+                    if changed.is_err() {
+                        return Err(tonic::Status::aborted("Bandwidth collection stopped"));
+                    }
+                    let snapshot = snapshots.borrow_and_update().clone();
+                    if let Some(error) = &snapshot.error {
+                        debug!("Skipping a failed reading: {error}");
+                        continue;
+                    }
+                    let now = Local::now();
+                    let report = report(&self.name, now, self.interval, &snapshot, protocols.since(now - self.interval));
+                    if reports.try_send(report).is_err() {
+                        debug!("The hub is behind; dropped a report");
+                    }
+                }
+            }
+        }
+    }
+
+    /// The hub's address with scheme and port, and the TLS settings it implies
+    async fn endpoint(&self) -> Result<Endpoint> {
+        let Some(hub) = self.hub.as_deref().map(str::trim).filter(|hub| !hub.is_empty()) else {
+            bail!("No hub to report to: pass --hub or set [remote] hub");
+        };
+        let uri = hub_uri(hub, self.ca_cert.is_some() || self.tls_domain.is_some())?;
+        let mut endpoint = Endpoint::from(uri.clone()).connect_timeout(CONNECT_TIMEOUT);
+        if uri.scheme_str() == Some("https") {
+            let mut tls = ClientTlsConfig::new();
+            tls = match &self.ca_cert {
+                Some(path) => {
+                    let pem = tokio::fs::read(path)
+                        .await
+                        .with_context(|| format!("Failed to read the CA certificate {}", path.display()))?;
+                    tls.ca_certificate(Certificate::from_pem(pem))
+                }
+                None => tls.with_native_roots(),
+            };
+            if let Some(domain) = &self.tls_domain {
+                tls = tls.domain_name(domain.clone());
+            }
+            endpoint = endpoint.tls_config(tls).context("Invalid TLS settings")?;
+        } else if self.token.is_some() {
+            warn!("The token is sent to {uri} unencrypted; give the hub a certificate and use https");
+        }
+        Ok(endpoint)
+    }
+}
+
+/// `host`, `host:port` or a URL, completed with a scheme (https when `tls`) and the default port
+fn hub_uri(hub: &str, tls: bool) -> Result<Uri> {
+    let hub = hub.trim_end_matches('/');
+    let url = if hub.contains("://") {
+        hub.to_string()
+    } else {
+        format!("{}://{hub}", if tls { "https" } else { "http" })
+    };
+    let uri: Uri = url.parse().with_context(|| format!("Invalid hub address {hub:?}"))?;
+    let (Some(scheme @ ("http" | "https")), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+        bail!("The hub address must be host[:port] or an http(s) URL, not {hub:?}");
+    };
+    if authority.port().is_some() {
+        return Ok(uri);
+    }
+    format!("{scheme}://{authority}:{DEFAULT_PORT}")
+        .parse()
+        .with_context(|| format!("Invalid hub address {hub:?}"))
+}
+
+fn report(
+    name: &str,
+    now: DateTime<Local>,
+    interval: Duration,
+    snapshot: &BandwidthSnapshot,
+    protocols: Vec<ProtocolSummary>,
+) -> AgentReport {
+    AgentReport {
+        agent: name.to_string(),
+        timestamp_ms: now.with_timezone(&Utc).timestamp_millis(),
+        interval_secs: interval.as_secs_f64(),
+        interfaces: snapshot.stats.iter().map(InterfaceReading::from).collect(),
+        protocols,
+    }
+}
+
+/// Protocols from the local packet database, opened once it exists
+struct ProtocolSource {
+    path: PathBuf,
+    storage: Option<PacketStorage>,
+}
+
+impl ProtocolSource {
+    fn new(path: PathBuf) -> Self {
+        Self { path, storage: None }
+    }
+
+    /// Protocols seen from `start` until now, busiest first; none without a packet database
+    fn since(&mut self, start: DateTime<Local>) -> Vec<ProtocolSummary> {
+        if self.storage.is_none() && self.path.is_file() {
+            self.storage = PacketStorage::open_read_only(&self.path)
+                .inspect_err(|e| warn!("Protocols are not reported: {e}"))
+                .ok();
+        }
+        let Some(storage) = &self.storage else {
+            return Vec::new();
+        };
+        match storage.get_usage_summary(start, Local::now()) {
+            Ok(usage) => usage
+                .applications
+                .into_iter()
+                .map(|(protocol, packets, bytes)| ProtocolSummary { protocol, packets, bytes })
+                .collect(),
+            Err(e) => {
+                debug!("Failed to summarize protocols: {e}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn default_name() -> String {
+    sysinfo::System::host_name().unwrap_or_else(|| "agent".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hub_uri() {
+        assert_eq!(hub_uri("hub.lan", false).unwrap().to_string(), "http://hub.lan:50051/");
+        assert_eq!(hub_uri("hub.lan:9000", true).unwrap().to_string(), "https://hub.lan:9000/");
+        assert_eq!(hub_uri("https://hub.lan/", false).unwrap().to_string(), "https://hub.lan:50051/");
+        assert_eq!(hub_uri("[::1]", false).unwrap().to_string(), "http://[::1]:50051/");
+        assert!(hub_uri("ftp://hub.lan", false).is_err());
+        assert!(hub_uri("", false).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+// `kw hub`: accepts report streams from agents, stores each interface's speeds in the
+// packet database as `<agent>/<interface>`, and lists the fleet every interval with each
+// machine's latest speeds and busiest protocols. Machines silent for three intervals are
+// shown as stale until they report again
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeZone};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::interval;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status, Streaming};
+
+use super::proto::hub_server::{Hub as HubService, HubServer};
+use super::proto::{AgentReport, ReportAck};
+use super::{authorize, AGENT_SEPARATOR};
+use crate::collectors::bandwidth::BandwidthStats;
+use crate::config::{data_dir, DataDir, RemoteConfig};
+use crate::storage::packet_storage::BandwidthSample;
+use crate::storage::PacketStorage;
+
+/// Intervals without a report after which a machine is shown as stale
+const STALE_INTERVALS: u32 = 3;
+
+/// Protocols listed per machine
+const LISTED_PROTOCOLS: usize = 3;
+
+/// Receives agents' reports
+pub struct Hub {
+    listen: String,
+    token: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    interval: Duration,
+    database: PathBuf,
+    batch_size: usize,
+    insecure: bool,
+}
+
+impl Hub {
+    /// A hub storing the agents' reports in the packet database of `data`
+    pub fn new(config: &RemoteConfig, data: &DataDir) -> Self {
+        Self {
+            listen: config.listen.clone(),
+            token: config.token.clone().filter(|token| !token.is_empty()),
+            tls_cert: config.tls_cert.clone(),
+            tls_key: config.tls_key.clone(),
+            interval: Duration::from_secs(config.interval_secs.max(1)),
+            database: data.database(),
+            batch_size: 100,
+            insecure: false,
+        }
+    }
+
+    /// Address to accept agents on instead of `[remote] listen`
+    pub fn with_listen(mut self, listen: String) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    /// Serves plain gRPC on a non-loopback address, token and reports in the clear
+    pub fn with_insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Serves agents until `shutdown` completes
+    pub async fn run<F: Future<Output = ()> + Send + 'static>(self, shutdown: F) -> Result<()> {
+        let address: SocketAddr = self
+            .listen
+            .parse()
+            .with_context(|| format!("Invalid hub listen address {:?}", self.listen))?;
+        let mut server = Server::builder();
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                let cert = tokio::fs::read(cert)
+                    .await
+                    .with_context(|| format!("Failed to read the hub certificate {}", cert.display()))?;
+                let key = tokio::fs::read(key)
+                    .await
+                    .with_context(|| format!("Failed to read the hub key {}", key.display()))?;
+                server = server
+                    .tls_config(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+                    .context("Invalid hub certificate or key")?;
+                true
+            }
+            (None, None) => false,
+            _ => bail!("[remote] tls_cert and tls_key must be set together"),
+        };
+        check_plaintext(address, tls, self.insecure)?;
+        if self.token.is_none() {
+            warn!("No [remote] token is set: any agent that reaches {address} can report");
+        }
+
+        if let Some(parent) = self.database.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            data_dir::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let storage = Arc::new(PacketStorage::new(&self.database, self.batch_size)?);
+        let fleet = Arc::new(Mutex::new(Fleet::default()));
+        let service = Reports {
+            storage: Arc::clone(&storage),
+            fleet: Arc::clone(&fleet),
+        };
+        let token = self.token.clone();
+        let service = HubServer::with_interceptor(service, move |request: Request<()>| {
+            authorize(token.as_deref(), request.metadata())?;
+            Ok(request)
+        });
+
+        info!("Hub listening on {address} ({})", if tls { "TLS" } else { "plain gRPC" });
+        println!("Waiting for agents on {address}; press Ctrl+C to stop");
+        let server = tokio::spawn(server.add_service(service).serve_with_shutdown(address, shutdown));
+        tokio::pin!(server);
+        let mut listing = interval(self.interval);
+        listing.tick().await;
+
+        let result = loop {
+            tokio::select! {
+                result = &mut server => break result,
+                _ = listing.tick() => print!("{}", fleet.lock().unwrap().render(self.interval * STALE_INTERVALS)),
+            }
+        };
+        storage.flush_all()?;
+        result
+            .context("The hub task panicked")?
+            .with_context(|| format!("Failed to serve agents on {address}"))
+    }
+}
+
+/// Refuses plain gRPC beyond this machine, where the token and the reports could be read
+/// on the way, unless asked for with `--insecure`
+fn check_plaintext(address: SocketAddr, tls: bool, insecure: bool) -> Result<()> {
+    if tls || address.ip().is_loopback() {
+        return Ok(());
+    }
+    if !insecure {
+        bail!(
+            "Refusing to accept agents on {address} without TLS, as the token and reports would travel in the clear; \
+             set [remote] tls_cert and tls_key, listen on a loopback address, or pass --insecure"
+        );
+    }
+    warn!("Accepting agents on {address} without TLS: the token and reports travel in the clear");
+    Ok(())
+}
+
+/// The gRPC service behind the hub
+struct Reports {
+    storage: Arc<PacketStorage>,
+    fleet: Arc<Mutex<Fleet>>,
+}
+
+#[tonic::async_trait]
+impl HubService for Reports {
+    async fn report(&self, request: Request<Streaming<AgentReport>>) -> Result<Response<ReportAck>, Status> {
+        let peer = request.remote_addr();
+        let mut stream = request.into_inner();
+        let mut reports = 0;
+        let mut agent = None;
+
+        while let Some(report) = stream.message().await? {
+            if report.agent.is_empty() || report.agent.contains(AGENT_SEPARATOR) {
+                return Err(Status::invalid_argument(format!(
+                    "The agent name must be non-empty and without '{AGENT_SEPARATOR}'"
+                )));
+            }
+            if agent.as_ref() != Some(&report.agent) {
+                info!("Agent {} connected from {}", report.agent, peer.map_or("?".to_string(), |peer| peer.to_string()));
+                agent = Some(report.agent.clone());
+            }
+            if let Err(e) = self.storage.store_bandwidth_samples(&samples(&report)) {
+                warn!("Failed to store the report of {}: {e}", report.agent);
+            }
+            self.fleet.lock().unwrap().record(report);
+            reports += 1;
+        }
+        if let Some(agent) = agent {
+            info!("Agent {agent} disconnected after {reports} reports");
+        }
+        Ok(Response::new(ReportAck { reports }))
+    }
+}
+
+/// Bandwidth samples of a report, named `<agent>/<interface>`
+fn samples(report: &AgentReport) -> Vec<BandwidthSample> {
+    let timestamp = Local
+        .timestamp_millis_opt(report.timestamp_ms)
+        .single()
+        .unwrap_or_else(Local::now);
+    report
+        .interfaces
+        .iter()
+        .map(|interface| BandwidthSample {
+            timestamp,
+            interface_name: format!("{}{AGENT_SEPARATOR}{}", report.agent, interface.name),
+            duration_secs: report.interval_secs,
+            download_bps: interface.download_bps,
+            upload_bps: interface.upload_bps,
+        })
+        .collect()
+}
+
+/// Latest report of every machine that has reported
+#[derive(Default)]
+struct Fleet {
+    machines: BTreeMap<String, Machine>,
+}
+
+struct Machine {
+    report: AgentReport,
+    received: Instant,
+    taken_at: DateTime<Local>,
+}
+
+impl Fleet {
+    fn record(&mut self, report: AgentReport) {
+        let taken_at = Local
+            .timestamp_millis_opt(report.timestamp_ms)
+            .single()
+            .unwrap_or_else(Local::now);
+        self.machines.insert(report.agent.clone(), Machine { report, received: Instant::now(), taken_at });
+    }
+
+    /// Speeds per machine and interface, fleet totals last
+    fn render(&self, stale_after: Duration) -> String {
+        if self.machines.is_empty() {
+            return "No agents have reported yet\n".to_string();
+        }
+        let mut out = format!("\n=== Fleet at {} ===\n", Local::now().format("%H:%M:%S"));
+        let (mut total_download, mut total_upload) = (0.0, 0.0);
+        for (name, machine) in &self.machines {
+            let stale = machine.received.elapsed() > stale_after;
+            let interfaces = || machine.report.interfaces.iter().filter(|i| i.state != "down");
+            let download: f64 = interfaces().map(|i| i.download_bps).sum();
+            let upload: f64 = interfaces().map(|i| i.upload_bps).sum();
+            if !stale {
+                total_download += download;
+                total_upload += upload;
+            }
+            out += &format!(
+                "{name}: ↓ {} ↑ {}  (last report {}{})\n",
+                BandwidthStats::format_speed(download),
+                BandwidthStats::format_speed(upload),
+                machine.taken_at.format("%H:%M:%S"),
+                if stale { ", stale" } else { "" }
+            );
+            for interface in interfaces().filter(|i| i.download_bps + i.upload_bps > 0.0) {
+                out += &format!(
+                    "  {:<16} ↓ {:>12} ↑ {:>12}\n",
+                    interface.name,
+                    BandwidthStats::format_speed(interface.download_bps),
+                    BandwidthStats::format_speed(interface.upload_bps)
+                );
+            }
+            let protocols: Vec<String> = machine
+                .report
+                .protocols
+                .iter()
+                .take(LISTED_PROTOCOLS)
+                .map(|p| format!("{} {}", p.protocol, BandwidthStats::format_bytes(p.bytes as f64)))
+                .collect();
+            if !protocols.is_empty() {
+                out += &format!("  Protocols: {}\n", protocols.join(", "));
+            }
+        }
+        out += &format!(
+            "Total ({} machine{}): ↓ {} ↑ {}\n",
+            self.machines.len(),
+            if self.machines.len() == 1 { "" } else { "s" },
+            BandwidthStats::format_speed(total_download),
+            BandwidthStats::format_speed(total_upload)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::proto::{InterfaceReading, ProtocolSummary};
+
+    fn report(agent: &str, download_bps: f64) -> AgentReport {
+        AgentReport {
+            agent: agent.to_string(),
+            timestamp_ms: 1_700_000_000_000,
+            interval_secs: 10.0,
+            interfaces: vec![
+                InterfaceReading { name: "eth0".to_string(), download_bps, upload_bps: 100.0, state: "up".to_string(), ..Default::default() },
+                InterfaceReading { name: "eth1".to_string(), download_bps: 5.0, state: "down".to_string(), ..Default::default() },
+            ],
+            protocols: vec![ProtocolSummary { protocol: "HTTPS".to_string(), packets: 10, bytes: 2048 }],
+        }
+    }
+
+    #[test]
+    fn test_plain_grpc_stays_on_loopback_unless_insecure() {
+        let public: SocketAddr = "0.0.0.0:50051".parse().unwrap();
+        assert!(check_plaintext(public, false, false).is_err());
+        assert!(check_plaintext(public, false, true).is_ok());
+        assert!(check_plaintext(public, true, false).is_ok());
+        assert!(check_plaintext("127.0.0.1:50051".parse().unwrap(), false, false).is_ok());
+        assert!(check_plaintext("[::1]:50051".parse().unwrap(), false, false).is_ok());
+    }
+
+    #[test]
+    fn test_samples_are_named_after_the_agent() {
+        let samples = samples(&report("nas", 1000.0));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].interface_name, "nas/eth0");
+        assert_eq!(samples[0].download_bps, 1000.0);
+        assert_eq!(samples[0].duration_secs, 10.0);
+        assert_eq!(samples[0].timestamp.timestamp_millis(), 1_700_000_000_000);
+    }
+
+    #[test]
+    fn test_fleet_keeps_the_latest_report_per_machine() {
+        let mut fleet = Fleet::default();
+        fleet.record(report("nas", 1000.0));
+        fleet.record(report("pi", 500.0));
+        fleet.record(report("nas", 2000.0));
+        assert_eq!(fleet.machines.len(), 2);
+        assert_eq!(fleet.machines["nas"].report.interfaces[0].download_bps, 2000.0);
+
+        let listing = fleet.render(Duration::from_secs(30));
+        assert!(listing.contains("nas:"));
+        assert!(listing.contains("Protocols: HTTPS"));
+        assert!(listing.contains("Total (2 machines)"));
+        assert!(!listing.contains("eth1"), "down interfaces are left out");
+    }
+}
@@ -0,0 +1,107 @@
+// Remote monitoring: `kw agent` streams each interval's interface speeds and captured
+// protocols to a `kw hub` over gRPC, and the hub stores the speeds as interfaces named
+// `<agent>/<interface>` so its reports, graphs and web UI cover every machine
+// - Transport: HTTP/2, with TLS once the hub has a certificate (proto/remote.proto)
+// - Auth: a shared token sent as `authorization: Bearer <token>` on every stream
+
+// tonic's interceptors and services return its large `Status` as their error
+#![allow(clippy::result_large_err)]
+
+pub mod agent;
+pub mod hub;
+
+pub use agent::Agent;
+pub use hub::Hub;
+
+/// Code generated from proto/remote.proto by build.rs
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("kaipo.remote.v1");
+}
+
+use anyhow::{bail, Context, Result};
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::Status;
+
+use crate::collectors::bandwidth::{BandwidthStats, InterfaceState};
+
+/// Port agents connect to when the hub address has none
+pub const DEFAULT_PORT: u16 = 50051;
+
+/// Joins an agent's name to its interface names in what the hub stores
+pub const AGENT_SEPARATOR: char = '/';
+
+/// The `authorization` header value carrying `token`
+fn bearer(token: &str) -> Result<MetadataValue<Ascii>> {
+    if token.is_empty() || !token.bytes().all(|byte| byte.is_ascii_graphic()) {
+        bail!("The remote token must be printable ASCII without spaces");
+    }
+    format!("Bearer {token}").parse().context("Invalid remote token")
+}
+
+/// Checks the bearer token of an incoming stream; every stream passes when no token is set
+fn authorize(expected: Option<&str>, metadata: &MetadataMap) -> Result<(), Status> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let presented = metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| Status::unauthenticated("A bearer token is required"))?;
+    if constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(Status::unauthenticated("Invalid token"))
+    }
+}
+
+/// Compares without returning early, so response times do not reveal how much of a token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+impl From<&BandwidthStats> for proto::InterfaceReading {
+    fn from(stats: &BandwidthStats) -> Self {
+        let state = match stats.interface_state {
+            InterfaceState::Up => "up",
+            InterfaceState::Down => "down",
+            InterfaceState::Unknown => "unknown",
+        };
+        Self {
+            name: stats.interface_name.clone(),
+            download_bps: stats.download_speed_bps,
+            upload_bps: stats.upload_speed_bps,
+            bytes_received: stats.bytes_received,
+            bytes_sent: stats.bytes_sent,
+            packets_received: stats.packets_received,
+            packets_sent: stats.packets_sent,
+            state: state.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let mut metadata = MetadataMap::new();
+        assert!(authorize(None, &metadata).is_ok());
+        assert_eq!(authorize(Some("secret"), &metadata).unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        metadata.insert("authorization", bearer("wrong").unwrap());
+        assert!(authorize(Some("secret"), &metadata).is_err());
+        metadata.insert("authorization", bearer("secret").unwrap());
+        assert!(authorize(Some("secret"), &metadata).is_ok());
+        assert!(authorize(Some("secret2"), &metadata).is_err());
+    }
+
+    #[test]
+    fn test_bearer_rejects_non_ascii() {
+        assert!(bearer("caf\u{e9}").is_err());
+        assert!(bearer("line\nbreak").is_err());
+        assert!(bearer("").is_err());
+    }
+}
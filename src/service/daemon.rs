@@ -0,0 +1,551 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde_json::json;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::interval;
+
+use crate::analyzers::{AnalysisResult, ClassificationSample, DuplicateSample, LatencySample, MtuSample, ProtocolAnalyzer, TcpSession, ThrottledFlow};
+use crate::collectors::bandwidth::formatting::format_bytes;
+use crate::collectors::bandwidth::SamplingPlan;
+use crate::collectors::capture_stats::CaptureMonitor;
+use crate::collectors::collector_service::CollectorService;
+use crate::collectors::platform::interface_events::{InterfaceEvent, InterfaceWatcher};
+use crate::collectors::ping_collector::{PingCollector, PingSample};
+use crate::collectors::socket_stats::{self, SocketStatsCollector};
+use crate::collectors::{BandwidthCollector, PacketCollector};
+use crate::config::data_dir::COUNTER_STATE_FILE;
+use crate::config::{
+    CaptureConfig, ConfigFingerprint, ContentionConfig, DatabaseBackend, HooksConfig, MqttConfig, PingConfig,
+    ResourceProfile, RetentionConfig, SecurityConfig, StorageConfig, WebhookConfig,
+};
+use crate::exporters::mqtt::{self, MqttPublisher};
+use crate::exporters::webhook::{StatsSummary, WebhookExporter};
+use crate::service::hooks::{self, CapTracker, HookEvent, HookRunner, SaturationTracker};
+use crate::service::quality::QualityMonitor;
+#[cfg(feature = "api")]
+use crate::service::web::{self, WebUi};
+use crate::shutdown::SessionSummary;
+use crate::storage::packet_storage::{BandwidthSample, CaptureSample, CollectorHealthSample, InterfaceChange, QualitySample};
+use crate::storage::{self, Database, PacketStorage, StorageBackend};
+
+/// How often buffered records are written even when a batch is not full
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often data quality, bandwidth, capture performance and collector health samples are recorded
+const QUALITY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often capture rates and queue depths are read between samples
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Unattended capture loop behind `kw service run`
+///
+/// Captures packets on one interface, analyzes them and records the results in
+/// the packet database, the same data `kw packets` stores interactively. Data
+/// quality samples are recorded alongside, for the quality section of `kw report`,
+/// together with per-interface throughput the bandwidth anomaly baselines learn from
+/// the capture performance shown by `kw capture-stats` and the bandwidth collector's own
+/// health shown by `kw stats self`. Interfaces appearing, disappearing and going up or down
+/// are recorded for the event markers of `kw graph bandwidth`. The configured
+/// connectivity targets are pinged throughout, with their latency, jitter and loss
+/// recorded per minute. TCP connections of the host itself carry the kernel's congestion
+/// window, round-trip time and retransmission counts for their sockets. Security events
+/// and configuration changes go to the audit log.
+/// Interface counters are kept in the data directory, so a restarted daemon measures speeds
+/// from its first reading. Old data is rolled up and deleted by the retention policy as the daemon runs.
+/// Configured hooks run on alerts, interface changes, when the monthly cap is reached and
+/// when a minute's throughput saturates an interface's link.
+/// Summaries of each period's bandwidth and protocols are posted to the webhook, if set,
+/// and each interface's speed and usage are published to the MQTT broker, if set.
+/// Optionally serves the browser UI from the same process.
+/// With `[storage] backend = "postgres"` the records go to a PostgreSQL server instead; the
+/// web UI, the cap hook, webhook summaries and the audit log keep using the local database.
+pub struct MonitorDaemon {
+    interface: String,
+    data_dir: PathBuf,
+    resources: ResourceProfile,
+    capture: CaptureConfig,
+    sampling: SamplingPlan,
+    web_address: Option<SocketAddr>,
+    security: SecurityConfig,
+    ping: PingConfig,
+    retention: RetentionConfig,
+    hooks: HookRunner,
+    contention: ContentionConfig,
+    webhook: WebhookConfig,
+    mqtt: MqttConfig,
+    storage: StorageConfig,
+    config_fingerprint: Option<ConfigFingerprint>,
+}
+
+impl MonitorDaemon {
+    pub fn new(interface: String, data_dir: PathBuf) -> Self {
+        Self {
+            interface,
+            data_dir,
+            resources: ResourceProfile::standard(),
+            capture: CaptureConfig::default(),
+            sampling: SamplingPlan::default(),
+            web_address: None,
+            security: SecurityConfig::default(),
+            ping: PingConfig::default(),
+            retention: RetentionConfig::default(),
+            hooks: HookRunner::default(),
+            contention: ContentionConfig::default(),
+            webhook: WebhookConfig::default(),
+            mqtt: MqttConfig::default(),
+            storage: StorageConfig::default(),
+            config_fingerprint: None,
+        }
+    }
+
+    /// Applies buffer sizes, storage rollup and the analyzer switch from the resource profile
+    pub fn with_resource_profile(mut self, resources: ResourceProfile) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Kernel interface packets are captured through
+    pub fn with_capture(mut self, config: CaptureConfig) -> Self {
+        self.capture = config;
+        self
+    }
+
+    /// Per-interface bandwidth sampling intervals for the data quality monitor
+    pub fn with_sampling(mut self, plan: SamplingPlan) -> Self {
+        self.sampling = plan;
+        self
+    }
+
+    /// Serves the web UI on `address` while the daemon runs
+    pub fn with_web_ui(mut self, address: Option<SocketAddr>) -> Self {
+        self.web_address = address;
+        self
+    }
+
+    /// Port scan, SYN flood and ICMP sweep thresholds
+    pub fn with_security_config(mut self, config: SecurityConfig) -> Self {
+        self.security = config;
+        self
+    }
+
+    /// Connectivity targets pinged while the daemon runs
+    pub fn with_ping(mut self, config: PingConfig) -> Self {
+        self.ping = config;
+        self
+    }
+
+    /// Retention policy applied every `interval_hours` while the daemon runs, when `auto` is set
+    pub fn with_retention(mut self, config: RetentionConfig) -> Self {
+        self.retention = config;
+        self
+    }
+
+    /// Commands run on alerts, interface changes, when the monthly cap is reached and when a link saturates
+    pub fn with_hooks(mut self, config: HooksConfig) -> Self {
+        self.hooks = HookRunner::new(config);
+        self
+    }
+
+    /// Saturation threshold and link speed override for the `on_link_saturated` hook
+    pub fn with_contention(mut self, config: ContentionConfig) -> Self {
+        self.contention = config;
+        self
+    }
+
+    /// Endpoint a summary is posted to every `interval_secs` while the daemon runs
+    pub fn with_webhook(mut self, config: WebhookConfig) -> Self {
+        self.webhook = config;
+        self
+    }
+
+    /// Broker each minute's interface speeds and today's usage are published to
+    pub fn with_mqtt(mut self, config: MqttConfig) -> Self {
+        self.mqtt = config;
+        self
+    }
+
+    /// Database the captured records are written to, the local packet database by default
+    pub fn with_storage(mut self, config: StorageConfig) -> Self {
+        self.storage = config;
+        self
+    }
+
+    /// Configuration the daemon was started with, recorded in the audit log when it changed
+    pub fn with_config_fingerprint(mut self, fingerprint: Option<ConfigFingerprint>) -> Self {
+        self.config_fingerprint = fingerprint;
+        self
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Runs until `shutdown` completes or the capture ends, then flushes storage and sums up
+    /// the session
+    pub async fn run<F: Future<Output = ()>>(&self, shutdown: F) -> Result<SessionSummary> {
+        let started = Instant::now();
+        #[cfg(not(feature = "api"))]
+        if self.web_address.is_some() {
+            anyhow::bail!("[web] listen is set, but this kw was built without the web UI (the `api` feature)");
+        }
+        let database = Database::open(self.data_dir.join("packets.db")).context("Failed to open packet database")?;
+        info!("Packet database at schema version {}", database.schema_version()?);
+        let storage = Arc::new(
+            PacketStorage::from_database(&database, self.resources.storage_batch_size)
+                .with_rollup(self.resources.storage_rollup)
+                .with_flush_interval(self.resources.storage_flush_interval),
+        );
+        if let Some(fingerprint) = &self.config_fingerprint {
+            match storage.record_config_fingerprint(fingerprint) {
+                Ok(true) => info!("Configuration {fingerprint} recorded in the audit log"),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to record the configuration in the audit log: {e}"),
+            }
+        }
+        let backend = storage::open_backend(
+            &self.storage,
+            Arc::clone(&storage),
+            self.resources.storage_batch_size,
+            self.resources.storage_flush_interval,
+        )
+        .context("Failed to open the [storage] backend")?;
+        if self.storage.backend == DatabaseBackend::Postgres
+            && (self.web_address.is_some() || self.webhook.url.is_some() || self.hooks.monthly_cap_bytes().is_some())
+        {
+            warn!("The web UI, webhook summaries and the cap hook read the local database, which PostgreSQL storage leaves empty");
+        }
+        let webhook = WebhookExporter::new(&self.webhook).context("Invalid [webhook] configuration")?;
+        let mut mqtt = MqttPublisher::start(&self.mqtt).context("Invalid [mqtt] configuration")?;
+        if let Some(mqtt) = &mut mqtt {
+            match storage.get_bandwidth_samples(mqtt::today_start(), Local::now()) {
+                Ok(samples) => mqtt.seed_usage(&samples),
+                Err(e) => warn!("Today's MQTT usage topics start from zero: {e}"),
+            }
+        }
+        // One collector for the quality samples, bandwidth samples and the web UI; without a
+        // sampling plan or web UI bandwidth is only read once per quality sample
+        let bandwidth_period = self.sampling.min_interval().unwrap_or(QUALITY_INTERVAL).min(QUALITY_INTERVAL);
+        #[cfg(feature = "api")]
+        let bandwidth_period = match self.web_address {
+            Some(_) => bandwidth_period.min(web::SAMPLE_INTERVAL),
+            None => bandwidth_period,
+        };
+        let bandwidth = CollectorService::start(
+            BandwidthCollector::new()
+                .with_sampling_plan(self.sampling.clone())
+                .with_interface_watcher()
+                .with_power_watcher()
+                .with_state_file(self.data_dir.join(COUNTER_STATE_FILE)),
+            bandwidth_period,
+        )
+        .await;
+        #[cfg(feature = "api")]
+        let web_ui = match self.web_address {
+            Some(address) => Some(tokio::spawn(
+                WebUi::bind(address, Arc::clone(&storage), bandwidth.subscribe()).await?.run(),
+            )),
+            None => None,
+        };
+        let collector = PacketCollector::new(self.interface.clone())
+            .context("Failed to create packet collector")?
+            .with_channel_capacity(self.resources.packet_channel_capacity)
+            .with_capture_backend(self.capture.backend);
+        collector.start().await.context("Failed to start packet capture")?;
+
+        info!(
+            "Monitoring daemon started on interface {} (data: {})",
+            self.interface,
+            self.data_dir.display()
+        );
+
+        let mut analyzer = ProtocolAnalyzer::with_connection_limit(self.resources.tracked_connections)
+            .with_security_config(self.security.clone());
+        let mut flush_interval = interval(FLUSH_INTERVAL);
+        let mut quality = QualityMonitor::new(self.interface.clone(), bandwidth.subscribe());
+        let mut quality_interval = interval(QUALITY_INTERVAL);
+        quality_interval.tick().await;
+        let mut health_since = Instant::now();
+        let mut capture = CaptureMonitor::new(self.interface.clone());
+        let mut capture_interval = interval(CAPTURE_POLL_INTERVAL);
+        let mut sockets = SocketStatsCollector::new();
+        let mut socket_stats_interval = interval(socket_stats::POLL_INTERVAL);
+        let mut ping = PingCollector::new(self.ping.clone());
+        ping.start();
+        let retention_period = Duration::from_secs(self.retention.interval_hours.max(1) * 3600);
+        let mut retention_interval = interval(retention_period);
+        let interface_watcher = InterfaceWatcher::start()
+            .inspect_err(|e| warn!("Interface changes will not be recorded or run the hook: {e}"))
+            .ok();
+        let mut cap = self
+            .hooks
+            .monthly_cap_bytes()
+            .filter(|_| self.hooks.is_configured(HookEvent::CapReached))
+            .map(CapTracker::new);
+        let mut saturation = self
+            .hooks
+            .is_configured(HookEvent::LinkSaturated)
+            .then(|| SaturationTracker::new(&self.contention));
+        let mut webhook_interval = interval(Duration::from_secs(self.webhook.interval_secs.max(1)));
+        webhook_interval.tick().await;
+        let mut webhook_period_start = Local::now();
+        let mut packet_count = 0u64;
+        let mut byte_count = 0u64;
+        let mut alert_count = 0usize;
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    info!("Shutdown requested");
+                    break;
+                }
+                _ = flush_interval.tick() => {
+                    record_tcp_sessions(&*backend, analyzer.take_finished_tcp_sessions());
+                    record_latency(&*backend, analyzer.take_latency_samples());
+                    record_classification(&*backend, analyzer.take_classification_samples());
+                    record_duplicates(&*backend, analyzer.take_duplicate_samples());
+                    record_mtu(&*backend, analyzer.take_mtu_samples());
+                    record_throttled(&*backend, analyzer.take_throttled_flows());
+                    record_ping(&*backend, ping.take_samples());
+                    if let Err(e) = backend.flush() {
+                        warn!("Failed to flush packet database: {e}");
+                    }
+                }
+                _ = quality_interval.tick() => {
+                    let stats = collector.get_stats().await;
+                    record_quality(&*backend, quality.sample(&stats));
+                    let samples = quality.take_bandwidth_samples();
+                    for details in saturation.iter_mut().flat_map(|saturation| saturation.check(&samples)) {
+                        info!("Link saturated: {details}");
+                        self.hooks.fire(HookEvent::LinkSaturated, details);
+                    }
+                    if let Some(mqtt) = &mut mqtt {
+                        mqtt.publish_samples(&samples);
+                    }
+                    record_bandwidth(&*backend, samples);
+                    record_capture(&*backend, capture.sample(&stats, collector.queue_depth(), backend.pending_records()));
+                    record_collector_health(&*backend, &bandwidth, &mut health_since);
+                    if let Some(cap) = &mut cap {
+                        check_cap(&storage, cap, &self.hooks);
+                    }
+                }
+                _ = capture_interval.tick() => {
+                    capture.poll(&collector.get_stats().await, collector.queue_depth(), backend.pending_records());
+                    for event in interface_watcher.iter().flat_map(InterfaceWatcher::try_events) {
+                        info!("Network {event}");
+                        record_interface_change(&*backend, &event);
+                        if let Some(saturation) = &mut saturation {
+                            saturation.forget(event.interface_name());
+                        }
+                        self.hooks.fire(HookEvent::InterfaceChange, hooks::interface_change_details(&event));
+                    }
+                }
+                _ = socket_stats_interval.tick(), if self.resources.analyzer_enabled => {
+                    analyzer.attach_socket_stats(&sockets.collect());
+                }
+                _ = retention_interval.tick(), if self.retention.auto => {
+                    if let Err(e) = storage.apply_retention(&self.retention) {
+                        warn!("Failed to apply the retention policy: {e}");
+                    }
+                }
+                _ = webhook_interval.tick(), if webhook.is_some() => {
+                    let now = Local::now();
+                    if let Some(webhook) = &webhook {
+                        push_summary(&storage, webhook, &self.interface, webhook_period_start, now);
+                    }
+                    webhook_period_start = now;
+                }
+                _ = quality.poll() => {}
+                packet = collector.receive_packet() => {
+                    let Some(packet) = packet else {
+                        warn!("Packet capture ended unexpectedly");
+                        break;
+                    };
+                    packet_count += 1;
+                    byte_count += packet.size_bytes;
+                    let analysis = if self.resources.analyzer_enabled {
+                        analyzer.analyze_packet(&packet).unwrap_or_default()
+                    } else {
+                        AnalysisResult::default()
+                    };
+                    alert_count += analysis.security_flags.len();
+                    for details in analysis.security_flags.iter().filter_map(|flag| hooks::alert_details(flag, &packet)) {
+                        self.hooks.fire(HookEvent::Alert, details);
+                    }
+                    if let Err(e) = backend.store_packet(&packet, &analysis) {
+                        warn!("Failed to store packet analysis: {e}");
+                    }
+                }
+            }
+        }
+
+        collector.stop().await?;
+        #[cfg(feature = "api")]
+        if let Some(web_ui) = web_ui {
+            web_ui.abort();
+        }
+        // Cover the final partial interval too
+        let stats = collector.get_stats().await;
+        record_quality(&*backend, quality.sample(&stats));
+        record_bandwidth(&*backend, quality.take_bandwidth_samples());
+        record_capture(&*backend, capture.sample(&stats, collector.queue_depth(), backend.pending_records()));
+        record_collector_health(&*backend, &bandwidth, &mut health_since);
+        record_tcp_sessions(&*backend, analyzer.drain_tcp_sessions());
+        record_latency(&*backend, analyzer.take_latency_samples());
+        record_classification(&*backend, analyzer.drain_classification_samples());
+        record_duplicates(&*backend, analyzer.drain_duplicate_samples());
+        record_mtu(&*backend, analyzer.drain_mtu_samples());
+        record_throttled(&*backend, analyzer.drain_throttled_flows());
+        ping.stop();
+        record_ping(&*backend, ping.drain());
+        let pending = backend.pending_records();
+        backend.flush().context("Failed to flush packet database")?;
+        if let Some(mqtt) = mqtt {
+            mqtt.shutdown().await;
+        }
+        info!("Monitoring daemon stopped after {packet_count} packets");
+
+        let mut summary = SessionSummary::new(format!("Monitoring summary for {}", self.interface), started);
+        summary.add("Packets", packet_count);
+        summary.add("Data", format_bytes(byte_count as f64));
+        summary.add("Security flags", alert_count);
+        summary.add("Written on exit", format!("{pending} buffered records"));
+        Ok(summary)
+    }
+}
+
+/// Runs `on_cap_reached` the first time this month's stored traffic reaches the cap
+fn check_cap(storage: &PacketStorage, cap: &mut CapTracker, hooks: &HookRunner) {
+    let now = Local::now();
+    let month = hooks::month_start(now);
+    match storage.get_usage_summary(month, now) {
+        Ok(usage) if cap.check(month.date_naive(), usage.bytes) => {
+            info!("Monthly cap of {} bytes reached ({} bytes used)", cap.cap_bytes(), usage.bytes);
+            hooks.fire(
+                HookEvent::CapReached,
+                json!({ "month": month.format("%Y-%m").to_string(), "bytes": usage.bytes, "cap_bytes": cap.cap_bytes() }),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to check this month's usage against the cap: {e}"),
+    }
+}
+
+/// Posts the summary of `start..end` in the background, so retries do not hold up capture
+fn push_summary(
+    storage: &PacketStorage,
+    webhook: &WebhookExporter,
+    interface: &str,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) {
+    let summary = match (storage.get_bandwidth_samples(start, end), storage.get_usage_summary(start, end)) {
+        (Ok(samples), Ok(usage)) => StatsSummary::new(start, end, interface.to_string(), &samples, &usage),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Failed to summarize traffic for the webhook: {e}");
+            return;
+        }
+    };
+    let webhook = webhook.clone();
+    tokio::spawn(async move {
+        if let Err(e) = webhook.push(&summary).await {
+            warn!("Failed to post summary to {}: {e:#}", webhook.url());
+        }
+    });
+}
+
+fn record_quality(storage: &dyn StorageBackend, samples: Vec<QualitySample>) {
+    for sample in &samples {
+        if let Err(e) = storage.store_quality_sample(sample) {
+            warn!("Failed to record data quality: {e}");
+        }
+    }
+}
+
+fn record_capture(storage: &dyn StorageBackend, sample: CaptureSample) {
+    if let Err(e) = storage.store_capture_sample(&sample) {
+        warn!("Failed to record capture statistics: {e}");
+    }
+}
+
+/// Records how the bandwidth collector fared since `since`, and moves `since` on
+fn record_collector_health(storage: &dyn StorageBackend, bandwidth: &CollectorService, since: &mut Instant) {
+    let telemetry = bandwidth.take_telemetry();
+    let duration_secs = since.elapsed().as_secs_f64();
+    *since = Instant::now();
+    if telemetry.is_empty() {
+        return;
+    }
+    let sample = CollectorHealthSample { timestamp: Local::now(), duration_secs, telemetry };
+    if let Err(e) = storage.store_collector_health(&sample) {
+        warn!("Failed to record collector health: {e}");
+    }
+}
+
+fn record_interface_change(storage: &dyn StorageBackend, event: &InterfaceEvent) {
+    let change = InterfaceChange {
+        timestamp: Local::now(),
+        interface_name: event.interface_name().to_string(),
+        change: event.change().to_string(),
+    };
+    if let Err(e) = storage.store_interface_change(&change) {
+        warn!("Failed to record the interface change: {e}");
+    }
+}
+
+fn record_bandwidth(storage: &dyn StorageBackend, samples: Vec<BandwidthSample>) {
+    if let Err(e) = storage.store_bandwidth_samples(&samples) {
+        warn!("Failed to record bandwidth samples: {e}");
+    }
+}
+
+fn record_tcp_sessions(storage: &dyn StorageBackend, sessions: Vec<TcpSession>) {
+    if let Err(e) = storage.store_tcp_sessions(&sessions) {
+        warn!("Failed to record TCP sessions: {e}");
+    }
+}
+
+fn record_latency(storage: &dyn StorageBackend, samples: Vec<LatencySample>) {
+    if let Err(e) = storage.store_latency_samples(&samples) {
+        warn!("Failed to record latency samples: {e}");
+    }
+}
+
+fn record_classification(storage: &dyn StorageBackend, samples: Vec<ClassificationSample>) {
+    if let Err(e) = storage.store_classification_samples(&samples) {
+        warn!("Failed to record classification counts: {e}");
+    }
+}
+
+fn record_duplicates(storage: &dyn StorageBackend, samples: Vec<DuplicateSample>) {
+    if let Err(e) = storage.store_duplicate_samples(&samples) {
+        warn!("Failed to record duplicate packet counts: {e}");
+    }
+}
+
+fn record_mtu(storage: &dyn StorageBackend, samples: Vec<MtuSample>) {
+    if let Err(e) = storage.store_mtu_samples(&samples) {
+        warn!("Failed to record fragmentation counts: {e}");
+    }
+}
+
+fn record_throttled(storage: &dyn StorageBackend, flows: Vec<ThrottledFlow>) {
+    if let Err(e) = storage.store_throttled_flows(&flows) {
+        warn!("Failed to record throttled connections: {e}");
+    }
+}
+
+fn record_ping(storage: &dyn StorageBackend, samples: Vec<PingSample>) {
+    if let Err(e) = storage.store_ping_samples(&samples) {
+        warn!("Failed to record ping statistics: {e}");
+    }
+}
@@ -0,0 +1,361 @@
+// Event hooks: shell commands run when something happens, configured under `[hooks]`
+// Each command runs in the background through the platform shell with a JSON payload on
+// stdin and the event name in KW_EVENT, and is killed when it outlives `timeout_secs`
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::analyzers::SecurityFlag;
+use crate::collectors::link_speed::{detect_link, LinkInfo};
+use crate::collectors::platform::interface_events::InterfaceEvent;
+use crate::config::{ContentionConfig, HooksConfig};
+use crate::models::NetworkPacket;
+use crate::storage::packet_storage::BandwidthSample;
+
+/// Something a hook can be attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// A port scan, SYN flood, ICMP sweep or unknown-traffic surge was detected
+    Alert,
+    /// An interface appeared, disappeared, went up or down or changed addresses
+    InterfaceChange,
+    /// This month's traffic reached `monthly_cap_gb`
+    CapReached,
+    /// An interface's throughput reached `[contention] saturation_percent` of its link speed
+    LinkSaturated,
+}
+
+impl HookEvent {
+    /// Configuration key of the event, also passed to the command as KW_EVENT
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Alert => "on_alert",
+            HookEvent::InterfaceChange => "on_interface_change",
+            HookEvent::CapReached => "on_cap_reached",
+            HookEvent::LinkSaturated => "on_link_saturated",
+        }
+    }
+}
+
+/// Runs the configured hook commands
+#[derive(Debug, Clone, Default)]
+pub struct HookRunner {
+    config: HooksConfig,
+}
+
+impl HookRunner {
+    pub fn new(config: HooksConfig) -> Self {
+        Self { config }
+    }
+
+    /// Bytes this month's traffic may reach before `on_cap_reached` runs, if a cap is set
+    pub fn monthly_cap_bytes(&self) -> Option<u64> {
+        self.config.monthly_cap_gb.filter(|gb| *gb > 0.0).map(|gb| (gb * 1e9) as u64)
+    }
+
+    pub fn is_configured(&self, event: HookEvent) -> bool {
+        self.command(event).is_some()
+    }
+
+    fn command(&self, event: HookEvent) -> Option<&str> {
+        let command = match event {
+            HookEvent::Alert => &self.config.on_alert,
+            HookEvent::InterfaceChange => &self.config.on_interface_change,
+            HookEvent::CapReached => &self.config.on_cap_reached,
+            HookEvent::LinkSaturated => &self.config.on_link_saturated,
+        };
+        command.as_deref().filter(|command| !command.trim().is_empty())
+    }
+
+    /// Starts the hook for `event`, if one is configured, without waiting for it
+    /// `details` is merged into the payload next to `event` and `timestamp`
+    pub fn fire(&self, event: HookEvent, details: Value) {
+        let Some(command) = self.command(event) else {
+            return;
+        };
+        let command = command.to_string();
+        let payload = payload(event, details);
+        let timeout = Duration::from_secs(self.config.timeout_secs.max(1));
+        tokio::spawn(async move {
+            match run_hook(&command, event, &payload, timeout).await {
+                Ok(()) => debug!("Hook {} finished", event.name()),
+                Err(e) => warn!("Hook {} ({command}) failed: {e:#}", event.name()),
+            }
+        });
+    }
+}
+
+/// JSON written to the hook's stdin
+fn payload(event: HookEvent, details: Value) -> Value {
+    let mut payload = json!({ "event": event.name(), "timestamp": Local::now() });
+    if let (Some(payload), Value::Object(details)) = (payload.as_object_mut(), details) {
+        payload.extend(details);
+    }
+    payload
+}
+
+/// Runs `command` through the shell with `payload` on stdin, killing it after `timeout`
+async fn run_hook(command: &str, event: HookEvent, payload: &Value, timeout: Duration) -> Result<()> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    let mut child = shell
+        .env("KW_EVENT", event.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start the shell")?;
+    info!("Running hook {} ({command})", event.name());
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its payload closes the pipe early; that is not an error
+        let _ = stdin.write_all(format!("{payload}\n").as_bytes()).await;
+    }
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output?,
+        // Dropping the child kills it
+        Err(_) => bail!("timed out after {}s and was killed", timeout.as_secs()),
+    };
+    if !output.status.success() {
+        bail!("{}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Payload details of a flag that counts as an alert, or `None` for per-packet flags
+pub fn alert_details(flag: &SecurityFlag, packet: &NetworkPacket) -> Option<Value> {
+    // An unknown-traffic surge describes the interface, not the packet that revealed it
+    let (about_packet, details) = match flag {
+        SecurityFlag::PortScan(ports) => (true, json!({ "ports": ports })),
+        SecurityFlag::SynFlood(sources) => (true, json!({ "sources": sources })),
+        SecurityFlag::IcmpSweep(hosts) => (true, json!({ "hosts": hosts })),
+        SecurityFlag::UnknownTrafficSurge(percent, usual) => {
+            (false, json!({ "unknown_percent": percent, "usual_percent": usual }))
+        }
+        _ => return None,
+    };
+    let mut alert = json!({ "type": flag.event_type(), "interface": packet.interface });
+    if about_packet {
+        alert["source"] = json!(packet.source_addr.map(|ip| ip.to_string()));
+        alert["destination"] = json!(packet.dest_addr.map(|ip| ip.to_string()));
+    }
+    if let (Some(alert), Value::Object(details)) = (alert.as_object_mut(), details) {
+        alert.extend(details);
+    }
+    Some(alert)
+}
+
+/// Payload details of an interface change
+pub fn interface_change_details(event: &InterfaceEvent) -> Value {
+    json!({ "interface": event.interface_name(), "change": event.change() })
+}
+
+/// Fires `on_cap_reached` once per calendar month, the first time usage reaches the cap
+#[derive(Debug)]
+pub struct CapTracker {
+    cap_bytes: u64,
+    /// First day of the month the hook last fired for
+    reached_in: Option<NaiveDate>,
+}
+
+impl CapTracker {
+    pub fn new(cap_bytes: u64) -> Self {
+        Self { cap_bytes, reached_in: None }
+    }
+
+    pub fn cap_bytes(&self) -> u64 {
+        self.cap_bytes
+    }
+
+    /// Whether `bytes` used in the month starting `month` has just reached the cap
+    pub fn check(&mut self, month: NaiveDate, bytes: u64) -> bool {
+        if bytes < self.cap_bytes || self.reached_in == Some(month) {
+            return false;
+        }
+        self.reached_in = Some(month);
+        true
+    }
+}
+
+/// Fires `on_link_saturated` when an interface's throughput reaches the saturation share of
+/// its link, and again only once it has dropped below and come back
+#[derive(Debug)]
+pub struct SaturationTracker {
+    threshold_percent: f64,
+    override_mbps: Option<u64>,
+    /// Detected link per interface, looked up once
+    links: HashMap<String, Option<LinkInfo>>,
+    saturated: HashSet<String>,
+}
+
+impl SaturationTracker {
+    pub fn new(config: &ContentionConfig) -> Self {
+        Self {
+            threshold_percent: config.saturation_percent,
+            override_mbps: config.link_speed_mbps,
+            links: HashMap::new(),
+            saturated: HashSet::new(),
+        }
+    }
+
+    /// Payload details of the interfaces whose throughput in `samples` has just reached the threshold
+    pub fn check(&mut self, samples: &[BandwidthSample]) -> Vec<Value> {
+        let mut links = std::mem::take(&mut self.links);
+        let details = self.check_with(samples, |interface| {
+            *links.entry(interface.to_string()).or_insert_with(|| detect_link(interface))
+        });
+        self.links = links;
+        details
+    }
+
+    /// Looks the interface's link up again on its next sample, after it changed
+    pub fn forget(&mut self, interface: &str) {
+        self.links.remove(interface);
+    }
+
+    fn check_with<F>(&mut self, samples: &[BandwidthSample], mut link: F) -> Vec<Value>
+    where
+        F: FnMut(&str) -> Option<LinkInfo>,
+    {
+        let mut details = Vec::new();
+        for sample in samples.iter().filter(|sample| !sample.is_loopback()) {
+            let detected = link(&sample.interface_name);
+            let link = match self.override_mbps {
+                Some(speed_mbps) => LinkInfo { speed_mbps, duplex: detected.and_then(|link| link.duplex) },
+                None => match detected {
+                    Some(link) => link,
+                    None => continue,
+                },
+            };
+            let percent = link.utilisation(sample.download_bps, sample.upload_bps) * 100.0;
+            if percent < self.threshold_percent {
+                self.saturated.remove(&sample.interface_name);
+            } else if self.saturated.insert(sample.interface_name.clone()) {
+                details.push(json!({
+                    "interface": sample.interface_name,
+                    "link_mbps": link.speed_mbps,
+                    "duplex": link.duplex.map(|duplex| duplex.to_string()),
+                    "utilisation_percent": (percent * 10.0).round() / 10.0,
+                    "download_bps": sample.download_bps,
+                    "upload_bps": sample.upload_bps,
+                }));
+            }
+        }
+        details
+    }
+}
+
+/// Midnight on the first day of `now`'s month
+pub fn month_start(now: DateTime<Local>) -> DateTime<Local> {
+    let today = now.date_naive();
+    today
+        .with_day(1)
+        .and_then(|first| first.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::link_speed::Duplex;
+
+    #[test]
+    fn test_payload_merges_details() {
+        let payload = payload(HookEvent::Alert, json!({ "type": "PortScan", "source": "10.0.0.9" }));
+        assert_eq!(payload["event"], "on_alert");
+        assert_eq!(payload["type"], "PortScan");
+        assert!(payload["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_unset_and_blank_hooks_are_not_configured() {
+        let runner = HookRunner::new(HooksConfig {
+            on_alert: Some("notify-send kw".to_string()),
+            on_interface_change: Some("  ".to_string()),
+            ..HooksConfig::default()
+        });
+        assert!(runner.is_configured(HookEvent::Alert));
+        assert!(!runner.is_configured(HookEvent::InterfaceChange));
+        assert!(!runner.is_configured(HookEvent::CapReached));
+        assert_eq!(runner.monthly_cap_bytes(), None);
+    }
+
+    #[test]
+    fn test_cap_fires_once_per_month() {
+        let mut cap = CapTracker::new(1000);
+        let october = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+        assert!(!cap.check(october, 999));
+        assert!(cap.check(october, 1000));
+        assert!(!cap.check(october, 5000));
+        assert!(cap.check(NaiveDate::from_ymd_opt(2026, 11, 1).unwrap(), 1200));
+    }
+
+    #[test]
+    fn test_saturation_fires_once_until_it_clears() {
+        let sample = |download_bps: f64| BandwidthSample {
+            timestamp: Local::now(),
+            interface_name: "eth0".to_string(),
+            duration_secs: 60.0,
+            download_bps,
+            upload_bps: 100_000.0,
+        };
+        let link = |_: &str| Some(LinkInfo { speed_mbps: 100, duplex: Some(Duplex::Full) });
+        let mut tracker = SaturationTracker::new(&ContentionConfig::default());
+        // 80% of 100 Mbit/s is 10 MB/s
+        assert!(tracker.check_with(&[sample(9_000_000.0)], link).is_empty());
+        let fired = tracker.check_with(&[sample(11_000_000.0)], link);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0]["interface"], "eth0");
+        assert_eq!(fired[0]["utilisation_percent"], 88.0);
+        assert_eq!(fired[0]["duplex"], "full duplex");
+        assert!(tracker.check_with(&[sample(12_000_000.0)], link).is_empty());
+        assert!(tracker.check_with(&[sample(1_000_000.0)], link).is_empty());
+        assert_eq!(tracker.check_with(&[sample(11_000_000.0)], link).len(), 1);
+
+        // Interfaces without a known speed are skipped
+        assert!(tracker.check_with(&[sample(11_000_000.0)], |_| None).is_empty());
+    }
+
+    #[test]
+    fn test_interface_change_details() {
+        let details = interface_change_details(&InterfaceEvent::Removed("wg0".to_string()));
+        assert_eq!(details, json!({ "interface": "wg0", "change": "removed" }));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hook_reads_payload_and_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payload.json");
+        let command = format!("cat > '{}' && test \"$KW_EVENT\" = on_cap_reached", path.display());
+        let payload = payload(HookEvent::CapReached, json!({ "bytes": 42 }));
+        run_hook(&command, HookEvent::CapReached, &payload, Duration::from_secs(5)).await.unwrap();
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["bytes"], 42);
+
+        let failed = run_hook("echo nope >&2; exit 3", HookEvent::Alert, &payload, Duration::from_secs(5)).await;
+        assert!(failed.unwrap_err().to_string().contains("nope"));
+
+        let slow = run_hook("sleep 5", HookEvent::Alert, &payload, Duration::from_secs(1)).await;
+        assert!(slow.unwrap_err().to_string().contains("timed out"));
+    }
+}
@@ -0,0 +1,193 @@
+// launchd integration: writes a LaunchDaemon that runs `kw service run` at boot
+// LaunchDaemons run as root, which packet capture needs for /dev/bpf*; output goes to a
+// log file since launchd has no journal of its own for stderr
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::service::{LAUNCHD_LABEL, SERVICE_NAME};
+
+/// Where the daemon's log output goes
+const LOG_PATH: &str = "/Library/Logs/kaipo-watcher.log";
+
+/// Where the plist is installed; `kw purge` looks for it here as well
+pub fn plist_path() -> PathBuf {
+    PathBuf::from(format!("/Library/LaunchDaemons/{LAUNCHD_LABEL}.plist"))
+}
+
+/// Default data directory for the service: `/Library/Application Support/kaipo-watcher/data`
+pub fn default_data_dir() -> PathBuf {
+    PathBuf::from("/Library/Application Support").join(SERVICE_NAME).join("data")
+}
+
+/// Writes the plist and loads it into the system domain, which starts the daemon
+pub fn install(interface: &str, data_dir: &Path) -> Result<()> {
+    if !is_root() {
+        bail!("Installing a LaunchDaemon needs root; run `sudo kw service install`");
+    }
+    let exe = std::env::current_exe().context("Failed to locate kw executable")?;
+    let data_dir = std::path::absolute(data_dir).context("Failed to resolve data directory")?;
+    std::fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create data directory {}", data_dir.display()))?;
+
+    let path = plist_path();
+    if path.is_file() {
+        // Reinstalling replaces the loaded definition
+        let _ = launchctl(&["bootout", &service_target()]);
+    }
+    let plist = render_plist(&exe, interface, &data_dir, crate::config::config_path().as_deref());
+    std::fs::write(&path, plist).with_context(|| format!("Failed to write {}", path.display()))?;
+    launchctl(&["bootstrap", "system", &path.to_string_lossy()])?;
+
+    info!("Installed LaunchDaemon {}", path.display());
+    Ok(())
+}
+
+/// Unloads the daemon and removes its plist
+pub fn uninstall() -> Result<()> {
+    let path = plist_path();
+    if !path.is_file() {
+        bail!("{} is not installed ({} not found)", SERVICE_NAME, path.display());
+    }
+    if !is_root() {
+        bail!("Removing a LaunchDaemon needs root; run `sudo kw service uninstall`");
+    }
+    // Fails when the daemon is not loaded, which is fine here
+    if let Err(e) = launchctl(&["bootout", &service_target()]) {
+        info!("{e}");
+    }
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(())
+}
+
+/// launchd state of the daemon (e.g. "running"), `None` when not installed
+pub fn status() -> Result<Option<String>> {
+    if !plist_path().is_file() {
+        return Ok(None);
+    }
+    let output = Command::new("launchctl")
+        .args(["print", &service_target()])
+        .output()
+        .context("Failed to run launchctl")?;
+    if !output.status.success() {
+        return Ok(Some("not loaded".to_string()));
+    }
+    let state = parse_state(&String::from_utf8_lossy(&output.stdout));
+    Ok(Some(state.unwrap_or_else(|| "unknown".to_string())))
+}
+
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// The daemon in launchctl's domain/label form
+fn service_target() -> String {
+    format!("system/{LAUNCHD_LABEL}")
+}
+
+/// Runs `launchctl`, turning a non-zero exit into an error carrying its message
+fn launchctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .context("Failed to run launchctl")?;
+    if !output.status.success() {
+        bail!("launchctl {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// The plist `install` writes
+fn render_plist(exe: &Path, interface: &str, data_dir: &Path, config: Option<&Path>) -> String {
+    let (exe, data_dir) = (exe.to_string_lossy(), data_dir.to_string_lossy());
+    let arguments: String = [exe.as_ref(), "service", "run", "--interface", interface, "--data-dir", data_dir.as_ref()]
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", escape(arg)))
+        .collect();
+    // `kw` run as root finds a different configuration file than the user who installed it
+    let config = config
+        .map(|path| {
+            format!(
+                "        <key>KW_CONFIG</key>\n        <string>{}</string>\n",
+                escape(&path.to_string_lossy())
+            )
+        })
+        .unwrap_or_default();
+
+    // Restarted after a crash, at most once a minute, as the Windows service is
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LAUNCHD_LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>RUST_LOG</key>
+        <string>info</string>
+{config}    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>60</integer>
+    <key>StandardOutPath</key>
+    <string>{LOG_PATH}</string>
+    <key>StandardErrorPath</key>
+    <string>{LOG_PATH}</string>
+</dict>
+</plist>
+"#
+    )
+}
+
+/// Escapes text for a plist `<string>`
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Extracts the state from `launchctl print` output
+/// e.g. "\tstate = running" -> "running"
+fn parse_state(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "state").then(|| value.trim().to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plist_lists_arguments_and_pins_config() {
+        let plist = render_plist(
+            Path::new("/usr/local/bin/kw"),
+            "en0",
+            Path::new("/Library/Application Support/kaipo-watcher/data"),
+            Some(Path::new("/Users/kai/R&D/kw.toml")),
+        );
+        assert!(plist.contains("        <string>en0</string>\n"));
+        assert!(plist.contains("<string>/Library/Application Support/kaipo-watcher/data</string>"));
+        assert!(plist.contains("<string>/Users/kai/R&amp;D/kw.toml</string>"));
+        assert!(plist.contains(&format!("<string>{LAUNCHD_LABEL}</string>")));
+    }
+
+    #[test]
+    fn test_parse_state_from_launchctl_print() {
+        let output = "system/com.kaipo.watcher = {\n\tactive count = 1\n\tstate = running\n\tprogram = /usr/local/bin/kw\n";
+        assert_eq!(parse_state(output).as_deref(), Some("running"));
+        assert_eq!(parse_state("Could not find service"), None);
+    }
+}
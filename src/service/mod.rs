@@ -0,0 +1,29 @@
+// Service integration: runs kaipo-watcher as a background monitoring daemon
+// and registers it with the platform service manager so it starts at boot
+// The daemon is built around packet capture, so all of it but the web UI, which `kw serve`
+// also runs on its own, needs the `capture` feature; the web UI is served by axum and
+// needs `api`. The names below are also what `kw purge` looks for
+
+#[cfg(feature = "capture")]
+pub mod daemon;
+#[cfg(feature = "capture")]
+pub mod hooks;
+#[cfg(all(target_os = "macos", feature = "capture"))]
+pub mod launchd;
+#[cfg(feature = "capture")]
+pub mod quality;
+#[cfg(all(target_os = "linux", feature = "capture"))]
+pub mod systemd;
+#[cfg(feature = "api")]
+pub mod web;
+#[cfg(all(windows, feature = "capture"))]
+pub mod windows;
+
+#[cfg(feature = "capture")]
+pub use daemon::MonitorDaemon;
+
+/// Service name used by the systemd unit and launchd label
+pub const SERVICE_NAME: &str = "kaipo-watcher";
+
+/// launchd label used for the macOS agent/daemon plist
+pub const LAUNCHD_LABEL: &str = "com.kaipo.watcher";
@@ -0,0 +1,275 @@
+use chrono::Local;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::watch;
+
+use crate::collectors::bandwidth_collector::{BandwidthStats, CalculationConfidence};
+use crate::collectors::collector_service::BandwidthSnapshot;
+use crate::models::PacketStatistics;
+use crate::storage::packet_storage::{BandwidthSample, QualitySample};
+
+/// Judges how trustworthy the daemon's measurements are
+///
+/// Takes in every reading of the daemon's `CollectorService` at `poll` and credits the
+/// time since the previous reading to the confidence observed. Each sample then reports
+/// that time per confidence level, plus counter resets, failed collections and packets
+/// the capture queue had to drop. The same readings give the average throughput of each
+/// interface between samples, which the anomaly baselines are learned from.
+pub struct QualityMonitor {
+    interface: String,
+    snapshots: watch::Receiver<BandwidthSnapshot>,
+    /// When the reading last taken in was collected
+    last_reading: Instant,
+    /// Seconds per confidence level since the last sample
+    confidence_secs: HashMap<CalculationConfidence, f64>,
+    last_failures: u64,
+    last_resets: u64,
+    last_captured: u64,
+    last_dropped: u64,
+    /// Interfaces from the most recent successful reading
+    latest: Arc<Vec<BandwidthStats>>,
+    /// Byte counters (received, sent) per interface at the last bandwidth sample
+    usage_counters: HashMap<String, (u64, u64)>,
+    usage_since: Instant,
+}
+
+impl QualityMonitor {
+    /// Creates a monitor starting from the service's current reading
+    pub fn new(interface: String, mut snapshots: watch::Receiver<BandwidthSnapshot>) -> Self {
+        let baseline = snapshots.borrow_and_update().clone();
+        let usage_counters = usage_counters(&baseline.stats, &interface);
+
+        Self {
+            interface,
+            snapshots,
+            last_reading: baseline.taken_at,
+            confidence_secs: HashMap::new(),
+            last_failures: baseline.failures,
+            last_resets: baseline.counter_resets,
+            last_captured: 0,
+            last_dropped: 0,
+            latest: baseline.stats,
+            usage_counters,
+            usage_since: baseline.taken_at,
+        }
+    }
+
+    /// Waits for the service's next reading and takes it in
+    pub async fn poll(&mut self) {
+        if self.snapshots.changed().await.is_err() {
+            // The service has stopped, so no reading will follow
+            std::future::pending::<()>().await;
+        }
+        self.take_reading();
+    }
+
+    /// Credits the time since the previous reading to the confidence of the newest one
+    fn take_reading(&mut self) {
+        let snapshot = self.snapshots.borrow_and_update().clone();
+        let elapsed = snapshot.taken_at.saturating_duration_since(self.last_reading).as_secs_f64();
+        self.last_reading = snapshot.taken_at;
+
+        let confidence = match snapshot.error {
+            None => interface_confidence(&snapshot.stats, &self.interface),
+            Some(_) => CalculationConfidence::None,
+        };
+        self.latest = snapshot.stats;
+        *self.confidence_secs.entry(confidence).or_default() += elapsed;
+    }
+
+    /// Closes the current interval; `capture` holds the collector's cumulative statistics
+    /// Returns one sample per confidence level seen, the counters riding on the first
+    pub fn sample(&mut self, capture: &PacketStatistics) -> Vec<QualitySample> {
+        if self.snapshots.has_changed().unwrap_or(false) {
+            self.take_reading();
+        }
+
+        let (resets, failures) = {
+            let snapshot = self.snapshots.borrow();
+            (snapshot.counter_resets, snapshot.failures)
+        };
+        let mut counters = Some((
+            resets - self.last_resets,
+            failures - self.last_failures,
+            capture.total_packets.saturating_sub(self.last_captured),
+            capture.dropped_packets.saturating_sub(self.last_dropped),
+        ));
+        self.last_resets = resets;
+        self.last_failures = failures;
+        self.last_captured = capture.total_packets;
+        self.last_dropped = capture.dropped_packets;
+
+        let timestamp = Local::now();
+        let mut confidence_secs = std::mem::take(&mut self.confidence_secs);
+        [
+            CalculationConfidence::High,
+            CalculationConfidence::Medium,
+            CalculationConfidence::Low,
+            CalculationConfidence::None,
+        ]
+        .into_iter()
+        .filter_map(|confidence| {
+            let duration_secs = confidence_secs.remove(&confidence)?;
+            let (counter_resets, collection_failures, packets_captured, packets_dropped) =
+                counters.take().unwrap_or_default();
+            Some(QualitySample {
+                timestamp,
+                interface_name: self.interface.clone(),
+                duration_secs,
+                confidence,
+                counter_resets,
+                collection_failures,
+                packets_captured,
+                packets_dropped,
+            })
+        })
+        .collect()
+    }
+
+    /// Average throughput per interface since the previous call, from the latest reading
+    /// Interfaces first seen in this interval, or whose counters went backwards, are left out
+    pub fn take_bandwidth_samples(&mut self) -> Vec<BandwidthSample> {
+        let duration_secs = self.last_reading.saturating_duration_since(self.usage_since).as_secs_f64();
+        self.usage_since = self.last_reading;
+        let counters = usage_counters(&self.latest, &self.interface);
+        let previous = std::mem::replace(&mut self.usage_counters, counters);
+        if duration_secs <= 0.0 {
+            return Vec::new();
+        }
+
+        let timestamp = Local::now();
+        let mut samples: Vec<BandwidthSample> = self
+            .usage_counters
+            .iter()
+            .filter_map(|(interface, &(received, sent))| {
+                let &(last_received, last_sent) = previous.get(interface)?;
+                if received < last_received || sent < last_sent {
+                    return None;
+                }
+                Some(BandwidthSample {
+                    timestamp,
+                    interface_name: interface.clone(),
+                    duration_secs,
+                    download_bps: (received - last_received) as f64 / duration_secs,
+                    upload_bps: (sent - last_sent) as f64 / duration_secs,
+                })
+            })
+            .collect();
+        samples.sort_by(|a, b| a.interface_name.cmp(&b.interface_name));
+        samples
+    }
+}
+
+/// Byte counters of the interfaces a monitor on `interface` covers
+fn usage_counters(stats: &[BandwidthStats], interface: &str) -> HashMap<String, (u64, u64)> {
+    stats
+        .iter()
+        .filter(|s| interface == "any" || s.interface_name == interface)
+        .map(|s| (s.interface_name.clone(), (s.bytes_received, s.bytes_sent)))
+        .collect()
+}
+
+/// Confidence for `interface`; an interface missing from the readings has no data
+/// Captures on "any" take the weakest confidence of all interfaces
+fn interface_confidence(stats: &[BandwidthStats], interface: &str) -> CalculationConfidence {
+    let rank = |c: &CalculationConfidence| match c {
+        CalculationConfidence::High => 3,
+        CalculationConfidence::Medium => 2,
+        CalculationConfidence::Low => 1,
+        CalculationConfidence::None => 0,
+    };
+
+    stats
+        .iter()
+        .filter(|s| interface == "any" || s.interface_name == interface)
+        .map(|s| s.calculation_confidence.clone())
+        .min_by_key(rank)
+        .unwrap_or(CalculationConfidence::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::bandwidth::{InterfaceState, InterfaceType};
+    use crate::models::{PacketSizeDistribution, ProtocolDistribution};
+    use chrono::Utc;
+    use std::time::Duration;
+
+    fn eth0(received: u64, sent: u64, confidence: CalculationConfidence) -> Arc<Vec<BandwidthStats>> {
+        Arc::new(vec![BandwidthStats {
+            timestamp: Utc::now(),
+            interface_name: "eth0".to_string(),
+            interface_type: InterfaceType::Ethernet,
+            interface_state: InterfaceState::Up,
+            bytes_received: received,
+            bytes_sent: sent,
+            packets_received: 0,
+            packets_sent: 0,
+            download_speed_bps: 0.0,
+            upload_speed_bps: 0.0,
+            calculation_confidence: confidence,
+            time_since_last_update: 1.0,
+        }])
+    }
+
+    fn snapshot(collection: u64, taken_at: Instant, stats: Arc<Vec<BandwidthStats>>) -> BandwidthSnapshot {
+        BandwidthSnapshot { collection, taken_at, stats, error: None, failures: 0, counter_resets: 0 }
+    }
+
+    fn capture(packets: u64, dropped: u64) -> PacketStatistics {
+        PacketStatistics {
+            total_packets: packets,
+            total_bytes: 0,
+            dropped_packets: dropped,
+            packets_per_second: 0.0,
+            bytes_per_second: 0.0,
+            protocol_distribution: ProtocolDistribution::default(),
+            top_connections: Vec::new(),
+            packet_sizes: PacketSizeDistribution::default(),
+            start_time: Local::now(),
+            end_time: Local::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_readings_are_credited_to_their_confidence() {
+        let start = Instant::now();
+        let (sender, receiver) = watch::channel(snapshot(1, start, eth0(1_000, 500, CalculationConfidence::High)));
+        let mut monitor = QualityMonitor::new("eth0".to_string(), receiver);
+
+        let stats = eth0(21_000, 3_500, CalculationConfidence::Low);
+        sender
+            .send(BandwidthSnapshot { counter_resets: 1, ..snapshot(2, start + Duration::from_secs(10), Arc::clone(&stats)) })
+            .unwrap();
+        monitor.poll().await;
+        // A failed collection keeps the last reading and has no confidence
+        sender
+            .send(BandwidthSnapshot {
+                error: Some("interfaces unavailable".to_string()),
+                failures: 1,
+                counter_resets: 1,
+                ..snapshot(3, start + Duration::from_secs(15), stats)
+            })
+            .unwrap();
+        monitor.poll().await;
+
+        let samples = monitor.sample(&capture(100, 2));
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].confidence.clone(), samples[0].duration_secs), (CalculationConfidence::Low, 10.0));
+        assert_eq!((samples[1].confidence.clone(), samples[1].duration_secs), (CalculationConfidence::None, 5.0));
+        // The interval's counters ride on the first sample only
+        assert_eq!(
+            (samples[0].counter_resets, samples[0].collection_failures, samples[0].packets_captured, samples[0].packets_dropped),
+            (1, 1, 100, 2)
+        );
+        assert_eq!((samples[1].counter_resets, samples[1].collection_failures), (0, 0));
+
+        let bandwidth = monitor.take_bandwidth_samples();
+        assert_eq!(bandwidth.len(), 1);
+        assert_eq!(bandwidth[0].duration_secs, 15.0);
+        assert_eq!((bandwidth[0].download_bps, bandwidth[0].upload_bps), (20_000.0 / 15.0, 3_000.0 / 15.0));
+        // Nothing has been read since
+        assert!(monitor.sample(&capture(100, 2)).is_empty());
+    }
+}
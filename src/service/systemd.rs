@@ -0,0 +1,179 @@
+// systemd integration: writes a system unit that runs `kw service run` at boot
+// The daemon runs as root with its capabilities bounded to the two packet capture needs
+// (CAP_NET_RAW, CAP_NET_ADMIN) and logs to the journal through stderr
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::service::SERVICE_NAME;
+
+/// Description shown by `systemctl status`
+const UNIT_DESCRIPTION: &str = "kaipo-watcher network traffic monitor";
+
+/// Where the unit file is installed; `kw purge` looks for it here as well
+pub fn unit_path() -> PathBuf {
+    PathBuf::from(format!("/etc/systemd/system/{SERVICE_NAME}.service"))
+}
+
+/// Default data directory for the service: `/var/lib/kaipo-watcher/data`
+pub fn default_data_dir() -> PathBuf {
+    PathBuf::from("/var/lib").join(SERVICE_NAME).join("data")
+}
+
+/// Writes the unit, then enables and starts it
+pub fn install(interface: &str, data_dir: &Path) -> Result<()> {
+    if !nix::unistd::Uid::effective().is_root() {
+        bail!("Installing a systemd service needs root; run `sudo kw service install`");
+    }
+    let exe = std::env::current_exe().context("Failed to locate kw executable")?;
+    let data_dir = std::path::absolute(data_dir).context("Failed to resolve data directory")?;
+    std::fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create data directory {}", data_dir.display()))?;
+
+    let unit = render_unit(&exe, interface, &data_dir, crate::config::config_path().as_deref());
+    let path = unit_path();
+    std::fs::write(&path, unit).with_context(|| format!("Failed to write {}", path.display()))?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", &format!("{SERVICE_NAME}.service")])?;
+
+    info!("Installed systemd unit {}", path.display());
+    Ok(())
+}
+
+/// Stops, disables and removes the unit
+pub fn uninstall() -> Result<()> {
+    let path = unit_path();
+    if !path.is_file() {
+        bail!("{} is not installed ({} not found)", SERVICE_NAME, path.display());
+    }
+    if !nix::unistd::Uid::effective().is_root() {
+        bail!("Removing a systemd service needs root; run `sudo kw service uninstall`");
+    }
+    systemctl(&["disable", "--now", &format!("{SERVICE_NAME}.service")])?;
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    systemctl(&["daemon-reload"])?;
+    Ok(())
+}
+
+/// Active and enabled state of the unit (e.g. "active (running), enabled"), `None` when not installed
+pub fn status() -> Result<Option<String>> {
+    if !unit_path().is_file() {
+        return Ok(None);
+    }
+    let output = Command::new("systemctl")
+        .args(["show", "--property=ActiveState,SubState,UnitFileState", &format!("{SERVICE_NAME}.service")])
+        .output()
+        .context("Failed to run systemctl")?;
+    if !output.status.success() {
+        bail!("systemctl show failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(Some(parse_show(&String::from_utf8_lossy(&output.stdout))))
+}
+
+/// Runs `systemctl`, turning a non-zero exit into an error carrying its message
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("systemctl")
+        .args(args)
+        .output()
+        .context("Failed to run systemctl")?;
+    if !output.status.success() {
+        bail!("systemctl {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// The unit file `install` writes
+fn render_unit(exe: &Path, interface: &str, data_dir: &Path, config: Option<&Path>) -> String {
+    let (exe, data_dir) = (exe.to_string_lossy(), data_dir.to_string_lossy());
+    let exec_start = [exe.as_ref(), "service", "run", "--interface", interface, "--data-dir", data_dir.as_ref()]
+        .map(quote)
+        .join(" ");
+    // `kw` run as root finds a different configuration file than the user who installed it
+    let config = config
+        .map(|path| format!("Environment={}\n", quote(&format!("KW_CONFIG={}", path.display()))))
+        .unwrap_or_default();
+
+    // Restarts a minute after a crash, as the Windows service does. Capture needs raw
+    // sockets and promiscuous mode, so root keeps only those two capabilities
+    format!(
+        "[Unit]
+Description={UNIT_DESCRIPTION}
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+ExecStart={exec_start}
+Environment=RUST_LOG=info
+{config}Restart=on-failure
+RestartSec=60
+CapabilityBoundingSet=CAP_NET_RAW CAP_NET_ADMIN
+AmbientCapabilities=CAP_NET_RAW CAP_NET_ADMIN
+NoNewPrivileges=yes
+ProtectSystem=full
+ProtectHome=read-only
+PrivateTmp=yes
+
+[Install]
+WantedBy=multi-user.target
+"
+    )
+}
+
+/// Quotes one word of a unit file command line or assignment
+/// `%` and `$` are doubled so systemd does not expand them as specifiers or variables
+fn quote(word: &str) -> String {
+    let escaped = word.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\')) {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Turns `systemctl show` output into e.g. "active (running), enabled"
+fn parse_show(output: &str) -> String {
+    let property = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .unwrap_or("unknown")
+    };
+    format!("{} ({}), {}", property("ActiveState"), property("SubState"), property("UnitFileState"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_quotes_paths_and_pins_config() {
+        let unit = render_unit(
+            Path::new("/opt/kw tools/kw"),
+            "eth0",
+            Path::new("/var/lib/kaipo-watcher/data"),
+            Some(Path::new("/etc/kaipo-watcher.toml")),
+        );
+        assert!(unit.contains(
+            "ExecStart=\"/opt/kw tools/kw\" service run --interface eth0 --data-dir /var/lib/kaipo-watcher/data\n"
+        ));
+        assert!(unit.contains("Environment=KW_CONFIG=/etc/kaipo-watcher.toml\n"));
+        assert!(unit.contains("AmbientCapabilities=CAP_NET_RAW CAP_NET_ADMIN\n"));
+        assert!(unit.ends_with("WantedBy=multi-user.target\n"));
+    }
+
+    #[test]
+    fn test_quote_escapes_specifiers() {
+        assert_eq!(quote("eth0"), "eth0");
+        assert_eq!(quote("50%"), "50%%");
+        assert_eq!(quote("my \"lan\""), r#""my \"lan\"""#);
+        assert_eq!(quote(""), "\"\"");
+    }
+
+    #[test]
+    fn test_parse_show() {
+        let output = "ActiveState=active\nSubState=running\nUnitFileState=enabled\n";
+        assert_eq!(parse_show(output), "active (running), enabled");
+        assert_eq!(parse_show(""), "unknown (unknown), unknown");
+    }
+}
@@ -0,0 +1,567 @@
+// Web UI: a small HTTP server run by the daemon when `[web] listen` is configured, or on
+// its own by `kw serve`. Serves one compiled-in page plus a JSON API with live speeds, the
+// last hour of history, stored bandwidth history, usage totals and recent security alerts.
+// `/api/ws` upgrades to a WebSocket that pushes every live reading as it is taken, for
+// pages served from this same address only. Routed by axum like `kw api`, with storage
+// queries run off the async threads
+
+use anyhow::{Context, Result};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::header::{CACHE_CONTROL, HOST, ORIGIN};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{middleware, Json, Router};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, watch};
+use tokio::time::interval;
+
+use crate::collectors::bandwidth_collector::{BandwidthStats, InterfaceType};
+use crate::cli::period::Period;
+use crate::collectors::collector_service::BandwidthSnapshot;
+use crate::storage::packet_storage::BandwidthSample;
+use crate::storage::PacketStorage;
+
+/// The single page, compiled into the binary
+const INDEX_HTML: &str = include_str!("assets/index.html");
+
+/// Where `kw serve` listens without `--web` or `[web] listen`
+pub const DEFAULT_ADDRESS: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 8080);
+
+/// How often live speeds are read; the daemon's collector service reads at least this often
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Speed history kept in memory: one hour at `SAMPLE_INTERVAL`
+const HISTORY_POINTS: usize = 1800;
+
+/// Alerts returned by `/api/alerts`
+const ALERT_LIMIT: usize = 50;
+
+/// Points in a `/api/bandwidth` chart, whatever its period
+const BANDWIDTH_BUCKETS: i64 = 288;
+
+/// Readings queued for a WebSocket client; a slower one skips to the newest
+const PUSH_QUEUE: usize = 4;
+
+/// Largest message a WebSocket client may send; browsers only send control frames here
+const MAX_CLIENT_MESSAGE: usize = 4096;
+
+/// Combined speed of all non-loopback interfaces at one moment
+#[derive(Debug, Clone, Serialize)]
+struct SpeedPoint {
+    timestamp: DateTime<Utc>,
+    download_bps: f64,
+    upload_bps: f64,
+}
+
+#[derive(Debug, Default)]
+struct LiveSpeeds {
+    interfaces: Vec<BandwidthStats>,
+    history: VecDeque<SpeedPoint>,
+}
+
+impl LiveSpeeds {
+    fn record(&mut self, stats: Vec<BandwidthStats>) {
+        let external = stats.iter().filter(|s| s.interface_type != InterfaceType::Loopback);
+        let (download_bps, upload_bps) = external.fold((0.0, 0.0), |(down, up), s| {
+            (down + s.download_speed_bps, up + s.upload_speed_bps)
+        });
+        if self.history.len() == HISTORY_POINTS {
+            self.history.pop_front();
+        }
+        self.history.push_back(SpeedPoint {
+            timestamp: Utc::now(),
+            download_bps,
+            upload_bps,
+        });
+        self.interfaces = stats;
+    }
+}
+
+/// What every request handler shares
+#[derive(Clone)]
+struct WebState {
+    storage: Arc<PacketStorage>,
+    live: Arc<Mutex<LiveSpeeds>>,
+    /// Each reading as the JSON pushed to WebSocket clients
+    updates: broadcast::Sender<Arc<str>>,
+}
+
+/// Browser dashboard served next to the monitoring daemon
+pub struct WebUi {
+    listener: TcpListener,
+    snapshots: watch::Receiver<BandwidthSnapshot>,
+    state: WebState,
+}
+
+impl WebUi {
+    /// Binds the listening socket so a taken port fails daemon start-up right away
+    /// Live speeds come from the readings on `snapshots`
+    pub async fn bind(
+        addr: SocketAddr,
+        storage: Arc<PacketStorage>,
+        snapshots: watch::Receiver<BandwidthSnapshot>,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen for the web UI on {addr}"))?;
+        Ok(Self {
+            listener,
+            snapshots,
+            state: WebState {
+                storage,
+                live: Arc::new(Mutex::new(LiveSpeeds::default())),
+                updates: broadcast::channel(PUSH_QUEUE).0,
+            },
+        })
+    }
+
+    /// The bound address, with the port chosen when binding to port 0
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Samples speeds and answers requests until the task is aborted
+    pub async fn run(self) {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("Web UI listening on http://{addr}");
+        }
+        let server = axum::serve(self.listener, router(self.state.clone())).into_future();
+        tokio::select! {
+            result = server => {
+                if let Err(e) = result {
+                    warn!("Web UI server failed: {e}");
+                }
+            }
+            _ = sample(self.snapshots, self.state) => {}
+        }
+    }
+}
+
+/// Adds each reading on `snapshots` to the history and pushes it to WebSocket clients
+async fn sample(mut snapshots: watch::Receiver<BandwidthSnapshot>, state: WebState) {
+    let mut sample_interval = interval(SAMPLE_INTERVAL);
+    loop {
+        sample_interval.tick().await;
+        // Readings between ticks are skipped so the history keeps its spacing
+        if !snapshots.has_changed().unwrap_or(false) {
+            continue;
+        }
+        let snapshot = snapshots.borrow_and_update().clone();
+        match snapshot.error {
+            None => {
+                state.live.lock().unwrap().record(snapshot.stats.to_vec());
+                if state.updates.receiver_count() > 0 {
+                    match query(&state, live_speeds).await {
+                        Ok(update) => drop(state.updates.send(update.to_string().into())),
+                        Err(e) => warn!("Web UI failed to prepare a live update: {e:#}"),
+                    }
+                }
+            }
+            Some(e) => debug!("Web UI bandwidth reading failed: {e}"),
+        }
+    }
+}
+
+fn router(state: WebState) -> Router {
+    Router::new()
+        .route("/", get(index))
+        .route("/index.html", get(index))
+        .route("/api/live", get(live))
+        .route("/api/history", get(history))
+        .route("/api/bandwidth", get(bandwidth))
+        .route("/api/usage", get(usage_totals))
+        .route("/api/alerts", get(recent_alerts))
+        .route("/api/ws", get(websocket))
+        .fallback(|| async { (StatusCode::NOT_FOUND, "404 Not Found") })
+        .layer(middleware::map_response(no_store))
+        .with_state(state)
+}
+
+/// Live readings go stale within seconds, so nothing is cached
+async fn no_store(mut response: Response) -> Response {
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+/// Runs `read` on the storage off the async threads
+async fn query<F>(state: &WebState, read: F) -> Result<serde_json::Value>
+where
+    F: FnOnce(&PacketStorage, &Mutex<LiveSpeeds>) -> Result<serde_json::Value> + Send + 'static,
+{
+    let (storage, live) = (Arc::clone(&state.storage), Arc::clone(&state.live));
+    tokio::task::spawn_blocking(move || read(&storage, &live))
+        .await
+        .context("The web UI query panicked")?
+}
+
+/// The JSON answer to a request for `path`, or a 500 after logging why it failed
+fn respond(path: &str, result: Result<serde_json::Value>) -> Response {
+    match result {
+        Ok(value) => Json(value).into_response(),
+        Err(e) => {
+            warn!("Web UI request for {path} failed: {e:#}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "500 Internal Server Error").into_response()
+        }
+    }
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+async fn live(State(state): State<WebState>) -> Response {
+    respond("/api/live", query(&state, live_speeds).await)
+}
+
+async fn history(State(state): State<WebState>) -> Json<serde_json::Value> {
+    Json(json!({ "points": state.live.lock().unwrap().history }))
+}
+
+/// `?period=`, read like `--period`
+#[derive(Debug, Deserialize)]
+struct PeriodQuery {
+    #[serde(default = "default_period")]
+    period: String,
+}
+
+fn default_period() -> String {
+    "day".to_string()
+}
+
+impl PeriodQuery {
+    /// The period asked for; none if it does not parse, to answer with a 400
+    fn period(&self) -> Option<Period> {
+        Period::parse(&self.period, Local::now())
+            .inspect_err(|e| debug!("Web UI request for period {:?}: {e:#}", self.period))
+            .ok()
+    }
+}
+
+const BAD_REQUEST: (StatusCode, &str) = (StatusCode::BAD_REQUEST, "400 Bad Request");
+
+async fn bandwidth(State(state): State<WebState>, Query(query_params): Query<PeriodQuery>) -> Response {
+    let Some(period) = query_params.period() else {
+        return BAD_REQUEST.into_response();
+    };
+    let value = query_params.period;
+    respond(
+        "/api/bandwidth",
+        query(&state, move |storage, _| bandwidth_history(storage, &value, &period)).await,
+    )
+}
+
+async fn usage_totals(State(state): State<WebState>, Query(query_params): Query<PeriodQuery>) -> Response {
+    let Some(period) = query_params.period() else {
+        return BAD_REQUEST.into_response();
+    };
+    let value = query_params.period;
+    respond("/api/usage", query(&state, move |storage, _| usage(storage, &value, &period)).await)
+}
+
+async fn recent_alerts(State(state): State<WebState>) -> Response {
+    respond("/api/alerts", query(&state, |storage, _| alerts(storage)).await)
+}
+
+/// Upgrades to a WebSocket pushing live readings, for pages served from this address
+/// Browsers send the page's origin with every upgrade, so another site's page cannot
+/// open one; clients that are not browsers send none and are let in
+async fn websocket(State(state): State<WebState>, headers: HeaderMap, upgrade: WebSocketUpgrade) -> Response {
+    if !same_origin(&headers) {
+        debug!("Web UI refused a WebSocket from origin {:?}", headers.get(ORIGIN));
+        return (StatusCode::FORBIDDEN, "403 Forbidden").into_response();
+    }
+    let first = match query(&state, live_speeds).await {
+        Ok(first) => first.to_string(),
+        Err(e) => return respond("/api/ws", Err(e)),
+    };
+    let updates = state.updates.subscribe();
+    upgrade
+        .max_message_size(MAX_CLIENT_MESSAGE)
+        .on_upgrade(move |socket| push_live(socket, first, updates))
+}
+
+/// Whether the request carries no `Origin`, or one naming the host it was sent to
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(ORIGIN) else {
+        return true;
+    };
+    let (Ok(origin), Some(Ok(host))) = (origin.to_str(), headers.get(HOST).map(HeaderValue::to_str)) else {
+        return false;
+    };
+    origin
+        .split_once("://")
+        .is_some_and(|(scheme, authority)| matches!(scheme, "http" | "https") && authority.eq_ignore_ascii_case(host))
+}
+
+/// Sends `first` and every later reading until the client closes the connection or stops
+/// answering; axum answers its pings and close
+async fn push_live(mut socket: WebSocket, first: String, mut updates: broadcast::Receiver<Arc<str>>) {
+    if socket.send(Message::Text(first)).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) => {
+                    if socket.send(Message::Text(update.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Current speeds per interface, with the user's interface tags keyed by name and the
+/// combined speed just added to the history
+fn live_speeds(storage: &PacketStorage, live: &Mutex<LiveSpeeds>) -> Result<serde_json::Value> {
+    let tags = storage.tag_book()?;
+    let live = live.lock().unwrap();
+    let labels: serde_json::Map<_, _> = live
+        .interfaces
+        .iter()
+        .filter_map(|s| tags.label(&s.interface_name).map(|label| (s.interface_name.clone(), json!(label))))
+        .collect();
+    Ok(json!({ "interfaces": live.interfaces, "labels": labels, "point": live.history.back() }))
+}
+
+/// Combined speed of the non-loopback interfaces over `?period=`, from the bandwidth samples
+/// `kw service run` stores, averaged into `BANDWIDTH_BUCKETS` buckets; empty buckets are left out
+fn bandwidth_history(storage: &PacketStorage, value: &str, period: &Period) -> Result<serde_json::Value> {
+    let bucket_secs = (period.length().num_seconds() / BANDWIDTH_BUCKETS).max(1);
+    let samples = storage.get_bandwidth_samples(period.start, period.end)?;
+    let points: Vec<_> = bucket_samples(&samples, period.start, bucket_secs)
+        .into_iter()
+        .map(|(timestamp, download_bps, upload_bps)| {
+            json!({ "timestamp": timestamp.to_rfc3339(), "download_bps": download_bps, "upload_bps": upload_bps })
+        })
+        .collect();
+    Ok(json!({ "period": value, "bucket_secs": bucket_secs, "points": points }))
+}
+
+/// (bucket start, download, upload) of every bucket with samples; each rate is the bytes moved
+/// in the bucket over its length
+fn bucket_samples(
+    samples: &[BandwidthSample],
+    start: DateTime<Local>,
+    bucket_secs: i64,
+) -> Vec<(DateTime<Local>, f64, f64)> {
+    let mut buckets: BTreeMap<i64, (f64, f64)> = BTreeMap::new();
+    for sample in samples.iter().filter(|s| !s.is_loopback()) {
+        let bucket = (sample.timestamp - start).num_seconds().max(0) / bucket_secs;
+        let bytes = buckets.entry(bucket).or_default();
+        bytes.0 += sample.download_bps * sample.duration_secs;
+        bytes.1 += sample.upload_bps * sample.duration_secs;
+    }
+    buckets
+        .into_iter()
+        .map(|(bucket, (download, upload))| {
+            let timestamp = start + chrono::Duration::seconds(bucket * bucket_secs);
+            (timestamp, download / bucket_secs as f64, upload / bucket_secs as f64)
+        })
+        .collect()
+}
+
+/// Traffic totals for `?period=` (default day)
+fn usage(storage: &PacketStorage, value: &str, period: &Period) -> Result<serde_json::Value> {
+    let usage = storage.get_usage_summary(period.start, period.end)?;
+    let applications: Vec<_> = usage
+        .applications
+        .iter()
+        .map(|(name, packets, bytes)| json!({ "name": name, "packets": packets, "bytes": bytes }))
+        .collect();
+    let tags = storage.tag_book()?;
+    let hosts: Vec<_> = usage
+        .hosts
+        .iter()
+        .map(|(address, packets, bytes)| {
+            json!({ "address": address, "label": tags.label(address), "packets": packets, "bytes": bytes })
+        })
+        .collect();
+    Ok(json!({
+        "period": value,
+        "connections": usage.connections,
+        "packets": usage.packets,
+        "bytes": usage.bytes,
+        "applications": applications,
+        "hosts": hosts,
+    }))
+}
+
+fn alerts(storage: &PacketStorage) -> Result<serde_json::Value> {
+    let tags = storage.tag_book()?;
+    let label = |address: &Option<String>| address.as_deref().and_then(|a| tags.label(a));
+    let alerts: Vec<_> = storage
+        .get_recent_security_events(ALERT_LIMIT)?
+        .into_iter()
+        .map(|event| {
+            json!({
+                "timestamp": event.timestamp.to_rfc3339(),
+                "type": event.event_type,
+                "severity": event.severity,
+                "description": event.description,
+                "source": event.source_ip,
+                "source_label": label(&event.source_ip),
+                "destination": event.dest_ip,
+                "destination_label": label(&event.dest_ip),
+                "port": event.port,
+            })
+        })
+        .collect();
+    Ok(json!({ "alerts": alerts }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Tag;
+    use crate::storage::packet_storage::SecurityEvent;
+    use tempfile::tempdir;
+
+    /// Serves the web UI on a free local port
+    async fn serve(storage: PacketStorage) -> SocketAddr {
+        let (_readings, snapshots) = watch::channel(BandwidthSnapshot {
+            collection: 0,
+            taken_at: std::time::Instant::now(),
+            stats: Arc::new(Vec::new()),
+            error: None,
+            failures: 0,
+            counter_resets: 0,
+        });
+        let web_ui = WebUi::bind("127.0.0.1:0".parse().unwrap(), Arc::new(storage), snapshots).await.unwrap();
+        let address = web_ui.local_addr().unwrap();
+        tokio::spawn(web_ui.run());
+        address
+    }
+
+    async fn get(address: SocketAddr, path: &str) -> (u16, serde_json::Value) {
+        let response = reqwest::get(format!("http://{address}{path}")).await.unwrap();
+        let status = response.status().as_u16();
+        (status, serde_json::from_str(&response.text().await.unwrap()).unwrap_or_default())
+    }
+
+    /// Status line of a WebSocket upgrade of `/api/ws` sent with `origin`
+    async fn upgrade(address: SocketAddr, origin: Option<&str>) -> String {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let origin = origin.map(|origin| format!("Origin: {origin}\r\n")).unwrap_or_default();
+        let request = format!(
+            "GET /api/ws HTTP/1.1\r\nHost: {address}\r\n{origin}Upgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).await.unwrap();
+        status.trim_end().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap();
+        storage
+            .store_security_event(SecurityEvent {
+                timestamp: Local::now(),
+                interface_name: "eth0".to_string(),
+                event_type: "suspicious_port".to_string(),
+                source_ip: Some("192.168.1.10".to_string()),
+                dest_ip: Some("203.0.113.5".to_string()),
+                port: Some(31337),
+                protocol: Some("Tcp".to_string()),
+                description: "Connection to suspicious port".to_string(),
+                severity: "high".to_string(),
+            })
+            .unwrap();
+        storage.flush_all().unwrap();
+        storage.set_tag(&Tag::new("192.168.1.10", "kid's tablet", None)).unwrap();
+        let address = serve(storage).await;
+
+        let page = reqwest::get(format!("http://{address}/")).await.unwrap();
+        assert!(page.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+        assert_eq!(page.headers()["cache-control"], "no-store");
+        assert!(page.text().await.unwrap().contains("/api/live"));
+
+        let (status, usage) = get(address, "/api/usage?period=week").await;
+        assert_eq!(status, 200);
+        assert_eq!(usage["period"], "week");
+        assert_eq!(usage["bytes"], 0);
+
+        assert_eq!(get(address, "/api/usage?period=2").await.0, 400);
+        assert_eq!(get(address, "/api/usage?period=2w..1w").await.0, 200);
+
+        let (_, bandwidth) = get(address, "/api/bandwidth").await;
+        assert_eq!(bandwidth["period"], "day");
+        assert_eq!(bandwidth["bucket_secs"], 300);
+        assert_eq!(bandwidth["points"], json!([]));
+
+        let (_, alerts) = get(address, "/api/alerts").await;
+        assert_eq!(alerts["alerts"][0]["port"], 31337);
+        assert_eq!(alerts["alerts"][0]["source_label"], "kid's tablet");
+        assert!(alerts["alerts"][0]["destination_label"].is_null());
+        assert_eq!(get(address, "/missing").await.0, 404);
+        let post = reqwest::Client::new().post(format!("http://{address}/api/live")).send().await.unwrap();
+        assert_eq!(post.status().as_u16(), 405);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_is_refused_to_other_sites() {
+        let temp_dir = tempdir().unwrap();
+        let address = serve(PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap()).await;
+
+        assert_eq!(upgrade(address, None).await, "HTTP/1.1 101 Switching Protocols");
+        assert_eq!(upgrade(address, Some(&format!("http://{address}"))).await, "HTTP/1.1 101 Switching Protocols");
+        assert_eq!(upgrade(address, Some("https://evil.example")).await, "HTTP/1.1 403 Forbidden");
+        assert_eq!(upgrade(address, Some("null")).await, "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn test_bucket_samples() {
+        let start = Local::now() - chrono::Duration::hours(1);
+        let sample = |minutes: i64, interface: &str, download_bps: f64| BandwidthSample {
+            timestamp: start + chrono::Duration::minutes(minutes),
+            interface_name: interface.to_string(),
+            duration_secs: 60.0,
+            download_bps,
+            upload_bps: 0.0,
+        };
+        let samples = [
+            sample(1, "eth0", 1000.0),
+            sample(2, "eth0", 3000.0),
+            sample(2, "lo", 1e9),
+            sample(2, "nas/lo", 1e9),
+            sample(20, "wlan0", 600.0),
+        ];
+        let buckets = bucket_samples(&samples, start, 600);
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].1, 400.0);
+        assert_eq!(buckets[1].0, start + chrono::Duration::minutes(20));
+        assert_eq!(buckets[1].1, 60.0);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut live = LiveSpeeds::default();
+        for _ in 0..HISTORY_POINTS + 10 {
+            live.record(Vec::new());
+        }
+        assert_eq!(live.history.len(), HISTORY_POINTS);
+    }
+}
@@ -0,0 +1,486 @@
+// Windows service wrapper: registers kw with the Service Control Manager (SCM),
+// hosts the monitoring daemon under SCM control and logs to the Windows event log
+
+use anyhow::{bail, Context, Result};
+use log::{error, info, warn, Level, LevelFilter, Log, Metadata, Record};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::ptr;
+use std::sync::OnceLock;
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+
+use super::MonitorDaemon;
+use crate::service::SERVICE_NAME;
+use crate::shutdown;
+
+/// Human readable name shown in the Services console
+pub const SERVICE_DISPLAY_NAME: &str = "Kaipo Watcher";
+
+/// Description registered alongside the service
+const SERVICE_DESCRIPTION: &str =
+    "Captures and records network traffic statistics for kaipo-watcher (kw)";
+
+/// Registry key registering the service as an Application event log source
+const EVENT_SOURCE_KEY: &str =
+    r"HKLM\SYSTEM\CurrentControlSet\Services\EventLog\Application\kaipo-watcher";
+
+/// Message file whose message table (IDs 1-1000) prints the logged string verbatim
+const EVENT_MESSAGE_FILE: &str = r"%SystemRoot%\System32\EventCreate.exe";
+
+/// Event ID used for every entry, within the range EventCreate.exe defines
+const EVENT_ID: u32 = 1;
+
+/// How long the SCM should wait for a pending start/stop before assuming a hang
+const PENDING_WAIT_HINT_MS: u32 = 10_000;
+
+/// Minimal advapi32 bindings for the service and event log APIs
+mod ffi {
+    use std::ffi::c_void;
+
+    pub type Handle = *mut c_void;
+
+    pub const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+    pub const SERVICE_STOPPED: u32 = 1;
+    pub const SERVICE_START_PENDING: u32 = 2;
+    pub const SERVICE_STOP_PENDING: u32 = 3;
+    pub const SERVICE_RUNNING: u32 = 4;
+    pub const SERVICE_ACCEPT_STOP: u32 = 0x1;
+    pub const SERVICE_ACCEPT_SHUTDOWN: u32 = 0x4;
+    pub const SERVICE_CONTROL_STOP: u32 = 1;
+    pub const SERVICE_CONTROL_INTERROGATE: u32 = 4;
+    pub const SERVICE_CONTROL_SHUTDOWN: u32 = 5;
+
+    pub const NO_ERROR: u32 = 0;
+    pub const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+    pub const ERROR_FAILED_SERVICE_CONTROLLER_CONNECT: i32 = 1063;
+    pub const ERROR_SERVICE_SPECIFIC_ERROR: u32 = 1066;
+    pub const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+
+    pub const EVENTLOG_ERROR_TYPE: u16 = 0x1;
+    pub const EVENTLOG_WARNING_TYPE: u16 = 0x2;
+    pub const EVENTLOG_INFORMATION_TYPE: u16 = 0x4;
+
+    #[repr(C)]
+    pub struct ServiceStatus {
+        pub service_type: u32,
+        pub current_state: u32,
+        pub controls_accepted: u32,
+        pub win32_exit_code: u32,
+        pub service_specific_exit_code: u32,
+        pub check_point: u32,
+        pub wait_hint: u32,
+    }
+
+    pub type ServiceMain = unsafe extern "system" fn(argc: u32, argv: *mut *mut u16);
+
+    pub type HandlerEx = unsafe extern "system" fn(
+        control: u32,
+        event_type: u32,
+        event_data: *mut c_void,
+        context: *mut c_void,
+    ) -> u32;
+
+    #[repr(C)]
+    pub struct ServiceTableEntry {
+        pub service_name: *mut u16,
+        pub service_proc: Option<ServiceMain>,
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        pub fn StartServiceCtrlDispatcherW(service_start_table: *const ServiceTableEntry) -> i32;
+        pub fn RegisterServiceCtrlHandlerExW(
+            service_name: *const u16,
+            handler: HandlerEx,
+            context: *mut c_void,
+        ) -> Handle;
+        pub fn SetServiceStatus(status_handle: Handle, status: *const ServiceStatus) -> i32;
+        pub fn RegisterEventSourceW(server_name: *const u16, source_name: *const u16) -> Handle;
+        pub fn ReportEventW(
+            event_log: Handle,
+            event_type: u16,
+            category: u16,
+            event_id: u32,
+            user_sid: *mut c_void,
+            num_strings: u16,
+            data_size: u32,
+            strings: *const *const u16,
+            raw_data: *mut c_void,
+        ) -> i32;
+    }
+}
+
+/// State shared with the SCM callbacks, which carry no user context of their own
+struct ServiceContext {
+    daemon: MonitorDaemon,
+    runtime: Handle,
+    stop: watch::Sender<bool>,
+    /// SERVICE_STATUS_HANDLE, stored as an integer so the context is `Sync`
+    status_handle: OnceLock<usize>,
+}
+
+static CONTEXT: OnceLock<ServiceContext> = OnceLock::new();
+
+/// Default data directory for the service: `%ProgramData%\kaipo-watcher\data`
+pub fn default_data_dir() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("kaipo-watcher")
+        .join("data")
+}
+
+/// Registers the service to start at boot, sets up its event log source and starts it
+pub fn install(interface: &str, data_dir: &Path) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate kw executable")?;
+    // Services start in System32, so the data directory must not be relative
+    let data_dir = std::path::absolute(data_dir).context("Failed to resolve data directory")?;
+    std::fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create data directory {}", data_dir.display()))?;
+
+    let command_line = service_command_line(&exe, interface, &data_dir);
+    sc(&[
+        "create",
+        SERVICE_NAME,
+        "binPath=",
+        &command_line,
+        "start=",
+        "auto",
+        "DisplayName=",
+        SERVICE_DISPLAY_NAME,
+    ])?;
+    sc(&["description", SERVICE_NAME, SERVICE_DESCRIPTION])?;
+    // Restart a minute after a crash; the failure count resets daily
+    sc(&["failure", SERVICE_NAME, "reset=", "86400", "actions=", "restart/60000"])?;
+    register_event_source()?;
+    sc(&["start", SERVICE_NAME])?;
+
+    info!("Installed Windows service {} running: {}", SERVICE_NAME, command_line);
+    Ok(())
+}
+
+/// Stops and removes the service and its event log source
+pub fn uninstall() -> Result<()> {
+    // Fails when the service is already stopped, which is fine here
+    if let Err(e) = sc(&["stop", SERVICE_NAME]) {
+        info!("{e}");
+    }
+    sc(&["delete", SERVICE_NAME])?;
+
+    let output = Command::new("reg")
+        .args(["delete", EVENT_SOURCE_KEY, "/f"])
+        .output()
+        .context("Failed to run reg.exe")?;
+    if !output.status.success() {
+        warn!("Event log source was not removed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(())
+}
+
+/// Current SCM state of the service (e.g. "RUNNING"), `None` when not installed
+pub fn status() -> Result<Option<String>> {
+    let output = Command::new("sc.exe")
+        .args(["query", SERVICE_NAME])
+        .output()
+        .context("Failed to run sc.exe")?;
+
+    if output.status.code() == Some(ffi::ERROR_SERVICE_DOES_NOT_EXIST) {
+        return Ok(None);
+    }
+    if !output.status.success() {
+        bail!("sc.exe query failed: {}", String::from_utf8_lossy(&output.stdout).trim());
+    }
+
+    let state = parse_state(&String::from_utf8_lossy(&output.stdout));
+    Ok(Some(state.unwrap_or_else(|| "UNKNOWN".to_string())))
+}
+
+/// Entry point for `kw service run`
+///
+/// Hands the process to the SCM dispatcher. When started from a console rather
+/// than by the SCM, runs the daemon in the foreground until Ctrl+C instead.
+pub async fn run(daemon: MonitorDaemon) -> Result<()> {
+    let (stop, _) = watch::channel(false);
+    let context = ServiceContext {
+        daemon,
+        runtime: Handle::current(),
+        stop,
+        status_handle: OnceLock::new(),
+    };
+    if CONTEXT.set(context).is_err() {
+        bail!("Service is already running in this process");
+    }
+
+    // Blocks until the service has stopped
+    let dispatched = tokio::task::spawn_blocking(|| {
+        let mut name = wide(SERVICE_NAME);
+        let table = [
+            ffi::ServiceTableEntry {
+                service_name: name.as_mut_ptr(),
+                service_proc: Some(service_main),
+            },
+            ffi::ServiceTableEntry {
+                service_name: ptr::null_mut(),
+                service_proc: None,
+            },
+        ];
+        if unsafe { ffi::StartServiceCtrlDispatcherW(table.as_ptr()) } != 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    })
+    .await
+    .context("Service dispatcher thread panicked")?;
+
+    match dispatched {
+        Ok(()) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(ffi::ERROR_FAILED_SERVICE_CONTROLLER_CONNECT) => {
+            env_logger::init();
+            println!("Not started by the Service Control Manager, running in the foreground (Ctrl+C to stop)");
+            let context = CONTEXT.get().context("Service context missing")?;
+            let summary = context.daemon.run(shutdown::requested()).await?;
+            println!("\n{summary}");
+            Ok(())
+        }
+        Err(e) => Err(e).context("Failed to connect to the Service Control Manager"),
+    }
+}
+
+/// ServiceMain callback, runs on a thread owned by the SCM dispatcher
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut *mut u16) {
+    EventLogLogger::install();
+
+    let Some(context) = CONTEXT.get() else { return };
+    let name = wide(SERVICE_NAME);
+    let handle =
+        unsafe { ffi::RegisterServiceCtrlHandlerExW(name.as_ptr(), control_handler, ptr::null_mut()) };
+    if handle.is_null() {
+        error!("Failed to register service control handler: {}", std::io::Error::last_os_error());
+        return;
+    }
+    let _ = context.status_handle.set(handle as usize);
+
+    set_status(ffi::SERVICE_START_PENDING, ffi::NO_ERROR);
+    set_status(ffi::SERVICE_RUNNING, ffi::NO_ERROR);
+
+    let mut stop = context.stop.subscribe();
+    let result = context.runtime.block_on(context.daemon.run(async move {
+        let _ = stop.wait_for(|stopped| *stopped).await;
+    }));
+
+    match result {
+        Ok(summary) => {
+            info!("{summary}");
+            set_status(ffi::SERVICE_STOPPED, ffi::NO_ERROR)
+        }
+        Err(e) => {
+            error!("Monitoring daemon failed: {e:#}");
+            set_status(ffi::SERVICE_STOPPED, ffi::ERROR_SERVICE_SPECIFIC_ERROR);
+        }
+    }
+}
+
+/// HandlerEx callback for stop/shutdown requests from the SCM
+unsafe extern "system" fn control_handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    match control {
+        ffi::SERVICE_CONTROL_STOP | ffi::SERVICE_CONTROL_SHUTDOWN => {
+            set_status(ffi::SERVICE_STOP_PENDING, ffi::NO_ERROR);
+            if let Some(context) = CONTEXT.get() {
+                context.stop.send_replace(true);
+            }
+            ffi::NO_ERROR
+        }
+        ffi::SERVICE_CONTROL_INTERROGATE => ffi::NO_ERROR,
+        _ => ffi::ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+fn set_status(state: u32, exit_code: u32) {
+    let Some(handle) = CONTEXT.get().and_then(|c| c.status_handle.get().copied()) else {
+        return;
+    };
+
+    let controls_accepted = if state == ffi::SERVICE_RUNNING {
+        ffi::SERVICE_ACCEPT_STOP | ffi::SERVICE_ACCEPT_SHUTDOWN
+    } else {
+        0
+    };
+    let pending = matches!(state, ffi::SERVICE_START_PENDING | ffi::SERVICE_STOP_PENDING);
+    let status = ffi::ServiceStatus {
+        service_type: ffi::SERVICE_WIN32_OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        win32_exit_code: exit_code,
+        service_specific_exit_code: u32::from(exit_code == ffi::ERROR_SERVICE_SPECIFIC_ERROR),
+        check_point: u32::from(pending),
+        wait_hint: if pending { PENDING_WAIT_HINT_MS } else { 0 },
+    };
+
+    if unsafe { ffi::SetServiceStatus(handle as ffi::Handle, &status) } == 0 {
+        warn!("Failed to report service status: {}", std::io::Error::last_os_error());
+    }
+}
+
+/// `log` backend writing to the Application event log under the service's source
+struct EventLogLogger {
+    /// Event source handle, stored as an integer so the logger is `Send + Sync`
+    source: usize,
+    level: LevelFilter,
+}
+
+impl EventLogLogger {
+    /// Installs the logger with the level from `RUST_LOG` (info when unset)
+    fn install() {
+        let name = wide(SERVICE_NAME);
+        let source = unsafe { ffi::RegisterEventSourceW(ptr::null(), name.as_ptr()) };
+        if source.is_null() {
+            return;
+        }
+
+        let level = std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(LevelFilter::Info);
+        let logger = Self { source: source as usize, level };
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+}
+
+impl Log for EventLogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let event_type = match record.level() {
+            Level::Error => ffi::EVENTLOG_ERROR_TYPE,
+            Level::Warn => ffi::EVENTLOG_WARNING_TYPE,
+            _ => ffi::EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(&format!("{}: {}", record.target(), record.args()));
+        let strings = [message.as_ptr()];
+        unsafe {
+            ffi::ReportEventW(
+                self.source as ffi::Handle,
+                event_type,
+                0,
+                EVENT_ID,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null_mut(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn register_event_source() -> Result<()> {
+    let values: [(&str, &str, &str); 2] = [
+        ("EventMessageFile", "REG_EXPAND_SZ", EVENT_MESSAGE_FILE),
+        ("TypesSupported", "REG_DWORD", "7"),
+    ];
+
+    for (name, kind, data) in values {
+        let output = Command::new("reg")
+            .args(["add", EVENT_SOURCE_KEY, "/v", name, "/t", kind, "/d", data, "/f"])
+            .output()
+            .context("Failed to run reg.exe")?;
+        if !output.status.success() {
+            bail!(
+                "Failed to register event log source: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `sc.exe`, turning a non-zero exit into an error carrying its message
+fn sc(args: &[&str]) -> Result<()> {
+    let output = Command::new("sc.exe")
+        .args(args)
+        .output()
+        .context("Failed to run sc.exe")?;
+
+    if !output.status.success() {
+        // sc.exe reports failures on stdout
+        bail!("sc.exe {} failed: {}", args[0], String::from_utf8_lossy(&output.stdout).trim());
+    }
+
+    Ok(())
+}
+
+/// Command line the SCM uses to start the service
+fn service_command_line(exe: &Path, interface: &str, data_dir: &Path) -> String {
+    format!(
+        "\"{}\" service run --interface \"{}\" --data-dir \"{}\"",
+        exe.display(),
+        interface,
+        data_dir.display()
+    )
+}
+
+/// Extracts the state name from `sc.exe query` output
+/// e.g. "        STATE              : 4  RUNNING" -> "RUNNING"
+fn parse_state(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() != "STATE" {
+            return None;
+        }
+        value.split_whitespace().nth(1).map(str::to_string)
+    })
+}
+
+fn wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_state_from_sc_query() {
+        let output = "\r\nSERVICE_NAME: kaipo-watcher\r\n        TYPE               : 10  WIN32_OWN_PROCESS\r\n        STATE              : 4  RUNNING\r\n                                (STOPPABLE, NOT_PAUSABLE, ACCEPTS_SHUTDOWN)\r\n";
+        assert_eq!(parse_state(output).as_deref(), Some("RUNNING"));
+        assert_eq!(parse_state("[SC] OpenService FAILED 1060"), None);
+    }
+
+    #[test]
+    fn test_service_command_line_quotes_paths() {
+        let line = service_command_line(
+            Path::new(r"C:\Program Files\kw\kw.exe"),
+            "Ethernet",
+            Path::new(r"C:\ProgramData\kaipo-watcher\data"),
+        );
+        assert_eq!(
+            line,
+            r#""C:\Program Files\kw\kw.exe" service run --interface "Ethernet" --data-dir "C:\ProgramData\kaipo-watcher\data""#
+        );
+    }
+
+    #[test]
+    fn test_wide_strings_are_nul_terminated() {
+        assert_eq!(wide("kw"), vec![b'k' as u16, b'w' as u16, 0]);
+    }
+}
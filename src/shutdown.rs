@@ -0,0 +1,150 @@
+// Shutdown: Ctrl+C, and SIGTERM on Unix, as one request every long-running command stops on
+// Commands wait on `requested()` rather than each listening for Ctrl+C, so however the process
+// is asked to stop, capture is stopped, buffered records are written and a summary of the
+// session is printed. A second signal while that is under way exits at once
+
+use log::{info, warn};
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Exit status after a second signal, as shells report a process ended by SIGINT
+const FORCED_EXIT_CODE: i32 = 130;
+
+/// Turns true once a shutdown is requested
+static REQUESTED: OnceLock<watch::Receiver<bool>> = OnceLock::new();
+
+/// Completes once a shutdown is requested
+///
+/// The first call, or the first `is_requested`, starts listening for the signals; until then
+/// they end the process as usual, so short commands need nothing of this
+pub async fn requested() {
+    let mut receiver = listener().clone();
+    // The sender is kept by the listening task, which only ends with the process
+    let _ = receiver.wait_for(|requested| *requested).await;
+}
+
+/// Whether a shutdown has been requested, for loops that poll rather than await
+pub fn is_requested() -> bool {
+    *listener().borrow()
+}
+
+fn listener() -> &'static watch::Receiver<bool> {
+    REQUESTED.get_or_init(|| {
+        let (sender, receiver) = watch::channel(false);
+        tokio::spawn(listen(sender));
+        receiver
+    })
+}
+
+async fn listen(requested: watch::Sender<bool>) {
+    let mut signals = Signals::new();
+    let signal = signals.next().await;
+    info!("{signal} received, shutting down");
+    requested.send_replace(true);
+
+    let signal = signals.next().await;
+    eprintln!("\n🛑 {signal} again: exiting without finishing the shutdown");
+    std::process::exit(FORCED_EXIT_CODE);
+}
+
+/// The signals that ask kw to stop
+struct Signals {
+    #[cfg(unix)]
+    terminate: Option<tokio::signal::unix::Signal>,
+}
+
+impl Signals {
+    fn new() -> Self {
+        Self {
+            // Service managers and `kill` stop processes with SIGTERM
+            #[cfg(unix)]
+            terminate: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .inspect_err(|e| warn!("SIGTERM will end kw without a clean shutdown: {e}"))
+                .ok(),
+        }
+    }
+
+    /// Waits for the next signal and names it
+    async fn next(&mut self) -> &'static str {
+        #[cfg(unix)]
+        if let Some(terminate) = &mut self.terminate {
+            return tokio::select! {
+                _ = ctrl_c() => "Ctrl+C",
+                _ = terminate.recv() => "SIGTERM",
+            };
+        }
+        ctrl_c().await;
+        "Ctrl+C"
+    }
+}
+
+/// Completes on Ctrl+C, or never when it cannot be listened for
+async fn ctrl_c() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Ctrl+C will end kw without a clean shutdown: {e}");
+        std::future::pending::<()>().await;
+    }
+}
+
+/// What a session did, printed as the command stops
+pub struct SessionSummary {
+    title: String,
+    started: Instant,
+    lines: Vec<(String, String)>,
+}
+
+impl SessionSummary {
+    /// A summary of the session that began at `started`, its length on the first line
+    pub fn new(title: impl Into<String>, started: Instant) -> Self {
+        Self { title: title.into(), started, lines: Vec::new() }
+    }
+
+    pub fn add(&mut self, label: &str, value: impl fmt::Display) {
+        self.lines.push((label.to_string(), value.to_string()));
+    }
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = ("Duration".to_string(), format_elapsed(self.started.elapsed()));
+        let lines: Vec<_> = std::iter::once(&duration).chain(&self.lines).collect();
+        let width = lines.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0) + 1;
+        write!(f, "📋 {}", self.title)?;
+        for (label, value) in lines {
+            write!(f, "\n   {:<width$} {value}", format!("{label}:"))?;
+        }
+        Ok(())
+    }
+}
+
+/// `45s`, `12m 05s`, `3h 04m` or `2d 03h`
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h {:02}m", secs / 3600, secs / 60 % 60),
+        _ => format!("{}d {:02}h", secs / 86400, secs / 3600 % 24),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_aligns_its_values() {
+        let mut summary = SessionSummary::new("Capture summary", Instant::now());
+        summary.add("Packets", 1200);
+        summary.add("Written on exit", "35 records");
+        assert_eq!(
+            summary.to_string(),
+            "📋 Capture summary\n   Duration:        0s\n   Packets:         1200\n   Written on exit: 35 records"
+        );
+        assert_eq!(format_elapsed(Duration::from_secs(725)), "12m 05s");
+        assert_eq!(format_elapsed(Duration::from_secs(3 * 3600 + 4 * 60 + 59)), "3h 04m");
+        assert_eq!(format_elapsed(Duration::from_secs(2 * 86400 + 3 * 3600)), "2d 03h");
+    }
+}
@@ -0,0 +1,204 @@
+// Audit Log: Append-only, hash-chained record of security events, alerts and admin actions
+// Every entry's hash covers the hash of the entry before it, so changing, removing or
+// reordering an entry breaks the chain from that point on; `kw audit verify` recomputes it
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use super::packet_storage::parse_local_timestamp;
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A security event stored from a capture
+pub const SECURITY_EVENT: &str = "security_event";
+/// An alert shown or sent to someone
+pub const ALERT: &str = "alert";
+/// The daemon started with a configuration different from the last one recorded
+pub const CONFIG: &str = "config";
+/// A change made by a user, such as a tag
+pub const ADMIN: &str = "admin";
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: DateTime<Local>,
+    pub category: String,
+    pub action: String,
+    pub detail: String,
+    pub hash: String,
+}
+
+/// Outcome of recomputing the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditVerification {
+    /// Entries checked, up to and including the first broken one
+    pub entries: u64,
+    /// Hash of the newest entry when the chain is intact
+    pub head: Option<String>,
+    /// First entry that does not fit the chain, and why
+    pub broken: Option<(i64, String)>,
+}
+
+/// Position at the end of the chain, for appending several entries in one transaction
+pub struct AuditChain {
+    next_id: i64,
+    head: String,
+}
+
+impl AuditChain {
+    /// Continues from the newest entry in the database
+    pub fn load(conn: &Connection) -> Result<Self> {
+        let last: Option<(i64, String)> = conn
+            .query_row("SELECT id, hash FROM audit_log ORDER BY id DESC LIMIT 1", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        Ok(match last {
+            Some((id, hash)) => Self { next_id: id + 1, head: hash },
+            None => Self { next_id: 1, head: GENESIS_HASH.to_string() },
+        })
+    }
+
+    /// Appends an entry and returns its hash
+    ///
+    /// Ids are assigned here rather than by SQLite, so two writers appending to
+    /// the same chain position fail on the primary key instead of forking it.
+    pub fn append(&mut self, conn: &Connection, category: &str, action: &str, detail: &str) -> Result<String> {
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let hash = entry_hash(&self.head, self.next_id, &timestamp, category, action, detail);
+        conn.execute(
+            "INSERT INTO audit_log (id, timestamp, category, action, detail, prev_hash, hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![self.next_id, timestamp, category, action, detail, self.head, hash],
+        )?;
+        self.next_id += 1;
+        self.head = hash.clone();
+        Ok(hash)
+    }
+}
+
+/// SHA-256 over the previous hash and the entry's fields, each preceded by its
+/// length in bytes as a little-endian u64, in lower-case hex
+pub fn entry_hash(prev_hash: &str, id: i64, timestamp: &str, category: &str, action: &str, detail: &str) -> String {
+    let mut hasher = Sha256::new();
+    let id = id.to_string();
+    for field in [prev_hash, id.as_str(), timestamp, category, action, detail] {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Recomputes every hash in id order, stopping at the first entry that does not fit
+pub fn verify_chain(conn: &Connection) -> Result<AuditVerification> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, category, action, detail, prev_hash, hash FROM audit_log ORDER BY id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut expected_id = 1;
+    let mut head = GENESIS_HASH.to_string();
+    let mut entries = 0;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let timestamp: String = row.get(1)?;
+        let category: String = row.get(2)?;
+        let action: String = row.get(3)?;
+        let detail: String = row.get(4)?;
+        let prev_hash: String = row.get(5)?;
+        let hash: String = row.get(6)?;
+        entries += 1;
+
+        let problem = if id != expected_id {
+            Some(format!("expected entry {expected_id}, entries are missing"))
+        } else if prev_hash != head {
+            Some("previous hash does not match the entry before it".to_string())
+        } else if hash != entry_hash(&prev_hash, id, &timestamp, &category, &action, &detail) {
+            Some("contents do not match the stored hash".to_string())
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return Ok(AuditVerification { entries, head: None, broken: Some((id, problem)) });
+        }
+
+        expected_id = id + 1;
+        head = hash;
+    }
+
+    Ok(AuditVerification {
+        entries,
+        head: (entries > 0).then_some(head),
+        broken: None,
+    })
+}
+
+/// Id of the entry with `hash`, if the log has one
+pub fn find_hash(conn: &Connection, hash: &str) -> Result<Option<i64>> {
+    Ok(conn
+        .query_row("SELECT id FROM audit_log WHERE hash = ?1", params![hash.to_lowercase()], |row| row.get(0))
+        .optional()?)
+}
+
+/// Newest entries first, optionally of one category
+pub fn read_entries(conn: &Connection, category: Option<&str>, limit: usize) -> Result<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, category, action, detail, hash FROM audit_log
+         WHERE ?1 IS NULL OR category = ?1
+         ORDER BY id DESC
+         LIMIT ?2",
+    )?;
+    let entries = stmt
+        .query_map(params![category, limit as i64], |row| {
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                timestamp: parse_local_timestamp(&row.get::<_, String>(1)?),
+                category: row.get(2)?,
+                action: row.get(3)?,
+                detail: row.get(4)?,
+                hash: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::schema::create_tables;
+
+    #[test]
+    fn test_chain_detects_changed_and_removed_entries() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        assert_eq!(verify_chain(&conn).unwrap(), AuditVerification { entries: 0, head: None, broken: None });
+
+        let mut chain = AuditChain::load(&conn).unwrap();
+        chain.append(&conn, SECURITY_EVENT, "PortScan", "eth0 10.0.0.9: 20 ports probed").unwrap();
+        chain.append(&conn, ADMIN, "tag set", "10.0.0.9 = \"printer\"").unwrap();
+        let head = AuditChain::load(&conn).unwrap().append(&conn, ALERT, "live dashboard", "Port scan").unwrap();
+
+        let verification = verify_chain(&conn).unwrap();
+        assert_eq!(verification.broken, None);
+        assert_eq!((verification.entries, verification.head), (3, Some(head.clone())));
+        assert_eq!(read_entries(&conn, Some(ADMIN), 10).unwrap()[0].action, "tag set");
+        assert_eq!(find_hash(&conn, &head.to_uppercase()).unwrap(), Some(3));
+
+        // The log refuses changes; an editor bypassing the triggers is caught by the hashes
+        assert!(conn.execute("UPDATE audit_log SET detail = 'nothing' WHERE id = 1", []).is_err());
+        assert!(conn.execute("DELETE FROM audit_log WHERE id = 2", []).is_err());
+        conn.execute_batch("DROP TRIGGER audit_log_no_update; DROP TRIGGER audit_log_no_delete;").unwrap();
+
+        conn.execute("UPDATE audit_log SET detail = 'nothing' WHERE id = 1", []).unwrap();
+        assert_eq!(verify_chain(&conn).unwrap().broken.unwrap().0, 1);
+
+        conn.execute("DELETE FROM audit_log WHERE id = 1", []).unwrap();
+        let verification = verify_chain(&conn).unwrap();
+        assert_eq!(verification.broken.unwrap().0, 2);
+        assert_eq!(verification.head, None);
+    }
+}
@@ -0,0 +1,144 @@
+// StorageBackend: where the monitoring daemon writes what it collects
+// The daemon only sees this trait, so its records can go to the local SQLite database
+// (`PacketStorage`) or, with the `postgres` feature, to a PostgreSQL server several daemons share
+
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::analyzers::{AnalysisResult, ClassificationSample, DuplicateSample, LatencySample, MtuSample, TcpSession, ThrottledFlow};
+use crate::collectors::ping_collector::PingSample;
+use crate::config::{DatabaseBackend, StorageConfig};
+use crate::models::NetworkPacket;
+use crate::storage::packet_storage::{BandwidthSample, CaptureSample, CollectorHealthSample, InterfaceChange, QualitySample};
+use crate::storage::PacketStorage;
+
+/// Destination for captured packets and the samples taken alongside them
+///
+/// Writes may be buffered; `flush` writes everything pending and is called on every
+/// flush interval and at shutdown.
+pub trait StorageBackend: Send + Sync {
+    /// Records a captured packet and what the analyzer made of it
+    fn store_packet(&self, packet: &NetworkPacket, analysis: &AnalysisResult) -> Result<()>;
+
+    fn store_quality_sample(&self, sample: &QualitySample) -> Result<()>;
+
+    fn store_bandwidth_samples(&self, samples: &[BandwidthSample]) -> Result<()>;
+
+    fn store_capture_sample(&self, sample: &CaptureSample) -> Result<()>;
+
+    fn store_collector_health(&self, sample: &CollectorHealthSample) -> Result<()>;
+
+    fn store_interface_change(&self, change: &InterfaceChange) -> Result<()>;
+
+    fn store_tcp_sessions(&self, sessions: &[TcpSession]) -> Result<()>;
+
+    fn store_latency_samples(&self, samples: &[LatencySample]) -> Result<()>;
+
+    fn store_classification_samples(&self, samples: &[ClassificationSample]) -> Result<()>;
+
+    fn store_duplicate_samples(&self, samples: &[DuplicateSample]) -> Result<()>;
+
+    fn store_mtu_samples(&self, samples: &[MtuSample]) -> Result<()>;
+
+    fn store_throttled_flows(&self, flows: &[ThrottledFlow]) -> Result<()>;
+
+    fn store_ping_samples(&self, samples: &[PingSample]) -> Result<()>;
+
+    /// Records buffered and not yet written, reported as the storage backlog
+    fn pending_records(&self) -> usize;
+
+    /// Writes all pending records
+    fn flush(&self) -> Result<()>;
+}
+
+/// The backend `[storage]` selects: `local` itself for SQLite, otherwise a connection to the
+/// configured server writing every `batch_size` records and at least every `flush_interval`
+pub fn open_backend(
+    config: &StorageConfig,
+    local: Arc<PacketStorage>,
+    batch_size: usize,
+    flush_interval: Duration,
+) -> Result<Arc<dyn StorageBackend>> {
+    match config.backend {
+        DatabaseBackend::Sqlite => Ok(local),
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::Postgres => {
+            use anyhow::Context;
+            let url = config.url.as_deref().context("storage.url must be set when storage.backend = \"postgres\"")?;
+            let machine = config
+                .name
+                .clone()
+                .or_else(sysinfo::System::host_name)
+                .unwrap_or_else(|| "kw".to_string());
+            Ok(Arc::new(super::postgres::PostgresBackend::connect(url, &machine, batch_size, flush_interval)?))
+        }
+        #[cfg(not(feature = "postgres"))]
+        DatabaseBackend::Postgres => {
+            let _ = (local, batch_size, flush_interval);
+            anyhow::bail!("storage.backend = \"postgres\" needs kw built with the `postgres` feature")
+        }
+    }
+}
+
+impl StorageBackend for PacketStorage {
+    fn store_packet(&self, packet: &NetworkPacket, analysis: &AnalysisResult) -> Result<()> {
+        self.analyze_packet_for_storage(packet, analysis)
+    }
+
+    fn store_quality_sample(&self, sample: &QualitySample) -> Result<()> {
+        PacketStorage::store_quality_sample(self, sample)
+    }
+
+    fn store_bandwidth_samples(&self, samples: &[BandwidthSample]) -> Result<()> {
+        PacketStorage::store_bandwidth_samples(self, samples)
+    }
+
+    fn store_capture_sample(&self, sample: &CaptureSample) -> Result<()> {
+        PacketStorage::store_capture_sample(self, sample)
+    }
+
+    fn store_collector_health(&self, sample: &CollectorHealthSample) -> Result<()> {
+        PacketStorage::store_collector_health(self, sample)
+    }
+
+    fn store_interface_change(&self, change: &InterfaceChange) -> Result<()> {
+        PacketStorage::store_interface_change(self, change)
+    }
+
+    fn store_tcp_sessions(&self, sessions: &[TcpSession]) -> Result<()> {
+        PacketStorage::store_tcp_sessions(self, sessions)
+    }
+
+    fn store_latency_samples(&self, samples: &[LatencySample]) -> Result<()> {
+        PacketStorage::store_latency_samples(self, samples)
+    }
+
+    fn store_classification_samples(&self, samples: &[ClassificationSample]) -> Result<()> {
+        PacketStorage::store_classification_samples(self, samples)
+    }
+
+    fn store_duplicate_samples(&self, samples: &[DuplicateSample]) -> Result<()> {
+        PacketStorage::store_duplicate_samples(self, samples)
+    }
+
+    fn store_mtu_samples(&self, samples: &[MtuSample]) -> Result<()> {
+        PacketStorage::store_mtu_samples(self, samples)
+    }
+
+    fn store_throttled_flows(&self, flows: &[ThrottledFlow]) -> Result<()> {
+        PacketStorage::store_throttled_flows(self, flows)
+    }
+
+    fn store_ping_samples(&self, samples: &[PingSample]) -> Result<()> {
+        PacketStorage::store_ping_samples(self, samples)
+    }
+
+    fn pending_records(&self) -> usize {
+        PacketStorage::pending_records(self)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.flush_all()
+    }
+}
@@ -0,0 +1,274 @@
+// Database: the SQLite layer every subsystem opens the packet database through
+// A database file is opened once per process: one writer connection shared by storage,
+// graphs and the service, and a small pool of read-only connections their queries run on,
+// in WAL mode so readers neither wait on the writer nor on each other; opening brings the
+// schema up to date with the versioned migrations in `schema`
+
+use crate::config::data_dir;
+use crate::storage::encryption;
+use crate::storage::schema::{self, migrate};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+/// How long a statement waits for another process holding the write lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Read-only connections opened beside the writer of each database
+const READERS: usize = 4;
+
+/// Writer connection and read pool of an open database
+type OpenDatabase = (Weak<Mutex<Connection>>, Weak<ReadPool>);
+
+/// Databases currently open, by canonical database path
+/// Entries are weak so a database is closed once its last user is dropped
+static OPEN_DATABASES: LazyLock<Mutex<HashMap<PathBuf, OpenDatabase>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Handle to a packet database; clones share the same connections
+#[derive(Clone)]
+pub struct Database {
+    conn: Arc<Mutex<Connection>>,
+    readers: Arc<ReadPool>,
+}
+
+/// Read-only connections to one database, handed out one query at a time
+pub struct ReadPool {
+    connections: Vec<Arc<Mutex<Connection>>>,
+    /// Connection to wait for next when every one is busy
+    next: AtomicUsize,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its directory if needed, and
+    /// migrates it to the current schema version
+    ///
+    /// A database already open in this process is shared rather than opened again,
+    /// so its writers are serialized on one connection instead of contending for the
+    /// file lock, while reads go to the pool
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            data_dir::create_dir_all(parent).context("Failed to create database directory")?;
+        }
+        let key = canonical_path(path)?;
+
+        let mut open = OPEN_DATABASES.lock().unwrap();
+        let (conn, readers) = open
+            .get(&key)
+            .map(|(conn, readers)| (conn.upgrade(), readers.upgrade()))
+            .unwrap_or_default();
+        if let (Some(conn), Some(readers)) = (&conn, &readers) {
+            return Ok(Self { conn: Arc::clone(conn), readers: Arc::clone(readers) });
+        }
+        open.retain(|_, (conn, readers)| conn.strong_count() > 0 || readers.strong_count() > 0);
+
+        // A writer still held by a write buffer stays the only one, with a new pool beside it
+        let conn = match conn {
+            Some(conn) => conn,
+            None => Arc::new(Mutex::new(open_connection(path)?)),
+        };
+        // Under sudo the files are created by root; SQLite gives the log files it creates
+        // later the database's owner
+        for file in [path.to_path_buf(), sidecar(path, "-wal"), sidecar(path, "-shm")] {
+            data_dir::give_to_sudo_user(&file);
+        }
+        let readers = match readers {
+            Some(readers) => readers,
+            None => Arc::new(ReadPool::open(|| open_reader(path))?),
+        };
+        open.insert(key, (Arc::downgrade(&conn), Arc::downgrade(&readers)));
+        Ok(Self { conn, readers })
+    }
+
+    /// Opens an existing database without write access or schema changes
+    /// Read-only handles are never shared, as their connection sees stand-ins for
+    /// missing tables that a writable one must not; it is also their only reader
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Arc::new(Mutex::new(schema::open_read_only(path)?));
+        Ok(Self {
+            readers: Arc::new(ReadPool {
+                connections: vec![Arc::clone(&conn)],
+                next: AtomicUsize::new(0),
+            }),
+            conn,
+        })
+    }
+
+    /// The shared writer connection
+    pub fn connection(&self) -> Arc<Mutex<Connection>> {
+        Arc::clone(&self.conn)
+    }
+
+    /// The pool of read-only connections
+    pub fn readers(&self) -> Arc<ReadPool> {
+        Arc::clone(&self.readers)
+    }
+
+    /// A read-only connection for a query, held until the guard is dropped
+    pub fn reader(&self) -> MutexGuard<'_, Connection> {
+        self.readers.get()
+    }
+
+    /// Schema version of the database, as recorded by the last migration applied
+    pub fn schema_version(&self) -> Result<u32> {
+        let conn = self.conn.lock().unwrap();
+        schema::schema_version(&conn)
+    }
+}
+
+impl ReadPool {
+    fn open(open: impl Fn() -> Result<Connection>) -> Result<Self> {
+        Ok(Self {
+            connections: (0..READERS)
+                .map(|_| Ok(Arc::new(Mutex::new(open()?))))
+                .collect::<Result<_>>()?,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// A free connection, or the next one in turn once it is free
+    pub fn get(&self) -> MutexGuard<'_, Connection> {
+        for conn in &self.connections {
+            if let Ok(conn) = conn.try_lock() {
+                return conn;
+            }
+        }
+        let next = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        self.connections[next].lock().unwrap()
+    }
+}
+
+/// Opens a connection in WAL mode and migrates the database
+fn open_connection(path: &Path) -> Result<Connection> {
+    let mut conn = Connection::open(path)
+        .with_context(|| format!("Failed to open database {}", path.display()))?;
+    encryption::unlock(&conn, path)?;
+    conn.busy_timeout(BUSY_TIMEOUT).context("Failed to set busy timeout")?;
+
+    let journal_mode: String = conn
+        .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))
+        .context("Failed to set the journal mode")?;
+    if journal_mode.eq_ignore_ascii_case("wal") {
+        // Safe against corruption in WAL mode; only the last commits can be lost on power failure
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+    } else {
+        warn!("{} does not support WAL, using the {journal_mode} journal", path.display());
+    }
+
+    let (from, to) = migrate(&mut conn)
+        .with_context(|| format!("Failed to migrate database {}", path.display()))?;
+    if from != to {
+        info!("Database {} migrated from schema version {from} to {to}", path.display());
+    }
+    Ok(conn)
+}
+
+/// Opens a connection of a writable database's read pool, once the writer has migrated it
+fn open_reader(path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open database {} for reading", path.display()))?;
+    encryption::unlock(&conn, path)?;
+    conn.busy_timeout(BUSY_TIMEOUT).context("Failed to set busy timeout")?;
+    Ok(conn)
+}
+
+/// The file SQLite keeps beside the database with `suffix`, `-wal` or `-shm`
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Path identifying a database file however it was named; the parent must exist
+fn canonical_path(path: &Path) -> Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} is not a database file path", path.display()))?;
+    Ok(parent
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", parent.display()))?
+        .join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_open_shares_connection_and_migrates() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data").join("packets.db");
+
+        let first = Database::open(&path).unwrap();
+        let second = Database::open(dir.path().join("data/../data/packets.db")).unwrap();
+        assert!(Arc::ptr_eq(&first.connection(), &second.connection()));
+        assert!(Arc::ptr_eq(&first.readers(), &second.readers()));
+        assert_eq!(first.schema_version().unwrap(), schema::SCHEMA_VERSION);
+
+        let journal_mode: String = first
+            .connection()
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        // Once every handle is dropped the next open gets a fresh connection
+        let previous = Arc::downgrade(&first.connection());
+        drop((first, second));
+        assert!(previous.upgrade().is_none());
+        let reopened = Database::open(&path).unwrap();
+        assert_eq!(reopened.schema_version().unwrap(), schema::SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_reads_run_on_the_pool_beside_the_writer() {
+        let dir = tempdir().unwrap();
+        let database = Database::open(dir.path().join("packets.db")).unwrap();
+        let writer = database.connection();
+        let writer = writer.lock().unwrap();
+        writer
+            .execute(
+                "INSERT INTO tags (target, kind, label, updated_at) VALUES ('10.0.0.1', 'host', 'nas', '2024-05-01 12:00:00')",
+                [],
+            )
+            .unwrap();
+
+        // Readers are free while the writer is held, each query on its own connection
+        let first = database.reader();
+        let second = database.reader();
+        assert!(!std::ptr::eq(&*first, &*second));
+        let tags: i64 = first.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0)).unwrap();
+        assert_eq!(tags, 1);
+        assert!(second.execute("DELETE FROM tags", []).is_err());
+    }
+
+    #[test]
+    fn test_read_only_handles_are_separate() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("packets.db");
+        let writable = Database::open(&path).unwrap();
+        let read_only = Database::open_read_only(&path).unwrap();
+        assert!(!Arc::ptr_eq(&writable.connection(), &read_only.connection()));
+        assert!(read_only
+            .connection()
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM main.tags", [])
+            .is_err());
+        assert!(read_only.reader().execute("DELETE FROM main.tags", []).is_err());
+    }
+}
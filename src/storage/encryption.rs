@@ -0,0 +1,309 @@
+// Encryption at rest: packet databases encrypted with SQLCipher (the `encryption` feature)
+// The key is set once for the process at startup, from `[encryption] key` (or
+// KW_ENCRYPTION__KEY) or from the OS keychain, and every connection is keyed with it before
+// anything is read. `kw encryption enable` and `disable` convert an existing database
+
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::Connection;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::EncryptionConfig;
+
+/// First bytes of every plaintext SQLite database; SQLCipher encrypts them with the rest
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Keychain service the database keys are stored under, one entry per profile
+#[cfg(feature = "encryption")]
+const KEYCHAIN_SERVICE: &str = "kaipo-watcher";
+
+const NOT_BUILT: &str = "This kw was built without database encryption (the `encryption` feature)";
+
+/// Key every database of this process is opened with, set once at startup
+static DATABASE_KEY: OnceLock<DatabaseKey> = OnceLock::new();
+
+/// A database passphrase, kept out of logs and debug output
+#[derive(Clone, PartialEq, Eq)]
+pub struct DatabaseKey(String);
+
+impl DatabaseKey {
+    pub fn new(key: String) -> Result<Self> {
+        if key.is_empty() {
+            bail!("The database key is empty");
+        }
+        Ok(Self(key))
+    }
+
+    /// 32 random bytes, hex encoded, from SQLite's generator, which is seeded by the OS
+    pub fn generate() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        let key: String = conn.query_row("SELECT lower(hex(randomblob(32)))", [], |row| row.get(0))?;
+        Ok(Self(key))
+    }
+
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+/// Where the key of a profile comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    /// `[encryption] key` or KW_ENCRYPTION__KEY
+    Config,
+    /// The OS keychain, with `[encryption] keychain = true`
+    Keychain,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config => f.write_str("the configuration"),
+            Self::Keychain => f.write_str("the OS keychain"),
+        }
+    }
+}
+
+/// The key `[encryption]` gives for `profile`, if any: `key` first, then the keychain
+pub fn configured_key(config: &EncryptionConfig, profile: &str) -> Result<Option<(DatabaseKey, KeySource)>> {
+    if let Some(key) = config.key.as_ref().filter(|key| !key.is_empty()) {
+        return Ok(Some((DatabaseKey::new(key.clone())?, KeySource::Config)));
+    }
+    if config.keychain {
+        return Ok(keychain_key(profile)?.map(|key| (key, KeySource::Keychain)));
+    }
+    Ok(None)
+}
+
+/// Opens every database of this process with `key`
+pub fn set_key(key: DatabaseKey) -> Result<()> {
+    if !cfg!(feature = "encryption") {
+        bail!("{NOT_BUILT}; remove the key from [encryption] or KW_ENCRYPTION__KEY");
+    }
+    DATABASE_KEY.set(key).map_err(|_| anyhow!("The database key is already set"))
+}
+
+/// Keys `conn`, just opened on `path`, with the key of this process before anything is read
+pub(crate) fn unlock(conn: &Connection, path: &Path) -> Result<()> {
+    unlock_with(conn, path, DATABASE_KEY.get())
+}
+
+fn unlock_with(conn: &Connection, path: &Path, key: Option<&DatabaseKey>) -> Result<()> {
+    match key {
+        Some(_) if is_plaintext(path) => bail!(
+            "{} is not encrypted; run `kw encryption enable` to encrypt it with the configured key",
+            path.display()
+        ),
+        Some(key) => {
+            conn.pragma_update(None, "key", key.as_str()).context("Failed to set the database key")?;
+            // A wrong key is reported below; SQLCipher would also log it to stderr
+            conn.pragma_update(None, "cipher_log", "off")?;
+            verify_key(conn, path)
+        }
+        None if is_encrypted(path) => bail!(
+            "{} is encrypted; give its key with [encryption] key, KW_ENCRYPTION__KEY or [encryption] keychain = true",
+            path.display()
+        ),
+        None => Ok(()),
+    }
+}
+
+/// SQLCipher only decrypts on the first read, so a wrong key shows there
+fn verify_key(conn: &Connection, path: &Path) -> Result<()> {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+        .map_err(|_| anyhow!("Could not decrypt {}: the database key is wrong", path.display()))
+}
+
+/// The first 16 bytes of `path`, when it has that many
+fn read_header(path: &Path) -> Option<[u8; 16]> {
+    let mut header = [0; 16];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    Some(header)
+}
+
+/// Whether `path` holds an unencrypted database; new and empty files hold neither
+pub fn is_plaintext(path: &Path) -> bool {
+    read_header(path).is_some_and(|header| &header == SQLITE_HEADER)
+}
+
+/// Whether `path` holds an encrypted database
+pub fn is_encrypted(path: &Path) -> bool {
+    read_header(path).is_some_and(|header| &header != SQLITE_HEADER)
+}
+
+/// Rewrites the plaintext database at `path` encrypted with `key`
+pub fn encrypt_database(path: &Path, key: &DatabaseKey) -> Result<()> {
+    convert(path, None, Some(key))
+}
+
+/// Rewrites the database at `path`, encrypted with `key`, as plaintext
+pub fn decrypt_database(path: &Path, key: &DatabaseKey) -> Result<()> {
+    convert(path, Some(key), None)
+}
+
+/// Exports the database at `path`, opened with `from`, into a new file keyed with `to` (no
+/// key: plaintext), then puts the new file in its place
+#[cfg(feature = "encryption")]
+fn convert(path: &Path, from: Option<&DatabaseKey>, to: Option<&DatabaseKey>) -> Result<()> {
+    use rusqlite::params;
+
+    let converted = path.with_extension("db.converting");
+    if converted.exists() {
+        std::fs::remove_file(&converted)
+            .with_context(|| format!("Failed to remove {}", converted.display()))?;
+    }
+    let conn = Connection::open(path).with_context(|| format!("Failed to open database {}", path.display()))?;
+    unlock_with(&conn, path, from)?;
+    // Everything still in the write-ahead log goes into the main file first, so the export sees it
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+        .context("Failed to checkpoint the write-ahead log")?;
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS converted KEY ?2",
+        params![converted.to_string_lossy(), to.map_or("", DatabaseKey::as_str)],
+    )
+    .with_context(|| format!("Failed to create {}", converted.display()))?;
+    conn.query_row("SELECT sqlcipher_export('converted')", [], |_| Ok(()))
+        .context("Failed to copy the database")?;
+    // The schema version is not part of the export
+    conn.pragma_update(Some("converted"), "user_version", version)?;
+    conn.execute("DETACH DATABASE converted", [])?;
+    drop(conn);
+
+    std::fs::rename(&converted, path)
+        .with_context(|| format!("Failed to replace {} with {}", path.display(), converted.display()))?;
+    crate::config::data_dir::give_to_sudo_user(path);
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn convert(_path: &Path, _from: Option<&DatabaseKey>, _to: Option<&DatabaseKey>) -> Result<()> {
+    bail!(NOT_BUILT)
+}
+
+/// The key kept in the keychain for `profile`
+#[cfg(feature = "encryption")]
+pub fn keychain_key(profile: &str) -> Result<Option<DatabaseKey>> {
+    let profile = profile.to_string();
+    on_own_thread(move || match keychain_entry(&profile)?.get_password() {
+        Ok(key) => Ok(Some(DatabaseKey::new(key)?)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow!(e).context("Failed to read the database key from the keychain")),
+    })
+}
+
+/// Keeps `key` in the keychain for `profile`, replacing any key there
+#[cfg(feature = "encryption")]
+pub fn store_keychain_key(profile: &str, key: &DatabaseKey) -> Result<()> {
+    let (profile, key) = (profile.to_string(), key.clone());
+    on_own_thread(move || {
+        keychain_entry(&profile)?
+            .set_password(key.as_str())
+            .context("Failed to store the database key in the keychain")
+    })
+}
+
+/// Deletes the key kept in the keychain for `profile`; false when there was none
+#[cfg(feature = "encryption")]
+pub fn remove_keychain_key(profile: &str) -> Result<bool> {
+    let profile = profile.to_string();
+    on_own_thread(move || match keychain_entry(&profile)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow!(e).context("Failed to remove the database key from the keychain")),
+    })
+}
+
+#[cfg(feature = "encryption")]
+fn keychain_entry(profile: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, profile).context("Failed to open the keychain")
+}
+
+/// Runs a keychain call on a thread of its own, as the Secret Service client starts a runtime
+/// of its own that cannot run inside kw's
+#[cfg(feature = "encryption")]
+fn on_own_thread<T: Send + 'static>(call: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    std::thread::spawn(call).join().map_err(|_| anyhow!("The keychain call panicked"))?
+}
+
+#[cfg(not(feature = "encryption"))]
+pub fn keychain_key(_profile: &str) -> Result<Option<DatabaseKey>> {
+    bail!("{NOT_BUILT}; set [encryption] keychain = false")
+}
+
+#[cfg(not(feature = "encryption"))]
+pub fn store_keychain_key(_profile: &str, _key: &DatabaseKey) -> Result<()> {
+    bail!(NOT_BUILT)
+}
+
+/// Without the keychain built in kw cannot have stored a key in it
+#[cfg(not(feature = "encryption"))]
+pub fn remove_keychain_key(_profile: &str) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyless_opens_refuse_encrypted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("plain.db");
+        let conn = Connection::open(&plain).unwrap();
+        conn.execute("CREATE TABLE t (x INTEGER)", []).unwrap();
+        assert!(is_plaintext(&plain) && !is_encrypted(&plain));
+        assert!(unlock_with(&conn, &plain, None).is_ok());
+
+        let key = DatabaseKey::generate().unwrap();
+        assert_eq!(key.as_str().len(), 64);
+        assert_eq!(format!("{key:?}"), "DatabaseKey(..)");
+        let error = unlock_with(&conn, &plain, Some(&key)).unwrap_err().to_string();
+        assert!(error.contains("kw encryption enable"), "{error}");
+
+        // Anything that is not a SQLite header reads as encrypted; a new file as neither
+        let scrambled = dir.path().join("scrambled.db");
+        std::fs::write(&scrambled, [0x5a; 4096]).unwrap();
+        assert!(is_encrypted(&scrambled));
+        assert!(unlock_with(&conn, &scrambled, None).is_err());
+        assert!(!is_plaintext(&dir.path().join("new.db")) && !is_encrypted(&dir.path().join("new.db")));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypt_and_decrypt_keep_the_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packets.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA user_version = 7; CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (42);")
+            .unwrap();
+        drop(conn);
+
+        let key = DatabaseKey::new("correct horse".to_string()).unwrap();
+        encrypt_database(&path, &key).unwrap();
+        assert!(is_encrypted(&path));
+
+        let conn = Connection::open(&path).unwrap();
+        let wrong = DatabaseKey::new("battery staple".to_string()).unwrap();
+        assert!(unlock_with(&conn, &path, Some(&wrong)).is_err());
+        let conn = Connection::open(&path).unwrap();
+        unlock_with(&conn, &path, Some(&key)).unwrap();
+        let x: i64 = conn.query_row("SELECT x FROM t", [], |row| row.get(0)).unwrap();
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!((x, version), (42, 7));
+        drop(conn);
+
+        decrypt_database(&path, &key).unwrap();
+        assert!(is_plaintext(&path));
+    }
+}
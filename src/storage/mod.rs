@@ -1,6 +1,16 @@
+pub mod audit;
+pub mod backend;
+pub mod database;
+pub mod encryption;
 pub mod packet_storage;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod retention;
 pub mod schema;
+pub mod write_buffer;
 
+pub use backend::{open_backend, StorageBackend};
+pub use database::{Database, ReadPool};
 pub use packet_storage::{
     PacketStorage,
 };
\ No newline at end of file
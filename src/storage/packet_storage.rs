@@ -2,17 +2,29 @@
 // Provides efficient storage and retrieval of network packet data and analysis results
 // Uses SQLite with optimizations for time-series data and concurrent access
 
-use crate::analyzers::{AnalysisResult, SecurityFlag, TrafficType};
-use crate::models::{NetworkPacket, PacketStatistics};
-use crate::storage::schema::{create_tables, setup_data_retention};
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local};
-use log::{debug, info, warn};
+use crate::analyzers::anomaly::BaselineModel;
+use crate::analyzers::discovery::{DeviceType, DiscoveredDevice, DiscoverySource};
+use crate::analyzers::{AnalysisResult, ClassificationSample, DuplicateSample, LatencySample, MtuSample, LossStats, SecurityFlag, TcpSession, ThrottleConfidence, ThrottledFlow, TrafficType};
+use crate::collectors::bandwidth::CollectorTelemetry;
+use crate::collectors::bandwidth_collector::CalculationConfidence;
+use crate::collectors::ping_collector::PingSample;
+use crate::collectors::socket_stats::TcpInfo;
+use crate::config::{AnomalyConfig, ConfigFingerprint, RetentionConfig};
+use crate::models::packet::is_private;
+use crate::models::{NetworkPacket, PacketSizeDistribution, PacketSizeHistogram, PacketStatistics, Redactor, Tag, TagBook, TagKind};
+use crate::storage::audit::{self, AuditChain, AuditEntry, AuditVerification};
+use crate::storage::{Database, ReadPool};
+use crate::storage::retention::{apply_retention, RetentionReport};
+use crate::storage::write_buffer::WriteBuffer;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime};
+use log::{debug, info};
 use rusqlite::{params, Connection};
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tokio::time::{interval, Duration};
+use std::time::Duration;
 
 /// High-performance storage system for network packet analysis data
 /// 
@@ -37,18 +49,31 @@ use tokio::time::{interval, Duration};
 /// 
 /// # Example
 /// 
-/// ```rust
+/// ```no_run
+/// use chrono::{Duration, Local};
+/// use kaipo_watcher::{NetworkPacket, PacketDirection, PacketProtocol, PacketStorage, ProtocolAnalyzer};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let packet = NetworkPacket::new("eth0".to_string(), 1500, PacketProtocol::IPv4, PacketDirection::Inbound);
+/// # let analysis = ProtocolAnalyzer::new().analyze_packet(&packet)?;
 /// let storage = PacketStorage::new("./data/packets.db", 100)?;
 /// storage.analyze_packet_for_storage(&packet, &analysis)?;
-/// let summary = storage.get_traffic_summary("eth0", since)?;
+/// let until = Local::now();
+/// let summary = storage.get_traffic_summary("eth0", until - Duration::hours(1), until)?;
+/// # Ok(())
+/// # }
 /// ```
 pub struct PacketStorage {
+    /// Writer connection, shared with the write buffer
     conn: Arc<Mutex<Connection>>,
-    batch_size: usize,
-    pending_stats: Arc<Mutex<Vec<PacketStatistics>>>,
-    pending_protocols: Arc<Mutex<Vec<ProtocolRecord>>>,
-    pending_connections: Arc<Mutex<Vec<ConnectionRecord>>>,
-    pending_security_events: Arc<Mutex<Vec<SecurityEvent>>>,
+    /// Read-only connections the queries run on, beside the writer
+    readers: Arc<ReadPool>,
+    /// Merge pending records that share a protocol or connection key
+    rollup: bool,
+    /// Records waiting to be written in the next batch
+    buffer: WriteBuffer,
+    /// Redacts the addresses and host names printed through `tag_book`, in privacy mode
+    redactor: Redactor,
 }
 
 #[derive(Debug, Clone)]
@@ -59,6 +84,8 @@ pub struct ProtocolRecord {
     pub packet_count: u64,
     pub byte_count: u64,
     pub is_encrypted: bool,
+    /// One packet in this many was analyzed; the counts are already scaled up by it
+    pub sample_rate: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -75,6 +102,10 @@ pub struct ConnectionRecord {
     pub packet_count: u64,
     pub byte_count: u64,
     pub is_active: bool,
+    /// How sure the analyzer was of `application_protocol`
+    pub classification_confidence: String,
+    /// Highest sampling rate any of the counted packets was kept at, 1 when none were sampled
+    pub sample_rate: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -90,6 +121,113 @@ pub struct SecurityEvent {
     pub severity: String,
 }
 
+/// What storing one packet adds: its protocol, its connection and any security events
+#[derive(Debug, Default)]
+pub struct PacketRecords {
+    pub protocol: Option<ProtocolRecord>,
+    pub connection: Option<ConnectionRecord>,
+    pub security_events: Vec<SecurityEvent>,
+}
+
+impl PacketRecords {
+    /// Records of a packet kept by sampling one in `sample_rate`, counted that many times
+    pub fn new(packet: &NetworkPacket, analysis: &AnalysisResult, sample_rate: u64) -> Self {
+        let protocol = analysis.application_protocol.as_ref().map(|protocol_name| ProtocolRecord {
+            timestamp: packet.timestamp,
+            interface_name: packet.interface.clone(),
+            protocol_name: protocol_name.clone(),
+            packet_count: sample_rate,
+            byte_count: packet.size_bytes * sample_rate,
+            is_encrypted: analysis.is_encrypted,
+            sample_rate,
+        });
+
+        let connection = match (packet.source_addr, packet.dest_addr) {
+            (Some(src), Some(dst)) => Some(ConnectionRecord {
+                connection_key: format!(
+                    "{}:{}-{}:{}",
+                    src,
+                    packet.source_port.unwrap_or(0),
+                    dst,
+                    packet.dest_port.unwrap_or(0)
+                ),
+                source_ip: src.to_string(),
+                dest_ip: dst.to_string(),
+                source_port: packet.source_port,
+                dest_port: packet.dest_port,
+                protocol: format!("{:?}", packet.transport_protocol),
+                application_protocol: analysis.application_protocol.clone(),
+                first_seen: packet.timestamp,
+                last_seen: packet.timestamp,
+                packet_count: sample_rate,
+                byte_count: packet.size_bytes * sample_rate,
+                is_active: true,
+                classification_confidence: analysis.classification_confidence.to_string(),
+                sample_rate,
+            }),
+            _ => None,
+        };
+
+        let security_events = analysis
+            .security_flags
+            .iter()
+            .map(|flag| {
+                // An unknown-traffic surge describes the interface, not the packet that revealed it
+                let about_packet = !matches!(flag, SecurityFlag::UnknownTrafficSurge(..));
+                SecurityEvent {
+                    timestamp: packet.timestamp,
+                    interface_name: packet.interface.clone(),
+                    event_type: flag.event_type().to_string(),
+                    source_ip: packet.source_addr.filter(|_| about_packet).map(|ip| ip.to_string()),
+                    dest_ip: packet.dest_addr.filter(|_| about_packet).map(|ip| ip.to_string()),
+                    port: packet.dest_port.or(packet.source_port).filter(|_| about_packet),
+                    protocol: Some(format!("{:?}", packet.transport_protocol)).filter(|_| about_packet),
+                    description: security_flag_description(flag),
+                    severity: security_flag_severity(flag),
+                }
+            })
+            .collect();
+
+        Self { protocol, connection, security_events }
+    }
+}
+
+fn security_flag_description(flag: &SecurityFlag) -> String {
+    match flag {
+        SecurityFlag::SuspiciousPort => "Traffic detected on suspicious port".to_string(),
+        SecurityFlag::UnencryptedSensitive => "Sensitive data transmitted without encryption".to_string(),
+        SecurityFlag::HighFrequency => "High frequency traffic pattern detected".to_string(),
+        SecurityFlag::UnknownProtocol => "Unknown or unusual protocol detected".to_string(),
+        SecurityFlag::LargePayload => "Unusually large payload detected".to_string(),
+        SecurityFlag::HighEntropyPayload(entropy) => format!(
+            "High payload entropy ({entropy:.2} bits/byte) on a port not known for encrypted traffic"
+        ),
+        SecurityFlag::PortScan(ports) => format!("Port scan: {ports} ports probed on one host"),
+        SecurityFlag::SynFlood(sources) => {
+            format!("Possible SYN flood: connection attempts from {sources} sources")
+        }
+        SecurityFlag::IcmpSweep(hosts) => format!("ICMP sweep: {hosts} hosts pinged"),
+        SecurityFlag::UnknownTrafficSurge(share, usual) => {
+            format!("Unclassified traffic at {share:.0}% of bytes, usually {usual:.0}%")
+        }
+    }
+}
+
+fn security_flag_severity(flag: &SecurityFlag) -> String {
+    match flag {
+        SecurityFlag::SuspiciousPort => "warning".to_string(),
+        SecurityFlag::UnencryptedSensitive => "high".to_string(),
+        SecurityFlag::HighFrequency => "info".to_string(),
+        SecurityFlag::UnknownProtocol => "info".to_string(),
+        SecurityFlag::LargePayload => "info".to_string(),
+        SecurityFlag::HighEntropyPayload(_) => "warning".to_string(),
+        SecurityFlag::PortScan(_) => "high".to_string(),
+        SecurityFlag::SynFlood(_) => "high".to_string(),
+        SecurityFlag::IcmpSweep(_) => "warning".to_string(),
+        SecurityFlag::UnknownTrafficSurge(..) => "warning".to_string(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TrafficSummary {
     #[allow(dead_code)]
@@ -99,6 +237,8 @@ pub struct TrafficSummary {
     pub total_packets: u64,
     pub total_bytes: u64,
     pub protocols: HashMap<String, ProtocolStats>,
+    /// Highest sampling rate in the period; above 1, packet and byte counts are estimates
+    pub sample_rate: u64,
     #[allow(dead_code)]
     pub traffic_types: HashMap<TrafficType, TrafficTypeStats>,
     pub top_connections: Vec<ConnectionSummary>,
@@ -133,86 +273,407 @@ pub struct ConnectionSummary {
     pub bytes: u64,
 }
 
-impl PacketStorage {
-    pub fn new<P: AsRef<Path>>(db_path: P, batch_size: usize) -> Result<Self> {
-        // Ensure the parent directory exists
-        if let Some(parent) = db_path.as_ref().parent() {
-            std::fs::create_dir_all(parent)
-                .context("Failed to create database directory")?;
+/// Measurement quality of one monitoring interval
+#[derive(Debug, Clone)]
+pub struct QualitySample {
+    pub timestamp: DateTime<Local>,
+    pub interface_name: String,
+    /// Length of the interval this sample covers
+    pub duration_secs: f64,
+    /// Confidence of the bandwidth calculation over the interval
+    pub confidence: CalculationConfidence,
+    pub counter_resets: u64,
+    pub collection_failures: u64,
+    pub packets_captured: u64,
+    pub packets_dropped: u64,
+}
+
+/// Average throughput of one interface over a monitoring interval
+#[derive(Debug, Clone)]
+pub struct BandwidthSample {
+    /// End of the interval
+    pub timestamp: DateTime<Local>,
+    pub interface_name: String,
+    pub duration_secs: f64,
+    pub download_bps: f64,
+    pub upload_bps: f64,
+}
+
+impl BandwidthSample {
+    /// Download and upload combined
+    pub fn total_bps(&self) -> f64 {
+        self.download_bps + self.upload_bps
+    }
+
+    /// Whether the interface is a loopback, judged by name as samples carry no interface
+    /// type; a hub's `<agent>/<interface>` names are judged by the interface part
+    pub fn is_loopback(&self) -> bool {
+        let name = self.interface_name.rsplit('/').next().unwrap_or(&self.interface_name);
+        name == "lo" || name == "lo0" || name.to_lowercase().contains("loopback")
+    }
+}
+
+/// One page of a longer query result
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Rows matching the query across all pages
+    pub total: u64,
+}
+
+/// Packets and bytes of one protocol over a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolTotal {
+    pub protocol: String,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Whether any of its traffic was encrypted
+    pub encrypted: bool,
+}
+
+/// Flows, packets and bytes of one application protocol over a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplicationUsage {
+    /// Application protocol, or the transport protocol of unclassified connections
+    pub protocol: String,
+    /// Connections that used the protocol
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// Traffic exchanged with one address outside the monitored network over a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteTraffic {
+    pub address: IpAddr,
+    /// Connections with the address
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// How the daemon's bandwidth collector fared over one monitoring interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectorHealthSample {
+    /// End of the interval
+    pub timestamp: DateTime<Local>,
+    pub duration_secs: f64,
+    pub telemetry: CollectorTelemetry,
+}
+
+/// A network interface appearing, disappearing, going up or down, or changing addresses
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceChange {
+    pub timestamp: DateTime<Local>,
+    pub interface_name: String,
+    /// added, removed, up, down or changed, as named by `InterfaceEvent::change`
+    pub change: String,
+}
+
+/// Capture performance over one interval of a capture session
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureSample {
+    /// End of the interval
+    pub timestamp: DateTime<Local>,
+    /// When the capture session started; identifies the session together with the interface
+    pub session_start: DateTime<Local>,
+    pub interface_name: String,
+    pub duration_secs: f64,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Highest one-second capture rate in the interval
+    pub peak_packets_per_second: f64,
+    pub peak_bytes_per_second: f64,
+    /// Packets discarded because the queue to the analyzer was full
+    pub queue_drops: u64,
+    /// Packets the kernel or driver dropped, where the platform reports it
+    pub kernel_drops: Option<u64>,
+    /// Packets the queue to the analyzer holds at most
+    pub queue_capacity: u64,
+    pub avg_queue_depth: f64,
+    pub peak_queue_depth: u64,
+    /// Most records waiting to be written to the database at once
+    pub peak_storage_backlog: u64,
+    /// Packets captured in the interval by size, kept apart in `packet_size_stats`
+    pub packet_sizes: PacketSizeDistribution,
+}
+
+impl CaptureSample {
+    /// Share of the analyzer queue in use at its fullest, 0.0 - 1.0
+    pub fn peak_queue_utilisation(&self) -> f64 {
+        if self.queue_capacity == 0 {
+            0.0
+        } else {
+            self.peak_queue_depth as f64 / self.queue_capacity as f64
         }
-        
-        let conn = Connection::open(db_path)
-            .context("Failed to open database connection")?;
-        
-        // Enable WAL mode for better concurrent access (ignore errors for in-memory DBs)
-        let _ = conn.pragma_update(None, "journal_mode", "WAL");
-        
-        // Set reasonable timeout
-        conn.busy_timeout(Duration::from_secs(5))
-            .context("Failed to set busy timeout")?;
-
-        create_tables(&conn)
-            .context("Failed to create database tables")?;
-
-        let storage = Self {
-            conn: Arc::new(Mutex::new(conn)),
-            batch_size,
-            pending_stats: Arc::new(Mutex::new(Vec::new())),
-            pending_protocols: Arc::new(Mutex::new(Vec::new())),
-            pending_connections: Arc::new(Mutex::new(Vec::new())),
-            pending_security_events: Arc::new(Mutex::new(Vec::new())),
+    }
+}
+
+/// Outcome of one active speed test; a phase that was skipped or failed is None
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedTestResult {
+    pub timestamp: DateTime<Local>,
+    /// Host of the download endpoint
+    pub server: String,
+    /// Median time of a small request over an open connection
+    pub latency_ms: Option<f64>,
+    /// Mean difference between consecutive latency samples
+    pub jitter_ms: Option<f64>,
+    pub download_bps: Option<f64>,
+    pub upload_bps: Option<f64>,
+    pub download_bytes: u64,
+    pub upload_bytes: u64,
+}
+
+/// Totals of one capture session, aggregated from its capture samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureSessionSummary {
+    pub session_start: DateTime<Local>,
+    pub interface_name: String,
+    /// End of the last recorded interval
+    pub last_sample: DateTime<Local>,
+    pub samples: u64,
+    pub duration_secs: f64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub peak_packets_per_second: f64,
+    pub peak_bytes_per_second: f64,
+    pub queue_drops: u64,
+    pub kernel_drops: Option<u64>,
+    pub queue_capacity: u64,
+    pub peak_queue_depth: u64,
+    pub peak_storage_backlog: u64,
+}
+
+impl CaptureSessionSummary {
+    pub fn packets_per_second(&self) -> f64 {
+        self.packets as f64 / self.duration_secs.max(1.0)
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.duration_secs.max(1.0)
+    }
+
+    /// Fraction of captured packets lost to a full analyzer queue
+    pub fn queue_drop_rate(&self) -> f64 {
+        if self.packets == 0 {
+            0.0
+        } else {
+            self.queue_drops as f64 / self.packets as f64
+        }
+    }
+
+    /// Share of the analyzer queue in use at its fullest, 0.0 - 1.0
+    pub fn peak_queue_utilisation(&self) -> f64 {
+        if self.queue_capacity == 0 {
+            0.0
+        } else {
+            self.peak_queue_depth as f64 / self.queue_capacity as f64
+        }
+    }
+}
+
+/// Data quality of a reporting period, aggregated from quality samples
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataQualitySummary {
+    /// Seconds of the period covered by quality samples
+    pub sampled_secs: f64,
+    pub high_secs: f64,
+    pub medium_secs: f64,
+    pub low_secs: f64,
+    pub none_secs: f64,
+    pub counter_resets: u64,
+    pub collection_failures: u64,
+    pub packets_captured: u64,
+    pub packets_dropped: u64,
+}
+
+impl DataQualitySummary {
+    /// Share of the sampled time measured with `confidence`, 0.0 - 1.0
+    pub fn share(&self, confidence: &CalculationConfidence) -> f64 {
+        if self.sampled_secs <= 0.0 {
+            return 0.0;
+        }
+        let secs = match confidence {
+            CalculationConfidence::High => self.high_secs,
+            CalculationConfidence::Medium => self.medium_secs,
+            CalculationConfidence::Low => self.low_secs,
+            CalculationConfidence::None => self.none_secs,
         };
+        secs / self.sampled_secs
+    }
+
+    /// Fraction of captured packets lost to a full capture queue
+    pub fn drop_rate(&self) -> f64 {
+        if self.packets_captured == 0 {
+            0.0
+        } else {
+            self.packets_dropped as f64 / self.packets_captured as f64
+        }
+    }
+}
+
+/// TCP loss counters read back from stored connections
+#[derive(Debug, Clone, Default)]
+pub struct TcpLossSummary {
+    /// (interface, counters), sorted by interface name
+    pub interfaces: Vec<(String, LossStats)>,
+    /// (client, server, counters) of the connections with the most retransmissions
+    pub connections: Vec<(String, String, LossStats)>,
+}
+
+/// Hosts listed in a usage summary
+const USAGE_HOST_LIMIT: i64 = 10;
+
+/// Captured traffic totals for a reporting period
+#[derive(Debug, Clone, Default)]
+pub struct UsageSummary {
+    pub connections: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    /// (application protocol, packets, bytes), busiest first
+    pub applications: Vec<(String, u64, u64)>,
+    /// (address, packets, bytes) of the busiest hosts, at either end of a connection
+    pub hosts: Vec<(String, u64, u64)>,
+}
+
+/// Bytes exchanged with one service in one hour, for shaping recommendations
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceUsage {
+    /// Start of the hour
+    pub hour: DateTime<Local>,
+    /// Transport protocol as stored, e.g. "Tcp"
+    pub protocol: String,
+    /// Lower port of the connection, taken as the service's
+    pub service_port: Option<u16>,
+    pub bytes: u64,
+}
 
-        // Start background flush task
-        storage.start_background_flush();
+impl PacketStorage {
+    /// Opens the database at `db_path`, migrated to the current schema and shared with
+    /// anything else in the process using the same file
+    pub fn new<P: AsRef<Path>>(db_path: P, batch_size: usize) -> Result<Self> {
+        Ok(Self::from_database(&Database::open(db_path)?, batch_size))
+    }
 
+    /// Storage on an open database, sharing its connections with the database's other users
+    pub fn from_database(database: &Database, batch_size: usize) -> Self {
+        let conn = database.connection();
         info!("Packet storage initialized with batch size: {batch_size}");
-        Ok(storage)
+        Self {
+            buffer: WriteBuffer::spawn(Arc::clone(&conn), batch_size),
+            conn,
+            readers: database.readers(),
+            rollup: false,
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Opens an existing database for reading only, without creating or migrating
+    /// anything, so a database copied from another machine is left exactly as it was
+    /// Any attempt to store records fails
+    pub fn open_read_only<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let database = Database::open_read_only(db_path)?;
+        let conn = database.connection();
+        info!("Packet storage opened read-only");
+        Ok(Self {
+            // Records are written at once by whoever stores them, so storing fails straight away
+            buffer: WriteBuffer::inline(Arc::clone(&conn), 1),
+            conn,
+            readers: database.readers(),
+            rollup: false,
+            redactor: Redactor::default(),
+        })
+    }
+
+    /// Rolls up pending records per protocol and per connection before they are
+    /// written, so buffers and the database grow with distinct flows rather than packets
+    pub fn with_rollup(mut self, rollup: bool) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Prints addresses and host names redacted through the tag books handed out
+    pub fn with_privacy(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// Writes buffered records at least this often, however few there are; 5 seconds by default
+    pub fn with_flush_interval(self, interval: Duration) -> Self {
+        self.buffer.set_flush_interval(interval);
+        self
     }
 
     #[allow(dead_code)]
     pub fn store_packet_stats(&self, stats: PacketStatistics) -> Result<()> {
-        let mut pending = self.pending_stats.lock().unwrap();
-        pending.push(stats);
-        
-        if pending.len() >= self.batch_size {
-            self.flush_packet_stats()?;
-        }
-        
-        Ok(())
+        self.buffer.push(|pending| pending.stats.push(stats))
     }
 
     pub fn store_protocol_info(&self, record: ProtocolRecord) -> Result<()> {
-        let mut pending = self.pending_protocols.lock().unwrap();
-        pending.push(record);
-        
-        if pending.len() >= self.batch_size {
-            self.flush_protocol_records()?;
-        }
-        
-        Ok(())
+        let rollup = self.rollup;
+        self.buffer.push(|pending| {
+            let pending = &mut pending.protocols;
+            let existing = if rollup {
+                pending.iter_mut().find(|p| {
+                    p.interface_name == record.interface_name
+                        && p.protocol_name == record.protocol_name
+                        && p.is_encrypted == record.is_encrypted
+                        && p.sample_rate == record.sample_rate
+                })
+            } else {
+                None
+            };
+            match existing {
+                Some(existing) => {
+                    existing.packet_count += record.packet_count;
+                    existing.byte_count += record.byte_count;
+                }
+                None => pending.push(record),
+            }
+        })
     }
 
     pub fn store_connection(&self, record: ConnectionRecord) -> Result<()> {
-        let mut pending = self.pending_connections.lock().unwrap();
-        pending.push(record);
-        
-        if pending.len() >= self.batch_size {
-            self.flush_connection_records()?;
-        }
-        
-        Ok(())
+        let rollup = self.rollup;
+        self.buffer.push(|pending| {
+            let pending = &mut pending.connections;
+            let existing = if rollup {
+                pending.iter_mut().find(|p| p.connection_key == record.connection_key)
+            } else {
+                None
+            };
+            match existing {
+                Some(existing) => {
+                    existing.packet_count += record.packet_count;
+                    existing.byte_count += record.byte_count;
+                    existing.last_seen = existing.last_seen.max(record.last_seen);
+                    existing.sample_rate = existing.sample_rate.max(record.sample_rate);
+                    if existing.application_protocol.is_none() {
+                        existing.application_protocol = record.application_protocol;
+                    }
+                }
+                None => pending.push(record),
+            }
+        })
     }
 
     pub fn store_security_event(&self, event: SecurityEvent) -> Result<()> {
-        let mut pending = self.pending_security_events.lock().unwrap();
-        pending.push(event);
-        
-        if pending.len() >= self.batch_size {
-            self.flush_security_events()?;
-        }
-        
-        Ok(())
+        self.buffer.push(|pending| pending.security_events.push(event))
+    }
+
+    /// Records buffered and not yet written to the database
+    pub fn pending_records(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Writes all pending records to the database immediately, in one transaction
+    pub fn flush_all(&self) -> Result<()> {
+        self.buffer.flush()
     }
 
     pub fn analyze_packet_for_storage(
@@ -220,82 +681,51 @@ impl PacketStorage {
         packet: &NetworkPacket,
         analysis: &AnalysisResult,
     ) -> Result<()> {
-        // Store protocol information
-        if let Some(protocol_name) = &analysis.application_protocol {
-            let protocol_record = ProtocolRecord {
-                timestamp: packet.timestamp,
-                interface_name: packet.interface.clone(),
-                protocol_name: protocol_name.clone(),
-                packet_count: 1,
-                byte_count: packet.size_bytes,
-                is_encrypted: analysis.is_encrypted,
-            };
-            self.store_protocol_info(protocol_record)?;
-        }
-
-        // Store connection information
-        if let (Some(src), Some(dst)) = (packet.source_addr, packet.dest_addr) {
-            let connection_key = format!(
-                "{}:{}-{}:{}",
-                src,
-                packet.source_port.unwrap_or(0),
-                dst,
-                packet.dest_port.unwrap_or(0)
-            );
-            
-            let connection_record = ConnectionRecord {
-                connection_key,
-                source_ip: src.to_string(),
-                dest_ip: dst.to_string(),
-                source_port: packet.source_port,
-                dest_port: packet.dest_port,
-                protocol: format!("{:?}", packet.transport_protocol),
-                application_protocol: analysis.application_protocol.clone(),
-                first_seen: packet.timestamp,
-                last_seen: packet.timestamp,
-                packet_count: 1,
-                byte_count: packet.size_bytes,
-                is_active: true,
-            };
-            self.store_connection(connection_record)?;
-        }
-
-        // Store security events
-        for flag in &analysis.security_flags {
-            let event = SecurityEvent {
-                timestamp: packet.timestamp,
-                interface_name: packet.interface.clone(),
-                event_type: format!("{flag:?}"),
-                source_ip: packet.source_addr.map(|ip| ip.to_string()),
-                dest_ip: packet.dest_addr.map(|ip| ip.to_string()),
-                port: packet.dest_port.or(packet.source_port),
-                protocol: Some(format!("{:?}", packet.transport_protocol)),
-                description: self.security_flag_description(flag),
-                severity: self.security_flag_severity(flag),
-            };
+        self.analyze_sampled_packet_for_storage(packet, analysis, 1)
+    }
+
+    /// Stores a packet kept by sampling one in `sample_rate`, counting it that many times
+    pub fn analyze_sampled_packet_for_storage(
+        &self,
+        packet: &NetworkPacket,
+        analysis: &AnalysisResult,
+        sample_rate: u64,
+    ) -> Result<()> {
+        let records = PacketRecords::new(packet, analysis, sample_rate);
+        if let Some(record) = records.protocol {
+            self.store_protocol_info(record)?;
+        }
+        if let Some(record) = records.connection {
+            self.store_connection(record)?;
+        }
+        for event in records.security_events {
             self.store_security_event(event)?;
         }
-
         Ok(())
     }
 
+    /// Packets, bytes and protocols of `interface` between `since` and `until`, with the
+    /// busiest connections active in that time
     pub fn get_traffic_summary(
         &self,
         interface: &str,
         since: DateTime<Local>,
+        until: DateTime<Local>,
     ) -> Result<TrafficSummary> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.readers.get();
+        let since = since.format("%Y-%m-%d %H:%M:%S").to_string();
+        let until = until.format("%Y-%m-%d %H:%M:%S").to_string();
         
         let mut stmt = conn.prepare(
             "SELECT 
                 COALESCE(SUM(total_packets), 0) as total_packets,
                 COALESCE(SUM(total_bytes), 0) as total_bytes
              FROM packet_stats 
-             WHERE interface_name = ?1 AND timestamp >= ?2"
+             WHERE interface_name = ?1 AND timestamp >= ?2 AND timestamp <= ?3"
         )?;
         
         let (total_packets, total_bytes): (u64, u64) = stmt.query_row(
-            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string()],
+            params![interface, since, until],
             |row| Ok((row.get(0)?, row.get(1)?))
         ).unwrap_or((0, 0));
 
@@ -304,12 +734,12 @@ impl PacketStorage {
         let mut protocol_stmt = conn.prepare(
             "SELECT protocol_name, SUM(packet_count), SUM(byte_count), COUNT(DISTINCT id)
              FROM protocol_distribution 
-             WHERE interface_name = ?1 AND timestamp >= ?2
+             WHERE interface_name = ?1 AND timestamp >= ?2 AND timestamp <= ?3
              GROUP BY protocol_name"
         )?;
         
         let protocol_rows = protocol_stmt.query_map(
-            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string()],
+            params![interface, since, until],
             |row| {
                 Ok((
                     row.get::<_, String>(0)?,
@@ -331,13 +761,13 @@ impl PacketStorage {
         let mut connection_stmt = conn.prepare(
             "SELECT source_ip, dest_ip, protocol, packet_count, byte_count
              FROM connections 
-             WHERE last_seen >= ?1
+             WHERE last_seen >= ?1 AND first_seen <= ?2
              ORDER BY byte_count DESC 
              LIMIT 10"
         )?;
         
         let connection_rows = connection_stmt.query_map(
-            params![since.format("%Y-%m-%d %H:%M:%S").to_string()],
+            params![since, until],
             |row| {
                 Ok(ConnectionSummary {
                     source: row.get(0)?,
@@ -351,256 +781,1728 @@ impl PacketStorage {
 
         let top_connections: Result<Vec<_>, _> = connection_rows.collect();
 
+        // Databases opened read-only from before sampling have no rates, read as NULL
+        let sample_rate: u64 = conn.query_row(
+            "SELECT MAX(
+                COALESCE((SELECT MAX(sample_rate) FROM protocol_distribution
+                          WHERE interface_name = ?1 AND timestamp >= ?2 AND timestamp <= ?3), 1),
+                COALESCE((SELECT MAX(sample_rate) FROM connections WHERE last_seen >= ?2 AND first_seen <= ?3), 1)
+             )",
+            params![interface, since, until],
+            |row| row.get(0),
+        )?;
+
         Ok(TrafficSummary {
             timestamp: Local::now(),
             interface_name: interface.to_string(),
             total_packets,
             total_bytes,
             protocols,
+            sample_rate,
             traffic_types: HashMap::new(), // TODO: Implement traffic type aggregation
             top_connections: top_connections?,
         })
     }
 
-    #[allow(dead_code)]
-    pub fn cleanup_old_data(&self) -> Result<()> {
+    /// Records a data quality sample; written immediately since samples are infrequent
+    pub fn store_quality_sample(&self, sample: &QualitySample) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let tx = conn.unchecked_transaction()?;
-        setup_data_retention(&tx)?;
-        tx.commit()?;
-        info!("Database cleanup completed");
+        conn.execute(
+            "INSERT INTO data_quality
+             (timestamp, interface_name, duration_secs, confidence, counter_resets,
+              collection_failures, packets_captured, packets_dropped)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                sample.interface_name,
+                sample.duration_secs,
+                format!("{:?}", sample.confidence),
+                sample.counter_resets,
+                sample.collection_failures,
+                sample.packets_captured,
+                sample.packets_dropped,
+            ],
+        )?;
         Ok(())
     }
 
-    #[allow(dead_code)]
-    fn flush_packet_stats(&self) -> Result<()> {
-        let stats_to_flush = {
-            let mut pending = self.pending_stats.lock().unwrap();
-            if pending.is_empty() {
-                return Ok(());
-            }
-            std::mem::take(&mut *pending)
-        };
+    /// Stores per-interface throughput samples in one transaction
+    pub fn store_bandwidth_samples(&self, samples: &[BandwidthSample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
 
         let conn = self.conn.lock().unwrap();
         let tx = conn.unchecked_transaction()?;
-
         {
             let mut stmt = tx.prepare(
-                "INSERT INTO packet_stats (
-                    timestamp, interface_name, total_packets, total_bytes,
-                    packets_per_second, bytes_per_second,
-                    tcp_packets, udp_packets, icmp_packets, other_packets,
-                    tcp_bytes, udp_bytes, icmp_bytes, other_bytes
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
+                "INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
             )?;
-
-            for stats in stats_to_flush {
+            for sample in samples {
                 stmt.execute(params![
-                    stats.start_time.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    "", // interface name would need to be added to PacketStatistics
-                    stats.total_packets,
-                    stats.total_bytes,
-                    stats.packets_per_second,
-                    stats.bytes_per_second,
-                    stats.protocol_distribution.tcp_packets,
-                    stats.protocol_distribution.udp_packets,
-                    stats.protocol_distribution.icmp_packets,
-                    stats.protocol_distribution.other_packets,
-                    stats.protocol_distribution.tcp_bytes,
-                    stats.protocol_distribution.udp_bytes,
-                    stats.protocol_distribution.icmp_bytes,
-                    stats.protocol_distribution.other_bytes,
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface_name,
+                    sample.duration_secs,
+                    sample.download_bps,
+                    sample.upload_bps,
                 ])?;
             }
         }
-
         tx.commit()?;
-        debug!("Flushed packet statistics to database");
+        debug!("Stored {} bandwidth samples", samples.len());
         Ok(())
     }
 
-    fn flush_protocol_records(&self) -> Result<()> {
-        let records_to_flush = {
-            let mut pending = self.pending_protocols.lock().unwrap();
-            if pending.is_empty() {
-                return Ok(());
-            }
-            std::mem::take(&mut *pending)
+    /// Stores samples of usage recorded by another tool in one transaction, skipping those whose
+    /// interval overlaps a sample already stored for the interface, so that importing twice, or
+    /// over days kaipo-watcher was recording, does not count traffic again. Returns the number stored
+    pub fn import_bandwidth_samples(&self, samples: &[BandwidthSample]) -> Result<usize> {
+        let (Some(first), Some(last)) = (
+            samples.iter().map(|sample| sample.timestamp - sample_span(sample)).min(),
+            samples.iter().map(|sample| sample.timestamp).max(),
+        ) else {
+            return Ok(0);
         };
 
         let conn = self.conn.lock().unwrap();
         let tx = conn.unchecked_transaction()?;
-
+        let mut stored = 0;
         {
-            let mut stmt = tx.prepare(
-                "INSERT INTO protocol_distribution (
-                    timestamp, interface_name, protocol_name, 
-                    packet_count, byte_count, is_encrypted
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
-            )?;
-
-            for record in records_to_flush {
-                stmt.execute(params![
-                    record.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    record.interface_name,
-                    record.protocol_name,
-                    record.packet_count,
-                    record.byte_count,
-                    record.is_encrypted,
-                ])?;
+            // Stored samples can end up to a month after the start of an imported one
+            let stored_samples = read_bandwidth_samples(&tx, first, last + chrono::Duration::days(MAX_SAMPLE_DAYS))?;
+            let mut covered: HashMap<String, Vec<(i64, i64)>> = HashMap::new();
+            for sample in &stored_samples {
+                covered.entry(sample.interface_name.clone()).or_default().push(sample_interval(sample));
             }
-        }
-
-        tx.commit()?;
-        debug!("Flushed protocol records to database");
-        Ok(())
-    }
-
-    fn flush_connection_records(&self) -> Result<()> {
-        let records_to_flush = {
-            let mut pending = self.pending_connections.lock().unwrap();
-            if pending.is_empty() {
-                return Ok(());
+            for intervals in covered.values_mut() {
+                *intervals = merge_intervals(std::mem::take(intervals));
             }
-            std::mem::take(&mut *pending)
-        };
-
-        let conn = self.conn.lock().unwrap();
-        let tx = conn.unchecked_transaction()?;
 
-        {
             let mut stmt = tx.prepare(
-                "INSERT OR REPLACE INTO connections (
-                    connection_key, source_ip, dest_ip, source_port, dest_port,
-                    protocol, application_protocol, first_seen, last_seen,
-                    packet_count, byte_count, is_active
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+                "INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
             )?;
-
-            for record in records_to_flush {
+            for sample in samples {
+                let (start, end) = sample_interval(sample);
+                let intervals = covered.entry(sample.interface_name.clone()).or_default();
+                let next = intervals.partition_point(|(_, covered_end)| *covered_end <= start);
+                if intervals.get(next).is_some_and(|(covered_start, _)| *covered_start < end) {
+                    continue;
+                }
+                intervals.insert(next, (start, end));
                 stmt.execute(params![
-                    record.connection_key,
-                    record.source_ip,
-                    record.dest_ip,
-                    record.source_port,
-                    record.dest_port,
-                    record.protocol,
-                    record.application_protocol,
-                    record.first_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    record.last_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    record.packet_count,
-                    record.byte_count,
-                    record.is_active,
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface_name,
+                    sample.duration_secs,
+                    sample.download_bps,
+                    sample.upload_bps,
                 ])?;
+                stored += 1;
             }
         }
-
         tx.commit()?;
-        debug!("Flushed connection records to database");
-        Ok(())
+        info!("Imported {stored} of {} bandwidth samples", samples.len());
+        Ok(stored)
     }
 
-    fn flush_security_events(&self) -> Result<()> {
-        let events_to_flush = {
-            let mut pending = self.pending_security_events.lock().unwrap();
-            if pending.is_empty() {
-                return Ok(());
-            }
-            std::mem::take(&mut *pending)
-        };
-
-        let conn = self.conn.lock().unwrap();
-        let tx = conn.unchecked_transaction()?;
+    /// Throughput samples recorded between `start` and `end`, oldest first
+    pub fn get_bandwidth_samples(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<Vec<BandwidthSample>> {
+        let conn = self.readers.get();
+        Ok(read_bandwidth_samples(&conn, start, end)?)
+    }
 
-        {
-            let mut stmt = tx.prepare(
-                "INSERT INTO security_events (
-                    timestamp, interface_name, event_type, source_ip, dest_ip,
-                    port, protocol, description, severity
-                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
-            )?;
+    /// `limit` throughput samples from `offset` on, oldest first, on `interface` or all of them
+    pub fn get_bandwidth_page(
+        &self,
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+        interface: Option<&str>,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Page<BandwidthSample>> {
+        let conn = self.readers.get();
+        let start = start.format("%Y-%m-%d %H:%M:%S").to_string();
+        let end = end.format("%Y-%m-%d %H:%M:%S").to_string();
+        let total = conn.query_row(
+            "SELECT COUNT(*) FROM bandwidth_samples
+             WHERE timestamp >= ?1 AND timestamp <= ?2 AND (?3 IS NULL OR interface_name = ?3)",
+            params![start, end, interface],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, duration_secs, download_bps, upload_bps
+             FROM bandwidth_samples
+             WHERE timestamp >= ?1 AND timestamp <= ?2 AND (?3 IS NULL OR interface_name = ?3)
+             ORDER BY timestamp, id
+             LIMIT ?4 OFFSET ?5",
+        )?;
+        let items = stmt
+            .query_map(params![start, end, interface, limit, offset], |row| {
+                Ok(BandwidthSample {
+                    timestamp: parse_local_timestamp(&row.get::<_, String>(0)?),
+                    interface_name: row.get(1)?,
+                    duration_secs: row.get(2)?,
+                    download_bps: row.get(3)?,
+                    upload_bps: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Page { items, total })
+    }
 
-            for event in events_to_flush {
-                stmt.execute(params![
-                    event.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
-                    event.interface_name,
-                    event.event_type,
-                    event.source_ip,
-                    event.dest_ip,
-                    event.port,
-                    event.protocol,
-                    event.description,
-                    event.severity,
-                ])?;
-            }
-        }
+    /// Captured protocols between `start` and `end`, busiest first, on `interface` or all of them
+    pub fn get_protocol_page(
+        &self,
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+        interface: Option<&str>,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Page<ProtocolTotal>> {
+        let conn = self.readers.get();
+        let start = start.format("%Y-%m-%d %H:%M:%S").to_string();
+        let end = end.format("%Y-%m-%d %H:%M:%S").to_string();
+        let total = conn.query_row(
+            "SELECT COUNT(DISTINCT protocol_name) FROM protocol_distribution
+             WHERE timestamp >= ?1 AND timestamp <= ?2 AND (?3 IS NULL OR interface_name = ?3)",
+            params![start, end, interface],
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT protocol_name, SUM(packet_count), SUM(byte_count), MAX(is_encrypted)
+             FROM protocol_distribution
+             WHERE timestamp >= ?1 AND timestamp <= ?2 AND (?3 IS NULL OR interface_name = ?3)
+             GROUP BY protocol_name
+             ORDER BY 3 DESC, protocol_name
+             LIMIT ?4 OFFSET ?5",
+        )?;
+        let items = stmt
+            .query_map(params![start, end, interface, limit, offset], |row| {
+                Ok(ProtocolTotal {
+                    protocol: row.get(0)?,
+                    packets: row.get(1)?,
+                    bytes: row.get(2)?,
+                    encrypted: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Page { items, total })
+    }
 
+    /// Records a capture performance sample and the packet sizes of its interval; written
+    /// immediately since samples are infrequent
+    pub fn store_capture_sample(&self, sample: &CaptureSample) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO capture_stats
+             (timestamp, session_start, interface_name, duration_secs, packets, bytes,
+              peak_packets_per_second, peak_bytes_per_second, queue_drops, kernel_drops,
+              queue_capacity, avg_queue_depth, peak_queue_depth, peak_storage_backlog)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                sample.session_start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                sample.interface_name,
+                sample.duration_secs,
+                sample.packets,
+                sample.bytes,
+                sample.peak_packets_per_second,
+                sample.peak_bytes_per_second,
+                sample.queue_drops,
+                sample.kernel_drops,
+                sample.queue_capacity,
+                sample.avg_queue_depth,
+                sample.peak_queue_depth,
+                sample.peak_storage_backlog,
+            ],
+        )?;
+        insert_packet_sizes(&tx, sample.timestamp, &sample.packet_sizes)?;
         tx.commit()?;
-        debug!("Flushed security events to database");
         Ok(())
     }
 
-    fn start_background_flush(&self) {
-        let stats_clone = Arc::clone(&self.pending_stats);
-        let protocols_clone = Arc::clone(&self.pending_protocols);
-        let connections_clone = Arc::clone(&self.pending_connections);
-        let security_events_clone = Arc::clone(&self.pending_security_events);
-        let conn_clone = Arc::clone(&self.conn);
-
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(30));
-            loop {
-                interval.tick().await;
-                
-                // Force flush all pending data every 30 seconds
-                if let Err(e) = Self::force_flush_all(
-                    &stats_clone,
-                    &protocols_clone,
-                    &connections_clone,
-                    &security_events_clone,
-                    &conn_clone,
-                ).await {
-                    warn!("Background flush failed: {e}");
-                }
-            }
-        });
+    /// Records the bandwidth collector's health over an interval; written immediately since
+    /// samples are infrequent
+    pub fn store_collector_health(&self, sample: &CollectorHealthSample) -> Result<()> {
+        let telemetry = &sample.telemetry;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO collector_health
+             (timestamp, duration_secs, collections, failed_collections, degraded_collections,
+              retries, total_duration_ms, max_duration_ms, failed_interfaces)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                sample.duration_secs,
+                telemetry.collections,
+                telemetry.failed_collections,
+                telemetry.degraded_collections,
+                telemetry.retries,
+                telemetry.total_duration.as_secs_f64() * 1000.0,
+                telemetry.max_duration.as_secs_f64() * 1000.0,
+                serde_json::to_string(&telemetry.failed_interfaces)?,
+            ],
+        )?;
+        Ok(())
     }
 
-    async fn force_flush_all(
-        _stats: &Arc<Mutex<Vec<PacketStatistics>>>,
-        _protocols: &Arc<Mutex<Vec<ProtocolRecord>>>,
-        _connections: &Arc<Mutex<Vec<ConnectionRecord>>>,
-        _security_events: &Arc<Mutex<Vec<SecurityEvent>>>,
-        _conn: &Arc<Mutex<Connection>>,
-    ) -> Result<()> {
-        // Implementation would flush all pending data
-        // This is a simplified version for now
-        debug!("Background flush executed");
+    /// Records a change to a network interface; written immediately since changes are rare
+    pub fn store_interface_change(&self, change: &InterfaceChange) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO interface_events (timestamp, interface_name, change) VALUES (?1, ?2, ?3)",
+            params![change.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(), change.interface_name, change.change],
+        )?;
+        Ok(())
+    }
+
+    /// Collector health samples since `since`, oldest first
+    pub fn get_collector_health(&self, since: DateTime<Local>) -> Result<Vec<CollectorHealthSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, duration_secs, collections, failed_collections, degraded_collections,
+                    retries, total_duration_ms, max_duration_ms, failed_interfaces
+             FROM collector_health
+             WHERE timestamp >= ?1
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![since.format("%Y-%m-%d %H:%M:%S").to_string()], |row| {
+            let failed_interfaces: String = row.get(8)?;
+            Ok(CollectorHealthSample {
+                timestamp: parse_local_timestamp(&row.get::<_, String>(0)?),
+                duration_secs: row.get(1)?,
+                telemetry: CollectorTelemetry {
+                    collections: row.get(2)?,
+                    failed_collections: row.get(3)?,
+                    degraded_collections: row.get(4)?,
+                    retries: row.get(5)?,
+                    total_duration: Duration::from_secs_f64(row.get::<_, f64>(6)?.max(0.0) / 1000.0),
+                    max_duration: Duration::from_secs_f64(row.get::<_, f64>(7)?.max(0.0) / 1000.0),
+                    // An unreadable list only loses the names, not the sample
+                    failed_interfaces: serde_json::from_str(&failed_interfaces).unwrap_or_default(),
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Records packet size histograms counted up to `timestamp`, for captures without
+    /// capture performance samples such as pcap imports
+    pub fn store_packet_sizes(&self, timestamp: DateTime<Local>, sizes: &PacketSizeDistribution) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        insert_packet_sizes(&tx, timestamp, sizes)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Packet size histograms recorded between `since` and `until`; `interface` "all" matches every interface
+    pub fn get_packet_sizes(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<PacketSizeDistribution> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT interface_name, protocol, SUM(tiny_packets), SUM(small_packets), SUM(large_packets), SUM(jumbo_packets)
+             FROM packet_size_stats
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             GROUP BY interface_name, protocol",
+        )?;
+        let mut sizes = PacketSizeDistribution::default();
+        let rows = stmt.query_map(params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                PacketSizeHistogram { tiny: row.get(2)?, small: row.get(3)?, large: row.get(4)?, jumbo: row.get(5)? },
+            ))
+        })?;
+        for row in rows {
+            let (interface, protocol, histogram) = row?;
+            sizes.merge(&interface, &protocol, &histogram);
+        }
+        Ok(sizes)
+    }
+
+    /// The most recent capture sessions, newest first
+    pub fn get_capture_sessions(&self, limit: usize) -> Result<Vec<CaptureSessionSummary>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT session_start, interface_name, MAX(timestamp), COUNT(*), SUM(duration_secs),
+                    SUM(packets), SUM(bytes), MAX(peak_packets_per_second), MAX(peak_bytes_per_second),
+                    SUM(queue_drops), SUM(kernel_drops), MAX(queue_capacity), MAX(peak_queue_depth),
+                    MAX(peak_storage_backlog)
+             FROM capture_stats
+             GROUP BY session_start, interface_name
+             ORDER BY session_start DESC
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(CaptureSessionSummary {
+                session_start: parse_local_timestamp(&row.get::<_, String>(0)?),
+                interface_name: row.get(1)?,
+                last_sample: parse_local_timestamp(&row.get::<_, String>(2)?),
+                samples: row.get(3)?,
+                duration_secs: row.get(4)?,
+                packets: row.get(5)?,
+                bytes: row.get(6)?,
+                peak_packets_per_second: row.get(7)?,
+                peak_bytes_per_second: row.get(8)?,
+                queue_drops: row.get(9)?,
+                kernel_drops: row.get(10)?,
+                queue_capacity: row.get(11)?,
+                peak_queue_depth: row.get(12)?,
+                peak_storage_backlog: row.get(13)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The samples of one capture session, oldest first
+    pub fn get_capture_samples(&self, session: &CaptureSessionSummary) -> Result<Vec<CaptureSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, session_start, interface_name, duration_secs, packets, bytes,
+                    peak_packets_per_second, peak_bytes_per_second, queue_drops, kernel_drops,
+                    queue_capacity, avg_queue_depth, peak_queue_depth, peak_storage_backlog
+             FROM capture_stats
+             WHERE session_start = ?1 AND interface_name = ?2
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                session.session_start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                session.interface_name
+            ],
+            |row| {
+                Ok(CaptureSample {
+                    timestamp: parse_local_timestamp(&row.get::<_, String>(0)?),
+                    session_start: parse_local_timestamp(&row.get::<_, String>(1)?),
+                    interface_name: row.get(2)?,
+                    duration_secs: row.get(3)?,
+                    packets: row.get(4)?,
+                    bytes: row.get(5)?,
+                    peak_packets_per_second: row.get(6)?,
+                    peak_bytes_per_second: row.get(7)?,
+                    queue_drops: row.get(8)?,
+                    kernel_drops: row.get(9)?,
+                    queue_capacity: row.get(10)?,
+                    avg_queue_depth: row.get(11)?,
+                    peak_queue_depth: row.get(12)?,
+                    peak_storage_backlog: row.get(13)?,
+                    packet_sizes: PacketSizeDistribution::default(),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Records the result of a speed test
+    pub fn store_speed_test(&self, result: &SpeedTestResult) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO speed_tests
+             (timestamp, server, latency_ms, jitter_ms, download_bps, upload_bps, download_bytes, upload_bytes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                result.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                result.server,
+                result.latency_ms,
+                result.jitter_ms,
+                result.download_bps,
+                result.upload_bps,
+                result.download_bytes,
+                result.upload_bytes,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent speed test results, newest first
+    pub fn get_speed_tests(&self, limit: usize) -> Result<Vec<SpeedTestResult>> {
+        let conn = self.readers.get();
+        read_speed_tests(&conn, None, limit)
+    }
+
+    /// Aggregates the quality samples recorded between `start` and `end`
+    pub fn get_data_quality(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<DataQualitySummary> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT confidence, SUM(duration_secs), SUM(counter_resets), SUM(collection_failures),
+                    SUM(packets_captured), SUM(packets_dropped)
+             FROM data_quality
+             WHERE timestamp >= ?1 AND timestamp <= ?2
+             GROUP BY confidence",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                end.format("%Y-%m-%d %H:%M:%S").to_string()
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, u64>(5)?,
+                ))
+            },
+        )?;
+
+        let mut summary = DataQualitySummary::default();
+        for row in rows {
+            let (confidence, secs, resets, failures, captured, dropped) = row?;
+            match confidence.as_str() {
+                "High" => summary.high_secs += secs,
+                "Medium" => summary.medium_secs += secs,
+                "Low" => summary.low_secs += secs,
+                _ => summary.none_secs += secs,
+            }
+            summary.sampled_secs += secs;
+            summary.counter_resets += resets;
+            summary.collection_failures += failures;
+            summary.packets_captured += captured;
+            summary.packets_dropped += dropped;
+        }
+        Ok(summary)
+    }
+
+    /// Connection bytes between `start` and `end` per hour, protocol and service port
+    /// Each connection counts towards the hour it was last seen in
+    pub fn get_service_usage(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<Vec<ServiceUsage>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT strftime('%Y-%m-%d %H:00:00', last_seen) AS hour, protocol,
+                    CASE WHEN source_port IS NULL OR dest_port IS NULL THEN COALESCE(source_port, dest_port)
+                         ELSE MIN(source_port, dest_port) END AS service_port,
+                    SUM(byte_count)
+             FROM connections
+             WHERE last_seen >= ?1 AND first_seen <= ?2
+             GROUP BY hour, protocol, service_port",
+        )?;
+        let usage = stmt
+            .query_map(
+                params![
+                    start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end.format("%Y-%m-%d %H:%M:%S").to_string()
+                ],
+                |row| {
+                    Ok(ServiceUsage {
+                        hour: parse_local_timestamp(&row.get::<_, String>(0)?),
+                        protocol: row.get(1)?,
+                        service_port: row.get(2)?,
+                        bytes: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(usage)
+    }
+
+    /// Connections active between `since` and `until` grouped by application protocol, busiest first
+    pub fn get_application_usage(&self, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<ApplicationUsage>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT COALESCE(application_protocol, protocol), COUNT(*), SUM(packet_count), SUM(byte_count)
+             FROM connections
+             WHERE last_seen >= ?1 AND first_seen <= ?2
+             GROUP BY 1
+             ORDER BY 4 DESC, 2 DESC, 1",
+        )?;
+        let usage = stmt
+            .query_map(params![since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()], |row| {
+                Ok(ApplicationUsage {
+                    protocol: row.get(0)?,
+                    flows: row.get(1)?,
+                    packets: row.get(2)?,
+                    bytes: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(usage)
+    }
+
+    /// Traffic with each public address at either end of a connection active between `start`
+    /// and `end`, busiest first; connections between two public addresses count for the destination
+    pub fn get_remote_traffic(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<Vec<RemoteTraffic>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT source_ip, dest_ip, COUNT(*), SUM(packet_count), SUM(byte_count)
+             FROM connections
+             WHERE last_seen >= ?1 AND first_seen <= ?2
+             GROUP BY source_ip, dest_ip",
+        )?;
+        let pairs = stmt
+            .query_map(
+                params![
+                    start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end.format("%Y-%m-%d %H:%M:%S").to_string()
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, u64>(3)?,
+                        row.get::<_, u64>(4)?,
+                    ))
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut remotes: HashMap<IpAddr, RemoteTraffic> = HashMap::new();
+        for (source, dest, flows, packets, bytes) in pairs {
+            let public = |address: &str| address.parse::<IpAddr>().ok().filter(|ip| !is_private(*ip));
+            let Some(address) = public(&dest).or_else(|| public(&source)) else { continue };
+            let remote = remotes
+                .entry(address)
+                .or_insert(RemoteTraffic { address, flows: 0, packets: 0, bytes: 0 });
+            remote.flows += flows;
+            remote.packets += packets;
+            remote.bytes += bytes;
+        }
+        let mut remotes: Vec<_> = remotes.into_values().collect();
+        remotes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.address.cmp(&b.address)));
+        Ok(remotes)
+    }
+
+    /// Totals of the connections active between `start` and `end`
+    pub fn get_usage_summary(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<UsageSummary> {
+        let conn = self.readers.get();
+        let range = params![
+            start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            end.format("%Y-%m-%d %H:%M:%S").to_string()
+        ];
+
+        let (connections, packets, bytes) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(packet_count), 0), COALESCE(SUM(byte_count), 0)
+             FROM connections
+             WHERE last_seen >= ?1 AND first_seen <= ?2",
+            range,
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT COALESCE(application_protocol, protocol), SUM(packet_count), SUM(byte_count)
+             FROM connections
+             WHERE last_seen >= ?1 AND first_seen <= ?2
+             GROUP BY 1
+             ORDER BY 3 DESC",
+        )?;
+        let applications = stmt
+            .query_map(range, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT address, SUM(packet_count), SUM(byte_count)
+             FROM (SELECT source_ip AS address, packet_count, byte_count, first_seen, last_seen FROM connections
+                   UNION ALL
+                   SELECT dest_ip, packet_count, byte_count, first_seen, last_seen FROM connections)
+             WHERE last_seen >= ?1 AND first_seen <= ?2
+             GROUP BY address
+             ORDER BY 3 DESC
+             LIMIT ?3",
+        )?;
+        let hosts = stmt
+            .query_map(
+                params![
+                    start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    USAGE_HOST_LIMIT
+                ],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(UsageSummary {
+            connections,
+            packets,
+            bytes,
+            applications,
+            hosts,
+        })
+    }
+
+    /// Every address at either end of a connection active in the period, sorted
+    pub fn get_hosts_seen(&self, start: DateTime<Local>, end: DateTime<Local>) -> Result<Vec<String>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT source_ip FROM connections WHERE last_seen >= ?1 AND first_seen <= ?2
+             UNION
+             SELECT dest_ip FROM connections WHERE last_seen >= ?1 AND first_seen <= ?2
+             ORDER BY 1",
+        )?;
+        let hosts = stmt
+            .query_map(
+                params![
+                    start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    end.format("%Y-%m-%d %H:%M:%S").to_string()
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hosts)
+    }
+
+    /// Adds a tag or replaces the existing one for the same target
+    pub fn set_tag(&self, tag: &Tag) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO tags (target, kind, label, note, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                tag.target,
+                tag.kind.as_str(),
+                tag.label,
+                tag.note,
+                tag.updated_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            ],
+        )?;
+        let detail = match &tag.note {
+            Some(note) => format!("{} = \"{}\" (note: {note})", tag.target, tag.label),
+            None => format!("{} = \"{}\"", tag.target, tag.label),
+        };
+        AuditChain::load(&tx)?.append(&tx, audit::ADMIN, "tag set", &detail)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Deletes the tag for `target`; false when there was none
+    pub fn remove_tag(&self, target: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        if tx.execute("DELETE FROM tags WHERE target = ?1", params![target])? == 0 {
+            return Ok(false);
+        }
+        AuditChain::load(&tx)?.append(&tx, audit::ADMIN, "tag removed", target)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Adds an entry to the audit log
+    pub fn append_audit(&self, category: &str, action: &str, detail: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        AuditChain::load(&tx)?.append(&tx, category, action, detail)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the configuration in the audit log when it differs from the last one recorded
+    /// Returns whether an entry was added
+    pub fn record_config_fingerprint(&self, fingerprint: &ConfigFingerprint) -> Result<bool> {
+        let last = {
+            let conn = self.conn.lock().unwrap();
+            audit::read_entries(&conn, Some(audit::CONFIG), 1)?
+        };
+        let action = match last.first() {
+            Some(entry) if entry.detail.starts_with(&fingerprint.digest) => return Ok(false),
+            Some(_) => "changed",
+            None => "loaded",
+        };
+        self.append_audit(audit::CONFIG, action, &fingerprint.to_string())?;
+        Ok(true)
+    }
+
+    /// Recomputes the audit log's hash chain
+    pub fn verify_audit_log(&self) -> Result<AuditVerification> {
+        let conn = self.readers.get();
+        audit::verify_chain(&conn)
+    }
+
+    /// Id of the audit log entry with `hash`, if any
+    pub fn find_audit_hash(&self, hash: &str) -> Result<Option<i64>> {
+        let conn = self.readers.get();
+        audit::find_hash(&conn, hash)
+    }
+
+    /// Most recent audit log entries, newest first, optionally of one category
+    pub fn get_audit_entries(&self, category: Option<&str>, limit: usize) -> Result<Vec<AuditEntry>> {
+        let conn = self.readers.get();
+        audit::read_entries(&conn, category, limit)
+    }
+
+    /// Every tag, sorted by kind and target
+    pub fn get_tags(&self) -> Result<Vec<Tag>> {
+        let conn = self.readers.get();
+        Ok(read_tags(&conn)?)
+    }
+
+    /// Tags, with the labels of discovered devices for addresses that have none
+    pub fn tag_book(&self) -> Result<TagBook> {
+        let mut book = TagBook::new(self.get_tags()?).with_redactor(self.redactor.clone());
+        for device in self.get_discovered_devices()? {
+            book.add_discovered(device.address, device.label());
+        }
+        Ok(book)
+    }
+
+    /// Records devices found or renamed by their announcements, keeping the first time each was seen
+    pub fn store_discovered_devices(&self, devices: &[DiscoveredDevice]) -> Result<()> {
+        if devices.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO discovered_devices (
+                    address, interface_name, name, device_type, source, first_seen, last_seen
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT(address) DO UPDATE SET
+                    interface_name = excluded.interface_name,
+                    name = COALESCE(excluded.name, name),
+                    device_type = CASE WHEN excluded.device_type = 'unknown' THEN device_type ELSE excluded.device_type END,
+                    source = excluded.source,
+                    last_seen = excluded.last_seen",
+            )?;
+            for device in devices {
+                stmt.execute(params![
+                    device.address.to_string(),
+                    device.interface,
+                    device.name,
+                    device.device_type.as_str(),
+                    device.source.as_str(),
+                    device.first_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    device.last_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} discovered devices", devices.len());
+        Ok(())
+    }
+
+    /// Every discovered device, by address
+    pub fn get_discovered_devices(&self) -> Result<Vec<DiscoveredDevice>> {
+        let conn = self.readers.get();
+        Ok(read_discovered_devices(&conn)?)
+    }
+
+    /// Most recent security events, newest first
+    pub fn get_recent_security_events(&self, limit: usize) -> Result<Vec<SecurityEvent>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, event_type, source_ip, dest_ip, port, protocol,
+                    description, severity
+             FROM security_events
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?1",
+        )?;
+        let events = stmt
+            .query_map(params![limit as i64], |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(SecurityEvent {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface_name: row.get(1)?,
+                    event_type: row.get(2)?,
+                    source_ip: row.get(3)?,
+                    dest_ip: row.get(4)?,
+                    port: row.get(5)?,
+                    protocol: row.get(6)?,
+                    description: row.get(7)?,
+                    severity: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(events)
+    }
+
+    /// Security events per event type between `since` and `until`, most frequent first
+    pub fn get_security_event_counts(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<(String, u64)>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT event_type, COUNT(*) FROM security_events
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             GROUP BY event_type
+             ORDER BY COUNT(*) DESC, event_type",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Every security event between `since` and `until`, oldest first
+    pub fn get_security_event_log(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<SecurityEvent>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, event_type, source_ip, dest_ip, port, protocol,
+                    description, severity
+             FROM security_events
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             ORDER BY timestamp, id",
+        )?;
+        let events = stmt
+            .query_map(params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()], |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(SecurityEvent {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface_name: row.get(1)?,
+                    event_type: row.get(2)?,
+                    source_ip: row.get(3)?,
+                    dest_ip: row.get(4)?,
+                    port: row.get(5)?,
+                    protocol: row.get(6)?,
+                    description: row.get(7)?,
+                    severity: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(events)
+    }
+
+    /// Connections between two addresses, in either direction, active within `slack` of `at`;
+    /// the busiest first
+    pub fn get_connections_between(
+        &self,
+        first: &str,
+        second: &str,
+        at: DateTime<Local>,
+        slack: chrono::Duration,
+        limit: usize,
+    ) -> Result<Vec<ConnectionRecord>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT connection_key, source_ip, dest_ip, source_port, dest_port, protocol, application_protocol,
+                    first_seen, last_seen, packet_count, byte_count, is_active,
+                    COALESCE(classification_confidence, 'unknown'), sample_rate
+             FROM connections
+             WHERE ((source_ip = ?1 AND dest_ip = ?2) OR (source_ip = ?2 AND dest_ip = ?1))
+                   AND first_seen <= ?3 AND last_seen >= ?4
+             ORDER BY byte_count DESC, id
+             LIMIT ?5",
+        )?;
+        let connections = stmt
+            .query_map(
+                params![
+                    first,
+                    second,
+                    (at + slack).format("%Y-%m-%d %H:%M:%S").to_string(),
+                    (at - slack).format("%Y-%m-%d %H:%M:%S").to_string(),
+                    limit
+                ],
+                |row| {
+                    Ok(ConnectionRecord {
+                        connection_key: row.get(0)?,
+                        source_ip: row.get(1)?,
+                        dest_ip: row.get(2)?,
+                        source_port: row.get(3)?,
+                        dest_port: row.get(4)?,
+                        protocol: row.get(5)?,
+                        application_protocol: row.get(6)?,
+                        first_seen: parse_local_timestamp(&row.get::<_, String>(7)?),
+                        last_seen: parse_local_timestamp(&row.get::<_, String>(8)?),
+                        packet_count: row.get(9)?,
+                        byte_count: row.get(10)?,
+                        is_active: row.get(11)?,
+                        classification_confidence: row.get(12)?,
+                        sample_rate: row.get(13)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(connections)
+    }
+
+    /// Security events of the given types between `since` and `until`, newest first
+    pub fn get_security_events(
+        &self,
+        interface: &str,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+        event_types: &[&str],
+        limit: usize,
+    ) -> Result<Vec<SecurityEvent>> {
+        if event_types.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.readers.get();
+        let placeholders = vec!["?"; event_types.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT timestamp, interface_name, event_type, source_ip, dest_ip, port, protocol,
+                    description, severity
+             FROM security_events
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+                   AND event_type IN ({placeholders})
+             ORDER BY timestamp DESC, id DESC
+             LIMIT {limit}"
+        ))?;
+        let since = since.format("%Y-%m-%d %H:%M:%S").to_string();
+        let until = until.format("%Y-%m-%d %H:%M:%S").to_string();
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&interface, &since, &until];
+        values.extend(event_types.iter().map(|t| t as &dyn rusqlite::ToSql));
+        let events = stmt
+            .query_map(values.as_slice(), |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(SecurityEvent {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface_name: row.get(1)?,
+                    event_type: row.get(2)?,
+                    source_ip: row.get(3)?,
+                    dest_ip: row.get(4)?,
+                    port: row.get(5)?,
+                    protocol: row.get(6)?,
+                    description: row.get(7)?,
+                    severity: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(events)
+    }
+
+    /// Records TCP connection lifecycles; a connection stored again replaces its earlier row
+    pub fn store_tcp_sessions(&self, sessions: &[TcpSession]) -> Result<()> {
+        if sessions.is_empty() {
+            return Ok(());
+        }
+        let format = |t: DateTime<Local>| t.format("%Y-%m-%d %H:%M:%S").to_string();
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO tcp_sessions (
+                    interface_name, client_ip, client_port, server_ip, server_port, state,
+                    first_seen, established_at, closed_at, last_seen, duration_secs,
+                    handshake_ms, packet_count, byte_count, retransmissions, segments,
+                    duplicate_acks, out_of_order, kernel_cwnd, kernel_rtt_ms, kernel_rtt_var_ms,
+                    kernel_retransmits, kernel_lost, congestion_control
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+                          ?19, ?20, ?21, ?22, ?23, ?24)",
+            )?;
+
+            for session in sessions {
+                let kernel = session.kernel.as_ref();
+                stmt.execute(params![
+                    session.interface,
+                    session.client.ip().to_string(),
+                    session.client.port(),
+                    session.server.ip().to_string(),
+                    session.server.port(),
+                    session.state.to_string(),
+                    format(session.first_seen),
+                    session.established_at.map(format),
+                    session.closed_at.map(format),
+                    format(session.last_seen),
+                    session.duration().num_milliseconds() as f64 / 1000.0,
+                    session.handshake_time().map(|t| t.num_microseconds().unwrap_or(0) as f64 / 1000.0),
+                    session.packets,
+                    session.bytes,
+                    session.loss.retransmissions,
+                    session.loss.segments,
+                    session.loss.duplicate_acks,
+                    session.loss.out_of_order,
+                    kernel.map(|info| info.congestion_window),
+                    kernel.map(TcpInfo::rtt_ms),
+                    kernel.map(TcpInfo::rtt_var_ms),
+                    kernel.map(|info| info.retransmits),
+                    kernel.map(|info| info.lost),
+                    kernel.and_then(|info| info.congestion_control.as_deref()),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} TCP sessions", sessions.len());
+        Ok(())
+    }
+
+    /// Stores round-trip time measurements in one transaction
+    pub fn store_latency_samples(&self, samples: &[LatencySample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO latency_samples (timestamp, interface_name, host, rtt_ms, source)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface,
+                    sample.host.to_string(),
+                    sample.rtt_ms,
+                    sample.source.as_str(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} latency samples", samples.len());
+        Ok(())
+    }
+
+    /// (host, rtt_ms) pairs measured between `since` and `until`; `interface` "all" matches every interface
+    pub fn get_latency_samples(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<(String, f64)>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT host, rtt_ms FROM latency_samples
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Stores per-minute classification counts in one transaction
+    pub fn store_classification_samples(&self, samples: &[ClassificationSample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO classification_stats (
+                    timestamp, interface_name, packets, bytes,
+                    unknown_packets, unknown_bytes, low_confidence_bytes
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface,
+                    sample.packets,
+                    sample.bytes,
+                    sample.unknown_packets,
+                    sample.unknown_bytes,
+                    sample.low_confidence_bytes,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} classification samples", samples.len());
         Ok(())
     }
 
-    fn security_flag_description(&self, flag: &SecurityFlag) -> String {
-        match flag {
-            SecurityFlag::SuspiciousPort => "Traffic detected on suspicious port".to_string(),
-            SecurityFlag::UnencryptedSensitive => "Sensitive data transmitted without encryption".to_string(),
-            SecurityFlag::HighFrequency => "High frequency traffic pattern detected".to_string(),
-            SecurityFlag::UnknownProtocol => "Unknown or unusual protocol detected".to_string(),
-            SecurityFlag::LargePayload => "Unusually large payload detected".to_string(),
+    /// Per-minute classification counts between `since` and `until`, oldest first;
+    /// `interface` "all" matches every interface
+    pub fn get_classification_samples(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<ClassificationSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, packets, bytes,
+                    unknown_packets, unknown_bytes, low_confidence_bytes
+             FROM classification_stats
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(ClassificationSample {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface: row.get(1)?,
+                    packets: row.get(2)?,
+                    bytes: row.get(3)?,
+                    unknown_packets: row.get(4)?,
+                    unknown_bytes: row.get(5)?,
+                    low_confidence_bytes: row.get(6)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Stores per-minute duplicate packet counts in one transaction
+    pub fn store_duplicate_samples(&self, samples: &[DuplicateSample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO duplicate_stats (
+                    timestamp, interface_name, packets, duplicates, ttl_changed,
+                    max_ttl_drop, max_copies, source_ip, dest_ip
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface,
+                    sample.packets,
+                    sample.duplicates,
+                    sample.ttl_changed,
+                    sample.max_ttl_drop,
+                    sample.max_copies,
+                    sample.source,
+                    sample.destination,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} duplicate packet samples", samples.len());
+        Ok(())
+    }
+
+    /// Per-minute duplicate packet counts between `since` and `until`, oldest first;
+    /// `interface` "all" matches every interface
+    pub fn get_duplicate_samples(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<DuplicateSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, packets, duplicates, ttl_changed,
+                    max_ttl_drop, max_copies, source_ip, dest_ip
+             FROM duplicate_stats
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(DuplicateSample {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface: row.get(1)?,
+                    packets: row.get(2)?,
+                    duplicates: row.get(3)?,
+                    ttl_changed: row.get(4)?,
+                    max_ttl_drop: row.get(5)?,
+                    max_copies: row.get(6)?,
+                    source: row.get(7)?,
+                    destination: row.get(8)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Stores per-minute fragmentation counts in one transaction
+    pub fn store_mtu_samples(&self, samples: &[MtuSample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO mtu_stats (
+                    timestamp, interface_name, remote_ip, fragments, fragmented_packets,
+                    largest_fragment, too_big, reported_mtu
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.interface,
+                    sample.remote,
+                    sample.fragments,
+                    sample.fragmented_packets,
+                    sample.largest_fragment,
+                    sample.too_big,
+                    sample.reported_mtu,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} fragmentation samples", samples.len());
+        Ok(())
+    }
+
+    /// Per-minute fragmentation counts between `since` and `until`, oldest first;
+    /// `interface` "all" matches every interface
+    pub fn get_mtu_samples(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<MtuSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, interface_name, remote_ip, fragments, fragmented_packets,
+                    largest_fragment, too_big, reported_mtu
+             FROM mtu_stats
+             WHERE (?1 = 'all' OR interface_name = ?1) AND timestamp >= ?2 AND timestamp <= ?3
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| {
+                let timestamp: String = row.get(0)?;
+                Ok(MtuSample {
+                    timestamp: parse_local_timestamp(&timestamp),
+                    interface: row.get(1)?,
+                    remote: row.get(2)?,
+                    fragments: row.get(3)?,
+                    fragmented_packets: row.get(4)?,
+                    largest_fragment: row.get(5)?,
+                    too_big: row.get(6)?,
+                    reported_mtu: row.get(7)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Stores connections flagged as held to a steady rate in one transaction
+    pub fn store_throttled_flows(&self, flows: &[ThrottledFlow]) -> Result<()> {
+        if flows.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO throttled_flows (
+                    start_time, end_time, interface_name, transport, sender, receiver, inbound,
+                    rate, variation, seconds, link_share, confidence
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for flow in flows {
+                stmt.execute(params![
+                    flow.start.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    flow.end.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    flow.interface,
+                    flow.transport,
+                    flow.sender,
+                    flow.receiver,
+                    flow.inbound,
+                    flow.rate,
+                    flow.variation,
+                    flow.seconds,
+                    flow.link_share,
+                    flow.confidence.as_str(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} throttled flows", flows.len());
+        Ok(())
+    }
+
+    /// Connections flagged as held to a steady rate at some point between `since` and
+    /// `until`, oldest first; `interface` "all" matches every interface
+    pub fn get_throttled_flows(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<ThrottledFlow>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT start_time, end_time, interface_name, transport, sender, receiver, inbound,
+                    rate, variation, seconds, link_share, confidence
+             FROM throttled_flows
+             WHERE (?1 = 'all' OR interface_name = ?1) AND end_time >= ?2 AND start_time <= ?3
+             ORDER BY start_time, id",
+        )?;
+        let rows = stmt.query_map(
+            params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()],
+            |row| {
+                let start: String = row.get(0)?;
+                let end: String = row.get(1)?;
+                let confidence: String = row.get(11)?;
+                Ok(ThrottledFlow {
+                    start: parse_local_timestamp(&start),
+                    end: parse_local_timestamp(&end),
+                    interface: row.get(2)?,
+                    transport: row.get(3)?,
+                    sender: row.get(4)?,
+                    receiver: row.get(5)?,
+                    inbound: row.get(6)?,
+                    rate: row.get(7)?,
+                    variation: row.get(8)?,
+                    seconds: row.get(9)?,
+                    link_share: row.get(10)?,
+                    confidence: ThrottleConfidence::parse(&confidence),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn store_ping_samples(&self, samples: &[PingSample]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO ping_stats (
+                    timestamp, target, address, sent, received, latency_ms, jitter_ms
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for sample in samples {
+                stmt.execute(params![
+                    sample.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    sample.target,
+                    sample.address,
+                    sample.sent,
+                    sample.received,
+                    sample.latency_ms,
+                    sample.jitter_ms,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        debug!("Stored {} ping samples", samples.len());
+        Ok(())
+    }
+
+    /// Per-minute ping statistics of every target between `since` and `until`, oldest first
+    pub fn get_ping_samples(&self, since: DateTime<Local>, until: DateTime<Local>) -> Result<Vec<PingSample>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, target, address, sent, received, latency_ms, jitter_ms
+             FROM ping_stats
+             WHERE timestamp >= ?1 AND timestamp <= ?2
+             ORDER BY timestamp, id",
+        )?;
+        let rows = stmt.query_map(params![since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string()], |row| {
+            let timestamp: String = row.get(0)?;
+            Ok(PingSample {
+                timestamp: parse_local_timestamp(&timestamp),
+                target: row.get(1)?,
+                address: row.get(2)?,
+                sent: row.get(3)?,
+                received: row.get(4)?,
+                latency_ms: row.get(5)?,
+                jitter_ms: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// TCP loss counters per interface, and the connections with the most
+    /// retransmissions, for connections active between `since` and `until`
+    pub fn get_tcp_loss(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>, limit: usize) -> Result<TcpLossSummary> {
+        let conn = self.readers.get();
+        let since = since.format("%Y-%m-%d %H:%M:%S").to_string();
+        let until = until.format("%Y-%m-%d %H:%M:%S").to_string();
+        let loss = |row: &rusqlite::Row, first: usize| -> rusqlite::Result<LossStats> {
+            Ok(LossStats {
+                segments: row.get(first)?,
+                retransmissions: row.get(first + 1)?,
+                duplicate_acks: row.get(first + 2)?,
+                out_of_order: row.get(first + 3)?,
+            })
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT interface_name, SUM(segments), SUM(retransmissions), SUM(duplicate_acks), SUM(out_of_order)
+             FROM tcp_sessions
+             WHERE (?1 = 'all' OR interface_name = ?1) AND last_seen >= ?2 AND first_seen <= ?3
+             GROUP BY interface_name
+             ORDER BY interface_name",
+        )?;
+        let interfaces = stmt
+            .query_map(params![interface, since, until], |row| Ok((row.get(0)?, loss(row, 1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT client_ip, client_port, server_ip, server_port,
+                    segments, retransmissions, duplicate_acks, out_of_order
+             FROM tcp_sessions
+             WHERE (?1 = 'all' OR interface_name = ?1) AND last_seen >= ?2 AND first_seen <= ?3
+                   AND retransmissions + duplicate_acks + out_of_order > 0
+             ORDER BY retransmissions DESC, duplicate_acks DESC
+             LIMIT ?4",
+        )?;
+        let connections = stmt
+            .query_map(params![interface, since, until, limit as i64], |row| {
+                let endpoint = |ip: String, port: u16| match ip.contains(':') {
+                    true => format!("[{ip}]:{port}"),
+                    false => format!("{ip}:{port}"),
+                };
+                Ok((
+                    endpoint(row.get(0)?, row.get(1)?),
+                    endpoint(row.get(2)?, row.get(3)?),
+                    loss(row, 4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(TcpLossSummary { interfaces, connections })
+    }
+
+    /// (client, server, kernel statistics) of the host's own TCP connections active between
+    /// `since` and `until`, those the kernel retransmitted most on first, then the slowest
+    pub fn get_kernel_tcp_stats(&self, interface: &str, since: DateTime<Local>, until: DateTime<Local>, limit: usize) -> Result<Vec<(String, String, TcpInfo)>> {
+        let conn = self.readers.get();
+        let mut stmt = conn.prepare(
+            "SELECT client_ip, client_port, server_ip, server_port, kernel_cwnd, kernel_rtt_ms,
+                    kernel_rtt_var_ms, kernel_retransmits, kernel_lost, congestion_control
+             FROM tcp_sessions
+             WHERE (?1 = 'all' OR interface_name = ?1) AND last_seen >= ?2 AND first_seen <= ?3 AND kernel_cwnd IS NOT NULL
+             ORDER BY kernel_retransmits DESC, kernel_rtt_ms DESC
+             LIMIT ?4",
+        )?;
+        let micros = |ms: f64| (ms * 1000.0).round() as u32;
+        let connections = stmt
+            .query_map(params![interface, since.format("%Y-%m-%d %H:%M:%S").to_string(), until.format("%Y-%m-%d %H:%M:%S").to_string(), limit as i64], |row| {
+                let endpoint = |ip: String, port: u16| match ip.contains(':') {
+                    true => format!("[{ip}]:{port}"),
+                    false => format!("{ip}:{port}"),
+                };
+                Ok((
+                    endpoint(row.get(0)?, row.get(1)?),
+                    endpoint(row.get(2)?, row.get(3)?),
+                    TcpInfo {
+                        congestion_window: row.get(4)?,
+                        rtt_us: micros(row.get(5)?),
+                        rtt_var_us: micros(row.get(6)?),
+                        retransmits: row.get(7)?,
+                        lost: row.get(8)?,
+                        congestion_control: row.get(9)?,
+                    },
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(connections)
+    }
+
+    /// Rolls up and deletes old data as `config` says
+    pub fn apply_retention(&self, config: &RetentionConfig) -> Result<RetentionReport> {
+        let conn = self.conn.lock().unwrap();
+        let report = apply_retention(&conn, config, Local::now())?;
+        info!(
+            "Retention applied: {} samples rolled into hours, {} hours into days, {} rows deleted",
+            report.rolled_into_hours,
+            report.rolled_into_days,
+            report.deleted_total()
+        );
+        Ok(report)
+    }
+
+    /// Rebuilds the database file without its free pages and truncates the WAL
+    /// Returns the database size in bytes before and after
+    pub fn compact(&self) -> Result<(u64, u64)> {
+        let conn = self.conn.lock().unwrap();
+        let size = |conn: &Connection| -> Result<u64> {
+            let pages: u64 = conn.pragma_query_value(None, "page_count", |row| row.get(0))?;
+            let page_size: u64 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+            Ok(pages * page_size)
+        };
+        let before = size(&conn)?;
+        conn.execute_batch("VACUUM")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok((before, size(&conn)?))
+    }
+}
+
+/// Speed test results from any connection to the packet database, newest first;
+/// with `since`, only those run from then on
+pub fn read_speed_tests(
+    conn: &Connection,
+    since: Option<DateTime<Local>>,
+    limit: usize,
+) -> Result<Vec<SpeedTestResult>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, server, latency_ms, jitter_ms, download_bps, upload_bps, download_bytes, upload_bytes
+         FROM speed_tests
+         WHERE ?1 IS NULL OR timestamp >= ?1
+         ORDER BY timestamp DESC, id DESC
+         LIMIT ?2",
+    )?;
+    let since = since.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+    let rows = stmt.query_map(params![since, limit as i64], |row| {
+        Ok(SpeedTestResult {
+            timestamp: parse_local_timestamp(&row.get::<_, String>(0)?),
+            server: row.get(1)?,
+            latency_ms: row.get(2)?,
+            jitter_ms: row.get(3)?,
+            download_bps: row.get(4)?,
+            upload_bps: row.get(5)?,
+            download_bytes: row.get(6)?,
+            upload_bytes: row.get(7)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Reads the tags table from any connection to the packet database
+/// (graphs open their own connection rather than a `PacketStorage`)
+pub fn read_tags(conn: &Connection) -> rusqlite::Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(
+        "SELECT target, kind, label, note, updated_at FROM tags ORDER BY kind, target",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let kind: String = row.get(1)?;
+        let updated_at: String = row.get(4)?;
+        Ok(Tag {
+            target: row.get(0)?,
+            kind: TagKind::parse(&kind),
+            label: row.get(2)?,
+            note: row.get(3)?,
+            updated_at: parse_local_timestamp(&updated_at),
+        })
+    })?;
+    rows.collect()
+}
+
+/// Reads the discovered devices table from any connection to the packet database
+pub fn read_discovered_devices(conn: &Connection) -> rusqlite::Result<Vec<DiscoveredDevice>> {
+    let mut stmt = conn.prepare(
+        "SELECT address, interface_name, name, device_type, source, first_seen, last_seen
+         FROM discovered_devices",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let address: String = row.get(0)?;
+        let device_type: String = row.get(3)?;
+        let source: String = row.get(4)?;
+        let first_seen: String = row.get(5)?;
+        let last_seen: String = row.get(6)?;
+        Ok((address, row.get(1)?, row.get(2)?, device_type, source, first_seen, last_seen))
+    })?;
+    let mut devices = Vec::new();
+    for row in rows {
+        let (address, interface, name, device_type, source, first_seen, last_seen) = row?;
+        // Rows are only written from parsed addresses; anything else is skipped
+        let Ok(address) = address.parse::<IpAddr>() else {
+            continue;
+        };
+        devices.push(DiscoveredDevice {
+            address,
+            interface,
+            name,
+            device_type: DeviceType::parse(&device_type),
+            source: DiscoverySource::parse(&source),
+            first_seen: parse_local_timestamp(&first_seen),
+            last_seen: parse_local_timestamp(&last_seen),
+        });
+    }
+    devices.sort_by_key(|device| device.address);
+    Ok(devices)
+}
+
+/// Tags from the packet database at `db_path`, for commands that show them without
+/// otherwise using storage; a missing database or tags table gives an empty book
+pub fn load_tag_book<P: AsRef<Path>>(db_path: P) -> TagBook {
+    let db_path = db_path.as_ref();
+    if !db_path.exists() {
+        return TagBook::default();
+    }
+    let tags = Database::open_read_only(db_path).and_then(|database| {
+        let conn = database.reader();
+        Ok((read_tags(&conn)?, read_discovered_devices(&conn)?))
+    });
+    match tags {
+        Ok((tags, devices)) => {
+            let mut book = TagBook::new(tags);
+            for device in devices {
+                book.add_discovered(device.address, device.label());
+            }
+            book
+        }
+        Err(e) => {
+            debug!("No tags loaded from {}: {e}", db_path.display());
+            TagBook::default()
+        }
+    }
+}
+
+/// One row per interface and transport protocol that saw packets
+fn insert_packet_sizes(conn: &Connection, timestamp: DateTime<Local>, sizes: &PacketSizeDistribution) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO packet_size_stats
+         (timestamp, interface_name, protocol, tiny_packets, small_packets, large_packets, jumbo_packets)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    let timestamp = timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    for (interface, protocols) in &sizes.histograms {
+        for (protocol, histogram) in protocols.iter().filter(|(_, histogram)| histogram.total() > 0) {
+            stmt.execute(params![
+                timestamp,
+                interface,
+                protocol,
+                histogram.tiny,
+                histogram.small,
+                histogram.large,
+                histogram.jumbo
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+fn read_bandwidth_samples(
+    conn: &Connection,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> rusqlite::Result<Vec<BandwidthSample>> {
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, interface_name, duration_secs, download_bps, upload_bps
+         FROM bandwidth_samples
+         WHERE timestamp >= ?1 AND timestamp <= ?2
+         ORDER BY timestamp, id",
+    )?;
+    let rows = stmt.query_map(
+        params![
+            start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            end.format("%Y-%m-%d %H:%M:%S").to_string()
+        ],
+        |row| {
+            Ok(BandwidthSample {
+                timestamp: parse_local_timestamp(&row.get::<_, String>(0)?),
+                interface_name: row.get(1)?,
+                duration_secs: row.get(2)?,
+                download_bps: row.get(3)?,
+                upload_bps: row.get(4)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+/// Longest interval one bandwidth sample is expected to cover, as imported monthly totals do
+const MAX_SAMPLE_DAYS: i64 = 32;
+
+fn sample_span(sample: &BandwidthSample) -> chrono::Duration {
+    chrono::Duration::milliseconds((sample.duration_secs * 1000.0) as i64)
+}
+
+/// The seconds a sample covers, which end at its timestamp
+fn sample_interval(sample: &BandwidthSample) -> (i64, i64) {
+    let end = sample.timestamp.timestamp();
+    (end - sample.duration_secs.round() as i64, end)
+}
+
+/// Sorted, non-overlapping intervals covering the same seconds as `intervals`
+fn merge_intervals(mut intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some((_, last_end)) if start < *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
         }
     }
+    merged
+}
 
-    fn security_flag_severity(&self, flag: &SecurityFlag) -> String {
-        match flag {
-            SecurityFlag::SuspiciousPort => "warning".to_string(),
-            SecurityFlag::UnencryptedSensitive => "high".to_string(),
-            SecurityFlag::HighFrequency => "info".to_string(),
-            SecurityFlag::UnknownProtocol => "info".to_string(),
-            SecurityFlag::LargePayload => "info".to_string(),
+/// Bandwidth baselines learned from the history in the packet database at `db_path`;
+/// a missing database or table gives a model without baselines
+pub fn load_bandwidth_baseline<P: AsRef<Path>>(db_path: P, config: &AnomalyConfig) -> BaselineModel {
+    let db_path = db_path.as_ref();
+    if !db_path.exists() {
+        return BaselineModel::new(config.clone());
+    }
+    let end = Local::now();
+    let start = end - chrono::Duration::days(config.history_days.into());
+    let samples = Database::open_read_only(db_path)
+        .and_then(|database| Ok(read_bandwidth_samples(&database.reader(), start, end)?));
+    match samples {
+        Ok(samples) => BaselineModel::learned_from(config.clone(), &samples),
+        Err(e) => {
+            debug!("No bandwidth history loaded from {}: {e}", db_path.display());
+            BaselineModel::new(config.clone())
         }
     }
 }
 
+/// Reads a timestamp stored as local time (`%Y-%m-%d %H:%M:%S`)
+pub(crate) fn parse_local_timestamp(value: &str) -> DateTime<Local> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|naive| naive.and_local_timezone(Local).earliest())
+        .unwrap_or_else(Local::now)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -630,6 +2532,7 @@ mod tests {
             packet_count: 1,
             byte_count: 1500,
             is_encrypted: false,
+            sample_rate: 1,
         };
 
         let result = storage.store_protocol_info(record);
@@ -655,14 +2558,777 @@ mod tests {
 
         let analysis = AnalysisResult {
             application_protocol: Some("HTTP".to_string()),
+            classification_confidence: crate::analyzers::ClassificationConfidence::Medium,
             is_encrypted: false,
             traffic_type: crate::analyzers::TrafficType::Web,
             security_flags: vec![],
-            flow_direction: crate::analyzers::FlowDirection::Outbound,
+            payload_entropy: None,
+            flow_direction: crate::analyzers::protocol_analyzer::FlowDirection::Outbound,
             geolocation: None,
         };
 
         let result = storage.analyze_packet_for_storage(&packet, &analysis);
         assert!(result.is_ok());
+
+        storage.flush_all().unwrap();
+        let now = Local::now();
+        let hosts = storage
+            .get_hosts_seen(now - chrono::Duration::hours(1), now + chrono::Duration::hours(1))
+            .unwrap();
+        assert_eq!(hosts, ["192.168.1.1", "8.8.8.8"]);
+    }
+
+    #[tokio::test]
+    async fn test_rollup_merges_pending_records() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = PacketStorage::new(db_path, 100).unwrap().with_rollup(true);
+
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            1000,
+            PacketProtocol::IPv4,
+            PacketDirection::Outbound,
+        );
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_port = Some(50000);
+        packet.dest_port = Some(443);
+
+        let analysis = AnalysisResult {
+            application_protocol: Some("HTTPS".to_string()),
+            ..AnalysisResult::default()
+        };
+        for _ in 0..3 {
+            storage.analyze_packet_for_storage(&packet, &analysis).unwrap();
+        }
+
+        let protocols = storage.buffer.lock().protocols.clone();
+        assert_eq!(protocols.len(), 1);
+        assert_eq!(protocols[0].packet_count, 3);
+        assert_eq!(protocols[0].byte_count, 3000);
+
+        let connections = storage.buffer.lock().connections.clone();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].packet_count, 3);
+
+        storage.flush_all().unwrap();
+        assert!(storage.buffer.lock().is_empty());
+    }
+
+    #[test]
+    fn test_unanalyzed_packets_keep_their_connection() {
+        // What the daemon stores when the analyzer is off or fails on a packet
+        let mut packet = NetworkPacket::new("eth0".to_string(), 400, PacketProtocol::IPv4, PacketDirection::Inbound);
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        packet.transport_protocol = TransportProtocol::Udp;
+        packet.source_port = Some(53);
+
+        let records = PacketRecords::new(&packet, &AnalysisResult::default(), 1);
+        assert!(records.protocol.is_none());
+        assert!(records.security_events.is_empty());
+        let connection = records.connection.unwrap();
+        assert_eq!(connection.connection_key, "8.8.8.8:53-192.168.1.1:0");
+        assert_eq!((connection.protocol.as_str(), connection.application_protocol), ("Udp", None));
+        assert_eq!(connection.classification_confidence, "unknown");
+    }
+
+    #[tokio::test]
+    async fn test_sampled_packets_are_scaled_and_their_rate_recorded() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+
+        let mut packet = NetworkPacket::new("eth0".to_string(), 1000, PacketProtocol::IPv4, PacketDirection::Outbound);
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.dest_port = Some(443);
+        let analysis = AnalysisResult {
+            application_protocol: Some("HTTPS".to_string()),
+            ..AnalysisResult::default()
+        };
+
+        let since = Local::now() - chrono::Duration::hours(1);
+        storage.analyze_packet_for_storage(&packet, &analysis).unwrap();
+        storage.flush_all().unwrap();
+        assert_eq!(storage.get_traffic_summary("eth0", since, Local::now()).unwrap().sample_rate, 1);
+
+        storage.analyze_sampled_packet_for_storage(&packet, &analysis, 100).unwrap();
+        storage.flush_all().unwrap();
+        let summary = storage.get_traffic_summary("eth0", since, Local::now()).unwrap();
+        assert_eq!(summary.sample_rate, 100);
+        let https = &summary.protocols["HTTPS"];
+        assert_eq!((https.packets, https.bytes), (101, 101_000));
+    }
+
+    #[tokio::test]
+    async fn test_data_quality_summary() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap();
+        let now = Local::now();
+
+        let sample = |confidence, duration_secs, failures, dropped| QualitySample {
+            timestamp: now,
+            interface_name: "eth0".to_string(),
+            duration_secs,
+            confidence,
+            counter_resets: 0,
+            collection_failures: failures,
+            packets_captured: 1000,
+            packets_dropped: dropped,
+        };
+        storage.store_quality_sample(&sample(CalculationConfidence::High, 60.0, 0, 0)).unwrap();
+        storage.store_quality_sample(&sample(CalculationConfidence::High, 20.0, 0, 50)).unwrap();
+        storage.store_quality_sample(&sample(CalculationConfidence::None, 20.0, 1, 0)).unwrap();
+
+        let summary = storage
+            .get_data_quality(now - chrono::Duration::minutes(1), now + chrono::Duration::minutes(1))
+            .unwrap();
+        assert_eq!(summary.sampled_secs, 100.0);
+        assert_eq!(summary.share(&CalculationConfidence::High), 0.8);
+        assert_eq!(summary.share(&CalculationConfidence::None), 0.2);
+        assert_eq!(summary.collection_failures, 1);
+        assert_eq!(summary.packets_dropped, 50);
+        assert!((summary.drop_rate() - 50.0 / 3000.0).abs() < 1e-9);
+
+        let earlier = storage
+            .get_data_quality(now - chrono::Duration::days(2), now - chrono::Duration::days(1))
+            .unwrap();
+        assert_eq!(earlier, DataQualitySummary::default());
+    }
+
+    #[tokio::test]
+    async fn test_speed_tests_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let result = |minutes_ago: i64, upload_bps: Option<f64>| SpeedTestResult {
+            timestamp: parse_local_timestamp(
+                &(Local::now() - chrono::Duration::minutes(minutes_ago)).format("%Y-%m-%d %H:%M:%S").to_string(),
+            ),
+            server: "speed.example.net".to_string(),
+            latency_ms: Some(12.5),
+            jitter_ms: Some(1.5),
+            download_bps: Some(12_500_000.0),
+            upload_bps,
+            download_bytes: 125_000_000,
+            upload_bytes: upload_bps.map_or(0, |_| 25_000_000),
+        };
+        storage.store_speed_test(&result(60, None)).unwrap();
+        storage.store_speed_test(&result(5, Some(2_500_000.0))).unwrap();
+
+        let results = storage.get_speed_tests(10).unwrap();
+        assert_eq!(results, vec![result(5, Some(2_500_000.0)), result(60, None)]);
+        assert_eq!(storage.get_speed_tests(1).unwrap().len(), 1);
+
+        let conn = storage.conn.lock().unwrap();
+        let since = Local::now() - chrono::Duration::minutes(30);
+        assert_eq!(read_speed_tests(&conn, Some(since), 10).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ping_samples_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let sample = |minutes_ago: i64, target: &str, received: u64| PingSample {
+            timestamp: parse_local_timestamp(
+                &(Local::now() - chrono::Duration::minutes(minutes_ago)).format("%Y-%m-%d %H:%M:00").to_string(),
+            ),
+            target: target.to_string(),
+            address: "192.168.1.1".to_string(),
+            sent: 30,
+            received,
+            latency_ms: (received > 0).then_some(1.5),
+            jitter_ms: (received > 1).then_some(0.25),
+        };
+        let samples = vec![sample(90, "gateway", 30), sample(10, "gateway", 0), sample(10, "1.1.1.1", 29)];
+        storage.store_ping_samples(&samples).unwrap();
+
+        let since = Local::now() - chrono::Duration::minutes(30);
+        assert_eq!(storage.get_ping_samples(since, Local::now()).unwrap(), samples[1..].to_vec());
+        assert_eq!(storage.get_ping_samples(since - chrono::Duration::hours(2), since).unwrap(), samples[..1].to_vec());
+    }
+
+    #[test]
+    fn test_collector_health_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let mut telemetry = CollectorTelemetry::default();
+        telemetry.record(Duration::from_millis(12), 0, &[], true);
+        telemetry.record(Duration::from_millis(250), 2, &["eth1".to_string()], true);
+        let sample = CollectorHealthSample {
+            timestamp: parse_local_timestamp(&Local::now().format("%Y-%m-%d %H:%M:00").to_string()),
+            duration_secs: 60.0,
+            telemetry,
+        };
+        storage.store_collector_health(&sample).unwrap();
+
+        let stored = storage.get_collector_health(Local::now() - chrono::Duration::hours(1)).unwrap();
+        assert_eq!(stored, vec![sample]);
+        assert!(storage.get_collector_health(Local::now() + chrono::Duration::hours(1)).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_capture_sessions() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap();
+        let started = Local::now() - chrono::Duration::hours(1);
+
+        let sample = |session_start: DateTime<Local>, minutes: i64, packets, kernel_drops| CaptureSample {
+            timestamp: session_start + chrono::Duration::minutes(minutes),
+            session_start,
+            interface_name: "eth0".to_string(),
+            duration_secs: 60.0,
+            packets,
+            bytes: packets * 100,
+            peak_packets_per_second: packets as f64 / 30.0,
+            peak_bytes_per_second: packets as f64 * 100.0 / 30.0,
+            queue_drops: 1,
+            kernel_drops,
+            queue_capacity: 1000,
+            avg_queue_depth: 2.0,
+            peak_queue_depth: minutes as u64 * 10,
+            peak_storage_backlog: 5,
+            packet_sizes: {
+                let mut sizes = PacketSizeDistribution::default();
+                sizes.merge("eth0", "TCP", &PacketSizeHistogram { tiny: packets / 2, large: packets / 2, ..Default::default() });
+                sizes
+            },
+        };
+        let earlier = started - chrono::Duration::days(1);
+        storage.store_capture_sample(&sample(earlier, 1, 10, None)).unwrap();
+        for (minute, packets) in [(1, 6_000), (2, 12_000)] {
+            storage.store_capture_sample(&sample(started, minute, packets, Some(4))).unwrap();
+        }
+
+        let sessions = storage.get_capture_sessions(10).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let last = &sessions[0];
+        assert_eq!(last.samples, 2);
+        assert_eq!(last.packets, 18_000);
+        assert_eq!(last.packets_per_second(), 150.0);
+        assert_eq!(last.peak_packets_per_second, 400.0);
+        assert_eq!(last.kernel_drops, Some(8));
+        assert_eq!(last.peak_queue_depth, 20);
+        assert_eq!(sessions[1].kernel_drops, None);
+
+        let samples = storage.get_capture_samples(last).unwrap();
+        assert_eq!(samples.iter().map(|s| s.packets).collect::<Vec<_>>(), vec![6_000, 12_000]);
+        assert_eq!(samples[1].peak_queue_utilisation(), 0.02);
+
+        // The earlier session's packet sizes are outside the period
+        let sizes = storage.get_packet_sizes("eth0", started, Local::now()).unwrap();
+        assert_eq!(sizes.total(), PacketSizeHistogram { tiny: 9_000, large: 9_000, ..Default::default() });
+        assert!(storage.get_packet_sizes("wlan0", started, Local::now()).unwrap().is_empty());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_bandwidth_samples_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let storage = PacketStorage::new(&db_path, 10).unwrap();
+        let now = Local::now();
+
+        let sample = |minutes_ago, interface: &str, download_bps| BandwidthSample {
+            timestamp: now - chrono::Duration::minutes(minutes_ago),
+            interface_name: interface.to_string(),
+            duration_secs: 60.0,
+            download_bps,
+            upload_bps: 100.0,
+        };
+        storage
+            .store_bandwidth_samples(&[sample(1, "eth0", 2_000.0), sample(5, "wlan0", 500.0), sample(3, "eth0", 1_000.0)])
+            .unwrap();
+
+        let samples = storage
+            .get_bandwidth_samples(now - chrono::Duration::minutes(4), now)
+            .unwrap();
+        let readings: Vec<(&str, f64)> = samples.iter().map(|s| (s.interface_name.as_str(), s.total_bps())).collect();
+        assert_eq!(readings, vec![("eth0", 1_100.0), ("eth0", 2_100.0)]);
+
+        // Too little history for a baseline at the default minimum
+        drop(storage);
+        assert!(load_bandwidth_baseline(&db_path, &AnomalyConfig::default()).is_empty());
+        assert!(load_bandwidth_baseline(temp_dir.path().join("missing.db"), &AnomalyConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_imported_samples_skip_recorded_intervals() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap();
+        let now = Local::now();
+        let sample = |end: DateTime<Local>, interface: &str, duration_secs: f64| BandwidthSample {
+            timestamp: end,
+            interface_name: interface.to_string(),
+            duration_secs,
+            download_bps: 1.0,
+            upload_bps: 0.0,
+        };
+        let day = 86_400.0;
+        storage.store_bandwidth_samples(&[sample(now - chrono::Duration::days(2), "eth0", 60.0)]).unwrap();
+
+        let imported = [
+            // Overlaps the recorded minute
+            sample(now - chrono::Duration::hours(36), "eth0", day),
+            sample(now - chrono::Duration::hours(60), "eth0", day),
+            // Another interface's history is not affected
+            sample(now - chrono::Duration::hours(36), "wlan0", day),
+            // Overlaps an earlier sample of the same import
+            sample(now - chrono::Duration::hours(70), "eth0", 3600.0),
+        ];
+        assert_eq!(storage.import_bandwidth_samples(&imported).unwrap(), 2);
+        // Importing again stores nothing
+        assert_eq!(storage.import_bandwidth_samples(&imported).unwrap(), 0);
+        let stored = storage.get_bandwidth_samples(now - chrono::Duration::days(5), now).unwrap();
+        assert_eq!(stored.len(), 3);
+    }
+
+    #[test]
+    fn test_bandwidth_and_protocol_pages() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap();
+        let now = Local::now();
+
+        let samples: Vec<BandwidthSample> = (0..5)
+            .map(|minutes_ago| BandwidthSample {
+                timestamp: now - chrono::Duration::minutes(minutes_ago),
+                interface_name: if minutes_ago % 2 == 0 { "eth0" } else { "wlan0" }.to_string(),
+                duration_secs: 60.0,
+                download_bps: minutes_ago as f64,
+                upload_bps: 0.0,
+            })
+            .collect();
+        storage.store_bandwidth_samples(&samples).unwrap();
+
+        let start = now - chrono::Duration::minutes(10);
+        let page = storage.get_bandwidth_page(start, now, None, 1, 2).unwrap();
+        assert_eq!(page.total, 5);
+        let readings: Vec<f64> = page.items.iter().map(|s| s.download_bps).collect();
+        assert_eq!(readings, vec![3.0, 2.0]);
+        let page = storage.get_bandwidth_page(start, now, Some("eth0"), 0, 10).unwrap();
+        assert_eq!(page.total, 3);
+        assert!(page.items.iter().all(|s| s.interface_name == "eth0"));
+
+        let protocol = |name: &str, bytes, is_encrypted| ProtocolRecord {
+            timestamp: now,
+            interface_name: "eth0".to_string(),
+            protocol_name: name.to_string(),
+            packet_count: 1,
+            byte_count: bytes,
+            is_encrypted,
+            sample_rate: 1,
+        };
+        storage.store_protocol_info(protocol("DNS", 100, false)).unwrap();
+        storage.store_protocol_info(protocol("HTTPS", 900, true)).unwrap();
+        storage.store_protocol_info(protocol("DNS", 200, false)).unwrap();
+        storage.flush_all().unwrap();
+
+        let page = storage.get_protocol_page(start, now, None, 0, 1).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(
+            page.items,
+            vec![ProtocolTotal { protocol: "HTTPS".to_string(), packets: 1, bytes: 900, encrypted: true }]
+        );
+        let page = storage.get_protocol_page(start, now, None, 1, 1).unwrap();
+        assert_eq!((page.items[0].protocol.as_str(), page.items[0].packets, page.items[0].bytes), ("DNS", 2, 300));
+        assert_eq!(storage.get_protocol_page(start, now, Some("wlan0"), 0, 10).unwrap().total, 0);
+    }
+
+    #[test]
+    fn test_security_event_log_and_evidence() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap();
+        let now = Local::now();
+
+        for (minutes_ago, event_type) in [(5, "PortScan"), (1, "SuspiciousPort"), (90, "SynFlood")] {
+            storage
+                .store_security_event(SecurityEvent {
+                    timestamp: now - chrono::Duration::minutes(minutes_ago),
+                    interface_name: "eth0".to_string(),
+                    event_type: event_type.to_string(),
+                    source_ip: Some("192.168.1.20".to_string()),
+                    dest_ip: Some("10.0.0.5".to_string()),
+                    port: Some(22),
+                    protocol: Some("Tcp".to_string()),
+                    description: event_type.to_string(),
+                    severity: "high".to_string(),
+                })
+                .unwrap();
+        }
+        let connection = |key: &str, source: &str, dest: &str, bytes, minutes_ago| ConnectionRecord {
+            connection_key: key.to_string(),
+            source_ip: source.to_string(),
+            dest_ip: dest.to_string(),
+            source_port: Some(50000),
+            dest_port: Some(22),
+            protocol: "TCP".to_string(),
+            application_protocol: Some("SSH".to_string()),
+            first_seen: now - chrono::Duration::minutes(minutes_ago),
+            last_seen: now - chrono::Duration::minutes(minutes_ago),
+            packet_count: 1,
+            byte_count: bytes,
+            is_active: false,
+            classification_confidence: "high".to_string(),
+            sample_rate: 1,
+        };
+        storage.store_connection(connection("a", "192.168.1.20", "10.0.0.5", 100, 5)).unwrap();
+        storage.store_connection(connection("b", "10.0.0.5", "192.168.1.20", 900, 5)).unwrap();
+        storage.store_connection(connection("c", "192.168.1.20", "10.0.0.9", 500, 5)).unwrap();
+        storage.store_connection(connection("d", "192.168.1.20", "10.0.0.5", 700, 30)).unwrap();
+        storage.flush_all().unwrap();
+
+        let events = storage.get_security_event_log("all", now - chrono::Duration::hours(1), now).unwrap();
+        let types: Vec<_> = events.iter().map(|e| e.event_type.as_str()).collect();
+        assert_eq!(types, ["PortScan", "SuspiciousPort"]);
+        assert!(storage.get_security_event_log("wlan0", now - chrono::Duration::hours(1), now).unwrap().is_empty());
+
+        let evidence = storage
+            .get_connections_between("192.168.1.20", "10.0.0.5", events[0].timestamp, chrono::Duration::seconds(60), 5)
+            .unwrap();
+        let keys: Vec<_> = evidence.iter().map(|c| c.connection_key.as_str()).collect();
+        assert_eq!(keys, ["b", "a"]);
+        assert_eq!(evidence[0].application_protocol.as_deref(), Some("SSH"));
+    }
+
+    #[test]
+    fn test_remote_traffic() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap();
+        let now = Local::now();
+
+        let connection = |key: &str, source: &str, dest: &str, bytes| ConnectionRecord {
+            connection_key: key.to_string(),
+            source_ip: source.to_string(),
+            dest_ip: dest.to_string(),
+            source_port: Some(50000),
+            dest_port: Some(443),
+            protocol: "TCP".to_string(),
+            application_protocol: None,
+            first_seen: now,
+            last_seen: now,
+            packet_count: 3,
+            byte_count: bytes,
+            is_active: false,
+            classification_confidence: "high".to_string(),
+            sample_rate: 1,
+        };
+        storage.store_connection(connection("a", "192.168.1.2", "1.1.1.1", 400)).unwrap();
+        storage.store_connection(connection("b", "1.1.1.1", "192.168.1.3", 600)).unwrap();
+        storage.store_connection(connection("c", "192.168.1.2", "8.8.8.8", 700)).unwrap();
+        storage.store_connection(connection("d", "192.168.1.2", "192.168.1.1", 9000)).unwrap();
+        storage.store_connection(connection("e", "fe80::1", "2606:4700::1111", 50)).unwrap();
+        storage.flush_all().unwrap();
+
+        let remotes = storage
+            .get_remote_traffic(now - chrono::Duration::hours(1), now + chrono::Duration::hours(1))
+            .unwrap();
+        let rows: Vec<_> = remotes.iter().map(|r| (r.address.to_string(), r.flows, r.packets, r.bytes)).collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("1.1.1.1".to_string(), 2, 6, 1000),
+                ("8.8.8.8".to_string(), 1, 3, 700),
+                ("2606:4700::1111".to_string(), 1, 3, 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_application_usage() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 1).unwrap();
+        let now = Local::now();
+
+        let connection = |key: &str, application: Option<&str>, bytes, minutes_ago| ConnectionRecord {
+            connection_key: key.to_string(),
+            source_ip: "192.168.1.2".to_string(),
+            dest_ip: "1.1.1.1".to_string(),
+            source_port: Some(50000),
+            dest_port: Some(443),
+            protocol: "TCP".to_string(),
+            application_protocol: application.map(str::to_string),
+            first_seen: now - chrono::Duration::minutes(minutes_ago),
+            last_seen: now - chrono::Duration::minutes(minutes_ago),
+            packet_count: 2,
+            byte_count: bytes,
+            is_active: false,
+            classification_confidence: "High".to_string(),
+            sample_rate: 1,
+        };
+        storage.store_connection(connection("a", Some("DNS"), 100, 1)).unwrap();
+        storage.store_connection(connection("b", Some("DNS"), 150, 2)).unwrap();
+        storage.store_connection(connection("c", Some("TLS"), 9000, 3)).unwrap();
+        storage.store_connection(connection("d", None, 500, 4)).unwrap();
+        storage.store_connection(connection("e", Some("SSH"), 7000, 120)).unwrap();
+        storage.flush_all().unwrap();
+
+        let usage = storage.get_application_usage(now - chrono::Duration::hours(1), now).unwrap();
+        let rows: Vec<_> = usage.iter().map(|u| (u.protocol.as_str(), u.flows, u.packets, u.bytes)).collect();
+        assert_eq!(rows, vec![("TLS", 1, 2, 9000), ("TCP", 1, 2, 500), ("DNS", 2, 4, 250)]);
+    }
+
+    #[tokio::test]
+    async fn test_store_tcp_sessions() {
+        use crate::analyzers::tcp_state::TcpStateTracker;
+        use crate::collectors::socket_stats::SocketStats;
+        use crate::models::TcpSegment;
+
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+
+        let mut tracker = TcpStateTracker::new(10);
+        let mut packet = NetworkPacket::new(
+            "eth0".to_string(),
+            60,
+            PacketProtocol::IPv4,
+            PacketDirection::Outbound,
+        );
+        packet.transport_protocol = TransportProtocol::Tcp;
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        packet.source_port = Some(50000);
+        packet.dest_port = Some(443);
+        packet.tcp = Some(TcpSegment {
+            flags: TcpSegment::SYN,
+            sequence: 1,
+            acknowledgement: 0,
+            window: 0,
+            payload_len: 0,
+            timestamps: None,
+        });
+        tracker.observe(&packet);
+        // SYN resent after the retransmission timeout
+        packet.timestamp += chrono::Duration::seconds(1);
+        tracker.observe(&packet);
+        let info = TcpInfo {
+            congestion_window: 1,
+            rtt_us: 0,
+            rtt_var_us: 0,
+            retransmits: 1,
+            lost: 1,
+            congestion_control: Some("cubic".to_string()),
+        };
+        tracker.attach_socket_stats(&[SocketStats {
+            local: "192.168.1.1:50000".parse().unwrap(),
+            remote: "8.8.8.8:443".parse().unwrap(),
+            info: info.clone(),
+        }]);
+
+        // Storing the same connection twice keeps one row with the latest counts
+        let sessions = tracker.drain();
+        storage.store_tcp_sessions(&sessions).unwrap();
+        storage.store_tcp_sessions(&sessions).unwrap();
+
+        let conn = storage.conn.lock().unwrap();
+        let (rows, state, retransmissions): (u64, String, u64) = conn
+            .query_row(
+                "SELECT COUNT(*), MAX(state), MAX(retransmissions) FROM tcp_sessions",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((rows, state.as_str(), retransmissions), (1, "SYN-SENT", 1));
+        drop(conn);
+
+        let loss = storage.get_tcp_loss("all", Local::now() - chrono::Duration::days(1), Local::now(), 10).unwrap();
+        assert_eq!(loss.interfaces.len(), 1);
+        assert_eq!((loss.interfaces[0].1.segments, loss.interfaces[0].1.retransmissions), (2, 1));
+        assert_eq!(loss.connections[0].0, "192.168.1.1:50000");
+
+        let kernel = storage.get_kernel_tcp_stats("all", Local::now() - chrono::Duration::days(1), Local::now(), 10).unwrap();
+        assert_eq!(kernel, vec![("192.168.1.1:50000".to_string(), "8.8.8.8:443".to_string(), info)]);
+    }
+
+    #[tokio::test]
+    async fn test_store_latency_samples() {
+        use crate::analyzers::latency::RttSource;
+
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let sample = |interface: &str, rtt_ms: f64| LatencySample {
+            timestamp: Local::now(),
+            interface: interface.to_string(),
+            host: IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+            rtt_ms,
+            source: RttSource::Timestamp,
+        };
+        storage
+            .store_latency_samples(&[sample("eth0", 12.5), sample("wlan0", 30.0)])
+            .unwrap();
+
+        let since = Local::now() - chrono::Duration::minutes(1);
+        assert_eq!(storage.get_latency_samples("all", since, Local::now()).unwrap().len(), 2);
+        assert_eq!(
+            storage.get_latency_samples("eth0", since, Local::now()).unwrap(),
+            vec![("8.8.8.8".to_string(), 12.5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_classification_samples_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let minute = Local::now() - chrono::Duration::minutes(2);
+        let sample = |interface: &str, unknown_bytes: u64| ClassificationSample {
+            timestamp: parse_local_timestamp(&minute.format("%Y-%m-%d %H:%M:%S").to_string()),
+            interface: interface.to_string(),
+            packets: 100,
+            bytes: 150_000,
+            unknown_packets: 10,
+            unknown_bytes,
+            low_confidence_bytes: 1_500,
+        };
+        storage
+            .store_classification_samples(&[sample("eth0", 15_000), sample("wlan0", 75_000)])
+            .unwrap();
+
+        let since = Local::now() - chrono::Duration::minutes(5);
+        assert_eq!(storage.get_classification_samples("all", since, Local::now()).unwrap().len(), 2);
+        assert_eq!(storage.get_classification_samples("wlan0", since, Local::now()).unwrap(), vec![sample("wlan0", 75_000)]);
+        assert!(storage.get_classification_samples("eth0", Local::now(), Local::now()).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_samples_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let minute = Local::now() - chrono::Duration::minutes(2);
+        let sample = DuplicateSample {
+            timestamp: parse_local_timestamp(&minute.format("%Y-%m-%d %H:%M:%S").to_string()),
+            interface: "eth0".to_string(),
+            packets: 1_000,
+            duplicates: 40,
+            ttl_changed: 2,
+            max_ttl_drop: 1,
+            max_copies: 7,
+            source: Some("10.0.0.5".to_string()),
+            destination: None,
+        };
+        storage.store_duplicate_samples(std::slice::from_ref(&sample)).unwrap();
+
+        let since = Local::now() - chrono::Duration::minutes(5);
+        assert_eq!(storage.get_duplicate_samples("all", since, Local::now()).unwrap(), vec![sample]);
+        assert!(storage.get_duplicate_samples("wlan0", since, Local::now()).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_security_events_by_type() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        let event = |event_type: &str, description: &str| SecurityEvent {
+            timestamp: Local::now(),
+            interface_name: "eth0".to_string(),
+            event_type: event_type.to_string(),
+            source_ip: Some("10.0.0.5".to_string()),
+            dest_ip: Some("10.0.0.1".to_string()),
+            port: Some(22),
+            protocol: Some("Tcp".to_string()),
+            description: description.to_string(),
+            severity: "high".to_string(),
+        };
+        storage.store_security_event(event("UnencryptedSensitive", "telnet")).unwrap();
+        storage.store_security_event(event("UnencryptedSensitive", "telnet")).unwrap();
+        storage.store_security_event(event("PortScan", "Port scan: 20 ports probed on one host")).unwrap();
+        storage.flush_all().unwrap();
+
+        let since = Local::now() - chrono::Duration::minutes(1);
+        assert_eq!(
+            storage.get_security_event_counts("all", since, Local::now()).unwrap(),
+            vec![("UnencryptedSensitive".to_string(), 2), ("PortScan".to_string(), 1)]
+        );
+        let scans = storage.get_security_events("eth0", since, Local::now(), &["PortScan", "SynFlood"], 10).unwrap();
+        assert_eq!(scans.len(), 1);
+        assert_eq!(scans[0].source_ip.as_deref(), Some("10.0.0.5"));
+        assert!(storage.get_security_events("wlan0", since, Local::now(), &["PortScan"], 10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tags_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap();
+
+        storage.set_tag(&Tag::new("192.168.1.34", "tablet", None)).unwrap();
+        // Tagging the same target again replaces the label
+        storage
+            .set_tag(&Tag::new("192.168.1.34", "kid's tablet", Some("bedtime 21:00".to_string())))
+            .unwrap();
+        storage.set_tag(&Tag::new("eth0", "wired uplink", None)).unwrap();
+
+        let book = storage.tag_book().unwrap();
+        assert_eq!(book.label("192.168.1.34"), Some("kid's tablet"));
+        assert_eq!(book.get("192.168.1.34").unwrap().note.as_deref(), Some("bedtime 21:00"));
+        assert_eq!(storage.get_tags().unwrap().len(), 2);
+
+        assert!(storage.remove_tag("eth0").unwrap());
+        assert!(!storage.remove_tag("eth0").unwrap());
+        assert_eq!(storage.get_tags().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_discovered_devices_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 10).unwrap();
+        let now = Local::now();
+        let mut device = DiscoveredDevice {
+            address: "192.168.1.40".parse().unwrap(),
+            interface: "eth0".to_string(),
+            name: Some("Office Printer".to_string()),
+            device_type: DeviceType::Printer,
+            source: DiscoverySource::Mdns,
+            first_seen: now,
+            last_seen: now,
+        };
+        storage.store_discovered_devices(std::slice::from_ref(&device)).unwrap();
+
+        // A later announcement without a name or type keeps what was learned before
+        device.name = None;
+        device.device_type = DeviceType::Unknown;
+        storage.store_discovered_devices(&[device]).unwrap();
+
+        let devices = storage.get_discovered_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].label(), "Office Printer, printer");
+        assert_eq!(storage.tag_book().unwrap().label("192.168.1.40"), Some("Office Printer, printer"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_events_tags_and_config() {
+        let temp_dir = tempdir().unwrap();
+        let storage = PacketStorage::new(temp_dir.path().join("test.db"), 100).unwrap();
+        storage
+            .store_security_event(SecurityEvent {
+                timestamp: Local::now(),
+                interface_name: "eth0".to_string(),
+                event_type: "PortScan".to_string(),
+                source_ip: Some("10.0.0.5".to_string()),
+                dest_ip: Some("10.0.0.1".to_string()),
+                port: None,
+                protocol: Some("Tcp".to_string()),
+                description: "Port scan: 20 ports probed on one host".to_string(),
+                severity: "high".to_string(),
+            })
+            .unwrap();
+        storage.flush_all().unwrap();
+        storage.set_tag(&Tag::new("10.0.0.5", "printer", None)).unwrap();
+        assert!(!storage.remove_tag("eth0").unwrap());
+
+        // The same configuration is only recorded again once it changed
+        let fingerprint = |digest: &str| ConfigFingerprint { path: None, digest: digest.to_string() };
+        assert!(storage.record_config_fingerprint(&fingerprint("aaaa")).unwrap());
+        assert!(!storage.record_config_fingerprint(&fingerprint("aaaa")).unwrap());
+        assert!(storage.record_config_fingerprint(&fingerprint("bbbb")).unwrap());
+
+        let entries = storage.get_audit_entries(None, 10).unwrap();
+        let actions: Vec<_> = entries.iter().map(|e| (e.category.as_str(), e.action.as_str())).collect();
+        assert_eq!(
+            actions,
+            vec![("config", "changed"), ("config", "loaded"), ("admin", "tag set"), ("security_event", "PortScan")]
+        );
+        assert!(entries[3].detail.contains("10.0.0.5 -> 10.0.0.1, Tcp, severity high"));
+
+        let verification = storage.verify_audit_log().unwrap();
+        assert_eq!((verification.entries, verification.broken), (4, None));
+        assert_eq!(storage.find_audit_hash(&entries[0].hash).unwrap(), Some(4));
+    }
+}
@@ -0,0 +1,1038 @@
+// PostgresBackend: the monitoring daemon's records written to a PostgreSQL server
+// Daemons on several machines can share one database; every row carries the name of the
+// machine that recorded it. Records are queued and written by a thread that owns the
+// connection, one transaction per batch, as the SQLite write buffer does, so capture never
+// waits on the network and the blocking client never runs on a Tokio worker
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use postgres::{Client, Statement, Transaction};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::analyzers::{AnalysisResult, ClassificationSample, DuplicateSample, LatencySample, MtuSample, TcpSession, ThrottledFlow};
+use crate::collectors::ping_collector::PingSample;
+use crate::collectors::socket_stats::TcpInfo;
+use crate::models::NetworkPacket;
+use crate::storage::packet_storage::{
+    BandwidthSample, CaptureSample, CollectorHealthSample, ConnectionRecord, InterfaceChange, PacketRecords, ProtocolRecord,
+    QualitySample, SecurityEvent,
+};
+use crate::storage::StorageBackend;
+
+/// Schema migrations in order; applying the Nth brings a database to version N
+///
+/// The version is kept in `kw_schema_version`. As with the SQLite schema, later changes
+/// are appended as new migrations rather than edited into earlier ones.
+const MIGRATIONS: &[&str] = &[CREATE_TABLES];
+
+/// Schema version of a database with every migration applied
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+/// Advisory lock daemons starting at the same time take turns migrating under
+const MIGRATION_LOCK: i64 = 0x6b77_6d69_6772;
+
+/// Records kept while the server cannot be reached; the oldest are dropped beyond this
+const MAX_PENDING_RECORDS: usize = 100_000;
+
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS protocol_distribution (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    protocol_name TEXT NOT NULL,
+    packet_count BIGINT NOT NULL,
+    byte_count BIGINT NOT NULL,
+    is_encrypted BOOLEAN NOT NULL,
+    sample_rate BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_protocol_distribution_timestamp ON protocol_distribution (timestamp);
+
+CREATE TABLE IF NOT EXISTS connections (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    connection_key TEXT NOT NULL,
+    source_ip TEXT NOT NULL,
+    dest_ip TEXT NOT NULL,
+    source_port INTEGER,
+    dest_port INTEGER,
+    protocol TEXT NOT NULL,
+    application_protocol TEXT,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    packet_count BIGINT NOT NULL,
+    byte_count BIGINT NOT NULL,
+    is_active BOOLEAN NOT NULL,
+    classification_confidence TEXT NOT NULL,
+    sample_rate BIGINT NOT NULL,
+    UNIQUE (machine, connection_key)
+);
+CREATE INDEX IF NOT EXISTS idx_connections_last_seen ON connections (last_seen);
+
+CREATE TABLE IF NOT EXISTS security_events (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    source_ip TEXT,
+    dest_ip TEXT,
+    port INTEGER,
+    protocol TEXT,
+    description TEXT NOT NULL,
+    severity TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_security_events_timestamp ON security_events (timestamp);
+
+CREATE TABLE IF NOT EXISTS data_quality (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL,
+    confidence TEXT NOT NULL,
+    counter_resets BIGINT NOT NULL,
+    collection_failures BIGINT NOT NULL,
+    packets_captured BIGINT NOT NULL,
+    packets_dropped BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_data_quality_timestamp ON data_quality (timestamp);
+
+CREATE TABLE IF NOT EXISTS bandwidth_samples (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL,
+    download_bps DOUBLE PRECISION NOT NULL,
+    upload_bps DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_bandwidth_samples_timestamp ON bandwidth_samples (timestamp);
+
+CREATE TABLE IF NOT EXISTS capture_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    session_start TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL,
+    packets BIGINT NOT NULL,
+    bytes BIGINT NOT NULL,
+    peak_packets_per_second DOUBLE PRECISION NOT NULL,
+    peak_bytes_per_second DOUBLE PRECISION NOT NULL,
+    queue_drops BIGINT NOT NULL,
+    kernel_drops BIGINT,
+    queue_capacity BIGINT NOT NULL,
+    avg_queue_depth DOUBLE PRECISION NOT NULL,
+    peak_queue_depth BIGINT NOT NULL,
+    peak_storage_backlog BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_capture_stats_session ON capture_stats (machine, session_start, interface_name);
+
+CREATE TABLE IF NOT EXISTS packet_size_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    protocol TEXT NOT NULL,
+    tiny_packets BIGINT NOT NULL,
+    small_packets BIGINT NOT NULL,
+    large_packets BIGINT NOT NULL,
+    jumbo_packets BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_packet_size_stats_timestamp ON packet_size_stats (timestamp);
+
+CREATE TABLE IF NOT EXISTS collector_health (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL,
+    collections BIGINT NOT NULL,
+    failed_collections BIGINT NOT NULL,
+    degraded_collections BIGINT NOT NULL,
+    retries BIGINT NOT NULL,
+    total_duration_ms DOUBLE PRECISION NOT NULL,
+    max_duration_ms DOUBLE PRECISION NOT NULL,
+    failed_interfaces TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_collector_health_timestamp ON collector_health (timestamp);
+
+CREATE TABLE IF NOT EXISTS interface_events (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    change TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_interface_events_timestamp ON interface_events (timestamp);
+
+CREATE TABLE IF NOT EXISTS tcp_sessions (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    interface_name TEXT NOT NULL,
+    client_ip TEXT NOT NULL,
+    client_port INTEGER NOT NULL,
+    server_ip TEXT NOT NULL,
+    server_port INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    established_at TIMESTAMPTZ,
+    closed_at TIMESTAMPTZ,
+    last_seen TIMESTAMPTZ NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL,
+    handshake_ms DOUBLE PRECISION,
+    packet_count BIGINT NOT NULL,
+    byte_count BIGINT NOT NULL,
+    retransmissions BIGINT NOT NULL,
+    segments BIGINT NOT NULL,
+    duplicate_acks BIGINT NOT NULL,
+    out_of_order BIGINT NOT NULL,
+    kernel_cwnd BIGINT,
+    kernel_rtt_ms DOUBLE PRECISION,
+    kernel_rtt_var_ms DOUBLE PRECISION,
+    kernel_retransmits BIGINT,
+    kernel_lost BIGINT,
+    congestion_control TEXT,
+    UNIQUE (machine, client_ip, client_port, server_ip, server_port, first_seen)
+);
+CREATE INDEX IF NOT EXISTS idx_tcp_sessions_last_seen ON tcp_sessions (last_seen);
+
+CREATE TABLE IF NOT EXISTS latency_samples (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    host TEXT NOT NULL,
+    rtt_ms DOUBLE PRECISION NOT NULL,
+    source TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_latency_samples_timestamp ON latency_samples (timestamp);
+
+CREATE TABLE IF NOT EXISTS classification_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    packets BIGINT NOT NULL,
+    bytes BIGINT NOT NULL,
+    unknown_packets BIGINT NOT NULL,
+    unknown_bytes BIGINT NOT NULL,
+    low_confidence_bytes BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_classification_stats_timestamp ON classification_stats (timestamp);
+
+CREATE TABLE IF NOT EXISTS duplicate_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    packets BIGINT NOT NULL,
+    duplicates BIGINT NOT NULL,
+    ttl_changed BIGINT NOT NULL,
+    max_ttl_drop INTEGER NOT NULL,
+    max_copies BIGINT NOT NULL,
+    source_ip TEXT,
+    dest_ip TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_duplicate_stats_timestamp ON duplicate_stats (timestamp);
+
+CREATE TABLE IF NOT EXISTS mtu_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    remote_ip TEXT NOT NULL,
+    fragments BIGINT NOT NULL,
+    fragmented_packets BIGINT NOT NULL,
+    largest_fragment BIGINT NOT NULL,
+    too_big BIGINT NOT NULL,
+    reported_mtu BIGINT
+);
+CREATE INDEX IF NOT EXISTS idx_mtu_stats_timestamp ON mtu_stats (timestamp);
+
+CREATE TABLE IF NOT EXISTS throttled_flows (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    start_time TIMESTAMPTZ NOT NULL,
+    end_time TIMESTAMPTZ NOT NULL,
+    interface_name TEXT NOT NULL,
+    transport TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    receiver TEXT NOT NULL,
+    inbound BOOLEAN NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+    variation DOUBLE PRECISION NOT NULL,
+    seconds BIGINT NOT NULL,
+    link_share DOUBLE PRECISION NOT NULL,
+    confidence TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_throttled_flows_start_time ON throttled_flows (start_time);
+
+CREATE TABLE IF NOT EXISTS ping_stats (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    target TEXT NOT NULL,
+    address TEXT NOT NULL,
+    sent BIGINT NOT NULL,
+    received BIGINT NOT NULL,
+    latency_ms DOUBLE PRECISION,
+    jitter_ms DOUBLE PRECISION
+);
+CREATE INDEX IF NOT EXISTS idx_ping_stats_timestamp ON ping_stats (timestamp);
+";
+
+/// One queued row, or a few rows for capture samples with packet sizes
+enum Record {
+    Protocol(ProtocolRecord),
+    Connection(ConnectionRecord),
+    SecurityEvent(SecurityEvent),
+    Quality(QualitySample),
+    Bandwidth(BandwidthSample),
+    Capture(CaptureSample),
+    CollectorHealth(CollectorHealthSample),
+    InterfaceChange(InterfaceChange),
+    TcpSession(TcpSession),
+    Latency(LatencySample),
+    Classification(ClassificationSample),
+    Duplicate(DuplicateSample),
+    Mtu(MtuSample),
+    Throttled(ThrottledFlow),
+    Ping(PingSample),
+}
+
+enum Message {
+    Records(Vec<Record>),
+    /// Write everything queued and report how it went
+    Flush(Sender<Result<()>>),
+}
+
+/// Writes the daemon's records to PostgreSQL through a writer thread
+pub struct PostgresBackend {
+    sender: Option<Sender<Message>>,
+    /// Records queued and not yet written
+    pending: Arc<AtomicUsize>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl PostgresBackend {
+    /// Connects to `url`, brings the schema up to date and starts the writer, which writes
+    /// every `batch_size` records and at least every `flush_interval`
+    ///
+    /// Rows are written with `machine` in their machine column.
+    pub fn connect(url: &str, machine: &str, batch_size: usize, flush_interval: Duration) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let (ready, connected) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let writer = Writer {
+            url: url.to_string(),
+            machine: machine.to_string(),
+            batch_size: batch_size.max(1),
+            flush_interval,
+            pending: Arc::clone(&pending),
+            connection: None,
+            queued: Vec::new(),
+        };
+        let writer = std::thread::Builder::new()
+            .name("kw-postgres-writer".to_string())
+            .spawn(move || writer.run(receiver, ready))
+            .context("Failed to start the PostgreSQL writer")?;
+        match connected.recv() {
+            Ok(Ok(version)) => info!("PostgreSQL storage at schema version {version}, writing as {machine}"),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => bail!("The PostgreSQL writer stopped while connecting"),
+        }
+        Ok(Self { sender: Some(sender), pending, writer: Some(writer) })
+    }
+
+    fn queue(&self, records: Vec<Record>) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.pending.fetch_add(records.len(), Ordering::Relaxed);
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(Message::Records(records)).ok())
+            .ok_or_else(|| anyhow!("The PostgreSQL writer has stopped"))
+    }
+}
+
+impl Drop for PostgresBackend {
+    fn drop(&mut self) {
+        // The writer writes what is left once the channel closes
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl StorageBackend for PostgresBackend {
+    fn store_packet(&self, packet: &NetworkPacket, analysis: &AnalysisResult) -> Result<()> {
+        let records = PacketRecords::new(packet, analysis, 1);
+        let mut queued = Vec::with_capacity(2 + records.security_events.len());
+        queued.extend(records.protocol.map(Record::Protocol));
+        queued.extend(records.connection.map(Record::Connection));
+        queued.extend(records.security_events.into_iter().map(Record::SecurityEvent));
+        self.queue(queued)
+    }
+
+    fn store_quality_sample(&self, sample: &QualitySample) -> Result<()> {
+        self.queue(vec![Record::Quality(sample.clone())])
+    }
+
+    fn store_bandwidth_samples(&self, samples: &[BandwidthSample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Bandwidth).collect())
+    }
+
+    fn store_capture_sample(&self, sample: &CaptureSample) -> Result<()> {
+        self.queue(vec![Record::Capture(sample.clone())])
+    }
+
+    fn store_collector_health(&self, sample: &CollectorHealthSample) -> Result<()> {
+        self.queue(vec![Record::CollectorHealth(sample.clone())])
+    }
+
+    fn store_interface_change(&self, change: &InterfaceChange) -> Result<()> {
+        self.queue(vec![Record::InterfaceChange(change.clone())])
+    }
+
+    fn store_tcp_sessions(&self, sessions: &[TcpSession]) -> Result<()> {
+        self.queue(sessions.iter().cloned().map(Record::TcpSession).collect())
+    }
+
+    fn store_latency_samples(&self, samples: &[LatencySample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Latency).collect())
+    }
+
+    fn store_classification_samples(&self, samples: &[ClassificationSample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Classification).collect())
+    }
+
+    fn store_duplicate_samples(&self, samples: &[DuplicateSample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Duplicate).collect())
+    }
+
+    fn store_mtu_samples(&self, samples: &[MtuSample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Mtu).collect())
+    }
+
+    fn store_throttled_flows(&self, flows: &[ThrottledFlow]) -> Result<()> {
+        self.queue(flows.iter().cloned().map(Record::Throttled).collect())
+    }
+
+    fn store_ping_samples(&self, samples: &[PingSample]) -> Result<()> {
+        self.queue(samples.iter().cloned().map(Record::Ping).collect())
+    }
+
+    fn pending_records(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    fn flush(&self) -> Result<()> {
+        let (done, result) = mpsc::channel();
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(Message::Flush(done)).ok())
+            .ok_or_else(|| anyhow!("The PostgreSQL writer has stopped"))?;
+        result.recv().map_err(|_| anyhow!("The PostgreSQL writer stopped while flushing"))?
+    }
+}
+
+/// A client with the statements prepared on it so far
+struct Connection {
+    client: Client,
+    statements: HashMap<&'static str, Statement>,
+}
+
+/// Owns the connection and the records waiting for it, on the writer thread
+struct Writer {
+    url: String,
+    machine: String,
+    batch_size: usize,
+    flush_interval: Duration,
+    pending: Arc<AtomicUsize>,
+    /// None after the connection was lost, until it is opened again
+    connection: Option<Connection>,
+    queued: Vec<Record>,
+}
+
+impl Writer {
+    fn run(mut self, messages: Receiver<Message>, ready: Sender<Result<i32>>) {
+        let connected = self.connection().map(|_| SCHEMA_VERSION);
+        let failed = connected.is_err();
+        let _ = ready.send(connected);
+        if failed {
+            return;
+        }
+
+        let mut last_write = Instant::now();
+        loop {
+            let remaining = self.flush_interval.saturating_sub(last_write.elapsed());
+            match messages.recv_timeout(remaining) {
+                Ok(Message::Records(records)) => {
+                    self.queued.extend(records);
+                    if self.queued.len() < self.batch_size {
+                        continue;
+                    }
+                }
+                Ok(Message::Flush(done)) => {
+                    let _ = done.send(self.write());
+                    last_write = Instant::now();
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Err(e) = self.write() {
+                        warn!("Failed to write {} queued records to PostgreSQL on shutdown: {e}", self.queued.len());
+                    }
+                    return;
+                }
+            }
+            if let Err(e) = self.write() {
+                warn!("Failed to write to PostgreSQL, keeping {} records to retry: {e:#}", self.queued.len());
+            }
+            last_write = Instant::now();
+        }
+    }
+
+    /// The open connection, connecting and migrating first when there is none
+    fn connection(&mut self) -> Result<&mut Connection> {
+        if self.connection.as_ref().is_some_and(|connection| connection.client.is_closed()) {
+            self.connection = None;
+        }
+        if self.connection.is_none() {
+            let connector = native_tls::TlsConnector::new().context("Failed to set up TLS")?;
+            let mut client = Client::connect(&self.url, postgres_native_tls::MakeTlsConnector::new(connector))
+                .context("Failed to connect to PostgreSQL")?;
+            let (from, to) = migrate(&mut client)?;
+            if from != to {
+                info!("Migrated the PostgreSQL schema from version {from} to {to}");
+            }
+            self.connection = Some(Connection { client, statements: HashMap::new() });
+        }
+        Ok(self.connection.as_mut().expect("connected above"))
+    }
+
+    /// Writes every queued record in one transaction; on failure they stay queued
+    fn write(&mut self) -> Result<()> {
+        if self.queued.is_empty() {
+            return Ok(());
+        }
+        if self.queued.len() > MAX_PENDING_RECORDS {
+            let dropped = self.queued.len() - MAX_PENDING_RECORDS;
+            self.queued.drain(..dropped);
+            self.pending.fetch_sub(dropped, Ordering::Relaxed);
+            warn!("Dropped the {dropped} oldest records waiting for PostgreSQL");
+        }
+        let queued = self.queued.len();
+        let records = merge_connections(std::mem::take(&mut self.queued));
+        self.pending.fetch_sub(queued - records.len(), Ordering::Relaxed);
+        let machine = self.machine.clone();
+        let written = self.connection().and_then(|connection| write_records(connection, &machine, &records));
+        match written {
+            Ok(()) => {
+                self.pending.fetch_sub(records.len(), Ordering::Relaxed);
+                debug!("Wrote {} records to PostgreSQL", records.len());
+                Ok(())
+            }
+            Err(e) => {
+                self.queued = records;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Merges the records of each connection into its first, as the upsert would add them up
+/// anyway, so a busy connection costs one statement per batch rather than one per packet
+fn merge_connections(records: Vec<Record>) -> Vec<Record> {
+    let mut merged: Vec<Record> = Vec::with_capacity(records.len());
+    let mut connections: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        let Record::Connection(connection) = record else {
+            merged.push(record);
+            continue;
+        };
+        match connections.get(&connection.connection_key).map(|&index| &mut merged[index]) {
+            Some(Record::Connection(existing)) => {
+                existing.packet_count += connection.packet_count;
+                existing.byte_count += connection.byte_count;
+                existing.last_seen = existing.last_seen.max(connection.last_seen);
+                existing.sample_rate = existing.sample_rate.max(connection.sample_rate);
+                existing.is_active = connection.is_active;
+                existing.classification_confidence = connection.classification_confidence;
+                if existing.application_protocol.is_none() {
+                    existing.application_protocol = connection.application_protocol;
+                }
+            }
+            _ => {
+                connections.insert(connection.connection_key.clone(), merged.len());
+                merged.push(Record::Connection(connection));
+            }
+        }
+    }
+    merged
+}
+
+/// Applies the migrations the database has not had yet, in one transaction
+/// Returns the versions before and after; a database from a newer build is refused
+pub fn migrate(client: &mut Client) -> Result<(i32, i32)> {
+    let mut tx = client.transaction()?;
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])?;
+    tx.batch_execute("CREATE TABLE IF NOT EXISTS kw_schema_version (version INTEGER NOT NULL)")?;
+    let from: i32 = tx
+        .query_opt("SELECT max(version) FROM kw_schema_version", &[])?
+        .and_then(|row| row.get(0))
+        .unwrap_or(0);
+    if from > SCHEMA_VERSION {
+        bail!(
+            "PostgreSQL schema version {from} is newer than this build supports ({SCHEMA_VERSION}); upgrade kaipo-watcher"
+        );
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        tx.batch_execute(migration)
+            .with_context(|| format!("Migration to PostgreSQL schema version {} failed", index + 1))?;
+    }
+    if from < SCHEMA_VERSION {
+        tx.execute("DELETE FROM kw_schema_version", &[])?;
+        tx.execute("INSERT INTO kw_schema_version (version) VALUES ($1)", &[&SCHEMA_VERSION])?;
+    }
+    tx.commit()?;
+    Ok((from, SCHEMA_VERSION))
+}
+
+const INSERT_PROTOCOL: &str = "INSERT INTO protocol_distribution (
+    machine, timestamp, interface_name, protocol_name, packet_count, byte_count, is_encrypted, sample_rate
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+const UPSERT_CONNECTION: &str = "INSERT INTO connections (
+    machine, connection_key, source_ip, dest_ip, source_port, dest_port, protocol, application_protocol,
+    first_seen, last_seen, packet_count, byte_count, is_active, classification_confidence, sample_rate
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+ON CONFLICT (machine, connection_key) DO UPDATE SET
+    application_protocol = COALESCE(connections.application_protocol, EXCLUDED.application_protocol),
+    last_seen = GREATEST(connections.last_seen, EXCLUDED.last_seen),
+    packet_count = connections.packet_count + EXCLUDED.packet_count,
+    byte_count = connections.byte_count + EXCLUDED.byte_count,
+    is_active = EXCLUDED.is_active,
+    classification_confidence = EXCLUDED.classification_confidence,
+    sample_rate = GREATEST(connections.sample_rate, EXCLUDED.sample_rate)";
+
+const INSERT_SECURITY_EVENT: &str = "INSERT INTO security_events (
+    machine, timestamp, interface_name, event_type, source_ip, dest_ip, port, protocol, description, severity
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+
+const INSERT_QUALITY: &str = "INSERT INTO data_quality (
+    machine, timestamp, interface_name, duration_secs, confidence, counter_resets,
+    collection_failures, packets_captured, packets_dropped
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+
+const INSERT_BANDWIDTH: &str = "INSERT INTO bandwidth_samples (
+    machine, timestamp, interface_name, duration_secs, download_bps, upload_bps
+) VALUES ($1, $2, $3, $4, $5, $6)";
+
+const INSERT_CAPTURE: &str = "INSERT INTO capture_stats (
+    machine, timestamp, session_start, interface_name, duration_secs, packets, bytes,
+    peak_packets_per_second, peak_bytes_per_second, queue_drops, kernel_drops,
+    queue_capacity, avg_queue_depth, peak_queue_depth, peak_storage_backlog
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)";
+
+const INSERT_PACKET_SIZES: &str = "INSERT INTO packet_size_stats (
+    machine, timestamp, interface_name, protocol, tiny_packets, small_packets, large_packets, jumbo_packets
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+const INSERT_COLLECTOR_HEALTH: &str = "INSERT INTO collector_health (
+    machine, timestamp, duration_secs, collections, failed_collections, degraded_collections,
+    retries, total_duration_ms, max_duration_ms, failed_interfaces
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+
+const INSERT_INTERFACE_CHANGE: &str =
+    "INSERT INTO interface_events (machine, timestamp, interface_name, change) VALUES ($1, $2, $3, $4)";
+
+const UPSERT_TCP_SESSION: &str = "INSERT INTO tcp_sessions (
+    machine, interface_name, client_ip, client_port, server_ip, server_port, state,
+    first_seen, established_at, closed_at, last_seen, duration_secs,
+    handshake_ms, packet_count, byte_count, retransmissions, segments,
+    duplicate_acks, out_of_order, kernel_cwnd, kernel_rtt_ms, kernel_rtt_var_ms,
+    kernel_retransmits, kernel_lost, congestion_control
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+          $19, $20, $21, $22, $23, $24, $25)
+ON CONFLICT (machine, client_ip, client_port, server_ip, server_port, first_seen) DO UPDATE SET
+    interface_name = EXCLUDED.interface_name, state = EXCLUDED.state,
+    established_at = EXCLUDED.established_at, closed_at = EXCLUDED.closed_at,
+    last_seen = EXCLUDED.last_seen, duration_secs = EXCLUDED.duration_secs,
+    handshake_ms = EXCLUDED.handshake_ms, packet_count = EXCLUDED.packet_count,
+    byte_count = EXCLUDED.byte_count, retransmissions = EXCLUDED.retransmissions,
+    segments = EXCLUDED.segments, duplicate_acks = EXCLUDED.duplicate_acks,
+    out_of_order = EXCLUDED.out_of_order, kernel_cwnd = EXCLUDED.kernel_cwnd,
+    kernel_rtt_ms = EXCLUDED.kernel_rtt_ms, kernel_rtt_var_ms = EXCLUDED.kernel_rtt_var_ms,
+    kernel_retransmits = EXCLUDED.kernel_retransmits, kernel_lost = EXCLUDED.kernel_lost,
+    congestion_control = EXCLUDED.congestion_control";
+
+const INSERT_LATENCY: &str = "INSERT INTO latency_samples (
+    machine, timestamp, interface_name, host, rtt_ms, source
+) VALUES ($1, $2, $3, $4, $5, $6)";
+
+const INSERT_CLASSIFICATION: &str = "INSERT INTO classification_stats (
+    machine, timestamp, interface_name, packets, bytes, unknown_packets, unknown_bytes, low_confidence_bytes
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+const INSERT_DUPLICATES: &str = "INSERT INTO duplicate_stats (
+    machine, timestamp, interface_name, packets, duplicates, ttl_changed,
+    max_ttl_drop, max_copies, source_ip, dest_ip
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
+
+const INSERT_MTU: &str = "INSERT INTO mtu_stats (
+    machine, timestamp, interface_name, remote_ip, fragments, fragmented_packets,
+    largest_fragment, too_big, reported_mtu
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+
+const INSERT_THROTTLED: &str = "INSERT INTO throttled_flows (
+    machine, start_time, end_time, interface_name, transport, sender, receiver, inbound,
+    rate, variation, seconds, link_share, confidence
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)";
+
+const INSERT_PING: &str = "INSERT INTO ping_stats (
+    machine, timestamp, target, address, sent, received, latency_ms, jitter_ms
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+/// BIGINT column value of a count; PostgreSQL has no unsigned integers
+fn count(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn write_records(connection: &mut Connection, machine: &str, records: &[Record]) -> Result<()> {
+    let Connection { client, statements } = connection;
+    let mut tx = client.transaction()?;
+    for record in records {
+        write_record(&mut tx, statements, machine, record)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// `sql` prepared on the connection, once per connection
+fn prepared(
+    tx: &mut Transaction<'_>,
+    statements: &mut HashMap<&'static str, Statement>,
+    sql: &'static str,
+) -> Result<Statement> {
+    if let Some(statement) = statements.get(sql) {
+        return Ok(statement.clone());
+    }
+    let statement = tx.prepare(sql)?;
+    statements.insert(sql, statement.clone());
+    Ok(statement)
+}
+
+fn write_record(
+    tx: &mut Transaction<'_>,
+    statements: &mut HashMap<&'static str, Statement>,
+    machine: &str,
+    record: &Record,
+) -> Result<()> {
+    let ms = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0;
+
+    match record {
+        Record::Protocol(r) => {
+            let statement = prepared(tx, statements, INSERT_PROTOCOL)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &r.timestamp, &r.interface_name, &r.protocol_name, &count(r.packet_count),
+                    &count(r.byte_count), &r.is_encrypted, &count(r.sample_rate),
+                ],
+            )?;
+        }
+        Record::Connection(r) => {
+            let statement = prepared(tx, statements, UPSERT_CONNECTION)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &r.connection_key, &r.source_ip, &r.dest_ip, &r.source_port.map(i32::from),
+                    &r.dest_port.map(i32::from), &r.protocol, &r.application_protocol, &r.first_seen, &r.last_seen,
+                    &count(r.packet_count), &count(r.byte_count), &r.is_active, &r.classification_confidence,
+                    &count(r.sample_rate),
+                ],
+            )?;
+        }
+        Record::SecurityEvent(e) => {
+            let statement = prepared(tx, statements, INSERT_SECURITY_EVENT)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &e.timestamp, &e.interface_name, &e.event_type, &e.source_ip, &e.dest_ip,
+                    &e.port.map(i32::from), &e.protocol, &e.description, &e.severity,
+                ],
+            )?;
+        }
+        Record::Quality(s) => {
+            let statement = prepared(tx, statements, INSERT_QUALITY)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.interface_name, &s.duration_secs, &format!("{:?}", s.confidence),
+                    &count(s.counter_resets), &count(s.collection_failures), &count(s.packets_captured),
+                    &count(s.packets_dropped),
+                ],
+            )?;
+        }
+        Record::Bandwidth(s) => {
+            let statement = prepared(tx, statements, INSERT_BANDWIDTH)?;
+            tx.execute(
+                &statement,
+                &[&machine, &s.timestamp, &s.interface_name, &s.duration_secs, &s.download_bps, &s.upload_bps],
+            )?;
+        }
+        Record::Capture(s) => {
+            let statement = prepared(tx, statements, INSERT_CAPTURE)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.session_start, &s.interface_name, &s.duration_secs, &count(s.packets),
+                    &count(s.bytes), &s.peak_packets_per_second, &s.peak_bytes_per_second, &count(s.queue_drops),
+                    &s.kernel_drops.map(count), &count(s.queue_capacity), &s.avg_queue_depth,
+                    &count(s.peak_queue_depth), &count(s.peak_storage_backlog),
+                ],
+            )?;
+            let sizes = prepared(tx, statements, INSERT_PACKET_SIZES)?;
+            for (interface, protocols) in &s.packet_sizes.histograms {
+                for (protocol, histogram) in protocols.iter().filter(|(_, histogram)| histogram.total() > 0) {
+                    tx.execute(
+                        &sizes,
+                        &[
+                            &machine, &s.timestamp, interface, protocol, &count(histogram.tiny),
+                            &count(histogram.small), &count(histogram.large), &count(histogram.jumbo),
+                        ],
+                    )?;
+                }
+            }
+        }
+        Record::CollectorHealth(s) => {
+            let telemetry = &s.telemetry;
+            let statement = prepared(tx, statements, INSERT_COLLECTOR_HEALTH)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.duration_secs, &count(telemetry.collections),
+                    &count(telemetry.failed_collections), &count(telemetry.degraded_collections),
+                    &count(telemetry.retries), &ms(telemetry.total_duration), &ms(telemetry.max_duration),
+                    &serde_json::to_string(&telemetry.failed_interfaces)?,
+                ],
+            )?;
+        }
+        Record::InterfaceChange(c) => {
+            let statement = prepared(tx, statements, INSERT_INTERFACE_CHANGE)?;
+            tx.execute(&statement, &[&machine, &c.timestamp, &c.interface_name, &c.change])?;
+        }
+        Record::TcpSession(session) => {
+            let kernel = session.kernel.as_ref();
+            let statement = prepared(tx, statements, UPSERT_TCP_SESSION)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine,
+                    &session.interface,
+                    &session.client.ip().to_string(),
+                    &i32::from(session.client.port()),
+                    &session.server.ip().to_string(),
+                    &i32::from(session.server.port()),
+                    &session.state.to_string(),
+                    &session.first_seen,
+                    &session.established_at,
+                    &session.closed_at,
+                    &session.last_seen,
+                    &(session.duration().num_milliseconds() as f64 / 1000.0),
+                    &session.handshake_time().map(|t| t.num_microseconds().unwrap_or(0) as f64 / 1000.0),
+                    &count(session.packets),
+                    &count(session.bytes),
+                    &count(session.loss.retransmissions),
+                    &count(session.loss.segments),
+                    &count(session.loss.duplicate_acks),
+                    &count(session.loss.out_of_order),
+                    &kernel.map(|info| i64::from(info.congestion_window)),
+                    &kernel.map(TcpInfo::rtt_ms),
+                    &kernel.map(TcpInfo::rtt_var_ms),
+                    &kernel.map(|info| i64::from(info.retransmits)),
+                    &kernel.map(|info| i64::from(info.lost)),
+                    &kernel.and_then(|info| info.congestion_control.as_deref()),
+                ],
+            )?;
+        }
+        Record::Latency(s) => {
+            let statement = prepared(tx, statements, INSERT_LATENCY)?;
+            tx.execute(
+                &statement,
+                &[&machine, &s.timestamp, &s.interface, &s.host.to_string(), &s.rtt_ms, &s.source.as_str()],
+            )?;
+        }
+        Record::Classification(s) => {
+            let statement = prepared(tx, statements, INSERT_CLASSIFICATION)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.interface, &count(s.packets), &count(s.bytes),
+                    &count(s.unknown_packets), &count(s.unknown_bytes), &count(s.low_confidence_bytes),
+                ],
+            )?;
+        }
+        Record::Duplicate(s) => {
+            let statement = prepared(tx, statements, INSERT_DUPLICATES)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.interface, &count(s.packets), &count(s.duplicates),
+                    &count(s.ttl_changed), &i32::from(s.max_ttl_drop), &i64::from(s.max_copies), &s.source,
+                    &s.destination,
+                ],
+            )?;
+        }
+        Record::Mtu(s) => {
+            let statement = prepared(tx, statements, INSERT_MTU)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.interface, &s.remote, &count(s.fragments),
+                    &count(s.fragmented_packets), &i64::from(s.largest_fragment), &count(s.too_big),
+                    &s.reported_mtu.map(i64::from),
+                ],
+            )?;
+        }
+        Record::Throttled(f) => {
+            let statement = prepared(tx, statements, INSERT_THROTTLED)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &f.start, &f.end, &f.interface, &f.transport, &f.sender, &f.receiver, &f.inbound,
+                    &f.rate, &f.variation, &i64::from(f.seconds), &f.link_share, &f.confidence.as_str(),
+                ],
+            )?;
+        }
+        Record::Ping(s) => {
+            let statement = prepared(tx, statements, INSERT_PING)?;
+            tx.execute(
+                &statement,
+                &[
+                    &machine, &s.timestamp, &s.target, &s.address, &count(s.sent), &count(s.received),
+                    &s.latency_ms, &s.jitter_ms,
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NetworkPacket, PacketDirection, PacketProtocol, TransportProtocol};
+    use chrono::{DateTime, Local};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    /// Server the live tests run against, e.g. `postgres://kw@localhost/kw_test`; they are
+    /// skipped when it is not set, and drop and recreate the tables they write
+    const TEST_URL_ENV: &str = "KW_TEST_POSTGRES_URL";
+
+    #[test]
+    fn test_every_table_carries_the_machine() {
+        let tables: Vec<&str> = CREATE_TABLES.split("CREATE TABLE IF NOT EXISTS ").skip(1).collect();
+        assert_eq!(tables.len(), 16);
+        for table in tables {
+            assert!(table.contains("\n    machine TEXT NOT NULL,"), "{}", table.lines().next().unwrap_or(""));
+        }
+        for insert in [INSERT_PROTOCOL, UPSERT_CONNECTION, INSERT_PING, UPSERT_TCP_SESSION, INSERT_THROTTLED] {
+            assert!(insert.contains("(\n    machine,"));
+        }
+        assert_eq!(count(u64::MAX), i64::MAX);
+    }
+
+    #[test]
+    fn test_connections_merge_before_writing() {
+        let mut packet = NetworkPacket::new("eth0".to_string(), 100, PacketProtocol::IPv4, PacketDirection::Outbound);
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)));
+        let first = PacketRecords::new(&packet, &AnalysisResult::default(), 1).connection.unwrap();
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        let other = PacketRecords::new(&packet, &AnalysisResult::default(), 1).connection.unwrap();
+        let mut later = first.clone();
+        later.last_seen = first.last_seen + chrono::Duration::seconds(5);
+        later.application_protocol = Some("HTTPS".to_string());
+
+        let merged = merge_connections(vec![
+            Record::Connection(first.clone()),
+            Record::Connection(other),
+            Record::InterfaceChange(InterfaceChange {
+                timestamp: first.first_seen,
+                interface_name: "eth0".to_string(),
+                change: "up".to_string(),
+            }),
+            Record::Connection(later.clone()),
+        ]);
+        assert_eq!(merged.len(), 3);
+        let Record::Connection(merged) = &merged[0] else { panic!("first record is not the connection") };
+        assert_eq!((merged.packet_count, merged.byte_count), (2, 200));
+        assert_eq!(merged.last_seen, later.last_seen);
+        assert_eq!(merged.application_protocol.as_deref(), Some("HTTPS"));
+    }
+
+    #[test]
+    fn test_records_reach_postgres() {
+        let Ok(url) = std::env::var(TEST_URL_ENV) else {
+            eprintln!("{TEST_URL_ENV} not set, skipping");
+            return;
+        };
+        let mut client = Client::connect(&url, postgres::NoTls).unwrap();
+        client
+            .batch_execute(
+                "DROP TABLE IF EXISTS kw_schema_version, protocol_distribution, connections, security_events,
+                 data_quality, bandwidth_samples, capture_stats, packet_size_stats, collector_health,
+                 interface_events, tcp_sessions, latency_samples, classification_stats, duplicate_stats,
+                 mtu_stats, throttled_flows, ping_stats",
+            )
+            .unwrap();
+
+        let backend = PostgresBackend::connect(&url, "test-pi", 100, Duration::from_secs(60)).unwrap();
+        let mut packet = NetworkPacket::new("eth0".to_string(), 100, PacketProtocol::IPv4, PacketDirection::Outbound);
+        packet.source_addr = Some(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        packet.dest_addr = Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)));
+        packet.source_port = Some(50000);
+        packet.dest_port = Some(443);
+        packet.transport_protocol = TransportProtocol::Tcp;
+        let analysis = AnalysisResult { application_protocol: Some("HTTPS".to_string()), ..Default::default() };
+        backend.store_packet(&packet, &analysis).unwrap();
+        backend.store_packet(&packet, &analysis).unwrap();
+        backend
+            .store_bandwidth_samples(&[BandwidthSample {
+                timestamp: Local::now(),
+                interface_name: "eth0".to_string(),
+                duration_secs: 60.0,
+                download_bps: 1000.0,
+                upload_bps: 10.0,
+            }])
+            .unwrap();
+        assert_eq!(backend.pending_records(), 5);
+        backend.flush().unwrap();
+        assert_eq!(backend.pending_records(), 0);
+        drop(backend);
+
+        let row = client
+            .query_one("SELECT machine, packet_count, byte_count FROM connections", &[])
+            .unwrap();
+        assert_eq!(row.get::<_, String>(0), "test-pi");
+        assert_eq!(row.get::<_, i64>(1), 2);
+        assert_eq!(row.get::<_, i64>(2), 200);
+        let protocols: i64 = client.query_one("SELECT count(*) FROM protocol_distribution", &[]).unwrap().get(0);
+        assert_eq!(protocols, 2);
+        let stored: DateTime<Local> = client.query_one("SELECT timestamp FROM bandwidth_samples", &[]).unwrap().get(0);
+        assert!(Local::now() - stored < chrono::Duration::minutes(1));
+
+        // A second daemon finds the schema current
+        assert_eq!(migrate(&mut client).unwrap(), (SCHEMA_VERSION, SCHEMA_VERSION));
+    }
+}
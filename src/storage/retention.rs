@@ -0,0 +1,296 @@
+// Retention: how long stored data is kept, and at what resolution
+// Bandwidth and protocol samples past `raw_days` are folded into hourly rollups, hourly
+// rollups past `hourly_days` into daily ones, and raw packet records are deleted; other
+// tables keep their fixed ages. Run by `kw maintain` and periodically by the daemon
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Timelike};
+use rusqlite::Connection;
+
+use crate::config::RetentionConfig;
+
+/// Raw packet records deleted once older than `raw_days`: (table, timestamp column)
+const RAW_TABLES: [(&str, &str); 4] = [
+    ("packet_stats", "timestamp"),
+    ("connections", "last_seen"),
+    ("tcp_sessions", "last_seen"),
+    ("latency_samples", "timestamp"),
+];
+
+/// Tables kept for a fixed number of days: (table, timestamp column, days)
+const FIXED_RETENTION: [(&str, &str, i64); 13] = [
+    ("security_events", "timestamp", 90),
+    ("classification_stats", "timestamp", 90),
+    ("duplicate_stats", "timestamp", 90),
+    ("mtu_stats", "timestamp", 90),
+    ("throttled_flows", "end_time", 90),
+    ("capture_stats", "timestamp", 90),
+    ("packet_size_stats", "timestamp", 90),
+    ("collector_health", "timestamp", 90),
+    ("interface_events", "timestamp", 90),
+    ("ping_stats", "timestamp", 90),
+    // Few rows, and they show long-term trends
+    ("speed_tests", "timestamp", 365),
+    ("traffic_analysis", "timestamp", 365),
+    // As long as the traffic analysis they qualify
+    ("data_quality", "timestamp", 365),
+];
+
+// The audit log is never pruned, removing entries would break its hash chain
+
+/// What one run of the policy did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    /// Raw bandwidth and protocol samples folded into hourly rollups
+    pub rolled_into_hours: usize,
+    /// Hourly rollups folded into daily ones
+    pub rolled_into_days: usize,
+    /// Rows deleted, per table that lost any
+    pub deleted: Vec<(&'static str, usize)>,
+}
+
+impl RetentionReport {
+    pub fn deleted_total(&self) -> usize {
+        self.deleted.iter().map(|(_, rows)| rows).sum()
+    }
+}
+
+/// Applies the retention policy as of `now` in one transaction
+pub fn apply_retention(conn: &Connection, config: &RetentionConfig, now: DateTime<Local>) -> Result<RetentionReport> {
+    let tx = conn.unchecked_transaction()?;
+    let mut report = RetentionReport::default();
+
+    if config.raw_days > 0 {
+        // Whole hours only, so no hour is split between raw samples and its rollup
+        let cutoff = timestamp(start_of_hour(now - Duration::days(config.raw_days.into())));
+        report.rolled_into_hours = roll_up_samples(&tx, &cutoff)?;
+        for (table, column) in RAW_TABLES {
+            delete_before(&tx, &mut report, table, column, "", &cutoff)?;
+        }
+    }
+
+    if config.hourly_days > 0 {
+        let cutoff = timestamp(start_of_day(now - Duration::days(config.hourly_days.into())));
+        report.rolled_into_days = roll_up_hours(&tx, &cutoff)?;
+    }
+
+    if config.daily_days > 0 {
+        let cutoff = timestamp(start_of_day(now - Duration::days(config.daily_days.into())));
+        for table in ["bandwidth_rollups", "protocol_rollups"] {
+            delete_before(&tx, &mut report, table, "period_start", "resolution = 'day' AND", &cutoff)?;
+        }
+    }
+
+    for (table, column, days) in FIXED_RETENTION {
+        delete_before(&tx, &mut report, table, column, "", &timestamp(now - Duration::days(days)))?;
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Deletes the rows of `table` matching `filter` whose `column` is before `cutoff`
+fn delete_before(
+    conn: &Connection,
+    report: &mut RetentionReport,
+    table: &'static str,
+    column: &str,
+    filter: &str,
+    cutoff: &str,
+) -> Result<()> {
+    let rows = conn.execute(&format!("DELETE FROM {table} WHERE {filter} {column} < ?1"), [cutoff])?;
+    if rows > 0 {
+        report.deleted.push((table, rows));
+    }
+    Ok(())
+}
+
+/// Folds bandwidth and protocol samples before `cutoff` into hourly rollups and removes them
+/// Returns the number of samples folded
+fn roll_up_samples(conn: &Connection, cutoff: &str) -> Result<usize> {
+    conn.execute(
+        &format!(
+            "INSERT INTO bandwidth_rollups
+                (period_start, resolution, interface_name, duration_secs, download_bps, upload_bps,
+                 peak_download_bps, peak_upload_bps, samples)
+             SELECT strftime('%Y-%m-%d %H:00:00', timestamp), 'hour', interface_name, SUM(duration_secs),
+                    COALESCE(SUM(download_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(download_bps)),
+                    COALESCE(SUM(upload_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(upload_bps)),
+                    MAX(download_bps), MAX(upload_bps), COUNT(*)
+             FROM bandwidth_samples
+             WHERE timestamp < ?1
+             GROUP BY 1, interface_name {MERGE_BANDWIDTH}"
+        ),
+        [cutoff],
+    )?;
+    let bandwidth = conn.execute("DELETE FROM bandwidth_samples WHERE timestamp < ?1", [cutoff])?;
+
+    conn.execute(
+        &format!(
+            "INSERT INTO protocol_rollups
+                (period_start, resolution, interface_name, protocol_name, packet_count, byte_count)
+             SELECT strftime('%Y-%m-%d %H:00:00', timestamp), 'hour', interface_name, protocol_name,
+                    SUM(packet_count), SUM(byte_count)
+             FROM protocol_distribution
+             WHERE timestamp < ?1
+             GROUP BY 1, interface_name, protocol_name {MERGE_PROTOCOLS}"
+        ),
+        [cutoff],
+    )?;
+    let protocols = conn.execute("DELETE FROM protocol_distribution WHERE timestamp < ?1", [cutoff])?;
+
+    Ok(bandwidth + protocols)
+}
+
+/// Folds hourly rollups of days before `cutoff` into daily rollups and removes them
+/// Returns the number of hourly rollups folded
+fn roll_up_hours(conn: &Connection, cutoff: &str) -> Result<usize> {
+    conn.execute(
+        &format!(
+            "INSERT INTO bandwidth_rollups
+                (period_start, resolution, interface_name, duration_secs, download_bps, upload_bps,
+                 peak_download_bps, peak_upload_bps, samples)
+             SELECT strftime('%Y-%m-%d 00:00:00', period_start), 'day', interface_name, SUM(duration_secs),
+                    COALESCE(SUM(download_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(download_bps)),
+                    COALESCE(SUM(upload_bps * duration_secs) / NULLIF(SUM(duration_secs), 0), AVG(upload_bps)),
+                    MAX(peak_download_bps), MAX(peak_upload_bps), SUM(samples)
+             FROM bandwidth_rollups
+             WHERE resolution = 'hour' AND period_start < ?1
+             GROUP BY 1, interface_name {MERGE_BANDWIDTH}"
+        ),
+        [cutoff],
+    )?;
+    let bandwidth = conn.execute(
+        "DELETE FROM bandwidth_rollups WHERE resolution = 'hour' AND period_start < ?1",
+        [cutoff],
+    )?;
+
+    conn.execute(
+        &format!(
+            "INSERT INTO protocol_rollups
+                (period_start, resolution, interface_name, protocol_name, packet_count, byte_count)
+             SELECT strftime('%Y-%m-%d 00:00:00', period_start), 'day', interface_name, protocol_name,
+                    SUM(packet_count), SUM(byte_count)
+             FROM protocol_rollups
+             WHERE resolution = 'hour' AND period_start < ?1
+             GROUP BY 1, interface_name, protocol_name {MERGE_PROTOCOLS}"
+        ),
+        [cutoff],
+    )?;
+    let protocols = conn.execute(
+        "DELETE FROM protocol_rollups WHERE resolution = 'hour' AND period_start < ?1",
+        [cutoff],
+    )?;
+
+    Ok(bandwidth + protocols)
+}
+
+/// Merges new bandwidth into a rollup that already covers the period
+const MERGE_BANDWIDTH: &str = "
+         ON CONFLICT (period_start, resolution, interface_name) DO UPDATE SET
+            download_bps = COALESCE((download_bps * duration_secs + excluded.download_bps * excluded.duration_secs)
+                / NULLIF(duration_secs + excluded.duration_secs, 0), excluded.download_bps),
+            upload_bps = COALESCE((upload_bps * duration_secs + excluded.upload_bps * excluded.duration_secs)
+                / NULLIF(duration_secs + excluded.duration_secs, 0), excluded.upload_bps),
+            duration_secs = duration_secs + excluded.duration_secs,
+            peak_download_bps = MAX(peak_download_bps, excluded.peak_download_bps),
+            peak_upload_bps = MAX(peak_upload_bps, excluded.peak_upload_bps),
+            samples = samples + excluded.samples";
+
+/// Merges new protocol traffic into a rollup that already covers the period
+const MERGE_PROTOCOLS: &str = "
+         ON CONFLICT (period_start, resolution, interface_name, protocol_name) DO UPDATE SET
+            packet_count = packet_count + excluded.packet_count,
+            byte_count = byte_count + excluded.byte_count";
+
+fn start_of_hour(time: DateTime<Local>) -> DateTime<Local> {
+    time.with_minute(0).and_then(|t| t.with_second(0)).and_then(|t| t.with_nanosecond(0)).unwrap_or(time)
+}
+
+fn start_of_day(time: DateTime<Local>) -> DateTime<Local> {
+    start_of_hour(time).with_hour(0).unwrap_or(time)
+}
+
+/// Stored form of a local time
+fn timestamp(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::schema::migrate;
+    use rusqlite::params;
+
+    fn insert_sample(conn: &Connection, at: DateTime<Local>, duration_secs: f64, download_bps: f64) {
+        conn.execute(
+            "INSERT INTO bandwidth_samples (timestamp, interface_name, duration_secs, download_bps, upload_bps)
+             VALUES (?1, 'eth0', ?2, ?3, 0.0)",
+            params![timestamp(at), duration_secs, download_bps],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_samples_roll_up_into_hours_then_days() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        let now = Local::now();
+        let old_hour = start_of_hour(now - Duration::days(40));
+        insert_sample(&conn, old_hour + Duration::minutes(1), 60.0, 1000.0);
+        insert_sample(&conn, old_hour + Duration::minutes(2), 180.0, 3000.0);
+        insert_sample(&conn, now - Duration::minutes(5), 60.0, 500.0);
+        conn.execute(
+            "INSERT INTO packet_stats (timestamp, interface_name) VALUES (?1, 'eth0')",
+            [timestamp(now - Duration::days(40))],
+        )
+        .unwrap();
+
+        let config = RetentionConfig::default();
+        let report = apply_retention(&conn, &config, now).unwrap();
+        assert_eq!(report.rolled_into_hours, 2);
+        assert_eq!(report.deleted, [("packet_stats", 1)]);
+
+        let (resolution, duration, download, peak, samples): (String, f64, f64, f64, i64) = conn
+            .query_row(
+                "SELECT resolution, duration_secs, download_bps, peak_download_bps, samples FROM bandwidth_rollups",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        // Weighted by sampled time: (60 * 1000 + 180 * 3000) / 240
+        assert_eq!((resolution.as_str(), duration, download, peak, samples), ("hour", 240.0, 2500.0, 3000.0, 2));
+        let recent: i64 = conn.query_row("SELECT COUNT(*) FROM bandwidth_samples", [], |row| row.get(0)).unwrap();
+        assert_eq!(recent, 1);
+
+        // Half a year later both hours, the recent sample's rolled up on the way, are merged into days
+        let later = apply_retention(&conn, &config, now + Duration::days(200)).unwrap();
+        assert_eq!(later.rolled_into_hours, 1);
+        assert_eq!(later.rolled_into_days, 2);
+        let download: f64 = conn
+            .query_row(
+                "SELECT download_bps FROM bandwidth_rollups WHERE resolution = 'day' AND period_start = ?1",
+                [timestamp(start_of_day(old_hour))],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(download, 2500.0);
+        let hours: i64 = conn
+            .query_row("SELECT COUNT(*) FROM bandwidth_rollups WHERE resolution = 'hour'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(hours, 0);
+    }
+
+    #[test]
+    fn test_disabled_stages_keep_data() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        let now = Local::now();
+        insert_sample(&conn, now - Duration::days(400), 60.0, 1000.0);
+
+        let keep_all = RetentionConfig { raw_days: 0, hourly_days: 0, daily_days: 0, ..RetentionConfig::default() };
+        assert_eq!(apply_retention(&conn, &keep_all, now).unwrap(), RetentionReport::default());
+        let samples: i64 = conn.query_row("SELECT COUNT(*) FROM bandwidth_samples", [], |row| row.get(0)).unwrap();
+        assert_eq!(samples, 1);
+    }
+}
@@ -1,5 +1,59 @@
-use anyhow::Result;
-use rusqlite::{Connection, Transaction};
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+use std::time::Duration;
+
+/// A step bringing the schema from one version to the next
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Schema migrations in order; applying the Nth brings a database to version N
+///
+/// The version is kept in `PRAGMA user_version`. Databases from before versioning
+/// report version 0 and are brought up by the first migration, which creates the
+/// tables, columns and indexes they are missing. Later schema changes are appended
+/// here as new migrations rather than edited into `create_tables`, which databases
+/// already at version 1 never run again.
+const MIGRATIONS: &[Migration] = &[
+    create_tables,
+    create_rollup_tables,
+    add_sample_rates,
+    create_devices_table,
+    create_duplicates_table,
+    create_mtu_table,
+    create_throttle_table,
+    add_socket_stats_columns,
+    create_packet_size_table,
+    create_collector_health_table,
+    create_interface_events_table,
+];
+
+/// Schema version of a database with every migration applied
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Schema version recorded in the database
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Applies the migrations the database has not had yet, each in its own transaction
+/// Returns the versions before and after; a database from a newer build is refused
+pub fn migrate(conn: &mut Connection) -> Result<(u32, u32)> {
+    let from = schema_version(conn)?;
+    if from > SCHEMA_VERSION {
+        bail!(
+            "Database schema version {from} is newer than this build supports ({SCHEMA_VERSION}); \
+             upgrade kaipo-watcher or open it with --read-only"
+        );
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        let version = index as u32 + 1;
+        let tx = conn.transaction()?;
+        migration(&tx).with_context(|| format!("Migration to schema version {version} failed"))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+    Ok((from, SCHEMA_VERSION))
+}
 
 pub fn create_tables(conn: &Connection) -> Result<()> {
     // Create packet statistics table
@@ -53,7 +107,8 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
             last_seen DATETIME NOT NULL,
             packet_count INTEGER NOT NULL DEFAULT 0,
             byte_count INTEGER NOT NULL DEFAULT 0,
-            is_active BOOLEAN NOT NULL DEFAULT TRUE
+            is_active BOOLEAN NOT NULL DEFAULT TRUE,
+            classification_confidence TEXT
         )",
         [],
     )?;
@@ -92,12 +147,490 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    // Create data quality table: how trustworthy the measurements of each period are
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS data_quality (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            duration_secs REAL NOT NULL,
+            confidence TEXT NOT NULL,
+            counter_resets INTEGER NOT NULL DEFAULT 0,
+            collection_failures INTEGER NOT NULL DEFAULT 0,
+            packets_captured INTEGER NOT NULL DEFAULT 0,
+            packets_dropped INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Create bandwidth sample table: average throughput per interface and monitoring interval
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bandwidth_samples (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            duration_secs REAL NOT NULL,
+            download_bps REAL NOT NULL DEFAULT 0.0,
+            upload_bps REAL NOT NULL DEFAULT 0.0
+        )",
+        [],
+    )?;
+
+    // Create capture statistics table: capture throughput, drops and queue depths per interval
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS capture_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            session_start DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            duration_secs REAL NOT NULL,
+            packets INTEGER NOT NULL DEFAULT 0,
+            bytes INTEGER NOT NULL DEFAULT 0,
+            peak_packets_per_second REAL NOT NULL DEFAULT 0.0,
+            peak_bytes_per_second REAL NOT NULL DEFAULT 0.0,
+            queue_drops INTEGER NOT NULL DEFAULT 0,
+            kernel_drops INTEGER,
+            queue_capacity INTEGER NOT NULL DEFAULT 0,
+            avg_queue_depth REAL NOT NULL DEFAULT 0.0,
+            peak_queue_depth INTEGER NOT NULL DEFAULT 0,
+            peak_storage_backlog INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Create classification table: classified and unclassified traffic per interface and minute
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS classification_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            packets INTEGER NOT NULL DEFAULT 0,
+            bytes INTEGER NOT NULL DEFAULT 0,
+            unknown_packets INTEGER NOT NULL DEFAULT 0,
+            unknown_bytes INTEGER NOT NULL DEFAULT 0,
+            low_confidence_bytes INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Create ping table: probes, latency and jitter per connectivity target and minute
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ping_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            target TEXT NOT NULL,
+            address TEXT NOT NULL,
+            sent INTEGER NOT NULL DEFAULT 0,
+            received INTEGER NOT NULL DEFAULT 0,
+            latency_ms REAL,
+            jitter_ms REAL
+        )",
+        [],
+    )?;
+
+    // Create speed test table: one row per active throughput test
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS speed_tests (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            server TEXT NOT NULL,
+            latency_ms REAL,
+            jitter_ms REAL,
+            download_bps REAL,
+            upload_bps REAL,
+            download_bytes INTEGER NOT NULL DEFAULT 0,
+            upload_bytes INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+
+    // Create TCP session table: one row per connection lifecycle
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tcp_sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            interface_name TEXT NOT NULL,
+            client_ip TEXT NOT NULL,
+            client_port INTEGER NOT NULL,
+            server_ip TEXT NOT NULL,
+            server_port INTEGER NOT NULL,
+            state TEXT NOT NULL,
+            first_seen DATETIME NOT NULL,
+            established_at DATETIME,
+            closed_at DATETIME,
+            last_seen DATETIME NOT NULL,
+            duration_secs REAL NOT NULL DEFAULT 0.0,
+            handshake_ms REAL,
+            packet_count INTEGER NOT NULL DEFAULT 0,
+            byte_count INTEGER NOT NULL DEFAULT 0,
+            retransmissions INTEGER NOT NULL DEFAULT 0,
+            segments INTEGER NOT NULL DEFAULT 0,
+            duplicate_acks INTEGER NOT NULL DEFAULT 0,
+            out_of_order INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (client_ip, client_port, server_ip, server_port, first_seen)
+        )",
+        [],
+    )?;
+
+    // Create latency table: one row per passively measured round-trip time
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS latency_samples (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            host TEXT NOT NULL,
+            rtt_ms REAL NOT NULL,
+            source TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create tags table: user names and notes for hosts, devices and interfaces
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            target TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            label TEXT NOT NULL,
+            note TEXT,
+            updated_at DATETIME NOT NULL
+        )",
+        [],
+    )?;
+
+    // Create audit log: hash-chained entries that may only be appended to
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY,
+            timestamp DATETIME NOT NULL,
+            category TEXT NOT NULL,
+            action TEXT NOT NULL,
+            detail TEXT NOT NULL,
+            prev_hash TEXT NOT NULL,
+            hash TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+         BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;
+         CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+         BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END;",
+    )?;
+
+    // Loss counters were added to tcp_sessions after the table first shipped
+    add_missing_columns(
+        conn,
+        "tcp_sessions",
+        &[
+            ("segments", "INTEGER NOT NULL DEFAULT 0"),
+            ("duplicate_acks", "INTEGER NOT NULL DEFAULT 0"),
+            ("out_of_order", "INTEGER NOT NULL DEFAULT 0"),
+        ],
+    )?;
+
+    // Classification confidence was added to connections after the table first shipped
+    add_missing_columns(conn, "connections", &[("classification_confidence", "TEXT")])?;
+
     // Create indexes for better query performance
     create_indexes(conn)?;
 
     Ok(())
 }
 
+/// Version 2: hourly and daily rollups that raw samples are folded into by the
+/// retention policy once they pass `raw_days`
+fn create_rollup_tables(conn: &Connection) -> Result<()> {
+    // Bandwidth per interface and hour or day; rates are averages weighted by sampled time
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bandwidth_rollups (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            period_start DATETIME NOT NULL,
+            resolution TEXT NOT NULL,
+            interface_name TEXT NOT NULL,
+            duration_secs REAL NOT NULL,
+            download_bps REAL NOT NULL DEFAULT 0.0,
+            upload_bps REAL NOT NULL DEFAULT 0.0,
+            peak_download_bps REAL NOT NULL DEFAULT 0.0,
+            peak_upload_bps REAL NOT NULL DEFAULT 0.0,
+            samples INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (period_start, resolution, interface_name)
+        )",
+        [],
+    )?;
+
+    // Protocol traffic per interface and hour or day
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS protocol_rollups (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            period_start DATETIME NOT NULL,
+            resolution TEXT NOT NULL,
+            interface_name TEXT NOT NULL,
+            protocol_name TEXT NOT NULL,
+            packet_count INTEGER NOT NULL DEFAULT 0,
+            byte_count INTEGER NOT NULL DEFAULT 0,
+            UNIQUE (period_start, resolution, interface_name, protocol_name)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_bandwidth_rollups_resolution_period 
+         ON bandwidth_rollups(resolution, period_start)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_protocol_rollups_resolution_period 
+         ON protocol_rollups(resolution, period_start)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Version 3: the sampling rate protocol and connection counts were scaled up by, 1 for
+/// records of every packet
+fn add_sample_rates(conn: &Connection) -> Result<()> {
+    add_missing_columns(conn, "protocol_distribution", &[("sample_rate", "INTEGER NOT NULL DEFAULT 1")])?;
+    add_missing_columns(conn, "connections", &[("sample_rate", "INTEGER NOT NULL DEFAULT 1")])?;
+    Ok(())
+}
+
+/// Version 4: LAN devices identified from their mDNS and SSDP announcements, one row per address
+fn create_devices_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS discovered_devices (
+            address TEXT PRIMARY KEY,
+            interface_name TEXT NOT NULL,
+            name TEXT,
+            device_type TEXT NOT NULL,
+            source TEXT NOT NULL,
+            first_seen DATETIME NOT NULL,
+            last_seen DATETIME NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 5: packets seen more than once per interface and minute, for loop diagnostics
+fn create_duplicates_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS duplicate_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            packets INTEGER NOT NULL DEFAULT 0,
+            duplicates INTEGER NOT NULL DEFAULT 0,
+            ttl_changed INTEGER NOT NULL DEFAULT 0,
+            max_ttl_drop INTEGER NOT NULL DEFAULT 0,
+            max_copies INTEGER NOT NULL DEFAULT 0,
+            source_ip TEXT,
+            dest_ip TEXT
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_duplicate_stats_timestamp ON duplicate_stats(timestamp)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Version 6: IP fragments and "packet too big" messages per remote host and minute
+fn create_mtu_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mtu_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            remote_ip TEXT NOT NULL,
+            fragments INTEGER NOT NULL DEFAULT 0,
+            fragmented_packets INTEGER NOT NULL DEFAULT 0,
+            largest_fragment INTEGER NOT NULL DEFAULT 0,
+            too_big INTEGER NOT NULL DEFAULT 0,
+            reported_mtu INTEGER
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_mtu_stats_timestamp ON mtu_stats(timestamp)", [])?;
+    Ok(())
+}
+
+fn create_throttle_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS throttled_flows (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            start_time DATETIME NOT NULL,
+            end_time DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            transport TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            receiver TEXT NOT NULL,
+            inbound BOOLEAN NOT NULL,
+            rate REAL NOT NULL,
+            variation REAL NOT NULL,
+            seconds INTEGER NOT NULL,
+            link_share REAL NOT NULL DEFAULT 0,
+            confidence TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_throttled_flows_end_time ON throttled_flows(end_time)", [])?;
+    Ok(())
+}
+
+/// Version 8: the kernel's statistics of the host's socket for each TCP connection, where
+/// it is an end of one
+fn add_socket_stats_columns(conn: &Connection) -> Result<()> {
+    add_missing_columns(
+        conn,
+        "tcp_sessions",
+        &[
+            ("kernel_cwnd", "INTEGER"),
+            ("kernel_rtt_ms", "REAL"),
+            ("kernel_rtt_var_ms", "REAL"),
+            ("kernel_retransmits", "INTEGER"),
+            ("kernel_lost", "INTEGER"),
+            ("congestion_control", "TEXT"),
+        ],
+    )
+}
+
+/// Version 9: packet counts by size per interface, transport protocol and capture sample
+fn create_packet_size_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS packet_size_stats (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            protocol TEXT NOT NULL,
+            tiny_packets INTEGER NOT NULL DEFAULT 0,
+            small_packets INTEGER NOT NULL DEFAULT 0,
+            large_packets INTEGER NOT NULL DEFAULT 0,
+            jumbo_packets INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_packet_size_stats_timestamp ON packet_size_stats(timestamp)", [])?;
+    Ok(())
+}
+
+/// Version 10: the bandwidth collector's own health per monitoring interval
+fn create_collector_health_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS collector_health (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            duration_secs REAL NOT NULL,
+            collections INTEGER NOT NULL DEFAULT 0,
+            failed_collections INTEGER NOT NULL DEFAULT 0,
+            degraded_collections INTEGER NOT NULL DEFAULT 0,
+            retries INTEGER NOT NULL DEFAULT 0,
+            total_duration_ms REAL NOT NULL DEFAULT 0,
+            max_duration_ms REAL NOT NULL DEFAULT 0,
+            failed_interfaces TEXT NOT NULL DEFAULT '{}'
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_collector_health_timestamp ON collector_health(timestamp)", [])?;
+    Ok(())
+}
+
+/// Version 11: interfaces appearing, disappearing and going up or down, seen by the daemon
+fn create_interface_events_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS interface_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp DATETIME NOT NULL,
+            interface_name TEXT NOT NULL,
+            change TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_interface_events_timestamp ON interface_events(timestamp)", [])?;
+    Ok(())
+}
+
+/// Opens an existing database without write access, for inspecting a copy
+///
+/// Nothing is created or migrated. Tables and columns the database predates are
+/// stood in for by empty temporary tables and views, which only exist for this
+/// connection, so queries written for the current schema still run.
+pub fn open_read_only<P: AsRef<Path>>(db_path: P) -> Result<Connection> {
+    let db_path = db_path.as_ref();
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {} read-only", db_path.display()))?;
+    super::encryption::unlock(&conn, db_path)?;
+    conn.busy_timeout(Duration::from_secs(5))
+        .context("Failed to set busy timeout")?;
+    shadow_missing_schema(&conn)
+        .with_context(|| format!("Failed to read the schema of {}", db_path.display()))?;
+    // Refuse writes even to the temporary schema from here on
+    conn.pragma_update(None, "query_only", true)?;
+    Ok(conn)
+}
+
+/// Creates temporary stand-ins for the tables and columns `conn`'s database lacks
+/// Unqualified names resolve to the temporary schema first, so a stand-in view
+/// takes the place of a table that is missing newer columns
+fn shadow_missing_schema(conn: &Connection) -> Result<()> {
+    let mut current = Connection::open_in_memory()?;
+    migrate(&mut current)?;
+    let tables: Vec<(String, String)> = current
+        .prepare("SELECT name, sql FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for (table, sql) in tables {
+        let existing = column_names(conn, "main", &table)?;
+        if existing.is_empty() {
+            // SQLite stores the statement without its IF NOT EXISTS clause
+            conn.execute(&sql.replacen("CREATE TABLE", "CREATE TEMP TABLE", 1), [])?;
+            continue;
+        }
+        let columns = column_names(&current, "main", &table)?;
+        if columns.iter().all(|column| existing.contains(column)) {
+            continue;
+        }
+        let select: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                if existing.contains(column) {
+                    format!("\"{column}\"")
+                } else {
+                    format!("NULL AS \"{column}\"")
+                }
+            })
+            .collect();
+        conn.execute(
+            &format!("CREATE TEMP VIEW \"{table}\" AS SELECT {} FROM main.\"{table}\"", select.join(", ")),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn column_names(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>> {
+    Ok(conn
+        .prepare(&format!("PRAGMA {schema}.table_info(\"{table}\")"))?
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<_>>()?)
+}
+
+/// Adds columns that a table created by an older version does not have yet
+fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let existing: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (name, definition) in columns {
+        if !existing.iter().any(|column| column == name) {
+            conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {name} {definition}"), [])?;
+        }
+    }
+    Ok(())
+}
+
 fn create_indexes(conn: &Connection) -> Result<()> {
     // Index on timestamp for time-based queries
     conn.execute(
@@ -130,70 +663,83 @@ fn create_indexes(conn: &Connection) -> Result<()> {
         [],
     )?;
 
-    // Index on interface for interface-specific queries
     conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_packet_stats_interface 
-         ON packet_stats(interface_name)",
+        "CREATE INDEX IF NOT EXISTS idx_data_quality_timestamp 
+         ON data_quality(timestamp)",
         [],
     )?;
 
     conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_protocol_distribution_interface 
-         ON protocol_distribution(interface_name)",
+        "CREATE INDEX IF NOT EXISTS idx_bandwidth_samples_timestamp 
+         ON bandwidth_samples(timestamp)",
         [],
     )?;
 
-    // Composite indexes for common query patterns
     conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_connections_active_last_seen 
-         ON connections(is_active, last_seen)",
+        "CREATE INDEX IF NOT EXISTS idx_capture_stats_session 
+         ON capture_stats(session_start, interface_name)",
         [],
     )?;
 
     conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_traffic_analysis_type_timestamp 
-         ON traffic_analysis(traffic_type, timestamp)",
+        "CREATE INDEX IF NOT EXISTS idx_ping_stats_timestamp 
+         ON ping_stats(timestamp)",
         [],
     )?;
 
-    Ok(())
-}
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_speed_tests_timestamp 
+         ON speed_tests(timestamp)",
+        [],
+    )?;
 
-#[allow(dead_code)]
-pub fn setup_data_retention(tx: &Transaction) -> Result<()> {
-    // Set up automatic cleanup of old data
-    // Keep detailed packet stats for 7 days
-    tx.execute(
-        "DELETE FROM packet_stats 
-         WHERE timestamp < datetime('now', '-7 days')",
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_classification_stats_timestamp 
+         ON classification_stats(timestamp)",
         [],
     )?;
 
-    // Keep protocol distribution for 30 days
-    tx.execute(
-        "DELETE FROM protocol_distribution 
-         WHERE timestamp < datetime('now', '-30 days')",
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp 
+         ON audit_log(timestamp)",
         [],
     )?;
 
-    // Keep inactive connections for 1 day
-    tx.execute(
-        "DELETE FROM connections 
-         WHERE is_active = FALSE AND last_seen < datetime('now', '-1 day')",
+    // Index on interface for interface-specific queries
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_packet_stats_interface 
+         ON packet_stats(interface_name)",
         [],
     )?;
 
-    // Keep security events for 90 days
-    tx.execute(
-        "DELETE FROM security_events 
-         WHERE timestamp < datetime('now', '-90 days')",
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_protocol_distribution_interface 
+         ON protocol_distribution(interface_name)",
         [],
     )?;
 
-    // Keep traffic analysis for 1 year
-    tx.execute(
-        "DELETE FROM traffic_analysis 
-         WHERE timestamp < datetime('now', '-1 year')",
+    // Composite indexes for common query patterns
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_connections_active_last_seen 
+         ON connections(is_active, last_seen)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_traffic_analysis_type_timestamp 
+         ON traffic_analysis(traffic_type, timestamp)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tcp_sessions_first_seen 
+         ON tcp_sessions(first_seen)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_latency_samples_timestamp 
+         ON latency_samples(timestamp)",
         [],
     )?;
 
@@ -218,7 +764,56 @@ mod tests {
             .query_row([], |row| row.get(0))
             .unwrap();
 
-        assert!(table_count >= 5); // We created 5 tables
+        assert!(table_count >= 7); // We created 7 tables
+    }
+
+    #[test]
+    fn test_read_only_stands_in_for_newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.db");
+        {
+            // A database from before the classification column and the ping table
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE connections (id INTEGER PRIMARY KEY, source_ip TEXT NOT NULL);
+                 INSERT INTO connections (source_ip) VALUES ('10.0.0.5');",
+            )
+            .unwrap();
+        }
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let conn = open_read_only(&path).unwrap();
+        let row: (String, Option<String>) = conn
+            .query_row("SELECT source_ip, classification_confidence FROM connections", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(row, ("10.0.0.5".to_string(), None));
+        let pings: i64 = conn.query_row("SELECT COUNT(*) FROM ping_stats", [], |row| row.get(0)).unwrap();
+        assert_eq!(pings, 0);
+
+        assert!(conn.execute("DELETE FROM main.connections", []).is_err());
+        assert!(open_read_only(dir.path().join("missing.db")).is_err());
+        drop(conn);
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn test_migrations_version_unversioned_databases() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // A database created before versioning, lacking a column added since
+        conn.execute("CREATE TABLE tcp_sessions (id INTEGER PRIMARY KEY, first_seen DATETIME, retransmissions INTEGER)", [])
+            .unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        assert_eq!(migrate(&mut conn).unwrap(), (0, SCHEMA_VERSION));
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(column_names(&conn, "main", "tcp_sessions").unwrap().contains(&"segments".to_string()));
+        // Nothing is left to apply the second time
+        assert_eq!(migrate(&mut conn).unwrap(), (SCHEMA_VERSION, SCHEMA_VERSION));
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
     }
 
     #[test]
@@ -234,4 +829,23 @@ mod tests {
 
         assert!(index_count > 0); // We created multiple indexes
     }
+
+    #[test]
+    fn test_missing_columns_are_added() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE tcp_sessions (id INTEGER PRIMARY KEY, retransmissions INTEGER)", [])
+            .unwrap();
+        conn.execute("INSERT INTO tcp_sessions (retransmissions) VALUES (3)", []).unwrap();
+
+        add_missing_columns(&conn, "tcp_sessions", &[("segments", "INTEGER NOT NULL DEFAULT 0")]).unwrap();
+        // Running again leaves the table alone
+        add_missing_columns(&conn, "tcp_sessions", &[("segments", "INTEGER NOT NULL DEFAULT 0")]).unwrap();
+
+        let (retransmissions, segments): (i64, i64) = conn
+            .query_row("SELECT retransmissions, segments FROM tcp_sessions", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((retransmissions, segments), (3, 0));
+    }
 }
\ No newline at end of file
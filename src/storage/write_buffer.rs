@@ -0,0 +1,520 @@
+// Write-ahead buffer between the capture path and the packet database
+// Records are queued in memory and a writer thread commits them in one transaction per
+// batch, when a batch fills or the flush interval passes, so capture never waits on SQLite
+// and the database sees a few large transactions instead of one per record kind and batch
+
+use anyhow::Result;
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::models::PacketStatistics;
+use crate::storage::audit::{self, AuditChain};
+use crate::storage::packet_storage::{ConnectionRecord, ProtocolRecord, SecurityEvent};
+
+/// How long records wait for a batch to fill before they are written anyway
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Batches the buffer may fall behind by before callers write a batch themselves, bounding
+/// memory when the database cannot keep up
+const MAX_PENDING_BATCHES: usize = 8;
+
+/// Records accepted and not yet written
+#[derive(Debug, Default)]
+pub struct PendingRecords {
+    pub stats: Vec<PacketStatistics>,
+    pub protocols: Vec<ProtocolRecord>,
+    pub connections: Vec<ConnectionRecord>,
+    pub security_events: Vec<SecurityEvent>,
+}
+
+impl PendingRecords {
+    pub fn len(&self) -> usize {
+        self.stats.len() + self.protocols.len() + self.connections.len() + self.security_events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Puts `older` records, of a write that failed, back in front of these, keeping at
+    /// most `capacity` records; the oldest go first, security events last, and the number
+    /// dropped is returned
+    fn requeue(&mut self, older: PendingRecords, capacity: usize) -> usize {
+        fn prepend<T>(current: &mut Vec<T>, mut older: Vec<T>) {
+            older.append(current);
+            *current = older;
+        }
+        fn drop_oldest<T>(records: &mut Vec<T>, excess: usize) -> usize {
+            let dropped = excess.min(records.len());
+            records.drain(..dropped);
+            excess - dropped
+        }
+        prepend(&mut self.stats, older.stats);
+        prepend(&mut self.protocols, older.protocols);
+        prepend(&mut self.connections, older.connections);
+        prepend(&mut self.security_events, older.security_events);
+
+        let dropped = self.len().saturating_sub(capacity);
+        let mut excess = drop_oldest(&mut self.stats, dropped);
+        excess = drop_oldest(&mut self.protocols, excess);
+        excess = drop_oldest(&mut self.connections, excess);
+        drop_oldest(&mut self.security_events, excess);
+        dropped
+    }
+}
+
+/// Shared between the storage handle and its writer thread
+struct Shared {
+    pending: Mutex<PendingRecords>,
+    /// Signalled when a batch fills, the interval changes or the buffer closes
+    wake: Condvar,
+    closed: AtomicBool,
+    flush_interval_ms: AtomicU64,
+    /// Records kept for a retry when a write fails; none without a writer, whose callers
+    /// get the error instead
+    retry_capacity: usize,
+}
+
+/// Queues records for `conn` and writes them in batches of `batch_size`
+///
+/// With a writer thread (`WriteBuffer::spawn`) callers only wait on the database when the
+/// buffer is `MAX_PENDING_BATCHES` behind; without one (`WriteBuffer::inline`) each full
+/// batch is written by the caller that filled it. Dropping the buffer writes what is left.
+pub struct WriteBuffer {
+    shared: Arc<Shared>,
+    conn: Arc<Mutex<Connection>>,
+    batch_size: usize,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl WriteBuffer {
+    /// A buffer with a background writer thread
+    pub fn spawn(conn: Arc<Mutex<Connection>>, batch_size: usize) -> Self {
+        let mut buffer = Self::new(conn, batch_size, batch_size.max(1) * MAX_PENDING_BATCHES);
+        let shared = Arc::clone(&buffer.shared);
+        let conn = Arc::clone(&buffer.conn);
+        let writer = std::thread::Builder::new()
+            .name("kw-storage-writer".to_string())
+            .spawn(move || run_writer(&shared, &conn, batch_size));
+        match writer {
+            Ok(writer) => buffer.writer = Some(writer),
+            Err(e) => warn!("Failed to start the storage writer, writing from the capture path: {e}"),
+        }
+        buffer
+    }
+
+    /// A buffer whose batches are written by the callers that fill them, so write errors
+    /// reach them; used where writes are expected to fail, as on read-only databases
+    pub fn inline(conn: Arc<Mutex<Connection>>, batch_size: usize) -> Self {
+        Self::new(conn, batch_size, 0)
+    }
+
+    fn new(conn: Arc<Mutex<Connection>>, batch_size: usize, retry_capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                pending: Mutex::new(PendingRecords::default()),
+                wake: Condvar::new(),
+                closed: AtomicBool::new(false),
+                flush_interval_ms: AtomicU64::new(DEFAULT_FLUSH_INTERVAL.as_millis() as u64),
+                retry_capacity,
+            }),
+            conn,
+            batch_size: batch_size.max(1),
+            writer: None,
+        }
+    }
+
+    /// Writes pending records at least this often
+    pub fn set_flush_interval(&self, interval: Duration) {
+        // Changed under the lock so a writer about to wait sees it
+        let _pending = self.lock();
+        self.shared.flush_interval_ms.store(interval.as_millis().max(1) as u64, Ordering::Relaxed);
+        self.shared.wake.notify_one();
+    }
+
+    /// Adds records through `add`, then hands a full batch to the writer, or writes it
+    /// when there is no writer or the writer has fallen too far behind
+    pub fn push(&self, add: impl FnOnce(&mut PendingRecords)) -> Result<()> {
+        let pending = {
+            let mut pending = self.lock();
+            add(&mut pending);
+            pending.len()
+        };
+        if pending < self.batch_size {
+            return Ok(());
+        }
+        if self.writer.is_some() && pending < self.batch_size * MAX_PENDING_BATCHES {
+            self.shared.wake.notify_one();
+            return Ok(());
+        }
+        self.flush()
+    }
+
+    /// Records accepted and not yet written
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Locks the pending records, e.g. to merge a record into one already queued
+    pub fn lock(&self) -> MutexGuard<'_, PendingRecords> {
+        self.shared.pending.lock().unwrap()
+    }
+
+    /// Writes every pending record now, in one transaction
+    pub fn flush(&self) -> Result<()> {
+        write_pending(&self.shared, &self.conn)
+    }
+}
+
+impl Drop for WriteBuffer {
+    fn drop(&mut self) {
+        {
+            let _pending = self.lock();
+            self.shared.closed.store(true, Ordering::SeqCst);
+            self.shared.wake.notify_one();
+        }
+        // The writer writes what is left before it exits, and what it could not write is
+        // tried once more here
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        if !self.is_empty()
+            && let Err(e) = self.flush()
+        {
+            warn!("Failed to write {} buffered records on shutdown: {e}", self.len());
+        }
+    }
+}
+
+/// Writes whenever a batch fills or the flush interval passes, until the buffer closes
+///
+/// After a failed write the records kept for a retry wait out the interval, so a database
+/// that stays unwritable is not retried in a busy loop.
+fn run_writer(shared: &Shared, conn: &Mutex<Connection>, batch_size: usize) {
+    let mut failed = false;
+    loop {
+        {
+            let mut pending = shared.pending.lock().unwrap();
+            let started = Instant::now();
+            loop {
+                if (pending.len() >= batch_size && !failed) || shared.closed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let interval = Duration::from_millis(shared.flush_interval_ms.load(Ordering::Relaxed));
+                let Some(remaining) = interval.checked_sub(started.elapsed()).filter(|d| !d.is_zero()) else {
+                    break;
+                };
+                pending = shared.wake.wait_timeout(pending, remaining).unwrap().0;
+            }
+        }
+        failed = match write_pending(shared, conn) {
+            Ok(()) => false,
+            Err(e) => {
+                warn!("Failed to write buffered records, retrying: {e}");
+                true
+            }
+        };
+        if shared.closed.load(Ordering::SeqCst) {
+            return;
+        }
+    }
+}
+
+/// Takes every pending record and writes them in one transaction
+///
+/// The connection is locked before the records are taken, so batches reach the database
+/// in the order they were queued even when a caller flushes while the writer does. When
+/// the write fails the records go back in front of any queued meanwhile, up to the retry
+/// capacity, and are written with the next batch.
+fn write_pending(shared: &Shared, conn: &Mutex<Connection>) -> Result<()> {
+    let conn = conn.lock().unwrap();
+    let records = std::mem::take(&mut *shared.pending.lock().unwrap());
+    if records.is_empty() {
+        return Ok(());
+    }
+    let count = records.len();
+    if let Err(e) = write_records(&conn, &records) {
+        if shared.retry_capacity > 0 {
+            let dropped = shared.pending.lock().unwrap().requeue(records, shared.retry_capacity);
+            if dropped > 0 {
+                warn!("Dropped {dropped} buffered records the database could not take in time");
+            }
+        }
+        return Err(e);
+    }
+    debug!("Wrote {count} buffered records to the database");
+    Ok(())
+}
+
+fn write_records(conn: &Connection, records: &PendingRecords) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+
+    if !records.stats.is_empty() {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO packet_stats (
+                timestamp, interface_name, total_packets, total_bytes,
+                packets_per_second, bytes_per_second,
+                tcp_packets, udp_packets, icmp_packets, other_packets,
+                tcp_bytes, udp_bytes, icmp_bytes, other_bytes
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )?;
+        for stats in &records.stats {
+            stmt.execute(params![
+                stats.start_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                "", // interface name would need to be added to PacketStatistics
+                stats.total_packets,
+                stats.total_bytes,
+                stats.packets_per_second,
+                stats.bytes_per_second,
+                stats.protocol_distribution.tcp_packets,
+                stats.protocol_distribution.udp_packets,
+                stats.protocol_distribution.icmp_packets,
+                stats.protocol_distribution.other_packets,
+                stats.protocol_distribution.tcp_bytes,
+                stats.protocol_distribution.udp_bytes,
+                stats.protocol_distribution.icmp_bytes,
+                stats.protocol_distribution.other_bytes,
+            ])?;
+        }
+    }
+
+    if !records.protocols.is_empty() {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO protocol_distribution (
+                timestamp, interface_name, protocol_name,
+                packet_count, byte_count, is_encrypted, sample_rate
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for record in &records.protocols {
+            stmt.execute(params![
+                record.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                record.interface_name,
+                record.protocol_name,
+                record.packet_count,
+                record.byte_count,
+                record.is_encrypted,
+                record.sample_rate,
+            ])?;
+        }
+    }
+
+    if !records.connections.is_empty() {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR REPLACE INTO connections (
+                connection_key, source_ip, dest_ip, source_port, dest_port,
+                protocol, application_protocol, first_seen, last_seen,
+                packet_count, byte_count, is_active, classification_confidence, sample_rate
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )?;
+        for record in &records.connections {
+            stmt.execute(params![
+                record.connection_key,
+                record.source_ip,
+                record.dest_ip,
+                record.source_port,
+                record.dest_port,
+                record.protocol,
+                record.application_protocol,
+                record.first_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
+                record.last_seen.format("%Y-%m-%d %H:%M:%S").to_string(),
+                record.packet_count,
+                record.byte_count,
+                record.is_active,
+                record.classification_confidence,
+                record.sample_rate,
+            ])?;
+        }
+    }
+
+    if !records.security_events.is_empty() {
+        let mut chain = AuditChain::load(&tx)?;
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO security_events (
+                timestamp, interface_name, event_type, source_ip, dest_ip,
+                port, protocol, description, severity
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for event in &records.security_events {
+            chain.append(&tx, audit::SECURITY_EVENT, &event.event_type, &security_event_detail(event))?;
+            stmt.execute(params![
+                event.timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+                event.interface_name,
+                event.event_type,
+                event.source_ip,
+                event.dest_ip,
+                event.port,
+                event.protocol,
+                event.description,
+                event.severity,
+            ])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Audit log text of a security event: when and where it was seen, the endpoints and its severity
+fn security_event_detail(event: &SecurityEvent) -> String {
+    let port = event.port.map(|port| format!(":{port}")).unwrap_or_default();
+    format!(
+        "{} on {}: {} [{} -> {}{}, {}, severity {}]",
+        event.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        event.interface_name,
+        event.description,
+        event.source_ip.as_deref().unwrap_or("-"),
+        event.dest_ip.as_deref().unwrap_or("-"),
+        port,
+        event.protocol.as_deref().unwrap_or("-"),
+        event.severity
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Database;
+    use chrono::Local;
+    use tempfile::tempdir;
+
+    fn record(protocol: &str) -> ProtocolRecord {
+        ProtocolRecord {
+            timestamp: Local::now(),
+            interface_name: "eth0".to_string(),
+            protocol_name: protocol.to_string(),
+            packet_count: 1,
+            byte_count: 100,
+            is_encrypted: false,
+            sample_rate: 1,
+        }
+    }
+
+    fn stored(conn: &Mutex<Connection>) -> i64 {
+        conn.lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM protocol_distribution", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_full_batch_is_written_by_the_writer() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        let buffer = WriteBuffer::spawn(Arc::clone(&conn), 10);
+        buffer.set_flush_interval(Duration::from_secs(3600));
+
+        for _ in 0..9 {
+            buffer.push(|pending| pending.protocols.push(record("DNS"))).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(stored(&conn), 0, "a part batch waits for the interval");
+
+        buffer.push(|pending| pending.protocols.push(record("DNS"))).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stored(&conn) < 10 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stored(&conn), 10);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_part_batch_is_written_after_the_interval() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        let buffer = WriteBuffer::spawn(Arc::clone(&conn), 1000);
+        buffer.set_flush_interval(Duration::from_millis(50));
+
+        buffer.push(|pending| pending.protocols.push(record("HTTPS"))).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stored(&conn) < 1 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stored(&conn), 1);
+    }
+
+    #[test]
+    fn test_dropping_the_buffer_writes_what_is_left() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        for buffer in [WriteBuffer::spawn(Arc::clone(&conn), 1000), WriteBuffer::inline(Arc::clone(&conn), 1000)] {
+            buffer.set_flush_interval(Duration::from_secs(3600));
+            for _ in 0..3 {
+                buffer.push(|pending| pending.protocols.push(record("SSH"))).unwrap();
+            }
+        }
+        assert_eq!(stored(&conn), 6);
+    }
+
+    #[test]
+    fn test_failed_write_is_retried() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        let hide = |from: &str, to: &str| {
+            conn.lock().unwrap().execute_batch(&format!("ALTER TABLE {from} RENAME TO {to}")).unwrap();
+        };
+
+        let buffer = WriteBuffer::new(Arc::clone(&conn), 1000, 4);
+        for protocol in ["A", "B", "C", "D", "E", "F"] {
+            buffer.push(|pending| pending.protocols.push(record(protocol))).unwrap();
+        }
+        hide("protocol_distribution", "protocol_distribution_hidden");
+        assert!(buffer.flush().is_err());
+
+        // Kept for the next write, without the oldest beyond the capacity
+        let kept: Vec<_> = buffer.lock().protocols.iter().map(|r| r.protocol_name.clone()).collect();
+        assert_eq!(kept, ["C", "D", "E", "F"]);
+
+        buffer.push(|pending| pending.protocols.push(record("G"))).unwrap();
+        hide("protocol_distribution_hidden", "protocol_distribution");
+        buffer.flush().unwrap();
+        assert_eq!(stored(&conn), 5);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_writer_retries_on_the_next_tick() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        let rename = |from: &str, to: &str| {
+            conn.lock().unwrap().execute_batch(&format!("ALTER TABLE {from} RENAME TO {to}")).unwrap();
+        };
+
+        rename("protocol_distribution", "protocol_distribution_hidden");
+        let buffer = WriteBuffer::spawn(Arc::clone(&conn), 1000);
+        buffer.set_flush_interval(Duration::from_millis(20));
+        buffer.push(|pending| pending.protocols.push(record("NTP"))).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        {
+            // Holding the connection keeps the writer from having the record taken meanwhile
+            let _conn = conn.lock().unwrap();
+            assert_eq!(buffer.len(), 1);
+        }
+
+        rename("protocol_distribution_hidden", "protocol_distribution");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stored(&conn) < 1 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stored(&conn), 1);
+    }
+
+    #[test]
+    fn test_inline_buffer_keeps_nothing_after_a_failed_write() {
+        let temp_dir = tempdir().unwrap();
+        let conn = Database::open(temp_dir.path().join("test.db")).unwrap().connection();
+        conn.lock().unwrap().execute_batch("DROP TABLE protocol_distribution").unwrap();
+
+        let buffer = WriteBuffer::inline(Arc::clone(&conn), 1);
+        assert!(buffer.push(|pending| pending.protocols.push(record("DNS"))).is_err());
+        assert!(buffer.is_empty());
+    }
+}